@@ -0,0 +1,12 @@
+CREATE TABLE views (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+
+    UNIQUE (organization_id, name)
+);
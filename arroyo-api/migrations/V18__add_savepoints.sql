@@ -0,0 +1,21 @@
+CREATE TYPE savepoint_state as ENUM ('inprogress', 'ready', 'failed');
+
+CREATE TABLE savepoints (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+
+    name TEXT NOT NULL,
+    -- not a foreign key, as savepoints are kept after the job they were taken from is deleted
+    job_id VARCHAR NOT NULL,
+    state savepoint_state NOT NULL DEFAULT 'inprogress',
+    epoch INT,
+    finish_time TIMESTAMPTZ,
+
+    UNIQUE (organization_id, name)
+);
+
+ALTER TABLE job_configs
+ADD COLUMN restore_from_savepoint TEXT;
@@ -0,0 +1,3 @@
+-- where the job's operators keep their state between checkpoints; memory if not set
+ALTER TABLE job_configs
+ADD COLUMN state_backend TEXT;
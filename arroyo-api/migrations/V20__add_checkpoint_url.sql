@@ -0,0 +1,3 @@
+-- overrides the cluster's checkpoint URL for the job's checkpoints
+ALTER TABLE job_configs
+ADD COLUMN checkpoint_url TEXT;
@@ -0,0 +1,3 @@
+-- the bounds within which the controller rescales the job based on its load
+ALTER TABLE job_configs
+ADD COLUMN autoscaling JSONB;
@@ -0,0 +1,3 @@
+-- the resources and placement of the job's worker pods, for the Kubernetes scheduler
+ALTER TABLE job_configs
+ADD COLUMN worker_pod JSONB;
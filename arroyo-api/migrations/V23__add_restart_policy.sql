@@ -0,0 +1,3 @@
+-- how the controller restarts the job when it fails, and when it moves it to failed instead
+ALTER TABLE job_configs
+ADD COLUMN restart_policy JSONB;
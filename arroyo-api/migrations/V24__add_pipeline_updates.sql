@@ -0,0 +1,35 @@
+-- a shadow job has its sinks replaced by web sinks, so that it can run alongside the job it's
+-- replacing until it's promoted
+ALTER TABLE job_configs
+ADD COLUMN shadow BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TYPE pipeline_update_state AS ENUM (
+    'savepointing', 'catching_up', 'awaiting_promotion', 'switching', 'finished', 'failed'
+);
+
+CREATE TABLE pipeline_updates (
+    id BIGSERIAL PRIMARY KEY,
+    pub_id VARCHAR NOT NULL UNIQUE,
+    organization_id VARCHAR NOT NULL,
+    created_by VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP NOT NULL,
+
+    -- the job running the current version of the pipeline
+    old_job_id VARCHAR NOT NULL REFERENCES job_configs(id) ON DELETE CASCADE,
+    -- the shadow job running the new version, started from a savepoint of the old job
+    new_job_id VARCHAR NOT NULL REFERENCES job_configs(id) ON DELETE CASCADE,
+    savepoint TEXT NOT NULL,
+
+    max_lag_micros BIGINT NOT NULL,
+    compare_outputs BOOLEAN NOT NULL,
+
+    state pipeline_update_state NOT NULL DEFAULT 'savepointing',
+    -- the run of the new job that was replaced when it was promoted
+    promoted_run_id BIGINT,
+    failure_message TEXT
+);
+
+-- a job can only be in one update at a time
+CREATE UNIQUE INDEX pipeline_updates_active_idx ON pipeline_updates (old_job_id)
+WHERE state NOT IN ('finished', 'failed');
@@ -126,9 +126,31 @@ DELETE FROM connection_tables
 WHERE organization_id = :organization_id AND pub_id = :pub_id;
 
 
+----------- views -------------------
+
+--! create_view
+INSERT INTO views (pub_id, organization_id, created_by, name, query)
+VALUES (:pub_id, :organization_id, :created_by, :name, :query);
+
+--! get_views: DbView
+SELECT pub_id, name, query, created_at
+FROM views
+WHERE organization_id = :organization_id
+ORDER BY created_at;
+
+--! get_view: DbView
+SELECT pub_id, name, query, created_at
+FROM views
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+--! delete_view
+DELETE FROM views
+WHERE organization_id = :organization_id AND pub_id = :pub_id;
+
+
 ----------- pipelines -------------------
 
---: DbPipeline (state?, ttl_micros?)
+--: DbPipeline (state?, ttl_micros?, autoscaling?, worker_pod?, restart_policy?)
 
 --! create_pipeline(udfs?, textual_repr?)
 INSERT INTO pipelines (pub_id, organization_id, created_by, name, type, textual_repr, udfs, program)
@@ -136,7 +158,7 @@ VALUES (:pub_id, :organization_id, :created_by, :name, :type, :textual_repr, :ud
 RETURNING id;
 
 --! get_pipelines : DbPipeline
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, autoscaling, worker_pod, restart_policy
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
@@ -151,7 +173,7 @@ ORDER BY pipelines.created_at DESC
 LIMIT :limit::integer;
 
 --! get_pipeline: DbPipeline
-SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros
+SELECT pipelines.pub_id, name, type, textual_repr, udfs, program, checkpoint_interval_micros, stop, pipelines.created_at, state, parallelism_overrides, ttl_micros, autoscaling, worker_pod, restart_policy
 FROM pipelines
     INNER JOIN job_configs on pipelines.id = job_configs.pipeline_id
     LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
@@ -168,7 +190,7 @@ WHERE pub_id = :pub_id AND organization_id = :organization_id;
 
 ----------- jobs -----------------------
 
---! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?)
+--! update_job(checkpoint_interval_micros?, stop?, parallelism_overrides?, autoscaling?, worker_pod?, restart_policy?)
 UPDATE job_configs
 SET
    updated_at = :updated_at,
@@ -176,7 +198,10 @@ SET
 
    stop = COALESCE(:stop, stop),
    checkpoint_interval_micros = COALESCE(:checkpoint_interval_micros, checkpoint_interval_micros),
-   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides)
+   parallelism_overrides = COALESCE(:parallelism_overrides, parallelism_overrides),
+   autoscaling = COALESCE(:autoscaling, autoscaling),
+   worker_pod = COALESCE(:worker_pod, worker_pod),
+   restart_policy = COALESCE(:restart_policy, restart_policy)
 WHERE id = :job_id AND organization_id = :organization_id;
 
 --! restart_job(mode)
@@ -188,10 +213,10 @@ SET
    restart_mode = :mode
 WHERE id = :job_id AND organization_id = :organization_id;
 
---! create_job(ttl_micros?)
+--! create_job(ttl_micros?, restore_from_savepoint?, checkpoint_url?, state_backend?, worker_pod?)
 INSERT INTO job_configs
-(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros)
-VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros);
+(id, organization_id, pipeline_name, created_by, pipeline_id, checkpoint_interval_micros, ttl_micros, restore_from_savepoint, checkpoint_url, state_backend, worker_pod)
+VALUES (:id, :organization_id, :pipeline_name, :created_by, :pipeline_id, :checkpoint_interval_micros, :ttl_micros, :restore_from_savepoint, :checkpoint_url, :state_backend, :worker_pod);
 
 --! create_job_status
 INSERT INTO job_statuses (pub_id, id, organization_id) VALUES (:pub_id, :id, :organization_id);
@@ -229,8 +254,8 @@ WHERE job_configs.organization_id = :organization_id AND job_configs.id = :job_i
 ORDER BY job_configs.created_at DESC;
 
 
---! get_job_details: (start_time?, finish_time?, state?, tasks?, textual_repr?, udfs, failure_message?, run_id?)
-SELECT pipeline_name, stop, parallelism_overrides, state, start_time, finish_time, tasks, textual_repr, program, pipeline_id, udfs, failure_message, run_id
+--! get_job_details: (start_time?, finish_time?, state?, tasks?, textual_repr?, udfs, failure_message?, run_id?, checkpoint_url?)
+SELECT pipeline_name, stop, parallelism_overrides, state, start_time, finish_time, tasks, textual_repr, program, pipeline_id, udfs, failure_message, run_id, job_configs.checkpoint_url
 FROM job_configs
          LEFT JOIN job_statuses ON job_configs.id = job_statuses.id
          INNER JOIN pipelines ON pipeline_id = pipelines.id
@@ -263,6 +288,81 @@ WHERE job_id = :job_id
     AND epoch = :epoch
     AND state != 'failed';
 
+----------- savepoints -------------------
+
+--: DbSavepoint (epoch?, finish_time?)
+
+--! create_savepoint
+INSERT INTO savepoints (pub_id, organization_id, created_by, name, job_id)
+VALUES (:pub_id, :organization_id, :created_by, :name, :job_id);
+
+--! get_savepoints: DbSavepoint
+SELECT pub_id, name, job_id, state, epoch, created_at, finish_time
+FROM savepoints
+WHERE organization_id = :organization_id
+ORDER BY created_at DESC;
+
+--! get_job_savepoints: DbSavepoint
+SELECT pub_id, name, job_id, state, epoch, created_at, finish_time
+FROM savepoints
+WHERE organization_id = :organization_id AND job_id = :job_id
+ORDER BY created_at DESC;
+
+--! get_savepoint_by_name: DbSavepoint
+SELECT pub_id, name, job_id, state, epoch, created_at, finish_time
+FROM savepoints
+WHERE organization_id = :organization_id AND name = :name;
+
+----------- pipeline updates -------------------
+
+--: DbPipelineUpdate (failure_message?)
+
+--! configure_update_job
+UPDATE job_configs AS new_job
+SET
+    shadow = true,
+    restore_from_savepoint = :savepoint,
+    checkpoint_interval_micros = old_job.checkpoint_interval_micros,
+    checkpoint_url = old_job.checkpoint_url,
+    state_backend = old_job.state_backend,
+    autoscaling = old_job.autoscaling,
+    worker_pod = old_job.worker_pod,
+    restart_policy = old_job.restart_policy
+FROM job_configs AS old_job
+WHERE new_job.id = :new_job_id AND old_job.id = :old_job_id;
+
+--! create_pipeline_update
+INSERT INTO pipeline_updates (pub_id, organization_id, created_by, old_job_id, new_job_id, savepoint, max_lag_micros, compare_outputs)
+VALUES (:pub_id, :organization_id, :created_by, :old_job_id, :new_job_id, :savepoint, :max_lag_micros, :compare_outputs);
+
+--! get_pipeline_updates: DbPipelineUpdate
+SELECT pipeline_updates.pub_id, old_pipelines.pub_id AS pipeline_id, new_pipelines.pub_id AS new_pipeline_id,
+    old_job_id, new_job_id, savepoint, max_lag_micros, compare_outputs, pipeline_updates.state,
+    pipeline_updates.created_at, failure_message
+FROM pipeline_updates
+    INNER JOIN job_configs old_jobs ON old_jobs.id = pipeline_updates.old_job_id
+    INNER JOIN pipelines old_pipelines ON old_pipelines.id = old_jobs.pipeline_id
+    INNER JOIN job_configs new_jobs ON new_jobs.id = pipeline_updates.new_job_id
+    INNER JOIN pipelines new_pipelines ON new_pipelines.id = new_jobs.pipeline_id
+WHERE pipeline_updates.organization_id = :organization_id AND old_pipelines.pub_id = :pipeline_pub_id
+ORDER BY pipeline_updates.created_at DESC;
+
+--! get_pipeline_update: DbPipelineUpdate
+SELECT pipeline_updates.pub_id, old_pipelines.pub_id AS pipeline_id, new_pipelines.pub_id AS new_pipeline_id,
+    old_job_id, new_job_id, savepoint, max_lag_micros, compare_outputs, pipeline_updates.state,
+    pipeline_updates.created_at, failure_message
+FROM pipeline_updates
+    INNER JOIN job_configs old_jobs ON old_jobs.id = pipeline_updates.old_job_id
+    INNER JOIN pipelines old_pipelines ON old_pipelines.id = old_jobs.pipeline_id
+    INNER JOIN job_configs new_jobs ON new_jobs.id = pipeline_updates.new_job_id
+    INNER JOIN pipelines new_pipelines ON new_pipelines.id = new_jobs.pipeline_id
+WHERE pipeline_updates.organization_id = :organization_id AND pipeline_updates.pub_id = :pub_id;
+
+--! promote_pipeline_update
+UPDATE pipeline_updates
+SET state = 'switching', updated_at = :updated_at
+WHERE organization_id = :organization_id AND pub_id = :pub_id AND state = 'awaiting_promotion';
+
 --! delete_pipeline_for_job
 DELETE FROM pipelines WHERE pipelines.id = (
     SELECT pipeline_id
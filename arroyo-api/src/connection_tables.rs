@@ -19,9 +19,10 @@ use tracing::warn;
 use arroyo_connectors::{connector_for_type, ErasedConnector};
 use arroyo_rpc::api_types::connections::{
     ConfluentSchema, ConfluentSchemaQueryParams, ConnectionProfile, ConnectionSchema,
-    ConnectionTable, ConnectionTablePost, SchemaDefinition,
+    ConnectionTable, ConnectionTablePost, InferSchemaQueryParams, InferredSchema, SchemaDefinition,
 };
 use arroyo_rpc::api_types::{ConnectionTableCollection, PaginationQueryParams};
+use arroyo_rpc::formats::{Format, JsonFormat};
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_sql::json_schema::convert_json_schema;
 use arroyo_sql::types::{StructField, TypeDef};
@@ -31,12 +32,16 @@ use crate::rest_utils::{
     authenticate, bad_request, client, log_and_map, not_found, paginate_results, required_field,
     validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
+use crate::schema_inference;
 use crate::{
     handle_db_error, handle_delete,
     queries::api_queries::{self, DbConnectionTable},
     to_micros, AuthData,
 };
 
+const DEFAULT_SAMPLE_SIZE: u32 = 20;
+const MAX_SAMPLE_SIZE: u32 = 1000;
+
 async fn get_and_validate_connector<E: GenericClient>(
     req: &ConnectionTablePost,
     auth: &AuthData,
@@ -481,6 +486,100 @@ pub(crate) async fn test_schema(
     }
 }
 
+/// Infer a Connection Schema from messages sampled from the source
+#[utoipa::path(
+    post,
+    path = "/v1/connection_tables/schemas/infer",
+    tag = "connection_tables",
+    params(
+        ("sampleSize" = Option<u32>, Query, description = "Number of messages to sample"),
+    ),
+    request_body = ConnectionTablePost,
+    responses(
+        (status = 200, description = "Inferred schema", body = InferredSchema),
+    ),
+)]
+pub(crate) async fn infer_schema(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    query_params: Query<InferSchemaQueryParams>,
+    WithRejection(Json(req), _): WithRejection<Json<ConnectionTablePost>, ApiError>,
+) -> Result<Json<InferredSchema>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let (connector, _, profile, schema) =
+        get_and_validate_connector(&req, &auth_data, &client).await?;
+
+    let json_format = match schema.as_ref().and_then(|s| s.format.as_ref()) {
+        Some(Format::Json(json)) if !json.unstructured => json.clone(),
+        None => JsonFormat::default(),
+        _ => {
+            return Err(bad_request(
+                "Schemas can only be inferred for structured JSON".to_string(),
+            ))
+        }
+    };
+
+    let sample_size = query_params.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE);
+    if sample_size == 0 || sample_size > MAX_SAMPLE_SIZE {
+        return Err(bad_request(format!(
+            "sampleSize must be between 1 and {}",
+            MAX_SAMPLE_SIZE
+        )));
+    }
+
+    let messages = connector
+        .sample(&profile, &req.config, sample_size as usize)
+        .map_err(|e| bad_request(format!("Failed to parse config: {:?}", e)))?
+        .await
+        .map_err(|e| bad_request(format!("Failed to sample messages: {}", e)))?;
+
+    if messages.is_empty() {
+        return Err(bad_request(
+            "No messages were received from the source".to_string(),
+        ));
+    }
+
+    let samples = messages
+        .iter()
+        .map(|msg| {
+            // messages written with the schema registry are prefixed by a magic byte and schema id
+            let msg = if json_format.confluent_schema_registry && msg.len() >= 5 {
+                &msg[5..]
+            } else {
+                &msg[..]
+            };
+
+            let mut value: serde_json::Value = serde_json::from_slice(msg)
+                .map_err(|e| bad_request(format!("Sampled message is not valid JSON: {}", e)))?;
+
+            if json_format.include_schema {
+                value = value.get("payload").cloned().unwrap_or(value);
+            }
+
+            if json_format.debezium {
+                value = match (value.get("after"), value.get("before")) {
+                    (Some(after), _) if !after.is_null() => after.clone(),
+                    (_, Some(before)) => before.clone(),
+                    _ => value,
+                };
+            }
+
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>, ErrorResp>>()?;
+
+    let fields = schema_inference::infer_fields(&samples).map_err(bad_request)?;
+    let ddl = schema_inference::to_ddl(&req.name, &req.connector, &fields);
+
+    Ok(Json(InferredSchema {
+        fields,
+        ddl,
+        messages_sampled: messages.len() as u32,
+    }))
+}
+
 /// Get a Confluent Schema
 #[utoipa::path(
     get,
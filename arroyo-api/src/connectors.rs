@@ -1,7 +1,12 @@
-use crate::rest_utils::ErrorResp;
+use crate::rest::AppState;
+use crate::rest_utils::{authenticate, bad_request, ApiError, BearerAuth, ErrorResp};
 use arroyo_connectors::connectors;
+use arroyo_connectors::remote::register_remote_connector;
+use arroyo_rpc::api_types::connections::{Connector, RemoteConnectorPost};
 use arroyo_rpc::api_types::ConnectorCollection;
+use axum::extract::State;
 use axum::Json;
+use axum_extra::extract::WithRejection;
 
 /// List all connectors
 #[utoipa::path(
@@ -22,3 +27,30 @@ pub async fn get_connectors() -> Result<Json<ConnectorCollection>, ErrorResp> {
     connectors.sort_by_cached_key(|c| c.name.clone());
     Ok(Json(ConnectorCollection { data: connectors }))
 }
+
+/// Register a remote connector
+///
+/// The connector is registered with this API server until it restarts; to register connectors
+/// on startup, list their endpoints in `REMOTE_CONNECTORS`.
+#[utoipa::path(
+    post,
+    path = "/v1/connectors/remote",
+    tag = "connectors",
+    request_body = RemoteConnectorPost,
+    responses(
+        (status = 200, description = "Registered remote connector", body = Connector),
+    ),
+)]
+pub async fn register_connector(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<RemoteConnectorPost>, ApiError>,
+) -> Result<Json<Connector>, ErrorResp> {
+    authenticate(&state.pool, bearer_auth).await?;
+
+    let connector = register_remote_connector(&req.endpoint)
+        .await
+        .map_err(|e| bad_request(format!("Failed to register connector: {}", e)))?;
+
+    Ok(Json(connector))
+}
@@ -1,29 +1,40 @@
 use crate::queries::api_queries::{
-    DbCheckpoint, DbLogMessage, DbPipelineJob, GetOperatorErrorsParams,
+    DbCheckpoint, DbLogMessage, DbPipelineJob, DbSavepoint, GetOperatorErrorsParams,
 };
+use arroyo_datastream::Program;
 use arroyo_rpc::api_types::checkpoints::{
-    Checkpoint, CheckpointEventSpan, CheckpointSpanType, OperatorCheckpointGroup,
-    SubtaskCheckpointGroup,
+    Checkpoint, CheckpointEventSpan, CheckpointSpanType, CheckpointStateQueryParams,
+    OperatorCheckpointGroup, OperatorCheckpointState, Savepoint, SavepointPost, SavepointState,
+    StateEntry, SubtaskCheckpointGroup, TableState,
+};
+use arroyo_rpc::api_types::pipelines::{
+    JobLogLevel, JobLogMessage, OperatorState, OperatorStateQueryParams, OutputData,
+    StateBackendType, StopType, WorkerPodConfig,
 };
-use arroyo_rpc::api_types::pipelines::{JobLogLevel, JobLogMessage, OutputData, StopType};
 use arroyo_rpc::api_types::{
     CheckpointCollection, JobCollection, JobLogMessageCollection,
-    OperatorCheckpointGroupCollection, PaginationQueryParams,
+    OperatorCheckpointGroupCollection, OperatorCheckpointStateCollection, PaginationQueryParams,
+    SavepointCollection,
 };
 use arroyo_rpc::grpc;
 use arroyo_rpc::grpc::api::{
-    CreateJobReq, JobStatus, OperatorCheckpointDetail, TaskCheckpointDetail,
+    CreateJobReq, JobStatus, OperatorCheckpointDetail, PipelineProgram, TaskCheckpointDetail,
     TaskCheckpointEventType,
 };
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_state::inspect::inspect_checkpoint;
+use arroyo_state::parquet::checkpoint_url_from_env;
+use arroyo_storage::BackendConfig;
 use axum::extract::{Path, Query, State};
 use axum::response::sse::{Event, Sse};
 use axum::Json;
+use axum_extra::extract::WithRejection;
 use cornucopia_async::GenericClient;
 use cornucopia_async::Params;
 use deadpool_postgres::Transaction;
 use futures_util::stream::Stream;
+use prost::Message;
 use std::convert::Infallible;
 use std::{collections::HashMap, time::Duration};
 use tokio_stream::wrappers::ReceiverStream;
@@ -37,15 +48,46 @@ use crate::pipelines::{query_job_by_pub_id, query_pipeline_by_pub_id};
 use crate::rest::AppState;
 use crate::rest_utils::{
     authenticate, bad_request, client, log_and_map, not_found, paginate_results,
-    validate_pagination_params, BearerAuth, ErrorResp,
+    validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
 use crate::types::public::LogLevel;
-use crate::{queries::api_queries, to_micros, types::public, AuthData};
+use crate::{handle_db_error, queries::api_queries, to_micros, types::public, AuthData};
+
+pub(crate) fn validate_worker_pod(config: &WorkerPodConfig) -> Result<(), ErrorResp> {
+    if config.slots_per_pod == Some(0) {
+        return Err(bad_request(
+            "workerPod slotsPerPod must be at least 1".to_string(),
+        ));
+    }
+
+    for toleration in config.tolerations.iter().flatten() {
+        if let Some(operator) = &toleration.operator {
+            if operator != "Exists" && operator != "Equal" {
+                return Err(bad_request(format!(
+                    "invalid toleration operator '{}'; expected Exists or Equal",
+                    operator
+                )));
+            }
+        }
+
+        if let Some(effect) = &toleration.effect {
+            if !["NoSchedule", "PreferNoSchedule", "NoExecute"].contains(&effect.as_str()) {
+                return Err(bad_request(format!(
+                    "invalid toleration effect '{}'; expected NoSchedule, PreferNoSchedule or NoExecute",
+                    effect
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
 
 pub(crate) async fn create_job<'a>(
     request: CreateJobReq,
     pipeline_name: &str,
     pipeline_id: &i64,
+    worker_pod: Option<&WorkerPodConfig>,
     auth: &AuthData,
     client: &Transaction<'a>,
 ) -> Result<String, ErrorResp> {
@@ -77,6 +119,43 @@ pub(crate) async fn create_job<'a>(
         return Err(bad_request(message));
     }
 
+    if let Some(name) = &request.restore_from_savepoint {
+        let savepoint = api_queries::get_savepoint_by_name()
+            .bind(client, &auth.organization_id, name)
+            .opt()
+            .await
+            .map_err(log_and_map)?
+            .ok_or_else(|| bad_request(format!("No savepoint named '{}'", name)))?;
+
+        if savepoint.state != public::SavepointState::ready {
+            return Err(bad_request(format!(
+                "Savepoint '{}' is not ready to be restored",
+                name
+            )));
+        }
+    }
+
+    if let Some(url) = &request.checkpoint_url {
+        BackendConfig::parse_url(url, false).map_err(|_| {
+            bad_request(format!(
+                "'{}' is not a valid checkpoint URL; expected an S3, GCS, Azure Blob or local path",
+                url
+            ))
+        })?;
+    }
+
+    if let Some(state_backend) = &request.state_backend {
+        StateBackendType::try_from(state_backend.clone()).map_err(bad_request)?;
+    }
+
+    if let Some(worker_pod) = worker_pod {
+        validate_worker_pod(worker_pod)?;
+    }
+    let worker_pod = worker_pod
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
     let job_id = generate_id(IdTypes::JobConfig);
 
     // TODO: handle chance of collision in ids
@@ -94,6 +173,10 @@ pub(crate) async fn create_job<'a>(
             } else {
                 None
             }),
+            &request.restore_from_savepoint,
+            &request.checkpoint_url,
+            &request.state_backend,
+            &worker_pod,
         )
         .await
         .map_err(log_and_map)?;
@@ -455,6 +538,128 @@ pub async fn get_checkpoint_details(
     Ok(Json(OperatorCheckpointGroupCollection { data: operators }))
 }
 
+/// Inspect the state stored in a checkpoint
+///
+/// Lists the tables of each operator's state with their sizes, record and key counts and how
+/// their keys are spread across the operator's subtasks, along with a sample of their entries.
+/// Entries are decoded as JSON where the types of the state are known, and shown as hex
+/// otherwise.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints/{epoch}/state",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        ("epoch" = u32, Path, description = "Epoch"),
+        CheckpointStateQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Got checkpoint's state", body = OperatorCheckpointStateCollection),
+    ),
+)]
+pub async fn get_checkpoint_state(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id, epoch)): Path<(String, String, u32)>,
+    query_params: Query<CheckpointStateQueryParams>,
+) -> Result<Json<OperatorCheckpointStateCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &client, &auth_data).await?;
+
+    api_queries::get_checkpoint_details()
+        .bind(
+            &client,
+            &job_pub_id,
+            &auth_data.organization_id,
+            &(epoch as i32),
+        )
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .ok_or_else(|| {
+            not_found(format!(
+                "Checkpoint with epoch {} for job '{}'",
+                epoch, job_pub_id
+            ))
+        })?;
+
+    let job = api_queries::get_job_details()
+        .bind(&client, &auth_data.organization_id, &job_pub_id)
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .ok_or_else(|| not_found("Job".to_string()))?;
+
+    let mut program: Program = PipelineProgram::decode(&job.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    program.update_parallelism(
+        &job.parallelism_overrides
+            .as_object()
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.as_u64().unwrap() as usize))
+            .collect(),
+    );
+
+    let parallelism = program
+        .graph
+        .node_weights()
+        .map(|node| (node.operator_id.clone(), node.parallelism))
+        .collect();
+
+    let checkpoint_url = job.checkpoint_url.unwrap_or_else(checkpoint_url_from_env);
+
+    let operators = inspect_checkpoint(
+        &checkpoint_url,
+        &job_pub_id,
+        epoch,
+        &parallelism,
+        &program.state_schemas,
+        query_params.sample.unwrap_or(10) as usize,
+    )
+    .await
+    .map_err(log_and_map)?;
+
+    let data = operators
+        .into_iter()
+        .map(|operator| OperatorCheckpointState {
+            operator_id: operator.operator_id,
+            bytes: operator.bytes,
+            tables: operator
+                .tables
+                .into_iter()
+                .map(|table| TableState {
+                    name: table.name,
+                    description: table.description,
+                    table_type: table.table_type.as_str_name().to_string(),
+                    files: table.files as u64,
+                    bytes: table.bytes,
+                    records: table.records as u64,
+                    keys: table.keys as u64,
+                    subtask_keys: table.subtask_keys.into_iter().map(|k| k as u64).collect(),
+                    sample: table
+                        .sample
+                        .into_iter()
+                        .map(|entry| StateEntry {
+                            timestamp: arroyo_types::to_micros(entry.timestamp),
+                            key: entry.key,
+                            value: entry.value,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(OperatorCheckpointStateCollection { data }))
+}
+
 /// Subscribe to a job's output
 #[utoipa::path(
     get,
@@ -536,6 +741,77 @@ pub async fn get_job_output(
     Ok(Sse::new(ReceiverStream::new(rx)))
 }
 
+/// Query an operator's current state for a key
+///
+/// The key is given as JSON in the form of the operator's key type; the request is routed to the
+/// subtask that owns the key. Currently only updating aggregates can be queried.
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/state/{operator_id}",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+        ("operator_id" = String, Path, description = "Operator id"),
+        OperatorStateQueryParams,
+    ),
+    responses(
+        (status = 200, description = "Got operator state", body = OperatorState),
+    ),
+)]
+pub async fn get_operator_state(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id, operator_id)): Path<(String, String, String)>,
+    query_params: Query<OperatorStateQueryParams>,
+) -> Result<Json<OperatorState>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &client, &auth_data).await?;
+
+    if job.state != "Running" {
+        return Err(bad_request(
+            "State can only be queried for running jobs".to_string(),
+        ));
+    }
+
+    let key: serde_json::Value = serde_json::from_str(&query_params.key)
+        .map_err(|e| bad_request(format!("Key is not valid JSON: {}", e)))?;
+
+    let mut controller = ControllerGrpcClient::connect(state.controller_addr.clone())
+        .await
+        .map_err(log_and_map)?;
+
+    let resp = controller
+        .query_state(Request::new(grpc::QueryStateReq {
+            job_id: job_pub_id,
+            operator_id: operator_id.clone(),
+            key: query_params.key.clone(),
+        }))
+        .await
+        .map_err(|status| match status.code() {
+            tonic::Code::InvalidArgument | tonic::Code::FailedPrecondition => {
+                bad_request(status.message().to_string())
+            }
+            tonic::Code::NotFound => not_found("Operator".to_string()),
+            _ => log_and_map(status),
+        })?
+        .into_inner();
+
+    let value = resp
+        .value
+        .map(|v| serde_json::from_str(&v))
+        .transpose()
+        .map_err(log_and_map)?;
+
+    Ok(Json(OperatorState {
+        operator_id,
+        key,
+        value,
+    }))
+}
+
 /// Get all jobs
 #[utoipa::path(
     get,
@@ -563,6 +839,156 @@ pub async fn get_jobs(
     }))
 }
 
+impl From<DbSavepoint> for Savepoint {
+    fn from(val: DbSavepoint) -> Self {
+        Savepoint {
+            id: val.pub_id,
+            name: val.name,
+            job_id: val.job_id,
+            state: match val.state {
+                public::SavepointState::inprogress => SavepointState::InProgress,
+                public::SavepointState::ready => SavepointState::Ready,
+                public::SavepointState::failed => SavepointState::Failed,
+            },
+            epoch: val.epoch.map(|e| e as u32),
+            created_at: to_micros(val.created_at),
+            finish_time: val.finish_time.map(to_micros),
+        }
+    }
+}
+
+fn is_valid_savepoint_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Take a savepoint of a running job
+///
+/// A savepoint is a snapshot of the job's state with a name, which is kept after the job's own
+/// checkpoints are cleaned up or the job is deleted, and which new pipelines can be started from.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/savepoints",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id")
+    ),
+    request_body = SavepointPost,
+    responses(
+        (status = 200, description = "Started savepoint", body = Savepoint),
+    ),
+)]
+pub async fn create_savepoint(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+    WithRejection(Json(req), _): WithRejection<Json<SavepointPost>, ApiError>,
+) -> Result<Json<Savepoint>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &client, &auth_data).await?;
+
+    if job.state != "Running" {
+        return Err(bad_request(
+            "Savepoints can only be taken of running jobs".to_string(),
+        ));
+    }
+
+    if !is_valid_savepoint_name(&req.name) {
+        return Err(bad_request(
+            "Savepoint names must contain only letters, numbers, dashes and underscores"
+                .to_string(),
+        ));
+    }
+
+    api_queries::create_savepoint()
+        .bind(
+            &client,
+            &generate_id(IdTypes::Savepoint),
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &job_pub_id,
+        )
+        .await
+        .map_err(|err| handle_db_error("savepoint", err))?;
+
+    let savepoint = api_queries::get_savepoint_by_name()
+        .bind(&client, &auth_data.organization_id, &req.name)
+        .one()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(Json(savepoint.into()))
+}
+
+/// List a job's savepoints
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/savepoints",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id")
+    ),
+    responses(
+        (status = 200, description = "Got job's savepoints", body = SavepointCollection),
+    ),
+)]
+pub async fn get_job_savepoints(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<Json<SavepointCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &client, &auth_data).await?;
+
+    let savepoints = api_queries::get_job_savepoints()
+        .bind(&client, &auth_data.organization_id, &job_pub_id)
+        .all()
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
+
+    Ok(Json(SavepointCollection { data: savepoints }))
+}
+
+/// List all savepoints, including those of jobs that have been deleted
+#[utoipa::path(
+    get,
+    path = "/v1/savepoints",
+    tag = "jobs",
+    responses(
+        (status = 200, description = "Got savepoints", body = SavepointCollection),
+    ),
+)]
+pub async fn get_savepoints(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<SavepointCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let savepoints = api_queries::get_savepoints()
+        .bind(&client, &auth_data.organization_id)
+        .all()
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
+
+    Ok(Json(SavepointCollection { data: savepoints }))
+}
+
 impl Into<Checkpoint> for DbCheckpoint {
     fn into(self) -> Checkpoint {
         Checkpoint {
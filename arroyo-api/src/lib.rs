@@ -9,23 +9,32 @@ use crate::connection_profiles::{
 };
 use crate::connection_tables::{
     __path_create_connection_table, __path_delete_connection_table, __path_get_confluent_schema,
-    __path_get_connection_tables, __path_test_connection_table, __path_test_schema,
+    __path_get_connection_tables, __path_infer_schema, __path_test_connection_table,
+    __path_test_schema,
 };
-use crate::connectors::__path_get_connectors;
+use crate::connectors::{__path_get_connectors, __path_register_connector};
 use crate::jobs::{
-    __path_get_checkpoint_details, __path_get_job_checkpoints, __path_get_job_errors,
-    __path_get_job_output, __path_get_jobs,
+    __path_create_savepoint, __path_get_checkpoint_details, __path_get_checkpoint_state,
+    __path_get_job_checkpoints, __path_get_job_errors, __path_get_job_output,
+    __path_get_job_savepoints, __path_get_jobs, __path_get_operator_state, __path_get_savepoints,
 };
 use crate::metrics::__path_get_operator_metric_groups;
 use crate::pipelines::__path_get_pipelines;
 use crate::pipelines::__path_post_pipeline;
 use crate::pipelines::{
-    __path_delete_pipeline, __path_get_pipeline, __path_get_pipeline_jobs, __path_patch_pipeline,
-    __path_restart_pipeline, __path_validate_query, __path_validate_udfs,
+    __path_delete_pipeline, __path_explain_query, __path_get_job_explanation, __path_get_pipeline,
+    __path_get_pipeline_jobs, __path_patch_pipeline, __path_restart_pipeline,
+    __path_validate_query, __path_validate_udfs,
 };
 use crate::rest::__path_ping;
 use crate::rest_utils::{bad_request, log_and_map, ErrorResp};
-use arroyo_rpc::api_types::{checkpoints::*, connections::*, metrics::*, pipelines::*, udfs::*, *};
+use crate::updates::{
+    __path_create_pipeline_update, __path_get_pipeline_updates, __path_promote_pipeline_update,
+};
+use crate::views::{__path_create_view, __path_delete_view, __path_get_views};
+use arroyo_rpc::api_types::{
+    checkpoints::*, connections::*, metrics::*, pipelines::*, udfs::*, views::*, *,
+};
 use arroyo_rpc::formats::*;
 mod cloud;
 mod connection_profiles;
@@ -37,6 +46,9 @@ mod optimizations;
 mod pipelines;
 pub mod rest;
 mod rest_utils;
+mod schema_inference;
+mod updates;
+mod views;
 
 include!(concat!(env!("OUT_DIR"), "/api-sql.rs"));
 
@@ -127,10 +139,14 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
     paths(
         ping,
         validate_query,
+        explain_query,
         validate_udfs,
         post_pipeline,
         patch_pipeline,
         restart_pipeline,
+        create_pipeline_update,
+        get_pipeline_updates,
+        promote_pipeline_update,
         get_pipeline,
         delete_pipeline,
         get_pipelines,
@@ -138,9 +154,15 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         get_pipeline_jobs,
         get_job_errors,
         get_job_checkpoints,
+        create_savepoint,
+        get_job_savepoints,
+        get_savepoints,
         get_job_output,
         get_operator_metric_groups,
+        get_job_explanation,
+        get_operator_state,
         get_connectors,
+        register_connector,
         get_connection_profiles,
         get_connection_tables,
         create_connection_table,
@@ -148,13 +170,31 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         delete_connection_table,
         test_connection_table,
         test_schema,
+        infer_schema,
         get_confluent_schema,
         get_checkpoint_details,
+        get_checkpoint_state,
+        create_view,
+        get_views,
+        delete_view,
     ),
     components(schemas(
         PipelinePost,
         PipelinePatch,
+        AutoscalingConfig,
+        WorkerPodConfig,
+        StateBackendType,
+        WorkerResources,
+        Toleration,
+        RestartPolicy,
+        FixedDelayRestart,
+        ExponentialBackoffRestart,
+        FailureRateRestart,
         PipelineRestart,
+        PipelineUpdatePost,
+        PipelineUpdateState,
+        PipelineUpdate,
+        PipelineUpdateCollection,
         Pipeline,
         PipelineGraph,
         PipelineNode,
@@ -169,7 +209,12 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         JobLogLevel,
         Checkpoint,
         CheckpointCollection,
+        Savepoint,
+        SavepointPost,
+        SavepointState,
+        SavepointCollection,
         OutputData,
+        OperatorState,
         MetricNames,
         Metric,
         SubtaskMetrics,
@@ -177,6 +222,7 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         OperatorMetricGroup,
         ConnectorCollection,
         Connector,
+        RemoteConnectorPost,
         ConnectionProfile,
         ConnectionProfilePost,
         ConnectionProfileCollection,
@@ -190,29 +236,49 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         SourceFieldType,
         FieldType,
         StructType,
+        DecimalType,
+        MapType,
         PrimitiveType,
         SchemaDefinition,
         TestSourceMessage,
         ConfluentSchema,
+        InferredSchema,
         JsonFormat,
         AvroFormat,
+        ProtobufFormat,
         ParquetFormat,
         RawStringFormat,
+        RawBytesFormat,
+        CsvFormat,
         TimestampFormat,
         Framing,
         FramingMethod,
         NewlineDelimitedFraming,
+        LengthPrefixedFraming,
+        BadData,
+        DeadLetterQueue,
+        Compression,
         PaginationQueryParams,
         CheckpointEventSpan,
         CheckpointSpanType,
         OperatorCheckpointGroupCollection,
         SubtaskCheckpointGroup,
         OperatorCheckpointGroup,
+        OperatorCheckpointStateCollection,
+        OperatorCheckpointState,
+        TableState,
+        StateEntry,
         ValidateQueryPost,
         QueryValidationResult,
+        QueryExplanationResult,
+        PipelineExplanation,
+        ExplainNode,
         ValidateUdfsPost,
         UdfValidationResult,
         Udf,
+        View,
+        ViewPost,
+        ViewCollection,
     )),
     tags(
         (name = "ping", description = "Ping endpoint"),
@@ -221,6 +287,7 @@ pub(crate) fn to_micros(dt: OffsetDateTime) -> u64 {
         (name = "pipelines", description = "Pipeline management endpoints"),
         (name = "jobs", description = "Job management endpoints"),
         (name = "connectors", description = "Connector management endpoints"),
+        (name = "views", description = "View management endpoints"),
     )
 )]
 pub struct ApiDoc;
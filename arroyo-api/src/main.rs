@@ -5,12 +5,14 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::{select, sync::broadcast};
 use tokio_postgres::NoTls;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use arroyo_api::rest;
 use arroyo_server_common::{log_event, start_admin_server};
-use arroyo_types::{ports, service_port, DatabaseConfig, CONTROLLER_ADDR_ENV, HTTP_PORT_ENV};
+use arroyo_types::{
+    ports, service_port, DatabaseConfig, CONTROLLER_ADDR_ENV, HTTP_PORT_ENV, REMOTE_CONNECTORS_ENV,
+};
 
 #[tokio::main]
 pub async fn main() {
@@ -69,6 +71,21 @@ async fn server(pool: Pool) {
     let controller_addr = std::env::var(CONTROLLER_ADDR_ENV)
         .unwrap_or_else(|_| format!("http://localhost:{}", ports::CONTROLLER_GRPC));
 
+    if let Ok(endpoints) = std::env::var(REMOTE_CONNECTORS_ENV) {
+        for endpoint in endpoints
+            .split(',')
+            .map(|e| e.trim())
+            .filter(|e| !e.is_empty())
+        {
+            if let Err(e) = arroyo_connectors::remote::register_remote_connector(endpoint).await {
+                warn!(
+                    "Failed to register remote connector at {}: {:?}",
+                    endpoint, e
+                );
+            }
+        }
+    }
+
     let http_port = service_port("api", ports::API_HTTP, HTTP_PORT_ENV);
     let addr = format!("0.0.0.0:{}", http_port).parse().unwrap();
 
@@ -13,8 +13,8 @@ use arroyo_rpc::api_types::metrics::{
 };
 use arroyo_rpc::api_types::OperatorMetricGroupCollection;
 use arroyo_types::{
-    to_millis, API_METRICS_RATE_ENV, BYTES_RECV, BYTES_SENT, MESSAGES_RECV, MESSAGES_SENT,
-    TX_QUEUE_REM, TX_QUEUE_SIZE,
+    to_millis, API_METRICS_RATE_ENV, BYTES_RECV, BYTES_SENT, DESERIALIZATION_ERRORS, MESSAGES_RECV,
+    MESSAGES_SENT, TX_QUEUE_REM, TX_QUEUE_SIZE,
 };
 use http::StatusCode;
 use http::{header::AUTHORIZATION, HeaderMap, HeaderValue};
@@ -72,7 +72,51 @@ fn get_query(metric_name: MetricNames, job_id: &str, run_id: &u64, rate: &str) -
         MetricNames::MessagesRecv => simple_query(MESSAGES_RECV, job_id, run_id, rate),
         MetricNames::MessagesSent => simple_query(MESSAGES_SENT, job_id, run_id, rate),
         MetricNames::Backpressure => backpressure_query(job_id, run_id),
+        MetricNames::DeserializationErrors => {
+            simple_query(DESERIALIZATION_ERRORS, job_id, run_id, rate)
+        }
+    }
+}
+
+/// The number of rows each operator of a job has received and sent, over all of its subtasks
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct RowCounts {
+    pub rows_in: u64,
+    pub rows_out: u64,
+}
+
+pub(crate) async fn get_operator_row_counts(
+    job_id: &str,
+    run_id: u64,
+) -> anyhow::Result<HashMap<String, RowCounts>> {
+    let query = |metric: &str| {
+        format!(
+            "sum by (operator_id) ({}{{job_id=\"{}\",run_id=\"{}\"}})",
+            metric, job_id, run_id
+        )
+    };
+
+    let (received, sent) = tokio::try_join!(
+        METRICS_CLIENT.query(query(MESSAGES_RECV)).get(),
+        METRICS_CLIENT.query(query(MESSAGES_SENT)).get(),
+    )?;
+
+    let mut counts: HashMap<String, RowCounts> = HashMap::new();
+    for (result, is_sent) in [(received, false), (sent, true)] {
+        for v in result.data().as_vector().unwrap_or_default() {
+            let Some(operator_id) = v.metric().get("operator_id") else {
+                continue;
+            };
+            let count = counts.entry(operator_id.clone()).or_default();
+            if is_sent {
+                count.rows_out = v.sample().value() as u64;
+            } else {
+                count.rows_in = v.sample().value() as u64;
+            }
+        }
     }
+
+    Ok(counts)
 }
 
 /// Get a job's metrics
@@ -142,12 +186,25 @@ pub async fn get_operator_metric_groups(
                 METRICS_GRANULARITY_SECS
             )
             .get(),
+        METRICS_CLIENT
+            .query_range(
+                get_query(
+                    MetricNames::DeserializationErrors,
+                    &job.id,
+                    &job.run_id,
+                    &rate
+                ),
+                start,
+                end,
+                METRICS_GRANULARITY_SECS
+            )
+            .get(),
     );
 
     let mut collection = OperatorMetricGroupCollection { data: vec![] };
 
     match result {
-        Ok((r1, r2, r3, r4, r5)) => {
+        Ok((r1, r2, r3, r4, r5, r6)) => {
             let mut metrics = HashMap::new();
 
             for (metric_name, query_result) in [
@@ -156,6 +213,7 @@ pub async fn get_operator_metric_groups(
                 (MetricNames::MessagesRecv, r3),
                 (MetricNames::MessagesSent, r4),
                 (MetricNames::Backpressure, r5),
+                (MetricNames::DeserializationErrors, r6),
             ] {
                 // for each metric query
 
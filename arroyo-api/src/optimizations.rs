@@ -473,6 +473,7 @@ mod tests {
             operator: arroyo_datastream::Operator::Window {
                 typ: arroyo_datastream::WindowType::Tumbling {
                     width: Duration::from_secs(10),
+                    alignment: Default::default(),
                 },
                 agg: None,
                 flatten: false,
@@ -562,7 +563,8 @@ mod tests {
         assert_eq!(
             arroyo_datastream::Operator::Window {
                 typ: arroyo_datastream::WindowType::Tumbling {
-                    width: Duration::from_secs(10)
+                    width: Duration::from_secs(10),
+                    alignment: Default::default(),
                 },
                 agg: Some(WindowAgg::Count),
                 flatten: false,
@@ -5,14 +5,16 @@ use axum::Json;
 use axum_extra::extract::WithRejection;
 use cornucopia_async::{GenericClient, Params};
 use deadpool_postgres::{Object, Transaction};
+use http::StatusCode;
 use std::collections::HashMap;
 use std::time::Duration;
 
 use crate::{jobs, pipelines, types};
 use arroyo_datastream::{ConnectorOp, Operator, Program};
 use arroyo_rpc::api_types::pipelines::{
-    Job, Pipeline, PipelineEdge, PipelineGraph, PipelineNode, PipelinePatch, PipelinePost,
-    PipelineRestart, QueryValidationResult, StopType, ValidateQueryPost,
+    AutoscalingConfig, ExplainNode, Job, Pipeline, PipelineEdge, PipelineExplanation,
+    PipelineGraph, PipelineNode, PipelinePatch, PipelinePost, PipelineRestart,
+    QueryExplanationResult, QueryValidationResult, RestartPolicy, StopType, ValidateQueryPost,
 };
 use arroyo_rpc::api_types::udfs::{UdfValidationResult, ValidateUdfsPost};
 use arroyo_rpc::api_types::{JobCollection, PaginationQueryParams, PipelineCollection};
@@ -24,7 +26,7 @@ use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::{CheckUdfsReq, ValidationResult};
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_server_common::log_event;
-use arroyo_sql::{ArroyoSchemaProvider, SqlConfig};
+use arroyo_sql::{ArroyoSchemaProvider, Explanation, SqlConfig};
 use petgraph::visit::EdgeRef;
 use prost::Message;
 use serde_json::json;
@@ -32,6 +34,7 @@ use time::OffsetDateTime;
 use tracing::warn;
 
 use crate::jobs::get_action;
+use crate::metrics::{get_operator_row_counts, RowCounts};
 use crate::queries::api_queries;
 use crate::queries::api_queries::{DbPipeline, DbPipelineJob, GetPipelinesParams};
 use crate::rest::AppState;
@@ -40,29 +43,35 @@ use crate::rest_utils::{
     unauthorized, validate_pagination_params, ApiError, BearerAuth, ErrorResp,
 };
 use crate::types::public::{PipelineType, RestartMode, StopMode};
-use crate::{connection_tables, to_micros};
+use crate::{connection_tables, to_micros, views};
 use crate::{handle_db_error, optimizations, AuthData};
 use create_pipeline_req::Config::Sql;
 
-const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
 
-async fn compile_sql<'e, E>(
-    sql: &CreateSqlJob,
+/// A schema provider with the organization's connection tables and views, and the given UDFs
+pub(crate) async fn get_schema_provider<E>(
+    udfs: &[CreateUdf],
     auth_data: &AuthData,
     tx: &E,
-) -> anyhow::Result<(Program, Vec<i64>)>
+) -> anyhow::Result<ArroyoSchemaProvider>
 where
     E: GenericClient,
 {
     let mut schema_provider = ArroyoSchemaProvider::new();
 
-    for udf in sql.udfs.iter() {
+    for udf in udfs {
         match UdfLanguage::from_i32(udf.language) {
             Some(UdfLanguage::Rust) => {
                 schema_provider
                     .add_rust_udf(&udf.definition)
                     .map_err(|e| anyhow!(format!("Could not process UDF: {:?}", e)))?;
             }
+            Some(UdfLanguage::Wasm) => {
+                schema_provider
+                    .add_wasm_udfs(&udf.definition)
+                    .map_err(|e| anyhow!(format!("Could not process WebAssembly UDF: {:?}", e)))?;
+            }
             None => {
                 return Err(anyhow!("Unsupported UDF language."));
             }
@@ -96,11 +105,35 @@ where
         schema_provider.add_connector_table(connection);
     }
 
-    let (program, connections) = arroyo_sql::parse_and_get_program(
+    let views = views::get_all_views(auth_data, tx)
+        .await
+        .map_err(|e| anyhow!(e.message))?;
+
+    for view in views {
+        if let Err(e) = schema_provider.add_view(&view.name, &view.query) {
+            warn!("Saved view {} could not be planned: {}", view.name, e);
+        }
+    }
+
+    Ok(schema_provider)
+}
+
+async fn compile_sql<'e, E>(
+    sql: &CreateSqlJob,
+    auth_data: &AuthData,
+    tx: &E,
+) -> anyhow::Result<Explanation>
+where
+    E: GenericClient,
+{
+    let schema_provider = get_schema_provider(&sql.udfs, auth_data, tx).await?;
+
+    arroyo_sql::explain(
         &sql.query,
         schema_provider,
         SqlConfig {
             default_parallelism: sql.parallelism as usize,
+            ..SqlConfig::default()
         },
     )
     .await
@@ -108,9 +141,7 @@ where
     .map_err(|err| {
         warn!("{:?}", err);
         anyhow!(format!("{}", err.root_cause()))
-    })?;
-
-    Ok((program, connections))
+    })
 }
 
 fn set_parallelism(program: &mut Program, parallelism: usize) {
@@ -159,9 +190,11 @@ pub(crate) async fn create_pipeline<'a>(
             }
 
             pipeline_type = PipelineType::sql;
-            (program, connections) = compile_sql(&sql, &auth, tx)
+            let explanation = compile_sql(&sql, &auth, tx)
                 .await
                 .map_err(|e| bad_request(e.to_string()))?;
+            program = explanation.program;
+            connections = explanation.connection_ids;
             text = Some(sql.query);
             udfs = Some(
                 sql.udfs
@@ -291,6 +324,21 @@ impl TryInto<Pipeline> for DbPipeline {
             action_text,
             action_in_progress,
             preview: self.ttl_micros.is_some(),
+            autoscaling: self
+                .autoscaling
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            worker_pod: self
+                .worker_pod
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
+            restart_policy: self
+                .restart_policy
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(log_and_map)?,
         })
     }
 }
@@ -338,7 +386,7 @@ pub async fn validate_query(
             .unwrap_or(vec![])
             .into_iter()
             .map(|u| CreateUdf {
-                language: 0,
+                language: UdfLanguage::from(u.language) as i32,
                 definition: u.definition.to_string(),
             })
             .collect(),
@@ -346,7 +394,7 @@ pub async fn validate_query(
     };
 
     let pipeline_graph_validation_result = match compile_sql(&sql, &auth_data, &client).await {
-        Ok((mut program, _)) => {
+        Ok(Explanation { mut program, .. }) => {
             optimizations::optimize(&mut program.graph);
             let nodes = program
                 .graph
@@ -388,6 +436,218 @@ pub async fn validate_query(
     Ok(Json(pipeline_graph_validation_result))
 }
 
+fn explain_program(
+    program: &Program,
+    logical_plans: Vec<String>,
+    analyze: bool,
+    row_counts: Option<&HashMap<String, RowCounts>>,
+) -> PipelineExplanation {
+    let nodes = program
+        .graph
+        .node_weights()
+        .map(|node| {
+            let rows =
+                row_counts.map(|counts| counts.get(&node.operator_id).copied().unwrap_or_default());
+            ExplainNode {
+                node_id: node.operator_id.to_string(),
+                operator: format!("{:?}", node),
+                parallelism: node.parallelism as u32,
+                state: node.operator.state_description(),
+                rows_in: rows.map(|r| r.rows_in),
+                rows_out: rows.map(|r| r.rows_out),
+            }
+        })
+        .collect();
+
+    let edges = program
+        .graph
+        .edge_references()
+        .map(|edge| {
+            let src = program.graph.node_weight(edge.source()).unwrap();
+            let target = program.graph.node_weight(edge.target()).unwrap();
+            PipelineEdge {
+                src_id: src.operator_id.to_string(),
+                dest_id: target.operator_id.to_string(),
+                key_type: edge.weight().key.to_string(),
+                value_type: edge.weight().value.to_string(),
+                edge_type: format!("{:?}", edge.weight().typ),
+            }
+        })
+        .collect();
+
+    PipelineExplanation {
+        analyze,
+        logical_plans,
+        nodes,
+        edges,
+    }
+}
+
+/// Explain a query, returning its logical plans and the dataflow graph it compiles to.
+///
+/// `EXPLAIN ANALYZE` queries are started as a preview pipeline, whose job's explanation
+/// includes the number of rows each operator has processed so far.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/explain",
+    tag = "pipelines",
+    request_body = ValidateQueryPost,
+    responses(
+        (status = 200, description = "Explained query", body = QueryExplanationResult),
+    ),
+)]
+pub async fn explain_query(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(explain_query_post), _): WithRejection<Json<ValidateQueryPost>, ApiError>,
+) -> Result<Json<QueryExplanationResult>, ErrorResp> {
+    let mut client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let sql = CreateSqlJob {
+        query: explain_query_post.query.clone(),
+        parallelism: 1,
+        udfs: explain_query_post
+            .udfs
+            .clone()
+            .unwrap_or(vec![])
+            .into_iter()
+            .map(|u| CreateUdf {
+                language: UdfLanguage::from(u.language) as i32,
+                definition: u.definition.to_string(),
+            })
+            .collect(),
+        preview: false,
+    };
+
+    let result = match compile_sql(&sql, &auth_data, &client).await {
+        Ok(mut explanation) => {
+            // EXPLAIN ANALYZE starts a preview run, which hasn't processed any rows yet; as it
+            // runs, its counts are returned by the job's explanation
+            let (pipeline_id, job_id, row_counts) = if explanation.analyze {
+                let (pipeline_id, job_id) = create_pipeline_and_job(
+                    PipelinePost {
+                        name: format!("explain-{}", to_micros(OffsetDateTime::now_utc())),
+                        query: explain_query_post.query,
+                        udfs: explain_query_post.udfs,
+                        preview: Some(true),
+                        parallelism: 1,
+                        savepoint: None,
+                        checkpoint_url: None,
+                        worker_pod: None,
+                        state_backend: None,
+                    },
+                    &auth_data,
+                    &mut client,
+                )
+                .await?;
+                (Some(pipeline_id), Some(job_id), Some(HashMap::new()))
+            } else {
+                (None, None, None)
+            };
+
+            optimizations::optimize(&mut explanation.program.graph);
+            QueryExplanationResult {
+                explanation: Some(explain_program(
+                    &explanation.program,
+                    explanation.logical_plans,
+                    explanation.analyze,
+                    row_counts.as_ref(),
+                )),
+                errors: None,
+                pipeline_id,
+                job_id,
+            }
+        }
+        Err(e) => QueryExplanationResult {
+            explanation: None,
+            errors: Some(vec![e.to_string()]),
+            pipeline_id: None,
+            job_id: None,
+        },
+    };
+
+    Ok(Json(result))
+}
+
+/// Explain a job, including the number of rows each operator has processed
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{pipeline_id}/jobs/{job_id}/explain",
+    tag = "jobs",
+    params(
+        ("pipeline_id" = String, Path, description = "Pipeline id"),
+        ("job_id" = String, Path, description = "Job id"),
+    ),
+    responses(
+        (status = 200, description = "Explained job", body = PipelineExplanation),
+    ),
+)]
+pub async fn get_job_explanation(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, job_pub_id)): Path<(String, String)>,
+) -> Result<Json<PipelineExplanation>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let job = query_job_by_pub_id(&pipeline_pub_id, &job_pub_id, &client, &auth_data).await?;
+
+    let pipeline = api_queries::get_pipeline()
+        .bind(&client, &pipeline_pub_id, &auth_data.organization_id)
+        .one()
+        .await
+        .map_err(log_and_map)?;
+
+    if pipeline.r#type != PipelineType::sql {
+        return Err(bad_request(
+            "Only SQL pipelines can be explained".to_string(),
+        ));
+    }
+
+    // the job runs the stored program, which may have been rewritten for a preview, so its graph
+    // is shown with the logical plans of the query it was compiled from
+    let program: Program = PipelineProgram::decode(&pipeline.program[..])
+        .map_err(log_and_map)?
+        .try_into()
+        .map_err(log_and_map)?;
+
+    let udfs: Vec<Udf> = serde_json::from_value(pipeline.udfs).map_err(log_and_map)?;
+    let sql = CreateSqlJob {
+        query: pipeline.textual_repr,
+        parallelism: 1,
+        udfs: udfs
+            .into_iter()
+            .map(|u| CreateUdf {
+                language: u.language,
+                definition: u.definition,
+            })
+            .collect(),
+        preview: false,
+    };
+
+    let explanation = compile_sql(&sql, &auth_data, &client)
+        .await
+        .map_err(|e| bad_request(e.to_string()))?;
+
+    let row_counts = get_operator_row_counts(&job.id, job.run_id)
+        .await
+        .map_err(|e| {
+            warn!("Failed to query row counts: {:?}", e);
+            ErrorResp {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: "Failed to query Prometheus".to_string(),
+            }
+        })?;
+
+    Ok(Json(explain_program(
+        &program,
+        explanation.logical_plans,
+        explanation.analyze,
+        Some(&row_counts),
+    )))
+}
+
 /// Validate UDFs
 #[utoipa::path(
     post,
@@ -472,6 +732,20 @@ pub async fn post_pipeline(
     let mut client = client(&state.pool).await?;
     let auth_data = authenticate(&state.pool, bearer_auth).await?;
 
+    let (pipeline_pub_id, _) =
+        create_pipeline_and_job(pipeline_post, &auth_data, &mut client).await?;
+
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+
+    Ok(Json(pipeline))
+}
+
+/// Creates a pipeline along with the job that runs it, returning the public ids of both
+async fn create_pipeline_and_job(
+    pipeline_post: PipelinePost,
+    auth_data: &AuthData,
+    client: &mut Object,
+) -> Result<(String, String), ErrorResp> {
     let preview = pipeline_post.preview.unwrap_or(false);
 
     let create_pipeline_req = CreatePipelineReq {
@@ -485,7 +759,7 @@ pub async fn post_pipeline(
                 .unwrap_or(vec![])
                 .into_iter()
                 .map(|u| CreateUdf {
-                    language: 0,
+                    language: UdfLanguage::from(u.language) as i32,
                     definition: u.definition.to_string(),
                 })
                 .collect(),
@@ -513,13 +787,17 @@ pub async fn post_pipeline(
         pipeline_id: format!("{}", pipeline_id),
         checkpoint_interval_micros: DEFAULT_CHECKPOINT_INTERVAL.as_micros() as u64,
         preview,
+        restore_from_savepoint: pipeline_post.savepoint.clone(),
+        checkpoint_url: pipeline_post.checkpoint_url.clone(),
+        state_backend: pipeline_post.state_backend.map(|b| b.to_string()),
     };
 
     let job_id = jobs::create_job(
         create_job,
         &pipeline_post.name,
         &pipeline_id,
-        &auth_data,
+        pipeline_post.worker_pod.as_ref(),
+        auth_data,
         &transaction,
     )
     .await?;
@@ -539,9 +817,7 @@ pub async fn post_pipeline(
         }),
     );
 
-    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
-
-    Ok(Json(pipeline))
+    Ok((pipeline_pub_id, job_id))
 }
 
 /// Update a pipeline
@@ -594,6 +870,58 @@ pub async fn patch_pipeline(
         }
     }
 
+    if let Some(autoscaling) = &pipeline_patch.autoscaling {
+        if autoscaling.min_parallelism < 1
+            || autoscaling.min_parallelism > autoscaling.max_parallelism
+        {
+            return Err(bad_request(
+                "autoscaling minParallelism must be at least 1 and no more than maxParallelism"
+                    .to_string(),
+            ));
+        }
+    }
+
+    let autoscaling = pipeline_patch
+        .autoscaling
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
+    if let Some(worker_pod) = &pipeline_patch.worker_pod {
+        jobs::validate_worker_pod(worker_pod)?;
+    }
+
+    let worker_pod = pipeline_patch
+        .worker_pod
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
+    match &pipeline_patch.restart_policy {
+        Some(RestartPolicy::ExponentialBackoff(backoff))
+            if backoff.initial_delay_millis > backoff.max_delay_millis =>
+        {
+            return Err(bad_request(
+                "restartPolicy initialDelayMillis must be no more than maxDelayMillis".to_string(),
+            ));
+        }
+        Some(RestartPolicy::FailureRate(rate)) if rate.interval_millis == 0 => {
+            return Err(bad_request(
+                "restartPolicy intervalMillis must be greater than 0".to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    let restart_policy = pipeline_patch
+        .restart_policy
+        .as_ref()
+        .map(serde_json::to_value)
+        .transpose()
+        .map_err(log_and_map)?;
+
     let parallelism_overrides = if let Some(parallelism) = pipeline_patch.parallelism {
         let res = api_queries::get_job_details()
             .bind(&client, &auth_data.organization_id, &job_id)
@@ -622,6 +950,9 @@ pub async fn patch_pipeline(
             &stop,
             &interval.map(|i| i.as_micros() as i64),
             &parallelism_overrides,
+            &autoscaling,
+            &worker_pod,
+            &restart_policy,
             &job_id,
             &auth_data.organization_id,
         )
@@ -20,18 +20,22 @@ use utoipa_swagger_ui::SwaggerUi;
 use crate::connection_profiles::{create_connection_profile, get_connection_profiles};
 use crate::connection_tables::{
     create_connection_table, delete_connection_table, get_confluent_schema, get_connection_tables,
-    test_connection_table, test_schema,
+    infer_schema, test_connection_table, test_schema,
 };
-use crate::connectors::get_connectors;
+use crate::connectors::{get_connectors, register_connector};
 use crate::jobs::{
-    get_checkpoint_details, get_job_checkpoints, get_job_errors, get_job_output, get_jobs,
+    create_savepoint, get_checkpoint_details, get_checkpoint_state, get_job_checkpoints,
+    get_job_errors, get_job_output, get_job_savepoints, get_jobs, get_operator_state,
+    get_savepoints,
 };
 use crate::metrics::get_operator_metric_groups;
 use crate::pipelines::{
-    delete_pipeline, get_pipeline, get_pipeline_jobs, get_pipelines, patch_pipeline, post_pipeline,
-    restart_pipeline, validate_query, validate_udfs,
+    delete_pipeline, explain_query, get_job_explanation, get_pipeline, get_pipeline_jobs,
+    get_pipelines, patch_pipeline, post_pipeline, restart_pipeline, validate_query, validate_udfs,
 };
 use crate::rest_utils::not_found;
+use crate::updates::{create_pipeline_update, get_pipeline_updates, promote_pipeline_update};
+use crate::views::{create_view, delete_view, get_views};
 use crate::ApiDoc;
 use arroyo_types::{telemetry_enabled, API_ENDPOINT_ENV, ASSET_DIR_ENV};
 
@@ -93,25 +97,35 @@ pub fn create_rest_app(pool: Pool, controller_addr: &str) -> Router {
         .route("/", get(get_pipeline_jobs))
         .route("/:job_id/errors", get(get_job_errors))
         .route("/:job_id/checkpoints", get(get_job_checkpoints))
+        .route("/:job_id/savepoints", get(get_job_savepoints))
+        .route("/:job_id/savepoints", post(create_savepoint))
         .route(
             "/:job_id/checkpoints/:checkpoint_id/operator_checkpoint_groups",
             get(get_checkpoint_details),
         )
+        .route(
+            "/:job_id/checkpoints/:checkpoint_id/state",
+            get(get_checkpoint_state),
+        )
         .route("/:job_id/output", get(get_job_output))
         .route(
             "/:job_id/operator_metric_groups",
             get(get_operator_metric_groups),
-        );
+        )
+        .route("/:job_id/explain", get(get_job_explanation))
+        .route("/:job_id/state/:operator_id", get(get_operator_state));
 
     let api_routes = Router::new()
         .route("/ping", get(ping))
         .route("/connectors", get(get_connectors))
+        .route("/connectors/remote", post(register_connector))
         .route("/connection_profiles", post(create_connection_profile))
         .route("/connection_profiles", get(get_connection_profiles))
         .route("/connection_tables", get(get_connection_tables))
         .route("/connection_tables", post(create_connection_table))
         .route("/connection_tables/test", post(test_connection_table))
         .route("/connection_tables/schemas/test", post(test_schema))
+        .route("/connection_tables/schemas/infer", post(infer_schema))
         .route(
             "/connection_tables/schemas/confluent",
             get(get_confluent_schema),
@@ -121,11 +135,22 @@ pub fn create_rest_app(pool: Pool, controller_addr: &str) -> Router {
         .route("/pipelines", get(get_pipelines))
         .route("/jobs", get(get_jobs))
         .route("/pipelines/validate_query", post(validate_query))
+        .route("/pipelines/explain", post(explain_query))
         .route("/pipelines/validate_udfs", post(validate_udfs))
         .route("/pipelines/:id", patch(patch_pipeline))
         .route("/pipelines/:id", get(get_pipeline))
         .route("/pipelines/:id/restart", post(restart_pipeline))
+        .route("/pipelines/:id/updates", post(create_pipeline_update))
+        .route("/pipelines/:id/updates", get(get_pipeline_updates))
+        .route(
+            "/pipelines/:id/updates/:update_id/promote",
+            post(promote_pipeline_update),
+        )
         .route("/pipelines/:id", delete(delete_pipeline))
+        .route("/views", get(get_views))
+        .route("/views", post(create_view))
+        .route("/views/:id", delete(delete_view))
+        .route("/savepoints", get(get_savepoints))
         .nest("/pipelines/:id/jobs", jobs_routes)
         .fallback(api_fallback);
 
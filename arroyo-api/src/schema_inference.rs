@@ -0,0 +1,265 @@
+use arroyo_rpc::api_types::connections::{
+    FieldType, PrimitiveType, SourceField, SourceFieldType, StructType,
+};
+use arroyo_rpc::field_type_to_sql;
+use chrono::DateTime;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inferred {
+    Null,
+    Bool,
+    Int,
+    UInt,
+    Float,
+    String,
+    Timestamp,
+    Json,
+    Struct(Vec<InferredField>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct InferredField {
+    name: String,
+    typ: Inferred,
+    nullable: bool,
+}
+
+fn infer_value(value: &Value) -> Inferred {
+    match value {
+        Value::Null => Inferred::Null,
+        Value::Bool(_) => Inferred::Bool,
+        Value::Number(n) if n.is_i64() => Inferred::Int,
+        Value::Number(n) if n.is_u64() => Inferred::UInt,
+        Value::Number(_) => Inferred::Float,
+        Value::String(s) if DateTime::parse_from_rfc3339(s).is_ok() => Inferred::Timestamp,
+        Value::String(_) => Inferred::String,
+        Value::Array(_) => Inferred::Json,
+        Value::Object(o) => Inferred::Struct(
+            o.iter()
+                .map(|(k, v)| InferredField {
+                    name: k.clone(),
+                    typ: infer_value(v),
+                    nullable: v.is_null(),
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn merge(a: Inferred, b: Inferred) -> Inferred {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Inferred::Null, t) | (t, Inferred::Null) => t,
+        (
+            Inferred::Int | Inferred::UInt | Inferred::Float,
+            Inferred::Int | Inferred::UInt | Inferred::Float,
+        ) => Inferred::Float,
+        (Inferred::String | Inferred::Timestamp, Inferred::String | Inferred::Timestamp) => {
+            Inferred::String
+        }
+        (Inferred::Struct(a), Inferred::Struct(b)) => Inferred::Struct(merge_fields(a, b)),
+        _ => Inferred::Json,
+    }
+}
+
+fn merge_fields(a: Vec<InferredField>, b: Vec<InferredField>) -> Vec<InferredField> {
+    let mut b: Vec<Option<InferredField>> = b.into_iter().map(Some).collect();
+
+    let mut fields: Vec<_> = a
+        .into_iter()
+        .map(|f| {
+            match b
+                .iter_mut()
+                .find(|o| o.as_ref().map(|o| o.name == f.name).unwrap_or(false))
+                .and_then(|o| o.take())
+            {
+                Some(other) => InferredField {
+                    name: f.name,
+                    typ: merge(f.typ, other.typ),
+                    nullable: f.nullable || other.nullable,
+                },
+                // fields that are missing from some records must be nullable
+                None => InferredField {
+                    nullable: true,
+                    ..f
+                },
+            }
+        })
+        .collect();
+
+    fields.extend(b.into_iter().flatten().map(|f| InferredField {
+        nullable: true,
+        ..f
+    }));
+
+    fields
+}
+
+fn to_source_field(field: InferredField) -> SourceField {
+    let nullable = field.nullable || field.typ == Inferred::Null;
+    let field_type = match field.typ {
+        Inferred::Bool => FieldType::Primitive(PrimitiveType::Bool),
+        Inferred::Int => FieldType::Primitive(PrimitiveType::Int64),
+        Inferred::UInt => FieldType::Primitive(PrimitiveType::UInt64),
+        Inferred::Float => FieldType::Primitive(PrimitiveType::F64),
+        // with no non-null values we can't know the type, so we fall back to the most general one
+        Inferred::Null | Inferred::String => FieldType::Primitive(PrimitiveType::String),
+        Inferred::Timestamp => FieldType::Primitive(PrimitiveType::DateTime),
+        Inferred::Json => FieldType::Primitive(PrimitiveType::Json),
+        Inferred::Struct(fields) => FieldType::Struct(StructType {
+            name: None,
+            fields: fields.into_iter().map(to_source_field).collect(),
+        }),
+    };
+
+    SourceField {
+        field_name: field.name,
+        field_type: SourceFieldType {
+            sql_name: field_type_to_sql(&field_type),
+            r#type: field_type,
+        },
+        nullable,
+        json_path: None,
+    }
+}
+
+/// Infers the fields of a table from sample records, which must all be JSON objects. Fields are
+/// nullable if they are null or missing in any of the samples, and fields whose type differs
+/// between samples are widened (integers to floats, timestamps to strings) or else treated as
+/// raw JSON.
+pub fn infer_fields(samples: &[Value]) -> Result<Vec<SourceField>, String> {
+    let mut fields: Option<Vec<InferredField>> = None;
+
+    for sample in samples {
+        let Inferred::Struct(sample_fields) = infer_value(sample) else {
+            return Err(format!(
+                "Expected messages to be JSON objects, but found '{}'",
+                sample
+            ));
+        };
+
+        fields = Some(match fields {
+            Some(fields) => merge_fields(fields, sample_fields),
+            None => sample_fields,
+        });
+    }
+
+    Ok(fields
+        .unwrap_or_default()
+        .into_iter()
+        .map(to_source_field)
+        .collect())
+}
+
+fn quote_ident(name: &str) -> String {
+    let simple = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if simple {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// Renders a CREATE TABLE statement for the inferred fields. SQL tables can't yet declare nested
+/// or raw JSON columns, so those are left as comments for the user to resolve.
+pub fn to_ddl(name: &str, connector: &str, fields: &[SourceField]) -> String {
+    let columns: Vec<_> = fields
+        .iter()
+        .map(|f| match (&f.field_type.r#type, &f.field_type.sql_name) {
+            (FieldType::Primitive(PrimitiveType::Json), _)
+            | (FieldType::Struct(_), _)
+            | (_, None) => format!(
+                "    -- {} has a nested or mixed type that can't be declared in SQL",
+                quote_ident(&f.field_name)
+            ),
+            (_, Some(sql_name)) => format!(
+                "    {} {}{}",
+                quote_ident(&f.field_name),
+                sql_name,
+                if f.nullable { "" } else { " NOT NULL" }
+            ),
+        })
+        .collect();
+
+    // comments can't take a trailing comma, so separators only go between real columns
+    let mut body = String::new();
+    let last_column = columns
+        .iter()
+        .rposition(|c| !c.trim_start().starts_with("--"));
+    for (i, column) in columns.iter().enumerate() {
+        body.push_str(column);
+        if !column.trim_start().starts_with("--") && Some(i) != last_column {
+            body.push(',');
+        }
+        body.push('\n');
+    }
+
+    format!(
+        "CREATE TABLE {} (\n{}) WITH (\n    connector = '{}',\n    format = 'json'\n);",
+        quote_ident(name),
+        body,
+        connector
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer_fields, to_ddl};
+    use arroyo_rpc::api_types::connections::{FieldType, PrimitiveType};
+    use serde_json::json;
+
+    #[test]
+    fn test_infer_fields() {
+        let samples = vec![
+            json!({"id": 1, "name": "a", "ts": "2023-10-01T00:00:00Z", "score": 1, "user": {"age": 5}}),
+            json!({"id": 2, "name": null, "ts": "2023-10-01T00:00:01Z", "score": 1.5, "tags": ["x"]}),
+        ];
+
+        let fields = infer_fields(&samples).unwrap();
+        let mut summary: Vec<_> = fields
+            .iter()
+            .map(|f| {
+                (
+                    f.field_name.as_str(),
+                    match &f.field_type.r#type {
+                        FieldType::Primitive(p) => Some(p.clone()),
+                        _ => None,
+                    },
+                    f.nullable,
+                )
+            })
+            .collect();
+        summary.sort_by_key(|(name, _, _)| *name);
+
+        assert_eq!(
+            summary,
+            vec![
+                ("id", Some(PrimitiveType::Int64), false),
+                ("name", Some(PrimitiveType::String), true),
+                ("score", Some(PrimitiveType::F64), false),
+                ("tags", Some(PrimitiveType::Json), true),
+                ("ts", Some(PrimitiveType::DateTime), false),
+                ("user", None, true),
+            ]
+        );
+
+        assert!(infer_fields(&[json!([1, 2])]).is_err());
+    }
+
+    #[test]
+    fn test_ddl() {
+        let fields = infer_fields(&[json!({"id": 1, "tags": [], "user name": "a"})]).unwrap();
+
+        assert_eq!(
+            to_ddl("events", "kafka", &fields),
+            "CREATE TABLE events (\n    id BIGINT NOT NULL,\n    -- tags has a nested or mixed type that can't be declared in SQL\n    \"user name\" TEXT NOT NULL\n) WITH (\n    connector = 'kafka',\n    format = 'json'\n);"
+        );
+    }
+}
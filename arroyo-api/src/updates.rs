@@ -0,0 +1,301 @@
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use time::OffsetDateTime;
+use tokio_postgres::error::SqlState;
+
+use arroyo_rpc::api_types::pipelines::{PipelineUpdate, PipelineUpdatePost, PipelineUpdateState};
+use arroyo_rpc::api_types::PipelineUpdateCollection;
+use arroyo_rpc::grpc::api::{
+    create_pipeline_req, CreateJobReq, CreatePipelineReq, CreateSqlJob, CreateUdf, UdfLanguage,
+};
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+
+use crate::pipelines::{query_pipeline_by_pub_id, DEFAULT_CHECKPOINT_INTERVAL};
+use crate::queries::api_queries::{self, DbPipelineUpdate};
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, client, log_and_map, not_found, ApiError, BearerAuth, ErrorResp,
+};
+use crate::types::public;
+use crate::{handle_db_error, jobs, pipelines, to_micros};
+
+const DEFAULT_MAX_LAG: Duration = Duration::from_secs(10);
+
+impl From<DbPipelineUpdate> for PipelineUpdate {
+    fn from(val: DbPipelineUpdate) -> Self {
+        PipelineUpdate {
+            id: val.pub_id,
+            pipeline_id: val.pipeline_id,
+            job_id: val.old_job_id,
+            new_pipeline_id: val.new_pipeline_id,
+            new_job_id: val.new_job_id,
+            savepoint: val.savepoint,
+            max_lag_millis: (val.max_lag_micros / 1000) as u64,
+            compare_outputs: val.compare_outputs,
+            state: match val.state {
+                public::PipelineUpdateState::savepointing => PipelineUpdateState::Savepointing,
+                public::PipelineUpdateState::catching_up => PipelineUpdateState::CatchingUp,
+                public::PipelineUpdateState::awaiting_promotion => {
+                    PipelineUpdateState::AwaitingPromotion
+                }
+                public::PipelineUpdateState::switching => PipelineUpdateState::Switching,
+                public::PipelineUpdateState::finished => PipelineUpdateState::Finished,
+                public::PipelineUpdateState::failed => PipelineUpdateState::Failed,
+            },
+            created_at: to_micros(val.created_at),
+            failure_message: val.failure_message,
+        }
+    }
+}
+
+/// Update a running pipeline to a new version
+///
+/// The controller takes a savepoint of the pipeline's job and starts the new version from it as
+/// a new pipeline, with its sinks replaced by web sinks. Once the new version has caught up (and
+/// the update has been promoted, if `compareOutputs` is set), the current version is stopped with
+/// a final checkpoint and the new one is restarted with the real sinks from where it stopped. The
+/// pipeline pauses briefly while it's switched, but its output isn't duplicated.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{id}/updates",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    request_body = PipelineUpdatePost,
+    responses(
+        (status = 200, description = "Started pipeline update", body = PipelineUpdate),
+    ),
+)]
+pub async fn create_pipeline_update(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+    WithRejection(Json(req), _): WithRejection<Json<PipelineUpdatePost>, ApiError>,
+) -> Result<Json<PipelineUpdate>, ErrorResp> {
+    let mut client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let pipeline = query_pipeline_by_pub_id(&pipeline_pub_id, &client, &auth_data).await?;
+
+    let job = api_queries::get_pipeline_jobs()
+        .bind(&client, &auth_data.organization_id, &pipeline_pub_id)
+        .all()
+        .await
+        .map_err(log_and_map)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| not_found("Job".to_string()))?;
+
+    if job.state.as_deref() != Some("Running") {
+        return Err(bad_request(
+            "Only pipelines with a running job can be updated".to_string(),
+        ));
+    }
+
+    if pipeline.preview {
+        return Err(bad_request(
+            "Preview pipelines can't be updated".to_string(),
+        ));
+    }
+
+    let max_lag = req
+        .max_lag_millis
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_MAX_LAG);
+
+    let update_pub_id = generate_id(IdTypes::PipelineUpdate);
+    let savepoint = format!("update-{}", update_pub_id);
+
+    let transaction = client.transaction().await.map_err(log_and_map)?;
+    transaction
+        .execute("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE", &[])
+        .await
+        .map_err(log_and_map)?;
+
+    api_queries::create_savepoint()
+        .bind(
+            &transaction,
+            &generate_id(IdTypes::Savepoint),
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &savepoint,
+            &job.id,
+        )
+        .await
+        .map_err(|err| handle_db_error("savepoint", err))?;
+
+    let create_pipeline_req = CreatePipelineReq {
+        name: pipeline.name.clone(),
+        config: Some(create_pipeline_req::Config::Sql(CreateSqlJob {
+            query: req.query,
+            parallelism: req.parallelism,
+            udfs: req
+                .udfs
+                .unwrap_or_default()
+                .into_iter()
+                .map(|u| CreateUdf {
+                    language: UdfLanguage::from(u.language) as i32,
+                    definition: u.definition,
+                })
+                .collect(),
+            preview: false,
+        })),
+    };
+
+    let (new_pipeline_id, _) = pipelines::create_pipeline(
+        &create_pipeline_req,
+        &generate_id(IdTypes::Pipeline),
+        auth_data.clone(),
+        &transaction,
+    )
+    .await?;
+
+    // the new job's savepoint and settings are filled in from the current job below, as the
+    // savepoint isn't ready yet
+    let create_job = CreateJobReq {
+        pipeline_id: format!("{}", new_pipeline_id),
+        checkpoint_interval_micros: DEFAULT_CHECKPOINT_INTERVAL.as_micros() as u64,
+        preview: false,
+        restore_from_savepoint: None,
+        checkpoint_url: None,
+        state_backend: None,
+    };
+
+    let new_job_id = jobs::create_job(
+        create_job,
+        &pipeline.name,
+        &new_pipeline_id,
+        None,
+        &auth_data,
+        &transaction,
+    )
+    .await?;
+
+    api_queries::configure_update_job()
+        .bind(&transaction, &savepoint, &new_job_id, &job.id)
+        .await
+        .map_err(log_and_map)?;
+
+    api_queries::create_pipeline_update()
+        .bind(
+            &transaction,
+            &update_pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &job.id,
+            &new_job_id,
+            &savepoint,
+            &(max_lag.as_micros() as i64),
+            &req.compare_outputs.unwrap_or(false),
+        )
+        .await
+        .map_err(|err| match err.as_db_error() {
+            // only one update of a job can be active at a time
+            Some(db) if *db.code() == SqlState::UNIQUE_VIOLATION => {
+                bad_request("The pipeline is already being updated".to_string())
+            }
+            _ => log_and_map(err),
+        })?;
+
+    transaction.commit().await.map_err(log_and_map)?;
+
+    let update = api_queries::get_pipeline_update()
+        .bind(&client, &auth_data.organization_id, &update_pub_id)
+        .one()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(Json(update.into()))
+}
+
+/// List a pipeline's updates
+#[utoipa::path(
+    get,
+    path = "/v1/pipelines/{id}/updates",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id")
+    ),
+    responses(
+        (status = 200, description = "Got pipeline's updates", body = PipelineUpdateCollection),
+    ),
+)]
+pub async fn get_pipeline_updates(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pipeline_pub_id): Path<String>,
+) -> Result<Json<PipelineUpdateCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let updates = api_queries::get_pipeline_updates()
+        .bind(&client, &auth_data.organization_id, &pipeline_pub_id)
+        .all()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(Json(PipelineUpdateCollection {
+        data: updates.into_iter().map(|u| u.into()).collect(),
+    }))
+}
+
+/// Promote an update that is waiting for its output to be compared
+///
+/// The current version is stopped with a final checkpoint, and the new version is restarted from
+/// it with the real sinks.
+#[utoipa::path(
+    post,
+    path = "/v1/pipelines/{id}/updates/{update_id}/promote",
+    tag = "pipelines",
+    params(
+        ("id" = String, Path, description = "Pipeline id"),
+        ("update_id" = String, Path, description = "Pipeline update id")
+    ),
+    responses(
+        (status = 200, description = "Promoted pipeline update", body = PipelineUpdate),
+    ),
+)]
+pub async fn promote_pipeline_update(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path((pipeline_pub_id, update_pub_id)): Path<(String, String)>,
+) -> Result<Json<PipelineUpdate>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let update = api_queries::get_pipeline_update()
+        .bind(&client, &auth_data.organization_id, &update_pub_id)
+        .opt()
+        .await
+        .map_err(log_and_map)?
+        .filter(|u| u.pipeline_id == pipeline_pub_id)
+        .ok_or_else(|| not_found("Pipeline update".to_string()))?;
+
+    let res = api_queries::promote_pipeline_update()
+        .bind(
+            &client,
+            &OffsetDateTime::now_utc(),
+            &auth_data.organization_id,
+            &update.pub_id,
+        )
+        .await
+        .map_err(log_and_map)?;
+
+    if res == 0 {
+        return Err(bad_request(
+            "Only updates that are awaiting promotion can be promoted".to_string(),
+        ));
+    }
+
+    let update = api_queries::get_pipeline_update()
+        .bind(&client, &auth_data.organization_id, &update_pub_id)
+        .one()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(Json(update.into()))
+}
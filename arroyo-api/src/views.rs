@@ -0,0 +1,167 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use axum_extra::extract::WithRejection;
+use cornucopia_async::GenericClient;
+
+use arroyo_rpc::api_types::views::{View, ViewPost};
+use arroyo_rpc::api_types::ViewCollection;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_sql::SqlConfig;
+
+use crate::pipelines::get_schema_provider;
+use crate::rest::AppState;
+use crate::rest_utils::{
+    authenticate, bad_request, client, log_and_map, not_found, ApiError, BearerAuth, ErrorResp,
+};
+use crate::{
+    handle_db_error,
+    queries::api_queries::{self, DbView},
+    to_micros, AuthData,
+};
+
+impl From<DbView> for View {
+    fn from(val: DbView) -> Self {
+        View {
+            id: val.pub_id,
+            name: val.name,
+            query: val.query,
+            created_at: to_micros(val.created_at),
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The organization's views, in the order they were created, so that each only refers to
+/// views before it
+pub(crate) async fn get_all_views<C: GenericClient>(
+    auth: &AuthData,
+    client: &C,
+) -> Result<Vec<View>, ErrorResp> {
+    let views = api_queries::get_views()
+        .bind(client, &auth.organization_id)
+        .all()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(views.into_iter().map(|v| v.into()).collect())
+}
+
+/// Create a view, which pipelines can read from like a table
+#[utoipa::path(
+    post,
+    path = "/v1/views",
+    tag = "views",
+    request_body = ViewPost,
+    responses(
+        (status = 200, description = "Created view", body = View),
+    ),
+)]
+pub async fn create_view(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    WithRejection(Json(req), _): WithRejection<Json<ViewPost>, ApiError>,
+) -> Result<Json<View>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    if !is_valid_name(&req.name) {
+        return Err(bad_request(
+            "View names must start with a letter or underscore and contain only letters, numbers and underscores".to_string(),
+        ));
+    }
+
+    // check that the view can be planned against the current tables and views
+    let mut schema_provider = get_schema_provider(&[], &auth_data, &client)
+        .await
+        .map_err(|e| bad_request(e.to_string()))?;
+    schema_provider
+        .add_view(&req.name, &req.query)
+        .map_err(|e| bad_request(format!("Invalid view: {}", e)))?;
+    arroyo_sql::parse_and_get_program(
+        &format!("SELECT * FROM {}", req.name),
+        schema_provider,
+        SqlConfig::default(),
+    )
+    .await
+    .map_err(|e| bad_request(format!("Invalid view: {}", e.root_cause())))?;
+
+    let pub_id = generate_id(IdTypes::View);
+
+    api_queries::create_view()
+        .bind(
+            &client,
+            &pub_id,
+            &auth_data.organization_id,
+            &auth_data.user_id,
+            &req.name,
+            &req.query,
+        )
+        .await
+        .map_err(|err| handle_db_error("view", err))?;
+
+    let view = api_queries::get_view()
+        .bind(&client, &auth_data.organization_id, &pub_id)
+        .one()
+        .await
+        .map_err(log_and_map)?;
+
+    Ok(Json(view.into()))
+}
+
+/// List all views
+#[utoipa::path(
+    get,
+    path = "/v1/views",
+    tag = "views",
+    responses(
+        (status = 200, description = "Got views", body = ViewCollection),
+    ),
+)]
+pub async fn get_views(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+) -> Result<Json<ViewCollection>, ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    Ok(Json(ViewCollection {
+        data: get_all_views(&auth_data, &client).await?,
+    }))
+}
+
+/// Delete a view
+#[utoipa::path(
+    delete,
+    path = "/v1/views/{id}",
+    tag = "views",
+    params(
+        ("id" = String, Path, description = "View id")
+    ),
+    responses(
+        (status = 200, description = "Deleted view"),
+    ),
+)]
+pub async fn delete_view(
+    State(state): State<AppState>,
+    bearer_auth: BearerAuth,
+    Path(pub_id): Path<String>,
+) -> Result<(), ErrorResp> {
+    let client = client(&state.pool).await?;
+    let auth_data = authenticate(&state.pool, bearer_auth).await?;
+
+    let deleted = api_queries::delete_view()
+        .bind(&client, &auth_data.organization_id, &pub_id)
+        .await
+        .map_err(log_and_map)?;
+
+    if deleted == 0 {
+        return Err(not_found("View".to_string()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector, EmptyConfig};
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/alert/table.json");
+const ICON: &str = include_str!("../resources/alert.svg");
+
+import_types!(schema = "../connector-schemas/alert/table.json");
+
+pub struct AlertConnector {}
+
+fn template_regex() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap()
+}
+
+impl Connector for AlertConnector {
+    type ProfileT = EmptyConfig;
+    type TableT = AlertTable;
+
+    fn name(&self) -> &'static str {
+        "alert"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "alert".to_string(),
+            name: "Slack / PagerDuty".to_string(),
+            icon: ICON.to_string(),
+            description: "Send alerts to Slack or PagerDuty".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        tokio::task::spawn(async move {
+            // we don't send a test alert, as that would page someone; instead we just check
+            // that the configuration is well-formed
+            let message = match validate_destination(&table.destination) {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated alert destination".to_string(),
+                },
+                Err(err) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: format!("{:?}", err),
+                },
+            };
+
+            tx.send(Ok(Event::default().json_data(message).unwrap()))
+                .await
+                .unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let destination = match pull_opt("destination", opts)?.as_str() {
+            "slack" => Destination::Slack {
+                webhook_url: pull_opt("slack.webhook_url", opts)?,
+            },
+            "pagerduty" => Destination::PagerDuty {
+                routing_key: pull_opt("pagerduty.routing_key", opts)?,
+                severity: match opts.remove("pagerduty.severity").as_deref() {
+                    Some("critical") => Some(Severity::Critical),
+                    Some("error") => Some(Severity::Error),
+                    Some("warning") => Some(Severity::Warning),
+                    Some("info") => Some(Severity::Info),
+                    None => None,
+                    Some(other) => bail!("invalid value for pagerduty.severity '{}'", other),
+                },
+            },
+            other => bail!(
+                "invalid destination '{}'; expected one of 'slack' or 'pagerduty'",
+                other
+            ),
+        };
+
+        let table = AlertTable {
+            message: pull_opt("message", opts)?,
+            dedup_key: opts.remove("dedup_key"),
+            dedup_window_seconds: pull_option_to_i64("dedup_window_seconds", opts)?,
+            destination,
+        };
+
+        Self::from_config(&self, None, name, EmptyConfig {}, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        validate_destination(&table.destination)?;
+
+        if matches!(table.dedup_window_seconds, Some(s) if s < 0) {
+            bail!("dedup_window_seconds must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for alert sink"))?;
+
+        // templates can only refer to fields that exist
+        for template in std::iter::once(&table.message).chain(table.dedup_key.iter()) {
+            for captures in template_regex().captures_iter(template) {
+                let field = &captures[1];
+                if !schema.fields.iter().any(|f| f.field_name == field) {
+                    bail!(
+                        "'{}' refers to field '{}', which is not in the schema",
+                        template,
+                        field
+                    );
+                }
+            }
+        }
+
+        let description = match &table.destination {
+            Destination::Slack { .. } => "AlertSink<Slack>".to_string(),
+            Destination::PagerDuty { .. } => "AlertSink<PagerDuty>".to_string(),
+        };
+
+        // records are rendered into the message from their fields, so no format is needed
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: None,
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::alert::AlertSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+fn validate_destination(destination: &Destination) -> anyhow::Result<()> {
+    match destination {
+        Destination::Slack { webhook_url } => {
+            reqwest::Url::parse(webhook_url)
+                .map_err(|e| anyhow!("invalid webhook_url '{}': {}", webhook_url, e))?;
+        }
+        Destination::PagerDuty { routing_key, .. } => {
+            // integration keys are 32 character identifiers
+            if routing_key.len() != 32 || !routing_key.chars().all(|c| c.is_ascii_alphanumeric()) {
+                bail!("routing_key must be a 32 character PagerDuty integration key");
+            }
+        }
+    }
+
+    Ok(())
+}
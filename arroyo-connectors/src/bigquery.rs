@@ -0,0 +1,283 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/bigquery/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/bigquery/table.json");
+const ICON: &str = include_str!("../resources/bigquery.svg");
+
+const BIGQUERY_SCOPE: &str = "https://www.googleapis.com/auth/bigquery";
+
+import_types!(schema = "../connector-schemas/bigquery/connection.json");
+import_types!(schema = "../connector-schemas/bigquery/table.json");
+
+pub struct BigQueryConnector {}
+
+impl Connector for BigQueryConnector {
+    type ProfileT = BigQueryConfig;
+    type TableT = BigQueryTable;
+
+    fn name(&self) -> &'static str {
+        "bigquery"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "bigquery".to_string(),
+            name: "BigQuery".to_string(),
+            icon: ICON.to_string(),
+            description: "Write rows to a BigQuery table with exactly-once semantics".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.project_id
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = BigQueryTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = BigQueryConfig {
+            project_id: pull_opt("project_id", opts)?,
+            credentials: opts.remove("credentials"),
+        };
+
+        let create_table = match opts.remove("create_table").as_ref().map(|f| f.as_str()) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            None => None,
+            Some(other) => bail!("invalid value for create_table '{}'", other),
+        };
+
+        let table = BigQueryTable {
+            dataset: pull_opt("dataset", opts)?,
+            table_name: pull_opt("table_name", opts)?,
+            create_table,
+            batch_size: pull_option_to_i64("batch_size", opts)?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if let Some(credentials) = &config.credentials {
+            CustomServiceAccount::from_json(credentials)
+                .map_err(|e| anyhow!("invalid service account key: {}", e))?;
+        }
+
+        if matches!(table.batch_size, Some(size) if size < 1) {
+            bail!("batch_size must be at least 1");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for BigQuery sink"))?;
+
+        // rows are converted to protobuf through JSON, which needs RFC3339 timestamps
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(f)) if !f.unstructured && !f.debezium => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::RFC3339,
+                ..f.clone()
+            }),
+            Some(_) => bail!("bigquery tables must use the 'json' format"),
+        };
+
+        for field in &schema.fields {
+            match &field.field_type.r#type {
+                FieldType::Primitive(PrimitiveType::Bytes)
+                | FieldType::Struct(_)
+                | FieldType::Map(_) => bail!(
+                    "field '{}' has a type that can't be written to BigQuery",
+                    field.field_name
+                ),
+                FieldType::Primitive(_) | FieldType::Decimal(_) | FieldType::List(_) => {}
+            }
+        }
+
+        let description = format!(
+            "BigQuerySink<{}.{}.{}>",
+            config.project_id, table.dataset, table.table_name
+        );
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::bigquery::sink::BigQuerySinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+struct BigQueryTester {
+    config: BigQueryConfig,
+    table: BigQueryTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl BigQueryTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let auth = match &self.config.credentials {
+            Some(credentials) => {
+                AuthenticationManager::from(CustomServiceAccount::from_json(credentials)?)
+            }
+            None => AuthenticationManager::new()
+                .await
+                .map_err(|e| anyhow!("Failed to find application default credentials: {}", e))?,
+        };
+
+        let token = auth
+            .get_token(&[BIGQUERY_SCOPE])
+            .await
+            .map_err(|e| anyhow!("Failed to authenticate with GCP: {}", e))?;
+
+        self.info("Authenticated with GCP").await;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let response = client
+            .get(format!(
+                "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables/{}",
+                self.config.project_id, self.table.dataset, self.table.table_name
+            ))
+            .bearer_auth(token.as_str())
+            .send()
+            .await?;
+
+        match response.status().as_u16() {
+            200 => {
+                self.info(format!("Found table {}", self.table.table_name))
+                    .await;
+            }
+            404 if self.table.create_table.unwrap_or(false) => {
+                self.info(format!(
+                    "Table {} does not exist and will be created",
+                    self.table.table_name
+                ))
+                .await;
+            }
+            404 => bail!(
+                "table {}.{} does not exist; create it or set create_table",
+                self.table.dataset,
+                self.table.table_name
+            ),
+            401 | 403 => bail!(
+                "the credentials do not have access to {}.{}",
+                self.table.dataset,
+                self.table.table_name
+            ),
+            status => bail!("BigQuery responded with {}", status),
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started BigQuery tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
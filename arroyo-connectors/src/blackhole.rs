@@ -90,8 +90,11 @@ impl Connector for BlackholeConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: None,
             framing: None,
+            bad_data: None,
+            compression: None,
         };
 
         Ok(Connection {
@@ -101,6 +104,8 @@ impl Connector for BlackholeConnector {
             schema: s.cloned().unwrap_or_else(|| ConnectionSchema {
                 format: None,
                 framing: None,
+                bad_data: None,
+                compression: None,
                 struct_name: None,
                 fields: vec![],
                 definition: None,
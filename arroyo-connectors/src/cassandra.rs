@@ -0,0 +1,306 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use scylla::{Session, SessionBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/cassandra/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/cassandra/table.json");
+const ICON: &str = include_str!("../resources/cassandra.svg");
+
+import_types!(schema = "../connector-schemas/cassandra/connection.json");
+import_types!(schema = "../connector-schemas/cassandra/table.json");
+
+pub struct CassandraConnector {}
+
+impl Connector for CassandraConnector {
+    type ProfileT = CassandraConfig;
+    type TableT = CassandraTable;
+
+    fn name(&self) -> &'static str {
+        "cassandra"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "cassandra".to_string(),
+            name: "Cassandra / ScyllaDB".to_string(),
+            icon: ICON.to_string(),
+            description: "Write rows to a Cassandra or ScyllaDB table".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.hosts
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = CassandraTester {
+            config,
+            table,
+            schema: schema.cloned(),
+            tx,
+        };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = CassandraConfig {
+            hosts: pull_opt("hosts", opts)?,
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+            local_datacenter: opts.remove("local_datacenter"),
+        };
+
+        let consistency = match opts.remove("consistency").as_ref().map(|f| f.as_str()) {
+            Some("one") => Some(Consistency::One),
+            Some("local_one") => Some(Consistency::LocalOne),
+            Some("quorum") => Some(Consistency::Quorum),
+            Some("local_quorum") => Some(Consistency::LocalQuorum),
+            Some("all") => Some(Consistency::All),
+            None => None,
+            Some(other) => bail!("invalid value for consistency '{}'", other),
+        };
+
+        let table = CassandraTable {
+            keyspace: pull_opt("keyspace", opts)?,
+            table_name: pull_opt("table_name", opts)?,
+            consistency,
+            batch_size: pull_option_to_i64("batch_size", opts)?,
+            flush_interval_ms: pull_option_to_i64("flush_interval_ms", opts)?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if config.hosts.split(',').all(|h| h.trim().is_empty()) {
+            bail!("at least one host must be provided");
+        }
+
+        if config.username.is_some() != config.password.is_some() {
+            bail!("username and password must be provided together");
+        }
+
+        if matches!(table.batch_size, Some(size) if size < 1) {
+            bail!("batch_size must be at least 1");
+        }
+
+        if matches!(table.flush_interval_ms, Some(interval) if interval < 0) {
+            bail!("flush_interval_ms must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Cassandra sink"))?;
+
+        if schema.fields.is_empty() {
+            bail!("Cassandra tables must declare the columns to write");
+        }
+
+        // rows are bound to the table's column types from JSON, so we only support the JSON
+        // formats (with debezium_json the sink can consume updating queries, which it applies
+        // as deletes)
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..Default::default()
+            }),
+            Some(Format::Json(f)) if !f.unstructured => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..f.clone()
+            }),
+            Some(_) => bail!("Cassandra tables must use the 'json' or 'debezium_json' format"),
+        };
+
+        let description = format!("CassandraSink<{}.{}>", table.keyspace, table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::cassandra::sink::CassandraSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+async fn connect(config: &CassandraConfig) -> anyhow::Result<Session> {
+    let hosts: Vec<&str> = config
+        .hosts
+        .split(',')
+        .map(|h| h.trim())
+        .filter(|h| !h.is_empty())
+        .collect();
+
+    let mut builder = SessionBuilder::new().known_nodes(&hosts);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.user(username, password);
+    }
+
+    Ok(builder.build().await?)
+}
+
+struct CassandraTester {
+    config: CassandraConfig,
+    table: CassandraTable,
+    schema: Option<ConnectionSchema>,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl CassandraTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let session = connect(&self.config)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Cassandra: {}", e))?;
+
+        self.info("Connected to Cassandra").await;
+
+        let columns: Vec<(String, String)> = session
+            .query(
+                "SELECT column_name, kind FROM system_schema.columns \
+                 WHERE keyspace_name = ? AND table_name = ?",
+                (&self.table.keyspace, &self.table.table_name),
+            )
+            .await?
+            .rows_typed::<(String, String)>()?
+            .collect::<Result<_, _>>()?;
+
+        if columns.is_empty() {
+            bail!(
+                "table {}.{} does not exist",
+                self.table.keyspace,
+                self.table.table_name
+            );
+        }
+
+        if let Some(schema) = &self.schema {
+            for field in &schema.fields {
+                if !columns.iter().any(|(c, _)| c == &field.field_name) {
+                    bail!(
+                        "field '{}' does not exist in table {}.{}",
+                        field.field_name,
+                        self.table.keyspace,
+                        self.table.table_name
+                    );
+                }
+            }
+
+            for (column, kind) in &columns {
+                if (kind == "partition_key" || kind == "clustering")
+                    && !schema.fields.iter().any(|f| &f.field_name == column)
+                {
+                    bail!("primary key column '{}' is missing from the schema", column);
+                }
+            }
+        }
+
+        self.info(format!(
+            "Found table {}.{} with {} columns",
+            self.table.keyspace,
+            self.table.table_name,
+            columns.len()
+        ))
+        .await;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Cassandra tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
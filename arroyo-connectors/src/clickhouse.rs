@@ -0,0 +1,342 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use clickhouse_rs::Pool;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/clickhouse/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/clickhouse/table.json");
+const ICON: &str = include_str!("../resources/clickhouse.svg");
+
+import_types!(schema = "../connector-schemas/clickhouse/connection.json");
+import_types!(schema = "../connector-schemas/clickhouse/table.json");
+
+pub struct ClickhouseConnector {}
+
+impl Connector for ClickhouseConnector {
+    type ProfileT = ClickhouseConfig;
+    type TableT = ClickhouseTable;
+
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "clickhouse".to_string(),
+            name: "ClickHouse".to_string(),
+            icon: ICON.to_string(),
+            description: "Insert rows into a ClickHouse table".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        format!(
+            "{}:{}/{}",
+            config.host,
+            config.port.unwrap_or(9000),
+            config.database()
+        )
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = ClickhouseTester {
+            config,
+            table,
+            schema: schema.cloned(),
+            tx,
+        };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = ClickhouseConfig {
+            host: pull_opt("host", opts)?,
+            port: pull_option_to_i64("port", opts)?,
+            database: opts.remove("database"),
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+        };
+
+        let async_insert = match opts.remove("async_insert").as_ref().map(|f| f.as_str()) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            None => None,
+            Some(other) => bail!("invalid value for async_insert '{}'", other),
+        };
+
+        let table = ClickhouseTable {
+            table_name: pull_opt("table_name", opts)?,
+            batch_size: pull_option_to_i64("batch_size", opts)?,
+            flush_interval_ms: pull_option_to_i64("flush_interval_ms", opts)?,
+            async_insert,
+            version_column: opts.remove("version_column"),
+            is_deleted_column: opts.remove("is_deleted_column"),
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        url(&config)?;
+
+        if matches!(table.batch_size, Some(size) if size < 1) {
+            bail!("batch_size must be at least 1");
+        }
+
+        if matches!(table.flush_interval_ms, Some(interval) if interval < 1) {
+            bail!("flush_interval_ms must be at least 1");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for ClickHouse sink"))?;
+
+        // rows are converted to columns through JSON; timestamps must be RFC3339 so that the
+        // sink can tell them apart from numbers
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(f)) if !f.unstructured => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::RFC3339,
+                ..f.clone()
+            }),
+            Some(_) => bail!("clickhouse tables must use the 'json' or 'debezium_json' format"),
+        };
+
+        if let Format::Json(JsonFormat { debezium: true, .. }) = &format {
+            if table.is_deleted_column.is_none() || table.version_column.is_none() {
+                bail!(
+                    "writing an updating query to ClickHouse requires version_column and \
+                    is_deleted_column to be set, for use with a ReplacingMergeTree table"
+                );
+            }
+        }
+
+        for column in [&table.version_column, &table.is_deleted_column]
+            .into_iter()
+            .flatten()
+        {
+            if schema.fields.iter().any(|f| &f.field_name == column) {
+                bail!(
+                    "column '{}' is set by the sink, so it can't also be a field in the schema",
+                    column
+                );
+            }
+        }
+
+        let description = format!("ClickhouseSink<{}.{}>", config.database(), table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::clickhouse::ClickhouseSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+impl ClickhouseConfig {
+    pub fn database(&self) -> &str {
+        self.database.as_deref().unwrap_or("default")
+    }
+}
+
+fn url(config: &ClickhouseConfig) -> anyhow::Result<String> {
+    let mut url = url::Url::parse(&format!(
+        "tcp://{}:{}",
+        config.host,
+        config.port.unwrap_or(9000)
+    ))
+    .map_err(|e| anyhow!("invalid host '{}': {}", config.host, e))?;
+
+    url.set_path(config.database());
+    url.set_username(config.username.as_deref().unwrap_or("default"))
+        .map_err(|_| anyhow!("invalid username"))?;
+    url.set_password(config.password.as_deref())
+        .map_err(|_| anyhow!("invalid password"))?;
+
+    Ok(url.to_string())
+}
+
+struct ClickhouseTester {
+    config: ClickhouseConfig,
+    table: ClickhouseTable,
+    schema: Option<ConnectionSchema>,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl ClickhouseTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let pool = Pool::new(url(&self.config)?);
+        let mut client = pool
+            .get_handle()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to ClickHouse: {}", e))?;
+
+        self.info("Connected to ClickHouse").await;
+
+        let block = client
+            .query(format!(
+                "SELECT name, type FROM system.columns WHERE database = '{}' AND table = '{}'",
+                escape(self.config.database()),
+                escape(&self.table.table_name)
+            ))
+            .fetch_all()
+            .await?;
+
+        let mut columns = vec![];
+        for row in block.rows() {
+            let name: String = row.get("name")?;
+            let type_: String = row.get("type")?;
+            columns.push((name, type_));
+        }
+
+        if columns.is_empty() {
+            bail!(
+                "table {}.{} does not exist",
+                self.config.database(),
+                self.table.table_name
+            );
+        }
+
+        if let Some(schema) = &self.schema {
+            for field in &schema.fields {
+                if !columns.iter().any(|(name, _)| name == &field.field_name) {
+                    bail!(
+                        "field '{}' does not exist in table {}.{}",
+                        field.field_name,
+                        self.config.database(),
+                        self.table.table_name
+                    );
+                }
+            }
+        }
+
+        for (column, expected) in [
+            (&self.table.version_column, "UInt64"),
+            (&self.table.is_deleted_column, "UInt8"),
+        ] {
+            let Some(column) = column else {
+                continue;
+            };
+
+            match columns.iter().find(|(name, _)| name == column) {
+                None => bail!(
+                    "column '{}' does not exist in table {}.{}",
+                    column,
+                    self.config.database(),
+                    self.table.table_name
+                ),
+                Some((_, type_)) if type_ != expected => {
+                    bail!("column '{}' must be {} but is {}", column, expected, type_)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started ClickHouse tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, SourceField, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector, EmptyConfig};
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/datagen/table.json");
+const ICON: &str = include_str!("../resources/datagen.svg");
+
+import_types!(schema = "../connector-schemas/datagen/table.json");
+
+pub struct DatagenConnector {}
+
+fn parse_f64(name: &str, value: Option<String>) -> anyhow::Result<Option<f64>> {
+    value
+        .map(|v| {
+            v.parse::<f64>()
+                .map_err(|_| anyhow!("invalid value for {}; expected number", name))
+        })
+        .transpose()
+}
+
+/// Collects generators from options of the form `fields.<field>.<property>`
+fn generators_from_options(
+    opts: &mut HashMap<String, String>,
+) -> anyhow::Result<Vec<FieldGenerator>> {
+    let mut fields: Vec<String> = opts
+        .keys()
+        .filter_map(|k| k.strip_prefix("fields."))
+        .filter_map(|k| k.rsplit_once('.').map(|(field, _)| field.to_string()))
+        .collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .map(|field| {
+            let opt = |p: &str| format!("fields.{}.{}", field, p);
+
+            let kind = match pull_opt(&opt("kind"), opts)?.as_str() {
+                "sequential" => GeneratorKind::Sequential,
+                "random" => GeneratorKind::Random,
+                "zipfian" => GeneratorKind::Zipfian,
+                "timestamp" => GeneratorKind::Timestamp,
+                other => bail!("invalid value for {} '{}'", opt("kind"), other),
+            };
+
+            Ok(FieldGenerator {
+                kind,
+                min: parse_f64(&opt("min"), opts.remove(&opt("min")))?,
+                max: parse_f64(&opt("max"), opts.remove(&opt("max")))?,
+                length: pull_option_to_i64(&opt("length"), opts)?,
+                cardinality: pull_option_to_i64(&opt("cardinality"), opts)?,
+                exponent: parse_f64(&opt("exponent"), opts.remove(&opt("exponent")))?,
+                field,
+            })
+        })
+        .collect()
+}
+
+fn validate_generator(generator: &FieldGenerator, field: &SourceField) -> anyhow::Result<()> {
+    use PrimitiveType::*;
+
+    let FieldType::Primitive(primitive) = &field.field_type.r#type else {
+        bail!(
+            "datagen can't generate values for struct field '{}'",
+            field.field_name
+        );
+    };
+
+    let numeric = matches!(primitive, Int32 | Int64 | UInt32 | UInt64 | F32 | F64);
+    let timestamp = matches!(primitive, UnixMillis | UnixMicros | UnixNanos | DateTime);
+
+    let valid = match generator.kind {
+        GeneratorKind::Sequential | GeneratorKind::Zipfian => numeric || *primitive == String,
+        GeneratorKind::Random => numeric || timestamp || matches!(primitive, String | Bool),
+        GeneratorKind::Timestamp => timestamp || matches!(primitive, Int64 | String),
+    };
+
+    if !valid {
+        bail!(
+            "{:?} generator can't produce values for field '{}' of type {:?}",
+            generator.kind,
+            field.field_name,
+            primitive
+        );
+    }
+
+    if let (Some(min), Some(max)) = (generator.min, generator.max) {
+        if min > max {
+            bail!(
+                "min must not be greater than max for field '{}'",
+                field.field_name
+            );
+        }
+    }
+
+    if matches!(generator.length, Some(l) if l < 0) {
+        bail!(
+            "length must not be negative for field '{}'",
+            field.field_name
+        );
+    }
+
+    if generator.kind == GeneratorKind::Zipfian {
+        if !matches!(generator.cardinality, Some(c) if c >= 1) {
+            bail!(
+                "zipfian generator for field '{}' requires a cardinality of at least 1",
+                field.field_name
+            );
+        }
+
+        if matches!(generator.exponent, Some(e) if e < 0.0) {
+            bail!(
+                "exponent must not be negative for field '{}'",
+                field.field_name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+impl Connector for DatagenConnector {
+    type ProfileT = EmptyConfig;
+    type TableT = DatagenTable;
+
+    fn name(&self) -> &'static str {
+        "datagen"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "datagen".to_string(),
+            name: "Datagen".to_string(),
+            icon: ICON.to_string(),
+            description: "Generate synthetic rows for benchmarks and demos".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(Ok(Event::default().json_data(message).unwrap()))
+                .await
+                .unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let event_rate = pull_opt("event_rate", opts)?
+            .parse::<f64>()
+            .map_err(|_| anyhow!("invalid value for event_rate; expected float"))?;
+
+        let table = DatagenTable {
+            event_rate,
+            message_count: pull_option_to_i64("message_count", opts)?,
+            timestamp_skew_ms: pull_option_to_i64("timestamp_skew_ms", opts)?,
+            generators: generators_from_options(opts)?,
+        };
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if table.event_rate <= 0.0 {
+            bail!("event_rate must be greater than 0");
+        }
+
+        if matches!(table.message_count, Some(c) if c < 0) {
+            bail!("message_count must not be negative");
+        }
+
+        if matches!(table.timestamp_skew_ms, Some(s) if s < 0) {
+            bail!("timestamp_skew_ms must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for datagen source"))?;
+
+        if schema.fields.is_empty() {
+            bail!("datagen tables must declare the fields to generate");
+        }
+
+        for field in &schema.fields {
+            let generator = table
+                .generators
+                .iter()
+                .find(|g| g.field == field.field_name);
+
+            match generator {
+                Some(generator) => validate_generator(generator, field)?,
+                None => {
+                    // fields without a generator get random values, so they must support them
+                    let FieldType::Primitive(p) = &field.field_type.r#type else {
+                        bail!(
+                            "datagen can't generate values for struct field '{}'",
+                            field.field_name
+                        );
+                    };
+                    if matches!(p, PrimitiveType::Bytes | PrimitiveType::Json) {
+                        bail!(
+                            "datagen can't generate values for field '{}' of type {:?}",
+                            field.field_name,
+                            p
+                        );
+                    }
+                }
+            }
+        }
+
+        for generator in &table.generators {
+            if !schema
+                .fields
+                .iter()
+                .any(|f| f.field_name == generator.field)
+            {
+                bail!(
+                    "generator refers to field '{}', which is not in the schema",
+                    generator.field
+                );
+            }
+        }
+
+        // rows are generated as JSON, so that they can be deserialized into any schema
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..Default::default()
+            }),
+            Some(Format::Json(f)) if !f.unstructured && !f.debezium => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..f.clone()
+            }),
+            Some(_) => bail!("datagen tables must use the 'json' format"),
+        };
+
+        let description = format!(
+            "{}Datagen<{} eps>",
+            if table.message_count.is_some() {
+                "Bounded"
+            } else {
+                ""
+            },
+            table.event_rate
+        );
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::datagen::DatagenSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
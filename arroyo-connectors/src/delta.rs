@@ -0,0 +1,168 @@
+use anyhow::{anyhow, bail, Result};
+use arroyo_storage::BackendConfig;
+use axum::response::sse::Event;
+use std::convert::Infallible;
+
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::Format;
+use arroyo_rpc::OperatorConfig;
+
+use crate::filesystem::{
+    table_from_options, DeltaSettings, FileSystemTable, FormatSettings, SchemaEvolution,
+};
+use crate::{Connection, EmptyConfig};
+
+use super::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/filesystem/table.json");
+const ICON: &str = include_str!("../resources/delta.svg");
+
+pub struct DeltaLakeConnector {}
+
+impl Connector for DeltaLakeConnector {
+    type ProfileT = EmptyConfig;
+
+    type TableT = FileSystemTable;
+
+    fn name(&self) -> &'static str {
+        "delta"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "delta".to_string(),
+            name: "Delta Lake".to_string(),
+            icon: ICON.to_string(),
+            description: "Write to a Delta Lake table".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: false,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_owned(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: tokio::sync::mpsc::Sender<Result<Event, Infallible>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = TestSourceMessage {
+                error: false,
+                done: true,
+                message: "Successfully validated connection".to_string(),
+            };
+            tx.send(Ok(Event::default().json_data(message).unwrap()))
+                .await
+                .unwrap();
+        });
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        BackendConfig::parse_url(&table.write_target.path, true)?;
+
+        let Some(FormatSettings::Parquet { .. }) = &table.format_settings else {
+            bail!("Delta Lake tables must use the parquet format");
+        };
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Delta Lake connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for Delta Lake connection"))?;
+
+        if let Some(partitioning) = table
+            .file_settings
+            .as_ref()
+            .and_then(|f| f.partitioning.as_ref())
+        {
+            // Delta partitions are column values recorded in the log, so there's no equivalent
+            // to a partition directory derived from the event time
+            if partitioning.time_partition_pattern.is_some() {
+                bail!(
+                    "time_partition_pattern is not supported for Delta Lake tables; partition \
+                    by a column instead"
+                );
+            }
+
+            for field in &partitioning.partition_fields {
+                if !schema.fields.is_empty()
+                    && !schema.fields.iter().any(|f| &f.field_name == field)
+                {
+                    bail!("partition field '{}' is not a field in the schema", field);
+                }
+            }
+        }
+
+        let description = format!("DeltaLake<{}>", table.write_target.path);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema,
+            operator: "connectors::filesystem::delta::DeltaFileSystemSink::<#in_k, #in_t, #in_tRecordBatchBuilder>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        if !matches!(
+            schema.and_then(|s| s.format.as_ref()),
+            Some(Format::Parquet(..))
+        ) {
+            bail!("Delta Lake tables must use the parquet format");
+        }
+
+        let schema_evolution = match opts.remove("schema_evolution").as_ref().map(|f| f.as_str()) {
+            Some("fail") => Some(SchemaEvolution::Fail),
+            Some("merge") => Some(SchemaEvolution::Merge),
+            None => None,
+            Some(other) => bail!("invalid value for schema_evolution '{}'", other),
+        };
+
+        let mut table = table_from_options(opts, schema)?;
+        table.delta_settings = Some(DeltaSettings { schema_evolution });
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+}
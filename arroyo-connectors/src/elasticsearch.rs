@@ -0,0 +1,270 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/elasticsearch/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/elasticsearch/table.json");
+const ICON: &str = include_str!("../resources/elasticsearch.svg");
+
+import_types!(schema = "../connector-schemas/elasticsearch/connection.json");
+import_types!(schema = "../connector-schemas/elasticsearch/table.json");
+
+pub struct ElasticsearchConnector {}
+
+impl Connector for ElasticsearchConnector {
+    type ProfileT = ElasticsearchConfig;
+    type TableT = ElasticsearchTable;
+
+    fn name(&self) -> &'static str {
+        "elasticsearch"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "elasticsearch".to_string(),
+            name: "Elasticsearch".to_string(),
+            icon: ICON.to_string(),
+            description: "Index records into Elasticsearch or OpenSearch".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.endpoint
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = ElasticsearchTester { config, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = ElasticsearchConfig {
+            endpoint: pull_opt("endpoint", opts)?,
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+            api_key: opts.remove("api_key"),
+        };
+
+        let table = ElasticsearchTable {
+            index: pull_opt("index", opts)?,
+            id_field: opts.remove("id_field"),
+            bulk_size: pull_option_to_i64("bulk_size", opts)?,
+            flush_interval_ms: pull_option_to_i64("flush_interval_ms", opts)?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        reqwest::Url::parse(&config.endpoint)
+            .map_err(|e| anyhow!("invalid endpoint '{}': {}", config.endpoint, e))?;
+
+        if config.api_key.is_some() && (config.username.is_some() || config.password.is_some()) {
+            bail!("only one of api_key or username/password may be set");
+        }
+
+        if config.password.is_some() && config.username.is_none() {
+            bail!("a username must be set along with the password");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Elasticsearch connection"))?;
+
+        // documents are always JSON
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(json)) => {
+                if json.include_schema || json.confluent_schema_registry {
+                    bail!(
+                        "json.include_schema and json.confluent_schema_registry are not supported \
+                        for Elasticsearch"
+                    );
+                }
+                Format::Json(json.clone())
+            }
+            Some(_) => bail!("Elasticsearch only supports the json format"),
+        };
+
+        if table.index.matches('{').count() != table.index.matches('}').count() {
+            bail!("index '{}' has unbalanced braces", table.index);
+        }
+
+        if let Some(id_field) = &table.id_field {
+            if !schema.fields.is_empty() && !schema.fields.iter().any(|f| &f.field_name == id_field)
+            {
+                bail!("id_field '{}' is not a field in the schema", id_field);
+            }
+        }
+
+        if matches!(table.bulk_size, Some(size) if size < 1) {
+            bail!("bulk_size must be at least 1");
+        }
+
+        if matches!(table.flush_interval_ms, Some(interval) if interval < 1) {
+            bail!("flush_interval_ms must be at least 1");
+        }
+
+        let description = format!("ElasticsearchSink<{}>", table.index);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::elasticsearch::ElasticsearchSinkFunc::<#in_k, #in_t>"
+                .to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+struct ElasticsearchTester {
+    config: ElasticsearchConfig,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl ElasticsearchTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let mut request = client.get(&self.config.endpoint);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.header("Authorization", format!("ApiKey {}", api_key));
+        } else if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", self.config.endpoint, e))?;
+
+        let status = response.status();
+        if status.as_u16() == 401 || status.as_u16() == 403 {
+            bail!("Authentication failed ({})", status);
+        }
+        if !status.is_success() {
+            bail!("Cluster responded with {}", status);
+        }
+
+        let info: Value = serde_json::from_slice(&response.bytes().await?)
+            .map_err(|e| anyhow!("Endpoint did not respond with cluster info: {}", e))?;
+
+        let distribution = info
+            .pointer("/version/distribution")
+            .and_then(|d| d.as_str())
+            .unwrap_or("elasticsearch");
+        let version = info
+            .pointer("/version/number")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown");
+
+        self.info(format!("Connected to {} {}", distribution, version))
+            .await;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Elasticsearch tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,283 @@
+use anyhow::{anyhow, bail, Result};
+use arroyo_storage::{BackendConfig, StorageProvider};
+use axum::response::sse::Event;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tokio::sync::mpsc::Sender;
+use tracing::info;
+use typify::import_types;
+
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::Format;
+use arroyo_rpc::OperatorConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{pull_opt, pull_option_to_i64, Connection, EmptyConfig};
+
+use super::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/filesystem_source/table.json");
+const ICON: &str = include_str!("../resources/filesystem_source.svg");
+
+import_types!(schema = "../connector-schemas/filesystem_source/table.json");
+
+pub struct FileSystemSourceConnector {}
+
+impl FileSystemSourceTable {
+    /// The format of the files, which defaults based on the format of the connection's schema
+    pub fn file_format(&self, format: &Format) -> FileFormat {
+        match (&self.file_format, format) {
+            (Some(f), _) => f.clone(),
+            (None, Format::Parquet(_)) => FileFormat::Parquet,
+            (None, Format::Csv(_)) => FileFormat::Csv,
+            (None, _) => FileFormat::Json,
+        }
+    }
+}
+
+struct FileSystemSourceTester {
+    table: FileSystemSourceTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl FileSystemSourceTester {
+    async fn test(&self) -> Result<usize> {
+        let provider = StorageProvider::for_url_with_options(
+            &self.table.path,
+            self.table.storage_options.clone(),
+        )
+        .await
+        .map_err(|e| anyhow!("failed to construct storage provider: {}", e))?;
+
+        let prefix = match provider.config() {
+            BackendConfig::Local(_) => None,
+            _ => StorageProvider::get_key(&self.table.path).ok(),
+        };
+
+        let files = provider
+            .list(prefix.as_deref())
+            .await
+            .map_err(|e| anyhow!("failed to list {}: {}", self.table.path, e))?;
+
+        let pattern = self
+            .table
+            .pattern
+            .as_ref()
+            .map(|p| regex::Regex::new(p))
+            .transpose()?;
+
+        Ok(files
+            .iter()
+            .filter(|f| {
+                pattern
+                    .as_ref()
+                    .map(|p| p.is_match(f.location.as_ref()))
+                    .unwrap_or(true)
+            })
+            .count())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        self.tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .unwrap();
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started FileSystem source tester");
+            self.info("Listing files").await;
+
+            let message = match self.test().await {
+                Ok(count) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: format!("Found {} files to read in {}", count, self.table.path),
+                },
+                Err(e) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                },
+            };
+
+            self.send(message).await;
+        });
+    }
+}
+
+impl Connector for FileSystemSourceConnector {
+    type ProfileT = EmptyConfig;
+
+    type TableT = FileSystemSourceTable;
+
+    fn name(&self) -> &'static str {
+        "filesystem_source"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "filesystem_source".to_string(),
+            name: "FileSystem Source".to_string(),
+            icon: ICON.to_string(),
+            description: "Read files from a directory or bucket (like S3 or GCS) as they arrive"
+                .to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_owned(),
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        FileSystemSourceTester { table, tx }.start();
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        BackendConfig::parse_url(&table.path, false)
+            .map_err(|_| anyhow!("'{}' is not a valid path or object store URL", table.path))?;
+
+        if let Some(pattern) = &table.pattern {
+            regex::Regex::new(pattern)
+                .map_err(|e| anyhow!("invalid pattern '{}': {}", pattern, e))?;
+        }
+
+        if table.sqs_queue_url.is_some()
+            && !matches!(
+                BackendConfig::parse_url(&table.path, false)?,
+                BackendConfig::S3(_)
+            )
+        {
+            bail!("sqs_queue_url can only be used with S3 paths");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for FileSystem source"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for FileSystem source"))?;
+
+        match (table.file_format(&format), &format) {
+            (FileFormat::Parquet, Format::Parquet(_)) => {}
+            (FileFormat::Parquet, _) | (_, Format::Parquet(_)) => {
+                bail!("parquet files must be read with the parquet format, and vice versa")
+            }
+            (FileFormat::Csv, Format::Csv(_)) => {}
+            (FileFormat::Csv, Format::Json(json)) if !json.unstructured && !json.debezium => {}
+            (FileFormat::Csv, _) => bail!("csv files must be read with the csv or json format"),
+            (FileFormat::Json, Format::Csv(_)) => {
+                bail!("the csv format can only be used to read csv files")
+            }
+            (FileFormat::Json, _) => {}
+        }
+
+        let description = format!("FileSystemSource<{}>", table.file_format(&format));
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            operator: "connectors::filesystem::source::FileSystemSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        let storage_options: HashMap<String, String> = opts
+            .iter()
+            .filter(|(k, _)| k.starts_with("storage."))
+            .map(|(k, v)| (k.trim_start_matches("storage.").to_string(), v.to_string()))
+            .collect();
+        opts.retain(|k, _| !k.starts_with("storage."));
+
+        let path = pull_opt("path", opts)?;
+
+        let file_format = opts
+            .remove("file_format")
+            .map(|f| match f.as_str() {
+                "json" => Ok(FileFormat::Json),
+                "csv" => Ok(FileFormat::Csv),
+                "parquet" => Ok(FileFormat::Parquet),
+                other => bail!(
+                    "invalid file_format '{}'; expected json, csv or parquet",
+                    other
+                ),
+            })
+            .transpose()?;
+
+        let scan_mode = opts
+            .remove("scan_mode")
+            .map(|m| match m.as_str() {
+                "continuous" => Ok(ScanMode::Continuous),
+                "once" => Ok(ScanMode::Once),
+                other => bail!("invalid scan_mode '{}'; expected continuous or once", other),
+            })
+            .transpose()?;
+
+        let table = FileSystemSourceTable {
+            path,
+            storage_options,
+            file_format,
+            pattern: opts.remove("pattern"),
+            scan_mode,
+            poll_interval_ms: pull_option_to_i64("poll_interval_ms", opts)?,
+            sqs_queue_url: opts.remove("sqs_queue_url"),
+        };
+
+        self.from_config(None, name, EmptyConfig {}, table, schema)
+    }
+}
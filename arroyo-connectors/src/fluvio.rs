@@ -147,8 +147,11 @@ impl Connector for FluvioConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
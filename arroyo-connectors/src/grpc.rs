@@ -0,0 +1,295 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::string_to_map;
+use axum::response::sse::Event;
+use prost_reflect::{DescriptorPool, MethodDescriptor};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tracing::info;
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/grpc/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/grpc/table.json");
+const ICON: &str = include_str!("../resources/grpc.svg");
+
+import_types!(schema = "../connector-schemas/grpc/connection.json");
+import_types!(schema = "../connector-schemas/grpc/table.json");
+
+pub struct GrpcConnector {}
+
+/// Finds the table's method in its descriptor set, checking that it streams in the right
+/// direction for the table type
+fn method(table: &GrpcTable) -> anyhow::Result<MethodDescriptor> {
+    let bytes = base64::decode(table.descriptor_set.trim())
+        .map_err(|e| anyhow!("descriptor_set is not valid base64: {}", e))?;
+    let pool = DescriptorPool::decode(bytes.as_slice())
+        .map_err(|e| anyhow!("descriptor_set is not a valid FileDescriptorSet: {}", e))?;
+
+    let (service, method) = table.method.rsplit_once('/').ok_or_else(|| {
+        anyhow!(
+            "method '{}' should have the form <package>.<Service>/<Method>",
+            table.method
+        )
+    })?;
+
+    let method = pool
+        .get_service_by_name(service)
+        .ok_or_else(|| anyhow!("service '{}' is not in the descriptor set", service))?
+        .methods()
+        .find(|m| m.name() == method)
+        .ok_or_else(|| anyhow!("service '{}' has no method '{}'", service, method))?;
+
+    match &table.type_ {
+        TableType::Source { .. }
+            if !method.is_server_streaming() || method.is_client_streaming() =>
+        {
+            bail!(
+                "sources must call a server-streaming method, but {} is not",
+                table.method
+            )
+        }
+        TableType::Sink {} if !method.is_client_streaming() || method.is_server_streaming() => {
+            bail!(
+                "sinks must call a client-streaming method, but {} is not",
+                table.method
+            )
+        }
+        _ => {}
+    }
+
+    Ok(method)
+}
+
+impl Connector for GrpcConnector {
+    type ProfileT = GrpcConfig;
+    type TableT = GrpcTable;
+
+    fn name(&self) -> &'static str {
+        "grpc"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "grpc".to_string(),
+            name: "gRPC".to_string(),
+            icon: ICON.to_string(),
+            description:
+                "Read from a server-streaming gRPC method or write to a client-streaming one"
+                    .to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.endpoint
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink {} => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = GrpcTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = GrpcConfig {
+            endpoint: pull_opt("endpoint", opts)?,
+            headers: opts.remove("headers").map(Headers),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                request: opts.remove("source.request"),
+            },
+            "sink" => TableType::Sink {},
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = GrpcTable {
+            method: pull_opt("method", opts)?,
+            descriptor_set: pull_opt("descriptor_set", opts)?,
+            type_: table_type,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        Endpoint::from_shared(config.endpoint.clone())
+            .map_err(|e| anyhow!("invalid endpoint '{}': {}", config.endpoint, e))?;
+
+        if let Some(headers) = &config.headers {
+            string_to_map(headers).ok_or_else(|| {
+                anyhow!(
+                    "Invalid format for headers; should be a \
+                    comma-separated list of colon-separated key value pairs"
+                )
+            })?;
+        }
+
+        let method = method(&table)?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for gRPC connection"))?;
+
+        let (typ, operator, desc, message) = match &table.type_ {
+            TableType::Source { request } => {
+                if let Some(request) = request {
+                    serde_json::from_str::<serde_json::Value>(request)
+                        .map_err(|e| anyhow!("request is not valid JSON: {}", e))?;
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::grpc::source::GrpcSourceFunc",
+                    format!("GrpcSource<{}>", table.method),
+                    method.output(),
+                )
+            }
+            TableType::Sink {} => (
+                ConnectionType::Sink,
+                "connectors::grpc::sink::GrpcSinkFunc::<#in_k, #in_t>",
+                format!("GrpcSink<{}>", table.method),
+                method.input(),
+            ),
+        };
+
+        for field in &schema.fields {
+            if message.get_field_by_name(&field.field_name).is_none() {
+                bail!(
+                    "field '{}' is not in message {}",
+                    field.field_name,
+                    message.full_name()
+                );
+            }
+        }
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: schema.format.clone(),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+struct GrpcTester {
+    config: GrpcConfig,
+    table: GrpcTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl GrpcTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let method = method(&self.table)?;
+        self.info(format!("Found method {}", method.full_name()))
+            .await;
+
+        let mut endpoint = Endpoint::from_shared(self.config.endpoint.clone())?
+            .connect_timeout(Duration::from_secs(10));
+        if self.config.endpoint.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        endpoint
+            .connect()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", self.config.endpoint, e))?;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        self.tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .unwrap();
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started gRPC tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,203 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::string_to_map;
+use axum::response::sse::Event;
+use tokio::sync::mpsc::Sender;
+use typify::import_types;
+
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::{construct_http_client, pull_opt, pull_option_to_i64, Connection, EmptyConfig};
+
+use super::Connector;
+
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/http_lookup/table.json");
+
+import_types!(schema = "../connector-schemas/http_lookup/table.json");
+const ICON: &str = include_str!("../resources/http.svg");
+
+pub struct HttpLookupConnector {}
+
+impl HttpLookupConnector {
+    async fn test_int(
+        config: &HttpLookupTable,
+        tx: Sender<Result<Event, Infallible>>,
+    ) -> anyhow::Result<()> {
+        let endpoint = render_template(&config.endpoint, "test");
+        let client = construct_http_client(&endpoint, config.headers.as_ref().map(|t| &t.0))?;
+
+        tx.send(Ok(Event::default()
+            .json_data(TestSourceMessage {
+                error: false,
+                done: false,
+                message: "Requesting a test key".to_string(),
+            })
+            .unwrap()))
+            .await
+            .unwrap();
+
+        let response = client
+            .get(&endpoint)
+            .send()
+            .await
+            .map_err(|e| anyhow!("HTTP request failed: {}", e))?;
+
+        // the test key is unlikely to exist, so a 404 is fine
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            bail!("request failed with status {}", response.status());
+        }
+
+        Ok(())
+    }
+}
+
+impl Connector for HttpLookupConnector {
+    type ProfileT = EmptyConfig;
+
+    type TableT = HttpLookupTable;
+
+    fn name(&self) -> &'static str {
+        "http_lookup"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "http_lookup".to_string(),
+            name: "HTTP Lookup".to_string(),
+            icon: ICON.to_string(),
+            description: "Look up rows from an HTTP endpoint in lookup joins".to_string(),
+            enabled: true,
+            source: false,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: None,
+            table_config: TABLE_SCHEMA.to_owned(),
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Lookup
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        tokio::task::spawn(async move {
+            let message = match Self::test_int(&table, tx.clone()).await {
+                Ok(_) => TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Successfully validated lookup endpoint".to_string(),
+                },
+                Err(err) => TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: format!("{:?}", err),
+                },
+            };
+
+            tx.send(Ok(Event::default().json_data(message).unwrap()))
+                .await
+                .unwrap();
+        });
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        let endpoint = pull_opt("endpoint", opts)?;
+        let headers = opts.remove("headers");
+
+        self.from_config(
+            None,
+            name,
+            EmptyConfig {},
+            HttpLookupTable {
+                endpoint,
+                headers: headers.map(Headers),
+                max_concurrency: pull_option_to_i64("max_concurrency", opts)?,
+            },
+            schema,
+        )
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<crate::Connection> {
+        let description = format!("HttpLookup<{}>", table.endpoint);
+
+        if !table.endpoint.contains("{{") {
+            bail!("'endpoint' must contain {{{{ key }}}}, which is replaced with the join key");
+        }
+
+        if let Some(headers) = &table.headers {
+            string_to_map(headers).ok_or_else(|| {
+                anyhow!(
+                    "Invalid format for headers; should be a \
+                    comma-separated list of colon-separated key value pairs"
+                )
+            })?;
+        }
+
+        if matches!(table.max_concurrency, Some(n) if n < 1) {
+            bail!("'max_concurrency' must be at least 1");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for HTTP lookup connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for HTTP lookup connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Lookup,
+            schema,
+            operator: "connectors::http_lookup::HttpLookup".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+/// Substitutes `{{ key }}` in the endpoint, as the lookup does for each key
+fn render_template(template: &str, key: &str) -> String {
+    regex::Regex::new(r"\{\{\s*key\s*\}\}")
+        .unwrap()
+        .replace_all(template, key)
+        .to_string()
+}
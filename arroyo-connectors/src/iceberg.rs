@@ -0,0 +1,337 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, ParquetFormat};
+use arroyo_rpc::OperatorConfig;
+use arroyo_storage::BackendConfig;
+use axum::response::sse::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/iceberg/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/iceberg/table.json");
+const ICON: &str = include_str!("../resources/iceberg.svg");
+
+import_types!(schema = "../connector-schemas/iceberg/connection.json");
+import_types!(schema = "../connector-schemas/iceberg/table.json");
+
+pub struct IcebergConnector {}
+
+impl Connector for IcebergConnector {
+    type ProfileT = IcebergConfig;
+    type TableT = IcebergTable;
+
+    fn name(&self) -> &'static str {
+        "iceberg"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "iceberg".to_string(),
+            name: "Iceberg".to_string(),
+            icon: ICON.to_string(),
+            description: "Read from and write to Apache Iceberg tables in a REST or Glue catalog"
+                .to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        match config.catalog {
+            Catalog::Rest { url, .. } => url,
+            Catalog::Glue { region, .. } => {
+                format!("glue:{}", region.as_deref().unwrap_or("default"))
+            }
+        }
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Sink { .. } => ConnectionType::Sink,
+            TableType::Source { .. } => ConnectionType::Source,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = IcebergTester { config, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let catalog = match pull_opt("catalog", opts)?.as_str() {
+            "rest" => Catalog::Rest {
+                url: pull_opt("catalog.url", opts)?,
+                warehouse: opts.remove("catalog.warehouse"),
+                token: opts.remove("catalog.token"),
+            },
+            "glue" => Catalog::Glue {
+                region: opts.remove("catalog.region"),
+                catalog_id: opts.remove("catalog.catalog_id"),
+            },
+            other => bail!(
+                "invalid value for catalog '{}'; expected one of 'rest' or 'glue'",
+                other
+            ),
+        };
+
+        let storage_options = opts
+            .iter()
+            .filter(|(k, _)| k.starts_with("storage."))
+            .map(|(k, v)| (k.trim_start_matches("storage.").to_string(), v.to_string()))
+            .collect();
+        opts.retain(|k, _| !k.starts_with("storage."));
+
+        let typ = match pull_opt("type", opts)?.as_str() {
+            "sink" => TableType::Sink {
+                location: pull_opt("location", opts)?,
+                partition_fields: opts
+                    .remove("partition_fields")
+                    .map(|fields| fields.split(',').map(|f| f.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                rollover_seconds: pull_option_to_i64("rollover_seconds", opts)?,
+                target_file_size: pull_option_to_i64("target_file_size", opts)?,
+            },
+            "source" => TableType::Source {
+                snapshot_id: pull_option_to_i64("snapshot_id", opts)?,
+                start_snapshot_id: pull_option_to_i64("start_snapshot_id", opts)?,
+                timestamp_column: opts.remove("timestamp_column"),
+            },
+            _ => bail!("type must be one of 'source' or 'sink'"),
+        };
+
+        let table = IcebergTable {
+            namespace: pull_opt("namespace", opts)?,
+            table_name: pull_opt("table_name", opts)?,
+            storage_options,
+            type_: typ,
+        };
+
+        Self::from_config(&self, None, name, IcebergConfig { catalog }, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Iceberg connection"))?;
+
+        let format = match &schema.format {
+            None => Format::Parquet(ParquetFormat {}),
+            Some(Format::Parquet(f)) => Format::Parquet(f.clone()),
+            Some(_) => bail!("Iceberg tables must use the parquet format"),
+        };
+
+        let (connection_type, operator, description) = match &table.type_ {
+            TableType::Sink {
+                location,
+                partition_fields,
+                ..
+            } => {
+                BackendConfig::parse_url(location, true)?;
+
+                for field in partition_fields {
+                    if !schema.fields.is_empty()
+                        && !schema.fields.iter().any(|f| &f.field_name == field)
+                    {
+                        bail!("partition field '{}' is not a field in the schema", field);
+                    }
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::iceberg::sink::IcebergSink::<#in_k, #in_t, #in_tRecordBatchBuilder>",
+                    format!("IcebergSink<{}.{}>", table.namespace, table.table_name),
+                )
+            }
+            TableType::Source {
+                snapshot_id,
+                start_snapshot_id,
+                timestamp_column,
+            } => {
+                // columns are read by name, so we need to know which ones to read
+                if schema.fields.is_empty() {
+                    bail!("a schema must be defined for Iceberg sources");
+                }
+
+                if let Some(column) = timestamp_column {
+                    let Some(field) = schema.fields.iter().find(|f| &f.field_name == column) else {
+                        bail!("timestamp_column '{}' is not a field in the schema", column);
+                    };
+
+                    if !matches!(
+                        field.field_type.r#type,
+                        FieldType::Primitive(PrimitiveType::UnixMillis)
+                            | FieldType::Primitive(PrimitiveType::UnixMicros)
+                            | FieldType::Primitive(PrimitiveType::UnixNanos)
+                            | FieldType::Primitive(PrimitiveType::DateTime)
+                    ) {
+                        bail!("timestamp_column '{}' must be a timestamp", column);
+                    }
+                }
+
+                if let (Some(start), Some(end)) = (start_snapshot_id, snapshot_id) {
+                    if start == end {
+                        bail!("start_snapshot_id must be different from snapshot_id");
+                    }
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::iceberg::source::IcebergSourceFunc",
+                    format!("IcebergSource<{}.{}>", table.namespace, table.table_name),
+                )
+            }
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+struct IcebergTester {
+    config: IcebergConfig,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl IcebergTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        match &self.config.catalog {
+            Catalog::Rest {
+                url,
+                warehouse,
+                token,
+            } => {
+                let client = reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()?;
+
+                let mut request = client.get(format!("{}/v1/config", url.trim_end_matches('/')));
+                if let Some(warehouse) = warehouse {
+                    request = request.query(&[("warehouse", warehouse)]);
+                }
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+
+                let response = request
+                    .send()
+                    .await
+                    .map_err(|e| anyhow!("Failed to connect to the REST catalog: {}", e))?;
+
+                if !response.status().is_success() {
+                    bail!(
+                        "REST catalog returned {} when fetching its configuration",
+                        response.status()
+                    );
+                }
+
+                self.info("Connected to REST catalog").await;
+            }
+            Catalog::Glue { .. } => {
+                self.info(
+                    "Glue credentials are loaded from the environment of the workers, and will \
+                    be checked when the pipeline starts",
+                )
+                .await;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Iceberg tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
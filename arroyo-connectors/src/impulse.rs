@@ -21,6 +21,8 @@ pub fn impulse_schema() -> ConnectionSchema {
     ConnectionSchema {
         format: None,
         framing: None,
+        bad_data: None,
+        compression: None,
         struct_name: Some("arroyo_types::ImpulseEvent".to_string()),
         fields: vec![
             source_field("counter", Primitive(PrimitiveType::UInt64)),
@@ -157,8 +159,11 @@ impl Connector for ImpulseConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: None,
             framing: None,
+            bad_data: None,
+            compression: None,
         };
 
         Ok(Connection {
@@ -1,23 +1,36 @@
 use anyhow::{anyhow, bail};
-use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{OperatorConfig, SinkMode};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
 use typify::import_types;
 
 use axum::response::sse::Event;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use arroyo_rpc::api_types::connections::{ConnectionSchema, TestSourceMessage};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, FieldType, PrimitiveType, SourceField, TestSourceMessage,
+};
+use arroyo_rpc::formats::{AvroFormat, Format, JsonFormat};
+use aws_sigv4::http_request::{
+    sign, SignableRequest, SignatureLocation, SigningParams, SigningSettings,
+};
+use aws_types::credentials::ProvideCredentials;
+use aws_types::region::Region;
 use rdkafka::{
-    consumer::{BaseConsumer, Consumer},
-    message::BorrowedMessage,
-    ClientConfig, Offset, TopicPartitionList,
+    client::OAuthToken,
+    consumer::{BaseConsumer, Consumer, ConsumerContext},
+    message::{BorrowedMessage, Message},
+    ClientConfig, ClientContext, Offset, TopicPartitionList,
 };
+use regex::Regex;
 use tokio::sync::mpsc::Sender;
 use tonic::Status;
 use tracing::{error, info, warn};
 
-use crate::{pull_opt, Connection, ConnectionType};
+use crate::testing::validate_messages;
+use crate::{pull_opt, pull_option_to_i64, Connection, ConnectionType, SAMPLE_TIMEOUT};
 
 use super::Connector;
 
@@ -67,6 +80,22 @@ impl Connector for KafkaConnector {
         table: KafkaTable,
         schema: Option<&ConnectionSchema>,
     ) -> anyhow::Result<Connection> {
+        if let TableType::Source {
+            topic_pattern,
+            partition_discovery_interval_ms,
+            ..
+        } = &table.type_
+        {
+            if topic_pattern.unwrap_or(false) {
+                topic_regex(&table.topic)
+                    .map_err(|e| anyhow!("invalid topic pattern '{}': {}", table.topic, e))?;
+            }
+
+            if matches!(partition_discovery_interval_ms, Some(i) if *i < 0) {
+                bail!("partition_discovery_interval_ms must not be negative");
+            }
+        }
+
         let (typ, operator, desc) = match table.type_ {
             TableType::Source { .. } => (
                 ConnectionType::Source,
@@ -90,12 +119,46 @@ impl Connector for KafkaConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for Kafka connection"))?;
 
+        validate_metadata_fields(&table.type_, &schema)?;
+
+        if config.schema_registry.is_none() {
+            match (&format, &table.type_) {
+                (
+                    Format::Avro(AvroFormat {
+                        confluent_schema_registry: true,
+                        ..
+                    })
+                    | Format::Protobuf(_),
+                    _,
+                ) => {
+                    bail!(
+                        "a schema registry must be configured on the connection to use this format"
+                    )
+                }
+                // the JSON sink needs to register its schema, while the source can just skip the
+                // schema id
+                (
+                    Format::Json(JsonFormat {
+                        confluent_schema_registry: true,
+                        ..
+                    }),
+                    TableType::Sink { .. },
+                ) => {
+                    bail!("a schema registry must be configured on the connection to write JSON with confluent_schema_registry")
+                }
+                _ => {}
+            }
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
@@ -114,18 +177,42 @@ impl Connector for KafkaConnector {
         _: &str,
         config: Self::ProfileT,
         table: Self::TableT,
-        _: Option<&ConnectionSchema>,
+        schema: Option<&ConnectionSchema>,
         tx: Sender<Result<Event, Infallible>>,
     ) {
         let tester = KafkaTester {
             connection: config,
             table,
+            schema: schema.cloned(),
             tx,
         };
 
         tester.start();
     }
 
+    fn sample(
+        &self,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        count: usize,
+    ) -> BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>> {
+        // sampling doesn't report progress, so the tester's channel is never read
+        let (tx, _) = tokio::sync::mpsc::channel(1);
+        let tester = KafkaTester {
+            connection: config,
+            table,
+            schema: None,
+            tx,
+        };
+
+        Box::pin(async move {
+            tester
+                .sample(count, SAMPLE_TIMEOUT)
+                .await
+                .map_err(|e| anyhow!(e))
+        })
+    }
+
     fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
         match table.type_ {
             TableType::Source { .. } => ConnectionType::Source,
@@ -133,6 +220,24 @@ impl Connector for KafkaConnector {
         }
     }
 
+    fn validate_sink_mode(&self, table: Self::TableT, mode: SinkMode) -> anyhow::Result<()> {
+        match (mode, &table.type_) {
+            (
+                SinkMode::Upsert,
+                TableType::Sink {
+                    key_field: None, ..
+                },
+            ) => {
+                bail!("upsert Kafka sinks must set 'sink.key_field', which messages are keyed by")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_partition_watermarks(&self, _: Self::TableT) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     fn from_options(
         &self,
         name: &str,
@@ -148,12 +253,30 @@ impl Connector for KafkaConnector {
                 username: pull_opt("auth.username", opts)?,
                 password: pull_opt("auth.password", opts)?,
             },
+            Some("oauth") => KafkaConfigAuthentication::OAuth {
+                token_endpoint: pull_opt("auth.token_endpoint", opts)?,
+                client_id: pull_opt("auth.client_id", opts)?,
+                client_secret: pull_opt("auth.client_secret", opts)?,
+                scope: opts.remove("auth.scope"),
+            },
+            Some("aws_msk_iam") => KafkaConfigAuthentication::AwsMskIam {
+                region: pull_opt("auth.region", opts)?,
+            },
             Some(other) => bail!("unknown auth type '{}'", other),
         };
 
+        let schema_registry =
+            opts.remove("schema_registry.endpoint")
+                .map(|endpoint| SchemaRegistry {
+                    endpoint,
+                    api_key: opts.remove("schema_registry.api_key"),
+                    api_secret: opts.remove("schema_registry.api_secret"),
+                });
+
         let connection = KafkaConfig {
             authentication: auth,
             bootstrap_servers: BootstrapServers(pull_opt("bootstrap_servers", opts)?),
+            schema_registry,
         };
 
         let typ = pull_opt("type", opts)?;
@@ -172,6 +295,40 @@ impl Connector for KafkaConnector {
                         Some(other) => bail!("invalid value for source.read_mode '{}'", other),
                     },
                     group_id: opts.remove("source.group_id"),
+                    commit_offsets: match opts
+                        .remove("source.commit_offsets")
+                        .as_ref()
+                        .map(|f| f.as_str())
+                    {
+                        Some("true") => Some(true),
+                        Some("false") => Some(false),
+                        None => None,
+                        Some(other) => {
+                            bail!("invalid value for source.commit_offsets '{}'", other)
+                        }
+                    },
+                    topic_pattern: match opts
+                        .remove("source.topic_pattern")
+                        .as_ref()
+                        .map(|f| f.as_str())
+                    {
+                        Some("true") => Some(true),
+                        Some("false") => Some(false),
+                        None => None,
+                        Some(other) => {
+                            bail!("invalid value for source.topic_pattern '{}'", other)
+                        }
+                    },
+                    partition_discovery_interval_ms: pull_option_to_i64(
+                        "source.partition_discovery_interval_ms",
+                        opts,
+                    )?,
+                    key_field: opts.remove("source.key_field"),
+                    topic_field: opts.remove("source.topic_field"),
+                    partition_field: opts.remove("source.partition_field"),
+                    offset_field: opts.remove("source.offset_field"),
+                    timestamp_field: opts.remove("source.timestamp_field"),
+                    header_fields: source_header_fields_from_options(opts),
                 }
             }
             "sink" => {
@@ -182,6 +339,17 @@ impl Connector for KafkaConnector {
                         Some("exactly_once") => Some(SinkCommitMode::ExactlyOnce),
                         Some(other) => bail!("invalid value for commit_mode '{}'", other),
                     },
+                    key_field: opts.remove("sink.key_field"),
+                    header_fields: opts
+                        .remove("sink.header_fields")
+                        .map(|fields| {
+                            fields
+                                .split(',')
+                                .map(|f| f.trim().to_string())
+                                .filter(|f| !f.is_empty())
+                                .collect()
+                        })
+                        .unwrap_or_default(),
                 }
             }
             _ => {
@@ -198,9 +366,149 @@ impl Connector for KafkaConnector {
     }
 }
 
+/// Collects header fields from options of the form `source.headers.<field> = '<header>'`
+fn source_header_fields_from_options(opts: &mut HashMap<String, String>) -> Vec<SourceHeaderField> {
+    let mut fields: Vec<_> = opts
+        .keys()
+        .filter_map(|k| k.strip_prefix("source.headers."))
+        .map(|f| f.to_string())
+        .collect();
+    fields.sort();
+
+    fields
+        .into_iter()
+        .map(|field| SourceHeaderField {
+            header: opts.remove(&format!("source.headers.{}", field)).unwrap(),
+            field,
+        })
+        .collect()
+}
+
+fn find_field<'a>(schema: &'a ConnectionSchema, name: &str) -> anyhow::Result<&'a SourceField> {
+    schema
+        .fields
+        .iter()
+        .find(|f| f.field_name == name)
+        .ok_or_else(|| anyhow!("field '{}' is not in the schema", name))
+}
+
+/// Checks that the fields that are read from or written to message metadata, rather than the
+/// payload, exist in the schema with types we can convert to
+fn validate_metadata_fields(
+    table_type: &TableType,
+    schema: &ConnectionSchema,
+) -> anyhow::Result<()> {
+    use PrimitiveType::*;
+
+    match table_type {
+        TableType::Source {
+            key_field,
+            topic_field,
+            partition_field,
+            offset_field,
+            timestamp_field,
+            header_fields,
+            ..
+        } => {
+            let metadata_fields: Vec<(&str, &[PrimitiveType], bool)> = key_field
+                .iter()
+                .map(|f| (f.as_str(), &[String][..], true))
+                .chain(
+                    topic_field
+                        .iter()
+                        .map(|f| (f.as_str(), &[String][..], false)),
+                )
+                .chain(
+                    partition_field
+                        .iter()
+                        .map(|f| (f.as_str(), &[Int32, Int64][..], false)),
+                )
+                .chain(
+                    offset_field
+                        .iter()
+                        .map(|f| (f.as_str(), &[Int64][..], false)),
+                )
+                .chain(timestamp_field.iter().map(|f| {
+                    (
+                        f.as_str(),
+                        &[UnixMillis, UnixMicros, UnixNanos, DateTime][..],
+                        false,
+                    )
+                }))
+                .chain(
+                    header_fields
+                        .iter()
+                        .map(|h| (h.field.as_str(), &[String][..], true)),
+                )
+                .collect();
+
+            if metadata_fields.is_empty() {
+                return Ok(());
+            }
+
+            // metadata is merged into the deserialized payload, which requires a structured record
+            match &schema.format {
+                Some(Format::Json(json)) if !json.debezium && !json.unstructured => {}
+                Some(Format::Avro(_)) | Some(Format::Protobuf(_)) => {}
+                _ => bail!(
+                    "metadata fields are only supported for tables with the 'json', 'avro', or \
+                    'protobuf' formats"
+                ),
+            }
+
+            let mut seen = HashSet::new();
+            for (name, types, must_be_nullable) in metadata_fields {
+                if !seen.insert(name) {
+                    bail!(
+                        "field '{}' is used for more than one kind of metadata",
+                        name
+                    );
+                }
+
+                let field = find_field(schema, name)?;
+                if !matches!(&field.field_type.r#type, FieldType::Primitive(p) if types.contains(p))
+                {
+                    bail!(
+                        "field '{}' has type {:?}, but must be one of {:?}",
+                        name,
+                        field.field_type.r#type,
+                        types
+                    );
+                }
+
+                // not every message has a key or a given header
+                if must_be_nullable && !field.nullable {
+                    bail!("field '{}' must be nullable", name);
+                }
+            }
+        }
+        TableType::Sink {
+            key_field,
+            header_fields,
+            ..
+        } => {
+            if schema.fields.is_empty() {
+                return Ok(());
+            }
+
+            for field in key_field.iter().chain(header_fields.iter()) {
+                find_field(schema, field)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Topic patterns must match the entire topic name
+fn topic_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
 struct KafkaTester {
     connection: KafkaConfig,
     table: KafkaTable,
+    schema: Option<ConnectionSchema>,
     tx: Sender<Result<Event, Infallible>>,
 }
 
@@ -209,7 +517,7 @@ pub struct TopicMetadata {
 }
 
 impl KafkaTester {
-    async fn connect(&self) -> Result<BaseConsumer, String> {
+    async fn connect(&self) -> Result<BaseConsumer<KafkaContext>, String> {
         let mut client_config = ClientConfig::new();
         client_config
             .set(
@@ -233,10 +541,15 @@ impl KafkaTester {
                 client_config.set("sasl.username", username);
                 client_config.set("sasl.password", password);
             }
+            KafkaConfigAuthentication::OAuth { .. }
+            | KafkaConfigAuthentication::AwsMskIam { .. } => {
+                client_config.set("sasl.mechanism", "OAUTHBEARER");
+                client_config.set("security.protocol", "SASL_SSL");
+            }
         };
 
-        let client: BaseConsumer = client_config
-            .create()
+        let client: BaseConsumer<KafkaContext> = client_config
+            .create_with_context(KafkaContext::new(&self.connection))
             .map_err(|e| format!("Failed to connect: {:?}", e))?;
 
         client
@@ -280,20 +593,57 @@ impl KafkaTester {
         })
     }
 
-    async fn test(&self) -> Result<(), String> {
-        let client = self.connect().await?;
-
-        self.info("Connected to Kafka").await;
-
+    /// Assigns all partitions of the table's topic, or of the topics that match its pattern, to
+    /// the client, starting from the beginning; returns the number of partitions assigned
+    fn assign(&self, client: &BaseConsumer<KafkaContext>) -> Result<usize, String> {
         let topic = self.table.topic.clone();
 
+        let pattern = match &self.table.type_ {
+            TableType::Source {
+                topic_pattern: Some(true),
+                ..
+            } => Some(topic_regex(&topic).map_err(|e| format!("Invalid topic pattern: {}", e))?),
+            _ => None,
+        };
+
         let metadata = client
-            .fetch_metadata(Some(&topic), Duration::from_secs(10))
+            .fetch_metadata(
+                if pattern.is_some() {
+                    None
+                } else {
+                    Some(&topic)
+                },
+                Duration::from_secs(10),
+            )
             .map_err(|e| format!("Failed to fetch metadata: {:?}", e))?;
 
-        self.info("Fetched topic metadata").await;
+        if let Some(pattern) = pattern {
+            let map: HashMap<_, _> = metadata
+                .topics()
+                .iter()
+                .filter(|t| {
+                    t.error().is_none() && !t.name().starts_with("__") && pattern.is_match(t.name())
+                })
+                .flat_map(|t| {
+                    t.partitions()
+                        .iter()
+                        .map(move |p| ((t.name().to_string(), p.id()), Offset::Beginning))
+                })
+                .collect();
 
-        {
+            if map.is_empty() {
+                return Err(format!(
+                    "No topics in the configured Kafka cluster match the pattern '{}'",
+                    topic
+                ));
+            }
+
+            client
+                .assign(&TopicPartitionList::from_topic_map(&map).unwrap())
+                .map_err(|e| format!("Failed to subscribe to topics '{}': {:?}", topic, e))?;
+
+            Ok(map.len())
+        } else {
             let topic_metadata = metadata.topics().get(0).ok_or_else(|| {
                 format!(
                     "Returned metadata was empty; unable to subscribe to topic '{}'",
@@ -321,7 +671,7 @@ impl KafkaTester {
                 }
             }
 
-            let map = topic_metadata
+            let map: HashMap<_, _> = topic_metadata
                 .partitions()
                 .iter()
                 .map(|p| ((topic.clone(), p.id()), Offset::Beginning))
@@ -330,7 +680,23 @@ impl KafkaTester {
             client
                 .assign(&TopicPartitionList::from_topic_map(&map).unwrap())
                 .map_err(|e| format!("Failed to subscribe to topic '{}': {:?}", topic, e))?;
+
+            Ok(map.len())
         }
+    }
+
+    async fn test(&self) -> Result<(), String> {
+        let client = self.connect().await?;
+
+        self.info("Connected to Kafka").await;
+
+        let partitions = self.assign(&client)?;
+
+        self.info(format!(
+            "Fetched topic metadata; found {} partitions",
+            partitions
+        ))
+        .await;
 
         if let TableType::Source { .. } = self.table.type_ {
             self.info("Waiting for messages").await;
@@ -341,7 +707,7 @@ impl KafkaTester {
                 match client.poll(Duration::ZERO) {
                     Some(Ok(message)) => {
                         self.info("Received message from Kafka").await;
-                        self.test_schema(message)?;
+                        self.test_schema(message).await?;
                         return Ok(());
                     }
                     Some(Err(e)) => {
@@ -364,9 +730,41 @@ impl KafkaTester {
         Ok(())
     }
 
-    fn test_schema(&self, _: BorrowedMessage) -> Result<(), String> {
-        // TODO: test the schema against the message
-        Ok(())
+    /// Reads up to `count` messages from the beginning of the topic, waiting at most `timeout`
+    async fn sample(&self, count: usize, timeout: Duration) -> Result<Vec<Vec<u8>>, String> {
+        let client = self.connect().await?;
+        self.assign(&client)?;
+
+        let mut messages = vec![];
+        let start = Instant::now();
+        while messages.len() < count && start.elapsed() < timeout {
+            match client.poll(Duration::ZERO) {
+                Some(Ok(message)) => {
+                    // tombstones have no payload to sample
+                    if let Some(payload) = message.payload() {
+                        messages.push(payload.to_vec());
+                    }
+                }
+                Some(Err(e)) => {
+                    return Err(format!("Error while reading messages from Kafka: {}", e));
+                }
+                None => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    async fn test_schema(&self, message: BorrowedMessage<'_>) -> Result<(), String> {
+        let (Some(schema), Some(payload)) = (&self.schema, message.payload()) else {
+            return Ok(());
+        };
+
+        validate_messages(&self.tx, schema, &[payload.to_vec()])
+            .await
+            .map_err(|e| e.to_string())
     }
 
     async fn info(&self, s: impl Into<String>) {
@@ -426,3 +824,150 @@ impl KafkaTester {
         });
     }
 }
+
+// MSK accepts signed tokens for at most 15 minutes
+const MSK_TOKEN_EXPIRY: Duration = Duration::from_secs(900);
+const DEFAULT_TOKEN_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// Client context that generates OAUTHBEARER tokens for OAuth and MSK IAM authentication
+struct KafkaContext {
+    authentication: KafkaConfigAuthentication,
+}
+
+impl KafkaContext {
+    fn new(connection: &KafkaConfig) -> Self {
+        Self {
+            authentication: connection.authentication.clone(),
+        }
+    }
+}
+
+impl ClientContext for KafkaContext {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn std::error::Error>> {
+        let authentication = self.authentication.clone();
+
+        // tokens are fetched on a separate thread with its own runtime, as this may be called
+        // from within our runtime
+        let token = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(oauth_token(authentication))
+        })
+        .join()
+        .map_err(|_| "OAUTHBEARER token generation panicked")??;
+
+        Ok(token)
+    }
+}
+
+impl ConsumerContext for KafkaContext {}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+async fn oauth_token(authentication: KafkaConfigAuthentication) -> anyhow::Result<OAuthToken> {
+    match authentication {
+        KafkaConfigAuthentication::OAuth {
+            token_endpoint,
+            client_id,
+            client_secret,
+            scope,
+        } => {
+            let mut form = vec![("grant_type", "client_credentials")];
+            if let Some(scope) = &scope {
+                form.push(("scope", scope.as_str()));
+            }
+
+            let resp = reqwest::Client::new()
+                .post(&token_endpoint)
+                .basic_auth(&client_id, Some(&client_secret))
+                .form(&form)
+                .send()
+                .await?;
+
+            let status = resp.status();
+            let body = resp.text().await?;
+            if !status.is_success() {
+                bail!("token endpoint responded with {}: {}", status, body);
+            }
+
+            let resp: TokenResponse = serde_json::from_str(&body)
+                .map_err(|e| anyhow!("invalid response from token endpoint: {}", e))?;
+            let expiry = resp
+                .expires_in
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_TOKEN_EXPIRY);
+
+            Ok(OAuthToken {
+                token: resp.access_token,
+                principal_name: client_id,
+                lifetime_ms: (SystemTime::now() + expiry)
+                    .duration_since(UNIX_EPOCH)?
+                    .as_millis() as i64,
+            })
+        }
+        KafkaConfigAuthentication::AwsMskIam { region } => {
+            let config = aws_config::from_env()
+                .region(Region::new(region.clone()))
+                .load()
+                .await;
+
+            let credentials = config
+                .credentials_provider()
+                .ok_or_else(|| anyhow!("no AWS credentials are configured"))?
+                .provide_credentials()
+                .await?;
+
+            let now = SystemTime::now();
+            let mut settings = SigningSettings::default();
+            settings.signature_location = SignatureLocation::QueryParams;
+            settings.expires_in = Some(MSK_TOKEN_EXPIRY);
+
+            let params = SigningParams::builder()
+                .access_key(credentials.access_key_id())
+                .secret_key(credentials.secret_access_key())
+                .set_security_token(credentials.session_token())
+                .region(&region)
+                .service_name("kafka-cluster")
+                .time(now)
+                .settings(settings)
+                .build()?;
+
+            // MSK IAM tokens are presigned kafka-cluster:Connect requests, base64-encoded
+            let mut request = http::Request::builder()
+                .method("GET")
+                .uri(format!(
+                    "https://kafka.{}.amazonaws.com/?Action=kafka-cluster%3AConnect",
+                    region
+                ))
+                .body("")?;
+
+            let (instructions, _) = sign(SignableRequest::from(&request), &params)
+                .map_err(|e| anyhow!("failed to sign MSK IAM token: {}", e))?
+                .into_parts();
+            instructions.apply_to_request(&mut request);
+
+            let url = format!("{}&User-Agent=arroyo", request.uri());
+
+            Ok(OAuthToken {
+                token: base64::encode_config(url, base64::URL_SAFE_NO_PAD),
+                principal_name: "arroyo".to_string(),
+                lifetime_ms: (now + MSK_TOKEN_EXPIRY)
+                    .duration_since(UNIX_EPOCH)?
+                    .as_millis() as i64,
+            })
+        }
+        KafkaConfigAuthentication::None {} | KafkaConfigAuthentication::Sasl { .. } => {
+            bail!("OAUTHBEARER authentication is not configured for this connection")
+        }
+    }
+}
@@ -103,12 +103,60 @@ impl Connector for KinesisConnector {
             .map(|format| format.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for kinesis connections"))?;
 
+        if let TableType::Sink {
+            partition_key_field,
+            records_per_shard_per_second,
+            ..
+        } = &table.type_
+        {
+            if let Some(field) = partition_key_field {
+                if !schema.fields.is_empty()
+                    && !schema.fields.iter().any(|f| &f.field_name == field)
+                {
+                    bail!(
+                        "partition key field '{}' does not exist in the schema for this table",
+                        field
+                    );
+                }
+            }
+
+            // Kinesis accepts at most 1000 records per second per shard
+            if let Some(rate) = records_per_shard_per_second {
+                if !(1..=1000).contains(rate) {
+                    bail!("sink.records_per_shard_per_second must be between 1 and 1000");
+                }
+            }
+        }
+
+        if let TableType::Source {
+            offset,
+            start_timestamp_millis,
+            ..
+        } = &table.type_
+        {
+            match (offset, start_timestamp_millis) {
+                (SourceOffset::Timestamp, None) => bail!(
+                    "source.start_timestamp_millis must be set when source.offset is 'timestamp'"
+                ),
+                (SourceOffset::Earliest | SourceOffset::Latest, Some(_)) => bail!(
+                    "source.start_timestamp_millis can only be set when source.offset is 'timestamp'"
+                ),
+                (_, Some(t)) if *t < 0 => {
+                    bail!("source.start_timestamp_millis must not be negative")
+                }
+                _ => {}
+            }
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
@@ -132,12 +180,18 @@ impl Connector for KinesisConnector {
         let table_type = match typ.as_str() {
             "source" => {
                 let offset: Option<String> = opts.remove("source.offset");
+                let start_timestamp_millis =
+                    pull_option_to_i64("source.start_timestamp_millis", opts)?;
+                let offset = match offset.as_ref().map(|f| f.as_str()) {
+                    Some("earliest") => SourceOffset::Earliest,
+                    None | Some("latest") => SourceOffset::Latest,
+                    Some("timestamp") => SourceOffset::Timestamp,
+                    Some(other) => bail!("invalid value for source.offset '{}'", other),
+                };
                 TableType::Source {
-                    offset: match offset.as_ref().map(|f| f.as_str()) {
-                        Some("earliest") => SourceOffset::Earliest,
-                        None | Some("latest") => SourceOffset::Latest,
-                        Some(other) => bail!("invalid value for source.offset '{}'", other),
-                    },
+                    offset,
+                    start_timestamp_millis,
+                    fan_out_consumer: opts.remove("source.fan_out_consumer"),
                 }
             }
             "sink" => {
@@ -145,10 +199,23 @@ impl Connector for KinesisConnector {
                     pull_option_to_i64("sink.flush_interval_millis", opts)?;
                 let batch_max_buffer_size = pull_option_to_i64("sink.max_bytes_per_batch", opts)?;
                 let records_per_batch = pull_option_to_i64("sink.max_records_per_batch", opts)?;
+                let aggregate_records = opts
+                    .remove("sink.aggregate_records")
+                    .map(|s| {
+                        s.parse::<bool>().map_err(|_| {
+                            anyhow!("invalid value for sink.aggregate_records '{}'", s)
+                        })
+                    })
+                    .transpose()?;
+                let records_per_shard_per_second =
+                    pull_option_to_i64("sink.records_per_shard_per_second", opts)?;
                 TableType::Sink {
                     batch_flush_interval_millis,
                     batch_max_buffer_size,
                     records_per_batch,
+                    partition_key_field: opts.remove("sink.partition_key_field"),
+                    aggregate_records,
+                    records_per_shard_per_second,
                 }
             }
             _ => {
@@ -160,6 +227,8 @@ impl Connector for KinesisConnector {
             stream_name: pull_opt("stream_name", opts)?,
             type_: table_type,
             aws_region: opts.remove("aws_region").map(|s| s.to_string()),
+            aws_profile: opts.remove("aws_profile"),
+            assume_role_arn: opts.remove("assume_role_arn"),
         };
 
         Self::from_config(&self, None, name, EmptyConfig {}, table, schema)
@@ -2,58 +2,141 @@ use anyhow::{anyhow, bail, Context};
 use arroyo_rpc::api_types::connections::{
     ConnectionSchema, ConnectionType, FieldType, SourceField, SourceFieldType,
 };
-use arroyo_rpc::primitive_to_sql;
+use arroyo_rpc::{field_type_to_sql, SinkMode};
 use arroyo_types::string_to_map;
 use axum::response::sse::Event;
 use blackhole::BlackholeConnector;
 use fluvio::FluvioConnector;
+use futures::future::BoxFuture;
 use impulse::ImpulseConnector;
 use nexmark::NexmarkConnector;
+use once_cell::sync::Lazy;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Client;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sse::SSEConnector;
 use std::collections::HashMap;
 use std::convert::Infallible;
+use std::sync::RwLock;
 use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 use websocket::WebsocketConnector;
 
 use self::kafka::KafkaConnector;
 
+pub mod alert;
+pub mod bigquery;
 pub mod blackhole;
+pub mod cassandra;
+pub mod clickhouse;
+pub mod datagen;
+pub mod delta;
+pub mod elasticsearch;
 pub mod filesystem;
+pub mod filesystem_source;
 pub mod fluvio;
+pub mod grpc;
+pub mod http_lookup;
+pub mod iceberg;
 pub mod impulse;
 pub mod kafka;
 pub mod kinesis;
+pub mod mongodb;
+pub mod mqtt;
+pub mod mysql_cdc;
+pub mod nats;
 pub mod nexmark;
 pub mod polling_http;
+pub mod postgres;
+pub mod postgres_cdc;
+pub mod pubsub;
+pub mod pulsar;
+pub mod rabbitmq;
+pub mod redis;
+pub mod remote;
 pub mod single_file;
+pub mod snowflake;
 pub mod sse;
+mod testing;
 pub mod webhook;
 pub mod websocket;
+/// How long sources may take to produce the messages requested by [`Connector::sample`]
+pub(crate) const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub fn connectors() -> HashMap<&'static str, Box<dyn ErasedConnector>> {
     let mut m: HashMap<&'static str, Box<dyn ErasedConnector>> = HashMap::new();
+    m.insert("alert", Box::new(alert::AlertConnector {}));
+    m.insert("bigquery", Box::new(bigquery::BigQueryConnector {}));
     m.insert("blackhole", Box::new(BlackholeConnector {}));
+    m.insert("cassandra", Box::new(cassandra::CassandraConnector {}));
+    m.insert("clickhouse", Box::new(clickhouse::ClickhouseConnector {}));
+    m.insert("datagen", Box::new(datagen::DatagenConnector {}));
+    m.insert("delta", Box::new(delta::DeltaLakeConnector {}));
+    m.insert(
+        "elasticsearch",
+        Box::new(elasticsearch::ElasticsearchConnector {}),
+    );
     m.insert("filesystem", Box::new(filesystem::FileSystemConnector {}));
+    m.insert(
+        "filesystem_source",
+        Box::new(filesystem_source::FileSystemSourceConnector {}),
+    );
     m.insert("fluvio", Box::new(FluvioConnector {}));
+    m.insert("grpc", Box::new(grpc::GrpcConnector {}));
+    m.insert("http_lookup", Box::new(http_lookup::HttpLookupConnector {}));
+    m.insert("iceberg", Box::new(iceberg::IcebergConnector {}));
     m.insert("impulse", Box::new(ImpulseConnector {}));
     m.insert("kafka", Box::new(KafkaConnector {}));
     m.insert("kinesis", Box::new(kinesis::KinesisConnector {}));
+    m.insert("mongodb", Box::new(mongodb::MongoDbConnector {}));
+    m.insert("mqtt", Box::new(mqtt::MqttConnector {}));
+    m.insert("mysql_cdc", Box::new(mysql_cdc::MySqlCdcConnector {}));
+    m.insert("nats", Box::new(nats::NatsConnector {}));
     m.insert("nexmark", Box::new(NexmarkConnector {}));
     m.insert(
         "polling_http",
         Box::new(polling_http::PollingHTTPConnector {}),
     );
+    m.insert("postgres", Box::new(postgres::PostgresConnector {}));
+    m.insert(
+        "postgres_cdc",
+        Box::new(postgres_cdc::PostgresCdcConnector {}),
+    );
+    m.insert("pubsub", Box::new(pubsub::PubSubConnector {}));
+    m.insert("pulsar", Box::new(pulsar::PulsarConnector {}));
+    m.insert("rabbitmq", Box::new(rabbitmq::RabbitmqConnector {}));
+    m.insert("redis", Box::new(redis::RedisConnector {}));
     m.insert("single_file", Box::new(single_file::SingleFileConnector {}));
+    m.insert("snowflake", Box::new(snowflake::SnowflakeConnector {}));
     m.insert("sse", Box::new(SSEConnector {}));
     m.insert("webhook", Box::new(webhook::WebhookConnector {}));
     m.insert("websocket", Box::new(WebsocketConnector {}));
 
+    for (name, factory) in REGISTERED_CONNECTORS.read().unwrap().iter() {
+        m.insert(name, factory());
+    }
+
     m
 }
 
+type ConnectorFactory = Box<dyn Fn() -> Box<dyn ErasedConnector> + Send + Sync>;
+
+static REGISTERED_CONNECTORS: Lazy<RwLock<HashMap<&'static str, ConnectorFactory>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Registers a connector that's implemented outside of this crate, making it available
+/// alongside the built-in connectors. A connector registered with the same name as an existing
+/// one replaces it.
+pub fn register_connector(
+    name: &'static str,
+    factory: impl Fn() -> Box<dyn ErasedConnector> + Send + Sync + 'static,
+) {
+    REGISTERED_CONNECTORS
+        .write()
+        .unwrap()
+        .insert(name, Box::new(factory));
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EmptyConfig {}
 
@@ -110,6 +193,41 @@ pub trait Connector: Send {
         tx: Sender<Result<Event, Infallible>>,
     );
 
+    /// Reads up to `count` messages from the source, for use in inferring a schema for the table
+    #[allow(unused)]
+    fn sample(
+        &self,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        count: usize,
+    ) -> BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>> {
+        let name = self.name();
+        Box::pin(async move { bail!("The {} connector does not support sampling messages", name) })
+    }
+
+    /// Checks that sinks for the table can write in `mode`. Any sink can append rows or write
+    /// them as a Debezium changelog, but upserts need a key that the connector writes them under.
+    #[allow(unused)]
+    fn validate_sink_mode(&self, table: Self::TableT, mode: SinkMode) -> anyhow::Result<()> {
+        match mode {
+            SinkMode::Append | SinkMode::Retract => Ok(()),
+            SinkMode::Upsert => bail!(
+                "the {} connector does not support upsert sinks",
+                self.name()
+            ),
+        }
+    }
+
+    /// Checks that sources for the table can generate watermarks for each partition they read,
+    /// which they do when their config sets `partition_watermarks`
+    #[allow(unused)]
+    fn validate_partition_watermarks(&self, table: Self::TableT) -> anyhow::Result<()> {
+        bail!(
+            "the {} connector does not support per-partition watermarks",
+            self.name()
+        )
+    }
+
     fn from_options(
         &self,
         name: &str,
@@ -160,6 +278,17 @@ pub trait ErasedConnector: Send {
         tx: Sender<Result<Event, Infallible>>,
     ) -> Result<(), serde_json::Error>;
 
+    fn sample(
+        &self,
+        config: &serde_json::Value,
+        table: &serde_json::Value,
+        count: usize,
+    ) -> Result<BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>>, serde_json::Error>;
+
+    fn validate_sink_mode(&self, table: &serde_json::Value, mode: SinkMode) -> anyhow::Result<()>;
+
+    fn validate_partition_watermarks(&self, table: &serde_json::Value) -> anyhow::Result<()>;
+
     fn from_options(
         &self,
         name: &str,
@@ -236,6 +365,23 @@ impl<C: Connector> ErasedConnector for C {
         Ok(())
     }
 
+    fn sample(
+        &self,
+        config: &serde_json::Value,
+        table: &serde_json::Value,
+        count: usize,
+    ) -> Result<BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>>, serde_json::Error> {
+        Ok(self.sample(self.parse_config(config)?, self.parse_table(table)?, count))
+    }
+
+    fn validate_sink_mode(&self, table: &serde_json::Value, mode: SinkMode) -> anyhow::Result<()> {
+        self.validate_sink_mode(self.parse_table(table)?, mode)
+    }
+
+    fn validate_partition_watermarks(&self, table: &serde_json::Value) -> anyhow::Result<()> {
+        self.validate_partition_watermarks(self.parse_table(table)?)
+    }
+
     fn from_options(
         &self,
         name: &str,
@@ -290,13 +436,11 @@ pub(crate) fn source_field(name: &str, field_type: FieldType) -> SourceField {
     SourceField {
         field_name: name.to_string(),
         field_type: SourceFieldType {
-            sql_name: match field_type.clone() {
-                FieldType::Primitive(p) => Some(primitive_to_sql(p).to_string()),
-                FieldType::Struct(_) => None,
-            },
+            sql_name: field_type_to_sql(&field_type),
             r#type: field_type,
         },
         nullable: false,
+        json_path: None,
     }
 }
 
@@ -305,6 +449,7 @@ pub(crate) fn nullable_field(name: &str, field_type: SourceFieldType) -> SourceF
         field_name: name.to_string(),
         field_type,
         nullable: true,
+        json_path: None,
     }
 }
 
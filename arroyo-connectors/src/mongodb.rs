@@ -0,0 +1,303 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::ClientOptions;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/mongodb/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/mongodb/table.json");
+const ICON: &str = include_str!("../resources/mongodb.svg");
+
+import_types!(schema = "../connector-schemas/mongodb/connection.json");
+import_types!(schema = "../connector-schemas/mongodb/table.json");
+
+pub struct MongoDbConnector {}
+
+impl Connector for MongoDbConnector {
+    type ProfileT = MongoDbConfig;
+    type TableT = MongoDbTable;
+
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "mongodb".to_string(),
+            name: "MongoDB".to_string(),
+            icon: ICON.to_string(),
+            description: "Read changes from a MongoDB collection via change streams".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        // only show the hosts, as the connection string may contain credentials
+        ClientOptions::parse_connection_string_sync(&config.uri)
+            .map(|o| {
+                o.hosts
+                    .iter()
+                    .map(|h| h.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_else(|_| "mongodb".to_string())
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = MongoDbTester {
+            config,
+            table,
+            debezium: schema
+                .and_then(|s| s.format.as_ref())
+                .map(is_debezium)
+                .unwrap_or(false),
+            tx,
+        };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = MongoDbConfig {
+            uri: pull_opt("uri", opts)?,
+        };
+
+        let table = MongoDbTable {
+            database: pull_opt("database", opts)?,
+            collection: pull_opt("collection", opts)?,
+            missing_fields: opts
+                .remove("missing_fields")
+                .map(|s| match s.as_str() {
+                    "null" => Ok(MissingFields::Null),
+                    "fail" => Ok(MissingFields::Fail),
+                    other => bail!(
+                        "invalid value for missing_fields '{}'; expected null or fail",
+                        other
+                    ),
+                })
+                .transpose()?,
+            extra_fields: opts
+                .remove("extra_fields")
+                .map(|s| match s.as_str() {
+                    "ignore" => Ok(ExtraFields::Ignore),
+                    "fail" => Ok(ExtraFields::Fail),
+                    other => bail!(
+                        "invalid value for extra_fields '{}'; expected ignore or fail",
+                        other
+                    ),
+                })
+                .transpose()?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        ClientOptions::parse_connection_string_sync(&config.uri)
+            .map_err(|e| anyhow!("invalid MongoDB connection string: {}", e))?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for MongoDB connection"))?;
+
+        // collections have no fixed schema to infer from, so the fields must be declared
+        if schema.fields.is_empty() {
+            bail!("MongoDB tables must declare the fields to read from each document");
+        }
+
+        // by default we emit the current version of each changed document; with debezium_json
+        // the source also emits deletes and the previous version of updated documents
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..Default::default()
+            }),
+            Some(f @ Format::Json(JsonFormat { unstructured, .. })) if !unstructured => f.clone(),
+            Some(_) => bail!("MongoDB tables must use the 'json' or 'debezium_json' format"),
+        };
+
+        let schema = ConnectionSchema::try_new(
+            Some(format.clone()),
+            None,
+            schema.bad_data,
+            None,
+            schema.struct_name,
+            schema.fields,
+            schema.definition,
+        )?;
+
+        let description = format!("MongoDbSource<{}.{}>", table.database, table.collection);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: None,
+            bad_data: schema.bad_data.clone(),
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            operator: "connectors::mongodb::source::MongoDbSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+fn is_debezium(format: &Format) -> bool {
+    matches!(format, Format::Json(JsonFormat { debezium: true, .. }))
+}
+
+struct MongoDbTester {
+    config: MongoDbConfig,
+    table: MongoDbTable,
+    debezium: bool,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl MongoDbTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = Client::with_uri_str(&self.config.uri)
+            .await
+            .map_err(|e| anyhow!("Invalid MongoDB connection string: {}", e))?;
+
+        let db = client.database(&self.table.database);
+        db.run_command(doc! { "ping": 1 }, None)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to MongoDB: {}", e))?;
+
+        self.info("Connected to MongoDB").await;
+
+        let spec = db
+            .list_collections(doc! { "name": &self.table.collection }, None)
+            .await?
+            .try_next()
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "collection {}.{} does not exist",
+                    self.table.database,
+                    self.table.collection
+                )
+            })?;
+
+        if self.debezium
+            && !spec
+                .options
+                .change_stream_pre_and_post_images
+                .map(|c| c.enabled)
+                .unwrap_or(false)
+        {
+            bail!(
+                "the debezium_json format requires changeStreamPreAndPostImages to be enabled \
+                on the collection, so that deletes and updates include the previous document"
+            );
+        }
+
+        // change streams are only available on replica sets and sharded clusters, which we check
+        // by opening one
+        db.collection::<Document>(&self.table.collection)
+            .watch(None, None)
+            .await
+            .map_err(|e| anyhow!("Failed to open a change stream: {}", e))?;
+
+        self.info(format!(
+            "Opened change stream on {}.{}",
+            self.table.database, self.table.collection
+        ))
+        .await;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started MongoDB tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,403 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use rumqttc::{
+    AsyncClient, Event as MqttEvent, MqttOptions, Packet, QoS, SubscribeReasonCode,
+    TlsConfiguration, Transport,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/mqtt/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/mqtt/table.json");
+const ICON: &str = include_str!("../resources/mqtt.svg");
+
+import_types!(schema = "../connector-schemas/mqtt/connection.json");
+import_types!(schema = "../connector-schemas/mqtt/table.json");
+
+pub struct MqttConnector {}
+
+impl Connector for MqttConnector {
+    type ProfileT = MqttConfig;
+    type TableT = MqttTable;
+
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "mqtt".to_string(),
+            name: "MQTT".to_string(),
+            icon: ICON.to_string(),
+            description: "Read and write from an MQTT broker".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.url
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source {} => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = MqttTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let tls = match (
+            opts.remove("tls.ca"),
+            opts.remove("tls.cert"),
+            opts.remove("tls.key"),
+        ) {
+            (None, None, None) => None,
+            (ca, cert, key) => Some(Tls { ca, cert, key }),
+        };
+
+        let connection = MqttConfig {
+            url: pull_opt("url", opts)?,
+            client_prefix: opts.remove("client_prefix"),
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+            tls,
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {},
+            "sink" => TableType::Sink {
+                retain: opts
+                    .remove("sink.retain")
+                    .map(|r| {
+                        r.parse::<bool>()
+                            .map_err(|_| anyhow!("invalid value for sink.retain '{}'", r))
+                    })
+                    .transpose()?,
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let qos = match opts.remove("qos").as_ref().map(|f| f.as_str()) {
+            Some("0") | Some("at_most_once") => Some(QualityOfService::AtMostOnce),
+            Some("1") | Some("at_least_once") | None => Some(QualityOfService::AtLeastOnce),
+            Some("2") | Some("exactly_once") => Some(QualityOfService::ExactlyOnce),
+            Some(other) => bail!("invalid value for qos '{}'", other),
+        };
+
+        let table = MqttTable {
+            topic: pull_opt("topic", opts)?,
+            qos,
+            type_: table_type,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        // fail early on malformed urls and certificates
+        mqtt_options(&config, "arroyo-validate".to_string())?;
+
+        let (typ, operator, desc) = match table.type_ {
+            TableType::Source {} => (
+                ConnectionType::Source,
+                "connectors::mqtt::source::MqttSourceFunc",
+                format!("MqttSource<{}>", table.topic),
+            ),
+            TableType::Sink { .. } => (
+                ConnectionType::Sink,
+                "connectors::mqtt::sink::MqttSinkFunc::<#in_k, #in_t>",
+                format!("MqttSink<{}>", table.topic),
+            ),
+        };
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for MQTT connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for MQTT connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+impl QualityOfService {
+    pub fn qos(&self) -> QoS {
+        match self {
+            QualityOfService::AtMostOnce => QoS::AtMostOnce,
+            QualityOfService::AtLeastOnce => QoS::AtLeastOnce,
+            QualityOfService::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+impl MqttTable {
+    pub fn qos(&self) -> QoS {
+        self.qos
+            .as_ref()
+            .map(|q| q.qos())
+            .unwrap_or(QoS::AtLeastOnce)
+    }
+}
+
+/// Builds the client options for a connection, validating its url and TLS settings; shared by
+/// the connector's validation and tester and the worker's source and sink
+pub fn mqtt_options(config: &MqttConfig, client_id: String) -> anyhow::Result<MqttOptions> {
+    let url = url::Url::parse(&config.url)
+        .map_err(|e| anyhow!("invalid broker url '{}': {}", config.url, e))?;
+
+    let tls = match url.scheme() {
+        "mqtt" | "tcp" => false,
+        "mqtts" | "ssl" => true,
+        other => bail!(
+            "unsupported scheme '{}' for broker url; expected mqtt:// or mqtts://",
+            other
+        ),
+    };
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("broker url '{}' must have a host", config.url))?;
+    let port = url.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(Duration::from_secs(10));
+
+    if let Some(username) = &config.username {
+        options.set_credentials(
+            username,
+            config.password.as_ref().map(|p| p.as_str()).unwrap_or(""),
+        );
+    }
+
+    if tls {
+        let tls_config = config.tls.as_ref();
+        let client_auth = match (
+            tls_config.and_then(|t| t.cert.as_ref()),
+            tls_config.and_then(|t| t.key.as_ref()),
+        ) {
+            (Some(cert), Some(key)) => Some((cert.as_bytes().to_vec(), key.as_bytes().to_vec())),
+            (None, None) => None,
+            _ => bail!("tls.cert and tls.key must be set together"),
+        };
+
+        let transport = match tls_config.and_then(|t| t.ca.as_ref()) {
+            Some(ca) => Transport::Tls(TlsConfiguration::Simple {
+                ca: ca.as_bytes().to_vec(),
+                alpn: None,
+                client_auth,
+            }),
+            None => {
+                if client_auth.is_some() {
+                    bail!("tls.ca must be set when using a client certificate");
+                }
+                // verify against the system roots
+                Transport::tls_with_default_config()
+            }
+        };
+
+        options.set_transport(transport);
+    } else if config.tls.is_some() {
+        bail!("tls options are only supported with mqtts:// broker urls");
+    }
+
+    Ok(options)
+}
+
+struct MqttTester {
+    config: MqttConfig,
+    table: MqttTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl MqttTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let options = mqtt_options(
+            &self.config,
+            format!(
+                "{}-tester-{}",
+                self.config.client_prefix.as_deref().unwrap_or("arroyo"),
+                rand::random::<u32>()
+            ),
+        )?;
+
+        let (client, mut eventloop) = AsyncClient::new(options, 10);
+
+        // wait for the broker to acknowledge our connection
+        let connected = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match eventloop.poll().await? {
+                    MqttEvent::Incoming(Packet::ConnAck(_)) => return anyhow::Ok(()),
+                    _ => continue,
+                }
+            }
+        })
+        .await;
+
+        match connected {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => bail!("Failed to connect to MQTT broker: {}", e),
+            Err(_) => bail!("Timed out waiting for connection to MQTT broker"),
+        }
+
+        self.info("Connected to MQTT broker").await;
+
+        if let TableType::Source {} = self.table.type_ {
+            let qos = self
+                .table
+                .qos
+                .as_ref()
+                .map(|q| q.qos())
+                .unwrap_or(QoS::AtLeastOnce);
+
+            client
+                .subscribe(&self.table.topic, qos)
+                .await
+                .map_err(|e| anyhow!("Failed to subscribe to '{}': {}", self.table.topic, e))?;
+
+            let subscribed = tokio::time::timeout(Duration::from_secs(10), async {
+                loop {
+                    match eventloop.poll().await? {
+                        MqttEvent::Incoming(Packet::SubAck(ack)) => {
+                            return anyhow::Ok(ack.return_codes);
+                        }
+                        _ => continue,
+                    }
+                }
+            })
+            .await;
+
+            match subscribed {
+                Ok(Ok(codes)) => {
+                    if codes
+                        .iter()
+                        .any(|c| matches!(c, SubscribeReasonCode::Failure))
+                    {
+                        bail!(
+                            "Broker rejected subscription to '{}'; check the topic and permissions",
+                            self.table.topic
+                        );
+                    }
+                }
+                Ok(Err(e)) => bail!("Error while subscribing to MQTT topic: {}", e),
+                Err(_) => bail!("Timed out waiting for subscription acknowledgement"),
+            }
+
+            self.info(format!("Subscribed to '{}'", self.table.topic))
+                .await;
+        }
+
+        if let Err(e) = client.disconnect().await {
+            warn!("Failed to cleanly disconnect MQTT tester: {:?}", e);
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started MQTT tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,357 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, SourceField, SourceFieldType,
+    TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use mysql_async::prelude::Queryable;
+use mysql_async::{Conn, OptsBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/mysql/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/mysql_cdc/table.json");
+const ICON: &str = include_str!("../resources/mysql.svg");
+
+import_types!(schema = "../connector-schemas/mysql/connection.json");
+import_types!(schema = "../connector-schemas/mysql_cdc/table.json");
+
+pub struct MySqlCdcConnector {}
+
+impl Connector for MySqlCdcConnector {
+    type ProfileT = MySqlConfig;
+    type TableT = MySqlCdcTable;
+
+    fn name(&self) -> &'static str {
+        "mysql_cdc"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "mysql_cdc".to_string(),
+            name: "MySQL CDC".to_string(),
+            icon: ICON.to_string(),
+            description: "Capture changes from a MySQL table via the binlog".to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        format!("{}:{}", config.host, config.port.unwrap_or(3306))
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = MySqlCdcTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = MySqlConfig {
+            host: pull_opt("host", opts)?,
+            port: pull_option_to_i64("port", opts)?,
+            username: pull_opt("username", opts)?,
+            password: opts.remove("password"),
+        };
+
+        let table = MySqlCdcTable {
+            database: pull_opt("database", opts)?,
+            table_name: pull_opt("table_name", opts)?,
+            server_id: pull_option_to_i64("server_id", opts)?,
+            snapshot: opts
+                .remove("snapshot")
+                .map(|s| {
+                    s.parse::<bool>()
+                        .map_err(|_| anyhow!("invalid value for snapshot '{}'", s))
+                })
+                .transpose()?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if let Some(server_id) = table.server_id {
+            if !(1..=u32::MAX as i64).contains(&server_id) {
+                bail!("server_id must be between 1 and {}", u32::MAX);
+            }
+        }
+
+        // changes are emitted as Debezium-style envelopes, so the table is always updating
+        let format = match schema.and_then(|s| s.format.as_ref()) {
+            None => Format::Json(JsonFormat {
+                debezium: true,
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..Default::default()
+            }),
+            Some(
+                f @ Format::Json(JsonFormat {
+                    debezium: true,
+                    timestamp_format: TimestampFormat::UnixMillis,
+                    ..
+                }),
+            ) => f.clone(),
+            Some(_) => bail!("mysql_cdc tables must use the 'debezium_json' format"),
+        };
+
+        let fields = match schema {
+            Some(schema) if !schema.fields.is_empty() => schema.fields.clone(),
+            _ => {
+                // no columns were provided, so we look them up from the table catalog
+                let config = config.clone();
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap()
+                        .block_on(infer_fields(&config, &table))
+                })
+                .join()
+                .map_err(|_| anyhow!("failed to infer schema from the table catalog"))??
+            }
+        };
+
+        let schema = ConnectionSchema::try_new(
+            Some(format.clone()),
+            None,
+            schema.and_then(|s| s.bad_data.clone()),
+            None,
+            schema.and_then(|s| s.struct_name.clone()),
+            fields,
+            schema.and_then(|s| s.definition.clone()),
+        )?;
+
+        let description = format!("MySqlCdcSource<{}.{}>", table.database, table.table_name);
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: None,
+            bad_data: schema.bad_data.clone(),
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            operator: "connectors::mysql_cdc::source::MySqlCdcSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+async fn connect(config: &MySqlConfig) -> anyhow::Result<Conn> {
+    let port = config.port.unwrap_or(3306);
+    if !(1..=65535).contains(&port) {
+        bail!("invalid port {}", port);
+    }
+
+    let opts = OptsBuilder::default()
+        .ip_or_hostname(&config.host)
+        .tcp_port(port as u16)
+        .user(Some(&config.username))
+        .pass(config.password.as_ref());
+
+    Ok(Conn::new(opts).await?)
+}
+
+fn primitive_type(data_type: &str, column_type: &str) -> PrimitiveType {
+    match data_type {
+        "tinyint" if column_type == "tinyint(1)" => PrimitiveType::Bool,
+        "tinyint" | "smallint" | "mediumint" => PrimitiveType::Int32,
+        "int" if column_type.ends_with("unsigned") => PrimitiveType::Int64,
+        "int" => PrimitiveType::Int32,
+        "bigint" if column_type.ends_with("unsigned") => PrimitiveType::UInt64,
+        "bigint" => PrimitiveType::Int64,
+        "float" => PrimitiveType::F32,
+        "double" | "decimal" => PrimitiveType::F64,
+        "datetime" | "timestamp" => PrimitiveType::DateTime,
+        "json" => PrimitiveType::Json,
+        _ => PrimitiveType::String,
+    }
+}
+
+async fn infer_fields(
+    config: &MySqlConfig,
+    table: &MySqlCdcTable,
+) -> anyhow::Result<Vec<SourceField>> {
+    let mut conn = connect(config).await?;
+
+    let columns: Vec<(String, String, String, String)> = conn
+        .exec(
+            "SELECT COLUMN_NAME, DATA_TYPE, COLUMN_TYPE, IS_NULLABLE FROM information_schema.columns \
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION",
+            (&table.database, &table.table_name),
+        )
+        .await?;
+
+    conn.disconnect().await?;
+
+    if columns.is_empty() {
+        bail!(
+            "table {}.{} does not exist or has no columns",
+            table.database,
+            table.table_name
+        );
+    }
+
+    Ok(columns
+        .into_iter()
+        .map(|(name, data_type, column_type, nullable)| SourceField {
+            field_name: name,
+            field_type: SourceFieldType {
+                r#type: FieldType::Primitive(primitive_type(&data_type, &column_type)),
+                sql_name: None,
+            },
+            nullable: nullable == "YES",
+            json_path: None,
+        })
+        .collect())
+}
+
+struct MySqlCdcTester {
+    config: MySqlConfig,
+    table: MySqlCdcTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl MySqlCdcTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let mut conn = connect(&self.config)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to MySQL: {}", e))?;
+
+        self.info("Connected to MySQL").await;
+
+        let (log_bin, binlog_format, gtid_mode, row_image): (i64, String, String, String) = conn
+            .query_first(
+                "SELECT @@GLOBAL.log_bin, @@GLOBAL.binlog_format, @@GLOBAL.gtid_mode, \
+                 @@GLOBAL.binlog_row_image",
+            )
+            .await?
+            .ok_or_else(|| anyhow!("Failed to read binlog configuration"))?;
+
+        conn.disconnect().await?;
+
+        if log_bin != 1 {
+            bail!("binary logging must be enabled (log_bin)");
+        }
+
+        if binlog_format != "ROW" {
+            bail!(
+                "binlog_format is '{}', but must be 'ROW' to use change data capture",
+                binlog_format
+            );
+        }
+
+        if gtid_mode != "ON" {
+            bail!("gtid_mode is '{}', but must be 'ON'", gtid_mode);
+        }
+
+        if row_image != "FULL" {
+            self.info(format!(
+                "Warning: binlog_row_image is '{}'; updates and deletes can only be fully \
+                captured with binlog_row_image=FULL",
+                row_image
+            ))
+            .await;
+        }
+
+        let fields = infer_fields(&self.config, &self.table).await?;
+        self.info(format!(
+            "Found table {}.{} with {} columns",
+            self.table.database,
+            self.table.table_name,
+            fields.len()
+        ))
+        .await;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started MySQL CDC tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,358 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use async_nats::ConnectOptions;
+use axum::response::sse::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/nats/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/nats/table.json");
+const ICON: &str = include_str!("../resources/nats.svg");
+
+import_types!(schema = "../connector-schemas/nats/connection.json");
+import_types!(schema = "../connector-schemas/nats/table.json");
+
+pub struct NatsConnector {}
+
+impl Connector for NatsConnector {
+    type ProfileT = NatsConfig;
+    type TableT = NatsTable;
+
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "nats".to_string(),
+            name: "NATS".to_string(),
+            icon: ICON.to_string(),
+            description: "Read and write from NATS subjects and JetStream streams".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.servers
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = NatsTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = NatsConfig {
+            servers: pull_opt("servers", opts)?,
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+            token: opts.remove("token"),
+            credentials: opts.remove("credentials"),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                stream: opts.remove("source.stream"),
+                consumer: opts.remove("source.consumer"),
+                deliver_policy: opts
+                    .remove("source.deliver_policy")
+                    .map(|p| match p.as_str() {
+                        "all" => Ok(DeliverPolicy::All),
+                        "new" => Ok(DeliverPolicy::New),
+                        "last" => Ok(DeliverPolicy::Last),
+                        other => bail!(
+                            "invalid value for source.deliver_policy '{}'; expected all, new or last",
+                            other
+                        ),
+                    })
+                    .transpose()?,
+            },
+            "sink" => TableType::Sink {
+                jetstream: opts
+                    .remove("sink.jetstream")
+                    .map(|r| {
+                        r.parse::<bool>()
+                            .map_err(|_| anyhow!("invalid value for sink.jetstream '{}'", r))
+                    })
+                    .transpose()?,
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = NatsTable {
+            subject: pull_opt("subject", opts)?,
+            type_: table_type,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        // fail early on malformed credentials
+        nats_options(&config)?;
+
+        let (typ, operator, desc) = match &table.type_ {
+            TableType::Source {
+                stream,
+                consumer,
+                deliver_policy,
+            } => {
+                validate_subject(&table.subject, true)?;
+                if stream.is_none() && (consumer.is_some() || deliver_policy.is_some()) {
+                    bail!("consumer and deliver_policy can only be set for JetStream sources; set stream to use JetStream");
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::nats::source::NatsSourceFunc",
+                    match stream {
+                        Some(stream) => format!("JetStreamSource<{}>", stream),
+                        None => format!("NatsSource<{}>", table.subject),
+                    },
+                )
+            }
+            TableType::Sink { jetstream } => {
+                validate_subject(&table.subject, false)?;
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::nats::sink::NatsSinkFunc::<#in_k, #in_t>",
+                    if jetstream.unwrap_or(false) {
+                        format!("JetStreamSink<{}>", table.subject)
+                    } else {
+                        format!("NatsSink<{}>", table.subject)
+                    },
+                )
+            }
+        };
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for NATS connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for NATS connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+/// Checks that the subject is well-formed; `*` matches a single token and `>` matches all
+/// remaining tokens, so it may only appear last
+fn validate_subject(subject: &str, allow_wildcards: bool) -> anyhow::Result<()> {
+    let tokens: Vec<_> = subject.split('.').collect();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_empty() || token.contains(char::is_whitespace) {
+            bail!("invalid subject '{}'", subject);
+        }
+
+        if token.contains('*') || token.contains('>') {
+            if !allow_wildcards {
+                bail!("sink subject '{}' cannot contain wildcards", subject);
+            }
+
+            if !(*token == "*" || (*token == ">" && i == tokens.len() - 1)) {
+                bail!(
+                    "invalid wildcard in subject '{}'; * must be a whole token and > must be the last token",
+                    subject
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn nats_options(config: &NatsConfig) -> anyhow::Result<ConnectOptions> {
+    let mut options = ConnectOptions::new().name("arroyo");
+
+    match (
+        &config.username,
+        &config.password,
+        &config.token,
+        &config.credentials,
+    ) {
+        (None, None, None, None) => {}
+        (Some(username), Some(password), None, None) => {
+            options = options.user_and_password(username.clone(), password.clone());
+        }
+        (None, None, Some(token), None) => {
+            options = options.token(token.clone());
+        }
+        (None, None, None, Some(credentials)) => {
+            options = options
+                .credentials(credentials)
+                .map_err(|e| anyhow!("invalid credentials: {}", e))?;
+        }
+        (Some(_), None, None, None) | (None, Some(_), None, None) => {
+            bail!("username and password must be set together")
+        }
+        _ => bail!("only one of username/password, token, or credentials may be set"),
+    }
+
+    Ok(options)
+}
+
+struct NatsTester {
+    config: NatsConfig,
+    table: NatsTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl NatsTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = tokio::time::timeout(
+            Duration::from_secs(10),
+            nats_options(&self.config)?.connect(self.config.servers.as_str()),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out connecting to NATS"))?
+        .map_err(|e| anyhow!("Failed to connect to NATS: {}", e))?;
+
+        self.info("Connected to NATS").await;
+
+        let jetstream = async_nats::jetstream::new(client.clone());
+        match &self.table.type_ {
+            TableType::Source {
+                stream: Some(stream),
+                ..
+            } => {
+                jetstream
+                    .get_stream(stream)
+                    .await
+                    .map_err(|e| anyhow!("Failed to find stream '{}': {}", stream, e))?;
+                self.info(format!("Found stream '{}'", stream)).await;
+            }
+            TableType::Sink {
+                jetstream: Some(true),
+            } => {
+                let stream = jetstream
+                    .stream_by_subject(self.table.subject.clone())
+                    .await
+                    .map_err(|e| {
+                        anyhow!("No stream captures subject '{}': {}", self.table.subject, e)
+                    })?;
+                self.info(format!(
+                    "Subject '{}' is captured by stream '{}'",
+                    self.table.subject, stream
+                ))
+                .await;
+            }
+            _ => {}
+        }
+
+        if let Err(e) = client.flush().await {
+            warn!("Failed to flush NATS tester: {:?}", e);
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started NATS tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -21,6 +21,8 @@ pub fn nexmark_schema() -> ConnectionSchema {
     ConnectionSchema {
         format: None,
         framing: None,
+        bad_data: None,
+        compression: None,
         struct_name: Some("arroyo_types::nexmark::Event".to_string()),
         fields: vec![
             nullable_field(
@@ -190,8 +192,11 @@ impl Connector for NexmarkConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: None,
             framing: None,
+            bad_data: None,
+            compression: None,
         };
 
         Ok(Connection {
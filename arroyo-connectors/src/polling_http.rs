@@ -1,6 +1,6 @@
 use std::convert::Infallible;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_rpc::OperatorConfig;
 use arroyo_types::string_to_map;
 use axum::response::sse::Event;
@@ -34,11 +34,11 @@ impl PollingHTTPConnector {
                 Some(Method::Put) => reqwest::Method::PUT,
                 Some(Method::Patch) => reqwest::Method::PATCH,
             },
-            &config.endpoint,
+            render_template(&config.endpoint, config.initial_watermark.as_deref()),
         );
 
         if let Some(body) = &config.body {
-            req = req.body(body.clone());
+            req = req.body(render_template(body, config.initial_watermark.as_deref()));
         }
 
         let req = req
@@ -150,6 +150,12 @@ impl Connector for PollingHTTPConnector {
         let body = opts.remove("body");
 
         let interval = pull_option_to_i64("poll_interval_ms", opts)?;
+        let pagination: Option<Pagination> = opts
+            .remove("pagination")
+            .map(|s| s.try_into())
+            .transpose()
+            .map_err(|_| anyhow!("invalid value for 'pagination'"))?;
+
         let emit_behavior: Option<EmitBehavior> = opts
             .remove("emit_behavior")
             .map(|s| s.try_into())
@@ -166,6 +172,14 @@ impl Connector for PollingHTTPConnector {
                 method,
                 body,
                 poll_interval_ms: interval,
+                records_path: opts.remove("records_path"),
+                pagination,
+                pagination_path: opts.remove("pagination_path"),
+                cursor_param: opts.remove("cursor_param"),
+                max_pages: pull_option_to_i64("max_pages", opts)?,
+                watermark_field: opts.remove("watermark_field"),
+                initial_watermark: opts.remove("initial_watermark"),
+                max_requests_per_second: pull_option_to_i64("max_requests_per_second", opts)?,
                 emit_behavior,
             },
             schema,
@@ -191,6 +205,25 @@ impl Connector for PollingHTTPConnector {
             })?;
         }
 
+        match table.pagination {
+            Some(Pagination::Cursor) | Some(Pagination::NextLink)
+                if table.pagination_path.is_none() =>
+            {
+                bail!("'pagination_path' must be set for cursor and next_link pagination");
+            }
+            _ => {}
+        }
+
+        if table.watermark_field.is_some() && table.records_path.is_none() {
+            bail!("'records_path' must be set to use 'watermark_field'");
+        }
+
+        if let Some(rate) = table.max_requests_per_second {
+            if rate <= 0 {
+                bail!("'max_requests_per_second' must be positive");
+            }
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for polling HTTP connection"))?;
@@ -205,8 +238,11 @@ impl Connector for PollingHTTPConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
@@ -220,3 +256,11 @@ impl Connector for PollingHTTPConnector {
         })
     }
 }
+
+/// Substitutes `{{ watermark }}` in the endpoint or body, as the source does before each poll
+fn render_template(template: &str, watermark: Option<&str>) -> String {
+    regex::Regex::new(r"\{\{\s*watermark\s*\}\}")
+        .unwrap()
+        .replace_all(template, watermark.unwrap_or(""))
+        .to_string()
+}
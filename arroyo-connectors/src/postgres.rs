@@ -0,0 +1,375 @@
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::postgres_cdc::{connect, pg_config, PostgresConfig, SslMode};
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/postgres/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/postgres/table.json");
+const ICON: &str = include_str!("../resources/postgres.svg");
+
+import_types!(schema = "../connector-schemas/postgres/table.json");
+
+pub struct PostgresConnector {}
+
+impl Connector for PostgresConnector {
+    type ProfileT = PostgresConfig;
+    type TableT = PostgresTable;
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "postgres".to_string(),
+            name: "Postgres".to_string(),
+            icon: ICON.to_string(),
+            description: "Write inserts, updates, and deletes to a Postgres table, or look up its \
+                rows in lookup joins"
+                .to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        format!(
+            "{}:{}/{}",
+            config.host,
+            config.port.unwrap_or(5432),
+            config.database
+        )
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        if table.is_lookup() {
+            ConnectionType::Lookup
+        } else {
+            ConnectionType::Sink
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = PostgresTester {
+            config,
+            table,
+            schema: schema.cloned(),
+            tx,
+        };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let ssl_mode = match opts.remove("ssl_mode").as_ref().map(|f| f.as_str()) {
+            Some("disable") => Some(SslMode::Disable),
+            Some("prefer") => Some(SslMode::Prefer),
+            Some("require") => Some(SslMode::Require),
+            None => None,
+            Some(other) => bail!("invalid value for ssl_mode '{}'", other),
+        };
+
+        let connection = PostgresConfig {
+            host: pull_opt("host", opts)?,
+            port: pull_option_to_i64("port", opts)?,
+            database: pull_opt("database", opts)?,
+            username: pull_opt("username", opts)?,
+            password: opts.remove("password"),
+            ssl_mode,
+        };
+
+        let commit_mode = match opts.remove("commit_mode").as_ref().map(|f| f.as_str()) {
+            Some("at_least_once") => Some(CommitMode::AtLeastOnce),
+            Some("exactly_once") => Some(CommitMode::ExactlyOnce),
+            None => None,
+            Some(other) => bail!("invalid value for commit_mode '{}'", other),
+        };
+
+        let type_ = match opts.remove("type").as_ref().map(|f| f.as_str()) {
+            Some("sink") => Some(TableType::Sink),
+            Some("lookup") => Some(TableType::Lookup),
+            None => None,
+            Some(other) => bail!("invalid value for type '{}'", other),
+        };
+
+        let table = PostgresTable {
+            type_,
+            schema_name: opts.remove("schema_name"),
+            table_name: pull_opt("table_name", opts)?,
+            primary_keys: opts
+                .remove("primary_keys")
+                .map(|keys| keys.split(',').map(|k| k.trim().to_string()).collect())
+                .unwrap_or_default(),
+            batch_size: pull_option_to_i64("batch_size", opts)?,
+            flush_interval_ms: pull_option_to_i64("flush_interval_ms", opts)?,
+            commit_mode,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        pg_config(&config)?;
+
+        if matches!(table.batch_size, Some(size) if size < 1) {
+            bail!("batch_size must be at least 1");
+        }
+
+        if matches!(table.flush_interval_ms, Some(interval) if interval < 0) {
+            bail!("flush_interval_ms must not be negative");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Postgres sink"))?;
+
+        // rows are passed to Postgres as JSON, so we only support the JSON formats (with
+        // debezium_json the sink can consume updating queries); timestamps must be RFC3339 so
+        // that Postgres can parse them
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(f)) if !f.unstructured => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::RFC3339,
+                ..f.clone()
+            }),
+            Some(_) => bail!("postgres tables must use the 'json' or 'debezium_json' format"),
+        };
+
+        // lookups read rows with row_to_json, which can't produce debezium messages
+        if table.is_lookup() && matches!(&format, Format::Json(f) if f.debezium) {
+            bail!("postgres lookup tables must use the 'json' format");
+        }
+
+        for key in &table.primary_keys {
+            if !schema.fields.is_empty() && !schema.fields.iter().any(|f| &f.field_name == key) {
+                bail!(
+                    "primary key '{}' does not exist in the schema for this table",
+                    key
+                );
+            }
+        }
+
+        let (connection_type, operator, description) = if table.is_lookup() {
+            (
+                ConnectionType::Lookup,
+                "connectors::postgres::lookup::PostgresLookup",
+                format!(
+                    "PostgresLookup<{}.{}>",
+                    table.schema_name(),
+                    table.table_name
+                ),
+            )
+        } else {
+            (
+                ConnectionType::Sink,
+                "connectors::postgres::sink::PostgresSinkFunc::<#in_k, #in_t>",
+                format!("PostgresSink<{}.{}>", table.schema_name(), table.table_name),
+            )
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+impl PostgresTable {
+    pub fn schema_name(&self) -> &str {
+        self.schema_name.as_deref().unwrap_or("public")
+    }
+
+    pub fn is_lookup(&self) -> bool {
+        matches!(self.type_, Some(TableType::Lookup))
+    }
+}
+
+struct PostgresTester {
+    config: PostgresConfig,
+    table: PostgresTable,
+    schema: Option<ConnectionSchema>,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl PostgresTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = connect(&self.config)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Postgres: {}", e))?;
+
+        self.info("Connected to Postgres").await;
+
+        let columns: Vec<String> = client
+            .query(
+                "SELECT column_name::text FROM information_schema.columns \
+                 WHERE table_schema = $1 AND table_name = $2",
+                &[&self.table.schema_name(), &self.table.table_name],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        if columns.is_empty() {
+            bail!(
+                "table {}.{} does not exist",
+                self.table.schema_name(),
+                self.table.table_name
+            );
+        }
+
+        if let Some(schema) = &self.schema {
+            for field in &schema.fields {
+                if !columns.contains(&field.field_name) {
+                    bail!(
+                        "field '{}' does not exist in table {}.{}",
+                        field.field_name,
+                        self.table.schema_name(),
+                        self.table.table_name
+                    );
+                }
+            }
+        }
+
+        if self.table.is_lookup() {
+            return Ok(());
+        }
+
+        if self.table.primary_keys.is_empty() {
+            let keys: Vec<String> = client
+                .query(
+                    "SELECT a.attname::text FROM pg_index i \
+                     JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+                     WHERE i.indrelid = format('%I.%I', $1::text, $2::text)::regclass \
+                     AND i.indisprimary",
+                    &[&self.table.schema_name(), &self.table.table_name],
+                )
+                .await?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            if keys.is_empty() {
+                self.info(
+                    "Warning: the table has no primary key and primary_keys is not set, so rows \
+                    will be appended and updating queries can't be written",
+                )
+                .await;
+            } else {
+                self.info(format!("Using primary key ({})", keys.join(", ")))
+                    .await;
+            }
+        }
+
+        if matches!(self.table.commit_mode, Some(CommitMode::ExactlyOnce)) {
+            let max_prepared: String = client
+                .query_one("SHOW max_prepared_transactions", &[])
+                .await?
+                .get(0);
+
+            if max_prepared == "0" {
+                bail!(
+                    "exactly-once writes require max_prepared_transactions > 0 on the server; \
+                    set it or use commit_mode 'at_least_once'"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Postgres tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,423 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, SourceField, SourceFieldType,
+    TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/postgres/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/postgres_cdc/table.json");
+const ICON: &str = include_str!("../resources/postgres.svg");
+
+import_types!(schema = "../connector-schemas/postgres/connection.json");
+import_types!(schema = "../connector-schemas/postgres_cdc/table.json");
+
+pub struct PostgresCdcConnector {}
+
+impl Connector for PostgresCdcConnector {
+    type ProfileT = PostgresConfig;
+    type TableT = PostgresCdcTable;
+
+    fn name(&self) -> &'static str {
+        "postgres_cdc"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "postgres_cdc".to_string(),
+            name: "Postgres CDC".to_string(),
+            icon: ICON.to_string(),
+            description: "Capture changes from a Postgres table via logical replication"
+                .to_string(),
+            enabled: true,
+            source: true,
+            sink: false,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        format!(
+            "{}:{}/{}",
+            config.host,
+            config.port.unwrap_or(5432),
+            config.database
+        )
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Source
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = PostgresCdcTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let ssl_mode = match opts.remove("ssl_mode").as_ref().map(|f| f.as_str()) {
+            Some("disable") => Some(SslMode::Disable),
+            Some("prefer") => Some(SslMode::Prefer),
+            Some("require") => Some(SslMode::Require),
+            None => None,
+            Some(other) => bail!("invalid value for ssl_mode '{}'", other),
+        };
+
+        let connection = PostgresConfig {
+            host: pull_opt("host", opts)?,
+            port: pull_option_to_i64("port", opts)?,
+            database: pull_opt("database", opts)?,
+            username: pull_opt("username", opts)?,
+            password: opts.remove("password"),
+            ssl_mode,
+        };
+
+        let table = PostgresCdcTable {
+            schema_name: opts.remove("schema_name"),
+            table_name: pull_opt("table_name", opts)?,
+            slot_name: opts.remove("slot_name"),
+            publication_name: opts.remove("publication_name"),
+            poll_interval_ms: pull_option_to_i64("poll_interval_ms", opts)?,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        pg_config(&config)?;
+
+        if let Some(slot) = &table.slot_name {
+            if slot.is_empty()
+                || !slot
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            {
+                bail!("slot_name may only contain lower case letters, numbers, and underscores");
+            }
+        }
+
+        if let Some(interval) = table.poll_interval_ms {
+            if interval < 10 {
+                bail!("poll_interval_ms must be at least 10");
+            }
+        }
+
+        // changes are emitted as Debezium-style envelopes, so the table is always updating
+        let format = match schema.and_then(|s| s.format.as_ref()) {
+            None => Format::Json(JsonFormat {
+                debezium: true,
+                timestamp_format: TimestampFormat::UnixMillis,
+                ..Default::default()
+            }),
+            Some(
+                f @ Format::Json(JsonFormat {
+                    debezium: true,
+                    timestamp_format: TimestampFormat::UnixMillis,
+                    ..
+                }),
+            ) => f.clone(),
+            Some(_) => bail!("postgres_cdc tables must use the 'debezium_json' format"),
+        };
+
+        let fields = match schema {
+            Some(schema) if !schema.fields.is_empty() => schema.fields.clone(),
+            _ => {
+                // no columns were provided, so we look them up from the table catalog
+                let config = config.clone();
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap()
+                        .block_on(infer_fields(&config, &table))
+                })
+                .join()
+                .map_err(|_| anyhow!("failed to infer schema from the table catalog"))??
+            }
+        };
+
+        let schema = ConnectionSchema::try_new(
+            Some(format.clone()),
+            None,
+            schema.and_then(|s| s.bad_data.clone()),
+            None,
+            schema.and_then(|s| s.struct_name.clone()),
+            fields,
+            schema.and_then(|s| s.definition.clone()),
+        )?;
+
+        let description = format!(
+            "PostgresCdcSource<{}.{}>",
+            table.schema_name(),
+            table.table_name
+        );
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: None,
+            bad_data: schema.bad_data.clone(),
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Source,
+            schema,
+            operator: "connectors::postgres_cdc::source::PostgresCdcSourceFunc".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+impl PostgresCdcTable {
+    pub fn schema_name(&self) -> &str {
+        self.schema_name.as_deref().unwrap_or("public")
+    }
+}
+
+pub(crate) fn pg_config(config: &PostgresConfig) -> anyhow::Result<tokio_postgres::Config> {
+    let port = config.port.unwrap_or(5432);
+    if !(1..=65535).contains(&port) {
+        bail!("invalid port {}", port);
+    }
+
+    let mut pg_config = tokio_postgres::Config::new();
+    pg_config
+        .host(&config.host)
+        .port(port as u16)
+        .dbname(&config.database)
+        .user(&config.username)
+        .application_name("arroyo")
+        .connect_timeout(Duration::from_secs(10))
+        .ssl_mode(match config.ssl_mode {
+            Some(SslMode::Disable) => tokio_postgres::config::SslMode::Disable,
+            Some(SslMode::Prefer) | None => tokio_postgres::config::SslMode::Prefer,
+            Some(SslMode::Require) => tokio_postgres::config::SslMode::Require,
+        });
+
+    if let Some(password) = &config.password {
+        pg_config.password(password);
+    }
+
+    Ok(pg_config)
+}
+
+pub(crate) async fn connect(config: &PostgresConfig) -> anyhow::Result<tokio_postgres::Client> {
+    let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+    let (client, connection) = pg_config(config)?.connect(tls).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!("Postgres connection error: {:?}", e);
+        }
+    });
+
+    Ok(client)
+}
+
+fn primitive_type(data_type: &str) -> PrimitiveType {
+    match data_type {
+        "boolean" => PrimitiveType::Bool,
+        "smallint" | "integer" => PrimitiveType::Int32,
+        "bigint" => PrimitiveType::Int64,
+        "real" => PrimitiveType::F32,
+        "double precision" | "numeric" => PrimitiveType::F64,
+        "timestamp without time zone" | "timestamp with time zone" => PrimitiveType::DateTime,
+        "json" | "jsonb" => PrimitiveType::Json,
+        _ => PrimitiveType::String,
+    }
+}
+
+async fn infer_fields(
+    config: &PostgresConfig,
+    table: &PostgresCdcTable,
+) -> anyhow::Result<Vec<SourceField>> {
+    let client = connect(config).await?;
+
+    let rows = client
+        .query(
+            "SELECT column_name, data_type, is_nullable FROM information_schema.columns \
+             WHERE table_schema = $1 AND table_name = $2 ORDER BY ordinal_position",
+            &[&table.schema_name(), &table.table_name],
+        )
+        .await?;
+
+    if rows.is_empty() {
+        bail!(
+            "table {}.{} does not exist or has no columns",
+            table.schema_name(),
+            table.table_name
+        );
+    }
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let name: String = row.get(0);
+            let data_type: String = row.get(1);
+            let nullable: String = row.get(2);
+            SourceField {
+                field_name: name,
+                field_type: SourceFieldType {
+                    r#type: FieldType::Primitive(primitive_type(&data_type)),
+                    sql_name: None,
+                },
+                nullable: nullable == "YES",
+                json_path: None,
+            }
+        })
+        .collect())
+}
+
+struct PostgresCdcTester {
+    config: PostgresConfig,
+    table: PostgresCdcTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl PostgresCdcTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = connect(&self.config)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Postgres: {}", e))?;
+
+        self.info("Connected to Postgres").await;
+
+        let wal_level: String = client.query_one("SHOW wal_level", &[]).await?.get(0);
+        if wal_level != "logical" {
+            bail!(
+                "wal_level is '{}', but must be 'logical' to use change data capture",
+                wal_level
+            );
+        }
+
+        let can_replicate: bool = client
+            .query_one(
+                "SELECT rolreplication OR rolsuper FROM pg_roles WHERE rolname = current_user",
+                &[],
+            )
+            .await?
+            .get(0);
+        if !can_replicate {
+            bail!(
+                "user '{}' must have the REPLICATION attribute",
+                self.config.username
+            );
+        }
+
+        let fields = infer_fields(&self.config, &self.table).await?;
+        self.info(format!(
+            "Found table {}.{} with {} columns",
+            self.table.schema_name(),
+            self.table.table_name,
+            fields.len()
+        ))
+        .await;
+
+        let replica_identity: i8 = client
+            .query_one(
+                "SELECT c.relreplident FROM pg_class c \
+                 JOIN pg_namespace n ON n.oid = c.relnamespace \
+                 WHERE n.nspname = $1 AND c.relname = $2",
+                &[&self.table.schema_name(), &self.table.table_name],
+            )
+            .await?
+            .get(0);
+
+        if replica_identity as u8 != b'f' {
+            self.info(format!(
+                "Warning: updates and deletes can only be captured if the table has full replica \
+                identity; run `ALTER TABLE {}.{} REPLICA IDENTITY FULL`",
+                self.table.schema_name(),
+                self.table.table_name
+            ))
+            .await;
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Postgres CDC tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
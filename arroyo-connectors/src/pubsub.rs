@@ -0,0 +1,339 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/pubsub/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/pubsub/table.json");
+const ICON: &str = include_str!("../resources/pubsub.svg");
+
+const PUBSUB_SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+
+import_types!(schema = "../connector-schemas/pubsub/connection.json");
+import_types!(schema = "../connector-schemas/pubsub/table.json");
+
+pub struct PubSubConnector {}
+
+/// Expands a short topic or subscription name into its full resource name
+fn resource_name(project_id: &str, kind: &str, name: &str) -> String {
+    if name.starts_with("projects/") {
+        name.to_string()
+    } else {
+        format!("projects/{}/{}/{}", project_id, kind, name)
+    }
+}
+
+impl Connector for PubSubConnector {
+    type ProfileT = PubSubConfig;
+    type TableT = PubSubTable;
+
+    fn name(&self) -> &'static str {
+        "pubsub"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "pubsub".to_string(),
+            name: "Google Pub/Sub".to_string(),
+            icon: ICON.to_string(),
+            description: "Read from Pub/Sub subscriptions and publish to topics".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.project_id
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = PubSubTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = PubSubConfig {
+            project_id: pull_opt("project_id", opts)?,
+            credentials: opts.remove("credentials"),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                subscription: pull_opt("source.subscription", opts)?,
+                ack_deadline_seconds: pull_option_to_i64("source.ack_deadline_seconds", opts)?,
+                max_outstanding_messages: pull_option_to_i64(
+                    "source.max_outstanding_messages",
+                    opts,
+                )?,
+            },
+            "sink" => TableType::Sink {
+                topic: pull_opt("sink.topic", opts)?,
+                ordering_key: opts.remove("sink.ordering_key"),
+                batch_size: pull_option_to_i64("sink.batch_size", opts)?,
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = PubSubTable { type_: table_type };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        if let Some(credentials) = &config.credentials {
+            CustomServiceAccount::from_json(credentials)
+                .map_err(|e| anyhow!("invalid service account key: {}", e))?;
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Pub/Sub connection"))?;
+
+        let (typ, operator, desc) = match &table.type_ {
+            TableType::Source {
+                subscription,
+                ack_deadline_seconds,
+                max_outstanding_messages,
+            } => {
+                if matches!(ack_deadline_seconds, Some(s) if !(10..=600).contains(s)) {
+                    bail!("ack_deadline_seconds must be between 10 and 600");
+                }
+                if matches!(max_outstanding_messages, Some(m) if *m < 1) {
+                    bail!("max_outstanding_messages must be at least 1");
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::pubsub::source::PubSubSourceFunc",
+                    format!(
+                        "PubSubSource<{}>",
+                        resource_name(&config.project_id, "subscriptions", subscription)
+                    ),
+                )
+            }
+            TableType::Sink {
+                topic,
+                ordering_key,
+                batch_size,
+            } => {
+                // Pub/Sub accepts at most 1000 messages per publish request
+                if matches!(batch_size, Some(s) if !(1..=1000).contains(s)) {
+                    bail!("batch_size must be between 1 and 1000");
+                }
+
+                if let Some(ordering_key) = ordering_key {
+                    let template = Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap();
+                    for captures in template.captures_iter(ordering_key) {
+                        if !schema.fields.iter().any(|f| f.field_name == captures[1]) {
+                            bail!(
+                                "ordering_key refers to field '{}', which is not in the schema",
+                                &captures[1]
+                            );
+                        }
+                    }
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::pubsub::sink::PubSubSinkFunc::<#in_k, #in_t>",
+                    format!(
+                        "PubSubSink<{}>",
+                        resource_name(&config.project_id, "topics", topic)
+                    ),
+                )
+            }
+        };
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for Pub/Sub connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+struct PubSubTester {
+    config: PubSubConfig,
+    table: PubSubTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl PubSubTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let auth = match &self.config.credentials {
+            Some(credentials) => {
+                AuthenticationManager::from(CustomServiceAccount::from_json(credentials)?)
+            }
+            None => AuthenticationManager::new()
+                .await
+                .map_err(|e| anyhow!("Failed to find application default credentials: {}", e))?,
+        };
+
+        let token = auth
+            .get_token(&[PUBSUB_SCOPE])
+            .await
+            .map_err(|e| anyhow!("Failed to authenticate with GCP: {}", e))?;
+
+        self.info("Authenticated with GCP").await;
+
+        let resource = match &self.table.type_ {
+            TableType::Source { subscription, .. } => {
+                resource_name(&self.config.project_id, "subscriptions", subscription)
+            }
+            TableType::Sink { topic, .. } => {
+                resource_name(&self.config.project_id, "topics", topic)
+            }
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let response = client
+            .get(format!("https://pubsub.googleapis.com/v1/{}", resource))
+            .bearer_auth(token.as_str())
+            .send()
+            .await?;
+
+        match response.status().as_u16() {
+            200 => {}
+            404 => bail!("{} does not exist", resource),
+            401 | 403 => bail!("the credentials do not have access to {}", resource),
+            status => bail!("Pub/Sub responded with {}", status),
+        }
+
+        if let TableType::Source { .. } = &self.table.type_ {
+            let subscription: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+            let enabled = |field: &str| subscription.get(field).and_then(|v| v.as_bool());
+
+            self.info(format!(
+                "Found subscription {} (exactly-once delivery {}, message ordering {})",
+                resource,
+                if enabled("enableExactlyOnceDelivery") == Some(true) {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                if enabled("enableMessageOrdering") == Some(true) {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+            ))
+            .await;
+        } else {
+            self.info(format!("Found topic {}", resource)).await;
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Pub/Sub tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
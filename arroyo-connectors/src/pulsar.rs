@@ -0,0 +1,303 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use pulsar::{Authentication, Pulsar, TokioExecutor};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/pulsar/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/pulsar/table.json");
+const ICON: &str = include_str!("../resources/pulsar.svg");
+
+import_types!(schema = "../connector-schemas/pulsar/connection.json");
+import_types!(schema = "../connector-schemas/pulsar/table.json");
+
+pub struct PulsarConnector {}
+
+impl Connector for PulsarConnector {
+    type ProfileT = PulsarConfig;
+    type TableT = PulsarTable;
+
+    fn name(&self) -> &'static str {
+        "pulsar"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "pulsar".to_string(),
+            name: "Pulsar".to_string(),
+            icon: ICON.to_string(),
+            description: "Read and write from an Apache Pulsar cluster".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.service_url
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = PulsarTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let authentication = match opts.remove("auth.token") {
+            Some(token) => PulsarConfigAuthentication::Token { token },
+            None => PulsarConfigAuthentication::None {},
+        };
+
+        let connection = PulsarConfig {
+            service_url: pull_opt("service_url", opts)?,
+            authentication,
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => {
+                let subscription_type = match opts
+                    .remove("source.subscription_type")
+                    .as_ref()
+                    .map(|f| f.as_str())
+                {
+                    Some("exclusive") | None => SubscriptionType::Exclusive,
+                    Some("failover") => SubscriptionType::Failover,
+                    Some("shared") => SubscriptionType::Shared,
+                    Some("key_shared") => SubscriptionType::KeyShared,
+                    Some(other) => bail!("invalid value for source.subscription_type '{}'", other),
+                };
+
+                let initial_position = match opts
+                    .remove("source.initial_position")
+                    .as_ref()
+                    .map(|f| f.as_str())
+                {
+                    Some("earliest") => Some(InitialPosition::Earliest),
+                    Some("latest") => Some(InitialPosition::Latest),
+                    None => None,
+                    Some(other) => bail!("invalid value for source.initial_position '{}'", other),
+                };
+
+                TableType::Source {
+                    subscription_name: opts.remove("source.subscription_name"),
+                    subscription_type,
+                    initial_position,
+                }
+            }
+            "sink" => TableType::Sink {
+                key_field: opts.remove("sink.key_field"),
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = PulsarTable {
+            topic: pull_opt("topic", opts)?,
+            type_: table_type,
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let (typ, operator, desc) = match table.type_ {
+            TableType::Source { .. } => (
+                ConnectionType::Source,
+                "connectors::pulsar::source::PulsarSourceFunc",
+                format!("PulsarSource<{}>", table.topic),
+            ),
+            TableType::Sink { .. } => (
+                ConnectionType::Sink,
+                "connectors::pulsar::sink::PulsarSinkFunc::<#in_k, #in_t>",
+                format!("PulsarSink<{}>", table.topic),
+            ),
+        };
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Pulsar connection"))?;
+
+        if let TableType::Sink {
+            key_field: Some(key_field),
+        } = &table.type_
+        {
+            if !schema.fields.is_empty()
+                && !schema.fields.iter().any(|f| &f.field_name == key_field)
+            {
+                bail!("key_field '{}' is not a field in the schema", key_field);
+            }
+        }
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for Pulsar connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+async fn pulsar_client(config: &PulsarConfig) -> Result<Pulsar<TokioExecutor>, pulsar::Error> {
+    let mut builder = Pulsar::builder(&config.service_url, TokioExecutor);
+
+    if let PulsarConfigAuthentication::Token { token } = &config.authentication {
+        builder = builder.with_auth(Authentication {
+            name: "token".to_string(),
+            data: token.as_bytes().to_vec(),
+        });
+    }
+
+    builder.build().await
+}
+
+struct PulsarTester {
+    config: PulsarConfig,
+    table: PulsarTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl PulsarTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = tokio::time::timeout(Duration::from_secs(10), pulsar_client(&self.config))
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Timed out connecting to Pulsar at {}",
+                    self.config.service_url
+                )
+            })?
+            .map_err(|e| anyhow!("Failed to connect to Pulsar: {}", e))?;
+
+        self.info("Connected to Pulsar").await;
+
+        // looking up the topic checks both that it exists (or can be auto-created) and that
+        // we're authorized to access it, without creating a durable subscription
+        let partitions = tokio::time::timeout(
+            Duration::from_secs(10),
+            client.lookup_partitioned_topic_number(&self.table.topic),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out looking up topic '{}'", self.table.topic))?
+        .map_err(|e| anyhow!("Failed to look up topic '{}': {}", self.table.topic, e))?;
+
+        if partitions == 0 {
+            self.info(format!(
+                "Found non-partitioned topic '{}'",
+                self.table.topic
+            ))
+            .await;
+        } else {
+            self.info(format!(
+                "Found topic '{}' with {} partitions",
+                self.table.topic, partitions
+            ))
+            .await;
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Pulsar tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
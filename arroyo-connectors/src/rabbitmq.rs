@@ -0,0 +1,333 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use lapin::options::{ExchangeDeclareOptions, QueueDeclareOptions};
+use lapin::types::FieldTable;
+use lapin::{ConnectionProperties, ExchangeKind};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/rabbitmq/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/rabbitmq/table.json");
+const ICON: &str = include_str!("../resources/rabbitmq.svg");
+
+import_types!(schema = "../connector-schemas/rabbitmq/connection.json");
+import_types!(schema = "../connector-schemas/rabbitmq/table.json");
+
+pub struct RabbitmqConnector {}
+
+fn template_regex() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap()
+}
+
+impl Connector for RabbitmqConnector {
+    type ProfileT = RabbitmqConfig;
+    type TableT = RabbitmqTable;
+
+    fn name(&self) -> &'static str {
+        "rabbitmq"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "rabbitmq".to_string(),
+            name: "RabbitMQ".to_string(),
+            icon: ICON.to_string(),
+            description: "Consume from RabbitMQ queues and publish to exchanges".to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        // strip credentials from the description
+        url::Url::parse(&config.url)
+            .map(|mut u| {
+                let _ = u.set_password(None);
+                let _ = u.set_username("");
+                u.to_string()
+            })
+            .unwrap_or(config.url)
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = RabbitmqTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = RabbitmqConfig {
+            url: pull_opt("url", opts)?,
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => TableType::Source {
+                queue: pull_opt("source.queue", opts)?,
+                prefetch_count: pull_option_to_i64("source.prefetch_count", opts)?,
+            },
+            "sink" => TableType::Sink {
+                exchange: opts.remove("sink.exchange"),
+                routing_key: opts.remove("sink.routing_key"),
+                persistent: opts
+                    .remove("sink.persistent")
+                    .map(|r| {
+                        r.parse::<bool>()
+                            .map_err(|_| anyhow!("invalid value for sink.persistent '{}'", r))
+                    })
+                    .transpose()?,
+            },
+            _ => {
+                bail!("type must be one of 'source' or 'sink'")
+            }
+        };
+
+        let table = RabbitmqTable { type_: table_type };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let url = url::Url::parse(&config.url)
+            .map_err(|e| anyhow!("invalid url '{}': {}", config.url, e))?;
+        if url.scheme() != "amqp" && url.scheme() != "amqps" {
+            bail!("url must start with amqp:// or amqps://");
+        }
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for RabbitMQ connection"))?;
+
+        let (typ, operator, desc) = match &table.type_ {
+            TableType::Source {
+                queue,
+                prefetch_count,
+            } => {
+                if let Some(prefetch) = prefetch_count {
+                    if !(1..=u16::MAX as i64).contains(prefetch) {
+                        bail!("prefetch_count must be between 1 and {}", u16::MAX);
+                    }
+                }
+
+                (
+                    ConnectionType::Source,
+                    "connectors::rabbitmq::source::RabbitmqSourceFunc",
+                    format!("RabbitmqSource<{}>", queue),
+                )
+            }
+            TableType::Sink {
+                exchange,
+                routing_key,
+                ..
+            } => {
+                // templates can only refer to fields that exist
+                for template in exchange.iter().chain(routing_key.iter()) {
+                    for captures in template_regex().captures_iter(template) {
+                        let field = &captures[1];
+                        if !schema.fields.iter().any(|f| f.field_name == field) {
+                            bail!(
+                                "'{}' refers to field '{}', which is not in the schema",
+                                template,
+                                field
+                            );
+                        }
+                    }
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::rabbitmq::sink::RabbitmqSinkFunc::<#in_k, #in_t>",
+                    format!(
+                        "RabbitmqSink<{}>",
+                        exchange
+                            .as_deref()
+                            .filter(|e| !e.is_empty())
+                            .unwrap_or("default")
+                    ),
+                )
+            }
+        };
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for RabbitMQ connection"))?;
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: typ,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description: desc,
+        })
+    }
+}
+
+struct RabbitmqTester {
+    config: RabbitmqConfig,
+    table: RabbitmqTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl RabbitmqTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let connection = tokio::time::timeout(
+            Duration::from_secs(10),
+            lapin::Connection::connect(&self.config.url, ConnectionProperties::default()),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out connecting to RabbitMQ"))?
+        .map_err(|e| anyhow!("Failed to connect to RabbitMQ: {}", e))?;
+
+        self.info("Connected to RabbitMQ").await;
+
+        let channel = connection.create_channel().await?;
+
+        match &self.table.type_ {
+            TableType::Source { queue, .. } => {
+                // a passive declare fails if the queue doesn't exist
+                let queue = channel
+                    .queue_declare(
+                        queue,
+                        QueueDeclareOptions {
+                            passive: true,
+                            ..Default::default()
+                        },
+                        FieldTable::default(),
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Failed to find queue '{}': {}", queue, e))?;
+
+                self.info(format!(
+                    "Found queue '{}' with {} messages",
+                    queue.name().as_str(),
+                    queue.message_count()
+                ))
+                .await;
+            }
+            TableType::Sink {
+                exchange: Some(exchange),
+                ..
+            } if !exchange.is_empty() && !template_regex().is_match(exchange) => {
+                channel
+                    .exchange_declare(
+                        exchange,
+                        ExchangeKind::Direct,
+                        ExchangeDeclareOptions {
+                            passive: true,
+                            ..Default::default()
+                        },
+                        FieldTable::default(),
+                    )
+                    .await
+                    .map_err(|e| anyhow!("Failed to find exchange '{}': {}", exchange, e))?;
+
+                self.info(format!("Found exchange '{}'", exchange)).await;
+            }
+            _ => {}
+        }
+
+        if let Err(e) = connection.close(0, "").await {
+            warn!("Failed to close RabbitMQ tester connection: {:?}", e);
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started RabbitMQ tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
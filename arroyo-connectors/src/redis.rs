@@ -0,0 +1,362 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use redis::IntoConnectionInfo;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, pull_option_to_i64, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/redis/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/redis/table.json");
+const ICON: &str = include_str!("../resources/redis.svg");
+
+import_types!(schema = "../connector-schemas/redis/connection.json");
+import_types!(schema = "../connector-schemas/redis/table.json");
+
+pub struct RedisConnector {}
+
+impl Connector for RedisConnector {
+    type ProfileT = RedisConfig;
+    type TableT = RedisTable;
+
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "redis".to_string(),
+            name: "Redis".to_string(),
+            icon: ICON.to_string(),
+            description:
+                "Read from Redis Streams, write to Redis strings, hashes, or streams, and \
+                look up strings in lookup joins"
+                    .to_string(),
+            enabled: true,
+            source: true,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        config.address
+    }
+
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match table.type_ {
+            TableType::Source { .. } => ConnectionType::Source,
+            TableType::Sink { .. } => ConnectionType::Sink,
+            TableType::Lookup { .. } => ConnectionType::Lookup,
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = RedisTester { config, table, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = RedisConfig {
+            address: pull_opt("address", opts)?,
+            username: opts.remove("username"),
+            password: opts.remove("password"),
+        };
+
+        let typ = pull_opt("type", opts)?;
+        let table_type = match typ.as_str() {
+            "source" => {
+                let offset = match opts.remove("source.offset").as_ref().map(|f| f.as_str()) {
+                    Some("earliest") => Some(SourceOffset::Earliest),
+                    Some("latest") => Some(SourceOffset::Latest),
+                    None => None,
+                    Some(other) => bail!("invalid value for source.offset '{}'", other),
+                };
+
+                TableType::Source {
+                    stream_key: pull_opt("source.stream_key", opts)?,
+                    group_name: opts.remove("source.group_name"),
+                    offset,
+                }
+            }
+            "sink" => {
+                let target = match pull_opt("target", opts)?.as_str() {
+                    "string" => Target::StringTable {
+                        key_prefix: pull_opt("target.key_prefix", opts)?,
+                        key_column: pull_opt("target.key_column", opts)?,
+                        ttl_secs: pull_option_to_i64("target.ttl_secs", opts)?,
+                    },
+                    "hash" => Target::HashTable {
+                        key_prefix: pull_opt("target.key_prefix", opts)?,
+                        key_column: opts.remove("target.key_column"),
+                        hash_field_column: pull_opt("target.hash_field_column", opts)?,
+                    },
+                    "stream" => Target::StreamTable {
+                        stream_key: pull_opt("target.stream_key", opts)?,
+                        max_len: pull_option_to_i64("target.max_len", opts)?,
+                    },
+                    other => {
+                        bail!(
+                            "invalid value for target '{}'; expected one of 'string', 'hash', or \
+                            'stream'",
+                            other
+                        )
+                    }
+                };
+
+                TableType::Sink { target }
+            }
+            "lookup" => TableType::Lookup {
+                key_prefix: pull_opt("lookup.key_prefix", opts)?,
+            },
+            _ => {
+                bail!("type must be one of 'source', 'sink', or 'lookup'")
+            }
+        };
+
+        let table = RedisTable { type_: table_type };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        config.address.as_str().into_connection_info()?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for Redis connection"))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for Redis connection"))?;
+
+        let (connection_type, operator, description) = match &table.type_ {
+            TableType::Source { stream_key, .. } => (
+                ConnectionType::Source,
+                "connectors::redis::source::RedisStreamSourceFunc",
+                format!("RedisStreamSource<{}>", stream_key),
+            ),
+            TableType::Sink { target } => {
+                let (columns, description) = match target {
+                    Target::StringTable {
+                        key_prefix,
+                        key_column,
+                        ttl_secs,
+                    } => {
+                        if matches!(ttl_secs, Some(ttl) if *ttl < 1) {
+                            bail!("ttl_secs must be at least 1");
+                        }
+                        (
+                            vec![key_column],
+                            format!("RedisSink<{}{{{}}}>", key_prefix, key_column),
+                        )
+                    }
+                    Target::HashTable {
+                        key_prefix,
+                        key_column,
+                        hash_field_column,
+                    } => (
+                        key_column
+                            .iter()
+                            .chain(std::iter::once(hash_field_column))
+                            .collect(),
+                        format!("RedisHashSink<{}>", key_prefix),
+                    ),
+                    Target::StreamTable {
+                        stream_key,
+                        max_len,
+                    } => {
+                        if matches!(max_len, Some(len) if *len < 1) {
+                            bail!("max_len must be at least 1");
+                        }
+                        (vec![], format!("RedisStreamSink<{}>", stream_key))
+                    }
+                };
+
+                for column in columns {
+                    if !schema.fields.is_empty()
+                        && !schema.fields.iter().any(|f| &f.field_name == column)
+                    {
+                        bail!("column '{}' is not a field in the schema", column);
+                    }
+                }
+
+                (
+                    ConnectionType::Sink,
+                    "connectors::redis::sink::RedisSinkFunc::<#in_k, #in_t>",
+                    description,
+                )
+            }
+            TableType::Lookup { key_prefix } => (
+                ConnectionType::Lookup,
+                "connectors::redis::lookup::RedisLookup",
+                format!("RedisLookup<{}>", key_prefix),
+            ),
+        };
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+impl RedisConfig {
+    pub fn client(&self) -> anyhow::Result<redis::Client> {
+        let mut info = self.address.as_str().into_connection_info()?;
+        if self.username.is_some() {
+            info.redis.username = self.username.clone();
+        }
+        if self.password.is_some() {
+            info.redis.password = self.password.clone();
+        }
+
+        Ok(redis::Client::open(info)?)
+    }
+}
+
+struct RedisTester {
+    config: RedisConfig,
+    table: RedisTable,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl RedisTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = self.config.client()?;
+
+        let mut conn = tokio::time::timeout(
+            Duration::from_secs(10),
+            client.get_multiplexed_tokio_connection(),
+        )
+        .await
+        .map_err(|_| anyhow!("Timed out connecting to Redis"))?
+        .map_err(|e| anyhow!("Failed to connect to Redis: {}", e))?;
+
+        self.info("Connected to Redis").await;
+
+        let _: String = redis::cmd("PING")
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| anyhow!("Failed to ping Redis: {}", e))?;
+
+        let stream_key = match &self.table.type_ {
+            TableType::Source { stream_key, .. } => Some(stream_key),
+            TableType::Sink {
+                target: Target::StreamTable { stream_key, .. },
+            } => Some(stream_key),
+            TableType::Sink { .. } | TableType::Lookup { .. } => None,
+        };
+
+        if let Some(stream_key) = stream_key {
+            let typ: String = redis::cmd("TYPE")
+                .arg(stream_key)
+                .query_async(&mut conn)
+                .await?;
+
+            match typ.as_str() {
+                "stream" => {}
+                "none" => {
+                    self.info(format!(
+                        "Stream '{}' does not exist yet; it will be created",
+                        stream_key
+                    ))
+                    .await;
+                }
+                other => bail!("key '{}' holds a {}, not a stream", stream_key, other),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Redis tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::grpc::connector::remote_connector_client::RemoteConnectorClient;
+use arroyo_rpc::grpc::connector::{ConnectorMetadata, GetMetadataReq, SampleReq, TestTableReq};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::Sender;
+use tonic::transport::Channel;
+use tracing::{info, warn};
+
+use crate::{register_connector, Connection, Connector};
+
+const PLANNER_OPTIONS: [&str; 3] = ["event_time_field", "watermark_field", "idle_micros"];
+
+/// A connector that runs as a separate service, implementing the `RemoteConnector` gRPC
+/// protocol. Its configs and tables are passed through as JSON, and are validated by the service
+/// when the table is tested.
+#[derive(Clone)]
+pub struct RemoteConnector {
+    name: &'static str,
+    endpoint: String,
+    metadata: ConnectorMetadata,
+}
+
+/// Fetches the metadata of the connector at `endpoint` and registers it, making it available
+/// alongside the built-in connectors
+pub async fn register_remote_connector(
+    endpoint: &str,
+) -> anyhow::Result<arroyo_rpc::api_types::connections::Connector> {
+    let metadata = client(endpoint)
+        .await?
+        .get_metadata(GetMetadataReq {})
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "failed to fetch metadata from {}: {}",
+                endpoint,
+                e.message()
+            )
+        })?
+        .into_inner();
+
+    if !metadata.source && !metadata.sink {
+        bail!(
+            "remote connector '{}' must support sources, sinks, or both",
+            metadata.id
+        );
+    }
+
+    info!(
+        "Registering remote connector '{}' at {}",
+        metadata.id, endpoint
+    );
+
+    // connector names live as long as the process, like those of the built-in connectors
+    let connector = RemoteConnector {
+        name: Box::leak(metadata.id.clone().into_boxed_str()),
+        endpoint: endpoint.to_string(),
+        metadata,
+    };
+
+    let metadata = connector.metadata();
+    register_connector(connector.name, move || Box::new(connector.clone()));
+    Ok(metadata)
+}
+
+async fn client(endpoint: &str) -> anyhow::Result<RemoteConnectorClient<Channel>> {
+    RemoteConnectorClient::connect(endpoint.to_string())
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "failed to connect to remote connector at {}: {}",
+                endpoint,
+                e
+            )
+        })
+}
+
+impl Connector for RemoteConnector {
+    type ProfileT = Value;
+    type TableT = Value;
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: self.metadata.id.clone(),
+            name: self.metadata.name.clone(),
+            icon: self.metadata.icon.clone(),
+            description: self.metadata.description.clone(),
+            enabled: true,
+            source: self.metadata.source,
+            sink: self.metadata.sink,
+            testing: self.metadata.testing,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: self.metadata.connection_config.clone(),
+            table_config: self.metadata.table_config.clone(),
+        }
+    }
+
+    /// Connectors that support both sources and sinks determine which a table is by its `type`
+    fn table_type(&self, _: Self::ProfileT, table: Self::TableT) -> ConnectionType {
+        match (self.metadata.source, self.metadata.sink) {
+            (true, false) => ConnectionType::Source,
+            (false, true) => ConnectionType::Sink,
+            _ => match table.get("type").and_then(|t| t.as_str()) {
+                Some("sink") => ConnectionType::Sink,
+                _ => ConnectionType::Source,
+            },
+        }
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let endpoint = self.endpoint.clone();
+        let req = TestTableReq {
+            config: config.to_string(),
+            table: table.to_string(),
+            schema: schema.map(|s| serde_json::to_string(s).unwrap()),
+        };
+
+        tokio::spawn(async move {
+            let send = |message: TestSourceMessage| {
+                let tx = tx.clone();
+                async move {
+                    if tx
+                        .send(Ok(Event::default().json_data(message).unwrap()))
+                        .await
+                        .is_err()
+                    {
+                        warn!("Test API rx closed while sending message");
+                    }
+                }
+            };
+
+            let result = async {
+                let mut stream = client(&endpoint)
+                    .await?
+                    .test_table(req)
+                    .await
+                    .map_err(|e| anyhow!("{}", e.message()))?
+                    .into_inner();
+
+                while let Some(msg) = stream
+                    .message()
+                    .await
+                    .map_err(|e| anyhow!("{}", e.message()))?
+                {
+                    let done = msg.done;
+                    send(TestSourceMessage {
+                        error: msg.error,
+                        done: msg.done,
+                        message: msg.message,
+                    })
+                    .await;
+
+                    if done {
+                        return Ok(());
+                    }
+                }
+
+                bail!("Remote connector closed the test without finishing")
+            }
+            .await;
+
+            if let Err(e) = result {
+                send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            }
+        });
+    }
+
+    fn sample(
+        &self,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        count: usize,
+    ) -> BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>> {
+        let endpoint = self.endpoint.clone();
+        Box::pin(async move {
+            Ok(client(&endpoint)
+                .await?
+                .sample(SampleReq {
+                    config: config.to_string(),
+                    table: table.to_string(),
+                    count: count as u32,
+                })
+                .await
+                .map_err(|e| anyhow!("{}", e.message()))?
+                .into_inner()
+                .messages)
+        })
+    }
+
+    /// Options are passed to the connector as the table, with string values, except for those
+    /// that the planner handles for every table
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let keys: Vec<_> = opts
+            .keys()
+            .filter(|k| !PLANNER_OPTIONS.contains(&k.as_str()))
+            .cloned()
+            .collect();
+
+        let table = keys
+            .into_iter()
+            .map(|k| {
+                let v = opts.remove(&k).unwrap();
+                (k, Value::String(v))
+            })
+            .collect();
+
+        self.from_config(None, name, json!({}), Value::Object(table), schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection_type = self.table_type(config.clone(), table.clone());
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("no schema defined for {} connection", self.metadata.id))?;
+
+        let format = schema
+            .format
+            .as_ref()
+            .map(|t| t.to_owned())
+            .ok_or_else(|| anyhow!("'format' must be set for {} connection", self.metadata.id))?;
+
+        let (operator, description) = match connection_type {
+            ConnectionType::Source => (
+                "connectors::remote::RemoteSourceFunc",
+                format!("RemoteSource<{}>", self.metadata.id),
+            ),
+            ConnectionType::Sink => (
+                "connectors::remote::RemoteSinkFunc::<#in_k, #in_t>",
+                format!("RemoteSink<{}>", self.metadata.id),
+            ),
+        };
+
+        let config = OperatorConfig {
+            connection: json!({
+                "endpoint": self.endpoint,
+                "config": config,
+            }),
+            table,
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format),
+            framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type,
+            schema,
+            operator: operator.to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
@@ -0,0 +1,265 @@
+use std::convert::Infallible;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail};
+use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::formats::{Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::OperatorConfig;
+use axum::response::sse::Event;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rsa::pkcs8::{DecodePrivateKey, EncodePublicKey};
+use rsa::RsaPrivateKey;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{pull_opt, Connection, Connector};
+
+const CONFIG_SCHEMA: &str = include_str!("../../connector-schemas/snowflake/connection.json");
+const TABLE_SCHEMA: &str = include_str!("../../connector-schemas/snowflake/table.json");
+const ICON: &str = include_str!("../resources/snowflake.svg");
+
+import_types!(schema = "../connector-schemas/snowflake/connection.json");
+import_types!(schema = "../connector-schemas/snowflake/table.json");
+
+pub struct SnowflakeConnector {}
+
+impl Connector for SnowflakeConnector {
+    type ProfileT = SnowflakeConfig;
+    type TableT = SnowflakeTable;
+
+    fn name(&self) -> &'static str {
+        "snowflake"
+    }
+
+    fn metadata(&self) -> arroyo_rpc::api_types::connections::Connector {
+        arroyo_rpc::api_types::connections::Connector {
+            id: "snowflake".to_string(),
+            name: "Snowflake".to_string(),
+            icon: ICON.to_string(),
+            description: "Write rows to a Snowflake table with Snowpipe Streaming".to_string(),
+            enabled: true,
+            source: false,
+            sink: true,
+            testing: true,
+            hidden: false,
+            custom_schemas: true,
+            connection_config: Some(CONFIG_SCHEMA.to_string()),
+            table_config: TABLE_SCHEMA.to_string(),
+        }
+    }
+
+    fn config_description(&self, config: Self::ProfileT) -> String {
+        format!("{}@{}", config.user, config.account)
+    }
+
+    fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
+        ConnectionType::Sink
+    }
+
+    fn test(
+        &self,
+        _: &str,
+        config: Self::ProfileT,
+        _: Self::TableT,
+        _: Option<&ConnectionSchema>,
+        tx: Sender<Result<Event, Infallible>>,
+    ) {
+        let tester = SnowflakeTester { config, tx };
+
+        tester.start();
+    }
+
+    fn from_options(
+        &self,
+        name: &str,
+        opts: &mut std::collections::HashMap<String, String>,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        let connection = SnowflakeConfig {
+            account: pull_opt("account", opts)?,
+            user: pull_opt("user", opts)?,
+            private_key: pull_opt("private_key", opts)?,
+            role: opts.remove("role"),
+        };
+
+        let table = SnowflakeTable {
+            database: pull_opt("database", opts)?,
+            schema_name: opts.remove("schema_name"),
+            table_name: pull_opt("table_name", opts)?,
+            pipe: opts.remove("pipe"),
+            channel_prefix: opts.remove("channel_prefix"),
+        };
+
+        Self::from_config(&self, None, name, connection, table, schema)
+    }
+
+    fn from_config(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        config: Self::ProfileT,
+        table: Self::TableT,
+        schema: Option<&ConnectionSchema>,
+    ) -> anyhow::Result<Connection> {
+        jwt(&config)?;
+
+        let schema = schema
+            .map(|s| s.to_owned())
+            .ok_or_else(|| anyhow!("No schema defined for Snowflake sink"))?;
+
+        // rows are sent as newline-delimited JSON
+        let format = match &schema.format {
+            None => Format::Json(JsonFormat::default()),
+            Some(Format::Json(f)) if !f.unstructured && !f.debezium => Format::Json(JsonFormat {
+                timestamp_format: TimestampFormat::RFC3339,
+                ..f.clone()
+            }),
+            Some(_) => bail!("snowflake tables must use the 'json' format"),
+        };
+
+        let description = format!(
+            "SnowflakeSink<{}.{}.{}>",
+            table.database,
+            table.schema_name.as_deref().unwrap_or("PUBLIC"),
+            table.table_name
+        );
+
+        let config = OperatorConfig {
+            connection: serde_json::to_value(config).unwrap(),
+            table: serde_json::to_value(table).unwrap(),
+            rate_limit: None,
+            partition_watermarks: None,
+            format: Some(format.clone()),
+            framing: None,
+            bad_data: None,
+            compression: None,
+        };
+
+        Ok(Connection {
+            id,
+            name: name.to_string(),
+            connection_type: ConnectionType::Sink,
+            schema: ConnectionSchema {
+                format: Some(format),
+                ..schema
+            },
+            operator: "connectors::snowflake::sink::SnowflakeSinkFunc::<#in_k, #in_t>".to_string(),
+            config: serde_json::to_string(&config).unwrap(),
+            description,
+        })
+    }
+}
+
+/// Creates a JWT for key-pair authentication, as described in
+/// https://docs.snowflake.com/en/developer-guide/sql-api/authenticating
+fn jwt(config: &SnowflakeConfig) -> anyhow::Result<String> {
+    let key = RsaPrivateKey::from_pkcs8_pem(&config.private_key)
+        .map_err(|e| anyhow!("invalid private key: {}", e))?;
+    let public_key = key.to_public_key().to_public_key_der()?;
+    let fingerprint = base64::encode(Sha256::digest(public_key.as_bytes()));
+
+    let account = config.account.to_uppercase().replace('.', "-");
+    let user = config.user.to_uppercase();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(jsonwebtoken::encode(
+        &Header::new(Algorithm::RS256),
+        &json!({
+            "iss": format!("{}.{}.SHA256:{}", account, user, fingerprint),
+            "sub": format!("{}.{}", account, user),
+            "iat": now,
+            "exp": now + 3600,
+        }),
+        &EncodingKey::from_rsa_pem(config.private_key.as_bytes())?,
+    )?)
+}
+
+struct SnowflakeTester {
+    config: SnowflakeConfig,
+    tx: Sender<Result<Event, Infallible>>,
+}
+
+impl SnowflakeTester {
+    async fn test(&self) -> anyhow::Result<()> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        let response = client
+            .get(format!(
+                "https://{}.snowflakecomputing.com/v2/streaming/hostname",
+                self.config.account
+            ))
+            .bearer_auth(jwt(&self.config)?)
+            .header("X-Snowflake-Authorization-Token-Type", "KEYPAIR_JWT")
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Snowflake: {}", e))?;
+
+        match response.status().as_u16() {
+            200 => {}
+            401 | 403 => bail!(
+                "Authentication failed; check that the public key is set for user {}",
+                self.config.user
+            ),
+            status => bail!(
+                "Snowflake responded with {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            ),
+        }
+
+        self.info(format!(
+            "Authenticated; streaming ingest host is {}",
+            response.text().await?.trim()
+        ))
+        .await;
+
+        Ok(())
+    }
+
+    async fn info(&self, s: impl Into<String>) {
+        self.send(TestSourceMessage {
+            error: false,
+            done: false,
+            message: s.into(),
+        })
+        .await;
+    }
+
+    async fn send(&self, msg: TestSourceMessage) {
+        if self
+            .tx
+            .send(Ok(Event::default().json_data(msg).unwrap()))
+            .await
+            .is_err()
+        {
+            warn!("Test API rx closed while sending message");
+        }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Started Snowflake tester");
+            if let Err(e) = self.test().await {
+                self.send(TestSourceMessage {
+                    error: true,
+                    done: true,
+                    message: e.to_string(),
+                })
+                .await;
+            } else {
+                self.send(TestSourceMessage {
+                    error: false,
+                    done: true,
+                    message: "Connection is valid".to_string(),
+                })
+                .await;
+            }
+        });
+    }
+}
@@ -5,15 +5,21 @@ use anyhow::{anyhow, bail};
 use arroyo_rpc::OperatorConfig;
 use arroyo_types::string_to_map;
 use axum::response::sse::Event;
-use eventsource_client::Client;
+use eventsource_client::{Client, SSE};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
 use futures::StreamExt;
 use tokio::sync::mpsc::Sender;
 use typify::import_types;
 
-use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, ConnectionType, FieldType, PrimitiveType, TestSourceMessage,
+};
+use arroyo_rpc::formats::Format;
 use serde::{Deserialize, Serialize};
 
-use crate::{pull_opt, Connection, EmptyConfig};
+use crate::testing::validate_messages;
+use crate::{pull_opt, pull_option_to_i64, Connection, EmptyConfig, SAMPLE_TIMEOUT};
 
 use super::Connector;
 
@@ -55,10 +61,31 @@ impl Connector for SSEConnector {
         _: &str,
         _: Self::ProfileT,
         table: Self::TableT,
-        _: Option<&ConnectionSchema>,
+        schema: Option<&ConnectionSchema>,
         tx: Sender<Result<Event, Infallible>>,
     ) {
-        SseTester { config: table, tx }.start();
+        SseTester {
+            config: table,
+            schema: schema.cloned(),
+            tx,
+        }
+        .start();
+    }
+
+    fn sample(
+        &self,
+        _: Self::ProfileT,
+        table: Self::TableT,
+        count: usize,
+    ) -> BoxFuture<'static, anyhow::Result<Vec<Vec<u8>>>> {
+        // sampling doesn't report progress, so the tester's channel is never read
+        let (tx, _) = tokio::sync::mpsc::channel(1);
+        let tester = SseTester {
+            config: table,
+            schema: None,
+            tx,
+        };
+        Box::pin(async move { tester.sample(count, SAMPLE_TIMEOUT).await })
     }
 
     fn table_type(&self, _: Self::ProfileT, _: Self::TableT) -> ConnectionType {
@@ -84,6 +111,26 @@ impl Connector for SSEConnector {
             })?;
         }
 
+        for (name, value) in [
+            ("reconnect_delay_ms", table.reconnect_delay_ms),
+            ("max_reconnect_delay_ms", table.max_reconnect_delay_ms),
+            ("reconnect_backoff_factor", table.reconnect_backoff_factor),
+        ] {
+            if value.map(|v| v <= 0).unwrap_or(false) {
+                bail!("{} must be greater than 0", name);
+            }
+        }
+
+        if table.max_reconnect_attempts.map(|v| v < 0).unwrap_or(false) {
+            bail!("max_reconnect_attempts must not be negative");
+        }
+
+        if let Some(auth) = &table.oauth {
+            if auth.client_secret.is_none() && auth.refresh_token.is_none() {
+                bail!("OAuth authentication requires either a client secret or a refresh token");
+            }
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for SSE connection"))?;
@@ -94,12 +141,23 @@ impl Connector for SSEConnector {
             .map(|t| t.to_owned())
             .ok_or_else(|| anyhow!("'format' must be set for SSE connection"))?;
 
+        if let Some(field) = &table.event_type_field {
+            validate_event_type_field(field, &format, &schema)?;
+        }
+
+        if table.unmatched_events.is_some() && table.events.is_none() {
+            bail!("unmatched_events requires a list of events to match");
+        }
+
         let config = OperatorConfig {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
@@ -122,6 +180,24 @@ impl Connector for SSEConnector {
         let endpoint = pull_opt("endpoint", opts)?;
         let headers = opts.remove("headers");
         let events = opts.remove("events");
+        let event_type_field = opts.remove("event_type_field");
+        let unmatched_events = match opts.remove("unmatched_events").as_deref() {
+            Some("drop") => Some(UnmatchedEvents::Drop),
+            Some("bad_data") => Some(UnmatchedEvents::BadData),
+            None => None,
+            Some(other) => bail!("invalid value for unmatched_events '{}'", other),
+        };
+
+        let oauth = match opts.remove("oauth.token_endpoint") {
+            Some(token_endpoint) => Some(TokenAuthentication {
+                token_endpoint,
+                client_id: pull_opt("oauth.client_id", opts)?,
+                client_secret: opts.remove("oauth.client_secret"),
+                scopes: opts.remove("oauth.scopes"),
+                refresh_token: opts.remove("oauth.refresh_token"),
+            }),
+            None => None,
+        };
 
         self.from_config(
             None,
@@ -130,15 +206,55 @@ impl Connector for SSEConnector {
             SseTable {
                 endpoint,
                 events,
+                event_type_field,
+                unmatched_events,
                 headers: headers.map(Headers),
+                reconnect_delay_ms: pull_option_to_i64("reconnect_delay_ms", opts)?,
+                max_reconnect_delay_ms: pull_option_to_i64("max_reconnect_delay_ms", opts)?,
+                reconnect_backoff_factor: pull_option_to_i64("reconnect_backoff_factor", opts)?,
+                max_reconnect_attempts: pull_option_to_i64("max_reconnect_attempts", opts)?,
+                oauth,
             },
             schema,
         )
     }
 }
 
+/// Checks that the event type can be merged into records, which requires a structured format and
+/// a TEXT field in the schema to hold it
+fn validate_event_type_field(
+    name: &str,
+    format: &Format,
+    schema: &ConnectionSchema,
+) -> anyhow::Result<()> {
+    match format {
+        Format::Json(json) if !json.debezium && !json.unstructured => {}
+        Format::Avro(_) | Format::Protobuf(_) => {}
+        _ => bail!(
+            "event_type_field is only supported for tables with the 'json', 'avro', or \
+            'protobuf' formats"
+        ),
+    }
+
+    let field = schema
+        .fields
+        .iter()
+        .find(|f| f.field_name == name)
+        .ok_or_else(|| anyhow!("event_type_field '{}' is not in the schema", name))?;
+
+    if !matches!(
+        field.field_type.r#type,
+        FieldType::Primitive(PrimitiveType::String)
+    ) {
+        bail!("event_type_field '{}' must have type TEXT", name);
+    }
+
+    Ok(())
+}
+
 struct SseTester {
     config: SseTable,
+    schema: Option<ConnectionSchema>,
     tx: Sender<Result<Event, Infallible>>,
 }
 
@@ -165,7 +281,50 @@ impl SseTester {
         });
     }
 
-    async fn test_internal(&self) -> anyhow::Result<()> {
+    /// Fetches an access token using the configured grant, for use as a bearer token
+    async fn token(auth: &TokenAuthentication) -> anyhow::Result<String> {
+        let mut form = vec![];
+        match &auth.refresh_token {
+            Some(refresh_token) => {
+                form.push(("grant_type", "refresh_token"));
+                form.push(("refresh_token", refresh_token.as_str()));
+            }
+            None => form.push(("grant_type", "client_credentials")),
+        }
+        if let Some(scopes) = &auth.scopes {
+            form.push(("scope", scopes.as_str()));
+        }
+
+        let mut request = reqwest::Client::new().post(&auth.token_endpoint);
+        request = match &auth.client_secret {
+            Some(secret) => request.basic_auth(&auth.client_id, Some(secret)),
+            None => {
+                form.push(("client_id", auth.client_id.as_str()));
+                request
+            }
+        };
+
+        let resp = request
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to reach token endpoint: {}", e))?;
+
+        let status = resp.status();
+        let body = resp.text().await?;
+        if !status.is_success() {
+            bail!("Token endpoint responded with {}: {}", status, body);
+        }
+
+        serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("access_token")?.as_str().map(|s| s.to_string()))
+            .ok_or_else(|| anyhow!("Token endpoint response did not contain an access_token"))
+    }
+
+    async fn stream(
+        &self,
+    ) -> anyhow::Result<BoxStream<'static, Result<SSE, eventsource_client::Error>>> {
         let mut client = eventsource_client::ClientBuilder::for_url(&self.config.endpoint)
             .map_err(|_| anyhow!("Endpoint URL is invalid"))?;
 
@@ -184,7 +343,18 @@ impl SseTester {
                 .map_err(|_| anyhow!("Invalid header '{}: {}'", k, v))?;
         }
 
-        let mut stream = client.build().stream();
+        if let Some(auth) = &self.config.oauth {
+            let token = Self::token(auth).await?;
+            client = client
+                .header("Authorization", &format!("Bearer {}", token))
+                .map_err(|_| anyhow!("Access token is not a valid header value"))?;
+        }
+
+        Ok(client.build().stream())
+    }
+
+    async fn test_internal(&self) -> anyhow::Result<()> {
+        let mut stream = self.stream().await?;
 
         let timeout = Duration::from_secs(30);
 
@@ -200,15 +370,18 @@ impl SseTester {
 
         tokio::select! {
             val = stream.next() => {
-                // TODO: validate schema
                 match val {
-                    Some(Ok(_)) => {
+                    Some(Ok(event)) => {
                         let message = TestSourceMessage {
                             error: false,
                             done: false,
                             message: "Received message from SSE server".to_string()
                         };
                         self.tx.send(Ok(Event::default().json_data(message).unwrap())).await.unwrap();
+
+                        if let (SSE::Event(event), Some(schema)) = (event, &self.schema) {
+                            validate_messages(&self.tx, schema, &[event.data.into_bytes()]).await?;
+                        }
                     }
                     Some(Err(e)) => {
                         bail!("Received error from server: {:?}", e);
@@ -225,4 +398,42 @@ impl SseTester {
 
         Ok(())
     }
+
+    /// Collects the data of up to `count` events, waiting at most `timeout`
+    async fn sample(&self, count: usize, timeout: Duration) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut stream = self.stream().await?;
+
+        let events: Vec<_> = self
+            .config
+            .events
+            .as_ref()
+            .map(|e| e.split(',').map(|e| e.trim().to_string()).collect())
+            .unwrap_or_default();
+
+        let mut messages = vec![];
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+
+        while messages.len() < count {
+            tokio::select! {
+                val = stream.next() => {
+                    match val {
+                        Some(Ok(SSE::Event(event))) => {
+                            if events.is_empty() || events.contains(&event.event_type) {
+                                messages.push(event.data.into_bytes());
+                            }
+                        }
+                        Some(Ok(SSE::Comment(_))) => {}
+                        Some(Err(e)) => {
+                            bail!("Received error from server: {:?}", e);
+                        }
+                        None => break,
+                    }
+                }
+                _ = &mut deadline => break,
+            }
+        }
+
+        Ok(messages)
+    }
 }
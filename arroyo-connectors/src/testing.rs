@@ -0,0 +1,276 @@
+use std::convert::Infallible;
+
+use arroyo_rpc::api_types::connections::{
+    ConnectionSchema, FieldType, PrimitiveType, SourceField, TestSourceMessage,
+};
+use arroyo_rpc::formats::{Format, FramingMethod, JsonFormat, TimestampFormat};
+use axum::response::sse::Event;
+use chrono::DateTime;
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
+
+/// Validates sampled messages against a table's schema, sending a message to the tester for each
+/// mismatch that's found. Returns an error summarizing the mismatches if there were any, so
+/// that testers can fail with it.
+pub(crate) async fn validate_messages(
+    tx: &Sender<Result<Event, Infallible>>,
+    schema: &ConnectionSchema,
+    messages: &[Vec<u8>],
+) -> anyhow::Result<()> {
+    let send = |error: bool, message: String| async move {
+        // the tester may have gone away, in which case there's no one to report to
+        let _ = tx
+            .send(Ok(Event::default()
+                .json_data(TestSourceMessage {
+                    error,
+                    done: false,
+                    message,
+                })
+                .unwrap()))
+            .await;
+    };
+
+    if let Some(reason) = unsupported(schema) {
+        send(false, format!("Skipping schema validation: {}", reason)).await;
+        return Ok(());
+    }
+
+    let mut mismatches = 0;
+    for (i, message) in messages.iter().enumerate() {
+        for error in validate_message(schema, message) {
+            mismatches += 1;
+            send(true, format!("Message {}: {}", i + 1, error)).await;
+        }
+    }
+
+    if mismatches > 0 {
+        anyhow::bail!(
+            "Found {} schema mismatches in {} messages",
+            mismatches,
+            messages.len()
+        );
+    }
+
+    send(
+        false,
+        format!("Validated {} messages against the schema", messages.len()),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Returns why the messages for this schema can't be validated, if they can't
+fn unsupported(schema: &ConnectionSchema) -> Option<String> {
+    if schema.compression.is_some() {
+        return Some("compressed messages can't be validated".to_string());
+    }
+
+    if let Some(FramingMethod::LengthPrefixed(_)) = schema.framing.as_ref().map(|f| &f.method) {
+        return Some("length-prefixed messages can't be validated".to_string());
+    }
+
+    match &schema.format {
+        None => Some("no format is set".to_string()),
+        Some(Format::Json(JsonFormat { debezium: true, .. })) => {
+            Some("debezium messages can't be validated".to_string())
+        }
+        Some(Format::Json(_) | Format::RawString(_) | Format::RawBytes(_)) => None,
+        Some(_) => Some("only JSON and raw formats can be validated".to_string()),
+    }
+}
+
+/// Checks a single message against the schema, returning a description of each mismatch
+fn validate_message(schema: &ConnectionSchema, message: &[u8]) -> Vec<String> {
+    let records: Vec<&[u8]> = match schema.framing.as_ref().map(|f| &f.method) {
+        Some(FramingMethod::Newline(_)) => message
+            .split(|b| *b == b'\n')
+            .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+            .filter(|l| !l.is_empty())
+            .collect(),
+        _ => vec![message],
+    };
+
+    let mut errors = vec![];
+    for record in records {
+        match &schema.format {
+            Some(Format::Json(json)) => validate_json(json, &schema.fields, record, &mut errors),
+            Some(Format::RawString(_)) => {
+                if std::str::from_utf8(record).is_err() {
+                    errors.push("message is not valid UTF-8".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    errors
+}
+
+fn validate_json(
+    format: &JsonFormat,
+    fields: &[SourceField],
+    msg: &[u8],
+    errors: &mut Vec<String>,
+) {
+    // messages written with the schema registry are prefixed with a magic byte and schema id
+    let msg = if format.confluent_schema_registry && msg.len() >= 5 {
+        &msg[5..]
+    } else {
+        msg
+    };
+
+    let value: Value = match serde_json::from_slice(msg) {
+        Ok(value) => value,
+        Err(e) => {
+            errors.push(format!("message is not valid JSON: {}", e));
+            return;
+        }
+    };
+
+    if format.unstructured {
+        return;
+    }
+
+    // records that include their schema carry the data in the payload
+    let value = if format.include_schema {
+        value.get("payload").cloned().unwrap_or(Value::Null)
+    } else {
+        value
+    };
+
+    if !value.is_object() {
+        errors.push(format!("expected a JSON object, but found '{}'", value));
+        return;
+    }
+
+    for field in fields {
+        let path = field
+            .json_path
+            .as_ref()
+            .or_else(|| format.field_paths.get(&field.field_name));
+
+        let v = match path {
+            Some(p) if p.is_empty() || p.starts_with('/') => value.pointer(p),
+            // JSONPath expressions aren't evaluated here, so these fields aren't checked
+            Some(_) => continue,
+            None => value.get(&field.field_name),
+        };
+
+        validate_field(format, field, &field.field_name, v, errors);
+    }
+}
+
+fn validate_field(
+    format: &JsonFormat,
+    field: &SourceField,
+    name: &str,
+    value: Option<&Value>,
+    errors: &mut Vec<String>,
+) {
+    let value = match value {
+        None | Some(Value::Null) if field.nullable => return,
+        None => {
+            errors.push(format!("field '{}' is missing", name));
+            return;
+        }
+        Some(Value::Null) => {
+            errors.push(format!("field '{}' is null, but is not nullable", name));
+            return;
+        }
+        Some(value) => value,
+    };
+
+    let expected = match &field.field_type.r#type {
+        FieldType::Struct(s) => {
+            if !value.is_object() {
+                errors.push(format!(
+                    "field '{}' should be an object, but found '{}'",
+                    name, value
+                ));
+                return;
+            }
+
+            for f in &s.fields {
+                validate_field(
+                    format,
+                    f,
+                    &format!("{}.{}", name, f.field_name),
+                    value.get(&f.field_name),
+                    errors,
+                );
+            }
+            return;
+        }
+        FieldType::List(item) => {
+            let Some(values) = value.as_array() else {
+                errors.push(format!(
+                    "field '{}' should be an array, but found '{}'",
+                    name, value
+                ));
+                return;
+            };
+
+            for (i, v) in values.iter().enumerate() {
+                validate_field(format, item, &format!("{}[{}]", name, i), Some(v), errors);
+            }
+            return;
+        }
+        FieldType::Map(map) => {
+            let Some(entries) = value.as_object() else {
+                errors.push(format!(
+                    "field '{}' should be an object, but found '{}'",
+                    name, value
+                ));
+                return;
+            };
+
+            for (k, v) in entries {
+                validate_field(
+                    format,
+                    &map.value,
+                    &format!("{}.{}", name, k),
+                    Some(v),
+                    errors,
+                );
+            }
+            return;
+        }
+        FieldType::Decimal(_) => {
+            if !(value.is_number() || value.is_string()) {
+                errors.push(format!(
+                    "field '{}' should be a decimal, but found '{}'",
+                    name, value
+                ));
+            }
+            return;
+        }
+        FieldType::Primitive(p) => p,
+    };
+
+    let matches = match expected {
+        PrimitiveType::Int32 | PrimitiveType::Int64 => value.is_i64(),
+        PrimitiveType::UInt32 | PrimitiveType::UInt64 => value.is_u64(),
+        PrimitiveType::F32 | PrimitiveType::F64 => value.is_number(),
+        PrimitiveType::Bool => value.is_boolean(),
+        PrimitiveType::String | PrimitiveType::Bytes => value.is_string(),
+        PrimitiveType::UnixMillis | PrimitiveType::UnixMicros | PrimitiveType::UnixNanos => {
+            value.is_i64()
+        }
+        PrimitiveType::DateTime => match format.timestamp_format {
+            TimestampFormat::RFC3339 => value
+                .as_str()
+                .map(|s| DateTime::parse_from_rfc3339(s).is_ok())
+                .unwrap_or(false),
+            TimestampFormat::UnixMillis => value.is_i64(),
+        },
+        PrimitiveType::Json => true,
+    };
+
+    if !matches {
+        errors.push(format!(
+            "field '{}' should have type {:?}, but found '{}'",
+            name, expected, value
+        ));
+    }
+}
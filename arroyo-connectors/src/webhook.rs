@@ -1,6 +1,6 @@
 use std::convert::Infallible;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use arroyo_rpc::OperatorConfig;
 
 use axum::response::sse::Event;
@@ -12,7 +12,7 @@ use typify::import_types;
 use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType, TestSourceMessage};
 use serde::{Deserialize, Serialize};
 
-use crate::{construct_http_client, pull_opt, Connection, EmptyConfig};
+use crate::{construct_http_client, pull_opt, pull_option_to_i64, Connection, EmptyConfig};
 
 use super::Connector;
 
@@ -135,6 +135,27 @@ impl Connector for WebhookConnector {
     ) -> anyhow::Result<crate::Connection> {
         let description = format!("WebhookSource<{}>", table.endpoint);
 
+        if table.batch_size.unwrap_or(1) > 1
+            && table
+                .headers
+                .as_ref()
+                .map(|h| h.0.contains("{{"))
+                .unwrap_or(false)
+        {
+            bail!("headers can't reference record fields when records are sent in batches");
+        }
+
+        if let Some(max_retries) = table.max_retries {
+            if max_retries < 0 {
+                bail!("'max_retries' must not be negative");
+            }
+        }
+
+        if let Some(dead_letter) = &table.dead_letter_endpoint {
+            reqwest::Url::parse(dead_letter)
+                .map_err(|e| anyhow!("invalid dead_letter_endpoint '{}': {}", dead_letter, e))?;
+        }
+
         let schema = schema
             .map(|s| s.to_owned())
             .ok_or_else(|| anyhow!("no schema defined for webhook connection"))?;
@@ -149,8 +170,11 @@ impl Connector for WebhookConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
@@ -178,7 +202,14 @@ impl Connector for WebhookConnector {
             .transpose()
             .map_err(|e| anyhow!("invalid value for 'headers' config: {:?}", e))?;
 
-        let table = WebhookTable { endpoint, headers };
+        let table = WebhookTable {
+            endpoint,
+            headers,
+            batch_size: pull_option_to_i64("batch_size", opts)?,
+            flush_interval_ms: pull_option_to_i64("flush_interval_ms", opts)?,
+            max_retries: pull_option_to_i64("max_retries", opts)?,
+            dead_letter_endpoint: opts.remove("dead_letter_endpoint"),
+        };
         let client = construct_http_client(&table.endpoint, table.headers.as_ref().map(|t| &t.0))?;
         let _ = Self::construct_test_request(&client, &table)?;
 
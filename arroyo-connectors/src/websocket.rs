@@ -166,25 +166,48 @@ impl Connector for WebsocketConnector {
                 }
             }
 
-            tokio::select! {
-                message = rx.next() => {
-                    match message {
-                        Some(Ok(_)) => {
-                            send(false, false, "Received message from websocket".to_string()).await;
-                        },
-                        Some(Err(e)) => {
-                            send(true, true, format!("Received error from websocket: {:?}", e)).await;
-                            return;
-                        }
-                        None => {
-                            send(true, true, "Websocket disconnected before sending message".to_string()).await;
-                            return;
+            let deadline = tokio::time::sleep(Duration::from_secs(30));
+            tokio::pin!(deadline);
+
+            // wait for the first data frame; control frames are handled by tungstenite
+            // and don't tell us anything about whether the subscription worked
+            loop {
+                tokio::select! {
+                    message = rx.next() => {
+                        match message {
+                            Some(Ok(tungstenite::Message::Text(_))) => {
+                                send(false, false, "Received text frame from websocket".to_string()).await;
+                                break;
+                            }
+                            Some(Ok(tungstenite::Message::Binary(_))) => {
+                                send(false, false, "Received binary frame from websocket".to_string()).await;
+                                break;
+                            }
+                            Some(Ok(tungstenite::Message::Close(frame))) => {
+                                let reason = frame
+                                    .map(|f| format!("{} ({})", f.reason, f.code))
+                                    .unwrap_or_else(|| "no reason given".to_string());
+                                send(true, true, format!("Websocket server closed the connection: {}", reason)).await;
+                                return;
+                            }
+                            Some(Ok(_)) => {
+                                // ping, pong, or raw frame
+                                continue;
+                            }
+                            Some(Err(e)) => {
+                                send(true, true, format!("Received error from websocket: {:?}", e)).await;
+                                return;
+                            }
+                            None => {
+                                send(true, true, "Websocket disconnected before sending message".to_string()).await;
+                                return;
+                            }
                         }
                     }
-                }
-                _ = tokio::time::sleep(Duration::from_secs(30)) => {
-                    send(true, true, "Did not receive any messages after 30 seconds".to_string()).await;
-                    return;
+                    _ = &mut deadline => {
+                        send(true, true, "Did not receive any messages after 30 seconds".to_string()).await;
+                        return;
+                    }
                 }
             }
 
@@ -234,8 +257,11 @@ impl Connector for WebsocketConnector {
             connection: serde_json::to_value(config).unwrap(),
             table: serde_json::to_value(table).unwrap(),
             rate_limit: None,
+            partition_watermarks: None,
             format: Some(format),
             framing: schema.framing.clone(),
+            bad_data: schema.bad_data.clone(),
+            compression: schema.compression,
         };
 
         Ok(Connection {
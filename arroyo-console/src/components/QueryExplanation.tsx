@@ -0,0 +1,71 @@
+import { Stack, Table, TableContainer, Tbody, Td, Text, Th, Thead, Tr } from '@chakra-ui/react';
+import React from 'react';
+import SyntaxHighlighter from 'react-syntax-highlighter';
+import { vs2015 } from 'react-syntax-highlighter/dist/esm/styles/hljs';
+import { PipelineExplanation } from '../lib/data_fetching';
+
+export interface QueryExplanationProps {
+  explanation: PipelineExplanation;
+}
+
+const QueryExplanation: React.FC<QueryExplanationProps> = ({ explanation }) => {
+  // operators only have row counts for EXPLAIN ANALYZE, once its preview has started
+  const rows = (count?: number | null) => (explanation.analyze ? <Td>{count ?? 0}</Td> : null);
+
+  const tableBody = (
+    <Tbody>
+      {explanation.nodes.map(n => {
+        return (
+          <Tr key={n.nodeId}>
+            <Td>
+              <Text maxW={400} whiteSpace={'normal'}>
+                {n.operator}
+              </Text>
+            </Td>
+            <Td>{n.parallelism}</Td>
+            <Td>
+              <Text maxW={300} whiteSpace={'normal'}>
+                {n.state ?? 'none'}
+              </Text>
+            </Td>
+            {rows(n.rowsIn)}
+            {rows(n.rowsOut)}
+          </Tr>
+        );
+      })}
+    </Tbody>
+  );
+
+  return (
+    <Stack spacing={4}>
+      <Text fontWeight={'bold'}>Logical plan</Text>
+      {explanation.logicalPlans.map((plan, i) => (
+        <SyntaxHighlighter
+          key={i}
+          language="text"
+          style={vs2015}
+          customStyle={{ borderRadius: '5px' }}
+        >
+          {plan}
+        </SyntaxHighlighter>
+      ))}
+      <Text fontWeight={'bold'}>Operators</Text>
+      <TableContainer>
+        <Table variant="striped" w={'100%'}>
+          <Thead>
+            <Tr>
+              <Th>Operator</Th>
+              <Th>Parallelism</Th>
+              <Th>State</Th>
+              {explanation.analyze ? <Th>Rows In</Th> : null}
+              {explanation.analyze ? <Th>Rows Out</Th> : null}
+            </Tr>
+          </Thead>
+          {tableBody}
+        </Table>
+      </TableContainer>
+    </Stack>
+  );
+};
+
+export default QueryExplanation;
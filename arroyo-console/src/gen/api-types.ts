@@ -41,6 +41,13 @@ export interface paths {
      */
     get: operations["get_confluent_schema"];
   };
+  "/v1/connection_tables/schemas/infer": {
+    /**
+     * Infer a Connection Schema from messages sampled from the source 
+     * @description Infer a Connection Schema from messages sampled from the source
+     */
+    post: operations["infer_schema"];
+  };
   "/v1/connection_tables/schemas/test": {
     /**
      * Test a Connection Schema 
@@ -69,6 +76,16 @@ export interface paths {
      */
     get: operations["get_connectors"];
   };
+  "/v1/connectors/remote": {
+    /**
+     * Register a remote connector 
+     * @description Register a remote connector
+     * 
+     * The connector is registered with this API server until it restarts; to register connectors
+     * on startup, list their endpoints in `REMOTE_CONNECTORS`.
+     */
+    post: operations["register_connector"];
+  };
   "/v1/jobs": {
     /**
      * Get all jobs 
@@ -97,6 +114,16 @@ export interface paths {
      */
     post: operations["post_pipeline"];
   };
+  "/v1/pipelines/explain": {
+    /**
+     * Explain a query, returning its logical plans and the dataflow graph it compiles to. 
+     * @description Explain a query, returning its logical plans and the dataflow graph it compiles to.
+     * 
+     * `EXPLAIN ANALYZE` queries are started as a preview pipeline, whose job's explanation
+     * includes the number of rows each operator has processed so far.
+     */
+    post: operations["explain_query"];
+  };
   "/v1/pipelines/validate_query": {
     /**
      * Get a pipeline graph 
@@ -142,6 +169,34 @@ export interface paths {
      */
     post: operations["restart_pipeline"];
   };
+  "/v1/pipelines/{id}/updates": {
+    /**
+     * List a pipeline's updates 
+     * @description List a pipeline's updates
+     */
+    get: operations["get_pipeline_updates"];
+    /**
+     * Update a running pipeline to a new version 
+     * @description Update a running pipeline to a new version
+     * 
+     * The controller takes a savepoint of the pipeline's job and starts the new version from it as
+     * a new pipeline, with its sinks replaced by web sinks. Once the new version has caught up (and
+     * the update has been promoted, if `compareOutputs` is set), the current version is stopped with
+     * a final checkpoint and the new one is restarted with the real sinks from where it stopped. The
+     * pipeline pauses briefly while it's switched, but its output isn't duplicated.
+     */
+    post: operations["create_pipeline_update"];
+  };
+  "/v1/pipelines/{id}/updates/{update_id}/promote": {
+    /**
+     * Promote an update that is waiting for its output to be compared 
+     * @description Promote an update that is waiting for its output to be compared
+     * 
+     * The current version is stopped with a final checkpoint, and the new version is restarted from
+     * it with the real sinks.
+     */
+    post: operations["promote_pipeline_update"];
+  };
   "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints": {
     /**
      * List a job's checkpoints 
@@ -156,6 +211,18 @@ export interface paths {
      */
     get: operations["get_checkpoint_details"];
   };
+  "/v1/pipelines/{pipeline_id}/jobs/{job_id}/checkpoints/{epoch}/state": {
+    /**
+     * Inspect the state stored in a checkpoint 
+     * @description Inspect the state stored in a checkpoint
+     * 
+     * Lists the tables of each operator's state with their sizes, record and key counts and how
+     * their keys are spread across the operator's subtasks, along with a sample of their entries.
+     * Entries are decoded as JSON where the types of the state are known, and shown as hex
+     * otherwise.
+     */
+    get: operations["get_checkpoint_state"];
+  };
   "/v1/pipelines/{pipeline_id}/jobs/{job_id}/errors": {
     /**
      * List a job's error messages 
@@ -163,6 +230,13 @@ export interface paths {
      */
     get: operations["get_job_errors"];
   };
+  "/v1/pipelines/{pipeline_id}/jobs/{job_id}/explain": {
+    /**
+     * Explain a job, including the number of rows each operator has processed 
+     * @description Explain a job, including the number of rows each operator has processed
+     */
+    get: operations["get_job_explanation"];
+  };
   "/v1/pipelines/{pipeline_id}/jobs/{job_id}/operator_metric_groups": {
     /**
      * Get a job's metrics 
@@ -177,13 +251,93 @@ export interface paths {
      */
     get: operations["get_job_output"];
   };
+  "/v1/pipelines/{pipeline_id}/jobs/{job_id}/savepoints": {
+    /**
+     * List a job's savepoints 
+     * @description List a job's savepoints
+     */
+    get: operations["get_job_savepoints"];
+    /**
+     * Take a savepoint of a running job 
+     * @description Take a savepoint of a running job
+     * 
+     * A savepoint is a snapshot of the job's state with a name, which is kept after the job's own
+     * checkpoints are cleaned up or the job is deleted, and which new pipelines can be started from.
+     */
+    post: operations["create_savepoint"];
+  };
+  "/v1/pipelines/{pipeline_id}/jobs/{job_id}/state/{operator_id}": {
+    /**
+     * Query an operator's current state for a key 
+     * @description Query an operator's current state for a key
+     * 
+     * The key is given as JSON in the form of the operator's key type; the request is routed to the
+     * subtask that owns the key. Currently only updating aggregates can be queried.
+     */
+    get: operations["get_operator_state"];
+  };
+  "/v1/savepoints": {
+    /**
+     * List all savepoints, including those of jobs that have been deleted 
+     * @description List all savepoints, including those of jobs that have been deleted
+     */
+    get: operations["get_savepoints"];
+  };
+  "/v1/views": {
+    /**
+     * List all views 
+     * @description List all views
+     */
+    get: operations["get_views"];
+    /**
+     * Create a view, which pipelines can read from like a table 
+     * @description Create a view, which pipelines can read from like a table
+     */
+    post: operations["create_view"];
+  };
+  "/v1/views/{id}": {
+    /**
+     * Delete a view 
+     * @description Delete a view
+     */
+    delete: operations["delete_view"];
+  };
 }
 
 export type webhooks = Record<string, never>;
 
 export interface components {
   schemas: {
-    AvroFormat: Record<string, never>;
+    /**
+     * @description Lets the controller rescale a running pipeline according to its load, based on how busy its
+     * operators are, backpressure and how far behind its sources are reading
+     */
+    AutoscalingConfig: {
+      enabled: boolean;
+      /** Format: int64 */
+      maxParallelism: number;
+      /** Format: int64 */
+      minParallelism: number;
+    };
+    AvroFormat: {
+      confluentSchemaRegistry?: boolean;
+      /**
+       * @description The writer schema for messages that aren't framed with a schema registry id; if not set,
+       * a schema is generated from the table's fields
+       */
+      schema?: string | null;
+    };
+    /** @description How sources handle messages that can't be deserialized */
+    BadData: OneOf<[{
+      /** @description Fail the pipeline with an error */
+      fail: Record<string, never>;
+    }, {
+      /** @description Skip the message */
+      skip: Record<string, never>;
+    }, {
+      /** @description Skip the message, writing it and the error to a dead letter queue */
+      route: components["schemas"]["DeadLetterQueue"];
+    }]>;
     Checkpoint: {
       backend: string;
       /** Format: int32 */
@@ -206,6 +360,12 @@ export interface components {
     };
     /** @enum {string} */
     CheckpointSpanType: "alignment" | "sync" | "async" | "committing";
+    /**
+     * @description Compression applied to whole message payloads; sources decompress messages before they're
+     * framed and deserialized, and sinks compress each message they write
+     * @enum {string}
+     */
+    Compression: "gzip" | "zstd" | "snappy";
     ConfluentSchema: {
       schema: string;
     };
@@ -225,6 +385,8 @@ export interface components {
       name: string;
     };
     ConnectionSchema: {
+      badData?: components["schemas"]["BadData"] | null;
+      compression?: components["schemas"]["Compression"] | null;
       definition?: components["schemas"]["SchemaDefinition"] | null;
       fields: (components["schemas"]["SourceField"])[];
       format?: components["schemas"]["Format"] | null;
@@ -256,7 +418,7 @@ export interface components {
       schema?: components["schemas"]["ConnectionSchema"] | null;
     };
     /** @enum {string} */
-    ConnectionType: "source" | "sink";
+    ConnectionType: "source" | "sink" | "lookup";
     Connector: {
       connectionConfig?: string | null;
       customSchemas: boolean;
@@ -274,25 +436,119 @@ export interface components {
     ConnectorCollection: {
       data: (components["schemas"]["Connector"])[];
     };
+    CsvFormat: {
+      delimiter?: string;
+      /**
+       * @description Whether each message starts with a header row naming its columns; without one, columns
+       * are matched to fields by position
+       */
+      header?: boolean;
+      /** @description The value that represents null */
+      nullValue?: string;
+      quote?: string;
+    };
+    /**
+     * @description A fixed-precision decimal, with `precision` digits of which `scale` are after the decimal
+     * point
+     */
+    DecimalType: {
+      /** Format: int32 */
+      precision: number;
+      /** Format: int32 */
+      scale: number;
+    };
+    /**
+     * @description A Kafka topic that messages which couldn't be deserialized are written to, with headers
+     * describing the error
+     */
+    DeadLetterQueue: {
+      bootstrapServers: string;
+      topic: string;
+    };
+    ExplainNode: {
+      nodeId: string;
+      operator: string;
+      /** Format: int32 */
+      parallelism: number;
+      /** Format: int64 */
+      rowsIn?: number | null;
+      /** Format: int64 */
+      rowsOut?: number | null;
+      state?: string | null;
+    };
+    /**
+     * @description Restarts the job after a delay that doubles with each restart in a row, up to
+     * `max_delay_millis`, failing it after `max_restarts` restarts in a row
+     */
+    ExponentialBackoffRestart: {
+      /** Format: int64 */
+      initialDelayMillis: number;
+      /** Format: int64 */
+      maxDelayMillis: number;
+      /** Format: int32 */
+      maxRestarts: number;
+    };
+    /**
+     * @description Restarts the job after a fixed delay, failing it once it has failed more than
+     * `max_failures` times within `interval_millis`
+     */
+    FailureRateRestart: {
+      /** Format: int64 */
+      delayMillis: number;
+      /** Format: int64 */
+      intervalMillis: number;
+      /** Format: int32 */
+      maxFailures: number;
+    };
     FieldType: OneOf<[{
       primitive: components["schemas"]["PrimitiveType"];
     }, {
       struct: components["schemas"]["StructType"];
+    }, {
+      decimal: components["schemas"]["DecimalType"];
+    }, {
+      list: components["schemas"]["SourceField"];
+    }, {
+      map: components["schemas"]["MapType"];
     }]>;
+    /**
+     * @description Restarts the job after the same delay each time, failing it after `max_restarts` restarts in
+     * a row without the job becoming healthy in between
+     */
+    FixedDelayRestart: {
+      /** Format: int64 */
+      delayMillis: number;
+      /** Format: int32 */
+      maxRestarts: number;
+    };
     Format: OneOf<[{
       json: components["schemas"]["JsonFormat"];
     }, {
       avro: components["schemas"]["AvroFormat"];
+    }, {
+      protobuf: components["schemas"]["ProtobufFormat"];
     }, {
       parquet: components["schemas"]["ParquetFormat"];
     }, {
       raw_string: components["schemas"]["RawStringFormat"];
+    }, {
+      raw_bytes: components["schemas"]["RawBytesFormat"];
+    }, {
+      csv: components["schemas"]["CsvFormat"];
     }]>;
     Framing: {
       method: components["schemas"]["FramingMethod"];
     };
-    FramingMethod: {
+    FramingMethod: OneOf<[{
       newline: components["schemas"]["NewlineDelimitedFraming"];
+    }, {
+      length_prefixed: components["schemas"]["LengthPrefixedFraming"];
+    }]>;
+    InferredSchema: {
+      ddl: string;
+      fields: (components["schemas"]["SourceField"])[];
+      /** Format: int32 */
+      messagesSampled: number;
     };
     Job: {
       /** Format: int64 */
@@ -333,10 +589,34 @@ export interface components {
     JsonFormat: {
       confluentSchemaRegistry?: boolean;
       debezium?: boolean;
+      /**
+       * @description Fields whose values are read from elsewhere in the record, given as a JSON pointer (like
+       * `/user/id`) or a JSONPath expression (like `$.user.id`)
+       */
+      fieldPaths?: {
+        [key: string]: string | undefined;
+      };
       includeSchema?: boolean;
       timestampFormat?: components["schemas"]["TimestampFormat"];
       unstructured?: boolean;
     };
+    /**
+     * @description Frames that each start with their length as an unsigned integer, as written by many binary
+     * protocols to batch several records into one message
+     */
+    LengthPrefixedFraming: {
+      /**
+       * Format: int32 
+       * @description The size of the length prefix in bytes; one of 1, 2, 4 or 8
+       */
+      lengthBytes: number;
+      littleEndian: boolean;
+    };
+    /** @description A map, whose keys are strings or integers */
+    MapType: {
+      key: components["schemas"]["SourceField"];
+      value: components["schemas"]["SourceField"];
+    };
     Metric: {
       /** Format: int64 */
       time: number;
@@ -348,7 +628,7 @@ export interface components {
       subtasks: (components["schemas"]["SubtaskMetrics"])[];
     };
     /** @enum {string} */
-    MetricNames: "bytes_recv" | "bytes_sent" | "messages_recv" | "messages_sent" | "backpressure";
+    MetricNames: "bytes_recv" | "bytes_sent" | "messages_recv" | "messages_sent" | "backpressure" | "deserialization_errors";
     NewlineDelimitedFraming: {
       /** Format: int64 */
       maxLineLength?: number | null;
@@ -362,6 +642,15 @@ export interface components {
     OperatorCheckpointGroupCollection: {
       data: (components["schemas"]["OperatorCheckpointGroup"])[];
     };
+    OperatorCheckpointState: {
+      /** Format: int64 */
+      bytes: number;
+      operatorId: string;
+      tables: (components["schemas"]["TableState"])[];
+    };
+    OperatorCheckpointStateCollection: {
+      data: (components["schemas"]["OperatorCheckpointState"])[];
+    };
     OperatorMetricGroup: {
       metricGroups: (components["schemas"]["MetricGroup"])[];
       operatorId: string;
@@ -369,6 +658,11 @@ export interface components {
     OperatorMetricGroupCollection: {
       data: (components["schemas"]["OperatorMetricGroup"])[];
     };
+    OperatorState: {
+      key: unknown;
+      operatorId: string;
+      value?: unknown;
+    };
     OutputData: {
       key: string;
       operatorId: string;
@@ -386,6 +680,7 @@ export interface components {
       action?: components["schemas"]["StopType"] | null;
       actionInProgress: boolean;
       actionText: string;
+      autoscaling?: components["schemas"]["AutoscalingConfig"] | null;
       /** Format: int64 */
       checkpointIntervalMicros: number;
       /** Format: int64 */
@@ -395,8 +690,10 @@ export interface components {
       name: string;
       preview: boolean;
       query: string;
+      restartPolicy?: components["schemas"]["RestartPolicy"] | null;
       stop: components["schemas"]["StopType"];
       udfs: (components["schemas"]["Udf"])[];
+      workerPod?: components["schemas"]["WorkerPodConfig"] | null;
     };
     PipelineCollection: {
       data: (components["schemas"]["Pipeline"])[];
@@ -409,6 +706,12 @@ export interface components {
       srcId: string;
       valueType: string;
     };
+    PipelineExplanation: {
+      analyze: boolean;
+      edges: (components["schemas"]["PipelineEdge"])[];
+      logicalPlans: (string)[];
+      nodes: (components["schemas"]["ExplainNode"])[];
+    };
     PipelineGraph: {
       edges: (components["schemas"]["PipelineEdge"])[];
       nodes: (components["schemas"]["PipelineNode"])[];
@@ -420,30 +723,137 @@ export interface components {
       parallelism: number;
     };
     PipelinePatch: {
+      autoscaling?: components["schemas"]["AutoscalingConfig"] | null;
       /** Format: int64 */
       checkpointIntervalMicros?: number | null;
       /** Format: int64 */
       parallelism?: number | null;
+      restartPolicy?: components["schemas"]["RestartPolicy"] | null;
       stop?: components["schemas"]["StopType"] | null;
+      /** @description Takes effect the next time the pipeline's workers are started, like when it's restarted */
+      workerPod?: components["schemas"]["WorkerPodConfig"] | null;
     };
     PipelinePost: {
+      /**
+       * @description Where to store the pipeline's checkpoints (like s3://bucket/path, gs://bucket/path,
+       * abfs://container@account.dfs.core.windows.net/path or a local or NFS-mounted directory),
+       * overriding the cluster's checkpoint URL
+       */
+      checkpointUrl?: string | null;
       name: string;
       /** Format: int64 */
       parallelism: number;
       preview?: boolean | null;
       query: string;
+      /** @description The name of a savepoint to start the pipeline's job from */
+      savepoint?: string | null;
+      /** @description Where the pipeline's operators keep their state between checkpoints; defaults to memory */
+      stateBackend?: components["schemas"]["StateBackendType"] | null;
       udfs?: (components["schemas"]["Udf"])[] | null;
+      workerPod?: components["schemas"]["WorkerPodConfig"] | null;
     };
     PipelineRestart: {
       force?: boolean | null;
     };
+    PipelineUpdate: {
+      compareOutputs: boolean;
+      /** Format: int64 */
+      createdAt: number;
+      failureMessage?: string | null;
+      id: string;
+      jobId: string;
+      /** Format: int64 */
+      maxLagMillis: number;
+      newJobId: string;
+      /** @description The pipeline running the new version, which keeps running once the update has finished */
+      newPipelineId: string;
+      pipelineId: string;
+      savepoint: string;
+      state: components["schemas"]["PipelineUpdateState"];
+    };
+    PipelineUpdateCollection: {
+      data: (components["schemas"]["PipelineUpdate"])[];
+    };
+    /**
+     * @description A new version of a running pipeline, which is started from a savepoint of the current
+     * version and runs alongside it, with its sinks replaced by web sinks, until it has caught up
+     */
+    PipelineUpdatePost: {
+      /**
+       * @description Wait for the update to be promoted once the new version has caught up, so that its output
+       * can be compared with the current version's before it replaces it
+       */
+      compareOutputs?: boolean | null;
+      /**
+       * Format: int64 
+       * @description How far behind the current time the new version's sources may be reading for it to count
+       * as caught up (defaults to 10 seconds)
+       */
+      maxLagMillis?: number | null;
+      /** Format: int64 */
+      parallelism: number;
+      query: string;
+      udfs?: (components["schemas"]["Udf"])[] | null;
+    };
+    /** @enum {string} */
+    PipelineUpdateState: "savepointing" | "catching_up" | "awaiting_promotion" | "switching" | "finished" | "failed";
     /** @enum {string} */
     PrimitiveType: "int32" | "int64" | "u_int32" | "u_int64" | "f32" | "f64" | "bool" | "string" | "bytes" | "unix_millis" | "unix_micros" | "unix_nanos" | "date_time" | "json";
+    ProtobufFormat: {
+      confluentSchemaRegistry?: boolean;
+    };
+    QueryExplanationResult: {
+      errors?: (string)[] | null;
+      explanation?: components["schemas"]["PipelineExplanation"] | null;
+      /** @description The job of the preview pipeline, whose explanation includes the row counts */
+      jobId?: string | null;
+      /** @description For `EXPLAIN ANALYZE` queries, the preview pipeline started to count each operator's rows */
+      pipelineId?: string | null;
+    };
     QueryValidationResult: {
       errors?: (string)[] | null;
       graph?: components["schemas"]["PipelineGraph"] | null;
     };
+    RawBytesFormat: Record<string, never>;
     RawStringFormat: Record<string, never>;
+    /**
+     * @description Registers a connector that runs as a separate service implementing the remote connector
+     * protocol
+     */
+    RemoteConnectorPost: {
+      endpoint: string;
+    };
+    /**
+     * @description How the controller restarts a pipeline's job when it fails, and when it gives up and moves the
+     * job to Failed instead
+     */
+    RestartPolicy: OneOf<[{
+      fixedDelay: components["schemas"]["FixedDelayRestart"];
+    }, {
+      exponentialBackoff: components["schemas"]["ExponentialBackoffRestart"];
+    }, {
+      failureRate: components["schemas"]["FailureRateRestart"];
+    }]>;
+    Savepoint: {
+      /** Format: int64 */
+      createdAt: number;
+      /** Format: int32 */
+      epoch?: number | null;
+      /** Format: int64 */
+      finishTime?: number | null;
+      id: string;
+      jobId: string;
+      name: string;
+      state: components["schemas"]["SavepointState"];
+    };
+    SavepointCollection: {
+      data: (components["schemas"]["Savepoint"])[];
+    };
+    SavepointPost: {
+      name: string;
+    };
+    /** @enum {string} */
+    SavepointState: "in_progress" | "ready" | "failed";
     SchemaDefinition: OneOf<[{
       json_schema: string;
     }, {
@@ -456,12 +866,29 @@ export interface components {
     SourceField: {
       fieldName: string;
       fieldType: components["schemas"]["SourceFieldType"];
+      /**
+       * @description For JSON sources, a JSON pointer or JSONPath expression locating the field's value in the
+       * record, if it isn't a top-level property with the field's name
+       */
+      jsonPath?: string | null;
       nullable: boolean;
     };
     SourceFieldType: {
       sqlName?: string | null;
       type: components["schemas"]["FieldType"];
     };
+    /**
+     * @description Where a pipeline's operators keep their state between checkpoints. Both are checkpointed the
+     * same way, so a pipeline can switch between them when it's restarted.
+     * @enum {string}
+     */
+    StateBackendType: "memory" | "rocksdb";
+    StateEntry: {
+      key: unknown;
+      /** Format: int64 */
+      timestamp: number;
+      value: unknown;
+    };
     /** @enum {string} */
     StopType: "none" | "checkpoint" | "graceful" | "immediate" | "force";
     StructType: {
@@ -480,6 +907,22 @@ export interface components {
       index: number;
       metrics: (components["schemas"]["Metric"])[];
     };
+    TableState: {
+      /** Format: int64 */
+      bytes: number;
+      description: string;
+      /** Format: int64 */
+      files: number;
+      /** Format: int64 */
+      keys: number;
+      name: string;
+      /** Format: int64 */
+      records: number;
+      sample: (components["schemas"]["StateEntry"])[];
+      /** @description The number of keys that belong to each subtask, for keyed tables */
+      subtaskKeys: (number)[];
+      tableType: string;
+    };
     TestSourceMessage: {
       done: boolean;
       error: boolean;
@@ -487,12 +930,25 @@ export interface components {
     };
     /** @enum {string} */
     TimestampFormat: "rfc3339" | "unix_millis";
+    /** @description A Kubernetes toleration, allowing worker pods to be scheduled onto nodes with matching taints */
+    Toleration: {
+      effect?: string | null;
+      key?: string | null;
+      operator?: string | null;
+      /** Format: int64 */
+      tolerationSeconds?: number | null;
+      value?: string | null;
+    };
     Udf: {
+      /**
+       * @description The source of a Rust UDF, or a base64-encoded WebAssembly module whose exported functions
+       * are each a UDF
+       */
       definition: string;
       language: components["schemas"]["UdfLanguage"];
     };
     /** @enum {string} */
-    UdfLanguage: "rust";
+    UdfLanguage: "rust" | "wasm";
     UdfValidationResult: {
       errors?: (string)[] | null;
       udfsRs?: string | null;
@@ -504,6 +960,46 @@ export interface components {
     ValidateUdfsPost: {
       udfsRs: string;
     };
+    View: {
+      /** Format: int64 */
+      createdAt: number;
+      id: string;
+      name: string;
+      query: string;
+    };
+    ViewCollection: {
+      data: (components["schemas"]["View"])[];
+    };
+    ViewPost: {
+      name: string;
+      query: string;
+    };
+    /**
+     * @description How the pods running a pipeline's workers are configured, when running on Kubernetes. Unset
+     * fields take the cluster's worker configuration.
+     */
+    WorkerPodConfig: {
+      annotations?: {
+        [key: string]: string | undefined;
+      } | null;
+      nodeSelector?: {
+        [key: string]: string | undefined;
+      } | null;
+      resources?: components["schemas"]["WorkerResources"] | null;
+      /**
+       * Format: int32 
+       * @description The number of task slots each pod has, which its resources are for
+       */
+      slotsPerPod?: number | null;
+      tolerations?: (components["schemas"]["Toleration"])[] | null;
+    };
+    /** @description The compute resources of a worker pod, as Kubernetes quantities (like 500m or 2Gi) */
+    WorkerResources: {
+      cpuLimit?: string | null;
+      cpuRequest?: string | null;
+      memoryLimit?: string | null;
+      memoryRequest?: string | null;
+    };
   };
   responses: never;
   parameters: never;
@@ -610,6 +1106,31 @@ export interface operations {
       };
     };
   };
+  /**
+   * Infer a Connection Schema from messages sampled from the source 
+   * @description Infer a Connection Schema from messages sampled from the source
+   */
+  infer_schema: {
+    parameters: {
+      query?: {
+        /** @description Number of messages to sample */
+        sampleSize?: number | null;
+      };
+    };
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["ConnectionTablePost"];
+      };
+    };
+    responses: {
+      /** @description Inferred schema */
+      200: {
+        content: {
+          "application/json": components["schemas"]["InferredSchema"];
+        };
+      };
+    };
+  };
   /**
    * Test a Connection Schema 
    * @description Test a Connection Schema
@@ -670,6 +1191,28 @@ export interface operations {
       };
     };
   };
+  /**
+   * Register a remote connector 
+   * @description Register a remote connector
+   * 
+   * The connector is registered with this API server until it restarts; to register connectors
+   * on startup, list their endpoints in `REMOTE_CONNECTORS`.
+   */
+  register_connector: {
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["RemoteConnectorPost"];
+      };
+    };
+    responses: {
+      /** @description Registered remote connector */
+      200: {
+        content: {
+          "application/json": components["schemas"]["Connector"];
+        };
+      };
+    };
+  };
   /**
    * Get all jobs 
    * @description Get all jobs
@@ -735,6 +1278,28 @@ export interface operations {
       };
     };
   };
+  /**
+   * Explain a query, returning its logical plans and the dataflow graph it compiles to. 
+   * @description Explain a query, returning its logical plans and the dataflow graph it compiles to.
+   * 
+   * `EXPLAIN ANALYZE` queries are started as a preview pipeline, whose job's explanation
+   * includes the number of rows each operator has processed so far.
+   */
+  explain_query: {
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["ValidateQueryPost"];
+      };
+    };
+    responses: {
+      /** @description Explained query */
+      200: {
+        content: {
+          "application/json": components["schemas"]["QueryExplanationResult"];
+        };
+      };
+    };
+  };
   /**
    * Get a pipeline graph 
    * @description Get a pipeline graph
@@ -879,6 +1444,82 @@ export interface operations {
       };
     };
   };
+  /**
+   * List a pipeline's updates 
+   * @description List a pipeline's updates
+   */
+  get_pipeline_updates: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        id: string;
+      };
+    };
+    responses: {
+      /** @description Got pipeline's updates */
+      200: {
+        content: {
+          "application/json": components["schemas"]["PipelineUpdateCollection"];
+        };
+      };
+    };
+  };
+  /**
+   * Update a running pipeline to a new version 
+   * @description Update a running pipeline to a new version
+   * 
+   * The controller takes a savepoint of the pipeline's job and starts the new version from it as
+   * a new pipeline, with its sinks replaced by web sinks. Once the new version has caught up (and
+   * the update has been promoted, if `compareOutputs` is set), the current version is stopped with
+   * a final checkpoint and the new one is restarted with the real sinks from where it stopped. The
+   * pipeline pauses briefly while it's switched, but its output isn't duplicated.
+   */
+  create_pipeline_update: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        id: string;
+      };
+    };
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["PipelineUpdatePost"];
+      };
+    };
+    responses: {
+      /** @description Started pipeline update */
+      200: {
+        content: {
+          "application/json": components["schemas"]["PipelineUpdate"];
+        };
+      };
+    };
+  };
+  /**
+   * Promote an update that is waiting for its output to be compared 
+   * @description Promote an update that is waiting for its output to be compared
+   * 
+   * The current version is stopped with a final checkpoint, and the new version is restarted from
+   * it with the real sinks.
+   */
+  promote_pipeline_update: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        id: string;
+        /** @description Pipeline update id */
+        update_id: string;
+      };
+    };
+    responses: {
+      /** @description Promoted pipeline update */
+      200: {
+        content: {
+          "application/json": components["schemas"]["PipelineUpdate"];
+        };
+      };
+    };
+  };
   /**
    * List a job's checkpoints 
    * @description List a job's checkpoints
@@ -925,6 +1566,39 @@ export interface operations {
       };
     };
   };
+  /**
+   * Inspect the state stored in a checkpoint 
+   * @description Inspect the state stored in a checkpoint
+   * 
+   * Lists the tables of each operator's state with their sizes, record and key counts and how
+   * their keys are spread across the operator's subtasks, along with a sample of their entries.
+   * Entries are decoded as JSON where the types of the state are known, and shown as hex
+   * otherwise.
+   */
+  get_checkpoint_state: {
+    parameters: {
+      query?: {
+        /** @description The number of entries of each table to include (defaults to 10) */
+        sample?: number | null;
+      };
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+        /** @description Epoch */
+        epoch: number;
+      };
+    };
+    responses: {
+      /** @description Got checkpoint's state */
+      200: {
+        content: {
+          "application/json": components["schemas"]["OperatorCheckpointStateCollection"];
+        };
+      };
+    };
+  };
   /**
    * List a job's error messages 
    * @description List a job's error messages
@@ -953,6 +1627,28 @@ export interface operations {
       };
     };
   };
+  /**
+   * Explain a job, including the number of rows each operator has processed 
+   * @description Explain a job, including the number of rows each operator has processed
+   */
+  get_job_explanation: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+      };
+    };
+    responses: {
+      /** @description Explained job */
+      200: {
+        content: {
+          "application/json": components["schemas"]["PipelineExplanation"];
+        };
+      };
+    };
+  };
   /**
    * Get a job's metrics 
    * @description Get a job's metrics
@@ -993,4 +1689,150 @@ export interface operations {
       200: never;
     };
   };
+  /**
+   * List a job's savepoints 
+   * @description List a job's savepoints
+   */
+  get_job_savepoints: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+      };
+    };
+    responses: {
+      /** @description Got job's savepoints */
+      200: {
+        content: {
+          "application/json": components["schemas"]["SavepointCollection"];
+        };
+      };
+    };
+  };
+  /**
+   * Take a savepoint of a running job 
+   * @description Take a savepoint of a running job
+   * 
+   * A savepoint is a snapshot of the job's state with a name, which is kept after the job's own
+   * checkpoints are cleaned up or the job is deleted, and which new pipelines can be started from.
+   */
+  create_savepoint: {
+    parameters: {
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+      };
+    };
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["SavepointPost"];
+      };
+    };
+    responses: {
+      /** @description Started savepoint */
+      200: {
+        content: {
+          "application/json": components["schemas"]["Savepoint"];
+        };
+      };
+    };
+  };
+  /**
+   * Query an operator's current state for a key 
+   * @description Query an operator's current state for a key
+   * 
+   * The key is given as JSON in the form of the operator's key type; the request is routed to the
+   * subtask that owns the key. Currently only updating aggregates can be queried.
+   */
+  get_operator_state: {
+    parameters: {
+      query: {
+        /** @description The key to look up, as JSON */
+        key: string;
+      };
+      path: {
+        /** @description Pipeline id */
+        pipeline_id: string;
+        /** @description Job id */
+        job_id: string;
+        /** @description Operator id */
+        operator_id: string;
+      };
+    };
+    responses: {
+      /** @description Got operator state */
+      200: {
+        content: {
+          "application/json": components["schemas"]["OperatorState"];
+        };
+      };
+    };
+  };
+  /**
+   * List all savepoints, including those of jobs that have been deleted 
+   * @description List all savepoints, including those of jobs that have been deleted
+   */
+  get_savepoints: {
+    responses: {
+      /** @description Got savepoints */
+      200: {
+        content: {
+          "application/json": components["schemas"]["SavepointCollection"];
+        };
+      };
+    };
+  };
+  /**
+   * List all views 
+   * @description List all views
+   */
+  get_views: {
+    responses: {
+      /** @description Got views */
+      200: {
+        content: {
+          "application/json": components["schemas"]["ViewCollection"];
+        };
+      };
+    };
+  };
+  /**
+   * Create a view, which pipelines can read from like a table 
+   * @description Create a view, which pipelines can read from like a table
+   */
+  create_view: {
+    requestBody: {
+      content: {
+        "application/json": components["schemas"]["ViewPost"];
+      };
+    };
+    responses: {
+      /** @description Created view */
+      200: {
+        content: {
+          "application/json": components["schemas"]["View"];
+        };
+      };
+    };
+  };
+  /**
+   * Delete a view 
+   * @description Delete a view
+   */
+  delete_view: {
+    parameters: {
+      path: {
+        /** @description View id */
+        id: string;
+      };
+    };
+    responses: {
+      /** @description Deleted view */
+      200: never;
+    };
+  };
 }
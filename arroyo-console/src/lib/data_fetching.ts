@@ -28,6 +28,8 @@ export type SourceField = schemas['SourceField'];
 export type OperatorCheckpointGroup = schemas['OperatorCheckpointGroup'];
 export type SubtaskCheckpointGroup = schemas['SubtaskCheckpointGroup'];
 export type CheckpointSpanType = schemas['CheckpointSpanType'];
+export type PipelineExplanation = schemas['PipelineExplanation'];
+export type ExplainNode = schemas['ExplainNode'];
 
 const BASE_URL = '/api';
 export const { get, post, patch, del } = createClient<paths>({ baseUrl: BASE_URL });
@@ -91,6 +93,10 @@ const jobCheckpointsKey = (pipelineId?: string, jobId?: string) => {
   return pipelineId && jobId ? { key: 'JobCheckpoints', pipelineId, jobId } : null;
 };
 
+const jobExplanationKey = (pipelineId?: string, jobId?: string) => {
+  return pipelineId && jobId ? { key: 'JobExplanation', pipelineId, jobId } : null;
+};
+
 const checkpointDetailsKey = (pipelineId?: string, jobId?: string, epoch?: number) => {
   return pipelineId && jobId && epoch
     ? { key: 'CheckpointDetails', pipelineId, jobId, epoch }
@@ -274,6 +280,38 @@ export const useJobMetrics = (pipelineId?: string, jobId?: string) => {
   };
 };
 
+// Job Explanation
+
+const jobExplanationFetcher = () => {
+  return async (params: { key: string; pipelineId: string; jobId: string }) => {
+    const { data, error } = await get('/v1/pipelines/{pipeline_id}/jobs/{job_id}/explain', {
+      params: {
+        path: {
+          pipeline_id: params.pipelineId,
+          job_id: params.jobId,
+        },
+      },
+    });
+
+    return processResponse(data, error);
+  };
+};
+
+export const useJobExplanation = (pipelineId?: string, jobId?: string) => {
+  const { data, error } = useSWR<schemas['PipelineExplanation']>(
+    jobExplanationKey(pipelineId, jobId),
+    jobExplanationFetcher(),
+    {
+      refreshInterval: 2000,
+    }
+  );
+
+  return {
+    jobExplanation: data,
+    jobExplanationError: error,
+  };
+};
+
 // JobCheckpointsReq
 
 const jobCheckpointsFetcher = () => {
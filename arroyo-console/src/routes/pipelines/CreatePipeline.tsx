@@ -37,8 +37,10 @@ import {
   ConnectionTable,
   JobLogMessage,
   OutputData,
+  PipelineExplanation,
   post,
   useConnectionTables,
+  useJobExplanation,
   useJobMetrics,
   useJobOutput,
   useOperatorErrors,
@@ -49,6 +51,7 @@ import {
 } from '../../lib/data_fetching';
 import Loading from '../../components/Loading';
 import OperatorErrors from '../../components/OperatorErrors';
+import QueryExplanation from '../../components/QueryExplanation';
 import StartPipelineModal from '../../components/StartPipelineModal';
 import { formatError } from '../../lib/util';
 import { WarningIcon } from '@chakra-ui/icons';
@@ -89,6 +92,12 @@ export function CreatePipeline() {
   const { udfsValidation, udfsValidationError, udfsValidationLoading } =
     useUdfsValidation(udfsInputToCheck);
   const { operatorMetricGroups } = useJobMetrics(pipelineId, job?.id);
+  const [explanation, setExplanation] = useState<PipelineExplanation | undefined>(undefined);
+  const [explainError, setExplainError] = useState<string | undefined>(undefined);
+  const { jobExplanation } = useJobExplanation(
+    explanation?.analyze ? pipelineId : undefined,
+    job?.id
+  );
 
   const { isOpen, onOpen, onClose } = useDisclosure();
   const [options, setOptions] = useState<SqlOptions>({ parallelism: 4, checkpointMS: 5000 });
@@ -224,6 +233,7 @@ export function CreatePipeline() {
     setTourStep(undefined);
     setQueryInputToCheck('');
     setPipelineId(undefined);
+    setExplanation(undefined);
 
     if (!(await pipelineIsValid())) {
       return;
@@ -248,6 +258,29 @@ export function CreatePipeline() {
     setTabIndex(1);
   };
 
+  const explain = async () => {
+    setQueryInputToCheck('');
+    setPipelineId(undefined);
+    setExplanation(undefined);
+    setExplainError(undefined);
+    setOutputs([]);
+
+    const { data, error } = await post('/v1/pipelines/explain', {
+      body: { query: queryInput, udfs: [{ language: 'rust', definition: udfsInput }] },
+    });
+
+    if (error) {
+      setExplainError(formatError(error));
+    } else if (data?.errors?.length) {
+      setExplainError(data.errors[0]);
+    } else if (data?.explanation) {
+      setExplanation(data.explanation);
+      // EXPLAIN ANALYZE queries are run as a preview, whose job's explanation has the row counts
+      setPipelineId(data.pipelineId ?? undefined);
+    }
+    setTabIndex(3);
+  };
+
   const stopPreview = async () => {
     await updatePipeline({ stop: 'immediate' });
 
@@ -413,6 +446,18 @@ export function CreatePipeline() {
     </Button>
   );
 
+  const explainButton = (
+    <Button
+      size="md"
+      colorScheme="blue"
+      onClick={explain}
+      title="Explain how the SQL is planned"
+      borderRadius={2}
+    >
+      Explain
+    </Button>
+  );
+
   const startPipelineButton = (
     <Button size="md" colorScheme="green" onClick={run} borderRadius={2}>
       Start Pipeline
@@ -422,6 +467,7 @@ export function CreatePipeline() {
   const buttonGroup = (
     <HStack spacing={4} p={4}>
       {checkButton}
+      {explainButton}
       <Popover
         isOpen={tourStep == TourSteps.Preview}
         placement={'top'}
@@ -578,11 +624,56 @@ export function CreatePipeline() {
     );
   }
 
+  let explainTabContent = (
+    <Text>
+      Explain your SQL to see how it's planned. Prefix it with EXPLAIN ANALYZE to also preview it
+      and count the rows each operator processes.
+    </Text>
+  );
+
+  if (explainError) {
+    explainTabContent = (
+      <Box overflow={'auto'}>
+        <Alert status="error">
+          <AlertIcon />
+          <AlertDescription>
+            <Text>Explain error</Text>
+          </AlertDescription>
+        </Alert>
+        <SyntaxHighlighter language="text" style={vs2015} customStyle={{ borderRadius: '5px' }}>
+          {explainError}
+        </SyntaxHighlighter>
+      </Box>
+    );
+  } else if (explanation) {
+    explainTabContent = (
+      <Box
+        style={{
+          top: 0,
+          bottom: 0,
+          left: 0,
+          right: 0,
+          position: 'absolute',
+        }}
+        overflow="auto"
+      >
+        <QueryExplanation explanation={jobExplanation ?? explanation} />
+      </Box>
+    );
+  }
+
+  const explainTab = (
+    <TabPanel overflowX="auto" height="100%" position="relative">
+      {explainTabContent}
+    </TabPanel>
+  );
+
   const previewTabsContent = (
     <TabPanels display={'flex'} flexDirection={'column'} flex={1} minHeight={0}>
       {previewPipelineTab}
       {previewResultsTab}
       {errorsTab}
+      {explainTab}
     </TabPanels>
   );
 
@@ -717,6 +808,7 @@ export function CreatePipeline() {
               <Text>Errors</Text>
               {errorsTabIcon}
             </Tab>
+            <Tab>Explain</Tab>
           </Flex>
           {buttonGroup}
         </Flex>
@@ -1,4 +1,4 @@
---! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?)
+--! all_jobs : Job(ttl_micros?, state?, start_time?, finish_time?, tasks?, failure_message?, run_id?, pipeline_path?, wasm_path?, restore_from_savepoint?, pending_savepoint?, checkpoint_url?, state_backend?, autoscaling?, worker_pod?, restart_policy?, restore_savepoint_state?)
 SELECT
     job_configs.id as id,
     job_configs.organization_id as org_id,
@@ -19,7 +19,21 @@ SELECT
     wasm_path,
     job_configs.restart_nonce as config_restart_nonce,
     job_statuses.restart_nonce as status_restart_nonce,
-    restart_mode
+    restart_mode,
+    restore_from_savepoint,
+    checkpoint_url,
+    state_backend,
+    autoscaling,
+    worker_pod,
+    restart_policy,
+    shadow,
+    (SELECT state FROM savepoints
+     WHERE savepoints.organization_id = job_configs.organization_id
+        AND savepoints.name = job_configs.restore_from_savepoint) as restore_savepoint_state,
+    (SELECT name FROM savepoints
+     WHERE savepoints.job_id = job_configs.id AND savepoints.state = 'inprogress'
+     ORDER BY savepoints.created_at
+     LIMIT 1) as pending_savepoint
 FROM job_configs
 LEFT JOIN job_statuses ON job_configs.id = job_statuses.id;
 
@@ -89,3 +103,77 @@ LIMIT 1;
 INSERT INTO job_log_messages (pub_id, job_id, operator_id, task_index, log_level, message, details)
 VALUES (:pub_id, :job_id, :operator_id, :task_index, :log_level, :message, :details)
 RETURNING id;
+
+--! pending_savepoints
+SELECT name
+FROM savepoints
+WHERE job_id = :job_id AND state = 'inprogress'
+ORDER BY created_at;
+
+--! finish_savepoint (epoch?)
+UPDATE savepoints
+SET
+    state = :state,
+    epoch = :epoch,
+    finish_time = :finish_time
+WHERE job_id = :job_id AND name = :name;
+
+--! set_parallelism_overrides
+UPDATE job_configs
+SET parallelism_overrides = :parallelism_overrides
+WHERE id = :job_id;
+
+--! active_pipeline_updates : (promoted_run_id?, old_state?, new_state?, new_run_id?, savepoint_state?, final_savepoint_state?)
+SELECT
+    pipeline_updates.id,
+    pipeline_updates.organization_id,
+    pipeline_updates.created_by,
+    pipeline_updates.state,
+    pipeline_updates.savepoint,
+    old_job_id,
+    new_job_id,
+    max_lag_micros,
+    compare_outputs,
+    promoted_run_id,
+    old_statuses.state as old_state,
+    old_jobs.stop as old_stop,
+    new_statuses.state as new_state,
+    new_statuses.run_id as new_run_id,
+    new_jobs.shadow as new_shadow,
+    savepoints.state as savepoint_state,
+    final_savepoints.state as final_savepoint_state
+FROM pipeline_updates
+    INNER JOIN job_configs old_jobs ON old_jobs.id = pipeline_updates.old_job_id
+    LEFT JOIN job_statuses old_statuses ON old_statuses.id = pipeline_updates.old_job_id
+    INNER JOIN job_configs new_jobs ON new_jobs.id = pipeline_updates.new_job_id
+    LEFT JOIN job_statuses new_statuses ON new_statuses.id = pipeline_updates.new_job_id
+    LEFT JOIN savepoints ON savepoints.organization_id = pipeline_updates.organization_id
+        AND savepoints.name = pipeline_updates.savepoint
+    -- the savepoint of the old job's final checkpoint, which the new job is switched to
+    LEFT JOIN savepoints final_savepoints
+        ON final_savepoints.organization_id = pipeline_updates.organization_id
+        AND final_savepoints.name = pipeline_updates.savepoint || '-final'
+WHERE pipeline_updates.state NOT IN ('finished', 'failed');
+
+--! update_pipeline_update (promoted_run_id?, failure_message?)
+UPDATE pipeline_updates
+SET
+    state = :state,
+    promoted_run_id = :promoted_run_id,
+    failure_message = :failure_message,
+    updated_at = :updated_at
+WHERE id = :id;
+
+--! create_savepoint
+INSERT INTO savepoints (pub_id, organization_id, created_by, name, job_id)
+VALUES (:pub_id, :organization_id, :created_by, :name, :job_id);
+
+--! promote_job
+UPDATE job_configs
+SET shadow = false, restore_from_savepoint = :savepoint
+WHERE id = :job_id;
+
+--! set_job_stop
+UPDATE job_configs
+SET stop = :stop
+WHERE id = :job_id;
@@ -0,0 +1,247 @@
+//! Rescales running jobs that have autoscaling enabled according to their load.
+//!
+//! The load of a job is read from the metrics its workers report to Prometheus: the fraction of
+//! time its busiest subtask spends processing records, how full the queues of its most
+//! backpressured subtask are, and how far behind the current time its sources are reading. When
+//! the job is overloaded or mostly idle, a new parallelism (within the pipeline's bounds) is saved
+//! as the job's parallelism overrides, which the job's state machine then rescales the job to
+//! from a checkpoint, like it does for a user-requested change.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use arroyo_datastream::Program;
+use arroyo_rpc::api_types::pipelines::AutoscalingConfig;
+use arroyo_types::{BUSY_TIME, SOURCE_LAG, TX_QUEUE_REM, TX_QUEUE_SIZE};
+use base64::engine::general_purpose;
+use base64::Engine;
+use deadpool_postgres::Pool;
+use lazy_static::lazy_static;
+use prometheus_http_query::Client;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use tracing::info;
+
+use crate::queries::controller_queries;
+
+// how often the load of a running job is checked
+pub const AUTOSCALING_INTERVAL: Duration = Duration::from_secs(30);
+
+// how long a job has to have been running at its current parallelism before it's rescaled
+// again, so that its metrics reflect that parallelism and it has caught up after restoring
+pub const AUTOSCALING_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+// the window over which the rates of the metrics are computed
+const METRICS_WINDOW: &str = "2m";
+
+// the busy fraction that the busiest subtask is scaled up towards
+const TARGET_BUSY: f64 = 0.6;
+const SCALE_UP_BUSY: f64 = 0.85;
+const SCALE_DOWN_BUSY: f64 = 0.3;
+
+const SCALE_UP_BACKPRESSURE: f64 = 0.5;
+const SCALE_DOWN_BACKPRESSURE: f64 = 0.1;
+
+const SCALE_UP_SOURCE_LAG: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref METRICS_CLIENT: Client = {
+        let mut headers = HeaderMap::new();
+        if let Ok(basic_auth) = std::env::var("PROM_AUTH") {
+            headers.append(
+                AUTHORIZATION,
+                HeaderValue::from_str(
+                    &("Basic ".to_owned() + &general_purpose::STANDARD.encode(basic_auth)),
+                )
+                .unwrap(),
+            );
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let prometheus_endpoint =
+            std::env::var("PROM_ENDPOINT").unwrap_or_else(|_| "http://localhost:9090".to_string());
+        Client::from(client, &prometheus_endpoint).unwrap()
+    };
+}
+
+/// The load of a job, as the maximum of each metric over its subtasks
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JobLoad {
+    // the fraction of time spent processing records
+    pub busy: f64,
+    // the fraction of the output queues that is full
+    pub backpressure: f64,
+    pub source_lag: Duration,
+}
+
+async fn query_max(query: String) -> Result<Option<f64>> {
+    let result = METRICS_CLIENT.query(query).get().await?;
+    Ok(result
+        .data()
+        .as_vector()
+        .ok_or_else(|| anyhow!("expected an instant vector from Prometheus"))?
+        .iter()
+        .map(|v| v.sample().value())
+        .filter(|v| v.is_finite())
+        .reduce(f64::max))
+}
+
+pub async fn job_load(job_id: &str, run_id: i64) -> Result<JobLoad> {
+    let selector =
+        |metric: &str| format!("{}{{job_id=\"{}\",run_id=\"{}\"}}", metric, job_id, run_id);
+
+    let (busy, backpressure, source_lag) = tokio::try_join!(
+        query_max(format!(
+            "max(rate({}[{}])) / 1000000",
+            selector(BUSY_TIME),
+            METRICS_WINDOW
+        )),
+        // add 1 to each value to account for uninitialized values, as for the API's metrics
+        query_max(format!(
+            "max(1 - (({} + 1) / ({} + 1)))",
+            selector(TX_QUEUE_REM),
+            selector(TX_QUEUE_SIZE)
+        )),
+        query_max(format!(
+            "max(min_over_time({}[{}]))",
+            selector(SOURCE_LAG),
+            METRICS_WINDOW
+        )),
+    )?;
+
+    Ok(JobLoad {
+        busy: busy.unwrap_or_default(),
+        backpressure: backpressure.unwrap_or_default(),
+        source_lag: Duration::from_millis(source_lag.unwrap_or_default().max(0.0) as u64),
+    })
+}
+
+/// The current source lag of a job, or None if it hasn't reported any yet
+pub async fn source_lag(job_id: &str, run_id: i64) -> Result<Option<Duration>> {
+    let lag = query_max(format!(
+        "max(min_over_time({}{{job_id=\"{}\",run_id=\"{}\"}}[{}]))",
+        SOURCE_LAG, job_id, run_id, METRICS_WINDOW
+    ))
+    .await?;
+
+    Ok(lag.map(|l| Duration::from_millis(l.max(0.0) as u64)))
+}
+
+/// The parallelism a job running with `current` parallelism should be rescaled to for its
+/// `load`, within the bounds of `config`
+pub fn desired_parallelism(current: usize, load: &JobLoad, config: &AutoscalingConfig) -> usize {
+    let min = config.min_parallelism.max(1) as usize;
+    let max = (config.max_parallelism as usize).max(min);
+
+    let desired = if load.busy > SCALE_UP_BUSY
+        || load.backpressure > SCALE_UP_BACKPRESSURE
+        || load.source_lag > SCALE_UP_SOURCE_LAG
+    {
+        // the parallelism at which the busiest subtask would be at the target
+        let for_busy = (current as f64 * load.busy / TARGET_BUSY).ceil() as usize;
+        for_busy.max(current + 1)
+    } else if load.busy < SCALE_DOWN_BUSY
+        && load.backpressure < SCALE_DOWN_BACKPRESSURE
+        && load.source_lag < SCALE_UP_SOURCE_LAG / 2
+    {
+        // halving the parallelism leaves the busiest subtask below the target, even if the
+        // load isn't spread evenly over the subtasks
+        (current + 1) / 2
+    } else {
+        current
+    };
+
+    desired.clamp(min, max)
+}
+
+/// Checks the load of the job, saving a new parallelism for it if it should be rescaled. Returns
+/// the new parallelism, if there is one.
+pub async fn autoscale(
+    pool: &Pool,
+    job_id: &str,
+    run_id: i64,
+    program: &Program,
+    config: &AutoscalingConfig,
+) -> Result<Option<usize>> {
+    let current = program
+        .graph
+        .node_weights()
+        .map(|node| node.parallelism)
+        .max()
+        .unwrap_or(1);
+
+    let load = job_load(job_id, run_id).await?;
+    let desired = desired_parallelism(current, &load, config);
+    if desired == current {
+        return Ok(None);
+    }
+
+    info!(
+        message = "Autoscaling job",
+        job_id,
+        current,
+        desired,
+        busy = load.busy,
+        backpressure = load.backpressure,
+        source_lag_ms = load.source_lag.as_millis() as u64,
+    );
+
+    let overrides: HashMap<String, usize> = program
+        .graph
+        .node_weights()
+        .map(|node| (node.operator_id.clone(), desired))
+        .collect();
+
+    let c = pool.get().await?;
+    controller_queries::set_parallelism_overrides()
+        .bind(&c, &serde_json::to_value(overrides)?, &job_id)
+        .await?;
+
+    Ok(Some(desired))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desired_parallelism() {
+        let config = AutoscalingConfig {
+            enabled: true,
+            min_parallelism: 1,
+            max_parallelism: 8,
+        };
+        let load = |busy, backpressure, source_lag| JobLoad {
+            busy,
+            backpressure,
+            source_lag: Duration::from_secs(source_lag),
+        };
+
+        // within the target range
+        assert_eq!(4, desired_parallelism(4, &load(0.6, 0.2, 10), &config));
+
+        // overloaded subtasks are scaled to the target
+        assert_eq!(6, desired_parallelism(4, &load(0.87, 0.0, 0), &config));
+
+        // backpressure and source lag scale up by at least one
+        assert_eq!(5, desired_parallelism(4, &load(0.5, 0.8, 0), &config));
+        assert_eq!(5, desired_parallelism(4, &load(0.5, 0.0, 120), &config));
+
+        // bounded by the maximum
+        assert_eq!(8, desired_parallelism(6, &load(1.0, 1.0, 0), &config));
+
+        // idle jobs are halved, down to the minimum
+        assert_eq!(2, desired_parallelism(4, &load(0.0, 0.0, 0), &config));
+        assert_eq!(3, desired_parallelism(5, &load(0.25, 0.0, 0), &config));
+        assert_eq!(1, desired_parallelism(1, &load(0.0, 0.0, 0), &config));
+
+        let config = AutoscalingConfig {
+            min_parallelism: 3,
+            ..config
+        };
+        assert_eq!(3, desired_parallelism(4, &load(0.0, 0.0, 0), &config));
+    }
+}
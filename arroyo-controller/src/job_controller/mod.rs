@@ -9,7 +9,7 @@ use anyhow::bail;
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::{
     worker_grpc_client::WorkerGrpcClient, CheckpointReq, JobFinishedReq, LoadCompactedDataReq,
-    StopExecutionReq, StopMode, TaskCheckpointEventType,
+    QueryStateReq, QueryStateResp, StopExecutionReq, StopMode, TaskCheckpointEventType,
 };
 use arroyo_state::{BackingStore, StateBackend};
 use arroyo_types::{to_micros, WorkerId};
@@ -25,6 +25,7 @@ use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
 use crate::types::public::CheckpointState as DbCheckpointState;
+use crate::types::public::SavepointState;
 use crate::{queries::controller_queries, JobConfig, JobMessage, RunningMessage};
 use arroyo_state::committing_state::CommittingState;
 
@@ -36,6 +37,40 @@ const CHECKPOINTS_TO_KEEP: u32 = 4;
 const COMPACT_EVERY: u32 = 2;
 const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
 
+fn unaligned_checkpoints_enabled() -> bool {
+    match env::var("UNALIGNED_CHECKPOINTS") {
+        Ok(val) => val.to_lowercase() == "true",
+        Err(_) => false,
+    }
+}
+
+// combines the workers' answers to a state query: only the worker running the subtask that owns
+// the key can have a value, and workers that run none of the operator's subtasks don't know it
+fn merge_state_query_results(
+    operator_id: &str,
+    results: Vec<Result<QueryStateResp, tonic::Status>>,
+) -> anyhow::Result<Option<String>> {
+    let mut found = false;
+    let mut value = None;
+    for result in results {
+        match result {
+            Ok(resp) => {
+                found = true;
+                if let Some(v) = resp.value {
+                    value = Some(v);
+                }
+            }
+            Err(status) if status.code() == tonic::Code::NotFound => {}
+            Err(status) => bail!("{}", status.message()),
+        }
+    }
+
+    if !found {
+        bail!("No operator with id {}", operator_id);
+    }
+    Ok(value)
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkerState {
     Running,
@@ -77,6 +112,7 @@ pub enum JobState {
 
 pub struct RunningJobModel {
     job_id: String,
+    checkpoint_url: String,
     state: JobState,
     program: Program,
     checkpoint_state: Option<CheckpointingOrCommittingState>,
@@ -275,6 +311,36 @@ impl RunningJobModel {
                     );
                 }
             }
+            RunningMessage::QueryState {
+                operator_id,
+                key,
+                tx,
+            } => {
+                // the controller doesn't know which worker runs the subtask that owns the key, so
+                // we ask all of them without holding up the controller; each routes the key to
+                // the owning subtask if it runs it
+                let workers: Vec<_> = self.workers.values().map(|w| w.connect.clone()).collect();
+                let job_id = self.job_id.clone();
+                tokio::spawn(async move {
+                    let results = futures::future::join_all(workers.into_iter().map(|mut c| {
+                        let req = QueryStateReq {
+                            job_id: job_id.clone(),
+                            operator_id: operator_id.clone(),
+                            key: key.clone(),
+                        };
+                        async move { c.query_state(Request::new(req)).await }
+                    }))
+                    .await;
+
+                    let _ = tx.send(merge_state_query_results(
+                        &operator_id,
+                        results
+                            .into_iter()
+                            .map(|r| r.map(|resp| resp.into_inner()))
+                            .collect(),
+                    ));
+                });
+            }
         }
 
         if self.state == JobState::Running
@@ -322,6 +388,7 @@ impl RunningJobModel {
                     min_epoch: self.min_epoch,
                     then_stop,
                     is_commit: false,
+                    unaligned: !then_stop && unaligned_checkpoints_enabled(),
                 }))
                 .await?;
         }
@@ -345,6 +412,7 @@ impl RunningJobModel {
 
         let state = CheckpointState::start(
             self.job_id.clone(),
+            self.checkpoint_url.clone(),
             checkpoint_id,
             self.epoch,
             self.min_epoch,
@@ -375,6 +443,7 @@ impl RunningJobModel {
         for (operator_id, parallelism) in self.operator_parallelism.clone() {
             // compact the operator's state and notify the workers to load the new files
             if let Ok(Some(compaction_result)) = ParquetBackend::compact_operator(
+                &self.checkpoint_url,
                 parallelism,
                 self.job_id.clone(),
                 operator_id.clone(),
@@ -449,6 +518,7 @@ impl RunningJobModel {
                                     epoch: self.epoch,
                                     then_stop: false,
                                     is_commit: true,
+                                    unaligned: false,
                                 }))
                                 .await?;
                         }
@@ -520,11 +590,23 @@ impl RunningJobModel {
     }
 }
 
+// a requested savepoint, and the epoch of the checkpoint it's being taken from once that has
+// been started
+#[derive(Debug)]
+struct PendingSavepoint {
+    name: String,
+    epoch: Option<u32>,
+}
+
 pub struct JobController {
     pool: Pool,
     config: JobConfig,
     model: RunningJobModel,
     cleanup_task: Option<JoinHandle<anyhow::Result<u32>>>,
+    pending_savepoint: Option<PendingSavepoint>,
+    savepoint_task: Option<JoinHandle<anyhow::Result<()>>>,
+    // the last savepoint that was taken, so that it isn't taken again on a stale config update
+    last_savepoint: Option<String>,
 }
 
 impl std::fmt::Debug for JobController {
@@ -533,6 +615,7 @@ impl std::fmt::Debug for JobController {
             .field("config", &self.config)
             .field("model", &self.model)
             .field("cleaning", &self.cleanup_task.is_some())
+            .field("pending_savepoint", &self.pending_savepoint)
             .finish()
     }
 }
@@ -556,6 +639,7 @@ impl JobController {
             pool,
             model: RunningJobModel {
                 job_id: config.id.clone(),
+                checkpoint_url: config.checkpoint_url.clone(),
                 state: JobState::Running,
                 checkpoint_state: commit_state
                     .map(|state| CheckpointingOrCommittingState::Committing(state)),
@@ -597,8 +681,30 @@ impl JobController {
                     .collect(),
                 program,
             },
+            pending_savepoint: config
+                .pending_savepoint
+                .clone()
+                .map(|name| PendingSavepoint { name, epoch: None }),
             config,
             cleanup_task: None,
+            savepoint_task: None,
+            last_savepoint: None,
+        }
+    }
+
+    /// Requests a savepoint, which is taken from the next checkpoint once any savepoint already
+    /// in progress has finished
+    pub fn savepoint(&mut self, name: &str) {
+        if self.pending_savepoint.is_none() && self.last_savepoint.as_deref() != Some(name) {
+            info!(
+                message = "savepoint requested",
+                job_id = self.config.id,
+                name
+            );
+            self.pending_savepoint = Some(PendingSavepoint {
+                name: name.to_string(),
+                epoch: None,
+            });
         }
     }
 
@@ -653,8 +759,46 @@ impl JobController {
             }
         }
 
+        // check on savepoints
+        if self.savepoint_task.is_some() && self.savepoint_task.as_ref().unwrap().is_finished() {
+            let task = self.savepoint_task.take().unwrap();
+            let savepoint = self.pending_savepoint.take().unwrap();
+
+            match task.await {
+                Ok(Ok(())) => {
+                    info!(
+                        message = "finished savepoint",
+                        job_id = self.config.id,
+                        name = savepoint.name
+                    );
+                }
+                Ok(Err(e)) => {
+                    error!(
+                        message = "savepoint failed",
+                        job_id = self.config.id,
+                        name = savepoint.name,
+                        error = format!("{:?}", e)
+                    );
+                }
+                Err(e) => {
+                    error!(
+                        message = "savepoint panicked",
+                        job_id = self.config.id,
+                        name = savepoint.name,
+                        error = format!("{:?}", e)
+                    );
+                }
+            }
+
+            self.last_savepoint = Some(savepoint.name);
+        }
+
+        // savepoints are copied from checkpoints that cleanup could otherwise delete
         if let Some(new_epoch) = self.model.cleanup_needed() {
-            if self.cleanup_task.is_none() && self.model.checkpoint_state.is_none() {
+            if self.cleanup_task.is_none()
+                && self.model.checkpoint_state.is_none()
+                && self.pending_savepoint.is_none()
+            {
                 self.cleanup_task = Some(self.start_cleanup(new_epoch));
             }
         }
@@ -662,6 +806,18 @@ impl JobController {
         // check on checkpointing
         if self.model.checkpoint_state.is_some() {
             self.model.finish_checkpoint_if_done(&self.pool).await?;
+        } else if let Some(savepoint) = &self.pending_savepoint {
+            match (savepoint.name.clone(), savepoint.epoch) {
+                (_, None) if self.cleanup_task.is_none() => {
+                    self.checkpoint(false).await?;
+                    self.pending_savepoint.as_mut().unwrap().epoch = Some(self.model.epoch);
+                }
+                (name, Some(epoch)) if self.savepoint_task.is_none() => {
+                    // the checkpoint has finished, so its state can be copied
+                    self.savepoint_task = Some(self.start_savepoint(name, epoch));
+                }
+                _ => {}
+            }
         } else if self.model.last_checkpoint.elapsed() > self.config.checkpoint_interval
             && self.cleanup_task.is_none()
         {
@@ -695,6 +851,67 @@ impl JobController {
         }
     }
 
+    /// Takes any savepoints still requested of a job that has stopped with a final checkpoint
+    /// from that checkpoint, so that they hold exactly the state the job stopped with
+    pub async fn take_final_savepoints(&mut self) -> anyhow::Result<()> {
+        // a savepoint that was already being copied from an earlier checkpoint records its own
+        // result, so it only needs to finish
+        if let Some(task) = self.savepoint_task.take() {
+            if let Err(e) = task.await {
+                warn!(
+                    message = "savepoint panicked",
+                    job_id = self.config.id,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+        self.pending_savepoint = None;
+
+        let names = {
+            let c = self.pool.get().await?;
+            controller_queries::pending_savepoints()
+                .bind(&c, &self.config.id)
+                .all()
+                .await?
+        };
+
+        let mut result = Ok(());
+        for name in names {
+            let epoch = self.model.epoch;
+            // a failed savepoint is marked as such, so the others are still taken
+            match self.start_savepoint(name.clone(), epoch).await {
+                Ok(Ok(())) => {
+                    info!(
+                        message = "finished savepoint",
+                        job_id = self.config.id,
+                        name
+                    );
+                }
+                Ok(Err(e)) => {
+                    error!(
+                        message = "savepoint failed",
+                        job_id = self.config.id,
+                        name,
+                        error = format!("{:?}", e)
+                    );
+                    result = Err(e);
+                }
+                Err(e) => {
+                    error!(
+                        message = "savepoint panicked",
+                        job_id = self.config.id,
+                        name,
+                        error = format!("{:?}", e)
+                    );
+                    result = Err(e.into());
+                }
+            }
+            self.last_savepoint = Some(name);
+        }
+
+        result
+    }
+
     pub fn finished(&self) -> bool {
         self.model.all_tasks_finished()
     }
@@ -721,6 +938,7 @@ impl JobController {
                     epoch: self.model.epoch,
                     then_stop: false,
                     is_commit: true,
+                    unaligned: false,
                 }))
                 .await?;
         }
@@ -761,9 +979,52 @@ impl JobController {
         self.model.operator_parallelism.get(op).cloned()
     }
 
+    fn start_savepoint(&mut self, name: String, epoch: u32) -> JoinHandle<anyhow::Result<()>> {
+        let job_id = self.config.id.clone();
+        let checkpoint_url = self.config.checkpoint_url.clone();
+        let savepoint_url = self.config.savepoint_url.clone();
+        let pool = self.pool.clone();
+        let schemas = self.program.state_schemas.clone();
+
+        info!(message = "Starting savepoint", job_id, name, epoch);
+
+        tokio::spawn(async move {
+            let result = ParquetBackend::write_savepoint(
+                &checkpoint_url,
+                &savepoint_url,
+                &job_id,
+                epoch,
+                &name,
+                &schemas,
+            )
+            .await;
+
+            let state = if result.is_ok() {
+                SavepointState::ready
+            } else {
+                SavepointState::failed
+            };
+
+            let c = pool.get().await?;
+            controller_queries::finish_savepoint()
+                .bind(
+                    &c,
+                    &state,
+                    &Some(epoch as i32),
+                    &OffsetDateTime::now_utc(),
+                    &job_id,
+                    &name,
+                )
+                .await?;
+
+            result
+        })
+    }
+
     fn start_cleanup(&mut self, new_min: u32) -> JoinHandle<anyhow::Result<u32>> {
         let min_epoch = self.model.min_epoch.max(1);
         let job_id = self.config.id.clone();
+        let checkpoint_url = self.config.checkpoint_url.clone();
         let pool = self.pool.clone();
 
         info!(message = "Starting cleaning", job_id, min_epoch, new_min);
@@ -771,18 +1032,20 @@ impl JobController {
         let cur_epoch = self.model.epoch;
 
         tokio::spawn(async move {
-            let checkpoint = StateBackend::load_checkpoint_metadata(&job_id, cur_epoch)
-                .await
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Couldn't find checkpoint for job during cleaning")
-                })?;
+            let checkpoint =
+                StateBackend::load_checkpoint_metadata(&checkpoint_url, &job_id, cur_epoch)
+                    .await
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Couldn't find checkpoint for job during cleaning")
+                    })?;
 
             let c = pool.get().await?;
             controller_queries::mark_compacting()
                 .bind(&c, &job_id, &(min_epoch as i32), &(new_min as i32))
                 .await?;
 
-            StateBackend::cleanup_checkpoint(checkpoint, min_epoch, new_min).await?;
+            StateBackend::cleanup_checkpoint(&checkpoint_url, checkpoint, min_epoch, new_min)
+                .await?;
 
             controller_queries::mark_checkpoints_compacted()
                 .bind(&c, &job_id, &(new_min as i32))
@@ -800,3 +1063,54 @@ impl JobController {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(v: Option<&str>) -> Result<QueryStateResp, tonic::Status> {
+        Ok(QueryStateResp {
+            value: v.map(|v| v.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_merge_state_query_results() {
+        // the worker running the owning subtask answers, the others have nothing
+        assert_eq!(
+            Some("5".to_string()),
+            merge_state_query_results(
+                "op",
+                vec![
+                    value(None),
+                    value(Some("5")),
+                    Err(tonic::Status::not_found("No operator with id op"))
+                ]
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            None,
+            merge_state_query_results("op", vec![value(None), value(None)]).unwrap()
+        );
+
+        // no worker runs the operator
+        assert!(merge_state_query_results(
+            "op",
+            vec![Err(tonic::Status::not_found("No operator with id op"))]
+        )
+        .is_err());
+
+        // errors from the operator, like an invalid key, are returned
+        let err = merge_state_query_results(
+            "op",
+            vec![
+                value(None),
+                Err(tonic::Status::invalid_argument("invalid key")),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!("invalid key", err.to_string());
+    }
+}
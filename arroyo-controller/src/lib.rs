@@ -2,14 +2,18 @@
 // TODO: factor out complex types
 #![allow(clippy::type_complexity)]
 
+use arroyo_rpc::api_types::pipelines::{
+    AutoscalingConfig, RestartPolicy, StateBackendType, WorkerPodConfig,
+};
 use arroyo_rpc::grpc::compiler_grpc_client::CompilerGrpcClient;
 use arroyo_rpc::grpc::controller_grpc_server::{ControllerGrpc, ControllerGrpcServer};
 use arroyo_rpc::grpc::{
     CheckUdfsReq, CheckUdfsResp, GrpcOutputSubscription, HeartbeatNodeReq, HeartbeatNodeResp,
-    HeartbeatReq, HeartbeatResp, OutputData, RegisterNodeReq, RegisterNodeResp, RegisterWorkerReq,
-    RegisterWorkerResp, TaskCheckpointCompletedReq, TaskCheckpointCompletedResp, TaskFailedReq,
-    TaskFailedResp, TaskFinishedReq, TaskFinishedResp, TaskStartedReq, TaskStartedResp,
-    ValidationResult, WorkerFinishedReq, WorkerFinishedResp,
+    HeartbeatReq, HeartbeatResp, OutputData, QueryStateReq, QueryStateResp, RegisterNodeReq,
+    RegisterNodeResp, RegisterWorkerReq, RegisterWorkerResp, SourceWatermark,
+    TaskCheckpointCompletedReq, TaskCheckpointCompletedResp, TaskFailedReq, TaskFailedResp,
+    TaskFinishedReq, TaskFinishedResp, TaskStartedReq, TaskStartedResp, ValidationResult,
+    WorkerFinishedReq, WorkerFinishedResp,
 };
 use arroyo_rpc::grpc::{
     SinkDataReq, SinkDataResp, TaskCheckpointEventReq, TaskCheckpointEventResp, WorkerErrorReq,
@@ -17,6 +21,7 @@ use arroyo_rpc::grpc::{
 };
 use arroyo_rpc::public_ids::{generate_id, IdTypes};
 use arroyo_server_common::log_event;
+use arroyo_state::parquet::checkpoint_url_from_env;
 use arroyo_types::{
     from_micros, ports, DatabaseConfig, NodeId, WorkerId, REMOTE_COMPILER_ENDPOINT_ENV,
 };
@@ -32,28 +37,36 @@ use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
 use syn::{parse_file, Item};
 use time::OffsetDateTime;
-use tokio::sync::broadcast;
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::{broadcast, oneshot};
 use tokio_postgres::NoTls;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+mod autoscaler;
 pub mod compiler;
 pub mod job_controller;
+mod restarts;
 pub mod schedulers;
 mod states;
+mod updates;
 
 include!(concat!(env!("OUT_DIR"), "/controller-sql.rs"));
 
 use crate::schedulers::{nomad::NomadScheduler, NodeScheduler, ProcessScheduler, Scheduler};
+use crate::types::public::SavepointState;
 use types::public::LogLevel;
 use types::public::{RestartMode, StopMode};
 
 pub const CHECKPOINTS_TO_KEEP: u32 = 5;
 
+// how long the source watermarks reported by a worker hold back those of the other workers, so
+// that a worker that's gone away doesn't stop its job's sources from being aligned
+const SOURCE_WATERMARK_TTL: Duration = Duration::from_secs(30);
+
 lazy_static! {
     static ref ACTIVE_PIPELINES: Gauge = register_gauge!(
         "arroyo_controller_active_pipelines",
@@ -84,6 +97,22 @@ pub struct JobConfig {
     parallelism_overrides: HashMap<String, usize>,
     restart_nonce: i32,
     restart_mode: RestartMode,
+    // the savepoint to restore from if the job doesn't have any checkpoints yet
+    restore_from_savepoint: Option<String>,
+    // the earliest requested savepoint that hasn't been taken yet
+    pending_savepoint: Option<String>,
+    autoscaling: Option<AutoscalingConfig>,
+    worker_pod: Option<WorkerPodConfig>,
+    restart_policy: RestartPolicy,
+    // whether the job's sinks are replaced by web sinks, while it's the new version in an update
+    shadow: bool,
+    // where the job's checkpoints are stored; the cluster's checkpoint storage unless the
+    // pipeline sets its own
+    checkpoint_url: String,
+    // where the job's savepoints are stored, which is always the cluster's checkpoint storage so
+    // that they can be restored into any pipeline
+    savepoint_url: String,
+    state_backend: StateBackendType,
 }
 
 #[derive(Clone, Debug)]
@@ -153,6 +182,11 @@ pub enum RunningMessage {
     WorkerFinished {
         worker_id: WorkerId,
     },
+    QueryState {
+        operator_id: String,
+        key: String,
+        tx: oneshot::Sender<anyhow::Result<Option<String>>>,
+    },
 }
 
 #[derive(Debug)]
@@ -180,6 +214,9 @@ pub struct ControllerServer {
     data_txs: Arc<tokio::sync::Mutex<HashMap<String, Vec<Sender<Result<OutputData, Status>>>>>>,
     scheduler: Arc<dyn Scheduler>,
     db: Pool,
+    // the watermarks of the aligned sources of each job, by the worker that reported them
+    source_watermarks:
+        Arc<tokio::sync::Mutex<HashMap<String, HashMap<u64, (Instant, Vec<SourceWatermark>)>>>>,
 }
 
 #[tonic::async_trait]
@@ -227,7 +264,36 @@ impl ControllerGrpc for ControllerServer {
         )
         .await?;
 
-        return Ok(Response::new(HeartbeatResp {}));
+        let mut source_watermarks = self.source_watermarks.lock().await;
+        let workers = source_watermarks.entry(req.job_id.clone()).or_default();
+        if req.source_watermarks.is_empty() {
+            workers.remove(&req.worker_id);
+        } else {
+            workers.insert(req.worker_id, (Instant::now(), req.source_watermarks));
+        }
+        workers.retain(|_, (time, _)| time.elapsed() < SOURCE_WATERMARK_TTL);
+
+        let mut group_watermarks: HashMap<&str, u64> = HashMap::new();
+        for watermark in workers.values().flat_map(|(_, watermarks)| watermarks) {
+            group_watermarks
+                .entry(&watermark.group)
+                .and_modify(|w| *w = (*w).min(watermark.watermark))
+                .or_insert(watermark.watermark);
+        }
+
+        let group_watermarks = group_watermarks
+            .into_iter()
+            .map(|(group, watermark)| SourceWatermark {
+                group: group.to_string(),
+                watermark,
+            })
+            .collect();
+
+        if workers.is_empty() {
+            source_watermarks.remove(&req.job_id);
+        }
+
+        return Ok(Response::new(HeartbeatResp { group_watermarks }));
     }
 
     async fn task_started(
@@ -477,6 +543,33 @@ impl ControllerGrpc for ControllerServer {
 
         client.check_udfs(CheckUdfsReq { udfs_rs }).await
     }
+
+    async fn query_state(
+        &self,
+        request: Request<QueryStateReq>,
+    ) -> Result<Response<QueryStateResp>, Status> {
+        let req = request.into_inner();
+        let (tx, rx) = oneshot::channel();
+
+        self.send_to_job_queue(
+            &req.job_id,
+            JobMessage::RunningMessage(RunningMessage::QueryState {
+                operator_id: req.operator_id,
+                key: req.key,
+                tx,
+            }),
+        )
+        .await?;
+
+        match rx.await {
+            Ok(Ok(value)) => Ok(Response::new(QueryStateResp { value })),
+            Ok(Err(e)) => Err(Status::invalid_argument(e.to_string())),
+            Err(_) => Err(Status::failed_precondition(format!(
+                "Job {} is not running",
+                req.job_id
+            ))),
+        }
+    }
 }
 
 impl ControllerServer {
@@ -499,6 +592,15 @@ impl ControllerServer {
                 #[cfg(not(feature = "k8s"))]
                 panic!("Kubernetes not enabled -- compile with `--features k8s` to enable")
             }
+            Some("ecs") => {
+                #[cfg(feature = "ecs")]
+                {
+                    info!("Using ecs scheduler");
+                    Arc::new(crate::schedulers::ecs::EcsScheduler::from_env().await)
+                }
+                #[cfg(not(feature = "ecs"))]
+                panic!("ECS not enabled -- compile with `--features ecs` to enable")
+            }
             _ => {
                 info!("Using process scheduler");
                 Arc::new(ProcessScheduler::new())
@@ -560,6 +662,7 @@ impl ControllerServer {
             data_txs: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             job_state: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             db: pool,
+            source_watermarks: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
         }
     }
 
@@ -598,6 +701,11 @@ impl ControllerServer {
                     .await
                     .unwrap();
                 for p in res {
+                    // the new job in an update waits for the savepoint of the job it's replacing
+                    if p.restore_savepoint_state == Some(SavepointState::inprogress) {
+                        continue;
+                    }
+
                     let config = JobConfig {
                         id: p.id.clone(),
                         organization_id: p.org_id,
@@ -617,6 +725,21 @@ impl ControllerServer {
                             .collect(),
                         restart_nonce: p.config_restart_nonce,
                         restart_mode: p.restart_mode,
+                        restore_from_savepoint: p.restore_from_savepoint,
+                        pending_savepoint: p.pending_savepoint,
+                        autoscaling: p.autoscaling.and_then(|a| serde_json::from_value(a).ok()),
+                        worker_pod: p.worker_pod.and_then(|w| serde_json::from_value(w).ok()),
+                        restart_policy: p
+                            .restart_policy
+                            .and_then(|r| serde_json::from_value(r).ok())
+                            .unwrap_or_default(),
+                        shadow: p.shadow,
+                        checkpoint_url: p.checkpoint_url.unwrap_or_else(checkpoint_url_from_env),
+                        savepoint_url: checkpoint_url_from_env(),
+                        state_backend: p
+                            .state_backend
+                            .and_then(|b| b.try_into().ok())
+                            .unwrap_or_default(),
                     };
 
                     let mut jobs = jobs.lock().await;
@@ -666,6 +789,7 @@ impl ControllerServer {
         );
 
         self.start_updater();
+        tokio::spawn(updates::run_updates(self.db.clone()));
 
         arroyo_server_common::grpc_server()
             .accept_http1(true)
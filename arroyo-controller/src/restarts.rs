@@ -0,0 +1,153 @@
+//! Decides, according to a pipeline's restart policy, whether a failed job is restarted (and
+//! after how long) or moved to Failed.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use arroyo_rpc::api_types::pipelines::RestartPolicy;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestartDecision {
+    Restart { delay: Duration },
+    Fail { reason: String },
+}
+
+/// Records a failure of the job at `now`, given the number of times it has already been
+/// restarted without becoming healthy (`restarts`) and the times of its recent failures, which
+/// are updated for failure-rate policies.
+pub fn on_failure(
+    policy: &RestartPolicy,
+    restarts: u32,
+    failures: &mut VecDeque<Instant>,
+    now: Instant,
+) -> RestartDecision {
+    match policy {
+        RestartPolicy::FixedDelay(fixed) => {
+            if restarts >= fixed.max_restarts {
+                return RestartDecision::Fail {
+                    reason: format!("job failed {} times in a row", restarts + 1),
+                };
+            }
+
+            RestartDecision::Restart {
+                delay: Duration::from_millis(fixed.delay_millis),
+            }
+        }
+        RestartPolicy::ExponentialBackoff(backoff) => {
+            if restarts >= backoff.max_restarts {
+                return RestartDecision::Fail {
+                    reason: format!("job failed {} times in a row", restarts + 1),
+                };
+            }
+
+            let delay = backoff
+                .initial_delay_millis
+                .saturating_mul(1u64.checked_shl(restarts).unwrap_or(u64::MAX))
+                .min(backoff.max_delay_millis);
+
+            RestartDecision::Restart {
+                delay: Duration::from_millis(delay),
+            }
+        }
+        RestartPolicy::FailureRate(rate) => {
+            let interval = Duration::from_millis(rate.interval_millis);
+            failures.push_back(now);
+            while failures
+                .front()
+                .map(|t| now.duration_since(*t) > interval)
+                .unwrap_or(false)
+            {
+                failures.pop_front();
+            }
+
+            if failures.len() > rate.max_failures as usize {
+                return RestartDecision::Fail {
+                    reason: format!(
+                        "job failed {} times in the last {:?}",
+                        failures.len(),
+                        interval
+                    ),
+                };
+            }
+
+            RestartDecision::Restart {
+                delay: Duration::from_millis(rate.delay_millis),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_rpc::api_types::pipelines::{
+        ExponentialBackoffRestart, FailureRateRestart, FixedDelayRestart,
+    };
+
+    fn delay(decision: RestartDecision) -> Option<Duration> {
+        match decision {
+            RestartDecision::Restart { delay } => Some(delay),
+            RestartDecision::Fail { .. } => None,
+        }
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let policy = RestartPolicy::FixedDelay(FixedDelayRestart {
+            max_restarts: 2,
+            delay_millis: 500,
+        });
+        let mut failures = VecDeque::new();
+        let now = Instant::now();
+
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            delay(on_failure(&policy, 0, &mut failures, now))
+        );
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            delay(on_failure(&policy, 1, &mut failures, now))
+        );
+        assert_eq!(None, delay(on_failure(&policy, 2, &mut failures, now)));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = RestartPolicy::ExponentialBackoff(ExponentialBackoffRestart {
+            max_restarts: 100,
+            initial_delay_millis: 1000,
+            max_delay_millis: 10_000,
+        });
+        let mut failures = VecDeque::new();
+        let now = Instant::now();
+
+        let delays: Vec<_> = [0, 1, 2, 3, 4, 99]
+            .into_iter()
+            .map(|restarts| delay(on_failure(&policy, restarts, &mut failures, now)).unwrap())
+            .map(|d| d.as_millis())
+            .collect();
+        assert_eq!(vec![1000, 2000, 4000, 8000, 10_000, 10_000], delays);
+
+        assert_eq!(None, delay(on_failure(&policy, 100, &mut failures, now)));
+    }
+
+    #[test]
+    fn test_failure_rate() {
+        let policy = RestartPolicy::FailureRate(FailureRateRestart {
+            max_failures: 2,
+            interval_millis: 60_000,
+            delay_millis: 0,
+        });
+        let mut failures = VecDeque::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // the number of restarts in a row doesn't matter, only how many failures are recent
+        assert!(delay(on_failure(&policy, 5, &mut failures, at(0))).is_some());
+        assert!(delay(on_failure(&policy, 5, &mut failures, at(30))).is_some());
+        // the first failure has left the interval
+        assert!(delay(on_failure(&policy, 5, &mut failures, at(61))).is_some());
+        assert_eq!(2, failures.len());
+        assert!(delay(on_failure(&policy, 5, &mut failures, at(62))).is_none());
+    }
+}
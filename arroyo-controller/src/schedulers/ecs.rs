@@ -0,0 +1,327 @@
+use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
+use anyhow::anyhow;
+use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
+use arroyo_types::{
+    string_config, u32_config, WorkerId, ADMIN_PORT_ENV, CONTROLLER_ADDR_ENV, ECS_CLUSTER_ENV,
+    ECS_WORKER_ASSIGN_PUBLIC_IP_ENV, ECS_WORKER_CONTAINER_NAME_ENV, ECS_WORKER_LAUNCH_TYPE_ENV,
+    ECS_WORKER_SECURITY_GROUPS_ENV, ECS_WORKER_SLOTS_ENV, ECS_WORKER_SUBNETS_ENV,
+    ECS_WORKER_TASK_DEFINITION_ENV, GRPC_PORT_ENV, JOB_ID_ENV, NODE_ID_ENV, RUN_ID_ENV,
+    TASK_SLOTS_ENV, WORKER_ID_ENV,
+};
+use async_trait::async_trait;
+use aws_sdk_ecs::model::{
+    AssignPublicIp, AwsVpcConfiguration, ContainerOverride, DesiredStatus, KeyValuePair,
+    LaunchType, NetworkConfiguration, Tag, Task, TaskField, TaskOverride,
+};
+use aws_sdk_ecs::Client;
+use rand::Rng;
+use std::str::FromStr;
+use tonic::Status;
+use tracing::{info, warn};
+
+const JOB_ID_TAG: &str = "job_id";
+const RUN_ID_TAG: &str = "run_id";
+const WORKER_ID_TAG: &str = "worker_id";
+const JOB_NAME_TAG: &str = "job_name";
+
+// describe_tasks accepts at most 100 tasks per call
+const DESCRIBE_TASKS_BATCH: usize = 100;
+
+/// Runs each worker as a standalone ECS task (on Fargate by default) from a task definition
+/// configured for the cluster. Standalone tasks aren't restarted by ECS when they fail, which
+/// leaves handling failures to the controller, as with Nomad.
+pub struct EcsScheduler {
+    client: Client,
+    cluster: String,
+    task_definition: String,
+    container_name: String,
+    launch_type: LaunchType,
+    subnets: Vec<String>,
+    security_groups: Vec<String>,
+    assign_public_ip: bool,
+    slots_per_task: u32,
+}
+
+fn list_config(var: &str) -> Vec<String> {
+    string_config(var, "")
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Splits `slots` over as few workers as possible, with at most `slots_per_worker` each
+fn slots_per_worker(slots: usize, slots_per_worker: usize) -> Vec<usize> {
+    let slots_per_worker = slots_per_worker.max(1);
+    (0..slots)
+        .step_by(slots_per_worker)
+        .map(|scheduled| (slots - scheduled).min(slots_per_worker))
+        .collect()
+}
+
+fn tag_value<'a>(task: &'a Task, key: &str) -> Option<&'a str> {
+    task.tags()?
+        .iter()
+        .find(|tag| tag.key() == Some(key))
+        .and_then(|tag| tag.value())
+}
+
+impl EcsScheduler {
+    pub async fn from_env() -> Self {
+        let task_definition = std::env::var(ECS_WORKER_TASK_DEFINITION_ENV).unwrap_or_else(|_| {
+            panic!(
+                "{} must be set to use the ECS scheduler",
+                ECS_WORKER_TASK_DEFINITION_ENV
+            )
+        });
+
+        Self {
+            client: Client::new(&aws_config::load_from_env().await),
+            cluster: string_config(ECS_CLUSTER_ENV, "default"),
+            task_definition,
+            container_name: string_config(ECS_WORKER_CONTAINER_NAME_ENV, "worker"),
+            launch_type: LaunchType::from(
+                string_config(ECS_WORKER_LAUNCH_TYPE_ENV, "FARGATE").as_str(),
+            ),
+            subnets: list_config(ECS_WORKER_SUBNETS_ENV),
+            security_groups: list_config(ECS_WORKER_SECURITY_GROUPS_ENV),
+            assign_public_ip: string_config(ECS_WORKER_ASSIGN_PUBLIC_IP_ENV, "false") == "true",
+            slots_per_task: u32_config(ECS_WORKER_SLOTS_ENV, 4),
+        }
+    }
+
+    fn network_configuration(&self) -> Option<NetworkConfiguration> {
+        if self.subnets.is_empty() {
+            return None;
+        }
+
+        Some(
+            NetworkConfiguration::builder()
+                .awsvpc_configuration(
+                    AwsVpcConfiguration::builder()
+                        .set_subnets(Some(self.subnets.clone()))
+                        .set_security_groups(Some(self.security_groups.clone()))
+                        .assign_public_ip(if self.assign_public_ip {
+                            AssignPublicIp::Enabled
+                        } else {
+                            AssignPublicIp::Disabled
+                        })
+                        .build(),
+                )
+                .build(),
+        )
+    }
+
+    fn container_override(
+        &self,
+        req: &StartPipelineReq,
+        worker_id: u64,
+        slots: usize,
+    ) -> ContainerOverride {
+        let mut env = vec![
+            ("PROD".to_string(), "true".to_string()),
+            (TASK_SLOTS_ENV.to_string(), slots.to_string()),
+            (WORKER_ID_ENV.to_string(), worker_id.to_string()),
+            (NODE_ID_ENV.to_string(), "1".to_string()),
+            (JOB_ID_ENV.to_string(), req.job_id.clone()),
+            (RUN_ID_ENV.to_string(), req.run_id.to_string()),
+            (GRPC_PORT_ENV.to_string(), "6900".to_string()),
+            (ADMIN_PORT_ENV.to_string(), "6901".to_string()),
+            ("WORKER_BIN".to_string(), req.pipeline_path.clone()),
+            ("WASM_BIN".to_string(), req.wasm_path.clone()),
+        ];
+
+        if let Ok(addr) = std::env::var(CONTROLLER_ADDR_ENV) {
+            env.push((CONTROLLER_ADDR_ENV.to_string(), addr));
+        }
+
+        for (key, value) in req.env_vars.iter() {
+            env.push((key.clone(), value.clone()));
+        }
+
+        ContainerOverride::builder()
+            .name(&self.container_name)
+            .set_environment(Some(
+                env.into_iter()
+                    .map(|(name, value)| KeyValuePair::builder().name(name).value(value).build())
+                    .collect(),
+            ))
+            .build()
+    }
+
+    async fn tasks_for_job(&self, job_id: &str, run_id: Option<i64>) -> anyhow::Result<Vec<Task>> {
+        let mut arns = vec![];
+        let mut next_token = None;
+        loop {
+            let resp = self
+                .client
+                .list_tasks()
+                .cluster(&self.cluster)
+                .started_by(job_id)
+                .desired_status(DesiredStatus::Running)
+                .set_next_token(next_token)
+                .send()
+                .await?;
+
+            arns.extend(resp.task_arns().unwrap_or_default().iter().cloned());
+
+            next_token = resp.next_token().map(|t| t.to_string());
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        let mut tasks = vec![];
+        for batch in arns.chunks(DESCRIBE_TASKS_BATCH) {
+            let resp = self
+                .client
+                .describe_tasks()
+                .cluster(&self.cluster)
+                .set_tasks(Some(batch.to_vec()))
+                .include(TaskField::Tags)
+                .send()
+                .await?;
+
+            tasks.extend(
+                resp.tasks()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|task| tag_value(task, JOB_ID_TAG) == Some(job_id))
+                    .filter(|task| {
+                        run_id.is_none()
+                            || tag_value(task, RUN_ID_TAG)
+                                == run_id.map(|r| r.to_string()).as_deref()
+                    })
+                    .cloned(),
+            );
+        }
+
+        Ok(tasks)
+    }
+}
+
+#[async_trait]
+impl Scheduler for EcsScheduler {
+    async fn start_workers(&self, req: StartPipelineReq) -> Result<(), SchedulerError> {
+        for slots in slots_per_worker(req.slots, self.slots_per_task as usize) {
+            let worker_id: u64 = rand::thread_rng().gen::<u32>() as u64;
+
+            let tags = [
+                (JOB_ID_TAG, req.job_id.clone()),
+                (RUN_ID_TAG, req.run_id.to_string()),
+                (WORKER_ID_TAG, worker_id.to_string()),
+                (JOB_NAME_TAG, req.name.clone()),
+            ]
+            .into_iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect();
+
+            let resp = self
+                .client
+                .run_task()
+                .cluster(&self.cluster)
+                .task_definition(&self.task_definition)
+                .launch_type(self.launch_type.clone())
+                .set_network_configuration(self.network_configuration())
+                .overrides(
+                    TaskOverride::builder()
+                        .container_overrides(self.container_override(&req, worker_id, slots))
+                        .build(),
+                )
+                .started_by(&req.job_id)
+                .set_tags(Some(tags))
+                .count(1)
+                .send()
+                .await
+                .map_err(|e| SchedulerError::Other(format!("Error scheduling: {:?}", e)))?;
+
+            if let Some(failure) = resp.failures().and_then(|f| f.first()) {
+                return Err(SchedulerError::Other(format!(
+                    "Error scheduling: {} ({})",
+                    failure.reason().unwrap_or("unknown reason"),
+                    failure.detail().unwrap_or_default()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn workers_for_job(
+        &self,
+        job_id: &str,
+        run_id: Option<i64>,
+    ) -> anyhow::Result<Vec<WorkerId>> {
+        self.tasks_for_job(job_id, run_id)
+            .await?
+            .iter()
+            .map(|task| {
+                let worker_id = tag_value(task, WORKER_ID_TAG)
+                    .ok_or_else(|| anyhow!("ECS task is missing the worker id tag"))?;
+                Ok(WorkerId(u64::from_str(worker_id)?))
+            })
+            .collect()
+    }
+
+    async fn register_node(&self, _req: RegisterNodeReq) {
+        // ignore
+    }
+
+    async fn heartbeat_node(&self, _req: HeartbeatNodeReq) -> Result<(), Status> {
+        // ignore
+        Ok(())
+    }
+
+    async fn worker_finished(&self, _req: WorkerFinishedReq) {
+        // ignore
+    }
+
+    async fn stop_workers(
+        &self,
+        job_id: &str,
+        run_id: Option<i64>,
+        _force: bool,
+    ) -> anyhow::Result<()> {
+        info!(message = "stopping workers", job_id = job_id);
+
+        for task in self.tasks_for_job(job_id, run_id).await? {
+            let Some(arn) = task.task_arn() else {
+                continue;
+            };
+
+            if let Err(e) = self
+                .client
+                .stop_task()
+                .cluster(&self.cluster)
+                .task(arn)
+                .reason("stopped by the Arroyo controller")
+                .send()
+                .await
+            {
+                warn!(
+                    message = "failed to stop worker",
+                    job_id = job_id,
+                    task = arn,
+                    error = format!("{:?}", e)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::slots_per_worker;
+
+    #[test]
+    fn test_slots_per_worker() {
+        assert_eq!(slots_per_worker(8, 4), vec![4, 4]);
+        assert_eq!(slots_per_worker(10, 4), vec![4, 4, 2]);
+        assert_eq!(slots_per_worker(3, 4), vec![3]);
+        assert_eq!(slots_per_worker(0, 4), Vec::<usize>::new());
+        assert_eq!(slots_per_worker(2, 0), vec![1, 1]);
+    }
+}
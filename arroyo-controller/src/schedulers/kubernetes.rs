@@ -1,5 +1,6 @@
 use crate::schedulers::{Scheduler, SchedulerError, StartPipelineReq};
 use anyhow::bail;
+use arroyo_rpc::api_types::pipelines::WorkerResources;
 use arroyo_rpc::grpc::{HeartbeatNodeReq, RegisterNodeReq, WorkerFinishedReq};
 use arroyo_types::{
     string_config, u32_config, WorkerId, ADMIN_PORT_ENV, CONTROLLER_ADDR_ENV, GRPC_PORT_ENV,
@@ -12,6 +13,7 @@ use arroyo_types::{
 use async_trait::async_trait;
 use k8s_openapi::api::apps::v1::ReplicaSet;
 use k8s_openapi::api::core::v1::{Pod, ResourceRequirements, Volume, VolumeMount};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use kube::api::{DeleteParams, ListParams};
 use kube::{Api, Client};
 use serde::de::DeserializeOwned;
@@ -93,8 +95,34 @@ impl KubernetesScheduler {
         }
     }
 
+    /// The cluster's worker resources, with those set for the pipeline replacing them
+    fn resources_for(&self, overrides: Option<&WorkerResources>) -> ResourceRequirements {
+        fn set(
+            quantities: &mut Option<BTreeMap<String, Quantity>>,
+            name: &str,
+            value: &Option<String>,
+        ) {
+            if let Some(value) = value {
+                quantities
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(name.to_string(), Quantity(value.clone()));
+            }
+        }
+
+        let mut resources = self.resources.clone();
+        if let Some(overrides) = overrides {
+            set(&mut resources.requests, "cpu", &overrides.cpu_request);
+            set(&mut resources.requests, "memory", &overrides.memory_request);
+            set(&mut resources.limits, "cpu", &overrides.cpu_limit);
+            set(&mut resources.limits, "memory", &overrides.memory_limit);
+        }
+        resources
+    }
+
     fn make_replicaset(&self, req: StartPipelineReq) -> ReplicaSet {
-        let replicas = (req.slots as f32 / self.slots_per_pod as f32).ceil() as usize;
+        let worker_pod = req.worker_pod.clone().unwrap_or_default();
+        let slots_per_pod = worker_pod.slots_per_pod.unwrap_or(self.slots_per_pod);
+        let replicas = (req.slots as f32 / slots_per_pod as f32).ceil() as usize;
 
         let mut labels = json!({
             CLUSTER_LABEL: self.name,
@@ -117,12 +145,20 @@ impl KubernetesScheduler {
                 .insert(k.clone(), Value::String(v.clone()));
         }
 
+        let mut pod_annotations = annotations.clone();
+        for (k, v) in worker_pod.annotations.iter().flatten() {
+            pod_annotations
+                .as_object_mut()
+                .unwrap()
+                .insert(k.clone(), Value::String(v.clone()));
+        }
+
         let mut env = json!([
             {
                 "name": "PROD", "value": "true",
             },
             {
-                "name": TASK_SLOTS_ENV, "value": format!("{}", self.slots_per_pod),
+                "name": TASK_SLOTS_ENV, "value": format!("{}", slots_per_pod),
             },
             {
                 "name": NODE_ID_ENV, "value": "1",
@@ -183,16 +219,18 @@ impl KubernetesScheduler {
                 "template": {
                     "metadata": {
                         "labels": labels,
-                        "annotations": annotations,
+                        "annotations": pod_annotations,
                     },
                     "spec": {
                         "volumes": self.volumes,
+                        "nodeSelector": worker_pod.node_selector,
+                        "tolerations": worker_pod.tolerations,
                         "containers": [
                             {
                                 "name": "worker",
                                 "image": self.image,
                                 "imagePullPolicy": self.image_pull_policy,
-                                "resources": self.resources,
+                                "resources": self.resources_for(worker_pod.resources.as_ref()),
                                 "ports": [
                                     {
                                         "containerPort": 6900,
@@ -322,6 +360,7 @@ impl Scheduler for KubernetesScheduler {
 mod test {
     use crate::schedulers::kubernetes::KubernetesScheduler;
     use crate::schedulers::StartPipelineReq;
+    use arroyo_rpc::api_types::pipelines::{Toleration, WorkerPodConfig, WorkerResources};
 
     #[test]
     fn test_resource_creation() {
@@ -334,10 +373,66 @@ mod test {
             run_id: 1,
             slots: 8,
             env_vars: Default::default(),
+            worker_pod: None,
         };
 
         KubernetesScheduler::new(None)
             // test that we don't panic when creating the replicaset
             .make_replicaset(req);
     }
+
+    #[test]
+    fn test_worker_pod_config() {
+        let req = StartPipelineReq {
+            name: "test_pipeline".to_string(),
+            pipeline_path: "file:///pipeline".to_string(),
+            wasm_path: "file:///wasm".to_string(),
+            job_id: "job123".to_string(),
+            hash: "12123123h".to_string(),
+            run_id: 1,
+            slots: 8,
+            env_vars: Default::default(),
+            worker_pod: Some(WorkerPodConfig {
+                slots_per_pod: Some(2),
+                resources: Some(WorkerResources {
+                    cpu_request: Some("2".to_string()),
+                    memory_limit: Some("4Gi".to_string()),
+                    ..Default::default()
+                }),
+                node_selector: Some([("pool".to_string(), "streaming".to_string())].into()),
+                tolerations: Some(vec![Toleration {
+                    key: Some("dedicated".to_string()),
+                    operator: Some("Equal".to_string()),
+                    value: Some("streaming".to_string()),
+                    effect: Some("NoSchedule".to_string()),
+                    toleration_seconds: None,
+                }]),
+                annotations: Some([("team".to_string(), "data".to_string())].into()),
+            }),
+        };
+
+        let rs = KubernetesScheduler::new(None).make_replicaset(req);
+        let spec = rs.spec.unwrap();
+        assert_eq!(Some(4), spec.replicas);
+
+        let template = spec.template.unwrap();
+        assert_eq!(
+            "data",
+            template.metadata.unwrap().annotations.unwrap()["team"]
+        );
+
+        let pod = template.spec.unwrap();
+        assert_eq!("streaming", pod.node_selector.unwrap()["pool"]);
+        assert_eq!(
+            Some("dedicated".to_string()),
+            pod.tolerations.unwrap()[0].key
+        );
+
+        let resources = pod.containers[0].resources.clone().unwrap();
+        let requests = resources.requests.unwrap();
+        assert_eq!("2", requests["cpu"].0);
+        // the cluster's memory request is kept
+        assert_eq!("200Mi", requests["memory"].0);
+        assert_eq!("4Gi", resources.limits.unwrap()["memory"].0);
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::bail;
+use arroyo_rpc::api_types::pipelines::WorkerPodConfig;
 use arroyo_rpc::grpc::node_grpc_client::NodeGrpcClient;
 use arroyo_rpc::grpc::{
     HeartbeatNodeReq, RegisterNodeReq, StartWorkerData, StartWorkerHeader, StartWorkerReq,
@@ -25,6 +26,9 @@ use tracing::{info, warn};
 #[cfg(feature = "k8s")]
 pub mod kubernetes;
 
+#[cfg(feature = "ecs")]
+pub mod ecs;
+
 pub mod nomad;
 
 lazy_static! {
@@ -99,6 +103,7 @@ pub struct StartPipelineReq {
     pub run_id: i64,
     pub slots: usize,
     pub env_vars: HashMap<String, String>,
+    pub worker_pod: Option<WorkerPodConfig>,
 }
 
 async fn get_binaries(req: &StartPipelineReq) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
@@ -1,4 +1,5 @@
 use arroyo_rpc::grpc;
+use tracing::warn;
 
 use crate::{states::StateError, JobMessage};
 
@@ -25,6 +26,15 @@ impl State for CheckpointStopping {
             match job_controller.checkpoint_finished().await {
                 Ok(done) => {
                     if done && job_controller.finished() && final_checkpoint_started {
+                        // savepoints requested with the stop (as for a pipeline update) are
+                        // taken from the final checkpoint before the job is reported stopped
+                        if let Err(e) = job_controller.take_final_savepoints().await {
+                            warn!(
+                                message = "failed to take final savepoints",
+                                job_id = ctx.config.id,
+                                error = format!("{:?}", e)
+                            );
+                        }
                         return Ok(Transition::next(*self, Stopped {}));
                     }
                 }
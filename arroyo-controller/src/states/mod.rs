@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 use std::{fmt::Debug, sync::Arc};
@@ -19,12 +20,14 @@ use anyhow::Result;
 use crate::job_controller::JobController;
 use crate::queries::controller_queries;
 use crate::types::public::StopMode;
+use crate::updates::shadow_sinks;
 use crate::{schedulers::Scheduler, JobConfig, JobMessage, JobStatus};
 use prost::Message;
 
 use self::checkpoint_stopping::CheckpointStopping;
 use self::compiling::Compiling;
 use self::finishing::Finishing;
+use self::promoting::Promoting;
 use self::recovering::Recovering;
 use self::rescaling::Rescaling;
 use self::running::Running;
@@ -34,6 +37,7 @@ use self::stopping::Stopping;
 mod checkpoint_stopping;
 mod compiling;
 mod finishing;
+mod promoting;
 mod recovering;
 mod rescaling;
 mod restarting;
@@ -205,6 +209,9 @@ impl TransitionTo<Recovering> for Running {
     }
 }
 impl TransitionTo<Rescaling> for Running {}
+impl TransitionTo<Promoting> for Running {}
+impl TransitionTo<Stopping> for Promoting {}
+impl TransitionTo<Compiling> for Promoting {}
 
 impl TransitionTo<Scheduling> for Rescaling {
     fn update_status(&self) -> TransitionFn {
@@ -215,12 +222,14 @@ impl TransitionTo<Scheduling> for Rescaling {
 }
 
 impl TransitionTo<Compiling> for Recovering {}
+impl TransitionTo<Stopping> for Recovering {}
 impl TransitionTo<Compiling> for Failed {
     fn update_status(&self) -> TransitionFn {
         Box::new(|ctx| {
             ctx.status.restart_nonce = ctx.config.restart_nonce;
             ctx.status.restarts = 0;
             ctx.status.failure_message = None;
+            ctx.failure_times.clear();
         })
     }
 }
@@ -358,6 +367,11 @@ pub struct JobContext<'a> {
     retries_attempted: usize,
     job_controller: Option<JobController>,
     last_transitioned_at: Instant,
+    // when the job has recently failed while running, for failure-rate restart policies
+    failure_times: VecDeque<Instant>,
+    // whether the program's sinks were replaced with web sinks, while the job is the shadow of
+    // a pipeline update
+    shadowed: bool,
 }
 
 impl<'a> JobContext<'a> {
@@ -556,6 +570,11 @@ pub async fn run_to_completion(
             .unwrap()
     };
 
+    let shadowed = config.read().unwrap().shadow;
+    if shadowed {
+        shadow_sinks(&mut program);
+    }
+
     let mut ctx = JobContext {
         config: config.read().unwrap().clone(),
         status: &mut status,
@@ -566,6 +585,8 @@ pub async fn run_to_completion(
         retries_attempted: 0,
         job_controller: None,
         last_transitioned_at: Instant::now(),
+        failure_times: VecDeque::new(),
+        shadowed,
     };
 
     loop {
@@ -581,6 +602,38 @@ pub async fn run_to_completion(
     }
 }
 
+// the state a job is resumed in when the controller starts
+fn initial_state(status: &mut JobStatus) -> Box<dyn State> {
+    // TODO: This seems pretty error-prone and easy to miss adding when we add states
+    match status.state.as_str() {
+        "Created" => Box::new(Created {}),
+        "Stopped" => Box::new(Stopped {}),
+        "Finished" => Box::new(Finished {}),
+        "Failed" => Box::new(Failed {}),
+        "Compiling" | "Scheduling" | "Running" | "Recovering" | "Rescaling" => {
+            Box::new(Compiling {})
+        }
+        // a promoted job must still drop the shadow's state before it's scheduled again
+        "Promoting" => Box::new(Promoting {}),
+        "Stopping" | "CheckpointStopping" => {
+            // TODO: do we need to handle a failure in CheckpointStopping specially?
+            if status.finish_time.is_none() {
+                status.finish_time = Some(OffsetDateTime::now_utc());
+            }
+            Box::new(Stopped {})
+        }
+        "Finishing" => {
+            if status.finish_time.is_none() {
+                status.finish_time = Some(OffsetDateTime::now_utc());
+            }
+            Box::new(Finished {})
+        }
+        s => {
+            panic!("Unhandled state {} in recovery", s);
+        }
+    }
+}
+
 pub struct StateMachine {
     tx: Option<Sender<JobMessage>>,
     config: Arc<RwLock<JobConfig>>,
@@ -613,51 +666,24 @@ impl StateMachine {
             return;
         }
 
-        // TODO: This seems pretty error-prone and easy to miss adding when we add states
-        let initial_state: Option<Box<dyn State>> = match status.state.as_str() {
-            "Created" => Some(Box::new(Created {})),
-            "Stopped" => Some(Box::new(Stopped {})),
-            "Finished" => Some(Box::new(Finished {})),
-            "Failed" => Some(Box::new(Failed {})),
-            "Compiling" | "Scheduling" | "Running" | "Recovering" | "Rescaling" => {
-                Some(Box::new(Compiling {}))
-            }
-            "Stopping" | "CheckpointStopping" => {
-                // TODO: do we need to handle a failure in CheckpointStopping specially?
-                if status.finish_time.is_none() {
-                    status.finish_time = Some(OffsetDateTime::now_utc());
-                }
-                Some(Box::new(Stopped {}))
-            }
-            "Finishing" => {
-                if status.finish_time.is_none() {
-                    status.finish_time = Some(OffsetDateTime::now_utc());
-                }
-                Some(Box::new(Finished {}))
-            }
-            s => {
-                panic!("Unhandled state {} in recovery", s);
-            }
-        };
-
-        if let Some(initial_state) = initial_state {
-            status.state = initial_state.name().to_string();
-            status.update_db(&self.pool).await.unwrap();
-            let (tx, rx) = channel(1024);
-            {
-                let config = self.config.clone();
-                let pool = self.pool.clone();
-                let scheduler = self.scheduler.clone();
-                tokio::spawn(async move {
-                    let id = { config.read().unwrap().id.clone() };
-                    info!(message = "starting state machine", job_id = id);
-                    run_to_completion(config, status, initial_state, pool, rx, scheduler).await;
-                    info!(message = "finished state machine", job_id = id);
-                });
-            }
-
-            self.tx = Some(tx);
+        let initial_state = initial_state(&mut status);
+
+        status.state = initial_state.name().to_string();
+        status.update_db(&self.pool).await.unwrap();
+        let (tx, rx) = channel(1024);
+        {
+            let config = self.config.clone();
+            let pool = self.pool.clone();
+            let scheduler = self.scheduler.clone();
+            tokio::spawn(async move {
+                let id = { config.read().unwrap().id.clone() };
+                info!(message = "starting state machine", job_id = id);
+                run_to_completion(config, status, initial_state, pool, rx, scheduler).await;
+                info!(message = "finished state machine", job_id = id);
+            });
         }
+
+        self.tx = Some(tx);
     }
 
     pub async fn update(&mut self, config: JobConfig, status: JobStatus) {
@@ -689,3 +715,37 @@ impl StateMachine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(state: &str) -> JobStatus {
+        JobStatus {
+            id: "job".to_string(),
+            run_id: 1,
+            state: state.to_string(),
+            start_time: None,
+            finish_time: None,
+            tasks: None,
+            failure_message: None,
+            restarts: 0,
+            pipeline_path: None,
+            wasm_path: None,
+            restart_nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_initial_state() {
+        assert_eq!("Compiling", initial_state(&mut status("Running")).name());
+        assert_eq!("Compiling", initial_state(&mut status("Rescaling")).name());
+
+        // a promoted job isn't restarted from the shadow's checkpoints
+        assert_eq!("Promoting", initial_state(&mut status("Promoting")).name());
+
+        let mut s = status("CheckpointStopping");
+        assert_eq!("Stopped", initial_state(&mut s).name());
+        assert!(s.finish_time.is_some());
+    }
+}
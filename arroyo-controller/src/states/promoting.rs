@@ -0,0 +1,81 @@
+use arroyo_datastream::Program;
+use arroyo_rpc::grpc::api::PipelineProgram;
+use prost::Message;
+use tracing::info;
+
+use crate::queries::controller_queries;
+use crate::states::stop_if_desired_non_running;
+
+use super::{
+    compiling::Compiling, recovering::Recovering, JobContext, State, StateError, Transition,
+};
+
+/// Restarts the new version of a pipeline in an update with its real sinks, once it's been
+/// promoted. By then the old version has stopped with a final checkpoint and been savepointed,
+/// so the shadow's own state is discarded and the job is recompiled to restore that savepoint:
+/// its sources resume exactly where the old version's stopped, and the two never write to the
+/// real sinks at the same time.
+#[derive(Debug)]
+pub struct Promoting {}
+
+#[async_trait::async_trait]
+impl State for Promoting {
+    fn name(&self) -> &'static str {
+        "Promoting"
+    }
+
+    async fn next(mut self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
+        stop_if_desired_non_running!(self, &ctx.config);
+
+        // reload the program, which was loaded with web sinks while the job was a shadow
+        let program: Result<Program, anyhow::Error> = async {
+            let c = ctx.pool.get().await?;
+            let res = controller_queries::get_program()
+                .bind(&c, &ctx.config.pipeline_id)
+                .one()
+                .await?;
+            PipelineProgram::decode(&res[..])?.try_into()
+        }
+        .await;
+
+        let program = match program {
+            Ok(program) => program,
+            Err(e) => return Err(ctx.retryable(self, "failed to load pipeline", e, 10)),
+        };
+
+        // there's no job to stop if the controller restarted while promoting
+        if ctx.job_controller.is_some() {
+            if let Err(e) = Recovering::cleanup(ctx).await {
+                return Err(ctx.retryable(self, "failed to stop shadow job", e, 10));
+            }
+            ctx.job_controller = None;
+        }
+
+        // without any checkpoints of its own the job is scheduled from its savepoint, which the
+        // update set to the one taken from the old version's final checkpoint
+        let discarded: Result<(), anyhow::Error> = async {
+            let c = ctx.pool.get().await?;
+            controller_queries::mark_failed()
+                .bind(&c, &ctx.config.id, &0)
+                .await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = discarded {
+            return Err(ctx.retryable(self, "failed to discard shadow checkpoints", e, 10));
+        }
+
+        info!(
+            message = "promoting job",
+            job_id = ctx.config.id,
+            savepoint = ctx.config.restore_from_savepoint
+        );
+
+        *ctx.program = program;
+        ctx.shadowed = false;
+        ctx.status.pipeline_path = None;
+        ctx.status.wasm_path = None;
+        Ok(Transition::next(*self, Compiling {}))
+    }
+}
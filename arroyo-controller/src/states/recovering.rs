@@ -5,10 +5,17 @@ use arroyo_rpc::grpc::StopMode;
 use tokio::time::timeout;
 use tracing::{info, warn};
 
+use crate::states::stopping::{StopBehavior, Stopping};
+use crate::types::public::StopMode as SqlStopMode;
+use crate::JobMessage;
+
 use super::{compiling::Compiling, JobContext, State, StateError, Transition};
 
 #[derive(Debug)]
-pub struct Recovering {}
+pub struct Recovering {
+    // how long to wait before restarting the job, according to its restart policy
+    pub delay: Duration,
+}
 
 impl Recovering {
     // tries, with increasing levels of force, to tear down the existing cluster
@@ -96,6 +103,39 @@ impl State for Recovering {
             return Err(ctx.retryable(self, "failed to tear down existing cluster", e, 10));
         }
 
-        Ok(Transition::next(*self, Compiling))
+        if !self.delay.is_zero() {
+            info!(
+                message = "waiting to restart job",
+                job_id = ctx.config.id,
+                delay_ms = self.delay.as_millis() as u64
+            );
+        }
+
+        let restart_at = tokio::time::sleep(self.delay);
+        tokio::pin!(restart_at);
+        loop {
+            tokio::select! {
+                _ = &mut restart_at => {
+                    return Ok(Transition::next(*self, Compiling));
+                }
+                msg = ctx.rx.recv() => match msg {
+                    Some(JobMessage::ConfigUpdate(c)) => {
+                        // the workers have already been torn down, so there's nothing left to stop
+                        // gracefully
+                        if !matches!(c.stop_mode, SqlStopMode::none) {
+                            return Ok(Transition::next(*self, Stopping {
+                                stop_mode: StopBehavior::StopWorkers,
+                            }));
+                        }
+                    }
+                    Some(m) => {
+                        ctx.handle(m)?;
+                    }
+                    None => {
+                        panic!("Job message channel closed: {}", ctx.config.id);
+                    }
+                }
+            }
+        }
     }
 }
@@ -3,9 +3,12 @@ use std::time::{Duration, Instant};
 use time::OffsetDateTime;
 use tokio::time::MissedTickBehavior;
 
-use tracing::error;
+use tracing::{error, warn};
 
+use crate::autoscaler::{autoscale, AUTOSCALING_COOLDOWN, AUTOSCALING_INTERVAL};
+use crate::restarts::{on_failure, RestartDecision};
 use crate::states::finishing::Finishing;
+use crate::states::promoting::Promoting;
 use crate::states::recovering::Recovering;
 use crate::states::rescaling::Rescaling;
 use crate::states::restarting::Restarting;
@@ -20,9 +23,6 @@ use super::{JobContext, State, Transition};
 // after this amount of time, we consider the job to be healthy and reset the restarts counter
 const HEALTHY_DURATION: Duration = Duration::from_secs(2 * 60);
 
-// how many times we allow the job to restart before moving it to failed
-const RESTARTS_ALLOWED: usize = 10;
-
 #[derive(Debug)]
 pub struct Running {}
 
@@ -35,11 +35,21 @@ impl State for Running {
     async fn next(mut self: Box<Self>, ctx: &mut JobContext) -> Result<Transition, StateError> {
         stop_if_desired_running!(self, ctx.config);
 
+        // the job may have been promoted while it was being scheduled or recovered
+        if ctx.shadowed && !ctx.config.shadow {
+            return Ok(Transition::next(*self, Promoting {}));
+        }
+
         let running_start = Instant::now();
 
         let mut log_interval = tokio::time::interval(Duration::from_secs(60));
         log_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+        let mut autoscaling_interval = tokio::time::interval(AUTOSCALING_INTERVAL);
+        autoscaling_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut autoscaling = ctx.config.autoscaling.clone();
+        let mut restart_policy = ctx.config.restart_policy.clone();
+
         loop {
             let ttl_end: Option<Duration> = ctx.config.ttl.map(|t| {
                 let elapsed = Duration::from_micros(
@@ -62,7 +72,19 @@ impl State for Running {
                                 }));
                             }
 
-                            let job_controller = ctx.job_controller.as_ref().unwrap();
+                            // the job has been promoted from the shadow of an update
+                            if ctx.shadowed && !c.shadow {
+                                return Ok(Transition::next(*self, Promoting {}));
+                            }
+
+                            autoscaling = c.autoscaling.clone();
+                            restart_policy = c.restart_policy.clone();
+
+                            let job_controller = ctx.job_controller.as_mut().unwrap();
+                            if let Some(name) = &c.pending_savepoint {
+                                job_controller.savepoint(name);
+                            }
+
                             for (op, p) in &c.parallelism_overrides {
                                 if let Some(actual) = job_controller.operator_parallelism(op){
                                     if actual != *p {
@@ -111,16 +133,17 @@ impl State for Running {
                         },
                         Err(err) => {
                             error!(message = "error while running", error = format!("{:?}", err), job_id = ctx.config.id);
-                            if ctx.status.restarts >= RESTARTS_ALLOWED as i32 {
-                                return Err(fatal(
-                                    "too many job failures",
+                            let restarts = ctx.status.restarts.max(0) as u32;
+                            return match on_failure(&restart_policy, restarts, &mut ctx.failure_times, Instant::now()) {
+                                RestartDecision::Restart { delay } => Ok(Transition::next(
+                                    *self,
+                                    Recovering { delay }
+                                )),
+                                RestartDecision::Fail { reason } => Err(fatal(
+                                    format!("too many job failures: {}", reason),
                                     err
-                                ));
-                            }
-                            return Ok(Transition::next(
-                                *self,
-                                Recovering {}
-                            ))
+                                )),
+                            };
                         }
                     }
                 }
@@ -135,6 +158,17 @@ impl State for Running {
                         }),
                     );
                 }
+                _ = autoscaling_interval.tick() => {
+                    // a new parallelism is picked up as a config update, which rescales the job
+                    if let Some(autoscaling) = autoscaling.as_ref().filter(|a| a.enabled) {
+                        if running_start.elapsed() > AUTOSCALING_COOLDOWN {
+                            if let Err(e) = autoscale(&ctx.pool, &ctx.config.id, ctx.status.run_id, ctx.program, autoscaling).await {
+                                warn!(message = "Failed to autoscale job", error = format!("{:?}", e),
+                                    job_id = ctx.config.id);
+                            }
+                        }
+                    }
+                }
                 _ = tokio::time::sleep(ttl_end.unwrap_or(Duration::MAX)) => {
                     // TTL has expired, stop the job
                     return Ok(Transition::next(
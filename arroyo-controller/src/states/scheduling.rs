@@ -1,20 +1,23 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use arroyo_datastream::Program;
 use arroyo_rpc::grpc::{
     worker_grpc_client::WorkerGrpcClient, StartExecutionReq, TableWriteBehavior, TaskAssignment,
 };
-use arroyo_types::WorkerId;
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+use arroyo_types::{WorkerId, STATE_BACKEND_ENV};
+use time::OffsetDateTime;
 use tokio::{sync::Mutex, task::JoinHandle};
 use tonic::{transport::Channel, Request};
 use tracing::{error, info, warn};
 
 use anyhow::anyhow;
-use arroyo_state::{parquet::get_storage_env_vars, BackingStore, StateBackend};
+use arroyo_state::parquet::{get_storage_env_vars, ParquetBackend};
+use arroyo_state::{BackingStore, StateBackend};
 
 use crate::{
     job_controller::JobController,
@@ -160,6 +163,11 @@ impl Scheduling {
         slots_needed: usize,
     ) -> Result<Either<Transition, Box<Self>>, StateError> {
         let start = Instant::now();
+        let mut env_vars = get_storage_env_vars(&ctx.config.checkpoint_url);
+        env_vars.insert(
+            STATE_BACKEND_ENV.to_string(),
+            ctx.config.state_backend.to_string(),
+        );
         loop {
             match ctx
                 .scheduler
@@ -171,7 +179,8 @@ impl Scheduling {
                     name: ctx.config.pipeline_name.clone(),
                     hash: ctx.program.get_hash(),
                     slots: slots_needed,
-                    env_vars: get_storage_env_vars(),
+                    env_vars: env_vars.clone(),
+                    worker_pod: ctx.config.worker_pod.clone(),
                 })
                 .await
             {
@@ -307,7 +316,7 @@ impl State for Scheduling {
             needs_commits: bool,
         }
 
-        let checkpoint_info = controller_queries::last_successful_checkpoint()
+        let mut checkpoint_info = controller_queries::last_successful_checkpoint()
             .bind(&c, &ctx.config.id)
             .opt()
             .await
@@ -328,6 +337,68 @@ impl State for Scheduling {
                 }
             });
 
+        // a job started from a savepoint restores it until it has checkpoints of its own
+        if let (None, Some(savepoint)) = (&checkpoint_info, &ctx.config.restore_from_savepoint) {
+            info!(
+                message = "restoring savepoint",
+                job_id = ctx.config.id,
+                savepoint
+            );
+
+            let operator_ids: Vec<_> = ctx
+                .program
+                .graph
+                .node_weights()
+                .map(|node| node.operator_id.clone())
+                .collect();
+
+            let metadata = match ParquetBackend::restore_savepoint(
+                &ctx.config.savepoint_url,
+                &ctx.config.checkpoint_url,
+                savepoint,
+                &ctx.config.id,
+                &operator_ids,
+                &ctx.program.state_schemas,
+            )
+            .await
+            {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    return Err(fatal(
+                        format!("Failed to restore job from savepoint {}", savepoint),
+                        e,
+                    ));
+                }
+            };
+
+            let id = controller_queries::create_checkpoint()
+                .bind(
+                    &c,
+                    &generate_id(IdTypes::Checkpoint),
+                    &ctx.config.organization_id,
+                    &ctx.config.id,
+                    &StateBackend::name().to_string(),
+                    &(metadata.epoch as i32),
+                    &(metadata.min_epoch as i32),
+                    &OffsetDateTime::now_utc(),
+                )
+                .one()
+                .await
+                .unwrap();
+
+            controller_queries::commit_checkpoint()
+                .bind(&c, &SystemTime::now().into(), &id)
+                .await
+                .unwrap();
+
+            checkpoint_info = Some(CheckpointInfo {
+                epoch: metadata.epoch,
+                min_epoch: metadata.min_epoch,
+                id,
+                needs_commits: false,
+            });
+        }
+
         {
             // mark in-progress checkpoints as failed
             let last_epoch = checkpoint_info
@@ -350,17 +421,21 @@ impl State for Scheduling {
             needs_commits,
         }) = checkpoint_info.clone()
         {
-            let mut metadata = StateBackend::load_checkpoint_metadata(&ctx.config.id, epoch)
-                .await
-                .ok_or_else(|| {
-                    fatal(
-                        format!("Failed to restore job; checkpoint {} not found.", epoch),
-                        anyhow!(format!(
-                            "epoch {} not found for job {}",
-                            epoch, ctx.config.id
-                        )),
-                    )
-                })?;
+            let mut metadata = StateBackend::load_checkpoint_metadata(
+                &ctx.config.checkpoint_url,
+                &ctx.config.id,
+                epoch,
+            )
+            .await
+            .ok_or_else(|| {
+                fatal(
+                    format!("Failed to restore job; checkpoint {} not found.", epoch),
+                    anyhow!(format!(
+                        "epoch {} not found for job {}",
+                        epoch, ctx.config.id
+                    )),
+                )
+            })?;
 
             if let Err(e) = StateBackend::prepare_checkpoint_load(&metadata).await {
                 return Err(ctx.retryable(self, "failed to prepare checkpoint for loading", e, 10));
@@ -369,9 +444,13 @@ impl State for Scheduling {
             if needs_commits {
                 let mut commit_subtasks = HashSet::new();
                 for operator_id in &metadata.operator_ids {
-                    let operator_metadata =
-                        StateBackend::load_operator_metadata(&ctx.config.id, operator_id, epoch)
-                            .await;
+                    let operator_metadata = StateBackend::load_operator_metadata(
+                        &ctx.config.checkpoint_url,
+                        &ctx.config.id,
+                        operator_id,
+                        epoch,
+                    )
+                    .await;
                     let Some(operator_metadata) = operator_metadata else {
                         panic!(
                             "operator metadata for {} not found for job {}",
@@ -398,7 +477,7 @@ impl State for Scheduling {
                 }
                 committing_state = Some((id, commit_subtasks));
             }
-            StateBackend::write_checkpoint_metadata(metadata).await;
+            StateBackend::write_checkpoint_metadata(&ctx.config.checkpoint_url, metadata).await;
         }
 
         let assignments = compute_assignments(workers.values().collect(), ctx.program);
@@ -0,0 +1,373 @@
+//! Drives blue/green pipeline updates. The new version of a pipeline runs as a shadow job, with
+//! its sinks replaced by web sinks, from a savepoint of the current version. Once it has caught
+//! up (and has been promoted, if its output is being compared) the current version is stopped
+//! with a final checkpoint, which commits its sinks, and a savepoint is taken from it. The new
+//! version is then restarted with its real sinks from that savepoint.
+//!
+//! Only one version writes to the real sinks at a time, and the new one starts exactly where the
+//! current one stopped, so outputs are neither duplicated nor lost. The pipeline produces no
+//! output while it's switching.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use arroyo_datastream::{ConnectorOp, Operator, Program};
+use deadpool_postgres::Pool;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use arroyo_rpc::public_ids::{generate_id, IdTypes};
+
+use crate::autoscaler::source_lag;
+use crate::queries::controller_queries;
+use crate::types::public::{PipelineUpdateState, SavepointState, StopMode};
+
+// how often the progress of active updates is checked
+const UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The name of the savepoint taken from the final checkpoint of the job being replaced, which
+/// the new job is restarted from when it's switched to its real sinks
+fn final_savepoint_name(savepoint: &str) -> String {
+    format!("{}-final", savepoint)
+}
+
+/// Replaces the sinks of a shadow job's program with web sinks, so that its output can be
+/// compared with that of the job it's replacing without writing to the real sinks
+pub(crate) fn shadow_sinks(program: &mut Program) {
+    for node in program.graph.node_weights_mut() {
+        if let Operator::ConnectorSink { .. } = node.operator {
+            node.operator = Operator::ConnectorSink(ConnectorOp::web_sink());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UpdateProgress {
+    state: PipelineUpdateState,
+    max_lag: Duration,
+    compare_outputs: bool,
+    promoted_run_id: Option<i64>,
+    savepoint_state: Option<SavepointState>,
+    final_savepoint_state: Option<SavepointState>,
+    old_state: Option<String>,
+    old_stop: StopMode,
+    new_state: Option<String>,
+    new_run_id: Option<i64>,
+    new_shadow: bool,
+    // only known while the new job is catching up
+    new_source_lag: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Wait,
+    MoveTo(PipelineUpdateState),
+    // switches the new job to its real sinks, which restarts it with a new run id
+    Promote { run_id: i64 },
+    // stops the old job with a final checkpoint and savepoints it
+    StopOld,
+    Finish,
+    Fail(String),
+}
+
+fn is_done(state: &Option<String>) -> bool {
+    matches!(state.as_deref(), Some("Stopped" | "Finished" | "Failed"))
+}
+
+fn next_action(u: &UpdateProgress) -> Action {
+    if u.new_state.as_deref() == Some("Failed") {
+        return Action::Fail("the new version of the pipeline failed".to_string());
+    }
+
+    // once the update is switching the old job is expected to stop
+    if u.state != PipelineUpdateState::switching
+        && (is_done(&u.old_state) || u.old_stop != StopMode::none)
+    {
+        return Action::Fail("the pipeline was stopped during the update".to_string());
+    }
+
+    match u.state {
+        PipelineUpdateState::savepointing => match u.savepoint_state {
+            Some(SavepointState::ready) => Action::MoveTo(PipelineUpdateState::catching_up),
+            Some(SavepointState::failed) => {
+                Action::Fail("failed to take a savepoint of the pipeline".to_string())
+            }
+            Some(SavepointState::inprogress) | None => Action::Wait,
+        },
+        PipelineUpdateState::catching_up => {
+            let caught_up = u.new_state.as_deref() == Some("Running")
+                && u.new_source_lag.map(|l| l <= u.max_lag).unwrap_or(false);
+
+            if !caught_up {
+                Action::Wait
+            } else if u.compare_outputs {
+                Action::MoveTo(PipelineUpdateState::awaiting_promotion)
+            } else {
+                Action::MoveTo(PipelineUpdateState::switching)
+            }
+        }
+        PipelineUpdateState::switching => {
+            if !u.new_shadow {
+                // promoted, so done once the new job is running with its real sinks
+                if u.new_state.as_deref() == Some("Running") && u.new_run_id > u.promoted_run_id {
+                    Action::Finish
+                } else {
+                    Action::Wait
+                }
+            } else if !is_done(&u.old_state) {
+                // the new job only takes over the real sinks once the old one has stopped
+                if u.old_stop == StopMode::none {
+                    Action::StopOld
+                } else {
+                    Action::Wait
+                }
+            } else {
+                // the final savepoint is finished before the old job is reported stopped
+                match (u.final_savepoint_state, u.new_run_id) {
+                    (Some(SavepointState::ready), Some(run_id)) => Action::Promote { run_id },
+                    (Some(SavepointState::ready), None) => Action::Wait,
+                    _ => Action::Fail(
+                        "failed to take a savepoint of the pipeline's final checkpoint; the \
+                        pipeline is stopped and can be restarted from that checkpoint"
+                            .to_string(),
+                    ),
+                }
+            }
+        }
+        // promotion is requested through the API
+        PipelineUpdateState::awaiting_promotion
+        | PipelineUpdateState::finished
+        | PipelineUpdateState::failed => Action::Wait,
+    }
+}
+
+async fn check_updates(pool: &Pool) -> Result<()> {
+    let mut c = pool.get().await?;
+
+    let updates = controller_queries::active_pipeline_updates()
+        .bind(&c)
+        .all()
+        .await?;
+
+    for u in updates {
+        let new_source_lag = match (&u.state, u.new_state.as_deref(), u.new_run_id) {
+            (PipelineUpdateState::catching_up, Some("Running"), Some(run_id)) => {
+                source_lag(&u.new_job_id, run_id).await?
+            }
+            _ => None,
+        };
+
+        let progress = UpdateProgress {
+            state: u.state,
+            max_lag: Duration::from_micros(u.max_lag_micros.max(0) as u64),
+            compare_outputs: u.compare_outputs,
+            promoted_run_id: u.promoted_run_id,
+            savepoint_state: u.savepoint_state,
+            final_savepoint_state: u.final_savepoint_state,
+            old_state: u.old_state,
+            old_stop: u.old_stop,
+            new_state: u.new_state,
+            new_run_id: u.new_run_id,
+            new_shadow: u.new_shadow,
+            new_source_lag,
+        };
+
+        let (state, promoted_run_id, failure_message) = match next_action(&progress) {
+            Action::Wait => continue,
+            Action::MoveTo(state) => (state, progress.promoted_run_id, None),
+            Action::Promote { run_id } => {
+                controller_queries::promote_job()
+                    .bind(&c, &final_savepoint_name(&u.savepoint), &u.new_job_id)
+                    .await?;
+                (PipelineUpdateState::switching, Some(run_id), None)
+            }
+            Action::StopOld => {
+                // the savepoint is requested along with the stop, so it's taken from the final
+                // checkpoint
+                let transaction = c.transaction().await?;
+                controller_queries::create_savepoint()
+                    .bind(
+                        &transaction,
+                        &generate_id(IdTypes::Savepoint),
+                        &u.organization_id,
+                        &u.created_by,
+                        &final_savepoint_name(&u.savepoint),
+                        &u.old_job_id,
+                    )
+                    .await?;
+                controller_queries::set_job_stop()
+                    .bind(&transaction, &StopMode::checkpoint, &u.old_job_id)
+                    .await?;
+                transaction.commit().await?;
+                continue;
+            }
+            Action::Finish => (
+                PipelineUpdateState::finished,
+                progress.promoted_run_id,
+                None,
+            ),
+            Action::Fail(message) => {
+                // the new job is only stopped if it hasn't replaced the old one yet
+                if progress.new_shadow && !is_done(&progress.new_state) {
+                    controller_queries::set_job_stop()
+                        .bind(&c, &StopMode::immediate, &u.new_job_id)
+                        .await?;
+                }
+                (
+                    PipelineUpdateState::failed,
+                    progress.promoted_run_id,
+                    Some(message),
+                )
+            }
+        };
+
+        info!(
+            message = "pipeline update progressed",
+            old_job_id = u.old_job_id,
+            new_job_id = u.new_job_id,
+            state = format!("{:?}", state)
+        );
+
+        controller_queries::update_pipeline_update()
+            .bind(
+                &c,
+                &state,
+                &promoted_run_id,
+                &failure_message,
+                &OffsetDateTime::now_utc(),
+                &u.id,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn run_updates(pool: Pool) {
+    loop {
+        if let Err(e) = check_updates(&pool).await {
+            warn!(
+                message = "Failed to check pipeline updates",
+                error = format!("{:?}", e)
+            );
+        }
+
+        tokio::time::sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(state: PipelineUpdateState) -> UpdateProgress {
+        UpdateProgress {
+            state,
+            max_lag: Duration::from_secs(10),
+            compare_outputs: false,
+            promoted_run_id: None,
+            savepoint_state: Some(SavepointState::ready),
+            final_savepoint_state: None,
+            old_state: Some("Running".to_string()),
+            old_stop: StopMode::none,
+            new_state: Some("Running".to_string()),
+            new_run_id: Some(1),
+            new_shadow: true,
+            new_source_lag: None,
+        }
+    }
+
+    #[test]
+    fn test_catching_up() {
+        let mut u = progress(PipelineUpdateState::catching_up);
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.new_source_lag = Some(Duration::from_secs(30));
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.new_source_lag = Some(Duration::from_secs(5));
+        assert_eq!(
+            Action::MoveTo(PipelineUpdateState::switching),
+            next_action(&u)
+        );
+
+        u.compare_outputs = true;
+        assert_eq!(
+            Action::MoveTo(PipelineUpdateState::awaiting_promotion),
+            next_action(&u)
+        );
+    }
+
+    #[test]
+    fn test_switching() {
+        // the old job is stopped before the new one is switched to the real sinks
+        let mut u = progress(PipelineUpdateState::switching);
+        assert_eq!(Action::StopOld, next_action(&u));
+
+        u.old_stop = StopMode::checkpoint;
+        u.final_savepoint_state = Some(SavepointState::inprogress);
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.old_state = Some("CheckpointStopping".to_string());
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.old_state = Some("Stopped".to_string());
+        u.final_savepoint_state = Some(SavepointState::ready);
+        assert_eq!(Action::Promote { run_id: 1 }, next_action(&u));
+
+        // promoted, so the new job restarts from the final savepoint with a new run id
+        u.new_shadow = false;
+        u.promoted_run_id = Some(1);
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.new_state = Some("Promoting".to_string());
+        assert_eq!(Action::Wait, next_action(&u));
+
+        u.new_state = Some("Running".to_string());
+        u.new_run_id = Some(2);
+        assert_eq!(Action::Finish, next_action(&u));
+    }
+
+    #[test]
+    fn test_switching_without_final_savepoint() {
+        let mut u = progress(PipelineUpdateState::switching);
+        u.old_stop = StopMode::checkpoint;
+        u.old_state = Some("Stopped".to_string());
+
+        u.final_savepoint_state = Some(SavepointState::failed);
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+
+        // it's taken before the old job stops, so it won't finish later
+        u.final_savepoint_state = Some(SavepointState::inprogress);
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+
+        // the old job failed or was stopped some other way
+        u.final_savepoint_state = None;
+        u.old_state = Some("Failed".to_string());
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+
+        // a promoted job that fails fails the update
+        let mut u = progress(PipelineUpdateState::switching);
+        u.new_shadow = false;
+        u.promoted_run_id = Some(1);
+        u.old_state = Some("Stopped".to_string());
+        u.new_state = Some("Failed".to_string());
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+    }
+
+    #[test]
+    fn test_failures() {
+        let mut u = progress(PipelineUpdateState::savepointing);
+        u.savepoint_state = Some(SavepointState::failed);
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+
+        let mut u = progress(PipelineUpdateState::awaiting_promotion);
+        assert_eq!(Action::Wait, next_action(&u));
+        u.old_stop = StopMode::graceful;
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+
+        let mut u = progress(PipelineUpdateState::switching);
+        u.new_state = Some("Failed".to_string());
+        assert!(matches!(next_action(&u), Action::Fail(_)));
+    }
+}
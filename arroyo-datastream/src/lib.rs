@@ -3,8 +3,8 @@
 
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashMap, HashSet};
-use std::fmt::{Debug, Formatter};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hasher;
 use std::marker::PhantomData;
 use std::ops::Add;
@@ -13,7 +13,8 @@ use std::time::{Duration, SystemTime};
 
 use arroyo_rpc::grpc::api::operator::Operator as GrpcOperator;
 use arroyo_rpc::grpc::api::{self as GrpcApi, ExpressionAggregator, Flatten, ProgramEdge};
-use arroyo_types::{Data, GlobalKey, JoinType, Key};
+use arroyo_types::state_schema::OperatorStateSchema;
+use arroyo_types::{Data, FrameBound, GlobalKey, JoinType, Key};
 use bincode::{Decode, Encode};
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
@@ -66,6 +67,38 @@ pub fn duration_to_syn_expr(duration: Duration) -> syn::Expr {
     parse_quote!(std::time::Duration::new(#secs, #nanos))
 }
 
+fn frame_bound_to_syn_expr(bound: FrameBound) -> syn::Expr {
+    match bound {
+        FrameBound::Unbounded => parse_quote!(arroyo_types::FrameBound::Unbounded),
+        FrameBound::Rows(n) => parse_quote!(arroyo_types::FrameBound::Rows(#n)),
+        FrameBound::Time(d) => {
+            let d = duration_to_syn_expr(d);
+            parse_quote!(arroyo_types::FrameBound::Time(#d))
+        }
+    }
+}
+
+fn frame_bound_to_grpc(bound: FrameBound) -> GrpcApi::FrameBound {
+    use GrpcApi::frame_bound::Bound;
+    GrpcApi::FrameBound {
+        bound: Some(match bound {
+            FrameBound::Unbounded => Bound::Unbounded(true),
+            FrameBound::Rows(n) => Bound::Rows(n),
+            FrameBound::Time(d) => Bound::TimeMicros(d.as_micros() as u64),
+        }),
+    }
+}
+
+fn frame_bound_from_grpc(bound: Option<GrpcApi::FrameBound>) -> Result<FrameBound> {
+    use GrpcApi::frame_bound::Bound;
+    Ok(match bound.and_then(|b| b.bound) {
+        Some(Bound::Unbounded(_)) => FrameBound::Unbounded,
+        Some(Bound::Rows(n)) => FrameBound::Rows(n),
+        Some(Bound::TimeMicros(micros)) => FrameBound::Time(Duration::from_micros(micros)),
+        None => bail!("frame bound is unset"),
+    })
+}
+
 pub trait ArroyoData {
     fn get_def() -> String;
 }
@@ -102,10 +135,91 @@ pub struct WasmUDF {
 
 #[derive(Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd)]
 pub enum WindowType {
-    Tumbling { width: Duration },
-    Sliding { width: Duration, slide: Duration },
+    Tumbling {
+        width: Duration,
+        alignment: WindowAlignment,
+    },
+    Sliding {
+        width: Duration,
+        slide: Duration,
+        alignment: WindowAlignment,
+    },
     Instant,
-    Session { gap: Duration },
+    Session {
+        gap: Duration,
+    },
+}
+
+impl WindowType {
+    /// Whether the window's bins start somewhere other than the epoch-aligned boundaries in UTC
+    pub fn has_alignment(&self) -> bool {
+        match self {
+            WindowType::Tumbling { alignment, .. } | WindowType::Sliding { alignment, .. } => {
+                !alignment.is_default()
+            }
+            WindowType::Instant | WindowType::Session { .. } => false,
+        }
+    }
+}
+
+/// Where the windows of a tumbling or sliding window start. By default windows are aligned to
+/// the unix epoch, in UTC.
+#[derive(
+    Clone, Debug, Default, Encode, Decode, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd,
+)]
+pub struct WindowAlignment {
+    // how long after the epoch-aligned boundaries each window starts
+    pub offset: Duration,
+    // the time zone whose local time windows are aligned to, so that daily windows start at its
+    // midnight across daylight saving time changes
+    pub timezone: Option<String>,
+}
+
+impl WindowAlignment {
+    pub fn is_default(&self) -> bool {
+        self.offset.is_zero() && self.timezone.is_none()
+    }
+
+    fn to_syn_expr(&self) -> syn::Expr {
+        let offset = duration_to_syn_expr(self.offset);
+        let timezone = match &self.timezone {
+            Some(timezone) => quote!(Some(#timezone)),
+            None => quote!(None),
+        };
+        parse_quote!(arroyo_worker::operators::window_alignment::WindowAlignment::new(#offset, #timezone))
+    }
+}
+
+impl Display for WindowAlignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.offset.is_zero() {
+            write!(f, ", offset: {}", format_duration(self.offset))?;
+        }
+        if let Some(timezone) = &self.timezone {
+            write!(f, ", timezone: {}", timezone)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<WindowAlignment> for GrpcApi::WindowAlignment {
+    fn from(alignment: WindowAlignment) -> Self {
+        GrpcApi::WindowAlignment {
+            offset_micros: alignment.offset.as_micros() as u64,
+            timezone: alignment.timezone,
+        }
+    }
+}
+
+impl From<Option<GrpcApi::WindowAlignment>> for WindowAlignment {
+    fn from(alignment: Option<GrpcApi::WindowAlignment>) -> Self {
+        alignment
+            .map(|alignment| WindowAlignment {
+                offset: Duration::from_micros(alignment.offset_micros),
+                timezone: alignment.timezone,
+            })
+            .unwrap_or_default()
+    }
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -129,15 +243,25 @@ fn format_duration(duration: Duration) -> String {
 impl Debug for WindowType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Tumbling { width } => {
-                write!(f, "TumblingWindow({})", format_duration(*width))
+            Self::Tumbling { width, alignment } => {
+                write!(
+                    f,
+                    "TumblingWindow({}{})",
+                    format_duration(*width),
+                    alignment
+                )
             }
-            Self::Sliding { width, slide } => {
+            Self::Sliding {
+                width,
+                slide,
+                alignment,
+            } => {
                 write!(
                     f,
-                    "SlidingWindow(size: {}, slide: {})",
+                    "SlidingWindow(size: {}, slide: {}{})",
                     format_duration(*width),
-                    format_duration(*slide)
+                    format_duration(*slide),
+                    alignment
                 )
             }
             Self::Instant => {
@@ -161,6 +285,8 @@ pub struct PeriodicWatermark {
 pub enum WatermarkStrategy {
     FixedLateness { max_lateness: Duration },
     Expression { expression: String },
+    // the source generates watermarks itself, which are forwarded
+    Source,
 }
 
 #[derive(Copy, Clone, Encode, Decode, Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -209,6 +335,7 @@ pub enum AggregateBehavior {
 pub struct SlidingWindowAggregator {
     pub width: Duration,
     pub slide: Duration,
+    pub alignment: WindowAlignment,
     // fn(&MemA) -> OutT
     pub aggregator: String,
     // fn(&T, Option<&BinA>) -> BinA
@@ -224,6 +351,11 @@ pub struct SlidingWindowAggregator {
 #[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TumblingWindowAggregator {
     pub width: Duration,
+    pub alignment: WindowAlignment,
+    // how long after a window is emitted rows can still update it
+    pub allowed_lateness: Duration,
+    // how often, in wall-clock time, the results of open windows are emitted
+    pub emit_partial: Option<Duration>,
     // fn(&MemA) -> OutT
     pub aggregator: String,
     // fn(&T, Option<&BinA>) -> BinA
@@ -240,6 +372,17 @@ pub struct TumblingTopN {
     pub converter: String,
 }
 
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TopN {
+    pub max_elements: usize,
+    pub expiration: Duration,
+    // fn(&T) -> SK
+    pub extractor: String,
+    pub sort_key_type: String,
+    // fn(T, usize) -> OutT
+    pub converter: String,
+}
+
 #[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SlidingAggregatingTopN {
     pub width: Duration,
@@ -273,6 +416,64 @@ pub struct NonWindowAggregator {
     pub bin_type: String,
 }
 
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OverWindow {
+    pub preceding: FrameBound,
+    pub following: FrameBound,
+    // fn(&[T], usize, u64, Option<&OutT>) -> OutT
+    pub aggregator: String,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MatchRecognize {
+    // the (min, max) number of rows matched by each element of the pattern
+    pub pattern: Vec<(u64, Option<u64>)>,
+    pub within: Option<Duration>,
+    // fn(&T, usize) -> bool
+    pub predicate: String,
+    // fn(&[(usize, T)]) -> OutT
+    pub measures: String,
+}
+
+#[derive(Clone, Encode, Decode, Serialize, Deserialize, PartialEq)]
+pub struct LookupJoin {
+    // the lookup table, whose operator is a `LookupSource`
+    pub connector: ConnectorOp,
+    // the column of the lookup table that the join is keyed on
+    pub key_column: String,
+    // the type of the rows of the lookup table
+    pub lookup_type: String,
+    // fn(&T) -> Option<String>
+    pub key: String,
+    // fn(&T, Option<&LookupT>) -> Option<OutT>
+    pub merge: String,
+    pub cache_size: u64,
+    pub cache_ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AsyncUdf {
+    pub name: String,
+    // the type returned by the UDF
+    pub result_type: String,
+    // fn(&T) -> Pin<Box<dyn Future<Output = R> + Send>>
+    pub call: String,
+    // fn(&T, Option<R>) -> OutT
+    pub merge: String,
+    pub ordered: bool,
+    pub max_concurrency: u64,
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BroadcastConnect {
+    // fn(&C) -> String, the key that a control record replaces the previous value of
+    pub control_key: String,
+    // fn(&T, &BTreeMap<String, C>) -> Option<OutT>
+    pub process: String,
+}
+
 #[derive(Copy, Clone, Debug, Encode, Decode, Serialize, Deserialize, PartialEq)]
 pub enum ImpulseSpec {
     Delay(Duration),
@@ -365,6 +566,11 @@ pub enum Operator {
         right_expiration: Duration,
         join_type: JoinType,
     },
+    // inner join on rows whose left time - right time is within the bounds, in nanoseconds
+    IntervalJoin {
+        lower_bound: i64,
+        upper_bound: i64,
+    },
     UpdatingOperator {
         name: String,
         expression: String,
@@ -374,6 +580,23 @@ pub enum Operator {
         name: String,
         expression: String,
     },
+    Deduplicate {
+        ttl: Duration,
+    },
+    // passes on only the rows that are too late for a tumbling window to include
+    LateDataFilter {
+        width: Duration,
+        alignment: WindowAlignment,
+        allowed_lateness: Duration,
+    },
+    OverWindow(OverWindow),
+    MatchRecognize(MatchRecognize),
+    LookupJoin(LookupJoin),
+    TopN(TopN),
+    AsyncUdf(AsyncUdf),
+    // processes a keyed stream against the latest records of a control stream that is
+    // broadcast to every subtask
+    BroadcastConnect(BroadcastConnect),
 }
 
 #[derive(Clone, Encode, Decode, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -446,20 +669,33 @@ impl Debug for Operator {
                 name,
                 expression: _,
             } => write!(f, "flat_map<{}>", name),
-            Operator::SlidingWindowAggregator(SlidingWindowAggregator { width, slide, .. }) => {
+            Operator::SlidingWindowAggregator(SlidingWindowAggregator {
+                width,
+                slide,
+                alignment,
+                ..
+            }) => {
                 write!(
                     f,
                     "SlidingWindowAggregator<{:?}>",
                     WindowType::Sliding {
                         width: *width,
-                        slide: *slide
+                        slide: *slide,
+                        alignment: alignment.clone(),
                     }
                 )
             }
-            Operator::TumblingWindowAggregator(TumblingWindowAggregator { width, .. }) => write!(
+            Operator::TumblingWindowAggregator(TumblingWindowAggregator {
+                width,
+                alignment,
+                ..
+            }) => write!(
                 f,
                 "TumblingWindowAggregator<{:?}>",
-                WindowType::Tumbling { width: *width }
+                WindowType::Tumbling {
+                    width: *width,
+                    alignment: alignment.clone(),
+                }
             ),
             Operator::TumblingTopN(TumblingTopN {
                 width,
@@ -468,7 +704,10 @@ impl Debug for Operator {
             }) => write!(
                 f,
                 "TumblingTopN<{:?}, {:?}>",
-                WindowType::Tumbling { width: *width },
+                WindowType::Tumbling {
+                    width: *width,
+                    alignment: WindowAlignment::default(),
+                },
                 *max_elements
             ),
             Operator::SlidingAggregatingTopN(SlidingAggregatingTopN { width, slide, .. }) => {
@@ -477,7 +716,8 @@ impl Debug for Operator {
                     "SlidingAggregatingTopN<{:?}>",
                     WindowType::Sliding {
                         width: *width,
-                        slide: *slide
+                        slide: *slide,
+                        alignment: WindowAlignment::default(),
                     }
                 )
             }
@@ -490,6 +730,14 @@ impl Debug for Operator {
                 "JoinWithExpiration<left_expire: {:?}, right_expire: {:?}, join_type: {:?}>",
                 left_expiration, right_expiration, join_type
             ),
+            Operator::IntervalJoin {
+                lower_bound,
+                upper_bound,
+            } => write!(
+                f,
+                "IntervalJoin<lower_bound: {}ns, upper_bound: {}ns>",
+                lower_bound, upper_bound
+            ),
             Operator::UpdatingOperator {
                 name,
                 expression: _,
@@ -499,6 +747,143 @@ impl Debug for Operator {
                 name,
                 expression: _,
             } => write!(f, "updating_key<{}>", name),
+            Operator::Deduplicate { ttl } => write!(f, "Deduplicate<ttl: {:?}>", ttl),
+            Operator::LateDataFilter {
+                width,
+                alignment,
+                allowed_lateness,
+            } => write!(
+                f,
+                "LateDataFilter<{:?}, allowed lateness: {}>",
+                WindowType::Tumbling {
+                    width: *width,
+                    alignment: alignment.clone(),
+                },
+                format_duration(*allowed_lateness)
+            ),
+            Operator::OverWindow(OverWindow {
+                preceding,
+                following,
+                ..
+            }) => write!(f, "OverWindow<{:?}, {:?}>", preceding, following),
+            Operator::MatchRecognize(MatchRecognize { within, .. }) => {
+                write!(f, "MatchRecognize<within: {:?}>", within)
+            }
+            Operator::LookupJoin(LookupJoin { connector, .. }) => {
+                write!(f, "LookupJoin<{}>", connector.description)
+            }
+            Operator::TopN(TopN { max_elements, .. }) => write!(f, "TopN<{}>", max_elements),
+            Operator::AsyncUdf(AsyncUdf {
+                name,
+                max_concurrency,
+                ..
+            }) => write!(f, "AsyncUdf<{}, concurrency: {}>", name, max_concurrency),
+            Operator::BroadcastConnect(_) => write!(f, "BroadcastConnect"),
+        }
+    }
+}
+
+impl Operator {
+    /// A description of the state the operator keeps, and for how long it's retained, or None
+    /// if it's stateless
+    pub fn state_description(&self) -> Option<String> {
+        match self {
+            Operator::ConnectorSource(_) => Some("source offsets".to_string()),
+            Operator::Window { typ, .. } => Some(format!("{:?} window buffer", typ)),
+            Operator::Aggregate(_) | Operator::Count => Some("aggregate per key".to_string()),
+            Operator::Watermark(_) => Some("watermark".to_string()),
+            Operator::WindowJoin { window } => {
+                Some(format!("{:?} window buffers for both inputs", window))
+            }
+            Operator::SlidingWindowAggregator(SlidingWindowAggregator { width, slide, .. }) => {
+                Some(format!(
+                    "aggregate bins per key, each {} and kept for {}",
+                    format_duration(*slide),
+                    format_duration(*width)
+                ))
+            }
+            Operator::TumblingWindowAggregator(TumblingWindowAggregator {
+                width,
+                allowed_lateness,
+                ..
+            }) => Some(format!(
+                "aggregate per key, kept for {}",
+                format_duration(*width + *allowed_lateness)
+            )),
+            Operator::TumblingTopN(TumblingTopN {
+                width,
+                max_elements,
+                ..
+            }) => Some(format!(
+                "top {} rows per partition, kept for {}",
+                max_elements,
+                format_duration(*width)
+            )),
+            Operator::SlidingAggregatingTopN(SlidingAggregatingTopN {
+                width,
+                max_elements,
+                ..
+            }) => Some(format!(
+                "aggregate bins per key and top {} per partition, kept for {}",
+                max_elements,
+                format_duration(*width)
+            )),
+            Operator::JoinWithExpiration {
+                left_expiration,
+                right_expiration,
+                ..
+            } => Some(format!(
+                "rows of the left input kept for {}, and of the right for {}",
+                format_duration(*left_expiration),
+                format_duration(*right_expiration)
+            )),
+            Operator::IntervalJoin {
+                lower_bound,
+                upper_bound,
+            } => Some(format!(
+                "rows of both inputs, kept for {}",
+                format_duration(Duration::from_nanos(
+                    lower_bound.unsigned_abs().max(upper_bound.unsigned_abs())
+                ))
+            )),
+            Operator::NonWindowAggregator(NonWindowAggregator { expiration, .. }) => Some(format!(
+                "aggregate per key, expiring after {}",
+                format_duration(*expiration)
+            )),
+            Operator::Deduplicate { ttl } => {
+                Some(format!("seen rows, kept for {}", format_duration(*ttl)))
+            }
+            Operator::OverWindow(_) => Some("rows in the window frame per key".to_string()),
+            Operator::MatchRecognize(MatchRecognize { within, .. }) => Some(match within {
+                Some(within) => format!("partial matches, kept for {}", format_duration(*within)),
+                None => "partial matches".to_string(),
+            }),
+            Operator::LookupJoin(LookupJoin { cache_size, .. }) => {
+                Some(format!("cache of up to {} lookups", cache_size))
+            }
+            Operator::TopN(TopN {
+                max_elements,
+                expiration,
+                ..
+            }) => Some(format!(
+                "top {} rows per key, expiring after {}",
+                max_elements,
+                format_duration(*expiration)
+            )),
+            Operator::AsyncUdf(_) => Some("rows with calls in flight".to_string()),
+            Operator::BroadcastConnect(_) => {
+                Some("latest control record per control key, on every subtask".to_string())
+            }
+            Operator::ConnectorSink(_)
+            | Operator::FusedWasmUDFs { .. }
+            | Operator::GlobalKey
+            | Operator::ExpressionOperator { .. }
+            | Operator::FlattenOperator { .. }
+            | Operator::ArrayMapOperator { .. }
+            | Operator::FlatMapOperator { .. }
+            | Operator::UpdatingOperator { .. }
+            | Operator::UpdatingKeyOperator { .. }
+            | Operator::LateDataFilter { .. } => None,
         }
     }
 }
@@ -521,6 +906,7 @@ pub enum EdgeType {
     Forward,
     Shuffle,
     ShuffleJoin(usize),
+    Broadcast,
 }
 
 #[derive(Clone, Encode, Decode, Serialize, Deserialize)]
@@ -556,6 +942,7 @@ impl Debug for StreamEdge {
             EdgeType::ShuffleJoin(0) => "-left→",
             EdgeType::ShuffleJoin(1) => "-right→",
             EdgeType::ShuffleJoin(_) => unimplemented!(),
+            EdgeType::Broadcast => "-broadcast→",
         };
         write!(f, "{} {} {}", self.key, arrow, self.value)
     }
@@ -712,6 +1099,7 @@ impl<T: Data> Stream<T> {
             other_defs: vec![],
             udfs: vec![],
             graph: self.graph.take(),
+            state_schemas: BTreeMap::new(),
         }
     }
 }
@@ -744,7 +1132,10 @@ impl<K: Key, T: Data> TumblingWindow<K, T> {
 impl<K: Key, T: Data> KeyedWindowFun<K, T> for TumblingWindow<K, T> {
     fn as_operator(&self) -> Operator {
         Operator::Window {
-            typ: WindowType::Tumbling { width: self.width },
+            typ: WindowType::Tumbling {
+                width: self.width,
+                alignment: WindowAlignment::default(),
+            },
             agg: None,
             flatten: false,
         }
@@ -773,6 +1164,7 @@ impl<K: Key, T: Data> KeyedWindowFun<K, T> for SlidingWindow<K, T> {
             typ: WindowType::Sliding {
                 width: self.width,
                 slide: self.slide,
+                alignment: WindowAlignment::default(),
             },
             agg: None,
             flatten: false,
@@ -986,12 +1378,69 @@ impl<K: Key, T: Data> KeyedStream<K, T> {
         }
     }
 
+    /// Processes this stream against the latest records of `control`, which is broadcast to
+    /// every subtask; see `BroadcastConnect` for the closures it takes
+    pub fn broadcast_connect<C: Data, OutT: Data>(
+        &mut self,
+        control: Stream<C>,
+        connect: BroadcastConnect,
+    ) -> KeyedStream<K, OutT> {
+        let idx_map = if Rc::ptr_eq(&self.graph, &control.graph) {
+            assert!(self.last_node.unwrap().index() < control.last_node.unwrap().index());
+            None
+        } else {
+            Some(add_all(
+                &mut (*self.graph).borrow_mut(),
+                &(*control.graph).borrow(),
+            ))
+        };
+
+        let connect_node = StreamNode {
+            operator_id: format!("node_{}", (*self.graph).borrow().node_count()),
+            operator: Operator::BroadcastConnect(connect),
+            parallelism: self.parallelism,
+        };
+
+        let new_idx = (*self.graph).borrow_mut().add_node(connect_node);
+
+        let data_edge = StreamEdge {
+            key: std::any::type_name::<K>().to_string(),
+            value: std::any::type_name::<T>().to_string(),
+            typ: EdgeType::ShuffleJoin(0),
+        };
+
+        let control_edge = StreamEdge {
+            key: std::any::type_name::<()>().to_string(),
+            value: std::any::type_name::<C>().to_string(),
+            typ: EdgeType::Broadcast,
+        };
+
+        let control_idx = idx_map
+            .map(|m| *m.get(&control.last_node.unwrap()).unwrap())
+            .or(control.last_node)
+            .unwrap();
+        (*self.graph)
+            .borrow_mut()
+            .add_edge(self.last_node.unwrap(), new_idx, data_edge);
+        (*self.graph)
+            .borrow_mut()
+            .add_edge(control_idx, new_idx, control_edge);
+
+        KeyedStream {
+            _t: PhantomData,
+            graph: self.graph.clone(),
+            last_node: Some(new_idx),
+            parallelism: self.parallelism,
+        }
+    }
+
     pub fn into_program(self) -> Program {
         Program {
             types: vec![],
             other_defs: vec![],
             udfs: vec![],
             graph: self.graph.take(),
+            state_schemas: BTreeMap::new(),
         }
     }
 }
@@ -1083,6 +1532,9 @@ pub struct Program {
     pub other_defs: Vec<String>,
     #[bincode(with_serde)]
     pub graph: DiGraph<StreamNode, StreamEdge>,
+    /// The types of the state of each operator, by operator id, for those that are known
+    #[bincode(with_serde)]
+    pub state_schemas: BTreeMap<String, OperatorStateSchema>,
 }
 
 impl Program {
@@ -1092,6 +1544,7 @@ impl Program {
             other_defs: vec![],
             udfs: vec![],
             graph: s.graph.take(),
+            state_schemas: BTreeMap::new(),
         }
     }
 
@@ -1143,9 +1596,36 @@ impl Program {
                 Operator::JoinWithExpiration { .. } => {
                     s.insert(format!("join with expiration"));
                 }
+                Operator::IntervalJoin { .. } => {
+                    s.insert(format!("interval join"));
+                }
                 Operator::NonWindowAggregator(_) => {
                     s.insert(format!("non-window aggregator"));
                 }
+                Operator::Deduplicate { .. } => {
+                    s.insert(format!("deduplication"));
+                }
+                Operator::LateDataFilter { .. } => {
+                    s.insert(format!("late data filter"));
+                }
+                Operator::OverWindow(_) => {
+                    s.insert(format!("over window"));
+                }
+                Operator::MatchRecognize(_) => {
+                    s.insert(format!("match recognize"));
+                }
+                Operator::LookupJoin(_) => {
+                    s.insert(format!("lookup join"));
+                }
+                Operator::TopN(_) => {
+                    s.insert(format!("top n"));
+                }
+                Operator::AsyncUdf(_) => {
+                    s.insert(format!("async udf"));
+                }
+                Operator::BroadcastConnect(_) => {
+                    s.insert(format!("broadcast connect"));
+                }
                 _ => {}
             }
         }
@@ -1201,7 +1681,13 @@ impl Program {
                 let edge1 = incoming_edges[0];
                 let edge2 = incoming_edges[1];
                 // must either be two sides of a join or multiple direct and shuffle edges with the same type.
-                if let (EdgeType::ShuffleJoin(edge_one), EdgeType::ShuffleJoin(edge_two)) =
+                if matches!(
+                    (&edge1.typ, &edge2.typ),
+                    (EdgeType::ShuffleJoin(0), EdgeType::Broadcast)
+                        | (EdgeType::Broadcast, EdgeType::ShuffleJoin(0))
+                ) {
+                    // a keyed stream connected to a broadcast control stream, whose types may differ
+                } else if let (EdgeType::ShuffleJoin(edge_one), EdgeType::ShuffleJoin(edge_two)) =
                     (&edge1.typ, &edge2.typ)
                 {
                     if edge_one == edge_two {
@@ -1367,7 +1853,7 @@ impl Program {
                     let strukt = parse_type(&c.operator);
                     let config = &c.config;
                     quote! {
-                        Box::new(#strukt::<#out_k, #out_t>::from_config(#config))
+                        Box::new(#strukt::<#out_k, #out_t>::from_config(&arroyo_worker::secrets::resolve(#config)))
                     }
                 }
                 Operator::ConnectorSink(c)  => {
@@ -1378,7 +1864,7 @@ impl Program {
                     let strukt = parse_type(&replaced_type);
                     let config = &c.config;
                     quote! {
-                        Box::new(#strukt::from_config(#config))
+                        Box::new(#strukt::from_config(&arroyo_worker::secrets::resolve(#config)))
                     }
                 }
                 Operator::FusedWasmUDFs { name, udfs: _ } => {
@@ -1423,7 +1909,8 @@ impl Program {
                     };
 
                     match typ {
-                        WindowType::Tumbling { width } => {
+                        WindowType::Tumbling { width, alignment } => {
+                            assert!(alignment.is_default(), "aligned windows are only supported by window aggregators");
                             let width = duration_to_syn_expr(*width);
 
                             quote! {
@@ -1431,7 +1918,8 @@ impl Program {
                                     tumbling_window(#width, #agg))
                             }
                         }
-                        WindowType::Sliding { width, slide } => {
+                        WindowType::Sliding { width, slide, alignment } => {
+                            assert!(alignment.is_default(), "aligned windows are only supported by window aggregators");
                             let width = duration_to_syn_expr(*width);
                             let slide = duration_to_syn_expr(*slide);
 
@@ -1489,6 +1977,12 @@ impl Program {
                                     watermark_function(#period, #idle_time, Box::new(#watermark_function)))
                             }
                         }
+                        WatermarkStrategy::Source => {
+                            quote! {
+                                Box::new(
+                                    PeriodicWatermarkGenerator::<#in_k, #in_t>::source_watermarks())
+                            }
+                        }
                     }
                 }
                 Operator::GlobalKey => {
@@ -1510,14 +2004,16 @@ impl Program {
                     let in_t2 = parse_type(&inputs[1].weight().value);
 
                     match window {
-                        WindowType::Tumbling { width } => {
+                        WindowType::Tumbling { width, alignment } => {
+                            assert!(alignment.is_default(), "aligned windows are not supported in joins");
                             let width = duration_to_syn_expr(*width);
                             quote! {
                                 Box::new(WindowedHashJoin::<#in_k, #in_t1, #in_t2, TumblingWindowAssigner, TumblingWindowAssigner>::
                                     tumbling_window(#width))
                             }
                         }
-                        WindowType::Sliding { width, slide } => {
+                        WindowType::Sliding { width, slide, alignment } => {
+                            assert!(alignment.is_default(), "aligned windows are not supported in joins");
                             let width = duration_to_syn_expr(*width);
                             let slide = duration_to_syn_expr(*slide);
                             quote! {
@@ -1658,7 +2154,7 @@ impl Program {
                     }
                 },
                 Operator::SlidingWindowAggregator(SlidingWindowAggregator{
-                    width,slide,aggregator,bin_merger,
+                    width,slide,alignment,aggregator,bin_merger,
                     in_memory_add,in_memory_remove,bin_type,mem_type}) => {
                     let in_k = parse_type(&input.unwrap().weight().key);
                     let in_t = parse_type(&input.unwrap().weight().value);
@@ -1667,6 +2163,7 @@ impl Program {
                     let mem_t = parse_type(mem_type);
                     let width = duration_to_syn_expr(*width);
                     let slide = duration_to_syn_expr(*slide);
+                    let alignment = alignment.to_syn_expr();
                     let aggregator: syn::ExprClosure = parse_str(aggregator).unwrap();
                     let bin_merger: syn::ExprClosure = parse_str(bin_merger).unwrap();
                     let in_memory_add: syn::ExprClosure = parse_str(in_memory_add).unwrap();
@@ -1676,24 +2173,37 @@ impl Program {
                         Box::new(arroyo_worker::operators::aggregating_window::AggregatingWindowFunc::<#in_k, #in_t, #bin_t, #mem_t, #out_t>::
                             new(#width,
                                 #slide,
+                                #alignment,
                                 #aggregator,
                                 #bin_merger,
                                 #in_memory_add,
                                 #in_memory_remove))
                     }
                 },
-                Operator::TumblingWindowAggregator(TumblingWindowAggregator { width, aggregator, bin_merger, bin_type }) => {
+                Operator::TumblingWindowAggregator(TumblingWindowAggregator { width, alignment, allowed_lateness, emit_partial, aggregator, bin_merger, bin_type }) => {
                     let in_k = parse_type(&input.unwrap().weight().key);
                     let in_t = parse_type(&input.unwrap().weight().value);
                     let out_t = parse_type(&output.unwrap().weight().value);
                     let bin_t = parse_type(bin_type);
                     let width = duration_to_syn_expr(*width);
+                    let alignment = alignment.to_syn_expr();
+                    let allowed_lateness = duration_to_syn_expr(*allowed_lateness);
+                    let emit_partial = match emit_partial {
+                        Some(interval) => {
+                            let interval = duration_to_syn_expr(*interval);
+                            quote!(Some(#interval))
+                        }
+                        None => quote!(None),
+                    };
                     let aggregator: syn::ExprClosure = parse_str(aggregator).unwrap();
                     let bin_merger: syn::ExprClosure = parse_str(bin_merger).unwrap();
                     quote!{
                         Box::new(arroyo_worker::operators::tumbling_aggregating_window::
                             TumblingAggregatingWindowFunc::<#in_k, #in_t, #bin_t, #out_t>::
                         new(#width,
+                            #alignment,
+                            #allowed_lateness,
+                            #emit_partial,
                             #aggregator,
                             #bin_merger))
                     }
@@ -1803,6 +2313,21 @@ impl Program {
                         },
                     }
                 },
+                Operator::IntervalJoin { lower_bound, upper_bound } => {
+                    let mut inputs: Vec<_> = self.graph.edges_directed(idx, Direction::Incoming)
+                        .collect();
+                    inputs.sort_by_key(|e| e.weight().typ.clone());
+                    assert_eq!(2, inputs.len(), "IntervalJoin should have 2 inputs, but has {}", inputs.len());
+                    assert_eq!(inputs[0].weight().key, inputs[1].weight().key, "IntervalJoin inputs must have the same key type");
+
+                    let in_k = parse_type(&inputs[0].weight().key);
+                    let in_t1 = parse_type(&inputs[0].weight().value);
+                    let in_t2 = parse_type(&inputs[1].weight().value);
+                    quote!{
+                        Box::new(arroyo_worker::operators::interval_join::
+                            IntervalJoin::<#in_k, #in_t1, #in_t2>::new(#lower_bound, #upper_bound))
+                    }
+                },
                 Operator::UpdatingOperator { name, expression } => {
                     let expr : syn::Expr = parse_str(expression).expect(expression);
                     let in_k = parse_type(&input.unwrap().weight().key);
@@ -1843,6 +2368,140 @@ impl Program {
                         new(#name.to_string(), #expr))
                     }
                 },
+                Operator::Deduplicate { ttl } => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let ttl = duration_to_syn_expr(*ttl);
+                    quote! {
+                        Box::new(arroyo_worker::operators::deduplicate::
+                            DeduplicateOperator::<#in_k, #in_t>::new(#ttl))
+                    }
+                },
+                Operator::LateDataFilter { width, alignment, allowed_lateness } => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let width = duration_to_syn_expr(*width);
+                    let alignment = alignment.to_syn_expr();
+                    let allowed_lateness = duration_to_syn_expr(*allowed_lateness);
+                    quote! {
+                        Box::new(arroyo_worker::operators::late_data::
+                            LateDataFilter::<#in_k, #in_t>::new(#width, #alignment, #allowed_lateness))
+                    }
+                },
+                Operator::OverWindow(OverWindow { preceding, following, aggregator }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let preceding = frame_bound_to_syn_expr(*preceding);
+                    let following = frame_bound_to_syn_expr(*following);
+                    let aggregator: syn::ExprClosure = parse_str(aggregator).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::over_window::
+                            OverWindowFunc::<#in_k, #in_t, #out_t>::new(#preceding, #following, #aggregator))
+                    }
+                },
+                Operator::MatchRecognize(MatchRecognize { pattern, within, predicate, measures }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let pattern = pattern.iter().map(|(min, max)| match max {
+                        Some(max) => quote!((#min, Some(#max))),
+                        None => quote!((#min, None)),
+                    });
+                    let within = match within {
+                        Some(within) => {
+                            let within = duration_to_syn_expr(*within);
+                            quote!(Some(#within))
+                        }
+                        None => quote!(None),
+                    };
+                    let predicate: syn::ExprClosure = parse_str(predicate).unwrap();
+                    let measures: syn::ExprClosure = parse_str(measures).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::match_recognize::
+                            MatchRecognizeFunc::<#in_k, #in_t, #out_t>::new(vec![#(#pattern),*], #within, #predicate, #measures))
+                    }
+                },
+                Operator::LookupJoin(LookupJoin { connector, key_column, lookup_type, key, merge, cache_size, cache_ttl }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let lookup_t = parse_type(lookup_type);
+                    let source = parse_type(&connector.operator);
+                    let config = &connector.config;
+                    let key: syn::ExprClosure = parse_str(key).unwrap();
+                    let merge: syn::ExprClosure = parse_str(merge).unwrap();
+                    let cache_size = *cache_size as usize;
+                    let cache_ttl = match cache_ttl {
+                        Some(ttl) => {
+                            let ttl = duration_to_syn_expr(*ttl);
+                            quote!(Some(#ttl))
+                        }
+                        None => quote!(None),
+                    };
+                    quote! {
+                        {
+                            let config = arroyo_worker::secrets::resolve(#config);
+                            Box::new(arroyo_worker::operators::lookup_join::
+                                LookupJoinFunc::<#in_k, #in_t, #lookup_t, #out_t>::new(
+                                    &config, Box::new(#source::from_config(&config, #key_column)),
+                                    #key, #merge, #cache_size, #cache_ttl))
+                        }
+                    }
+                },
+                Operator::TopN(TopN { max_elements, expiration, extractor, sort_key_type, converter }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let updating_out_t = parse_type(&output.unwrap().weight().value);
+                    let out_t = extract_container_type("UpdatingData", &updating_out_t).unwrap();
+                    let sk_type = parse_type(sort_key_type);
+                    let expiration = duration_to_syn_expr(*expiration);
+                    let extractor: syn::ExprClosure = parse_str(extractor).unwrap();
+                    let converter: syn::ExprClosure = parse_str(converter).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::top_n::
+                            TopNFunc::<#in_k, #in_t, #sk_type, #out_t>::
+                        new(#max_elements,
+                            #expiration,
+                            #extractor,
+                            #converter))
+                    }
+                },
+                Operator::AsyncUdf(AsyncUdf { name, result_type, call, merge, ordered, max_concurrency, timeout, retries }) => {
+                    let in_k = parse_type(&input.unwrap().weight().key);
+                    let in_t = parse_type(&input.unwrap().weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let result_t = parse_type(result_type);
+                    let call: syn::ExprClosure = parse_str(call).unwrap();
+                    let merge: syn::ExprClosure = parse_str(merge).unwrap();
+                    let max_concurrency = *max_concurrency as usize;
+                    let timeout = duration_to_syn_expr(*timeout);
+                    quote! {
+                        Box::new(arroyo_worker::operators::async_udf::
+                            AsyncUdfFunc::<#in_k, #in_t, #result_t, #out_t>::new(
+                                #name, #call, #merge, #ordered, #max_concurrency, #timeout, #retries))
+                    }
+                },
+                Operator::BroadcastConnect(BroadcastConnect { control_key, process }) => {
+                    let mut inputs: Vec<_> = self.graph.edges_directed(idx, Direction::Incoming)
+                        .collect();
+                    inputs.sort_by_key(|e| e.weight().typ.clone());
+                    assert_eq!(2, inputs.len(), "BroadcastConnect should have 2 inputs, but has {}", inputs.len());
+                    assert_eq!(inputs[1].weight().typ, EdgeType::Broadcast, "BroadcastConnect's control input must be broadcast");
+
+                    let in_k = parse_type(&inputs[0].weight().key);
+                    let in_t = parse_type(&inputs[0].weight().value);
+                    let control_k = parse_type(&inputs[1].weight().key);
+                    let control_t = parse_type(&inputs[1].weight().value);
+                    let out_t = parse_type(&output.unwrap().weight().value);
+                    let control_key: syn::ExprClosure = parse_str(control_key).unwrap();
+                    let process: syn::ExprClosure = parse_str(process).unwrap();
+                    quote! {
+                        Box::new(arroyo_worker::operators::broadcast_connect::
+                            BroadcastConnect::<#in_k, #in_t, #control_k, #control_t, #out_t>::new(
+                                #control_key, #process))
+                    }
+                },
             };
 
             (node.operator_id.clone(), description, body, node.parallelism)
@@ -1891,6 +2550,9 @@ impl Program {
                     EdgeType::ShuffleJoin(order) => {
                         quote! { LogicalEdge::ShuffleJoin(#order) }
                     }
+                    EdgeType::Broadcast => {
+                        quote! { LogicalEdge::Broadcast }
+                    }
                 };
 
                 quote! {
@@ -1956,6 +2618,7 @@ impl TryFrom<Program> for PipelineProgram {
                         EdgeType::Shuffle => GrpcApi::EdgeType::Shuffle,
                         EdgeType::ShuffleJoin(0) => GrpcApi::EdgeType::LeftJoin,
                         EdgeType::ShuffleJoin(1) => GrpcApi::EdgeType::RightJoin,
+                        EdgeType::Broadcast => GrpcApi::EdgeType::Broadcast,
                         _ => todo!(),
                     }
                     .into(),
@@ -1969,6 +2632,11 @@ impl TryFrom<Program> for PipelineProgram {
             udfs: program.udfs,
             nodes,
             edges,
+            state_schemas: program
+                .state_schemas
+                .iter()
+                .map(|(id, schema)| Ok((id.clone(), serde_json::to_string(schema)?)))
+                .collect::<Result<_>>()?,
         })
     }
 }
@@ -2027,6 +2695,15 @@ impl From<Operator> for GrpcApi::operator::Operator {
                             period_micros,
                             max_lateness_micros: max_lateness.as_micros() as u64,
                             idle_time_micros,
+                            source_watermarks: false,
+                        })
+                    }
+                    WatermarkStrategy::Source => {
+                        GrpcOperator::PeriodicWatermark(GrpcApi::PeriodicWatermark {
+                            period_micros,
+                            max_lateness_micros: 0,
+                            idle_time_micros,
+                            source_watermarks: true,
                         })
                     }
                     WatermarkStrategy::Expression { expression } => {
@@ -2067,6 +2744,7 @@ impl From<Operator> for GrpcApi::operator::Operator {
             Operator::SlidingWindowAggregator(SlidingWindowAggregator {
                 width,
                 slide,
+                alignment,
                 aggregator,
                 bin_merger,
                 in_memory_add,
@@ -2076,6 +2754,7 @@ impl From<Operator> for GrpcApi::operator::Operator {
             }) => GrpcOperator::SlidingWindowAggregator(GrpcApi::SlidingWindowAggregator {
                 width_micros: width.as_micros() as u64,
                 slide_micros: slide.as_micros() as u64,
+                alignment: Some(alignment.into()),
                 aggregator,
                 bin_merger,
                 in_memory_add,
@@ -2085,11 +2764,17 @@ impl From<Operator> for GrpcApi::operator::Operator {
             }),
             Operator::TumblingWindowAggregator(TumblingWindowAggregator {
                 width,
+                alignment,
+                allowed_lateness,
+                emit_partial,
                 aggregator,
                 bin_merger,
                 bin_type,
             }) => GrpcOperator::TumblingWindowAggregator(GrpcApi::TumblingWindowAggregator {
                 width_micros: width.as_micros() as u64,
+                alignment: Some(alignment.into()),
+                allowed_lateness_micros: allowed_lateness.as_micros() as u64,
+                emit_partial_micros: emit_partial.map(|e| e.as_micros() as u64),
                 aggregator,
                 bin_merger,
                 bin_type,
@@ -2149,6 +2834,13 @@ impl From<Operator> for GrpcApi::operator::Operator {
                 }
                 .into(),
             }),
+            Operator::IntervalJoin {
+                lower_bound,
+                upper_bound,
+            } => GrpcOperator::IntervalJoin(GrpcApi::IntervalJoin {
+                lower_bound_nanos: lower_bound,
+                upper_bound_nanos: upper_bound,
+            }),
             Operator::UpdatingOperator { name, expression } => {
                 GrpcOperator::UpdatingOperator(GrpcApi::UpdatingOperator { name, expression })
             }
@@ -2166,6 +2858,97 @@ impl From<Operator> for GrpcApi::operator::Operator {
             Operator::UpdatingKeyOperator { name, expression } => {
                 GrpcOperator::UpdatingKeyOperator(GrpcApi::UpdatingKeyOperator { name, expression })
             }
+            Operator::Deduplicate { ttl } => GrpcOperator::Deduplicate(GrpcApi::Deduplicate {
+                ttl_micros: ttl.as_micros() as u64,
+            }),
+            Operator::LateDataFilter {
+                width,
+                alignment,
+                allowed_lateness,
+            } => GrpcOperator::LateDataFilter(GrpcApi::LateDataFilter {
+                width_micros: width.as_micros() as u64,
+                alignment: Some(alignment.into()),
+                allowed_lateness_micros: allowed_lateness.as_micros() as u64,
+            }),
+            Operator::OverWindow(OverWindow {
+                preceding,
+                following,
+                aggregator,
+            }) => GrpcOperator::OverWindow(GrpcApi::OverWindow {
+                preceding: Some(frame_bound_to_grpc(preceding)),
+                following: Some(frame_bound_to_grpc(following)),
+                aggregator,
+            }),
+            Operator::MatchRecognize(MatchRecognize {
+                pattern,
+                within,
+                predicate,
+                measures,
+            }) => GrpcOperator::MatchRecognize(GrpcApi::MatchRecognize {
+                pattern: pattern
+                    .into_iter()
+                    .map(|(min, max)| GrpcApi::PatternElement { min, max })
+                    .collect(),
+                within_micros: within.map(|w| w.as_micros() as u64),
+                predicate,
+                measures,
+            }),
+            Operator::LookupJoin(LookupJoin {
+                connector,
+                key_column,
+                lookup_type,
+                key,
+                merge,
+                cache_size,
+                cache_ttl,
+            }) => GrpcOperator::LookupJoin(GrpcApi::LookupJoin {
+                connector: Some(connector.into()),
+                key_column,
+                lookup_type,
+                key,
+                merge,
+                cache_size,
+                cache_ttl_micros: cache_ttl.map(|t| t.as_micros() as u64),
+            }),
+            Operator::TopN(TopN {
+                max_elements,
+                expiration,
+                extractor,
+                sort_key_type,
+                converter,
+            }) => GrpcOperator::TopN(GrpcApi::TopN {
+                max_elements: max_elements as u64,
+                expiration_micros: expiration.as_micros() as u64,
+                extractor,
+                sort_key_type,
+                converter,
+            }),
+            Operator::AsyncUdf(AsyncUdf {
+                name,
+                result_type,
+                call,
+                merge,
+                ordered,
+                max_concurrency,
+                timeout,
+                retries,
+            }) => GrpcOperator::AsyncUdf(GrpcApi::AsyncUdf {
+                name,
+                result_type,
+                call,
+                merge,
+                ordered,
+                max_concurrency,
+                timeout_micros: timeout.as_micros() as u64,
+                retries,
+            }),
+            Operator::BroadcastConnect(BroadcastConnect {
+                control_key,
+                process,
+            }) => GrpcOperator::BroadcastConnect(GrpcApi::BroadcastConnect {
+                control_key,
+                process,
+            }),
         }
     }
 }
@@ -2212,17 +2995,21 @@ impl From<WasmBehavior> for i32 {
 impl From<WindowType> for GrpcApi::window::Window {
     fn from(window_type: WindowType) -> Self {
         match window_type {
-            WindowType::Tumbling { width } => {
+            WindowType::Tumbling { width, alignment } => {
                 GrpcApi::window::Window::TumblingWindow(GrpcApi::TumblingWindow {
                     size_micros: width.as_micros() as u64,
+                    alignment: Some(alignment.into()),
                 })
             }
-            WindowType::Sliding { width, slide } => {
-                GrpcApi::window::Window::SlidingWindow(GrpcApi::SlidingWindow {
-                    size_micros: width.as_micros() as u64,
-                    slide_micros: slide.as_micros() as u64,
-                })
-            }
+            WindowType::Sliding {
+                width,
+                slide,
+                alignment,
+            } => GrpcApi::window::Window::SlidingWindow(GrpcApi::SlidingWindow {
+                size_micros: width.as_micros() as u64,
+                slide_micros: slide.as_micros() as u64,
+                alignment: Some(alignment.into()),
+            }),
             WindowType::Instant => {
                 GrpcApi::window::Window::InstantWindow(GrpcApi::InstantWindow {})
             }
@@ -2270,11 +3057,18 @@ impl TryFrom<PipelineProgram> for Program {
                 edge.into(),
             );
         }
+        let state_schemas = program
+            .state_schemas
+            .into_iter()
+            .map(|(id, schema)| Ok((id, serde_json::from_str(&schema)?)))
+            .collect::<Result<_>>()?;
+
         Ok(Program {
             types,
             other_defs,
             udfs,
             graph,
+            state_schemas,
         })
     }
 }
@@ -2332,11 +3126,16 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                     period_micros,
                     max_lateness_micros,
                     idle_time_micros,
+                    source_watermarks,
                 }) => Operator::Watermark(PeriodicWatermark {
                     period: Duration::from_micros(period_micros),
                     idle_time: idle_time_micros.map(Duration::from_micros),
-                    strategy: WatermarkStrategy::FixedLateness {
-                        max_lateness: Duration::from_micros(max_lateness_micros),
+                    strategy: if source_watermarks {
+                        WatermarkStrategy::Source
+                    } else {
+                        WatermarkStrategy::FixedLateness {
+                            max_lateness: Duration::from_micros(max_lateness_micros),
+                        }
                     },
                 }),
                 GrpcOperator::ExpressionWatermark(GrpcApi::ExpressionWatermark {
@@ -2374,6 +3173,7 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                 GrpcOperator::SlidingWindowAggregator(GrpcApi::SlidingWindowAggregator {
                     width_micros,
                     slide_micros,
+                    alignment,
                     aggregator,
                     bin_merger,
                     in_memory_add,
@@ -2383,6 +3183,7 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                 }) => Operator::SlidingWindowAggregator(SlidingWindowAggregator {
                     width: Duration::from_micros(width_micros),
                     slide: Duration::from_micros(slide_micros),
+                    alignment: alignment.into(),
                     aggregator,
                     bin_merger,
                     in_memory_add,
@@ -2392,11 +3193,17 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                 }),
                 GrpcOperator::TumblingWindowAggregator(GrpcApi::TumblingWindowAggregator {
                     width_micros,
+                    alignment,
+                    allowed_lateness_micros,
+                    emit_partial_micros,
                     aggregator,
                     bin_merger,
                     bin_type,
                 }) => Operator::TumblingWindowAggregator(TumblingWindowAggregator {
                     width: Duration::from_micros(width_micros),
+                    alignment: alignment.into(),
+                    allowed_lateness: Duration::from_micros(allowed_lateness_micros),
+                    emit_partial: emit_partial_micros.map(Duration::from_micros),
                     aggregator,
                     bin_merger,
                     bin_type,
@@ -2456,6 +3263,13 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                         None => JoinType::Inner,
                     },
                 },
+                GrpcOperator::IntervalJoin(GrpcApi::IntervalJoin {
+                    lower_bound_nanos,
+                    upper_bound_nanos,
+                }) => Operator::IntervalJoin {
+                    lower_bound: lower_bound_nanos,
+                    upper_bound: upper_bound_nanos,
+                },
                 GrpcOperator::UpdatingOperator(GrpcApi::UpdatingOperator { name, expression }) => {
                     Operator::UpdatingOperator { name, expression }
                 }
@@ -2474,6 +3288,98 @@ impl TryFrom<arroyo_rpc::grpc::api::Operator> for Operator {
                     name,
                     expression,
                 }) => Operator::UpdatingKeyOperator { name, expression },
+                GrpcOperator::Deduplicate(GrpcApi::Deduplicate { ttl_micros }) => {
+                    Operator::Deduplicate {
+                        ttl: Duration::from_micros(ttl_micros),
+                    }
+                }
+                GrpcOperator::LateDataFilter(GrpcApi::LateDataFilter {
+                    width_micros,
+                    alignment,
+                    allowed_lateness_micros,
+                }) => Operator::LateDataFilter {
+                    width: Duration::from_micros(width_micros),
+                    alignment: alignment.into(),
+                    allowed_lateness: Duration::from_micros(allowed_lateness_micros),
+                },
+                GrpcOperator::OverWindow(GrpcApi::OverWindow {
+                    preceding,
+                    following,
+                    aggregator,
+                }) => Operator::OverWindow(OverWindow {
+                    preceding: frame_bound_from_grpc(preceding)?,
+                    following: frame_bound_from_grpc(following)?,
+                    aggregator,
+                }),
+                GrpcOperator::MatchRecognize(GrpcApi::MatchRecognize {
+                    pattern,
+                    within_micros,
+                    predicate,
+                    measures,
+                }) => Operator::MatchRecognize(MatchRecognize {
+                    pattern: pattern.into_iter().map(|e| (e.min, e.max)).collect(),
+                    within: within_micros.map(Duration::from_micros),
+                    predicate,
+                    measures,
+                }),
+                GrpcOperator::LookupJoin(GrpcApi::LookupJoin {
+                    connector,
+                    key_column,
+                    lookup_type,
+                    key,
+                    merge,
+                    cache_size,
+                    cache_ttl_micros,
+                }) => Operator::LookupJoin(LookupJoin {
+                    connector: connector
+                        .ok_or_else(|| anyhow!("lookup join is missing its connector"))?
+                        .into(),
+                    key_column,
+                    lookup_type,
+                    key,
+                    merge,
+                    cache_size,
+                    cache_ttl: cache_ttl_micros.map(Duration::from_micros),
+                }),
+                GrpcOperator::TopN(GrpcApi::TopN {
+                    max_elements,
+                    expiration_micros,
+                    extractor,
+                    sort_key_type,
+                    converter,
+                }) => Operator::TopN(TopN {
+                    max_elements: max_elements as usize,
+                    expiration: Duration::from_micros(expiration_micros),
+                    extractor,
+                    sort_key_type,
+                    converter,
+                }),
+                GrpcOperator::AsyncUdf(GrpcApi::AsyncUdf {
+                    name,
+                    result_type,
+                    call,
+                    merge,
+                    ordered,
+                    max_concurrency,
+                    timeout_micros,
+                    retries,
+                }) => Operator::AsyncUdf(AsyncUdf {
+                    name,
+                    result_type,
+                    call,
+                    merge,
+                    ordered,
+                    max_concurrency,
+                    timeout: Duration::from_micros(timeout_micros),
+                    retries,
+                }),
+                GrpcOperator::BroadcastConnect(GrpcApi::BroadcastConnect {
+                    control_key,
+                    process,
+                }) => Operator::BroadcastConnect(BroadcastConnect {
+                    control_key,
+                    process,
+                }),
             },
             None => bail!("unset on operator {:?}", operator),
         };
@@ -2516,11 +3422,13 @@ impl From<arroyo_rpc::grpc::api::Window> for WindowType {
                 WindowType::Sliding {
                     width: Duration::from_micros(sliding_window.size_micros),
                     slide: Duration::from_micros(sliding_window.slide_micros),
+                    alignment: sliding_window.alignment.into(),
                 }
             }
             Some(arroyo_rpc::grpc::api::window::Window::TumblingWindow(tumbling_window)) => {
                 WindowType::Tumbling {
                     width: Duration::from_micros(tumbling_window.size_micros),
+                    alignment: tumbling_window.alignment.into(),
                 }
             }
             Some(arroyo_rpc::grpc::api::window::Window::InstantWindow(_)) => WindowType::Instant,
@@ -2557,6 +3465,7 @@ impl From<arroyo_rpc::grpc::api::ProgramEdge> for StreamEdge {
             arroyo_rpc::grpc::api::EdgeType::Shuffle => EdgeType::Shuffle,
             arroyo_rpc::grpc::api::EdgeType::LeftJoin => EdgeType::ShuffleJoin(0),
             arroyo_rpc::grpc::api::EdgeType::RightJoin => EdgeType::ShuffleJoin(1),
+            arroyo_rpc::grpc::api::EdgeType::Broadcast => EdgeType::Broadcast,
         };
         StreamEdge {
             key: edge.key_type,
@@ -404,6 +404,7 @@ fn impl_stream_node_type(
     };
     let handler_count = handlers.len();
     let mut handle_matchers = vec![];
+    let mut replay_matchers = vec![];
 
     for (i, (in_k, in_t, handle_fn)) in handlers.into_iter().enumerate() {
         let deserialize_error = format!(
@@ -432,10 +433,16 @@ fn impl_stream_node_type(
                 if let arroyo_types::Message::Record(record) = &message {
                     crate::metrics::TaskCounters::MessagesReceived.for_task(&ctx.task_info).inc();
 
+                    if counter.is_in_flight(idx) {
+                        counter.log_in_flight(#i, bincode::encode_to_vec(record, config::standard()).unwrap());
+                    }
+
+                    let start = std::time::Instant::now();
                     Self::#handle_fn(&mut (*self), record, &mut ctx)
                       .instrument(tracing::trace_span!("handle_fn",
                         name, operator_id=task_info.operator_id, subtask_idx=task_info.task_index))
                       .await;
+                    crate::metrics::TaskCounters::BusyTime.for_task(&ctx.task_info).inc_by(start.elapsed().as_micros() as u64);
                 } else {
                     match Self::handle_control_message(&mut (*self), idx, &message, &mut counter, &mut closed, in_partitions, &mut ctx).await {
                         crate::ControlOutcome::Continue => {
@@ -465,7 +472,26 @@ fn impl_stream_node_type(
                     sel.push(s);
                 }
             }
-        })
+        });
+
+        replay_matchers.push(quote! {
+            #i => {
+                let record: arroyo_types::Record<#in_k, #in_t> = bincode::decode_from_slice(&bytes, config::standard())
+                    .expect(#deserialize_error)
+                    .0;
+
+                // keyed records are replayed by the subtask that now owns their key, as their
+                // state may have moved if the job has been rescaled
+                let owned = match &record.key {
+                    Some(key) => ctx.task_info.key_range.contains(&arroyo_state::hash_key(key)),
+                    None => subtask % ctx.task_info.parallelism == ctx.task_info.task_index,
+                };
+
+                if owned {
+                    Self::#handle_fn(&mut (*self), &record, &mut ctx).await;
+                }
+            }
+        });
     }
 
     let handle_body = if handler_count == 0 {
@@ -519,11 +545,23 @@ fn impl_stream_node_type(
                 sel.push(Box::pin(stream));
             }
 
+            // replay the records that were overtaken by the unaligned checkpoint being restored
+            for (subtask, records) in ctx.state.restored_in_flight().await {
+                for (input, bytes) in records {
+                    match input {
+                        #(#replay_matchers
+                        )*
+                        _ => unreachable!()
+                    }
+                }
+            }
+
             let mut blocked = vec![];
             let mut final_message = None;
             #tick_setup
 
             loop {
+                let next_processing_timer = ctx.next_processing_timer();
                 tokio::select! {
                     Some(control_message) = ctx.control_rx.recv() => {
                         match control_message {
@@ -535,6 +573,9 @@ fn impl_stream_node_type(
                             arroyo_rpc::ControlMessage::LoadCompacted { compacted } => {
                                 ctx.load_compacted(compacted).await;
                             }
+                            arroyo_rpc::ControlMessage::QueryState { key, tx } => {
+                                let _ = tx.send(self.query_state(key, &mut ctx).await);
+                            }
                             arroyo_rpc::ControlMessage::NoOp => {}
                         }
                     }
@@ -553,6 +594,9 @@ fn impl_stream_node_type(
                             }
                         }
                     }
+                    _ = crate::process_fn::ProcessFnUtils::sleep_until(next_processing_timer) => {
+                        self.handle_processing_timers_int(&mut ctx).await;
+                    }
                     #tick_case
                 }
             }
@@ -560,6 +604,19 @@ fn impl_stream_node_type(
         }
     };
 
+    let tables = if handler_count == 0 {
+        quote! { self.tables() }
+    } else {
+        quote! {{
+            let mut tables = self.tables();
+            tables.push(arroyo_state::global_table(
+                arroyo_state::IN_FLIGHT_TABLE.to_string(),
+                "records overtaken by unaligned checkpoints",
+            ));
+            tables
+        }}
+    };
+
     defs.push(quote! {
         fn start_fn(
             mut self: Box<Self>,
@@ -586,7 +643,7 @@ fn impl_stream_node_type(
 
             let mut in_qs: Vec<_> = in_qs.into_iter().flatten().collect();
 
-            let tables = self.tables();
+            let tables = #tables;
             tokio::spawn(async move {
                 let mut ctx = crate::engine::Context::<#out_k, #out_t>::new(
                     task_info,
@@ -597,6 +654,7 @@ fn impl_stream_node_type(
                     out_qs,
                     tables,
                 ).await;
+                ctx.restore_processing_timers::<#timer_t>().await;
 
                 Self::on_start(&mut (*self), &mut ctx).await;
 
@@ -644,7 +702,8 @@ fn impl_stream_node_type(
                             idx
                         );
 
-                        if counter.all_clear() {
+                        let first = counter.all_clear();
+                        if first {
                             ctx.control_tx.send(arroyo_rpc::ControlResp::CheckpointEvent(arroyo_rpc::CheckpointEvent {
                                 checkpoint_epoch: t.epoch,
                                 operator_id: ctx.task_info.operator_id.clone(),
@@ -654,7 +713,27 @@ fn impl_stream_node_type(
                             })).await.unwrap();
                         }
 
-                        if counter.mark(idx, &t) {
+                        if t.unaligned && in_partitions > 1 {
+                            // unaligned checkpoints are taken (and the barrier forwarded) at the
+                            // first barrier; the records received on the other inputs until their
+                            // barriers arrive are written to the checkpoint to complete it
+                            let last = counter.mark(idx, &t);
+                            if first {
+                                tracing::debug!(
+                                    "Checkpointing unaligned {}-{}-{}",
+                                    self.name(),
+                                    ctx.task_info.operator_id,
+                                    ctx.task_info.task_index
+                                );
+
+                                // unaligned checkpoints are never stopping checkpoints
+                                let _ = self.checkpoint_int(*t, true, ctx).await;
+                            }
+                            if last {
+                                let in_flight = counter.take_in_flight();
+                                ctx.state.write_in_flight(t.epoch, in_flight).await;
+                            }
+                        } else if counter.mark(idx, &t) {
                             tracing::debug!(
                                 "Checkpointing {}-{}-{}",
                                 self.name(),
@@ -712,6 +791,16 @@ fn impl_stream_node_type(
         async fn checkpoint(&mut self,
             checkpoint_barrier: arroyo_types::CheckpointBarrier,
             ctx: &mut crate::engine::Context<#out_k, #out_t>) -> bool {
+            self.checkpoint_int(checkpoint_barrier, false, ctx).await
+        }
+    });
+
+    defs.push(quote! {
+        #[must_use]
+        async fn checkpoint_int(&mut self,
+            checkpoint_barrier: arroyo_types::CheckpointBarrier,
+            unaligned: bool,
+            ctx: &mut crate::engine::Context<#out_k, #out_t>) -> bool {
 
             crate::process_fn::ProcessFnUtils::send_checkpoint_event(checkpoint_barrier, ctx, arroyo_rpc::grpc::TaskCheckpointEventType::StartedCheckpointing).await;
 
@@ -720,7 +809,11 @@ fn impl_stream_node_type(
             crate::process_fn::ProcessFnUtils::send_checkpoint_event(checkpoint_barrier, ctx, arroyo_rpc::grpc::TaskCheckpointEventType::FinishedOperatorSetup).await;
 
             let watermark = ctx.watermarks.last_present_watermark();
-            ctx.state.checkpoint(checkpoint_barrier, watermark).await;
+            if unaligned {
+                ctx.state.checkpoint_unaligned(checkpoint_barrier, watermark).await;
+            } else {
+                ctx.state.checkpoint(checkpoint_barrier, watermark).await;
+            }
 
             crate::process_fn::ProcessFnUtils::send_checkpoint_event(checkpoint_barrier, ctx, arroyo_rpc::grpc::TaskCheckpointEventType::FinishedSync).await;
 
@@ -747,6 +840,16 @@ fn impl_stream_node_type(
         }
     });
 
+    defs.push(quote! {
+        async fn handle_processing_timers_int(&mut self, ctx: &mut crate::engine::Context<#out_k, #out_t>) {
+            let finished = crate::process_fn::ProcessFnUtils::finished_processing_timers(std::time::SystemTime::now(), ctx).await;
+
+            for (k, tv) in finished {
+                self.handle_processing_timer(k, tv.data, ctx).await;
+            }
+        }
+    });
+
     let mut methods = HashSet::new();
 
     for item in &input.items {
@@ -784,6 +887,12 @@ fn impl_stream_node_type(
         })
     }
 
+    if !methods.contains("handle_processing_timer") {
+        defs.push(quote! {
+            async fn handle_processing_timer(&mut self, key: #out_k, tv: #timer_t, ctx: &mut crate::engine::Context<#out_k, #out_t>) {}
+        })
+    }
+
     if !methods.contains("handle_tick") {
         defs.push(quote! {
             async fn handle_tick(&mut self, tick: u64, ctx: &mut crate::engine::Context<#out_k, #out_t>) {}
@@ -808,6 +917,15 @@ fn impl_stream_node_type(
         })
     }
 
+    if !methods.contains("query_state") {
+        defs.push(quote! {
+            async fn query_state(&mut self, key: String, ctx: &mut crate::engine::Context<#out_k, #out_t>)
+                -> anyhow::Result<arroyo_rpc::StateQueryResp> {
+                anyhow::bail!("the state of {} operators can't be queried", self.name())
+            }
+        });
+    }
+
     if !methods.contains("tables") {
         defs.push(quote! {
             fn tables(&self) -> Vec<arroyo_rpc::grpc::TableDescriptor> {
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("proto/rpc.proto")?;
+    tonic_build::compile_protos("proto/connector.proto")?;
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -10,6 +10,32 @@ pub struct Checkpoint {
     pub finish_time: Option<u64>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SavepointState {
+    InProgress,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Savepoint {
+    pub id: String,
+    pub name: String,
+    pub job_id: String,
+    pub state: SavepointState,
+    pub epoch: Option<u32>,
+    pub created_at: u64,
+    pub finish_time: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SavepointPost {
+    pub name: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum CheckpointSpanType {
@@ -43,3 +69,42 @@ pub struct OperatorCheckpointGroup {
     pub bytes: u64,
     pub subtasks: Vec<SubtaskCheckpointGroup>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct CheckpointStateQueryParams {
+    /// The number of entries of each table to include (defaults to 10)
+    pub sample: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StateEntry {
+    pub timestamp: u64,
+    pub key: serde_json::Value,
+    pub value: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TableState {
+    pub name: String,
+    pub description: String,
+    pub table_type: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub records: u64,
+    pub keys: u64,
+    /// The number of keys that belong to each subtask, for keyed tables
+    pub subtask_keys: Vec<u64>,
+    pub sample: Vec<StateEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorCheckpointState {
+    pub operator_id: String,
+    pub bytes: u64,
+    pub tables: Vec<TableState>,
+}
@@ -1,5 +1,5 @@
-use crate::formats::{Format, Framing};
-use anyhow::bail;
+use crate::formats::{validate_field_path, BadData, Compression, Format, Framing};
+use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use utoipa::{IntoParams, ToSchema};
@@ -31,6 +31,14 @@ pub struct ConnectionProfile {
     pub description: String,
 }
 
+/// Registers a connector that runs as a separate service implementing the remote connector
+/// protocol
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteConnectorPost {
+    pub endpoint: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ConnectionProfilePost {
@@ -44,6 +52,8 @@ pub struct ConnectionProfilePost {
 pub enum ConnectionType {
     Source,
     Sink,
+    /// Tables that are queried by key in lookup joins, rather than read as streams
+    Lookup,
 }
 
 impl Display for ConnectionType {
@@ -51,6 +61,7 @@ impl Display for ConnectionType {
         match self {
             ConnectionType::Source => write!(f, "SOURCE"),
             ConnectionType::Sink => write!(f, "SINK"),
+            ConnectionType::Lookup => write!(f, "LOOKUP"),
         }
     }
 }
@@ -62,6 +73,7 @@ impl TryFrom<String> for ConnectionType {
         match value.to_lowercase().as_str() {
             "source" => Ok(ConnectionType::Source),
             "sink" => Ok(ConnectionType::Sink),
+            "lookup" => Ok(ConnectionType::Lookup),
             _ => Err(format!("Invalid connection type: {}", value)),
         }
     }
@@ -93,11 +105,31 @@ pub struct StructType {
     pub fields: Vec<SourceField>,
 }
 
+/// A fixed-precision decimal, with `precision` digits of which `scale` are after the decimal
+/// point
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DecimalType {
+    pub precision: u8,
+    pub scale: i8,
+}
+
+/// A map, whose keys are strings or integers
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MapType {
+    pub key: Box<SourceField>,
+    pub value: Box<SourceField>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum FieldType {
     Primitive(PrimitiveType),
     Struct(StructType),
+    Decimal(DecimalType),
+    List(Box<SourceField>),
+    Map(MapType),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, PartialEq, Eq)]
@@ -113,6 +145,10 @@ pub struct SourceField {
     pub field_name: String,
     pub field_type: SourceFieldType,
     pub nullable: bool,
+    /// For JSON sources, a JSON pointer or JSONPath expression locating the field's value in the
+    /// record, if it isn't a top-level property with the field's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -129,6 +165,10 @@ pub enum SchemaDefinition {
 pub struct ConnectionSchema {
     pub format: Option<Format>,
     pub framing: Option<Framing>,
+    #[serde(default)]
+    pub bad_data: Option<BadData>,
+    #[serde(default)]
+    pub compression: Option<Compression>,
     pub struct_name: Option<String>,
     pub fields: Vec<SourceField>,
     pub definition: Option<SchemaDefinition>,
@@ -138,6 +178,8 @@ impl ConnectionSchema {
     pub fn try_new(
         format: Option<Format>,
         framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
         struct_name: Option<String>,
         fields: Vec<SourceField>,
         definition: Option<SchemaDefinition>,
@@ -145,6 +187,8 @@ impl ConnectionSchema {
         let s = ConnectionSchema {
             format,
             framing,
+            bad_data,
+            compression,
             struct_name,
             fields,
             definition,
@@ -153,7 +197,26 @@ impl ConnectionSchema {
         s.validate()
     }
 
-    pub fn validate(self) -> anyhow::Result<Self> {
+    pub fn validate(mut self) -> anyhow::Result<Self> {
+        let paths: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| Some((f.field_name.clone(), f.json_path.clone()?)))
+            .collect();
+
+        if !paths.is_empty() {
+            match &mut self.format {
+                Some(Format::Json(json)) if !json.debezium && !json.unstructured => {
+                    for (field, path) in paths {
+                        validate_field_path(&path)
+                            .map_err(|e| anyhow!("invalid path for field '{}': {}", field, e))?;
+                        json.field_paths.insert(field, path);
+                    }
+                }
+                _ => bail!("field paths are only supported for the json format"),
+            }
+        }
+
         match &self.format {
             Some(Format::RawString(_)) => {
                 if self.fields.len() != 1
@@ -163,6 +226,14 @@ impl ConnectionSchema {
                     bail!("raw_string format requires a schema with a single field of type TEXT");
                 }
             }
+            Some(Format::RawBytes(_)) => {
+                if self.fields.len() != 1
+                    || self.fields.get(0).unwrap().field_type.r#type
+                        != FieldType::Primitive(PrimitiveType::Bytes)
+                {
+                    bail!("raw_bytes format requires a schema with a single field of type BYTEA");
+                }
+            }
             _ => {}
         }
 
@@ -217,3 +288,17 @@ pub struct ConfluentSchemaQueryParams {
     pub endpoint: String,
     pub topic: String,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct InferSchemaQueryParams {
+    pub sample_size: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InferredSchema {
+    pub fields: Vec<SourceField>,
+    pub ddl: String,
+    pub messages_sampled: u32,
+}
@@ -9,6 +9,7 @@ pub enum MetricNames {
     MessagesRecv,
     MessagesSent,
     Backpressure,
+    DeserializationErrors,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
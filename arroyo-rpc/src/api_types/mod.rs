@@ -1,10 +1,13 @@
 use crate::api_types::checkpoints::Checkpoint;
 use crate::api_types::checkpoints::OperatorCheckpointGroup;
+use crate::api_types::checkpoints::OperatorCheckpointState;
+use crate::api_types::checkpoints::Savepoint;
 use crate::api_types::connections::ConnectionProfile;
 use crate::api_types::connections::ConnectionTable;
 use crate::api_types::connections::Connector;
 use crate::api_types::metrics::OperatorMetricGroup;
-use crate::api_types::pipelines::{Job, JobLogMessage, Pipeline};
+use crate::api_types::pipelines::{Job, JobLogMessage, Pipeline, PipelineUpdate};
+use crate::api_types::views::View;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
@@ -13,6 +16,7 @@ pub mod connections;
 pub mod metrics;
 pub mod pipelines;
 pub mod udfs;
+pub mod views;
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -31,10 +35,14 @@ pub struct PaginatedCollection<T> {
 #[aliases(
     JobCollection = NonPaginatedCollection<Job>,
     OperatorCheckpointGroupCollection = NonPaginatedCollection<OperatorCheckpointGroup>,
+    OperatorCheckpointStateCollection = NonPaginatedCollection<OperatorCheckpointState>,
     CheckpointCollection = NonPaginatedCollection<Checkpoint>,
     OperatorMetricGroupCollection = NonPaginatedCollection<OperatorMetricGroup>,
     ConnectorCollection = NonPaginatedCollection<Connector>,
     ConnectionProfileCollection = NonPaginatedCollection<ConnectionProfile>,
+    ViewCollection = NonPaginatedCollection<View>,
+    SavepointCollection = NonPaginatedCollection<Savepoint>,
+    PipelineUpdateCollection = NonPaginatedCollection<PipelineUpdate>,
 )]
 pub struct NonPaginatedCollection<T> {
     pub data: Vec<T>,
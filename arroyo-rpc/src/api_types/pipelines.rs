@@ -2,7 +2,9 @@ use crate::api_types::udfs::Udf;
 use crate::grpc as grpc_proto;
 use crate::grpc::api as api_proto;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -18,6 +20,39 @@ pub struct QueryValidationResult {
     pub errors: Option<Vec<String>>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryExplanationResult {
+    pub explanation: Option<PipelineExplanation>,
+    pub errors: Option<Vec<String>>,
+    /// For `EXPLAIN ANALYZE` queries, the preview pipeline started to count each operator's rows
+    pub pipeline_id: Option<String>,
+    /// The job of the preview pipeline, whose explanation includes the row counts
+    pub job_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineExplanation {
+    // whether the query asked for row counts with EXPLAIN ANALYZE, which are only filled in for
+    // the run it started
+    pub analyze: bool,
+    pub logical_plans: Vec<String>,
+    pub nodes: Vec<ExplainNode>,
+    pub edges: Vec<PipelineEdge>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainNode {
+    pub node_id: String,
+    pub operator: String,
+    pub parallelism: u32,
+    pub state: Option<String>,
+    pub rows_in: Option<u64>,
+    pub rows_out: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PipelinePost {
@@ -26,6 +61,48 @@ pub struct PipelinePost {
     pub udfs: Option<Vec<Udf>>,
     pub preview: Option<bool>,
     pub parallelism: u64,
+    /// The name of a savepoint to start the pipeline's job from
+    pub savepoint: Option<String>,
+    /// Where to store the pipeline's checkpoints (like s3://bucket/path, gs://bucket/path,
+    /// abfs://container@account.dfs.core.windows.net/path or a local or NFS-mounted directory),
+    /// overriding the cluster's checkpoint URL
+    pub checkpoint_url: Option<String>,
+    pub worker_pod: Option<WorkerPodConfig>,
+    /// Where the pipeline's operators keep their state between checkpoints; defaults to memory
+    pub state_backend: Option<StateBackendType>,
+}
+
+/// Where a pipeline's operators keep their state between checkpoints. Both are checkpointed the
+/// same way, so a pipeline can switch between them when it's restarted.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StateBackendType {
+    /// In memory, which is the fastest, but limits the state of each task to its worker's memory
+    #[default]
+    Memory,
+    /// In a RocksDB database on the worker's disk, for keyed state that doesn't fit in memory
+    Rocksdb,
+}
+
+impl Display for StateBackendType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateBackendType::Memory => write!(f, "memory"),
+            StateBackendType::Rocksdb => write!(f, "rocksdb"),
+        }
+    }
+}
+
+impl TryFrom<String> for StateBackendType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "memory" => Ok(StateBackendType::Memory),
+            "rocksdb" => Ok(StateBackendType::Rocksdb),
+            _ => Err(format!("Invalid state backend: {}", value)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -34,6 +111,102 @@ pub struct PipelinePatch {
     pub parallelism: Option<u64>,
     pub checkpoint_interval_micros: Option<u64>,
     pub stop: Option<StopType>,
+    pub autoscaling: Option<AutoscalingConfig>,
+    pub restart_policy: Option<RestartPolicy>,
+    /// Takes effect the next time the pipeline's workers are started, like when it's restarted
+    pub worker_pod: Option<WorkerPodConfig>,
+}
+
+/// Lets the controller rescale a running pipeline according to its load, based on how busy its
+/// operators are, backpressure and how far behind its sources are reading
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoscalingConfig {
+    pub enabled: bool,
+    pub min_parallelism: u64,
+    pub max_parallelism: u64,
+}
+
+/// How the controller restarts a pipeline's job when it fails, and when it gives up and moves the
+/// job to Failed instead
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartPolicy {
+    FixedDelay(FixedDelayRestart),
+    ExponentialBackoff(ExponentialBackoffRestart),
+    FailureRate(FailureRateRestart),
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::FixedDelay(FixedDelayRestart {
+            max_restarts: 10,
+            delay_millis: 0,
+        })
+    }
+}
+
+/// Restarts the job after the same delay each time, failing it after `max_restarts` restarts in
+/// a row without the job becoming healthy in between
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FixedDelayRestart {
+    pub max_restarts: u32,
+    pub delay_millis: u64,
+}
+
+/// Restarts the job after a delay that doubles with each restart in a row, up to
+/// `max_delay_millis`, failing it after `max_restarts` restarts in a row
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExponentialBackoffRestart {
+    pub max_restarts: u32,
+    pub initial_delay_millis: u64,
+    pub max_delay_millis: u64,
+}
+
+/// Restarts the job after a fixed delay, failing it once it has failed more than
+/// `max_failures` times within `interval_millis`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureRateRestart {
+    pub max_failures: u32,
+    pub interval_millis: u64,
+    pub delay_millis: u64,
+}
+
+/// How the pods running a pipeline's workers are configured, when running on Kubernetes. Unset
+/// fields take the cluster's worker configuration.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerPodConfig {
+    /// The number of task slots each pod has, which its resources are for
+    pub slots_per_pod: Option<u32>,
+    pub resources: Option<WorkerResources>,
+    pub node_selector: Option<BTreeMap<String, String>>,
+    pub tolerations: Option<Vec<Toleration>>,
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// The compute resources of a worker pod, as Kubernetes quantities (like 500m or 2Gi)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerResources {
+    pub cpu_request: Option<String>,
+    pub cpu_limit: Option<String>,
+    pub memory_request: Option<String>,
+    pub memory_limit: Option<String>,
+}
+
+/// A Kubernetes toleration, allowing worker pods to be scheduled onto nodes with matching taints
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Toleration {
+    pub key: Option<String>,
+    pub operator: Option<String>,
+    pub value: Option<String>,
+    pub effect: Option<String>,
+    pub toleration_seconds: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -42,6 +215,50 @@ pub struct PipelineRestart {
     pub force: Option<bool>,
 }
 
+/// A new version of a running pipeline, which is started from a savepoint of the current
+/// version and runs alongside it, with its sinks replaced by web sinks, until it has caught up
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineUpdatePost {
+    pub query: String,
+    pub udfs: Option<Vec<Udf>>,
+    pub parallelism: u64,
+    /// How far behind the current time the new version's sources may be reading for it to count
+    /// as caught up (defaults to 10 seconds)
+    pub max_lag_millis: Option<u64>,
+    /// Wait for the update to be promoted once the new version has caught up, so that its output
+    /// can be compared with the current version's before it replaces it
+    pub compare_outputs: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineUpdateState {
+    Savepointing,
+    CatchingUp,
+    AwaitingPromotion,
+    Switching,
+    Finished,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineUpdate {
+    pub id: String,
+    pub pipeline_id: String,
+    pub job_id: String,
+    /// The pipeline running the new version, which keeps running once the update has finished
+    pub new_pipeline_id: String,
+    pub new_job_id: String,
+    pub savepoint: String,
+    pub max_lag_millis: u64,
+    pub compare_outputs: bool,
+    pub state: PipelineUpdateState,
+    pub created_at: u64,
+    pub failure_message: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Pipeline {
@@ -57,6 +274,9 @@ pub struct Pipeline {
     pub action_in_progress: bool,
     pub graph: PipelineGraph,
     pub preview: bool,
+    pub autoscaling: Option<AutoscalingConfig>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub worker_pod: Option<WorkerPodConfig>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -178,3 +398,19 @@ impl From<grpc_proto::OutputData> for OutputData {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "snake_case")]
+pub struct OperatorStateQueryParams {
+    /// The key to look up, as JSON
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OperatorState {
+    pub operator_id: String,
+    pub key: serde_json::Value,
+    pub value: Option<serde_json::Value>,
+}
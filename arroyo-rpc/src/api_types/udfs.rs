@@ -6,12 +6,14 @@ use utoipa::ToSchema;
 #[serde(rename_all = "camelCase")]
 pub enum UdfLanguage {
     Rust,
+    Wasm,
 }
 
 impl ToString for UdfLanguage {
     fn to_string(&self) -> String {
         match self {
             UdfLanguage::Rust => "rust".to_string(),
+            UdfLanguage::Wasm => "wasm".to_string(),
         }
     }
 }
@@ -20,6 +22,16 @@ impl From<api_proto::UdfLanguage> for UdfLanguage {
     fn from(value: api_proto::UdfLanguage) -> Self {
         match value {
             api_proto::UdfLanguage::Rust => UdfLanguage::Rust,
+            api_proto::UdfLanguage::Wasm => UdfLanguage::Wasm,
+        }
+    }
+}
+
+impl From<UdfLanguage> for api_proto::UdfLanguage {
+    fn from(value: UdfLanguage) -> Self {
+        match value {
+            UdfLanguage::Rust => api_proto::UdfLanguage::Rust,
+            UdfLanguage::Wasm => api_proto::UdfLanguage::Wasm,
         }
     }
 }
@@ -28,6 +40,7 @@ impl From<String> for UdfLanguage {
     fn from(value: String) -> Self {
         match value.to_lowercase().as_str() {
             "rust" => UdfLanguage::Rust,
+            "wasm" => UdfLanguage::Wasm,
             _ => panic!("Invalid UDF language: {}", value),
         }
     }
@@ -46,6 +59,8 @@ impl From<api_proto::Udf> for Udf {
 #[serde(rename_all = "camelCase")]
 pub struct Udf {
     pub language: UdfLanguage,
+    /// The source of a Rust UDF, or a base64-encoded WebAssembly module whose exported functions
+    /// are each a UDF
     pub definition: String,
 }
 
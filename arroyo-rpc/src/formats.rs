@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use utoipa::ToSchema;
 
@@ -45,6 +45,27 @@ pub struct JsonFormat {
 
     #[serde(default)]
     pub timestamp_format: TimestampFormat,
+
+    /// Fields whose values are read from elsewhere in the record, given as a JSON pointer (like
+    /// `/user/id`) or a JSONPath expression (like `$.user.id`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub field_paths: BTreeMap<String, String>,
+}
+
+/// Checks that `path` is either a JSON pointer or a valid JSONPath expression
+pub fn validate_field_path(path: &str) -> Result<(), String> {
+    if path.is_empty() || path.starts_with('/') {
+        Ok(())
+    } else if path.starts_with('$') {
+        serde_json_path::JsonPath::parse(path)
+            .map(|_| ())
+            .map_err(|e| format!("invalid JSONPath '{}': {}", path, e))
+    } else {
+        Err(format!(
+            "invalid path '{}'; expected a JSON pointer starting with '/' or a JSONPath starting with '$'",
+            path
+        ))
+    }
 }
 
 impl JsonFormat {
@@ -77,12 +98,33 @@ impl JsonFormat {
                 }
             });
 
+        // paths are set per field, as `json.path.<field> = '<path>'`
+        let path_options: Vec<_> = opts
+            .keys()
+            .filter(|k| k.starts_with("json.path."))
+            .cloned()
+            .collect();
+
+        let mut field_paths = BTreeMap::new();
+        for option in path_options {
+            let path = opts.remove(&option).unwrap();
+            validate_field_path(&path)?;
+            field_paths.insert(option["json.path.".len()..].to_string(), path);
+        }
+
+        if !field_paths.is_empty() && (debezium || unstructured) {
+            return Err(
+                "json.path options can't be used with debezium or unstructured JSON".to_string(),
+            );
+        }
+
         Ok(Self {
             confluent_schema_registry,
             include_schema,
             debezium,
             unstructured,
             timestamp_format,
+            field_paths,
         })
     }
 }
@@ -93,19 +135,161 @@ pub struct RawStringFormat {}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "camelCase")]
-pub struct AvroFormat {}
+pub struct RawBytesFormat {}
+
+#[derive(
+    Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default, Hash, PartialOrd, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AvroFormat {
+    #[serde(default)]
+    pub confluent_schema_registry: bool,
+    /// The writer schema for messages that aren't framed with a schema registry id; if not set,
+    /// a schema is generated from the table's fields
+    #[serde(default)]
+    pub schema: Option<String>,
+}
+
+impl AvroFormat {
+    fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let confluent_schema_registry = opts
+            .remove("avro.confluent_schema_registry")
+            .filter(|t| t == "true")
+            .is_some();
+
+        let schema = opts.remove("avro.schema");
+
+        if confluent_schema_registry && schema.is_some() {
+            return Err(
+                "avro.schema can't be set when avro.confluent_schema_registry = 'true'".to_string(),
+            );
+        }
+
+        if let Some(schema) = &schema {
+            apache_avro::Schema::parse_str(schema)
+                .map_err(|e| format!("invalid avro.schema: {}", e))?;
+        }
+
+        Ok(Self {
+            confluent_schema_registry,
+            schema,
+        })
+    }
+}
+
+#[derive(
+    Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default, Hash, PartialOrd, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtobufFormat {
+    #[serde(default)]
+    pub confluent_schema_registry: bool,
+}
+
+impl ProtobufFormat {
+    fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let confluent_schema_registry = opts
+            .remove("protobuf.confluent_schema_registry")
+            .filter(|t| t == "true")
+            .is_some();
+
+        if !confluent_schema_registry {
+            return Err("protobuf is currently only supported with protobuf.confluent_schema_registry = 'true'".to_string());
+        }
+
+        Ok(Self {
+            confluent_schema_registry,
+        })
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ParquetFormat {}
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvFormat {
+    pub delimiter: char,
+    pub quote: char,
+    /// Whether each message starts with a header row naming its columns; without one, columns
+    /// are matched to fields by position
+    pub header: bool,
+    /// The value that represents null
+    pub null_value: String,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            header: false,
+            null_value: String::new(),
+        }
+    }
+}
+
+impl CsvFormat {
+    fn from_opts(delimiter: char, opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let parse_char = |name: &str, value: String| -> Result<char, String> {
+            let c = match value.as_str() {
+                "\\t" | "tab" => '\t',
+                _ => {
+                    let mut chars = value.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => return Err(format!("{} must be a single character", name)),
+                    }
+                }
+            };
+
+            if !c.is_ascii() || c == '\n' || c == '\r' {
+                return Err(format!(
+                    "{} must be an ASCII character other than a newline",
+                    name
+                ));
+            }
+            Ok(c)
+        };
+
+        let delimiter = opts
+            .remove("csv.delimiter")
+            .map(|d| parse_char("csv.delimiter", d))
+            .transpose()?
+            .unwrap_or(delimiter);
+
+        let quote = opts
+            .remove("csv.quote")
+            .map(|q| parse_char("csv.quote", q))
+            .transpose()?
+            .unwrap_or('"');
+
+        if delimiter == quote {
+            return Err("csv.delimiter and csv.quote must be different characters".to_string());
+        }
+
+        let header = opts.remove("csv.header").filter(|t| t == "true").is_some();
+
+        Ok(Self {
+            delimiter,
+            quote,
+            header,
+            null_value: opts.remove("csv.null_value").unwrap_or_default(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Format {
     Json(JsonFormat),
     Avro(AvroFormat),
+    Protobuf(ProtobufFormat),
     Parquet(ParquetFormat),
     RawString(RawStringFormat),
+    RawBytes(RawBytesFormat),
+    Csv(CsvFormat),
 }
 
 impl Format {
@@ -117,18 +301,47 @@ impl Format {
         Ok(Some(match name.as_str() {
             "json" => Format::Json(JsonFormat::from_opts(false, opts)?),
             "debezium_json" => Format::Json(JsonFormat::from_opts(true, opts)?),
-            "protobuf" => return Err("protobuf is not yet supported".to_string()),
-            "avro" => return Err("avro is not yet supported".to_string()),
+            "protobuf" => Format::Protobuf(ProtobufFormat::from_opts(opts)?),
+            "avro" => Format::Avro(AvroFormat::from_opts(opts)?),
             "raw_string" => Format::RawString(RawStringFormat {}),
+            "raw_bytes" => Format::RawBytes(RawBytesFormat {}),
             "parquet" => Format::Parquet(ParquetFormat {}),
+            "csv" => Format::Csv(CsvFormat::from_opts(',', opts)?),
+            "tsv" => Format::Csv(CsvFormat::from_opts('\t', opts)?),
             f => return Err(format!("Unknown format '{}'", f)),
         }))
     }
 
+    /// Whether messages are framed with the id of their schema in a Confluent Schema Registry
+    pub fn uses_schema_registry(&self) -> bool {
+        match self {
+            Format::Json(JsonFormat {
+                confluent_schema_registry,
+                ..
+            })
+            | Format::Avro(AvroFormat {
+                confluent_schema_registry,
+                ..
+            })
+            | Format::Protobuf(ProtobufFormat {
+                confluent_schema_registry,
+            }) => *confluent_schema_registry,
+            Format::Parquet(_) | Format::RawString(_) | Format::RawBytes(_) | Format::Csv(_) => {
+                false
+            }
+        }
+    }
+
     pub fn is_updating(&self) -> bool {
         match self {
             Format::Json(JsonFormat { debezium: true, .. }) => true,
-            Format::Json(_) | Format::Avro(_) | Format::Parquet(_) | Format::RawString(_) => false,
+            Format::Json(_)
+            | Format::Avro(_)
+            | Format::Protobuf(_)
+            | Format::Parquet(_)
+            | Format::RawString(_)
+            | Format::RawBytes(_)
+            | Format::Csv(_) => false,
         }
     }
 }
@@ -147,6 +360,9 @@ impl Framing {
 
         let method = match method.as_str() {
             "newline" => FramingMethod::Newline(NewlineDelimitedFraming::from_opts(opts)?),
+            "length_prefixed" => {
+                FramingMethod::LengthPrefixed(LengthPrefixedFraming::from_opts(opts)?)
+            }
             f => return Err(format!("Unknown framing method '{}'", f)),
         };
 
@@ -154,6 +370,96 @@ impl Framing {
     }
 }
 
+/// Compression applied to whole message payloads; sources decompress messages before they're
+/// framed and deserialized, and sinks compress each message they write
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    /// Snappy, using the framing format
+    Snappy,
+}
+
+impl Compression {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let Some(compression) = opts.remove("compression") else {
+            return Ok(None);
+        };
+
+        Ok(match compression.as_str() {
+            "none" => None,
+            "gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            "snappy" => Some(Compression::Snappy),
+            c => {
+                return Err(format!(
+                    "Unknown compression '{}'; expected one of 'none', 'gzip', 'zstd' or 'snappy'",
+                    c
+                ))
+            }
+        })
+    }
+}
+
+/// How sources handle messages that can't be deserialized
+#[derive(
+    Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default, Hash, PartialOrd, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BadData {
+    /// Fail the pipeline with an error
+    #[default]
+    Fail {},
+    /// Skip the message
+    Skip {},
+    /// Skip the message, writing it and the error to a dead letter queue
+    Route(DeadLetterQueue),
+}
+
+impl BadData {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let Some(policy) = opts.remove("bad_data") else {
+            return Ok(None);
+        };
+
+        Ok(Some(match policy.as_str() {
+            "fail" => BadData::Fail {},
+            "skip" => BadData::Skip {},
+            "route" => BadData::Route(DeadLetterQueue::from_opts(opts)?),
+            p => {
+                return Err(format!(
+                    "Unknown bad_data policy '{}'; expected one of 'fail', 'skip' or 'route'",
+                    p
+                ))
+            }
+        }))
+    }
+}
+
+/// A Kafka topic that messages which couldn't be deserialized are written to, with headers
+/// describing the error
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterQueue {
+    pub bootstrap_servers: String,
+    pub topic: String,
+}
+
+impl DeadLetterQueue {
+    fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let mut pull = |name: &str| {
+            opts.remove(name)
+                .ok_or_else(|| format!("'{}' must be set when bad_data is 'route'", name))
+        };
+
+        Ok(Self {
+            bootstrap_servers: pull("bad_data.bootstrap_servers")?,
+            topic: pull("bad_data.topic")?,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NewlineDelimitedFraming {
@@ -174,8 +480,64 @@ impl NewlineDelimitedFraming {
     }
 }
 
+/// Frames that each start with their length as an unsigned integer, as written by many binary
+/// protocols to batch several records into one message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LengthPrefixedFraming {
+    /// The size of the length prefix in bytes; one of 1, 2, 4 or 8
+    pub length_bytes: u8,
+    pub little_endian: bool,
+}
+
+impl Default for LengthPrefixedFraming {
+    fn default() -> Self {
+        Self {
+            length_bytes: 4,
+            little_endian: false,
+        }
+    }
+}
+
+impl LengthPrefixedFraming {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Self, String> {
+        let mut framing = Self::default();
+
+        if let Some(length_bytes) = opts.remove("framing.length_prefixed.bytes") {
+            framing.length_bytes = match length_bytes.as_str() {
+                "1" => 1,
+                "2" => 2,
+                "4" => 4,
+                "8" => 8,
+                _ => {
+                    return Err(format!(
+                        "invalid value for framing.length_prefixed.bytes '{}'; must be one of 1, 2, 4 or 8",
+                        length_bytes
+                    ))
+                }
+            };
+        }
+
+        if let Some(endianness) = opts.remove("framing.length_prefixed.endianness") {
+            framing.little_endian = match endianness.as_str() {
+                "big" => false,
+                "little" => true,
+                _ => {
+                    return Err(format!(
+                        "invalid value for framing.length_prefixed.endianness '{}'; must be 'big' or 'little'",
+                        endianness
+                    ))
+                }
+            };
+        }
+
+        Ok(framing)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum FramingMethod {
     Newline(NewlineDelimitedFraming),
+    LengthPrefixed(LengthPrefixedFraming),
 }
@@ -2,10 +2,10 @@ pub mod api_types;
 pub mod formats;
 pub mod public_ids;
 
-use std::{fs, time::SystemTime};
+use std::{collections::HashMap, fs, time::SystemTime};
 
-use crate::api_types::connections::PrimitiveType;
-use crate::formats::{Format, Framing};
+use crate::api_types::connections::{FieldType, PrimitiveType};
+use crate::formats::{BadData, Compression, Format, Framing};
 use crate::grpc::{LoadCompactedDataReq, SubtaskCheckpointMetadata};
 use arroyo_types::CheckpointBarrier;
 use grpc::{StopMode, TaskCheckpointEventType};
@@ -25,6 +25,11 @@ pub mod grpc {
         tonic::include_proto!("arroyo_api");
     }
 
+    pub mod connector {
+        #![allow(clippy::derive_partial_eq_without_eq)]
+        tonic::include_proto!("arroyo_connector");
+    }
+
     pub const API_FILE_DESCRIPTOR_SET: &[u8] =
         tonic::include_file_descriptor_set!("api_descriptor");
 }
@@ -32,12 +37,32 @@ pub mod grpc {
 #[derive(Debug)]
 pub enum ControlMessage {
     Checkpoint(CheckpointBarrier),
-    Stop { mode: StopMode },
-    Commit { epoch: u32 },
-    LoadCompacted { compacted: CompactionResult },
+    Stop {
+        mode: StopMode,
+    },
+    Commit {
+        epoch: u32,
+    },
+    LoadCompacted {
+        compacted: CompactionResult,
+    },
+    /// Asks an operator for its state for a key, given as JSON
+    QueryState {
+        key: String,
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<StateQueryResp>>,
+    },
     NoOp,
 }
 
+/// A subtask's answer to a [`ControlMessage::QueryState`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateQueryResp {
+    /// The subtask's state for the key, as JSON, if it has any
+    Value(Option<String>),
+    /// The key belongs to the operator's subtask with this index, which should be asked instead
+    OwnedBy(usize),
+}
+
 #[derive(Debug, Clone)]
 pub struct CompactionResult {
     pub operator_id: String,
@@ -145,9 +170,103 @@ pub fn primitive_to_sql(primitive_type: PrimitiveType) -> &'static str {
     }
 }
 
+/// The SQL type of a field, or None for structs, which can't be declared in SQL
+pub fn field_type_to_sql(field_type: &FieldType) -> Option<String> {
+    match field_type {
+        FieldType::Primitive(p) => Some(primitive_to_sql(p.clone()).to_string()),
+        FieldType::Decimal(d) => Some(format!("DECIMAL({}, {})", d.precision, d.scale)),
+        FieldType::List(item) => Some(format!("{}[]", field_type_to_sql(&item.field_type.r#type)?)),
+        FieldType::Map(map) => Some(format!(
+            "MAP({}, {})",
+            field_type_to_sql(&map.key.field_type.r#type)?,
+            field_type_to_sql(&map.value.field_type.r#type)?
+        )),
+        FieldType::Struct(_) => None,
+    }
+}
+
+/// Limits on how quickly each subtask of a source reads from its connection
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RateLimit {
-    pub messages_per_second: u32,
+    #[serde(default)]
+    pub messages_per_second: Option<u32>,
+    #[serde(default)]
+    pub bytes_per_second: Option<u32>,
+}
+
+impl RateLimit {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        let mut limit = |key: &str| {
+            opts.remove(key)
+                .map(|v| match v.parse::<u32>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!("'{}' must be a positive integer, not '{}'", key, v)),
+                })
+                .transpose()
+        };
+
+        let messages_per_second = limit("rate_limit.messages_per_second")?;
+        let bytes_per_second = limit("rate_limit.bytes_per_second")?;
+
+        if messages_per_second.is_none() && bytes_per_second.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(RateLimit {
+            messages_per_second,
+            bytes_per_second,
+        }))
+    }
+}
+
+/// Watermarks that a source generates for each partition that it reads, rather than for the
+/// stream as a whole, so that a partition that falls behind holds the watermark back and one
+/// that stops receiving messages doesn't
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PartitionWatermarks {
+    pub period_micros: u64,
+    pub max_out_of_orderness_micros: u64,
+    /// Partitions without messages for this long are ignored until they receive another
+    pub idle_timeout_micros: Option<u64>,
+    #[serde(default)]
+    pub alignment: Option<WatermarkAlignment>,
+}
+
+/// Keeps the watermarks of the sources in a group within `max_drift_micros` of each other by
+/// pausing any source that gets further than that ahead of the slowest, so that joins and windows
+/// over them don't have to buffer the difference
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WatermarkAlignment {
+    pub group: String,
+    pub max_drift_micros: u64,
+}
+
+/// How a sink writes the output of a query, set by the `sink.mode` option
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkMode {
+    /// Every row is written once, so updating queries can't be written
+    Append,
+    /// Rows are written as upserts of their key, and retracted rows as deletes of it
+    Upsert,
+    /// Every change is written as a Debezium message, with the rows before and after it
+    Retract,
+}
+
+impl SinkMode {
+    pub fn from_opts(opts: &mut HashMap<String, String>) -> Result<Option<Self>, String> {
+        opts.remove("sink.mode")
+            .map(|mode| match mode.as_str() {
+                "append" => Ok(SinkMode::Append),
+                "upsert" => Ok(SinkMode::Upsert),
+                "retract" => Ok(SinkMode::Retract),
+                other => Err(format!(
+                    "unknown sink mode '{}', expected 'append', 'upsert' or 'retract'",
+                    other
+                )),
+            })
+            .transpose()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -157,4 +276,10 @@ pub struct OperatorConfig {
     pub format: Option<Format>,
     pub framing: Option<Framing>,
     pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub partition_watermarks: Option<PartitionWatermarks>,
+    #[serde(default)]
+    pub bad_data: Option<BadData>,
+    #[serde(default)]
+    pub compression: Option<Compression>,
 }
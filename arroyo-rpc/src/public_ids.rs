@@ -21,6 +21,9 @@ pub enum IdTypes {
     JobLogMessage,
     ConnectionTable,
     ConnectionTablePipeline,
+    View,
+    Savepoint,
+    PipelineUpdate,
 }
 
 pub fn generate_id(id_type: IdTypes) -> String {
@@ -36,6 +39,9 @@ pub fn generate_id(id_type: IdTypes) -> String {
         IdTypes::JobLogMessage => "jlm",
         IdTypes::ConnectionTable => "ct",
         IdTypes::ConnectionTablePipeline => "ctp",
+        IdTypes::View => "vw",
+        IdTypes::Savepoint => "sp",
+        IdTypes::PipelineUpdate => "pu",
     };
     let id = nanoid!(ID_LENGTH, &ALPHABET);
     format!("{}_{}", prefix, id)
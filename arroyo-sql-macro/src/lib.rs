@@ -164,6 +164,7 @@ fn get_pipeline_module(
         schema_provider,
         SqlConfig {
             default_parallelism: 1,
+            ..SqlConfig::default()
         },
     )
     .unwrap();
@@ -306,6 +306,80 @@ from demo_stream
 group by window;
 "}
 
+full_pipeline_codegen! {"sketch_aggregates",
+"select
+  hop(interval '10 seconds', interval '30 seconds') as window,
+  approx_count_distinct(bid.auction) as auctions,
+  hll_sketch_estimate(hll_sketch_agg(bid.url)) as urls
+from nexmark
+group by window;
+"}
+
+full_pipeline_codegen! {"approx_percentiles",
+"select
+  hop(interval '10 seconds', interval '30 seconds') as window,
+  approx_percentile_cont(bid.price, 0.95) as p95,
+  approx_median(bid.price) as median
+from nexmark
+group by window;
+"}
+
+full_pipeline_codegen! {"updating_approx_median",
+"select bid.auction, approx_median(bid.price) as median
+from nexmark
+group by 1;
+"}
+
+full_pipeline_codegen! {"array_agg",
+"select
+  hop(interval '10 seconds', interval '30 seconds') as window,
+  bid.auction as auction,
+  array_agg(bid.price) as prices,
+  cardinality(array_agg(bid.extra)) as extras
+from nexmark
+where bid is not null
+group by 1, 2;
+"}
+
+full_pipeline_codegen! {"map_functions",
+"CREATE TABLE events (
+  id BIGINT NOT NULL,
+  counts MAP(TEXT, BIGINT),
+  labels TEXT[]
+) WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'source',
+  topic = 'events',
+  format = 'json'
+);
+
+SELECT id, counts, map_keys(counts) as keys, map_values(counts) as values,
+  element_at(counts, 'clicks') as clicks, cardinality(labels) as label_count, labels[1] as first_label
+FROM events;
+"}
+
+full_pipeline_codegen! {"decimals",
+"CREATE TABLE trades (
+  symbol TEXT NOT NULL,
+  price DECIMAL(10, 2) NOT NULL,
+  quantity BIGINT NOT NULL,
+  fee DECIMAL(6, 4)
+) WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'source',
+  topic = 'trades',
+  format = 'json'
+);
+
+SELECT symbol, price * quantity as notional, price - fee as net_price,
+  CAST(price AS BIGINT) as whole_price, CAST(quantity AS DECIMAL(20, 2)) as exact_quantity,
+  CAST(price AS TEXT) as price_text
+FROM trades
+WHERE price > 100;
+"}
+
 full_pipeline_codegen! {"top_n_tumbling",
 "SELECT * FROM (
   SELECT *, ROW_NUMBER()  OVER (
@@ -331,6 +405,17 @@ SELECT bid.auction as auction,
   GROUP BY 1, 2)) WHERE row_number < 4
 "}
 
+full_pipeline_codegen! {"top_n_per_key",
+"SELECT * FROM (
+  SELECT *, ROW_NUMBER() OVER (
+      PARTITION BY auction
+      ORDER BY price DESC) as row_number
+  FROM (
+    SELECT bid.auction as auction, bid.price as price
+      FROM nexmark
+      WHERE bid is not null)) where row_number <= 3
+"}
+
 full_pipeline_codegen! {"top_n_offset",
 "SELECT * FROM (
   SELECT *, ROW_NUMBER()  OVER (
@@ -421,6 +506,32 @@ SELECT 'test'
 FROM nexmark;
 "}
 
+full_pipeline_codegen! {"raw_bytes_test",
+"CREATE TABLE raw_source (
+  value BYTEA NOT NULL
+) WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'source',
+  topic = 'inputs',
+  format = 'raw_bytes'
+);
+
+CREATE TABLE raw_sink (
+  output BYTEA
+) WITH (
+  connector = 'kafka',
+  bootstrap_servers = 'localhost:9092',
+  type = 'sink',
+  topic = 'outputs',
+  format = 'raw_bytes'
+);
+
+INSERT INTO raw_sink
+SELECT value
+FROM raw_source;
+"}
+
 full_pipeline_codegen! {"polling_http_source",
 "CREATE TABLE polling_source (
   value TEXT NOT NULL
@@ -456,6 +567,14 @@ select extract_json_string(unnested, '$.a'), extract_json_string(unnested, '$.b'
   from nexmark);
 "}
 
+full_pipeline_codegen! {"unnest_join",
+"
+select b.auction, p.part from (
+  select bid.auction as auction, bid.url as url
+  from nexmark where bid is not null) b
+cross join unnest(extract_json(b.url, '$.a[*]')) as p(part);
+"}
+
 full_pipeline_codegen! {"non_updating_select_distinct",
 "CREATE TABLE non_updating_sink (
    url TEXT
@@ -471,3 +590,10 @@ INSERT INTO non_updating_sink
 select distinct(bid.url)
 from nexmark;
 "}
+
+full_pipeline_codegen! {"time_series_functions",
+"SELECT bid.auction,
+  rate(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as price_rate,
+  counter_delta(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as delta,
+  ewma(bid.price, 0.5) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as average
+FROM nexmark WHERE bid is not null"}
@@ -1043,6 +1043,62 @@ mod tests {
         String::from("ThoXXs")
     );
 
+    single_test_codegen!(
+        "regexp_extract",
+        "regexp_extract(non_nullable_string, '([a-z]+)@([a-z]+)\\.com', 2)",
+        arroyo_sql::TestStruct {
+            non_nullable_string: "mail alice@example.com".into(),
+            ..Default::default()
+        },
+        Some("example".to_string())
+    );
+
+    single_test_codegen!(
+        "regexp_like",
+        "regexp_like(nullable_string, '^order-[0-9]+$')",
+        arroyo_sql::TestStruct {
+            nullable_string: Some("order-123".into()),
+            ..Default::default()
+        },
+        Some(true)
+    );
+
+    single_test_codegen!(
+        "split_to_array",
+        "split_to_array(non_nullable_string, ',')",
+        arroyo_sql::TestStruct {
+            non_nullable_string: "a,b,,c".into(),
+            ..Default::default()
+        },
+        vec![
+            "a".to_string(),
+            "b".to_string(),
+            "".to_string(),
+            "c".to_string()
+        ]
+    );
+
+    single_test_codegen!(
+        "parse_url",
+        "parse_url(non_nullable_string, 'QUERY', 'id')",
+        arroyo_sql::TestStruct {
+            non_nullable_string: "https://www.nexmark.com/item.htm?id=12&ref=home".into(),
+            ..Default::default()
+        },
+        Some("12".to_string())
+    );
+
+    single_test_codegen!(
+        "format",
+        "format('%s bid %s', nullable_string, non_nullable_i64)",
+        arroyo_sql::TestStruct {
+            nullable_string: None,
+            non_nullable_i64: 5,
+            ..Default::default()
+        },
+        Some(" bid 5".to_string())
+    );
+
     // test CASE statements
     single_test_codegen!(
         "match_case_statement_non_nullable",
@@ -1246,4 +1302,44 @@ mod tests {
         },
         arroyo_types::from_millis(168565954000)
     );
+
+    single_test_codegen!(
+        "geohash_encode",
+        "geohash_encode(non_nullable_f64, 42.6, 5)",
+        arroyo_sql::TestStruct {
+            non_nullable_f64: -5.6,
+            ..Default::default()
+        },
+        Some("ezs42".to_string())
+    );
+
+    single_test_codegen!(
+        "st_contains",
+        "st_contains('POLYGON((0 0, 10 0, 10 10, 0 10, 0 0))', st_point(nullable_f64, 1.0))",
+        arroyo_sql::TestStruct {
+            nullable_f64: Some(1.0),
+            ..Default::default()
+        },
+        Some(true)
+    );
+
+    single_test_codegen!(
+        "st_contains_null",
+        "st_contains('POLYGON((0 0, 10 0, 10 10, 0 10, 0 0))', st_point(nullable_f64, 1.0))",
+        arroyo_sql::TestStruct {
+            nullable_f64: None,
+            ..Default::default()
+        },
+        None
+    );
+
+    single_test_codegen!(
+        "st_distance",
+        "st_distance(st_point(0.0, 0.0), nullable_string)",
+        arroyo_sql::TestStruct {
+            nullable_string: Some("POINT(0 0)".to_string()),
+            ..Default::default()
+        },
+        Some(0.0)
+    );
 }
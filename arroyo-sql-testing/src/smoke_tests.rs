@@ -1,5 +1,5 @@
 #![allow(warnings)]
-use arroyo_state::parquet::ParquetBackend;
+use arroyo_state::parquet::{checkpoint_url_from_env, ParquetBackend};
 use std::collections::HashMap;
 use std::{env, fmt::Debug, time::SystemTime};
 use tokio::sync::mpsc::Receiver;
@@ -31,6 +31,7 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
     let checkpoint_id = epoch as i64;
     let mut checkpoint_state = CheckpointState::new(
         ctx.job_id.clone(),
+        checkpoint_url_from_env(),
         checkpoint_id,
         epoch,
         0,
@@ -44,6 +45,7 @@ async fn checkpoint(ctx: &mut SmokeTestContext<'_>, epoch: u32) {
         min_epoch: 0,
         timestamp: SystemTime::now(),
         then_stop: false,
+        unaligned: false,
     };
 
     for source in ctx.engine.source_controls() {
@@ -98,9 +100,14 @@ async fn compact(
 ) {
     let operator_controls = running_engine.operator_controls();
     for (operator, parallelism) in tasks_per_operator {
-        if let Ok(Some(compacted)) =
-            ParquetBackend::compact_operator(parallelism, job_id.clone(), operator.clone(), epoch)
-                .await
+        if let Ok(Some(compacted)) = ParquetBackend::compact_operator(
+            &checkpoint_url_from_env(),
+            parallelism,
+            job_id.clone(),
+            operator.clone(),
+            epoch,
+        )
+        .await
         {
             let operator_controls = operator_controls.get(&operator).unwrap();
             for s in operator_controls {
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+
+use crate::{
+    code_gen::{CodeGenerator, ValuePointerContext},
+    expressions::{ColumnExpression, Expression, RustUdfExpression},
+    types::{StructDef, StructField, TypeDef},
+    AsyncUdfConfig,
+};
+
+// the field that the result of an async UDF is added to its input in, which the projection that
+// called it reads
+const ASYNC_RESULT_FIELD: &str = "__async_result";
+
+/// Calls an async UDF for each row of its input, adding the result as a field of the row. The
+/// calls are made concurrently, so rows may be emitted out of order unless the UDF is ordered.
+#[derive(Debug, Clone)]
+pub struct AsyncUdfOperator {
+    pub udf: RustUdfExpression,
+    pub config: AsyncUdfConfig,
+    input_struct: StructDef,
+}
+
+impl AsyncUdfOperator {
+    /// Replaces the call of an async UDF in an expression with a reference to the field that its
+    /// result is added in, setting `call` to it. Only one async UDF may be called per projection,
+    /// though it may appear in several of its expressions.
+    pub fn split(expr: &mut Expression, call: &mut Option<RustUdfExpression>) -> Result<()> {
+        let mut ctx: (Option<RustUdfExpression>, Result<()>) = (call.take(), Ok(()));
+
+        // expressions are traversed bottom-up, so a call nested in the arguments of another is
+        // replaced first, and the outer one is then a second call
+        expr.traverse_mut(&mut ctx, &|(call, result), e| {
+            let Expression::RustUdf(udf) = e else {
+                return;
+            };
+            if udf.async_config.is_none() {
+                return;
+            }
+            if matches!(call, Some(prev) if prev != udf) {
+                *result = Err(anyhow!(
+                    "multiple async UDF calls in a single SELECT, which is not currently supported"
+                ));
+                return;
+            }
+
+            let field = Self::result_field(udf);
+            *call = Some(udf.clone());
+            *e = Expression::Column(ColumnExpression::new(field));
+        });
+
+        let (found, result) = ctx;
+        *call = found;
+        result
+    }
+
+    pub fn new(udf: RustUdfExpression, input_struct: StructDef) -> Self {
+        let config = udf
+            .async_config
+            .clone()
+            .expect("async UDF operator for a UDF that isn't async");
+        Self {
+            udf,
+            config,
+            input_struct,
+        }
+    }
+
+    fn result_field(udf: &RustUdfExpression) -> StructField {
+        StructField::new(
+            ASYNC_RESULT_FIELD.to_string(),
+            None,
+            udf.expression_type(&ValuePointerContext::new()),
+        )
+    }
+
+    pub fn output_struct(&self) -> StructDef {
+        let mut fields = self.input_struct.fields.clone();
+        fields.push(Self::result_field(&self.udf));
+        StructDef::for_fields(fields)
+    }
+
+    // the type that the future of a call resolves to, which is None if a null is passed for a
+    // non-null parameter
+    fn call_output_type(&self) -> TypeDef {
+        self.udf.ret_type.clone().to_optional()
+    }
+
+    pub fn result_type(&self) -> String {
+        self.call_output_type()
+            .return_type()
+            .to_token_stream()
+            .to_string()
+    }
+
+    /// `fn(&T) -> Pin<Box<dyn Future<Output = R> + Send>>`, which calls the UDF for a row
+    pub fn call(&self) -> TokenStream {
+        let call = self.udf.generate_call(&ValuePointerContext::new());
+        let output_type = self.call_output_type().return_type();
+        let flatten = self.udf.ret_type.is_optional().then(|| quote!(.flatten()));
+
+        quote!(|arg| {
+            let call = #call;
+            Box::pin(async move {
+                match call {
+                    Some(call) => Some(call.await),
+                    None => None,
+                } #flatten
+            }) as std::pin::Pin<Box<dyn std::future::Future<Output = #output_type> + Send>>
+        })
+    }
+
+    /// `fn(&T, Option<R>) -> OutT`, which adds the result of a call to its row, or null if it
+    /// timed out
+    pub fn merge(&self) -> TokenStream {
+        let output_struct = self.output_struct();
+        let output_type = output_struct.get_type();
+        let (result, fields) = output_struct.fields.split_last().unwrap();
+        let result = result.field_ident();
+        let field_assignments = fields.iter().map(|f| {
+            let ident = f.field_ident();
+            quote! { #ident: arg.#ident.clone() }
+        });
+
+        quote!(|arg, result| #output_type {
+            #(#field_assignments, )*
+            #result: result.flatten(),
+        })
+    }
+}
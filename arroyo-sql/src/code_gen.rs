@@ -334,6 +334,9 @@ pub enum BinType {
     Option(Box<BinType>),
     Tuple(Vec<BinType>),
     BTreeMap(Box<BinType>, Box<BinType>),
+    Vec(Box<BinType>),
+    // the accumulator struct of a UDAF, defined in the udfs module
+    Accumulator(String),
 }
 
 impl BinType {
@@ -360,7 +363,15 @@ impl BinType {
                 let value_ident = value.syn_type();
                 parse_quote!(std::collections::BTreeMap<#key_ident, #value_ident>)
             }
+            BinType::Vec(inner_type) => {
+                let inner_syn_type = inner_type.syn_type();
+                parse_quote!(Vec<#inner_syn_type>)
+            }
             BinType::Usize => parse_quote!(usize),
+            BinType::Accumulator(name) => {
+                let name = format_ident!("{}", name);
+                parse_quote!(udfs::#name)
+            }
         }
     }
 }
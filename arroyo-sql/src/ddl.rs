@@ -0,0 +1,257 @@
+use anyhow::{anyhow, bail, Result};
+use datafusion::sql::sqlparser::{
+    dialect::PostgreSqlDialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::match_recognize::render;
+use crate::statement_set::match_words;
+
+/// The type given to computed columns, which is replaced by the type of their expression
+pub(crate) const INFERRED_TYPE: &str = "__arroyo_inferred";
+
+/// Rewrites the parts of table definitions that DataFusion can't parse into the forms it can.
+///
+/// Computed columns written as `name AS expr` become `name GENERATED ALWAYS AS (expr)` columns
+/// whose type is inferred from the expression, and `WATERMARK FOR column AS expr` becomes the
+/// `event_time_field` and `watermark_expression` options of the table.
+pub fn rewrite(query: &str) -> Result<String> {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, query).tokenize()?;
+
+    let mut output = String::new();
+    let mut changed = false;
+    let mut last = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some(start) = column_list(&tokens, i) else {
+            i += 1;
+            continue;
+        };
+        let end = closing_paren(&tokens, start)?;
+
+        let (columns, watermark) = rewrite_columns(&tokens[start + 1..end])?;
+        let Some(columns) = columns else {
+            i = end + 1;
+            continue;
+        };
+        changed = true;
+
+        output.push_str(&render(&tokens[last..=start]));
+        output.push_str(&columns);
+        output.push(')');
+        i = end + 1;
+
+        if let Some((column, expression)) = watermark {
+            let options = format!(
+                "event_time_field = '{}', watermark_expression = '{}'",
+                column.replace('\'', "''"),
+                expression.replace('\'', "''")
+            );
+
+            match with_options(&tokens, i) {
+                Some(options_start) => {
+                    let options_end = closing_paren(&tokens, options_start)?;
+                    for token in &tokens[options_start + 1..options_end] {
+                        if let Token::Word(w) = token {
+                            if w.value.eq_ignore_ascii_case("event_time_field")
+                                || w.value.eq_ignore_ascii_case("watermark_field")
+                            {
+                                bail!("a table with a WATERMARK can't also set '{}'", w.value);
+                            }
+                        }
+                    }
+
+                    output.push_str(&render(&tokens[i..=options_start]));
+                    output.push_str(&options);
+                    if tokens[options_start + 1..options_end]
+                        .iter()
+                        .any(|t| !matches!(t, Token::Whitespace(_)))
+                    {
+                        output.push_str(", ");
+                    }
+                    i = options_start + 1;
+                }
+                None => {
+                    output.push_str(&format!(" WITH ({})", options));
+                }
+            }
+        }
+        last = i;
+    }
+
+    if !changed {
+        return Ok(query.to_string());
+    }
+
+    output.push_str(&render(&tokens[last..]));
+    Ok(output)
+}
+
+/// If a `CREATE TABLE` starts at `i`, returns the index of the parenthesis that opens its
+/// columns
+fn column_list(tokens: &[Token], i: usize) -> Option<usize> {
+    let mut j = match_words(tokens, i, &["create", "table"])
+        .or_else(|| match_words(tokens, i, &["create", "temporary", "table"]))
+        .or_else(|| match_words(tokens, i, &["create", "temp", "table"]))?;
+
+    while j < tokens.len() {
+        match &tokens[j] {
+            Token::LParen => return Some(j),
+            Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case("as") => {
+                return None
+            }
+            Token::SemiColon => return None,
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+/// If a `WITH (` follows `i`, returns the index of its parenthesis
+fn with_options(tokens: &[Token], i: usize) -> Option<usize> {
+    let mut j = skip_whitespace(tokens, i);
+    j = match_words(tokens, j, &["with"])?;
+    j = skip_whitespace(tokens, j);
+    matches!(tokens.get(j), Some(Token::LParen)).then_some(j)
+}
+
+fn closing_paren(tokens: &[Token], open: usize) -> Result<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("unbalanced parentheses in table definition"))
+}
+
+fn skip_whitespace(tokens: &[Token], mut i: usize) -> usize {
+    while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+        i += 1;
+    }
+    i
+}
+
+/// Rewrites the computed columns in a column list and removes its watermark declaration,
+/// returning the new list (or None if nothing changed) and the watermark's column and expression
+#[allow(clippy::type_complexity)]
+fn rewrite_columns(tokens: &[Token]) -> Result<(Option<String>, Option<(String, String)>)> {
+    let mut elements: Vec<&[Token]> = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::Comma if depth == 0 => {
+                elements.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    elements.push(&tokens[start..]);
+
+    let mut changed = false;
+    let mut watermark = None;
+    let mut columns = vec![];
+    for element in elements {
+        let first = skip_whitespace(element, 0);
+
+        if let Some(end) = match_words(element, first, &["watermark", "for"]) {
+            if watermark.is_some() {
+                bail!("a table can only have one WATERMARK");
+            }
+            let column = skip_whitespace(element, end);
+            let Some(Token::Word(column_name)) = element.get(column) else {
+                bail!("WATERMARK FOR must be followed by the name of a column");
+            };
+            let expression = skip_whitespace(element, column + 1);
+            let Some(expression) = match_words(element, expression, &["as"]) else {
+                bail!(
+                    "WATERMARK FOR {} must be followed by AS and an expression",
+                    column_name
+                );
+            };
+            watermark = Some((
+                column_name.value.clone(),
+                render(&element[expression..]).trim().to_string(),
+            ));
+            changed = true;
+            continue;
+        }
+
+        if let Some(Token::Word(_)) = element.get(first) {
+            let next = skip_whitespace(element, first + 1);
+            if let Some(expression) = match_words(element, next, &["as"]) {
+                columns.push(format!(
+                    "{} {} GENERATED ALWAYS AS ({})",
+                    render(&element[..=first]),
+                    INFERRED_TYPE,
+                    render(&element[expression..]).trim()
+                ));
+                changed = true;
+                continue;
+            }
+        }
+
+        columns.push(render(element));
+    }
+
+    if !changed {
+        return Ok((None, None));
+    }
+
+    Ok((Some(columns.join(",")), watermark))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark() {
+        assert_eq!(
+            rewrite(
+                "CREATE TABLE events (id TEXT, ts TIMESTAMP, \
+                WATERMARK FOR ts AS ts - INTERVAL '5' SECOND) WITH (connector = 'kafka')"
+            )
+            .unwrap(),
+            "CREATE TABLE events (id TEXT, ts TIMESTAMP) WITH (event_time_field = 'ts', \
+            watermark_expression = 'ts - INTERVAL ''5'' SECOND', connector = 'kafka')"
+        );
+
+        assert_eq!(
+            rewrite("CREATE TABLE events (ts TIMESTAMP, WATERMARK FOR ts AS ts)").unwrap(),
+            "CREATE TABLE events (ts TIMESTAMP) WITH (event_time_field = 'ts', \
+            watermark_expression = 'ts')"
+        );
+
+        assert!(rewrite(
+            "CREATE TABLE events (ts TIMESTAMP, WATERMARK FOR ts AS ts) \
+            WITH (event_time_field = 'ts')"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_computed_columns() {
+        assert_eq!(
+            rewrite("CREATE TABLE t (price INT, total AS price * 2, c INT); SELECT a AS b FROM t")
+                .unwrap(),
+            "CREATE TABLE t (price INT, total __arroyo_inferred GENERATED ALWAYS AS (price * 2), \
+            c INT); SELECT a AS b FROM t"
+        );
+
+        let query = "CREATE TABLE t (price INT) WITH (connector = 'kafka'); \
+            CREATE TABLE u AS SELECT * FROM t";
+        assert_eq!(rewrite(query).unwrap(), query);
+    }
+}
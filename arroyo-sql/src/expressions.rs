@@ -5,13 +5,16 @@ use crate::{
     operators::TwoPhaseAggregation,
     pipeline::{JoinType, SortDirection, SqlOperator},
     schemas::window_type_def,
-    types::{interval_month_day_nanos_to_duration, StructDef, StructField, TypeDef},
-    ArroyoSchemaProvider,
+    types::{
+        data_type_as_syn_type, interval_month_day_nanos_to_duration, map_key_value, StructDef,
+        StructField, TypeDef,
+    },
+    AccumulatorDef, ArroyoSchemaProvider, AsyncUdfConfig,
 };
 use anyhow::{anyhow, bail, Ok, Result};
 use arrow::datatypes::DataType;
 use arrow_schema::{Field, TimeUnit};
-use arroyo_datastream::WindowType;
+use arroyo_datastream::{WindowAlignment, WindowType};
 use arroyo_types::{DatePart, DateTruncPrecision};
 use datafusion_common::ScalarValue;
 use datafusion_expr::{
@@ -43,6 +46,9 @@ pub enum Expression {
     Date(DateTimeFunction),
     String(StringFunction),
     Hash(HashExpression),
+    Sketch(SketchFunction),
+    Geo(GeoFunction),
+    StringUdf(StringUdfExpression),
     DataStructure(DataStructureFunction),
     Json(JsonExpression),
     RustUdf(RustUdfExpression),
@@ -243,6 +249,9 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for Expression {
             Expression::Date(date) => date.generate(input_context),
             Expression::String(string) => string.generate(input_context),
             Expression::Hash(hash) => hash.generate(input_context),
+            Expression::Sketch(sketch) => sketch.generate(input_context),
+            Expression::Geo(geo) => geo.generate(input_context),
+            Expression::StringUdf(e) => e.generate(input_context),
             Expression::DataStructure(data_structure) => data_structure.generate(input_context),
             Expression::Json(json) => json.generate(input_context),
             Expression::RustUdf(udf) => udf.generate(input_context),
@@ -292,6 +301,9 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for Expression {
             Expression::Date(date_function) => date_function.expression_type(input_context),
             Expression::String(string_function) => string_function.expression_type(input_context),
             Expression::Hash(hash_expression) => hash_expression.expression_type(input_context),
+            Expression::Sketch(sketch_function) => sketch_function.expression_type(input_context),
+            Expression::Geo(geo) => geo.expression_type(input_context),
+            Expression::StringUdf(e) => e.expression_type(input_context),
             Expression::DataStructure(data_structure_expression) => {
                 data_structure_expression.expression_type(input_context)
             }
@@ -418,6 +430,9 @@ impl Expression {
             | Expression::Date(_)
             | Expression::String(_)
             | Expression::Hash(_)
+            | Expression::Sketch(_)
+            | Expression::Geo(_)
+            | Expression::StringUdf(_)
             | Expression::DataStructure(_)
             | Expression::Json(_)
             | Expression::RustUdf(_)
@@ -441,6 +456,50 @@ impl Expression {
         }
     }
 
+    /// Reads the optional offset and time zone that follow the durations of tumble() and hop(),
+    /// which move the start of their windows away from the epoch-aligned boundaries in UTC
+    pub(crate) fn window_alignment(
+        function: &str,
+        args: &[Expr],
+        bin_width: Duration,
+    ) -> Result<WindowAlignment> {
+        let mut alignment = WindowAlignment::default();
+        for arg in args {
+            if Self::is_tumble_option(arg) {
+                if function != "tumble" {
+                    bail!("only tumble() windows support allowed_lateness(), late_data_sink() and emit_partial()");
+                }
+                continue;
+            }
+            if let Expr::Literal(ScalarValue::Utf8(Some(timezone))) = arg {
+                if function != "tumble" {
+                    bail!("only tumble() windows can be aligned to a time zone");
+                }
+                timezone.parse::<chrono_tz::Tz>().map_err(|_| {
+                    anyhow!("'{}' is not a valid time zone for {}()", timezone, function)
+                })?;
+                alignment.timezone = Some(timezone.clone());
+            } else {
+                alignment.offset = Self::get_duration(arg)?;
+                if alignment.offset >= bin_width {
+                    bail!(
+                        "the offset of {}() must be shorter than its {}",
+                        function,
+                        if function == "hop" { "slide" } else { "width" }
+                    );
+                }
+            }
+        }
+        Ok(alignment)
+    }
+
+    /// Whether a window function argument is `allowed_lateness()`, `late_data_sink()` or
+    /// `emit_partial()`, which configure when the window emits rather than its bins
+    pub(crate) fn is_tumble_option(arg: &Expr) -> bool {
+        matches!(arg, Expr::ScalarUDF(ScalarUDF { fun, .. })
+            if matches!(fun.name.as_str(), "allowed_lateness" | "late_data_sink" | "emit_partial"))
+    }
+
     pub fn traverse_mut<T, F: Fn(&mut T, &mut Expression) -> ()>(
         &mut self,
         context: &mut T,
@@ -487,6 +546,21 @@ impl Expression {
             Expression::Hash(e) => {
                 (&mut *e.input).traverse_mut(context, f);
             }
+            Expression::Sketch(e) => match e {
+                SketchFunction::HllEstimate(e) => {
+                    (&mut *e).traverse_mut(context, f);
+                }
+            },
+            Expression::Geo(e) => {
+                for e in &mut e.args {
+                    e.traverse_mut(context, f);
+                }
+            }
+            Expression::StringUdf(e) => {
+                for e in &mut e.args {
+                    e.traverse_mut(context, f);
+                }
+            }
             Expression::DataStructure(e) => match e {
                 DataStructureFunction::Coalesce(exprs)
                 | DataStructureFunction::MakeArray(exprs) => {
@@ -504,10 +578,24 @@ impl Expression {
                 } => {
                     (&mut *array_expression).traverse_mut(context, f);
                 }
+                DataStructureFunction::Cardinality(e)
+                | DataStructureFunction::MapKeys(e)
+                | DataStructureFunction::MapValues(e) => {
+                    (&mut *e).traverse_mut(context, f);
+                }
+                DataStructureFunction::MapGet {
+                    map_expression,
+                    key,
+                } => {
+                    (&mut *map_expression).traverse_mut(context, f);
+                    (&mut *key).traverse_mut(context, f);
+                }
             },
             Expression::Json(e) => {
                 (&mut *e.json_string).traverse_mut(context, f);
-                (&mut *e.path).traverse_mut(context, f);
+                if let Some(path) = &mut e.path {
+                    (&mut *path).traverse_mut(context, f);
+                }
             }
             Expression::RustUdf(udf) => {
                 for (_, arg) in &mut udf.args {
@@ -561,6 +649,16 @@ pub struct ExpressionContext<'a> {
 }
 
 impl<'a> ExpressionContext<'a> {
+    // checks that the argument of a collection function is a map, or an array if `allow_arrays`
+    fn check_collection(name: &str, argument: &Expression, allow_arrays: bool) -> Result<()> {
+        match argument.expression_type(&ValuePointerContext::new()) {
+            TypeDef::DataType(DataType::Map(_, _), _) => Ok(()),
+            TypeDef::DataType(DataType::List(_), _) if allow_arrays => Ok(()),
+            _ if allow_arrays => bail!("{}() may only be called on arrays and maps", name),
+            _ => bail!("{}() may only be called on maps", name),
+        }
+    }
+
     pub fn compile_expr(&self, expression: &Expr) -> Result<Expression> {
         match expression {
             Expr::Alias(datafusion_expr::expr::Alias { expr, name: _ }) => self.compile_expr(expr),
@@ -804,6 +902,13 @@ impl<'a> ExpressionContext<'a> {
                             arg_expressions,
                         )))
                     }
+                    BuiltinScalarFunction::Cardinality => {
+                        let collection = arg_expressions.remove(0);
+                        Self::check_collection("cardinality", &collection, true)?;
+                        Ok(Expression::DataStructure(
+                            DataStructureFunction::Cardinality(Box::new(collection)),
+                        ))
+                    }
                     BuiltinScalarFunction::Struct | BuiltinScalarFunction::ArrowTypeof => {
                         bail!("data structure function {:?} not implemented", fun)
                     }
@@ -884,7 +989,6 @@ impl<'a> ExpressionContext<'a> {
                     | BuiltinScalarFunction::ArrayRemove
                     | BuiltinScalarFunction::ArrayReplace
                     | BuiltinScalarFunction::ArrayToString
-                    | BuiltinScalarFunction::Cardinality
                     | BuiltinScalarFunction::TrimArray => {
                         bail!("array functions not implemented yet")
                     }
@@ -906,20 +1010,80 @@ impl<'a> ExpressionContext<'a> {
                     self.compile_expr(&args[0])?,
                     self.compile_expr(&args[1])?,
                 ),
+                "json_extract" => JsonExpression::new(
+                    JsonFunction::GetFirstJsonObject,
+                    self.compile_expr(&args[0])?,
+                    self.compile_expr(&args[1])?,
+                ),
+                "json_value" => JsonExpression::new(
+                    JsonFunction::JsonValue,
+                    self.compile_expr(&args[0])?,
+                    self.compile_expr(&args[1])?,
+                ),
+                "json_query" => JsonExpression::new(
+                    JsonFunction::JsonQuery,
+                    self.compile_expr(&args[0])?,
+                    self.compile_expr(&args[1])?,
+                ),
+                "json_array_elements" => JsonExpression::new_without_path(
+                    JsonFunction::JsonArrayElements,
+                    self.compile_expr(&args[0])?,
+                ),
+                "is_json" => JsonExpression::new_without_path(
+                    JsonFunction::IsJson,
+                    self.compile_expr(&args[0])?,
+                ),
+                "hll_sketch_estimate" => {
+                    if args.len() != 1 {
+                        bail!("wrong number of arguments for hll_sketch_estimate(), expected one");
+                    }
+                    Ok(Expression::Sketch(SketchFunction::HllEstimate(Box::new(
+                        self.compile_expr(&args[0])?,
+                    ))))
+                }
+                "st_point" | "st_distance" | "st_contains" | "st_within" | "geohash_encode"
+                | "geohash_decode" => Ok(Expression::Geo(GeoFunction {
+                    function: fun.name.clone(),
+                    args: args
+                        .iter()
+                        .map(|arg| self.compile_expr(arg))
+                        .collect::<Result<_>>()?,
+                })),
+                "regexp_extract" | "regexp_like" | "split_to_array" | "parse_url" | "format" => {
+                    StringUdfExpression::new(
+                        &fun.name,
+                        args.iter()
+                            .map(|arg| self.compile_expr(arg))
+                            .collect::<Result<_>>()?,
+                    )
+                }
                 "hop" => {
-                    if args.len() != 2 {
-                        bail!("wrong number of arguments for hop(), expected two");
+                    if !(2..=3).contains(&args.len()) {
+                        bail!("wrong number of arguments for hop(), expected two or three");
                     }
                     let slide = Expression::get_duration(&args[0])?;
                     let width = Expression::get_duration(&args[1])?;
-                    Ok(Expression::WindowUDF(WindowType::Sliding { width, slide }))
+                    let alignment = Expression::window_alignment("hop", &args[2..], slide)?;
+                    Ok(Expression::WindowUDF(WindowType::Sliding {
+                        width,
+                        slide,
+                        alignment,
+                    }))
                 }
                 "tumble" => {
-                    if args.len() != 1 {
-                        bail!("wrong number of arguments for tumble(), expect one");
+                    let bin_args = args
+                        .iter()
+                        .filter(|arg| !Expression::is_tumble_option(arg))
+                        .count();
+                    if !(1..=3).contains(&bin_args) {
+                        bail!("wrong number of arguments for tumble(), expected one to three");
                     }
                     let width = Expression::get_duration(&args[0])?;
-                    Ok(Expression::WindowUDF(WindowType::Tumbling { width }))
+                    let alignment = Expression::window_alignment("tumble", &args[1..], width)?;
+                    Ok(Expression::WindowUDF(WindowType::Tumbling {
+                        width,
+                        alignment,
+                    }))
                 }
                 "session" => {
                     if args.len() != 1 {
@@ -928,6 +1092,32 @@ impl<'a> ExpressionContext<'a> {
                     let gap = Expression::get_duration(&args[0])?;
                     Ok(Expression::WindowUDF(WindowType::Session { gap }))
                 }
+                "allowed_lateness" | "late_data_sink" | "emit_partial" => {
+                    bail!("{}() can only be used as an argument to tumble()", fun.name)
+                }
+                "map_keys" | "map_values" => {
+                    if args.len() != 1 {
+                        bail!("wrong number of arguments for {}(), expected one", fun.name);
+                    }
+                    let map = self.compile_expr(&args[0])?;
+                    Self::check_collection(&fun.name, &map, false)?;
+                    Ok(Expression::DataStructure(if fun.name == "map_keys" {
+                        DataStructureFunction::MapKeys(Box::new(map))
+                    } else {
+                        DataStructureFunction::MapValues(Box::new(map))
+                    }))
+                }
+                "element_at" => {
+                    if args.len() != 2 {
+                        bail!("wrong number of arguments for element_at(), expected two");
+                    }
+                    let map = self.compile_expr(&args[0])?;
+                    Self::check_collection("element_at", &map, false)?;
+                    Ok(Expression::DataStructure(DataStructureFunction::MapGet {
+                        map_expression: Box::new(map),
+                        key: Box::new(self.compile_expr(&args[1])?),
+                    }))
+                }
                 "unnest" => {
                     if args.len() != 1 {
                         bail!("wrong number of arguments for unnest(), expected one");
@@ -962,6 +1152,7 @@ impl<'a> ExpressionContext<'a> {
                         name: udf.to_string(),
                         args: def.args.clone().into_iter().zip(inputs).collect(),
                         ret_type: def.ret.clone(),
+                        async_config: def.async_config.clone(),
                     }))
                 }
             },
@@ -1138,7 +1329,7 @@ impl LiteralExpression {
         TypeDef::DataType(self.literal.get_datatype(), self.literal.is_null())
     }
 
-    fn new(literal: ScalarValue) -> Expression {
+    pub fn new(literal: ScalarValue) -> Expression {
         Expression::Literal(Self { literal })
     }
 }
@@ -1331,6 +1522,32 @@ impl BinaryMathOperator {
             BinaryMathOperator::Modulo => quote!(%),
         }
     }
+
+    /// The type of the result of the operator on two decimals, which as in DataFusion is wide
+    /// enough to hold the exact result (or 6 more digits of a quotient)
+    fn decimal_result_type(&self, left: (u8, i8), right: (u8, i8)) -> DataType {
+        let ((p1, s1), (p2, s2)) = (
+            (left.0 as i32, left.1 as i32),
+            (right.0 as i32, right.1 as i32),
+        );
+        let (precision, scale) = match self {
+            BinaryMathOperator::Plus | BinaryMathOperator::Minus => {
+                let scale = s1.max(s2);
+                ((p1 - s1).max(p2 - s2) + scale + 1, scale)
+            }
+            BinaryMathOperator::Multiply => (p1 + p2 + 1, s1 + s2),
+            BinaryMathOperator::Divide => {
+                let scale = 6.max(s1 + p2 + 1);
+                (p1 - s1 + s2 + scale, scale)
+            }
+            BinaryMathOperator::Modulo => {
+                let scale = s1.max(s2);
+                ((p1 - s1).min(p2 - s2) + scale, scale)
+            }
+        };
+        let max = arroyo_types::decimal::MAX_PRECISION as i32;
+        DataType::Decimal128(precision.min(max) as u8, scale.min(max) as i8)
+    }
 }
 
 impl TryFrom<datafusion_expr::Operator> for BinaryMathOperator {
@@ -1396,11 +1613,19 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for BinaryMathExpres
     }
 
     fn expression_type(&self, input_context: &ValuePointerContext) -> TypeDef {
-        let nullable = self.left.expression_type(input_context).is_optional()
-            || self.right.expression_type(input_context).is_optional();
-        self.left
-            .expression_type(input_context)
-            .with_nullity(nullable)
+        let left = self.left.expression_type(input_context);
+        let right = self.right.expression_type(input_context);
+        let nullable = left.is_optional() || right.is_optional();
+        match (&left, &right) {
+            (
+                TypeDef::DataType(DataType::Decimal128(p1, s1), _),
+                TypeDef::DataType(DataType::Decimal128(p2, s2), _),
+            ) => TypeDef::DataType(
+                self.op.decimal_result_type((*p1, *s1), (*p2, *s2)),
+                nullable,
+            ),
+            _ => left.with_nullity(nullable),
+        }
     }
 }
 
@@ -1485,7 +1710,7 @@ impl AggregateComputation {
     pub fn allows_two_phase(&self) -> bool {
         match self {
             AggregateComputation::Builtin { computation, .. } => computation.allows_two_phase(),
-            AggregateComputation::UDAF { .. } => false,
+            AggregateComputation::UDAF { computation, .. } => computation.accumulator.is_some(),
         }
     }
     pub fn try_from_expression(
@@ -1504,6 +1729,17 @@ impl AggregateComputation {
                 })
             }
             Expr::AggregateUDF(aggregate_udf) => {
+                if let Some(aggregator) = Aggregator::from_udaf(&aggregate_udf.fun.name) {
+                    let computation = AggregationExpression::try_from_builtin_udaf(
+                        ctx,
+                        aggregate_udf,
+                        aggregator,
+                    )?;
+                    return Ok(Self::Builtin {
+                        column: Column::convert(column),
+                        computation,
+                    });
+                }
                 let computation = RustUdafExpression::try_from_aggregate_udf(ctx, aggregate_udf)?;
                 Ok(Self::UDAF {
                     column: Column::convert(column),
@@ -1552,9 +1788,43 @@ pub enum Aggregator {
     Max,
     Avg,
     CountDistinct,
+    // the approximate number of distinct values, estimated with a HyperLogLog sketch
+    ApproxCountDistinct,
+    // the serialized HyperLogLog sketch of the values, which can be merged by HllUnion
+    HllSketch,
+    // merges serialized sketches into one
+    HllUnion,
+    // the approximate value at a percentile, given in millionths, estimated with a DDSketch
+    ApproxPercentile(u32),
+    // the values in the order they arrived, including nulls
+    ArrayAgg,
 }
 
 impl Aggregator {
+    /// The aggregator for a built-in aggregate that's registered as a UDAF, as DataFusion has no
+    /// equivalent
+    pub fn from_udaf(name: &str) -> Option<Self> {
+        match name {
+            "approx_count_distinct" => Some(Self::ApproxCountDistinct),
+            "hll_sketch_agg" => Some(Self::HllSketch),
+            "hll_union_agg" => Some(Self::HllUnion),
+            _ => None,
+        }
+    }
+
+    /// Whether values can be retracted from the state of the aggregate, which is required to
+    /// compute it over updating inputs. HyperLogLog sketches can be merged but not subtracted
+    /// from, and ARRAY_AGG keeps its values in the order they arrived.
+    pub fn is_retractable(&self) -> bool {
+        !matches!(
+            self,
+            Aggregator::ApproxCountDistinct
+                | Aggregator::HllSketch
+                | Aggregator::HllUnion
+                | Aggregator::ArrayAgg
+        )
+    }
+
     pub fn from_datafusion(
         aggregator: aggregate_function::AggregateFunction,
         distinct: bool,
@@ -1566,6 +1836,10 @@ impl Aggregator {
             (datafusion_expr::AggregateFunction::Max, false) => Ok(Self::Max),
             (datafusion_expr::AggregateFunction::Avg, false) => Ok(Self::Avg),
             (datafusion_expr::AggregateFunction::Count, true) => Ok(Self::CountDistinct),
+            (datafusion_expr::AggregateFunction::ApproxMedian, false) => {
+                Ok(Self::ApproxPercentile(500_000))
+            }
+            (datafusion_expr::AggregateFunction::ArrayAgg, false) => Ok(Self::ArrayAgg),
             (aggregator, true) => bail!("distinct not supported for {:?}", aggregator),
             (aggregator, false) => bail!("aggregator {:?} not supported yet", aggregator),
         }
@@ -1586,7 +1860,18 @@ impl Aggregator {
             Aggregator::Avg => {
                 avg_return_type(&input_type).expect("data fusion should've validated types")
             }
-            Aggregator::CountDistinct => DataType::Int64,
+            Aggregator::CountDistinct | Aggregator::ApproxCountDistinct => DataType::Int64,
+            Aggregator::HllSketch | Aggregator::HllUnion => DataType::Binary,
+            Aggregator::ApproxPercentile(_) => input_type,
+            Aggregator::ArrayAgg => DataType::List(Arc::new(Field::new("item", input_type, true))),
+        }
+    }
+
+    /// The percentile computed by an approximate percentile aggregate, between 0 and 1
+    pub fn percentile(&self) -> Option<f64> {
+        match self {
+            Aggregator::ApproxPercentile(per_million) => Some(*per_million as f64 / 1_000_000.0),
+            _ => None,
         }
     }
 }
@@ -1634,8 +1919,13 @@ impl AggregationExpression {
             | Aggregator::Sum
             | Aggregator::Min
             | Aggregator::Avg
-            | Aggregator::Max => true,
-            Aggregator::CountDistinct => false,
+            | Aggregator::Max
+            | Aggregator::CountDistinct
+            | Aggregator::ApproxCountDistinct
+            | Aggregator::HllSketch
+            | Aggregator::HllUnion
+            | Aggregator::ApproxPercentile(_)
+            | Aggregator::ArrayAgg => true,
         }
     }
 
@@ -1653,6 +1943,23 @@ impl AggregationExpression {
         let distinct = aggregate_function.distinct;
         let args = &aggregate_function.args;
 
+        if *fun == aggregate_function::AggregateFunction::ApproxPercentileCont {
+            let [arg, percentile] = args.as_slice() else {
+                bail!("approx_percentile_cont() takes a value and a percentile");
+            };
+            let percentile = match percentile {
+                Expr::Literal(ScalarValue::Float64(Some(p))) if (0.0..=1.0).contains(p) => *p,
+                _ => bail!(
+                    "the percentile of approx_percentile_cont() must be a literal between 0 and 1, not {}",
+                    percentile
+                ),
+            };
+            return Ok(AggregationExpression {
+                producing_expression: Box::new(ctx.compile_expr(arg)?),
+                aggregator: Aggregator::ApproxPercentile((percentile * 1_000_000.0).round() as u32),
+            });
+        }
+
         if args.len() != 1 {
             bail!("unexpected arg length");
         }
@@ -1663,6 +1970,39 @@ impl AggregationExpression {
             aggregator,
         })
     }
+
+    fn try_from_builtin_udaf(
+        ctx: &mut ExpressionContext,
+        aggregate_udf: &AggregateUDF,
+        aggregator: Aggregator,
+    ) -> Result<Self> {
+        let name = &aggregate_udf.fun.name;
+        if aggregate_udf.filter.is_some() {
+            bail!("Not supporting aggregate filters right now");
+        }
+        if aggregate_udf.order_by.is_some() {
+            bail!("Not supporting aggregate sorts right now");
+        }
+        if aggregate_udf.args.len() != 1 {
+            bail!("{}() takes a single argument", name);
+        }
+
+        let producing_expression = Box::new(ctx.compile_expr(&aggregate_udf.args[0])?);
+        let input_type = producing_expression.expression_type(&ValuePointerContext::new());
+        match (&aggregator, &input_type) {
+            (_, TypeDef::StructDef(_, _)) => bail!("{}() can't be computed over structs", name),
+            (Aggregator::HllUnion, TypeDef::DataType(DataType::Binary, _)) => {}
+            (Aggregator::HllUnion, _) => {
+                bail!("hll_union_agg() merges sketches produced by hll_sketch_agg()")
+            }
+            _ => {}
+        }
+
+        Ok(AggregationExpression {
+            producing_expression,
+            aggregator,
+        })
+    }
 }
 
 impl CodeGenerator<VecOfPointersContext, TypeDef, syn::Expr> for AggregationExpression {
@@ -1686,6 +2026,16 @@ impl CodeGenerator<VecOfPointersContext, TypeDef, syn::Expr> for AggregationExpr
             Some(quote!(.unwrap()))
         };
 
+        let average: syn::Expr = match self
+            .producing_expression
+            .expression_type(&single_value_context)
+        {
+            TypeDef::DataType(DataType::Decimal128(_, _), _) => {
+                parse_quote!(result.1 / arroyo_types::Decimal::from(result.0 as i64))
+            }
+            _ => parse_quote!((result.1 as f64) / (result.0 as f64)),
+        };
+
         match &self.aggregator {
             Aggregator::Count => {
                 if producing_expression_is_optional {
@@ -1721,7 +2071,7 @@ impl CodeGenerator<VecOfPointersContext, TypeDef, syn::Expr> for AggregationExpr
                     .#map_type(|#single_value_ident| #sub_expr)
                     .map(|val| (1, val))
                     .reduce(|left, right| (left.0 + right.0, left.1+right.1))
-                    .map(|result| (result.1 as f64)/(result.0 as f64))
+                    .map(|result| #average)
                     #unwrap
             }),
             Aggregator::CountDistinct => parse_quote! ({
@@ -1730,14 +2080,80 @@ impl CodeGenerator<VecOfPointersContext, TypeDef, syn::Expr> for AggregationExpr
                     .collect::<std::collections::HashSet<_>>()
                     .len() as i64
             }),
+            Aggregator::ApproxCountDistinct => parse_quote!({
+                let mut sketch = arroyo_worker::operators::functions::hll::new_sketch();
+                for value in #vec_ident.iter().#map_type(|#single_value_ident| #sub_expr) {
+                    arroyo_worker::operators::functions::hll::add(&mut sketch, &value);
+                }
+                arroyo_worker::operators::functions::hll::estimate(&sketch).unwrap()
+            }),
+            Aggregator::HllSketch => parse_quote!({
+                let mut sketch = arroyo_worker::operators::functions::hll::new_sketch();
+                for value in #vec_ident.iter().#map_type(|#single_value_ident| #sub_expr) {
+                    arroyo_worker::operators::functions::hll::add(&mut sketch, &value);
+                }
+                sketch
+            }),
+            Aggregator::HllUnion => parse_quote!({
+                let mut sketch = arroyo_worker::operators::functions::hll::new_sketch();
+                for value in #vec_ident.iter().#map_type(|#single_value_ident| #sub_expr) {
+                    arroyo_worker::operators::functions::hll::merge(&mut sketch, &value);
+                }
+                sketch
+            }),
+            Aggregator::ApproxPercentile(_) => {
+                let percentile = self.aggregator.percentile().unwrap();
+                let output_type = data_type_as_syn_type(
+                    self.expression_type(input_context).as_datatype().unwrap(),
+                );
+                parse_quote!({
+                    let mut sketch = std::collections::BTreeMap::new();
+                    for value in #vec_ident.iter().#map_type(|#single_value_ident| #sub_expr) {
+                        arroyo_worker::operators::functions::ddsketch::add(&mut sketch, value as f64);
+                    }
+                    arroyo_worker::operators::functions::ddsketch::percentile(&sketch, #percentile)
+                        .map(|value| value as #output_type)
+                })
+            }
+            Aggregator::ArrayAgg => {
+                if producing_expression_is_optional {
+                    parse_quote!({
+                        #vec_ident.iter()
+                            .map(|#single_value_ident| #sub_expr)
+                            .collect::<Vec<_>>()
+                    })
+                } else {
+                    parse_quote!({
+                        #vec_ident.iter()
+                            .map(|#single_value_ident| Some(#sub_expr))
+                            .collect::<Vec<_>>()
+                    })
+                }
+            }
         }
     }
 
     fn expression_type(&self, _input_context: &VecOfPointersContext) -> TypeDef {
         match &self.aggregator {
-            Aggregator::Count | Aggregator::CountDistinct => {
+            Aggregator::Count | Aggregator::CountDistinct | Aggregator::ApproxCountDistinct => {
                 TypeDef::DataType(DataType::Int64, false)
             }
+            Aggregator::HllSketch | Aggregator::HllUnion => {
+                TypeDef::DataType(DataType::Binary, false)
+            }
+            // an empty sketch has no percentiles
+            Aggregator::ApproxPercentile(_) => {
+                let input_type = self
+                    .producing_expression
+                    .expression_type(&ValuePointerContext::new());
+                TypeDef::DataType(self.aggregator.return_data_type(input_type), true)
+            }
+            Aggregator::ArrayAgg => {
+                let input_type = self
+                    .producing_expression
+                    .expression_type(&ValuePointerContext::new());
+                TypeDef::DataType(self.aggregator.return_data_type(input_type), false)
+            }
             aggregator => {
                 let single_value_context = ValuePointerContext::new();
                 let input_type = self
@@ -1790,6 +2206,15 @@ impl CastExpression {
             && (Self::is_numeric(output_data_type) || Self::is_string(output_data_type))
         {
             true
+        // handle casts to and from decimals
+        } else if (Self::is_decimal(input_data_type) || Self::is_decimal(output_data_type))
+            && [input_data_type, output_data_type].iter().all(|data_type| {
+                Self::is_decimal(data_type)
+                    || Self::is_numeric(data_type)
+                    || Self::is_string(data_type)
+            })
+        {
+            true
         // handle date to string casts.
         } else if Self::is_date(input_data_type) && Self::is_string(output_data_type) {
             true
@@ -1823,6 +2248,10 @@ impl CastExpression {
         )
     }
 
+    fn is_decimal(data_type: &DataType) -> bool {
+        matches!(data_type, DataType::Decimal128(_, _))
+    }
+
     fn is_date(data_type: &DataType) -> bool {
         matches!(data_type, DataType::Timestamp(_, None))
     }
@@ -1831,7 +2260,30 @@ impl CastExpression {
         matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
     }
     fn cast_expr(input_type: &DataType, output_type: &DataType, sub_expr: syn::Expr) -> syn::Expr {
-        if Self::is_numeric(input_type) && Self::is_numeric(output_type) {
+        if let DataType::Decimal128(precision, scale) = output_type {
+            let decimal: syn::Expr = match input_type {
+                DataType::Decimal128(_, _) => parse_quote!(#sub_expr),
+                DataType::Float16 | DataType::Float32 | DataType::Float64 => parse_quote!(
+                    arroyo_types::Decimal::from_f64((#sub_expr) as f64, #scale)
+                        .expect("only finite floats can be cast to decimals")
+                ),
+                _ if Self::is_string(input_type) => {
+                    parse_quote!(#sub_expr.parse::<arroyo_types::Decimal>().unwrap())
+                }
+                _ => parse_quote!(arroyo_types::Decimal::from(#sub_expr)),
+            };
+            parse_quote!((#decimal).with_precision_and_scale(#precision, #scale))
+        } else if Self::is_decimal(input_type) {
+            let cast_type: syn::Type =
+                parse_str(&StructField::data_type_name(output_type)).unwrap();
+            match output_type {
+                DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+                    parse_quote!((#sub_expr).to_f64() as #cast_type)
+                }
+                _ if Self::is_string(output_type) => parse_quote!((#sub_expr).to_string()),
+                _ => parse_quote!((#sub_expr).to_i128() as #cast_type),
+            }
+        } else if Self::is_numeric(input_type) && Self::is_numeric(output_type) {
             let cast_type: syn::Type =
                 parse_str(&StructField::data_type_name(output_type)).unwrap();
             parse_quote!(#sub_expr as #cast_type)
@@ -2278,6 +2730,245 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for HashExpression {
     }
 }
 
+/// Functions over the serialized sketches produced by sketch aggregates
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum SketchFunction {
+    // the number of distinct values estimated by a HyperLogLog sketch, or NULL if the value
+    // isn't a valid sketch
+    HllEstimate(Box<Expression>),
+}
+
+impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for SketchFunction {
+    fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
+        match self {
+            SketchFunction::HllEstimate(input) => {
+                let expr = input.generate(input_context);
+                if input.expression_type(input_context).is_optional() {
+                    parse_quote!(#expr.and_then(arroyo_worker::operators::functions::hll::hll_sketch_estimate))
+                } else {
+                    parse_quote!(arroyo_worker::operators::functions::hll::hll_sketch_estimate(#expr))
+                }
+            }
+        }
+    }
+
+    fn expression_type(&self, _input_context: &ValuePointerContext) -> TypeDef {
+        match self {
+            SketchFunction::HllEstimate(_) => TypeDef::DataType(DataType::Int64, true),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum StringUdf {
+    // the text matched by a group of a regex, the first by default
+    RegexpExtract,
+    RegexpLike,
+    SplitToArray,
+    // a part of a URL, or the value of a query parameter
+    ParseUrl,
+    // Postgres-style format, with %s and %%
+    Format,
+}
+
+/// String functions that DataFusion doesn't provide, which are registered as UDFs
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct StringUdfExpression {
+    function: StringUdf,
+    pub(crate) args: Vec<Expression>,
+}
+
+impl StringUdfExpression {
+    fn new(name: &str, mut args: Vec<Expression>) -> Result<Expression> {
+        let function = match name {
+            "regexp_extract" => StringUdf::RegexpExtract,
+            "regexp_like" => StringUdf::RegexpLike,
+            "split_to_array" => StringUdf::SplitToArray,
+            "parse_url" => StringUdf::ParseUrl,
+            "format" => StringUdf::Format,
+            _ => bail!("unknown string function {}", name),
+        };
+
+        match function {
+            StringUdf::RegexpExtract | StringUdf::RegexpLike => {
+                let Some(Expression::Literal(LiteralExpression {
+                    literal: ScalarValue::Utf8(Some(regex)),
+                })) = args.get(1)
+                else {
+                    bail!("regex argument of {}() must be a string literal", name)
+                };
+                Regex::new(regex)?;
+                if args.len() == 2 && function == StringUdf::RegexpExtract {
+                    args.push(LiteralExpression::new(ScalarValue::Int64(Some(1))));
+                }
+            }
+            StringUdf::Format => {
+                if args.is_empty() {
+                    bail!("format() requires a format string");
+                }
+                // the arguments are formatted as strings
+                for arg in &mut args[1..] {
+                    if !matches!(
+                        arg.expression_type(&ValuePointerContext::new()),
+                        TypeDef::DataType(DataType::Utf8, _)
+                    ) {
+                        let input = Box::new(arg.clone());
+                        *arg = CastExpression::new(
+                            input,
+                            &DataType::Utf8,
+                            &ValuePointerContext::new(),
+                            false,
+                        )?;
+                    }
+                }
+            }
+            StringUdf::SplitToArray | StringUdf::ParseUrl => {}
+        }
+
+        Ok(Expression::StringUdf(Self { function, args }))
+    }
+
+    // the arguments that the result is null if any are. The format arguments are instead passed
+    // as options, as nulls are formatted as empty strings.
+    fn bound_args(&self) -> &[Expression] {
+        match self.function {
+            StringUdf::Format => &self.args[..1],
+            _ => &self.args[..],
+        }
+    }
+
+    // whether the function itself may return null, as well as for null arguments
+    fn fallible(&self) -> bool {
+        matches!(
+            self.function,
+            StringUdf::RegexpExtract | StringUdf::ParseUrl | StringUdf::Format
+        )
+    }
+}
+
+impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for StringUdfExpression {
+    fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
+        let bound_args = self.bound_args();
+        let params: Vec<_> = (0..bound_args.len())
+            .map(|i| format_ident!("__{}", i))
+            .collect();
+        let bindings = bound_args.iter().zip(&params).map(|(arg, param)| {
+            let expr = arg.generate(input_context);
+            if arg.expression_type(input_context).is_optional() {
+                quote!(let #param = (#expr)?;)
+            } else {
+                quote!(let #param = #expr;)
+            }
+        });
+
+        let call = match self.function {
+            StringUdf::RegexpExtract => quote!(
+                arroyo_worker::operators::functions::regexp::regexp_extract(#(#params),*)
+            ),
+            StringUdf::RegexpLike => quote!(
+                arroyo_worker::operators::functions::regexp::regexp_like(#(#params),*)
+            ),
+            StringUdf::SplitToArray => quote!(
+                arroyo_worker::operators::functions::strings::split_to_array(#(#params),*)
+            ),
+            StringUdf::ParseUrl => {
+                let url = &params[0];
+                let part = &params[1];
+                let key = match params.get(2) {
+                    Some(key) => quote!(Some(#key)),
+                    None => quote!(None),
+                };
+                quote!(arroyo_worker::operators::functions::strings::parse_url(#url, #part, #key))
+            }
+            StringUdf::Format => {
+                let format = &params[0];
+                let args = self.args[1..].iter().map(|arg| {
+                    let expr = arg.generate(input_context);
+                    if arg.expression_type(input_context).is_optional() {
+                        quote!(#expr)
+                    } else {
+                        quote!(Some(#expr))
+                    }
+                });
+                quote!(arroyo_worker::operators::functions::strings::format(#format, vec![#(#args),*]))
+            }
+        };
+
+        let nullable_args = bound_args
+            .iter()
+            .any(|arg| arg.expression_type(input_context).is_optional());
+        match (nullable_args, self.fallible()) {
+            (false, false) => parse_quote!({
+                #(#bindings)*
+                #call
+            }),
+            (true, false) => parse_quote!((|| {
+                #(#bindings)*
+                Some(#call)
+            })()),
+            (_, true) => parse_quote!((|| {
+                #(#bindings)*
+                #call
+            })()),
+        }
+    }
+
+    fn expression_type(&self, input_context: &ValuePointerContext) -> TypeDef {
+        let data_type = match self.function {
+            StringUdf::RegexpLike => DataType::Boolean,
+            StringUdf::SplitToArray => {
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, false)))
+            }
+            StringUdf::RegexpExtract | StringUdf::ParseUrl | StringUdf::Format => DataType::Utf8,
+        };
+        let bound_args = self.bound_args();
+        let nullable = self.fallible()
+            || bound_args
+                .iter()
+                .any(|arg| arg.expression_type(input_context).is_optional());
+        TypeDef::DataType(data_type, nullable)
+    }
+}
+
+/// Geospatial functions over points and polygons as WKT, which are all null if any of their
+/// arguments are null or aren't valid geometries
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct GeoFunction {
+    // the name of the function in arroyo_worker::operators::functions::geo
+    function: String,
+    pub(crate) args: Vec<Expression>,
+}
+
+impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for GeoFunction {
+    fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
+        let function = format_ident!("{}", self.function);
+        let params: Vec<_> = (0..self.args.len())
+            .map(|i| format_ident!("__{}", i))
+            .collect();
+        let bindings = self.args.iter().zip(&params).map(|(arg, param)| {
+            let expr = arg.generate(input_context);
+            if arg.expression_type(input_context).is_optional() {
+                quote!(let #param = (#expr)?;)
+            } else {
+                quote!(let #param = #expr;)
+            }
+        });
+        parse_quote!((|| {
+            #(#bindings)*
+            arroyo_worker::operators::functions::geo::#function(#(#params),*)
+        })())
+    }
+
+    fn expression_type(&self, _input_context: &ValuePointerContext) -> TypeDef {
+        let data_type = match self.function.as_str() {
+            "st_distance" => DataType::Float64,
+            "st_contains" | "st_within" => DataType::Boolean,
+            _ => DataType::Utf8,
+        };
+        TypeDef::DataType(data_type, true)
+    }
+}
+
 impl TryFrom<(BuiltinScalarFunction, Vec<Expression>)> for StringFunction {
     type Error = anyhow::Error;
 
@@ -3254,6 +3945,15 @@ pub enum DataStructureFunction {
         array_expression: Box<Expression>,
         index: usize,
     },
+    // the number of elements in an array or entries in a map
+    Cardinality(Box<Expression>),
+    MapKeys(Box<Expression>),
+    MapValues(Box<Expression>),
+    // the value for a key in a map, or null if it has none
+    MapGet {
+        map_expression: Box<Expression>,
+        key: Box<Expression>,
+    },
 }
 
 impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for DataStructureFunction {
@@ -3371,24 +4071,84 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for DataStructureFun
                 index,
             } => {
                 let array_expr = array_expression.generate(input_context);
-                let array_nullable = array_expression
-                    .expression_type(input_context)
-                    .is_optional();
-                match array_nullable {
+                let array_type = array_expression.expression_type(input_context);
+                // elements may themselves be null
+                let flatten = match &array_type {
+                    TypeDef::DataType(DataType::List(field), _) if field.is_nullable() => {
+                        Some(quote!(.flatten()))
+                    }
+                    _ => None,
+                };
+                match array_type.is_optional() {
                     true => parse_quote!({
                         let array = #array_expr;
                         if let Some(array) = array {
-                            array.get(#index - 1).cloned()
+                            array.get(#index - 1).cloned()#flatten
                         } else {
                             None
                         }
                     }),
                     false => parse_quote!({
                         let array = #array_expr;
-                        array.get(#index - 1).cloned()
+                        array.get(#index - 1).cloned()#flatten
                     }),
                 }
             }
+            DataStructureFunction::Cardinality(collection) => {
+                let expr = collection.generate(input_context);
+                if collection.expression_type(input_context).is_optional() {
+                    parse_quote!(#expr.map(|collection| collection.len() as u64))
+                } else {
+                    parse_quote!((#expr.len() as u64))
+                }
+            }
+            DataStructureFunction::MapKeys(map) => {
+                let expr = map.generate(input_context);
+                if map.expression_type(input_context).is_optional() {
+                    parse_quote!(#expr.map(|map| map.into_keys().collect::<Vec<_>>()))
+                } else {
+                    parse_quote!(#expr.into_keys().collect::<Vec<_>>())
+                }
+            }
+            DataStructureFunction::MapValues(map) => {
+                let expr = map.generate(input_context);
+                if map.expression_type(input_context).is_optional() {
+                    parse_quote!(#expr.map(|map| map.into_values().collect::<Vec<_>>()))
+                } else {
+                    parse_quote!(#expr.into_values().collect::<Vec<_>>())
+                }
+            }
+            DataStructureFunction::MapGet {
+                map_expression,
+                key,
+            } => {
+                let map_expr = map_expression.generate(input_context);
+                let key_expr = key.generate(input_context);
+                let map_type = map_expression.expression_type(input_context);
+                let flatten = match &map_type {
+                    TypeDef::DataType(DataType::Map(entries, _), _)
+                        if map_key_value(entries).1.is_nullable() =>
+                    {
+                        Some(quote!(.flatten()))
+                    }
+                    _ => None,
+                };
+                let map = if map_type.is_optional() {
+                    quote!(#map_expr?)
+                } else {
+                    quote!(#map_expr)
+                };
+                let key = if key.expression_type(input_context).is_optional() {
+                    quote!(#key_expr?)
+                } else {
+                    quote!(#key_expr)
+                };
+                parse_quote!((|| {
+                    let map = #map;
+                    let key = #key;
+                    map.get(&key).cloned()#flatten
+                })())
+            }
         }
     }
     fn expression_type(&self, input_context: &ValuePointerContext) -> TypeDef {
@@ -3432,6 +4192,41 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for DataStructureFun
                 };
                 TypeDef::DataType(field.data_type().clone(), true)
             }
+            DataStructureFunction::Cardinality(collection) => TypeDef::DataType(
+                DataType::UInt64,
+                collection.expression_type(input_context).is_optional(),
+            ),
+            DataStructureFunction::MapKeys(map) | DataStructureFunction::MapValues(map) => {
+                let TypeDef::DataType(DataType::Map(entries, _), nullable) =
+                    map.expression_type(input_context)
+                else {
+                    unreachable!("map functions should only be called on maps")
+                };
+                let (key, value) = map_key_value(&entries);
+                let element = match self {
+                    DataStructureFunction::MapKeys(_) => key,
+                    _ => value,
+                };
+                TypeDef::DataType(
+                    DataType::List(Arc::new(Field::new(
+                        "item",
+                        element.data_type().clone(),
+                        element.is_nullable(),
+                    ))),
+                    nullable,
+                )
+            }
+            DataStructureFunction::MapGet {
+                map_expression,
+                key: _,
+            } => {
+                let TypeDef::DataType(DataType::Map(entries, _), _) =
+                    map_expression.expression_type(input_context)
+                else {
+                    unreachable!("element_at should only be called on maps")
+                };
+                TypeDef::DataType(map_key_value(&entries).1.data_type().clone(), true)
+            }
         }
     }
 }
@@ -3441,13 +4236,18 @@ enum JsonFunction {
     GetFirstJsonObject,
     GetJsonObjects,
     ExtractJsonString,
+    JsonValue,
+    JsonQuery,
+    // functions that don't take a path
+    JsonArrayElements,
+    IsJson,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub struct JsonExpression {
     function: JsonFunction,
     json_string: Box<Expression>,
-    path: Box<Expression>,
+    path: Option<Box<Expression>>,
 }
 
 impl JsonExpression {
@@ -3468,24 +4268,46 @@ impl JsonExpression {
         Ok(Expression::Json(JsonExpression {
             function,
             json_string: Box::new(json_string),
-            path: Box::new(path),
+            path: Some(Box::new(path)),
+        }))
+    }
+
+    fn new_without_path(function: JsonFunction, json_string: Expression) -> Result<Expression> {
+        Ok(Expression::Json(JsonExpression {
+            function,
+            json_string: Box::new(json_string),
+            path: None,
         }))
     }
 
     fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
-        let path_nullable = self.path.expression_type(input_context).is_optional();
         let json_nullable = self
             .json_string
             .expression_type(input_context)
             .is_optional();
-        let path_expr = self.path.generate(input_context);
         let json_string_expr = self.json_string.generate(input_context);
         let function_tokens = match self.function {
             JsonFunction::GetFirstJsonObject => quote!(get_first_json_object),
             JsonFunction::GetJsonObjects => quote!(get_json_objects),
             JsonFunction::ExtractJsonString => quote!(extract_json_string),
+            JsonFunction::JsonValue => quote!(json_value),
+            JsonFunction::JsonQuery => quote!(json_query),
+            JsonFunction::JsonArrayElements => quote!(json_array_elements),
+            JsonFunction::IsJson => quote!(is_json),
         };
 
+        let Some(path) = &self.path else {
+            let function: syn::Expr =
+                parse_quote!(arroyo_worker::operators::functions::json::#function_tokens);
+            return match (&self.function, json_nullable) {
+                (JsonFunction::IsJson, true) => parse_quote!(#json_string_expr.map(#function)),
+                (_, true) => parse_quote!(#json_string_expr.and_then(#function)),
+                (_, false) => parse_quote!(#function(#json_string_expr)),
+            };
+        };
+        let path_nullable = path.expression_type(input_context).is_optional();
+        let path_expr = path.generate(input_context);
+
         // Handle different nullabilities.
         match (path_nullable, json_nullable) {
             (true, true) => parse_quote!({
@@ -3519,31 +4341,40 @@ impl JsonExpression {
         }
     }
 
-    fn expression_type(&self, _input_context: &ValuePointerContext) -> TypeDef {
+    fn expression_type(&self, input_context: &ValuePointerContext) -> TypeDef {
         match self.function {
-            JsonFunction::GetFirstJsonObject => TypeDef::DataType(DataType::Utf8, true),
-            JsonFunction::GetJsonObjects => TypeDef::DataType(
+            JsonFunction::GetFirstJsonObject
+            | JsonFunction::ExtractJsonString
+            | JsonFunction::JsonValue
+            | JsonFunction::JsonQuery => TypeDef::DataType(DataType::Utf8, true),
+            JsonFunction::GetJsonObjects | JsonFunction::JsonArrayElements => TypeDef::DataType(
                 DataType::List(Arc::new(Field::new("item", DataType::Utf8, false))),
                 true,
             ),
-            JsonFunction::ExtractJsonString => TypeDef::DataType(DataType::Utf8, true),
+            JsonFunction::IsJson => TypeDef::DataType(
+                DataType::Boolean,
+                self.json_string
+                    .expression_type(input_context)
+                    .is_optional(),
+            ),
         }
     }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub struct RustUdfExpression {
-    name: String,
-    args: Vec<(TypeDef, Expression)>,
-    ret_type: TypeDef,
+    pub(crate) name: String,
+    pub(crate) args: Vec<(TypeDef, Expression)>,
+    pub(crate) ret_type: TypeDef,
+    // set for async UDFs, which are split out of projections into their own operator
+    pub(crate) async_config: Option<AsyncUdfConfig>,
 }
 
-impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for RustUdfExpression {
-    fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
-        let name = format_ident!("{}", &self.name);
-
-        let (defs, args): (Vec<_>, Vec<_>) = self
-            .args
+impl RustUdfExpression {
+    // binds each argument to a variable, returning None from the enclosing closure if a null is
+    // passed for a non-null parameter
+    fn bind_args(&self, input_context: &ValuePointerContext) -> (Vec<TokenStream>, Vec<Ident>) {
+        self.args
             .iter()
             .enumerate()
             .map(|(i, (def, expr))| {
@@ -3557,9 +4388,34 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for RustUdfExpressio
                     (true, false) => quote!(let #id = Some(#t)),
                     (false, true) => quote!(let #id = (#t)?),
                 };
-                (def, quote!(#id))
+                (def, id)
             })
-            .unzip();
+            .unzip()
+    }
+
+    /// For async UDFs, an `Option` of the future returned by the UDF, which is None if a null is
+    /// passed for a non-null parameter
+    pub(crate) fn generate_call(&self, input_context: &ValuePointerContext) -> syn::Expr {
+        let name = format_ident!("{}", &self.name);
+        let (defs, args) = self.bind_args(input_context);
+
+        parse_quote!({
+            (|| {
+                #(#defs; )*
+                Some(udfs::#name(#(#args, )*))
+            })()
+        })
+    }
+}
+
+impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for RustUdfExpression {
+    fn generate(&self, input_context: &ValuePointerContext) -> syn::Expr {
+        if self.async_config.is_some() {
+            panic!("async UDF {} appeared outside of a projection", self.name);
+        }
+
+        let name = format_ident!("{}", &self.name);
+        let (defs, args) = self.bind_args(input_context);
 
         let mut ret = quote!(udfs::#name(#(#args, )*));
 
@@ -3579,6 +4435,8 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for RustUdfExpressio
     fn expression_type(&self, input_context: &ValuePointerContext) -> TypeDef {
         self.ret_type.with_nullity(
             self.ret_type.is_optional()
+                // async UDFs return null if their calls time out
+                || self.async_config.is_some()
                 || self
                     .args
                     .iter()
@@ -3592,9 +4450,10 @@ impl CodeGenerator<ValuePointerContext, TypeDef, syn::Expr> for RustUdfExpressio
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub struct RustUdafExpression {
-    name: String,
-    args: Vec<(TypeDef, Expression)>,
-    ret_type: TypeDef,
+    pub(crate) name: String,
+    pub(crate) args: Vec<(TypeDef, Expression)>,
+    pub(crate) ret_type: TypeDef,
+    pub(crate) accumulator: Option<AccumulatorDef>,
 }
 impl RustUdafExpression {
     fn try_from_aggregate_udf(
@@ -3602,6 +4461,12 @@ impl RustUdafExpression {
         aggregate_udf: &AggregateUDF,
     ) -> Result<Self> {
         let udf_name = &aggregate_udf.fun.name;
+        if crate::pipeline::TimeSeriesFunction::NAMES.contains(&udf_name.as_str()) {
+            bail!(
+                "{}() is a window function, and must be used with OVER (PARTITION BY .. ORDER BY ..)",
+                udf_name
+            );
+        }
         let udf = ctx
             .schema_provider
             .udf_defs
@@ -3630,11 +4495,67 @@ impl RustUdafExpression {
             name: udf_name.to_string(),
             args: udf.args.clone().into_iter().zip(inputs).collect(),
             ret_type: udf.ret.clone(),
+            accumulator: udf.accumulator.clone(),
+        })
+    }
+
+    pub(crate) fn accumulator_type(&self) -> syn::Type {
+        let accumulator = format_ident!(
+            "{}",
+            self.accumulator
+                .as_ref()
+                .expect("only accumulator UDAFs have an accumulator type")
+                .name
+        );
+        parse_quote!(udfs::#accumulator)
+    }
+
+    // adds the row in the value context to `accumulator`, skipping it if any of the arguments
+    // that accumulate() doesn't take as an Option are null, like built-in aggregates do
+    pub(crate) fn accumulate(&self, accumulator: &Ident) -> syn::Expr {
+        let value_context = ValuePointerContext::new();
+        let terms: Vec<syn::Expr> = self
+            .args
+            .iter()
+            .map(|(def, expr)| {
+                let sub_expr = expr.generate(&value_context);
+                match (
+                    def.is_optional(),
+                    expr.expression_type(&value_context).is_optional(),
+                ) {
+                    (true, true) | (false, false) => parse_quote!(#sub_expr),
+                    (true, false) => parse_quote!(Some(#sub_expr)),
+                    (false, true) => parse_quote!((#sub_expr)?),
+                }
+            })
+            .collect();
+        let arg_idents: Vec<Ident> = (0..self.args.len())
+            .map(|i| format_ident!("udaf_arg_{}", i))
+            .collect();
+        parse_quote!({
+            if let Some((#(#arg_idents,)*)) = (|| Some((#(#terms,)*)))() {
+                #accumulator.accumulate(#(#arg_idents),*);
+            }
         })
     }
 }
 impl CodeGenerator<VecOfPointersContext, TypeDef, syn::Expr> for RustUdafExpression {
     fn generate(&self, input_context: &VecOfPointersContext) -> syn::Expr {
+        if self.accumulator.is_some() {
+            let accumulator_type = self.accumulator_type();
+            let accumulator: Ident = parse_quote!(accumulator);
+            let accumulate = self.accumulate(&accumulator);
+            let vec_arg = input_context.variable_ident();
+            let single_value_ident = ValuePointerContext::new().variable_ident();
+            return parse_quote!({
+                let mut #accumulator = #accumulator_type::default();
+                for #single_value_ident in #vec_arg.iter() {
+                    #accumulate
+                }
+                #accumulator.finish()
+            });
+        }
+
         let name = format_ident!("{}", &self.name);
 
         // early exit means there are terms that won't be included in the aggregate
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use arroyo_datastream::Operator;
+use arroyo_rpc::WatermarkAlignment;
 
 use crate::types::StructDef;
 
@@ -17,6 +18,32 @@ pub struct SqlSource {
     pub operator: Operator,
     pub processing_mode: ProcessingMode,
     pub idle_time: Option<Duration>,
+    pub watermarks: SourceWatermarks,
+}
+
+pub const DEFAULT_MAX_OUT_OF_ORDERNESS: Duration = Duration::from_secs(1);
+
+/// How the watermarks of a source are generated, as set by its `watermark.*` options
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceWatermarks {
+    pub period: Duration,
+    // how far the watermark trails the latest event time, if it isn't set by a column
+    pub max_out_of_orderness: Option<Duration>,
+    // whether the source generates watermarks for each partition that it reads itself
+    pub per_partition: bool,
+    // keeps the watermarks of the source within a max drift of the others in its group
+    pub alignment: Option<WatermarkAlignment>,
+}
+
+impl Default for SourceWatermarks {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(1),
+            max_out_of_orderness: None,
+            per_partition: false,
+            alignment: None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -32,6 +59,8 @@ pub enum SinkUpdateType {
     Allow,
     Disallow,
     Force,
+    // updates are written as upserts, and retractions as deletes
+    Upsert,
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+use anyhow::{bail, Result};
+use datafusion::sql::sqlparser::{
+    ast::{
+        visit_expressions_mut, Expr, FunctionArg, FunctionArgExpr, Ident, JoinConstraint,
+        JoinOperator, Query, Select, SelectItem, SetExpr, Statement, TableAlias, TableFactor,
+        TableWithJoins, Value, VisitMut,
+    },
+    dialect::PostgreSqlDialect,
+    parser::Parser,
+};
+
+use crate::match_recognize::normalize;
+
+/// Rewrites joins against `UNNEST(..)` and `LATERAL` subqueries, which DataFusion can't plan, into
+/// subqueries that compute the right side from each row of the table it's joined with, using the
+/// `unnest` function to explode arrays into rows. The subquery takes the alias of that table, and
+/// references to the alias of the right side are rewritten to use it.
+pub fn rewrite(statement: &mut Statement) -> Result<()> {
+    match statement {
+        Statement::Query(query) => rewrite_query(query),
+        Statement::Insert { source, .. } => rewrite_query(source),
+        Statement::CreateView { query, .. } => rewrite_query(query),
+        Statement::CreateTable {
+            query: Some(query), ..
+        } => rewrite_query(query),
+        _ => Ok(()),
+    }
+}
+
+fn rewrite_query(query: &mut Query) -> Result<()> {
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            rewrite_query(&mut cte.query)?;
+        }
+    }
+    rewrite_set_expr(&mut query.body)
+}
+
+fn rewrite_set_expr(body: &mut SetExpr) -> Result<()> {
+    match body {
+        SetExpr::Select(select) => rewrite_select(select),
+        SetExpr::Query(query) => rewrite_query(query),
+        SetExpr::SetOperation { left, right, .. } => {
+            rewrite_set_expr(left)?;
+            rewrite_set_expr(right)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn rewrite_select(select: &mut Select) -> Result<()> {
+    let mut renames = HashMap::new();
+    let mut from: Vec<TableWithJoins> = vec![];
+
+    for table in std::mem::take(&mut select.from) {
+        let relation = rewrite_relation(table.relation)?;
+
+        // the comma form, as in `FROM t, UNNEST(t.tags) AS u(tag)`
+        if table.joins.is_empty() && is_correlated(&relation) {
+            let Some(left) = from.pop() else {
+                bail!("{} must follow the table it reads from", relation);
+            };
+            from.push(expand(left, relation, &mut renames)?);
+            continue;
+        }
+
+        let mut expanded = TableWithJoins {
+            relation,
+            joins: vec![],
+        };
+        for mut join in table.joins {
+            join.relation = rewrite_relation(join.relation)?;
+            if !is_correlated(&join.relation) {
+                expanded.joins.push(join);
+                continue;
+            }
+
+            match &join.join_operator {
+                JoinOperator::CrossJoin
+                | JoinOperator::Inner(JoinConstraint::None)
+                | JoinOperator::Inner(JoinConstraint::On(Expr::Value(Value::Boolean(true)))) => {}
+                _ => bail!(
+                    "{} can only be used in a CROSS JOIN or an INNER JOIN ON TRUE",
+                    join.relation
+                ),
+            }
+            expanded = expand(expanded, join.relation, &mut renames)?;
+        }
+        from.push(expanded);
+    }
+
+    select.from = from;
+    rename(select, &renames);
+    Ok(())
+}
+
+fn rewrite_relation(relation: TableFactor) -> Result<TableFactor> {
+    match relation {
+        TableFactor::Derived {
+            lateral: false,
+            mut subquery,
+            alias,
+        } => {
+            rewrite_query(&mut subquery)?;
+            Ok(TableFactor::Derived {
+                lateral: false,
+                subquery,
+                alias,
+            })
+        }
+        relation => Ok(relation),
+    }
+}
+
+/// Whether the relation is computed from the rows of the table before it
+fn is_correlated(relation: &TableFactor) -> bool {
+    as_unnest(relation).is_some() || matches!(relation, TableFactor::Derived { lateral: true, .. })
+}
+
+/// The array expression and alias of an `UNNEST(..)` relation
+fn as_unnest(relation: &TableFactor) -> Option<(&Expr, Option<&TableAlias>)> {
+    match relation {
+        // the Postgres dialect parses UNNEST as a table function
+        TableFactor::Table {
+            name,
+            args: Some(args),
+            alias,
+            ..
+        } if name.to_string().eq_ignore_ascii_case("unnest") => match args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(expr))] => Some((expr, alias.as_ref())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The name of the column produced by `UNNEST(..) AS alias`, which as in Postgres is the alias
+/// itself unless columns are listed
+fn unnest_column(alias: Option<&TableAlias>) -> Result<Ident> {
+    match alias {
+        None => Ok(Ident::new("unnest")),
+        Some(alias) => match alias.columns.as_slice() {
+            [] => Ok(alias.name.clone()),
+            [column] => Ok(column.clone()),
+            _ => bail!("UNNEST produces a single column, but {} were named", alias),
+        },
+    }
+}
+
+/// Replaces a table and the correlated relation joined with it by a subquery that computes both
+fn expand(
+    left: TableWithJoins,
+    right: TableFactor,
+    renames: &mut HashMap<String, Ident>,
+) -> Result<TableWithJoins> {
+    if !left.joins.is_empty() {
+        bail!("{} must directly follow the table it reads from", right);
+    }
+
+    let alias = match &left.relation {
+        TableFactor::Table {
+            alias: Some(alias), ..
+        }
+        | TableFactor::Derived {
+            alias: Some(alias), ..
+        } => alias.name.clone(),
+        TableFactor::Table {
+            name, alias: None, ..
+        } => name.0.last().unwrap().clone(),
+        _ => bail!("the table that {} reads from must have an alias", right),
+    };
+
+    let (right_alias, sql) = match &right {
+        TableFactor::Derived {
+            subquery,
+            alias: right_alias,
+            ..
+        } => (
+            right_alias.as_ref(),
+            lateral_select(&left, &alias, subquery, renames)?,
+        ),
+        right => {
+            let (array, right_alias) = as_unnest(right).unwrap();
+            let mut array = array.clone();
+            rename(&mut array, renames);
+            (
+                right_alias,
+                format!(
+                    "SELECT *, unnest({}) AS {} FROM {}",
+                    array,
+                    unnest_column(right_alias)?,
+                    left
+                ),
+            )
+        }
+    };
+
+    if let Some(right_alias) = right_alias {
+        renames.insert(normalize(&right_alias.name), alias.clone());
+    }
+
+    Ok(TableWithJoins {
+        relation: derived(&sql, alias)?,
+        joins: vec![],
+    })
+}
+
+/// A query that adds the columns of a `LATERAL` subquery to each row of `left`. Subqueries may
+/// compute columns from the row, filter it, and read from a single `UNNEST(..)` of its columns,
+/// but not aggregate.
+fn lateral_select(
+    left: &TableWithJoins,
+    alias: &Ident,
+    subquery: &Query,
+    renames: &HashMap<String, Ident>,
+) -> Result<String> {
+    let SetExpr::Select(select) = subquery.body.as_ref() else {
+        bail!("LATERAL subqueries must be a single SELECT");
+    };
+    if subquery.with.is_some()
+        || !subquery.order_by.is_empty()
+        || subquery.limit.is_some()
+        || !select.group_by.is_empty()
+        || select.having.is_some()
+    {
+        bail!("LATERAL subqueries only support projections, filters and UNNEST");
+    }
+
+    let mut renames = renames.clone();
+    let (source, unnest_column) = match select.from.as_slice() {
+        [] => (left.to_string(), None),
+        [TableWithJoins { relation, joins }] if joins.is_empty() => {
+            let Some((array, unnest_alias)) = as_unnest(relation) else {
+                bail!("LATERAL subqueries can only read from UNNEST(..)");
+            };
+            let mut array = array.clone();
+            rename(&mut array, &renames);
+            let column = unnest_column(unnest_alias)?;
+            if let Some(unnest_alias) = unnest_alias {
+                renames.insert(normalize(&unnest_alias.name), alias.clone());
+            }
+            (
+                format!(
+                    "(SELECT *, unnest({}) AS {} FROM {}) AS {}",
+                    array, column, left, alias
+                ),
+                Some(column),
+            )
+        }
+        _ => bail!("LATERAL subqueries can only read from a single UNNEST(..)"),
+    };
+
+    let mut columns = vec!["*".to_string()];
+    for item in &select.projection {
+        let (mut expr, name) = match item {
+            SelectItem::UnnamedExpr(expr @ Expr::Identifier(name)) => (expr.clone(), name.clone()),
+            SelectItem::UnnamedExpr(expr @ Expr::CompoundIdentifier(names)) => {
+                (expr.clone(), names.last().unwrap().clone())
+            }
+            SelectItem::UnnamedExpr(expr) => {
+                bail!("'{}' in a LATERAL subquery must be named with AS", expr)
+            }
+            SelectItem::ExprWithAlias { expr, alias } => (expr.clone(), alias.clone()),
+            _ => bail!("LATERAL subqueries can't select '{}'", item),
+        };
+        rename(&mut expr, &renames);
+
+        // the unnested column is already selected by the *
+        let selected = match &expr {
+            Expr::Identifier(ident) => Some(ident),
+            Expr::CompoundIdentifier(names) => names.last(),
+            _ => None,
+        };
+        if let (Some(column), Some(selected)) = (&unnest_column, selected) {
+            if normalize(selected) == normalize(column) && normalize(&name) == normalize(column) {
+                continue;
+            }
+        }
+        columns.push(format!("{} AS {}", expr, name));
+    }
+
+    let mut sql = format!("SELECT {} FROM {}", columns.join(", "), source);
+    if let Some(selection) = &select.selection {
+        let mut selection = selection.clone();
+        rename(&mut selection, &renames);
+        sql.push_str(&format!(" WHERE {}", selection));
+    }
+    Ok(sql)
+}
+
+fn derived(sql: &str, alias: Ident) -> Result<TableFactor> {
+    let subquery = Parser::new(&PostgreSqlDialect {})
+        .try_with_sql(sql)?
+        .parse_query()?;
+
+    Ok(TableFactor::Derived {
+        lateral: false,
+        subquery: Box::new(subquery),
+        alias: Some(TableAlias {
+            name: alias,
+            columns: vec![],
+        }),
+    })
+}
+
+/// Points references to the aliases of expanded relations at the subqueries that replaced them
+fn rename<V: VisitMut>(node: &mut V, renames: &HashMap<String, Ident>) {
+    if renames.is_empty() {
+        return;
+    }
+
+    visit_expressions_mut(node, |expr| {
+        if let Expr::CompoundIdentifier(names) = expr {
+            if names.len() == 2 {
+                if let Some(alias) = renames.get(&normalize(&names[0])) {
+                    names[0] = alias.clone();
+                }
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewritten(sql: &str) -> String {
+        let mut statement = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        rewrite(&mut statement).unwrap();
+        statement.to_string()
+    }
+
+    #[test]
+    fn test_unnest() {
+        assert_eq!(
+            rewritten("SELECT o.id, i.item FROM orders AS o CROSS JOIN UNNEST(o.items) AS i(item)"),
+            "SELECT o.id, o.item FROM (SELECT *, unnest(o.items) AS item FROM orders AS o) AS o"
+        );
+
+        assert_eq!(
+            rewritten("SELECT id, tag FROM orders, UNNEST(tags) AS tag WHERE tag <> 'x'"),
+            "SELECT id, tag FROM (SELECT *, unnest(tags) AS tag FROM orders) AS orders WHERE tag <> 'x'"
+        );
+    }
+
+    #[test]
+    fn test_lateral() {
+        assert_eq!(
+            rewritten(
+                "SELECT o.id, l.total FROM orders o JOIN LATERAL \
+                (SELECT i.item, o.price * 2 AS total FROM UNNEST(o.items) AS i(item) WHERE i.item <> '') l ON TRUE"
+            ),
+            "SELECT o.id, o.total FROM (SELECT *, o.price * 2 AS total \
+            FROM (SELECT *, unnest(o.items) AS item FROM orders AS o) AS o WHERE o.item <> '') AS o"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_joins() {
+        let mut statement = Parser::parse_sql(
+            &PostgreSqlDialect {},
+            "SELECT * FROM orders o LEFT JOIN UNNEST(o.items) AS i(item) ON i.item = o.id",
+        )
+        .unwrap()
+        .remove(0);
+        assert!(rewrite(&mut statement).is_err());
+    }
+}
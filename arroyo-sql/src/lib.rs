@@ -8,20 +8,28 @@ use arroyo_connectors::{Connection, Connector};
 use arroyo_datastream::Program;
 use datafusion::physical_plan::functions::make_scalar_function;
 
+mod async_udf;
 pub(crate) mod code_gen;
+mod ddl;
 pub mod expressions;
 pub mod external;
 pub mod json_schema;
+mod lateral;
+mod lookup;
+mod match_recognize;
 mod operators;
 mod optimizations;
 mod pipeline;
 mod plan_graph;
 pub mod schemas;
+mod statement_set;
 mod tables;
 pub mod types;
+mod wasm_udfs;
 
 use datafusion::prelude::create_udf;
 
+use datafusion::sql::sqlparser::ast::Statement;
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::sql::{planner::ContextProvider, TableReference};
@@ -31,7 +39,7 @@ use datafusion_expr::{
 };
 use datafusion_expr::{
     AccumulatorFactoryFunction, LogicalPlan, ReturnTypeFunction, Signature, StateTypeFunction,
-    Volatility, WindowUDF,
+    TypeSignature, Volatility, WindowUDF,
 };
 use expressions::{Expression, ExpressionContext};
 use pipeline::{SqlOperator, SqlPipelineBuilder};
@@ -40,14 +48,20 @@ use schemas::window_arrow_struct;
 use tables::{schema_defs, ConnectorTable, Insert, Table};
 
 use crate::code_gen::{CodeGenerator, ValuePointerContext};
-use crate::types::{StructDef, StructField, TypeDef};
+use crate::types::{map_key_value, StructDef, StructField, TypeDef};
 use arroyo_rpc::api_types::connections::{ConnectionSchema, ConnectionType};
 use arroyo_rpc::formats::{Format, JsonFormat};
 use datafusion_common::DataFusionError;
-use quote::ToTokens;
+use quote::{quote, ToTokens};
 use std::time::{Duration, SystemTime};
-use std::{collections::HashMap, sync::Arc};
-use syn::{parse_quote, parse_str, FnArg, Item, ReturnType, Visibility};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use syn::{
+    parse_quote, parse_str, Attribute, FnArg, ImplItem, Item, ItemImpl, ItemStruct, LitInt,
+    ReturnType, Visibility,
+};
 
 const DEFAULT_IDLE_TIME: Option<Duration> = Some(Duration::from_secs(5 * 60));
 
@@ -59,6 +73,77 @@ pub struct UdfDef {
     args: Vec<TypeDef>,
     ret: TypeDef,
     def: String,
+    // set for UDAFs that are computed incrementally by an accumulator struct
+    accumulator: Option<AccumulatorDef>,
+    // set for async UDFs, which are called concurrently by their own operator
+    async_config: Option<AsyncUdfConfig>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct AccumulatorDef {
+    // the name of the struct
+    pub name: String,
+    // whether the struct defines retract(), which removes the rows of another accumulator
+    pub retractable: bool,
+}
+
+/// How the calls of an async UDF are made, configured by an attribute on its definition like
+/// `#[udf(ordered, max_concurrency = 10, timeout_ms = 1000, retries = 2)]`
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct AsyncUdfConfig {
+    // whether results are emitted in the order of their rows, rather than as their calls complete
+    pub ordered: bool,
+    pub max_concurrency: u64,
+    // how long each attempt of a call may take, after which it's retried
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+impl Default for AsyncUdfConfig {
+    fn default() -> Self {
+        Self {
+            ordered: false,
+            max_concurrency: 100,
+            timeout: Duration::from_secs(5),
+            retries: 0,
+        }
+    }
+}
+
+impl AsyncUdfConfig {
+    fn from_attributes(name: &str, attrs: &[Attribute]) -> Result<Self> {
+        let mut config = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("udf")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("ordered") {
+                    config.ordered = true;
+                } else if meta.path.is_ident("unordered") {
+                    config.ordered = false;
+                } else if meta.path.is_ident("max_concurrency") {
+                    config.max_concurrency = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                } else if meta.path.is_ident("timeout_ms") {
+                    config.timeout =
+                        Duration::from_millis(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                } else if meta.path.is_ident("retries") {
+                    config.retries = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                } else {
+                    return Err(meta.error(
+                        "expected ordered, unordered, max_concurrency, timeout_ms or retries",
+                    ));
+                }
+                Ok(())
+            })
+            .map_err(|e| anyhow!("invalid #[udf] attribute on {}: {}", name, e))?;
+        }
+
+        if config.max_concurrency == 0 {
+            bail!("max_concurrency of async UDF {} must be at least 1", name);
+        }
+        if config.timeout.is_zero() {
+            bail!("timeout_ms of async UDF {} must be at least 1", name);
+        }
+        Ok(config)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -69,6 +154,8 @@ pub struct ArroyoSchemaProvider {
     pub aggregate_functions: HashMap<String, Arc<AggregateUDF>>,
     pub connections: HashMap<String, Connection>,
     pub udf_defs: HashMap<String, UdfDef>,
+    // tables that the query reads FOR SYSTEM_TIME AS OF, which may be lookup tables
+    system_time_tables: HashSet<String>,
     config_options: datafusion::config::ConfigOptions,
 }
 
@@ -80,35 +167,93 @@ impl ArroyoSchemaProvider {
         let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
 
         let window_return_type = Arc::new(window_arrow_struct());
+        let interval = DataType::Interval(datatypes::IntervalUnit::MonthDayNano);
+        // hop() and tumble() take an optional offset after their durations, and tumble() also
+        // takes the time zone that its windows are aligned to, followed by any of the
+        // allowed_lateness(), late_data_sink() and emit_partial() arguments, in any order
+        let utf8 = DataType::Utf8;
+        let option_types = [
+            vec![interval.clone()],
+            vec![utf8.clone()],
+            vec![interval.clone(), interval.clone()],
+            vec![interval.clone(), utf8.clone()],
+            vec![utf8.clone(), interval.clone()],
+            vec![interval.clone(), interval.clone(), utf8.clone()],
+            vec![interval.clone(), utf8.clone(), interval.clone()],
+            vec![utf8.clone(), interval.clone(), interval.clone()],
+        ];
+        let window_udf = |name: &str, durations: usize, timezone: bool| {
+            let return_type = window_return_type.clone();
+            let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(return_type.clone()));
+
+            let mut signatures = vec![];
+            for args in [
+                vec![interval.clone(); durations],
+                vec![interval.clone(); durations + 1],
+            ] {
+                if timezone {
+                    let mut with_timezone = args.clone();
+                    with_timezone.push(DataType::Utf8);
+                    for args in [args.clone(), with_timezone] {
+                        for options in &option_types {
+                            let mut with_options = args.clone();
+                            with_options.extend(options.iter().cloned());
+                            signatures.push(TypeSignature::Exact(with_options));
+                        }
+                        signatures.push(TypeSignature::Exact(args));
+                    }
+                } else {
+                    signatures.push(TypeSignature::Exact(args));
+                }
+            }
+
+            Arc::new(ScalarUDF::new(
+                name,
+                &Signature::one_of(signatures, Volatility::Volatile),
+                &return_type,
+                &make_scalar_function(fn_impl),
+            ))
+        };
+        functions.insert("hop".to_string(), window_udf("hop", 2, false));
+        functions.insert("tumble".to_string(), window_udf("tumble", 1, true));
         functions.insert(
-            "hop".to_string(),
+            "session".to_string(),
             Arc::new(create_udf(
-                "hop",
-                vec![
-                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
-                    DataType::Interval(datatypes::IntervalUnit::MonthDayNano),
-                ],
-                window_return_type.clone(),
+                "session",
+                vec![DataType::Interval(datatypes::IntervalUnit::MonthDayNano)],
+                window_return_type,
                 Volatility::Volatile,
                 make_scalar_function(fn_impl),
             )),
         );
+        // arguments to tumble(), which set how late its rows may be, where those that are later
+        // are written, and how often the results of open windows are emitted
         functions.insert(
-            "tumble".to_string(),
+            "emit_partial".to_string(),
             Arc::new(create_udf(
-                "tumble",
-                vec![DataType::Interval(datatypes::IntervalUnit::MonthDayNano)],
-                window_return_type.clone(),
+                "emit_partial",
+                vec![interval.clone()],
+                Arc::new(interval.clone()),
                 Volatility::Volatile,
                 make_scalar_function(fn_impl),
             )),
         );
         functions.insert(
-            "session".to_string(),
+            "allowed_lateness".to_string(),
             Arc::new(create_udf(
-                "session",
-                vec![DataType::Interval(datatypes::IntervalUnit::MonthDayNano)],
-                window_return_type,
+                "allowed_lateness",
+                vec![interval.clone()],
+                Arc::new(interval.clone()),
+                Volatility::Volatile,
+                make_scalar_function(fn_impl),
+            )),
+        );
+        functions.insert(
+            "late_data_sink".to_string(),
+            Arc::new(create_udf(
+                "late_data_sink",
+                vec![DataType::Utf8],
+                Arc::new(DataType::Utf8),
                 Volatility::Volatile,
                 make_scalar_function(fn_impl),
             )),
@@ -134,6 +279,38 @@ impl ArroyoSchemaProvider {
                 )
             }),
         );
+        for (name, arg_count) in [("map_keys", 1), ("map_values", 1), ("element_at", 2)] {
+            let return_type: ReturnTypeFunction = Arc::new(move |args| {
+                let Some(DataType::Map(entries, _)) = args.get(0) else {
+                    return Err(DataFusionError::Plan(format!(
+                        "{} may only be called on maps",
+                        name
+                    )));
+                };
+                let (key, value) = map_key_value(entries);
+                Ok(Arc::new(match name {
+                    "map_keys" => {
+                        DataType::List(Arc::new(Field::new("item", key.data_type().clone(), false)))
+                    }
+                    "map_values" => DataType::List(Arc::new(Field::new(
+                        "item",
+                        value.data_type().clone(),
+                        value.is_nullable(),
+                    ))),
+                    _ => value.data_type().clone(),
+                }))
+            });
+            functions.insert(
+                name.to_string(),
+                Arc::new(ScalarUDF::new(
+                    name,
+                    &Signature::any(arg_count, Volatility::Immutable),
+                    &return_type,
+                    &make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
         functions.insert(
             "get_first_json_object".to_string(),
             Arc::new(create_udf(
@@ -185,13 +362,207 @@ impl ArroyoSchemaProvider {
             )),
         );
 
+        for (name, args, return_type) in [
+            (
+                "json_extract",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Utf8,
+            ),
+            (
+                "json_value",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Utf8,
+            ),
+            (
+                "json_query",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Utf8,
+            ),
+            (
+                "json_array_elements",
+                vec![DataType::Utf8],
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, false))),
+            ),
+            ("is_json", vec![DataType::Utf8], DataType::Boolean),
+        ] {
+            functions.insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    args,
+                    Arc::new(return_type),
+                    Volatility::Volatile,
+                    make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
+        functions.insert(
+            "hll_sketch_estimate".to_string(),
+            Arc::new(create_udf(
+                "hll_sketch_estimate",
+                vec![DataType::Binary],
+                Arc::new(DataType::Int64),
+                Volatility::Immutable,
+                make_scalar_function(fn_impl),
+            )),
+        );
+
+        // geospatial functions, over points and polygons as WKT
+        for (name, args, return_type) in [
+            (
+                "st_point",
+                vec![DataType::Float64, DataType::Float64],
+                DataType::Utf8,
+            ),
+            (
+                "st_distance",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Float64,
+            ),
+            (
+                "st_contains",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Boolean,
+            ),
+            (
+                "st_within",
+                vec![DataType::Utf8, DataType::Utf8],
+                DataType::Boolean,
+            ),
+            (
+                "geohash_encode",
+                vec![DataType::Float64, DataType::Float64, DataType::Int64],
+                DataType::Utf8,
+            ),
+            ("geohash_decode", vec![DataType::Utf8], DataType::Utf8),
+        ] {
+            functions.insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    args,
+                    Arc::new(return_type),
+                    Volatility::Immutable,
+                    make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
+        // string functions that DataFusion doesn't provide
+        let utf8_list = DataType::List(Arc::new(Field::new("item", DataType::Utf8, false)));
+        for (name, signature, return_type) in [
+            (
+                "regexp_extract",
+                Signature::one_of(
+                    vec![
+                        TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8]),
+                        TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8, DataType::Int64]),
+                    ],
+                    Volatility::Immutable,
+                ),
+                DataType::Utf8,
+            ),
+            (
+                "regexp_like",
+                Signature::exact(vec![DataType::Utf8, DataType::Utf8], Volatility::Immutable),
+                DataType::Boolean,
+            ),
+            (
+                "split_to_array",
+                Signature::exact(vec![DataType::Utf8, DataType::Utf8], Volatility::Immutable),
+                utf8_list,
+            ),
+            (
+                "parse_url",
+                Signature::one_of(
+                    vec![
+                        TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8]),
+                        TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8, DataType::Utf8]),
+                    ],
+                    Volatility::Immutable,
+                ),
+                DataType::Utf8,
+            ),
+            (
+                "format",
+                Signature::variadic_any(Volatility::Immutable),
+                DataType::Utf8,
+            ),
+        ] {
+            let return_type = Arc::new(return_type);
+            let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(return_type.clone()));
+            functions.insert(
+                name.to_string(),
+                Arc::new(ScalarUDF::new(
+                    name,
+                    &signature,
+                    &return_type,
+                    &make_scalar_function(fn_impl),
+                )),
+            );
+        }
+
+        // aggregates that DataFusion doesn't provide; they're compiled as built-in aggregates or
+        // window functions rather than UDAFs
+        let mut aggregate_functions = HashMap::new();
+        for (name, signature, return_type) in [
+            (
+                "approx_count_distinct",
+                Signature::any(1, Volatility::Immutable),
+                DataType::Int64,
+            ),
+            (
+                "hll_sketch_agg",
+                Signature::any(1, Volatility::Immutable),
+                DataType::Binary,
+            ),
+            (
+                "hll_union_agg",
+                Signature::exact(vec![DataType::Binary], Volatility::Immutable),
+                DataType::Binary,
+            ),
+            // time series functions, which are only computed as window functions
+            (
+                "rate",
+                Signature::any(1, Volatility::Immutable),
+                DataType::Float64,
+            ),
+            (
+                "counter_delta",
+                Signature::any(1, Volatility::Immutable),
+                DataType::Float64,
+            ),
+            (
+                "ewma",
+                Signature::any(2, Volatility::Immutable),
+                DataType::Float64,
+            ),
+        ] {
+            let return_type = Arc::new(return_type);
+            let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(return_type.clone()));
+            let accumulator: AccumulatorFactoryFunction = Arc::new(|_| unreachable!());
+            let state_type: StateTypeFunction = Arc::new(|_| unreachable!());
+            aggregate_functions.insert(
+                name.to_string(),
+                Arc::new(AggregateUDF::new(
+                    name,
+                    &signature,
+                    &return_type,
+                    &accumulator,
+                    &state_type,
+                )),
+            );
+        }
+
         Self {
             tables,
             functions,
-            aggregate_functions: HashMap::new(),
+            aggregate_functions,
             source_defs: HashMap::new(),
             connections: HashMap::new(),
             udf_defs: HashMap::new(),
+            system_time_tables: HashSet::new(),
             config_options: datafusion::config::ConfigOptions::new(),
         }
     }
@@ -207,6 +578,24 @@ impl ArroyoSchemaProvider {
         );
     }
 
+    /// Adds a saved view, which queries can read from like a table. Views are planned when they
+    /// are added, so they can only refer to tables and views that were added before them.
+    pub fn add_view(&mut self, name: &str, query: &str) -> Result<()> {
+        let sql = format!("CREATE VIEW {} AS {}", name, query);
+        let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, &sql)?;
+        if statements.len() != 1 {
+            bail!("view {} must be defined by a single query", name);
+        }
+        let mut statement = statements.remove(0);
+        lateral::rewrite(&mut statement)?;
+
+        let Some(table) = Table::try_from_statement(&statement, self)? else {
+            bail!("view {} must be defined by a query", name);
+        };
+        self.insert_table(table);
+        Ok(())
+    }
+
     fn insert_table(&mut self, table: Table) {
         self.tables.insert(table.name().to_string(), table);
     }
@@ -235,9 +624,20 @@ impl ArroyoSchemaProvider {
     pub fn add_rust_udf(&mut self, body: &str) -> Result<()> {
         let file = syn::parse_file(body)?;
 
+        let mut structs = HashMap::new();
+        let mut impls = vec![];
         for item in file.items {
-            let Item::Fn(mut function) = item else {
-                continue;
+            let mut function = match item {
+                Item::Fn(function) => function,
+                Item::Struct(item_struct) => {
+                    structs.insert(item_struct.ident.to_string(), item_struct);
+                    continue;
+                }
+                Item::Impl(item_impl) => {
+                    impls.push(item_impl);
+                    continue;
+                }
+                _ => continue,
             };
 
             let mut args: Vec<TypeDef> = vec![];
@@ -272,62 +672,283 @@ impl ArroyoSchemaProvider {
             if vec_arguments > 0 && vec_arguments != args.len() {
                 bail!("all arguments must be vectors or none");
             }
-            if vec_arguments > 0 {
-                let return_type = Arc::new(ret.as_datatype().unwrap().clone());
-                let name = function.sig.ident.to_string();
-                let signature = Signature::exact(
-                    args.iter()
-                        .map(|t| t.as_datatype().unwrap().clone())
-                        .collect(),
-                    Volatility::Volatile,
-                );
-                let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(return_type.clone()));
-                let accumulator: AccumulatorFactoryFunction = Arc::new(|_| unreachable!());
-                let state_type: StateTypeFunction = Arc::new(|_| unreachable!());
-                let udaf =
-                    AggregateUDF::new(&name, &signature, &return_type, &accumulator, &state_type);
-                self.aggregate_functions
-                    .insert(function.sig.ident.to_string(), Arc::new(udaf));
+
+            let name = function.sig.ident.to_string();
+            let async_config = if function.sig.asyncness.is_some() {
+                if vec_arguments > 0 {
+                    bail!("UDAF {} can't be async", name);
+                }
+                Some(AsyncUdfConfig::from_attributes(&name, &function.attrs)?)
             } else {
-                let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
-
-                if self
-                    .functions
-                    .insert(
-                        function.sig.ident.to_string(),
-                        Arc::new(create_udf(
-                            &function.sig.ident.to_string(),
-                            args.iter()
-                                .map(|t| t.as_datatype().unwrap().clone())
-                                .collect(),
-                            Arc::new(ret.as_datatype().unwrap().clone()),
-                            Volatility::Volatile,
-                            make_scalar_function(fn_impl),
-                        )),
-                    )
-                    .is_some()
+                if function
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("udf"))
                 {
-                    bail!("Could not register UDF '{}', as there is already a built-in function with that name",
-                        function.sig.ident.to_string());
-                };
+                    bail!(
+                        "#[udf] attributes are only allowed on async UDFs, but {} isn't async",
+                        name
+                    );
+                }
+                None
+            };
+            function.attrs.retain(|attr| !attr.path().is_ident("udf"));
+
+            if vec_arguments > 0 {
+                self.register_udaf(&name, &args, &ret)?;
+            } else {
+                self.register_udf(&name, &args, &ret)?;
             }
 
             function.vis = Visibility::Public(Default::default());
 
             self.udf_defs.insert(
-                function.sig.ident.to_string(),
+                name,
                 UdfDef {
                     args,
                     ret,
                     def: function.to_token_stream().to_string(),
+                    accumulator: None,
+                    async_config,
+                },
+            );
+        }
+
+        for item_impl in impls {
+            let syn::Type::Path(self_type) = &*item_impl.self_ty else {
+                continue;
+            };
+            let Some(ident) = self_type.path.get_ident() else {
+                continue;
+            };
+            let is_accumulator = item_impl.trait_.is_none()
+                && item_impl.items.iter().any(
+                    |item| matches!(item, ImplItem::Fn(method) if method.sig.ident == "accumulate"),
+                );
+            if !is_accumulator {
+                continue;
+            }
+            let item_struct = structs.remove(&ident.to_string()).ok_or_else(|| {
+                anyhow!(
+                    "the accumulator struct {} must be defined along with its methods",
+                    ident
+                )
+            })?;
+            self.add_accumulator_udaf(item_struct, item_impl)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a UDAF that's computed incrementally by an accumulator struct, which is called by the
+    /// snake case name of the struct. Its impl block defines
+    ///  * `accumulate(&mut self, ...)`, which adds the arguments of a row
+    ///  * `merge(&mut self, other: &Self)`, which adds the rows of another accumulator
+    ///  * `finish(&self) -> T`, which computes the result
+    ///  * optionally `retract(&mut self, other: &Self)`, which removes the rows of another
+    ///    accumulator, and is needed for updating inputs
+    ///
+    /// Accumulators are kept in state, so the struct must derive `Default`, `Clone`, `Debug`,
+    /// `PartialEq`, `bincode::Encode` and `bincode::Decode`.
+    fn add_accumulator_udaf(
+        &mut self,
+        mut item_struct: ItemStruct,
+        mut item_impl: ItemImpl,
+    ) -> Result<()> {
+        let struct_name = item_struct.ident.to_string();
+        let name = snake_case(&struct_name);
+
+        let mut args = None;
+        let mut ret = None;
+        let mut mergeable = false;
+        let mut retractable = false;
+        for item in &mut item_impl.items {
+            let ImplItem::Fn(method) = item else {
+                continue;
+            };
+            method.vis = Visibility::Public(Default::default());
+            let method_name = method.sig.ident.to_string();
+            let mut inputs = method.sig.inputs.iter();
+            let Some(FnArg::Receiver(receiver)) = inputs.next() else {
+                continue;
+            };
+            let takes_mut_self = receiver.reference.is_some() && receiver.mutability.is_some();
+            match method_name.as_str() {
+                "accumulate" => {
+                    if !takes_mut_self {
+                        bail!("{}::accumulate() must take &mut self", struct_name);
+                    }
+                    args = Some(
+                        inputs
+                            .enumerate()
+                            .map(|(i, arg)| match arg {
+                                FnArg::Typed(t) => (&*t.ty).try_into().map_err(|_| {
+                                    anyhow!(
+                                        "Could not convert arg {} of {}::accumulate() into a SQL data type",
+                                        i,
+                                        struct_name
+                                    )
+                                }),
+                                FnArg::Receiver(_) => unreachable!(),
+                            })
+                            .collect::<Result<Vec<TypeDef>>>()?,
+                    );
+                }
+                "merge" | "retract" => {
+                    if !takes_mut_self || inputs.count() != 1 {
+                        bail!(
+                            "{}::{}() must take &mut self and another accumulator",
+                            struct_name,
+                            method_name
+                        );
+                    }
+                    if method_name == "merge" {
+                        mergeable = true;
+                    } else {
+                        retractable = true;
+                    }
+                }
+                "finish" => {
+                    ret = Some(match &method.sig.output {
+                        ReturnType::Default => {
+                            bail!("return type must be specified in {}::finish()", struct_name)
+                        }
+                        ReturnType::Type(_, t) => (&**t).try_into().map_err(|_| {
+                            anyhow!(
+                                "Could not convert the return type of {}::finish() into a SQL data type",
+                                struct_name
+                            )
+                        })?,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let (Some(args), Some(ret), true) = (args, ret, mergeable) else {
+            bail!(
+                "the accumulator {} must define accumulate(), merge() and finish()",
+                struct_name
+            );
+        };
+        if args.is_empty() {
+            bail!(
+                "{}::accumulate() must take at least one argument",
+                struct_name
+            );
+        }
+        self.register_udaf(&name, &args, &ret)?;
+
+        item_struct.vis = Visibility::Public(Default::default());
+        let def = quote!(#item_struct #item_impl).to_string();
+        self.udf_defs.insert(
+            name,
+            UdfDef {
+                args,
+                ret,
+                def,
+                accumulator: Some(AccumulatorDef {
+                    name: struct_name,
+                    retractable,
+                }),
+                async_config: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Adds a UDF for each function exported by a base64-encoded WebAssembly module, which lets
+    /// UDFs be written in any language that compiles to WebAssembly. See
+    /// [`wasm_udfs::parse_wasm_udfs`] for what the module may contain.
+    pub fn add_wasm_udfs(&mut self, definition: &str) -> Result<()> {
+        for udf in wasm_udfs::parse_wasm_udfs(definition)? {
+            self.register_udf(&udf.name, &udf.args, &udf.ret)?;
+            self.udf_defs.insert(
+                udf.name,
+                UdfDef {
+                    args: udf.args,
+                    ret: udf.ret,
+                    def: udf.def,
+                    accumulator: None,
+                    async_config: None,
                 },
             );
         }
+        Ok(())
+    }
+
+    fn register_udf(&mut self, name: &str, args: &[TypeDef], ret: &TypeDef) -> Result<()> {
+        let fn_impl = |args: &[ArrayRef]| Ok(Arc::new(args[0].clone()) as ArrayRef);
+
+        if self
+            .functions
+            .insert(
+                name.to_string(),
+                Arc::new(create_udf(
+                    name,
+                    args.iter()
+                        .map(|t| t.as_datatype().unwrap().clone())
+                        .collect(),
+                    Arc::new(ret.as_datatype().unwrap().clone()),
+                    Volatility::Volatile,
+                    make_scalar_function(fn_impl),
+                )),
+            )
+            .is_some()
+        {
+            bail!(
+                "Could not register UDF '{}', as there is already a built-in function with that name",
+                name
+            );
+        }
+        Ok(())
+    }
 
+    fn register_udaf(&mut self, name: &str, args: &[TypeDef], ret: &TypeDef) -> Result<()> {
+        let return_type = Arc::new(ret.as_datatype().unwrap().clone());
+        let signature = Signature::exact(
+            args.iter()
+                .map(|t| t.as_datatype().unwrap().clone())
+                .collect(),
+            Volatility::Volatile,
+        );
+        let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(return_type.clone()));
+        let accumulator: AccumulatorFactoryFunction = Arc::new(|_| unreachable!());
+        let state_type: StateTypeFunction = Arc::new(|_| unreachable!());
+        let udaf = AggregateUDF::new(name, &signature, &return_type, &accumulator, &state_type);
+        if self
+            .aggregate_functions
+            .insert(name.to_string(), Arc::new(udaf))
+            .is_some()
+        {
+            bail!(
+                "Could not register UDAF '{}', as there is already a built-in aggregate with that name",
+                name
+            );
+        }
         Ok(())
     }
 }
 
+// the name of the UDAF computed by an accumulator struct, like geo_mean for GeoMean
+fn snake_case(name: &str) -> String {
+    let mut snake_case = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if previous.map_or(false, |p| p.is_lowercase() || p.is_ascii_digit()) {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+        previous = Some(c);
+    }
+    snake_case
+}
+
 fn create_table_source(fields: Vec<Field>) -> Arc<dyn TableSource> {
     Arc::new(LogicalTableSource::new(Arc::new(
         datatypes::Schema::new_with_metadata(fields, HashMap::new()),
@@ -375,13 +996,35 @@ impl ContextProvider for ArroyoSchemaProvider {
 #[derive(Clone, Debug)]
 pub struct SqlConfig {
     pub default_parallelism: usize,
+    /// How long joins, updating aggregates and top-N operators keep the state of a key that
+    /// hasn't been updated, which can be set with `SET state_ttl = '6 hours'`
+    pub state_ttl: Duration,
 }
 
 impl Default for SqlConfig {
     fn default() -> Self {
         Self {
             default_parallelism: 4,
+            state_ttl: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl SqlConfig {
+    /// Applies a `SET name = value` statement from the query
+    fn set(&mut self, name: &str, value: &[datafusion::sql::sqlparser::ast::Expr]) -> Result<()> {
+        use datafusion::sql::sqlparser::ast::{Expr, Value};
+
+        match name.to_lowercase().as_str() {
+            "state_ttl" => {
+                let [Expr::Value(Value::SingleQuotedString(ttl))] = value else {
+                    bail!("state_ttl must be set to a duration, like '6 hours'");
+                };
+                self.state_ttl = tables::parse_ttl(ttl)?;
+            }
+            _ => bail!("unknown setting '{}'; only state_ttl can be set", name),
         }
+        Ok(())
     }
 }
 
@@ -403,20 +1046,120 @@ pub async fn parse_and_get_program(
 
 pub fn parse_and_get_program_sync(
     query: String,
-    mut schema_provider: ArroyoSchemaProvider,
+    schema_provider: ArroyoSchemaProvider,
     config: SqlConfig,
 ) -> Result<(Program, Vec<i64>)> {
+    let explanation = plan(query, schema_provider, config)?;
+    Ok((explanation.program, explanation.connection_ids))
+}
+
+/// The plans that a query compiles to, as shown by `EXPLAIN`
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    /// the optimized logical plan of each query in the SQL, in order
+    pub logical_plans: Vec<String>,
+    pub program: Program,
+    pub connection_ids: Vec<i64>,
+    /// whether a query was prefixed with `EXPLAIN ANALYZE`, asking for the row counts of a run
+    pub analyze: bool,
+}
+
+/// Plans the SQL like [parse_and_get_program], but also returns the logical plans. Queries may be
+/// prefixed with `EXPLAIN` or `EXPLAIN ANALYZE`, which is otherwise ignored.
+pub async fn explain(
+    query: &str,
+    schema_provider: ArroyoSchemaProvider,
+    config: SqlConfig,
+) -> Result<Explanation> {
+    let query = query.to_string();
+
+    if query.trim().is_empty() {
+        bail!("Query is empty");
+    }
+
+    tokio::spawn(async move { plan(query, schema_provider, config) })
+        .await
+        .map_err(|_| anyhow!("Something went wrong"))?
+}
+
+fn plan(
+    query: String,
+    mut schema_provider: ArroyoSchemaProvider,
+    mut config: SqlConfig,
+) -> Result<Explanation> {
     let dialect = PostgreSqlDialect {};
+    let query = ddl::rewrite(&query)?;
+    let query = statement_set::extract(&query)?;
+    let (query, mut match_recognizes) = match_recognize::extract(&query)?;
+    let (query, system_time_tables) = lookup::extract(&query)?;
+    schema_provider.system_time_tables = system_time_tables;
     let mut inserts = vec![];
-    for statement in Parser::parse_sql(&dialect, &query)? {
-        if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
+    let mut analyze = false;
+    for mut statement in Parser::parse_sql(&dialect, &query)? {
+        if let Statement::Explain {
+            statement: explained,
+            analyze: explain_analyze,
+            ..
+        } = statement
+        {
+            analyze |= explain_analyze;
+            statement = *explained;
+        }
+        lateral::rewrite(&mut statement)?;
+
+        // MATCH_RECOGNIZE clauses are planned once the tables they read from are defined
+        let (ready, pending) = match_recognizes
+            .into_iter()
+            .partition(|m| schema_provider.get_table(&m.input).is_some());
+        match_recognizes = pending;
+        for clause in ready {
+            let table = clause.into_table(&schema_provider)?;
+            schema_provider.insert_table(table);
+        }
+
+        if let Some(clause) = match_recognizes
+            .iter()
+            .find(|m| statement.to_string().contains(&m.name))
+        {
+            bail!("table {} not found", clause.input);
+        }
+
+        if let Statement::SetVariable {
+            variable, value, ..
+        } = &statement
+        {
+            config.set(&variable.to_string(), value)?;
+        } else if let Some(table) = Table::try_from_statement(&statement, &schema_provider)? {
             schema_provider.insert_table(table);
         } else {
             inserts.push(Insert::try_from_statement(&statement, &schema_provider)?);
         };
     }
 
+    for name in &schema_provider.system_time_tables {
+        if let Some(table) = schema_provider.get_table(name) {
+            if !matches!(
+                table,
+                Table::ConnectorTable(ConnectorTable {
+                    connection_type: ConnectionType::Lookup,
+                    ..
+                })
+            ) {
+                bail!(
+                    "FOR SYSTEM_TIME AS OF is only supported for lookup tables, and {} is not one",
+                    name
+                );
+            }
+        }
+    }
+
+    let logical_plans = inserts
+        .iter()
+        .map(|insert| insert.logical_plan().display_indent().to_string())
+        .collect();
+
     let mut sql_pipeline_builder = SqlPipelineBuilder::new(&mut schema_provider);
+    sql_pipeline_builder.share_common_plans(inserts.iter().map(|insert| insert.logical_plan()));
     for insert in inserts {
         sql_pipeline_builder.add_insert(insert)?;
     }
@@ -451,7 +1194,12 @@ pub fn parse_and_get_program_sync(
             })),
             event_time_field: None,
             watermark_field: None,
+            watermark_expression: None,
             idle_time: DEFAULT_IDLE_TIME,
+            watermarks: Default::default(),
+            deduplication: None,
+            lookup_cache: Default::default(),
+            sink_mode: None,
         });
 
         plan_graph.add_sql_operator(sink.as_sql_sink(insert)?);
@@ -461,7 +1209,19 @@ pub fn parse_and_get_program_sync(
         plan_graph.add_sql_operator(output);
     }
 
-    get_program(plan_graph, sql_pipeline_builder.schema_provider.clone())
+    for late_data_sink in sql_pipeline_builder.late_data_sinks.into_iter() {
+        plan_graph.add_sql_operator(late_data_sink);
+    }
+
+    let (program, connection_ids) =
+        get_program(plan_graph, sql_pipeline_builder.schema_provider.clone())?;
+
+    Ok(Explanation {
+        logical_plans,
+        program,
+        connection_ids,
+        analyze,
+    })
 }
 
 #[derive(Clone)]
@@ -620,6 +1380,8 @@ pub fn get_test_expression(
     let schema = ConnectionSchema {
         format: Some(Format::Json(JsonFormat::default())),
         framing: None,
+        bad_data: None,
+        compression: None,
         struct_name: struct_def.name.clone(),
         fields: struct_def
             .fields
@@ -637,6 +1399,7 @@ pub fn get_test_expression(
             KafkaConfig {
                 authentication: arroyo_connectors::kafka::KafkaConfigAuthentication::None {},
                 bootstrap_servers: "localhost:9092".to_string().try_into().unwrap(),
+                schema_registry: None,
             },
             KafkaTable {
                 topic: "test_topic".to_string(),
@@ -644,6 +1407,15 @@ pub fn get_test_expression(
                     offset: arroyo_connectors::kafka::SourceOffset::Latest,
                     read_mode: Some(arroyo_connectors::kafka::ReadMode::ReadUncommitted),
                     group_id: "test-consumer-group".to_string().try_into().unwrap(),
+                    commit_offsets: None,
+                    topic_pattern: None,
+                    partition_discovery_interval_ms: None,
+                    key_field: None,
+                    topic_field: None,
+                    partition_field: None,
+                    offset_field: None,
+                    timestamp_field: None,
+                    header_fields: vec![],
                 },
             },
             Some(&schema),
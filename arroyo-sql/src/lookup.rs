@@ -0,0 +1,398 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use arrow_schema::DataType;
+use arroyo_datastream::ConnectorOp;
+use arroyo_rpc::api_types::connections::ConnectionType;
+use datafusion::sql::sqlparser::{
+    dialect::PostgreSqlDialect,
+    tokenizer::{Token, Tokenizer},
+};
+use datafusion_expr::{
+    logical_plan::{Join, TableScan},
+    utils::{conjunction, split_conjunction},
+    Expr, JoinConstraint, LogicalPlan,
+};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{
+    code_gen::{CodeGenerator, JoinPairContext, ValuePointerContext},
+    expressions::{Column, ColumnExpression, Expression, ExpressionContext},
+    match_recognize::{normalize, render},
+    operators::Projection,
+    pipeline::{JoinType, SqlOperator},
+    tables::{ConnectorTable, Table},
+    types::{StructDef, TypeDef},
+    ArroyoSchemaProvider,
+};
+
+/// How many lookup results are cached by each subtask of a lookup join, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LookupCacheConfig {
+    pub max_entries: u64,
+    pub ttl: Duration,
+}
+
+impl Default for LookupCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Cuts each `FOR SYSTEM_TIME AS OF <time>` out of the query, which DataFusion can't parse, and
+/// returns the names of the tables they followed. Lookups always read the current contents of
+/// the table, so the time itself is ignored.
+pub fn extract(query: &str) -> Result<(String, HashSet<String>)> {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, query).tokenize()?;
+
+    let mut output: Vec<Token> = vec![];
+    let mut tables = HashSet::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let Some(end) = system_time_clause(&tokens, i) else {
+            output.push(tokens[i].clone());
+            i += 1;
+            continue;
+        };
+
+        while matches!(output.last(), Some(Token::Whitespace(_))) {
+            output.pop();
+        }
+        match output.last() {
+            Some(Token::Word(w)) => tables.insert(normalize(&w.to_ident())),
+            _ => bail!("FOR SYSTEM_TIME AS OF must follow the name of the table it reads from"),
+        };
+
+        i = skip_time_expression(&tokens, end)?;
+    }
+
+    if tables.is_empty() {
+        return Ok((query.to_string(), tables));
+    }
+
+    Ok((render(&output), tables))
+}
+
+/// The index after `FOR SYSTEM_TIME AS OF`, if the tokens at `i` start with it
+fn system_time_clause(tokens: &[Token], mut i: usize) -> Option<usize> {
+    for (n, word) in ["for", "system_time", "as", "of"].iter().enumerate() {
+        if n > 0 {
+            while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+                i += 1;
+            }
+        }
+        match tokens.get(i) {
+            Some(Token::Word(w))
+                if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word) =>
+            {
+                i += 1
+            }
+            _ => return None,
+        }
+    }
+    Some(i)
+}
+
+/// The index after the time expression that starts at `i`, which may be a column or a function
+/// call like `PROCTIME()`
+fn skip_time_expression(tokens: &[Token], mut i: usize) -> Result<usize> {
+    while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+        i += 1;
+    }
+    if !matches!(tokens.get(i), Some(Token::Word(_))) {
+        bail!("expected a column or function call after FOR SYSTEM_TIME AS OF");
+    }
+    i += 1;
+
+    loop {
+        match (tokens.get(i), tokens.get(i + 1)) {
+            (Some(Token::Period), Some(Token::Word(_))) => i += 2,
+            (Some(Token::LParen), _) => {
+                let mut depth = 0;
+                loop {
+                    match tokens.get(i) {
+                        Some(Token::LParen) => depth += 1,
+                        Some(Token::RParen) => depth -= 1,
+                        Some(_) => {}
+                        None => bail!("FOR SYSTEM_TIME AS OF is missing a closing ')'"),
+                    }
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ => return Ok(i),
+        }
+    }
+}
+
+/// Joins each row with the row of a lookup table whose key column is equal to an expression
+/// over the row, which is fetched from the external system when the row arrives
+#[derive(Debug, Clone)]
+pub struct LookupJoinOperator {
+    pub connector: ConnectorOp,
+    pub key_column: String,
+    pub lookup_struct: StructDef,
+    pub join_type: JoinType,
+    pub cache: LookupCacheConfig,
+    key: Expression,
+    // predicates on the lookup table that were pushed down into the join's right side; rows
+    // that don't pass them don't match
+    filter: Option<Expression>,
+    // the columns of the lookup table that the query reads, qualified as in the query
+    projection: Projection,
+    left_struct: StructDef,
+}
+
+impl LookupJoinOperator {
+    /// Plans the join as a lookup join if its right side reads from a lookup table
+    pub fn try_new(
+        join: &Join,
+        left: &SqlOperator,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Option<Self>> {
+        let Some((table, scan, filters)) = lookup_scan(&join.right, schema_provider) else {
+            return Ok(None);
+        };
+
+        if !schema_provider
+            .system_time_tables
+            .contains(&scan.table_name.to_string())
+        {
+            bail!(
+                "lookup table {} must be joined with FOR SYSTEM_TIME AS OF, like JOIN {} FOR SYSTEM_TIME AS OF o.proc_time ON ...",
+                table.name,
+                table.name
+            );
+        }
+        if left.is_updating() {
+            bail!("lookup joins are not supported over updating inputs");
+        }
+        if join.join_constraint == JoinConstraint::Using {
+            bail!("don't support 'using' in joins");
+        }
+        let join_type: JoinType = join.join_type.try_into()?;
+        let join_type = match join_type {
+            join_type @ (JoinType::Inner | JoinType::Left) => join_type,
+            _ => bail!("lookup joins only support INNER and LEFT joins"),
+        };
+        if !scan.filters.is_empty() || join.filter.is_some() {
+            bail!("lookup joins only support an equality condition on the key of the lookup table");
+        }
+        if table.fields.iter().any(|f| f.is_virtual()) {
+            bail!("virtual fields are not supported in lookup tables");
+        }
+
+        let [(left_key, Expr::Column(key_column))] = join.on.as_slice() else {
+            bail!("lookup joins must have a single equality condition on a column of the lookup table, like ON o.customer_id = c.id");
+        };
+
+        let lookup_struct = StructDef::new(
+            table.type_name.clone(),
+            table.type_name.is_none(),
+            table
+                .fields
+                .iter()
+                .map(|f| f.struct_field().clone())
+                .collect(),
+            table.format.clone(),
+        );
+
+        let key_field = lookup_struct.get_field(None, &key_column.name)?;
+        let left_struct = left.return_type();
+        let key = ExpressionContext {
+            input_struct: &left_struct,
+            schema_provider,
+        }
+        .compile_expr(left_key)?;
+
+        for (side, data_type) in [
+            ("key column", &key_field.data_type),
+            ("key", &key.expression_type(&ValuePointerContext::new())),
+        ] {
+            if !is_key_type(data_type) {
+                bail!(
+                    "the {} of a lookup join must be a string or an integer, not {:?}",
+                    side,
+                    data_type
+                );
+            }
+        }
+
+        let filter = conjunction(filters.into_iter().cloned())
+            .map(|filter| {
+                ExpressionContext {
+                    input_struct: &lookup_struct,
+                    schema_provider,
+                }
+                .compile_expr(&filter)
+            })
+            .transpose()?;
+
+        let projection = Projection::new(
+            join.right
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| {
+                    Ok((
+                        Column::convert(&field.qualified_column()),
+                        Expression::Column(ColumnExpression::new(
+                            lookup_struct.get_field(None, field.name())?,
+                        )),
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        );
+
+        Ok(Some(Self {
+            connector: table.connector_op(),
+            key_column: key_column.name.clone(),
+            lookup_struct,
+            join_type,
+            cache: table.lookup_cache,
+            key,
+            filter,
+            projection,
+            left_struct,
+        }))
+    }
+
+    pub fn output_struct(&self) -> StructDef {
+        self.join_type
+            .output_struct(&self.left_struct, &self.projection.output_struct())
+    }
+
+    pub fn right_struct(&self) -> StructDef {
+        self.projection.output_struct()
+    }
+
+    /// `fn(&T) -> Option<String>`, the key to look up for a row, or None if it's null
+    pub fn key(&self) -> TokenStream {
+        let ctx = ValuePointerContext::new();
+        let expr = self.key.generate(&ctx);
+        if self.key.expression_type(&ctx).is_optional() {
+            quote!(|arg| (#expr).map(|key| key.to_string()))
+        } else {
+            quote!(|arg| Some((#expr).to_string()))
+        }
+    }
+
+    /// `fn(&T, Option<&LookupT>) -> Option<OutT>`, the joined row for a row and the lookup row
+    /// with its key, if there is one
+    pub fn merge(&self) -> TokenStream {
+        let ctx = ValuePointerContext::new();
+        let filter = self.filter.as_ref().map(|filter| {
+            let expr = filter.generate(&ctx);
+            if filter.expression_type(&ctx).is_optional() {
+                quote!(.filter(|arg| (#expr).unwrap_or(false)))
+            } else {
+                quote!(.filter(|arg| #expr))
+            }
+        });
+        let projection = self.projection.generate(&ctx);
+
+        let merged = self.join_type.generate(&JoinPairContext::new(
+            self.left_struct.clone(),
+            self.right_struct(),
+        ));
+        let unmatched = match self.join_type {
+            JoinType::Inner => Some(quote!(let right = right?;)),
+            _ => None,
+        };
+
+        quote!(|left, right| {
+            let right = right #filter .map(|arg| #projection);
+            #unmatched
+            Some(#merged)
+        })
+    }
+}
+
+/// The lookup table that a plan reads from, looking through aliases and filters that were pushed
+/// down to it
+fn lookup_scan<'a>(
+    mut plan: &'a LogicalPlan,
+    schema_provider: &'a ArroyoSchemaProvider,
+) -> Option<(&'a ConnectorTable, &'a TableScan, Vec<&'a Expr>)> {
+    let mut filters = vec![];
+    loop {
+        match plan {
+            LogicalPlan::SubqueryAlias(alias) => plan = &alias.input,
+            LogicalPlan::Filter(filter) => {
+                filters.extend(split_conjunction(&filter.predicate));
+                plan = &filter.input;
+            }
+            LogicalPlan::TableScan(scan) => {
+                let Some(Table::ConnectorTable(table)) =
+                    schema_provider.get_table(&scan.table_name.to_string())
+                else {
+                    return None;
+                };
+                return matches!(table.connection_type, ConnectionType::Lookup)
+                    .then_some((table, scan, filters));
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn is_key_type(data_type: &TypeDef) -> bool {
+    matches!(
+        data_type,
+        TypeDef::DataType(
+            DataType::Utf8
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64,
+            _
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let (query, tables) = extract(
+            "SELECT * FROM orders AS o
+            JOIN Customers FOR SYSTEM_TIME AS OF o.proc_time AS c ON o.customer_id = c.id
+            LEFT JOIN rates for system_time as of PROCTIME() r ON o.currency = r.currency
+            WHERE c.name != 'it''s'",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query,
+            "SELECT * FROM orders AS o
+            JOIN Customers AS c ON o.customer_id = c.id
+            LEFT JOIN rates r ON o.currency = r.currency
+            WHERE c.name != 'it''s'"
+        );
+        assert_eq!(
+            tables,
+            HashSet::from(["customers".to_string(), "rates".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_extract_without_clause() {
+        let query = "SELECT * FROM orders WHERE note = 'for system_time'";
+        let (extracted, tables) = extract(query).unwrap();
+        assert_eq!(extracted, query);
+        assert!(tables.is_empty());
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use arrow_schema::DataType;
+use datafusion::sql::{
+    planner::{PlannerContext, SqlToRel},
+    sqlparser::{
+        ast::{Expr as SqlExpr, FunctionArg, FunctionArgExpr, Ident, OrderByExpr},
+        dialect::PostgreSqlDialect,
+        keywords::Keyword,
+        parser::Parser,
+        tokenizer::{Token, Tokenizer},
+    },
+};
+use datafusion_common::{DFField, DFSchema};
+use datafusion_expr::Expr;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{
+    code_gen::{CodeGenerator, ValuePointerContext},
+    expressions::{Column, Expression, ExpressionContext},
+    operators::Projection,
+    pipeline::{SqlOperator, SqlPipelineBuilder},
+    tables::Table,
+    types::{StructDef, StructField, TypeDef},
+    ArroyoSchemaProvider,
+};
+
+/// A `MATCH_RECOGNIZE` clause, which is planned as a table that reads from its input table
+#[derive(Debug, Clone)]
+pub struct MatchRecognize {
+    /// The name of the table that replaces the clause in the query
+    pub name: String,
+    pub input: String,
+    partition_by: Vec<SqlExpr>,
+    order_by: Vec<OrderByExpr>,
+    measures: Vec<(SqlExpr, Ident)>,
+    pattern: Vec<PatternElement>,
+    within: Option<SqlExpr>,
+    define: Vec<(Ident, SqlExpr)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PatternElement {
+    variable: String,
+    min: u64,
+    max: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Measure {
+    // the pattern element whose rows the measure is computed from, or None for the whole match
+    element: Option<usize>,
+    // whether the measure is computed from the first row, rather than the last
+    first: bool,
+    expression: Expression,
+}
+
+/// Finds sequences of rows that match a pattern within each partition, emitting a row of
+/// measures for each match
+#[derive(Debug, Clone)]
+pub struct MatchRecognizeOperator {
+    pub partition: Projection,
+    pub pattern: Vec<(u64, Option<u64>)>,
+    pub within: Option<Duration>,
+    // the partition columns, which are computed from the first row of each match
+    partition_columns: Vec<Expression>,
+    conditions: Vec<Option<Expression>>,
+    measures: Vec<Measure>,
+    output: StructDef,
+}
+
+/// Cuts each `<table> MATCH_RECOGNIZE (...)` out of the query, which DataFusion can't parse, and
+/// replaces it with the name of a table for the clause's output
+pub fn extract(query: &str) -> Result<(String, Vec<MatchRecognize>)> {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, query).tokenize()?;
+
+    if !tokens.iter().any(is_match_recognize) {
+        return Ok((query.to_string(), vec![]));
+    }
+
+    let mut output: Vec<Token> = vec![];
+    let mut clauses = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        if !is_match_recognize(&tokens[i]) {
+            output.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+
+        while matches!(output.last(), Some(Token::Whitespace(_))) {
+            output.pop();
+        }
+        let input = match output.pop() {
+            Some(Token::Word(w)) => normalize(&w.to_ident()),
+            _ => bail!("MATCH_RECOGNIZE must follow the name of the table it reads from"),
+        };
+
+        i += 1;
+        while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+            i += 1;
+        }
+        if tokens.get(i) != Some(&Token::LParen) {
+            bail!("expected '(' after MATCH_RECOGNIZE");
+        }
+
+        let start = i + 1;
+        let mut depth = 1;
+        while depth > 0 {
+            i += 1;
+            match tokens.get(i) {
+                Some(Token::LParen) => depth += 1,
+                Some(Token::RParen) => depth -= 1,
+                Some(_) => {}
+                None => bail!("MATCH_RECOGNIZE is missing a closing ')'"),
+            }
+        }
+
+        let name = format!("__match_recognize_{}", clauses.len());
+        clauses.push(MatchRecognize::parse(
+            name.clone(),
+            input,
+            tokens[start..i].to_vec(),
+        )?);
+        output.push(Token::make_word(&name, None));
+        i += 1;
+    }
+
+    Ok((render(&output), clauses))
+}
+
+/// Turns tokens back into a query
+pub(crate) fn render(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            // the Display impl doesn't escape quotes
+            Token::SingleQuotedString(s) => format!("'{}'", s.replace('\'', "''")),
+            token => token.to_string(),
+        })
+        .collect()
+}
+
+fn is_match_recognize(token: &Token) -> bool {
+    matches!(token, Token::Word(w)
+        if w.quote_style.is_none() && w.value.eq_ignore_ascii_case("match_recognize"))
+}
+
+/// Unquoted identifiers are case-insensitive, as DataFusion treats them
+pub(crate) fn normalize(ident: &Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+/// Consumes the words if the next tokens are those words
+fn parse_words(parser: &mut Parser, words: &[&str]) -> bool {
+    let matches = words.iter().enumerate().all(|(i, word)| {
+        matches!(parser.peek_nth_token(i).token, Token::Word(w) if w.value.eq_ignore_ascii_case(word))
+    });
+
+    if matches {
+        for _ in words {
+            parser.next_token();
+        }
+    }
+
+    matches
+}
+
+fn expect_words(parser: &mut Parser, words: &[&str]) -> Result<()> {
+    if !parse_words(parser, words) {
+        bail!(
+            "expected {} in MATCH_RECOGNIZE, found {}",
+            words.join(" "),
+            parser.peek_token().token
+        );
+    }
+    Ok(())
+}
+
+fn parse_pattern(parser: &mut Parser) -> Result<Vec<PatternElement>> {
+    parser.expect_token(&Token::LParen)?;
+
+    let mut pattern: Vec<PatternElement> = vec![];
+    loop {
+        let variable = match parser.next_token().token {
+            Token::Word(w) => normalize(&w.to_ident()),
+            Token::RParen => break,
+            token => bail!(
+                "unsupported token {} in PATTERN; only sequences of variables with the \
+                quantifiers *, +, ? and {{n,m}} are supported",
+                token
+            ),
+        };
+
+        let (min, max) = match parser.peek_token().token {
+            Token::Mul => {
+                parser.next_token();
+                (0, None)
+            }
+            Token::Plus => {
+                parser.next_token();
+                (1, None)
+            }
+            Token::Placeholder(p) if p == "?" => {
+                parser.next_token();
+                (0, Some(1))
+            }
+            Token::LBrace => {
+                parser.next_token();
+                let min = parser.parse_literal_uint()?;
+                let max = if parser.consume_token(&Token::Comma) {
+                    match parser.peek_token().token {
+                        Token::RBrace => None,
+                        _ => Some(parser.parse_literal_uint()?),
+                    }
+                } else {
+                    Some(min)
+                };
+                parser.expect_token(&Token::RBrace)?;
+
+                if max.map(|max| max == 0 || max < min).unwrap_or(false) {
+                    bail!("invalid quantifier for pattern variable {}", variable);
+                }
+
+                (min, max)
+            }
+            _ => (1, Some(1)),
+        };
+        if pattern.iter().any(|e| e.variable == variable) {
+            bail!(
+                "pattern variable {} may only appear once in PATTERN",
+                variable
+            );
+        }
+
+        pattern.push(PatternElement { variable, min, max });
+    }
+
+    if pattern.is_empty() {
+        bail!("PATTERN must contain at least one variable");
+    }
+
+    Ok(pattern)
+}
+
+/// Returns the expression within `FIRST(..)` or `LAST(..)`, and whether it's the former. Other
+/// expressions are computed from the last row.
+fn first_or_last(expr: &SqlExpr) -> (&SqlExpr, bool) {
+    if let SqlExpr::Function(f) = expr {
+        if let (Some(name), [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))], None) =
+            (f.name.0.last(), f.args.as_slice(), &f.over)
+        {
+            if f.name.0.len() == 1 {
+                match name.value.to_lowercase().as_str() {
+                    "first" => return (arg, true),
+                    "last" => return (arg, false),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    (expr, false)
+}
+
+impl MatchRecognize {
+    fn parse(name: String, input: String, tokens: Vec<Token>) -> Result<Self> {
+        let dialect = PostgreSqlDialect {};
+        let mut parser = Parser::new(&dialect).with_tokens(tokens);
+        let parser = &mut parser;
+
+        let partition_by = if parser.parse_keywords(&[Keyword::PARTITION, Keyword::BY]) {
+            parser.parse_comma_separated(Parser::parse_expr)?
+        } else {
+            vec![]
+        };
+
+        let order_by = if parser.parse_keywords(&[Keyword::ORDER, Keyword::BY]) {
+            parser.parse_comma_separated(Parser::parse_order_by_expr)?
+        } else {
+            vec![]
+        };
+
+        expect_words(parser, &["MEASURES"])?;
+        let measures = parser.parse_comma_separated(|p| {
+            let expr = p.parse_expr()?;
+            p.expect_keyword(Keyword::AS)?;
+            Ok((expr, p.parse_identifier()?))
+        })?;
+
+        if parse_words(parser, &["ALL", "ROWS", "PER", "MATCH"]) {
+            bail!("only ONE ROW PER MATCH is supported in MATCH_RECOGNIZE");
+        }
+        parse_words(parser, &["ONE", "ROW", "PER", "MATCH"]);
+
+        if parse_words(parser, &["AFTER", "MATCH", "SKIP"])
+            && !parse_words(parser, &["PAST", "LAST", "ROW"])
+        {
+            bail!("only AFTER MATCH SKIP PAST LAST ROW is supported in MATCH_RECOGNIZE");
+        }
+
+        expect_words(parser, &["PATTERN"])?;
+        let pattern = parse_pattern(parser)?;
+
+        let within = if parse_words(parser, &["WITHIN"]) {
+            Some(parser.parse_expr()?)
+        } else {
+            None
+        };
+
+        // variables without a condition match any row
+        let define = if parse_words(parser, &["DEFINE"]) {
+            parser.parse_comma_separated(|p| {
+                let variable = p.parse_identifier()?;
+                p.expect_keyword(Keyword::AS)?;
+                Ok((variable, p.parse_expr()?))
+            })?
+        } else {
+            vec![]
+        };
+
+        if parser.peek_token().token != Token::EOF {
+            bail!(
+                "unexpected {} in MATCH_RECOGNIZE",
+                parser.peek_token().token
+            );
+        }
+
+        Ok(Self {
+            name,
+            input,
+            partition_by,
+            order_by,
+            measures,
+            pattern,
+            within,
+            define,
+        })
+    }
+
+    /// Converts `expr` to a DataFusion expression over the input fields, which are qualified by
+    /// each of `qualifiers`
+    fn to_df_expr(
+        expr: &SqlExpr,
+        qualifiers: &[Option<&str>],
+        input: &StructDef,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Expr> {
+        let fields = qualifiers
+            .iter()
+            .flat_map(|qualifier| {
+                // struct fields can't be referred to
+                input.fields.iter().filter_map(move |f| {
+                    let TypeDef::DataType(data_type, nullable) = &f.data_type else {
+                        return None;
+                    };
+                    Some(DFField::new(
+                        qualifier.map(str::to_string),
+                        &f.name,
+                        data_type.clone(),
+                        *nullable,
+                    ))
+                })
+            })
+            .collect();
+
+        let schema = DFSchema::new_with_metadata(fields, HashMap::new())?;
+
+        Ok(SqlToRel::new(schema_provider).sql_to_expr(
+            expr.clone(),
+            &schema,
+            &mut PlannerContext::default(),
+        )?)
+    }
+
+    fn compile(
+        expr: &SqlExpr,
+        qualifiers: &[Option<&str>],
+        input: &StructDef,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<(Expr, Expression)> {
+        let df_expr = Self::to_df_expr(expr, qualifiers, input, schema_provider)?;
+        let expression = ExpressionContext {
+            input_struct: input,
+            schema_provider,
+        }
+        .compile_expr(&df_expr)?;
+        Ok((df_expr, expression))
+    }
+
+    /// Plans the clause over rows of type `input`
+    fn plan(
+        &self,
+        input: &StructDef,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<MatchRecognizeOperator> {
+        let ctx = ValuePointerContext::new();
+
+        let ordered_by_time = match self.order_by.as_slice() {
+            [order] if order.asc != Some(false) => {
+                let (_, expr) = Self::compile(&order.expr, &[None], input, schema_provider)?;
+                matches!(
+                    expr.expression_type(&ctx).as_datatype(),
+                    Some(DataType::Timestamp(_, _))
+                )
+            }
+            _ => false,
+        };
+        if !ordered_by_time {
+            bail!("MATCH_RECOGNIZE must be ordered by the event time, ascending");
+        }
+
+        let mut partition_terms = vec![];
+        let mut partition_columns = vec![];
+        let mut fields = vec![];
+        for (i, term) in self.partition_by.iter().enumerate() {
+            let (df_expr, expr) = Self::compile(term, &[None], input, schema_provider)?;
+            let Expr::Column(column) = df_expr else {
+                bail!("MATCH_RECOGNIZE can only be partitioned by columns");
+            };
+
+            fields.push(StructField::new(
+                column.name.clone(),
+                None,
+                expr.expression_type(&ctx),
+            ));
+            partition_terms.push((
+                Column {
+                    relation: None,
+                    name: format!("_{}", i),
+                },
+                expr.clone(),
+            ));
+            partition_columns.push(expr);
+        }
+
+        let mut conditions = vec![None; self.pattern.len()];
+        for (variable, condition) in &self.define {
+            let variable = normalize(variable);
+            let index = self
+                .pattern
+                .iter()
+                .position(|e| e.variable == variable)
+                .ok_or_else(|| anyhow!("DEFINE refers to {}, which isn't in PATTERN", variable))?;
+
+            if conditions[index].is_some() {
+                bail!("{} is defined more than once", variable);
+            }
+
+            let (_, expr) = Self::compile(
+                condition,
+                &[Some(variable.as_str())],
+                input,
+                schema_provider,
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "failed to plan the condition for {} ({}); conditions may only refer \
+                            to the current row",
+                    variable,
+                    e
+                )
+            })?;
+
+            if expr.expression_type(&ctx).as_datatype() != Some(&DataType::Boolean) {
+                bail!("the condition for {} must be a boolean", variable);
+            }
+
+            conditions[index] = Some(expr);
+        }
+
+        let qualifiers: Vec<_> = self
+            .pattern
+            .iter()
+            .map(|e| Some(e.variable.as_str()))
+            .collect();
+
+        let mut measures = vec![];
+        for (expr, alias) in &self.measures {
+            let (expr, first) = first_or_last(expr);
+            let (df_expr, expression) = Self::compile(expr, &qualifiers, input, schema_provider)?;
+
+            let mut elements: Vec<usize> = df_expr
+                .to_columns()?
+                .into_iter()
+                .filter_map(|c| c.relation)
+                .filter_map(|r| self.pattern.iter().position(|e| e.variable == r.table()))
+                .collect();
+            elements.sort();
+            elements.dedup();
+            if elements.len() > 1 {
+                bail!(
+                    "measure {} may only refer to a single pattern variable",
+                    alias.value
+                );
+            }
+            let element = elements.pop();
+
+            let mut data_type = expression.expression_type(&ctx);
+            if let Some(element) = element {
+                // variables that may match no rows have no value
+                if self.pattern[element].min == 0 {
+                    data_type = data_type.to_optional();
+                }
+            }
+
+            fields.push(StructField::new(normalize(alias), None, data_type));
+            measures.push(Measure {
+                element,
+                first,
+                expression,
+            });
+        }
+
+        let within = self
+            .within
+            .as_ref()
+            .map(|within| {
+                SqlPipelineBuilder::get_duration(&Self::to_df_expr(
+                    within,
+                    &[],
+                    input,
+                    schema_provider,
+                )?)
+            })
+            .transpose()?;
+
+        if within.is_none() && self.pattern.iter().any(|e| e.max.is_none()) {
+            bail!(
+                "MATCH_RECOGNIZE patterns with unbounded quantifiers like + and * must have a \
+                WITHIN interval, which bounds how long partial matches are kept"
+            );
+        }
+
+        Ok(MatchRecognizeOperator {
+            partition: Projection::new(partition_terms),
+            pattern: self.pattern.iter().map(|e| (e.min, e.max)).collect(),
+            within,
+            partition_columns,
+            conditions,
+            measures,
+            output: StructDef::for_fields(fields),
+        })
+    }
+
+    /// Plans the clause against its input table, as a table that queries can read from
+    pub fn into_table(self, schema_provider: &ArroyoSchemaProvider) -> Result<Table> {
+        let input = schema_provider
+            .get_table(&self.input)
+            .ok_or_else(|| anyhow!("table {} not found", self.input))?;
+
+        let input_struct = StructDef::for_fields(
+            input
+                .get_fields()?
+                .into_iter()
+                .filter(|f| !matches!(f.data_type(), DataType::Struct(_)))
+                .map(|f| {
+                    StructField::new(
+                        f.name().clone(),
+                        None,
+                        TypeDef::DataType(f.data_type().clone(), f.is_nullable()),
+                    )
+                })
+                .collect(),
+        );
+
+        let fields = self.plan(&input_struct, schema_provider)?.output.fields;
+
+        Ok(Table::MatchRecognize {
+            clause: self,
+            fields,
+        })
+    }
+
+    pub fn as_sql_source(&self, builder: &mut SqlPipelineBuilder) -> Result<SqlOperator> {
+        let schema_provider = builder.schema_provider;
+        let input = schema_provider
+            .get_table(&self.input)
+            .ok_or_else(|| anyhow!("table {} not found", self.input))?
+            .as_sql_source(builder)?;
+
+        if input.is_updating() {
+            bail!("MATCH_RECOGNIZE is not supported over updating inputs");
+        }
+
+        let operator = self.plan(&input.return_type(), schema_provider)?;
+        Ok(SqlOperator::MatchRecognize(Box::new(input), operator))
+    }
+}
+
+impl MatchRecognizeOperator {
+    pub fn output_struct(&self) -> StructDef {
+        self.output.clone()
+    }
+
+    /// `fn(&T, usize) -> bool`, whether a row may match the pattern element
+    pub fn predicate(&self) -> TokenStream {
+        let ctx = ValuePointerContext::new();
+        let arms = self
+            .conditions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, condition)| {
+                let condition = condition.as_ref()?;
+                let expr = condition.generate(&ctx);
+                Some(if condition.expression_type(&ctx).is_optional() {
+                    quote!(#i => (#expr).unwrap_or(false))
+                } else {
+                    quote!(#i => #expr)
+                })
+            });
+
+        quote!(|arg, element| match element {
+            #(#arms,)*
+            _ => true,
+        })
+    }
+
+    /// `fn(&[(usize, T)]) -> OutT`, the output row for the rows of a match and the pattern
+    /// elements they matched
+    pub fn measures(&self) -> TokenStream {
+        let ctx = ValuePointerContext::new();
+        let output_type = self.output.get_type();
+
+        let mut assignments = vec![];
+        for (field, column) in self.output.fields.iter().zip(&self.partition_columns) {
+            let ident = field.field_ident();
+            let value = column.generate(&ctx);
+            assignments.push(quote!(#ident: {
+                let arg = &matched[0].1;
+                #value
+            }));
+        }
+
+        let measure_fields = &self.output.fields[self.partition_columns.len()..];
+        for (field, measure) in measure_fields.iter().zip(&self.measures) {
+            let ident = field.field_ident();
+            let value = measure.expression.generate(&ctx);
+
+            let row = match (measure.element, measure.first) {
+                (Some(e), true) => quote!(matched.iter().find(|(element, _)| *element == #e)),
+                (Some(e), false) => {
+                    quote!(matched.iter().rev().find(|(element, _)| *element == #e))
+                }
+                (None, true) => quote!(matched.first()),
+                (None, false) => quote!(matched.last()),
+            };
+
+            let optional = measure
+                .element
+                .map(|e| self.pattern[e].0 == 0)
+                .unwrap_or(false);
+
+            let value = if !optional {
+                quote!({
+                    let arg = &#row.unwrap().1;
+                    #value
+                })
+            } else if measure.expression.expression_type(&ctx).is_optional() {
+                quote!(#row.and_then(|(_, arg)| #value))
+            } else {
+                quote!(#row.map(|(_, arg)| #value))
+            };
+
+            assignments.push(quote!(#ident: #value));
+        }
+
+        quote!(|matched| #output_type {
+            #(#assignments,)*
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let (query, clauses) = extract(
+            "SELECT * FROM Events MATCH_RECOGNIZE (
+                PARTITION BY user_id
+                ORDER BY event_time
+                MEASURES FIRST(A.amount) AS first_amount
+                PATTERN (A B+ C? D* E{2,3})
+                WITHIN INTERVAL '5' MINUTE
+                DEFINE A AS kind = 'it''s'
+            ) AS m WHERE first_amount > 10",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query,
+            "SELECT * FROM __match_recognize_0 AS m WHERE first_amount > 10"
+        );
+
+        let clause = &clauses[0];
+        assert_eq!(clause.input, "events");
+        assert_eq!(
+            clause
+                .pattern
+                .iter()
+                .map(|e| (e.variable.as_str(), e.min, e.max))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a", 1, Some(1)),
+                ("b", 1, None),
+                ("c", 0, Some(1)),
+                ("d", 0, None),
+                ("e", 2, Some(3))
+            ]
+        );
+        assert_eq!(clause.define[0].1.to_string(), "kind = 'it''s'");
+    }
+
+    #[test]
+    fn test_extract_without_clause() {
+        let query = "SELECT 'it''s' FROM events";
+        let (extracted, clauses) = extract(query).unwrap();
+        assert_eq!(extracted, query);
+        assert!(clauses.is_empty());
+    }
+}
@@ -6,6 +6,7 @@ use crate::{
     },
     expressions::{
         AggregateComputation, AggregateResultExtraction, Aggregator, Column, Expression,
+        RustUdafExpression,
     },
     types::{data_type_as_syn_type, StructDef, StructField, TypeDef},
 };
@@ -165,6 +166,18 @@ impl AggregateProjection {
             .iter()
             .all(|computation| computation.allows_two_phase())
     }
+
+    pub(crate) fn is_retractable(&self) -> bool {
+        self.aggregates.iter().all(|computation| match computation {
+            AggregateComputation::Builtin { computation, .. } => {
+                computation.aggregator.is_retractable()
+            }
+            AggregateComputation::UDAF { computation, .. } => computation
+                .accumulator
+                .as_ref()
+                .map_or(true, |accumulator| accumulator.retractable),
+        })
+    }
 }
 
 impl CodeGenerator<VecAggregationContext, StructDef, syn::Expr> for AggregateProjection {
@@ -221,7 +234,7 @@ impl CodeGenerator<VecAggregationContext, StructDef, syn::Expr> for AggregatePro
 
 #[derive(Debug, Clone)]
 pub struct TwoPhaseAggregateProjection {
-    pub aggregates: Vec<(Column, TwoPhaseAggregation)>,
+    pub aggregates: Vec<(Column, TwoPhaseComputation)>,
     pub group_bys: Vec<(StructField, AggregateResultExtraction)>,
 }
 
@@ -236,12 +249,21 @@ impl TryFrom<AggregateProjection> for TwoPhaseAggregateProjection {
                 AggregateComputation::Builtin {
                     column,
                     computation,
-                } => Ok((column, computation.try_into()?)),
+                } => Ok((
+                    column,
+                    TwoPhaseComputation::Builtin(computation.try_into()?),
+                )),
+                AggregateComputation::UDAF {
+                    column,
+                    computation,
+                } if computation.accumulator.is_some() => {
+                    Ok((column, TwoPhaseComputation::UDAF(computation)))
+                }
                 AggregateComputation::UDAF { .. } => {
-                    bail!("UDAFs not supported in two phase aggregation")
+                    bail!("UDAFs without an accumulator not supported in two phase aggregation")
                 }
             })
-            .collect::<Result<Vec<(Column, TwoPhaseAggregation)>>>()?;
+            .collect::<Result<Vec<(Column, TwoPhaseComputation)>>>()?;
 
         Ok(Self {
             aggregates,
@@ -516,6 +538,256 @@ impl TwoPhaseAggregateProjection {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum TwoPhaseComputation {
+    Builtin(TwoPhaseAggregation),
+    UDAF(RustUdafExpression),
+}
+
+impl CodeGenerator<ValueBinMergingContext, BinType, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &ValueBinMergingContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &ValueBinMergingContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl CodeGenerator<CombiningContext, BinType, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &CombiningContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &CombiningContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl CodeGenerator<MemoryAddingContext, BinType, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &MemoryAddingContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &MemoryAddingContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl CodeGenerator<MemoryRemovingContext, BinType, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &MemoryRemovingContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &MemoryRemovingContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl CodeGenerator<BinAggregatingContext, TypeDef, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &BinAggregatingContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &BinAggregatingContext) -> TypeDef {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl CodeGenerator<MemoryAggregatingContext, TypeDef, syn::Expr> for TwoPhaseComputation {
+    fn generate(&self, input_context: &MemoryAggregatingContext) -> syn::Expr {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.generate(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.generate(input_context),
+        }
+    }
+
+    fn expression_type(&self, input_context: &MemoryAggregatingContext) -> TypeDef {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.expression_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.expression_type(input_context),
+        }
+    }
+}
+
+impl TwoPhaseComputation {
+    fn output_type_def(&self, input_context: &ValuePointerContext) -> TypeDef {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.output_type_def(input_context),
+            TwoPhaseComputation::UDAF(udaf) => udaf.ret_type.clone(),
+        }
+    }
+
+    fn bin_type(&self, input_context: &ValuePointerContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.bin_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => Self::accumulator_bin_type(udaf),
+        }
+    }
+
+    fn mem_type(&self, input_context: &ValuePointerContext) -> BinType {
+        match self {
+            TwoPhaseComputation::Builtin(aggregation) => aggregation.mem_type(input_context),
+            TwoPhaseComputation::UDAF(udaf) => Self::accumulator_mem_type(udaf),
+        }
+    }
+
+    fn accumulator_bin_type(udaf: &RustUdafExpression) -> BinType {
+        BinType::Accumulator(udaf.accumulator.as_ref().unwrap().name.clone())
+    }
+
+    // accumulators that can be retracted cover the whole window, while others are kept for each
+    // bin in the window, oldest first, and merged when it closes
+    fn accumulator_mem_type(udaf: &RustUdafExpression) -> BinType {
+        let bin_type = Self::accumulator_bin_type(udaf);
+        if udaf.accumulator.as_ref().unwrap().retractable {
+            bin_type
+        } else {
+            BinType::Vec(Box::new(bin_type))
+        }
+    }
+}
+
+impl CodeGenerator<ValueBinMergingContext, BinType, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &ValueBinMergingContext) -> syn::Expr {
+        let current_bin_ident = input_context.bin_context.current_bin_ident();
+        let accumulator: syn::Ident = parse_quote!(accumulator);
+        let accumulate = self.accumulate(&accumulator);
+        parse_quote!({
+            let mut #accumulator = #current_bin_ident.unwrap_or_default();
+            #accumulate
+            #accumulator
+        })
+    }
+
+    fn expression_type(&self, _input_context: &ValueBinMergingContext) -> BinType {
+        TwoPhaseComputation::accumulator_bin_type(self)
+    }
+}
+
+impl CodeGenerator<CombiningContext, BinType, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &CombiningContext) -> syn::Expr {
+        let current_bin_ident = input_context.current_bin_ident();
+        let new_bin_ident = input_context.new_bin_ident();
+        parse_quote!({
+            let mut accumulator = #current_bin_ident;
+            accumulator.merge(&#new_bin_ident);
+            accumulator
+        })
+    }
+
+    fn expression_type(&self, _input_context: &CombiningContext) -> BinType {
+        TwoPhaseComputation::accumulator_bin_type(self)
+    }
+}
+
+impl CodeGenerator<MemoryAddingContext, BinType, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &MemoryAddingContext) -> syn::Expr {
+        let memory_ident = input_context.memory_value_ident();
+        let bin_value_ident = input_context.bin_value_ident();
+        if self.accumulator.as_ref().unwrap().retractable {
+            parse_quote!({
+                let mut accumulator = #memory_ident.unwrap_or_default();
+                accumulator.merge(&#bin_value_ident);
+                accumulator
+            })
+        } else {
+            parse_quote!({
+                arroyo_worker::operators::aggregating_window::bins_add(#memory_ident, #bin_value_ident)
+            })
+        }
+    }
+
+    fn expression_type(&self, _input_context: &MemoryAddingContext) -> BinType {
+        TwoPhaseComputation::accumulator_mem_type(self)
+    }
+}
+
+impl CodeGenerator<MemoryRemovingContext, BinType, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &MemoryRemovingContext) -> syn::Expr {
+        let memory_ident = input_context.memory_value_ident();
+        let bin_value_ident = input_context.bin_value_ident();
+        if self.accumulator.as_ref().unwrap().retractable {
+            parse_quote!({
+                let mut accumulator = #memory_ident;
+                accumulator.retract(&#bin_value_ident);
+                Some(accumulator)
+            })
+        } else {
+            parse_quote!({
+                arroyo_worker::operators::aggregating_window::bins_remove(#memory_ident, #bin_value_ident)
+            })
+        }
+    }
+
+    fn expression_type(&self, _input_context: &MemoryRemovingContext) -> BinType {
+        TwoPhaseComputation::accumulator_mem_type(self)
+    }
+}
+
+impl CodeGenerator<BinAggregatingContext, TypeDef, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &BinAggregatingContext) -> syn::Expr {
+        let bin_name = input_context.bin_name();
+        parse_quote!(#bin_name.finish())
+    }
+
+    fn expression_type(&self, _input_context: &BinAggregatingContext) -> TypeDef {
+        self.ret_type.clone()
+    }
+}
+
+impl CodeGenerator<MemoryAggregatingContext, TypeDef, syn::Expr> for RustUdafExpression {
+    fn generate(&self, input_context: &MemoryAggregatingContext) -> syn::Expr {
+        let bin_name = input_context.bin_name();
+        if self.accumulator.as_ref().unwrap().retractable {
+            parse_quote!(#bin_name.finish())
+        } else {
+            let accumulator_type = self.accumulator_type();
+            parse_quote!({
+                let mut accumulator = #accumulator_type::default();
+                for bin_accumulator in #bin_name.iter() {
+                    accumulator.merge(bin_accumulator);
+                }
+                accumulator.finish()
+            })
+        }
+    }
+
+    fn expression_type(&self, _input_context: &MemoryAggregatingContext) -> TypeDef {
+        self.ret_type.clone()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TwoPhaseAggregation {
     pub incoming_expression: Expression,
@@ -529,11 +801,14 @@ impl CodeGenerator<ValueBinMergingContext, BinType, syn::Expr> for TwoPhaseAggre
             .generate(&input_context.value_context);
         let current_bin_ident = input_context.bin_context.current_bin_ident();
         // TODO: factor this out.
-        let aggregate_type = data_type_as_syn_type(
-            self.intermediate_type_def(&input_context.value_context)
-                .as_datatype()
-                .expect("aggregates shouldn't return structs"),
-        );
+        let aggregate_type = self
+            .intermediate_type_def(&input_context.value_context)
+            .as_datatype()
+            .expect("aggregates shouldn't return structs")
+            .clone();
+        let addition = Self::to_sum_type(quote!(addition), &aggregate_type);
+        let value = Self::to_sum_type(quote!(value), &aggregate_type);
+        let sum_expr = Self::to_sum_type(quote!(#expr), &aggregate_type);
         let input_nullable = self
             .incoming_expression
             .expression_type(&input_context.value_context)
@@ -547,16 +822,16 @@ impl CodeGenerator<ValueBinMergingContext, BinType, syn::Expr> for TwoPhaseAggre
             (Aggregator::Count, false) => parse_quote!({ #current_bin_ident.unwrap_or(0) + 1 }),
             (Aggregator::Sum, true) => parse_quote!({
                 match (#current_bin_ident.flatten(), #expr) {
-                    (Some(value), Some(addition)) => Some(value + (addition as #aggregate_type)),
+                    (Some(value), Some(addition)) => Some(value + #addition),
                     (Some(value), None) => Some(value),
-                    (None, Some(addition)) => Some(addition as #aggregate_type),
+                    (None, Some(addition)) => Some(#addition),
                     (None, None) => None,
                 }
             }),
             (Aggregator::Sum, false) => parse_quote!({
                 match #current_bin_ident {
-                    Some(value) => value + (#expr as #aggregate_type),
-                    None => (#expr as #aggregate_type),
+                    Some(value) => value + #sum_expr,
+                    None => #sum_expr,
                 }
             }),
             (Aggregator::Min, true) => parse_quote!({
@@ -589,19 +864,76 @@ impl CodeGenerator<ValueBinMergingContext, BinType, syn::Expr> for TwoPhaseAggre
             }),
             (Aggregator::Avg, true) => parse_quote!({
                 match (#current_bin_ident.flatten(), #expr) {
-                    (Some((count, sum)), Some(value)) => Some((count + 1, sum + (value as #aggregate_type))),
+                    (Some((count, sum)), Some(value)) => Some((count + 1, sum + #value)),
                     (Some((count, sum)), None) => Some((count, sum)),
-                    (None, Some(value)) => Some((1, value as #aggregate_type)),
+                    (None, Some(value)) => Some((1, #value)),
                     (None, None) => None,
                 }
             }),
             (Aggregator::Avg, false) => parse_quote!({
                 match #current_bin_ident {
-                    Some((count, sum)) => (count + 1, sum + (#expr as #aggregate_type)),
-                    None => (1, #expr as #aggregate_type)
+                    Some((count, sum)) => (count + 1, sum + #sum_expr),
+                    None => (1, #sum_expr)
+                }
+            }),
+            (Aggregator::CountDistinct, true) => parse_quote!({
+                let mut distinct = #current_bin_ident.unwrap_or_default();
+                if let Some(value) = #expr {
+                    *distinct.entry(value).or_insert(0) += 1;
                 }
+                distinct
+            }),
+            (Aggregator::CountDistinct, false) => parse_quote!({
+                let mut distinct = #current_bin_ident.unwrap_or_default();
+                *distinct.entry(#expr).or_insert(0) += 1;
+                distinct
+            }),
+            (Aggregator::ApproxCountDistinct | Aggregator::HllSketch, true) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_else(arroyo_worker::operators::functions::hll::new_sketch);
+                if let Some(value) = #expr {
+                    arroyo_worker::operators::functions::hll::add(&mut sketch, &value);
+                }
+                sketch
+            }),
+            (Aggregator::ApproxCountDistinct | Aggregator::HllSketch, false) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_else(arroyo_worker::operators::functions::hll::new_sketch);
+                arroyo_worker::operators::functions::hll::add(&mut sketch, &#expr);
+                sketch
+            }),
+            (Aggregator::HllUnion, true) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_else(arroyo_worker::operators::functions::hll::new_sketch);
+                if let Some(value) = #expr {
+                    arroyo_worker::operators::functions::hll::merge(&mut sketch, &value);
+                }
+                sketch
+            }),
+            (Aggregator::HllUnion, false) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_else(arroyo_worker::operators::functions::hll::new_sketch);
+                arroyo_worker::operators::functions::hll::merge(&mut sketch, &#expr);
+                sketch
+            }),
+            (Aggregator::ApproxPercentile(_), true) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_default();
+                if let Some(value) = #expr {
+                    arroyo_worker::operators::functions::ddsketch::add(&mut sketch, value as f64);
+                }
+                sketch
+            }),
+            (Aggregator::ApproxPercentile(_), false) => parse_quote!({
+                let mut sketch = #current_bin_ident.unwrap_or_default();
+                arroyo_worker::operators::functions::ddsketch::add(&mut sketch, #expr as f64);
+                sketch
+            }),
+            (Aggregator::ArrayAgg, true) => parse_quote!({
+                let mut values = #current_bin_ident.unwrap_or_default();
+                values.push(#expr);
+                values
+            }),
+            (Aggregator::ArrayAgg, false) => parse_quote!({
+                let mut values = #current_bin_ident.unwrap_or_default();
+                values.push(Some(#expr));
+                values
             }),
-            (Aggregator::CountDistinct, _) => unreachable!("no two phase for count distinct"),
         }
     }
 
@@ -660,7 +992,21 @@ impl CodeGenerator<CombiningContext, BinType, syn::Expr> for TwoPhaseAggregation
             (Aggregator::Avg, false) => {
                 parse_quote!({ (#current_bin_ident.0 + #new_bin_ident.0, #current_bin_ident.1 + #new_bin_ident.1) })
             }
-            (Aggregator::CountDistinct, _) => unreachable!("no two phase for count distinct"),
+            (Aggregator::CountDistinct | Aggregator::ApproxPercentile(_), _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::distinct_merge(#current_bin_ident, #new_bin_ident)
+            }),
+            (Aggregator::ApproxCountDistinct | Aggregator::HllSketch | Aggregator::HllUnion, _) => {
+                parse_quote!({
+                    let mut sketch = #current_bin_ident;
+                    arroyo_worker::operators::functions::hll::merge(&mut sketch, &#new_bin_ident);
+                    sketch
+                })
+            }
+            (Aggregator::ArrayAgg, _) => parse_quote!({
+                let mut values = #current_bin_ident;
+                values.extend(#new_bin_ident);
+                values
+            }),
         }
     }
 
@@ -710,8 +1056,18 @@ impl CodeGenerator<MemoryAddingContext, BinType, syn::Expr> for TwoPhaseAggregat
             (Aggregator::Avg, false) => parse_quote!({
                 arroyo_worker::operators::aggregating_window::non_nullable_average_add::<#expr_type>(#memory_ident, #bin_value_ident)
             }),
-            (Aggregator::CountDistinct, true) => todo!(),
-            (Aggregator::CountDistinct, false) => todo!(),
+            (Aggregator::CountDistinct | Aggregator::ApproxPercentile(_), _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::distinct_add(#memory_ident, #bin_value_ident)
+            }),
+            (
+                Aggregator::ApproxCountDistinct
+                | Aggregator::HllSketch
+                | Aggregator::HllUnion
+                | Aggregator::ArrayAgg,
+                _,
+            ) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::bins_add(#memory_ident, #bin_value_ident)
+            }),
         }
     }
 
@@ -755,8 +1111,18 @@ impl CodeGenerator<MemoryRemovingContext, BinType, syn::Expr> for TwoPhaseAggreg
             (Aggregator::Avg, false) => parse_quote!({
                 arroyo_worker::operators::aggregating_window::non_nullable_average_remove::<#expr_type>(#memory_ident, #bin_value_ident)
             }),
-            (Aggregator::CountDistinct, true) => todo!(),
-            (Aggregator::CountDistinct, false) => todo!(),
+            (Aggregator::CountDistinct | Aggregator::ApproxPercentile(_), _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::distinct_remove(#memory_ident, #bin_value_ident)
+            }),
+            (
+                Aggregator::ApproxCountDistinct
+                | Aggregator::HllSketch
+                | Aggregator::HllUnion
+                | Aggregator::ArrayAgg,
+                _,
+            ) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::bins_remove(#memory_ident, #bin_value_ident)
+            }),
         }
     }
 
@@ -772,20 +1138,33 @@ impl CodeGenerator<BinAggregatingContext, TypeDef, syn::Expr> for TwoPhaseAggreg
             .expression_type(&input_context.value_context)
             .is_optional();
         let bin_name = input_context.bin_name();
+        let sum_type = self.intermediate_type_def(&input_context.value_context);
+        let sum_type = sum_type.as_datatype().unwrap();
         match (&self.aggregator, input_nullable) {
             (Aggregator::Count, _)
             | (Aggregator::Sum, _)
             | (Aggregator::Min, _)
             | (Aggregator::Max, _) => parse_quote!(#bin_name.clone()),
-            (Aggregator::Avg, true) => parse_quote!(match #bin_name {
-                Some((count, sum)) => Some((*sum as f64) / (*count as f64)),
-                None => None,
-            }),
+            (Aggregator::Avg, true) => {
+                let average = Self::average(quote!(*sum), quote!(*count), sum_type);
+                parse_quote!(match #bin_name {
+                    Some((count, sum)) => Some(#average),
+                    None => None,
+                })
+            }
             (Aggregator::Avg, false) => {
-                parse_quote!({ (#bin_name.1 as f64) / (#bin_name.0 as f64) })
+                let average = Self::average(quote!(#bin_name.1), quote!(#bin_name.0), sum_type);
+                parse_quote!({ #average })
             }
-            (Aggregator::CountDistinct, true) => unimplemented!(),
-            (Aggregator::CountDistinct, false) => unimplemented!(),
+            (Aggregator::CountDistinct, _) => parse_quote!({ #bin_name.len() as i64 }),
+            (Aggregator::ApproxCountDistinct, _) => parse_quote!({
+                arroyo_worker::operators::functions::hll::estimate(#bin_name).unwrap()
+            }),
+            (Aggregator::HllSketch | Aggregator::HllUnion, _) => parse_quote!(#bin_name.clone()),
+            (Aggregator::ApproxPercentile(_), _) => {
+                self.percentile(&bin_name, &input_context.value_context)
+            }
+            (Aggregator::ArrayAgg, _) => parse_quote!(#bin_name.clone()),
         }
     }
 
@@ -802,6 +1181,8 @@ impl CodeGenerator<MemoryAggregatingContext, TypeDef, syn::Expr> for TwoPhaseAgg
             .expression_type(&input_context.value_context);
         let input_nullable = incoming_type.is_optional();
         let expr_type = data_type_as_syn_type(incoming_type.as_datatype().unwrap());
+        let sum_type = self.intermediate_type_def(&input_context.value_context);
+        let sum_type = sum_type.as_datatype().unwrap();
         match (&self.aggregator, input_nullable) {
             (Aggregator::Count, _) => {
                 parse_quote!({ arroyo_worker::operators::aggregating_window::count_aggregate(#bin_name) })
@@ -824,17 +1205,32 @@ impl CodeGenerator<MemoryAggregatingContext, TypeDef, syn::Expr> for TwoPhaseAgg
             (Aggregator::Max, false) => parse_quote!({
                 arroyo_worker::operators::aggregating_window::non_nullable_max_heap_aggregate::<#expr_type>(#bin_name)
             }),
-            (Aggregator::Avg, true) => parse_quote!({
-                match &#bin_name.2 {
-                    Some((count, sum)) => Some((*sum as f64) / (*count as f64)),
-                    None => None,
-                }
-            }),
+            (Aggregator::Avg, true) => {
+                let average = Self::average(quote!(*sum), quote!(*count), sum_type);
+                parse_quote!({
+                    match &#bin_name.2 {
+                        Some((count, sum)) => Some(#average),
+                        None => None,
+                    }
+                })
+            }
             (Aggregator::Avg, false) => {
-                parse_quote!({ (#bin_name.1 as f64) / (#bin_name.0 as f64) })
+                let average = Self::average(quote!(#bin_name.1), quote!(#bin_name.0), sum_type);
+                parse_quote!({ #average })
+            }
+            (Aggregator::CountDistinct, _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::distinct_aggregate(#bin_name)
+            }),
+            (Aggregator::ApproxCountDistinct, _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::approx_count_distinct_aggregate(#bin_name)
+            }),
+            (Aggregator::HllSketch | Aggregator::HllUnion, _) => parse_quote!({
+                arroyo_worker::operators::aggregating_window::sketch_aggregate(#bin_name)
+            }),
+            (Aggregator::ApproxPercentile(_), _) => {
+                self.percentile(&bin_name, &input_context.value_context)
             }
-            (Aggregator::CountDistinct, true) => unimplemented!(),
-            (Aggregator::CountDistinct, false) => unimplemented!(),
+            (Aggregator::ArrayAgg, _) => parse_quote!({ #bin_name.concat() }),
         }
     }
 
@@ -844,6 +1240,26 @@ impl CodeGenerator<MemoryAggregatingContext, TypeDef, syn::Expr> for TwoPhaseAgg
 }
 
 impl TwoPhaseAggregation {
+    // converts a value being added to a sum into the type of the sum. Decimals are summed as
+    // decimals, and can't be converted with `as`.
+    fn to_sum_type(value: TokenStream, sum_type: &DataType) -> syn::Expr {
+        if matches!(sum_type, DataType::Decimal128(_, _)) {
+            parse_quote!(#value)
+        } else {
+            let sum_type = data_type_as_syn_type(sum_type);
+            parse_quote!((#value as #sum_type))
+        }
+    }
+
+    // the average of `count` values that add up to `sum`
+    fn average(sum: TokenStream, count: TokenStream, sum_type: &DataType) -> syn::Expr {
+        if matches!(sum_type, DataType::Decimal128(_, _)) {
+            parse_quote!(#sum / arroyo_types::Decimal::from(#count as i64))
+        } else {
+            parse_quote!((#sum as f64) / (#count as f64))
+        }
+    }
+
     fn output_type_def(&self, input_context: &ValuePointerContext) -> TypeDef {
         let incoming_type = self.incoming_expression.expression_type(input_context);
         let (data_type, nullable) = match incoming_type {
@@ -859,7 +1275,20 @@ impl TwoPhaseAggregation {
                 sum_return_type(&data_type).expect("datafusion should've prevented this")
             }
             Aggregator::Min | Aggregator::Max => data_type,
-            Aggregator::CountDistinct => unimplemented!(),
+            Aggregator::CountDistinct | Aggregator::ApproxCountDistinct => {
+                return TypeDef::DataType(DataType::Int64, false)
+            }
+            Aggregator::HllSketch | Aggregator::HllUnion => {
+                return TypeDef::DataType(DataType::Binary, false)
+            }
+            // an empty sketch has no percentiles
+            Aggregator::ApproxPercentile(_) => return TypeDef::DataType(data_type, true),
+            Aggregator::ArrayAgg => {
+                let list_type = self
+                    .aggregator
+                    .return_data_type(TypeDef::DataType(data_type, nullable));
+                return TypeDef::DataType(list_type, false);
+            }
         };
         TypeDef::DataType(aggregate_type, nullable)
     }
@@ -876,8 +1305,19 @@ impl TwoPhaseAggregation {
             Aggregator::Avg | Aggregator::Sum => {
                 sum_return_type(&data_type).expect("datafusion should've prevented this")
             }
-            Aggregator::Min | Aggregator::Max => data_type,
-            Aggregator::CountDistinct => unimplemented!(),
+            Aggregator::Min
+            | Aggregator::Max
+            | Aggregator::CountDistinct
+            | Aggregator::ApproxPercentile(_) => data_type,
+            Aggregator::ApproxCountDistinct | Aggregator::HllSketch | Aggregator::HllUnion => {
+                return TypeDef::DataType(DataType::Binary, false)
+            }
+            Aggregator::ArrayAgg => {
+                let list_type = self
+                    .aggregator
+                    .return_data_type(TypeDef::DataType(data_type, nullable));
+                return TypeDef::DataType(list_type, false);
+            }
         };
         TypeDef::DataType(aggregate_type, nullable)
     }
@@ -906,7 +1346,20 @@ impl TwoPhaseAggregation {
                 BinType::DataType(DataType::Int64),
                 BinType::DataType(aggregate_type),
             ]),
-            (Aggregator::CountDistinct, _) => unimplemented!(),
+            // the number of times each distinct value was seen
+            (Aggregator::CountDistinct, _) => BinType::BTreeMap(
+                Box::new(BinType::DataType(aggregate_type)),
+                Box::new(BinType::Usize),
+            ),
+            (Aggregator::ApproxCountDistinct | Aggregator::HllSketch | Aggregator::HllUnion, _) => {
+                BinType::DataType(DataType::Binary)
+            }
+            // a DDSketch, from bucket keys to counts
+            (Aggregator::ApproxPercentile(_), _) => BinType::BTreeMap(
+                Box::new(BinType::DataType(DataType::Int32)),
+                Box::new(BinType::Usize),
+            ),
+            (Aggregator::ArrayAgg, _) => BinType::DataType(aggregate_type),
         }
     }
 
@@ -955,7 +1408,37 @@ impl TwoPhaseAggregation {
                 BinType::DataType(DataType::Int64),
                 BinType::DataType(aggregate_data_type),
             ]),
-            (Aggregator::CountDistinct, _) => unimplemented!(),
+            (Aggregator::CountDistinct, _) => BinType::BTreeMap(
+                Box::new(BinType::DataType(aggregate_data_type)),
+                Box::new(BinType::Usize),
+            ),
+            // the sketch of each bin in the window, oldest first
+            (Aggregator::ApproxCountDistinct | Aggregator::HllSketch | Aggregator::HllUnion, _) => {
+                BinType::Vec(Box::new(BinType::DataType(DataType::Binary)))
+            }
+            // DDSketches can be subtracted, so a single sketch covers the window
+            (Aggregator::ApproxPercentile(_), _) => BinType::BTreeMap(
+                Box::new(BinType::DataType(DataType::Int32)),
+                Box::new(BinType::Usize),
+            ),
+            // the values of each bin in the window, oldest first
+            (Aggregator::ArrayAgg, _) => {
+                BinType::Vec(Box::new(BinType::DataType(aggregate_data_type)))
+            }
         }
     }
+
+    // the estimated percentile of the DDSketch `sketch`, as the type of the input
+    fn percentile(&self, sketch: &syn::Ident, input_context: &ValuePointerContext) -> syn::Expr {
+        let percentile = self.aggregator.percentile().unwrap();
+        let output_type = data_type_as_syn_type(
+            self.output_type_def(input_context)
+                .as_datatype()
+                .expect("aggregates shouldn't return structs"),
+        );
+        parse_quote!({
+            arroyo_worker::operators::functions::ddsketch::percentile(#sketch, #percentile)
+                .map(|value| value as #output_type)
+        })
+    }
 }
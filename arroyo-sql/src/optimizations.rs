@@ -1,7 +1,9 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use arroyo_datastream::{EdgeType, ExpressionReturnType, ExpressionReturnType::*, WindowType};
+use arroyo_datastream::{
+    EdgeType, ExpressionReturnType, ExpressionReturnType::*, WindowAlignment, WindowType,
+};
 
 use petgraph::data::DataMap;
 use petgraph::graph::DiGraph;
@@ -15,7 +17,7 @@ use tracing::debug;
 
 use crate::code_gen::ValueBinMergingContext;
 use crate::operators::{AggregateProjection, Projection, TwoPhaseAggregateProjection};
-use crate::pipeline::RecordTransform;
+use crate::pipeline::{RecordTransform, WindowFunction};
 use crate::plan_graph::{
     FusedRecordTransform, PlanEdge, PlanNode, PlanOperator, PlanType, WindowFunctionOperator,
 };
@@ -255,13 +257,23 @@ impl Optimizer for TwoPhaseOptimization {
         node: PlanNode,
         graph: &mut DiGraph<PlanNode, PlanEdge>,
     ) -> bool {
-        let PlanOperator::WindowAggregate { window, projection } = node.operator else {
+        let PlanOperator::WindowAggregate {
+            window,
+            projection,
+            allowed_lateness,
+            emit_partial,
+        } = node.operator
+        else {
             return false;
         };
-        let (width, slide) = match window {
-            WindowType::Tumbling { width } => (width, width),
-            WindowType::Sliding { width, slide } => (width, slide),
-            WindowType::Instant => (Duration::ZERO, Duration::ZERO),
+        let (width, slide, alignment) = match window {
+            WindowType::Tumbling { width, alignment } => (width, width, alignment),
+            WindowType::Sliding {
+                width,
+                slide,
+                alignment,
+            } => (width, slide, alignment),
+            WindowType::Instant => (Duration::ZERO, Duration::ZERO, WindowAlignment::default()),
             WindowType::Session { .. } => return false,
         };
         if !slide.is_zero() && width.as_micros() % slide.as_micros() != 0 {
@@ -273,12 +285,16 @@ impl Optimizer for TwoPhaseOptimization {
         let operator = if width == slide {
             PlanOperator::TumblingWindowTwoPhaseAggregator {
                 tumble_width: width,
+                alignment,
+                allowed_lateness,
+                emit_partial,
                 projection,
             }
         } else {
             PlanOperator::SlidingWindowTwoPhaseAggregator {
                 width,
                 slide,
+                alignment,
                 projection,
             }
         };
@@ -337,7 +353,15 @@ impl Optimizer for WindowTopNOptimization {
                 }
             }
             SearchTarget::WindowAggregate => {
-                if let PlanOperator::WindowAggregate { window, projection } = node.operator {
+                if let PlanOperator::WindowAggregate {
+                    window,
+                    projection,
+                    // the local aggregator and top N operators drop late rows, and only emit
+                    // when windows close
+                    allowed_lateness: Duration::ZERO,
+                    emit_partial: None,
+                } = node.operator
+                {
                     self.window_aggregate = Some((window, projection));
                     self.nodes.push(node_index);
                     self.search_target = SearchTarget::PartitionProjection;
@@ -369,7 +393,13 @@ impl Optimizer for WindowTopNOptimization {
                 }
             }
             SearchTarget::WindowFunctionOperator => {
-                if let PlanOperator::WindowFunction(window_function_operator) = node.operator {
+                if let PlanOperator::WindowFunction(
+                    window_function_operator @ WindowFunctionOperator {
+                        window_function: WindowFunction::RowNumber,
+                        ..
+                    },
+                ) = node.operator
+                {
                     let _field_name = window_function_operator.field_name.clone();
                     self.window_function_operator = Some(window_function_operator);
                     self.nodes.push(node_index);
@@ -414,9 +444,15 @@ impl Optimizer for WindowTopNOptimization {
                         let mut additional_nodes = vec![];
                         // Non-shuffle slide-width tumbling aggregator.
                         let (window, projection) = self.window_aggregate.take().unwrap();
+                        if window.has_alignment() {
+                            // the local aggregator and top N operators always align their
+                            // windows to the epoch
+                            self.clear();
+                            return false;
+                        }
                         let (width, slide) = match window {
-                            WindowType::Tumbling { width } => (width, width),
-                            WindowType::Sliding { width, slide } => (width, slide),
+                            WindowType::Tumbling { width, .. } => (width, width),
+                            WindowType::Sliding { width, slide, .. } => (width, slide),
                             WindowType::Instant => (Duration::ZERO, Duration::ZERO),
                             WindowType::Session { .. } => {
                                 return false;
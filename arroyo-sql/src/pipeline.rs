@@ -1,5 +1,5 @@
 #![allow(clippy::comparison_chain)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::time::Duration;
 use std::unreachable;
@@ -7,18 +7,31 @@ use std::unreachable;
 use anyhow::{anyhow, bail};
 use anyhow::{Ok, Result};
 use arrow_schema::DataType;
-use arroyo_datastream::{Operator, WindowType};
+use arroyo_datastream::{Operator, WindowAlignment, WindowType};
+use arroyo_types::FrameBound;
 use datafusion_common::{DFField, ScalarValue};
-use datafusion_expr::expr::ScalarUDF;
+use datafusion_expr::expr::{GroupingSet, ScalarUDF};
+use datafusion_expr::utils::split_conjunction;
 use datafusion_expr::{
-    BinaryExpr, BuiltInWindowFunction, Expr, JoinConstraint, LogicalPlan, Window, WriteOp,
+    Between, BinaryExpr, BuiltInWindowFunction, Expr, JoinConstraint, LogicalPlan, Window,
+    WindowFrameBound, WindowFrameUnits, WriteOp,
 };
 
+use proc_macro2::TokenStream;
 use quote::quote;
+use syn::{parse_quote, Ident};
 
-use crate::code_gen::{CodeGenerator, ValuePointerContext, VecAggregationContext};
-use crate::expressions::{AggregateComputation, AggregateResultExtraction, ExpressionContext};
+use crate::async_udf::AsyncUdfOperator;
+use crate::code_gen::{
+    CodeGenerator, ValuePointerContext, VecAggregationContext, VecOfPointersContext,
+};
+use crate::expressions::{
+    AggregateComputation, AggregateResultExtraction, AggregationExpression, Aggregator,
+    CastExpression, DataStructureFunction, ExpressionContext, LiteralExpression,
+};
 use crate::external::{ProcessingMode, SqlSink, SqlSource};
+use crate::lookup::LookupJoinOperator;
+use crate::match_recognize::MatchRecognizeOperator;
 use crate::operators::{UnnestFieldType, UnnestProjection};
 use crate::schemas::window_type_def;
 use crate::tables::{Insert, Table};
@@ -29,6 +42,11 @@ use crate::{
     ArroyoSchemaProvider,
 };
 
+// the index of the grouping set that a row is aggregated in, which is part of the key of
+// aggregations with ROLLUP, CUBE or GROUPING SETS
+const GROUPING_SET_FIELD: &str = "__grouping_set";
+const MAX_CUBE_EXPRESSIONS: usize = 8;
+
 #[derive(Debug, Clone)]
 pub enum SqlOperator {
     Source(SourceOperator),
@@ -39,6 +57,11 @@ pub enum SqlOperator {
     Union(Vec<SqlOperator>),
     Sink(String, SqlSink, Box<SqlOperator>),
     NamedTable(String, Box<SqlOperator>),
+    MatchRecognize(Box<SqlOperator>, MatchRecognizeOperator),
+    LookupJoin(Box<SqlOperator>, LookupJoinOperator),
+    TopN(Box<SqlOperator>, TopNOperator),
+    AsyncUdf(Box<SqlOperator>, AsyncUdfOperator),
+    LateData(Box<SqlOperator>, LateDataOperator),
 }
 
 #[derive(Debug, Clone)]
@@ -57,6 +80,14 @@ pub struct SourceOperator {
     pub virtual_field_projection: Option<Projection>,
     pub timestamp_override: Option<Expression>,
     pub watermark_column: Option<Expression>,
+    pub deduplication: Option<Deduplication>,
+}
+
+/// Drops records from a source whose key was already seen within the ttl
+#[derive(Debug, Clone)]
+pub struct Deduplication {
+    pub key: Projection,
+    pub ttl: Duration,
 }
 
 impl SourceOperator {
@@ -160,6 +191,8 @@ pub struct AggregateOperator {
     pub key: Projection,
     pub window: WindowType,
     pub aggregating: AggregateProjection,
+    pub allowed_lateness: Duration,
+    pub emit_partial: Option<Duration>,
 }
 
 impl AggregateOperator {
@@ -169,9 +202,371 @@ impl AggregateOperator {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
 pub enum WindowFunction {
     RowNumber,
+    Lag(OffsetWindowFunction),
+    Lead(OffsetWindowFunction),
+    Aggregate(AggregationExpression),
+    TimeSeries(TimeSeriesFunction),
+}
+
+impl WindowFunction {
+    pub fn return_type(&self) -> TypeDef {
+        match self {
+            WindowFunction::RowNumber => TypeDef::DataType(DataType::UInt64, false),
+            WindowFunction::Lag(f) | WindowFunction::Lead(f) => f.return_type(),
+            WindowFunction::Aggregate(aggregate) => {
+                aggregate.expression_type(&VecOfPointersContext)
+            }
+            WindowFunction::TimeSeries(_) => TypeDef::DataType(DataType::Float64, true),
+        }
+    }
+
+    /// The frame the function is computed over. Only aggregates use the frame from the query, as
+    /// the others are defined in terms of rows.
+    fn frame(&self, window_frame: &datafusion_expr::WindowFrame) -> Result<Frame> {
+        Ok(match self {
+            WindowFunction::RowNumber => Frame {
+                preceding: FrameBound::Rows(0),
+                following: FrameBound::Rows(0),
+            },
+            WindowFunction::Lag(f) => Frame {
+                preceding: FrameBound::Rows(f.offset),
+                following: FrameBound::Rows(0),
+            },
+            WindowFunction::Lead(f) => Frame {
+                preceding: FrameBound::Rows(0),
+                following: FrameBound::Rows(f.offset),
+            },
+            WindowFunction::Aggregate(_) => Frame {
+                preceding: Frame::bound(window_frame, &window_frame.start_bound, true)?,
+                following: Frame::bound(window_frame, &window_frame.end_bound, false)?,
+            },
+            WindowFunction::TimeSeries(f) => f.frame(),
+        })
+    }
+
+    /// Generates the function's value for the row at index `current` of `frame`, the rows of its
+    /// frame, which is row `row_number` of its partition. `previous` is the last output for the
+    /// partition, in which the function's value is `field`.
+    pub fn generate(&self, field: &Ident) -> syn::Expr {
+        match self {
+            WindowFunction::RowNumber => parse_quote!(row_number),
+            WindowFunction::Lag(f) => {
+                let offset = f.offset as usize;
+                f.generate(quote!(current.checked_sub(#offset)))
+            }
+            WindowFunction::Lead(f) => {
+                let offset = f.offset as usize;
+                f.generate(quote!(current.checked_add(#offset)))
+            }
+            WindowFunction::Aggregate(aggregate) => {
+                let aggregate = aggregate.generate(&VecOfPointersContext);
+                parse_quote!({
+                    let arg = frame;
+                    #aggregate
+                })
+            }
+            WindowFunction::TimeSeries(f) => f.generate(field),
+        }
+    }
+}
+
+/// LAG or LEAD, which return the value of `expression` for the row `offset` rows before or after
+/// the current one, or `default` if there is no such row
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct OffsetWindowFunction {
+    pub expression: Expression,
+    pub offset: u64,
+    pub default: Option<Expression>,
+}
+
+impl OffsetWindowFunction {
+    fn new(ctx: &mut ExpressionContext, args: &[Expr]) -> Result<Self> {
+        let expression = ctx.compile_expr(
+            args.get(0)
+                .ok_or_else(|| anyhow!("LAG and LEAD require an argument"))?,
+        )?;
+
+        let offset = match args.get(1) {
+            None => 1,
+            Some(Expr::Literal(ScalarValue::Int64(Some(offset)))) if *offset >= 0 => *offset as u64,
+            Some(offset) => bail!(
+                "offsets for LAG and LEAD must be non-negative integers, not {}",
+                offset
+            ),
+        };
+
+        let value_context = ValuePointerContext::new();
+        let default = match args.get(2) {
+            Some(default) => {
+                let default = ctx.compile_expr(default)?;
+                let data_type = expression
+                    .expression_type(&value_context)
+                    .as_datatype()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("LAG and LEAD don't support structs"))?;
+                if default.expression_type(&value_context).as_datatype() == Some(&data_type) {
+                    Some(default)
+                } else {
+                    Some(CastExpression::new(
+                        Box::new(default),
+                        &data_type,
+                        &value_context,
+                        false,
+                    )?)
+                }
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            expression,
+            offset,
+            default,
+        })
+    }
+
+    fn return_type(&self) -> TypeDef {
+        let value_context = ValuePointerContext::new();
+        let nullable = self
+            .default
+            .as_ref()
+            .map(|default| default.expression_type(&value_context).is_optional())
+            .unwrap_or(true);
+        let expression_type = self.expression.expression_type(&value_context);
+        let nullable = nullable || expression_type.is_optional();
+        expression_type.with_nullity(nullable)
+    }
+
+    fn generate(&self, index: TokenStream) -> syn::Expr {
+        let value_context = ValuePointerContext::new();
+        let value = self.expression.generate(&value_context);
+        let value = if self
+            .expression
+            .expression_type(&value_context)
+            .is_optional()
+        {
+            quote!(#value)
+        } else {
+            quote!(Some(#value))
+        };
+        let offset_value = quote!(#index.and_then(|i| frame.get(i)).and_then(|arg| #value));
+
+        let Some(default) = &self.default else {
+            return parse_quote!(#offset_value);
+        };
+
+        let default_value = default.generate(&value_context);
+        if !self.return_type().is_optional() {
+            parse_quote!(#offset_value.unwrap_or_else(|| #default_value))
+        } else if default.expression_type(&value_context).is_optional() {
+            parse_quote!(#offset_value.or_else(|| #default_value))
+        } else {
+            parse_quote!(#offset_value.or_else(|| Some(#default_value)))
+        }
+    }
+}
+
+/// Functions over the rows of each partition in time order, for metrics and other time series.
+/// They're computed from the previous row, or the previous output, so they ignore the frame
+/// from the query.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub enum TimeSeriesFunction {
+    /// The change in `value` per second of `time` since the previous row
+    Rate { value: Expression, time: Expression },
+    /// The increase of a counter since the previous row, where a decrease means the counter was
+    /// reset, so the increase is its new value
+    CounterDelta { value: Expression },
+    /// The exponentially weighted moving average of `value`, in which the current row has weight
+    /// `alpha`, a literal between 0 and 1
+    Ewma {
+        value: Expression,
+        alpha: Expression,
+    },
+}
+
+impl TimeSeriesFunction {
+    pub const NAMES: [&'static str; 3] = ["rate", "counter_delta", "ewma"];
+
+    fn new(
+        ctx: &mut ExpressionContext,
+        name: &str,
+        args: &[Expr],
+        order_by: &[Expr],
+    ) -> Result<Self> {
+        let expected_args = if name == "ewma" { 2 } else { 1 };
+        if args.len() != expected_args {
+            bail!("wrong number of arguments for {}()", name);
+        }
+
+        if !SqlPipelineBuilder::is_event_time_order(ctx, order_by)? {
+            bail!(
+                "{}() must be ordered by a timestamp ascending, as in \
+                {}(..) OVER (PARTITION BY .. ORDER BY event_time)",
+                name,
+                name
+            );
+        }
+
+        let value = ctx.compile_expr(&args[0])?;
+        match value.expression_type(&ValuePointerContext::new()) {
+            TypeDef::DataType(
+                DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Float32
+                | DataType::Float64,
+                _,
+            ) => {}
+            t => bail!("{}() requires a numeric argument, not {:?}", name, t),
+        }
+
+        Ok(match name {
+            "rate" => {
+                let [Expr::Sort(sort)] = order_by else {
+                    unreachable!("event time order is a single sort")
+                };
+                TimeSeriesFunction::Rate {
+                    value,
+                    time: ctx.compile_expr(&sort.expr)?,
+                }
+            }
+            "counter_delta" => TimeSeriesFunction::CounterDelta { value },
+            "ewma" => {
+                let alpha = match &args[1] {
+                    Expr::Literal(ScalarValue::Float64(Some(alpha))) => *alpha,
+                    Expr::Literal(ScalarValue::Int64(Some(alpha))) => *alpha as f64,
+                    _ => bail!("the alpha of ewma() must be a numeric literal"),
+                };
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    bail!("the alpha of ewma() must be greater than 0 and at most 1");
+                }
+                TimeSeriesFunction::Ewma {
+                    value,
+                    alpha: LiteralExpression::new(ScalarValue::Float64(Some(alpha))),
+                }
+            }
+            _ => bail!("unknown time series function {}", name),
+        })
+    }
+
+    fn frame(&self) -> Frame {
+        let preceding = match self {
+            TimeSeriesFunction::Rate { .. } | TimeSeriesFunction::CounterDelta { .. } => 1,
+            TimeSeriesFunction::Ewma { .. } => 0,
+        };
+        Frame {
+            preceding: FrameBound::Rows(preceding),
+            following: FrameBound::Rows(0),
+        }
+    }
+
+    // an Option of the value of `expression` for `arg`, as an f64 if `numeric`
+    fn optional(expression: &Expression, numeric: bool) -> TokenStream {
+        let value_context = ValuePointerContext::new();
+        let value = expression.generate(&value_context);
+        let nullable = expression.expression_type(&value_context).is_optional();
+        match (nullable, numeric) {
+            (true, true) => quote!((#value).map(|v| v as f64)),
+            (true, false) => quote!(#value),
+            (false, true) => quote!(Some((#value) as f64)),
+            (false, false) => quote!(Some(#value)),
+        }
+    }
+
+    fn generate(&self, field: &Ident) -> syn::Expr {
+        match self {
+            TimeSeriesFunction::Rate { value, time } => {
+                let value = Self::optional(value, true);
+                let time = Self::optional(time, false);
+                parse_quote!((|| {
+                    let value = #value?;
+                    let time = #time?;
+                    let arg = current.checked_sub(1).and_then(|i| frame.get(i))?;
+                    let previous_value = #value?;
+                    let seconds = time.duration_since(#time?).ok()?.as_secs_f64();
+                    (seconds > 0.0).then(|| (value - previous_value) / seconds)
+                })())
+            }
+            TimeSeriesFunction::CounterDelta { value } => {
+                let value = Self::optional(value, true);
+                parse_quote!((|| {
+                    let value = #value?;
+                    let arg = current.checked_sub(1).and_then(|i| frame.get(i))?;
+                    let previous_value = #value?;
+                    Some(if value >= previous_value {
+                        value - previous_value
+                    } else {
+                        value
+                    })
+                })())
+            }
+            TimeSeriesFunction::Ewma { value, alpha } => {
+                let value = Self::optional(value, true);
+                let alpha = alpha.generate(&ValuePointerContext::new());
+                parse_quote!({
+                    let alpha: f64 = #alpha;
+                    match (#value, previous.and_then(|previous| previous.#field)) {
+                        (Some(value), Some(average)) => {
+                            Some(alpha * value + (1.0 - alpha) * average)
+                        }
+                        (value, average) => value.or(average),
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// The rows that a window function is computed over, relative to the current row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub preceding: FrameBound,
+    pub following: FrameBound,
+}
+
+impl Frame {
+    fn bound(
+        window_frame: &datafusion_expr::WindowFrame,
+        bound: &WindowFrameBound,
+        start: bool,
+    ) -> Result<FrameBound> {
+        let value = match (bound, start) {
+            (WindowFrameBound::CurrentRow, _) => {
+                return Ok(match window_frame.units {
+                    // a range includes the rows with the same time as the current one
+                    WindowFrameUnits::Range => FrameBound::Time(Duration::ZERO),
+                    _ => FrameBound::Rows(0),
+                });
+            }
+            (WindowFrameBound::Preceding(value), true)
+            | (WindowFrameBound::Following(value), false) => value,
+            _ => bail!("window frames must include the current row"),
+        };
+
+        if value.is_null() {
+            return Ok(FrameBound::Unbounded);
+        }
+
+        match window_frame.units {
+            WindowFrameUnits::Rows => match value {
+                ScalarValue::UInt64(Some(rows)) => Ok(FrameBound::Rows(*rows)),
+                _ => bail!("expected a number of rows for ROWS frame, found {}", value),
+            },
+            WindowFrameUnits::Range => Ok(FrameBound::Time(
+                SqlPipelineBuilder::get_duration(&Expr::Literal(value.clone()))
+                    .map_err(|_| anyhow!("RANGE frames must be bounded by intervals of time"))?,
+            )),
+            WindowFrameUnits::Groups => bail!("GROUPS frames are not supported"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -186,7 +581,37 @@ pub struct SqlWindowOperator {
     pub partition: Projection,
     pub order_by: Vec<SortExpression>,
     pub field_name: String,
-    pub window_type: WindowType,
+    /// The window that the function is partitioned by, or None if it's computed over the rows
+    /// of each partition as they arrive
+    pub window_type: Option<WindowType>,
+    pub frame: Frame,
+}
+
+/// The `allowed_lateness()`, `late_data_sink()` and `emit_partial()` arguments of a tumble()
+#[derive(Debug, Default, PartialEq)]
+struct TumbleOptions {
+    allowed_lateness: Duration,
+    late_data_sink: Option<String>,
+    emit_partial: Option<Duration>,
+}
+
+/// The rows of a tumbling window's input that arrive too late for the window to include them,
+/// given its allowed lateness. They're written to the sink passed to `late_data_sink()`.
+#[derive(Debug, Clone)]
+pub struct LateDataOperator {
+    pub width: Duration,
+    pub alignment: WindowAlignment,
+    pub allowed_lateness: Duration,
+}
+
+/// A ROW_NUMBER that isn't partitioned by a window, filtered to the first `max_elements` rows of
+/// each partition. Only those rows are kept, and their row numbers are updated as rows arrive.
+#[derive(Debug, Clone)]
+pub struct TopNOperator {
+    pub partition: Projection,
+    pub order_by: Vec<SortExpression>,
+    pub field_name: String,
+    pub max_elements: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -194,6 +619,134 @@ pub struct JoinOperator {
     pub left_key: Projection,
     pub right_key: Projection,
     pub join_type: JoinType,
+    /// For interval joins, the bounds on the time between matching rows
+    pub interval: Option<JoinInterval>,
+}
+
+/// Bounds on `left time - right time`, in nanoseconds, for rows to match in an interval join
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinInterval {
+    pub lower_bound: i64,
+    pub upper_bound: i64,
+}
+
+/// The time band accumulated from the non-equality predicates of a join
+#[derive(Debug, Default)]
+struct TimeBand {
+    columns: Option<(Expr, Expr)>,
+    lower_bound: Option<i64>,
+    upper_bound: Option<i64>,
+}
+
+impl TimeBand {
+    fn add_predicate(
+        &mut self,
+        predicate: &Expr,
+        left_relation: &str,
+        right_relation: &str,
+    ) -> Result<()> {
+        use datafusion_expr::Operator as Op;
+
+        let (left, op, right) = match predicate {
+            Expr::Between(Between {
+                expr,
+                negated: false,
+                low,
+                high,
+            }) => {
+                self.add_predicate(
+                    &Expr::BinaryExpr(BinaryExpr::new(expr.clone(), Op::GtEq, low.clone())),
+                    left_relation,
+                    right_relation,
+                )?;
+                return self.add_predicate(
+                    &Expr::BinaryExpr(BinaryExpr::new(expr.clone(), Op::LtEq, high.clone())),
+                    left_relation,
+                    right_relation,
+                );
+            }
+            Expr::BinaryExpr(BinaryExpr { left, op, right })
+                if matches!(op, Op::Lt | Op::LtEq | Op::Gt | Op::GtEq) =>
+            {
+                (left, *op, right)
+            }
+            _ => bail!(
+                "only equality and time-bound predicates are supported in joins, not {}",
+                predicate
+            ),
+        };
+
+        let (Some((first, first_offset)), Some((second, second_offset))) =
+            (Self::time_offset(left), Self::time_offset(right))
+        else {
+            bail!(
+                "time bounds in joins must compare columns offset by intervals, not {}",
+                predicate
+            );
+        };
+
+        // normalize to `left time - right time <op> bound`
+        let relation = |c: &Expr| Column::convert_expr(c).ok().and_then(|c| c.relation);
+        let (left_time, right_time, op, bound) = if relation(&first).as_deref()
+            == Some(left_relation)
+            && relation(&second).as_deref() == Some(right_relation)
+        {
+            (first, second, op, second_offset - first_offset)
+        } else if relation(&first).as_deref() == Some(right_relation)
+            && relation(&second).as_deref() == Some(left_relation)
+        {
+            let op = match op {
+                Op::Lt => Op::Gt,
+                Op::LtEq => Op::GtEq,
+                Op::Gt => Op::Lt,
+                _ => Op::LtEq,
+            };
+            (second, first, op, first_offset - second_offset)
+        } else {
+            bail!(
+                "time bounds in joins must compare a column from each side of the join, not {}",
+                predicate
+            );
+        };
+
+        match &self.columns {
+            Some(columns) if *columns != (left_time.clone(), right_time.clone()) => {
+                bail!("all time bounds in a join must compare the same pair of columns")
+            }
+            _ => self.columns = Some((left_time, right_time)),
+        }
+
+        match op {
+            Op::Gt | Op::GtEq => {
+                let bound = if op == Op::Gt { bound + 1 } else { bound };
+                self.lower_bound = Some(self.lower_bound.map_or(bound, |b| b.max(bound)));
+            }
+            _ => {
+                let bound = if op == Op::Lt { bound - 1 } else { bound };
+                self.upper_bound = Some(self.upper_bound.map_or(bound, |b| b.min(bound)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits an expression like `b.ts - INTERVAL '10' MINUTE` into the column and its offset in
+    /// nanoseconds
+    fn time_offset(expr: &Expr) -> Option<(Expr, i64)> {
+        use datafusion_expr::Operator as Op;
+        match expr {
+            Expr::Column(_) => Some((expr.clone(), 0)),
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+                let (column, offset) = Self::time_offset(left)?;
+                let duration = SqlPipelineBuilder::get_duration(right).ok()?.as_nanos() as i64;
+                match op {
+                    Op::Plus => Some((column, offset + duration)),
+                    Op::Minus => Some((column, offset - duration)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -304,7 +857,7 @@ impl SqlOperator {
                 input_struct.fields.push(StructField::new(
                     window.field_name.clone(),
                     None,
-                    TypeDef::DataType(DataType::UInt64, false),
+                    window.window_fn.return_type(),
                 ));
                 input_struct
             }
@@ -314,6 +867,19 @@ impl SqlOperator {
             SqlOperator::Sink(_, sql_sink, _) => sql_sink.struct_def.clone(),
             SqlOperator::NamedTable(_table_name, table) => table.return_type(),
             SqlOperator::Union(inputs) => inputs[0].return_type(),
+            SqlOperator::MatchRecognize(_, match_recognize) => match_recognize.output_struct(),
+            SqlOperator::LookupJoin(_, lookup_join) => lookup_join.output_struct(),
+            SqlOperator::TopN(input, top_n) => {
+                let mut input_struct = input.return_type();
+                input_struct.fields.push(StructField::new(
+                    top_n.field_name.clone(),
+                    None,
+                    WindowFunction::RowNumber.return_type(),
+                ));
+                input_struct
+            }
+            SqlOperator::AsyncUdf(_, async_udf) => async_udf.output_struct(),
+            SqlOperator::LateData(input, _) => input.return_type(),
         }
     }
 
@@ -324,11 +890,18 @@ impl SqlOperator {
                 !matches!(aggregator.window, WindowType::Instant) || input.has_window()
             }
             SqlOperator::JoinOperator(left, right, _) => left.has_window() || right.has_window(),
-            SqlOperator::Window(_, _) => true,
+            SqlOperator::Window(input, window) => {
+                window.window_type.is_some() || input.has_window()
+            }
             SqlOperator::RecordTransform(input, _) => input.has_window(),
             SqlOperator::Sink(_, _, input) => input.has_window(),
             SqlOperator::NamedTable(_, input) => input.has_window(),
             SqlOperator::Union(inputs) => inputs[0].has_window(),
+            SqlOperator::MatchRecognize(..) => false,
+            SqlOperator::LookupJoin(input, _) => input.has_window(),
+            SqlOperator::TopN(..) => false,
+            SqlOperator::AsyncUdf(input, _) => input.has_window(),
+            SqlOperator::LateData(input, _) => input.has_window(),
         }
     }
 
@@ -351,12 +924,19 @@ impl SqlOperator {
             }
             SqlOperator::Window(input, sql_window_operator) => {
                 input.is_updating() // TODO: figure out when this second case is supposed to be triggered.
-                    || (!input.has_window() && sql_window_operator.window_type == WindowType::Instant)
+                    || (!input.has_window() && sql_window_operator.window_type == Some(WindowType::Instant))
             }
             SqlOperator::RecordTransform(input, _) => input.is_updating(),
             SqlOperator::Sink(_, _, input) => input.is_updating(),
             SqlOperator::NamedTable(_, table_operator) => table_operator.is_updating(),
             SqlOperator::Union(inputs) => inputs[0].is_updating(),
+            SqlOperator::MatchRecognize(..) => false,
+            // each row is joined once, with whatever the lookup table held at the time
+            SqlOperator::LookupJoin(..) => false,
+            // rows are retracted as they're pushed out of the top N
+            SqlOperator::TopN(..) => true,
+            SqlOperator::AsyncUdf(..) => false,
+            SqlOperator::LateData(input, _) => input.is_updating(),
         }
     }
 
@@ -370,13 +950,27 @@ impl SqlOperator {
                 WindowType::Instant => input.get_window(),
             },
             SqlOperator::JoinOperator(left, _, _) => left.get_window(),
-            SqlOperator::Window(_, sql_window_operator) => {
-                Some(sql_window_operator.window_type.clone())
-            }
+            SqlOperator::Window(input, sql_window_operator) => sql_window_operator
+                .window_type
+                .clone()
+                .or_else(|| input.get_window()),
             SqlOperator::RecordTransform(input, _) => input.get_window(),
             SqlOperator::Sink(_, _, input) => input.get_window(),
             SqlOperator::NamedTable(_, input) => input.get_window(),
             SqlOperator::Union(inputs) => inputs[0].get_window(),
+            SqlOperator::MatchRecognize(..) => None,
+            SqlOperator::LookupJoin(input, _) => input.get_window(),
+            SqlOperator::TopN(..) => None,
+            SqlOperator::AsyncUdf(input, _) => input.get_window(),
+            SqlOperator::LateData(input, _) => input.get_window(),
+        }
+    }
+}
+
+fn collect_subplans<'a>(plan: &'a LogicalPlan, subplans: &mut HashSet<&'a LogicalPlan>) {
+    if subplans.insert(plan) {
+        for input in plan.inputs() {
+            collect_subplans(input, subplans);
         }
     }
 }
@@ -386,6 +980,10 @@ pub struct SqlPipelineBuilder<'a> {
     pub schema_provider: &'a ArroyoSchemaProvider,
     pub planned_tables: HashMap<String, SqlOperator>,
     pub insert_nodes: Vec<SqlOperator>,
+    // subplans read by more than one insert, which are planned as named tables so they're shared
+    shared_plans: HashMap<LogicalPlan, String>,
+    // sinks for the rows that tumbling windows drop for being late, from late_data_sink()
+    pub late_data_sinks: Vec<SqlOperator>,
 }
 
 impl<'a> SqlPipelineBuilder<'a> {
@@ -394,6 +992,37 @@ impl<'a> SqlPipelineBuilder<'a> {
             schema_provider,
             planned_tables: HashMap::new(),
             insert_nodes: vec![],
+            shared_plans: HashMap::new(),
+            late_data_sinks: vec![],
+        }
+    }
+
+    /// Finds the parts of the inserts' plans that more than one of them computes, so that a
+    /// statement set with several inserts reading from the same query computes it once and fans
+    /// out to each sink. Table scans are already shared by name, so they're left alone.
+    pub(crate) fn share_common_plans<'b>(
+        &mut self,
+        plans: impl IntoIterator<Item = &'b LogicalPlan>,
+    ) {
+        let mut counts: HashMap<&LogicalPlan, usize> = HashMap::new();
+        let mut order = vec![];
+        for plan in plans {
+            let mut subplans = HashSet::new();
+            collect_subplans(plan, &mut subplans);
+            for subplan in subplans {
+                let count = counts.entry(subplan).or_default();
+                if *count == 0 {
+                    order.push(subplan);
+                }
+                *count += 1;
+            }
+        }
+
+        for plan in order {
+            if counts[plan] > 1 && !matches!(plan, LogicalPlan::TableScan(_)) {
+                let name = format!("__shared_plan_{}", self.shared_plans.len());
+                self.shared_plans.insert(plan.clone(), name);
+            }
         }
     }
 
@@ -405,6 +1034,16 @@ impl<'a> SqlPipelineBuilder<'a> {
     }
 
     pub fn insert_sql_plan(&mut self, plan: &LogicalPlan) -> Result<SqlOperator> {
+        if !self.shared_plans.is_empty() {
+            if let Some(name) = self.shared_plans.get(plan).cloned() {
+                let operator = self.insert_plan_node(plan)?;
+                return Ok(SqlOperator::NamedTable(name, Box::new(operator)));
+            }
+        }
+        self.insert_plan_node(plan)
+    }
+
+    fn insert_plan_node(&mut self, plan: &LogicalPlan) -> Result<SqlOperator> {
         match plan {
             LogicalPlan::Projection(projection) => self.insert_projection(projection),
             LogicalPlan::Filter(filter) => self.insert_filter(filter),
@@ -488,12 +1127,22 @@ impl<'a> SqlPipelineBuilder<'a> {
         &mut self,
         filter: &datafusion_expr::logical_plan::Filter,
     ) -> Result<SqlOperator> {
-        let input = self.insert_sql_plan(&filter.input)?;
+        let input = match filter.input.as_ref() {
+            LogicalPlan::Window(window) => {
+                let input = self.insert_sql_plan(&window.input)?;
+                match self.top_n_operator(&input, window, &filter.predicate)? {
+                    Some(top_n) => SqlOperator::TopN(Box::new(input), top_n),
+                    None => self.add_window_functions(input, window)?,
+                }
+            }
+            input => self.insert_sql_plan(input)?,
+        };
         let struct_def = input.return_type();
         let ctx = self.ctx(&struct_def);
         let mut predicate = ctx.compile_expr(&filter.predicate)?;
 
         Self::assert_no_unnest("where", &mut predicate)?;
+        Self::assert_no_async_udf("where", &predicate)?;
 
         Ok(SqlOperator::RecordTransform(
             Box::new(input),
@@ -535,6 +1184,27 @@ impl<'a> SqlPipelineBuilder<'a> {
         }
     }
 
+    fn assert_no_async_udf(ctx: &str, expr: &Expression) -> Result<()> {
+        let mut found = None;
+        let mut expr = expr.clone();
+        expr.traverse_mut(&mut found, &|ctx, e| match e {
+            Expression::RustUdf(udf) if udf.async_config.is_some() => {
+                ctx.replace(udf.name.clone());
+            }
+            _ => {}
+        });
+
+        if let Some(name) = found {
+            bail!(
+                "{} may not call async UDF {}, as async UDFs can only be called in the SELECT list",
+                ctx,
+                name
+            );
+        } else {
+            Ok(())
+        }
+    }
+
     fn insert_projection(
         &mut self,
         projection: &datafusion_expr::logical_plan::Projection,
@@ -558,10 +1228,12 @@ impl<'a> SqlPipelineBuilder<'a> {
 
         let fields: Vec<_> = names.zip(functions).collect();
 
+        let mut async_udf = None;
         let mut unnest = None;
         let fields = fields
             .into_iter()
             .map(|(col, mut expr)| {
+                AsyncUdfOperator::split(&mut expr, &mut async_udf)?;
                 let typ = if let Some(e) = Self::split_unnest(&mut expr)? {
                     if let Some(prev) = unnest.replace(e) {
                         if &prev != unnest.as_ref().unwrap() {
@@ -577,6 +1249,19 @@ impl<'a> SqlPipelineBuilder<'a> {
             })
             .collect::<Result<Vec<_>>>()?;
 
+        // async UDFs are called by their own operator, which adds their results to the rows that
+        // the projection reads
+        let input = match async_udf {
+            Some(udf) => {
+                if input.is_updating() {
+                    bail!("async UDFs are not supported over updating inputs");
+                }
+                let async_udf = AsyncUdfOperator::new(udf, struct_def);
+                SqlOperator::AsyncUdf(Box::new(input), async_udf)
+            }
+            None => input,
+        };
+
         if let Some(unnest_inner) = unnest {
             Ok(SqlOperator::RecordTransform(
                 Box::new(input),
@@ -600,23 +1285,46 @@ impl<'a> SqlPipelineBuilder<'a> {
         &mut self,
         aggregate: &datafusion_expr::logical_plan::Aggregate,
     ) -> Result<SqlOperator> {
-        let source = self.insert_sql_plan(&aggregate.input)?;
+        let mut source = self.insert_sql_plan(&aggregate.input)?;
         /*if source.is_updating() {
             bail!("can't aggregate over updating inputs");
         }*/
-        let key = self.aggregation_key(
-            &aggregate.group_expr,
+
+        let mut group_expr = aggregate.group_expr.clone();
+        let mut grouping_sets = false;
+        if let [Expr::GroupingSet(grouping_set)] = aggregate.group_expr.as_slice() {
+            let sets = Self::expand_grouping_set(grouping_set)?;
+            let (expanded, keys) = Self::grouping_set_keys(source, &sets)?;
+            source = expanded;
+            group_expr = keys;
+            grouping_sets = true;
+        }
+
+        let mut key = self.aggregation_key(
+            &group_expr,
             aggregate.schema.fields(),
             &source.return_type(),
         )?;
+        if grouping_sets {
+            let source_return_type = source.return_type();
+            let grouping_set = self.ctx(&source_return_type).compile_expr(&Expr::Column(
+                datafusion_common::Column::from_name(GROUPING_SET_FIELD),
+            ))?;
+            key.fields.push((
+                Column {
+                    relation: None,
+                    name: GROUPING_SET_FIELD.to_string(),
+                },
+                grouping_set,
+            ));
+        }
 
-        let window = self.window(&aggregate.group_expr)?;
+        let window = self.window(&group_expr)?;
 
         let source_return_type = source.return_type();
         let mut ctx = self.ctx(&source_return_type);
 
-        let group_bys = aggregate
-            .group_expr
+        let group_bys = group_expr
             .iter()
             .zip(aggregate.schema.fields().iter())
             .map(|(expr, field)| {
@@ -624,6 +1332,7 @@ impl<'a> SqlPipelineBuilder<'a> {
 
                 let expression = ctx.compile_expr(expr)?;
                 Self::assert_no_unnest("group by", &expression)?;
+                Self::assert_no_async_udf("group by", &expression)?;
 
                 let (data_type, extraction) = if let Some(window) = Self::find_window(expr)? {
                     if let WindowType::Instant = window {
@@ -648,10 +1357,25 @@ impl<'a> SqlPipelineBuilder<'a> {
             .schema
             .fields()
             .iter()
-            .skip(aggregate.group_expr.len()) // the group bys always come first
+            .skip(group_expr.len()) // the group bys always come first
             .zip(aggregate.aggr_expr.iter())
             .map(|(field, expr)| {
-                AggregateComputation::try_from_expression(&mut ctx, &field.qualified_column(), expr)
+                let computation = AggregateComputation::try_from_expression(
+                    &mut ctx,
+                    &field.qualified_column(),
+                    expr,
+                )?;
+                match &computation {
+                    AggregateComputation::Builtin { computation, .. } => {
+                        Self::assert_no_async_udf("aggregate", &computation.producing_expression)?
+                    }
+                    AggregateComputation::UDAF { computation, .. } => {
+                        for (_, arg) in &computation.args {
+                            Self::assert_no_async_udf("aggregate", arg)?;
+                        }
+                    }
+                }
+                Ok(computation)
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -664,7 +1388,53 @@ impl<'a> SqlPipelineBuilder<'a> {
             && matches!(window, WindowType::Instant)
             && !aggregating.supports_two_phase()
         {
-            bail!("updating aggregates only support two phase aggregations. Currently count distinct is not supported");
+            bail!("updating aggregates only support two phase aggregations. Currently UDAFs are only supported if they're computed by an accumulator");
+        }
+
+        if window.has_alignment() && !aggregating.supports_two_phase() {
+            bail!("windows with an offset or a time zone only support UDAFs that are computed by an accumulator");
+        }
+
+        if source.is_updating() && !aggregating.is_retractable() {
+            bail!("approx_count_distinct(), hll_sketch_agg(), hll_union_agg(), array_agg() and UDAFs without a retract() method can't be computed over updating inputs, as their state can't be retracted");
+        }
+
+        let options = Self::tumble_options(&group_expr)?;
+        if options != TumbleOptions::default() && !aggregating.supports_two_phase() {
+            bail!("allowed_lateness(), late_data_sink() and emit_partial() only support UDAFs that are computed by an accumulator");
+        }
+
+        if let (Some(sink), WindowType::Tumbling { width, alignment }) =
+            (&options.late_data_sink, &window)
+        {
+            let Some(table) = self.schema_provider.get_table(sink) else {
+                bail!("late_data_sink() table {} does not exist", sink);
+            };
+            let Table::ConnectorTable(table) = table else {
+                bail!(
+                    "late_data_sink() must be passed a connector table, not {}",
+                    sink
+                );
+            };
+
+            // the window and the late data filter read the same input
+            source = SqlOperator::NamedTable(
+                format!("__late_data_input_{}", self.late_data_sinks.len()),
+                Box::new(source),
+            );
+            let late_rows = SqlOperator::LateData(
+                Box::new(source.clone()),
+                LateDataOperator {
+                    width: *width,
+                    alignment: alignment.clone(),
+                    allowed_lateness: options.allowed_lateness,
+                },
+            );
+            self.late_data_sinks.push(
+                table
+                    .as_sql_sink(late_rows)
+                    .map_err(|e| anyhow!("failed to plan late data sink {}: {}", sink, e))?,
+            );
         }
 
         Ok(SqlOperator::Aggregator(
@@ -673,10 +1443,180 @@ impl<'a> SqlPipelineBuilder<'a> {
                 key,
                 window,
                 aggregating,
+                allowed_lateness: options.allowed_lateness,
+                emit_partial: options.emit_partial,
             },
         ))
     }
 
+    /// Reads the options passed to a tumble() in the group by
+    fn tumble_options(group_expressions: &[Expr]) -> Result<TumbleOptions> {
+        let mut options = TumbleOptions::default();
+        for expr in group_expressions {
+            let expr = match expr {
+                Expr::Alias(datafusion_expr::expr::Alias { expr, name: _ }) => expr.as_ref(),
+                expr => expr,
+            };
+            let Expr::ScalarUDF(ScalarUDF { fun, args }) = expr else {
+                continue;
+            };
+            if fun.name != "tumble" {
+                continue;
+            }
+            for arg in args {
+                let Expr::ScalarUDF(ScalarUDF { fun, args }) = arg else {
+                    continue;
+                };
+                match (fun.name.as_str(), args.as_slice()) {
+                    ("allowed_lateness", [lateness]) => {
+                        options.allowed_lateness = Self::get_duration(lateness)?;
+                    }
+                    ("late_data_sink", [Expr::Literal(ScalarValue::Utf8(Some(sink)))]) => {
+                        options.late_data_sink = Some(sink.clone());
+                    }
+                    ("late_data_sink", _) => {
+                        bail!("late_data_sink() expects the name of a table")
+                    }
+                    ("emit_partial", [interval]) => {
+                        let interval = Self::get_duration(interval)?;
+                        if interval.is_zero() {
+                            bail!("the interval of emit_partial() must be positive");
+                        }
+                        options.emit_partial = Some(interval);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Lists the sets of expressions that a ROLLUP, CUBE or GROUPING SETS groups by, starting
+    /// with the one with the most expressions for ROLLUP and CUBE
+    fn expand_grouping_set(grouping_set: &GroupingSet) -> Result<Vec<Vec<Expr>>> {
+        match grouping_set {
+            GroupingSet::Rollup(exprs) => Ok((0..=exprs.len())
+                .rev()
+                .map(|n| exprs[..n].to_vec())
+                .collect()),
+            GroupingSet::Cube(exprs) => {
+                if exprs.len() > MAX_CUBE_EXPRESSIONS {
+                    bail!(
+                        "CUBE supports at most {} expressions, as each row is aggregated once per combination of them",
+                        MAX_CUBE_EXPRESSIONS
+                    );
+                }
+                Ok((0..1usize << exprs.len())
+                    .rev()
+                    .map(|mask| {
+                        exprs
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| mask & (1 << (exprs.len() - 1 - i)) != 0)
+                            .map(|(_, expr)| expr.clone())
+                            .collect()
+                    })
+                    .collect())
+            }
+            GroupingSet::GroupingSets(sets) => Ok(sets.clone()),
+        }
+    }
+
+    /// Grouping sets are computed by a single aggregation, rather than one per set. Each input
+    /// row is emitted once per grouping set, tagged with the index of the set, and the
+    /// aggregation is keyed by that index along with every grouping expression, with the
+    /// expressions that aren't in a row's set replaced by nulls. All of the sets are then
+    /// windowed and stored by the same operator.
+    ///
+    /// Returns the expanded input and the grouping expressions, in the order of the aggregate's
+    /// output fields.
+    fn grouping_set_keys(
+        source: SqlOperator,
+        sets: &[Vec<Expr>],
+    ) -> Result<(SqlOperator, Vec<Expr>)> {
+        if source.is_updating() {
+            bail!("grouping sets are not supported over updating inputs");
+        }
+
+        let mut exprs: Vec<&Expr> = vec![];
+        for expr in sets.iter().flatten() {
+            if !exprs.contains(&expr) {
+                exprs.push(expr);
+            }
+        }
+
+        let grouping_set = Expr::Column(datafusion_common::Column::from_name(GROUPING_SET_FIELD));
+        let keys = exprs
+            .into_iter()
+            .map(|expr| {
+                let in_sets: Vec<_> = (0..sets.len())
+                    .filter(|i| sets[*i].contains(expr))
+                    .collect();
+                if in_sets.len() == sets.len() {
+                    return Ok(expr.clone());
+                }
+                if Self::is_window(expr) {
+                    bail!("every grouping set must include the window {}", expr);
+                }
+                Ok(Expr::Case(datafusion_expr::Case {
+                    expr: Some(Box::new(grouping_set.clone())),
+                    when_then_expr: in_sets
+                        .into_iter()
+                        .map(|i| {
+                            (
+                                Box::new(Expr::Literal(ScalarValue::Int64(Some(i as i64)))),
+                                Box::new(expr.clone()),
+                            )
+                        })
+                        .collect(),
+                    else_expr: None,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut fields: Vec<_> = source
+            .return_type()
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    Column {
+                        relation: field.alias.clone(),
+                        name: field.name.clone(),
+                    },
+                    Expression::Column(ColumnExpression::new(field.clone())),
+                    UnnestFieldType::Default,
+                )
+            })
+            .collect();
+
+        let set_indices = Expression::DataStructure(DataStructureFunction::MakeArray(
+            (0..sets.len())
+                .map(|i| LiteralExpression::new(ScalarValue::Int64(Some(i as i64))))
+                .collect(),
+        ));
+        fields.push((
+            Column {
+                relation: None,
+                name: GROUPING_SET_FIELD.to_string(),
+            },
+            Expression::Unnest(Box::new(set_indices.clone()), true),
+            UnnestFieldType::UnnestOuter,
+        ));
+
+        Ok((
+            SqlOperator::RecordTransform(
+                Box::new(source),
+                RecordTransform::UnnestProjection(UnnestProjection {
+                    fields,
+                    unnest_inner: set_indices,
+                    format: None,
+                }),
+            ),
+            keys,
+        ))
+    }
+
     fn aggregation_key(
         &mut self,
         group_expressions: &[Expr],
@@ -734,19 +1674,25 @@ impl<'a> SqlPipelineBuilder<'a> {
         match expression {
             Expr::ScalarUDF(ScalarUDF { fun, args }) => match fun.name.as_str() {
                 "hop" => {
-                    if args.len() != 2 {
+                    if args.len() < 2 {
                         unreachable!();
                     }
                     let slide = Self::get_duration(&args[0])?;
                     let width = Self::get_duration(&args[1])?;
-                    Ok(Some(WindowType::Sliding { width, slide }))
+                    let alignment = Expression::window_alignment("hop", &args[2..], slide)?;
+                    Ok(Some(WindowType::Sliding {
+                        width,
+                        slide,
+                        alignment,
+                    }))
                 }
                 "tumble" => {
-                    if args.len() != 1 {
-                        unreachable!("wrong number of arguments for tumble(), expect one");
+                    if args.is_empty() {
+                        unreachable!("wrong number of arguments for tumble(), expect at least one");
                     }
                     let width = Self::get_duration(&args[0])?;
-                    Ok(Some(WindowType::Tumbling { width }))
+                    let alignment = Expression::window_alignment("tumble", &args[1..], width)?;
+                    Ok(Some(WindowType::Tumbling { width, alignment }))
                 }
                 "session" => {
                     if args.len() != 1 {
@@ -761,7 +1707,7 @@ impl<'a> SqlPipelineBuilder<'a> {
             _ => Ok(None),
         }
     }
-    fn get_duration(expression: &Expr) -> Result<Duration> {
+    pub(crate) fn get_duration(expression: &Expr) -> Result<Duration> {
         match expression {
             Expr::Literal(ScalarValue::IntervalDayTime(Some(val))) => {
                 Ok(Duration::from_millis(*val as u64))
@@ -777,8 +1723,13 @@ impl<'a> SqlPipelineBuilder<'a> {
     }
 
     fn insert_join(&mut self, join: &datafusion_expr::logical_plan::Join) -> Result<SqlOperator> {
-        let left_input = self.insert_sql_plan(&join.left)?;
-        let right_input = self.insert_sql_plan(&join.right)?;
+        let mut left_input = self.insert_sql_plan(&join.left)?;
+        if let Some(lookup_join) =
+            LookupJoinOperator::try_new(join, &left_input, self.schema_provider)?
+        {
+            return Ok(SqlOperator::LookupJoin(Box::new(left_input), lookup_join));
+        }
+        let mut right_input = self.insert_sql_plan(&join.right)?;
         if left_input.is_updating() || right_input.is_updating() {
             bail!("don't support joins with updating inputs");
         }
@@ -794,53 +1745,93 @@ impl<'a> SqlPipelineBuilder<'a> {
             }
             _ => {}
         }
+        if left_input
+            .get_window()
+            .into_iter()
+            .chain(right_input.get_window())
+            .any(|window| window.has_alignment())
+        {
+            bail!("joins don't support windows with an offset or a time zone");
+        }
+        // check which side each column comes from. Assumes there's at least one field
+        let left_relation = join
+            .schema
+            .fields()
+            .first()
+            .unwrap()
+            .qualifier()
+            .as_ref()
+            .unwrap()
+            .to_string();
+        let right_relation = join
+            .schema
+            .fields()
+            .last()
+            .unwrap()
+            .qualifier()
+            .as_ref()
+            .unwrap()
+            .to_string();
+
         let mut join_pairs = join.on.clone();
-        if let Some(Expr::BinaryExpr(BinaryExpr { left, op, right })) = &join.filter {
-            if *op != datafusion_expr::Operator::Eq {
-                bail!("only equality joins are supported");
-            }
-            // check which side each column comes from. Assumes there's at least one field
-            let left_relation = join
-                .schema
-                .fields()
-                .first()
-                .unwrap()
-                .qualifier()
-                .as_ref()
-                .unwrap()
-                .to_string();
-            let right_relation = join
-                .schema
-                .fields()
-                .last()
-                .unwrap()
-                .qualifier()
-                .as_ref()
-                .unwrap()
-                .to_string();
-            let left_table = Column::convert_expr(left)?.relation.unwrap();
-            let right_table = Column::convert_expr(right)?.relation.unwrap();
-            let pair = if right_table == right_relation && left_table == left_relation {
-                (left.as_ref().clone(), right.as_ref().clone())
-            } else if left_table == right_relation && right_table == left_relation {
-                ((right.as_ref()).clone(), left.as_ref().clone())
-            } else {
-                bail!("join filter must contain at least one column from each side of the join");
-            };
-            join_pairs.push(pair);
-        } else if join.filter.is_some() {
-            bail!(
-                "only equality joins are supported, not filter {:?}",
-                join.filter
-            );
+        let mut band = TimeBand::default();
+        if let Some(filter) = &join.filter {
+            for predicate in split_conjunction(filter) {
+                if let Expr::BinaryExpr(BinaryExpr {
+                    left,
+                    op: datafusion_expr::Operator::Eq,
+                    right,
+                }) = predicate
+                {
+                    let left_table = Column::convert_expr(left)?.relation.unwrap();
+                    let right_table = Column::convert_expr(right)?.relation.unwrap();
+                    let pair = if right_table == right_relation && left_table == left_relation {
+                        (left.as_ref().clone(), right.as_ref().clone())
+                    } else if left_table == right_relation && right_table == left_relation {
+                        ((right.as_ref()).clone(), left.as_ref().clone())
+                    } else {
+                        bail!(
+                            "join filter must contain at least one column from each side of the join"
+                        );
+                    };
+                    join_pairs.push(pair);
+                } else {
+                    band.add_predicate(predicate, &left_relation, &right_relation)?;
+                }
+            }
         }
 
+        let interval = match band.columns {
+            None => None,
+            Some((left_time, right_time)) => {
+                let (Some(lower_bound), Some(upper_bound)) = (band.lower_bound, band.upper_bound)
+                else {
+                    bail!("interval joins require both a lower and an upper bound on the time between the two sides, like a.ts BETWEEN b.ts - INTERVAL '10' MINUTE AND b.ts");
+                };
+                if lower_bound > upper_bound {
+                    bail!("the time bounds of the join don't allow any rows to match");
+                }
+                if join_type != JoinType::Inner {
+                    bail!("interval joins only support INNER joins");
+                }
+                if left_input.has_window() {
+                    bail!("interval joins are not supported over windowed inputs");
+                }
+
+                left_input = self.with_join_time(left_input, &left_time)?;
+                right_input = self.with_join_time(right_input, &right_time)?;
+                Some(JoinInterval {
+                    lower_bound,
+                    upper_bound,
+                })
+            }
+        };
+
         let join_projection_field_names: Vec<_> = join_pairs
             .iter()
             .map(|(left, _right)| Column::convert_expr(left))
             .collect::<Result<Vec<_>>>()?;
-        let (left_computations, right_computations): (Vec<_>, Vec<_>) = join
-            .on
+        let (left_computations, right_computations): (Vec<_>, Vec<_>) = join_pairs
             .iter()
             .map(|(left, right)| {
                 Ok((
@@ -855,7 +1846,10 @@ impl<'a> SqlPipelineBuilder<'a> {
         left_computations
             .iter()
             .chain(right_computations.iter())
-            .map(|e| Self::assert_no_unnest("join", e))
+            .map(|e| {
+                Self::assert_no_unnest("join", e)?;
+                Self::assert_no_async_udf("join", e)
+            })
             .collect::<Result<Vec<()>>>()?;
 
         let left_key = Projection::new(
@@ -880,10 +1874,41 @@ impl<'a> SqlPipelineBuilder<'a> {
                 left_key,
                 right_key,
                 join_type,
+                interval,
             },
         ))
     }
 
+    /// Sets the timestamps of the rows of one side of an interval join from its time column, as
+    /// the band is evaluated on record timestamps. Rows with a null time can't match, so they're
+    /// filtered out first.
+    fn with_join_time(
+        &self,
+        input: SqlOperator,
+        time: &datafusion_expr::Expr,
+    ) -> Result<SqlOperator> {
+        let return_type = input.return_type();
+        let ctx = self.ctx(&return_type);
+        let timestamp = ctx.compile_expr(time)?;
+        let TypeDef::DataType(DataType::Timestamp(_, _), nullable) =
+            timestamp.expression_type(&ValuePointerContext::new())
+        else {
+            bail!("interval joins must compare timestamps, not {}", time);
+        };
+
+        let input = if nullable {
+            let not_null = ctx.compile_expr(&Expr::IsNotNull(Box::new(time.clone())))?;
+            SqlOperator::RecordTransform(Box::new(input), RecordTransform::Filter(not_null))
+        } else {
+            input
+        };
+
+        Ok(SqlOperator::RecordTransform(
+            Box::new(input),
+            RecordTransform::TimestampAssignment(timestamp),
+        ))
+    }
+
     fn insert_table_scan(
         &mut self,
         table_scan: &datafusion::logical_expr::TableScan,
@@ -923,100 +1948,303 @@ impl<'a> SqlPipelineBuilder<'a> {
 
     fn insert_window(&mut self, window: &Window) -> Result<SqlOperator> {
         let input = self.insert_sql_plan(&window.input)?;
+        self.add_window_functions(input, window)
+    }
 
+    fn add_window_functions(&self, mut input: SqlOperator, window: &Window) -> Result<SqlOperator> {
         if input.is_updating() {
             bail!("don't support window functions over updating inputs");
         }
 
-        if let Some(expr) = window.window_expr.get(0) {
-            let w = match expr {
-                Expr::Alias(datafusion_expr::expr::Alias { expr, name: _ }) => match **expr {
-                    Expr::WindowFunction(ref w) => w,
-                    _ => bail!("expected window function"),
-                },
-                Expr::WindowFunction(window_function) => window_function,
+        if window.window_expr.is_empty() {
+            bail!("no expression for window");
+        }
+
+        // each function adds a field after those of the input, and is computed by its own operator
+        let field_names = window.schema.field_names();
+        let first_field = field_names.len() - window.window_expr.len();
+        for (i, expr) in window.window_expr.iter().enumerate() {
+            let window_operator =
+                self.window_operator(&input, expr, field_names[first_field + i].clone())?;
+            input = SqlOperator::Window(Box::new(input), window_operator);
+        }
+
+        Ok(input)
+    }
+
+    fn window_operator(
+        &self,
+        input: &SqlOperator,
+        expr: &Expr,
+        field_name: String,
+    ) -> Result<SqlWindowOperator> {
+        let w = match expr {
+            Expr::Alias(datafusion_expr::expr::Alias { expr, name: _ }) => match **expr {
+                Expr::WindowFunction(ref w) => w,
                 _ => bail!("expected window function"),
-            };
-            let window_fn = match &w.fun {
-                datafusion_expr::WindowFunction::AggregateFunction(_) => {
-                    bail!("window aggregate functions not yet supported")
-                }
-                datafusion_expr::WindowFunction::BuiltInWindowFunction(
-                    BuiltInWindowFunction::RowNumber,
-                ) => WindowFunction::RowNumber,
-                datafusion_expr::WindowFunction::BuiltInWindowFunction(w) => {
-                    bail!("Window function {} not yet supported", w);
-                }
-                datafusion_expr::WindowFunction::AggregateUDF(_) => {
-                    bail!("Window UDAFs not yet supported");
+            },
+            Expr::WindowFunction(window_function) => window_function,
+            _ => bail!("expected window function"),
+        };
+        let input_struct = input.return_type();
+        let mut ctx = self.ctx(&input_struct);
+
+        let window_fn = match &w.fun {
+            datafusion_expr::WindowFunction::AggregateFunction(fun) => {
+                if w.args.len() != 1 {
+                    bail!("unexpected arg length");
                 }
-                datafusion_expr::WindowFunction::WindowUDF(_) => {
-                    bail!("Window UDFs not yet supported");
+                WindowFunction::Aggregate(AggregationExpression {
+                    producing_expression: Box::new(ctx.compile_expr(&w.args[0])?),
+                    aggregator: Aggregator::from_datafusion(fun.clone(), false)?,
+                })
+            }
+            datafusion_expr::WindowFunction::BuiltInWindowFunction(
+                BuiltInWindowFunction::RowNumber,
+            ) => WindowFunction::RowNumber,
+            datafusion_expr::WindowFunction::BuiltInWindowFunction(BuiltInWindowFunction::Lag) => {
+                WindowFunction::Lag(OffsetWindowFunction::new(&mut ctx, &w.args)?)
+            }
+            datafusion_expr::WindowFunction::BuiltInWindowFunction(BuiltInWindowFunction::Lead) => {
+                WindowFunction::Lead(OffsetWindowFunction::new(&mut ctx, &w.args)?)
+            }
+            datafusion_expr::WindowFunction::BuiltInWindowFunction(w) => {
+                bail!("Window function {} not yet supported", w);
+            }
+            datafusion_expr::WindowFunction::AggregateUDF(udaf)
+                if TimeSeriesFunction::NAMES.contains(&udaf.name.as_str()) =>
+            {
+                WindowFunction::TimeSeries(TimeSeriesFunction::new(
+                    &mut ctx,
+                    &udaf.name,
+                    &w.args,
+                    &w.order_by,
+                )?)
+            }
+            datafusion_expr::WindowFunction::AggregateUDF(_) => {
+                bail!("Window UDAFs not yet supported");
+            }
+            datafusion_expr::WindowFunction::WindowUDF(_) => {
+                bail!("Window UDFs not yet supported");
+            }
+        };
+        let mut frame = window_fn.frame(&w.window_frame)?;
+
+        let order_by: Vec<_> = w
+            .order_by
+            .iter()
+            .map(|expr| {
+                if let Expr::Sort(sort) = expr {
+                    SortExpression::from_expression(&mut ctx, sort)
+                } else {
+                    panic!("expected sort expression, found {:?}", expr);
                 }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // functions partitioned by a window are computed once the window closes; otherwise
+        // they're computed over the rows of each partition as they arrive
+        let window_type = match w.partition_by.first() {
+            Some(first_term) => ctx.compile_expr(first_term)?.get_window_type(input)?,
+            None => None,
+        };
+
+        if !input.has_window()
+            && window_type
+                .as_ref()
+                .map_or(false, |window| window.has_alignment())
+        {
+            bail!("window functions don't support windows with an offset or a time zone");
+        }
+
+        let partition_terms = if window_type.is_some() {
+            &w.partition_by[1..]
+        } else {
+            &w.partition_by[..]
+        };
+
+        if window_type.is_some() {
+            // windows are finite, so frames may be unbounded, but we don't keep the times of
+            // the rows in them
+            frame = Frame {
+                preceding: Self::window_frame_bound(frame.preceding)?,
+                following: Self::window_frame_bound(frame.following)?,
             };
+        } else {
+            if !Self::is_event_time_order(&mut ctx, &w.order_by)? {
+                bail!(
+                    "window functions that aren't partitioned by a window must be ordered by \
+                    the event time, ascending"
+                );
+            }
 
-            let input_struct = input.return_type();
-            let mut ctx = self.ctx(&input_struct);
+            if frame.preceding == FrameBound::Unbounded || frame.following == FrameBound::Unbounded
+            {
+                bail!(
+                    "window functions that aren't partitioned by a window must have a \
+                    bounded frame, like ROWS BETWEEN 10 PRECEDING AND CURRENT ROW"
+                );
+            }
+        }
 
-            let order_by: Vec<_> = w
-                .order_by
-                .iter()
-                .map(|expr| {
-                    if let Expr::Sort(sort) = expr {
-                        SortExpression::from_expression(&mut ctx, sort)
-                    } else {
-                        panic!("expected sort expression, found {:?}", expr);
-                    }
-                })
-                .collect::<Result<Vec<_>>>()?;
-            let first_term = w.partition_by.first().ok_or_else(|| {
-                anyhow!("window function must have at least one partition expression")
-            })?;
-            let first_expression = ctx.compile_expr(first_term)?;
-            let window_type = first_expression.get_window_type(&input)?.ok_or_else(|| {
-                anyhow!("window function must be partitioned by a window as the first argument")
-            })?;
-
-            let field_names = w
-                .partition_by
+        let field_names = partition_terms.iter().enumerate().map(|(i, _t)| Column {
+            relation: None,
+            name: format!("_{}", i),
+        });
+
+        let field_computations = partition_terms
+            .iter()
+            .map(|expression| {
+                let expr = ctx.compile_expr(expression)?;
+                Self::assert_no_unnest("window", &expr)?;
+                Self::assert_no_async_udf("window", &expr)?;
+                if expr.get_window_type(input)?.is_some() {
+                    bail!("window functions can only be partitioned by a window as the first argument");
+                } else {
+                    Ok(expr)
+                }
+            })
+        .collect::<Result<Vec<_>>>()?;
+
+        let partition = Projection::new(field_names.zip(field_computations).collect());
+
+        Ok(SqlWindowOperator {
+            window_fn,
+            partition,
+            order_by,
+            field_name,
+            window_type,
+            frame,
+        })
+    }
+
+    fn is_event_time_order(ctx: &mut ExpressionContext, order_by: &[Expr]) -> Result<bool> {
+        Ok(match order_by {
+            [Expr::Sort(sort)] => {
+                sort.asc
+                    && matches!(
+                        ctx.compile_expr(&sort.expr)?
+                            .expression_type(&ValuePointerContext::new())
+                            .as_datatype(),
+                        Some(DataType::Timestamp(_, _))
+                    )
+            }
+            _ => false,
+        })
+    }
+
+    /// Plans a ROW_NUMBER that's filtered by `predicate` as a top-N, unless it's partitioned by a
+    /// window or ordered by the event time, which the window operators already compute with
+    /// bounded state
+    fn top_n_operator(
+        &self,
+        input: &SqlOperator,
+        window: &Window,
+        predicate: &Expr,
+    ) -> Result<Option<TopNOperator>> {
+        let [expr] = window.window_expr.as_slice() else {
+            return Ok(None);
+        };
+        let w = match expr {
+            Expr::Alias(datafusion_expr::expr::Alias { expr, name: _ }) => match **expr {
+                Expr::WindowFunction(ref w) => w,
+                _ => return Ok(None),
+            },
+            Expr::WindowFunction(window_function) => window_function,
+            _ => return Ok(None),
+        };
+        if !matches!(
+            w.fun,
+            datafusion_expr::WindowFunction::BuiltInWindowFunction(
+                BuiltInWindowFunction::RowNumber
+            )
+        ) || input.is_updating()
+        {
+            return Ok(None);
+        }
+
+        let input_struct = input.return_type();
+        let mut ctx = self.ctx(&input_struct);
+        if let Some(first_term) = w.partition_by.first() {
+            if ctx
+                .compile_expr(first_term)?
+                .get_window_type(input)?
+                .is_some()
+            {
+                return Ok(None);
+            }
+        }
+        if Self::is_event_time_order(&mut ctx, &w.order_by)? {
+            return Ok(None);
+        }
+
+        let field_name = window.schema.field_names().last().unwrap().clone();
+        let mut output_struct = input_struct.clone();
+        output_struct.fields.push(StructField::new(
+            field_name.clone(),
+            None,
+            WindowFunction::RowNumber.return_type(),
+        ));
+        let field = output_struct.get_field(None, &field_name)?;
+        let Some(max_elements) = self
+            .ctx(&output_struct)
+            .compile_expr(predicate)?
+            .has_max_value(&field)
+        else {
+            bail!(
+                "ROW_NUMBER() that isn't partitioned by a window must be filtered to the first N rows, \
+                like WHERE row_num <= 10"
+            );
+        };
+
+        let order_by = w
+            .order_by
+            .iter()
+            .map(|expr| {
+                if let Expr::Sort(sort) = expr {
+                    SortExpression::from_expression(&mut ctx, sort)
+                } else {
+                    panic!("expected sort expression, found {:?}", expr);
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let partition = Projection::new(
+            w.partition_by
                 .iter()
-                .skip(1)
                 .enumerate()
-                .map(|(i, _t)| Column {
-                    relation: None,
-                    name: format!("_{}", i),
-                });
-
-            let field_computations = w
-                .partition_by
-                .iter().skip(1)
-                .map(|expression| {
+                .map(|(i, expression)| {
                     let expr = ctx.compile_expr(expression)?;
                     Self::assert_no_unnest("window", &expr)?;
-                    if expr.get_window_type(&input)?.is_some() {
-                        bail!("window functions can only be partitioned by a window as the first argument");
-                    } else {
-                        Ok(expr)
-                    }
+                    Self::assert_no_async_udf("window", &expr)?;
+                    Ok((
+                        Column {
+                            relation: None,
+                            name: format!("_{}", i),
+                        },
+                        expr,
+                    ))
                 })
-            .collect::<Result<Vec<_>>>()?;
+                .collect::<Result<_>>()?,
+        );
 
-            let partition = Projection::new(field_names.zip(field_computations).collect());
-            let field_name = window.schema.field_names().last().cloned().unwrap();
+        Ok(Some(TopNOperator {
+            partition,
+            order_by,
+            field_name,
+            max_elements: max_elements as usize,
+        }))
+    }
 
-            return Ok(SqlOperator::Window(
-                Box::new(input),
-                SqlWindowOperator {
-                    window_fn,
-                    partition,
-                    order_by,
-                    field_name,
-                    window_type,
-                },
-            ));
+    /// Within a window, frames are computed over the sorted rows of the window by their positions
+    fn window_frame_bound(bound: FrameBound) -> Result<FrameBound> {
+        match bound {
+            FrameBound::Time(time) if time.is_zero() => Ok(FrameBound::Rows(0)),
+            FrameBound::Time(_) => {
+                bail!("RANGE frames are not supported for window functions partitioned by a window")
+            }
+            bound => Ok(bound),
         }
-
-        bail!("no expression for window");
     }
 
     fn insert_subquery_alias(
@@ -1085,10 +2313,12 @@ impl<'a> SqlPipelineBuilder<'a> {
                                 .map_err(|e| anyhow!("failed to plan {}: {}", c.name, e))?,
                         );
                     }
-                    Table::TableFromQuery {
-                        name: _,
-                        logical_plan: _,
-                    } => todo!(),
+                    Table::TableFromQuery { name, .. } => {
+                        bail!("can't insert into view {}", name)
+                    }
+                    Table::MatchRecognize { .. } => {
+                        bail!("can't insert into a MATCH_RECOGNIZE clause")
+                    }
                 }
             }
             Insert::Anonymous { logical_plan } => {
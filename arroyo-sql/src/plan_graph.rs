@@ -1,36 +1,45 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    hash::{Hash, Hasher},
     time::Duration,
 };
 
 use arrow_schema::DataType;
 use arroyo_datastream::{
-    EdgeType, ExpressionReturnType, NonWindowAggregator, Operator, PeriodicWatermark, Program,
-    SlidingAggregatingTopN, SlidingWindowAggregator, StreamEdge, StreamNode, TumblingTopN,
-    TumblingWindowAggregator, WindowAgg, WindowType,
+    EdgeType, ExpressionReturnType, NonWindowAggregator, Operator, OverWindow, PeriodicWatermark,
+    Program, SlidingAggregatingTopN, SlidingWindowAggregator, StreamEdge, StreamNode, TumblingTopN,
+    TumblingWindowAggregator, WindowAgg, WindowAlignment, WindowType,
 };
 
+use arroyo_types::state_schema::{OperatorStateSchema, StateField, StateType};
+use arroyo_types::FrameBound;
 use petgraph::graph::{DiGraph, NodeIndex};
+use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{parse_quote, parse_str, Type};
 
 use crate::{
+    async_udf::AsyncUdfOperator,
     code_gen::{
         BinAggregatingContext, CodeGenerator, CombiningContext, JoinListsContext, JoinPairContext,
         MemoryAddingContext, MemoryAggregatingContext, MemoryRemovingContext,
         ValueBinMergingContext, ValuePointerContext, VecAggregationContext,
     },
     expressions::{Column, ColumnExpression, Expression, SortExpression},
-    external::{ProcessingMode, SinkUpdateType, SqlSink, SqlSource},
+    external::{ProcessingMode, SinkUpdateType, SqlSink, SqlSource, DEFAULT_MAX_OUT_OF_ORDERNESS},
+    lookup::LookupJoinOperator,
+    match_recognize::MatchRecognizeOperator,
     operators::{AggregateProjection, Projection, TwoPhaseAggregateProjection},
     optimizations::optimize,
     pipeline::{
-        JoinType, MethodCompiler, RecordTransform, SourceOperator, SqlOperator, WindowFunction,
+        Frame, JoinInterval, JoinType, LateDataOperator, MethodCompiler, RecordTransform,
+        SourceOperator, SqlOperator, TopNOperator, WindowFunction,
     },
     types::{StructDef, StructField, StructPair, TypeDef},
     ArroyoSchemaProvider, SqlConfig,
 };
 use anyhow::Result;
+use petgraph::visit::EdgeRef;
 use petgraph::Direction;
 
 #[derive(Debug, Clone)]
@@ -43,6 +52,8 @@ pub enum PlanOperator {
     WindowAggregate {
         window: WindowType,
         projection: AggregateProjection,
+        allowed_lateness: Duration,
+        emit_partial: Option<Duration>,
     },
     NonWindowAggregate {
         input_is_update: bool,
@@ -51,11 +62,15 @@ pub enum PlanOperator {
     },
     TumblingWindowTwoPhaseAggregator {
         tumble_width: Duration,
+        alignment: WindowAlignment,
+        allowed_lateness: Duration,
+        emit_partial: Option<Duration>,
         projection: TwoPhaseAggregateProjection,
     },
     SlidingWindowTwoPhaseAggregator {
         width: Duration,
         slide: Duration,
+        alignment: WindowAlignment,
         projection: TwoPhaseAggregateProjection,
     },
     InstantJoin,
@@ -64,11 +79,16 @@ pub enum PlanOperator {
         right_expiration: Duration,
         join_type: JoinType,
     },
+    IntervalJoin {
+        lower_bound: i64,
+        upper_bound: i64,
+    },
     JoinListMerge(JoinType, StructPair),
     JoinPairMerge(JoinType, StructPair),
     Flatten,
     // TODO: figure out naming of various things called 'window'
     WindowFunction(WindowFunctionOperator),
+    OverWindow(WindowFunctionOperator),
     TumblingLocalAggregator {
         width: Duration,
         projection: TwoPhaseAggregateProjection,
@@ -87,11 +107,26 @@ pub enum PlanOperator {
         max_elements: usize,
         window_function: WindowFunctionOperator,
     },
+    TopN {
+        max_elements: usize,
+        expiration: Duration,
+        order_by: Vec<SortExpression>,
+        // the input with the row number appended
+        result_struct: StructDef,
+    },
     // for external nodes, mainly sinks.
     StreamOperator(String, Operator),
     ToDebezium,
     FromDebezium,
+    ToUpsert,
     FromUpdating,
+    Deduplicate {
+        ttl: Duration,
+    },
+    LateDataFilter(LateDataOperator),
+    MatchRecognize(MatchRecognizeOperator),
+    LookupJoin(LookupJoinOperator),
+    AsyncUdf(AsyncUdfOperator),
     Sink(String, SqlSink),
 }
 
@@ -100,10 +135,39 @@ pub struct WindowFunctionOperator {
     pub window_function: WindowFunction,
     pub order_by: Vec<SortExpression>,
     pub window_type: WindowType,
+    pub frame: Frame,
     pub result_struct: StructDef,
     pub field_name: String,
 }
 
+impl WindowFunctionOperator {
+    /// The output for the row `arg`, which is at index `current` of `frame`, given the `previous`
+    /// output for its partition
+    fn output_expression(&self) -> TokenStream {
+        let window_field = self.result_struct.fields.last().unwrap().field_ident();
+        let result_struct_name = self.result_struct.get_type();
+        let mut field_assignments: Vec<_> = self
+            .result_struct
+            .fields
+            .iter()
+            .take(self.result_struct.fields.len() - 1)
+            .map(|f| {
+                let ident = f.field_ident();
+                quote! { #ident: arg.#ident.clone() }
+            })
+            .collect();
+
+        let value = self.window_function.generate(&window_field);
+        field_assignments.push(quote! {
+            #window_field: #value
+        });
+
+        quote!(#result_struct_name {
+            #(#field_assignments, )*
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FusedRecordTransform {
     pub expressions: Vec<RecordTransform>,
@@ -279,16 +343,79 @@ pub struct PlanNode {
 }
 
 impl PlanNode {
-    fn into_stream_node(&self, index: usize, sql_config: &SqlConfig) -> StreamNode {
-        let name = format!("{}_{}", self.prefix(), index);
+    fn into_stream_node(&self, operator_id: String, sql_config: &SqlConfig) -> StreamNode {
         let operator = self.to_operator();
         StreamNode {
-            operator_id: name,
+            operator_id,
             parallelism: sql_config.default_parallelism,
             operator,
         }
     }
 
+    /// The types of this node's state, given the types of its inputs in the order of their edges
+    fn state_schema(&self, inputs: &[&PlanType]) -> Option<OperatorStateSchema> {
+        let key = inputs
+            .first()
+            .and_then(|input| input.key_struct())
+            .map(|key| key.state_type());
+
+        match &self.operator {
+            // these store their input records as they were received, so they can be migrated
+            PlanOperator::InstantJoin
+            | PlanOperator::JoinWithExpiration { .. }
+            | PlanOperator::IntervalJoin { .. } => Some(OperatorStateSchema {
+                key,
+                tables: [
+                    ("l".to_string(), inputs[0].value_state_type()),
+                    ("r".to_string(), inputs[1].value_state_type()),
+                ]
+                .into_iter()
+                .collect(),
+                derived: None,
+            }),
+            PlanOperator::WindowFunction(_) => Some(OperatorStateSchema {
+                key,
+                tables: [("w".to_string(), inputs[0].value_state_type())]
+                    .into_iter()
+                    .collect(),
+                derived: None,
+            }),
+            // these store aggregates, buffered rows or results that depend on both their inputs
+            // and outputs
+            PlanOperator::WindowAggregate { .. }
+            | PlanOperator::NonWindowAggregate { .. }
+            | PlanOperator::TumblingWindowTwoPhaseAggregator { .. }
+            | PlanOperator::SlidingWindowTwoPhaseAggregator { .. }
+            | PlanOperator::TumblingLocalAggregator { .. }
+            | PlanOperator::SlidingAggregatingTopN { .. }
+            | PlanOperator::TumblingTopN { .. }
+            | PlanOperator::TopN { .. }
+            | PlanOperator::OverWindow(_)
+            | PlanOperator::Deduplicate { .. }
+            | PlanOperator::MatchRecognize(_)
+            | PlanOperator::LookupJoin(_)
+            | PlanOperator::AsyncUdf(_) => {
+                let mut fields: Vec<_> = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, input)| {
+                        StateField::new(format!("input_{}", i), input.value_state_type())
+                    })
+                    .collect();
+                fields.push(StateField::new(
+                    "output",
+                    self.output_type.value_state_type(),
+                ));
+                Some(OperatorStateSchema {
+                    key,
+                    tables: BTreeMap::new(),
+                    derived: Some(StateType::Struct(fields)),
+                })
+            }
+            _ => None,
+        }
+    }
+
     fn from_record_transform(record_transform: RecordTransform, input_node: &PlanNode) -> Self {
         let input_type = &input_node.output_type;
         let output_type = match &record_transform {
@@ -325,18 +452,27 @@ impl PlanNode {
             }
             PlanOperator::InstantJoin => "instant_join".to_string(),
             PlanOperator::JoinWithExpiration { .. } => "join_with_expiration".to_string(),
+            PlanOperator::IntervalJoin { .. } => "interval_join".to_string(),
             PlanOperator::JoinListMerge(_, _) => "join_list_merge".to_string(),
             PlanOperator::JoinPairMerge(_, _) => "join_pair_merge".to_string(),
             PlanOperator::Flatten => "flatten".to_string(),
             PlanOperator::WindowFunction { .. } => "window_function".to_string(),
+            PlanOperator::OverWindow { .. } => "over_window".to_string(),
             PlanOperator::StreamOperator(name, _) => name.to_string(),
             PlanOperator::TumblingLocalAggregator { .. } => "tumbling_local_aggregator".to_string(),
             PlanOperator::SlidingAggregatingTopN { .. } => "sliding_aggregating_top_n".to_string(),
             PlanOperator::TumblingTopN { .. } => "tumbling_top_n".to_string(),
+            PlanOperator::TopN { .. } => "top_n".to_string(),
             PlanOperator::Sink(name, _) => format!("sink_{}", name),
             PlanOperator::ToDebezium => "to_debezium".to_string(),
             PlanOperator::FromDebezium => "from_debezium".to_string(),
+            PlanOperator::ToUpsert => "to_upsert".to_string(),
             PlanOperator::FromUpdating => "from_updating".to_string(),
+            PlanOperator::Deduplicate { .. } => "deduplicate".to_string(),
+            PlanOperator::LateDataFilter(_) => "late_data_filter".to_string(),
+            PlanOperator::MatchRecognize(_) => "match_recognize".to_string(),
+            PlanOperator::LookupJoin(_) => "lookup_join".to_string(),
+            PlanOperator::AsyncUdf(_) => "async_udf".to_string(),
             PlanOperator::NonWindowAggregate { .. } => "non_window_aggregate".to_string(),
         }
     }
@@ -348,7 +484,9 @@ impl PlanNode {
             PlanOperator::RecordTransform(record_transform) => {
                 record_transform.as_operator(self.output_type.is_updating())
             }
-            PlanOperator::WindowAggregate { window, projection } => {
+            PlanOperator::WindowAggregate {
+                window, projection, ..
+            } => {
                 let aggregating_context = VecAggregationContext::new();
                 let aggregate_expr = projection.generate(&aggregating_context);
                 arroyo_datastream::Operator::Window {
@@ -363,6 +501,9 @@ impl PlanNode {
             }
             PlanOperator::TumblingWindowTwoPhaseAggregator {
                 tumble_width,
+                alignment,
+                allowed_lateness,
+                emit_partial,
                 projection,
             } => {
                 let value_bin_merging_context = ValueBinMergingContext::new();
@@ -383,6 +524,9 @@ impl PlanNode {
 
                 arroyo_datastream::Operator::TumblingWindowAggregator(TumblingWindowAggregator {
                     width: *tumble_width,
+                    alignment: alignment.clone(),
+                    allowed_lateness: *allowed_lateness,
+                    emit_partial: *emit_partial,
                     aggregator,
                     bin_merger,
                     bin_type,
@@ -391,6 +535,7 @@ impl PlanNode {
             PlanOperator::SlidingWindowTwoPhaseAggregator {
                 width,
                 slide,
+                alignment,
                 projection,
             } => {
                 let value_bin_merger_context = ValueBinMergingContext::new();
@@ -428,6 +573,7 @@ impl PlanNode {
                 arroyo_datastream::Operator::SlidingWindowAggregator(SlidingWindowAggregator {
                     width: *width,
                     slide: *slide,
+                    alignment: alignment.clone(),
                     aggregator,
                     bin_merger,
                     in_memory_add,
@@ -448,6 +594,13 @@ impl PlanNode {
                 right_expiration: *right_expiration,
                 join_type: join_type.clone().into(),
             },
+            PlanOperator::IntervalJoin {
+                lower_bound,
+                upper_bound,
+            } => Operator::IntervalJoin {
+                lower_bound: *lower_bound,
+                upper_bound: *upper_bound,
+            },
             PlanOperator::JoinListMerge(join_type, struct_pair) => {
                 let context =
                     JoinListsContext::new(struct_pair.left.clone(), struct_pair.right.clone());
@@ -474,54 +627,50 @@ impl PlanNode {
                 }
             }
 
-            PlanOperator::WindowFunction(WindowFunctionOperator {
-                window_function,
-                order_by,
-                window_type,
-                result_struct,
-                field_name: _,
-            }) => {
-                let window_field = result_struct.fields.last().unwrap().field_ident();
-                let result_struct_name = result_struct.get_type();
-                let mut field_assignments: Vec<_> = result_struct
-                    .fields
-                    .iter()
-                    .take(result_struct.fields.len() - 1)
-                    .map(|f| {
-                        let ident = f.field_ident();
-                        quote! { #ident: arg.#ident.clone() }
-                    })
-                    .collect();
-
-                match window_function {
-                    WindowFunction::RowNumber => {
-                        field_assignments.push(quote! {
-                            #window_field: i as u64
-                        });
-                    }
-                }
-
-                let output_expression = quote!(#result_struct_name {
-                    #(#field_assignments, )*
-                });
+            PlanOperator::WindowFunction(window_function) => {
+                let output_expression = window_function.output_expression();
+                let result_type = window_function.result_struct.get_type();
 
-                let sort = if !order_by.is_empty() {
-                    let sort_tokens = SortExpression::sort_tuple_expression(order_by);
+                let sort = if !window_function.order_by.is_empty() {
+                    let sort_tokens =
+                        SortExpression::sort_tuple_expression(&window_function.order_by);
                     Some(quote!(arg.sort_by_key(|arg| #sort_tokens);))
                 } else {
                     None
                 };
+
+                let start = match window_function.frame.preceding {
+                    FrameBound::Rows(n) => {
+                        let n = n as usize;
+                        quote!(index.saturating_sub(#n))
+                    }
+                    _ => quote!(0),
+                };
+                let end = match window_function.frame.following {
+                    FrameBound::Rows(n) => {
+                        let n = n as usize;
+                        quote!((index + #n + 1).min(arg.len()))
+                    }
+                    _ => quote!(arg.len()),
+                };
+
                 arroyo_datastream::Operator::Window {
-                    typ: window_type.clone(),
+                    typ: window_function.window_type.clone(),
                     agg: Some(WindowAgg::Expression {
                         name: "sql_window".to_string(),
                         expression: quote! {
                             {
                                 #sort
-                                let mut result = vec![];
-                                for (index, arg) in arg.iter().enumerate() {
-                                    let i = index + 1;
-                                    result.push(#output_expression);
+                                let mut result: Vec<#result_type> = vec![];
+                                for index in 0..arg.len() {
+                                    let start = #start;
+                                    let frame = &arg[start..#end];
+                                    let current = index - start;
+                                    let row_number = index as u64 + 1;
+                                    let previous = result.last();
+                                    let arg = &frame[current];
+                                    let output = #output_expression;
+                                    result.push(output);
                                 }
                                 result
                             }
@@ -531,6 +680,19 @@ impl PlanNode {
                     flatten: true,
                 }
             }
+            PlanOperator::OverWindow(window_function) => {
+                let output_expression = window_function.output_expression();
+
+                arroyo_datastream::Operator::OverWindow(OverWindow {
+                    preceding: window_function.frame.preceding,
+                    following: window_function.frame.following,
+                    aggregator: quote!(|frame, current, row_number, previous| {
+                        let arg = &frame[current];
+                        #output_expression
+                    })
+                    .to_string(),
+                })
+            }
             PlanOperator::StreamOperator(_, stream_operator) => stream_operator.clone(),
             PlanOperator::FusedRecordTransform(fused_record_transform) => {
                 fused_record_transform.to_operator()
@@ -560,6 +722,9 @@ impl PlanNode {
 
                 arroyo_datastream::Operator::TumblingWindowAggregator(TumblingWindowAggregator {
                     width: *width,
+                    alignment: WindowAlignment::default(),
+                    allowed_lateness: Duration::ZERO,
+                    emit_partial: None,
                     aggregator: quote!(|key, window, arg| { arg.clone() }).to_string(),
                     bin_merger,
                     bin_type,
@@ -662,34 +827,9 @@ impl PlanNode {
                 let sort_expression =
                     SortExpression::sort_tuple_expression(&window_function.order_by);
 
-                let window_field = window_function
-                    .result_struct
-                    .fields
-                    .last()
-                    .unwrap()
-                    .field_ident();
-                let output_struct = window_function.result_struct.get_type();
-                let mut field_assignments: Vec<_> = window_function
-                    .result_struct
-                    .fields
-                    .iter()
-                    .take(window_function.result_struct.fields.len() - 1)
-                    .map(|f| {
-                        let ident = f.field_ident();
-                        quote! { #ident: arg.#ident.clone() }
-                    })
-                    .collect();
-
-                match window_function.window_function {
-                    WindowFunction::RowNumber => {
-                        field_assignments.push(quote! {
-                            #window_field: i as u64
-                        });
-                    }
+                if window_function.window_function != WindowFunction::RowNumber {
+                    unreachable!("only ROW_NUMBER is optimized into a top-n");
                 }
-                let output_expression = quote!(#output_struct {
-                    #(#field_assignments, )*
-                });
 
                 let extractor = quote!(
                     |arg| {
@@ -697,10 +837,7 @@ impl PlanNode {
                     }
                 )
                 .to_string();
-                let converter = quote!(
-                    |arg, i| #output_expression
-                )
-                .to_string();
+                let converter = row_number_converter(&window_function.result_struct).to_string();
                 let sort_type = SortExpression::sort_tuple_type(&window_function.order_by);
                 let partition_key_type = quote!(#sort_type).to_string();
 
@@ -712,6 +849,23 @@ impl PlanNode {
                     converter,
                 })
             }
+            PlanOperator::TopN {
+                max_elements,
+                expiration,
+                order_by,
+                result_struct,
+            } => {
+                let sort_expression = SortExpression::sort_tuple_expression(order_by);
+                let sort_type = SortExpression::sort_tuple_type(order_by);
+
+                arroyo_datastream::Operator::TopN(arroyo_datastream::TopN {
+                    max_elements: *max_elements,
+                    expiration: *expiration,
+                    extractor: quote!(|arg| { #sort_expression }).to_string(),
+                    sort_key_type: quote!(#sort_type).to_string(),
+                    converter: row_number_converter(result_struct).to_string(),
+                })
+            }
             PlanOperator::Flatten => arroyo_datastream::Operator::FlattenOperator {
                 name: "flatten".into(),
             },
@@ -728,6 +882,18 @@ impl PlanNode {
                 .to_string(),
                 return_type: ExpressionReturnType::Record,
             },
+            PlanOperator::ToUpsert => arroyo_datastream::Operator::ExpressionOperator {
+                name: "to_upsert".into(),
+                expression: quote!({
+                    arroyo_types::Record {
+                        timestamp: record.timestamp,
+                        key: None,
+                        value: record.value.clone().into(),
+                    }
+                })
+                .to_string(),
+                return_type: ExpressionReturnType::Record,
+            },
             PlanOperator::FromDebezium => arroyo_datastream::Operator::ExpressionOperator {
                 name: "from_debezium".into(),
                 expression: quote!({
@@ -740,6 +906,45 @@ impl PlanNode {
                 .to_string(),
                 return_type: ExpressionReturnType::Record,
             },
+            PlanOperator::Deduplicate { ttl } => Operator::Deduplicate { ttl: *ttl },
+            PlanOperator::LateDataFilter(late_data) => Operator::LateDataFilter {
+                width: late_data.width,
+                alignment: late_data.alignment.clone(),
+                allowed_lateness: late_data.allowed_lateness,
+            },
+            PlanOperator::MatchRecognize(match_recognize) => {
+                Operator::MatchRecognize(arroyo_datastream::MatchRecognize {
+                    pattern: match_recognize.pattern.clone(),
+                    within: match_recognize.within,
+                    predicate: match_recognize.predicate().to_string(),
+                    measures: match_recognize.measures().to_string(),
+                })
+            }
+            PlanOperator::LookupJoin(lookup_join) => {
+                Operator::LookupJoin(arroyo_datastream::LookupJoin {
+                    connector: lookup_join.connector.clone(),
+                    key_column: lookup_join.key_column.clone(),
+                    lookup_type: lookup_join
+                        .lookup_struct
+                        .get_type()
+                        .to_token_stream()
+                        .to_string(),
+                    key: lookup_join.key().to_string(),
+                    merge: lookup_join.merge().to_string(),
+                    cache_size: lookup_join.cache.max_entries,
+                    cache_ttl: Some(lookup_join.cache.ttl),
+                })
+            }
+            PlanOperator::AsyncUdf(async_udf) => Operator::AsyncUdf(arroyo_datastream::AsyncUdf {
+                name: async_udf.udf.name.clone(),
+                result_type: async_udf.result_type(),
+                call: async_udf.call().to_string(),
+                merge: async_udf.merge().to_string(),
+                ordered: async_udf.config.ordered,
+                max_concurrency: async_udf.config.max_concurrency,
+                timeout: async_udf.config.timeout,
+                retries: async_udf.config.retries,
+            }),
             PlanOperator::FromUpdating => Operator::ExpressionOperator {
                 name: "from_updating".into(),
                 expression: quote!({
@@ -876,12 +1081,33 @@ impl PlanNode {
             } => {
                 output_types.extend(projection.output_struct().all_structs());
             }
+            PlanOperator::LookupJoin(lookup_join) => {
+                output_types.extend(lookup_join.lookup_struct.all_structs());
+                output_types.extend(lookup_join.right_struct().all_structs());
+            }
             _ => {}
         }
         output_types
     }
 }
 
+/// `fn(T, usize) -> OutT`, which appends the row number to a row of the input of a top-N, where
+/// `result_struct` is the input with the row number as its last field
+fn row_number_converter(result_struct: &StructDef) -> TokenStream {
+    let output_struct = result_struct.get_type();
+    let (row_number, fields) = result_struct.fields.split_last().unwrap();
+    let row_number = row_number.field_ident();
+    let field_assignments = fields.iter().map(|f| {
+        let ident = f.field_ident();
+        quote! { #ident: arg.#ident.clone() }
+    });
+
+    quote!(|arg, i| #output_struct {
+        #(#field_assignments, )*
+        #row_number: i as u64,
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct PlanEdge {
     pub edge_type: EdgeType,
@@ -915,6 +1141,11 @@ pub enum PlanType {
         value_type: String,
         values: Vec<StructDef>,
     },
+    Upsert {
+        key: Option<StructDef>,
+        value_type: String,
+        values: Vec<StructDef>,
+    },
     Updating(Box<PlanType>),
 }
 
@@ -961,6 +1192,10 @@ impl PlanType {
                 let v: Type = parse_str(value_type).unwrap();
                 parse_quote!(arroyo_types::Debezium<#v>)
             }
+            PlanType::Upsert { value_type, .. } => {
+                let v: Type = parse_str(value_type).unwrap();
+                parse_quote!(arroyo_types::Upsert<#v>)
+            }
             PlanType::Updating(inner_type) => {
                 let inner_type = inner_type.as_syn_type();
                 parse_quote!(arroyo_types::UpdatingData<#inner_type>)
@@ -968,17 +1203,47 @@ impl PlanType {
         }
     }
 
+    fn key_struct(&self) -> Option<&StructDef> {
+        match self {
+            PlanType::Unkeyed(_)
+            | PlanType::UnkeyedList(_)
+            | PlanType::KeyedLiteralTypeValue { key: None, .. }
+            | PlanType::Debezium { key: None, .. }
+            | PlanType::Upsert { key: None, .. } => None,
+            PlanType::Keyed { key, .. }
+            | PlanType::KeyedPair { key, .. }
+            | PlanType::KeyedLiteralTypeValue { key: Some(key), .. }
+            | PlanType::KeyedListPair { key, .. }
+            | PlanType::Debezium { key: Some(key), .. }
+            | PlanType::Upsert { key: Some(key), .. } => Some(key),
+            PlanType::Updating(inner) => inner.key_struct(),
+        }
+    }
+
+    // the layout of the values when they're stored in state; only plain structs are described
+    fn value_state_type(&self) -> StateType {
+        match self {
+            PlanType::Unkeyed(value) | PlanType::Keyed { value, .. } => value.state_type(),
+            other => {
+                let value_type = other.as_syn_type();
+                StateType::Opaque(quote!(#value_type).to_string())
+            }
+        }
+    }
+
     fn key_type(&self) -> syn::Type {
         match self {
             PlanType::Unkeyed(_)
             | PlanType::UnkeyedList(_)
             | PlanType::KeyedLiteralTypeValue { key: None, .. }
-            | PlanType::Debezium { key: None, .. } => parse_quote!(()),
+            | PlanType::Debezium { key: None, .. }
+            | PlanType::Upsert { key: None, .. } => parse_quote!(()),
             PlanType::Keyed { key, .. }
             | PlanType::KeyedPair { key, .. }
             | PlanType::KeyedLiteralTypeValue { key: Some(key), .. }
             | PlanType::KeyedListPair { key, .. }
-            | PlanType::Debezium { key: Some(key), .. } => key.get_type(),
+            | PlanType::Debezium { key: Some(key), .. }
+            | PlanType::Upsert { key: Some(key), .. } => key.get_type(),
             PlanType::Updating(inner) => inner.key_type(),
         }
     }
@@ -999,7 +1264,7 @@ impl PlanType {
                 ..
             } => vec![left_value.clone(), right_value.clone()],
             PlanType::KeyedLiteralTypeValue { .. } => vec![],
-            PlanType::Debezium { values, .. } => values.clone(),
+            PlanType::Debezium { values, .. } | PlanType::Upsert { values, .. } => values.clone(),
             PlanType::Updating(v) => v.value_structs(),
         }
     }
@@ -1015,11 +1280,13 @@ impl PlanType {
             PlanType::Unkeyed(_)
             | PlanType::UnkeyedList(_)
             | PlanType::KeyedLiteralTypeValue { key: None, .. }
-            | PlanType::Debezium { key: None, .. } => vec![],
+            | PlanType::Debezium { key: None, .. }
+            | PlanType::Upsert { key: None, .. } => vec![],
             PlanType::Keyed { key, .. }
             | PlanType::KeyedPair { key, .. }
             | PlanType::KeyedLiteralTypeValue { key: Some(key), .. }
             | PlanType::Debezium { key: Some(key), .. }
+            | PlanType::Upsert { key: Some(key), .. }
             | PlanType::KeyedListPair { key, .. } => key.all_names(),
             PlanType::Updating(inner) => inner.get_key_struct_names(),
         }
@@ -1055,7 +1322,7 @@ impl PlanType {
                 Some(key) => key.all_structs().into_iter().collect(),
                 None => HashSet::new(),
             },
-            PlanType::Debezium { key, values, .. } => key
+            PlanType::Debezium { key, values, .. } | PlanType::Upsert { key, values, .. } => key
                 .iter()
                 .flat_map(|key| key.all_structs())
                 .chain(values.iter().flat_map(|value| value.all_structs()))
@@ -1114,6 +1381,13 @@ impl PlanType {
                 value_type: value_type.clone(),
                 values: values.clone(),
             },
+            PlanType::Upsert {
+                value_type, values, ..
+            } => PlanType::Upsert {
+                key: Some(key),
+                value_type: value_type.clone(),
+                values: values.clone(),
+            },
             PlanType::Updating(inner) => PlanType::Updating(Box::new(inner.with_key(key))),
         }
     }
@@ -1127,6 +1401,7 @@ impl PlanType {
             PlanType::KeyedListPair { .. } => unreachable!(),
             PlanType::KeyedLiteralTypeValue { .. } => unreachable!(),
             PlanType::Debezium { .. } => unreachable!(),
+            PlanType::Upsert { .. } => unreachable!(),
             PlanType::Updating(inner) => PlanType::Updating(Box::new(inner.with_value(value))),
         }
     }
@@ -1184,6 +1459,13 @@ impl PlanGraph {
                 }
             }
             SqlOperator::Union(inputs) => self.add_union(inputs),
+            SqlOperator::MatchRecognize(input, match_recognize) => {
+                self.add_match_recognize(input, match_recognize)
+            }
+            SqlOperator::LookupJoin(input, lookup_join) => self.add_lookup_join(input, lookup_join),
+            SqlOperator::TopN(input, top_n) => self.add_top_n(input, top_n),
+            SqlOperator::AsyncUdf(input, async_udf) => self.add_async_udf(input, async_udf),
+            SqlOperator::LateData(input, late_data) => self.add_late_data(input, late_data),
         }
     }
 
@@ -1261,7 +1543,10 @@ impl PlanGraph {
             current_index = timestamp_index;
         }
 
-        let strategy = if let Some(watermark_expression) = source_operator.watermark_column {
+        let watermarks = &source_operator.source.watermarks;
+        let strategy = if watermarks.per_partition {
+            arroyo_datastream::WatermarkStrategy::Source
+        } else if let Some(watermark_expression) = source_operator.watermark_column {
             let arg_ident = ValuePointerContext::new().variable_ident();
             let expression = watermark_expression.generate(&ValuePointerContext::new());
             let null_checked_expression = if watermark_expression
@@ -1282,13 +1567,19 @@ impl PlanGraph {
             }
         } else {
             arroyo_datastream::WatermarkStrategy::FixedLateness {
-                max_lateness: Duration::from_secs(1),
+                max_lateness: watermarks
+                    .max_out_of_orderness
+                    .unwrap_or(DEFAULT_MAX_OUT_OF_ORDERNESS),
             }
         };
 
         let watermark_operator = PlanOperator::Watermark(arroyo_datastream::PeriodicWatermark {
-            period: Duration::from_secs(1),
-            idle_time: source_operator.source.idle_time,
+            period: watermarks.period,
+            // the source decides when its partitions are idle
+            idle_time: source_operator
+                .source
+                .idle_time
+                .filter(|_| !watermarks.per_partition),
             strategy,
         });
         let watermark_index = self.insert_operator(
@@ -1300,8 +1591,55 @@ impl PlanGraph {
         };
         self.graph
             .add_edge(current_index, watermark_index, watermark_edge);
-        self.sources.insert(source_operator.name, watermark_index);
-        watermark_index
+        current_index = watermark_index;
+
+        // deduplication runs after the watermark, which it uses to expire the keys it has seen
+        if let Some(deduplication) = source_operator.deduplication {
+            let key_struct = deduplication.key.output_struct();
+            let key_index = self.insert_operator(
+                PlanOperator::RecordTransform(RecordTransform::KeyProjection(deduplication.key)),
+                self.get_plan_node(current_index)
+                    .output_type
+                    .with_key(key_struct),
+            );
+            self.graph.add_edge(
+                current_index,
+                key_index,
+                PlanEdge {
+                    edge_type: EdgeType::Forward,
+                },
+            );
+
+            let dedup_index = self.insert_operator(
+                PlanOperator::Deduplicate {
+                    ttl: deduplication.ttl,
+                },
+                self.get_plan_node(key_index).output_type.clone(),
+            );
+            self.graph.add_edge(
+                key_index,
+                dedup_index,
+                PlanEdge {
+                    edge_type: EdgeType::Shuffle,
+                },
+            );
+
+            let unkey_index = self.insert_operator(
+                PlanOperator::Unkey,
+                self.get_plan_node(current_index).output_type.clone(),
+            );
+            self.graph.add_edge(
+                dedup_index,
+                unkey_index,
+                PlanEdge {
+                    edge_type: EdgeType::Forward,
+                },
+            );
+            current_index = unkey_index;
+        }
+
+        self.sources.insert(source_operator.name, current_index);
+        current_index
     }
 
     pub fn insert_operator(&mut self, operator: PlanOperator, typ: PlanType) -> NodeIndex {
@@ -1343,6 +1681,8 @@ impl PlanGraph {
         let aggregate_operator = PlanOperator::WindowAggregate {
             window: aggregate.window,
             projection: aggregate_projection,
+            allowed_lateness: aggregate.allowed_lateness,
+            emit_partial: aggregate.emit_partial,
         };
 
         let aggregate_index = self.insert_operator(
@@ -1374,6 +1714,7 @@ impl PlanGraph {
         // right now left and right either both have or don't have windows.
         let has_window = left.has_window();
         let join_type = join_operator.join_type;
+        let interval = join_operator.interval;
         let left_index = self.add_sql_operator(*left);
         let right_index = self.add_sql_operator(*right);
 
@@ -1427,6 +1768,7 @@ impl PlanGraph {
                 left_type,
                 right_type,
                 join_type,
+                interval,
             )
         }
     }
@@ -1495,11 +1837,22 @@ impl PlanGraph {
         left_struct: StructDef,
         right_struct: StructDef,
         join_type: JoinType,
+        interval: Option<JoinInterval>,
     ) -> NodeIndex {
-        let join_node = PlanOperator::JoinWithExpiration {
-            left_expiration: Duration::from_secs(24 * 60 * 60),
-            right_expiration: Duration::from_secs(24 * 60 * 60),
-            join_type: join_type.clone(),
+        let join_node = match interval {
+            // interval joins only keep rows for as long as they may fall in the band
+            Some(JoinInterval {
+                lower_bound,
+                upper_bound,
+            }) => PlanOperator::IntervalJoin {
+                lower_bound,
+                upper_bound,
+            },
+            None => PlanOperator::JoinWithExpiration {
+                left_expiration: self.sql_config.state_ttl,
+                right_expiration: self.sql_config.state_ttl,
+                join_type: join_type.clone(),
+            },
         };
         let join_node_output_type = PlanType::KeyedPair {
             key: key_struct.clone(),
@@ -1548,23 +1901,196 @@ impl PlanGraph {
         merge_index
     }
 
+    fn add_lookup_join(
+        &mut self,
+        input: Box<SqlOperator>,
+        lookup_join: LookupJoinOperator,
+    ) -> NodeIndex {
+        let input_index = self.add_sql_operator(*input);
+        let output_struct = lookup_join.output_struct();
+
+        let lookup_join_index = self.insert_operator(
+            PlanOperator::LookupJoin(lookup_join),
+            PlanType::Unkeyed(output_struct),
+        );
+        self.graph.add_edge(
+            input_index,
+            lookup_join_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+        lookup_join_index
+    }
+
+    fn add_async_udf(&mut self, input: Box<SqlOperator>, async_udf: AsyncUdfOperator) -> NodeIndex {
+        let input_index = self.add_sql_operator(*input);
+        let output_struct = async_udf.output_struct();
+
+        let async_udf_index = self.insert_operator(
+            PlanOperator::AsyncUdf(async_udf),
+            PlanType::Unkeyed(output_struct),
+        );
+        self.graph.add_edge(
+            input_index,
+            async_udf_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+        async_udf_index
+    }
+
+    fn add_late_data(&mut self, input: Box<SqlOperator>, late_data: LateDataOperator) -> NodeIndex {
+        let input_index = self.add_sql_operator(*input);
+        let output_type = self.get_plan_node(input_index).output_type.clone();
+
+        let late_data_index =
+            self.insert_operator(PlanOperator::LateDataFilter(late_data), output_type);
+        // the window's input is shuffled, so ours is too in order to see the same watermarks
+        self.graph.add_edge(
+            input_index,
+            late_data_index,
+            PlanEdge {
+                edge_type: EdgeType::Shuffle,
+            },
+        );
+        late_data_index
+    }
+
+    fn add_top_n(&mut self, input: Box<SqlOperator>, top_n: TopNOperator) -> NodeIndex {
+        let input_type = input.return_type();
+        let input_index = self.add_sql_operator(*input);
+        let mut result_struct = input_type.clone();
+        result_struct.fields.push(StructField::new(
+            top_n.field_name,
+            None,
+            WindowFunction::RowNumber.return_type(),
+        ));
+        let partition_struct = top_n.partition.output_struct();
+
+        let partition_key_index = self.insert_operator(
+            PlanOperator::RecordTransform(RecordTransform::KeyProjection(top_n.partition)),
+            PlanType::Keyed {
+                key: partition_struct.clone(),
+                value: input_type,
+            },
+        );
+        self.graph.add_edge(
+            input_index,
+            partition_key_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+
+        let top_n_index = self.insert_operator(
+            PlanOperator::TopN {
+                max_elements: top_n.max_elements,
+                expiration: self.sql_config.state_ttl,
+                order_by: top_n.order_by,
+                result_struct: result_struct.clone(),
+            },
+            PlanType::Updating(Box::new(PlanType::Keyed {
+                key: partition_struct,
+                value: result_struct.clone(),
+            })),
+        );
+        self.graph.add_edge(
+            partition_key_index,
+            top_n_index,
+            PlanEdge {
+                edge_type: EdgeType::Shuffle,
+            },
+        );
+
+        let unkey_index = self.insert_operator(
+            PlanOperator::Unkey,
+            PlanType::Updating(Box::new(PlanType::Unkeyed(result_struct))),
+        );
+        self.graph.add_edge(
+            top_n_index,
+            unkey_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+        unkey_index
+    }
+
+    fn add_match_recognize(
+        &mut self,
+        input: Box<SqlOperator>,
+        match_recognize: MatchRecognizeOperator,
+    ) -> NodeIndex {
+        let input_type = input.return_type();
+        let input_index = self.add_sql_operator(*input);
+        let partition_struct = match_recognize.partition.output_struct();
+        let output_struct = match_recognize.output_struct();
+
+        let partition_key_index = self.insert_operator(
+            PlanOperator::RecordTransform(RecordTransform::KeyProjection(
+                match_recognize.partition.clone(),
+            )),
+            PlanType::Keyed {
+                key: partition_struct.clone(),
+                value: input_type,
+            },
+        );
+        self.graph.add_edge(
+            input_index,
+            partition_key_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+
+        let match_recognize_index = self.insert_operator(
+            PlanOperator::MatchRecognize(match_recognize),
+            PlanType::Keyed {
+                key: partition_struct,
+                value: output_struct.clone(),
+            },
+        );
+        self.graph.add_edge(
+            partition_key_index,
+            match_recognize_index,
+            PlanEdge {
+                edge_type: EdgeType::Shuffle,
+            },
+        );
+
+        let unkey_index =
+            self.insert_operator(PlanOperator::Unkey, PlanType::Unkeyed(output_struct));
+        self.graph.add_edge(
+            match_recognize_index,
+            unkey_index,
+            PlanEdge {
+                edge_type: EdgeType::Forward,
+            },
+        );
+        unkey_index
+    }
+
     fn add_window(
         &mut self,
         input: Box<SqlOperator>,
         window_operator: crate::pipeline::SqlWindowOperator,
     ) -> NodeIndex {
         let input_type = input.return_type();
-        let window_type = if input.has_window() {
-            WindowType::Instant
-        } else {
-            window_operator.window_type
-        };
+        let window_type = window_operator.window_type.map(|window_type| {
+            if input.has_window() {
+                WindowType::Instant
+            } else {
+                window_type
+            }
+        });
         let input_index = self.add_sql_operator(*input);
         let mut result_type = input_type.clone();
         result_type.fields.push(StructField::new(
             window_operator.field_name.clone(),
             None,
-            TypeDef::DataType(DataType::UInt64, false),
+            window_operator.window_fn.return_type(),
         ));
         let partition_struct = window_operator.partition.output_struct();
 
@@ -1585,13 +2111,18 @@ impl PlanGraph {
         self.graph
             .add_edge(input_index, partition_key_index, partition_key_edge);
 
-        let window_function_node = PlanOperator::WindowFunction(WindowFunctionOperator {
+        let window_function = WindowFunctionOperator {
             window_function: window_operator.window_fn,
             order_by: window_operator.order_by,
-            window_type,
+            window_type: window_type.clone().unwrap_or(WindowType::Instant),
+            frame: window_operator.frame,
             result_struct: result_type.clone(),
             field_name: window_operator.field_name,
-        });
+        };
+        let window_function_node = match window_type {
+            Some(_) => PlanOperator::WindowFunction(window_function),
+            None => PlanOperator::OverWindow(window_function),
+        };
         let window_function_index = self.insert_operator(
             window_function_node,
             PlanType::Keyed {
@@ -1648,7 +2179,35 @@ impl PlanGraph {
     ) -> NodeIndex {
         let input_index = self.add_sql_operator(*input);
         let input_node = self.get_plan_node(input_index);
-        if let PlanType::Updating(inner) = &input_node.output_type {
+        if matches!(sql_sink.updating_type, SinkUpdateType::Upsert) {
+            // retractions become deletes of the rows they retract
+            let value_plan_type = match &input_node.output_type {
+                PlanType::Updating(inner) => inner.as_ref(),
+                output_type => output_type,
+            };
+            let value_type = value_plan_type.as_syn_type();
+            let upsert_type = PlanType::Upsert {
+                key: None,
+                value_type: quote!(#value_type).to_string(),
+                values: value_plan_type.value_structs(),
+            };
+            let upsert_index = self.insert_operator(PlanOperator::ToUpsert, upsert_type.clone());
+            let edge = PlanEdge {
+                edge_type: EdgeType::Forward,
+            };
+            self.graph.add_edge(input_index, upsert_index, edge);
+
+            let plan_node = PlanOperator::Sink(name, sql_sink);
+            let plan_node_index = self.insert_operator(plan_node, upsert_type);
+
+            let upsert_edge = PlanEdge {
+                edge_type: EdgeType::Forward,
+            };
+
+            self.graph
+                .add_edge(upsert_index, plan_node_index, upsert_edge);
+            plan_node_index
+        } else if let PlanType::Updating(inner) = &input_node.output_type {
             let value_type = inner.as_syn_type();
             let debezium_type = PlanType::Debezium {
                 key: None,
@@ -1736,7 +2295,7 @@ impl PlanGraph {
         let aggregate_struct = aggregate_projection.expression_type(&VecAggregationContext::new());
         let aggregate_operator = PlanOperator::NonWindowAggregate {
             input_is_update: input_updating,
-            expiration: Duration::from_secs(60 * 60 * 24),
+            expiration: self.sql_config.state_ttl,
             projection: aggregate_projection.clone().try_into().unwrap(),
         };
 
@@ -1833,10 +2392,76 @@ impl PlanGraph {
     }
 }
 
+/// Assigns each node an id that depends on what it does and on the ids of its inputs, rather than
+/// on its index in the graph, so that when a changed version of a query is restored from a
+/// savepoint its operators find their state even if others were added before them.
+fn stable_operator_ids(graph: &DiGraph<PlanNode, PlanEdge>) -> HashMap<NodeIndex, String> {
+    let mut ids = HashMap::new();
+    let mut used = HashSet::new();
+
+    for index in petgraph::algo::toposort(graph, None).expect("plan graph must be acyclic") {
+        let node = graph.node_weight(index).unwrap();
+        let mut inputs: Vec<_> = graph
+            .edges_directed(index, Direction::Incoming)
+            .map(|edge| (edge.weight().edge_type.clone(), ids[&edge.source()].clone()))
+            .collect();
+        inputs.sort();
+
+        let mut hasher = DefaultHasher::new();
+        node.prefix().hash(&mut hasher);
+        for (edge_type, input) in &inputs {
+            format!("{:?}", edge_type).hash(&mut hasher);
+            input.hash(&mut hasher);
+        }
+        let base = format!("{}_{:08x}", node.prefix(), hasher.finish() as u32);
+
+        // identical nodes reading the same inputs are told apart by the order they're visited in
+        let mut id = base.clone();
+        let mut n = 1;
+        while !used.insert(id.clone()) {
+            id = format!("{}_{}", base, n);
+            n += 1;
+        }
+        ids.insert(index, id);
+    }
+
+    ids
+}
+
+impl PlanGraph {
+    /// The types of the state of the graph's stateful operators, by operator id
+    fn state_schemas(&self) -> BTreeMap<String, OperatorStateSchema> {
+        let ids = stable_operator_ids(&self.graph);
+        self.graph
+            .node_indices()
+            .filter_map(|index| {
+                let mut inputs: Vec<_> = self
+                    .graph
+                    .edges_directed(index, Direction::Incoming)
+                    .map(|edge| {
+                        let input = self.graph.node_weight(edge.source()).unwrap();
+                        (edge.weight().edge_type.clone(), &input.output_type)
+                    })
+                    .collect();
+                inputs.sort_by(|a, b| a.0.cmp(&b.0));
+                let inputs: Vec<_> = inputs.into_iter().map(|(_, t)| t).collect();
+
+                let schema = self
+                    .graph
+                    .node_weight(index)
+                    .unwrap()
+                    .state_schema(&inputs)?;
+                Some((ids[&index].clone(), schema))
+            })
+            .collect()
+    }
+}
+
 impl From<PlanGraph> for DiGraph<StreamNode, StreamEdge> {
     fn from(val: PlanGraph) -> Self {
+        let ids = stable_operator_ids(&val.graph);
         val.graph.map(
-            |index: NodeIndex, node| node.into_stream_node(index.index(), &val.sql_config),
+            |index: NodeIndex, node| node.into_stream_node(ids[&index].clone(), &val.sql_config),
             |index, edge| {
                 let source_index = val.graph.edge_endpoints(index).unwrap().0;
                 let source_node = val.graph.node_weight(source_index).unwrap();
@@ -1922,6 +2547,7 @@ pub fn get_program(
             .join("\n\n")
     ));
 
+    let state_schemas = plan_graph.state_schemas();
     let graph: DiGraph<StreamNode, StreamEdge> = plan_graph.into();
 
     Ok((
@@ -1932,6 +2558,7 @@ pub fn get_program(
             other_defs,
             udfs,
             graph,
+            state_schemas,
         },
         sources,
     ))
@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+use datafusion::sql::sqlparser::{
+    dialect::PostgreSqlDialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::match_recognize::render;
+
+/// Removes the `EXECUTE STATEMENT SET BEGIN .. END;` (or `BEGIN STATEMENT SET; .. END;`) around
+/// a group of INSERT statements, as written for Flink, which DataFusion can't parse. Every insert
+/// in a query is already planned into a single pipeline, sharing the sources and subqueries they
+/// have in common, so the set itself doesn't change the plan.
+pub fn extract(query: &str) -> Result<String> {
+    let tokens = Tokenizer::new(&PostgreSqlDialect {}, query).tokenize()?;
+
+    let mut output: Vec<Token> = vec![];
+    let mut found = false;
+    let mut open = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        if at_statement_start(&output) {
+            let start =
+                match_words(&tokens, i, &["execute", "statement", "set", "begin"]).or_else(|| {
+                    match_words(&tokens, i, &["begin", "statement", "set"])
+                        .map(|end| skip_semicolon(&tokens, end))
+                });
+            if let Some(end) = start {
+                if open {
+                    bail!("statement sets can't be nested");
+                }
+                found = true;
+                open = true;
+                i = end;
+                continue;
+            }
+
+            if open {
+                if let Some(end) = match_words(&tokens, i, &["end"]) {
+                    open = false;
+                    i = skip_semicolon(&tokens, end);
+                    continue;
+                }
+            }
+        }
+
+        output.push(tokens[i].clone());
+        i += 1;
+    }
+
+    if open {
+        bail!("statement set is missing its END");
+    }
+
+    if !found {
+        return Ok(query.to_string());
+    }
+
+    Ok(render(&output))
+}
+
+fn at_statement_start(output: &[Token]) -> bool {
+    matches!(
+        output
+            .iter()
+            .rev()
+            .find(|t| !matches!(t, Token::Whitespace(_))),
+        None | Some(Token::SemiColon)
+    )
+}
+
+/// If the tokens starting at `i` are the given keywords, separated by whitespace, returns the
+/// index after the last of them
+pub(crate) fn match_words(tokens: &[Token], mut i: usize, words: &[&str]) -> Option<usize> {
+    for (n, word) in words.iter().enumerate() {
+        if n > 0 {
+            while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+                i += 1;
+            }
+        }
+        match tokens.get(i) {
+            Some(Token::Word(w))
+                if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word) =>
+            {
+                i += 1
+            }
+            _ => return None,
+        }
+    }
+    Some(i)
+}
+
+fn skip_semicolon(tokens: &[Token], end: usize) -> usize {
+    let mut i = end;
+    while matches!(tokens.get(i), Some(Token::Whitespace(_))) {
+        i += 1;
+    }
+    if matches!(tokens.get(i), Some(Token::SemiColon)) {
+        i + 1
+    } else {
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_sets() {
+        assert_eq!(
+            extract(
+                "EXECUTE STATEMENT SET BEGIN INSERT INTO a SELECT * FROM t; \
+                INSERT INTO b SELECT * FROM t; END;"
+            )
+            .unwrap(),
+            " INSERT INTO a SELECT * FROM t; INSERT INTO b SELECT * FROM t; "
+        );
+
+        assert_eq!(
+            extract("begin statement set; INSERT INTO a SELECT * FROM t; end;").unwrap(),
+            " INSERT INTO a SELECT * FROM t; "
+        );
+
+        // END in a CASE expression doesn't close the set
+        let query = "SELECT CASE WHEN x THEN 1 END FROM t";
+        assert_eq!(extract(query).unwrap(), query);
+
+        assert!(extract("BEGIN STATEMENT SET; INSERT INTO a SELECT * FROM t;").is_err());
+    }
+}
@@ -8,12 +8,15 @@ use arroyo_datastream::{ConnectorOp, Operator};
 use arroyo_rpc::api_types::connections::{
     ConnectionSchema, ConnectionType, SchemaDefinition, SourceField,
 };
-use arroyo_rpc::formats::{Format, Framing};
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::{OperatorConfig, PartitionWatermarks, RateLimit, SinkMode, WatermarkAlignment};
 use datafusion::{
     optimizer::{analyzer::Analyzer, optimizer::Optimizer, OptimizerContext},
     sql::{
         planner::{PlannerContext, SqlToRel},
-        sqlparser::ast::{ColumnDef, ColumnOption, Statement, Value},
+        sqlparser::ast::{ColumnDef, ColumnOption, DataType as SQLDataType, Statement, Value},
+        sqlparser::dialect::PostgreSqlDialect,
+        sqlparser::parser::Parser,
     },
 };
 use datafusion_common::{config::ConfigOptions, DFField, DFSchema};
@@ -22,16 +25,19 @@ use datafusion_expr::{
 };
 
 use crate::code_gen::{CodeGenerator, ValuePointerContext};
+use crate::ddl::INFERRED_TYPE;
 use crate::expressions::CastExpression;
-use crate::external::SinkUpdateType;
+use crate::external::{SinkUpdateType, SourceWatermarks, DEFAULT_MAX_OUT_OF_ORDERNESS};
+use crate::lookup::LookupCacheConfig;
+use crate::match_recognize::MatchRecognize;
 use crate::DEFAULT_IDLE_TIME;
 use crate::{
     expressions::{Column, ColumnExpression, Expression, ExpressionContext},
     external::{ProcessingMode, SqlSink, SqlSource},
     json_schema,
     operators::Projection,
-    pipeline::{SourceOperator, SqlOperator, SqlPipelineBuilder},
-    types::{convert_data_type, StructDef, StructField, TypeDef},
+    pipeline::{Deduplication, SourceOperator, SqlOperator, SqlPipelineBuilder},
+    types::{convert_data_type, is_json_type, StructDef, StructField, TypeDef},
     ArroyoSchemaProvider,
 };
 
@@ -48,7 +54,13 @@ pub struct ConnectorTable {
     pub format: Option<Format>,
     pub event_time_field: Option<String>,
     pub watermark_field: Option<String>,
+    // the watermark declared by `WATERMARK FOR`, which is computed from the other fields
+    pub watermark_expression: Option<Expression>,
     pub idle_time: Option<Duration>,
+    pub watermarks: SourceWatermarks,
+    pub deduplication: Option<Deduplication>,
+    pub lookup_cache: LookupCacheConfig,
+    pub sink_mode: Option<SinkMode>,
 }
 
 #[derive(Debug, Clone)]
@@ -61,13 +73,13 @@ pub enum FieldSpec {
 }
 
 impl FieldSpec {
-    fn is_virtual(&self) -> bool {
+    pub(crate) fn is_virtual(&self) -> bool {
         match self {
             FieldSpec::StructField(_) => false,
             FieldSpec::VirtualField { .. } => true,
         }
     }
-    fn struct_field(&self) -> &StructField {
+    pub(crate) fn struct_field(&self) -> &StructField {
         match self {
             FieldSpec::StructField(f) => f,
             FieldSpec::VirtualField { field, .. } => field,
@@ -144,7 +156,12 @@ impl From<Connection> for ConnectorTable {
             format: value.schema.format.clone(),
             event_time_field: None,
             watermark_field: None,
+            watermark_expression: None,
             idle_time: DEFAULT_IDLE_TIME,
+            watermarks: SourceWatermarks::default(),
+            deduplication: None,
+            lookup_cache: LookupCacheConfig::default(),
+            sink_mode: None,
         }
     }
 }
@@ -163,6 +180,75 @@ impl ConnectorTable {
 
         let framing = Framing::from_opts(options).map_err(|e| anyhow!("invalid framing: '{e}'"))?;
 
+        let bad_data = BadData::from_opts(options)
+            .map_err(|e| anyhow!("invalid bad_data configuration: '{e}'"))?;
+
+        let compression =
+            Compression::from_opts(options).map_err(|e| anyhow!("invalid compression: '{e}'"))?;
+
+        let rate_limit =
+            RateLimit::from_opts(options).map_err(|e| anyhow!("invalid rate limit: {e}"))?;
+
+        let sink_mode =
+            SinkMode::from_opts(options).map_err(|e| anyhow!("invalid sink mode: {e}"))?;
+
+        // retracting sinks write Debezium changelogs, which no other mode can write
+        let debezium = format.as_ref().map(|f| f.is_updating()).unwrap_or(false);
+        match sink_mode {
+            Some(SinkMode::Retract) if !debezium => {
+                bail!("retract sinks write Debezium changelogs, so must use the 'debezium_json' format")
+            }
+            Some(SinkMode::Append | SinkMode::Upsert) if debezium => {
+                bail!("sinks with the 'debezium_json' format write every change, so must use the 'retract' sink mode")
+            }
+            _ => {}
+        }
+
+        let mut watermarks = SourceWatermarks::default();
+        if let Some(period) = options.remove("watermark.interval") {
+            watermarks.period = parse_duration(&period)?;
+            if watermarks.period.is_zero() {
+                bail!("'watermark.interval' must be greater than zero");
+            }
+        }
+        watermarks.max_out_of_orderness = options
+            .remove("watermark.max_out_of_orderness")
+            .map(|d| parse_duration(&d))
+            .transpose()?;
+        watermarks.per_partition = match options.remove("watermark.per_partition").as_deref() {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(other) => bail!(
+                "'watermark.per_partition' must be 'true' or 'false', not '{}'",
+                other
+            ),
+        };
+        let idle_timeout = options
+            .remove("watermark.idle_timeout")
+            .map(|d| parse_duration(&d))
+            .transpose()?;
+        let alignment_group = options.remove("watermark.alignment.group");
+        watermarks.alignment = match options.remove("watermark.alignment.max_drift") {
+            Some(max_drift) => {
+                let max_drift = parse_duration(&max_drift)?;
+                if max_drift.is_zero() {
+                    bail!("'watermark.alignment.max_drift' must be greater than zero");
+                }
+                Some(WatermarkAlignment {
+                    group: alignment_group.unwrap_or_else(|| "default".to_string()),
+                    max_drift_micros: max_drift.as_micros() as u64,
+                })
+            }
+            None if alignment_group.is_some() => {
+                bail!("'watermark.alignment.group' requires 'watermark.alignment.max_drift' to be set")
+            }
+            None => None,
+        };
+        // sources can only be paused on their own watermarks, so they must generate them
+        if watermarks.alignment.is_some() && !watermarks.per_partition {
+            bail!("watermark alignment requires 'watermark.per_partition' to be enabled");
+        }
+
         let schema_fields: Result<Vec<SourceField>> = fields
             .iter()
             .filter(|f| !f.is_virtual())
@@ -178,23 +264,85 @@ impl ConnectorTable {
             })
             .collect();
 
-        let schema = ConnectionSchema::try_new(format, framing, None, schema_fields?, None)?;
+        let schema = ConnectionSchema::try_new(
+            format,
+            framing,
+            bad_data,
+            compression,
+            None,
+            schema_fields?,
+            None,
+        )?;
+
+        let mut connection = connector.from_options(name, options, Some(&schema))?;
+
+        if let Some(rate_limit) = rate_limit {
+            if !matches!(connection.connection_type, ConnectionType::Source) {
+                bail!("rate limits can only be set on sources");
+            }
+
+            let mut config: OperatorConfig = serde_json::from_str(&connection.config)?;
+            config.rate_limit = Some(rate_limit);
+            connection.config = serde_json::to_string(&config)?;
+        }
+
+        if (watermarks != SourceWatermarks::default() || idle_timeout.is_some())
+            && !matches!(connection.connection_type, ConnectionType::Source)
+        {
+            bail!("watermarks can only be configured on sources");
+        }
+
+        if let Some(sink_mode) = sink_mode {
+            if !matches!(connection.connection_type, ConnectionType::Sink) {
+                bail!("sink modes can only be set on sinks");
+            }
 
-        let connection = connector.from_options(name, options, Some(&schema))?;
+            let config: OperatorConfig = serde_json::from_str(&connection.config)?;
+            connector.validate_sink_mode(&config.table, sink_mode)?;
+        }
 
         let mut table: ConnectorTable = connection.into();
-        table.fields = fields;
+        table.sink_mode = sink_mode;
+        // connectors may infer the schema themselves if no columns were declared
+        if !fields.is_empty() {
+            table.fields = fields;
+        }
         table.event_time_field = options.remove("event_time_field");
         table.watermark_field = options.remove("watermark_field");
 
-        table.idle_time = options
+        let idle_micros = options
             .remove("idle_micros")
             .map(|t| i64::from_str(&t))
             .transpose()
-            .map_err(|_| anyhow!("idle_micros must be sent to a number"))?
-            .or_else(|| DEFAULT_IDLE_TIME.map(|t| t.as_micros() as i64))
-            .filter(|t| *t <= 0)
-            .map(|t| Duration::from_micros(t as u64));
+            .map_err(|_| anyhow!("idle_micros must be sent to a number"))?;
+
+        // sources never go idle if the timeout isn't positive
+        table.idle_time = match (idle_timeout, idle_micros) {
+            (Some(_), Some(_)) => {
+                bail!("only one of 'watermark.idle_timeout' and 'idle_micros' may be set")
+            }
+            (Some(timeout), None) => Some(timeout).filter(|t| !t.is_zero()),
+            (None, idle_micros) => idle_micros
+                .or_else(|| DEFAULT_IDLE_TIME.map(|t| t.as_micros() as i64))
+                .filter(|t| *t > 0)
+                .map(|t| Duration::from_micros(t as u64)),
+        };
+
+        if watermarks.per_partition {
+            let mut config: OperatorConfig = serde_json::from_str(&table.config)?;
+            connector.validate_partition_watermarks(&config.table)?;
+            config.partition_watermarks = Some(PartitionWatermarks {
+                period_micros: watermarks.period.as_micros() as u64,
+                max_out_of_orderness_micros: watermarks
+                    .max_out_of_orderness
+                    .unwrap_or(DEFAULT_MAX_OUT_OF_ORDERNESS)
+                    .as_micros() as u64,
+                idle_timeout_micros: table.idle_time.map(|t| t.as_micros() as u64),
+                alignment: watermarks.alignment.clone(),
+            });
+            table.config = serde_json::to_string(&config)?;
+        }
+        table.watermarks = watermarks;
 
         if !options.is_empty() {
             let keys: Vec<String> = options.keys().map(|s| format!("'{}'", s)).collect();
@@ -296,6 +444,10 @@ impl ConnectorTable {
     }
 
     fn watermark_column(&self) -> Result<Option<Expression>> {
+        if let Some(expression) = &self.watermark_expression {
+            return Ok(Some(expression.clone()));
+        }
+
         if let Some(field_name) = &self.watermark_field {
             // check that a column exists and it is a timestamp
             let field = self
@@ -323,7 +475,7 @@ impl ConnectorTable {
         }
     }
 
-    fn connector_op(&self) -> ConnectorOp {
+    pub(crate) fn connector_op(&self) -> ConnectorOp {
         ConnectorOp {
             operator: self.operator.clone(),
             config: self.config.clone(),
@@ -345,6 +497,9 @@ impl ConnectorTable {
             ConnectionType::Sink => {
                 bail!("cannot read from sink")
             }
+            ConnectionType::Lookup => {
+                bail!("lookup tables can only be read in lookup joins, like JOIN {} FOR SYSTEM_TIME AS OF o.proc_time ON ...", self.name)
+            }
         };
 
         if self.is_update() && self.has_virtual_fields() {
@@ -355,6 +510,17 @@ impl ConnectorTable {
         let timestamp_override = self.timestamp_override()?;
         let watermark_column = self.watermark_column()?;
 
+        if watermark_column.is_some() && self.watermarks.max_out_of_orderness.is_some() {
+            bail!("'watermark.max_out_of_orderness' can't be set on a table whose watermark is set by a column");
+        }
+
+        // the source's watermarks are computed from the timestamps of the messages it reads
+        if self.watermarks.per_partition
+            && (timestamp_override.is_some() || watermark_column.is_some())
+        {
+            bail!("per-partition watermarks are computed from the timestamps of the messages the source reads, so they can't be used with an event_time_field, watermark_field or WATERMARK FOR");
+        }
+
         let source = SqlSource {
             id: self.id,
             struct_def: StructDef::new(
@@ -372,6 +538,7 @@ impl ConnectorTable {
             operator: Operator::ConnectorSource(self.connector_op()),
             processing_mode: self.processing_mode(),
             idle_time: self.idle_time,
+            watermarks: self.watermarks.clone(),
         };
 
         Ok(SqlOperator::Source(SourceOperator {
@@ -380,6 +547,7 @@ impl ConnectorTable {
             virtual_field_projection,
             timestamp_override,
             watermark_column,
+            deduplication: self.deduplication.clone(),
         }))
     }
 
@@ -388,6 +556,9 @@ impl ConnectorTable {
             ConnectionType::Source => {
                 bail!("inserting into a source is not allowed")
             }
+            ConnectionType::Lookup => {
+                bail!("inserting into a lookup table is not allowed")
+            }
             ConnectionType::Sink => {}
         }
 
@@ -396,14 +567,14 @@ impl ConnectorTable {
             bail!("virtual fields are not currently supported in sinks");
         }
 
-        let updating_type = if self.is_update() {
-            SinkUpdateType::Force
-        } else {
-            SinkUpdateType::Disallow
+        let updating_type = match self.sink_mode {
+            Some(SinkMode::Upsert) => SinkUpdateType::Upsert,
+            _ if self.is_update() => SinkUpdateType::Force,
+            _ => SinkUpdateType::Disallow,
         };
 
         if updating_type == SinkUpdateType::Disallow && input.is_updating() {
-            bail!("sink does not support update messages, cannot be used with an updating query");
+            bail!("sink does not support update messages, cannot be used with an updating query; set 'sink.mode' to 'upsert' or 'retract' to write its changes");
         }
 
         if let Some(format) = &self.format {
@@ -459,6 +630,49 @@ pub enum Table {
         name: String,
         logical_plan: LogicalPlan,
     },
+    MatchRecognize {
+        clause: MatchRecognize,
+        fields: Vec<StructField>,
+    },
+}
+
+/// Parses durations like '10 minutes', '1h' or '500ms'
+fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (count, unit) = duration.split_at(split);
+
+    let count: u64 = count.parse().map_err(|_| {
+        anyhow!(
+            "invalid duration '{}'; expected a duration like '10 minutes'",
+            duration
+        )
+    })?;
+
+    let millis = match unit.trim() {
+        "ms" | "millisecond" | "milliseconds" => 1,
+        "s" | "sec" | "second" | "seconds" => 1000,
+        "m" | "min" | "minute" | "minutes" => 60 * 1000,
+        "h" | "hour" | "hours" => 60 * 60 * 1000,
+        "d" | "day" | "days" => 24 * 60 * 60 * 1000,
+        unit => bail!(
+            "invalid duration unit '{}'; expected one of milliseconds, seconds, minutes, hours or days",
+            unit
+        ),
+    };
+
+    Ok(Duration::from_millis(count * millis))
+}
+
+pub(crate) fn parse_ttl(ttl: &str) -> Result<Duration> {
+    let ttl = parse_duration(ttl)?;
+    if ttl.is_zero() {
+        bail!("ttl must be greater than zero");
+    }
+
+    Ok(ttl)
 }
 
 fn value_to_inner_string(value: &Value) -> Result<String> {
@@ -470,6 +684,11 @@ fn value_to_inner_string(value: &Value) -> Result<String> {
     }
 }
 
+fn is_inferred_type(data_type: &SQLDataType) -> bool {
+    matches!(data_type, SQLDataType::Custom(name, modifiers)
+        if modifiers.is_empty() && name.to_string() == INFERRED_TYPE)
+}
+
 impl Table {
     fn schema_from_columns(
         columns: &Vec<ColumnDef>,
@@ -479,14 +698,24 @@ impl Table {
             .iter()
             .map(|column| {
                 let name = column.name.value.to_string();
-                let data_type = convert_data_type(&column.data_type)?;
+                // computed columns declared as `name AS expr` take the type of their expression,
+                // which is filled in once it's compiled
+                let inferred = is_inferred_type(&column.data_type);
+                let data_type = if inferred {
+                    DataType::Null
+                } else {
+                    convert_data_type(&column.data_type)?
+                };
                 let nullable = !column
                     .options
                     .iter()
                     .any(|option| matches!(option.option, ColumnOption::NotNull));
 
-                let struct_field =
-                    StructField::new(name, None, TypeDef::DataType(data_type, nullable));
+                let struct_field = if is_json_type(&column.data_type) {
+                    StructField::json(name, None, nullable)
+                } else {
+                    StructField::new(name, None, TypeDef::DataType(data_type, nullable))
+                };
 
                 let generating_expression = column.options.iter().find_map(|option| {
                     if let ColumnOption::Generated {
@@ -498,7 +727,7 @@ impl Table {
                         None
                     }
                 });
-                Ok((struct_field, generating_expression))
+                Ok((struct_field, generating_expression, inferred))
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -506,7 +735,7 @@ impl Table {
             struct_field_pairs
                 .iter()
                 .filter_map(
-                    |(field, generating_expression)| match generating_expression {
+                    |(field, generating_expression, _)| match generating_expression {
                         Some(_) => None,
                         None => Some(field.clone()),
                     },
@@ -536,7 +765,7 @@ impl Table {
         let sql_to_rel = SqlToRel::new(schema_provider);
         struct_field_pairs
             .into_iter()
-            .map(|(struct_field, generating_expression)| {
+            .map(|(mut struct_field, generating_expression, inferred)| {
                 if let Some(generating_expression) = generating_expression {
                     // TODO: Implement automatic type coercion here, as we have elsewhere.
                     // It is done by calling the Analyzer which inserts CAST operators where necessary.
@@ -547,6 +776,10 @@ impl Table {
                         &mut PlannerContext::default(),
                     )?;
                     let expression = expression_context.compile_expr(&df_expr)?;
+                    if inferred {
+                        struct_field.data_type =
+                            expression.expression_type(&ValuePointerContext::new());
+                    }
                     Ok(FieldSpec::VirtualField {
                         field: struct_field,
                         expression,
@@ -558,6 +791,145 @@ impl Table {
             .collect::<Result<Vec<_>>>()
     }
 
+    /// Compiles the `dedup.key` expression, which may refer to any of the table's fields,
+    /// including virtual ones
+    fn deduplication(
+        table: &ConnectorTable,
+        key: Option<String>,
+        ttl: Option<String>,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Option<Deduplication>> {
+        let (key, ttl) = match (key, ttl) {
+            (None, None) => return Ok(None),
+            (Some(key), Some(ttl)) => (key, parse_ttl(&ttl)?),
+            (Some(_), None) => bail!("'dedup.ttl' must be set along with 'dedup.key'"),
+            (None, Some(_)) => bail!("'dedup.key' must be set along with 'dedup.ttl'"),
+        };
+
+        if !matches!(table.connection_type, ConnectionType::Source) {
+            bail!("deduplication can only be configured on sources");
+        }
+
+        if table.is_update() {
+            bail!("deduplication is not supported for sources in update mode");
+        }
+
+        let expression = Self::compile_field_expression(table, &key, schema_provider)?;
+
+        Ok(Some(Deduplication {
+            key: Projection::new(vec![(
+                Column {
+                    relation: None,
+                    name: "key".to_string(),
+                },
+                expression,
+            )]),
+            ttl,
+        }))
+    }
+
+    /// Compiles the `watermark_expression` option that `WATERMARK FOR` declarations are
+    /// rewritten into, which must compute a timestamp from the table's fields
+    fn watermark_expression(
+        table: &ConnectorTable,
+        expression: Option<String>,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Option<Expression>> {
+        let Some(expression) = expression else {
+            return Ok(None);
+        };
+
+        if !matches!(table.connection_type, ConnectionType::Source) {
+            bail!("watermarks can only be declared on sources");
+        }
+
+        if table.watermark_field.is_some() {
+            bail!("a table with a WATERMARK can't also set 'watermark_field'");
+        }
+
+        let expression = Self::compile_field_expression(table, &expression, schema_provider)?;
+        if !matches!(
+            expression.expression_type(&ValuePointerContext::new()),
+            TypeDef::DataType(DataType::Timestamp(..), _)
+        ) {
+            bail!("the WATERMARK expression must be a timestamp");
+        }
+
+        Ok(Some(expression))
+    }
+
+    /// Compiles an expression that may refer to any of the table's fields, including virtual ones
+    fn compile_field_expression(
+        table: &ConnectorTable,
+        sql: &str,
+        schema_provider: &ArroyoSchemaProvider,
+    ) -> Result<Expression> {
+        let input_struct = StructDef::for_fields(
+            table
+                .fields
+                .iter()
+                .map(|f| f.struct_field().clone())
+                .collect(),
+        );
+
+        let schema = DFSchema::new_with_metadata(
+            input_struct
+                .fields
+                .iter()
+                .map(|f| {
+                    let TypeDef::DataType(data_type, nullable) = f.data_type.clone() else {
+                        bail!("expressions can't refer to struct fields")
+                    };
+                    Ok(DFField::new_unqualified(&f.name, data_type, nullable))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            HashMap::new(),
+        )?;
+
+        let expr = Parser::new(&PostgreSqlDialect {})
+            .try_with_sql(sql)?
+            .parse_expr()?;
+
+        let df_expr = SqlToRel::new(schema_provider).sql_to_expr(
+            expr,
+            &schema,
+            &mut PlannerContext::default(),
+        )?;
+
+        ExpressionContext {
+            input_struct: &input_struct,
+            schema_provider,
+        }
+        .compile_expr(&df_expr)
+    }
+
+    /// Reads the `lookup.cache.*` options, which configure how lookup joins against the table
+    /// cache the rows they fetch
+    fn lookup_cache(
+        table: &ConnectorTable,
+        max_entries: Option<String>,
+        ttl: Option<String>,
+    ) -> Result<LookupCacheConfig> {
+        if (max_entries.is_some() || ttl.is_some())
+            && !matches!(table.connection_type, ConnectionType::Lookup)
+        {
+            bail!("the lookup cache can only be configured on lookup tables");
+        }
+
+        let default = LookupCacheConfig::default();
+        Ok(LookupCacheConfig {
+            max_entries: max_entries
+                .map(|n| u64::from_str(&n))
+                .transpose()
+                .map_err(|_| anyhow!("'lookup.cache.max_entries' must be a number"))?
+                .unwrap_or(default.max_entries),
+            ttl: ttl
+                .map(|ttl| parse_ttl(&ttl))
+                .transpose()?
+                .unwrap_or(default.ttl),
+        })
+    }
+
     pub fn try_from_statement(
         statement: &Statement,
         schema_provider: &ArroyoSchemaProvider,
@@ -581,6 +953,11 @@ impl Table {
 
             let connector = with_map.remove("connector");
             let fields = Self::schema_from_columns(columns, schema_provider)?;
+            let dedup_key = with_map.remove("dedup.key");
+            let dedup_ttl = with_map.remove("dedup.ttl");
+            let cache_max_entries = with_map.remove("lookup.cache.max_entries");
+            let cache_ttl = with_map.remove("lookup.cache.ttl");
+            let watermark_expression = with_map.remove("watermark_expression");
 
             match connector.as_ref().map(|c| c.as_str()) {
                 Some("memory") | None => {
@@ -604,10 +981,33 @@ impl Table {
                             .collect(),
                     }))
                 }
-                Some(connector) => Ok(Some(Table::ConnectorTable(
-                    ConnectorTable::from_options(&name, connector, fields, &mut with_map)
-                        .map_err(|e| anyhow!("Failed to construct table '{}': {:?}", name, e))?,
-                ))),
+                Some(connector) => {
+                    let mut table =
+                        ConnectorTable::from_options(&name, connector, fields, &mut with_map)
+                            .map_err(|e| {
+                                anyhow!("Failed to construct table '{}': {:?}", name, e)
+                            })?;
+
+                    table.deduplication =
+                        Self::deduplication(&table, dedup_key, dedup_ttl, schema_provider)
+                            .map_err(|e| {
+                                anyhow!("Invalid deduplication for table '{}': {:?}", name, e)
+                            })?;
+
+                    table.watermark_expression = Self::watermark_expression(
+                        &table,
+                        watermark_expression,
+                        schema_provider,
+                    )
+                    .map_err(|e| anyhow!("Invalid watermark for table '{}': {:?}", name, e))?;
+
+                    table.lookup_cache = Self::lookup_cache(&table, cache_max_entries, cache_ttl)
+                        .map_err(|e| {
+                        anyhow!("Invalid lookup cache for table '{}': {:?}", name, e)
+                    })?;
+
+                    Ok(Some(Table::ConnectorTable(table)))
+                }
             }
         } else {
             match &produce_optimized_plan(statement, schema_provider)? {
@@ -633,12 +1033,13 @@ impl Table {
         match self {
             Table::MemoryTable { name, .. } | Table::TableFromQuery { name, .. } => name.as_str(),
             Table::ConnectorTable(c) => c.name.as_str(),
+            Table::MatchRecognize { clause, .. } => clause.name.as_str(),
         }
     }
 
     pub fn get_fields(&self) -> Result<Vec<Field>> {
         match self {
-            Table::MemoryTable { fields, .. } => fields
+            Table::MemoryTable { fields, .. } | Table::MatchRecognize { fields, .. } => fields
                 .iter()
                 .map(|field| {
                     let field: Field = field.clone().into();
@@ -680,6 +1081,7 @@ impl Table {
             Table::TableFromQuery { logical_plan, .. } => {
                 builder.insert_sql_plan(&logical_plan.clone())
             }
+            Table::MatchRecognize { clause, .. } => clause.as_sql_source(builder),
         }
     }
 
@@ -689,7 +1091,8 @@ impl Table {
             Table::MemoryTable { name, .. } => {
                 Ok(SqlOperator::NamedTable(name.clone(), Box::new(input)))
             }
-            Table::TableFromQuery { .. } => todo!(),
+            Table::TableFromQuery { name, .. } => bail!("can't insert into view {}", name),
+            Table::MatchRecognize { .. } => bail!("can't insert into a MATCH_RECOGNIZE clause"),
         }
     }
 }
@@ -727,4 +1130,12 @@ impl Insert {
             _ => Ok(Insert::Anonymous { logical_plan }),
         }
     }
+
+    pub fn logical_plan(&self) -> &LogicalPlan {
+        match self {
+            Insert::InsertQuery { logical_plan, .. } | Insert::Anonymous { logical_plan } => {
+                logical_plan
+            }
+        }
+    }
 }
@@ -1,10 +1,17 @@
+use std::time::Duration;
+
 use arrow_schema::DataType;
 use arroyo_connectors::{
     nexmark::{NexmarkConnector, NexmarkTable},
     Connector, EmptyConfig,
 };
+use arroyo_datastream::{
+    Operator, PeriodicWatermark, Program, SlidingWindowAggregator, TumblingWindowAggregator,
+    WatermarkStrategy,
+};
+use arroyo_rpc::{OperatorConfig, WatermarkAlignment};
 
-use crate::{parse_and_get_program, types::TypeDef, ArroyoSchemaProvider, SqlConfig};
+use crate::{explain, parse_and_get_program, types::TypeDef, ArroyoSchemaProvider, SqlConfig};
 
 #[tokio::test]
 async fn test_parse() {
@@ -117,6 +124,281 @@ async fn test_window_function() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_window_function_lead() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT *, LEAD(count, 1, 0) OVER (
+        PARTITION BY window
+        ORDER BY count DESC) as next_count
+    FROM (SELECT count(*) as count,
+        tumble(interval '10 seconds') as window
+            FROM nexmark
+            group by window)";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_over_window_functions() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT bid.auction, bid.price,
+        ROW_NUMBER() OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as bid_number,
+        LAG(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as previous_price,
+        SUM(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime
+            ROWS BETWEEN 10 PRECEDING AND CURRENT ROW) as recent_total,
+        AVG(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime
+            RANGE BETWEEN INTERVAL '1' MINUTE PRECEDING AND CURRENT ROW) as minute_average
+    FROM nexmark WHERE bid is not null";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_over_window_requires_bounded_frame() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT bid.auction,
+        SUM(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as total
+    FROM nexmark WHERE bid is not null";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "window functions that aren't partitioned by a window must have a bounded frame, like ROWS BETWEEN 10 PRECEDING AND CURRENT ROW"
+    );
+}
+
+#[tokio::test]
+async fn test_time_series_functions() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT bid.auction,
+        rate(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as price_rate,
+        counter_delta(bid.price) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as delta,
+        ewma(bid.price, 0.2) OVER (PARTITION BY bid.auction ORDER BY bid.datetime) as average
+    FROM nexmark WHERE bid is not null";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_time_series_functions_require_time_order() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "SELECT bid.auction,
+        ewma(bid.price, 0.2) OVER (PARTITION BY bid.auction ORDER BY bid.price) as average
+    FROM nexmark WHERE bid is not null";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ewma() must be ordered by a timestamp ascending, as in ewma(..) OVER (PARTITION BY .. ORDER BY event_time)"
+    );
+}
+
+#[tokio::test]
+async fn test_match_recognize() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+
+    SELECT * FROM bids MATCH_RECOGNIZE (
+        PARTITION BY auction
+        ORDER BY datetime
+        MEASURES
+            FIRST(A.price) AS start_price,
+            LAST(B.price) AS last_low_price,
+            C.price AS spike_price,
+            C.datetime AS spike_time
+        ONE ROW PER MATCH
+        AFTER MATCH SKIP PAST LAST ROW
+        PATTERN (A B* C)
+        WITHIN INTERVAL '5' MINUTE
+        DEFINE
+            A AS A.price < 1000,
+            B AS price < 10000,
+            C AS C.price >= 10000
+    ) AS m WHERE spike_price > 20000";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_match_recognize_requires_within() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+
+    SELECT * FROM bids MATCH_RECOGNIZE (
+        PARTITION BY auction
+        ORDER BY datetime
+        MEASURES LAST(B.price) AS price
+        PATTERN (A B+)
+        DEFINE A AS price < 1000, B AS price >= 1000
+    )";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "MATCH_RECOGNIZE patterns with unbounded quantifiers like + and * must have a WITHIN \
+        interval, which bounds how long partial matches are kept"
+    );
+}
+
+#[tokio::test]
+async fn test_interval_join() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+    CREATE VIEW auctions AS
+        SELECT auction.id as id, auction.datetime as datetime
+        FROM nexmark WHERE auction is not null;
+
+    SELECT a.id, b.price FROM auctions a JOIN bids b
+        ON a.id = b.auction
+        AND b.datetime BETWEEN a.datetime AND a.datetime + INTERVAL '10' MINUTE";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_interval_join_requires_both_bounds() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+    CREATE VIEW auctions AS
+        SELECT auction.id as id, auction.datetime as datetime
+        FROM nexmark WHERE auction is not null;
+
+    SELECT a.id, b.price FROM auctions a JOIN bids b
+        ON a.id = b.auction AND b.datetime >= a.datetime";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("interval joins require both a lower and an upper bound"));
+}
+
+#[tokio::test]
+async fn test_unnest_join() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.url as url
+        FROM nexmark WHERE bid is not null;
+
+    SELECT b.auction, p.part FROM bids b
+        CROSS JOIN UNNEST(extract_json(b.url, '$.parts[*]')) AS p(part)";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_lateral_join() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = "CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.url as url
+        FROM nexmark WHERE bid is not null;
+
+    SELECT b.auction, l.name FROM bids b, LATERAL (
+        SELECT extract_json_string(p.part, '$.name') AS name
+        FROM UNNEST(extract_json(b.url, '$.parts[*]')) AS p(part)
+        WHERE p.part IS NOT NULL
+    ) l";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_lookup_join() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = r#"CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+    CREATE TABLE auction_details (
+        id BIGINT,
+        category TEXT
+    ) WITH (
+        connector = 'redis',
+        address = 'redis://localhost:6379',
+        type = 'lookup',
+        format = 'json',
+        "lookup.key_prefix" = 'auction:',
+        "lookup.cache.ttl" = '5 minutes'
+    );
+
+    SELECT b.price, d.category FROM bids b
+        LEFT JOIN auction_details FOR SYSTEM_TIME AS OF b.datetime AS d
+        ON b.auction = d.id"#;
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_lookup_join_requires_system_time() {
+    let schema_provider = get_test_schema_provider();
+
+    let sql = r#"CREATE VIEW bids AS
+        SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+        FROM nexmark WHERE bid is not null;
+    CREATE TABLE auction_details (
+        id BIGINT,
+        category TEXT
+    ) WITH (
+        connector = 'redis',
+        address = 'redis://localhost:6379',
+        type = 'lookup',
+        format = 'json',
+        "lookup.key_prefix" = 'auction:'
+    );
+
+    SELECT b.price, d.category FROM bids b
+        JOIN auction_details d ON b.auction = d.id"#;
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("must be joined with FOR SYSTEM_TIME AS OF"));
+}
+
 #[tokio::test]
 async fn test_no_updating_window_functions() {
     let schema_provider = get_test_schema_provider();
@@ -127,8 +409,228 @@ async fn test_no_updating_window_functions() {
         .unwrap_err();
     assert_eq!(
         err.to_string(),
-        "window function must be partitioned by a window as the first argument"
+        "window functions that aren't partitioned by a window must be ordered by the event time, ascending"
+    );
+}
+
+#[tokio::test]
+async fn test_top_n() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE VIEW bids AS
+    SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime
+    FROM nexmark WHERE bid is not null;
+
+    SELECT * FROM (
+        SELECT *, ROW_NUMBER() OVER (PARTITION BY auction ORDER BY price DESC) as row_num
+        FROM bids) WHERE row_num <= 3";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_top_n_requires_limit() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT * FROM (
+        SELECT *, row_number() OVER (partition by bid.auction order by bid.price desc) as row_num
+        FROM nexmark where bid is not null) WHERE row_num > 3";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("must be filtered to the first N rows"));
+}
+
+#[tokio::test]
+async fn test_updating_count_distinct() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT bid.auction, count(distinct bid.bidder) as bidders
+    FROM nexmark WHERE bid is not null GROUP BY 1";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_approx_count_distinct() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE VIEW sketches AS
+    SELECT bid.auction as auction, tumble(interval '1 minute') as window,
+      approx_count_distinct(bid.bidder) as bidders,
+      hll_sketch_agg(bid.bidder) as sketch
+    FROM nexmark WHERE bid is not null GROUP BY 1, 2;
+
+    SELECT hop(interval '1 minute', interval '10 minutes') as window,
+      hll_sketch_estimate(hll_union_agg(sketch)) as bidders
+    FROM sketches GROUP BY 1";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_approx_percentile() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT hop(interval '1 minute', interval '10 minutes') as window,
+      approx_percentile_cont(bid.price, 0.99) as p99,
+      approx_median(bid.price) as median
+    FROM nexmark WHERE bid is not null GROUP BY 1";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_approx_percentile_requires_literal() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT tumble(interval '1 minute') as window,
+      approx_percentile_cont(bid.price, 1.5) as p
+    FROM nexmark WHERE bid is not null GROUP BY 1";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("must be a literal between 0 and 1"));
+}
+
+#[tokio::test]
+async fn test_json_functions() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE events (
+        id BIGINT NOT NULL,
+        payload JSON,
+        metadata JSONB NOT NULL
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json'
+    );
+
+    SELECT id, json_value(payload, '$.user.name') as name,
+        json_query(metadata, '$.tags') as tags,
+        json_extract(payload, '$.items[0]') as first_item,
+        unnest(json_array_elements(json_query(payload, '$.items'))) as item
+    FROM events
+    WHERE is_json(payload)";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_array_agg() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT hop(interval '1 minute', interval '10 minutes') as window,
+      bid.auction as auction,
+      array_agg(bid.price) as prices,
+      cardinality(array_agg(bid.bidder)) as bids
+    FROM nexmark WHERE bid is not null GROUP BY 1, 2";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_array_agg_over_updating_input() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "SELECT count, array_agg(auction) FROM (
+      SELECT bid.auction as auction, count(*) as count FROM nexmark GROUP BY 1
+    ) GROUP BY 1";
+
+    let err = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("array_agg()"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_map_functions() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE events (
+        id BIGINT NOT NULL,
+        counts MAP(TEXT, BIGINT) NOT NULL,
+        attributes MAP(INT, TEXT),
+        labels TEXT[]
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json'
+    );
+
+    SELECT id, map_keys(counts) as keys, map_values(attributes) as values,
+        element_at(counts, 'clicks') as clicks,
+        cardinality(counts) as count_entries,
+        cardinality(labels) as label_count,
+        labels[1] as first_label
+    FROM events";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_map_declaration() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE events (
+        attributes MAP(DOUBLE, TEXT)
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json'
+    );
+
+    SELECT attributes FROM events";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_decimals() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "CREATE TABLE trades (
+        symbol TEXT NOT NULL,
+        price DECIMAL(10, 2) NOT NULL,
+        quantity BIGINT NOT NULL,
+        fee DECIMAL(6, 4)
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'trades',
+        format = 'json'
     );
+
+    SELECT symbol, tumble(interval '1 minute') as window,
+        sum(price * quantity) as notional,
+        avg(price) as average_price,
+        max(price - fee) as max_net_price,
+        sum(CAST(fee AS DECIMAL(12, 2))) as fees,
+        CAST(min(price) AS DOUBLE) as min_price
+    FROM trades
+    GROUP BY 1, 2";
+
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
 }
 
 #[tokio::test]
@@ -185,6 +687,254 @@ async fn test_no_inserting_updates_into_non_updating() {
         .unwrap_err();
 }
 
+#[tokio::test]
+async fn test_upsert_sink() {
+    let schema_provider = get_test_schema_provider();
+    let sql = r#"CREATE table sink (
+        auction bigint,
+        count bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'sink',
+        topic = 'sink',
+        format = 'json',
+        "sink.mode" = 'upsert',
+        "sink.key_field" = 'auction'
+      );
+
+      INSERT into sink
+      SELECT bid.auction, count(*) FROM nexmark GROUP BY 1"#;
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_sink_mode_validation() {
+    let sink = |options: &str| {
+        format!(
+            r#"CREATE table sink (
+                auction bigint,
+                count bigint
+              ) WITH (
+                connector = 'kafka',
+                bootstrap_servers = 'localhost:9092',
+                type = 'sink',
+                topic = 'sink',
+                {}
+              );
+
+              INSERT into sink
+              SELECT bid.auction, count(*) FROM nexmark GROUP BY 1"#,
+            options
+        )
+    };
+
+    for (options, error) in [
+        (
+            "format = 'json'",
+            "set 'sink.mode' to 'upsert' or 'retract'",
+        ),
+        (
+            r#"format = 'json', "sink.mode" = 'append'"#,
+            "does not support update messages",
+        ),
+        (
+            r#"format = 'json', "sink.mode" = 'upsert'"#,
+            "must set 'sink.key_field'",
+        ),
+        (
+            r#"format = 'json', "sink.mode" = 'retract'"#,
+            "must use the 'debezium_json' format",
+        ),
+        (
+            r#"format = 'debezium_json', "sink.mode" = 'upsert'"#,
+            "must use the 'retract' sink mode",
+        ),
+    ] {
+        let err = parse_and_get_program(
+            &sink(options),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains(error), "{}: {}", options, err);
+    }
+
+    parse_and_get_program(
+        &sink(r#"format = 'debezium_json', "sink.mode" = 'retract'"#),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_source_deduplication() {
+    let schema_provider = get_test_schema_provider();
+    let sql = r#"CREATE table events (
+        id text,
+        kind text,
+        value int
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json',
+        "dedup.key" = 'concat(id, kind)',
+        "dedup.ttl" = '10 minutes'
+      );
+      SELECT * FROM events"#;
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_deduplication_requires_ttl() {
+    let schema_provider = get_test_schema_provider();
+    let sql = r#"CREATE table events (
+        id text
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'events',
+        format = 'json',
+        "dedup.key" = 'id'
+      );
+      SELECT * FROM events"#;
+    let _ = parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_watermark_options() {
+    let source = |options: &str| {
+        format!(
+            r#"CREATE table events (
+                id text,
+                ts timestamp
+              ) WITH (
+                connector = 'kafka',
+                bootstrap_servers = 'localhost:9092',
+                type = 'source',
+                topic = 'events',
+                format = 'json',
+                {}
+              );
+              SELECT * FROM events"#,
+            options
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &source(
+            r#""watermark.max_out_of_orderness" = '5 seconds',
+            "watermark.interval" = '500ms',
+            "watermark.idle_timeout" = '30 seconds'"#,
+        ),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(program.graph.node_weights().any(|node| node.operator
+        == Operator::Watermark(PeriodicWatermark {
+            period: Duration::from_millis(500),
+            idle_time: Some(Duration::from_secs(30)),
+            strategy: WatermarkStrategy::FixedLateness {
+                max_lateness: Duration::from_secs(5),
+            },
+        })));
+
+    let (program, _) = parse_and_get_program(
+        &source(r#""watermark.per_partition" = 'true'"#),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(program.graph.node_weights().any(|node| matches!(
+        &node.operator,
+        Operator::Watermark(PeriodicWatermark {
+            strategy: WatermarkStrategy::Source,
+            ..
+        })
+    )));
+
+    let (program, _) = parse_and_get_program(
+        &source(
+            r#""watermark.per_partition" = 'true',
+            "watermark.alignment.group" = 'backfill',
+            "watermark.alignment.max_drift" = '5 minutes'"#,
+        ),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    let config = program
+        .graph
+        .node_weights()
+        .find_map(|node| match &node.operator {
+            Operator::ConnectorSource(op) => {
+                Some(serde_json::from_str::<OperatorConfig>(&op.config).unwrap())
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(
+        config.partition_watermarks.unwrap().alignment,
+        Some(WatermarkAlignment {
+            group: "backfill".to_string(),
+            max_drift_micros: 300_000_000,
+        })
+    );
+
+    for (options, error) in [
+        (
+            r#""watermark.per_partition" = 'true', event_time_field = 'ts'"#,
+            "per-partition watermarks",
+        ),
+        (
+            r#""watermark.max_out_of_orderness" = '5 seconds', watermark_field = 'ts'"#,
+            "set by a column",
+        ),
+        (
+            r#""watermark.idle_timeout" = '1 minute', idle_micros = '1000'"#,
+            "only one of",
+        ),
+        (
+            r#""watermark.alignment.max_drift" = '5 minutes'"#,
+            "requires 'watermark.per_partition'",
+        ),
+        (
+            r#""watermark.per_partition" = 'true', "watermark.alignment.group" = 'a'"#,
+            "requires 'watermark.alignment.max_drift'",
+        ),
+    ] {
+        let err = parse_and_get_program(
+            &source(options),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            format!("{:?}", err).contains(error),
+            "{}: {:?}",
+            options,
+            err
+        );
+    }
+}
+
 #[tokio::test]
 async fn test_no_aggregates_in_window() {
     let schema_provider = get_test_schema_provider();
@@ -217,3 +967,642 @@ async fn test_udf() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_explain() {
+    let schema_provider = get_test_schema_provider();
+    let sql = "EXPLAIN ANALYZE SELECT bid.auction, count(*) FROM nexmark
+        GROUP BY 1, TUMBLE(INTERVAL '1' MINUTE)";
+    let explanation = explain(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    assert!(explanation.analyze);
+    assert_eq!(explanation.logical_plans.len(), 1);
+    assert!(explanation.logical_plans[0].contains("Aggregate"));
+    assert!(explanation
+        .program
+        .graph
+        .node_weights()
+        .any(|node| node.operator.state_description().is_some()));
+
+    let schema_provider = get_test_schema_provider();
+    let explanation = explain(
+        "SELECT bid.auction FROM nexmark",
+        schema_provider,
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(!explanation.analyze);
+}
+
+#[tokio::test]
+async fn test_statement_set_shares_computation() {
+    let tables = "CREATE TABLE counts_a (
+        auction bigint,
+        count bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'sink',
+        topic = 'a',
+        format = 'json'
+      );
+      CREATE TABLE counts_b (
+        auction bigint,
+        count bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'sink',
+        topic = 'b',
+        format = 'json'
+      );";
+    let insert = |sink: &str, min: i64| {
+        format!(
+            "INSERT INTO {} SELECT auction, count FROM (
+              SELECT bid.auction AS auction, count(*) AS count FROM nexmark WHERE bid IS NOT NULL
+              GROUP BY 1, TUMBLE(INTERVAL '1' MINUTE)
+            ) WHERE count > {};",
+            sink, min
+        )
+    };
+    let stateful_operators = |program: &Program| {
+        program
+            .graph
+            .node_weights()
+            .filter(|node| node.operator.state_description().is_some())
+            .count()
+    };
+
+    let (single, _) = parse_and_get_program(
+        &format!("{}\n{}", tables, insert("counts_a", 10)),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let (set, _) = parse_and_get_program(
+        &format!(
+            "{}\nEXECUTE STATEMENT SET BEGIN\n{}\n{}\nEND;",
+            tables,
+            insert("counts_a", 10),
+            insert("counts_b", 100)
+        ),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(stateful_operators(&set), stateful_operators(&single));
+    assert_eq!(
+        set.graph
+            .node_weights()
+            .filter(|node| matches!(node.operator, Operator::ConnectorSink(_)))
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn test_views() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider
+        .add_view(
+            "bids",
+            "SELECT bid.auction AS auction, bid.price AS price FROM nexmark WHERE bid IS NOT NULL",
+        )
+        .unwrap();
+    schema_provider
+        .add_view("expensive_bids", "SELECT * FROM bids WHERE price > 1000")
+        .unwrap();
+    assert!(schema_provider
+        .add_view("missing", "SELECT * FROM not_a_table")
+        .is_err());
+
+    let sql = "CREATE TEMPORARY VIEW counts AS
+        SELECT auction, count(*) AS count FROM expensive_bids
+        GROUP BY 1, TUMBLE(INTERVAL '1' MINUTE);
+      SELECT * FROM counts WHERE count > 10";
+    parse_and_get_program(sql, schema_provider.clone(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "INSERT INTO bids SELECT bid.auction, bid.price FROM nexmark";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_watermark_declaration() {
+    let sql = "CREATE TABLE orders (
+        id BIGINT,
+        price BIGINT,
+        ts TIMESTAMP,
+        total AS price * 2,
+        WATERMARK FOR ts AS ts - INTERVAL '5' SECOND
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json'
+    );
+
+    SELECT count(*), sum(total) FROM orders GROUP BY TUMBLE(INTERVAL '1' MINUTE)";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let sql = "CREATE TABLE orders (
+        id BIGINT,
+        WATERMARK FOR id AS id - 5
+    ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'source',
+        topic = 'orders',
+        format = 'json'
+    );
+
+    SELECT * FROM orders";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_grouping_sets() {
+    let stateful_operators = |program: &Program| {
+        program
+            .graph
+            .node_weights()
+            .filter(|node| node.operator.state_description().is_some())
+            .count()
+    };
+
+    let query = |group_by: &str| {
+        format!(
+            "SELECT bid.auction, bid.bidder, count(*), sum(bid.price) FROM nexmark
+            WHERE bid IS NOT NULL
+            GROUP BY TUMBLE(INTERVAL '1' MINUTE), {}",
+            group_by
+        )
+    };
+
+    let (single, _) = parse_and_get_program(
+        &query("bid.auction, bid.bidder"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    for group_by in [
+        "ROLLUP(bid.auction, bid.bidder)",
+        "CUBE(bid.auction, bid.bidder)",
+        "GROUPING SETS ((bid.auction), (bid.bidder), ())",
+    ] {
+        let (program, _) = parse_and_get_program(
+            &query(group_by),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        // every grouping set is computed by the same aggregation
+        assert_eq!(
+            stateful_operators(&program),
+            stateful_operators(&single),
+            "{}",
+            group_by
+        );
+    }
+
+    let sql = "SELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+        GROUP BY ROLLUP(TUMBLE(INTERVAL '1' MINUTE), bid.auction)";
+    parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_window_alignment() {
+    for window in [
+        "TUMBLE(INTERVAL '1' DAY, 'America/New_York')",
+        "TUMBLE(INTERVAL '1' DAY, INTERVAL '6' HOUR, 'Europe/Berlin')",
+        "TUMBLE(INTERVAL '1' HOUR, INTERVAL '15' MINUTE)",
+        "HOP(INTERVAL '1' MINUTE, INTERVAL '5' MINUTE, INTERVAL '30' SECOND)",
+    ] {
+        let sql = format!(
+            "SELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+            GROUP BY {}, bid.auction",
+            window
+        );
+        let (program, _) =
+            parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+                .await
+                .unwrap();
+
+        assert!(
+            program.graph.node_weights().any(|node| matches!(
+                &node.operator,
+                Operator::TumblingWindowAggregator(TumblingWindowAggregator { alignment, .. })
+                    | Operator::SlidingWindowAggregator(SlidingWindowAggregator { alignment, .. })
+                    if !alignment.is_default()
+            )),
+            "{}",
+            window
+        );
+    }
+
+    for window in [
+        "TUMBLE(INTERVAL '1' DAY, 'Mars/Olympus_Mons')",
+        "TUMBLE(INTERVAL '1' HOUR, INTERVAL '1' HOUR)",
+        "HOP(INTERVAL '1' MINUTE, INTERVAL '5' MINUTE, 'America/New_York')",
+    ] {
+        let sql = format!(
+            "SELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+            GROUP BY {}, bid.auction",
+            window
+        );
+        parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+            .await
+            .unwrap_err();
+    }
+}
+
+#[tokio::test]
+async fn test_allowed_lateness() {
+    let late_bids = "CREATE TABLE late_bids (
+        auction bigint,
+        price bigint
+      ) WITH (
+        connector = 'kafka',
+        bootstrap_servers = 'localhost:9092',
+        type = 'sink',
+        topic = 'late_bids',
+        format = 'json'
+      );";
+    let query = |window: &str| {
+        format!(
+            "{}\nSELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+            GROUP BY {}, bid.auction",
+            late_bids, window
+        )
+    };
+
+    let (program, _) = parse_and_get_program(
+        &query("TUMBLE(INTERVAL '1' MINUTE, allowed_lateness(INTERVAL '30' SECOND), late_data_sink('late_bids'))"),
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    assert!(program.graph.node_weights().any(|node| matches!(
+        &node.operator,
+        Operator::TumblingWindowAggregator(TumblingWindowAggregator { allowed_lateness, .. })
+            if *allowed_lateness == Duration::from_secs(30)
+    )));
+    assert!(program.graph.node_weights().any(|node| matches!(
+        &node.operator,
+        Operator::LateDataFilter { allowed_lateness, .. }
+            if *allowed_lateness == Duration::from_secs(30)
+    )));
+    // the late rows are written to their own sink, and the results to the web sink
+    assert_eq!(
+        program
+            .graph
+            .node_weights()
+            .filter(|node| matches!(node.operator, Operator::ConnectorSink(_)))
+            .count(),
+        2
+    );
+
+    for window in [
+        "TUMBLE(INTERVAL '1' MINUTE, late_data_sink('missing'))",
+        "TUMBLE(INTERVAL '1' MINUTE, late_data_sink('nexmark'))",
+        "HOP(INTERVAL '1' MINUTE, INTERVAL '5' MINUTE, allowed_lateness(INTERVAL '30' SECOND))",
+    ] {
+        parse_and_get_program(
+            &query(window),
+            get_test_schema_provider(),
+            SqlConfig::default(),
+        )
+        .await
+        .unwrap_err();
+    }
+}
+
+#[tokio::test]
+async fn test_emit_partial() {
+    let sql = "SELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+        GROUP BY TUMBLE(INTERVAL '1' HOUR, emit_partial(INTERVAL '10' SECOND)), bid.auction";
+    let (program, _) = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    assert!(program.graph.node_weights().any(|node| matches!(
+        &node.operator,
+        Operator::TumblingWindowAggregator(TumblingWindowAggregator { emit_partial, .. })
+            if *emit_partial == Some(Duration::from_secs(10))
+    )));
+
+    for window in [
+        "TUMBLE(INTERVAL '1' HOUR, emit_partial(INTERVAL '0' SECOND))",
+        "HOP(INTERVAL '1' MINUTE, INTERVAL '5' MINUTE, emit_partial(INTERVAL '10' SECOND))",
+    ] {
+        let sql = format!(
+            "SELECT bid.auction, count(*) FROM nexmark WHERE bid IS NOT NULL
+            GROUP BY {}, bid.auction",
+            window
+        );
+        parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+            .await
+            .unwrap_err();
+    }
+}
+
+#[tokio::test]
+async fn test_accumulator_udaf() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider
+        .add_rust_udf(
+            "#[derive(Clone, Debug, Default, PartialEq, bincode::Encode, bincode::Decode)]
+            struct GeoMean {
+                log_sum: f64,
+                count: i64,
+            }
+
+            impl GeoMean {
+                fn accumulate(&mut self, value: i64) {
+                    self.log_sum += (value as f64).ln();
+                    self.count += 1;
+                }
+
+                fn merge(&mut self, other: &Self) {
+                    self.log_sum += other.log_sum;
+                    self.count += other.count;
+                }
+
+                fn retract(&mut self, other: &Self) {
+                    self.log_sum -= other.log_sum;
+                    self.count -= other.count;
+                }
+
+                fn finish(&self) -> Option<f64> {
+                    (self.count > 0).then(|| (self.log_sum / self.count as f64).exp())
+                }
+            }",
+        )
+        .unwrap();
+
+    let def = schema_provider.udf_defs.get("geo_mean").unwrap();
+    assert_eq!(def.ret, TypeDef::DataType(DataType::Float64, true));
+    assert!(def.accumulator.as_ref().unwrap().retractable);
+
+    for window in [
+        "TUMBLE(INTERVAL '1' MINUTE)",
+        "HOP(INTERVAL '10' SECOND, INTERVAL '1' MINUTE)",
+    ] {
+        let sql = format!(
+            "SELECT bid.auction, geo_mean(bid.price) FROM nexmark WHERE bid IS NOT NULL
+            GROUP BY {}, bid.auction",
+            window
+        );
+        let (program, _) =
+            parse_and_get_program(&sql, schema_provider.clone(), SqlConfig::default())
+                .await
+                .unwrap();
+
+        // accumulators let the UDAF be computed incrementally
+        assert!(
+            program.graph.node_weights().any(|node| matches!(
+                node.operator,
+                Operator::TumblingWindowAggregator(_) | Operator::SlidingWindowAggregator(_)
+            )),
+            "{}",
+            window
+        );
+    }
+
+    // without merge(), partial accumulators can't be combined
+    get_test_schema_provider()
+        .add_rust_udf(
+            "#[derive(Clone, Debug, Default, PartialEq, bincode::Encode, bincode::Decode)]
+            struct Total(i64);
+
+            impl Total {
+                fn accumulate(&mut self, value: i64) {
+                    self.0 += value;
+                }
+
+                fn finish(&self) -> i64 {
+                    self.0
+                }
+            }",
+        )
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_async_udf() {
+    let mut schema_provider = get_test_schema_provider();
+    schema_provider
+        .add_rust_udf(
+            "#[udf(ordered, max_concurrency = 10, timeout_ms = 500, retries = 2)]
+            async fn slow_square(x: i64) -> i64 {
+                x * x
+            }",
+        )
+        .unwrap();
+
+    let def = schema_provider.udf_defs.get("slow_square").unwrap();
+    let config = def.async_config.as_ref().unwrap();
+    assert!(config.ordered);
+    assert_eq!(config.max_concurrency, 10);
+    assert_eq!(config.timeout, std::time::Duration::from_millis(500));
+    assert_eq!(config.retries, 2);
+    assert!(!def.def.contains("udf"));
+
+    let sql = "SELECT bid.auction, slow_square(bid.price) + 1 as squared, slow_square(bid.price)
+        FROM nexmark WHERE bid IS NOT NULL";
+    let (program, _) = parse_and_get_program(sql, schema_provider.clone(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let async_udfs: Vec<_> = program
+        .graph
+        .node_weights()
+        .filter_map(|node| match &node.operator {
+            Operator::AsyncUdf(async_udf) => Some(async_udf),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(async_udfs.len(), 1);
+    assert!(async_udfs[0].ordered);
+    assert_eq!(async_udfs[0].retries, 2);
+
+    for sql in [
+        // async UDFs can only be called in the SELECT list
+        "SELECT bid.auction FROM nexmark WHERE slow_square(bid.price) > 100",
+        // and only one per SELECT
+        "SELECT slow_square(bid.price), slow_square(bid.auction) FROM nexmark",
+    ] {
+        parse_and_get_program(sql, schema_provider.clone(), SqlConfig::default())
+            .await
+            .unwrap_err();
+    }
+
+    // options are only for async UDFs
+    get_test_schema_provider()
+        .add_rust_udf(
+            "#[udf(ordered)]
+            fn square(x: i64) -> i64 {
+                x * x
+            }",
+        )
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_wasm_udfs() {
+    let mut schema_provider = get_test_schema_provider();
+    // exports add_one(i64) -> i64, and spin(i64) -> i64
+    schema_provider
+        .add_wasm_udfs(
+            "AGFzbQEAAAABBgFgAX4BfgMDAgAABxICB2FkZF9vbmUAAARzcGluAAEKEgIHACAAQgF8CwgAA0AMAAsACw==",
+        )
+        .unwrap();
+
+    let def = schema_provider.udf_defs.get("add_one").unwrap();
+    assert_eq!(def.args, vec![TypeDef::DataType(DataType::Int64, false)]);
+    // the result is null if the call fails
+    assert_eq!(def.ret, TypeDef::DataType(DataType::Int64, true));
+    assert!(schema_provider.udf_defs.contains_key("spin"));
+
+    let sql = "SELECT add_one(bid.price) FROM nexmark WHERE bid IS NOT NULL";
+    parse_and_get_program(sql, schema_provider, SqlConfig::default())
+        .await
+        .unwrap();
+
+    // modules can't import functions from the host
+    get_test_schema_provider()
+        .add_wasm_udfs("AGFzbQEAAAABBgFgAX4BfgIJAQNlbnYBZgAA")
+        .unwrap_err();
+    get_test_schema_provider()
+        .add_wasm_udfs("not a module")
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn test_stable_operator_ids() {
+    let plan = |extra_column: &str| {
+        let sql = format!(
+            "CREATE VIEW bids AS
+                SELECT bid.auction as auction, bid.price as price, bid.datetime as datetime{0}
+                FROM nexmark WHERE bid is not null;
+            CREATE VIEW auctions AS
+                SELECT auction.id as id, auction.datetime as datetime
+                FROM nexmark WHERE auction is not null;
+
+            SELECT a.id, b.price{0} FROM auctions a JOIN bids b
+                ON a.id = b.auction
+                AND b.datetime BETWEEN a.datetime AND a.datetime + INTERVAL '10' MINUTE",
+            extra_column
+        );
+        async move {
+            parse_and_get_program(&sql, get_test_schema_provider(), SqlConfig::default())
+                .await
+                .unwrap()
+                .0
+        }
+    };
+
+    let join_schema = |program: &Program| {
+        program
+            .state_schemas
+            .iter()
+            .find(|(id, _)| id.starts_with("interval_join"))
+            .map(|(id, schema)| (id.clone(), schema.clone()))
+            .unwrap()
+    };
+
+    let old = plan("").await;
+    let new = plan(", bid.channel as channel").await;
+
+    // the join keeps its id when a column is added to one of its inputs
+    let (old_id, old_schema) = join_schema(&old);
+    let (new_id, new_schema) = join_schema(&new);
+    assert_eq!(old_id, new_id);
+
+    // and only the state of that input has changed
+    assert_eq!(old_schema.key, new_schema.key);
+    assert_eq!(old_schema.tables["l"], new_schema.tables["l"]);
+    assert_ne!(old_schema.tables["r"], new_schema.tables["r"]);
+
+    // planning is deterministic
+    let ids = |program: &Program| {
+        let mut ids: Vec<_> = program
+            .graph
+            .node_weights()
+            .map(|n| n.operator_id.clone())
+            .collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(&old), ids(&plan("").await));
+}
+
+#[tokio::test]
+async fn test_state_ttl() {
+    let sql = "
+        SET state_ttl = '6 hours';
+        CREATE VIEW bids AS SELECT bid.auction as auction, bid.price as price
+            FROM nexmark WHERE bid is not null;
+        CREATE VIEW auctions AS SELECT auction.id as id FROM nexmark WHERE auction is not null;
+        SELECT a.id, b.price FROM auctions a JOIN bids b ON a.id = b.auction";
+
+    let (program, _) = parse_and_get_program(sql, get_test_schema_provider(), SqlConfig::default())
+        .await
+        .unwrap();
+
+    let expirations: Vec<_> = program
+        .graph
+        .node_weights()
+        .filter_map(|node| match node.operator {
+            Operator::JoinWithExpiration {
+                left_expiration,
+                right_expiration,
+                ..
+            } => Some((left_expiration, right_expiration)),
+            _ => None,
+        })
+        .collect();
+    let ttl = Duration::from_secs(6 * 60 * 60);
+    assert_eq!(expirations, vec![(ttl, ttl)]);
+
+    let err = parse_and_get_program(
+        "SET state_ttl = 'forever'; SELECT bid FROM nexmark",
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("invalid duration"), "{}", err);
+
+    let err = parse_and_get_program(
+        "SET parallelism = '4'; SELECT bid FROM nexmark",
+        get_test_schema_provider(),
+        SqlConfig::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("unknown setting"), "{}", err);
+}
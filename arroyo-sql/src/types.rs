@@ -8,20 +8,19 @@ use std::{
 use anyhow::Result;
 use anyhow::{anyhow, bail};
 use arrow::datatypes::{DataType, IntervalMonthDayNanoType};
-use arrow::{
-    array::Decimal128Array,
-    datatypes::{Field, IntervalDayTimeType},
-};
+use arrow::datatypes::{Field, IntervalDayTimeType};
 use arrow_schema::{IntervalUnit, TimeUnit, DECIMAL128_MAX_PRECISION, DECIMAL_DEFAULT_SCALE};
 use arroyo_rpc::{
+    field_type_to_sql,
     formats::{Format, JsonFormat, TimestampFormat},
-    primitive_to_sql,
 };
 use datafusion::sql::sqlparser::ast::{DataType as SQLDataType, ExactNumberInfo, TimezoneInfo};
+use datafusion::sql::sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
 
 use arroyo_rpc::api_types::connections::{
-    FieldType, PrimitiveType, SourceField, SourceFieldType, StructType,
+    DecimalType, FieldType, MapType, PrimitiveType, SourceField, SourceFieldType, StructType,
 };
+use arroyo_types::state_schema::{StateField, StateType};
 use datafusion_common::ScalarValue;
 use proc_macro2::{Ident, TokenStream};
 use quote::quote;
@@ -101,6 +100,16 @@ impl StructDef {
         }
     }
 
+    /// The layout of values of this struct when they're stored in state
+    pub fn state_type(&self) -> StateType {
+        StateType::Struct(
+            self.fields
+                .iter()
+                .map(|field| StateField::new(&field.field_name, field.data_type.state_type()))
+                .collect(),
+        )
+    }
+
     pub fn struct_name_ident(&self) -> String {
         let name = self.struct_name();
         name.replace(":", "_")
@@ -274,6 +283,25 @@ impl StructDef {
                 quote! { unimplemented!("to_raw_string is not implemented for this type") }
             };
 
+            let to_raw_bytes = if self.fields.len() == 1
+                && matches!(
+                    self.fields[0].data_type,
+                    TypeDef::DataType(DataType::Binary, _)
+                ) {
+                let field = &self.fields[0].field_ident();
+                if self.fields[0].nullable() {
+                    quote! {
+                        self.#field.clone()
+                    }
+                } else {
+                    quote! {
+                        Some(self.#field.clone())
+                    }
+                }
+            } else {
+                quote! { unimplemented!("to_raw_bytes is not implemented for this type") }
+            };
+
             Some(quote! {
                 impl arroyo_worker::SchemaData for #struct_type {
                     fn name() -> &'static str {
@@ -288,6 +316,10 @@ impl StructDef {
                     fn to_raw_string(&self) -> Option<Vec<u8>> {
                         #to_raw_string
                     }
+
+                    fn to_raw_bytes(&self) -> Option<Vec<u8>> {
+                        #to_raw_bytes
+                    }
                 }
             })
         } else {
@@ -395,6 +427,20 @@ impl StructField {
         }
     }
 
+    /// A text field that holds JSON, which is read from and written to JSON formats as the JSON
+    /// itself rather than as a string
+    pub fn json(name: String, alias: Option<String>, nullable: bool) -> Self {
+        Self {
+            original_type: Some("json".to_string()),
+            ..Self::new(name, alias, TypeDef::DataType(DataType::Utf8, nullable))
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        matches!(self.data_type, TypeDef::DataType(DataType::Utf8, _))
+            && self.original_type.as_deref() == Some("json")
+    }
+
     pub fn with_rename(
         name: String,
         alias: Option<String>,
@@ -464,10 +510,27 @@ impl From<SourceField> for StructField {
                     PrimitiveType::UnixMicros => DataType::Timestamp(TimeUnit::Microsecond, None),
                     PrimitiveType::UnixNanos => DataType::Timestamp(TimeUnit::Nanosecond, None),
                     PrimitiveType::DateTime => DataType::Timestamp(TimeUnit::Microsecond, None),
-                    PrimitiveType::Json => DataType::Utf8,
+                    PrimitiveType::Json => {
+                        return StructField::json(f.field_name, None, f.nullable);
+                    }
                 },
                 f.nullable,
             ),
+            FieldType::Decimal(d) => {
+                TypeDef::DataType(DataType::Decimal128(d.precision, d.scale), f.nullable)
+            }
+            FieldType::List(item) => {
+                let item: Field = StructField::from(*item).into();
+                TypeDef::DataType(DataType::List(Arc::new(item)), f.nullable)
+            }
+            FieldType::Map(map) => {
+                let key: Field = StructField::from(*map.key).into();
+                let value: Field = StructField::from(*map.value).into();
+                TypeDef::DataType(
+                    map_data_type(key.data_type().clone(), value.data_type().clone()),
+                    f.nullable,
+                )
+            }
             FieldType::Struct(s) => TypeDef::StructDef(
                 StructDef::for_name(
                     s.name.clone(),
@@ -564,6 +627,20 @@ impl TypeDef {
         }
     }
 
+    pub fn state_type(&self) -> StateType {
+        let (typ, nullable) = match self {
+            TypeDef::StructDef(details, nullable) => (details.state_type(), *nullable),
+            TypeDef::DataType(data_type, nullable) => {
+                (StructField::data_type_state_type(data_type), *nullable)
+            }
+        };
+        if nullable {
+            StateType::Optional(Box::new(typ))
+        } else {
+            typ
+        }
+    }
+
     // TODO: rename this
     pub fn return_type(&self) -> Type {
         if self.is_optional() {
@@ -605,13 +682,9 @@ impl TypeDef {
             ScalarValue::Boolean(Some(value)) => parse_quote!(#value),
             ScalarValue::Float32(Some(value)) => parse_quote!(#value),
             ScalarValue::Float64(Some(value)) => parse_quote!(#value),
-            ScalarValue::Decimal128(Some(value), precision, scale) => parse_str(
-                &Decimal128Array::from_value(*value, 1)
-                    .with_precision_and_scale(*precision, *scale)
-                    .unwrap()
-                    .value_as_string(0),
-            )
-            .unwrap(),
+            ScalarValue::Decimal128(Some(value), _, scale) => {
+                parse_quote!(arroyo_types::Decimal::new(#value, #scale))
+            }
             ScalarValue::Int8(Some(value)) => parse_quote!(#value),
             ScalarValue::Int16(Some(value)) => parse_quote!(#value),
             ScalarValue::Int32(Some(value)) => parse_quote!(#value),
@@ -718,12 +791,35 @@ impl StructField {
             });
         }
 
+        if let (
+            true,
+            Some(Format::Json(JsonFormat {
+                debezium: false, ..
+            })),
+        ) = (self.is_json(), format)
+        {
+            if self.data_type.is_optional() {
+                attributes.push(quote! {
+                    #[serde(default)]
+                    #[serde(deserialize_with = "arroyo_worker::deserialize_raw_json_opt")]
+                    #[serde(serialize_with = "arroyo_worker::serialize_raw_json_opt")]
+                });
+            } else {
+                attributes.push(quote! {
+                    #[serde(deserialize_with = "arroyo_worker::deserialize_raw_json")]
+                    #[serde(serialize_with = "arroyo_worker::serialize_raw_json")]
+                });
+            }
+        }
+
         if let TypeDef::DataType(DataType::Timestamp(_, _), nullable) = self.data_type {
             match format.as_ref().map(|t| &*t) {
+                // Avro timestamps are encoded with the timestamp-millis logical type
                 Some(Format::Json(JsonFormat {
                     timestamp_format: TimestampFormat::UnixMillis,
                     ..
-                })) => {
+                }))
+                | Some(Format::Avro(_)) => {
                     if nullable {
                         attributes.push(quote! {
                             #[serde(default)]
@@ -819,12 +915,15 @@ impl StructField {
             DataType::Time64(_) => todo!(),
             DataType::Duration(_) => todo!(),
             DataType::Interval(_) => todo!(),
-            DataType::Binary => todo!(),
+            DataType::Binary => quote!(arrow::datatypes::DataType::Binary),
             DataType::FixedSizeBinary(_) => todo!(),
             DataType::LargeBinary => todo!(),
             DataType::Utf8 => quote!(arrow::datatypes::DataType::Utf8),
             DataType::LargeUtf8 => todo!(),
-            DataType::List(_) => todo!(),
+            DataType::List(field) => {
+                let field = Self::get_field_literal(field, false);
+                quote!(arrow::datatypes::DataType::List(std::sync::Arc::new(#field)))
+            }
             DataType::FixedSizeList(_, _) => todo!(),
             DataType::LargeList(_) => todo!(),
             DataType::Struct(struct_fields) => {
@@ -843,9 +942,14 @@ impl StructField {
             }
             DataType::Union(_, _) => todo!(),
             DataType::Dictionary(_, _) => todo!(),
-            DataType::Decimal128(_, _) => todo!(),
+            DataType::Decimal128(precision, scale) => {
+                quote!(arrow::datatypes::DataType::Decimal128(#precision, #scale))
+            }
             DataType::Decimal256(_, _) => todo!(),
-            DataType::Map(_, _) => todo!(),
+            DataType::Map(entries, sorted) => {
+                let entries = Self::get_field_literal(entries, false);
+                quote!(arrow::datatypes::DataType::Map(std::sync::Arc::new(#entries), #sorted))
+            }
             DataType::RunEndEncoded(_, _) => todo!(),
         }
     }
@@ -896,6 +1000,58 @@ impl StructField {
             ) => {
                 quote!(self.#field_array_name.append_option(data.#field_name.map(|time| arroyo_types::to_nanos(time) as i64)))
             }
+            TypeDef::DataType(DataType::Decimal128(_, scale), true) => {
+                quote!(self.#field_array_name.append_option(data.#field_name.map(|d| d.unscaled(#scale))))
+            }
+            TypeDef::DataType(DataType::Decimal128(_, scale), false) => {
+                quote!(self.#field_array_name.append_value(data.#field_name.unscaled(#scale)))
+            }
+            TypeDef::DataType(DataType::List(element), nullable) => {
+                let append = Self::element_append(element);
+
+                if *nullable {
+                    quote!(match data.#field_name {
+                        Some(list) => {
+                            for v in list {
+                                self.#field_array_name.values().#append;
+                            }
+                            self.#field_array_name.append(true);
+                        }
+                        None => self.#field_array_name.append_null(),
+                    })
+                } else {
+                    quote!({
+                        for v in data.#field_name {
+                            self.#field_array_name.values().#append;
+                        }
+                        self.#field_array_name.append(true);
+                    })
+                }
+            }
+            TypeDef::DataType(DataType::Map(entries, _), nullable) => {
+                let (key, value) = map_key_value(entries);
+                let append_key = Self::element_append(key);
+                let append_value = Self::element_append(value);
+                let append_entries = quote!({
+                    for (k, v) in map {
+                        self.#field_array_name.keys().#append_key;
+                        self.#field_array_name.values().#append_value;
+                    }
+                    self.#field_array_name.append(true).unwrap();
+                });
+
+                if *nullable {
+                    quote!(match data.#field_name {
+                        Some(map) => #append_entries,
+                        None => self.#field_array_name.append(false).unwrap(),
+                    })
+                } else {
+                    quote!({
+                        let map = data.#field_name;
+                        #append_entries
+                    })
+                }
+            }
             TypeDef::DataType(_, true) => {
                 quote!(self.#field_array_name.append_option(data.#field_name))
             }
@@ -905,6 +1061,28 @@ impl StructField {
         }
     }
 
+    // appends the element `v` of a list or map to the builder of its field
+    fn element_append(element: &Field) -> TokenStream {
+        let value = match element.data_type() {
+            DataType::Timestamp(arrow_schema::TimeUnit::Millisecond, None) => {
+                quote!(arroyo_types::to_millis(v) as i64)
+            }
+            DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None) => {
+                quote!(arroyo_types::to_micros(v) as i64)
+            }
+            DataType::Timestamp(arrow_schema::TimeUnit::Nanosecond, None) => {
+                quote!(arroyo_types::to_nanos(v) as i64)
+            }
+            DataType::Decimal128(_, scale) => quote!(v.unscaled(#scale)),
+            _ => quote!(v),
+        };
+        if element.is_nullable() {
+            quote!(append_option(v.map(|v| #value)))
+        } else {
+            quote!(append_value(#value))
+        }
+    }
+
     pub(crate) fn data_type_name(data_type: &DataType) -> String {
         match data_type {
             DataType::Null => todo!(),
@@ -926,7 +1104,7 @@ impl StructField {
             DataType::Time32(_) => todo!(),
             DataType::Time64(_) => todo!(),
             DataType::Duration(_) | DataType::Interval(_) => "std::time::Duration".to_string(),
-            DataType::Binary => todo!(),
+            DataType::Binary => "Vec<u8>".to_string(),
             DataType::FixedSizeBinary(_) => todo!(),
             DataType::LargeBinary => todo!(),
             DataType::Utf8 => "String".to_string(),
@@ -944,13 +1122,66 @@ impl StructField {
             DataType::Struct(_) => unreachable!(),
             DataType::Union(_, _) => todo!(),
             DataType::Dictionary(_, _) => todo!(),
-            DataType::Decimal128(_, _) => todo!(),
+            DataType::Decimal128(_, _) => "arroyo_types::Decimal".to_string(),
             DataType::Decimal256(_, _) => todo!(),
-            DataType::Map(_, _) => todo!(),
+            DataType::Map(entries, _) => {
+                let (key, value) = map_key_value(entries);
+                let key_type = Self::data_type_name(key.data_type());
+                let value_type = Self::data_type_name(value.data_type());
+                if value.is_nullable() {
+                    format!(
+                        "std::collections::BTreeMap<{}, Option<{}>>",
+                        key_type, value_type
+                    )
+                } else {
+                    format!("std::collections::BTreeMap<{}, {}>", key_type, value_type)
+                }
+            }
             DataType::RunEndEncoded(_, _) => todo!(),
         }
     }
 
+    // the state layout of the rust types from data_type_name
+    fn data_type_state_type(data_type: &DataType) -> StateType {
+        let optional = |t: StateType, nullable: bool| {
+            if nullable {
+                StateType::Optional(Box::new(t))
+            } else {
+                t
+            }
+        };
+
+        match data_type {
+            DataType::Boolean => StateType::Bool,
+            DataType::Int8 => StateType::I8,
+            DataType::Int16 | DataType::Int32 | DataType::Int64 => StateType::SignedInt,
+            DataType::UInt8 => StateType::U8,
+            DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => StateType::UnsignedInt,
+            DataType::Float32 => StateType::F32,
+            DataType::Float64 => StateType::F64,
+            DataType::Timestamp(_, _) | DataType::Date32 | DataType::Date64 => StateType::Timestamp,
+            DataType::Duration(_) | DataType::Interval(_) => StateType::Duration,
+            DataType::Binary => StateType::Bytes,
+            DataType::Utf8 => StateType::String,
+            DataType::Decimal128(_, _) => StateType::Decimal,
+            DataType::List(field) => StateType::List(Box::new(optional(
+                Self::data_type_state_type(field.data_type()),
+                field.is_nullable(),
+            ))),
+            DataType::Map(entries, _) => {
+                let (key, value) = map_key_value(entries);
+                StateType::Map(
+                    Box::new(Self::data_type_state_type(key.data_type())),
+                    Box::new(optional(
+                        Self::data_type_state_type(value.data_type()),
+                        value.is_nullable(),
+                    )),
+                )
+            }
+            data_type => StateType::Opaque(data_type.to_string()),
+        }
+    }
+
     fn create_array_builder(&self) -> TokenStream {
         match &self.data_type {
             TypeDef::StructDef(struct_type, false) => {
@@ -963,49 +1194,7 @@ impl StructField {
                 let builder_ident: Ident = parse_str(&builder_name).expect(&builder_name);
                 quote!(#builder_ident::nullable())
             }
-            TypeDef::DataType(data_type, _) => match data_type {
-                DataType::Null => todo!(),
-                DataType::Boolean
-                | DataType::Int8
-                | DataType::Int16
-                | DataType::Int32
-                | DataType::Int64
-                | DataType::UInt8
-                | DataType::UInt16
-                | DataType::UInt32
-                | DataType::UInt64
-                | DataType::Float16
-                | DataType::Float32
-                | DataType::Float64
-                | DataType::Timestamp(_, None) => {
-                    let builder_type = self.to_array_builder_type();
-                    quote!(#builder_type::with_capacity(1024))
-                }
-                DataType::Timestamp(_, _) => todo!(),
-                DataType::Date32 => todo!(),
-                DataType::Date64 => todo!(),
-                DataType::Time32(_) => todo!(),
-                DataType::Time64(_) => todo!(),
-                DataType::Duration(_) => todo!(),
-                DataType::Interval(_) => todo!(),
-                DataType::Binary => todo!(),
-                DataType::FixedSizeBinary(_) => todo!(),
-                DataType::LargeBinary => todo!(),
-                DataType::Utf8 => quote!(arrow_array::builder::GenericByteBuilder::<
-                    arrow_array::types::GenericStringType<i32>,
-                >::new()),
-                DataType::LargeUtf8 => todo!(),
-                DataType::List(_) => todo!(),
-                DataType::FixedSizeList(_, _) => todo!(),
-                DataType::LargeList(_) => todo!(),
-                DataType::Struct(_) => todo!(),
-                DataType::Union(_, _) => todo!(),
-                DataType::Dictionary(_, _) => todo!(),
-                DataType::Decimal128(_, _) => todo!(),
-                DataType::Decimal256(_, _) => todo!(),
-                DataType::Map(_, _) => todo!(),
-                DataType::RunEndEncoded(_, _) => todo!(),
-            },
+            TypeDef::DataType(data_type, _) => Self::array_builder_constructor(data_type),
         }
     }
 
@@ -1015,94 +1204,170 @@ impl StructField {
                 let builder_name = format!("{}RecordBatchBuilder", struct_type.struct_name_ident());
                 parse_str(&builder_name).unwrap()
             }
-            TypeDef::DataType(data_type, _) => match data_type {
-                DataType::Null => todo!(),
-                DataType::Boolean => quote!(arrow_array::builder::BooleanBuilder),
-                DataType::Int8 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int8Type>)
-                }
-                DataType::Int16 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int16Type>)
-                }
-                DataType::Int32 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int32Type>)
-                }
-                DataType::Int64 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int64Type>)
-                }
-                DataType::UInt8 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt8Type>)
-                }
-                DataType::UInt16 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt16Type>)
-                }
-                DataType::UInt32 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt32Type>)
-                }
-                DataType::UInt64 => {
-                    quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt64Type>)
-                }
-                DataType::Float16 => {
-                    quote!(
-                        arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float16Type>
-                    )
-                }
-                DataType::Float32 => {
-                    quote!(
-                        arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float32Type>
-                    )
-                }
-                DataType::Float64 => {
-                    quote!(
-                        arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float64Type>
-                    )
-                }
-                DataType::Timestamp(arrow_schema::TimeUnit::Millisecond, None) => quote!(
-                    arrow_array::builder::PrimitiveBuilder::<
-                        arrow_array::types::TimestampMillisecondType,
-                    >
-                ),
-                DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None) => quote!(
-                    arrow_array::builder::PrimitiveBuilder::<
-                        arrow_array::types::TimestampMicrosecondType,
-                    >
-                ),
-                DataType::Timestamp(arrow_schema::TimeUnit::Nanosecond, None) => quote!(
-                    arrow_array::builder::PrimitiveBuilder::<
-                        arrow_array::types::TimestampNanosecondType,
-                    >
-                ),
-                DataType::Date32 => todo!(),
-                DataType::Date64 => todo!(),
-                DataType::Time32(_) => todo!(),
-                DataType::Time64(_) => todo!(),
-                DataType::Duration(_) => todo!(),
-                DataType::Interval(_) => todo!(),
-                DataType::Binary => todo!(),
-                DataType::FixedSizeBinary(_) => todo!(),
-                DataType::LargeBinary => todo!(),
-                DataType::Utf8 => {
-                    quote!(
-                        arrow_array::builder::GenericByteBuilder<
-                            arrow_array::types::GenericStringType<i32>,
-                        >
-                    )
-                }
-                DataType::LargeUtf8 => todo!(),
-                DataType::List(_) => todo!(),
-                DataType::FixedSizeList(_, _) => todo!(),
-                DataType::LargeList(_) => todo!(),
-                DataType::Struct(_) => todo!(),
-                DataType::Union(_, _) => todo!(),
-                DataType::Dictionary(_, _) => todo!(),
-                DataType::Decimal128(_, _) => todo!(),
-                DataType::Decimal256(_, _) => todo!(),
-                _ => todo!("{:?}", self),
-            },
+            TypeDef::DataType(data_type, _) => Self::array_builder_type(data_type),
         };
         parse_str(&tokens.to_string()).unwrap()
     }
 
+    fn array_builder_constructor(data_type: &DataType) -> TokenStream {
+        match data_type {
+            DataType::Null => todo!(),
+            DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float16
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Timestamp(_, None) => {
+                let builder_type = Self::array_builder_type(data_type);
+                quote!(#builder_type::with_capacity(1024))
+            }
+            DataType::Timestamp(_, _) => todo!(),
+            DataType::Date32 => todo!(),
+            DataType::Date64 => todo!(),
+            DataType::Time32(_) => todo!(),
+            DataType::Time64(_) => todo!(),
+            DataType::Duration(_) => todo!(),
+            DataType::Interval(_) => todo!(),
+            DataType::Binary => quote!(arrow_array::builder::GenericByteBuilder::<
+                arrow_array::types::GenericBinaryType<i32>,
+            >::new()),
+            DataType::FixedSizeBinary(_) => todo!(),
+            DataType::LargeBinary => todo!(),
+            DataType::Utf8 => quote!(arrow_array::builder::GenericByteBuilder::<
+                arrow_array::types::GenericStringType<i32>,
+            >::new()),
+            DataType::LargeUtf8 => todo!(),
+            DataType::List(field) => {
+                let values = Self::array_builder_constructor(field.data_type());
+                let field = Self::get_field_literal(field, false);
+                quote!(arrow_array::builder::GenericListBuilder::<i32, _>::new(#values)
+                .with_field(std::sync::Arc::new(#field)))
+            }
+            DataType::FixedSizeList(_, _) => todo!(),
+            DataType::LargeList(_) => todo!(),
+            DataType::Struct(_) => todo!(),
+            DataType::Union(_, _) => todo!(),
+            DataType::Dictionary(_, _) => todo!(),
+            DataType::Decimal128(precision, scale) => {
+                let builder_type = Self::array_builder_type(data_type);
+                quote!(#builder_type::with_capacity(1024)
+                    .with_precision_and_scale(#precision, #scale)
+                    .unwrap())
+            }
+            DataType::Decimal256(_, _) => todo!(),
+            DataType::Map(entries, _) => {
+                let (key, value) = map_key_value(entries);
+                let keys = Self::array_builder_constructor(key.data_type());
+                let values = Self::array_builder_constructor(value.data_type());
+                quote!(arrow_array::builder::MapBuilder::new(None, #keys, #values))
+            }
+            DataType::RunEndEncoded(_, _) => todo!(),
+        }
+    }
+
+    fn array_builder_type(data_type: &DataType) -> TokenStream {
+        match data_type {
+            DataType::Null => todo!(),
+            DataType::Boolean => quote!(arrow_array::builder::BooleanBuilder),
+            DataType::Int8 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int8Type>)
+            }
+            DataType::Int16 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int16Type>)
+            }
+            DataType::Int32 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int32Type>)
+            }
+            DataType::Int64 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Int64Type>)
+            }
+            DataType::UInt8 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt8Type>)
+            }
+            DataType::UInt16 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt16Type>)
+            }
+            DataType::UInt32 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt32Type>)
+            }
+            DataType::UInt64 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::UInt64Type>)
+            }
+            DataType::Float16 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float16Type>)
+            }
+            DataType::Float32 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float32Type>)
+            }
+            DataType::Float64 => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Float64Type>)
+            }
+            DataType::Timestamp(arrow_schema::TimeUnit::Millisecond, None) => quote!(
+                arrow_array::builder::PrimitiveBuilder::<
+                    arrow_array::types::TimestampMillisecondType,
+                >
+            ),
+            DataType::Timestamp(arrow_schema::TimeUnit::Microsecond, None) => quote!(
+                arrow_array::builder::PrimitiveBuilder::<
+                    arrow_array::types::TimestampMicrosecondType,
+                >
+            ),
+            DataType::Timestamp(arrow_schema::TimeUnit::Nanosecond, None) => quote!(
+                arrow_array::builder::PrimitiveBuilder::<arrow_array::types::TimestampNanosecondType>
+            ),
+            DataType::Date32 => todo!(),
+            DataType::Date64 => todo!(),
+            DataType::Time32(_) => todo!(),
+            DataType::Time64(_) => todo!(),
+            DataType::Duration(_) => todo!(),
+            DataType::Interval(_) => todo!(),
+            DataType::Binary => {
+                quote!(
+                    arrow_array::builder::GenericByteBuilder<
+                        arrow_array::types::GenericBinaryType<i32>,
+                    >
+                )
+            }
+            DataType::FixedSizeBinary(_) => todo!(),
+            DataType::LargeBinary => todo!(),
+            DataType::Utf8 => {
+                quote!(
+                    arrow_array::builder::GenericByteBuilder<
+                        arrow_array::types::GenericStringType<i32>,
+                    >
+                )
+            }
+            DataType::LargeUtf8 => todo!(),
+            DataType::List(field) => {
+                let values = Self::array_builder_type(field.data_type());
+                quote!(arrow_array::builder::GenericListBuilder<i32, #values>)
+            }
+            DataType::FixedSizeList(_, _) => todo!(),
+            DataType::LargeList(_) => todo!(),
+            DataType::Struct(_) => todo!(),
+            DataType::Union(_, _) => todo!(),
+            DataType::Dictionary(_, _) => todo!(),
+            DataType::Decimal128(_, _) => {
+                quote!(arrow_array::builder::PrimitiveBuilder::<arrow_array::types::Decimal128Type>)
+            }
+            DataType::Decimal256(_, _) => todo!(),
+            DataType::Map(entries, _) => {
+                let (key, value) = map_key_value(entries);
+                let keys = Self::array_builder_type(key.data_type());
+                let values = Self::array_builder_type(value.data_type());
+                quote!(arrow_array::builder::MapBuilder<#keys, #values>)
+            }
+            _ => todo!("{:?}", data_type),
+        }
+    }
+
     pub fn get_return_expression(&self, parent_ident: TokenStream) -> TokenStream {
         let ident: Ident = parse_str(&self.field_name()).unwrap();
         quote!(#parent_ident.#ident.clone())
@@ -1124,6 +1389,9 @@ impl StructField {
         let array_field = self.field_array_ident();
         match self.data_type {
             TypeDef::StructDef(_, _) => quote!(self.#array_field.add_data(None)),
+            TypeDef::DataType(DataType::Map(_, _), _) => {
+                quote!(self.#array_field.append(false).unwrap())
+            }
             TypeDef::DataType(_, _) => quote!(self.#array_field.append_null()),
         }
     }
@@ -1165,7 +1433,7 @@ pub(crate) fn data_type_as_syn_type(data_type: &DataType) -> syn::Type {
         DataType::Duration(_) | DataType::Interval(_) => {
             parse_quote!(std::time::Duration)
         }
-        DataType::Binary => todo!(),
+        DataType::Binary => parse_quote!(Vec<u8>),
         DataType::FixedSizeBinary(_) => todo!(),
         DataType::LargeBinary => todo!(),
         DataType::Utf8 => parse_quote!(String),
@@ -1183,13 +1451,49 @@ pub(crate) fn data_type_as_syn_type(data_type: &DataType) -> syn::Type {
         DataType::Struct(_) => unreachable!(),
         DataType::Union(_, _) => todo!(),
         DataType::Dictionary(_, _) => todo!(),
-        DataType::Decimal128(_, _) => todo!(),
+        DataType::Decimal128(_, _) => parse_quote!(arroyo_types::Decimal),
         DataType::Decimal256(_, _) => todo!(),
-        DataType::Map(_, _) => todo!(),
+        DataType::Map(entries, _) => {
+            let (key, value) = map_key_value(entries);
+            let key_type = data_type_as_syn_type(key.data_type());
+            let value_type = data_type_as_syn_type(value.data_type());
+            if value.is_nullable() {
+                parse_quote!(std::collections::BTreeMap<#key_type, Option<#value_type>>)
+            } else {
+                parse_quote!(std::collections::BTreeMap<#key_type, #value_type>)
+            }
+        }
         DataType::RunEndEncoded(_, _) => todo!(),
     }
 }
 
+/// A map with non-null keys and nullable values, laid out the way arrow's `MapBuilder` builds
+/// them. Maps are represented as `BTreeMap`s in generated code.
+pub(crate) fn map_data_type(key_type: DataType, value_type: DataType) -> DataType {
+    DataType::Map(
+        Arc::new(Field::new(
+            "entries",
+            DataType::Struct(
+                vec![
+                    Field::new("keys", key_type, false),
+                    Field::new("values", value_type, true),
+                ]
+                .into(),
+            ),
+            false,
+        )),
+        false,
+    )
+}
+
+/// The key and value fields of the entries of a map
+pub(crate) fn map_key_value(entries: &Field) -> (&Field, &Field) {
+    let DataType::Struct(fields) = entries.data_type() else {
+        unreachable!("map entries are always structs");
+    };
+    (fields[0].as_ref(), fields[1].as_ref())
+}
+
 // Pulled from DataFusion
 
 pub(crate) fn convert_data_type(sql_type: &SQLDataType) -> Result<DataType> {
@@ -1204,10 +1508,42 @@ pub(crate) fn convert_data_type(sql_type: &SQLDataType) -> Result<DataType> {
         SQLDataType::Array(None) => {
             bail!("Arrays with unspecified type is not supported".to_string())
         }
+        // the parser doesn't support MAP<K, V>, so maps are declared as MAP(K, V)
+        SQLDataType::Custom(name, modifiers) if name.to_string().eq_ignore_ascii_case("map") => {
+            let [key_type, value_type] = modifiers.as_slice() else {
+                bail!("MAP columns must be declared as MAP(key_type, value_type)");
+            };
+            let key_type = convert_type_name(key_type)?;
+            if !matches!(
+                key_type,
+                DataType::Utf8
+                    | DataType::Int8
+                    | DataType::Int16
+                    | DataType::Int32
+                    | DataType::Int64
+                    | DataType::UInt8
+                    | DataType::UInt16
+                    | DataType::UInt32
+                    | DataType::UInt64
+            ) {
+                bail!(
+                    "the keys of a MAP must be strings or integers, not {:?}",
+                    key_type
+                );
+            }
+            Ok(map_data_type(key_type, convert_type_name(value_type)?))
+        }
         other => convert_simple_data_type(other),
     }
 }
 
+fn convert_type_name(name: &str) -> Result<DataType> {
+    let sql_type = Parser::new(&PostgreSqlDialect {})
+        .try_with_sql(name)?
+        .parse_data_type()?;
+    convert_simple_data_type(&sql_type)
+}
+
 fn convert_simple_data_type(sql_type: &SQLDataType) -> Result<DataType> {
     match sql_type {
         SQLDataType::Boolean => Ok(DataType::Boolean),
@@ -1250,13 +1586,25 @@ fn convert_simple_data_type(sql_type: &SQLDataType) -> Result<DataType> {
             };
             make_decimal_type(precision, scale)
         }
-        SQLDataType::Bytea => Ok(DataType::Binary),
+        SQLDataType::Bytea
+        | SQLDataType::Binary(_)
+        | SQLDataType::Varbinary(_)
+        | SQLDataType::Blob(_) => Ok(DataType::Binary),
         SQLDataType::Interval => Ok(DataType::Interval(IntervalUnit::MonthDayNano)),
-        SQLDataType::JSON => bail!("JSON data type is not supported yet".to_string()),
+        // JSON columns hold the text of their JSON, which can be read with the JSON functions
+        sql_type if is_json_type(sql_type) => Ok(DataType::Utf8),
         _ => bail!(format!("Unsupported SQL type {sql_type:?}")),
     }
 }
 
+pub(crate) fn is_json_type(sql_type: &SQLDataType) -> bool {
+    match sql_type {
+        SQLDataType::JSON => true,
+        SQLDataType::Custom(name, _) => name.to_string().eq_ignore_ascii_case("jsonb"),
+        _ => false,
+    }
+}
+
 /// Returns a validated `DataType` for the specified precision and
 /// scale
 pub(crate) fn make_decimal_type(precision: Option<u64>, scale: Option<u64>) -> Result<DataType> {
@@ -1280,12 +1628,22 @@ pub(crate) fn make_decimal_type(precision: Option<u64>, scale: Option<u64>) -> R
     }
 }
 
+// the struct field for an element of a list or map
+fn element_struct_field(field: &Field) -> StructField {
+    StructField::new(
+        field.name().clone(),
+        None,
+        TypeDef::DataType(field.data_type().clone(), field.is_nullable()),
+    )
+}
+
 impl TryFrom<StructField> for SourceField {
     type Error = String;
 
     fn try_from(f: StructField) -> Result<Self, Self::Error> {
         let field_name = f.name();
         let nullable = f.nullable();
+        let is_json = f.is_json();
         let (field_type, sql_name) = match f.data_type {
             TypeDef::StructDef(StructDef { name, fields, .. }, _) => {
                 let fields: Result<_, String> = fields.into_iter().map(|f| f.try_into()).collect();
@@ -1297,6 +1655,21 @@ impl TryFrom<StructField> for SourceField {
 
                 (FieldType::Struct(st), name)
             }
+            TypeDef::DataType(DataType::Decimal128(precision, scale), _) => {
+                (FieldType::Decimal(DecimalType { precision, scale }), None)
+            }
+            TypeDef::DataType(DataType::List(item), _) => {
+                let item = Self::try_from(element_struct_field(&item))?;
+                (FieldType::List(Box::new(item)), None)
+            }
+            TypeDef::DataType(DataType::Map(entries, _), _) => {
+                let (key, value) = map_key_value(&entries);
+                let map = MapType {
+                    key: Box::new(Self::try_from(element_struct_field(key))?),
+                    value: Box::new(Self::try_from(element_struct_field(value))?),
+                };
+                (FieldType::Map(map), None)
+            }
             TypeDef::DataType(dt, _) => {
                 let pt = match dt {
                     DataType::Boolean => Ok(PrimitiveType::Bool),
@@ -1310,16 +1683,15 @@ impl TryFrom<StructField> for SourceField {
                     DataType::Timestamp(TimeUnit::Millisecond, _) => Ok(PrimitiveType::UnixMillis),
                     DataType::Timestamp(TimeUnit::Microsecond, _) => Ok(PrimitiveType::UnixMicros),
                     DataType::Timestamp(TimeUnit::Nanosecond, _) => Ok(PrimitiveType::UnixNanos),
+                    DataType::Utf8 if is_json => Ok(PrimitiveType::Json),
                     DataType::Utf8 => Ok(PrimitiveType::String),
                     dt => Err(format!("Unsupported data type {:?}", dt)),
                 }?;
 
-                (
-                    FieldType::Primitive(pt.clone()),
-                    Some(primitive_to_sql(pt).to_string()),
-                )
+                (FieldType::Primitive(pt), None)
             }
         };
+        let sql_name = sql_name.or_else(|| field_type_to_sql(&field_type));
 
         Ok(SourceField {
             field_name,
@@ -1328,6 +1700,7 @@ impl TryFrom<StructField> for SourceField {
                 sql_name,
             },
             nullable,
+            json_path: None,
         })
     }
 }
@@ -0,0 +1,153 @@
+use anyhow::{anyhow, bail, Result};
+use arrow_schema::DataType;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use quote::{format_ident, quote};
+use wasmparser::{ExternalKind, FuncType, Parser, Payload, ValType};
+
+use crate::types::TypeDef;
+
+/// A function exported by a WebAssembly module, which is called as a scalar UDF
+pub(crate) struct WasmUdf {
+    pub name: String,
+    pub args: Vec<TypeDef>,
+    pub ret: TypeDef,
+    // the function that calls it, which is added to the udfs module of the pipeline
+    pub def: String,
+}
+
+/// Parses a base64-encoded WebAssembly module into a UDF for each function that it exports.
+///
+/// The functions are run in a sandbox, so the module may not import anything, and they may only
+/// take and return numbers. Their results are null if they trap or run out of fuel or memory.
+pub(crate) fn parse_wasm_udfs(definition: &str) -> Result<Vec<WasmUdf>> {
+    let module = definition.trim();
+    let bytes = STANDARD
+        .decode(module)
+        .map_err(|e| anyhow!("WebAssembly UDFs must be base64-encoded modules: {}", e))?;
+    wasmparser::validate(&bytes).map_err(|e| anyhow!("invalid WebAssembly module: {}", e))?;
+
+    let mut types = vec![];
+    let mut functions = vec![];
+    let mut exports = vec![];
+    for payload in Parser::new(0).parse_all(&bytes) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for ty in reader {
+                    types.push(ty?);
+                }
+            }
+            Payload::ImportSection(reader) => {
+                if let Some(import) = reader.into_iter().next() {
+                    let import = import?;
+                    bail!(
+                        "WebAssembly UDFs may not import anything, but the module imports {}.{}",
+                        import.module,
+                        import.name
+                    );
+                }
+            }
+            Payload::FunctionSection(reader) => {
+                for function in reader {
+                    functions.push(function?);
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        exports.push((export.name.to_string(), export.index));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if exports.is_empty() {
+        bail!("the WebAssembly module doesn't export any functions");
+    }
+
+    exports
+        .into_iter()
+        .map(|(name, index)| {
+            // without imports, the exported index is the index of the function in the module
+            let ty = &types[functions[index as usize] as usize];
+            #[allow(unreachable_patterns)]
+            let func_type = match ty {
+                wasmparser::Type::Func(func_type) => func_type,
+                _ => bail!("exported WebAssembly UDF {} isn't a function", name),
+            };
+            wasm_udf(module, name, func_type)
+        })
+        .collect()
+}
+
+fn wasm_udf(module: &str, name: String, func_type: &FuncType) -> Result<WasmUdf> {
+    let ident = syn::parse_str::<syn::Ident>(&name).map_err(|_| {
+        anyhow!(
+            "WebAssembly UDF {} must be named like a SQL function, with only letters, digits and underscores",
+            name
+        )
+    })?;
+
+    let args = func_type
+        .params()
+        .iter()
+        .map(|t| {
+            number_type(t).ok_or_else(|| {
+                anyhow!(
+                    "the arguments of WebAssembly UDF {} must be numbers, but one is {:?}",
+                    name,
+                    t
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let [ret] = func_type.results() else {
+        bail!("WebAssembly UDF {} must return a single value", name);
+    };
+    let (ret, getter) = number_type(ret).ok_or_else(|| {
+        anyhow!(
+            "WebAssembly UDF {} must return a number, but returns {:?}",
+            name,
+            ret
+        )
+    })?;
+
+    let params: Vec<_> = (0..args.len()).map(|i| format_ident!("__{}", i)).collect();
+    let arg_types = args.iter().map(|(t, _)| t.return_type());
+    let ret_type = ret.return_type();
+    let def = quote! {
+        pub fn #ident(#(#params: #arg_types),*) -> Option<#ret_type> {
+            arroyo_worker::wasm_udfs::call(
+                #module,
+                #name,
+                &[#(arroyo_worker::wasm_udfs::Val::from(#params)),*],
+            )
+            .and_then(|result| result.#getter())
+        }
+    }
+    .to_string();
+
+    Ok(WasmUdf {
+        name,
+        args: args.into_iter().map(|(t, _)| t).collect(),
+        ret: ret.to_optional(),
+        def,
+    })
+}
+
+// the SQL type of a WebAssembly number, and the method of a wasmtime Val that extracts it
+fn number_type(t: &ValType) -> Option<(TypeDef, syn::Ident)> {
+    let (data_type, getter) = match t {
+        ValType::I32 => (DataType::Int32, "i32"),
+        ValType::I64 => (DataType::Int64, "i64"),
+        ValType::F32 => (DataType::Float32, "f32"),
+        ValType::F64 => (DataType::Float64, "f64"),
+        _ => return None,
+    };
+    Some((
+        TypeDef::DataType(data_type, false),
+        format_ident!("{}", getter),
+    ))
+}
@@ -0,0 +1,262 @@
+use crate::parquet::ParquetBackend;
+use crate::rocksdb::RocksDbBackend;
+use crate::tables::DataTuple;
+use crate::BackingStore;
+use anyhow::Result;
+use arroyo_rpc::api_types::pipelines::StateBackendType;
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, OperatorCheckpointMetadata, TableDescriptor, TableType,
+};
+use arroyo_rpc::{CompactionResult, ControlResp};
+use arroyo_types::{CheckpointBarrier, Data, Key, TaskInfo, STATE_BACKEND_ENV};
+use std::env;
+use std::ops::Range;
+use std::time::SystemTime;
+use tokio::sync::mpsc::Sender;
+
+/// The backend that workers keep their state in, chosen per pipeline. Both kinds write their
+/// checkpoints in the parquet format, so everything that only works with checkpoints (loading
+/// and writing metadata, cleanup, compaction and savepoints) is the same for either.
+pub enum StateBackend {
+    Memory(ParquetBackend),
+    RocksDb(RocksDbBackend),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $backend:ident => $body:expr) => {
+        match $self {
+            StateBackend::Memory($backend) => $body,
+            StateBackend::RocksDb($backend) => $body,
+        }
+    };
+}
+
+impl StateBackend {
+    // workers are started with the state backend of their job
+    fn backend_type() -> StateBackendType {
+        match env::var(STATE_BACKEND_ENV) {
+            Ok(backend) => backend
+                .try_into()
+                .unwrap_or_else(|e| panic!("invalid {}: {}", STATE_BACKEND_ENV, e)),
+            Err(_) => StateBackendType::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BackingStore for StateBackend {
+    fn name() -> &'static str {
+        ParquetBackend::name()
+    }
+
+    fn task_info(&self) -> &TaskInfo {
+        dispatch!(self, backend => backend.task_info())
+    }
+
+    fn checkpoint_url(&self) -> &str {
+        dispatch!(self, backend => backend.checkpoint_url())
+    }
+
+    async fn load_latest_checkpoint_metadata(job_id: &str) -> Option<CheckpointMetadata> {
+        ParquetBackend::load_latest_checkpoint_metadata(job_id).await
+    }
+
+    async fn load_checkpoint_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        epoch: u32,
+    ) -> Option<CheckpointMetadata> {
+        ParquetBackend::load_checkpoint_metadata(checkpoint_url, job_id, epoch).await
+    }
+
+    async fn load_operator_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+    ) -> Option<OperatorCheckpointMetadata> {
+        ParquetBackend::load_operator_metadata(checkpoint_url, job_id, operator_id, epoch).await
+    }
+
+    async fn write_operator_checkpoint_metadata(
+        checkpoint_url: &str,
+        metadata: OperatorCheckpointMetadata,
+    ) {
+        ParquetBackend::write_operator_checkpoint_metadata(checkpoint_url, metadata).await
+    }
+
+    async fn write_checkpoint_metadata(checkpoint_url: &str, metadata: CheckpointMetadata) {
+        ParquetBackend::write_checkpoint_metadata(checkpoint_url, metadata).await
+    }
+
+    async fn new(
+        checkpoint_url: &str,
+        task_info: &TaskInfo,
+        tables: Vec<TableDescriptor>,
+        tx: Sender<ControlResp>,
+    ) -> Self {
+        match Self::backend_type() {
+            StateBackendType::Memory => {
+                Self::Memory(ParquetBackend::new(checkpoint_url, task_info, tables, tx).await)
+            }
+            StateBackendType::Rocksdb => {
+                Self::RocksDb(RocksDbBackend::new(checkpoint_url, task_info, tables, tx).await)
+            }
+        }
+    }
+
+    async fn from_checkpoint(
+        checkpoint_url: &str,
+        task_info: &TaskInfo,
+        metadata: CheckpointMetadata,
+        tables: Vec<TableDescriptor>,
+        control_tx: Sender<ControlResp>,
+    ) -> Self {
+        match Self::backend_type() {
+            StateBackendType::Memory => Self::Memory(
+                ParquetBackend::from_checkpoint(
+                    checkpoint_url,
+                    task_info,
+                    metadata,
+                    tables,
+                    control_tx,
+                )
+                .await,
+            ),
+            StateBackendType::Rocksdb => Self::RocksDb(
+                RocksDbBackend::from_checkpoint(
+                    checkpoint_url,
+                    task_info,
+                    metadata,
+                    tables,
+                    control_tx,
+                )
+                .await,
+            ),
+        }
+    }
+
+    async fn prepare_checkpoint_load(metadata: &CheckpointMetadata) -> Result<()> {
+        ParquetBackend::prepare_checkpoint_load(metadata).await
+    }
+
+    async fn cleanup_checkpoint(
+        checkpoint_url: &str,
+        metadata: CheckpointMetadata,
+        old_min_epoch: u32,
+        new_min_epoch: u32,
+    ) -> Result<()> {
+        ParquetBackend::cleanup_checkpoint(checkpoint_url, metadata, old_min_epoch, new_min_epoch)
+            .await
+    }
+
+    async fn checkpoint(
+        &mut self,
+        barrier: CheckpointBarrier,
+        watermark: Option<SystemTime>,
+    ) -> u32 {
+        dispatch!(self, backend => backend.checkpoint(barrier, watermark).await)
+    }
+
+    async fn write_in_flight<K: Key, V: Data>(
+        &mut self,
+        epoch: u32,
+        table: char,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        dispatch!(self, backend => backend.write_in_flight(epoch, table, key, value).await)
+    }
+
+    async fn get_data_tuples<K: Key, V: Data>(&self, table: char) -> Vec<DataTuple<K, V>> {
+        dispatch!(self, backend => backend.get_data_tuples(table).await)
+    }
+
+    async fn write_data_tuple<K: Key, V: Data>(
+        &mut self,
+        table: char,
+        table_type: TableType,
+        timestamp: SystemTime,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        dispatch!(self, backend => {
+            backend
+                .write_data_tuple(table, table_type, timestamp, key, value)
+                .await
+        })
+    }
+
+    async fn delete_time_key<K: Key>(
+        &mut self,
+        table: char,
+        table_type: TableType,
+        timestamp: SystemTime,
+        key: &mut K,
+    ) {
+        dispatch!(self, backend => {
+            backend
+                .delete_time_key(table, table_type, timestamp, key)
+                .await
+        })
+    }
+
+    async fn delete_key<K: Key>(&mut self, table: char, key: &mut K) {
+        dispatch!(self, backend => backend.delete_key(table, key).await)
+    }
+
+    async fn delete_data_value<K: Key, V: Data>(
+        &mut self,
+        table: char,
+        timestamp: SystemTime,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        dispatch!(self, backend => {
+            backend
+                .delete_data_value(table, timestamp, key, value)
+                .await
+        })
+    }
+
+    async fn delete_time_range<K: Key>(
+        &mut self,
+        table: char,
+        key: &mut K,
+        range: Range<SystemTime>,
+    ) {
+        dispatch!(self, backend => backend.delete_time_range(table, key, range).await)
+    }
+
+    async fn write_key_value<K: Key, V: Data>(&mut self, table: char, key: &mut K, value: &mut V) {
+        dispatch!(self, backend => backend.write_key_value(table, key, value).await)
+    }
+
+    async fn get_global_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)> {
+        dispatch!(self, backend => backend.get_global_key_values(table).await)
+    }
+
+    async fn get_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)> {
+        dispatch!(self, backend => backend.get_key_values(table).await)
+    }
+
+    async fn load_compacted(&mut self, compaction: CompactionResult) {
+        dispatch!(self, backend => backend.load_compacted(compaction).await)
+    }
+
+    fn keeps_keyed_values(&self) -> bool {
+        dispatch!(self, backend => backend.keeps_keyed_values())
+    }
+
+    fn get_keyed_value<K: Key, V: Data>(&self, table: char, key: &K) -> Option<V> {
+        dispatch!(self, backend => backend.get_keyed_value(table, key))
+    }
+
+    fn put_keyed_value<K: Key, V: Data>(&mut self, table: char, key: &K, value: &V) {
+        dispatch!(self, backend => backend.put_keyed_value(table, key, value))
+    }
+
+    fn delete_keyed_value<K: Key>(&mut self, table: char, key: &K) {
+        dispatch!(self, backend => backend.delete_keyed_value(table, key))
+    }
+}
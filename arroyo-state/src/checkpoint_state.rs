@@ -15,6 +15,7 @@ use tracing::{debug, info, warn};
 
 pub struct CheckpointState {
     job_id: String,
+    checkpoint_url: String,
     checkpoint_id: i64,
     epoch: u32,
     min_epoch: u32,
@@ -32,6 +33,7 @@ pub struct CheckpointState {
 impl CheckpointState {
     pub fn new(
         job_id: String,
+        checkpoint_url: String,
         checkpoint_id: i64,
         epoch: u32,
         min_epoch: u32,
@@ -39,6 +41,7 @@ impl CheckpointState {
     ) -> Self {
         Self {
             job_id,
+            checkpoint_url,
             checkpoint_id,
             epoch,
             min_epoch,
@@ -61,6 +64,7 @@ impl CheckpointState {
 
     pub async fn start(
         job_id: String,
+        checkpoint_url: String,
         checkpoint_id: i64,
         epoch: u32,
         min_epoch: u32,
@@ -71,6 +75,7 @@ impl CheckpointState {
 
         Ok(Self::new(
             job_id,
+            checkpoint_url,
             checkpoint_id,
             epoch,
             min_epoch,
@@ -269,19 +274,23 @@ impl CheckpointState {
             .values()
             .fold(0, |size, s| size + s.metadata.as_ref().unwrap().bytes);
 
-        StateBackend::write_operator_checkpoint_metadata(OperatorCheckpointMetadata {
-            job_id: self.job_id.to_string(),
-            operator_id: operator_id.clone(),
-            epoch: self.epoch,
-            start_time: to_micros(start_time),
-            finish_time: to_micros(finish_time),
-            min_watermark,
-            max_watermark,
-            has_state,
-            tables: tables.into_values().collect(),
-            backend_data: backend_data.into_values().collect(),
-            bytes: size,
-        })
+        StateBackend::write_operator_checkpoint_metadata(
+            &self.checkpoint_url,
+            OperatorCheckpointMetadata {
+                job_id: self.job_id.to_string(),
+                operator_id: operator_id.clone(),
+                epoch: self.epoch,
+                start_time: to_micros(start_time),
+                finish_time: to_micros(finish_time),
+                min_watermark,
+                max_watermark,
+                has_state,
+                tables: tables.into_values().collect(),
+                backend_data: backend_data.into_values().collect(),
+                bytes: size,
+                state_schema: None,
+            },
+        )
         .await;
 
         if let Some(op) = self.operator_details.get_mut(&operator_id) {
@@ -312,14 +321,17 @@ impl CheckpointState {
 
     pub async fn save_state(&self) -> anyhow::Result<()> {
         let finish_time = SystemTime::now();
-        StateBackend::write_checkpoint_metadata(CheckpointMetadata {
-            job_id: self.job_id.clone(),
-            epoch: self.epoch,
-            start_time: to_micros(self.start_time),
-            finish_time: to_micros(finish_time),
-            min_epoch: self.min_epoch,
-            operator_ids: self.completed_operators.iter().cloned().collect(),
-        })
+        StateBackend::write_checkpoint_metadata(
+            &self.checkpoint_url,
+            CheckpointMetadata {
+                job_id: self.job_id.clone(),
+                epoch: self.epoch,
+                start_time: to_micros(self.start_time),
+                finish_time: to_micros(finish_time),
+                min_epoch: self.min_epoch,
+                operator_ids: self.completed_operators.iter().cloned().collect(),
+            },
+        )
         .await;
         Ok(())
     }
@@ -0,0 +1,151 @@
+//! Summaries of the state in a checkpoint, for debugging state growth and skew.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use arroyo_rpc::grpc::backend_data::BackendData;
+use arroyo_rpc::grpc::{CheckpointMetadata, OperatorCheckpointMetadata, TableType};
+use arroyo_types::server_for_hash;
+use arroyo_types::state_schema::{OperatorStateSchema, StateType};
+use prost::Message;
+use serde_json::Value;
+
+use crate::migration::{hex, to_json};
+use crate::parquet::{
+    base_path, metadata_path, operator_path, storage_provider_for_url, ParquetBackend,
+};
+use crate::tables::BlindDataTuple;
+use crate::{DataOperation, FULL_KEY_RANGE};
+
+#[derive(Debug, Clone)]
+pub struct OperatorStateSummary {
+    pub operator_id: String,
+    pub bytes: u64,
+    pub tables: Vec<TableStateSummary>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableStateSummary {
+    pub name: String,
+    pub description: String,
+    pub table_type: TableType,
+    pub files: usize,
+    pub bytes: u64,
+    pub records: usize,
+    pub keys: usize,
+    /// The number of keys that belong to each subtask of the operator, for keyed tables
+    pub subtask_keys: Vec<usize>,
+    pub sample: Vec<StateEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateEntry {
+    pub timestamp: SystemTime,
+    pub key: Value,
+    pub value: Value,
+}
+
+/// Decodes a key or value as JSON if its type is known, falling back to hex if it isn't (or the
+/// value can't be read as that type)
+fn decode(typ: Option<&StateType>, bytes: &[u8]) -> Value {
+    typ.and_then(|t| to_json(t, bytes).ok())
+        .unwrap_or_else(|| Value::String(hex(bytes)))
+}
+
+/// Summarizes the state of each operator in the checkpoint `epoch` of `job_id`, stored under
+/// `checkpoint_url`. Keys are assigned to subtasks according to the operators' `parallelism`,
+/// and up to `sample` inserted entries of each table are decoded with the operators' `schemas`
+/// where they're known.
+pub async fn inspect_checkpoint(
+    checkpoint_url: &str,
+    job_id: &str,
+    epoch: u32,
+    parallelism: &HashMap<String, usize>,
+    schemas: &BTreeMap<String, OperatorStateSchema>,
+    sample: usize,
+) -> Result<Vec<OperatorStateSummary>> {
+    let storage = storage_provider_for_url(checkpoint_url).await?;
+
+    let data = storage
+        .get(&metadata_path(&base_path(job_id, epoch)))
+        .await
+        .map_err(|_| anyhow!("checkpoint {} of job {} not found", epoch, job_id))?;
+    let metadata = CheckpointMetadata::decode(&data[..])?;
+
+    let mut operators = vec![];
+    for operator_id in &metadata.operator_ids {
+        let data = storage
+            .get(&metadata_path(&operator_path(job_id, epoch, operator_id)))
+            .await?;
+        let operator_metadata = OperatorCheckpointMetadata::decode(&data[..])?;
+        let schema = schemas.get(operator_id);
+        let parallelism = parallelism.get(operator_id).copied().unwrap_or(1);
+
+        let mut tables = vec![];
+        for table in &operator_metadata.tables {
+            let files: Vec<_> = operator_metadata
+                .backend_data
+                .iter()
+                .filter_map(|b| match &b.backend_data {
+                    Some(BackendData::ParquetStore(p)) if p.table == table.name => Some(p),
+                    _ => None,
+                })
+                .collect();
+
+            let mut bytes = 0;
+            let mut tuples: Vec<BlindDataTuple> = vec![];
+            for file in &files {
+                let data = storage.get(&file.file).await?;
+                bytes += data.len() as u64;
+                tuples.extend(ParquetBackend::blind_tuples_from_parquet_bytes(
+                    data.to_vec(),
+                    &FULL_KEY_RANGE,
+                ));
+            }
+
+            let keyed = table.table_type() != TableType::Global;
+            let mut keys = HashSet::new();
+            let mut subtask_keys = vec![0; if keyed { parallelism } else { 0 }];
+            for tuple in &tuples {
+                if keys.insert(tuple.key.as_slice()) && keyed {
+                    subtask_keys[server_for_hash(tuple.key_hash, parallelism)] += 1;
+                }
+            }
+
+            // global tables aren't keyed by the operator's key
+            let key_type = schema.filter(|_| keyed).and_then(|s| s.key.as_ref());
+            let value_type = schema.and_then(|s| s.tables.get(&table.name));
+            let sample = tuples
+                .iter()
+                .filter(|t| matches!(t.operation, DataOperation::Insert))
+                .take(sample)
+                .map(|t| StateEntry {
+                    timestamp: t.timestamp,
+                    key: decode(key_type, &t.key),
+                    value: decode(value_type, &t.value),
+                })
+                .collect();
+
+            tables.push(TableStateSummary {
+                name: table.name.clone(),
+                description: table.description.clone(),
+                table_type: table.table_type(),
+                files: files.len(),
+                bytes,
+                records: tuples.len(),
+                keys: keys.len(),
+                subtask_keys,
+                sample,
+            });
+        }
+
+        operators.push(OperatorStateSummary {
+            operator_id: operator_id.clone(),
+            bytes: tables.iter().map(|t| t.bytes).sum(),
+            tables,
+        });
+    }
+
+    Ok(operators)
+}
@@ -22,17 +22,24 @@ use tables::time_key_map::{TimeKeyMap, TimeKeyMapCache};
 use tables::{global_keyed_map, key_time_multi_map, keyed_map, time_key_map};
 use tokio::sync::mpsc::Sender;
 
+mod backend;
 pub mod checkpoint_state;
 pub mod committing_state;
+pub mod inspect;
+mod local_cache;
 mod metrics;
+pub mod migration;
 pub mod parquet;
+pub mod rocksdb;
 mod subtask_state;
 pub mod tables;
 
 pub const BINCODE_CONFIG: Configuration = bincode::config::standard();
 pub const FULL_KEY_RANGE: RangeInclusive<u64> = 0..=u64::MAX;
+/// Reserved table holding the records that were overtaken by an unaligned checkpoint
+pub const IN_FLIGHT_TABLE: char = '_';
 
-pub type StateBackend = parquet::ParquetBackend;
+pub use backend::StateBackend;
 
 pub fn global_table(name: impl Into<String>, description: impl Into<String>) -> TableDescriptor {
     TableDescriptor {
@@ -120,20 +127,27 @@ pub trait BackingStore {
 
     async fn load_latest_checkpoint_metadata(job_id: &str) -> Option<CheckpointMetadata>;
 
-    async fn load_checkpoint_metadata(job_id: &str, epoch: u32) -> Option<CheckpointMetadata>;
+    async fn load_checkpoint_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        epoch: u32,
+    ) -> Option<CheckpointMetadata>;
 
     async fn load_operator_metadata(
+        checkpoint_url: &str,
         job_id: &str,
         operator_id: &str,
         epoch: u32,
     ) -> Option<OperatorCheckpointMetadata>;
 
     async fn new(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         tables: Vec<TableDescriptor>,
         control_tx: Sender<ControlResp>,
     ) -> Self;
     async fn from_checkpoint(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         metadata: CheckpointMetadata,
         tables: Vec<TableDescriptor>,
@@ -144,11 +158,18 @@ pub trait BackingStore {
 
     fn task_info(&self) -> &TaskInfo;
 
-    async fn write_operator_checkpoint_metadata(metadata: OperatorCheckpointMetadata);
+    // the URL of the storage that the task's checkpoints are written to
+    fn checkpoint_url(&self) -> &str;
 
-    async fn write_checkpoint_metadata(metadata: CheckpointMetadata);
+    async fn write_operator_checkpoint_metadata(
+        checkpoint_url: &str,
+        metadata: OperatorCheckpointMetadata,
+    );
+
+    async fn write_checkpoint_metadata(checkpoint_url: &str, metadata: CheckpointMetadata);
 
     async fn cleanup_checkpoint(
+        checkpoint_url: &str,
         metadata: CheckpointMetadata,
         old_min_epoch: u32,
         new_min_epoch: u32,
@@ -160,6 +181,15 @@ pub trait BackingStore {
         watermark: Option<SystemTime>,
     ) -> u32;
 
+    // writes to the checkpoint `epoch`, which must be unaligned and not yet completed
+    async fn write_in_flight<K: Key, V: Data>(
+        &mut self,
+        epoch: u32,
+        table: char,
+        key: &mut K,
+        value: &mut V,
+    );
+
     async fn get_data_tuples<K: Key, V: Data>(&self, table: char) -> Vec<DataTuple<K, V>>;
 
     async fn write_data_tuple<K: Key, V: Data>(
@@ -202,6 +232,17 @@ pub trait BackingStore {
     async fn get_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)>;
 
     async fn load_compacted(&mut self, compaction: CompactionResult);
+
+    // Backends that keep the values of keyed tables themselves, rather than having the tables
+    // cache them in memory, return true here; the keyed value methods below are only called on
+    // those. The values are still written to the checkpoint with `write_data_tuple`.
+    fn keeps_keyed_values(&self) -> bool;
+
+    fn get_keyed_value<K: Key, V: Data>(&self, table: char, key: &K) -> Option<V>;
+
+    fn put_keyed_value<K: Key, V: Data>(&mut self, table: char, key: &K, value: &V);
+
+    fn delete_keyed_value<K: Key>(&mut self, table: char, key: &K);
 }
 
 pub struct StateStore<S: BackingStore> {
@@ -220,11 +261,12 @@ pub fn hash_key<K: Hash>(key: &K) -> u64 {
 
 impl<S: BackingStore> StateStore<S> {
     pub async fn new(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         tables: Vec<TableDescriptor>,
         control_tx: Sender<ControlResp>,
     ) -> Self {
-        let backend = S::new(task_info, tables.clone(), control_tx).await;
+        let backend = S::new(checkpoint_url, task_info, tables.clone(), control_tx).await;
 
         StateStore {
             backend,
@@ -239,13 +281,20 @@ impl<S: BackingStore> StateStore<S> {
     }
 
     pub async fn from_checkpoint(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         checkpoint_metadata: CheckpointMetadata,
         tables: Vec<TableDescriptor>,
         tx: Sender<ControlResp>,
     ) -> Self {
-        let backend =
-            S::from_checkpoint(task_info, checkpoint_metadata.clone(), tables.clone(), tx).await;
+        let backend = S::from_checkpoint(
+            checkpoint_url,
+            task_info,
+            checkpoint_metadata.clone(),
+            tables.clone(),
+            tx,
+        )
+        .await;
 
         StateStore {
             backend,
@@ -389,7 +438,7 @@ impl<S: BackingStore> StateStore<S> {
             let cache: Box<dyn Any + Send> = match &self.restore_from {
                 Some(_restore_from) => {
                     let cache =
-                        KeyedStateCache::<K, V>::from_checkpoint(&self.backend, table).await;
+                        KeyedStateCache::<K, V>::from_checkpoint(&mut self.backend, table).await;
                     Box::new(cache)
                 }
                 None => Box::<keyed_map::KeyedStateCache<K, V>>::default(),
@@ -410,7 +459,51 @@ impl<S: BackingStore> StateStore<S> {
     }
 
     pub async fn checkpoint(&mut self, barrier: CheckpointBarrier, watermark: Option<SystemTime>) {
-        self.backend.checkpoint(barrier, watermark).await;
+        self.backend
+            .checkpoint(
+                CheckpointBarrier {
+                    unaligned: false,
+                    ..barrier
+                },
+                watermark,
+            )
+            .await;
+    }
+
+    /// Checkpoints the state as of the first barrier of an unaligned checkpoint. The checkpoint
+    /// isn't completed until the records that overtake it have been written with
+    /// [`StateStore::write_in_flight`].
+    pub async fn checkpoint_unaligned(
+        &mut self,
+        barrier: CheckpointBarrier,
+        watermark: Option<SystemTime>,
+    ) {
+        self.backend
+            .checkpoint(
+                CheckpointBarrier {
+                    unaligned: true,
+                    ..barrier
+                },
+                watermark,
+            )
+            .await;
+    }
+
+    /// Writes the records, as (logical input, encoded record), that were received on inputs
+    /// whose barrier hadn't yet arrived when the unaligned checkpoint `epoch` was taken
+    pub async fn write_in_flight(&mut self, epoch: u32, records: Vec<(usize, Vec<u8>)>) {
+        let mut key = self.task_info.task_index;
+        let mut value = (self.task_info.task_index, records);
+        self.backend
+            .write_in_flight(epoch, IN_FLIGHT_TABLE, &mut key, &mut value)
+            .await;
+    }
+
+    /// The in-flight records of the checkpoint being restored, by the subtask that wrote them
+    pub async fn restored_in_flight(&mut self) -> Vec<(usize, Vec<(usize, Vec<u8>)>)> {
+        let mut state: GlobalKeyedState<usize, (usize, Vec<(usize, Vec<u8>)>), _> =
+            self.get_global_keyed_state(IN_FLIGHT_TABLE).await;
+        state.get_all().into_iter().cloned().collect()
     }
 
     pub async fn load_compacted(&mut self, compaction: CompactionResult) {
@@ -420,10 +513,12 @@ impl<S: BackingStore> StateStore<S> {
 
 #[cfg(test)]
 mod test {
+    use arroyo_rpc::grpc::backend_data::BackendData;
     use arroyo_rpc::grpc::{
         CheckpointMetadata, OperatorCheckpointMetadata, TableDeleteBehavior, TableDescriptor,
         TableWriteBehavior,
     };
+    use std::collections::BTreeMap;
     use std::env;
     use test_case::test_case;
     use tokio::sync::mpsc::Receiver;
@@ -434,13 +529,17 @@ mod test {
     use tokio::sync::mpsc::channel;
 
     use crate::parquet::ParquetBackend;
+    use crate::rocksdb::RocksDbBackend;
     use crate::tables::key_time_multi_map::KeyTimeMultiMap;
     use crate::tables::keyed_map::KeyedState;
     use crate::tables::time_key_map::TimeKeyMap;
     use crate::{
         global_table, key_time_multi_map_table, timestamp_table, BackingStore, StateStore,
     };
-    use arroyo_types::{to_micros, CheckpointBarrier, TaskInfo};
+    use arroyo_types::{range_for_server, to_micros, CheckpointBarrier, TaskInfo};
+
+    const CHECKPOINT_URL: &str = "file:///tmp/arroyo";
+    const SAVEPOINT_URL: &str = "file:///tmp/arroyo-savepoints";
 
     fn default_tables() -> Vec<TableDescriptor> {
         vec![
@@ -468,6 +567,26 @@ mod test {
         let (tx, rx) = channel(10);
         (
             StateStore::<ParquetBackend>::new(
+                CHECKPOINT_URL,
+                &TaskInfo::for_test(
+                    &format!("test_job_{}", job_id),
+                    &format!("test_op_{}", operator_id),
+                ),
+                default_tables(),
+                tx,
+            )
+            .await,
+            rx,
+        )
+    }
+
+    async fn rocksdb_for_test() -> (StateStore<RocksDbBackend>, Receiver<ControlResp>) {
+        let job_id = rand::thread_rng().next_u64();
+        let operator_id = rand::thread_rng().next_u64();
+        let (tx, rx) = channel(10);
+        (
+            StateStore::<RocksDbBackend>::new(
+                CHECKPOINT_URL,
                 &TaskInfo::for_test(
                     &format!("test_job_{}", job_id),
                     &format!("test_op_{}", operator_id),
@@ -490,6 +609,7 @@ mod test {
 
         (
             StateStore::<ParquetBackend>::from_checkpoint(
+                CHECKPOINT_URL,
                 &task_info,
                 checkpoint_metadata.clone(),
                 default_tables(),
@@ -503,6 +623,7 @@ mod test {
     async fn do_compaction(job_id: &str, operator_id: &str, epoch: u32) -> CompactionResult {
         env::set_var("MIN_FILES_TO_COMPACT", "2");
         let result = match ParquetBackend::compact_operator(
+            CHECKPOINT_URL,
             1,
             job_id.to_string(),
             operator_id.to_string(),
@@ -535,6 +656,7 @@ mod test {
                     min_epoch: 0,
                     timestamp: SystemTime::now(),
                     then_stop: false,
+                    unaligned: false,
                 },
                 Some(SystemTime::UNIX_EPOCH),
             )
@@ -546,19 +668,23 @@ mod test {
             _ => panic!("Received unexpected message on command queue"),
         };
 
-        ParquetBackend::write_operator_checkpoint_metadata(OperatorCheckpointMetadata {
-            job_id: job_id.to_string(),
-            operator_id: operator_id.to_string(),
-            epoch,
-            start_time: to_micros(SystemTime::now()),
-            finish_time: to_micros(SystemTime::now()),
-            min_watermark: None,
-            max_watermark: None,
-            has_state: true,
-            tables: default_tables(),
-            backend_data: message.subtask_metadata.backend_data,
-            bytes: 5,
-        })
+        ParquetBackend::write_operator_checkpoint_metadata(
+            CHECKPOINT_URL,
+            OperatorCheckpointMetadata {
+                job_id: job_id.to_string(),
+                operator_id: operator_id.to_string(),
+                epoch,
+                start_time: to_micros(SystemTime::now()),
+                finish_time: to_micros(SystemTime::now()),
+                min_watermark: None,
+                max_watermark: None,
+                has_state: true,
+                tables: default_tables(),
+                backend_data: message.subtask_metadata.backend_data,
+                bytes: 5,
+                state_schema: None,
+            },
+        )
         .await;
 
         let checkpoint_metadata: CheckpointMetadata = CheckpointMetadata {
@@ -570,12 +696,14 @@ mod test {
             operator_ids: vec![operator_id.to_string()],
         };
 
-        ParquetBackend::write_checkpoint_metadata(checkpoint_metadata.clone()).await;
+        ParquetBackend::write_checkpoint_metadata(CHECKPOINT_URL, checkpoint_metadata.clone())
+            .await;
 
         checkpoint_metadata
     }
 
     #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
     #[tokio::test]
     async fn test_global(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
         let (mut ss, _rx) = p;
@@ -598,6 +726,7 @@ mod test {
     }
 
     #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
     #[tokio::test]
     async fn test_key_time_multi_map(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
         let (mut ss, mut rx) = p;
@@ -644,6 +773,7 @@ mod test {
     }
 
     #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
     #[tokio::test]
     async fn test_key_time_multi_map_compaction(
         p: (StateStore<impl BackingStore>, Receiver<ControlResp>),
@@ -732,6 +862,7 @@ mod test {
     }
 
     #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
     #[tokio::test]
     async fn test_time_key_map(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
         let (mut ss, mut rx) = p;
@@ -770,6 +901,7 @@ mod test {
     }
 
     #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
     #[tokio::test]
     async fn test_key_state_compaction(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
         let (mut ss, mut rx) = p;
@@ -840,7 +972,211 @@ mod test {
 
         // check that the key is gone
 
-        let ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+        let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
         assert_eq!(None, ks.get(&mut 1));
     }
+
+    #[test_case(parquet_for_test().await; "parquet store")]
+    #[test_case(rocksdb_for_test().await; "rocksdb store")]
+    #[tokio::test]
+    async fn test_rescaled_compaction(p: (StateStore<impl BackingStore>, Receiver<ControlResp>)) {
+        let (mut ss, mut rx) = p;
+        let job_id = ss.task_info.job_id.clone();
+        let operator_id = ss.task_info.operator_id.clone();
+
+        let mut ks: KeyedState<usize, i32, _> = ss.get_key_state('t').await;
+        for k in 0..100 {
+            ks.insert(SystemTime::UNIX_EPOCH, k, k as i32).await;
+        }
+
+        do_checkpoint(&mut ss, &job_id, &operator_id, 1, &mut rx).await;
+
+        // the single file written with a parallelism of 1 is split across the two partitions,
+        // even though neither has enough files to be compacted otherwise
+        env::set_var("MIN_FILES_TO_COMPACT", "2");
+        let result = ParquetBackend::compact_operator(
+            CHECKPOINT_URL,
+            2,
+            job_id.clone(),
+            operator_id.clone(),
+            1,
+        )
+        .await
+        .unwrap()
+        .expect("no compaction result");
+
+        assert_eq!(1, result.backend_data_to_drop.len());
+        assert_eq!(2, result.backend_data_to_load.len());
+        ss.load_compacted(result).await;
+
+        let checkpoint2 = do_checkpoint(&mut ss, &job_id, &operator_id, 2, &mut rx).await;
+
+        // every key is restored by the subtask it now belongs to
+        let mut restored_keys = 0;
+        for task_index in 0..2 {
+            let task_info = TaskInfo {
+                task_index,
+                parallelism: 2,
+                key_range: range_for_server(task_index, 2),
+                ..TaskInfo::for_test(&job_id, &operator_id)
+            };
+            let (tx, _rx) = channel(10);
+            let mut restored = StateStore::<ParquetBackend>::from_checkpoint(
+                CHECKPOINT_URL,
+                &task_info,
+                checkpoint2.clone(),
+                default_tables(),
+                tx,
+            )
+            .await;
+
+            let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+            for k in 0..100 {
+                if let Some(v) = ks.get(&k) {
+                    assert_eq!(k as i32, *v);
+                    restored_keys += 1;
+                }
+            }
+        }
+        assert_eq!(100, restored_keys);
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_key_state_restore() {
+        let (mut ss, mut rx) = rocksdb_for_test().await;
+        let job_id = ss.task_info.job_id.clone();
+        let operator_id = ss.task_info.operator_id.clone();
+
+        let mut ks: KeyedState<usize, i32, _> = ss.get_key_state('t').await;
+        ks.insert(SystemTime::UNIX_EPOCH, 1, 1).await;
+        ks.insert(SystemTime::UNIX_EPOCH, 2, 2).await;
+        ks.insert(SystemTime::UNIX_EPOCH, 1, 3).await;
+        ks.remove(&mut 2).await;
+        assert_eq!(Some(&3), ks.get(&1));
+        assert_eq!(None, ks.get(&2));
+
+        // the values are kept in the database rather than in the table's cache
+        assert_eq!(Some(3), ss.backend.get_keyed_value::<usize, i32>('t', &1));
+        assert_eq!(None, ss.backend.get_keyed_value::<usize, i32>('t', &2));
+
+        let checkpoint = do_checkpoint(&mut ss, &job_id, &operator_id, 1, &mut rx).await;
+
+        // the checkpoint can be restored by either backend; the restored task reopens the
+        // database, so the original one has to be closed first
+        drop(ss);
+        let (tx, _rx) = channel(10);
+        let mut restored = StateStore::<RocksDbBackend>::from_checkpoint(
+            CHECKPOINT_URL,
+            &TaskInfo::for_test(&job_id, &operator_id),
+            checkpoint.clone(),
+            default_tables(),
+            tx,
+        )
+        .await;
+        let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+        assert_eq!(Some(&3), ks.get(&1));
+        assert_eq!(None, ks.get(&2));
+
+        let (mut restored, _) =
+            parquet_for_test_from_checkpoint(&job_id, &operator_id, &checkpoint).await;
+        let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+        assert_eq!(Some(&3), ks.get(&1));
+        assert_eq!(None, ks.get(&2));
+    }
+
+    #[tokio::test]
+    async fn test_savepoint() {
+        let (mut ss, mut rx) = parquet_for_test().await;
+        let job_id = ss.task_info.job_id.clone();
+        let operator_id = ss.task_info.operator_id.clone();
+
+        let mut ks: KeyedState<usize, i32, _> = ss.get_key_state('t').await;
+        ks.insert(SystemTime::UNIX_EPOCH, 1, 1).await;
+        ks.insert(SystemTime::UNIX_EPOCH, 2, 2).await;
+        do_checkpoint(&mut ss, &job_id, &operator_id, 1, &mut rx).await;
+
+        let name = format!("test_savepoint_{}", rand::thread_rng().next_u64());
+        ParquetBackend::write_savepoint(
+            CHECKPOINT_URL,
+            SAVEPOINT_URL,
+            &job_id,
+            1,
+            &name,
+            &BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+
+        // start a new job from the savepoint, with an operator that wasn't in it
+        let new_job_id = format!("test_job_{}", rand::thread_rng().next_u64());
+        let new_operator_id = "new_op".to_string();
+        let metadata = ParquetBackend::restore_savepoint(
+            SAVEPOINT_URL,
+            CHECKPOINT_URL,
+            &name,
+            &new_job_id,
+            &[operator_id.clone(), new_operator_id.clone()],
+            &BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(new_job_id, metadata.job_id);
+        assert_eq!(1, metadata.epoch);
+        assert_eq!(1, metadata.min_epoch);
+        assert_eq!(
+            Some(metadata.clone()),
+            ParquetBackend::load_checkpoint_metadata(CHECKPOINT_URL, &new_job_id, 1).await
+        );
+
+        // the state was copied into the new job's checkpoints
+        let operator_metadata =
+            ParquetBackend::load_operator_metadata(CHECKPOINT_URL, &new_job_id, &operator_id, 1)
+                .await
+                .unwrap();
+        assert!(!operator_metadata.backend_data.is_empty());
+        for backend_data in operator_metadata.backend_data {
+            let Some(BackendData::ParquetStore(parquet_store)) = backend_data.backend_data else {
+                panic!("expected parquet data");
+            };
+            assert!(parquet_store
+                .file
+                .starts_with(&format!("{}/checkpoints/", new_job_id)));
+        }
+
+        let (mut restored, _) =
+            parquet_for_test_from_checkpoint(&new_job_id, &operator_id, &metadata).await;
+        let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+        assert_eq!(Some(&1), ks.get(&1));
+        assert_eq!(Some(&2), ks.get(&2));
+
+        // operators that weren't in the savepoint start without state
+        let (mut restored, _) =
+            parquet_for_test_from_checkpoint(&new_job_id, &new_operator_id, &metadata).await;
+        let mut ks: KeyedState<usize, i32, _> = restored.get_key_state('t').await;
+        assert_eq!(None, ks.get(&1));
+
+        assert!(ParquetBackend::restore_savepoint(
+            SAVEPOINT_URL,
+            CHECKPOINT_URL,
+            "missing_savepoint",
+            &new_job_id,
+            &[operator_id.clone()],
+            &BTreeMap::new()
+        )
+        .await
+        .is_err());
+
+        // savepoints are only written to the savepoint storage
+        assert!(ParquetBackend::restore_savepoint(
+            CHECKPOINT_URL,
+            CHECKPOINT_URL,
+            &name,
+            &new_job_id,
+            &[operator_id],
+            &BTreeMap::new()
+        )
+        .await
+        .is_err());
+    }
 }
@@ -0,0 +1,162 @@
+//! A copy of the most recently checkpointed state of a task on the worker's disk.
+//!
+//! When a task is restarted on the same node, it reads the files of the checkpoint it's
+//! restoring from the local copy rather than downloading them from the checkpoint store again,
+//! which for large state is most of the time spent recovering. Checkpoint files can be rewritten
+//! after a job is restored from an earlier checkpoint, so a local file is only used if the
+//! version of it in the checkpoint store (its size and modification time) is the one that was
+//! cached.
+
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use arroyo_storage::StorageProvider;
+use arroyo_types::{TaskInfo, LOCAL_STATE_DIR_ENV};
+use bytes::Bytes;
+use tracing::{debug, warn};
+
+const VERSION_SUFFIX: &str = ".version";
+
+pub struct LocalStateCache {
+    dir: PathBuf,
+}
+
+impl LocalStateCache {
+    /// The cache for the task, if a local state directory is configured
+    pub fn for_task(task_info: &TaskInfo) -> Option<Self> {
+        let base = env::var(LOCAL_STATE_DIR_ENV).ok()?;
+        Some(Self::new(base, task_info))
+    }
+
+    pub fn new(base: impl Into<PathBuf>, task_info: &TaskInfo) -> Self {
+        Self {
+            dir: base.into().join(&task_info.job_id).join(format!(
+                "{}-{}",
+                task_info.operator_id, task_info.task_index
+            )),
+        }
+    }
+
+    fn file_name(key: &str) -> String {
+        key.replace('/', "__")
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(Self::file_name(key))
+    }
+
+    fn version_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}{}", Self::file_name(key), VERSION_SUFFIX))
+    }
+
+    async fn remote_version(storage: &StorageProvider, key: &str) -> Result<String> {
+        let meta = storage.head(key).await?;
+        Ok(format!("{}:{}", meta.size, meta.last_modified.to_rfc3339()))
+    }
+
+    async fn write(&self, key: &str, bytes: &[u8], version: &str) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.path(key), bytes).await?;
+        tokio::fs::write(self.version_path(key), version).await?;
+        Ok(())
+    }
+
+    /// Keeps a copy of a file that has just been written to `storage`
+    pub async fn put(&self, storage: &StorageProvider, key: &str, bytes: &[u8]) {
+        let result = match Self::remote_version(storage, key).await {
+            Ok(version) => self.write(key, bytes, &version).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            warn!("failed to cache state file {} locally: {:?}", key, e);
+        }
+    }
+
+    /// Reads a file from the local copy if it's the current version of the file in `storage`,
+    /// and from `storage` otherwise, keeping a copy of it
+    pub async fn get(&self, storage: &StorageProvider, key: &str) -> Result<Bytes> {
+        let version = Self::remote_version(storage, key).await?;
+
+        if matches!(tokio::fs::read_to_string(self.version_path(key)).await,
+            Ok(cached) if cached == version)
+        {
+            if let Ok(bytes) = tokio::fs::read(self.path(key)).await {
+                debug!("restoring state file {} from local copy", key);
+                return Ok(bytes.into());
+            }
+        }
+
+        let bytes = storage.get(key).await?;
+        if let Err(e) = self.write(key, &bytes, &version).await {
+            warn!("failed to cache state file {} locally: {:?}", key, e);
+        }
+        Ok(bytes)
+    }
+
+    /// Removes the local copies of files that aren't among `keys`, which are those of the most
+    /// recent checkpoint
+    pub async fn retain(&self, keys: &HashSet<&str>) {
+        let keep: HashSet<_> = keys.iter().map(|key| Self::file_name(key)).collect();
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file = name.strip_suffix(VERSION_SUFFIX).unwrap_or(&name);
+            if !keep.contains(file) {
+                if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                    warn!("failed to remove local state file {}: {:?}", name, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_types::to_micros;
+    use std::time::SystemTime;
+
+    #[tokio::test]
+    async fn test_local_state_cache() {
+        let base = env::temp_dir().join(format!(
+            "arroyo-local-state-{}",
+            to_micros(SystemTime::now())
+        ));
+        let storage =
+            StorageProvider::for_url(&format!("file://{}/remote", base.to_string_lossy()))
+                .await
+                .unwrap();
+        let cache = LocalStateCache::new(base.join("local"), &TaskInfo::for_test("job", "op"));
+
+        storage.put("a", b"first".to_vec()).await.unwrap();
+        cache.put(&storage, "a", b"first").await;
+        storage.put("b", b"other".to_vec()).await.unwrap();
+
+        assert_eq!(&cache.get(&storage, "a").await.unwrap()[..], b"first");
+        assert!(cache.path("a").exists());
+
+        // files that aren't cached are downloaded and kept
+        assert_eq!(&cache.get(&storage, "b").await.unwrap()[..], b"other");
+        assert!(cache.path("b").exists());
+
+        // a file that has been rewritten since it was cached is downloaded again
+        storage.put("a", b"rewritten".to_vec()).await.unwrap();
+        assert_eq!(&cache.get(&storage, "a").await.unwrap()[..], b"rewritten");
+
+        cache.retain(&["b"].into_iter().collect()).await;
+        assert!(!cache.path("a").exists());
+        assert!(!cache.version_path("a").exists());
+        assert!(cache.path("b").exists());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -17,4 +17,28 @@ lazy_static! {
         &TABLE_LABELS_NAMES
     )
     .unwrap();
+    pub static ref ROCKSDB_SIZE_GAUGE: GaugeVec = register_gauge_vec!(
+        "arroyo_worker_rocksdb_size_bytes",
+        "Total size of the SST files of the RocksDB state",
+        &WORKER_LABELS_NAMES
+    )
+    .unwrap();
+    pub static ref ROCKSDB_LIVE_DATA_GAUGE: GaugeVec = register_gauge_vec!(
+        "arroyo_worker_rocksdb_live_data_bytes",
+        "Estimated size of the live data in the RocksDB state",
+        &WORKER_LABELS_NAMES
+    )
+    .unwrap();
+    pub static ref ROCKSDB_PENDING_COMPACTION_GAUGE: GaugeVec = register_gauge_vec!(
+        "arroyo_worker_rocksdb_pending_compaction_bytes",
+        "Estimated bytes that RocksDB has to rewrite to finish compacting the state",
+        &WORKER_LABELS_NAMES
+    )
+    .unwrap();
+    pub static ref ROCKSDB_RUNNING_COMPACTIONS_GAUGE: GaugeVec = register_gauge_vec!(
+        "arroyo_worker_rocksdb_running_compactions",
+        "Number of compactions running on the RocksDB state",
+        &WORKER_LABELS_NAMES
+    )
+    .unwrap();
 }
@@ -0,0 +1,587 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_types::decimal::Decimal;
+use arroyo_types::state_schema::{OperatorStateSchema, StateType};
+use serde_json::{json, Value};
+
+/// Determines how the state of an operator that was written with the types in `from` needs to
+/// change to be read with those in `to`. Returns the tables among `tables` (those that have data)
+/// whose values need to be migrated, along with their old and new types, or an error if the
+/// state can't be migrated.
+pub fn plan_operator_migration<'a>(
+    operator_id: &str,
+    from: &OperatorStateSchema,
+    to: &OperatorStateSchema,
+    tables: impl IntoIterator<Item = &'a str>,
+) -> Result<BTreeMap<String, (StateType, StateType)>> {
+    let mut migrations = BTreeMap::new();
+    if from == to {
+        return Ok(migrations);
+    }
+
+    if from.key != to.key {
+        bail!(
+            "the key of operator {} has changed, so its state can't be restored",
+            operator_id
+        );
+    }
+
+    if from.derived != to.derived {
+        bail!(
+            "the types of operator {} have changed in a way that its state can't be migrated",
+            operator_id
+        );
+    }
+
+    for table in tables {
+        match (from.tables.get(table), to.tables.get(table)) {
+            (Some(old), Some(new)) if old == new => {}
+            (Some(old), Some(new)) => {
+                check_migration(old, new).map_err(|e| {
+                    anyhow!(
+                        "table {} of operator {} can't be migrated: {}",
+                        table,
+                        operator_id,
+                        e
+                    )
+                })?;
+                migrations.insert(table.to_string(), (old.clone(), new.clone()));
+            }
+            (None, None) => {}
+            _ => bail!(
+                "table {} of operator {} has changed, so its state can't be restored",
+                table,
+                operator_id
+            ),
+        }
+    }
+
+    Ok(migrations)
+}
+
+/// Checks that values of type `from` can be migrated to `to`. Fields of structs are matched by
+/// name; new fields must be nullable, and are null in migrated values, and fields that have been
+/// removed are dropped. Non-nullable values can become nullable, but no other types can change.
+pub fn check_migration(from: &StateType, to: &StateType) -> Result<()> {
+    // every part of the old value needs to be read, even where it's unchanged
+    check_readable(from)?;
+
+    match (from, to) {
+        (from, to) if from == to => Ok(()),
+        (StateType::Optional(from), StateType::Optional(to)) => check_migration(from, to),
+        (from, StateType::Optional(to)) => check_migration(from, to),
+        (StateType::List(from), StateType::List(to)) => check_migration(from, to),
+        (StateType::Map(from_key, from_value), StateType::Map(to_key, to_value)) => {
+            // maps are sorted by their keys, so those can't change
+            if from_key != to_key {
+                bail!("map keys can't change from {:?} to {:?}", from_key, to_key);
+            }
+            check_migration(from_value, to_value)
+        }
+        (StateType::Struct(from), StateType::Struct(to)) => {
+            for field in to {
+                match from.iter().find(|f| f.name == field.name) {
+                    Some(old) => check_migration(&old.typ, &field.typ)
+                        .map_err(|e| anyhow!("field {}: {}", field.name, e))?,
+                    None if matches!(field.typ, StateType::Optional(_)) => {}
+                    None => bail!("new field {} isn't nullable", field.name),
+                }
+            }
+            Ok(())
+        }
+        (from, to) => bail!("{:?} can't be changed to {:?}", from, to),
+    }
+}
+
+fn check_readable(typ: &StateType) -> Result<()> {
+    match typ {
+        StateType::Optional(t) | StateType::List(t) => check_readable(t),
+        StateType::Map(k, v) => {
+            check_readable(k)?;
+            check_readable(v)
+        }
+        StateType::Struct(fields) => fields.iter().try_for_each(|f| check_readable(&f.typ)),
+        StateType::Opaque(name) => bail!("values of type {} can't be read", name),
+        _ => Ok(()),
+    }
+}
+
+/// Migrates a value encoded as `from` to `to`, which must have passed [`check_migration`]
+pub fn migrate(from: &StateType, to: &StateType, bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut out = Vec::with_capacity(bytes.len());
+    migrate_value(from, to, &mut reader, &mut out)?;
+    if reader.pos != bytes.len() {
+        bail!(
+            "value has {} bytes left over after reading it as {:?}",
+            bytes.len() - reader.pos,
+            from
+        );
+    }
+    Ok(out)
+}
+
+fn migrate_value(
+    from: &StateType,
+    to: &StateType,
+    reader: &mut Reader,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    if from == to {
+        out.extend_from_slice(reader.skip(from)?);
+        return Ok(());
+    }
+
+    match (from, to) {
+        (StateType::Optional(from), StateType::Optional(to)) => {
+            let tag = reader.option_tag()?;
+            out.push(tag);
+            if tag == 1 {
+                migrate_value(from, to, reader, out)?;
+            }
+        }
+        (from, StateType::Optional(to)) => {
+            out.push(1);
+            migrate_value(from, to, reader, out)?;
+        }
+        (StateType::List(from), StateType::List(to)) => {
+            let (len, encoded) = reader.varint()?;
+            out.extend_from_slice(encoded);
+            for _ in 0..len {
+                migrate_value(from, to, reader, out)?;
+            }
+        }
+        (StateType::Map(key, from), StateType::Map(_, to)) => {
+            let (len, encoded) = reader.varint()?;
+            out.extend_from_slice(encoded);
+            for _ in 0..len {
+                out.extend_from_slice(reader.skip(key)?);
+                migrate_value(from, to, reader, out)?;
+            }
+        }
+        (StateType::Struct(from), StateType::Struct(to)) => {
+            let mut fields = HashMap::new();
+            for field in from {
+                fields.insert(field.name.as_str(), (&field.typ, reader.skip(&field.typ)?));
+            }
+
+            for field in to {
+                match fields.get(field.name.as_str()) {
+                    Some((typ, bytes)) => {
+                        migrate_value(typ, &field.typ, &mut Reader { bytes, pos: 0 }, out)?
+                    }
+                    None if matches!(field.typ, StateType::Optional(_)) => out.push(0),
+                    None => bail!("no value for new field {}", field.name),
+                }
+            }
+        }
+        (from, to) => bail!("{:?} can't be changed to {:?}", from, to),
+    }
+
+    Ok(())
+}
+
+/// Renders a value encoded as `typ` as JSON, for inspecting state. Timestamps and durations are
+/// rendered as microseconds, bytes as hex, and maps as lists of key-value pairs.
+pub fn to_json(typ: &StateType, bytes: &[u8]) -> Result<Value> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let value = value_to_json(typ, &mut reader)?;
+    if reader.pos != bytes.len() {
+        bail!(
+            "value has {} bytes left over after reading it as {:?}",
+            bytes.len() - reader.pos,
+            typ
+        );
+    }
+    Ok(value)
+}
+
+/// Renders bytes whose type isn't known as hex
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn zigzag(n: u128) -> i128 {
+    (n >> 1) as i128 ^ -((n & 1) as i128)
+}
+
+fn integer_to_json<T: TryInto<i64> + TryInto<u64> + ToString + Copy>(n: T) -> Value {
+    if let Ok(n) = TryInto::<u64>::try_into(n) {
+        json!(n)
+    } else if let Ok(n) = TryInto::<i64>::try_into(n) {
+        json!(n)
+    } else {
+        // too large for a JSON number
+        json!(n.to_string())
+    }
+}
+
+fn value_to_json(typ: &StateType, reader: &mut Reader) -> Result<Value> {
+    Ok(match typ {
+        StateType::Bool => json!(reader.take(1)?[0] != 0),
+        StateType::U8 => json!(reader.take(1)?[0]),
+        StateType::I8 => json!(reader.take(1)?[0] as i8),
+        StateType::UnsignedInt => integer_to_json(reader.varint()?.0),
+        StateType::SignedInt => integer_to_json(zigzag(reader.varint()?.0)),
+        StateType::F32 => json!(f32::from_le_bytes(reader.take(4)?.try_into().unwrap())),
+        StateType::F64 => json!(f64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+        StateType::String => {
+            let (len, _) = reader.varint()?;
+            json!(String::from_utf8_lossy(reader.take(len as usize)?))
+        }
+        StateType::Bytes => {
+            let (len, _) = reader.varint()?;
+            json!(hex(reader.take(len as usize)?))
+        }
+        StateType::Timestamp | StateType::Duration => {
+            let (seconds, _) = reader.varint()?;
+            let (nanos, _) = reader.varint()?;
+            integer_to_json(seconds * 1_000_000 + nanos / 1_000)
+        }
+        StateType::Decimal => {
+            let (value, _) = reader.varint()?;
+            let scale = reader.take(1)?[0] as i8;
+            json!(Decimal::new(zigzag(value), scale).to_string())
+        }
+        StateType::Optional(t) => {
+            if reader.option_tag()? == 1 {
+                value_to_json(t, reader)?
+            } else {
+                Value::Null
+            }
+        }
+        StateType::List(t) => {
+            let (len, _) = reader.varint()?;
+            Value::Array(
+                (0..len)
+                    .map(|_| value_to_json(t, reader))
+                    .collect::<Result<_>>()?,
+            )
+        }
+        StateType::Map(k, v) => {
+            let (len, _) = reader.varint()?;
+            Value::Array(
+                (0..len)
+                    .map(|_| {
+                        Ok(json!([
+                            value_to_json(k, reader)?,
+                            value_to_json(v, reader)?
+                        ]))
+                    })
+                    .collect::<Result<_>>()?,
+            )
+        }
+        StateType::Struct(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|f| Ok((f.name.clone(), value_to_json(&f.typ, reader)?)))
+                .collect::<Result<_>>()?,
+        ),
+        StateType::Opaque(name) => bail!("values of type {} can't be read", name),
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            bail!("value ended unexpectedly");
+        }
+        let start = self.pos;
+        self.pos += n;
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    fn option_tag(&mut self) -> Result<u8> {
+        match self.take(1)?[0] {
+            tag @ (0 | 1) => Ok(tag),
+            tag => bail!("invalid option tag {}", tag),
+        }
+    }
+
+    /// Reads a varint, returning its value along with its encoding
+    fn varint(&mut self) -> Result<(u128, &'a [u8])> {
+        let start = self.pos;
+        let value = match self.take(1)?[0] {
+            b @ 0..=250 => b as u128,
+            251 => u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as u128,
+            252 => u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u128,
+            253 => u64::from_le_bytes(self.take(8)?.try_into().unwrap()) as u128,
+            254 => u128::from_le_bytes(self.take(16)?.try_into().unwrap()),
+            b => bail!("invalid varint byte {}", b),
+        };
+        Ok((value, &self.bytes[start..self.pos]))
+    }
+
+    /// Skips over a value of type `typ`, returning its encoding
+    fn skip(&mut self, typ: &StateType) -> Result<&'a [u8]> {
+        let start = self.pos;
+        match typ {
+            StateType::Bool | StateType::U8 | StateType::I8 => {
+                self.take(1)?;
+            }
+            StateType::UnsignedInt | StateType::SignedInt => {
+                self.varint()?;
+            }
+            StateType::F32 => {
+                self.take(4)?;
+            }
+            StateType::F64 => {
+                self.take(8)?;
+            }
+            StateType::String | StateType::Bytes => {
+                let (len, _) = self.varint()?;
+                self.take(len as usize)?;
+            }
+            // seconds and nanoseconds
+            StateType::Timestamp | StateType::Duration => {
+                self.varint()?;
+                self.varint()?;
+            }
+            // the unscaled value and the scale
+            StateType::Decimal => {
+                self.varint()?;
+                self.take(1)?;
+            }
+            StateType::Optional(t) => {
+                if self.option_tag()? == 1 {
+                    self.skip(t)?;
+                }
+            }
+            StateType::List(t) => {
+                let (len, _) = self.varint()?;
+                for _ in 0..len {
+                    self.skip(t)?;
+                }
+            }
+            StateType::Map(k, v) => {
+                let (len, _) = self.varint()?;
+                for _ in 0..len {
+                    self.skip(k)?;
+                    self.skip(v)?;
+                }
+            }
+            StateType::Struct(fields) => {
+                for field in fields {
+                    self.skip(&field.typ)?;
+                }
+            }
+            StateType::Opaque(name) => bail!("values of type {} can't be read", name),
+        }
+        Ok(&self.bytes[start..self.pos])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BINCODE_CONFIG;
+    use arroyo_types::state_schema::StateField;
+    use bincode::{Decode, Encode};
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Encode)]
+    struct Inner {
+        x: u64,
+    }
+
+    #[derive(Encode)]
+    struct Old {
+        id: i64,
+        name: String,
+        dropped: Vec<u32>,
+        time: SystemTime,
+        inner: Inner,
+        tags: Vec<Inner>,
+    }
+
+    #[derive(Decode, Debug, PartialEq)]
+    struct NewInner {
+        x: u64,
+        y: Option<String>,
+    }
+
+    #[derive(Decode, Debug, PartialEq)]
+    struct New {
+        time: SystemTime,
+        id: i64,
+        added: Option<f64>,
+        name: Option<String>,
+        inner: Option<NewInner>,
+        tags: Vec<NewInner>,
+    }
+
+    fn field(name: &str, typ: StateType) -> StateField {
+        StateField::new(name, typ)
+    }
+
+    fn old_type() -> StateType {
+        StateType::Struct(vec![
+            field("id", StateType::SignedInt),
+            field("name", StateType::String),
+            field("dropped", StateType::List(Box::new(StateType::UnsignedInt))),
+            field("time", StateType::Timestamp),
+            field(
+                "inner",
+                StateType::Struct(vec![field("x", StateType::UnsignedInt)]),
+            ),
+            field(
+                "tags",
+                StateType::List(Box::new(StateType::Struct(vec![field(
+                    "x",
+                    StateType::UnsignedInt,
+                )]))),
+            ),
+        ])
+    }
+
+    fn new_type() -> StateType {
+        let inner = StateType::Struct(vec![
+            field("x", StateType::UnsignedInt),
+            field("y", StateType::Optional(Box::new(StateType::String))),
+        ]);
+        StateType::Struct(vec![
+            field("time", StateType::Timestamp),
+            field("id", StateType::SignedInt),
+            field("added", StateType::Optional(Box::new(StateType::F64))),
+            field("name", StateType::Optional(Box::new(StateType::String))),
+            field("inner", StateType::Optional(Box::new(inner.clone()))),
+            field("tags", StateType::List(Box::new(inner))),
+        ])
+    }
+
+    #[test]
+    fn test_migrate_struct() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_690_000_000_123_456_789);
+        let old = Old {
+            id: -300,
+            name: "arroyo".to_string(),
+            dropped: vec![1, 1000, 100_000],
+            time,
+            inner: Inner { x: 70_000 },
+            tags: vec![Inner { x: 1 }, Inner { x: u64::MAX }],
+        };
+
+        check_migration(&old_type(), &new_type()).unwrap();
+
+        let bytes = bincode::encode_to_vec(&old, BINCODE_CONFIG).unwrap();
+        let migrated = migrate(&old_type(), &new_type(), &bytes).unwrap();
+        let (new, _): (New, usize) = bincode::decode_from_slice(&migrated, BINCODE_CONFIG).unwrap();
+
+        assert_eq!(
+            new,
+            New {
+                time,
+                id: -300,
+                added: None,
+                name: Some("arroyo".to_string()),
+                inner: Some(NewInner { x: 70_000, y: None }),
+                tags: vec![
+                    NewInner { x: 1, y: None },
+                    NewInner {
+                        x: u64::MAX,
+                        y: None
+                    }
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_incompatible_changes() {
+        let old = StateType::Struct(vec![field("a", StateType::SignedInt)]);
+
+        // new fields must be nullable
+        assert!(check_migration(
+            &old,
+            &StateType::Struct(vec![
+                field("a", StateType::SignedInt),
+                field("b", StateType::SignedInt)
+            ])
+        )
+        .is_err());
+
+        // and types can't change
+        assert!(check_migration(
+            &old,
+            &StateType::Struct(vec![field("a", StateType::String)])
+        )
+        .is_err());
+
+        // nor can values become non-nullable
+        assert!(check_migration(
+            &StateType::Optional(Box::new(StateType::Bool)),
+            &StateType::Bool
+        )
+        .is_err());
+
+        // and values that can't be read can't be migrated
+        assert!(check_migration(
+            &StateType::Struct(vec![field("a", StateType::Opaque("f16".to_string()))]),
+            &StateType::Struct(vec![])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_plan_operator_migration() {
+        let from = OperatorStateSchema {
+            key: Some(StateType::Struct(vec![field("k", StateType::String)])),
+            tables: [("l".to_string(), old_type()), ("r".to_string(), old_type())]
+                .into_iter()
+                .collect(),
+            derived: None,
+        };
+
+        let mut to = from.clone();
+        to.tables.insert("l".to_string(), new_type());
+
+        let migrations = plan_operator_migration("join", &from, &to, ["l", "r"]).unwrap();
+        assert_eq!(migrations.keys().collect::<Vec<_>>(), vec!["l"]);
+
+        // state is partitioned by key, so it can't be restored if that changes
+        to.key = Some(StateType::Struct(vec![field("k", StateType::SignedInt)]));
+        assert!(plan_operator_migration("join", &from, &to, ["l", "r"]).is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_690_000_000_123_456_789);
+        let old = Old {
+            id: -300,
+            name: "arroyo".to_string(),
+            dropped: vec![1, 1000, 100_000],
+            time,
+            inner: Inner { x: 70_000 },
+            tags: vec![Inner { x: 1 }, Inner { x: u64::MAX }],
+        };
+
+        let bytes = bincode::encode_to_vec(&old, BINCODE_CONFIG).unwrap();
+        assert_eq!(
+            to_json(&old_type(), &bytes).unwrap(),
+            serde_json::json!({
+                "id": -300,
+                "name": "arroyo",
+                "dropped": [1, 1000, 100_000],
+                "time": 1_690_000_000_123_456u64,
+                "inner": {"x": 70_000},
+                "tags": [{"x": 1}, {"x": u64::MAX}],
+            })
+        );
+
+        let decimal = arroyo_types::decimal::Decimal::new(-1250, 2);
+        let bytes = bincode::encode_to_vec(decimal, BINCODE_CONFIG).unwrap();
+        assert_eq!(
+            to_json(&StateType::Decimal, &bytes).unwrap(),
+            serde_json::json!("-12.50")
+        );
+
+        // values must be read completely
+        assert!(to_json(&StateType::Bool, &[1, 0]).is_err());
+    }
+}
@@ -1,10 +1,12 @@
+use crate::local_cache::LocalStateCache;
 use crate::metrics::CURRENT_FILES_GAUGE;
+use crate::migration::{migrate, plan_operator_migration};
 use crate::tables::{BlindDataTuple, Compactor, DataTuple};
 use crate::{
     hash_key, BackingStore, DataOperation, DeleteKeyOperation, DeleteTimeKeyOperation,
     DeleteTimeRangeOperation, DeleteValueOperation, StateStore, BINCODE_CONFIG,
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use arrow_array::RecordBatch;
 use arroyo_rpc::grpc::backend_data::BackendData;
 use arroyo_rpc::grpc::{
@@ -13,9 +15,11 @@ use arroyo_rpc::grpc::{
 };
 use arroyo_rpc::{grpc, CheckpointCompleted, CompactionResult, ControlResp};
 use arroyo_storage::StorageProvider;
+use arroyo_types::state_schema::OperatorStateSchema;
 use arroyo_types::{
-    from_nanos, range_for_server, to_micros, to_nanos, CheckpointBarrier, Data, Key, TaskInfo,
-    CHECKPOINT_URL_ENV, S3_ENDPOINT_ENV, S3_REGION_ENV,
+    from_micros, from_nanos, range_for_server, to_micros, to_nanos, CheckpointBarrier, Data, Key,
+    TaskInfo, AZURE_STORAGE_ACCOUNT_ENV, CHECKPOINT_URL_ENV, LOCAL_STATE_DIR_ENV, S3_ENDPOINT_ENV,
+    S3_REGION_ENV,
 };
 use bincode::config;
 use bytes::Bytes;
@@ -36,20 +40,20 @@ use tokio::sync::Mutex;
 use tracing::{debug, info, warn};
 
 pub const FULL_KEY_RANGE: RangeInclusive<u64> = 0..=u64::MAX;
-pub const GENERATIONS_TO_COMPACT: u32 = 1; // only compact generation 0 files
+pub const GENERATIONS_TO_COMPACT: u32 = 3; // compact files of generations 0, 1 and 2
 
-async fn get_storage_provider() -> anyhow::Result<StorageProvider> {
-    // TODO: this should be encoded in the config so that the controller doesn't need
-    // to be synchronized with the workers
-    let storage_url =
-        env::var(CHECKPOINT_URL_ENV).unwrap_or_else(|_| "file:///tmp/arroyo".to_string());
+/// The checkpoint URL that this process was configured with. For the controller and the API,
+/// that's the cluster's checkpoint storage, which holds savepoints and the checkpoints of jobs
+/// that don't set their own; workers are started with the URL of their job.
+pub fn checkpoint_url_from_env() -> String {
+    env::var(CHECKPOINT_URL_ENV).unwrap_or_else(|_| "file:///tmp/arroyo".to_string())
+}
 
-    StorageProvider::for_url(&storage_url)
-        .await
-        .context(format!(
-            "failed to construct checkpoint backend for URL {}",
-            storage_url
-        ))
+pub(crate) async fn storage_provider_for_url(storage_url: &str) -> anyhow::Result<StorageProvider> {
+    StorageProvider::for_url(storage_url).await.context(format!(
+        "failed to construct checkpoint backend for URL {}",
+        storage_url
+    ))
 }
 
 pub struct ParquetBackend {
@@ -60,21 +64,27 @@ pub struct ParquetBackend {
     writer: ParquetWriter,
     task_info: TaskInfo,
     tables: HashMap<char, TableDescriptor>,
+    checkpoint_url: String,
     storage: StorageProvider,
+    local_cache: Option<LocalStateCache>,
 }
 
-fn base_path(job_id: &str, epoch: u32) -> String {
+pub(crate) fn base_path(job_id: &str, epoch: u32) -> String {
     format!("{}/checkpoints/checkpoint-{:0>7}", job_id, epoch)
 }
 
-fn metadata_path(path: &str) -> String {
+pub(crate) fn metadata_path(path: &str) -> String {
     format!("{}/metadata", path)
 }
 
-fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
+pub(crate) fn operator_path(job_id: &str, epoch: u32, operator: &str) -> String {
     format!("{}/operator-{}", base_path(job_id, epoch), operator)
 }
 
+fn savepoint_path(name: &str) -> String {
+    format!("savepoints/{}", name)
+}
+
 fn table_checkpoint_path(task_info: &TaskInfo, table: char, epoch: u32, compacted: bool) -> String {
     format!(
         "{}/table-{}-{:0>3}{}",
@@ -95,13 +105,21 @@ impl BackingStore for ParquetBackend {
         &self.task_info
     }
 
+    fn checkpoint_url(&self) -> &str {
+        &self.checkpoint_url
+    }
+
     async fn load_latest_checkpoint_metadata(_job_id: &str) -> Option<CheckpointMetadata> {
         todo!()
     }
 
     // TODO: should this be a Result, rather than an option?
-    async fn load_checkpoint_metadata(job_id: &str, epoch: u32) -> Option<CheckpointMetadata> {
-        let storage_client = get_storage_provider().await.unwrap();
+    async fn load_checkpoint_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        epoch: u32,
+    ) -> Option<CheckpointMetadata> {
+        let storage_client = storage_provider_for_url(checkpoint_url).await.unwrap();
         let data = storage_client
             .get(&metadata_path(&base_path(job_id, epoch)))
             .await
@@ -111,11 +129,12 @@ impl BackingStore for ParquetBackend {
     }
 
     async fn load_operator_metadata(
+        checkpoint_url: &str,
         job_id: &str,
         operator_id: &str,
         epoch: u32,
     ) -> Option<OperatorCheckpointMetadata> {
-        let storage_client = get_storage_provider().await.unwrap();
+        let storage_client = storage_provider_for_url(checkpoint_url).await.unwrap();
         let data = storage_client
             .get(&metadata_path(&operator_path(job_id, epoch, operator_id)))
             .await
@@ -123,8 +142,11 @@ impl BackingStore for ParquetBackend {
         Some(OperatorCheckpointMetadata::decode(&data[..]).unwrap())
     }
 
-    async fn write_operator_checkpoint_metadata(metadata: OperatorCheckpointMetadata) {
-        let storage_client = get_storage_provider().await.unwrap();
+    async fn write_operator_checkpoint_metadata(
+        checkpoint_url: &str,
+        metadata: OperatorCheckpointMetadata,
+    ) {
+        let storage_client = storage_provider_for_url(checkpoint_url).await.unwrap();
         let path = metadata_path(&operator_path(
             &metadata.job_id,
             metadata.epoch,
@@ -137,9 +159,9 @@ impl BackingStore for ParquetBackend {
             .unwrap();
     }
 
-    async fn write_checkpoint_metadata(metadata: CheckpointMetadata) {
+    async fn write_checkpoint_metadata(checkpoint_url: &str, metadata: CheckpointMetadata) {
         debug!("writing checkpoint {:?}", metadata);
-        let storage_client = get_storage_provider().await.unwrap();
+        let storage_client = storage_provider_for_url(checkpoint_url).await.unwrap();
         let path = metadata_path(&base_path(&metadata.job_id, metadata.epoch));
         // TODO: propagate error
         storage_client
@@ -149,11 +171,12 @@ impl BackingStore for ParquetBackend {
     }
 
     async fn new(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         tables: Vec<TableDescriptor>,
         tx: Sender<ControlResp>,
     ) -> Self {
-        let storage = get_storage_provider().await.unwrap();
+        let storage = storage_provider_for_url(checkpoint_url).await.unwrap();
         Self {
             epoch: 1,
             min_epoch: 1,
@@ -170,25 +193,32 @@ impl BackingStore for ParquetBackend {
                 .into_iter()
                 .map(|table| (table.name.clone().chars().next().unwrap(), table))
                 .collect(),
+            checkpoint_url: checkpoint_url.to_string(),
             storage,
+            local_cache: LocalStateCache::for_task(task_info),
         }
     }
 
     async fn from_checkpoint(
+        checkpoint_url: &str,
         task_info: &TaskInfo,
         metadata: CheckpointMetadata,
         tables: Vec<TableDescriptor>,
         control_tx: Sender<ControlResp>,
     ) -> Self {
-        let operator_metadata =
-            Self::load_operator_metadata(&task_info.job_id, &task_info.operator_id, metadata.epoch)
-                .await
-                .unwrap_or_else(|| {
-                    panic!(
-                        "missing metadata for operator {}, epoch {}",
-                        task_info.operator_id, metadata.epoch
-                    )
-                });
+        let operator_metadata = Self::load_operator_metadata(
+            checkpoint_url,
+            &task_info.job_id,
+            &task_info.operator_id,
+            metadata.epoch,
+        )
+        .await
+        .unwrap_or_else(|| {
+            panic!(
+                "missing metadata for operator {}, epoch {}",
+                task_info.operator_id, metadata.epoch
+            )
+        });
         let mut current_files: HashMap<char, BTreeMap<u32, Vec<ParquetStoreData>>> = HashMap::new();
         let tables: HashMap<char, TableDescriptor> = tables
             .into_iter()
@@ -222,7 +252,7 @@ impl BackingStore for ParquetBackend {
 
         let writer_current_files = current_files.clone();
 
-        let storage = get_storage_provider().await.unwrap();
+        let storage = storage_provider_for_url(checkpoint_url).await.unwrap();
         Self {
             epoch: metadata.epoch + 1,
             min_epoch: metadata.min_epoch,
@@ -236,7 +266,9 @@ impl BackingStore for ParquetBackend {
             ),
             task_info: task_info.clone(),
             tables,
+            checkpoint_url: checkpoint_url.to_string(),
             storage,
+            local_cache: LocalStateCache::for_task(task_info),
         }
     }
 
@@ -245,6 +277,7 @@ impl BackingStore for ParquetBackend {
     }
 
     async fn cleanup_checkpoint(
+        checkpoint_url: &str,
         mut metadata: CheckpointMetadata,
         old_min_epoch: u32,
         min_epoch: u32,
@@ -260,6 +293,7 @@ impl BackingStore for ParquetBackend {
             .iter()
             .map(|operator_id| {
                 Self::cleanup_operator(
+                    checkpoint_url,
                     metadata.job_id.clone(),
                     operator_id.clone(),
                     old_min_epoch,
@@ -268,7 +302,7 @@ impl BackingStore for ParquetBackend {
             })
             .collect();
 
-        let storage_client = Mutex::new(get_storage_provider().await?);
+        let storage_client = Mutex::new(storage_provider_for_url(checkpoint_url).await?);
 
         // wait for all of the futures to complete
         while let Some(result) = futures.next().await {
@@ -298,7 +332,7 @@ impl BackingStore for ParquetBackend {
                 .await?;
         }
         metadata.min_epoch = min_epoch;
-        Self::write_checkpoint_metadata(metadata).await;
+        Self::write_checkpoint_metadata(checkpoint_url, metadata).await;
         Ok(())
     }
 
@@ -309,13 +343,43 @@ impl BackingStore for ParquetBackend {
     ) -> u32 {
         assert_eq!(barrier.epoch, self.epoch);
         self.writer
-            .checkpoint(self.epoch, barrier.timestamp, watermark, barrier.then_stop)
+            .checkpoint(
+                self.epoch,
+                barrier.timestamp,
+                watermark,
+                barrier.then_stop,
+                barrier.unaligned,
+            )
             .await;
         self.epoch += 1;
         self.min_epoch = barrier.min_epoch;
         self.epoch - 1
     }
 
+    async fn write_in_flight<K: Key, V: Data>(
+        &mut self,
+        epoch: u32,
+        table: char,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        let (key_hash, key_bytes, value_bytes) = Self::get_hash_and_bytes(key, value);
+
+        self.writer
+            .write_in_flight(
+                epoch,
+                ParquetWrite {
+                    table,
+                    key_hash,
+                    timestamp: SystemTime::UNIX_EPOCH,
+                    key: key_bytes,
+                    data: value_bytes,
+                    operation: DataOperation::Insert,
+                },
+            )
+            .await;
+    }
+
     async fn get_data_tuples<K: Key, V: Data>(&self, table: char) -> Vec<DataTuple<K, V>> {
         let mut result = vec![];
         match self.tables.get(&table).unwrap().table_type() {
@@ -327,7 +391,7 @@ impl BackingStore for ParquetBackend {
                     return vec![];
                 };
                 for file in files.values().flatten() {
-                    let bytes = self.storage.get(&file.file).await.unwrap_or_else(|_| {
+                    let bytes = self.get_file(&file.file).await.unwrap_or_else(|_| {
                         panic!("unable to find file {} in checkpoint", file.file)
                     });
                     result.append(
@@ -483,9 +547,30 @@ impl BackingStore for ParquetBackend {
     async fn load_compacted(&mut self, compaction: CompactionResult) {
         self.writer.load_compacted_data(compaction).await;
     }
+
+    // keyed tables cache their values in memory on top of this backend, so it has none of its own
+    fn keeps_keyed_values(&self) -> bool {
+        false
+    }
+
+    fn get_keyed_value<K: Key, V: Data>(&self, _table: char, _key: &K) -> Option<V> {
+        None
+    }
+
+    fn put_keyed_value<K: Key, V: Data>(&mut self, _table: char, _key: &K, _value: &V) {}
+
+    fn delete_keyed_value<K: Key>(&mut self, _table: char, _key: &K) {}
 }
 
 impl ParquetBackend {
+    /// Reads a file of the checkpoint, from the local copy of the task's state if there is one
+    async fn get_file(&self, key: &str) -> Result<Bytes> {
+        match &self.local_cache {
+            Some(cache) => cache.get(&self.storage, key).await,
+            None => Ok(self.storage.get(key).await?),
+        }
+    }
+
     /// Get all key-value pairs in the given table.
     /// Looks at the operation to determine if the key-value pair should be included.
     async fn get_key_values_for_key_range<K: Key, V: Data>(
@@ -499,8 +584,7 @@ impl ParquetBackend {
         let mut state_map = HashMap::new();
         for file in files.values().flatten() {
             let bytes = self
-                .storage
-                .get(&file.file)
+                .get_file(&file.file)
                 .await
                 .unwrap_or_else(|_| panic!("unable to find file {} in checkpoint", file.file))
                 .into();
@@ -549,6 +633,8 @@ impl ParquetBackend {
         storage_client: StorageProvider,
         new_min_epoch: u32,
         table_descriptor: &TableDescriptor,
+        full: bool,
+        watermark: Option<SystemTime>,
     ) -> Option<ParquetStoreData> {
         // accumulate this partition's tuples from all the table's files
         // (spread across multiple epochs and files)
@@ -563,10 +649,26 @@ impl ParquetBackend {
             tuples_in.extend(tuples);
         }
 
+        // drop the data that has outlived the table's retention, which can no longer be read
+        if let Some(watermark) = watermark {
+            if table_descriptor.delete_behavior() == TableDeleteBehavior::NoReadsBeforeWatermark {
+                let cutoff = from_micros(
+                    to_micros(watermark).saturating_sub(table_descriptor.retention_micros),
+                );
+                tuples_in.retain(|tuple| {
+                    tuple.timestamp >= cutoff || tuple.operation != DataOperation::Insert
+                });
+            }
+        }
+
         // do the compaction
         let compactor: Compactor = Compactor::for_table_type(table_descriptor.table_type());
         let tuples_length = tuples_in.len();
-        let tuples_out = compactor.compact_tuples(tuples_in);
+        let tuples_out = if full {
+            compactor.compact_all_tuples(tuples_in)
+        } else {
+            compactor.compact_tuples(tuples_in)
+        };
 
         info!(
             message = "Compaction summary for operator",
@@ -576,7 +678,9 @@ impl ParquetBackend {
             task_index = task.task_index,
             tuples_in = tuples_length,
             tuples_out = tuples_out.len(),
-            compacted = tuples_length - tuples_out.len()
+            compacted = tuples_length - tuples_out.len(),
+            generation = generation + 1,
+            full
         );
 
         let mut parquet_writer =
@@ -599,8 +703,31 @@ impl ParquetBackend {
         p
     }
 
+    /// Returns the highest generation that a table partition's files should be compacted up to,
+    /// if any. Each generation is compacted once it has accumulated `min_files` files, so the
+    /// files of a long-running job are merged into successively larger ones rather than
+    /// growing in number with every checkpoint.
+    fn generation_to_compact(files: &[&ParquetStoreData], min_files: usize) -> Option<u32> {
+        (0..GENERATIONS_TO_COMPACT)
+            .take_while(|generation| {
+                files
+                    .iter()
+                    .filter(|file| file.generation == *generation)
+                    .count()
+                    >= min_files
+            })
+            .last()
+    }
+
+    /// Whether all of a file's keys belong to the partition with `key_range`. Files written
+    /// before a job was rescaled may also hold keys that now belong to other partitions.
+    fn within_key_range(file: &ParquetStoreData, key_range: &RangeInclusive<u64>) -> bool {
+        *key_range.start() <= file.min_routing_key && file.max_routing_key <= *key_range.end()
+    }
+
     /// Called after a checkpoint is committed
     pub async fn compact_operator(
+        checkpoint_url: &str,
         parallelism: usize,
         job_id: String,
         operator_id: String,
@@ -611,7 +738,7 @@ impl ParquetBackend {
             .parse()
             .unwrap();
 
-        let checkpoint_metadata = Self::load_checkpoint_metadata(&job_id, epoch)
+        let checkpoint_metadata = Self::load_checkpoint_metadata(checkpoint_url, &job_id, epoch)
             .await
             .unwrap_or_else(|| {
                 panic!(
@@ -621,7 +748,7 @@ impl ParquetBackend {
             });
 
         let operator_checkpoint_metadata =
-            Self::load_operator_metadata(&job_id, &operator_id, epoch)
+            Self::load_operator_metadata(checkpoint_url, &job_id, &operator_id, epoch)
                 .await
                 .expect("expect operator metadata to still be present");
 
@@ -646,6 +773,7 @@ impl ParquetBackend {
             // we must access the data through the state store because
             // it keeps track of the table -> file relation
             let mut state_store = StateStore::<ParquetBackend>::from_checkpoint(
+                checkpoint_url,
                 &task,
                 checkpoint_metadata.clone(),
                 operator_checkpoint_metadata.tables.clone(),
@@ -655,54 +783,85 @@ impl ParquetBackend {
 
             // for each table this operator has, generate this partition's compacted file
             for (table_char, epoch_files) in state_store.backend.current_files.drain() {
-                for generation in 0..GENERATIONS_TO_COMPACT {
-                    // get just the files for this table
-                    let generation_files: Vec<ParquetStoreData> = epoch_files
-                        .values()
-                        .flatten()
-                        .filter(|file| file.generation == generation)
-                        .cloned()
-                        .collect();
+                let table_descriptor = state_store.table_descriptors.get(&table_char).unwrap();
+                if table_descriptor.table_type() == TableType::Global {
+                    // global tables are written in full on every checkpoint
+                    continue;
+                }
 
-                    if generation_files.len() < min_files_to_compact {
+                let files: Vec<&ParquetStoreData> = epoch_files.values().flatten().collect();
+
+                // A file that's shared with other partitions is dropped once it's compacted, so
+                // every partition that it has keys for must compact it. After the job has been
+                // rescaled, each partition's files are all compacted into one holding only its
+                // keys, which repartitions the state for the new parallelism.
+                let rescaled = files
+                    .iter()
+                    .any(|file| !Self::within_key_range(file, &key_range));
+                let generation = if rescaled {
+                    files
+                        .iter()
+                        .map(|file| file.generation)
+                        .max()
+                        .unwrap_or_default()
+                        .min(GENERATIONS_TO_COMPACT - 1)
+                } else {
+                    let Some(generation) =
+                        Self::generation_to_compact(&files, min_files_to_compact)
+                    else {
                         continue;
-                    }
+                    };
+                    generation
+                };
 
-                    info!(
-                        message = "Compacting table partition",
-                        job_id,
-                        operator_id,
-                        table = table_char.to_string(),
-                        epoch,
-                        index,
-                        files = generation_files.len(),
+                // Files are compacted along with all of the newer, lower generation files, so
+                // that a higher generation's files always hold older data than a lower one's. If
+                // that's every file, there's no older state left for deletes to apply to.
+                let generation_files: Vec<ParquetStoreData> = files
+                    .iter()
+                    .filter(|file| rescaled || file.generation <= generation)
+                    .map(|file| (*file).clone())
+                    .collect();
+                let full = generation_files.len() == files.len();
+
+                info!(
+                    message = "Compacting table partition",
+                    job_id,
+                    operator_id,
+                    table = table_char.to_string(),
+                    epoch,
+                    index,
+                    generation,
+                    rescaled,
+                    files = generation_files.len(),
+                );
+
+                for file in &generation_files {
+                    backend_data_to_drop.insert(
+                        file.file.clone(),
+                        grpc::BackendData {
+                            backend_data: Some(BackendData::ParquetStore(file.clone())),
+                        },
                     );
+                }
 
-                    for file in &generation_files {
-                        backend_data_to_drop.insert(
-                            file.file.clone(),
-                            grpc::BackendData {
-                                backend_data: Some(BackendData::ParquetStore(file.clone())),
-                            },
-                        );
-                    }
-
-                    let compact_parquet_store_data = ParquetBackend::compact_table_partition(
-                        table_char,
-                        task.clone(),
-                        generation,
-                        generation_files,
-                        get_storage_provider().await?,
-                        epoch,
-                        state_store.table_descriptors.get(&table_char).unwrap(),
-                    )
-                    .await;
+                let compact_parquet_store_data = ParquetBackend::compact_table_partition(
+                    table_char,
+                    task.clone(),
+                    generation,
+                    generation_files,
+                    storage_provider_for_url(checkpoint_url).await?,
+                    epoch,
+                    table_descriptor,
+                    full,
+                    operator_checkpoint_metadata.min_watermark.map(from_micros),
+                )
+                .await;
 
-                    if let Some(p) = compact_parquet_store_data {
-                        backend_data_to_load.push(grpc::BackendData {
-                            backend_data: Some(BackendData::ParquetStore(p)),
-                        });
-                    }
+                if let Some(p) = compact_parquet_store_data {
+                    backend_data_to_load.push(grpc::BackendData {
+                        backend_data: Some(BackendData::ParquetStore(p)),
+                    });
                 }
             }
         }
@@ -721,13 +880,14 @@ impl ParquetBackend {
 
     /// Delete files no longer referenced by the new min epoch
     pub async fn cleanup_operator(
+        checkpoint_url: &str,
         job_id: String,
         operator_id: String,
         old_min_epoch: u32,
         new_min_epoch: u32,
     ) -> Result<String> {
         let paths_to_keep: HashSet<String> =
-            Self::load_operator_metadata(&job_id, &operator_id, new_min_epoch)
+            Self::load_operator_metadata(checkpoint_url, &job_id, &operator_id, new_min_epoch)
                 .await
                 .expect("expect new_min_epoch metadata to still be present")
                 .backend_data
@@ -742,11 +902,16 @@ impl ParquetBackend {
                 .collect();
 
         let mut deleted_paths = HashSet::new();
-        let storage_client = get_storage_provider().await?;
+        let storage_client = storage_provider_for_url(checkpoint_url).await?;
 
         for epoch_to_remove in old_min_epoch..new_min_epoch {
-            let Some(metadata) =
-                Self::load_operator_metadata(&job_id, &operator_id, epoch_to_remove).await
+            let Some(metadata) = Self::load_operator_metadata(
+                checkpoint_url,
+                &job_id,
+                &operator_id,
+                epoch_to_remove,
+            )
+            .await
             else {
                 continue;
             };
@@ -768,6 +933,229 @@ impl ParquetBackend {
         Ok(operator_id)
     }
 
+    /// Copies the state of the job's checkpoint at `epoch` to the savepoint `name`, which is
+    /// kept until it's deleted, rather than cleaned up along with the job's checkpoints. The
+    /// schemas of the job's state are kept with it, so that it can be migrated if the savepoint
+    /// is restored into a pipeline whose types have changed. The checkpoint is read from
+    /// `checkpoint_url`, and the savepoint is written to `savepoint_url`.
+    pub async fn write_savepoint(
+        checkpoint_url: &str,
+        savepoint_url: &str,
+        job_id: &str,
+        epoch: u32,
+        name: &str,
+        schemas: &BTreeMap<String, OperatorStateSchema>,
+    ) -> Result<()> {
+        let storage = storage_provider_for_url(checkpoint_url).await?;
+        let savepoint_storage = storage_provider_for_url(savepoint_url).await?;
+        let metadata = Self::copy_state(
+            &storage,
+            &savepoint_storage,
+            &base_path(job_id, epoch),
+            &format!("{}/checkpoints/", job_id),
+            &savepoint_path(name),
+            &format!("{}/data/", savepoint_path(name)),
+            job_id,
+            schemas,
+        )
+        .await?;
+
+        info!(
+            message = "Wrote savepoint",
+            job_id,
+            epoch,
+            name,
+            operators = metadata.operator_ids.len()
+        );
+        Ok(())
+    }
+
+    /// Copies the state of the savepoint `name` into the checkpoints of `job_id`, so that the job
+    /// can be restored from it like from one of its own checkpoints. Operators of the job that
+    /// aren't in the savepoint start without state, and operators in the savepoint that aren't
+    /// in the job are dropped. State whose types differ from the job's `schemas` is migrated
+    /// to them, or an error is returned if it can't be. The savepoint is read from
+    /// `savepoint_url`, and the checkpoint is written to `checkpoint_url`. Returns the metadata
+    /// of the restored checkpoint.
+    pub async fn restore_savepoint(
+        savepoint_url: &str,
+        checkpoint_url: &str,
+        name: &str,
+        job_id: &str,
+        operator_ids: &[String],
+        schemas: &BTreeMap<String, OperatorStateSchema>,
+    ) -> Result<CheckpointMetadata> {
+        let savepoint_storage = storage_provider_for_url(savepoint_url).await?;
+        let storage = storage_provider_for_url(checkpoint_url).await?;
+        let data = savepoint_storage
+            .get(&metadata_path(&savepoint_path(name)))
+            .await
+            .context(format!("savepoint {} not found", name))?;
+        let epoch = CheckpointMetadata::decode(&data[..])?.epoch;
+
+        let mut metadata = Self::copy_state(
+            &savepoint_storage,
+            &storage,
+            &savepoint_path(name),
+            &format!("{}/data/", savepoint_path(name)),
+            &base_path(job_id, epoch),
+            &format!("{}/checkpoints/", job_id),
+            job_id,
+            schemas,
+        )
+        .await?;
+
+        for operator_id in operator_ids {
+            if !metadata.operator_ids.contains(operator_id) {
+                Self::write_operator_checkpoint_metadata(
+                    checkpoint_url,
+                    OperatorCheckpointMetadata {
+                        job_id: job_id.to_string(),
+                        operator_id: operator_id.clone(),
+                        epoch,
+                        start_time: metadata.start_time,
+                        finish_time: metadata.finish_time,
+                        ..Default::default()
+                    },
+                )
+                .await;
+            }
+        }
+
+        metadata.operator_ids = operator_ids.to_vec();
+        Self::write_checkpoint_metadata(checkpoint_url, metadata.clone()).await;
+
+        info!(message = "Restored savepoint", job_id, epoch, name);
+        Ok(metadata)
+    }
+
+    /// Copies the checkpoint whose metadata is under `from` in `from_storage` to `to` in
+    /// `to_storage`, along with all of the data files that it refers to. The paths of those
+    /// files under `from_data` are kept under `to_data`. State that was written with a recorded schema is migrated to `schemas`, which
+    /// are recorded in the copy.
+    async fn copy_state(
+        from_storage: &StorageProvider,
+        to_storage: &StorageProvider,
+        from: &str,
+        from_data: &str,
+        to: &str,
+        to_data: &str,
+        job_id: &str,
+        schemas: &BTreeMap<String, OperatorStateSchema>,
+    ) -> Result<CheckpointMetadata> {
+        let data = from_storage.get(&metadata_path(from)).await?;
+        let mut metadata = CheckpointMetadata::decode(&data[..])?;
+
+        for operator_id in &metadata.operator_ids {
+            let data = from_storage
+                .get(&metadata_path(&format!(
+                    "{}/operator-{}",
+                    from, operator_id
+                )))
+                .await?;
+            let mut operator_metadata = OperatorCheckpointMetadata::decode(&data[..])?;
+
+            let schema = schemas.get(operator_id);
+            let migrations = match (&operator_metadata.state_schema, schema) {
+                (Some(old), Some(new)) => {
+                    let old: OperatorStateSchema = serde_json::from_str(old)?;
+                    let tables: HashSet<_> = operator_metadata
+                        .backend_data
+                        .iter()
+                        .filter_map(|b| match &b.backend_data {
+                            Some(BackendData::ParquetStore(p)) => Some(p.table.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    plan_operator_migration(operator_id, &old, new, tables)?
+                }
+                _ => BTreeMap::new(),
+            };
+
+            for backend_data in &mut operator_metadata.backend_data {
+                let Some(BackendData::ParquetStore(parquet_store)) = &mut backend_data.backend_data
+                else {
+                    unreachable!("expect parquet backends")
+                };
+                let Some(relative) = parquet_store.file.strip_prefix(from_data) else {
+                    bail!(
+                        "file {} of operator {} is not under {}",
+                        parquet_store.file,
+                        operator_id,
+                        from_data
+                    );
+                };
+                let file = format!("{}{}", to_data, relative);
+                let bytes = from_storage.get(&parquet_store.file).await?;
+
+                let batch = match migrations.get(&parquet_store.table) {
+                    Some((old, new)) => {
+                        let mut builder = RecordBatchBuilder::default();
+                        for mut tuple in
+                            Self::blind_tuples_from_parquet_bytes(bytes.to_vec(), &(0..=u64::MAX))
+                        {
+                            match &mut tuple.operation {
+                                DataOperation::Insert => {
+                                    tuple.value = migrate(old, new, &tuple.value)?;
+                                }
+                                DataOperation::DeleteValue(op) => {
+                                    op.value = migrate(old, new, &op.value)?;
+                                }
+                                _ => {}
+                            }
+                            builder.insert(
+                                tuple.key_hash,
+                                tuple.timestamp,
+                                tuple.key,
+                                tuple.value,
+                                tuple.operation,
+                            );
+                        }
+                        builder.flush()
+                    }
+                    None => None,
+                };
+
+                match batch {
+                    Some((batch, _)) => {
+                        ParquetCompactFileWriter::upload_record_batch(&file, batch, to_storage)
+                            .await?;
+                    }
+                    None => {
+                        to_storage.put(&file, bytes.to_vec()).await?;
+                    }
+                }
+                parquet_store.file = file;
+            }
+
+            if !migrations.is_empty() {
+                info!(
+                    message = "Migrated operator state",
+                    operator_id,
+                    tables = migrations.keys().cloned().collect::<Vec<_>>().join(",")
+                );
+            }
+
+            operator_metadata.state_schema = schema.map(serde_json::to_string).transpose()?;
+            operator_metadata.job_id = job_id.to_string();
+            to_storage
+                .put(
+                    metadata_path(&format!("{}/operator-{}", to, operator_id)),
+                    operator_metadata.encode_to_vec(),
+                )
+                .await?;
+        }
+
+        // the copy has all of the state it needs, so nothing before it is required
+        metadata.job_id = job_id.to_string();
+        metadata.min_epoch = metadata.epoch;
+        to_storage
+            .put(metadata_path(to), metadata.encode_to_vec())
+            .await?;
+
+        Ok(metadata)
+    }
+
     /// Return rows from the given bytes that are in the given key range
     fn tuples_from_parquet_bytes<K: Key, V: Data>(
         &self,
@@ -846,7 +1234,7 @@ impl ParquetBackend {
 
     /// Return rows from the given bytes that are in the given key range,
     /// but without deserializing the key and value.
-    fn blind_tuples_from_parquet_bytes(
+    pub(crate) fn blind_tuples_from_parquet_bytes(
         bytes: Vec<u8>,
         range: &RangeInclusive<u64>,
     ) -> Vec<BlindDataTuple> {
@@ -1011,15 +1399,19 @@ impl ParquetWriter {
             storage,
             control_tx,
             finish_tx: Some(finish_tx),
-            task_info,
             table_descriptors: tables
                 .iter()
                 .map(|table| (table.name.chars().next().unwrap(), table.clone()))
                 .collect(),
             builders: HashMap::new(),
+            coalesced: HashMap::new(),
+            writes: 0,
             current_files,
             load_compacted_tx,
             new_compacted: vec![],
+            pending_in_flight: None,
+            local_cache: LocalStateCache::for_task(&task_info),
+            task_info,
         })
         .start();
 
@@ -1052,6 +1444,13 @@ impl ParquetWriter {
             .unwrap();
     }
 
+    async fn write_in_flight(&mut self, epoch: u32, write: ParquetWrite) {
+        self.sender
+            .send(ParquetQueueItem::InFlight(epoch, write))
+            .await
+            .unwrap();
+    }
+
     async fn load_compacted_data(&mut self, compaction: CompactionResult) {
         self.load_compacted_rx.send(compaction).await.unwrap();
     }
@@ -1062,6 +1461,7 @@ impl ParquetWriter {
         time: SystemTime,
         watermark: Option<SystemTime>,
         then_stop: bool,
+        in_flight: bool,
     ) {
         self.sender
             .send(ParquetQueueItem::Checkpoint(ParquetCheckpoint {
@@ -1069,6 +1469,7 @@ impl ParquetWriter {
                 time,
                 watermark,
                 then_stop,
+                in_flight,
             }))
             .await
             .unwrap();
@@ -1085,6 +1486,8 @@ impl ParquetWriter {
 enum ParquetQueueItem {
     Write(ParquetWrite),
     Checkpoint(ParquetCheckpoint),
+    // the records overtaken by the unaligned checkpoint of the epoch
+    InFlight(u32, ParquetWrite),
 }
 
 #[derive(Debug)]
@@ -1103,6 +1506,8 @@ struct ParquetCheckpoint {
     time: SystemTime,
     watermark: Option<SystemTime>,
     then_stop: bool,
+    // unaligned checkpoints aren't complete until their in-flight records have been written
+    in_flight: bool,
 }
 
 struct RecordBatchBuilder {
@@ -1225,9 +1630,13 @@ struct ParquetFlusher {
     task_info: TaskInfo,
     table_descriptors: HashMap<char, TableDescriptor>,
     builders: HashMap<char, RecordBatchBuilder>,
+    coalesced: HashMap<char, HashMap<(SystemTime, Vec<u8>), (usize, ParquetWrite)>>, // table -> (time, key) -> (sequence, write)
+    writes: usize,
     current_files: HashMap<char, BTreeMap<u32, Vec<ParquetStoreData>>>, // table -> epoch -> file
     load_compacted_tx: Receiver<CompactionResult>,
     new_compacted: Vec<ParquetStoreData>,
+    pending_in_flight: Option<(u32, SubtaskCheckpointMetadata)>, // epoch, metadata without the in-flight file
+    local_cache: Option<LocalStateCache>,
 }
 
 impl ParquetFlusher {
@@ -1255,7 +1664,15 @@ impl ParquetFlusher {
         writer.flush().unwrap();
         let parquet_bytes = writer.into_inner().unwrap();
         let bytes = parquet_bytes.len();
-        self.storage.put(key, parquet_bytes).await?;
+        match &self.local_cache {
+            Some(cache) => {
+                self.storage.put(key, parquet_bytes.clone()).await?;
+                cache.put(&self.storage, key, &parquet_bytes).await;
+            }
+            None => {
+                self.storage.put(key, parquet_bytes).await?;
+            }
+        }
         Ok(bytes)
     }
 
@@ -1306,6 +1723,103 @@ impl ParquetFlusher {
         }
     }
 
+    /// Tables that hold a single value for each key and time only need the last write to each
+    /// of them in an epoch to be checkpointed, so those writes are coalesced until the
+    /// checkpoint rather than all being uploaded. They're flushed in the order they were last
+    /// written, as deletes from keyed state apply to a key at any time.
+    fn buffer_write(&mut self, write: ParquetWrite) {
+        self.writes += 1;
+        match self
+            .table_descriptors
+            .get(&write.table)
+            .unwrap()
+            .table_type()
+        {
+            TableType::Global | TableType::TimeKeyMap => {
+                self.coalesced
+                    .entry(write.table)
+                    .or_default()
+                    .insert((write.timestamp, write.key.clone()), (self.writes, write));
+            }
+            TableType::KeyTimeMultiMap => {
+                self.builders.entry(write.table).or_default().insert(
+                    write.key_hash,
+                    write.timestamp,
+                    write.key,
+                    write.data,
+                    write.operation,
+                );
+            }
+        }
+    }
+
+    /// Uploads the records overtaken by the unaligned checkpoint `epoch` as a file of that epoch,
+    /// completing the checkpoint.
+    async fn complete_in_flight(&mut self, epoch: u32, write: ParquetWrite) -> Result<()> {
+        let Some((pending_epoch, mut subtask_metadata)) = self.pending_in_flight.take() else {
+            bail!(
+                "received in-flight records for epoch {} without an unaligned checkpoint",
+                epoch
+            );
+        };
+        if pending_epoch != epoch {
+            bail!(
+                "received in-flight records for epoch {} while epoch {} is pending",
+                epoch,
+                pending_epoch
+            );
+        }
+
+        let table = write.table;
+        let mut builder = RecordBatchBuilder::default();
+        builder.insert(
+            write.key_hash,
+            write.timestamp,
+            write.key,
+            write.data,
+            write.operation,
+        );
+        let (record_batch, stats) = builder.flush().unwrap();
+        let s3_key = table_checkpoint_path(&self.task_info, table, epoch, false);
+        let bytes = self.upload_record_batch(&s3_key, record_batch).await?;
+
+        let file = ParquetStoreData {
+            epoch,
+            file: s3_key,
+            table: table.to_string(),
+            min_routing_key: stats.min_routing_key,
+            max_routing_key: stats.max_routing_key,
+            max_timestamp_micros: arroyo_types::to_micros(stats.max_timestamp) + 1,
+            min_required_timestamp_micros: None,
+            generation: 0,
+        };
+        // in-flight records are only restored from the checkpoint they were overtaken by, which
+        // happens as the table is global and its older files are dropped at each checkpoint
+        self.current_files
+            .entry(table)
+            .or_default()
+            .entry(epoch)
+            .or_default()
+            .push(file.clone());
+
+        subtask_metadata.backend_data.push(grpc::BackendData {
+            backend_data: Some(BackendData::ParquetStore(file)),
+        });
+        subtask_metadata.has_state = true;
+        subtask_metadata.bytes += bytes as u64;
+        subtask_metadata.finish_time = to_micros(SystemTime::now());
+
+        self.control_tx
+            .send(ControlResp::CheckpointCompleted(CheckpointCompleted {
+                checkpoint_epoch: epoch,
+                operator_id: self.task_info.operator_id.clone(),
+                subtask_metadata,
+            }))
+            .await
+            .unwrap();
+        Ok(())
+    }
+
     async fn flush_iteration(&mut self) -> Result<bool> {
         let mut checkpoint_epoch = None;
 
@@ -1323,12 +1837,15 @@ impl ParquetFlusher {
                 }
                 op = self.queue.recv() => {
                     match op {
-                        Some(ParquetQueueItem::Write( ParquetWrite{table, key_hash, timestamp, key, data, operation})) => {
-                            self.builders.entry(table).or_default().insert(key_hash, timestamp, key, data, operation);
+                        Some(ParquetQueueItem::Write(write)) => {
+                            self.buffer_write(write);
                         }
                         Some(ParquetQueueItem::Checkpoint(epoch)) => {
                             checkpoint_epoch = Some(epoch);
                         },
+                        Some(ParquetQueueItem::InFlight(epoch, write)) => {
+                            self.complete_in_flight(epoch, write).await?;
+                        }
                         None => {
                             debug!("Parquet flusher closed");
                             return Ok(false);
@@ -1339,7 +1856,22 @@ impl ParquetFlusher {
         }
 
         if let Some(cp) = checkpoint_epoch {
-            let mut bytes = 0;
+            for (table, writes) in self.coalesced.drain() {
+                let mut writes: Vec<_> = writes.into_values().collect();
+                writes.sort_by_key(|(sequence, _)| *sequence);
+
+                let builder = self.builders.entry(table).or_default();
+                for (_, write) in writes {
+                    builder.insert(
+                        write.key_hash,
+                        write.timestamp,
+                        write.key,
+                        write.data,
+                        write.operation,
+                    );
+                }
+            }
+
             let mut to_write = vec![];
             for (table, builder) in self.builders.drain() {
                 let Some((record_batch, stats)) = builder.flush() else {
@@ -1349,9 +1881,17 @@ impl ParquetFlusher {
                 to_write.push((record_batch, s3_key, table, stats));
             }
 
-            // write the files and update current_files
-            for (record_batch, s3_key, table, stats) in to_write {
-                bytes += self.upload_record_batch(&s3_key, record_batch).await?;
+            // write the files concurrently, then update current_files
+            let bytes: usize = futures::future::try_join_all(to_write.iter().map(
+                |(record_batch, s3_key, _, _)| {
+                    self.upload_record_batch(s3_key, record_batch.clone())
+                },
+            ))
+            .await?
+            .into_iter()
+            .sum();
+
+            for (_, s3_key, table, stats) in to_write {
                 self.current_files
                     .entry(table)
                     .or_default()
@@ -1412,6 +1952,17 @@ impl ParquetFlusher {
             self.current_files = new_current_files;
             self.new_compacted = vec![];
 
+            // only the state of the latest checkpoint is kept locally
+            if let Some(cache) = &self.local_cache {
+                let files = self
+                    .current_files
+                    .values()
+                    .flat_map(|epoch_files| epoch_files.values().flatten())
+                    .map(|file| file.file.as_str())
+                    .collect();
+                cache.retain(&files).await;
+            }
+
             // compute total number of files in this checkpoint
             let mut total_files = 0;
             let mut max_timestamp: u64 = 0;
@@ -1441,6 +1992,10 @@ impl ParquetFlusher {
                 backend_data: checkpoint_backend_data,
                 bytes: bytes as u64,
             };
+            if cp.in_flight {
+                self.pending_in_flight = Some((cp.epoch, subtask_metadata));
+                return Ok(true);
+            }
             self.control_tx
                 .send(ControlResp::CheckpointCompleted(CheckpointCompleted {
                     checkpoint_epoch: cp.epoch,
@@ -1458,9 +2013,18 @@ impl ParquetFlusher {
     }
 }
 
-pub fn get_storage_env_vars() -> HashMap<String, String> {
-    [S3_REGION_ENV, S3_ENDPOINT_ENV, CHECKPOINT_URL_ENV]
-        .iter()
-        .filter_map(|&var| env::var(var).ok().map(|v| (var.to_string(), v)))
-        .collect()
+/// The storage environment of a job's workers, which store their checkpoints under the job's
+/// `checkpoint_url`
+pub fn get_storage_env_vars(checkpoint_url: &str) -> HashMap<String, String> {
+    let mut vars: HashMap<_, _> = [
+        S3_REGION_ENV,
+        S3_ENDPOINT_ENV,
+        AZURE_STORAGE_ACCOUNT_ENV,
+        LOCAL_STATE_DIR_ENV,
+    ]
+    .iter()
+    .filter_map(|&var| env::var(var).ok().map(|v| (var.to_string(), v)))
+    .collect();
+    vars.insert(CHECKPOINT_URL_ENV.to_string(), checkpoint_url.to_string());
+    vars
 }
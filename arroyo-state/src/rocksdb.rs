@@ -0,0 +1,298 @@
+//! A state backend that keeps the values of keyed tables in a RocksDB instance on the worker's
+//! disk rather than in memory, for pipelines whose state doesn't fit in the memory of their
+//! workers.
+//!
+//! Checkpoints are written by the parquet backend in the same format as for in-memory state, so
+//! the controller compacts, cleans up and savepoints them the same way, and a pipeline can switch
+//! between backends when it's restarted. The database only holds the task's working state: it's
+//! recreated when the task starts and filled from the checkpoint it's restored from.
+
+use crate::metrics::{
+    ROCKSDB_LIVE_DATA_GAUGE, ROCKSDB_PENDING_COMPACTION_GAUGE, ROCKSDB_RUNNING_COMPACTIONS_GAUGE,
+    ROCKSDB_SIZE_GAUGE,
+};
+use crate::parquet::ParquetBackend;
+use crate::tables::DataTuple;
+use crate::{BackingStore, BINCODE_CONFIG};
+use anyhow::Result;
+use arroyo_rpc::grpc::{
+    CheckpointMetadata, OperatorCheckpointMetadata, TableDescriptor, TableType,
+};
+use arroyo_rpc::{CompactionResult, ControlResp};
+use arroyo_types::{CheckpointBarrier, Data, Key, TaskInfo, LOCAL_STATE_DIR_ENV};
+use rocksdb::{Options, DB};
+use std::env;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tokio::sync::mpsc::Sender;
+use tracing::warn;
+
+pub struct RocksDbBackend {
+    parquet: ParquetBackend,
+    db: DB,
+}
+
+impl RocksDbBackend {
+    fn db_path(task_info: &TaskInfo) -> PathBuf {
+        let base = env::var(LOCAL_STATE_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| env::temp_dir().join("arroyo"));
+        base.join(&task_info.job_id).join(format!(
+            "{}-{}-rocksdb",
+            task_info.operator_id, task_info.task_index
+        ))
+    }
+
+    // opens an empty database for the task, dropping anything left by a previous run
+    fn open_db(task_info: &TaskInfo) -> DB {
+        let path = Self::db_path(task_info);
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        if let Err(e) = DB::destroy(&options, &path) {
+            warn!("failed to remove RocksDB state at {:?}: {:?}", path, e);
+        }
+        DB::open(&options, &path)
+            .unwrap_or_else(|e| panic!("failed to open RocksDB state at {:?}: {:?}", path, e))
+    }
+
+    fn db_key<K: Key>(table: char, key: &K) -> Vec<u8> {
+        let mut db_key = table.to_string().into_bytes();
+        db_key.extend(bincode::encode_to_vec(key, BINCODE_CONFIG).unwrap());
+        db_key
+    }
+
+    fn update_metrics(&self) {
+        let task_index = self.task_info().task_index.to_string();
+        let labels = [self.task_info().operator_id.as_str(), task_index.as_str()];
+        for (property, gauge) in [
+            ("rocksdb.total-sst-files-size", &*ROCKSDB_SIZE_GAUGE),
+            ("rocksdb.estimate-live-data-size", &*ROCKSDB_LIVE_DATA_GAUGE),
+            (
+                "rocksdb.estimate-pending-compaction-bytes",
+                &*ROCKSDB_PENDING_COMPACTION_GAUGE,
+            ),
+            (
+                "rocksdb.num-running-compactions",
+                &*ROCKSDB_RUNNING_COMPACTIONS_GAUGE,
+            ),
+        ] {
+            match self.db.property_int_value(property) {
+                Ok(Some(value)) => gauge.with_label_values(&labels).set(value as f64),
+                Ok(None) => {}
+                Err(e) => warn!("failed to read RocksDB property {}: {:?}", property, e),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BackingStore for RocksDbBackend {
+    fn name() -> &'static str {
+        "rocksdb"
+    }
+
+    fn task_info(&self) -> &TaskInfo {
+        self.parquet.task_info()
+    }
+
+    fn checkpoint_url(&self) -> &str {
+        self.parquet.checkpoint_url()
+    }
+
+    async fn load_latest_checkpoint_metadata(job_id: &str) -> Option<CheckpointMetadata> {
+        ParquetBackend::load_latest_checkpoint_metadata(job_id).await
+    }
+
+    async fn load_checkpoint_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        epoch: u32,
+    ) -> Option<CheckpointMetadata> {
+        ParquetBackend::load_checkpoint_metadata(checkpoint_url, job_id, epoch).await
+    }
+
+    async fn load_operator_metadata(
+        checkpoint_url: &str,
+        job_id: &str,
+        operator_id: &str,
+        epoch: u32,
+    ) -> Option<OperatorCheckpointMetadata> {
+        ParquetBackend::load_operator_metadata(checkpoint_url, job_id, operator_id, epoch).await
+    }
+
+    async fn write_operator_checkpoint_metadata(
+        checkpoint_url: &str,
+        metadata: OperatorCheckpointMetadata,
+    ) {
+        ParquetBackend::write_operator_checkpoint_metadata(checkpoint_url, metadata).await
+    }
+
+    async fn write_checkpoint_metadata(checkpoint_url: &str, metadata: CheckpointMetadata) {
+        ParquetBackend::write_checkpoint_metadata(checkpoint_url, metadata).await
+    }
+
+    async fn new(
+        checkpoint_url: &str,
+        task_info: &TaskInfo,
+        tables: Vec<TableDescriptor>,
+        tx: Sender<ControlResp>,
+    ) -> Self {
+        Self {
+            parquet: ParquetBackend::new(checkpoint_url, task_info, tables, tx).await,
+            db: Self::open_db(task_info),
+        }
+    }
+
+    async fn from_checkpoint(
+        checkpoint_url: &str,
+        task_info: &TaskInfo,
+        metadata: CheckpointMetadata,
+        tables: Vec<TableDescriptor>,
+        control_tx: Sender<ControlResp>,
+    ) -> Self {
+        Self {
+            parquet: ParquetBackend::from_checkpoint(
+                checkpoint_url,
+                task_info,
+                metadata,
+                tables,
+                control_tx,
+            )
+            .await,
+            db: Self::open_db(task_info),
+        }
+    }
+
+    async fn prepare_checkpoint_load(metadata: &CheckpointMetadata) -> Result<()> {
+        ParquetBackend::prepare_checkpoint_load(metadata).await
+    }
+
+    async fn cleanup_checkpoint(
+        checkpoint_url: &str,
+        metadata: CheckpointMetadata,
+        old_min_epoch: u32,
+        new_min_epoch: u32,
+    ) -> Result<()> {
+        ParquetBackend::cleanup_checkpoint(checkpoint_url, metadata, old_min_epoch, new_min_epoch)
+            .await
+    }
+
+    async fn checkpoint(
+        &mut self,
+        barrier: CheckpointBarrier,
+        watermark: Option<SystemTime>,
+    ) -> u32 {
+        let epoch = self.parquet.checkpoint(barrier, watermark).await;
+        self.update_metrics();
+        epoch
+    }
+
+    async fn write_in_flight<K: Key, V: Data>(
+        &mut self,
+        epoch: u32,
+        table: char,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        self.parquet.write_in_flight(epoch, table, key, value).await
+    }
+
+    async fn get_data_tuples<K: Key, V: Data>(&self, table: char) -> Vec<DataTuple<K, V>> {
+        self.parquet.get_data_tuples(table).await
+    }
+
+    async fn write_data_tuple<K: Key, V: Data>(
+        &mut self,
+        table: char,
+        table_type: TableType,
+        timestamp: SystemTime,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        self.parquet
+            .write_data_tuple(table, table_type, timestamp, key, value)
+            .await
+    }
+
+    async fn delete_time_key<K: Key>(
+        &mut self,
+        table: char,
+        table_type: TableType,
+        timestamp: SystemTime,
+        key: &mut K,
+    ) {
+        self.parquet
+            .delete_time_key(table, table_type, timestamp, key)
+            .await
+    }
+
+    async fn delete_key<K: Key>(&mut self, table: char, key: &mut K) {
+        self.parquet.delete_key(table, key).await
+    }
+
+    async fn delete_data_value<K: Key, V: Data>(
+        &mut self,
+        table: char,
+        timestamp: SystemTime,
+        key: &mut K,
+        value: &mut V,
+    ) {
+        self.parquet
+            .delete_data_value(table, timestamp, key, value)
+            .await
+    }
+
+    async fn delete_time_range<K: Key>(
+        &mut self,
+        table: char,
+        key: &mut K,
+        range: Range<SystemTime>,
+    ) {
+        self.parquet.delete_time_range(table, key, range).await
+    }
+
+    async fn write_key_value<K: Key, V: Data>(&mut self, table: char, key: &mut K, value: &mut V) {
+        self.parquet.write_key_value(table, key, value).await
+    }
+
+    async fn get_global_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)> {
+        self.parquet.get_global_key_values(table).await
+    }
+
+    async fn get_key_values<K: Key, V: Data>(&self, table: char) -> Vec<(K, V)> {
+        self.parquet.get_key_values(table).await
+    }
+
+    async fn load_compacted(&mut self, compaction: CompactionResult) {
+        self.parquet.load_compacted(compaction).await
+    }
+
+    fn keeps_keyed_values(&self) -> bool {
+        true
+    }
+
+    fn get_keyed_value<K: Key, V: Data>(&self, table: char, key: &K) -> Option<V> {
+        let bytes = self
+            .db
+            .get_pinned(Self::db_key(table, key))
+            .expect("failed to read from RocksDB")?;
+        let (value, _) = bincode::decode_from_slice(&bytes, BINCODE_CONFIG).unwrap();
+        Some(value)
+    }
+
+    fn put_keyed_value<K: Key, V: Data>(&mut self, table: char, key: &K, value: &V) {
+        self.db
+            .put(
+                Self::db_key(table, key),
+                bincode::encode_to_vec(value, BINCODE_CONFIG).unwrap(),
+            )
+            .expect("failed to write to RocksDB");
+    }
+
+    fn delete_keyed_value<K: Key>(&mut self, table: char, key: &K) {
+        self.db
+            .delete(Self::db_key(table, key))
+            .expect("failed to delete from RocksDB");
+    }
+}
@@ -118,6 +118,7 @@ impl<K: Key, V: Data> KeyTimeMultiMapCache<K, V> {
         // TODO: there may be a race here, as the initial checkpoint_metadata might get stale.
         // This is unlikely as this method is only called on start, but should probably be the domain of the backing store.
         let operator_metadata = StateBackend::load_operator_metadata(
+            backing_store.checkpoint_url(),
             &task_info.job_id,
             &task_info.operator_id,
             checkpoint_metadata.epoch,
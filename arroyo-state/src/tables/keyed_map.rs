@@ -1,8 +1,8 @@
 use crate::metrics::TABLE_SIZE_GAUGE;
-use crate::BackingStore;
+use crate::{BackingStore, DataOperation};
 use arroyo_rpc::grpc::TableType;
 use arroyo_types::{Data, Key};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::time::SystemTime;
 
 pub struct KeyedState<'a, K: Key, V: Data, S: BackingStore> {
@@ -34,7 +34,12 @@ impl<'a, K: Key, V: Data, S: BackingStore> KeyedState<'a, K, V, S> {
                 &mut value,
             )
             .await;
-        self.cache.insert(key, value);
+        if self.backing_state.keeps_keyed_values() {
+            self.backing_state.put_keyed_value(self.table, &key, &value);
+            self.cache.set_time(timestamp, key);
+        } else {
+            self.cache.insert(timestamp, key, value);
+        }
 
         TABLE_SIZE_GAUGE
             .with_label_values(&[
@@ -42,46 +47,139 @@ impl<'a, K: Key, V: Data, S: BackingStore> KeyedState<'a, K, V, S> {
                 &self.backing_state.task_info().task_index.to_string(),
                 &self.table.to_string(),
             ])
-            .set(self.cache.values.len() as f64);
+            .set(self.cache.times.len() as f64);
     }
 
     pub async fn remove(&mut self, key: &mut K) {
         self.cache.remove(&key);
+        if self.backing_state.keeps_keyed_values() {
+            self.backing_state.delete_keyed_value(self.table, key);
+        }
         self.backing_state
             .delete_time_key(self.table, TableType::Global, SystemTime::UNIX_EPOCH, key)
             .await;
     }
 
-    pub fn get(&self, key: &K) -> Option<&V> {
-        self.cache.values.get(key)
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if self.backing_state.keeps_keyed_values() {
+            self.cache.read = self.backing_state.get_keyed_value(self.table, key);
+            self.cache.read.as_ref()
+        } else {
+            self.cache.values.get(key)
+        }
+    }
+
+    /// Removes the keys that haven't been written to since `expiration_time`
+    pub async fn expire_entries_before(&mut self, expiration_time: SystemTime) {
+        for mut key in self.cache.expire_entries_before(expiration_time) {
+            if self.backing_state.keeps_keyed_values() {
+                self.backing_state.delete_keyed_value(self.table, &key);
+            }
+            self.backing_state
+                .delete_time_key(
+                    self.table,
+                    TableType::Global,
+                    SystemTime::UNIX_EPOCH,
+                    &mut key,
+                )
+                .await;
+        }
     }
 }
 
 pub struct KeyedStateCache<K: Key, V: Data> {
+    // empty if the backend keeps the values of the table
     values: HashMap<K, V>,
+    // the last value read from a backend that keeps the values of the table
+    read: Option<V>,
+    // the time each key was last written at, and the keys last written at each time
+    times: HashMap<K, SystemTime>,
+    expirations: BTreeMap<SystemTime, HashSet<K>>,
 }
 
 impl<K: Key, V: Data> KeyedStateCache<K, V> {
-    pub async fn from_checkpoint<S: BackingStore>(backing_store: &S, table: char) -> Self {
-        let mut values = HashMap::new();
-        for (key, value) in backing_store.get_key_values(table).await {
-            values.insert(key, value);
+    pub async fn from_checkpoint<S: BackingStore>(backing_store: &mut S, table: char) -> Self {
+        let mut cache = Self::default();
+        let keeps_values = backing_store.keeps_keyed_values();
+        for tuple in backing_store.get_data_tuples::<K, V>(table).await {
+            match tuple.operation {
+                DataOperation::Insert if keeps_values => {
+                    backing_store.put_keyed_value(table, &tuple.key, &tuple.value.unwrap());
+                    cache.set_time(tuple.timestamp, tuple.key);
+                }
+                DataOperation::Insert => {
+                    cache.insert(tuple.timestamp, tuple.key, tuple.value.unwrap());
+                }
+                DataOperation::DeleteTimeKey(_) => {
+                    if keeps_values {
+                        backing_store.delete_keyed_value(table, &tuple.key);
+                    }
+                    cache.remove(&tuple.key);
+                }
+                DataOperation::DeleteKey(_)
+                | DataOperation::DeleteValue(_)
+                | DataOperation::DeleteTimeRange(_) => {
+                    panic!("Not supported")
+                }
+            }
         }
-        Self { values }
+        cache
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
+    pub fn insert(&mut self, timestamp: SystemTime, key: K, value: V) {
+        self.set_time(timestamp, key.clone());
         self.values.insert(key, value);
     }
+
+    // records that `key` was last written at `timestamp`
+    fn set_time(&mut self, timestamp: SystemTime, key: K) {
+        self.remove_expiration(&key);
+        self.expirations
+            .entry(timestamp)
+            .or_default()
+            .insert(key.clone());
+        self.times.insert(key, timestamp);
+    }
+
     pub fn remove(&mut self, key: &K) {
+        self.remove_expiration(key);
         self.values.remove(key);
     }
+
+    fn remove_expiration(&mut self, key: &K) {
+        let Some(time) = self.times.remove(key) else {
+            return;
+        };
+        if let Some(keys) = self.expirations.get_mut(&time) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.expirations.remove(&time);
+            }
+        }
+    }
+
+    fn expire_entries_before(&mut self, time: SystemTime) -> Vec<K> {
+        let retained = self.expirations.split_off(&time);
+        let expired = std::mem::replace(&mut self.expirations, retained);
+        expired
+            .into_values()
+            .flatten()
+            .map(|key| {
+                self.values.remove(&key);
+                self.times.remove(&key);
+                key
+            })
+            .collect()
+    }
 }
 
 impl<K: Key, V: Data> Default for KeyedStateCache<K, V> {
     fn default() -> Self {
         Self {
             values: Default::default(),
+            read: None,
+            times: Default::default(),
+            expirations: Default::default(),
         }
     }
 }
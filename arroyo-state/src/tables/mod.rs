@@ -129,6 +129,21 @@ impl Compactor {
             }
         }
     }
+
+    /// Compacts tuples that make up the entire history of a table's partition. As there's no
+    /// earlier state for their deletes to apply to, a multimap's deletes are dropped along with
+    /// what they deleted. A time-key map's deletes are kept, as they're compacted out of order
+    /// and a delete from keyed state applies to its key at any time.
+    pub(crate) fn compact_all_tuples(&self, tuples: Vec<BlindDataTuple>) -> Vec<BlindDataTuple> {
+        match self {
+            Compactor::TimeKeyMap => self.compact_tuples(tuples),
+            Compactor::KeyTimeMultiMap => self
+                .compact_tuples(tuples)
+                .into_iter()
+                .filter(|tuple| matches!(tuple.operation, DataOperation::Insert))
+                .collect(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -246,4 +261,50 @@ mod test {
             Compactor::KeyTimeMultiMap.compact_tuples(tuples_out),
         );
     }
+
+    #[tokio::test]
+    async fn test_full_compaction_drops_deletes() {
+        let t1 = SystemTime::now();
+        let t2 = t1 + Duration::from_secs(1);
+        let t3 = t2 + Duration::from_secs(1);
+
+        let k1 = "k1".as_bytes().to_vec();
+        let k2 = "k2".as_bytes().to_vec();
+        let v1 = "v1".as_bytes().to_vec();
+
+        let insert_1 = BlindDataTuple {
+            key_hash: 123,
+            timestamp: t1,
+            key: k1.clone(),
+            value: v1.clone(),
+            operation: DataOperation::Insert,
+        };
+
+        let insert_2 = BlindDataTuple {
+            key_hash: 123,
+            timestamp: t2,
+            key: k2.clone(),
+            value: v1.clone(),
+            operation: DataOperation::Insert,
+        };
+
+        let delete_all = BlindDataTuple {
+            key_hash: 123,
+            timestamp: t2,
+            key: k1.clone(),
+            value: v1.clone(),
+            operation: DataOperation::DeleteTimeRange(DeleteTimeRangeOperation {
+                key: k1.clone(),
+                start: t1,
+                end: t3,
+            }),
+        };
+
+        let tuples_in = vec![insert_1, insert_2.clone(), delete_all];
+
+        assert_eq!(
+            vec![insert_2],
+            Compactor::KeyTimeMultiMap.compact_all_tuples(tuples_in)
+        );
+    }
 }
@@ -117,6 +117,17 @@ impl<'a, K: Key, V: Data + PartialEq, S: BackingStore> TimeKeyMap<'a, K, V, S> {
         }
     }
 
+    /// The earliest time at or after `time` that has any values
+    pub fn get_min_time_from(&self, time: SystemTime) -> Option<SystemTime> {
+        let persisted_time = self.cache.persisted_values.range(time..).next();
+        let buffered_time = self.cache.buffered_values.range(time..).next();
+        persisted_time
+            .into_iter()
+            .chain(buffered_time)
+            .map(|(time, _)| *time)
+            .min()
+    }
+
     pub fn evict_all_before_watermark(&mut self, watermark: SystemTime) -> Vec<(K, V)> {
         let mut result = vec![];
         loop {
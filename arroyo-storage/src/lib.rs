@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::{
@@ -5,14 +6,16 @@ use std::{
     sync::{Arc, OnceLock},
 };
 
-use arroyo_types::{S3_ENDPOINT_ENV, S3_REGION_ENV};
+use arroyo_types::{AZURE_STORAGE_ACCOUNT_ENV, S3_ENDPOINT_ENV, S3_REGION_ENV};
 use aws::ArroyoCredentialProvider;
 use bytes::Bytes;
+use futures::TryStreamExt;
 use object_store::aws::AmazonS3ConfigKey;
-use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::azure::{AzureConfigKey, MicrosoftAzureBuilder};
+use object_store::gcp::{GoogleCloudStorageBuilder, GoogleConfigKey};
 use object_store::path::Path;
 use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, ObjectStore};
-use object_store::{MultipartId, UploadPart};
+use object_store::{MultipartId, ObjectMeta, UploadPart};
 use regex::{Captures, Regex};
 use thiserror::Error;
 
@@ -69,10 +72,18 @@ const GCS_PATH: &str =
     r"^https://storage\.googleapis\.com/(?P<bucket>[a-z\d\-_\.]+)(/(?P<key>.+))?$";
 const GCS_URL: &str = r"^[gG][sS]://(?P<bucket>[a-z0-9\-\.]+)(/(?P<key>.+))?$";
 
+// https://ACCOUNT.blob.core.windows.net/CONTAINER/BLOB_NAME
+const AZURE_HTTPS: &str = r"^https://(?P<account>[a-z\d]+)\.blob\.core\.windows\.net/(?P<container>[a-z\d\-]+)(/(?P<key>.+))?$";
+// abfss://CONTAINER@ACCOUNT.dfs.core.windows.net/BLOB_NAME
+const AZURE_ABFS: &str = r"^[aA][bB][fF][sS][sS]?://(?P<container>[a-z\d\-]+)@(?P<account>[a-z\d]+)\.dfs\.core\.windows\.net(/(?P<key>.+))?$";
+// unofficial, with the account taken from AZURE_STORAGE_ACCOUNT_NAME -- az://CONTAINER/BLOB_NAME
+const AZURE_URL: &str = r"^[aA][zZ]://(?P<container>[a-z\d\-]+)(/(?P<key>.+))?$";
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Copy)]
 enum Backend {
     S3,
     GCS,
+    Azure,
     Local,
 }
 
@@ -100,6 +111,15 @@ fn matchers() -> &'static HashMap<Backend, Vec<Regex>> {
             ],
         );
 
+        m.insert(
+            Backend::Azure,
+            vec![
+                Regex::new(AZURE_HTTPS).unwrap(),
+                Regex::new(AZURE_ABFS).unwrap(),
+                Regex::new(AZURE_URL).unwrap(),
+            ],
+        );
+
         m.insert(
             Backend::Local,
             vec![
@@ -142,6 +162,13 @@ pub struct GCSConfig {
     key: Option<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureConfig {
+    account: Option<String>,
+    container: String,
+    key: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LocalConfig {
     pub path: String,
@@ -152,6 +179,7 @@ pub struct LocalConfig {
 pub enum BackendConfig {
     S3(S3Config),
     GCS(GCSConfig),
+    Azure(AzureConfig),
     Local(LocalConfig),
 }
 
@@ -162,6 +190,7 @@ impl BackendConfig {
                 return match k {
                     Backend::S3 => Self::parse_s3(matches),
                     Backend::GCS => Self::parse_gcs(matches),
+                    Backend::Azure => Self::parse_azure(matches),
                     Backend::Local => Self::parse_local(matches, with_key),
                 };
             }
@@ -231,6 +260,27 @@ impl BackendConfig {
         Ok(BackendConfig::GCS(GCSConfig { bucket, key }))
     }
 
+    fn parse_azure(matches: Captures) -> Result<Self, StorageError> {
+        let container = matches
+            .name("container")
+            .expect("container should always be available")
+            .as_str()
+            .to_string();
+
+        let account = last([
+            std::env::var(AZURE_STORAGE_ACCOUNT_ENV).ok(),
+            matches.name("account").map(|m| m.as_str().to_string()),
+        ]);
+
+        let key = matches.name("key").map(|r| r.as_str().to_string());
+
+        Ok(BackendConfig::Azure(AzureConfig {
+            account,
+            container,
+            key,
+        }))
+    }
+
     fn parse_local(matches: Captures, with_key: bool) -> Result<Self, StorageError> {
         let path = matches
             .name("path")
@@ -274,7 +324,8 @@ impl StorageProvider {
 
         match config {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
-            BackendConfig::GCS(config) => Self::construct_gcs(config),
+            BackendConfig::GCS(config) => Self::construct_gcs(config, options),
+            BackendConfig::Azure(config) => Self::construct_azure(config, options),
             BackendConfig::Local(config) => Self::construct_local(config).await,
         }
     }
@@ -290,13 +341,15 @@ impl StorageProvider {
 
         let provider = match config {
             BackendConfig::S3(config) => Self::construct_s3(config, options).await,
-            BackendConfig::GCS(config) => Self::construct_gcs(config),
+            BackendConfig::GCS(config) => Self::construct_gcs(config, options),
+            BackendConfig::Azure(config) => Self::construct_azure(config, options),
             BackendConfig::Local(config) => Self::construct_local(config).await,
         }?;
 
         let path = match &provider.config {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
+            BackendConfig::Azure(azure) => azure.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
         }
         .ok_or_else(|| StorageError::NoKeyInUrl)?;
@@ -311,6 +364,7 @@ impl StorageProvider {
         let key = match &config {
             BackendConfig::S3(s3) => s3.key.as_ref(),
             BackendConfig::GCS(gcs) => gcs.key.as_ref(),
+            BackendConfig::Azure(azure) => azure.key.as_ref(),
             BackendConfig::Local(local) => local.key.as_ref(),
         }
         .ok_or_else(|| StorageError::NoKeyInUrl)?;
@@ -369,16 +423,57 @@ impl StorageProvider {
         })
     }
 
-    fn construct_gcs(config: GCSConfig) -> Result<Self, StorageError> {
-        let gcs = GoogleCloudStorageBuilder::from_env()
-            .with_bucket_name(&config.bucket)
-            .build()?;
+    fn construct_gcs(
+        config: GCSConfig,
+        options: HashMap<String, String>,
+    ) -> Result<Self, StorageError> {
+        // credentials are picked up from GOOGLE_SERVICE_ACCOUNT or GOOGLE_APPLICATION_CREDENTIALS,
+        // falling back to the instance's service account
+        let mut builder = GoogleCloudStorageBuilder::from_env().with_bucket_name(&config.bucket);
+        for (key, value) in options {
+            let gcs_config_key: GoogleConfigKey = key.parse().map_err(|_| {
+                StorageError::CredentialsError(format!("invalid GCS config key: {}", key))
+            })?;
+            builder = builder.with_config(gcs_config_key, value);
+        }
 
         let canonical_url = format!("https://{}.storage.googleapis.com", config.bucket);
 
         Ok(Self {
             config: BackendConfig::GCS(config),
-            object_store: Arc::new(gcs),
+            object_store: Arc::new(builder.build()?),
+            canonical_url,
+        })
+    }
+
+    fn construct_azure(
+        config: AzureConfig,
+        options: HashMap<String, String>,
+    ) -> Result<Self, StorageError> {
+        // credentials are picked up from AZURE_STORAGE_ACCOUNT_KEY, a service principal
+        // (AZURE_CLIENT_ID, AZURE_CLIENT_SECRET and AZURE_TENANT_ID) or a managed identity
+        let mut builder = MicrosoftAzureBuilder::from_env().with_container_name(&config.container);
+        if let Some(account) = &config.account {
+            builder = builder.with_account(account);
+        }
+        for (key, value) in options {
+            let azure_config_key: AzureConfigKey = key.parse().map_err(|_| {
+                StorageError::CredentialsError(format!("invalid Azure config key: {}", key))
+            })?;
+            builder = builder.with_config(azure_config_key, value);
+        }
+
+        let canonical_url = match &config.account {
+            Some(account) => format!(
+                "https://{}.blob.core.windows.net/{}",
+                account, config.container
+            ),
+            None => format!("az://{}", config.container),
+        };
+
+        Ok(Self {
+            config: BackendConfig::Azure(config),
+            object_store: Arc::new(builder.build()?),
             canonical_url,
         })
     }
@@ -417,6 +512,15 @@ impl StorageProvider {
         Ok(bytes)
     }
 
+    pub async fn get_range<P: Into<String>>(
+        &self,
+        path: P,
+        range: Range<usize>,
+    ) -> Result<Bytes, StorageError> {
+        let path: String = path.into();
+        Ok(self.object_store.get_range(&path.into(), range).await?)
+    }
+
     pub async fn put<P: Into<String>>(
         &self,
         path: P,
@@ -437,6 +541,42 @@ impl StorageProvider {
         };
     }
 
+    /// The fully-qualified URL of an object in this store (like `s3://bucket/key`), in the
+    /// form expected by libraries outside of Arroyo
+    pub fn qualify_path(&self, path: &Path) -> String {
+        match &self.config {
+            BackendConfig::S3(s3) => format!("s3://{}/{}", s3.bucket, path),
+            BackendConfig::GCS(gcs) => format!("gs://{}/{}", gcs.bucket, path),
+            BackendConfig::Azure(azure) => match &azure.account {
+                Some(account) => format!(
+                    "abfs://{}@{}.dfs.core.windows.net/{}",
+                    azure.container, account, path
+                ),
+                None => format!("az://{}/{}", azure.container, path),
+            },
+            BackendConfig::Local(local) => {
+                format!("file://{}/{}", local.path.trim_end_matches('/'), path)
+            }
+        }
+    }
+
+    /// Lists the objects under `prefix` (relative to the root of the store), or all objects if
+    /// it's None
+    pub async fn list(&self, prefix: Option<&str>) -> Result<Vec<ObjectMeta>, StorageError> {
+        let prefix = prefix.map(Path::from);
+        Ok(self
+            .object_store
+            .list(prefix.as_ref())
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    pub async fn head<P: Into<String>>(&self, path: P) -> Result<ObjectMeta, StorageError> {
+        let path: String = path.into();
+        Ok(self.object_store.head(&path.into()).await?)
+    }
+
     pub async fn start_multipart(&self, path: &Path) -> Result<MultipartId, StorageError> {
         Ok(self
             .object_store
@@ -562,6 +702,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_azure_configs() {
+        assert_eq!(
+            BackendConfig::parse_url(
+                "https://myaccount.blob.core.windows.net/my-container/path/test.pdf",
+                false
+            )
+            .unwrap(),
+            BackendConfig::Azure(crate::AzureConfig {
+                account: Some("myaccount".to_string()),
+                container: "my-container".to_string(),
+                key: Some("path/test.pdf".to_string()),
+            })
+        );
+
+        assert_eq!(
+            BackendConfig::parse_url(
+                "abfss://my-container@myaccount.dfs.core.windows.net/checkpoints",
+                false
+            )
+            .unwrap(),
+            BackendConfig::Azure(crate::AzureConfig {
+                account: Some("myaccount".to_string()),
+                container: "my-container".to_string(),
+                key: Some("checkpoints".to_string()),
+            })
+        );
+
+        assert_eq!(
+            BackendConfig::parse_url(
+                "https://myaccount.blob.core.windows.net/my-container",
+                false
+            )
+            .unwrap(),
+            BackendConfig::Azure(crate::AzureConfig {
+                account: Some("myaccount".to_string()),
+                container: "my-container".to_string(),
+                key: None,
+            })
+        );
+    }
+
     #[test]
     fn test_local_configs() {
         assert_eq!(
@@ -0,0 +1,403 @@
+//! Fixed-precision decimals, for the `DECIMAL(p, s)` SQL type.
+//!
+//! A decimal is an unscaled 128-bit integer along with its scale, the number of digits after
+//! the decimal point, so `12.50` is `1250` with a scale of 2. Arithmetic keeps the scale of its
+//! result exact (addition aligns scales, multiplication adds them), and values are only
+//! rescaled to the scale of their column when they're written out. Decimals are serialized as
+//! strings, so that they round-trip through JSON without going through floats, and can be
+//! deserialized from either strings or numbers.
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+use bincode::{Decode, Encode};
+use serde::de::Visitor;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The largest precision and scale of a decimal, as in arrow's Decimal128
+pub const MAX_PRECISION: u8 = 38;
+// the number of extra digits of scale kept by division
+const DIVISION_SCALE: i8 = 6;
+
+#[derive(Clone, Copy, Debug, Default, Encode, Decode)]
+pub struct Decimal {
+    value: i128,
+    scale: i8,
+}
+
+fn pow10(exponent: u32) -> Option<i128> {
+    10i128.checked_pow(exponent)
+}
+
+impl Decimal {
+    pub fn new(value: i128, scale: i8) -> Self {
+        Self { value, scale }
+    }
+
+    /// The unscaled value
+    pub fn value(&self) -> i128 {
+        self.value
+    }
+
+    pub fn scale(&self) -> i8 {
+        self.scale
+    }
+
+    /// The same value with a different scale, rounding half away from zero if digits are
+    /// dropped, or None if it doesn't fit
+    pub fn rescale(self, scale: i8) -> Option<Self> {
+        let value = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.value,
+            Ordering::Greater => self
+                .value
+                .checked_mul(pow10((scale - self.scale) as u32)?)?,
+            Ordering::Less => {
+                let divisor = pow10((self.scale - scale) as u32)?;
+                let (quotient, remainder) = (self.value / divisor, self.value % divisor);
+                if remainder.abs() * 2 >= divisor {
+                    quotient + self.value.signum()
+                } else {
+                    quotient
+                }
+            }
+        };
+        Some(Self { value, scale })
+    }
+
+    /// The unscaled value at `scale`, as stored in an arrow Decimal128 array with that scale.
+    /// Panics if the value doesn't fit.
+    pub fn unscaled(self, scale: i8) -> i128 {
+        self.rescale(scale)
+            .unwrap_or_else(|| panic!("{} does not fit in a decimal with scale {}", self, scale))
+            .value
+    }
+
+    /// Casts the decimal to `scale`, checking that it has no more than `precision` digits
+    pub fn with_precision_and_scale(self, precision: u8, scale: i8) -> Self {
+        let rescaled = self
+            .rescale(scale)
+            .filter(|d| pow10(precision as u32).map_or(true, |max| d.value.abs() < max));
+        rescaled
+            .unwrap_or_else(|| panic!("{} does not fit in DECIMAL({}, {})", self, precision, scale))
+    }
+
+    /// Converts a float, rounding it to `scale`. Returns None for NaNs, infinities and values
+    /// that don't fit.
+    pub fn from_f64(value: f64, scale: i8) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // the shortest representation of the float, so that 0.1 is 0.1 and not 0.1000000000000000055
+        format!("{}", value).parse::<Decimal>().ok()?.rescale(scale)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.value as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    /// The integer part of the decimal, truncated towards zero
+    pub fn to_i128(self) -> i128 {
+        if self.scale >= 0 {
+            self.value / pow10(self.scale as u32).unwrap_or(i128::MAX)
+        } else {
+            self.value * pow10((-self.scale) as u32).unwrap_or(0)
+        }
+    }
+
+    // the two decimals rescaled to the larger of their scales
+    fn align(self, other: Self) -> (i128, i128, i8) {
+        let scale = self.scale.max(other.scale);
+        let left = self.rescale(scale).expect("decimal overflow");
+        let right = other.rescale(scale).expect("decimal overflow");
+        (left.value, right.value, scale)
+    }
+
+    // the canonical form of the decimal, without trailing zeros after the decimal point
+    fn normalize(self) -> Self {
+        let mut normalized = self;
+        while normalized.scale > 0 && normalized.value % 10 == 0 {
+            normalized.value /= 10;
+            normalized.scale -= 1;
+        }
+        normalized
+    }
+}
+
+macro_rules! from_integer {
+    ($($t: ty),*) => {
+        $(impl From<$t> for Decimal {
+            fn from(value: $t) -> Self {
+                Self::new(value as i128, 0)
+            }
+        })*
+    };
+}
+
+from_integer!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (left, right) = (self.normalize(), other.normalize());
+        let scale = left.scale.max(right.scale);
+        match (left.rescale(scale), right.rescale(scale)) {
+            (Some(left), Some(right)) => left.value.cmp(&right.value),
+            // one of them is too large to align, so they can only be ordered approximately
+            _ => left.to_f64().total_cmp(&right.to_f64()),
+        }
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.value.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let (left, right, scale) = self.align(rhs);
+        Self::new(left + right, scale)
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let (left, right, scale) = self.align(rhs);
+        Self::new(left - right, scale)
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let product = Self::new(self.value * rhs.value, self.scale + rhs.scale);
+        if product.scale > MAX_PRECISION as i8 {
+            product.rescale(MAX_PRECISION as i8).unwrap()
+        } else {
+            product
+        }
+    }
+}
+
+impl Div for Decimal {
+    type Output = Decimal;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        if rhs.value == 0 {
+            panic!("attempt to divide {} by zero", self);
+        }
+        let scale = (self.scale.max(rhs.scale) + DIVISION_SCALE).min(MAX_PRECISION as i8);
+        // computed with one more digit than needed, which is then rounded
+        let numerator = self
+            .rescale(scale + rhs.scale + 1)
+            .expect("decimal overflow")
+            .value;
+        Self::new(numerator / rhs.value, scale + 1)
+            .rescale(scale)
+            .unwrap()
+    }
+}
+
+impl Rem for Decimal {
+    type Output = Decimal;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        let (left, right, scale) = self.align(rhs);
+        Self::new(left % right, scale)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Self::Output {
+        Self::new(-self.value, self.scale)
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.scale <= 0 {
+            return write!(f, "{}", self.to_i128());
+        }
+
+        let digits = self.value.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        let sign = if self.value < 0 { "-" } else { "" };
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    /// Parses decimals like `-12.50` and `1.5e3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a valid decimal", s);
+        let s = s.trim();
+
+        let (mantissa, exponent) = match s.find(|c| c == 'e' || c == 'E') {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let (negative, integer) = match integer.strip_prefix('-') {
+            Some(integer) => (true, integer),
+            None => (false, integer.strip_prefix('+').unwrap_or(integer)),
+        };
+
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let value: i128 = format!("0{}{}", integer, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        let scale = fraction.len() as i32 - exponent;
+        if scale.abs() > MAX_PRECISION as i32 {
+            return Err(invalid());
+        }
+
+        let decimal = Self::new(if negative { -value } else { value }, scale as i8);
+        // negative scales are only used while parsing
+        if scale < 0 {
+            decimal.rescale(0).ok_or_else(invalid)
+        } else {
+            Ok(decimal)
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+struct DecimalVisitor;
+
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a decimal, as a string or a number")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Decimal::from(v))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Decimal::from(v))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        format!("{}", v).parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(d("12.50"), Decimal::new(1250, 2));
+        assert_eq!(d("12.50").to_string(), "12.50");
+        assert_eq!(d("-0.05").to_string(), "-0.05");
+        assert_eq!(d("1.5e3").to_string(), "1500");
+        assert_eq!(d("25e-4").to_string(), "0.0025");
+        assert_eq!(d("+7").to_string(), "7");
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("".parse::<Decimal>().is_err());
+        assert!("abc".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!((d("0.1") + d("0.2")).to_string(), "0.3");
+        assert_eq!((d("10.25") - d("0.5")).to_string(), "9.75");
+        assert_eq!((d("1.5") * d("1.5")).to_string(), "2.25");
+        assert_eq!((d("1") / d("3")).to_string(), "0.333333");
+        assert_eq!((d("2.00") / d("3")).to_string(), "0.66666667");
+        assert_eq!((d("7.5") % d("2")).to_string(), "1.5");
+        assert_eq!((-d("1.5")).to_string(), "-1.5");
+    }
+
+    #[test]
+    fn test_rescale_and_compare() {
+        assert_eq!(d("1.005").rescale(2), Some(d("1.01")));
+        assert_eq!(d("-1.005").rescale(2), Some(d("-1.01")));
+        assert_eq!(d("1.004").unscaled(2), 100);
+        assert_eq!(d("1e30").rescale(20), None);
+
+        assert_eq!(d("1.50"), d("1.5"));
+        assert!(d("1.49") < d("1.5"));
+        assert!(d("-2") < d("1.5"));
+
+        let hash = |d: Decimal| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            d.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(d("1.50")), hash(d("1.5")));
+    }
+
+    #[test]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&d("12.50")).unwrap(), "\"12.50\"");
+        assert_eq!(
+            serde_json::from_str::<Decimal>("\"12.50\"").unwrap(),
+            d("12.5")
+        );
+        assert_eq!(serde_json::from_str::<Decimal>("12.5").unwrap(), d("12.5"));
+        assert_eq!(serde_json::from_str::<Decimal>("-3").unwrap(), d("-3"));
+        assert_eq!(Decimal::from_f64(0.1, 2), Some(d("0.10")));
+        assert_eq!(Decimal::from_f64(f64::NAN, 2), None);
+    }
+}
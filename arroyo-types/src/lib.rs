@@ -11,6 +11,10 @@ use std::ops::{Range, RangeInclusive};
 use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+pub mod decimal;
+pub use decimal::Decimal;
+pub mod state_schema;
+
 #[derive(Copy, Hash, Debug, Clone, Eq, PartialEq, Encode, Decode, PartialOrd, Ord, Deserialize)]
 pub struct Window {
     pub start: SystemTime,
@@ -102,11 +106,22 @@ pub const API_ENDPOINT_ENV: &str = "API_ENDPOINT";
 // The rate parameter (e.g., "15s") used by the API when querying prometheus metrics -- this should
 // be at least 4x the configured scrape interval for your prometheus config
 pub const API_METRICS_RATE_ENV: &str = "API_METRICS_RATE";
+// Comma-separated endpoints of remote connectors to register with the API on startup
+pub const REMOTE_CONNECTORS_ENV: &str = "REMOTE_CONNECTORS";
 
 // storage configuration
 pub const S3_ENDPOINT_ENV: &str = "S3_ENDPOINT";
 pub const S3_REGION_ENV: &str = "S3_REGION";
 pub const CHECKPOINT_URL_ENV: &str = "CHECKPOINT_URL";
+pub const AZURE_STORAGE_ACCOUNT_ENV: &str = "AZURE_STORAGE_ACCOUNT_NAME";
+// directory on worker disks holding a copy of each task's most recently checkpointed state
+pub const LOCAL_STATE_DIR_ENV: &str = "LOCAL_STATE_DIR";
+// where the job's operators keep their state between checkpoints, set on workers by the controller
+pub const STATE_BACKEND_ENV: &str = "STATE_BACKEND";
+
+// secrets, referenced from connection configs as ${secret:NAME}
+pub const SECRETS_BACKEND_ENV: &str = "SECRETS_BACKEND";
+pub const SECRETS_DIR_ENV: &str = "SECRETS_DIR";
 
 // compiler service
 pub const ARTIFACT_URL_ENV: &str = "ARTIFACT_URL";
@@ -125,6 +140,16 @@ pub const K8S_WORKER_VOLUMES_ENV: &str = "K8S_WORKER_VOLUMES";
 pub const K8S_WORKER_VOLUME_MOUNTS_ENV: &str = "K8S_WORKER_VOLUME_MOUNTS";
 pub const K8S_WORKER_CONFIG_MAP_ENV: &str = "K8S_WORKER_CONFIG_MAP";
 
+// ecs scheduler configuration
+pub const ECS_CLUSTER_ENV: &str = "ECS_CLUSTER";
+pub const ECS_WORKER_TASK_DEFINITION_ENV: &str = "ECS_WORKER_TASK_DEFINITION";
+pub const ECS_WORKER_CONTAINER_NAME_ENV: &str = "ECS_WORKER_CONTAINER_NAME";
+pub const ECS_WORKER_LAUNCH_TYPE_ENV: &str = "ECS_WORKER_LAUNCH_TYPE";
+pub const ECS_WORKER_SUBNETS_ENV: &str = "ECS_WORKER_SUBNETS";
+pub const ECS_WORKER_SECURITY_GROUPS_ENV: &str = "ECS_WORKER_SECURITY_GROUPS";
+pub const ECS_WORKER_ASSIGN_PUBLIC_IP_ENV: &str = "ECS_WORKER_ASSIGN_PUBLIC_IP";
+pub const ECS_WORKER_SLOTS_ENV: &str = "ECS_WORKER_SLOTS";
+
 // telemetry configuration
 pub const DISABLE_TELEMETRY_ENV: &str = "DISABLE_TELEMETRY";
 pub const POSTHOG_KEY: &str = "phc_ghJo7Aa9QOo4inoWFYZP7o2aKszllEUyH77QeFgznUe";
@@ -389,7 +414,8 @@ impl<'de> Deserialize<'de> for DebeziumOp {
     {
         let s = String::deserialize(deserializer)?;
         match s.as_str() {
-            "c" => Ok(DebeziumOp::Create),
+            // rows read while snapshotting the table are inserts as far as we're concerned
+            "c" | "r" => Ok(DebeziumOp::Create),
             "u" => Ok(DebeziumOp::Update),
             "d" => Ok(DebeziumOp::Delete),
             _ => Err(serde::de::Error::custom(format!(
@@ -518,6 +544,37 @@ impl<T: Data> From<Debezium<T>> for UpdatingData<T> {
     }
 }
 
+/// A row written by an upsert sink, which either replaces the row with the same key or deletes
+/// it. Deletes carry the retracted row, so that sinks can find the key to delete.
+#[derive(Clone, Encode, Decode, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Upsert<T: Data> {
+    Value(T),
+    Delete(T),
+}
+
+impl<T: Data> Upsert<T> {
+    pub fn is_delete(&self) -> bool {
+        matches!(self, Upsert::Delete(_))
+    }
+}
+
+impl<T: Data> From<UpdatingData<T>> for Upsert<T> {
+    fn from(value: UpdatingData<T>) -> Self {
+        match value {
+            UpdatingData::Retract(old) => Upsert::Delete(old),
+            UpdatingData::Update { new, .. } => Upsert::Value(new),
+            UpdatingData::Append(new) => Upsert::Value(new),
+        }
+    }
+}
+
+impl<T: Data> From<T> for Upsert<T> {
+    fn from(value: T) -> Self {
+        Upsert::Value(value)
+    }
+}
+
 #[derive(Clone, Encode, Decode, Debug, Serialize, Deserialize, PartialEq)]
 pub enum JoinType {
     /// Inner Join
@@ -530,6 +587,15 @@ pub enum JoinType {
     Full,
 }
 
+/// One end of the frame of rows that an OVER window function is computed over, measured from the
+/// current row
+#[derive(Clone, Copy, Encode, Decode, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum FrameBound {
+    Unbounded,
+    Rows(u64),
+    Time(Duration),
+}
+
 pub trait RecordBatchBuilder: Default + Debug + Sync + Send {
     type Data: Data;
     fn add_data(&mut self, data: Option<Self::Data>);
@@ -747,8 +813,11 @@ pub static MESSAGES_RECV: &str = "arroyo_worker_messages_recv";
 pub static MESSAGES_SENT: &str = "arroyo_worker_messages_sent";
 pub static BYTES_RECV: &str = "arroyo_worker_bytes_recv";
 pub static BYTES_SENT: &str = "arroyo_worker_bytes_sent";
+pub static DESERIALIZATION_ERRORS: &str = "arroyo_worker_deserialization_errors";
 pub static TX_QUEUE_SIZE: &str = "arroyo_worker_tx_queue_size";
 pub static TX_QUEUE_REM: &str = "arroyo_worker_tx_queue_rem";
+pub static BUSY_TIME: &str = "arroyo_worker_busy_time_micros";
+pub static SOURCE_LAG: &str = "arroyo_worker_source_lag_millis";
 
 #[derive(Debug, Copy, Clone, Encode, Decode)]
 pub struct CheckpointBarrier {
@@ -756,6 +825,9 @@ pub struct CheckpointBarrier {
     pub min_epoch: u32,
     pub timestamp: SystemTime,
     pub then_stop: bool,
+    /// Unaligned barriers don't block the inputs they arrive on; records that arrive on other
+    /// inputs before their barrier are persisted alongside the checkpoint instead
+    pub unaligned: bool,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Hash, Serialize)]
@@ -851,6 +923,24 @@ pub fn range_for_server(i: usize, n: usize) -> RangeInclusive<u64> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_debezium_ops() {
+        let snapshot: Debezium<i32> =
+            serde_json::from_str(r#"{"before": null, "after": 5, "op": "r"}"#).unwrap();
+        assert_eq!(UpdatingData::Append(5), UpdatingData::from(snapshot));
+
+        let update: Debezium<i32> =
+            serde_json::from_str(r#"{"before": 4, "after": 5, "op": "u"}"#).unwrap();
+        assert_eq!(
+            UpdatingData::Update { old: 4, new: 5 },
+            UpdatingData::from(update)
+        );
+
+        assert!(serde_json::from_str::<Debezium<i32>>(r#"{"before": 4, "op": "d"}"#).is_ok());
+        assert!(serde_json::from_str::<Debezium<i32>>(r#"{"after": 4, "op": "d"}"#).is_err());
+        assert!(serde_json::from_str::<Debezium<i32>>(r#"{"after": 4, "op": "t"}"#).is_err());
+    }
+
     #[test]
     fn test_range_for_server() {
         let n = 6;
@@ -0,0 +1,65 @@
+//! Descriptions of the types stored in operator state.
+//!
+//! State is stored as bincode, which isn't self-describing, so state written by one version of a
+//! pipeline can't be read by another whose types differ, even if they only gained a nullable
+//! field. The planner describes the layout of the state of each of a pipeline's operators, which
+//! is kept with its savepoints, so that when a new version of the pipeline is started from one
+//! the stored records can be migrated field by field.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The bincode layout of a value stored in state, as written with the standard config
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateType {
+    Bool,
+    U8,
+    I8,
+    /// u16, u32, u64 and u128, which are all varint-encoded
+    UnsignedInt,
+    /// i16, i32, i64 and i128, which are all zigzag- and varint-encoded
+    SignedInt,
+    F32,
+    F64,
+    String,
+    Bytes,
+    Timestamp,
+    Duration,
+    Decimal,
+    Optional(Box<StateType>),
+    List(Box<StateType>),
+    Map(Box<StateType>, Box<StateType>),
+    Struct(Vec<StateField>),
+    /// A type whose layout isn't described, which can only be compared for equality
+    Opaque(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateField {
+    pub name: String,
+    pub typ: StateType,
+}
+
+impl StateField {
+    pub fn new(name: impl Into<String>, typ: StateType) -> Self {
+        Self {
+            name: name.into(),
+            typ,
+        }
+    }
+}
+
+/// The types of the state of an operator
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperatorStateSchema {
+    /// The type of the keys of the operator's keyed state. State is partitioned by the hash of
+    /// its key, so it can't be migrated if this changes.
+    pub key: Option<StateType>,
+    /// The types of the values of the tables that store input records as they were received,
+    /// which can be migrated field by field
+    pub tables: BTreeMap<String, StateType>,
+    /// For operators that store state derived from their inputs, like partial aggregates, a
+    /// type that changes whenever that state's layout does. Such state can't be migrated.
+    pub derived: Option<StateType>,
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::{grpc::TableDescriptor, OperatorConfig};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::{from_millis, to_millis, CheckpointBarrier, Key, Record};
+use bincode::{Decode, Encode};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+use typify::import_types;
+
+use crate::connectors::webhook::render_template;
+use crate::{
+    engine::{Context, StreamNode},
+    SchemaData,
+};
+
+import_types!(schema = "../connector-schemas/alert/table.json");
+
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(300);
+const MAX_RETRIES: u32 = 5;
+// PagerDuty rejects summaries longer than this
+const MAX_SUMMARY_LENGTH: usize = 1024;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct AlertSinkState {
+    // pairs of dedup key and the time of the last alert sent for it, in millis
+    sent: Vec<(String, u64)>,
+}
+
+#[derive(StreamNode)]
+pub struct AlertSinkFunc<K, T>
+where
+    K: Key,
+    T: Serialize + SchemaData,
+{
+    table: AlertTable,
+    client: reqwest::Client,
+    dedup_window: Duration,
+    // the event time of the last alert sent for each dedup key
+    sent: HashMap<String, SystemTime>,
+    max_time: SystemTime,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<(K, T)>,
+}
+
+/// Whether an alert for `key` at `time` falls outside of the dedup window of the last alert sent
+/// for that key; if so, it's recorded as the new last alert
+fn should_send(
+    sent: &mut HashMap<String, SystemTime>,
+    key: String,
+    time: SystemTime,
+    window: Duration,
+) -> bool {
+    match sent.get(&key) {
+        Some(last) if time < *last + window => false,
+        _ => {
+            sent.insert(key, time);
+            true
+        }
+    }
+}
+
+/// Truncates `s` to at most `max` bytes, on a character boundary
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K, T> AlertSinkFunc<K, T>
+where
+    K: Key,
+    T: Serialize + SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for AlertSink");
+        let table: AlertTable =
+            serde_json::from_value(config.table).expect("Invalid table config for AlertSink");
+
+        Self {
+            dedup_window: table
+                .dedup_window_seconds
+                .map(|s| Duration::from_secs(s as u64))
+                .unwrap_or(DEFAULT_DEDUP_WINDOW),
+            table,
+            client: reqwest::ClientBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("could not construct reqwest client"),
+            sent: HashMap::new(),
+            max_time: SystemTime::UNIX_EPOCH,
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "AlertSink".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("a", "alert sink dedup state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        // dedup windows are restored from every subtask, as records for a key may be routed
+        // to a different subtask after rescaling
+        let mut s: GlobalKeyedState<usize, AlertSinkState, _> =
+            ctx.state.get_global_keyed_state('a').await;
+        for state in s.get_all() {
+            for (key, time) in &state.sent {
+                let time = from_millis(*time);
+                let last = self.sent.entry(key.clone()).or_insert(time);
+                *last = (*last).max(time);
+            }
+        }
+    }
+
+    fn request(&self, record: &Value, dedup_key: Option<&str>) -> (&str, Value) {
+        let message = render_template(&self.table.message, record);
+
+        match &self.table.destination {
+            Destination::Slack { webhook_url } => {
+                (webhook_url.as_str(), json!({ "text": message }))
+            }
+            Destination::PagerDuty {
+                routing_key,
+                severity,
+            } => {
+                let severity = match severity {
+                    Some(Severity::Critical) => "critical",
+                    Some(Severity::Warning) => "warning",
+                    Some(Severity::Info) => "info",
+                    Some(Severity::Error) | None => "error",
+                };
+
+                let mut event = json!({
+                    "routing_key": routing_key,
+                    "event_action": "trigger",
+                    "payload": {
+                        "summary": truncate(&message, MAX_SUMMARY_LENGTH),
+                        "source": "arroyo",
+                        "severity": severity,
+                        "custom_details": record,
+                    }
+                });
+
+                if let Some(dedup_key) = dedup_key {
+                    event["dedup_key"] = Value::String(dedup_key.to_string());
+                }
+
+                (PAGERDUTY_EVENTS_URL, event)
+            }
+        }
+    }
+
+    /// Sends the alert, retrying rate limits and server errors with exponential backoff
+    async fn send(&self, url: &str, body: &Value) -> Result<(), String> {
+        let mut retries = 0;
+        loop {
+            let result = self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .send()
+                .await;
+            let error = match result {
+                Ok(resp) if resp.status().is_success() => return Ok(()),
+                Ok(resp)
+                    if resp.status() != StatusCode::TOO_MANY_REQUESTS
+                        && !resp.status().is_server_error() =>
+                {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_default();
+                    return Err(format!("server responded with {}: {}", status, text));
+                }
+                Ok(resp) => format!("server responded with {}", resp.status()),
+                Err(e) => e.to_string(),
+            };
+
+            if retries >= MAX_RETRIES {
+                return Err(error);
+            }
+
+            warn!("failed to send alert (retry {}): {}", retries, error);
+            retries += 1;
+            tokio::time::sleep(Duration::from_millis(200 * (1 << retries))).await;
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let value = serde_json::to_value(&record.value).unwrap_or(Value::Null);
+        self.max_time = self.max_time.max(record.timestamp);
+
+        let dedup_key = self
+            .table
+            .dedup_key
+            .as_ref()
+            .map(|t| render_template(t, &value));
+
+        if let Some(key) = &dedup_key {
+            if !should_send(
+                &mut self.sent,
+                key.clone(),
+                record.timestamp,
+                self.dedup_window,
+            ) {
+                return;
+            }
+        }
+
+        let (url, body) = self.request(&value, dedup_key.as_deref());
+        if let Err(e) = self.send(url, &body).await {
+            // alerts that can't be delivered are dropped rather than failing the pipeline
+            self.errors += 1;
+            if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+                ctx.report_error(format!("Failed to send alert x {}", self.errors), e)
+                    .await;
+                self.errors = 0;
+                self.last_reported_error = Instant::now();
+            }
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        // keys whose windows have closed no longer need to be tracked
+        let (max_time, window) = (self.max_time, self.dedup_window);
+        self.sent.retain(|_, last| *last + window > max_time);
+
+        let mut s = ctx.state.get_global_keyed_state('a').await;
+        s.insert(
+            ctx.task_info.task_index,
+            AlertSinkState {
+                sent: self
+                    .sent
+                    .iter()
+                    .map(|(key, time)| (key.clone(), to_millis(*time)))
+                    .collect(),
+            },
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    use super::{should_send, truncate};
+
+    #[test]
+    fn test_should_send() {
+        let mut sent = HashMap::new();
+        let window = Duration::from_secs(60);
+        let t = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+
+        assert!(should_send(&mut sent, "a".to_string(), t, window));
+        assert!(!should_send(
+            &mut sent,
+            "a".to_string(),
+            t + Duration::from_secs(30),
+            window
+        ));
+        assert!(should_send(&mut sent, "b".to_string(), t, window));
+        assert!(should_send(
+            &mut sent,
+            "a".to_string(),
+            t + Duration::from_secs(60),
+            window
+        ));
+        assert!(!should_send(
+            &mut sent,
+            "a".to_string(),
+            t + Duration::from_secs(90),
+            window
+        ));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello", 3), "hel");
+        assert_eq!(truncate("héllo", 2), "h");
+    }
+}
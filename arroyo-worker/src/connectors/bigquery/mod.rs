@@ -0,0 +1,256 @@
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::{DataType, Schema};
+use chrono::DateTime;
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FieldDescriptorProto};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use typify::import_types;
+
+pub mod proto;
+pub mod sink;
+
+import_types!(schema = "../connector-schemas/bigquery/connection.json");
+import_types!(schema = "../connector-schemas/bigquery/table.json");
+
+const BIGQUERY_SCOPE: &str = "https://www.googleapis.com/auth/bigquery";
+
+pub(crate) async fn authenticate(config: &BigQueryConfig) -> Result<AuthenticationManager> {
+    Ok(match &config.credentials {
+        Some(credentials) => {
+            AuthenticationManager::from(CustomServiceAccount::from_json(credentials)?)
+        }
+        None => AuthenticationManager::new().await?,
+    })
+}
+
+pub(crate) async fn token(auth: &AuthenticationManager) -> Result<String> {
+    // tokens are cached by the manager and refreshed before they expire
+    Ok(auth
+        .get_token(&[BIGQUERY_SCOPE])
+        .await?
+        .as_str()
+        .to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BigQueryType {
+    Bool,
+    Int64,
+    Float64,
+    String,
+    Timestamp,
+}
+
+impl BigQueryType {
+    fn for_arrow(data_type: &DataType) -> Option<Self> {
+        Some(match data_type {
+            DataType::Boolean => BigQueryType::Bool,
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64 => BigQueryType::Int64,
+            DataType::Float16 | DataType::Float32 | DataType::Float64 => BigQueryType::Float64,
+            DataType::Utf8 | DataType::LargeUtf8 => BigQueryType::String,
+            DataType::Timestamp(_, _) => BigQueryType::Timestamp,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            BigQueryType::Bool => "BOOL",
+            BigQueryType::Int64 => "INT64",
+            BigQueryType::Float64 => "FLOAT64",
+            BigQueryType::String => "STRING",
+            BigQueryType::Timestamp => "TIMESTAMP",
+        }
+    }
+
+    fn proto_type(&self) -> Type {
+        match self {
+            BigQueryType::Bool => Type::Bool,
+            BigQueryType::Int64 => Type::Int64,
+            BigQueryType::Float64 => Type::Double,
+            BigQueryType::String => Type::String,
+            // timestamps are written as microseconds since the epoch
+            BigQueryType::Timestamp => Type::Int64,
+        }
+    }
+}
+
+/// A column of the BigQuery table, which is written as the field of the row message with tag
+/// `index + 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigQueryField {
+    name: String,
+    type_: BigQueryType,
+    nullable: bool,
+}
+
+pub fn fields_for_schema(schema: &Schema) -> Result<Vec<BigQueryField>> {
+    schema
+        .fields()
+        .iter()
+        .map(|f| {
+            Ok(BigQueryField {
+                name: f.name().clone(),
+                type_: BigQueryType::for_arrow(f.data_type()).ok_or_else(|| {
+                    anyhow!(
+                        "field '{}' has type {:?}, which can't be written to BigQuery",
+                        f.name(),
+                        f.data_type()
+                    )
+                })?,
+                nullable: f.is_nullable(),
+            })
+        })
+        .collect()
+}
+
+/// The proto2 descriptor for rows; BigQuery matches its fields to columns by name
+pub fn descriptor(fields: &[BigQueryField]) -> DescriptorProto {
+    DescriptorProto {
+        name: Some("ArroyoRow".to_string()),
+        field: fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| FieldDescriptorProto {
+                name: Some(f.name.clone()),
+                number: Some(i as i32 + 1),
+                label: Some(Label::Optional as i32),
+                r#type: Some(f.type_.proto_type() as i32),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// The table resource used to create the table through the REST API
+pub fn table_resource(
+    project_id: &str,
+    dataset: &str,
+    table_name: &str,
+    fields: &[BigQueryField],
+) -> Value {
+    json!({
+        "tableReference": {
+            "projectId": project_id,
+            "datasetId": dataset,
+            "tableId": table_name,
+        },
+        "schema": {
+            "fields": fields.iter().map(|f| json!({
+                "name": f.name,
+                "type": f.type_.name(),
+                "mode": if f.nullable { "NULLABLE" } else { "REQUIRED" },
+            })).collect::<Vec<_>>(),
+        },
+    })
+}
+
+/// Encodes a row (as serialized to JSON) as a protobuf message matching `descriptor`
+pub fn encode_row(fields: &[BigQueryField], row: &Value, buf: &mut Vec<u8>) -> Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        let tag = i as u32 + 1;
+        let value = row.get(&field.name).unwrap_or(&Value::Null);
+        if value.is_null() {
+            if !field.nullable {
+                bail!("field '{}' is null", field.name);
+            }
+            continue;
+        }
+
+        let invalid = || anyhow!("invalid value {} for field '{}'", value, field.name);
+
+        match field.type_ {
+            BigQueryType::Bool => {
+                prost::encoding::bool::encode(tag, &value.as_bool().ok_or_else(invalid)?, buf)
+            }
+            BigQueryType::Int64 => {
+                prost::encoding::int64::encode(tag, &value.as_i64().ok_or_else(invalid)?, buf)
+            }
+            BigQueryType::Float64 => {
+                prost::encoding::double::encode(tag, &value.as_f64().ok_or_else(invalid)?, buf)
+            }
+            BigQueryType::String => match value {
+                Value::String(s) => prost::encoding::string::encode(tag, s, buf),
+                other => prost::encoding::string::encode(tag, &other.to_string(), buf),
+            },
+            BigQueryType::Timestamp => {
+                let micros = match value {
+                    Value::String(s) => DateTime::parse_from_rfc3339(s)
+                        .map_err(|_| invalid())?
+                        .timestamp_micros(),
+                    _ => value.as_i64().ok_or_else(invalid)? * 1000,
+                };
+                prost::encoding::int64::encode(tag, &micros, buf)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use prost::Message;
+    use serde_json::json;
+
+    use super::{encode_row, fields_for_schema};
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Row {
+        #[prost(int64, optional, tag = "1")]
+        id: Option<i64>,
+        #[prost(string, optional, tag = "2")]
+        name: Option<String>,
+        #[prost(int64, optional, tag = "3")]
+        time: Option<i64>,
+    }
+
+    #[test]
+    fn test_encode_row() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::UInt32, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "time",
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                false,
+            ),
+        ]);
+
+        let fields = fields_for_schema(&schema).unwrap();
+
+        let mut buf = vec![];
+        encode_row(
+            &fields,
+            &json!({"id": 5, "name": null, "time": "2023-10-01T12:00:00.000001Z"}),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Row::decode(&buf[..]).unwrap(),
+            Row {
+                id: Some(5),
+                name: None,
+                time: Some(1696161600000001),
+            }
+        );
+
+        assert!(encode_row(&fields, &json!({"id": null}), &mut vec![]).is_err());
+        assert!(encode_row(&fields, &json!({"id": "5"}), &mut vec![]).is_err());
+
+        let unsupported = Schema::new(vec![Field::new("b", DataType::Binary, false)]);
+        assert!(fields_for_schema(&unsupported).is_err());
+    }
+}
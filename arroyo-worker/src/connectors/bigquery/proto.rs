@@ -0,0 +1,297 @@
+//! The subset of the BigQuery Storage Write API (google.cloud.bigquery.storage.v1.BigQueryWrite)
+//! used by the sink, along with a minimal client for it.
+
+use anyhow::{anyhow, Result};
+use prost_types::DescriptorProto;
+use tokio::sync::mpsc::Sender;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, ClientTlsConfig};
+use tonic::Request;
+
+const ENDPOINT: &str = "https://bigquerystorage.googleapis.com";
+const DOMAIN: &str = "bigquerystorage.googleapis.com";
+const SERVICE: &str = "/google.cloud.bigquery.storage.v1.BigQueryWrite";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum WriteStreamType {
+    Unspecified = 0,
+    Committed = 1,
+    Pending = 2,
+    Buffered = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum StorageErrorCode {
+    Unspecified = 0,
+    TableNotFound = 1,
+    StreamAlreadyCommitted = 2,
+    StreamNotFound = 3,
+    InvalidStreamType = 4,
+    InvalidStreamState = 5,
+    StreamFinalized = 6,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WriteStream {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(enumeration = "WriteStreamType", tag = "2")]
+    pub r#type: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateWriteStreamRequest {
+    #[prost(string, tag = "1")]
+    pub parent: String,
+    #[prost(message, optional, tag = "2")]
+    pub write_stream: Option<WriteStream>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoSchema {
+    #[prost(message, optional, tag = "1")]
+    pub proto_descriptor: Option<DescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoRows {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub serialized_rows: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProtoData {
+    #[prost(message, optional, tag = "1")]
+    pub writer_schema: Option<ProtoSchema>,
+    #[prost(message, optional, tag = "2")]
+    pub rows: Option<ProtoRows>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AppendRowsRequest {
+    #[prost(string, tag = "1")]
+    pub write_stream: String,
+    #[prost(message, optional, tag = "4")]
+    pub proto_rows: Option<ProtoData>,
+    #[prost(string, tag = "6")]
+    pub trace_id: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Status {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RowError {
+    #[prost(int64, tag = "1")]
+    pub index: i64,
+    #[prost(string, tag = "3")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AppendRowsResponse {
+    #[prost(message, optional, tag = "2")]
+    pub error: Option<Status>,
+    #[prost(message, repeated, tag = "4")]
+    pub row_errors: Vec<RowError>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FinalizeWriteStreamRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FinalizeWriteStreamResponse {
+    #[prost(int64, tag = "1")]
+    pub row_count: i64,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchCommitWriteStreamsRequest {
+    #[prost(string, tag = "1")]
+    pub parent: String,
+    #[prost(string, repeated, tag = "2")]
+    pub write_streams: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StorageError {
+    #[prost(enumeration = "StorageErrorCode", tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub entity: String,
+    #[prost(string, tag = "3")]
+    pub error_message: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchCommitWriteStreamsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub commit_time: Option<prost_types::Timestamp>,
+    #[prost(message, repeated, tag = "2")]
+    pub stream_errors: Vec<StorageError>,
+}
+
+/// An open AppendRows call; requests are sent over `tx` and each is answered in order by a
+/// message on `responses`
+pub struct AppendRowsStream {
+    tx: Sender<AppendRowsRequest>,
+    responses: Streaming<AppendRowsResponse>,
+}
+
+impl AppendRowsStream {
+    pub async fn append(&mut self, request: AppendRowsRequest) -> Result<AppendRowsResponse> {
+        self.tx
+            .send(request)
+            .await
+            .map_err(|_| anyhow!("AppendRows stream closed"))?;
+
+        self.responses
+            .message()
+            .await?
+            .ok_or_else(|| anyhow!("AppendRows stream closed"))
+    }
+}
+
+#[derive(Clone)]
+pub struct BigQueryWriteClient {
+    grpc: tonic::client::Grpc<Channel>,
+}
+
+impl BigQueryWriteClient {
+    pub async fn connect() -> Result<Self> {
+        let channel = Channel::from_static(ENDPOINT)
+            .tls_config(ClientTlsConfig::new().domain_name(DOMAIN))?
+            .connect()
+            .await?;
+
+        Ok(Self {
+            grpc: tonic::client::Grpc::new(channel),
+        })
+    }
+
+    fn request<T>(message: T, token: &str, routing: String) -> Result<Request<T>> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            MetadataValue::try_from(format!("Bearer {}", token))?,
+        );
+        request
+            .metadata_mut()
+            .insert("x-goog-request-params", MetadataValue::try_from(routing)?);
+        Ok(request)
+    }
+
+    async fn unary<Req, Resp>(
+        &mut self,
+        method: &'static str,
+        request: Request<Req>,
+    ) -> Result<Resp>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.grpc.ready().await?;
+        let path = PathAndQuery::from_maybe_shared(format!("{}/{}", SERVICE, method))?;
+        Ok(self
+            .grpc
+            .unary(request, path, ProstCodec::default())
+            .await?
+            .into_inner())
+    }
+
+    pub async fn create_write_stream(
+        &mut self,
+        token: &str,
+        table: &str,
+        stream_type: WriteStreamType,
+    ) -> Result<WriteStream> {
+        let request = Self::request(
+            CreateWriteStreamRequest {
+                parent: table.to_string(),
+                write_stream: Some(WriteStream {
+                    name: String::new(),
+                    r#type: stream_type as i32,
+                }),
+            },
+            token,
+            format!("parent={}", table),
+        )?;
+
+        self.unary("CreateWriteStream", request).await
+    }
+
+    /// Opens an AppendRows call, sending `first` to start it
+    pub async fn append_rows(
+        &mut self,
+        token: &str,
+        first: AppendRowsRequest,
+    ) -> Result<(AppendRowsStream, AppendRowsResponse)> {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let routing = format!("write_stream={}", first.write_stream);
+        // the server doesn't respond until it receives the first request
+        tx.send(first).await.unwrap();
+
+        self.grpc.ready().await?;
+        let path = PathAndQuery::from_maybe_shared(format!("{}/AppendRows", SERVICE))?;
+        let request = Self::request(ReceiverStream::new(rx), token, routing)?;
+        let mut responses = self
+            .grpc
+            .streaming(request, path, ProstCodec::default())
+            .await?
+            .into_inner();
+
+        let response = responses
+            .message()
+            .await?
+            .ok_or_else(|| anyhow!("AppendRows stream closed"))?;
+
+        Ok((AppendRowsStream { tx, responses }, response))
+    }
+
+    pub async fn finalize_write_stream(
+        &mut self,
+        token: &str,
+        name: &str,
+    ) -> Result<FinalizeWriteStreamResponse> {
+        let request = Self::request(
+            FinalizeWriteStreamRequest {
+                name: name.to_string(),
+            },
+            token,
+            format!("name={}", name),
+        )?;
+
+        self.unary("FinalizeWriteStream", request).await
+    }
+
+    pub async fn batch_commit_write_streams(
+        &mut self,
+        token: &str,
+        table: &str,
+        streams: Vec<String>,
+    ) -> Result<BatchCommitWriteStreamsResponse> {
+        let request = Self::request(
+            BatchCommitWriteStreamsRequest {
+                parent: table.to_string(),
+                write_streams: streams,
+            },
+            token,
+            format!("parent={}", table),
+        )?;
+
+        self.unary("BatchCommitWriteStreams", request).await
+    }
+}
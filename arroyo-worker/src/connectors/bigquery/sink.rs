@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::{Key, Record, TaskInfo};
+use async_trait::async_trait;
+use gcp_auth::AuthenticationManager;
+use prost_types::DescriptorProto;
+use tracing::{info, warn};
+
+use crate::connectors::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
+use crate::SchemaData;
+
+use super::proto::{
+    AppendRowsRequest, AppendRowsStream, BigQueryWriteClient, ProtoData, ProtoRows, ProtoSchema,
+    StorageErrorCode, WriteStreamType,
+};
+use super::{
+    authenticate, descriptor, encode_row, fields_for_schema, table_resource, token, BigQueryConfig,
+    BigQueryField, BigQueryTable,
+};
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+// AppendRows requests are limited to 10MB
+const MAX_BATCH_BYTES: usize = 8 * 1024 * 1024;
+
+/// The pending write stream for the current epoch. Rows appended to a pending stream aren't
+/// visible until the stream is committed, which happens once the checkpoint completes; streams
+/// that are never committed are discarded by BigQuery.
+struct PendingStream {
+    name: String,
+    append: Option<AppendRowsStream>,
+}
+
+pub struct BigQuerySinkFunc<K: Key, T: SchemaData + Sync> {
+    config: BigQueryConfig,
+    table: BigQueryTable,
+    table_path: String,
+    fields: Vec<BigQueryField>,
+    descriptor: DescriptorProto,
+    auth: Option<AuthenticationManager>,
+    client: Option<BigQueryWriteClient>,
+    stream: Option<PendingStream>,
+    rows: Vec<Vec<u8>>,
+    buffered_bytes: usize,
+    batch_size: usize,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key, T: SchemaData + Sync> BigQuerySinkFunc<K, T> {
+    pub fn from_config(config: &str) -> TwoPhaseCommitterOperator<K, T, Self> {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for BigQuerySink");
+        let connection: BigQueryConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for BigQuerySink");
+        let table: BigQueryTable =
+            serde_json::from_value(config.table).expect("Invalid table config for BigQuerySink");
+
+        let fields = fields_for_schema(&T::schema()).expect("Invalid schema for BigQuerySink");
+
+        TwoPhaseCommitterOperator::new(Self {
+            table_path: format!(
+                "projects/{}/datasets/{}/tables/{}",
+                connection.project_id, table.dataset, table.table_name
+            ),
+            config: connection,
+            descriptor: descriptor(&fields),
+            fields,
+            auth: None,
+            client: None,
+            stream: None,
+            rows: vec![],
+            buffered_bytes: 0,
+            batch_size: table
+                .batch_size
+                .map(|s| s as usize)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            table,
+            _t: PhantomData,
+        })
+    }
+
+    async fn token(&self) -> Result<String> {
+        token(
+            self.auth
+                .as_ref()
+                .expect("bigquery sink was not initialized"),
+        )
+        .await
+    }
+
+    fn client(&mut self) -> &mut BigQueryWriteClient {
+        self.client
+            .as_mut()
+            .expect("bigquery sink was not initialized")
+    }
+
+    async fn create_table(&self) -> Result<()> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables",
+                self.config.project_id, self.table.dataset
+            ))
+            .bearer_auth(self.token().await?)
+            .json(&table_resource(
+                &self.config.project_id,
+                &self.table.dataset,
+                &self.table.table_name,
+                &self.fields,
+            ))
+            .send()
+            .await?;
+
+        match response.status().as_u16() {
+            200 => info!("created BigQuery table {}", self.table_path),
+            // another subtask (or a previous run) created it
+            409 => {}
+            status => bail!(
+                "failed to create BigQuery table {}: {} {}",
+                self.table_path,
+                status,
+                response.text().await.unwrap_or_default()
+            ),
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let token = self.token().await?;
+
+        if self.stream.is_none() {
+            let table_path = self.table_path.clone();
+            let stream = self
+                .client()
+                .create_write_stream(&token, &table_path, WriteStreamType::Pending)
+                .await?;
+            self.stream = Some(PendingStream {
+                name: stream.name,
+                append: None,
+            });
+        }
+
+        let rows = ProtoRows {
+            serialized_rows: std::mem::take(&mut self.rows),
+        };
+        self.buffered_bytes = 0;
+
+        let stream = self.stream.as_mut().unwrap();
+        let response = match &mut stream.append {
+            Some(append) => {
+                append
+                    .append(AppendRowsRequest {
+                        write_stream: String::new(),
+                        proto_rows: Some(ProtoData {
+                            writer_schema: None,
+                            rows: Some(rows),
+                        }),
+                        trace_id: String::new(),
+                    })
+                    .await?
+            }
+            None => {
+                // the first request on a connection identifies the stream and the row schema
+                let request = AppendRowsRequest {
+                    write_stream: stream.name.clone(),
+                    proto_rows: Some(ProtoData {
+                        writer_schema: Some(ProtoSchema {
+                            proto_descriptor: Some(self.descriptor.clone()),
+                        }),
+                        rows: Some(rows),
+                    }),
+                    trace_id: "arroyo".to_string(),
+                };
+
+                let (append, response) = self
+                    .client
+                    .as_mut()
+                    .unwrap()
+                    .append_rows(&token, request)
+                    .await?;
+                stream.append = Some(append);
+                response
+            }
+        };
+
+        if let Some(error) = response.row_errors.first() {
+            bail!(
+                "BigQuery rejected row {} of the batch: {}",
+                error.index,
+                error.message
+            );
+        }
+
+        if let Some(error) = response.error {
+            bail!("failed to append rows to BigQuery: {}", error.message);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<K: Key, T: SchemaData + Sync> TwoPhaseCommitter<K, T> for BigQuerySinkFunc<K, T> {
+    type DataRecovery = ();
+    type PreCommit = String;
+
+    fn name(&self) -> String {
+        "bigquery_sink".to_string()
+    }
+
+    async fn init(&mut self, _: &TaskInfo, _: Vec<Self::DataRecovery>) -> Result<()> {
+        self.auth = Some(
+            authenticate(&self.config)
+                .await
+                .map_err(|e| anyhow!("failed to authenticate with GCP: {}", e))?,
+        );
+        self.client = Some(BigQueryWriteClient::connect().await?);
+
+        if self.table.create_table.unwrap_or(false) {
+            self.create_table().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn insert_record(&mut self, record: &Record<K, T>) -> Result<()> {
+        let value = serde_json::to_value(&record.value)?;
+
+        let mut buf = vec![];
+        encode_row(&self.fields, &value, &mut buf)?;
+
+        self.buffered_bytes += buf.len();
+        self.rows.push(buf);
+
+        if self.rows.len() >= self.batch_size || self.buffered_bytes >= MAX_BATCH_BYTES {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn commit(&mut self, _: &TaskInfo, pre_commit: Vec<Self::PreCommit>) -> Result<()> {
+        if pre_commit.is_empty() {
+            return Ok(());
+        }
+
+        let token = self.token().await?;
+        let table_path = self.table_path.clone();
+        let response = self
+            .client()
+            .batch_commit_write_streams(&token, &table_path, pre_commit)
+            .await?;
+
+        for error in response.stream_errors {
+            if error.code == StorageErrorCode::StreamAlreadyCommitted as i32 {
+                // we were restarted after committing but before the checkpoint completed
+                warn!("write stream {} was already committed", error.entity);
+            } else {
+                bail!(
+                    "failed to commit write stream {}: {}",
+                    error.entity,
+                    error.error_message
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn checkpoint(
+        &mut self,
+        _: &TaskInfo,
+        _: Option<SystemTime>,
+        _: bool,
+    ) -> Result<(Self::DataRecovery, HashMap<String, Self::PreCommit>)> {
+        self.flush().await?;
+
+        let mut pre_commits = HashMap::new();
+        if let Some(stream) = self.stream.take() {
+            // closes the AppendRows call before finalizing the stream
+            drop(stream.append);
+
+            let token = self.token().await?;
+            let response = self
+                .client()
+                .finalize_write_stream(&token, &stream.name)
+                .await?;
+            info!(
+                "finalized write stream {} with {} rows",
+                stream.name, response.row_count
+            );
+
+            pre_commits.insert(stream.name.clone(), stream.name);
+        }
+
+        Ok(((), pre_commits))
+    }
+}
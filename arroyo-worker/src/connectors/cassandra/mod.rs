@@ -0,0 +1,235 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+use scylla::execution_profile::ExecutionProfile;
+use scylla::frame::response::result::{ColumnType, CqlValue};
+use scylla::load_balancing::DefaultPolicy;
+use scylla::{Session, SessionBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use typify::import_types;
+
+pub mod sink;
+
+import_types!(schema = "../connector-schemas/cassandra/connection.json");
+import_types!(schema = "../connector-schemas/cassandra/table.json");
+
+impl CassandraTable {
+    /// The quoted, keyspace-qualified name of the table
+    pub fn qualified_name(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_ident(&self.keyspace),
+            quote_ident(&self.table_name)
+        )
+    }
+
+    pub fn consistency(&self) -> scylla::statement::Consistency {
+        use scylla::statement::Consistency as C;
+        match self.consistency {
+            Some(Consistency::One) => C::One,
+            Some(Consistency::LocalOne) => C::LocalOne,
+            Some(Consistency::Quorum) => C::Quorum,
+            Some(Consistency::All) => C::All,
+            Some(Consistency::LocalQuorum) | None => C::LocalQuorum,
+        }
+    }
+}
+
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+pub(crate) async fn connect(config: &CassandraConfig) -> Result<Session> {
+    let hosts: Vec<&str> = config
+        .hosts
+        .split(',')
+        .map(|h| h.trim())
+        .filter(|h| !h.is_empty())
+        .collect();
+
+    let mut policy = DefaultPolicy::builder().token_aware(true);
+    if let Some(dc) = &config.local_datacenter {
+        policy = policy.prefer_datacenter(dc.clone());
+    }
+
+    let profile = ExecutionProfile::builder()
+        .load_balancing_policy(policy.build())
+        .build();
+
+    let mut builder = SessionBuilder::new()
+        .known_nodes(&hosts)
+        .default_execution_profile_handle(profile.into_handle());
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        builder = builder.user(username, password);
+    }
+
+    Ok(builder.build().await?)
+}
+
+fn int<T: TryFrom<i64>>(value: &Value) -> Result<T> {
+    value
+        .as_i64()
+        .and_then(|i| T::try_from(i).ok())
+        .ok_or_else(|| anyhow!("{} is not a valid integer for this column", value))
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Converts a JSON value from a record into a value of the given CQL type; nulls are returned
+/// as `None`, which binds as a CQL null
+pub(crate) fn to_cql(value: &Value, typ: &ColumnType) -> Result<Option<CqlValue>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    let v = match typ {
+        ColumnType::Ascii => CqlValue::Ascii(text(value)),
+        ColumnType::Text => CqlValue::Text(text(value)),
+        ColumnType::Boolean => CqlValue::Boolean(
+            value
+                .as_bool()
+                .ok_or_else(|| anyhow!("{} is not a valid boolean", value))?,
+        ),
+        ColumnType::TinyInt => CqlValue::TinyInt(int(value)?),
+        ColumnType::SmallInt => CqlValue::SmallInt(int(value)?),
+        ColumnType::Int => CqlValue::Int(int(value)?),
+        ColumnType::BigInt => CqlValue::BigInt(int(value)?),
+        ColumnType::Counter => bail!("counter columns can't be written by INSERT"),
+        ColumnType::Float => CqlValue::Float(
+            value
+                .as_f64()
+                .ok_or_else(|| anyhow!("{} is not a valid float", value))? as f32,
+        ),
+        ColumnType::Double => CqlValue::Double(
+            value
+                .as_f64()
+                .ok_or_else(|| anyhow!("{} is not a valid double", value))?,
+        ),
+        // timestamps are written by the sink as milliseconds since the epoch
+        ColumnType::Timestamp => CqlValue::Timestamp(chrono::Duration::milliseconds(int(value)?)),
+        ColumnType::Date => {
+            let date = NaiveDate::parse_from_str(&text(value), "%Y-%m-%d")
+                .map_err(|_| anyhow!("{} is not a valid date", value))?;
+            let days = date
+                .signed_duration_since(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap())
+                .num_days();
+            // dates are encoded as days since the epoch, centered at 2^31
+            CqlValue::Date((days + (1 << 31)) as u32)
+        }
+        ColumnType::Uuid => CqlValue::Uuid(uuid::Uuid::parse_str(&text(value))?),
+        ColumnType::Timeuuid => CqlValue::Timeuuid(uuid::Uuid::parse_str(&text(value))?),
+        ColumnType::Inet => CqlValue::Inet(
+            text(value)
+                .parse()
+                .map_err(|_| anyhow!("{} is not a valid IP address", value))?,
+        ),
+        ColumnType::Blob => match value {
+            Value::Array(bytes) => {
+                CqlValue::Blob(bytes.iter().map(int::<u8>).collect::<Result<Vec<_>>>()?)
+            }
+            other => CqlValue::Blob(text(other).into_bytes()),
+        },
+        ColumnType::List(inner) | ColumnType::Set(inner) => {
+            let Value::Array(values) = value else {
+                bail!("{} is not a valid list", value);
+            };
+
+            let values = values
+                .iter()
+                .map(|v| {
+                    to_cql(v, inner)?.ok_or_else(|| anyhow!("collections can't contain nulls"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if matches!(typ, ColumnType::Set(_)) {
+                CqlValue::Set(values)
+            } else {
+                CqlValue::List(values)
+            }
+        }
+        ColumnType::Map(key_type, value_type) => {
+            let Value::Object(entries) = value else {
+                bail!("{} is not a valid map", value);
+            };
+
+            CqlValue::Map(
+                entries
+                    .iter()
+                    .map(|(k, v)| {
+                        let key = to_cql(&Value::String(k.clone()), key_type)?.unwrap();
+                        let value = to_cql(v, value_type)?
+                            .ok_or_else(|| anyhow!("collections can't contain nulls"))?;
+                        Ok((key, value))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        }
+        other => bail!("columns of type {:?} are not supported", other),
+    };
+
+    Ok(Some(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use scylla::frame::response::result::{ColumnType, CqlValue};
+    use serde_json::json;
+
+    use super::to_cql;
+
+    #[test]
+    fn test_to_cql() {
+        assert_eq!(to_cql(&json!(null), &ColumnType::Int).unwrap(), None);
+        assert_eq!(
+            to_cql(&json!(5), &ColumnType::SmallInt).unwrap(),
+            Some(CqlValue::SmallInt(5))
+        );
+        assert!(to_cql(&json!(100000), &ColumnType::SmallInt).is_err());
+        assert_eq!(
+            to_cql(&json!("hello"), &ColumnType::Text).unwrap(),
+            Some(CqlValue::Text("hello".to_string()))
+        );
+        assert_eq!(
+            to_cql(&json!(1696942938000i64), &ColumnType::Timestamp).unwrap(),
+            Some(CqlValue::Timestamp(chrono::Duration::milliseconds(
+                1696942938000
+            )))
+        );
+        assert_eq!(
+            to_cql(&json!("1970-01-02"), &ColumnType::Date).unwrap(),
+            Some(CqlValue::Date((1 << 31) + 1))
+        );
+        assert_eq!(
+            to_cql(
+                &json!(["a", "b"]),
+                &ColumnType::Set(Box::new(ColumnType::Text))
+            )
+            .unwrap(),
+            Some(CqlValue::Set(vec![
+                CqlValue::Text("a".to_string()),
+                CqlValue::Text("b".to_string())
+            ]))
+        );
+        assert_eq!(
+            to_cql(
+                &json!({"x": 1}),
+                &ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::BigInt))
+            )
+            .unwrap(),
+            Some(CqlValue::Map(vec![(
+                CqlValue::Text("x".to_string()),
+                CqlValue::BigInt(1)
+            )]))
+        );
+        assert!(to_cql(
+            &json!([1, null]),
+            &ColumnType::List(Box::new(ColumnType::Int))
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::DataType;
+use arroyo_macro::process_fn;
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use futures::{StreamExt, TryStreamExt};
+use scylla::batch::{Batch, BatchType};
+use scylla::frame::response::result::CqlValue;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::Session;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::engine::{Context, StreamNode};
+use crate::SchemaData;
+
+use super::{connect, quote_ident, to_cql, CassandraConfig, CassandraTable};
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// large batches put pressure on the coordinator, so partitions with many changes are split
+const MAX_STATEMENTS_PER_BATCH: usize = 100;
+const MAX_CONCURRENT_BATCHES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Upsert(Value),
+    Delete(Value),
+}
+
+#[derive(StreamNode)]
+pub struct CassandraSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: CassandraConfig,
+    table: CassandraTable,
+    debezium: bool,
+    session: Option<Session>,
+    insert: Option<PreparedStatement>,
+    delete: Option<PreparedStatement>,
+    // the columns bound by the insert statement, in order
+    columns: Vec<String>,
+    partition_keys: Vec<String>,
+    // partition keys followed by clustering keys
+    primary_keys: Vec<String>,
+    // changes are buffered in arrival order, keeping only the last change for each row; all
+    // statements in a batch share a write timestamp, so a row must not change twice in a batch
+    buffer: Vec<Change>,
+    buffer_index: HashMap<String, usize>,
+    last_flush: Instant,
+    _t: PhantomData<(K, T)>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> CassandraSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for CassandraSink");
+        let connection: CassandraConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for CassandraSink");
+        let table: CassandraTable =
+            serde_json::from_value(config.table).expect("Invalid table config for CassandraSink");
+
+        let debezium = match config.format {
+            Some(Format::Json(JsonFormat { debezium, .. })) => debezium,
+            _ => panic!("CassandraSink requires a JSON format"),
+        };
+
+        // with debezium, the columns are those of the before and after structs
+        let schema = T::schema();
+        let fields = if debezium {
+            match schema.field_with_name("after").map(|f| f.data_type()) {
+                Ok(DataType::Struct(fields)) => fields.clone(),
+                _ => panic!("invalid debezium schema for CassandraSink"),
+            }
+        } else {
+            schema.fields.clone()
+        };
+
+        Self {
+            config: connection,
+            table,
+            debezium,
+            session: None,
+            insert: None,
+            delete: None,
+            columns: fields.iter().map(|f| f.name().clone()).collect(),
+            partition_keys: vec![],
+            primary_keys: vec![],
+            buffer: vec![],
+            buffer_index: HashMap::new(),
+            last_flush: Instant::now(),
+            _t: PhantomData,
+        }
+    }
+
+    fn session(&self) -> &Session {
+        self.session
+            .as_ref()
+            .expect("cassandra sink was not initialized")
+    }
+
+    /// Loads the partition and clustering keys of the table, each in key order
+    async fn load_keys(&self) -> Result<(Vec<String>, Vec<String>)> {
+        let mut columns: Vec<(String, String, i32)> = self
+            .session()
+            .query(
+                "SELECT column_name, kind, position FROM system_schema.columns \
+                 WHERE keyspace_name = ? AND table_name = ?",
+                (&self.table.keyspace, &self.table.table_name),
+            )
+            .await?
+            .rows_typed::<(String, String, i32)>()?
+            .collect::<Result<_, _>>()?;
+
+        if columns.is_empty() {
+            bail!("table {} does not exist", self.table.qualified_name());
+        }
+
+        columns.sort_by_key(|(_, _, position)| *position);
+
+        let keys = |kind: &str| {
+            columns
+                .iter()
+                .filter(|(_, k, _)| k == kind)
+                .map(|(c, _, _)| c.clone())
+                .collect::<Vec<_>>()
+        };
+
+        Ok((keys("partition_key"), keys("clustering")))
+    }
+
+    async fn init(&mut self) -> Result<()> {
+        self.session = Some(connect(&self.config).await?);
+
+        let (partition_keys, clustering_keys) = self.load_keys().await?;
+        for key in partition_keys.iter().chain(&clustering_keys) {
+            if !self.columns.contains(key) {
+                bail!(
+                    "primary key column '{}' of {} is missing from the schema",
+                    key,
+                    self.table.qualified_name()
+                );
+            }
+        }
+
+        self.primary_keys = partition_keys
+            .iter()
+            .chain(&clustering_keys)
+            .cloned()
+            .collect();
+        self.partition_keys = partition_keys;
+
+        let table = self.table.qualified_name();
+        let insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            self.columns
+                .iter()
+                .map(|c| quote_ident(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; self.columns.len()].join(", ")
+        );
+        let delete = format!(
+            "DELETE FROM {} WHERE {}",
+            table,
+            self.primary_keys
+                .iter()
+                .map(|c| format!("{} = ?", quote_ident(c)))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        );
+
+        // prepared statements carry the positions of the partition key, which lets the driver
+        // route each batch directly to a replica that owns it
+        let mut insert = self.session().prepare(insert).await?;
+        insert.set_consistency(self.table.consistency());
+        let mut delete = self.session().prepare(delete).await?;
+        delete.set_consistency(self.table.consistency());
+
+        self.insert = Some(insert);
+        self.delete = Some(delete);
+
+        info!(
+            "writing to {} with partition key ({})",
+            table,
+            self.partition_keys.join(", ")
+        );
+
+        Ok(())
+    }
+
+    fn key(row: &Value, columns: &[String]) -> Result<String> {
+        let key: Vec<&Value> = columns
+            .iter()
+            .map(|k| {
+                row.get(k)
+                    .filter(|v| !v.is_null())
+                    .ok_or_else(|| anyhow!("primary key column '{}' missing from record", k))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(serde_json::to_string(&key)?)
+    }
+
+    fn buffer_change(&mut self, change: Change) -> Result<()> {
+        let row = match &change {
+            Change::Upsert(row) | Change::Delete(row) => row,
+        };
+
+        let key = Self::key(row, &self.primary_keys)?;
+        if let Some(idx) = self.buffer_index.get(&key) {
+            self.buffer[*idx] = change;
+        } else {
+            self.buffer_index.insert(key, self.buffer.len());
+            self.buffer.push(change);
+        }
+
+        Ok(())
+    }
+
+    fn bind(&self, statement: &PreparedStatement, row: &Value) -> Result<Vec<Option<CqlValue>>> {
+        statement
+            .get_variable_col_specs()
+            .iter()
+            .map(|spec| {
+                let value = row.get(&spec.name).unwrap_or(&Value::Null);
+                to_cql(value, &spec.typ)
+                    .map_err(|e| anyhow!("invalid value for column '{}': {}", spec.name, e))
+            })
+            .collect()
+    }
+
+    /// Writes the buffered changes as unlogged batches, each containing changes to a single
+    /// partition; batches for different partitions are written concurrently
+    async fn flush(&mut self) -> Result<()> {
+        self.last_flush = Instant::now();
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let insert = self.insert.as_ref().unwrap();
+        let delete = self.delete.as_ref().unwrap();
+
+        let mut partitions: Vec<Vec<(&PreparedStatement, Vec<Option<CqlValue>>)>> = vec![];
+        let mut partition_index = HashMap::new();
+        for change in &self.buffer {
+            let (statement, row) = match change {
+                Change::Upsert(row) => (insert, row),
+                Change::Delete(row) => (delete, row),
+            };
+
+            let idx = *partition_index
+                .entry(Self::key(row, &self.partition_keys)?)
+                .or_insert_with(|| {
+                    partitions.push(vec![]);
+                    partitions.len() - 1
+                });
+
+            partitions[idx].push((statement, self.bind(statement, row)?));
+        }
+
+        let session = self.session();
+        let consistency = self.table.consistency();
+        futures::stream::iter(
+            partitions
+                .iter()
+                .flat_map(|p| p.chunks(MAX_STATEMENTS_PER_BATCH)),
+        )
+        .map(move |chunk| async move {
+            let mut batch = Batch::new(BatchType::Unlogged);
+            batch.set_consistency(consistency);
+            for (statement, _) in chunk {
+                batch.append_statement((*statement).clone());
+            }
+            let values: Vec<_> = chunk.iter().map(|(_, values)| values.clone()).collect();
+            session.batch(&batch, values).await
+        })
+        .buffer_unordered(MAX_CONCURRENT_BATCHES)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+        self.buffer.clear();
+        self.buffer_index.clear();
+
+        Ok(())
+    }
+
+    fn insert_record(&mut self, record: &Record<K, T>) -> Result<()> {
+        let value = serde_json::to_value(&record.value)?;
+
+        if self.debezium {
+            let op = value.get("op").and_then(|op| op.as_str());
+            let before = value.get("before").filter(|v| !v.is_null());
+            let after = value.get("after").filter(|v| !v.is_null());
+
+            match (op, before, after) {
+                (Some("c"), _, Some(after)) => {
+                    self.buffer_change(Change::Upsert(after.clone()))?;
+                }
+                (Some("u"), Some(before), Some(after)) => {
+                    // if the key changed, the old row needs to be removed
+                    if Self::key(before, &self.primary_keys)?
+                        != Self::key(after, &self.primary_keys)?
+                    {
+                        self.buffer_change(Change::Delete(before.clone()))?;
+                    }
+                    self.buffer_change(Change::Upsert(after.clone()))?;
+                }
+                (Some("d"), Some(before), _) => {
+                    self.buffer_change(Change::Delete(before.clone()))?;
+                }
+                _ => bail!("invalid debezium record: {}", value),
+            }
+        } else {
+            self.buffer_change(Change::Upsert(value))?;
+        }
+
+        Ok(())
+    }
+
+    async fn fail(&self, ctx: &mut Context<(), ()>, e: anyhow::Error) {
+        ctx.report_error(
+            format!("Failed to write to {}", self.table.qualified_name()),
+            format!("{:?}", e),
+        )
+        .await;
+        panic!(
+            "Failed to write to {}: {:?}",
+            self.table.qualified_name(),
+            e
+        );
+    }
+}
+
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> CassandraSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("cassandra-sink-{}", self.table.table_name)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        if let Err(e) = self.init().await {
+            ctx.report_error("Failed to connect to Cassandra".to_string(), e.to_string())
+                .await;
+            panic!("Failed to connect to Cassandra: {:?}", e);
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        if let Err(e) = self.insert_record(record) {
+            self.fail(ctx, e).await;
+            return;
+        }
+
+        let batch_size = self
+            .table
+            .batch_size
+            .map(|s| s as usize)
+            .unwrap_or(DEFAULT_BATCH_SIZE);
+
+        if self.buffer.len() >= batch_size {
+            if let Err(e) = self.flush().await {
+                self.fail(ctx, e).await;
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<(), ()>) {
+        let flush_interval = self
+            .table
+            .flush_interval_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL);
+
+        if self.last_flush.elapsed() >= flush_interval {
+            if let Err(e) = self.flush().await {
+                self.fail(ctx, e).await;
+            }
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        if let Err(e) = self.flush().await {
+            self.fail(ctx, e).await;
+        }
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        if let Err(e) = self.flush().await {
+            self.fail(ctx, e).await;
+        }
+    }
+}
@@ -0,0 +1,712 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::{grpc::TableDescriptor, OperatorConfig};
+use arroyo_types::{to_nanos, CheckpointBarrier, Key, Record};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use clickhouse_rs::{Block, ClientHandle, Pool};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+use typify::import_types;
+
+use crate::{
+    engine::{Context, StreamNode},
+    SchemaData,
+};
+
+import_types!(schema = "../connector-schemas/clickhouse/connection.json");
+import_types!(schema = "../connector-schemas/clickhouse/table.json");
+
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 20;
+
+/// The ClickHouse types that the sink knows how to write
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Bool,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+    String,
+    Date,
+    DateTime,
+    DateTime64(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ColumnType {
+    scalar: ScalarType,
+    nullable: bool,
+}
+
+impl ColumnType {
+    /// Parses a type as reported by system.columns, like `Nullable(Int64)` or
+    /// `DateTime64(3, 'UTC')`
+    fn parse(s: &str) -> Result<Self, String> {
+        let unwrap = |s: &str, wrapper: &str| {
+            s.strip_prefix(wrapper)
+                .and_then(|s| s.strip_prefix('('))
+                .and_then(|s| s.strip_suffix(')'))
+                .map(|s| s.trim().to_string())
+        };
+
+        let mut inner = s.trim().to_string();
+        if let Some(t) = unwrap(&inner, "LowCardinality") {
+            inner = t;
+        }
+
+        let nullable = match unwrap(&inner, "Nullable") {
+            Some(t) => {
+                inner = t;
+                true
+            }
+            None => false,
+        };
+
+        let scalar = match inner.as_str() {
+            "Bool" => ScalarType::Bool,
+            "Int8" => ScalarType::Int8,
+            "Int16" => ScalarType::Int16,
+            "Int32" => ScalarType::Int32,
+            "Int64" => ScalarType::Int64,
+            "UInt8" => ScalarType::UInt8,
+            "UInt16" => ScalarType::UInt16,
+            "UInt32" => ScalarType::UInt32,
+            "UInt64" => ScalarType::UInt64,
+            "Float32" => ScalarType::Float32,
+            "Float64" => ScalarType::Float64,
+            "String" => ScalarType::String,
+            "Date" => ScalarType::Date,
+            t if t == "DateTime" || unwrap(t, "DateTime").is_some() => ScalarType::DateTime,
+            t => match unwrap(t, "DateTime64") {
+                Some(args) => {
+                    let precision = args.split(',').next().unwrap().trim();
+                    ScalarType::DateTime64(
+                        precision
+                            .parse()
+                            .map_err(|_| format!("invalid precision in {}", s))?,
+                    )
+                }
+                None => return Err(format!("unsupported column type {}", s)),
+            },
+        };
+
+        Ok(Self { scalar, nullable })
+    }
+}
+
+/// The buffered values for a single column; nulls are only allowed in nullable columns
+#[derive(Debug, Clone, PartialEq)]
+enum ColumnData {
+    Bool(Vec<Option<bool>>),
+    Int8(Vec<Option<i8>>),
+    Int16(Vec<Option<i16>>),
+    Int32(Vec<Option<i32>>),
+    Int64(Vec<Option<i64>>),
+    UInt8(Vec<Option<u8>>),
+    UInt16(Vec<Option<u16>>),
+    UInt32(Vec<Option<u32>>),
+    UInt64(Vec<Option<u64>>),
+    Float32(Vec<Option<f32>>),
+    Float64(Vec<Option<f64>>),
+    String(Vec<Option<String>>),
+    Date(Vec<Option<NaiveDate>>),
+    DateTime(Vec<Option<DateTime<Tz>>>),
+}
+
+macro_rules! add_column {
+    ($block:expr, $name:expr, $nullable:expr, $values:expr) => {
+        if $nullable {
+            $block.column($name, $values.clone())
+        } else {
+            $block.column($name, $values.iter().cloned().flatten().collect::<Vec<_>>())
+        }
+    };
+}
+
+fn int<T: TryFrom<i64>>(value: &Value) -> Option<T> {
+    value.as_i64().and_then(|v| v.try_into().ok())
+}
+
+fn uint<T: TryFrom<u64>>(value: &Value) -> Option<T> {
+    value.as_u64().and_then(|v| v.try_into().ok())
+}
+
+fn timestamp(value: &Value) -> Option<DateTime<Tz>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Tz::UTC)),
+        Value::Number(n) => {
+            NaiveDateTime::from_timestamp_millis(n.as_i64()?).map(|t| Tz::UTC.from_utc_datetime(&t))
+        }
+        _ => None,
+    }
+}
+
+impl ColumnData {
+    fn new(scalar: ScalarType) -> Self {
+        match scalar {
+            ScalarType::Bool => ColumnData::Bool(vec![]),
+            ScalarType::Int8 => ColumnData::Int8(vec![]),
+            ScalarType::Int16 => ColumnData::Int16(vec![]),
+            ScalarType::Int32 => ColumnData::Int32(vec![]),
+            ScalarType::Int64 => ColumnData::Int64(vec![]),
+            ScalarType::UInt8 => ColumnData::UInt8(vec![]),
+            ScalarType::UInt16 => ColumnData::UInt16(vec![]),
+            ScalarType::UInt32 => ColumnData::UInt32(vec![]),
+            ScalarType::UInt64 => ColumnData::UInt64(vec![]),
+            ScalarType::Float32 => ColumnData::Float32(vec![]),
+            ScalarType::Float64 => ColumnData::Float64(vec![]),
+            ScalarType::String => ColumnData::String(vec![]),
+            ScalarType::Date => ColumnData::Date(vec![]),
+            ScalarType::DateTime | ScalarType::DateTime64(_) => ColumnData::DateTime(vec![]),
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        match self {
+            ColumnData::Bool(v) => v.truncate(len),
+            ColumnData::Int8(v) => v.truncate(len),
+            ColumnData::Int16(v) => v.truncate(len),
+            ColumnData::Int32(v) => v.truncate(len),
+            ColumnData::Int64(v) => v.truncate(len),
+            ColumnData::UInt8(v) => v.truncate(len),
+            ColumnData::UInt16(v) => v.truncate(len),
+            ColumnData::UInt32(v) => v.truncate(len),
+            ColumnData::UInt64(v) => v.truncate(len),
+            ColumnData::Float32(v) => v.truncate(len),
+            ColumnData::Float64(v) => v.truncate(len),
+            ColumnData::String(v) => v.truncate(len),
+            ColumnData::Date(v) => v.truncate(len),
+            ColumnData::DateTime(v) => v.truncate(len),
+        }
+    }
+
+    /// Appends a JSON value, converting it to the column's type
+    fn push(&mut self, value: &Value, nullable: bool) -> Result<(), String> {
+        if value.is_null() {
+            if !nullable {
+                return Err("null value for a non-nullable column".to_string());
+            }
+            match self {
+                ColumnData::Bool(v) => v.push(None),
+                ColumnData::Int8(v) => v.push(None),
+                ColumnData::Int16(v) => v.push(None),
+                ColumnData::Int32(v) => v.push(None),
+                ColumnData::Int64(v) => v.push(None),
+                ColumnData::UInt8(v) => v.push(None),
+                ColumnData::UInt16(v) => v.push(None),
+                ColumnData::UInt32(v) => v.push(None),
+                ColumnData::UInt64(v) => v.push(None),
+                ColumnData::Float32(v) => v.push(None),
+                ColumnData::Float64(v) => v.push(None),
+                ColumnData::String(v) => v.push(None),
+                ColumnData::Date(v) => v.push(None),
+                ColumnData::DateTime(v) => v.push(None),
+            }
+            return Ok(());
+        }
+
+        let converted = match self {
+            ColumnData::Bool(v) => value
+                .as_bool()
+                .or_else(|| value.as_u64().filter(|n| *n <= 1).map(|n| n == 1))
+                .map(|b| v.push(Some(b))),
+            ColumnData::Int8(v) => int(value).map(|n| v.push(Some(n))),
+            ColumnData::Int16(v) => int(value).map(|n| v.push(Some(n))),
+            ColumnData::Int32(v) => int(value).map(|n| v.push(Some(n))),
+            ColumnData::Int64(v) => int(value).map(|n| v.push(Some(n))),
+            ColumnData::UInt8(v) => uint(value).map(|n| v.push(Some(n))),
+            ColumnData::UInt16(v) => uint(value).map(|n| v.push(Some(n))),
+            ColumnData::UInt32(v) => uint(value).map(|n| v.push(Some(n))),
+            ColumnData::UInt64(v) => uint(value).map(|n| v.push(Some(n))),
+            ColumnData::Float32(v) => value.as_f64().map(|n| v.push(Some(n as f32))),
+            ColumnData::Float64(v) => value.as_f64().map(|n| v.push(Some(n))),
+            ColumnData::String(v) => {
+                v.push(Some(match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                }));
+                Some(())
+            }
+            ColumnData::Date(v) => value
+                .as_str()
+                .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+                .or_else(|| timestamp(value).map(|t| t.date_naive()))
+                .map(|d| v.push(Some(d))),
+            ColumnData::DateTime(v) => timestamp(value).map(|t| v.push(Some(t))),
+        };
+
+        converted.ok_or_else(|| format!("can't convert {} to the column's type", value))
+    }
+
+    fn add_to(&self, block: Block, name: &str, nullable: bool) -> Block {
+        match self {
+            ColumnData::Bool(v) => add_column!(block, name, nullable, v),
+            ColumnData::Int8(v) => add_column!(block, name, nullable, v),
+            ColumnData::Int16(v) => add_column!(block, name, nullable, v),
+            ColumnData::Int32(v) => add_column!(block, name, nullable, v),
+            ColumnData::Int64(v) => add_column!(block, name, nullable, v),
+            ColumnData::UInt8(v) => add_column!(block, name, nullable, v),
+            ColumnData::UInt16(v) => add_column!(block, name, nullable, v),
+            ColumnData::UInt32(v) => add_column!(block, name, nullable, v),
+            ColumnData::UInt64(v) => add_column!(block, name, nullable, v),
+            ColumnData::Float32(v) => add_column!(block, name, nullable, v),
+            ColumnData::Float64(v) => add_column!(block, name, nullable, v),
+            ColumnData::String(v) => add_column!(block, name, nullable, v),
+            ColumnData::Date(v) => add_column!(block, name, nullable, v),
+            ColumnData::DateTime(v) => add_column!(block, name, nullable, v),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ColumnBuffer {
+    name: String,
+    type_: ColumnType,
+    data: ColumnData,
+}
+
+/// Turns a record into the rows to insert, along with whether each row is a deletion. Updates
+/// are written as a deletion of the old row followed by the new row, which has a higher
+/// version, so a ReplacingMergeTree table will keep the new row if the key didn't change and
+/// drop the old one if it did.
+fn changes(value: Value, debezium: bool) -> Result<Vec<(Value, bool)>, String> {
+    if !debezium {
+        return Ok(vec![(value, false)]);
+    }
+
+    let op = value.get("op").and_then(|op| op.as_str());
+    let before = value.get("before").filter(|v| !v.is_null());
+    let after = value.get("after").filter(|v| !v.is_null());
+
+    match (op, before, after) {
+        (Some("c"), _, Some(after)) => Ok(vec![(after.clone(), false)]),
+        (Some("u"), Some(before), Some(after)) => {
+            Ok(vec![(before.clone(), true), (after.clone(), false)])
+        }
+        (Some("d"), Some(before), _) => Ok(vec![(before.clone(), true)]),
+        _ => Err(format!("invalid debezium record: {}", value)),
+    }
+}
+
+pub struct ClickhouseSinkFunc<K: Key, T: SchemaData + Serialize> {
+    config: ClickhouseConfig,
+    table: ClickhouseTable,
+    debezium: bool,
+    pool: Pool,
+    client: Option<ClientHandle>,
+    table_columns: HashMap<String, ColumnType>,
+    // created from the fields of the first record, plus the version and is_deleted columns
+    columns: Option<Vec<ColumnBuffer>>,
+    rows: usize,
+    last_version: u64,
+    batch_size: usize,
+    flush_interval: Duration,
+    last_flush: Instant,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<(K, T)>,
+}
+
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
+impl<K: Key, T: SchemaData + Serialize> ClickhouseSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for ClickhouseSink");
+        let connection: ClickhouseConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for ClickhouseSink");
+        let table: ClickhouseTable =
+            serde_json::from_value(config.table).expect("Invalid table config for ClickhouseSink");
+
+        let debezium = match config.format {
+            Some(Format::Json(JsonFormat { debezium, .. })) => debezium,
+            _ => panic!("ClickhouseSink requires a JSON format"),
+        };
+
+        Self {
+            pool: Pool::new(url(&connection)),
+            config: connection,
+            debezium,
+            client: None,
+            table_columns: HashMap::new(),
+            columns: None,
+            rows: 0,
+            last_version: 0,
+            batch_size: table
+                .batch_size
+                .map(|s| s as usize)
+                .unwrap_or(DEFAULT_BATCH_SIZE),
+            flush_interval: table
+                .flush_interval_ms
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            table,
+            last_flush: Instant::now(),
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "ClickhouseSink".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn on_start(&mut self, _: &mut Context<(), ()>) {
+        let query = format!(
+            "SELECT name, type FROM system.columns WHERE database = '{}' AND table = '{}'",
+            escape(database(&self.config)),
+            escape(&self.table.table_name)
+        );
+
+        let block = self
+            .client()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to connect to ClickHouse: {}", e))
+            .query(query)
+            .fetch_all()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to load columns for ClickHouse table: {}", e));
+
+        for row in block.rows() {
+            let name: String = row.get("name").unwrap();
+            let type_: String = row.get("type").unwrap();
+            // columns that we can't write are only an error if a record contains them
+            if let Ok(type_) = ColumnType::parse(&type_) {
+                self.table_columns.insert(name, type_);
+            }
+        }
+
+        if block.row_count() == 0 {
+            panic!(
+                "ClickHouse table {}.{} does not exist",
+                database(&self.config),
+                self.table.table_name
+            );
+        }
+
+        for (column, expected) in [
+            (&self.table.version_column, ScalarType::UInt64),
+            (&self.table.is_deleted_column, ScalarType::UInt8),
+        ] {
+            if let Some(column) = column {
+                if self.table_columns.get(column).map(|t| t.scalar) != Some(expected) {
+                    panic!("ClickHouse column '{}' must be {:?}", column, expected);
+                }
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let rows = match serde_json::to_value(&record.value)
+            .map_err(|e| format!("failed to serialize record: {}", e))
+            .and_then(|value| changes(value, self.debezium))
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.report_error(ctx, e).await;
+                return;
+            }
+        };
+
+        for (mut row, deleted) in rows {
+            let Value::Object(fields) = &mut row else {
+                self.report_error(ctx, format!("expected a JSON object but found {}", row))
+                    .await;
+                continue;
+            };
+
+            if let Some(column) = self.table.version_column.clone() {
+                fields.insert(column, Value::from(self.next_version()));
+            }
+
+            if let Some(column) = &self.table.is_deleted_column {
+                fields.insert(column.clone(), Value::from(deleted as u8));
+            }
+
+            if let Err(e) = self.push_row(&row) {
+                self.report_error(ctx, e).await;
+            }
+        }
+
+        if self.rows >= self.batch_size {
+            self.flush().await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, _: &mut Context<(), ()>) {
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush().await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        // everything before the barrier must be inserted before the checkpoint completes
+        self.flush().await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush().await;
+    }
+
+    /// Returns a version that's higher than any previously written by this subtask, based on
+    /// the current time so that versions keep increasing across restarts
+    fn next_version(&mut self) -> u64 {
+        let now = to_nanos(SystemTime::now()) as u64;
+        self.last_version = now.max(self.last_version + 1);
+        self.last_version
+    }
+
+    fn push_row(&mut self, row: &Value) -> Result<(), String> {
+        if self.columns.is_none() {
+            let Value::Object(fields) = row else {
+                return Err(format!("expected a JSON object but found {}", row));
+            };
+
+            let mut columns = vec![];
+            for name in fields.keys() {
+                let type_ = *self.table_columns.get(name).ok_or_else(|| {
+                    format!(
+                        "column '{}' does not exist in table {} or has an unsupported type",
+                        name, self.table.table_name
+                    )
+                })?;
+
+                columns.push(ColumnBuffer {
+                    name: name.clone(),
+                    type_,
+                    data: ColumnData::new(type_.scalar),
+                });
+            }
+            self.columns = Some(columns);
+        }
+
+        let columns = self.columns.as_mut().unwrap();
+        for i in 0..columns.len() {
+            let column = &mut columns[i];
+            let value = row.get(&column.name).unwrap_or(&Value::Null);
+            if let Err(e) = column.data.push(value, column.type_.nullable) {
+                let error = format!("invalid value for column '{}': {}", column.name, e);
+                // remove the partially-written row so the columns stay aligned
+                for column in &mut columns[..i] {
+                    column.data.truncate(self.rows);
+                }
+                return Err(error);
+            }
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    async fn client(&mut self) -> Result<&mut ClientHandle, clickhouse_rs::errors::Error> {
+        if self.client.is_none() {
+            let mut client = self.pool.get_handle().await?;
+            if self.table.async_insert.unwrap_or(false) {
+                // with wait_for_async_insert the insert returns once the server has flushed its
+                // buffer, so data is durable by the time the checkpoint completes
+                client.execute("SET async_insert = 1").await?;
+                client.execute("SET wait_for_async_insert = 1").await?;
+            }
+            info!("Connected to ClickHouse at {}", self.config.host);
+            self.client = Some(client);
+        }
+
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    fn block(&self) -> Block {
+        let mut block = Block::new();
+        for column in self.columns.iter().flatten() {
+            block = column
+                .data
+                .add_to(block, &column.name, column.type_.nullable);
+        }
+        block
+    }
+
+    async fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.rows == 0 {
+            return;
+        }
+
+        let table = format!("`{}`.`{}`", database(&self.config), self.table.table_name);
+
+        let mut attempts = 0;
+        loop {
+            let block = self.block();
+            let result = match self.client().await {
+                Ok(client) => client.insert(&table, block).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(()) => break,
+                Err(e) => {
+                    warn!("Insert into ClickHouse failed, retrying: {}", e);
+                    // the connection may be broken, so get a new one for the next attempt
+                    self.client = None;
+                }
+            }
+
+            attempts += 1;
+            if attempts >= MAX_RETRIES {
+                panic!("Failed to write to ClickHouse after {} attempts", attempts);
+            }
+
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts.min(7)))).await;
+        }
+
+        for column in self.columns.iter_mut().flatten() {
+            column.data = ColumnData::new(column.type_.scalar);
+        }
+        self.rows = 0;
+    }
+
+    async fn report_error(&mut self, ctx: &mut Context<(), ()>, details: String) {
+        self.errors += 1;
+        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+            ctx.report_error(
+                format!("ClickHouse insert failed x {}", self.errors),
+                details,
+            )
+            .await;
+            self.errors = 0;
+            self.last_reported_error = Instant::now();
+        }
+    }
+}
+
+fn database(config: &ClickhouseConfig) -> &str {
+    config.database.as_deref().unwrap_or("default")
+}
+
+fn url(config: &ClickhouseConfig) -> String {
+    let mut url = url::Url::parse(&format!(
+        "tcp://{}:{}",
+        config.host,
+        config.port.unwrap_or(9000)
+    ))
+    .expect("Invalid host for ClickhouseSink");
+
+    url.set_path(database(config));
+    url.set_username(config.username.as_deref().unwrap_or("default"))
+        .expect("Invalid username for ClickhouseSink");
+    url.set_password(config.password.as_deref())
+        .expect("Invalid password for ClickhouseSink");
+
+    url.to_string()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{changes, ColumnData, ColumnType, ScalarType};
+
+    #[test]
+    fn test_parse_column_type() {
+        assert_eq!(
+            ColumnType::parse("Int64").unwrap(),
+            ColumnType {
+                scalar: ScalarType::Int64,
+                nullable: false
+            }
+        );
+        assert_eq!(
+            ColumnType::parse("Nullable(String)").unwrap(),
+            ColumnType {
+                scalar: ScalarType::String,
+                nullable: true
+            }
+        );
+        assert_eq!(
+            ColumnType::parse("LowCardinality(Nullable(String))").unwrap(),
+            ColumnType {
+                scalar: ScalarType::String,
+                nullable: true
+            }
+        );
+        assert_eq!(
+            ColumnType::parse("DateTime('UTC')").unwrap().scalar,
+            ScalarType::DateTime
+        );
+        assert_eq!(
+            ColumnType::parse("DateTime64(3, 'UTC')").unwrap().scalar,
+            ScalarType::DateTime64(3)
+        );
+        assert!(ColumnType::parse("Array(Int64)").is_err());
+    }
+
+    #[test]
+    fn test_push_values() {
+        let mut ints = ColumnData::new(ScalarType::UInt8);
+        ints.push(&json!(5), false).unwrap();
+        assert!(ints.push(&json!(300), false).is_err());
+        assert!(ints.push(&json!(-1), false).is_err());
+        assert!(ints.push(&json!(null), false).is_err());
+        ints.push(&json!(null), true).unwrap();
+        assert_eq!(ints, ColumnData::UInt8(vec![Some(5), None]));
+
+        let mut times = ColumnData::new(ScalarType::DateTime64(3));
+        times
+            .push(&json!("2023-10-01T12:00:00.500Z"), false)
+            .unwrap();
+        times.push(&json!(1696161600500i64), false).unwrap();
+        let ColumnData::DateTime(times) = times else {
+            panic!("expected a datetime column");
+        };
+        assert_eq!(times[0], times[1]);
+    }
+
+    #[test]
+    fn test_debezium_changes() {
+        let row = json!({"id": 1, "count": 2});
+        assert_eq!(changes(row.clone(), false).unwrap(), vec![(row, false)]);
+
+        let update = json!({
+            "op": "u",
+            "before": {"id": 1, "count": 2},
+            "after": {"id": 1, "count": 3},
+        });
+        assert_eq!(
+            changes(update, true).unwrap(),
+            vec![
+                (json!({"id": 1, "count": 2}), true),
+                (json!({"id": 1, "count": 3}), false)
+            ]
+        );
+
+        let delete = json!({"op": "d", "before": {"id": 1, "count": 3}, "after": null});
+        assert_eq!(
+            changes(delete, true).unwrap(),
+            vec![(json!({"id": 1, "count": 3}), true)]
+        );
+
+        assert!(changes(json!({"op": "d", "after": {"id": 1}}), true).is_err());
+    }
+}
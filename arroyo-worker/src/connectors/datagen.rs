@@ -0,0 +1,418 @@
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use arrow::datatypes::{DataType, Field};
+use arroyo_macro::source_fn;
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, OperatorConfig};
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use rand::distributions::{Alphanumeric, DistString};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::Zipf;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::{debug, info};
+use typify::import_types;
+
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+
+import_types!(schema = "../connector-schemas/datagen/table.json");
+
+const DEFAULT_STRING_LENGTH: usize = 8;
+const DEFAULT_INT_RANGE: (f64, f64) = (0.0, 1000.0);
+const DEFAULT_FLOAT_RANGE: (f64, f64) = (0.0, 1.0);
+
+#[derive(Encode, Decode, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DatagenSourceState {
+    counter: u64,
+}
+
+/// Produces the values for a single field of the output schema
+#[derive(Debug)]
+struct ValueGenerator {
+    name: String,
+    data_type: DataType,
+    kind: GeneratorKind,
+    min: Option<f64>,
+    max: Option<f64>,
+    length: usize,
+    zipf: Option<Zipf<f64>>,
+}
+
+impl ValueGenerator {
+    fn new(field: &Field, spec: Option<&FieldGenerator>) -> Self {
+        let kind = spec.map(|s| s.kind).unwrap_or_else(|| {
+            if matches!(field.data_type(), DataType::Timestamp(_, _)) {
+                GeneratorKind::Timestamp
+            } else {
+                GeneratorKind::Random
+            }
+        });
+
+        let zipf = (kind == GeneratorKind::Zipfian).then(|| {
+            let spec = spec.unwrap();
+            Zipf::new(
+                spec.cardinality.unwrap_or(1).max(1) as u64,
+                spec.exponent.unwrap_or(1.0),
+            )
+            .expect("invalid zipfian generator")
+        });
+
+        Self {
+            name: field.name().clone(),
+            data_type: field.data_type().clone(),
+            kind,
+            min: spec.and_then(|s| s.min),
+            max: spec.and_then(|s| s.max),
+            length: spec
+                .and_then(|s| s.length)
+                .map(|l| l as usize)
+                .unwrap_or(DEFAULT_STRING_LENGTH),
+            zipf,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(
+            self.data_type,
+            DataType::Float16 | DataType::Float32 | DataType::Float64
+        )
+    }
+
+    fn is_string(&self) -> bool {
+        matches!(self.data_type, DataType::Utf8 | DataType::LargeUtf8)
+    }
+
+    /// Renders a numeric value as the JSON representation of this field's type
+    fn number(&self, n: f64) -> Value {
+        if self.is_string() {
+            Value::String((n as i64).to_string())
+        } else if self.is_float() {
+            json!(n)
+        } else {
+            json!(n as i64)
+        }
+    }
+
+    /// Generates the value for the row with the given global index (which is unique across
+    /// subtasks) and event time
+    fn generate(&self, index: u64, timestamp: SystemTime, rng: &mut impl Rng) -> Value {
+        match self.kind {
+            GeneratorKind::Sequential => self.number(self.min.unwrap_or(0.0) + index as f64),
+            GeneratorKind::Zipfian => self.number(rng.sample(self.zipf.unwrap())),
+            GeneratorKind::Timestamp => {
+                if self.is_string() {
+                    Value::String(chrono::DateTime::<chrono::Utc>::from(timestamp).to_rfc3339())
+                } else {
+                    json!(to_millis(timestamp))
+                }
+            }
+            GeneratorKind::Random => match &self.data_type {
+                DataType::Boolean => Value::Bool(rng.gen()),
+                DataType::Utf8 | DataType::LargeUtf8 => {
+                    Value::String(Alphanumeric.sample_string(rng, self.length))
+                }
+                DataType::Timestamp(_, _) if self.min.is_none() || self.max.is_none() => {
+                    json!(to_millis(timestamp))
+                }
+                _ => {
+                    let (min, max) = if self.is_float() {
+                        DEFAULT_FLOAT_RANGE
+                    } else {
+                        DEFAULT_INT_RANGE
+                    };
+                    let (min, max) = (self.min.unwrap_or(min), self.max.unwrap_or(max));
+                    self.number(rng.gen_range(min..=max))
+                }
+            },
+        }
+    }
+}
+
+#[derive(StreamNode)]
+pub struct DatagenSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    table: DatagenTable,
+    generators: Vec<ValueGenerator>,
+    deserializer: DataDeserializer<T>,
+    state: DatagenSourceState,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> DatagenSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for DatagenSource");
+        let table: DatagenTable =
+            serde_json::from_value(config.table).expect("Invalid table config for DatagenSource");
+
+        let generators = T::schema()
+            .fields
+            .iter()
+            .map(|f| ValueGenerator::new(f, table.generators.iter().find(|g| &g.field == f.name())))
+            .collect();
+
+        Self {
+            table,
+            generators,
+            deserializer: DataDeserializer::new(
+                config
+                    .format
+                    .expect("Format must be specified for DatagenSource"),
+                None,
+            ),
+            state: DatagenSourceState { counter: 0 },
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "datagen-source".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("d", "datagen source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let s = ctx
+            .state
+            .get_global_keyed_state::<usize, DatagenSourceState>('d')
+            .await;
+
+        if let Some(state) = s.get(&ctx.task_info.task_index) {
+            self.state = *state;
+        }
+    }
+
+    async fn report_row_error(&mut self, name: String, details: String, ctx: &mut Context<(), T>) {
+        self.errors += 1;
+        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+            ctx.report_error(format!("{} x {}", name, self.errors), details)
+                .await;
+            self.errors = 0;
+            self.last_reported_error = Instant::now();
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        let parallelism = ctx.task_info.parallelism as u64;
+        let task_index = ctx.task_info.task_index as u64;
+
+        let delay = Duration::from_secs_f64(1.0 / (self.table.event_rate / parallelism as f64));
+
+        // the message count is split across subtasks, with the remainder going to the first ones
+        let limit = self
+            .table
+            .message_count
+            .map(|n| n as u64 / parallelism + u64::from(task_index < n as u64 % parallelism))
+            .unwrap_or(u64::MAX);
+
+        let skew = self.table.timestamp_skew_ms.unwrap_or(0) as u64;
+
+        info!(
+            "Starting datagen source with delay {:?} and limit {}",
+            delay, limit
+        );
+
+        let mut rng = SmallRng::seed_from_u64((task_index << 48) ^ self.state.counter);
+        let start_time = SystemTime::now() - delay * self.state.counter as u32;
+
+        while self.state.counter < limit {
+            let now = SystemTime::now();
+            let timestamp = if skew > 0 {
+                now - Duration::from_millis(rng.gen_range(0..=skew))
+            } else {
+                now
+            };
+
+            let index = self.state.counter * parallelism + task_index;
+            let row: Map<String, Value> = self
+                .generators
+                .iter()
+                .map(|g| (g.name.clone(), g.generate(index, timestamp, &mut rng)))
+                .collect();
+
+            let json = serde_json::to_vec(&row).unwrap();
+            for value in self.deserializer.deserialize_slice(&json) {
+                match value {
+                    Ok(value) => {
+                        ctx.collector
+                            .collect(Record {
+                                timestamp,
+                                key: None,
+                                value,
+                            })
+                            .await;
+                    }
+                    Err(e) => {
+                        self.report_row_error(e.name, e.details, ctx).await;
+                    }
+                }
+            }
+
+            self.state.counter += 1;
+
+            match ctx.control_rx.try_recv() {
+                Ok(ControlMessage::Checkpoint(c)) => {
+                    // checkpoint our state
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    ctx.state
+                        .get_global_keyed_state('d')
+                        .await
+                        .insert(ctx.task_info.task_index, self.state)
+                        .await;
+                    if self.checkpoint(c, ctx).await {
+                        return SourceFinishType::Immediate;
+                    }
+                }
+                Ok(ControlMessage::Stop { mode }) => {
+                    info!("Stopping datagen source {:?}", mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return SourceFinishType::Graceful;
+                        }
+                        StopMode::Immediate => {
+                            return SourceFinishType::Immediate;
+                        }
+                    }
+                }
+                Ok(ControlMessage::Commit { .. }) => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                Ok(ControlMessage::LoadCompacted { compacted }) => {
+                    ctx.load_compacted(compacted).await;
+                }
+                Ok(ControlMessage::QueryState { tx, .. }) => {
+                    ctx.reject_state_query(tx);
+                }
+                Ok(ControlMessage::NoOp) => {}
+                Err(_) => {
+                    // no messages
+                }
+            }
+
+            let next_sleep = start_time + delay * self.state.counter as u32;
+            if let Ok(sleep_time) = next_sleep.duration_since(SystemTime::now()) {
+                tokio::time::sleep(sleep_time).await;
+            }
+        }
+
+        SourceFinishType::Final
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use arrow::datatypes::{DataType, Field, TimeUnit};
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+    use serde_json::{json, Value};
+
+    use super::{FieldGenerator, GeneratorKind, ValueGenerator};
+
+    fn spec(kind: GeneratorKind) -> FieldGenerator {
+        FieldGenerator {
+            field: "f".to_string(),
+            kind,
+            min: None,
+            max: None,
+            length: None,
+            cardinality: None,
+            exponent: None,
+        }
+    }
+
+    #[test]
+    fn test_generators() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let ts = SystemTime::UNIX_EPOCH + Duration::from_millis(1000);
+
+        let sequential = ValueGenerator::new(
+            &Field::new("f", DataType::Int64, false),
+            Some(&FieldGenerator {
+                min: Some(10.0),
+                ..spec(GeneratorKind::Sequential)
+            }),
+        );
+        assert_eq!(sequential.generate(5, ts, &mut rng), json!(15));
+
+        let sequential = ValueGenerator::new(
+            &Field::new("f", DataType::Utf8, false),
+            Some(&spec(GeneratorKind::Sequential)),
+        );
+        assert_eq!(sequential.generate(5, ts, &mut rng), json!("5"));
+
+        let random = ValueGenerator::new(
+            &Field::new("f", DataType::Float64, false),
+            Some(&FieldGenerator {
+                min: Some(-1.0),
+                max: Some(1.0),
+                ..spec(GeneratorKind::Random)
+            }),
+        );
+        for _ in 0..100 {
+            let v = random.generate(0, ts, &mut rng).as_f64().unwrap();
+            assert!((-1.0..=1.0).contains(&v));
+        }
+
+        let string = ValueGenerator::new(&Field::new("f", DataType::Utf8, false), None);
+        match string.generate(0, ts, &mut rng) {
+            Value::String(s) => assert_eq!(s.len(), 8),
+            other => panic!("expected string, got {}", other),
+        }
+
+        // timestamp fields default to the event time
+        let timestamp = ValueGenerator::new(
+            &Field::new("f", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+            None,
+        );
+        assert_eq!(timestamp.generate(0, ts, &mut rng), json!(1000));
+    }
+
+    #[test]
+    fn test_zipfian() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let zipf = ValueGenerator::new(
+            &Field::new("f", DataType::Int64, false),
+            Some(&FieldGenerator {
+                cardinality: Some(100),
+                exponent: Some(1.5),
+                ..spec(GeneratorKind::Zipfian)
+            }),
+        );
+
+        let mut counts = vec![0; 101];
+        for _ in 0..10000 {
+            let k = zipf
+                .generate(0, SystemTime::UNIX_EPOCH, &mut rng)
+                .as_i64()
+                .unwrap();
+            assert!((1..=100).contains(&k));
+            counts[k as usize] += 1;
+        }
+
+        // the first key should be far more frequent than the last
+        assert!(counts[1] > counts[100] * 10);
+    }
+}
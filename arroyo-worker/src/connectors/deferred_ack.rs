@@ -0,0 +1,95 @@
+/// Holds the acks for the messages a source has read until the checkpoint covering them has
+/// completed, so that a message is never acked before the state that reflects it is durable.
+///
+/// Sources aren't told when a checkpoint completes, but checkpoints don't overlap: by the time the
+/// next checkpoint starts, the previous one must have completed. So the acks read before a
+/// checkpoint are released when the one after it starts, and a source that fails in between
+/// leaves its unacked messages to be redelivered.
+///
+/// `C` holds the acks read between two checkpoints, usually a `Vec` of one per message; sources
+/// whose acks cover the messages before them can keep only the last one.
+#[derive(Debug, Default)]
+pub struct DeferredAck<C> {
+    // acks for the messages read since the last checkpoint
+    read: C,
+    // acks for the messages covered by the last checkpoint
+    checkpointed: C,
+}
+
+impl<C: Default> DeferredAck<C> {
+    /// The acks for the messages read since the last checkpoint, which new acks are added to
+    pub fn reading(&mut self) -> &mut C {
+        &mut self.read
+    }
+
+    /// Called when a checkpoint starts, returning the acks for the messages covered by the
+    /// previous checkpoint
+    pub fn checkpoint(&mut self) -> C {
+        std::mem::replace(&mut self.checkpointed, std::mem::take(&mut self.read))
+    }
+}
+
+impl<A> DeferredAck<Vec<A>> {
+    pub fn read(&mut self, ack: A) {
+        self.read.push(ack);
+    }
+
+    /// Adds the ack for a message covered by a checkpoint that has already completed, like the
+    /// one the source was restored from, which is released when the next checkpoint starts
+    pub fn restored(&mut self, ack: A) {
+        self.checkpointed.push(ack);
+    }
+
+    /// The acks that are being held, from the most recently read
+    pub fn held(&self) -> impl Iterator<Item = &A> {
+        self.read.iter().chain(&self.checkpointed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeferredAck;
+
+    #[test]
+    fn test_acks_held_until_checkpoint_completes() {
+        let mut acks = DeferredAck::default();
+        acks.read(1);
+        acks.read(2);
+
+        // nothing is acked when the first checkpoint starts, as it may not complete
+        assert!(acks.checkpoint().is_empty());
+        assert_eq!(vec![&1, &2], acks.held().collect::<Vec<_>>());
+
+        acks.read(3);
+        assert_eq!(vec![&3, &1, &2], acks.held().collect::<Vec<_>>());
+
+        // once the next one starts, the messages covered by the first can be acked
+        assert_eq!(vec![1, 2], acks.checkpoint());
+        assert_eq!(vec![3], acks.checkpoint());
+
+        // nothing was read during the last checkpoint
+        assert!(acks.checkpoint().is_empty());
+        assert_eq!(0, acks.held().count());
+    }
+
+    #[test]
+    fn test_restored_acks_released_at_first_checkpoint() {
+        let mut acks = DeferredAck::default();
+        acks.restored(1);
+        acks.read(2);
+
+        assert_eq!(vec![1], acks.checkpoint());
+        assert_eq!(vec![2], acks.checkpoint());
+    }
+
+    #[test]
+    fn test_last_ack_only() {
+        let mut acks: DeferredAck<Option<u32>> = DeferredAck::default();
+        *acks.reading() = Some(1);
+        *acks.reading() = Some(2);
+
+        assert_eq!(None, acks.checkpoint());
+        assert_eq!(Some(2), acks.checkpoint());
+        assert_eq!(None, acks.checkpoint());
+    }
+}
@@ -0,0 +1,469 @@
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::formats::Format;
+use arroyo_rpc::{grpc::TableDescriptor, OperatorConfig};
+use arroyo_types::{CheckpointBarrier, Key, Record};
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+use typify::import_types;
+
+use crate::{
+    engine::{Context, StreamNode},
+    SchemaData,
+};
+
+import_types!(schema = "../connector-schemas/elasticsearch/connection.json");
+import_types!(schema = "../connector-schemas/elasticsearch/table.json");
+
+const DEFAULT_BULK_SIZE: usize = 1000;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// keep requests well under the default http.max_content_length of 100mb
+const MAX_BULK_BYTES: usize = 10 * 1024 * 1024;
+const MAX_RETRIES: u32 = 20;
+
+/// A part of an index name, which is either a literal or a date formatted from the event time
+#[derive(Debug, Clone, PartialEq)]
+enum IndexPart {
+    Literal(String),
+    Date(String),
+}
+
+/// An index name like `logs-{yyyy.MM.dd}`, where parts in braces are date patterns in the
+/// format used by Elasticsearch (and Java)
+#[derive(Debug, Clone, PartialEq)]
+struct IndexPattern {
+    parts: Vec<IndexPart>,
+}
+
+impl IndexPattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(IndexPart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed '{{' in index '{}'", pattern))?
+                + start;
+            parts.push(IndexPart::Date(to_strftime(&rest[start + 1..end])?));
+            rest = &rest[end + 1..];
+        }
+
+        if rest.contains('}') {
+            return Err(format!("unmatched '}}' in index '{}'", pattern));
+        }
+        if !rest.is_empty() {
+            parts.push(IndexPart::Literal(rest.to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+
+    fn format(&self, timestamp: SystemTime) -> String {
+        let timestamp: DateTime<Utc> = timestamp.into();
+        self.parts
+            .iter()
+            .map(|part| match part {
+                IndexPart::Literal(s) => s.clone(),
+                IndexPart::Date(format) => timestamp.format(format).to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Converts a date pattern like `yyyy.MM.dd` into a strftime format string
+fn to_strftime(pattern: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut len = 1;
+        while chars.peek() == Some(&c) {
+            chars.next();
+            len += 1;
+        }
+
+        let spec = match (c, len) {
+            ('y', 4) | ('u', 4) => "%Y",
+            ('y', 2) | ('u', 2) => "%y",
+            ('M', 2) => "%m",
+            ('M', 1) => "%-m",
+            ('d', 2) => "%d",
+            ('d', 1) => "%-d",
+            ('H', 2) => "%H",
+            ('H', 1) => "%-H",
+            ('m', 2) => "%M",
+            ('s', 2) => "%S",
+            ('w', 2) => "%V",
+            (c, _) if c.is_ascii_alphabetic() => {
+                return Err(format!(
+                    "unsupported date pattern '{}' in index",
+                    c.to_string().repeat(len)
+                ));
+            }
+            ('%', _) => {
+                result.push_str(&"%%".repeat(len));
+                continue;
+            }
+            (c, _) => {
+                result.extend(std::iter::repeat(c).take(len));
+                continue;
+            }
+        };
+        result.push_str(spec);
+    }
+
+    Ok(result)
+}
+
+/// A single document to be indexed by a bulk request
+#[derive(Debug, Clone, PartialEq)]
+struct BulkAction {
+    action: String,
+    document: String,
+}
+
+impl BulkAction {
+    fn len(&self) -> usize {
+        self.action.len() + self.document.len() + 2
+    }
+}
+
+/// The outcome of a bulk request for the actions that didn't succeed
+#[derive(Debug, Default, PartialEq)]
+struct BulkFailures {
+    /// Actions that were rejected because the cluster was overloaded, and should be retried
+    retry: Vec<BulkAction>,
+    /// Errors for actions that failed permanently
+    errors: Vec<String>,
+}
+
+/// Matches the per-item results of a bulk response to the actions that were sent
+fn parse_bulk_response(response: &Value, actions: Vec<BulkAction>) -> BulkFailures {
+    let mut failures = BulkFailures::default();
+    if response.get("errors").and_then(|e| e.as_bool()) == Some(false) {
+        return failures;
+    }
+
+    let items = response
+        .get("items")
+        .and_then(|i| i.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    for (action, item) in actions.into_iter().zip(items.iter()) {
+        // each item is keyed by the action type, which is always "index" for us
+        let Some(result) = item.as_object().and_then(|o| o.values().next()) else {
+            continue;
+        };
+
+        match result.get("status").and_then(|s| s.as_u64()) {
+            Some(status) if status < 300 => {}
+            Some(429) => failures.retry.push(action),
+            status => failures.errors.push(format!(
+                "failed to index document (status {}): {}",
+                status.map(|s| s.to_string()).unwrap_or_default(),
+                result.get("error").unwrap_or(&Value::Null)
+            )),
+        }
+    }
+
+    failures
+}
+
+#[derive(StreamNode)]
+pub struct ElasticsearchSinkFunc<K: Key, T: SchemaData + Serialize> {
+    client: reqwest::Client,
+    config: ElasticsearchConfig,
+    bulk_url: String,
+    index: IndexPattern,
+    id_field: Option<String>,
+    unstructured: bool,
+    bulk_size: usize,
+    flush_interval: Duration,
+    actions: Vec<BulkAction>,
+    buffered_bytes: usize,
+    last_flush: Instant,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<(K, T)>,
+}
+
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
+impl<K: Key, T: SchemaData + Serialize> ElasticsearchSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for ElasticsearchSink");
+        let connection: ElasticsearchConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for ElasticsearchSink");
+        let table: ElasticsearchTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for ElasticsearchSink");
+
+        let unstructured = matches!(&config.format, Some(Format::Json(json)) if json.unstructured);
+
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()
+                .expect("could not construct http client"),
+            bulk_url: format!("{}/_bulk", connection.endpoint.trim_end_matches('/')),
+            config: connection,
+            index: IndexPattern::parse(&table.index).expect("Invalid index for ElasticsearchSink"),
+            id_field: table.id_field,
+            unstructured,
+            bulk_size: table
+                .bulk_size
+                .map(|s| s as usize)
+                .unwrap_or(DEFAULT_BULK_SIZE),
+            flush_interval: table
+                .flush_interval_ms
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            actions: vec![],
+            buffered_bytes: 0,
+            last_flush: Instant::now(),
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "ElasticsearchSink".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let value = match serde_json::to_value(&record.value) {
+            Ok(value) => value,
+            Err(e) => {
+                self.report_error(ctx, format!("failed to serialize record: {}", e))
+                    .await;
+                return;
+            }
+        };
+
+        let id = match &self.id_field {
+            Some(field) => match value.get(field) {
+                None | Some(Value::Null) => {
+                    self.report_error(
+                        ctx,
+                        format!("records with a null value for '{}' can't be indexed", field),
+                    )
+                    .await;
+                    return;
+                }
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(other) => Some(other.to_string()),
+            },
+            None => None,
+        };
+
+        let document = if self.unstructured {
+            match record.value.to_raw_string() {
+                Some(raw) => String::from_utf8_lossy(&raw).into_owned(),
+                None => return,
+            }
+        } else {
+            value.to_string()
+        };
+
+        let mut metadata = json!({ "_index": self.index.format(record.timestamp) });
+        if let Some(id) = id {
+            metadata["_id"] = Value::String(id);
+        }
+
+        let action = BulkAction {
+            action: json!({ "index": metadata }).to_string(),
+            document,
+        };
+
+        self.buffered_bytes += action.len();
+        self.actions.push(action);
+
+        if self.actions.len() >= self.bulk_size || self.buffered_bytes >= MAX_BULK_BYTES {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<(), ()>) {
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        // everything before the barrier must be indexed before the checkpoint completes
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        self.last_flush = Instant::now();
+        let mut pending = std::mem::take(&mut self.actions);
+        self.buffered_bytes = 0;
+
+        let mut attempts = 0;
+        while !pending.is_empty() {
+            match self.send(&pending).await {
+                Ok(response) => {
+                    let failures = parse_bulk_response(&response, pending);
+                    for error in failures.errors {
+                        self.report_error(ctx, error).await;
+                    }
+                    pending = failures.retry;
+                    if !pending.is_empty() {
+                        warn!(
+                            "Elasticsearch rejected {} documents, retrying",
+                            pending.len()
+                        );
+                    }
+                }
+                Err(e) => {
+                    warn!("Bulk request to Elasticsearch failed, retrying: {}", e);
+                }
+            }
+
+            if pending.is_empty() {
+                break;
+            }
+
+            attempts += 1;
+            if attempts >= MAX_RETRIES {
+                panic!(
+                    "Failed to write to Elasticsearch after {} attempts",
+                    attempts
+                );
+            }
+
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts.min(7)))).await;
+        }
+    }
+
+    /// Sends a bulk request, returning the response for retryable failures as an error
+    async fn send(&self, actions: &[BulkAction]) -> Result<Value, String> {
+        let mut body = String::with_capacity(actions.iter().map(|a| a.len()).sum());
+        for action in actions {
+            body.push_str(&action.action);
+            body.push('\n');
+            body.push_str(&action.document);
+            body.push('\n');
+        }
+
+        let mut request = self
+            .client
+            .post(&self.bulk_url)
+            .header("Content-Type", "application/x-ndjson")
+            .body(body);
+
+        if let Some(api_key) = &self.config.api_key {
+            request = request.header("Authorization", format!("ApiKey {}", api_key));
+        } else if let Some(username) = &self.config.username {
+            request = request.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Err(format!("server responded with {}", status));
+        }
+
+        if !status.is_success() {
+            panic!(
+                "Elasticsearch bulk request failed with {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+
+        serde_json::from_slice(&body).map_err(|e| format!("invalid bulk response: {}", e))
+    }
+
+    async fn report_error(&mut self, ctx: &mut Context<(), ()>, details: String) {
+        self.errors += 1;
+        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+            ctx.report_error(
+                format!("Elasticsearch indexing failed x {}", self.errors),
+                details,
+            )
+            .await;
+            self.errors = 0;
+            self.last_reported_error = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arroyo_types::from_millis;
+    use serde_json::json;
+
+    use super::{parse_bulk_response, BulkAction, IndexPattern};
+
+    #[test]
+    fn test_index_pattern() {
+        // 2023-03-04T05:06:07Z
+        let t = from_millis(1_677_906_367_000);
+
+        assert_eq!(IndexPattern::parse("events").unwrap().format(t), "events");
+        assert_eq!(
+            IndexPattern::parse("logs-{yyyy.MM.dd}").unwrap().format(t),
+            "logs-2023.03.04"
+        );
+        assert_eq!(
+            IndexPattern::parse("{yy}-{M}-metrics").unwrap().format(t),
+            "23-3-metrics"
+        );
+        assert_eq!(
+            IndexPattern::parse("logs-{yyyy.MM.dd-HH}")
+                .unwrap()
+                .format(t),
+            "logs-2023.03.04-05"
+        );
+
+        assert!(IndexPattern::parse("logs-{yyyy").is_err());
+        assert!(IndexPattern::parse("logs-}").is_err());
+        assert!(IndexPattern::parse("logs-{QQ}").is_err());
+    }
+
+    #[test]
+    fn test_parse_bulk_response() {
+        let action = |id: &str| BulkAction {
+            action: json!({"index": {"_index": "test", "_id": id}}).to_string(),
+            document: "{}".to_string(),
+        };
+
+        let actions = vec![action("1"), action("2"), action("3")];
+
+        let ok = json!({"errors": false, "items": []});
+        assert!(parse_bulk_response(&ok, actions.clone()).retry.is_empty());
+
+        let response = json!({
+            "errors": true,
+            "items": [
+                {"index": {"_id": "1", "status": 201}},
+                {"index": {"_id": "2", "status": 429, "error": {"type": "es_rejected_execution_exception"}}},
+                {"index": {"_id": "3", "status": 400, "error": {"type": "mapper_parsing_exception"}}},
+            ]
+        });
+
+        let failures = parse_bulk_response(&response, actions);
+        assert_eq!(failures.retry, vec![action("2")]);
+        assert_eq!(failures.errors.len(), 1);
+        assert!(failures.errors[0].contains("mapper_parsing_exception"));
+    }
+}
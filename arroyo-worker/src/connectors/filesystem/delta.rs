@@ -0,0 +1,272 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::SchemaRef;
+use arroyo_rpc::OperatorConfig;
+use arroyo_storage::StorageProvider;
+use arroyo_types::{Data, Key, RecordBatchBuilder};
+use deltalake::{
+    operations::transaction::commit,
+    protocol::{Action, Add, DeltaOperation, MetaData, SaveMode},
+    DeltaOps, DeltaTable, DeltaTableError, Schema as DeltaSchema,
+};
+use object_store::path::Path;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use super::{
+    parse_partition_values, CommitStrategy, FileSystemTable, FinishedFile, ParquetFileSystemSink,
+    SchemaEvolution,
+};
+use crate::connectors::two_phase_committer::TwoPhaseCommitterOperator;
+
+/// A filesystem sink that writes parquet files and then adds them to a Delta Lake table when
+/// the checkpoint is committed.
+///
+/// Each subtask commits its own files to the log, so on S3 a locking provider (like DynamoDB)
+/// must be configured through the storage options for concurrent commits to be safe.
+pub struct DeltaFileSystemSink<K, T, R> {
+    _ts: PhantomData<(K, T, R)>,
+}
+
+impl<K: Key, T: Data + Sync + Serialize, R: RecordBatchBuilder<Data = T>>
+    DeltaFileSystemSink<K, T, R>
+{
+    pub fn from_config(
+        config_str: &str,
+    ) -> TwoPhaseCommitterOperator<K, T, ParquetFileSystemSink<K, T, R>> {
+        let config: OperatorConfig =
+            serde_json::from_str(config_str).expect("Invalid config for DeltaFileSystemSink");
+        let table: FileSystemTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for DeltaFileSystemSink");
+
+        ParquetFileSystemSink::<K, T, R>::create_and_start(
+            table,
+            CommitStrategy::DeltaLake {
+                schema: R::default().schema(),
+            },
+        )
+    }
+}
+
+pub(super) async fn commit_files_to_delta(
+    finished_files: Vec<FinishedFile>,
+    table_path: &Path,
+    table: &FileSystemTable,
+    schema: SchemaRef,
+    storage_provider: Arc<StorageProvider>,
+) -> Result<()> {
+    if finished_files.is_empty() {
+        return Ok(());
+    }
+
+    let partition_columns = partition_columns(table);
+    let delta_schema = DeltaSchema::try_from(schema.as_ref())?;
+    let uri = storage_provider.qualify_path(table_path);
+    let delta_table = load_or_create_table(&uri, table, &delta_schema, &partition_columns).await?;
+
+    let mut actions = vec![];
+    if let Some(metadata) = evolve_schema(&delta_table, &delta_schema, table)? {
+        actions.push(metadata);
+    }
+
+    // if we failed after committing but before the checkpoint was finalized the files will be
+    // committed again on recovery, so skip any that are already in the table
+    let existing_files: HashSet<String> = delta_table
+        .get_files_iter()
+        .map(|path| path.to_string())
+        .collect();
+
+    let prefix = format!("{}/", table_path);
+    for file in finished_files {
+        let relative_path = file
+            .filename
+            .strip_prefix(&prefix)
+            .ok_or_else(|| anyhow!("file {} is not in table {}", file.filename, table_path))?
+            .to_string();
+
+        if existing_files.contains(&relative_path) {
+            continue;
+        }
+
+        let metadata = storage_provider.head(file.filename.clone()).await?;
+        let partition_values = match &file.partition {
+            Some(partition) => partition_values(partition, &partition_columns)?,
+            None => HashMap::new(),
+        };
+
+        actions.push(Action::add(Add {
+            path: relative_path,
+            size: metadata.size as i64,
+            partition_values,
+            modification_time: metadata.last_modified.timestamp_millis(),
+            data_change: true,
+            ..Default::default()
+        }));
+    }
+
+    let added = actions
+        .iter()
+        .filter(|a| matches!(a, Action::add(_)))
+        .count();
+    if added == 0 {
+        return Ok(());
+    }
+
+    let version = commit(
+        delta_table.object_store().as_ref(),
+        &actions,
+        DeltaOperation::Write {
+            mode: SaveMode::Append,
+            partition_by: (!partition_columns.is_empty()).then(|| partition_columns.clone()),
+            predicate: None,
+        },
+        &delta_table.state,
+        None,
+    )
+    .await?;
+
+    info!(
+        "committed {} files to delta table {} at version {}",
+        added, table.write_target.path, version
+    );
+
+    Ok(())
+}
+
+fn partition_columns(table: &FileSystemTable) -> Vec<String> {
+    table
+        .file_settings
+        .as_ref()
+        .and_then(|settings| settings.partitioning.as_ref())
+        .map(|partitioning| partitioning.partition_fields.clone())
+        .unwrap_or_default()
+}
+
+async fn load_or_create_table(
+    uri: &str,
+    table: &FileSystemTable,
+    schema: &DeltaSchema,
+    partition_columns: &[String],
+) -> Result<DeltaTable> {
+    let storage_options = table.write_target.storage_options.clone();
+
+    match deltalake::open_table_with_storage_options(uri, storage_options.clone()).await {
+        Ok(table) => return Ok(table),
+        Err(DeltaTableError::NotATable(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    info!("creating delta table at {}", uri);
+    let created = DeltaOps::try_from_uri_with_storage_options(uri, storage_options.clone())
+        .await?
+        .create()
+        .with_columns(schema.get_fields().clone())
+        .with_partition_columns(partition_columns.to_vec())
+        .await;
+
+    match created {
+        Ok(table) => Ok(table),
+        // another subtask may have created the table concurrently
+        Err(_) => Ok(deltalake::open_table_with_storage_options(uri, storage_options).await?),
+    }
+}
+
+/// Compares the schema we're writing with the table's schema, returning a metadata action that
+/// adds the missing columns if the table is configured to merge schemas
+fn evolve_schema(
+    delta_table: &DeltaTable,
+    schema: &DeltaSchema,
+    table: &FileSystemTable,
+) -> Result<Option<Action>> {
+    let table_schema = delta_table.get_schema()?;
+
+    let mut missing = vec![];
+    for field in schema.get_fields() {
+        match table_schema.get_field_with_name(field.get_name()) {
+            Ok(existing) if existing.get_type() != field.get_type() => {
+                bail!(
+                    "column '{}' has type {:?} in delta table {}, but {:?} is being written",
+                    field.get_name(),
+                    existing.get_type(),
+                    table.write_target.path,
+                    field.get_type()
+                );
+            }
+            Ok(_) => {}
+            Err(_) => missing.push(field.clone()),
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    let names: Vec<_> = missing.iter().map(|f| f.get_name().to_string()).collect();
+    match table
+        .delta_settings
+        .as_ref()
+        .and_then(|settings| settings.schema_evolution.clone())
+        .unwrap_or(SchemaEvolution::Fail)
+    {
+        SchemaEvolution::Fail => bail!(
+            "delta table {} is missing columns [{}]; set schema_evolution to 'merge' to add them",
+            table.write_target.path,
+            names.join(", ")
+        ),
+        SchemaEvolution::Merge => {
+            info!(
+                "adding columns [{}] to delta table {}",
+                names.join(", "),
+                table.write_target.path
+            );
+            let mut metadata = delta_table.get_metadata()?.clone();
+            let mut fields = table_schema.get_fields().clone();
+            fields.extend(missing);
+            metadata.schema = DeltaSchema::new(fields);
+            Ok(Some(Action::metaData(MetaData::try_from(metadata)?)))
+        }
+    }
+}
+
+fn partition_values(
+    partition: &str,
+    partition_columns: &[String],
+) -> Result<HashMap<String, Option<String>>> {
+    Ok(partition_columns
+        .iter()
+        .cloned()
+        .zip(parse_partition_values(partition, partition_columns)?)
+        .map(|(column, value)| {
+            let value = match value {
+                Value::Null => None,
+                Value::String(s) => Some(s),
+                other => Some(other.to_string()),
+            };
+            (column, value)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::partition_values;
+
+    #[test]
+    fn test_partition_values() {
+        let columns = vec!["category".to_string(), "id".to_string()];
+        let values = partition_values("category=\"a/b\"/id=5", &columns).unwrap();
+        assert_eq!(values.get("category").unwrap().as_deref(), Some("a/b"));
+        assert_eq!(values.get("id").unwrap().as_deref(), Some("5"));
+
+        let values = partition_values("category=null/id=5", &columns).unwrap();
+        assert_eq!(values.get("category").unwrap(), &None);
+
+        assert!(partition_values("id=5", &columns).is_err());
+    }
+}
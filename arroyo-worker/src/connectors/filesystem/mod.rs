@@ -8,6 +8,7 @@ use std::{
 };
 
 use anyhow::{bail, Result};
+use arrow::datatypes::SchemaRef;
 use arroyo_rpc::OperatorConfig;
 use arroyo_storage::StorageProvider;
 use async_trait::async_trait;
@@ -24,10 +25,12 @@ use typify::import_types;
 import_types!(schema = "../connector-schemas/filesystem/table.json");
 
 use arroyo_types::*;
+pub mod delta;
 pub mod json;
 pub mod local;
 pub mod parquet;
 pub mod single_file;
+pub mod source;
 
 use self::{
     json::{JsonLocalWriter, JsonWriter, PassThrough},
@@ -35,6 +38,7 @@ use self::{
     parquet::{FixedSizeRecordBatchBuilder, ParquetLocalWriter, RecordBatchBufferingWriter},
 };
 
+use super::iceberg::sink::IcebergCommitter;
 use super::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
 
 pub struct FileSystemSink<
@@ -82,6 +86,13 @@ impl<K: Key, T: Data + Sync + Serialize, R: MultiPartWriter<InputType = T> + Sen
         let table: FileSystemTable =
             serde_json::from_value(config.table).expect("Invalid table config for FileSystemSink");
 
+        Self::create_and_start(table, CommitStrategy::PerFile)
+    }
+
+    pub(crate) fn create_and_start(
+        table: FileSystemTable,
+        commit_strategy: CommitStrategy,
+    ) -> TwoPhaseCommitterOperator<K, T, Self> {
         let (sender, receiver) = tokio::sync::mpsc::channel(10000);
         let (checkpoint_sender, checkpoint_receiver) = tokio::sync::mpsc::channel(10000);
         let partition_func = get_partitioner_from_table(&table);
@@ -101,6 +112,7 @@ impl<K: Key, T: Data + Sync + Serialize, R: MultiPartWriter<InputType = T> + Sen
                 receiver,
                 checkpoint_sender,
                 table.clone(),
+                commit_strategy,
             );
             writer.run().await.unwrap();
         });
@@ -160,6 +172,39 @@ fn partition_string_for_fields<T: Data + Serialize>(
     Ok(fields.join("/"))
 }
 
+/// Recovers the values of the partition fields from a partition path of the form
+/// `field1=value1/field2=value2` (as produced by [`partition_string_for_fields`]), in the order of
+/// `partition_fields`
+pub(crate) fn parse_partition_values(
+    partition: &str,
+    partition_fields: &[String],
+) -> Result<Vec<serde_json::Value>> {
+    let mut values = vec![];
+    let mut rest = partition;
+    for (i, field) in partition_fields.iter().enumerate() {
+        rest = rest
+            .strip_prefix(field.as_str())
+            .and_then(|r| r.strip_prefix('='))
+            .ok_or_else(|| anyhow::anyhow!("missing field {} in partition {}", field, partition))?;
+
+        let end = match partition_fields.get(i + 1) {
+            Some(next) => rest.find(&format!("/{}=", next)).ok_or_else(|| {
+                anyhow::anyhow!("missing field {} in partition {}", next, partition)
+            })?,
+            None => rest.len(),
+        };
+
+        values.push(
+            serde_json::from_str(&rest[..end])
+                .unwrap_or_else(|_| serde_json::Value::String(rest[..end].to_string())),
+        );
+
+        rest = rest[end..].strip_prefix('/').unwrap_or("");
+    }
+
+    Ok(values)
+}
+
 #[derive(Debug)]
 enum FileSystemMessages<T: Data> {
     Data {
@@ -332,6 +377,17 @@ struct AsyncMultipartFileSystemWriter<T: Data + Sync, R: MultiPartWriter> {
     files_to_finish: Vec<FileToFinish>,
     properties: FileSystemTable,
     rolling_policy: RollingPolicy,
+    commit_strategy: CommitStrategy,
+}
+
+/// Determines what happens once the files for a checkpoint have been finished
+pub(crate) enum CommitStrategy {
+    /// Each file is visible to readers as soon as its upload is completed
+    PerFile,
+    /// Finished files are added to a Delta Lake table in a single transaction
+    DeltaLake { schema: SchemaRef },
+    /// Finished files are added to an Iceberg table as a new snapshot
+    Iceberg(IcebergCommitter),
 }
 
 #[async_trait]
@@ -491,6 +547,12 @@ pub struct FileToFinish {
     completed_parts: Vec<String>,
 }
 
+#[derive(Debug)]
+pub(crate) struct FinishedFile {
+    pub(crate) filename: String,
+    pub(crate) partition: Option<String>,
+}
+
 enum RollingPolicy {
     PartLimit(usize),
     SizeLimit(usize),
@@ -582,6 +644,7 @@ where
         receiver: Receiver<FileSystemMessages<T>>,
         checkpoint_sender: Sender<CheckpointData<T>>,
         writer_properties: FileSystemTable,
+        commit_strategy: CommitStrategy,
     ) -> Self {
         Self {
             path,
@@ -599,6 +662,7 @@ where
                 writer_properties.file_settings.as_ref().unwrap(),
             ),
             properties: writer_properties,
+            commit_strategy,
         }
     }
 
@@ -641,8 +705,29 @@ where
                             self.checkpoint_sender.send(CheckpointData::Finished {  max_file_index: self.max_file_index}).await?;
                         },
                         FileSystemMessages::FilesToFinish(files_to_finish) =>{
+                            let mut finished_files = vec![];
                             for file_to_finish in files_to_finish {
-                                self.finish_file(file_to_finish).await?;
+                                if let Some(finished_file) = self.finish_file(file_to_finish).await? {
+                                    finished_files.push(finished_file);
+                                }
+                            }
+                            match &mut self.commit_strategy {
+                                CommitStrategy::PerFile => {}
+                                CommitStrategy::DeltaLake { schema } => {
+                                    delta::commit_files_to_delta(
+                                        finished_files,
+                                        &self.path,
+                                        &self.properties,
+                                        schema.clone(),
+                                        self.object_store.clone(),
+                                    )
+                                    .await?;
+                                }
+                                CommitStrategy::Iceberg(committer) => {
+                                    committer
+                                        .commit(finished_files, &self.path, self.object_store.clone())
+                                        .await?;
+                                }
                             }
                             self.checkpoint_sender.send(CheckpointData::Finished {  max_file_index: self.max_file_index}).await?;
                         }
@@ -757,16 +842,16 @@ where
         }
     }
 
-    async fn finish_file(&mut self, file_to_finish: FileToFinish) -> Result<()> {
+    async fn finish_file(&mut self, file_to_finish: FileToFinish) -> Result<Option<FinishedFile>> {
         let FileToFinish {
             filename,
-            partition: _,
+            partition,
             multi_part_upload_id,
             completed_parts,
         } = file_to_finish;
         if completed_parts.len() == 0 {
             warn!("no parts to finish for file {}", filename);
-            return Ok(());
+            return Ok(None);
         }
         let parts: Vec<_> = completed_parts
             .into_iter()
@@ -778,7 +863,10 @@ where
         self.object_store
             .close_multipart(&location, &multi_part_upload_id, parts)
             .await?;
-        Ok(())
+        Ok(Some(FinishedFile {
+            filename,
+            partition,
+        }))
     }
 
     async fn stop(&mut self) -> Result<()> {
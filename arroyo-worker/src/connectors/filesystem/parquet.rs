@@ -10,7 +10,7 @@ use arrow_array::RecordBatch;
 use arroyo_types::RecordBatchBuilder;
 use parquet::{
     arrow::ArrowWriter,
-    basic::{GzipLevel, ZstdLevel},
+    basic::{BrotliLevel, GzipLevel, ZstdLevel},
     file::properties::WriterProperties,
 };
 
@@ -35,6 +35,7 @@ fn writer_properties_from_table(table: &FileSystemTable) -> WriterProperties {
                 Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
                 Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
                 Compression::Lz4 => parquet::basic::Compression::LZ4,
+                Compression::Brotli => parquet::basic::Compression::BROTLI(BrotliLevel::default()),
             };
             parquet_writer_options = parquet_writer_options.set_compression(compression);
         }
@@ -0,0 +1,654 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use arrow::json::writer::record_batches_to_json_rows;
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{CsvFormat, Format};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, OperatorConfig};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_storage::{BackendConfig, StorageProvider};
+use arroyo_types::{Record, UserError};
+use bincode::{Decode, Encode};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info, warn};
+use typify::import_types;
+
+use crate::connectors::iceberg::source::timestamps_to_strings;
+use crate::engine::{Context, StreamNode};
+use crate::formats::{csv, DataDeserializer};
+use crate::{SchemaData, SourceFinishType};
+
+import_types!(schema = "../connector-schemas/filesystem_source/table.json");
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// how many rows we read between checks for control messages
+const ROWS_PER_CHECK: u64 = 512;
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+pub struct FileState {
+    path: String,
+    /// The number of rows (or lines, for JSON files) of the file that have been read
+    offset: u64,
+    finished: bool,
+}
+
+/// Reads files from a directory or object store prefix as they appear.
+///
+/// New files are discovered either by listing the prefix every `poll_interval_ms`, or from S3
+/// event notifications delivered to an SQS queue. Files are split between subtasks by the hash
+/// of their path (or all read by the first subtask when using SQS), and the number of rows read
+/// from each file is stored in checkpoints so that each row is emitted exactly once.
+#[derive(StreamNode)]
+pub struct FileSystemSourceFunc<K: Send + 'static, T: SchemaData> {
+    table: FileSystemSourceTable,
+    file_format: FileFormat,
+    pattern: Option<Regex>,
+    files: HashMap<String, FileState>,
+    format: Format,
+    deserializer: DataDeserializer<T>,
+    rows_since_check: u64,
+    // receipt handles for SQS messages whose files have been read, to be deleted once they've
+    // been checkpointed
+    processed_messages: Vec<String>,
+    checkpointed_messages: Option<Vec<String>>,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K: Send + 'static, T: SchemaData> FileSystemSourceFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for FileSystemSource");
+        let table: FileSystemSourceTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for FileSystemSource");
+        let format = config.format.expect("FileSystemSource requires a format");
+
+        let file_format = match (&table.file_format, &format) {
+            (Some(f), _) => f.clone(),
+            (None, Format::Parquet(_)) => FileFormat::Parquet,
+            (None, Format::Csv(_)) => FileFormat::Csv,
+            (None, _) => FileFormat::Json,
+        };
+
+        Self {
+            pattern: table
+                .pattern
+                .as_ref()
+                .map(|p| Regex::new(p).expect("invalid pattern for FileSystemSource")),
+            table,
+            deserializer: DataDeserializer::new(format.clone(), None)
+                .with_bad_data(config.bad_data)
+                .with_rate_limit(config.rate_limit),
+            format,
+            file_format,
+            files: HashMap::new(),
+            rows_since_check: 0,
+            processed_messages: vec![],
+            checkpointed_messages: None,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "FileSystemSource".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("f", "filesystem source files")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let files: GlobalKeyedState<String, FileState, _> =
+            ctx.state.get_global_keyed_state('f').await;
+        self.files = files
+            .get_all()
+            .into_iter()
+            .map(|s| (s.path.clone(), s.clone()))
+            .collect();
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let sqs = self.table.sqs_queue_url.is_some();
+        if sqs && ctx.task_info.task_index != 0 {
+            // files from the queue are all read by the first subtask
+            return Ok(SourceFinishType::Final);
+        }
+
+        let provider = StorageProvider::for_url_with_options(
+            &self.table.path,
+            self.table.storage_options.clone(),
+        )
+        .await
+        .map_err(|e| {
+            UserError::new(
+                "failed to connect to storage",
+                format!(
+                    "failed to create storage provider for {}: {}",
+                    self.table.path, e
+                ),
+            )
+        })?;
+
+        let prefix = match provider.config() {
+            // local providers are rooted at the directory itself
+            BackendConfig::Local(_) => None,
+            _ => StorageProvider::get_key(&self.table.path).ok(),
+        };
+
+        // we always start by listing the prefix, even when using SQS, which picks up files that
+        // were written before we started or whose notifications we deleted before a failure
+        if let Some(finish) = self
+            .read_new_files(ctx, &provider, prefix.as_deref())
+            .await?
+        {
+            return Ok(finish);
+        }
+
+        if matches!(self.table.scan_mode, Some(ScanMode::Once)) {
+            info!("finished reading {}", self.table.path);
+            return Ok(SourceFinishType::Final);
+        }
+
+        if sqs {
+            return self.run_sqs(ctx, &provider).await;
+        }
+
+        let poll_interval = self
+            .table
+            .poll_interval_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+        loop {
+            if let Some(finish) = self.wait(ctx, poll_interval).await {
+                return Ok(finish);
+            }
+
+            if let Some(finish) = self
+                .read_new_files(ctx, &provider, prefix.as_deref())
+                .await?
+            {
+                return Ok(finish);
+            }
+        }
+    }
+
+    /// Lists the prefix and reads all of the files assigned to this subtask that we haven't
+    /// finished, oldest first
+    async fn read_new_files(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        provider: &StorageProvider,
+        prefix: Option<&str>,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        let mut objects = provider.list(prefix).await.map_err(|e| {
+            UserError::new(
+                "failed to list files",
+                format!("failed to list {}: {}", self.table.path, e),
+            )
+        })?;
+        objects.sort_by(|a, b| (a.last_modified, &a.location).cmp(&(b.last_modified, &b.location)));
+
+        let sqs = self.table.sqs_queue_url.is_some();
+        for object in objects {
+            let path = object.location.to_string();
+            if !sqs && !owns(&path, ctx.task_info.task_index, ctx.task_info.parallelism) {
+                continue;
+            }
+
+            if let Some(finish) = self.maybe_read_file(ctx, provider, &path).await? {
+                return Ok(Some(finish));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads new files from S3 event notifications delivered to the SQS queue
+    async fn run_sqs(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        provider: &StorageProvider,
+    ) -> Result<SourceFinishType, UserError> {
+        let queue_url = self.table.sqs_queue_url.clone().unwrap();
+        let client = aws_sdk_sqs::Client::new(&aws_config::load_from_env().await);
+        let sqs_error = |e: &dyn std::fmt::Display| {
+            UserError::new(
+                "failed to receive from SQS",
+                format!("failed to receive from {}: {}", queue_url, e),
+            )
+        };
+
+        loop {
+            // short waits so that we can handle control messages in between
+            let response = client
+                .receive_message()
+                .queue_url(&queue_url)
+                .max_number_of_messages(10)
+                .wait_time_seconds(1)
+                .send()
+                .await
+                .map_err(|e| sqs_error(&e))?;
+
+            for message in response.messages.unwrap_or_default() {
+                let keys = message
+                    .body
+                    .as_deref()
+                    .map(s3_event_keys)
+                    .unwrap_or_default();
+
+                for key in keys {
+                    if let Some(finish) = self.maybe_read_file(ctx, provider, &key).await? {
+                        return Ok(finish);
+                    }
+                }
+
+                if let Some(receipt_handle) = message.receipt_handle {
+                    self.processed_messages.push(receipt_handle);
+                }
+            }
+
+            if let Some(finish) = self.handle_control_messages(ctx).await {
+                return Ok(finish);
+            }
+
+            // delete the notifications for files whose progress is in state that's been
+            // written; if the checkpoint fails, the files are found by listing on restart
+            if let Some(checkpointed) = self.checkpointed_messages.take() {
+                for receipt_handle in checkpointed {
+                    if let Err(e) = client
+                        .delete_message()
+                        .queue_url(&queue_url)
+                        .receipt_handle(receipt_handle)
+                        .send()
+                        .await
+                    {
+                        warn!("failed to delete message from {}: {}", queue_url, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the file if it matches the pattern and we haven't already finished it
+    async fn maybe_read_file(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        provider: &StorageProvider,
+        path: &str,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(path) {
+                return Ok(None);
+            }
+        }
+
+        let offset = match self.files.get(path) {
+            Some(state) if state.finished => return Ok(None),
+            Some(state) => state.offset,
+            None => 0,
+        };
+
+        self.read_file(ctx, provider, path, offset).await
+    }
+
+    /// Reads a file starting from `offset` rows in, returning early if the source needs to stop
+    async fn read_file(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        provider: &StorageProvider,
+        path: &str,
+        offset: u64,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        debug!("reading {} from row {}", path, offset);
+        let read_error = |e: &dyn std::fmt::Display| {
+            UserError::new(
+                "failed to read file",
+                format!("failed to read {}: {}", path, e),
+            )
+        };
+
+        let bytes = provider.get(path).await.map_err(|e| read_error(&e))?;
+
+        match self.file_format {
+            FileFormat::Json => {
+                for (i, line) in bytes
+                    .split(|b| *b == b'\n')
+                    .enumerate()
+                    .skip(offset as usize)
+                {
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    self.deserializer.throttle(line).await;
+                    let values: Vec<_> = if line.iter().all(|b| b.is_ascii_whitespace()) {
+                        vec![]
+                    } else {
+                        self.deserializer.deserialize_slice(line).collect()
+                    };
+
+                    if let Some(finish) = self.emit(ctx, path, i as u64 + 1, line, values).await? {
+                        return Ok(Some(finish));
+                    }
+                }
+            }
+            FileFormat::Csv => {
+                // csv files read with the json format have a header and are comma-separated
+                let format = match &self.format {
+                    Format::Csv(csv) => csv.clone(),
+                    _ => CsvFormat {
+                        header: true,
+                        ..Default::default()
+                    },
+                };
+
+                let schema = T::schema();
+                let mut reader = csv::reader(&format, &bytes[..]);
+                let headers = if format.header {
+                    Some(reader.headers().map_err(|e| read_error(&e))?.clone())
+                } else {
+                    None
+                };
+
+                for (i, record) in reader.records().enumerate().skip(offset as usize) {
+                    let row = record
+                        .as_ref()
+                        .map(|r| {
+                            r.iter()
+                                .collect::<Vec<_>>()
+                                .join(&format.delimiter.to_string())
+                        })
+                        .unwrap_or_default();
+                    let value = record
+                        .map_err(|e| e.to_string())
+                        .and_then(|r| csv::record_to_json(&format, &schema, headers.as_ref(), &r))
+                        .and_then(|v| serde_json::from_value(v).map_err(|e| e.to_string()))
+                        .map_err(|e| {
+                            UserError::new(
+                                "Deserialization failed",
+                                format!("failed to read row {} of {}: {}", i + 1, path, e),
+                            )
+                        });
+
+                    if let Some(finish) = self
+                        .emit(ctx, path, i as u64 + 1, row.as_bytes(), vec![value])
+                        .await?
+                    {
+                        return Ok(Some(finish));
+                    }
+                }
+            }
+            FileFormat::Parquet => {
+                let builder =
+                    ParquetRecordBatchReaderBuilder::try_new(bytes).map_err(|e| read_error(&e))?;
+
+                // columns are matched by name to the fields of the output type
+                let schema = T::schema();
+                let columns: Vec<_> = builder
+                    .schema()
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, f)| schema.field_with_name(f.name()).is_ok())
+                    .map(|(i, _)| i)
+                    .collect();
+                let mask = ProjectionMask::roots(builder.parquet_schema(), columns);
+                let reader = builder
+                    .with_projection(mask)
+                    .with_offset(offset as usize)
+                    .build()
+                    .map_err(|e| read_error(&e))?;
+
+                let mut rows_read = offset;
+                for batch in reader {
+                    let batch = batch.map_err(|e| read_error(&e))?;
+                    let rows = record_batches_to_json_rows(&[&timestamps_to_strings(&batch)])
+                        .map_err(|e| read_error(&e))?;
+
+                    for row in rows {
+                        rows_read += 1;
+                        let row = Value::Object(row);
+                        let value = T::deserialize(&row).map_err(|e| {
+                            UserError::new(
+                                "Deserialization failed",
+                                format!("failed to deserialize row from {}: {}", path, e),
+                            )
+                        });
+
+                        // the row is only needed as a message if it can't be deserialized
+                        let msg = match &value {
+                            Ok(_) => vec![],
+                            Err(_) => serde_json::to_vec(&row).unwrap_or_default(),
+                        };
+
+                        if let Some(finish) =
+                            self.emit(ctx, path, rows_read, &msg, vec![value]).await?
+                        {
+                            return Ok(Some(finish));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.files.insert(
+            path.to_string(),
+            FileState {
+                path: path.to_string(),
+                offset: self.files.get(path).map(|f| f.offset).unwrap_or(offset),
+                finished: true,
+            },
+        );
+        info!("finished reading {}", path);
+
+        Ok(self.handle_control_messages(ctx).await)
+    }
+
+    /// Emits the values read from a row of the file, records that we've read up to `offset`, and
+    /// periodically handles control messages. `msg` is the raw row, which is passed to the bad
+    /// data policy for values that failed to deserialize.
+    async fn emit(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        path: &str,
+        offset: u64,
+        msg: &[u8],
+        values: Vec<Result<T, UserError>>,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        for value in values {
+            match value {
+                Ok(value) => {
+                    ctx.collect(Record {
+                        timestamp: SystemTime::now(),
+                        key: None,
+                        value,
+                    })
+                    .await;
+                }
+                Err(e) => {
+                    if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, msg, e)? {
+                        ctx.report_user_error(e).await;
+                    }
+                }
+            }
+        }
+
+        self.files.insert(
+            path.to_string(),
+            FileState {
+                path: path.to_string(),
+                offset,
+                finished: false,
+            },
+        );
+
+        self.rows_since_check += 1;
+        if self.rows_since_check >= ROWS_PER_CHECK {
+            self.rows_since_check = 0;
+            return Ok(self.handle_control_messages(ctx).await);
+        }
+
+        Ok(None)
+    }
+
+    /// Waits for `duration` while handling control messages
+    async fn wait(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        duration: Duration,
+    ) -> Option<SourceFinishType> {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            if let Some(finish) = self.handle_control_messages(ctx).await {
+                return Some(finish);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        None
+    }
+
+    async fn handle_control_messages(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> Option<SourceFinishType> {
+        while let Ok(msg) = ctx.control_rx.try_recv() {
+            match msg {
+                ControlMessage::Checkpoint(c) => {
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    let mut files: GlobalKeyedState<String, FileState, _> =
+                        ctx.state.get_global_keyed_state('f').await;
+                    for (path, state) in &self.files {
+                        files.insert(path.clone(), state.clone()).await;
+                    }
+
+                    self.checkpointed_messages
+                        .get_or_insert_with(Vec::new)
+                        .append(&mut self.processed_messages);
+
+                    if self.checkpoint(c, ctx).await {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+                ControlMessage::Stop { mode } => {
+                    info!("Stopping FileSystem source {:?}", mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return Some(SourceFinishType::Graceful);
+                        }
+                        StopMode::Immediate => {
+                            return Some(SourceFinishType::Immediate);
+                        }
+                    }
+                }
+                ControlMessage::Commit { epoch: _ } => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                ControlMessage::LoadCompacted { compacted } => {
+                    ctx.load_compacted(compacted).await;
+                }
+                ControlMessage::QueryState { tx, .. } => {
+                    ctx.reject_state_query(tx);
+                }
+                ControlMessage::NoOp => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Whether the file at `path` should be read by this subtask
+fn owns(path: &str, task_index: usize, parallelism: usize) -> bool {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish() as usize % parallelism == task_index
+}
+
+/// Returns the keys of the objects created in an S3 event notification, which are URL-encoded
+fn s3_event_keys(body: &str) -> Vec<String> {
+    let Ok(event) = serde_json::from_str::<Value>(body) else {
+        warn!("received invalid S3 event notification: {}", body);
+        return vec![];
+    };
+
+    event
+        .get("Records")
+        .and_then(|r| r.as_array())
+        .map(|records| {
+            records
+                .iter()
+                .filter(|r| {
+                    r.get("eventName")
+                        .and_then(|n| n.as_str())
+                        .map(|n| n.starts_with("ObjectCreated:"))
+                        .unwrap_or(false)
+                })
+                .filter_map(|r| r.pointer("/s3/object/key")?.as_str())
+                .map(|key| {
+                    percent_encoding::percent_decode_str(&key.replace('+', " "))
+                        .decode_utf8_lossy()
+                        .to_string()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{owns, s3_event_keys};
+
+    #[test]
+    fn test_owns() {
+        let paths: Vec<_> = (0..100).map(|i| format!("events/{}.json", i)).collect();
+        for path in &paths {
+            assert_eq!((0..4).filter(|i| owns(path, *i, 4)).count(), 1);
+        }
+        assert!(paths.iter().all(|p| owns(p, 0, 1)));
+    }
+
+    #[test]
+    fn test_s3_event_keys() {
+        let body = json!({
+            "Records": [
+                {
+                    "eventName": "ObjectCreated:Put",
+                    "s3": {"bucket": {"name": "bucket"}, "object": {"key": "events/my+file%281%29.json"}}
+                },
+                {
+                    "eventName": "ObjectRemoved:Delete",
+                    "s3": {"bucket": {"name": "bucket"}, "object": {"key": "events/old.json"}}
+                }
+            ]
+        });
+
+        assert_eq!(
+            s3_event_keys(&body.to_string()),
+            vec!["events/my file(1).json".to_string()]
+        );
+
+        // sent when notifications are first configured
+        assert!(s3_event_keys(r#"{"Service":"Amazon S3","Event":"s3:TestEvent"}"#).is_empty());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
 use arroyo_macro::process_fn;
 use arroyo_rpc::OperatorConfig;
 use arroyo_types::*;
@@ -13,14 +15,15 @@ use serde::Serialize;
 use super::{FluvioTable, TableType};
 
 #[derive(StreamNode)]
-pub struct FluvioSinkFunc<K: Key + Serialize, T: Data + Serialize> {
+pub struct FluvioSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
     topic: String,
     endpoint: Option<String>,
     producer: Option<TopicProducer>,
-    _t: PhantomData<(K, T)>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
 }
 
-impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
+impl<K: Key + Serialize, T: SchemaData + Serialize> FluvioSinkFunc<K, T> {
     pub fn from_config(config: &str) -> Self {
         let config: OperatorConfig =
             serde_json::from_str(config).expect("Invalid config for FluvioSink");
@@ -34,13 +37,19 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
             topic: table.topic,
             endpoint: table.endpoint,
             producer: None,
+            serializer: DataSerializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for FluvioSink"),
+            )
+            .with_compression(config.compression),
             _t: PhantomData,
         }
     }
 }
 
 #[process_fn(in_k = K, in_t = T)]
-impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
+impl<K: Key + Serialize, T: SchemaData + Serialize> FluvioSinkFunc<K, T> {
     fn name(&self) -> String {
         format!("fluvio-sink-{}", self.topic)
     }
@@ -78,22 +87,34 @@ impl<K: Key + Serialize, T: Data + Serialize> FluvioSinkFunc<K, T> {
         }
     }
 
-    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
-        self.producer.as_mut().unwrap().flush().await.unwrap();
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        // all records sent before the barrier must be acknowledged before the checkpoint completes
+        if let Err(e) = self.producer.as_mut().unwrap().flush().await {
+            ctx.report_error("Failed to flush to Fluvio".to_string(), e.to_string())
+                .await;
+            panic!("Failed to flush to Fluvio: {:?}", e);
+        }
     }
 
-    async fn process_element(&mut self, record: &Record<K, T>, _ctx: &mut Context<(), ()>) {
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
         let k = record
             .key
             .as_ref()
             .map(|k| serde_json::to_string(k).unwrap());
-        let v = serde_json::to_string(&record.value).unwrap();
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
 
-        self.producer
+        if let Err(e) = self
+            .producer
             .as_mut()
             .unwrap()
             .send(k.unwrap_or_default(), v)
             .await
-            .unwrap();
+        {
+            ctx.report_error("Failed to write to Fluvio".to_string(), e.to_string())
+                .await;
+            panic!("Failed to write to Fluvio: {:?}", e);
+        }
     }
 }
@@ -84,7 +84,10 @@ where
             deserializer: DataDeserializer::new(
                 config.format.expect("Format must be specified for fluvio"),
                 config.framing,
-            ),
+            )
+            .with_bad_data(config.bad_data)
+            .with_compression(config.compression)
+            .with_rate_limit(config.rate_limit),
             _t: PhantomData,
         }
     }
@@ -200,12 +203,21 @@ where
                     match message {
                         Some((_, Ok(msg))) => {
                             let timestamp = from_millis(msg.timestamp().max(0) as u64);
+                            self.deserializer.throttle(msg.value()).await;
                             let iter = self.deserializer.deserialize_slice(msg.value());
                             for value in iter {
+                                let value = match value {
+                                    Ok(value) => value,
+                                    Err(e) => match self.deserializer.handle_bad_data(&ctx.task_info, msg.value(), e)? {
+                                        Some(e) => return Err(e),
+                                        None => continue,
+                                    },
+                                };
+
                                 ctx.collector.collect(Record {
                                     timestamp,
                                     key: None,
-                                    value: value?,
+                                    value,
                                 }).await;
                             }
                             offsets.insert(msg.partition(), msg.offset());
@@ -253,6 +265,9 @@ where
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
+                        Some(ControlMessage::QueryState { tx, .. }) => {
+                            ctx.reject_state_query(tx);
+                        }
                         Some(ControlMessage::NoOp ) => {}
                         None => {
 
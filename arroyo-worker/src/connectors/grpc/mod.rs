@@ -0,0 +1,246 @@
+use anyhow::{anyhow, Result};
+use arroyo_types::string_to_map;
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DeserializeOptions, DynamicMessage, MessageDescriptor, MethodDescriptor,
+    SerializeOptions,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::{MetadataKey, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Request, Status};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/grpc/connection.json");
+import_types!(schema = "../connector-schemas/grpc/table.json");
+
+impl GrpcTable {
+    /// Finds the table's method in its descriptor set
+    pub fn method(&self) -> Result<MethodDescriptor> {
+        let bytes = base64::decode(self.descriptor_set.trim())
+            .map_err(|e| anyhow!("descriptor_set is not valid base64: {}", e))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|e| anyhow!("descriptor_set is not a valid FileDescriptorSet: {}", e))?;
+
+        let (service, method) = self
+            .method
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("invalid method '{}'", self.method))?;
+
+        pool.get_service_by_name(service)
+            .ok_or_else(|| anyhow!("service '{}' is not in the descriptor set", service))?
+            .methods()
+            .find(|m| m.name() == method)
+            .ok_or_else(|| anyhow!("service '{}' has no method '{}'", service, method))
+    }
+
+    /// The HTTP/2 path that the method is called on
+    pub fn path(&self) -> Result<PathAndQuery> {
+        Ok(PathAndQuery::from_maybe_shared(format!(
+            "/{}",
+            self.method
+        ))?)
+    }
+}
+
+impl GrpcConfig {
+    pub async fn connect(&self) -> Result<tonic::client::Grpc<Channel>> {
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone())?;
+        if self.endpoint.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        Ok(tonic::client::Grpc::new(endpoint.connect().await?))
+    }
+
+    /// Wraps the message in a request carrying the configured metadata
+    pub fn request<T>(&self, message: T) -> Result<Request<T>> {
+        let mut request = Request::new(message);
+        let headers = string_to_map(self.headers.as_ref().map(|h| h.0.as_str()).unwrap_or(""))
+            .ok_or_else(|| anyhow!("invalid headers"))?;
+
+        for (k, v) in headers {
+            request.metadata_mut().insert(
+                MetadataKey::from_bytes(k.to_lowercase().as_bytes())
+                    .map_err(|_| anyhow!("invalid header name {}", k))?,
+                MetadataValue::try_from(&v).map_err(|_| anyhow!("invalid header value {}", v))?,
+            );
+        }
+
+        Ok(request)
+    }
+}
+
+/// Converts a message to JSON, using the proto field names and writing 64-bit integers as
+/// numbers so that they can be deserialized into our types
+pub fn to_json(message: &DynamicMessage) -> Result<Value> {
+    Ok(message.serialize_with_options(
+        serde_json::value::Serializer,
+        &SerializeOptions::new()
+            .use_proto_field_name(true)
+            .stringify_64_bit_integers(false)
+            .skip_default_fields(false),
+    )?)
+}
+
+/// Builds a message of type `descriptor` from JSON, ignoring fields that aren't in the message
+pub fn from_json(descriptor: MessageDescriptor, value: &Value) -> Result<DynamicMessage> {
+    Ok(DynamicMessage::deserialize_with_options(
+        descriptor,
+        value,
+        &DeserializeOptions::new().deny_unknown_fields(false),
+    )?)
+}
+
+/// A codec for messages whose types are only known at runtime
+#[derive(Clone)]
+pub struct DynamicCodec {
+    response: MessageDescriptor,
+}
+
+impl DynamicCodec {
+    pub fn new(response: MessageDescriptor) -> Self {
+        Self { response }
+    }
+}
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder(self.response.clone())
+    }
+}
+
+pub struct DynamicEncoder;
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst)
+            .map_err(|e| Status::internal(format!("failed to encode message: {}", e)))
+    }
+}
+
+pub struct DynamicDecoder(MessageDescriptor);
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(Some(DynamicMessage::decode(self.0.clone(), src).map_err(
+            |e| Status::internal(format!("failed to decode message: {}", e)),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::{
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        MethodDescriptorProto, ServiceDescriptorProto,
+    };
+    use serde_json::json;
+
+    use super::{from_json, to_json, GrpcTable, TableType};
+
+    fn field(name: &str, number: i32, typ: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(typ as i32),
+            json_name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn table() -> GrpcTable {
+        let file = FileDescriptorProto {
+            name: Some("events.proto".to_string()),
+            package: Some("events.v1".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Event".to_string()),
+                    field: vec![
+                        field("event_id", 1, Type::Int64),
+                        field("name", 2, Type::String),
+                    ],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("SubscribeRequest".to_string()),
+                    ..Default::default()
+                },
+            ],
+            service: vec![ServiceDescriptorProto {
+                name: Some("EventService".to_string()),
+                method: vec![MethodDescriptorProto {
+                    name: Some("Subscribe".to_string()),
+                    input_type: Some(".events.v1.SubscribeRequest".to_string()),
+                    output_type: Some(".events.v1.Event".to_string()),
+                    server_streaming: Some(true),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        GrpcTable {
+            method: "events.v1.EventService/Subscribe".to_string(),
+            descriptor_set: base64::encode(FileDescriptorSet { file: vec![file] }.encode_to_vec()),
+            type_: TableType::Source { request: None },
+        }
+    }
+
+    #[test]
+    fn test_method() {
+        let method = table().method().unwrap();
+        assert!(method.is_server_streaming());
+        assert_eq!(method.output().full_name(), "events.v1.Event");
+        assert_eq!(
+            table().path().unwrap().as_str(),
+            "/events.v1.EventService/Subscribe"
+        );
+
+        let mut missing = table();
+        missing.method = "events.v1.EventService/Publish".to_string();
+        assert!(missing.method().is_err());
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let descriptor = table().method().unwrap().output();
+
+        let message = from_json(
+            descriptor,
+            &json!({"event_id": 12345678901i64, "name": "click", "unknown": true}),
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_json(&message).unwrap(),
+            json!({"event_id": 12345678901i64, "name": "click"})
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use prost_reflect::{DynamicMessage, MethodDescriptor};
+use serde::Serialize;
+use std::marker::PhantomData;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::info;
+
+use super::{from_json, DynamicCodec, GrpcConfig, GrpcTable, TableType};
+
+/// A client-streaming call that's in progress; the call completes once the sender is dropped
+struct Call {
+    tx: mpsc::Sender<DynamicMessage>,
+    response: JoinHandle<anyhow::Result<()>>,
+}
+
+#[derive(StreamNode)]
+pub struct GrpcSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: GrpcConfig,
+    table: GrpcTable,
+    method: MethodDescriptor,
+    call: Option<Call>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> GrpcSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for GrpcSink");
+        let connection: GrpcConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for GrpcSink");
+        let table: GrpcTable =
+            serde_json::from_value(config.table).expect("Invalid table config for GrpcSink");
+        let TableType::Sink {} = &table.type_ else {
+            panic!("found non-sink gRPC config in sink operator");
+        };
+
+        Self {
+            config: connection,
+            method: table.method().expect("Invalid method for GrpcSink"),
+            table,
+            call: None,
+            serializer: DataSerializer::new(
+                config.format.expect("Format must be defined for GrpcSink"),
+            ),
+            _t: PhantomData,
+        }
+    }
+
+    async fn start_call(&self) -> anyhow::Result<Call> {
+        let mut grpc = self.config.connect().await?;
+        grpc.ready().await?;
+
+        let (tx, rx) = mpsc::channel(128);
+        let request = self.config.request(ReceiverStream::new(rx))?;
+        let path = self.table.path()?;
+        let codec = DynamicCodec::new(self.method.output());
+
+        let response = tokio::spawn(async move {
+            grpc.client_streaming(request, path, codec).await?;
+            Ok(())
+        });
+
+        Ok(Call { tx, response })
+    }
+
+    async fn fail(&self, ctx: &mut Context<(), ()>, details: String) {
+        ctx.report_error(
+            format!("Failed to write to {}", self.table.method),
+            details.clone(),
+        )
+        .await;
+        panic!("Failed to write to {}: {}", self.table.method, details);
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> GrpcSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("grpc-sink-{}", self.table.method)
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        let message = match serde_json::from_slice(&v)
+            .map_err(anyhow::Error::from)
+            .and_then(|v| from_json(self.method.input(), &v))
+        {
+            Ok(message) => message,
+            Err(e) => {
+                ctx.report_error(
+                    "Failed to convert record to protobuf".to_string(),
+                    e.to_string(),
+                )
+                .await;
+                return;
+            }
+        };
+
+        if self.call.is_none() {
+            match self.start_call().await {
+                Ok(call) => self.call = Some(call),
+                Err(e) => {
+                    self.fail(ctx, format!("{:?}", e)).await;
+                    return;
+                }
+            }
+        }
+
+        if self.call.as_ref().unwrap().tx.send(message).await.is_err() {
+            // the call has ended early; finishing it will surface the server's error
+            self.finish_call(ctx).await;
+            self.fail(ctx, "call ended unexpectedly".to_string()).await;
+        }
+    }
+
+    /// Closes the request stream and waits for the server to respond, so that everything
+    /// written before a checkpoint has been acknowledged by the time the checkpoint completes
+    async fn finish_call(&mut self, ctx: &mut Context<(), ()>) {
+        let Some(Call { tx, response }) = self.call.take() else {
+            return;
+        };
+
+        drop(tx);
+        match response.await {
+            Ok(Ok(())) => {
+                info!("Finished call to {}", self.table.method);
+            }
+            Ok(Err(e)) => self.fail(ctx, format!("{:?}", e)).await,
+            Err(e) => self.fail(ctx, format!("{:?}", e)).await,
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.finish_call(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.finish_call(ctx).await;
+    }
+}
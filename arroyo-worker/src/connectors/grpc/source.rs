@@ -0,0 +1,258 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_types::*;
+use prost_reflect::DynamicMessage;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tonic::Streaming;
+use tracing::{debug, info, warn};
+
+use super::{from_json, to_json, DynamicCodec, GrpcConfig, GrpcTable, TableType};
+
+#[derive(StreamNode)]
+pub struct GrpcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: GrpcConfig,
+    table: GrpcTable,
+    request: Value,
+    deserializer: DataDeserializer<T>,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> GrpcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn new(
+        config: GrpcConfig,
+        table: GrpcTable,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        let TableType::Source { request } = &table.type_ else {
+            panic!("found non-source gRPC config in source operator");
+        };
+
+        let request = request
+            .as_ref()
+            .map(|r| serde_json::from_str(r).expect("Invalid request for GrpcSource"))
+            .unwrap_or_else(|| Value::Object(Default::default()));
+
+        Self {
+            config,
+            table,
+            request,
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_rate_limit(rate_limit),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for GrpcSource");
+        let connection: GrpcConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for GrpcSource");
+        let table: GrpcTable =
+            serde_json::from_value(config.table).expect("Invalid table config for GrpcSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for GrpcSource"),
+            config.framing,
+            config.bad_data,
+            config.rate_limit,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("grpc-{}", self.table.method)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping gRPC source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    /// Opens the server stream, sending the configured request
+    async fn subscribe(&self) -> anyhow::Result<Streaming<DynamicMessage>> {
+        let method = self.table.method()?;
+        let request = from_json(method.input(), &self.request)?;
+
+        let mut grpc = self.config.connect().await?;
+        grpc.ready().await?;
+
+        Ok(grpc
+            .server_streaming(
+                self.config.request(request)?,
+                self.table.path()?,
+                DynamicCodec::new(method.output()),
+            )
+            .await?
+            .into_inner())
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // a server stream can't be split across readers, so only the first subtask calls the method
+        if ctx.task_info.task_index != 0 {
+            ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
+
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        // fail fast on configuration problems rather than retrying them
+        self.table
+            .method()
+            .map_err(|e| UserError::new("Invalid gRPC method", e.to_string()))?;
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+        let mut backoff = Duration::from_millis(100);
+
+        loop {
+            let mut stream = match self.subscribe().await {
+                Ok(stream) => {
+                    info!("Subscribed to {}", self.table.method);
+                    backoff = Duration::from_millis(100);
+                    stream
+                }
+                Err(e) => {
+                    warn!("Failed to call {}: {:?}", self.table.method, e);
+                    select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        control_message = ctx.control_rx.recv() => {
+                            if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                                return Ok(r);
+                            }
+                        }
+                    }
+                    backoff = (backoff * 2).min(Duration::from_secs(10));
+                    continue;
+                }
+            };
+
+            loop {
+                select! {
+                    message = stream.message() => {
+                        match message {
+                            Ok(Some(message)) => {
+                                let json = to_json(&message)
+                                    .map(|v| serde_json::to_vec(&v).unwrap())
+                                    .map_err(|e| UserError::new("Failed to convert gRPC message", e.to_string()))?;
+
+                                self.deserializer.throttle(&json).await;
+                                for value in self.deserializer.deserialize_slice(&json) {
+                                    match value {
+                                        Ok(value) => {
+                                            ctx.collector.collect(Record {
+                                                timestamp: SystemTime::now(),
+                                                key: None,
+                                                value,
+                                            }).await;
+                                        }
+                                        Err(e) => {
+                                            if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, &json, e)? {
+                                                errors += 1;
+                                                if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                                    ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                                    errors = 0;
+                                                    last_reported_error = Instant::now();
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            Ok(None) => {
+                                info!("Server closed stream for {}", self.table.method);
+                                return Ok(SourceFinishType::Final);
+                            }
+                            Err(status) => {
+                                warn!("Error from gRPC stream {}: {:?}", self.table.method, status);
+                                break;
+                            }
+                        }
+                    }
+                    control_message = ctx.control_rx.recv() => {
+                        if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                            return Ok(r);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::string_to_map;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use regex::Regex;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+use crate::operators::lookup_join::LookupSource;
+
+import_types!(schema = "../connector-schemas/http_lookup/table.json");
+
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
+
+/// Looks up rows by requesting the endpoint with the key substituted for `{{ key }}`
+pub struct HttpLookup {
+    client: reqwest::Client,
+    endpoint: String,
+    key_pattern: Regex,
+    max_concurrency: usize,
+}
+
+impl HttpLookup {
+    pub fn from_config(config: &str, _key_column: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for HttpLookup");
+        let table: HttpLookupTable =
+            serde_json::from_value(config.table).expect("Invalid table config for HttpLookup");
+
+        let headers = string_to_map(table.headers.as_ref().map(|t| t.0.as_str()).unwrap_or(""))
+            .expect("Invalid header map")
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    (&k).try_into()
+                        .expect(&format!("invalid header name {}", k)),
+                    (&v).try_into()
+                        .expect(&format!("invalid header value {}", v)),
+                )
+            })
+            .collect();
+
+        Self {
+            client: reqwest::ClientBuilder::new()
+                .default_headers(headers)
+                .timeout(Duration::from_secs(5))
+                .build()
+                .expect("could not construct http client"),
+            endpoint: table.endpoint,
+            key_pattern: Regex::new(r"\{\{\s*key\s*\}\}").unwrap(),
+            max_concurrency: table
+                .max_concurrency
+                .map(|n| n.max(1) as usize)
+                .unwrap_or(DEFAULT_MAX_CONCURRENCY),
+        }
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let url = self
+            .key_pattern
+            .replace_all(
+                &self.endpoint,
+                utf8_percent_encode(key, NON_ALPHANUMERIC).to_string(),
+            )
+            .to_string();
+
+        let response = self.client.get(&url).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+            status => bail!("lookup request to {} failed with status {}", url, status),
+        }
+    }
+}
+
+#[async_trait]
+impl LookupSource for HttpLookup {
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        futures::stream::iter(keys)
+            .map(|key| self.get(key))
+            .buffered(self.max_concurrency)
+            .try_collect()
+            .await
+    }
+}
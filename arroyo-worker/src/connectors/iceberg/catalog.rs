@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use aws_sdk_glue::{
+    model::{StorageDescriptor, TableInput},
+    types::SdkError,
+    Client as GlueClient, Region,
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::metadata::{Snapshot, TableMetadata, MAIN_BRANCH};
+use super::{read_file, write_file, Catalog as CatalogConfig, IcebergConfig, IcebergTable};
+
+/// A table as loaded from the catalog
+#[derive(Debug, Clone)]
+pub struct LoadedTable {
+    pub metadata_location: String,
+    pub metadata: TableMetadata,
+    /// Catalog-specific version of the table entry, used for optimistic concurrency
+    version: Option<String>,
+}
+
+/// The subset of an Iceberg catalog's operations needed to append to a table
+#[async_trait]
+pub trait Catalog: Send + Sync {
+    async fn load_table(&self) -> Result<Option<LoadedTable>>;
+
+    async fn create_table(&self, metadata: TableMetadata) -> Result<LoadedTable>;
+
+    /// Atomically adds `snapshot` to the head of the main branch of the table, returning false
+    /// if the table was modified since `base` was loaded
+    async fn commit(
+        &self,
+        base: &LoadedTable,
+        snapshot: Snapshot,
+        properties: HashMap<String, String>,
+    ) -> Result<bool>;
+}
+
+pub async fn catalog_for_config(
+    config: &IcebergConfig,
+    table: &IcebergTable,
+) -> Result<Box<dyn Catalog>> {
+    Ok(match &config.catalog {
+        CatalogConfig::Rest {
+            url,
+            warehouse,
+            token,
+        } => Box::new(RestCatalog::new(url, warehouse.as_deref(), token.as_deref(), table).await?),
+        CatalogConfig::Glue { region, catalog_id } => {
+            Box::new(GlueCatalog::new(region.as_deref(), catalog_id.clone(), table).await)
+        }
+    })
+}
+
+/// A catalog implementing the Iceberg REST catalog protocol
+pub struct RestCatalog {
+    client: reqwest::Client,
+    base_url: String,
+    namespace: Vec<String>,
+    table_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct LoadTableResult {
+    metadata_location: Option<String>,
+    metadata: TableMetadata,
+}
+
+impl From<LoadTableResult> for LoadedTable {
+    fn from(result: LoadTableResult) -> Self {
+        LoadedTable {
+            metadata_location: result.metadata_location.unwrap_or_default(),
+            metadata: result.metadata,
+            version: None,
+        }
+    }
+}
+
+fn encode(s: &str) -> String {
+    url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
+}
+
+impl RestCatalog {
+    async fn new(
+        url: &str,
+        warehouse: Option<&str>,
+        token: Option<&str>,
+        table: &IcebergTable,
+    ) -> Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(token) = token {
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                format!("Bearer {}", token).parse()?,
+            );
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        let url = url.trim_end_matches('/');
+
+        // the catalog may tell us to use a prefix for all of its routes (generally identifying
+        // the warehouse)
+        let mut request = client.get(format!("{}/v1/config", url));
+        if let Some(warehouse) = warehouse {
+            request = request.query(&[("warehouse", warehouse)]);
+        }
+        let config: Value = request.send().await?.error_for_status()?.json().await?;
+        let prefix = ["overrides", "defaults"]
+            .iter()
+            .find_map(|k| config.get(k)?.get("prefix")?.as_str());
+
+        let base_url = match prefix {
+            Some(prefix) => format!("{}/v1/{}", url, prefix),
+            None => format!("{}/v1", url),
+        };
+
+        Ok(Self {
+            client,
+            base_url,
+            namespace: table.namespace_levels(),
+            table_name: table.table_name.clone(),
+        })
+    }
+
+    fn tables_url(&self) -> String {
+        format!(
+            "{}/namespaces/{}/tables",
+            self.base_url,
+            encode(&self.namespace.join("\u{1f}"))
+        )
+    }
+
+    fn table_url(&self) -> String {
+        format!("{}/{}", self.tables_url(), encode(&self.table_name))
+    }
+}
+
+async fn error_body(response: reqwest::Response) -> String {
+    let status = response.status();
+    format!(
+        "{}: {}",
+        status,
+        response.text().await.unwrap_or_else(|e| e.to_string())
+    )
+}
+
+#[async_trait]
+impl Catalog for RestCatalog {
+    async fn load_table(&self) -> Result<Option<LoadedTable>> {
+        let response = self.client.get(self.table_url()).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => Ok(Some(response.json::<LoadTableResult>().await?.into())),
+            _ => bail!("failed to load table: {}", error_body(response).await),
+        }
+    }
+
+    async fn create_table(&self, metadata: TableMetadata) -> Result<LoadedTable> {
+        let response = self
+            .client
+            .post(self.tables_url())
+            .json(&json!({
+                "name": self.table_name,
+                "location": metadata.location,
+                "schema": metadata.current_schema()?,
+                "partition-spec": metadata.default_spec()?,
+                "properties": metadata.properties,
+            }))
+            .send()
+            .await?;
+
+        match response.status() {
+            // another subtask created the table first
+            StatusCode::CONFLICT => self
+                .load_table()
+                .await?
+                .ok_or_else(|| anyhow!("table was created concurrently but could not be loaded")),
+            s if s.is_success() => Ok(response.json::<LoadTableResult>().await?.into()),
+            _ => bail!("failed to create table: {}", error_body(response).await),
+        }
+    }
+
+    async fn commit(
+        &self,
+        base: &LoadedTable,
+        snapshot: Snapshot,
+        properties: HashMap<String, String>,
+    ) -> Result<bool> {
+        let current_snapshot_id = base.metadata.current_snapshot().map(|s| s.snapshot_id);
+        let snapshot_id = snapshot.snapshot_id;
+
+        let mut updates = vec![
+            json!({"action": "add-snapshot", "snapshot": snapshot}),
+            json!({
+                "action": "set-snapshot-ref",
+                "ref-name": MAIN_BRANCH,
+                "type": "branch",
+                "snapshot-id": snapshot_id,
+            }),
+        ];
+        if !properties.is_empty() {
+            updates.push(json!({"action": "set-properties", "updates": properties}));
+        }
+
+        let response = self
+            .client
+            .post(self.table_url())
+            .json(&json!({
+                "requirements": [
+                    {"type": "assert-table-uuid", "uuid": base.metadata.table_uuid},
+                    {"type": "assert-ref-snapshot-id", "ref": MAIN_BRANCH, "snapshot-id": current_snapshot_id},
+                ],
+                "updates": updates,
+            }))
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::CONFLICT => Ok(false),
+            s if s.is_success() => Ok(true),
+            _ => bail!("failed to commit to table: {}", error_body(response).await),
+        }
+    }
+}
+
+/// A catalog backed by the AWS Glue data catalog, which stores a pointer to the current
+/// metadata file of each table
+pub struct GlueCatalog {
+    client: GlueClient,
+    catalog_id: Option<String>,
+    database: String,
+    table_name: String,
+    storage_options: HashMap<String, String>,
+}
+
+const METADATA_LOCATION: &str = "metadata_location";
+const PREVIOUS_METADATA_LOCATION: &str = "previous_metadata_location";
+
+impl GlueCatalog {
+    async fn new(region: Option<&str>, catalog_id: Option<String>, table: &IcebergTable) -> Self {
+        let mut loader = aws_config::from_env();
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region.to_string()));
+        }
+
+        Self {
+            client: GlueClient::new(&loader.load().await),
+            catalog_id,
+            database: table.namespace.clone(),
+            table_name: table.table_name.clone(),
+            storage_options: table.storage_options.clone(),
+        }
+    }
+
+    /// Writes a new metadata file for the table, returning its location
+    async fn write_metadata(
+        &self,
+        metadata: &TableMetadata,
+        previous: Option<&str>,
+    ) -> Result<String> {
+        // metadata files are named {version}-{uuid}.metadata.json
+        let version = previous
+            .and_then(|p| p.rsplit('/').next())
+            .and_then(|name| name.split('-').next())
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|v| v + 1)
+            .unwrap_or(0);
+
+        let location = format!(
+            "{}/metadata/{:05}-{}.metadata.json",
+            metadata.location,
+            version,
+            uuid::Uuid::new_v4()
+        );
+
+        write_file(
+            &location,
+            serde_json::to_vec(metadata)?,
+            &self.storage_options,
+        )
+        .await?;
+
+        Ok(location)
+    }
+
+    fn table_input(
+        &self,
+        metadata: &TableMetadata,
+        location: &str,
+        previous: Option<&str>,
+    ) -> TableInput {
+        let mut parameters = HashMap::new();
+        parameters.insert("table_type".to_string(), "ICEBERG".to_string());
+        parameters.insert(METADATA_LOCATION.to_string(), location.to_string());
+        if let Some(previous) = previous {
+            parameters.insert(PREVIOUS_METADATA_LOCATION.to_string(), previous.to_string());
+        }
+
+        TableInput::builder()
+            .name(&self.table_name)
+            .table_type("EXTERNAL_TABLE")
+            .set_parameters(Some(parameters))
+            .storage_descriptor(
+                StorageDescriptor::builder()
+                    .location(&metadata.location)
+                    .build(),
+            )
+            .build()
+    }
+}
+
+#[async_trait]
+impl Catalog for GlueCatalog {
+    async fn load_table(&self) -> Result<Option<LoadedTable>> {
+        let result = self
+            .client
+            .get_table()
+            .set_catalog_id(self.catalog_id.clone())
+            .database_name(&self.database)
+            .name(&self.table_name)
+            .send()
+            .await;
+
+        let table = match result {
+            Ok(output) => output
+                .table()
+                .cloned()
+                .ok_or_else(|| anyhow!("Glue returned no table"))?,
+            Err(SdkError::ServiceError { err, .. }) if err.is_entity_not_found_exception() => {
+                return Ok(None);
+            }
+            Err(e) => bail!("failed to load table from Glue: {}", e),
+        };
+
+        let metadata_location = table
+            .parameters()
+            .and_then(|p| p.get(METADATA_LOCATION))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Glue table {}.{} is not an Iceberg table",
+                    self.database,
+                    self.table_name
+                )
+            })?
+            .clone();
+
+        let metadata =
+            serde_json::from_slice(&read_file(&metadata_location, &self.storage_options).await?)?;
+
+        Ok(Some(LoadedTable {
+            metadata_location,
+            metadata,
+            version: table.version_id().map(|v| v.to_string()),
+        }))
+    }
+
+    async fn create_table(&self, metadata: TableMetadata) -> Result<LoadedTable> {
+        let location = self.write_metadata(&metadata, None).await?;
+
+        let result = self
+            .client
+            .create_table()
+            .set_catalog_id(self.catalog_id.clone())
+            .database_name(&self.database)
+            .table_input(self.table_input(&metadata, &location, None))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => {}
+            // another subtask created the table first
+            Err(SdkError::ServiceError { err, .. }) if err.is_already_exists_exception() => {}
+            Err(e) => bail!("failed to create table in Glue: {}", e),
+        }
+
+        self.load_table()
+            .await?
+            .ok_or_else(|| anyhow!("table was created but could not be loaded"))
+    }
+
+    async fn commit(
+        &self,
+        base: &LoadedTable,
+        snapshot: Snapshot,
+        properties: HashMap<String, String>,
+    ) -> Result<bool> {
+        let metadata = base
+            .metadata
+            .with_snapshot(snapshot, &base.metadata_location, properties);
+        let location = self
+            .write_metadata(&metadata, Some(&base.metadata_location))
+            .await?;
+
+        // the version id makes the update conditional on the table not having changed since
+        // we loaded it
+        let result = self
+            .client
+            .update_table()
+            .set_catalog_id(self.catalog_id.clone())
+            .database_name(&self.database)
+            .table_input(self.table_input(&metadata, &location, Some(&base.metadata_location)))
+            .set_version_id(base.version.clone())
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => {
+                info!(
+                    "updated Glue table {}.{} to {}",
+                    self.database, self.table_name, location
+                );
+                Ok(true)
+            }
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_concurrent_modification_exception() =>
+            {
+                Ok(false)
+            }
+            Err(e) => bail!("failed to update table in Glue: {}", e),
+        }
+    }
+}
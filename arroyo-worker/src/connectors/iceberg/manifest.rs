@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use apache_avro::{types::Value as AvroValue, Reader, Schema as AvroSchema, Writer};
+use serde_json::{json, Value};
+
+use super::metadata::{PartitionSpec, Schema, Type};
+
+/// Manifest entry status for files added in the snapshot that wrote the manifest
+pub const STATUS_ADDED: i32 = 1;
+/// Manifest entry status for files removed from the table in the snapshot that wrote the manifest
+pub const STATUS_DELETED: i32 = 2;
+/// The `content` of manifests and data files that hold data (as opposed to deletes)
+pub const CONTENT_DATA: i32 = 0;
+
+/// A data file to be added to the table
+#[derive(Debug, Clone)]
+pub struct DataFile {
+    pub file_path: String,
+    pub file_size_in_bytes: i64,
+    pub record_count: i64,
+    /// The JSON-encoded value of each partition field, in spec order
+    pub partition: Vec<Option<Value>>,
+}
+
+/// An entry in a manifest, as read from an existing table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub status: i32,
+    /// The snapshot that added or removed the file; in v2 manifests this is inherited from the
+    /// manifest when null
+    pub snapshot_id: Option<i64>,
+    pub content: i32,
+    pub file_path: String,
+    pub file_format: String,
+    pub record_count: i64,
+    /// Lower bounds of each column (by field id), in Iceberg's single-value binary serialization
+    pub lower_bounds: HashMap<i32, Vec<u8>>,
+}
+
+/// An entry in a manifest list
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestFile {
+    pub manifest_path: String,
+    pub manifest_length: i64,
+    pub partition_spec_id: i32,
+    pub content: i32,
+    pub sequence_number: i64,
+    pub min_sequence_number: i64,
+    pub added_snapshot_id: i64,
+    pub added_files_count: i32,
+    pub existing_files_count: i32,
+    pub deleted_files_count: i32,
+    pub added_rows_count: i64,
+    pub existing_rows_count: i64,
+    pub deleted_rows_count: i64,
+}
+
+fn manifest_list_schema() -> AvroSchema {
+    AvroSchema::parse(&json!({
+        "type": "record",
+        "name": "manifest_file",
+        "fields": [
+            {"name": "manifest_path", "type": "string", "field-id": 500},
+            {"name": "manifest_length", "type": "long", "field-id": 501},
+            {"name": "partition_spec_id", "type": "int", "field-id": 502},
+            {"name": "content", "type": "int", "field-id": 517},
+            {"name": "sequence_number", "type": "long", "field-id": 515},
+            {"name": "min_sequence_number", "type": "long", "field-id": 516},
+            {"name": "added_snapshot_id", "type": "long", "field-id": 503},
+            {"name": "added_files_count", "type": "int", "field-id": 504},
+            {"name": "existing_files_count", "type": "int", "field-id": 505},
+            {"name": "deleted_files_count", "type": "int", "field-id": 506},
+            {"name": "added_rows_count", "type": "long", "field-id": 512},
+            {"name": "existing_rows_count", "type": "long", "field-id": 513},
+            {"name": "deleted_rows_count", "type": "long", "field-id": 514},
+        ]
+    }))
+    .expect("manifest list schema is valid")
+}
+
+/// The Avro type of a partition value for an identity partition of a column of type `typ`
+fn partition_avro_type(typ: &Type) -> Result<&'static str> {
+    Ok(match typ {
+        Type::Primitive(p) => match p.as_str() {
+            "boolean" => "boolean",
+            "int" => "int",
+            "long" => "long",
+            "float" => "float",
+            "double" => "double",
+            "string" => "string",
+            other => bail!(
+                "partitioning by a column of type {} is not supported",
+                other
+            ),
+        },
+        other => bail!(
+            "partitioning by a column of type {:?} is not supported",
+            other
+        ),
+    })
+}
+
+fn manifest_schema(schema: &Schema, spec: &PartitionSpec) -> Result<AvroSchema> {
+    let partition_fields = spec
+        .fields
+        .iter()
+        .map(|f| {
+            let source = schema.field_by_id(f.source_id).ok_or_else(|| {
+                anyhow!(
+                    "partition source column {} is not in the schema",
+                    f.source_id
+                )
+            })?;
+            Ok(json!({
+                "name": f.name,
+                "type": ["null", partition_avro_type(&source.typ)?],
+                "default": null,
+                "field-id": f.field_id,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(AvroSchema::parse(&json!({
+        "type": "record",
+        "name": "manifest_entry",
+        "fields": [
+            {"name": "status", "type": "int", "field-id": 0},
+            {"name": "snapshot_id", "type": ["null", "long"], "default": null, "field-id": 1},
+            {"name": "sequence_number", "type": ["null", "long"], "default": null, "field-id": 3},
+            {"name": "file_sequence_number", "type": ["null", "long"], "default": null, "field-id": 4},
+            {"name": "data_file", "field-id": 2, "type": {
+                "type": "record",
+                "name": "r2",
+                "fields": [
+                    {"name": "content", "type": "int", "field-id": 134},
+                    {"name": "file_path", "type": "string", "field-id": 100},
+                    {"name": "file_format", "type": "string", "field-id": 101},
+                    {"name": "partition", "field-id": 102, "type": {
+                        "type": "record",
+                        "name": "r102",
+                        "fields": partition_fields,
+                    }},
+                    {"name": "record_count", "type": "long", "field-id": 103},
+                    {"name": "file_size_in_bytes", "type": "long", "field-id": 104},
+                ]
+            }},
+        ]
+    }))?)
+}
+
+fn partition_value(value: &Option<Value>, avro_type: &str) -> Result<AvroValue> {
+    let Some(value) = value.as_ref().filter(|v| !v.is_null()) else {
+        return Ok(AvroValue::Union(0, Box::new(AvroValue::Null)));
+    };
+
+    let invalid = || anyhow!("invalid {} partition value {}", avro_type, value);
+    let v = match avro_type {
+        "boolean" => AvroValue::Boolean(value.as_bool().ok_or_else(invalid)?),
+        "int" => AvroValue::Int(value.as_i64().ok_or_else(invalid)? as i32),
+        "long" => AvroValue::Long(value.as_i64().ok_or_else(invalid)?),
+        "float" => AvroValue::Float(value.as_f64().ok_or_else(invalid)? as f32),
+        "double" => AvroValue::Double(value.as_f64().ok_or_else(invalid)?),
+        _ => AvroValue::String(match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }),
+    };
+
+    Ok(AvroValue::Union(1, Box::new(v)))
+}
+
+fn record(fields: Vec<(&str, AvroValue)>) -> AvroValue {
+    AvroValue::Record(
+        fields
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+/// Writes a manifest adding `files` to the table in snapshot `snapshot_id`
+pub fn write_manifest(
+    schema: &Schema,
+    spec: &PartitionSpec,
+    snapshot_id: i64,
+    files: &[DataFile],
+) -> Result<Vec<u8>> {
+    let avro_schema = manifest_schema(schema, spec)?;
+    let partition_types = spec
+        .fields
+        .iter()
+        .map(|f| partition_avro_type(&schema.field_by_id(f.source_id).unwrap().typ))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = Writer::new(&avro_schema, Vec::new());
+    writer.add_user_metadata("schema".to_string(), serde_json::to_string(schema)?)?;
+    writer.add_user_metadata("schema-id".to_string(), schema.schema_id.to_string())?;
+    writer.add_user_metadata(
+        "partition-spec".to_string(),
+        serde_json::to_string(&spec.fields)?,
+    )?;
+    writer.add_user_metadata("partition-spec-id".to_string(), spec.spec_id.to_string())?;
+    writer.add_user_metadata("format-version".to_string(), "2")?;
+    writer.add_user_metadata("content".to_string(), "data")?;
+
+    for file in files {
+        if file.partition.len() != spec.fields.len() {
+            bail!(
+                "file {} has {} partition values, but the partition spec has {} fields",
+                file.file_path,
+                file.partition.len(),
+                spec.fields.len()
+            );
+        }
+
+        let partition = spec
+            .fields
+            .iter()
+            .zip(file.partition.iter().zip(&partition_types))
+            .map(|(f, (value, typ))| Ok((f.name.as_str(), partition_value(value, typ)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        writer.append(record(vec![
+            ("status", AvroValue::Int(STATUS_ADDED)),
+            (
+                "snapshot_id",
+                AvroValue::Union(1, Box::new(AvroValue::Long(snapshot_id))),
+            ),
+            // null sequence numbers are inherited from the manifest list
+            (
+                "sequence_number",
+                AvroValue::Union(0, Box::new(AvroValue::Null)),
+            ),
+            (
+                "file_sequence_number",
+                AvroValue::Union(0, Box::new(AvroValue::Null)),
+            ),
+            (
+                "data_file",
+                record(vec![
+                    ("content", AvroValue::Int(CONTENT_DATA)),
+                    ("file_path", AvroValue::String(file.file_path.clone())),
+                    ("file_format", AvroValue::String("PARQUET".to_string())),
+                    ("partition", record(partition)),
+                    ("record_count", AvroValue::Long(file.record_count)),
+                    (
+                        "file_size_in_bytes",
+                        AvroValue::Long(file.file_size_in_bytes),
+                    ),
+                ]),
+            ),
+        ]))?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Writes the manifest list for a snapshot
+pub fn write_manifest_list(
+    snapshot_id: i64,
+    parent_snapshot_id: Option<i64>,
+    sequence_number: i64,
+    manifests: &[ManifestFile],
+) -> Result<Vec<u8>> {
+    let schema = manifest_list_schema();
+    let mut writer = Writer::new(&schema, Vec::new());
+    writer.add_user_metadata("snapshot-id".to_string(), snapshot_id.to_string())?;
+    writer.add_user_metadata(
+        "parent-snapshot-id".to_string(),
+        parent_snapshot_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "null".to_string()),
+    )?;
+    writer.add_user_metadata("sequence-number".to_string(), sequence_number.to_string())?;
+    writer.add_user_metadata("format-version".to_string(), "2")?;
+
+    for m in manifests {
+        writer.append(record(vec![
+            ("manifest_path", AvroValue::String(m.manifest_path.clone())),
+            ("manifest_length", AvroValue::Long(m.manifest_length)),
+            ("partition_spec_id", AvroValue::Int(m.partition_spec_id)),
+            ("content", AvroValue::Int(m.content)),
+            ("sequence_number", AvroValue::Long(m.sequence_number)),
+            (
+                "min_sequence_number",
+                AvroValue::Long(m.min_sequence_number),
+            ),
+            ("added_snapshot_id", AvroValue::Long(m.added_snapshot_id)),
+            ("added_files_count", AvroValue::Int(m.added_files_count)),
+            (
+                "existing_files_count",
+                AvroValue::Int(m.existing_files_count),
+            ),
+            ("deleted_files_count", AvroValue::Int(m.deleted_files_count)),
+            ("added_rows_count", AvroValue::Long(m.added_rows_count)),
+            (
+                "existing_rows_count",
+                AvroValue::Long(m.existing_rows_count),
+            ),
+            ("deleted_rows_count", AvroValue::Long(m.deleted_rows_count)),
+        ]))?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Unwraps unions, so that optional fields can be read the same as required ones
+fn unwrap_union(value: &AvroValue) -> &AvroValue {
+    match value {
+        AvroValue::Union(_, inner) => inner,
+        other => other,
+    }
+}
+
+fn record_fields(value: &AvroValue) -> Result<HashMap<&str, &AvroValue>> {
+    match unwrap_union(value) {
+        AvroValue::Record(fields) => Ok(fields
+            .iter()
+            .map(|(k, v)| (k.as_str(), unwrap_union(v)))
+            .collect()),
+        other => bail!("expected an avro record, found {:?}", other),
+    }
+}
+
+fn get_long(fields: &HashMap<&str, &AvroValue>, name: &str) -> Option<i64> {
+    match fields.get(name)? {
+        AvroValue::Long(l) => Some(*l),
+        AvroValue::Int(i) => Some(*i as i64),
+        _ => None,
+    }
+}
+
+fn get_string(fields: &HashMap<&str, &AvroValue>, name: &str) -> Option<String> {
+    match fields.get(name)? {
+        AvroValue::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Reads the entries of a manifest list; this supports lists written by v1 tables as well,
+/// where the counts are optional and there are no sequence numbers
+pub fn read_manifest_list(bytes: &[u8]) -> Result<Vec<ManifestFile>> {
+    Reader::new(bytes)?
+        .map(|value| {
+            let fields = record_fields(&value?)?;
+            let long = |name: &str| get_long(&fields, name).unwrap_or(0);
+            Ok(ManifestFile {
+                manifest_path: get_string(&fields, "manifest_path")
+                    .ok_or_else(|| anyhow!("manifest list entry is missing manifest_path"))?,
+                manifest_length: long("manifest_length"),
+                partition_spec_id: long("partition_spec_id") as i32,
+                content: long("content") as i32,
+                sequence_number: long("sequence_number"),
+                min_sequence_number: long("min_sequence_number"),
+                added_snapshot_id: long("added_snapshot_id"),
+                added_files_count: long("added_files_count") as i32,
+                existing_files_count: long("existing_files_count") as i32,
+                deleted_files_count: long("deleted_files_count") as i32,
+                added_rows_count: long("added_rows_count"),
+                existing_rows_count: long("existing_rows_count"),
+                deleted_rows_count: long("deleted_rows_count"),
+            })
+        })
+        .collect()
+}
+
+fn get_bounds(fields: &HashMap<&str, &AvroValue>, name: &str) -> Result<HashMap<i32, Vec<u8>>> {
+    let Some(AvroValue::Array(entries)) = fields.get(name) else {
+        return Ok(HashMap::new());
+    };
+
+    // Avro maps must have string keys, so Iceberg stores these as arrays of key/value records
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = record_fields(entry)?;
+            let key = get_long(&entry, "key")
+                .ok_or_else(|| anyhow!("{} entry is missing its key", name))?;
+            let value = match entry.get("value") {
+                Some(AvroValue::Bytes(b)) => b.clone(),
+                _ => bail!("{} entry is missing its value", name),
+            };
+            Ok((key as i32, value))
+        })
+        .collect()
+}
+
+/// Reads the entries of a manifest
+pub fn read_manifest(bytes: &[u8]) -> Result<Vec<ManifestEntry>> {
+    Reader::new(bytes)?
+        .map(|value| {
+            let fields = record_fields(&value?)?;
+            let data_file = record_fields(
+                fields
+                    .get("data_file")
+                    .ok_or_else(|| anyhow!("manifest entry is missing data_file"))?,
+            )?;
+
+            Ok(ManifestEntry {
+                status: get_long(&fields, "status").unwrap_or(0) as i32,
+                snapshot_id: get_long(&fields, "snapshot_id"),
+                content: get_long(&data_file, "content").unwrap_or(0) as i32,
+                file_path: get_string(&data_file, "file_path")
+                    .ok_or_else(|| anyhow!("manifest entry is missing file_path"))?,
+                file_format: get_string(&data_file, "file_format").unwrap_or_default(),
+                record_count: get_long(&data_file, "record_count").unwrap_or(0),
+                lower_bounds: get_bounds(&data_file, "lower_bounds")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_manifest, read_manifest_list, write_manifest, write_manifest_list, DataFile,
+        ManifestFile, STATUS_ADDED,
+    };
+    use crate::connectors::iceberg::metadata::{identity_spec, NestedField, Schema, Type};
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let schema = Schema {
+            typ: "struct".to_string(),
+            schema_id: 0,
+            identifier_field_ids: None,
+            fields: vec![
+                NestedField {
+                    id: 1,
+                    name: "id".to_string(),
+                    required: true,
+                    typ: Type::Primitive("long".to_string()),
+                    doc: None,
+                },
+                NestedField {
+                    id: 2,
+                    name: "category".to_string(),
+                    required: false,
+                    typ: Type::Primitive("string".to_string()),
+                    doc: None,
+                },
+            ],
+        };
+        let spec = identity_spec(&schema, &["category".to_string()]).unwrap();
+
+        let manifest = write_manifest(
+            &schema,
+            &spec,
+            10,
+            &[DataFile {
+                file_path: "s3://bucket/table/data/category=\"a\"/00001-000.parquet".to_string(),
+                file_size_in_bytes: 100,
+                record_count: 5,
+                partition: vec![Some(serde_json::json!("a"))],
+            }],
+        )
+        .unwrap();
+        let entries = read_manifest(&manifest).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status, STATUS_ADDED);
+        assert_eq!(entries[0].snapshot_id, Some(10));
+        assert_eq!(entries[0].record_count, 5);
+        assert_eq!(entries[0].file_format, "PARQUET");
+
+        let manifests = vec![ManifestFile {
+            manifest_path: "s3://bucket/table/metadata/m0.avro".to_string(),
+            manifest_length: manifest.len() as i64,
+            partition_spec_id: 0,
+            content: 0,
+            sequence_number: 3,
+            min_sequence_number: 3,
+            added_snapshot_id: 10,
+            added_files_count: 1,
+            existing_files_count: 0,
+            deleted_files_count: 0,
+            added_rows_count: 5,
+            existing_rows_count: 0,
+            deleted_rows_count: 0,
+        }];
+
+        let list = write_manifest_list(10, None, 3, &manifests).unwrap();
+        assert_eq!(read_manifest_list(&list).unwrap(), manifests);
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, TimeUnit};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+pub const MAIN_BRANCH: &str = "main";
+
+/// Iceberg table metadata, as stored in the metadata JSON files. Only the parts that are needed
+/// to append to the table are modeled; everything else is carried through unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TableMetadata {
+    pub format_version: i32,
+    pub table_uuid: String,
+    pub location: String,
+    #[serde(default)]
+    pub last_sequence_number: i64,
+    pub last_updated_ms: i64,
+    pub last_column_id: i32,
+    pub schemas: Vec<Schema>,
+    pub current_schema_id: i32,
+    pub partition_specs: Vec<PartitionSpec>,
+    pub default_spec_id: i32,
+    pub last_partition_id: i32,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_snapshot_id: Option<i64>,
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+    #[serde(default)]
+    pub snapshot_log: Vec<SnapshotLogEntry>,
+    #[serde(default)]
+    pub metadata_log: Vec<MetadataLogEntry>,
+    #[serde(default)]
+    pub refs: HashMap<String, SnapshotRef>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Schema {
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(default)]
+    pub schema_id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier_field_ids: Option<Vec<i32>>,
+    pub fields: Vec<NestedField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NestedField {
+    pub id: i32,
+    pub name: String,
+    pub required: bool,
+    #[serde(rename = "type")]
+    pub typ: Type,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Type {
+    Primitive(String),
+    Struct {
+        #[serde(rename = "type")]
+        typ: String,
+        fields: Vec<NestedField>,
+    },
+    List {
+        #[serde(rename = "type")]
+        typ: String,
+        #[serde(rename = "element-id")]
+        element_id: i32,
+        #[serde(rename = "element-required")]
+        element_required: bool,
+        element: Box<Type>,
+    },
+    Map {
+        #[serde(rename = "type")]
+        typ: String,
+        #[serde(rename = "key-id")]
+        key_id: i32,
+        key: Box<Type>,
+        #[serde(rename = "value-id")]
+        value_id: i32,
+        #[serde(rename = "value-required")]
+        value_required: bool,
+        value: Box<Type>,
+    },
+}
+
+impl PartialEq for NestedField {
+    // field ids are assigned by the table, so two fields are the same if they have the same
+    // name and type
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.required == other.required && self.typ == other.typ
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PartitionSpec {
+    pub spec_id: i32,
+    pub fields: Vec<PartitionField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PartitionField {
+    pub source_id: i32,
+    pub field_id: i32,
+    pub name: String,
+    pub transform: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Snapshot {
+    pub snapshot_id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_snapshot_id: Option<i64>,
+    #[serde(default)]
+    pub sequence_number: i64,
+    pub timestamp_ms: i64,
+    pub manifest_list: String,
+    pub summary: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnapshotLogEntry {
+    pub snapshot_id: i64,
+    pub timestamp_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MetadataLogEntry {
+    pub metadata_file: String,
+    pub timestamp_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SnapshotRef {
+    pub snapshot_id: i64,
+    #[serde(rename = "type")]
+    pub typ: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl TableMetadata {
+    /// Metadata for a new, empty table with the given schema, partitioned by identity
+    /// transforms of `partition_fields`
+    pub fn new(
+        location: &str,
+        schema: Schema,
+        partition_fields: &[String],
+        properties: HashMap<String, String>,
+        now_ms: i64,
+    ) -> Result<Self> {
+        let spec = identity_spec(&schema, partition_fields)?;
+        Ok(Self {
+            format_version: 2,
+            table_uuid: uuid::Uuid::new_v4().to_string(),
+            location: location.trim_end_matches('/').to_string(),
+            last_sequence_number: 0,
+            last_updated_ms: now_ms,
+            last_column_id: schema.max_field_id(),
+            schemas: vec![schema],
+            current_schema_id: 0,
+            last_partition_id: spec.fields.iter().map(|f| f.field_id).max().unwrap_or(999),
+            partition_specs: vec![spec],
+            default_spec_id: 0,
+            properties,
+            current_snapshot_id: None,
+            snapshots: vec![],
+            snapshot_log: vec![],
+            metadata_log: vec![],
+            refs: HashMap::new(),
+            extra: json!({
+                "sort-orders": [{"order-id": 0, "fields": []}],
+                "default-sort-order-id": 0,
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        })
+    }
+
+    pub fn current_schema(&self) -> Result<&Schema> {
+        self.schemas
+            .iter()
+            .find(|s| s.schema_id == self.current_schema_id)
+            .ok_or_else(|| anyhow!("table metadata is missing its current schema"))
+    }
+
+    pub fn default_spec(&self) -> Result<&PartitionSpec> {
+        self.partition_specs
+            .iter()
+            .find(|s| s.spec_id == self.default_spec_id)
+            .ok_or_else(|| anyhow!("table metadata is missing its default partition spec"))
+    }
+
+    /// The snapshot at the head of the main branch
+    pub fn current_snapshot(&self) -> Option<&Snapshot> {
+        let id = match self.refs.get(MAIN_BRANCH) {
+            Some(r) => r.snapshot_id,
+            // v1 tables use -1 for "no snapshot"
+            None => self.current_snapshot_id.filter(|id| *id >= 0)?,
+        };
+
+        self.snapshots.iter().find(|s| s.snapshot_id == id)
+    }
+
+    pub fn snapshot(&self, id: i64) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|s| s.snapshot_id == id)
+    }
+
+    /// Returns a copy of this metadata with `snapshot` appended to the main branch
+    pub fn with_snapshot(
+        &self,
+        snapshot: Snapshot,
+        previous_metadata_location: &str,
+        properties: HashMap<String, String>,
+    ) -> TableMetadata {
+        let mut updated = self.clone();
+        updated.last_sequence_number = snapshot.sequence_number;
+        updated.last_updated_ms = snapshot.timestamp_ms;
+        updated.current_snapshot_id = Some(snapshot.snapshot_id);
+        updated.snapshot_log.push(SnapshotLogEntry {
+            snapshot_id: snapshot.snapshot_id,
+            timestamp_ms: snapshot.timestamp_ms,
+        });
+        updated.metadata_log.push(MetadataLogEntry {
+            metadata_file: previous_metadata_location.to_string(),
+            timestamp_ms: self.last_updated_ms,
+        });
+        updated.refs.insert(
+            MAIN_BRANCH.to_string(),
+            SnapshotRef {
+                snapshot_id: snapshot.snapshot_id,
+                typ: "branch".to_string(),
+                extra: Map::new(),
+            },
+        );
+        updated.properties.extend(properties);
+        updated.snapshots.push(snapshot);
+        updated
+    }
+}
+
+impl Schema {
+    pub fn max_field_id(&self) -> i32 {
+        fn max_id(typ: &Type) -> i32 {
+            match typ {
+                Type::Primitive(_) => 0,
+                Type::Struct { fields, .. } => fields
+                    .iter()
+                    .map(|f| f.id.max(max_id(&f.typ)))
+                    .max()
+                    .unwrap_or(0),
+                Type::List {
+                    element_id,
+                    element,
+                    ..
+                } => (*element_id).max(max_id(element)),
+                Type::Map {
+                    key_id,
+                    key,
+                    value_id,
+                    value,
+                    ..
+                } => (*key_id).max(*value_id).max(max_id(key)).max(max_id(value)),
+            }
+        }
+
+        self.fields
+            .iter()
+            .map(|f| f.id.max(max_id(&f.typ)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn field_by_name(&self, name: &str) -> Option<&NestedField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn field_by_id(&self, id: i32) -> Option<&NestedField> {
+        self.fields.iter().find(|f| f.id == id)
+    }
+
+    /// Converts an Arrow schema into an Iceberg schema, assigning field ids in order
+    pub fn from_arrow(schema: &ArrowSchema) -> Result<Self> {
+        let mut next_id = 0;
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|f| nested_field(f, &mut next_id))
+            .collect::<Result<_>>()?;
+
+        Ok(Schema {
+            typ: "struct".to_string(),
+            schema_id: 0,
+            identifier_field_ids: None,
+            fields,
+        })
+    }
+
+    /// The default name mapping for this schema, which lets readers map the columns of data
+    /// files that don't carry Iceberg field ids (like the ones we write) by name
+    pub fn name_mapping(&self) -> Value {
+        fn mapping(fields: &[NestedField]) -> Value {
+            fields
+                .iter()
+                .map(|f| {
+                    let mut m = json!({"field-id": f.id, "names": [f.name]});
+                    if let Type::Struct { fields, .. } = &f.typ {
+                        m["fields"] = mapping(fields);
+                    }
+                    m
+                })
+                .collect()
+        }
+
+        mapping(&self.fields)
+    }
+
+    /// Checks that data with this schema can be appended to a table with `table_schema`
+    pub fn check_compatible(&self, table_schema: &Schema) -> Result<()> {
+        for field in &self.fields {
+            let Some(existing) = table_schema.field_by_name(&field.name) else {
+                bail!("column '{}' does not exist in the table", field.name);
+            };
+
+            if !types_compatible(&field.typ, &existing.typ) {
+                bail!(
+                    "column '{}' has type {:?} in the table, but {:?} is being written",
+                    field.name,
+                    existing.typ,
+                    field.typ
+                );
+            }
+
+            if existing.required && !field.required {
+                bail!(
+                    "column '{}' is required in the table, but is nullable in the stream",
+                    field.name
+                );
+            }
+        }
+
+        for field in &table_schema.fields {
+            if field.required && self.field_by_name(&field.name).is_none() {
+                bail!(
+                    "required column '{}' is missing from the stream schema",
+                    field.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn types_compatible(ours: &Type, theirs: &Type) -> bool {
+    match (ours, theirs) {
+        (Type::Primitive(a), Type::Primitive(b)) => {
+            a == b
+                || matches!(
+                    (a.as_str(), b.as_str()),
+                    ("int", "long") | ("float", "double")
+                )
+                || (a == "timestamp" && b == "timestamptz")
+        }
+        (Type::Struct { fields: a, .. }, Type::Struct { fields: b, .. }) => a.iter().all(|f| {
+            b.iter()
+                .any(|g| g.name == f.name && types_compatible(&f.typ, &g.typ))
+        }),
+        (Type::List { element: a, .. }, Type::List { element: b, .. }) => types_compatible(a, b),
+        (
+            Type::Map {
+                key: ak, value: av, ..
+            },
+            Type::Map {
+                key: bk, value: bv, ..
+            },
+        ) => types_compatible(ak, bk) && types_compatible(av, bv),
+        _ => false,
+    }
+}
+
+fn nested_field(field: &Field, next_id: &mut i32) -> Result<NestedField> {
+    *next_id += 1;
+    let id = *next_id;
+    Ok(NestedField {
+        id,
+        name: field.name().clone(),
+        required: !field.is_nullable(),
+        typ: iceberg_type(field.data_type(), next_id)
+            .map_err(|e| anyhow!("column '{}': {}", field.name(), e))?,
+        doc: None,
+    })
+}
+
+fn iceberg_type(data_type: &DataType, next_id: &mut i32) -> Result<Type> {
+    let primitive = match data_type {
+        DataType::Boolean => "boolean".to_string(),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            "int".to_string()
+        }
+        DataType::Int64 | DataType::UInt32 => "long".to_string(),
+        DataType::Float16 | DataType::Float32 => "float".to_string(),
+        DataType::Float64 => "double".to_string(),
+        DataType::Utf8 | DataType::LargeUtf8 => "string".to_string(),
+        DataType::Binary | DataType::LargeBinary => "binary".to_string(),
+        DataType::FixedSizeBinary(size) => format!("fixed[{}]", size),
+        DataType::Date32 | DataType::Date64 => "date".to_string(),
+        DataType::Time32(_) | DataType::Time64(_) => "time".to_string(),
+        DataType::Timestamp(_, Some(_)) => "timestamptz".to_string(),
+        DataType::Timestamp(
+            TimeUnit::Second | TimeUnit::Millisecond | TimeUnit::Microsecond,
+            _,
+        )
+        | DataType::Timestamp(TimeUnit::Nanosecond, None) => "timestamp".to_string(),
+        DataType::Decimal128(precision, scale) => format!("decimal({}, {})", precision, scale),
+        DataType::Struct(fields) => {
+            return Ok(Type::Struct {
+                typ: "struct".to_string(),
+                fields: fields
+                    .iter()
+                    .map(|f| nested_field(f, next_id))
+                    .collect::<Result<_>>()?,
+            });
+        }
+        DataType::List(element) | DataType::LargeList(element) => {
+            *next_id += 1;
+            let element_id = *next_id;
+            return Ok(Type::List {
+                typ: "list".to_string(),
+                element_id,
+                element_required: !element.is_nullable(),
+                element: Box::new(iceberg_type(element.data_type(), next_id)?),
+            });
+        }
+        other => bail!("type {:?} is not supported by Iceberg", other),
+    };
+
+    Ok(Type::Primitive(primitive))
+}
+
+/// Builds a partition spec of identity transforms over the named columns
+pub fn identity_spec(schema: &Schema, partition_fields: &[String]) -> Result<PartitionSpec> {
+    let fields = partition_fields
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let field = schema
+                .field_by_name(name)
+                .ok_or_else(|| anyhow!("partition field '{}' is not in the schema", name))?;
+            Ok(PartitionField {
+                source_id: field.id,
+                field_id: 1000 + i as i32,
+                name: name.clone(),
+                transform: "identity".to_string(),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(PartitionSpec { spec_id: 0, fields })
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, TimeUnit};
+
+    use super::{Schema, Type};
+
+    #[test]
+    fn test_from_arrow() {
+        let arrow = ArrowSchema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new(
+                "tags",
+                DataType::List(Field::new("item", DataType::Utf8, true).into()),
+                true,
+            ),
+            Field::new(
+                "time",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+        ]);
+
+        let schema = Schema::from_arrow(&arrow).unwrap();
+        assert_eq!(schema.fields[0].id, 1);
+        assert_eq!(schema.fields[0].typ, Type::Primitive("long".to_string()));
+        assert!(schema.fields[0].required);
+        assert_eq!(schema.fields[1].id, 2);
+        let Type::List { element_id, .. } = &schema.fields[1].typ else {
+            panic!("expected a list");
+        };
+        assert_eq!(*element_id, 3);
+        assert_eq!(schema.fields[2].id, 4);
+        assert_eq!(schema.max_field_id(), 4);
+
+        let table_schema = Schema::from_arrow(&ArrowSchema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new(
+                "tags",
+                DataType::List(Field::new("item", DataType::Utf8, true).into()),
+                true,
+            ),
+            Field::new(
+                "time",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                true,
+            ),
+            Field::new("extra", DataType::Utf8, true),
+        ]))
+        .unwrap();
+        schema.check_compatible(&table_schema).unwrap();
+        assert!(table_schema.check_compatible(&schema).is_err());
+    }
+}
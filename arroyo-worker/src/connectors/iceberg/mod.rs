@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use arroyo_storage::StorageProvider;
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+mod catalog;
+mod manifest;
+mod metadata;
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/iceberg/connection.json");
+import_types!(schema = "../connector-schemas/iceberg/table.json");
+
+impl IcebergTable {
+    /// The levels of the table's namespace
+    pub fn namespace_levels(&self) -> Vec<String> {
+        self.namespace.split('.').map(|s| s.to_string()).collect()
+    }
+}
+
+/// Writes a file to an absolute URL, like `s3://bucket/table/metadata/v1.metadata.json`
+async fn write_file(url: &str, bytes: Vec<u8>, options: &HashMap<String, String>) -> Result<()> {
+    let (dir, _) = url
+        .rsplit_once('/')
+        .ok_or_else(|| anyhow::anyhow!("invalid file url {}", url))?;
+    let provider = StorageProvider::for_url_with_options(dir, options.clone()).await?;
+    provider.put(StorageProvider::get_key(url)?, bytes).await?;
+    Ok(())
+}
+
+async fn read_file(url: &str, options: &HashMap<String, String>) -> Result<Vec<u8>> {
+    Ok(StorageProvider::get_url_with_options(url, options.clone())
+        .await?
+        .to_vec())
+}
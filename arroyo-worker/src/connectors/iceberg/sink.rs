@@ -0,0 +1,428 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use arrow::datatypes::SchemaRef;
+use arroyo_rpc::OperatorConfig;
+use arroyo_storage::StorageProvider;
+use arroyo_types::{to_millis, Data, Key, RecordBatchBuilder};
+use object_store::path::Path;
+use parquet::file::footer::{decode_footer, decode_metadata};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::catalog::{catalog_for_config, Catalog, LoadedTable};
+use super::manifest::{
+    read_manifest_list, write_manifest, write_manifest_list, DataFile, ManifestFile,
+};
+use super::metadata::{identity_spec, Schema, Snapshot, TableMetadata};
+use super::{read_file, write_file, IcebergConfig, IcebergTable, TableType};
+use crate::connectors::filesystem::{
+    parse_partition_values, CommitStrategy, FileSettings, FileSystemTable, FinishedFile, FolderUrl,
+    FormatSettings, ParquetFileSystemSink, Partitioning,
+};
+use crate::connectors::two_phase_committer::TwoPhaseCommitterOperator;
+
+const MAX_COMMIT_ATTEMPTS: u32 = 10;
+
+/// Snapshot summary property recording which set of files a snapshot added, so that commits
+/// that are retried after a failure aren't applied twice
+const COMMIT_ID_PROPERTY: &str = "arroyo.commit-id";
+
+/// A sink that writes parquet files through the filesystem sink, and then adds them to an
+/// Iceberg table as a new snapshot when the checkpoint is committed.
+pub struct IcebergSink<K, T, R> {
+    _ts: PhantomData<(K, T, R)>,
+}
+
+impl<K: Key, T: Data + Sync + Serialize, R: RecordBatchBuilder<Data = T>> IcebergSink<K, T, R> {
+    pub fn from_config(
+        config_str: &str,
+    ) -> TwoPhaseCommitterOperator<K, T, ParquetFileSystemSink<K, T, R>> {
+        let config: OperatorConfig =
+            serde_json::from_str(config_str).expect("Invalid config for IcebergSink");
+        let connection: IcebergConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for IcebergSink");
+        let table: IcebergTable =
+            serde_json::from_value(config.table).expect("Invalid table config for IcebergSink");
+
+        let TableType::Sink {
+            location,
+            partition_fields,
+            rollover_seconds,
+            target_file_size,
+        } = &table.type_
+        else {
+            panic!("IcebergSink configured with a source table");
+        };
+
+        let file_system_table = FileSystemTable {
+            write_target: FolderUrl {
+                path: format!("{}/data", location.trim_end_matches('/')),
+                storage_options: table.storage_options.clone(),
+            },
+            format_settings: Some(FormatSettings::Parquet {
+                compression: None,
+                row_batch_size: None,
+                row_group_size: None,
+            }),
+            file_settings: Some(FileSettings {
+                inactivity_rollover_seconds: None,
+                max_parts: None,
+                rollover_seconds: *rollover_seconds,
+                target_file_size: *target_file_size,
+                target_part_size: None,
+                partitioning: (!partition_fields.is_empty()).then(|| Partitioning {
+                    time_partition_pattern: None,
+                    partition_fields: partition_fields.clone(),
+                }),
+            }),
+            delta_settings: None,
+        };
+
+        let committer = IcebergCommitter {
+            config: connection,
+            location: location.trim_end_matches('/').to_string(),
+            partition_fields: partition_fields.clone(),
+            table,
+            schema: R::default().schema(),
+            catalog: None,
+        };
+
+        ParquetFileSystemSink::<K, T, R>::create_and_start(
+            file_system_table,
+            CommitStrategy::Iceberg(committer),
+        )
+    }
+}
+
+/// Adds finished files to an Iceberg table
+pub struct IcebergCommitter {
+    config: IcebergConfig,
+    table: IcebergTable,
+    location: String,
+    partition_fields: Vec<String>,
+    schema: SchemaRef,
+    catalog: Option<Box<dyn Catalog>>,
+}
+
+impl IcebergCommitter {
+    pub async fn commit(
+        &mut self,
+        finished_files: Vec<FinishedFile>,
+        data_path: &Path,
+        storage_provider: Arc<StorageProvider>,
+    ) -> Result<()> {
+        if finished_files.is_empty() {
+            return Ok(());
+        }
+
+        if self.catalog.is_none() {
+            self.catalog = Some(catalog_for_config(&self.config, &self.table).await?);
+        }
+
+        let mut data_files = vec![];
+        for file in finished_files {
+            data_files.push(self.data_file(file, data_path, &storage_provider).await?);
+        }
+
+        let commit_id = commit_id(&data_files);
+        let schema = Schema::from_arrow(&self.schema)?;
+
+        for attempt in 1..=MAX_COMMIT_ATTEMPTS {
+            let base = self.load_or_create_table(&schema).await?;
+
+            if base
+                .metadata
+                .snapshots
+                .iter()
+                .any(|s| s.summary.get(COMMIT_ID_PROPERTY) == Some(&commit_id))
+            {
+                info!(
+                    "files for commit {} have already been added to {}.{}",
+                    commit_id, self.table.namespace, self.table.table_name
+                );
+                return Ok(());
+            }
+
+            if self.try_commit(&base, &data_files, &commit_id).await? {
+                return Ok(());
+            }
+
+            warn!(
+                "table {}.{} was concurrently modified, retrying commit (attempt {})",
+                self.table.namespace, self.table.table_name, attempt
+            );
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt.min(6)))).await;
+        }
+
+        bail!(
+            "failed to commit to {}.{} after {} attempts",
+            self.table.namespace,
+            self.table.table_name,
+            MAX_COMMIT_ATTEMPTS
+        )
+    }
+
+    async fn data_file(
+        &self,
+        file: FinishedFile,
+        data_path: &Path,
+        storage_provider: &StorageProvider,
+    ) -> Result<DataFile> {
+        let size = storage_provider.head(file.filename.clone()).await?.size;
+        let record_count = read_record_count(storage_provider, &file.filename, size).await?;
+
+        let partition = match &file.partition {
+            Some(partition) => parse_partition_values(partition, &self.partition_fields)?
+                .into_iter()
+                .map(|v| (!v.is_null()).then_some(v))
+                .collect(),
+            None => vec![],
+        };
+
+        let relative_path = file
+            .filename
+            .strip_prefix(&format!("{}/", data_path))
+            .ok_or_else(|| anyhow!("file {} is not in {}", file.filename, data_path))?;
+
+        Ok(DataFile {
+            file_path: format!("{}/data/{}", self.location, relative_path),
+            file_size_in_bytes: size as i64,
+            record_count,
+            partition,
+        })
+    }
+
+    async fn load_or_create_table(&self, schema: &Schema) -> Result<LoadedTable> {
+        let catalog = self.catalog.as_ref().unwrap();
+        if let Some(table) = catalog.load_table().await? {
+            return Ok(table);
+        }
+
+        info!(
+            "creating Iceberg table {}.{} at {}",
+            self.table.namespace, self.table.table_name, self.location
+        );
+
+        let mut properties = HashMap::new();
+        properties.insert(
+            "schema.name-mapping.default".to_string(),
+            schema.name_mapping().to_string(),
+        );
+
+        let metadata = TableMetadata::new(
+            &self.location,
+            schema.clone(),
+            &self.partition_fields,
+            properties,
+            to_millis(SystemTime::now()) as i64,
+        )?;
+
+        catalog.create_table(metadata).await
+    }
+
+    /// Attempts to commit a new snapshot with the data files on top of `base`, returning false
+    /// if there was a conflict
+    async fn try_commit(
+        &self,
+        base: &LoadedTable,
+        data_files: &[DataFile],
+        commit_id: &str,
+    ) -> Result<bool> {
+        let metadata = &base.metadata;
+        if metadata.format_version < 2 {
+            bail!(
+                "table {}.{} uses Iceberg format version {}; only version 2 tables are supported",
+                self.table.namespace,
+                self.table.table_name,
+                metadata.format_version
+            );
+        }
+
+        let table_schema = metadata.current_schema()?;
+        Schema::from_arrow(&self.schema)?
+            .check_compatible(table_schema)
+            .map_err(|e| {
+                anyhow!(
+                    "can't write to {}.{}: {}",
+                    self.table.namespace,
+                    self.table.table_name,
+                    e
+                )
+            })?;
+
+        // the files are partitioned by the configured fields, so the table's spec must be
+        // the identity spec over those same fields
+        let spec = metadata.default_spec()?;
+        let expected = identity_spec(table_schema, &self.partition_fields)?;
+        if spec.fields.len() != expected.fields.len()
+            || spec
+                .fields
+                .iter()
+                .zip(&expected.fields)
+                .any(|(a, b)| a.source_id != b.source_id || a.transform != "identity")
+        {
+            bail!(
+                "the partition spec of {}.{} ({}) does not match the partition fields [{}]; only \
+                identity partitions are supported",
+                self.table.namespace,
+                self.table.table_name,
+                spec.fields
+                    .iter()
+                    .map(|f| format!("{}({})", f.transform, f.name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                self.partition_fields.join(", ")
+            );
+        }
+
+        let snapshot_id = (rand::random::<u64>() >> 1) as i64;
+        let sequence_number = metadata.last_sequence_number + 1;
+        let parent = metadata.current_snapshot();
+        let now = to_millis(SystemTime::now()) as i64;
+        let options = &self.table.storage_options;
+
+        let manifest = write_manifest(table_schema, spec, snapshot_id, data_files)?;
+        let manifest_path = format!(
+            "{}/metadata/{}-m0.avro",
+            metadata.location,
+            uuid::Uuid::new_v4()
+        );
+        let manifest_length = manifest.len() as i64;
+        write_file(&manifest_path, manifest, options).await?;
+
+        // this is a fast append: the new manifest list is the previous snapshot's manifests plus
+        // the one we just wrote
+        let mut manifests = match parent {
+            Some(parent) => read_manifest_list(&read_file(&parent.manifest_list, options).await?)?,
+            None => vec![],
+        };
+
+        let added_rows: i64 = data_files.iter().map(|f| f.record_count).sum();
+        manifests.insert(
+            0,
+            ManifestFile {
+                manifest_path,
+                manifest_length,
+                partition_spec_id: spec.spec_id,
+                content: 0,
+                sequence_number,
+                min_sequence_number: sequence_number,
+                added_snapshot_id: snapshot_id,
+                added_files_count: data_files.len() as i32,
+                existing_files_count: 0,
+                deleted_files_count: 0,
+                added_rows_count: added_rows,
+                existing_rows_count: 0,
+                deleted_rows_count: 0,
+            },
+        );
+
+        let manifest_list_path = format!(
+            "{}/metadata/snap-{}-1-{}.avro",
+            metadata.location,
+            snapshot_id,
+            uuid::Uuid::new_v4()
+        );
+        write_file(
+            &manifest_list_path,
+            write_manifest_list(
+                snapshot_id,
+                parent.map(|p| p.snapshot_id),
+                sequence_number,
+                &manifests,
+            )?,
+            options,
+        )
+        .await?;
+
+        let mut summary = HashMap::new();
+        summary.insert("operation".to_string(), "append".to_string());
+        summary.insert("added-data-files".to_string(), data_files.len().to_string());
+        summary.insert("added-records".to_string(), added_rows.to_string());
+        summary.insert(COMMIT_ID_PROPERTY.to_string(), commit_id.to_string());
+
+        let snapshot = Snapshot {
+            snapshot_id,
+            parent_snapshot_id: parent.map(|p| p.snapshot_id),
+            sequence_number,
+            timestamp_ms: now,
+            manifest_list: manifest_list_path,
+            summary,
+            schema_id: Some(table_schema.schema_id),
+        };
+
+        // tables created outside of Arroyo may not have a name mapping, which readers need to
+        // read our files since they don't contain field ids
+        let mut properties = HashMap::new();
+        if !metadata
+            .properties
+            .contains_key("schema.name-mapping.default")
+        {
+            properties.insert(
+                "schema.name-mapping.default".to_string(),
+                table_schema.name_mapping().to_string(),
+            );
+        }
+
+        let committed = self
+            .catalog
+            .as_ref()
+            .unwrap()
+            .commit(base, snapshot, properties)
+            .await?;
+
+        if committed {
+            info!(
+                "committed {} files to {}.{} in snapshot {}",
+                data_files.len(),
+                self.table.namespace,
+                self.table.table_name,
+                snapshot_id
+            );
+        }
+
+        Ok(committed)
+    }
+}
+
+/// Computes a stable identifier for a set of data files
+fn commit_id(data_files: &[DataFile]) -> String {
+    let mut paths: Vec<_> = data_files.iter().map(|f| f.file_path.as_str()).collect();
+    paths.sort();
+
+    let mut hasher = Sha256::new();
+    for path in paths {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Reads the number of rows in a parquet file from its footer
+async fn read_record_count(
+    storage_provider: &StorageProvider,
+    path: &str,
+    size: usize,
+) -> Result<i64> {
+    if size < 8 {
+        bail!("parquet file {} is too small ({} bytes)", path, size);
+    }
+
+    let footer = storage_provider.get_range(path, size - 8..size).await?;
+    let metadata_len = decode_footer(footer.as_ref().try_into()?)?;
+    if metadata_len + 8 > size {
+        bail!("parquet file {} has an invalid footer", path);
+    }
+
+    let metadata = storage_provider
+        .get_range(path, size - 8 - metadata_len..size - 8)
+        .await?;
+    Ok(decode_metadata(&metadata)?.file_metadata().num_rows())
+}
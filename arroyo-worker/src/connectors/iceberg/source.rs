@@ -0,0 +1,683 @@
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+use arrow::array::{Array, ArrayRef, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, TimeUnit};
+use arrow::json::writer::record_batches_to_json_rows;
+use arrow::record_batch::RecordBatch;
+use arroyo_macro::source_fn;
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, OperatorConfig};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::{from_nanos, Message, Record, UserError, Watermark};
+use bincode::{Decode, Encode};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+use serde_json::Value;
+use tracing::{debug, info};
+
+use super::catalog::catalog_for_config;
+use super::manifest::{
+    read_manifest, read_manifest_list, ManifestFile, CONTENT_DATA, STATUS_ADDED, STATUS_DELETED,
+};
+use super::metadata::{Snapshot, TableMetadata, Type};
+use super::{read_file, IcebergConfig, IcebergTable, TableType};
+use crate::engine::{Context, StreamNode};
+use crate::{SchemaData, SourceFinishType};
+
+/// A data file to be read by the source
+#[derive(Debug, Clone, PartialEq)]
+struct ScanFile {
+    path: String,
+    record_count: i64,
+    /// The smallest value of the timestamp column in the file, if known
+    lower_bound: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+pub struct IcebergFileState {
+    path: String,
+    rows_read: u64,
+    finished: bool,
+}
+
+/// A bounded source that reads the data files of a snapshot of an Iceberg table, or the files
+/// appended between two snapshots.
+///
+/// When a timestamp column is configured it's used as the event time, and each subtask reads its
+/// files in order of their lower bound for that column so that it can emit watermarks as it goes
+/// without any of the data it reads later being late.
+#[derive(StreamNode)]
+pub struct IcebergSourceFunc<K: Send + 'static, T: SchemaData> {
+    config: IcebergConfig,
+    table: IcebergTable,
+    snapshot_id: Option<i64>,
+    start_snapshot_id: Option<i64>,
+    timestamp_column: Option<String>,
+    files: HashMap<String, IcebergFileState>,
+    _t: PhantomData<(K, T)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K: Send + 'static, T: SchemaData> IcebergSourceFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for IcebergSource");
+        let connection: IcebergConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for IcebergSource");
+        let table: IcebergTable =
+            serde_json::from_value(config.table).expect("Invalid table config for IcebergSource");
+
+        let TableType::Source {
+            snapshot_id,
+            start_snapshot_id,
+            timestamp_column,
+        } = table.type_.clone()
+        else {
+            panic!("IcebergSource configured with a sink table");
+        };
+
+        Self {
+            config: connection,
+            table,
+            snapshot_id,
+            start_snapshot_id,
+            timestamp_column,
+            files: HashMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "IcebergSource".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![
+            arroyo_state::global_table("f", "iceberg source files"),
+            arroyo_state::global_table("s", "iceberg source snapshot"),
+        ]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let mut files: GlobalKeyedState<String, IcebergFileState, _> =
+            ctx.state.get_global_keyed_state('f').await;
+        self.files = files
+            .get_all()
+            .into_iter()
+            .map(|s| (s.path.clone(), s.clone()))
+            .collect();
+
+        // if we've already started reading a snapshot we need to keep reading the same one,
+        // even if the table has changed since
+        let snapshot: GlobalKeyedState<(), i64, _> = ctx.state.get_global_keyed_state('s').await;
+        if let Some(id) = snapshot.get(&()) {
+            self.snapshot_id = Some(*id);
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let files = self.plan(ctx).await.map_err(|e| {
+            UserError::new(
+                "failed to plan Iceberg scan",
+                format!(
+                    "failed to plan scan of {}.{}: {:?}",
+                    self.table.namespace, self.table.table_name, e
+                ),
+            )
+        })?;
+
+        info!(
+            "reading {} files ({} rows) from {}.{}",
+            files.len(),
+            files.iter().map(|f| f.record_count).sum::<i64>(),
+            self.table.namespace,
+            self.table.table_name
+        );
+
+        let mut last_watermark = None;
+        for file in files {
+            let rows_read = match self.files.get(&file.path) {
+                Some(state) if state.finished => continue,
+                Some(state) => state.rows_read,
+                None => 0,
+            };
+
+            // every file we have left to read has a lower bound at least as large as this one's
+            if let Some(lower_bound) = file.lower_bound {
+                if last_watermark.map(|w| lower_bound > w).unwrap_or(true) {
+                    ctx.broadcast(Message::Watermark(Watermark::EventTime(lower_bound)))
+                        .await;
+                    last_watermark = Some(lower_bound);
+                }
+            }
+
+            if let Some(finish) = self.read_file(ctx, &file, rows_read).await? {
+                return Ok(finish);
+            }
+        }
+
+        info!(
+            "finished reading {}.{}",
+            self.table.namespace, self.table.table_name
+        );
+        Ok(SourceFinishType::Final)
+    }
+
+    /// Determines which files this subtask should read, in the order they should be read
+    async fn plan(&mut self, ctx: &mut Context<(), T>) -> Result<Vec<ScanFile>> {
+        let catalog = catalog_for_config(&self.config, &self.table).await?;
+        let table = catalog.load_table().await?.ok_or_else(|| {
+            anyhow!(
+                "table {}.{} does not exist",
+                self.table.namespace,
+                self.table.table_name
+            )
+        })?;
+        let metadata = table.metadata;
+
+        let snapshot = match self.snapshot_id {
+            Some(id) => Some(
+                metadata
+                    .snapshot(id)
+                    .ok_or_else(|| anyhow!("snapshot {} does not exist", id))?,
+            ),
+            None => metadata.current_snapshot(),
+        };
+
+        let Some(snapshot) = snapshot else {
+            info!(
+                "table {}.{} has no snapshots",
+                self.table.namespace, self.table.table_name
+            );
+            return Ok(vec![]);
+        };
+
+        self.snapshot_id = Some(snapshot.snapshot_id);
+
+        let timestamp_field = match &self.timestamp_column {
+            Some(column) => {
+                let field = metadata
+                    .current_schema()?
+                    .field_by_name(column)
+                    .ok_or_else(|| anyhow!("timestamp column '{}' is not in the table", column))?;
+                Some((field.id, timestamp_unit(&field.typ)?))
+            }
+            None => None,
+        };
+
+        let options = &self.table.storage_options;
+        let mut files = vec![];
+        for (manifest, snapshots) in
+            manifests_to_scan(&metadata, snapshot, self.start_snapshot_id, options).await?
+        {
+            for entry in read_manifest(&read_file(&manifest.manifest_path, options).await?)? {
+                let added_by = entry.snapshot_id.unwrap_or(manifest.added_snapshot_id);
+                let include = match &snapshots {
+                    Some(snapshots) => {
+                        entry.status == STATUS_ADDED && snapshots.contains(&added_by)
+                    }
+                    None => entry.status != STATUS_DELETED,
+                };
+
+                if !include || entry.content != CONTENT_DATA {
+                    continue;
+                }
+
+                if !entry.file_format.eq_ignore_ascii_case("parquet") {
+                    bail!(
+                        "data file {} has format {}; only parquet files are supported",
+                        entry.file_path,
+                        entry.file_format
+                    );
+                }
+
+                let lower_bound = timestamp_field
+                    .and_then(|(id, unit)| decode_timestamp(entry.lower_bounds.get(&id)?, unit));
+
+                files.push(ScanFile {
+                    path: entry.file_path,
+                    record_count: entry.record_count,
+                    lower_bound,
+                });
+            }
+        }
+
+        let files = assign_files(files, ctx.task_info.task_index, ctx.task_info.parallelism);
+
+        // only files that we haven't finished need to be tracked
+        let paths: HashSet<_> = files.iter().map(|f| &f.path).collect();
+        self.files.retain(|path, _| paths.contains(path));
+
+        Ok(files)
+    }
+
+    /// Reads a data file starting from `skip` rows in, returning early if the source needs to
+    /// stop
+    async fn read_file(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        file: &ScanFile,
+        skip: u64,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        debug!("reading {} from row {}", file.path, skip);
+        let read_error = |e: &dyn std::fmt::Display| {
+            UserError::new(
+                "failed to read Iceberg data file",
+                format!("failed to read {}: {}", file.path, e),
+            )
+        };
+
+        let bytes = Bytes::from(
+            read_file(&file.path, &self.table.storage_options)
+                .await
+                .map_err(|e| read_error(&e))?,
+        );
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(bytes).map_err(|e| read_error(&e))?;
+
+        // columns are matched by name to the fields of the output type
+        let schema = T::schema();
+        let columns: Vec<_> = builder
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| schema.field_with_name(f.name()).is_ok())
+            .map(|(i, _)| i)
+            .collect();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), columns);
+        let reader = builder
+            .with_projection(mask)
+            .build()
+            .map_err(|e| read_error(&e))?;
+
+        let mut rows_read = 0u64;
+        for batch in reader {
+            let batch = batch.map_err(|e| read_error(&e))?;
+            let len = batch.num_rows() as u64;
+
+            // skip rows we emitted before the last checkpoint
+            if rows_read + len <= skip {
+                rows_read += len;
+                continue;
+            }
+            let offset = skip.saturating_sub(rows_read) as usize;
+            let batch = batch.slice(offset, batch.num_rows() - offset);
+            rows_read += offset as u64;
+
+            let timestamps = match &self.timestamp_column {
+                Some(column) => Some(
+                    batch
+                        .column_by_name(column)
+                        .and_then(timestamp_nanos)
+                        .ok_or_else(|| {
+                            read_error(&format!("timestamp column '{}' is missing", column))
+                        })?,
+                ),
+                None => None,
+            };
+
+            let rows = record_batches_to_json_rows(&[&timestamps_to_strings(&batch)])
+                .map_err(|e| read_error(&e))?;
+
+            for (i, row) in rows.into_iter().enumerate() {
+                let timestamp = timestamps
+                    .as_ref()
+                    .and_then(|t| t.get(i).copied().flatten())
+                    .map(|nanos| from_nanos(nanos as u128))
+                    .unwrap_or_else(SystemTime::now);
+
+                match serde_json::from_value(Value::Object(row)) {
+                    Ok(value) => {
+                        ctx.collect(Record {
+                            timestamp,
+                            key: None,
+                            value,
+                        })
+                        .await;
+                    }
+                    Err(e) => {
+                        ctx.report_user_error(UserError::new(
+                            "Deserialization failed",
+                            format!("failed to deserialize row from {}: {}", file.path, e),
+                        ))
+                        .await;
+                    }
+                }
+            }
+
+            rows_read += batch.num_rows() as u64;
+            self.files.insert(
+                file.path.clone(),
+                IcebergFileState {
+                    path: file.path.clone(),
+                    rows_read,
+                    finished: false,
+                },
+            );
+
+            if let Some(finish) = self.handle_control_messages(ctx).await {
+                return Ok(Some(finish));
+            }
+        }
+
+        self.files.insert(
+            file.path.clone(),
+            IcebergFileState {
+                path: file.path.clone(),
+                rows_read,
+                finished: true,
+            },
+        );
+
+        Ok(self.handle_control_messages(ctx).await)
+    }
+
+    async fn handle_control_messages(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> Option<SourceFinishType> {
+        while let Ok(msg) = ctx.control_rx.try_recv() {
+            match msg {
+                ControlMessage::Checkpoint(c) => {
+                    debug!("starting checkpointing {}", ctx.task_info.task_index);
+                    let mut files: GlobalKeyedState<String, IcebergFileState, _> =
+                        ctx.state.get_global_keyed_state('f').await;
+                    for (path, state) in &self.files {
+                        files.insert(path.clone(), state.clone()).await;
+                    }
+
+                    if let Some(snapshot_id) = self.snapshot_id {
+                        let mut s: GlobalKeyedState<(), i64, _> =
+                            ctx.state.get_global_keyed_state('s').await;
+                        s.insert((), snapshot_id).await;
+                    }
+
+                    if self.checkpoint(c, ctx).await {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+                ControlMessage::Stop { mode } => {
+                    info!("Stopping Iceberg source {:?}", mode);
+
+                    match mode {
+                        StopMode::Graceful => {
+                            return Some(SourceFinishType::Graceful);
+                        }
+                        StopMode::Immediate => {
+                            return Some(SourceFinishType::Immediate);
+                        }
+                    }
+                }
+                ControlMessage::Commit { epoch: _ } => {
+                    unreachable!("sources shouldn't receive commit messages");
+                }
+                ControlMessage::LoadCompacted { compacted } => {
+                    ctx.load_compacted(compacted).await;
+                }
+                ControlMessage::QueryState { tx, .. } => {
+                    ctx.reject_state_query(tx);
+                }
+                ControlMessage::NoOp => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the manifests to read for the scan, along with (for incremental scans) the set of
+/// snapshots whose added files should be read from them
+async fn manifests_to_scan(
+    metadata: &TableMetadata,
+    snapshot: &Snapshot,
+    start_snapshot_id: Option<i64>,
+    options: &HashMap<String, String>,
+) -> Result<Vec<(ManifestFile, Option<HashSet<i64>>)>> {
+    let Some(start_snapshot_id) = start_snapshot_id else {
+        let manifests = read_manifest_list(&read_file(&snapshot.manifest_list, options).await?)?;
+        if manifests.iter().any(|m| m.content != CONTENT_DATA) {
+            bail!("tables with row-level deletes are not supported");
+        }
+        return Ok(manifests.into_iter().map(|m| (m, None)).collect());
+    };
+
+    let mut result = vec![];
+    for snapshot in snapshots_since(metadata, snapshot, start_snapshot_id)? {
+        let manifests = read_manifest_list(&read_file(&snapshot.manifest_list, options).await?)?;
+        let ids: HashSet<_> = [snapshot.snapshot_id].into_iter().collect();
+        result.extend(
+            manifests
+                .into_iter()
+                .filter(|m| m.added_snapshot_id == snapshot.snapshot_id)
+                .map(|m| (m, Some(ids.clone()))),
+        );
+    }
+
+    Ok(result)
+}
+
+/// Returns the snapshots after `start_snapshot_id` up to and including `end` that added data
+fn snapshots_since<'a>(
+    metadata: &'a TableMetadata,
+    end: &'a Snapshot,
+    start_snapshot_id: i64,
+) -> Result<Vec<&'a Snapshot>> {
+    let mut snapshots = vec![];
+    let mut current = end;
+    while current.snapshot_id != start_snapshot_id {
+        match current.summary.get("operation").map(|s| s.as_str()) {
+            Some("append") | None => snapshots.push(current),
+            // rewrites (like compactions) don't change the table's data
+            Some("replace") => {}
+            Some(op) => bail!(
+                "incremental reads only support appends, but snapshot {} is an {}",
+                current.snapshot_id,
+                op
+            ),
+        }
+
+        current = current
+            .parent_snapshot_id
+            .and_then(|id| metadata.snapshot(id))
+            .ok_or_else(|| {
+                anyhow!(
+                    "start snapshot {} is not an ancestor of snapshot {}",
+                    start_snapshot_id,
+                    end.snapshot_id
+                )
+            })?;
+    }
+
+    snapshots.reverse();
+    Ok(snapshots)
+}
+
+/// Picks this subtask's share of the files, ordered by their lower bounds (with files with
+/// unknown bounds first)
+fn assign_files(mut files: Vec<ScanFile>, task_index: usize, parallelism: usize) -> Vec<ScanFile> {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files.dedup_by(|a, b| a.path == b.path);
+
+    let mut files: Vec<_> = files
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| i % parallelism == task_index)
+        .map(|(_, f)| f)
+        .collect();
+
+    files.sort_by_key(|f| f.lower_bound);
+    files
+}
+
+fn timestamp_unit(typ: &Type) -> Result<TimeUnit> {
+    match typ {
+        Type::Primitive(p) if p == "timestamp" || p == "timestamptz" => Ok(TimeUnit::Microsecond),
+        Type::Primitive(p) if p == "timestamp_ns" || p == "timestamptz_ns" => {
+            Ok(TimeUnit::Nanosecond)
+        }
+        other => bail!("timestamp column has type {:?}, not a timestamp", other),
+    }
+}
+
+/// Decodes a timestamp bound, which is stored as a little-endian i64
+fn decode_timestamp(bytes: &[u8], unit: TimeUnit) -> Option<SystemTime> {
+    let value = i64::from_le_bytes(bytes.try_into().ok()?);
+    let nanos = match unit {
+        TimeUnit::Nanosecond => value,
+        _ => value.checked_mul(1_000)?,
+    };
+    Some(from_nanos(u128::try_from(nanos).ok()?))
+}
+
+/// Reads a timestamp column as nanoseconds since the epoch
+fn timestamp_nanos(column: &ArrayRef) -> Option<Vec<Option<i64>>> {
+    let DataType::Timestamp(unit, _) = column.data_type() else {
+        return None;
+    };
+
+    let multiplier = match unit {
+        TimeUnit::Second => 1_000_000_000,
+        TimeUnit::Millisecond => 1_000_000,
+        TimeUnit::Microsecond => 1_000,
+        TimeUnit::Nanosecond => 1,
+    };
+
+    let values = arrow::compute::cast(column, &DataType::Int64).ok()?;
+    let values = values.as_any().downcast_ref::<Int64Array>()?;
+    Some(values.iter().map(|v| Some(v? * multiplier)).collect())
+}
+
+/// Replaces timestamp columns with RFC3339 strings, which is how timestamps are deserialized
+/// into our data types
+pub(crate) fn timestamps_to_strings(batch: &RecordBatch) -> RecordBatch {
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        match timestamp_nanos(column) {
+            Some(nanos) => {
+                let strings: StringArray = nanos
+                    .into_iter()
+                    .map(|n| n.map(|n| Utc.timestamp_nanos(n).to_rfc3339()))
+                    .collect();
+                fields.push(Field::new(field.name(), DataType::Utf8, true));
+                columns.push(Arc::new(strings) as ArrayRef);
+            }
+            None => {
+                fields.push(field.as_ref().clone());
+                columns.push(column.clone());
+            }
+        }
+    }
+
+    RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), columns)
+        .expect("replacing columns with strings preserves the batch's shape")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arroyo_types::from_micros;
+
+    use super::{assign_files, decode_timestamp, snapshots_since, ScanFile};
+    use crate::connectors::iceberg::metadata::{Snapshot, TableMetadata};
+
+    fn snapshot(id: i64, parent: Option<i64>, operation: &str) -> Snapshot {
+        Snapshot {
+            snapshot_id: id,
+            parent_snapshot_id: parent,
+            sequence_number: id,
+            timestamp_ms: 0,
+            manifest_list: format!("s3://bucket/table/metadata/snap-{}.avro", id),
+            summary: [("operation".to_string(), operation.to_string())]
+                .into_iter()
+                .collect(),
+            schema_id: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_snapshots_since() {
+        let schema = crate::connectors::iceberg::metadata::Schema {
+            typ: "struct".to_string(),
+            schema_id: 0,
+            identifier_field_ids: None,
+            fields: vec![],
+        };
+        let mut metadata =
+            TableMetadata::new("s3://bucket/table", schema, &[], HashMap::new(), 0).unwrap();
+        metadata.snapshots = vec![
+            snapshot(1, None, "append"),
+            snapshot(2, Some(1), "append"),
+            snapshot(3, Some(2), "replace"),
+            snapshot(4, Some(3), "append"),
+            snapshot(5, Some(4), "overwrite"),
+        ];
+
+        let ids: Vec<_> = snapshots_since(&metadata, metadata.snapshot(4).unwrap(), 1)
+            .unwrap()
+            .iter()
+            .map(|s| s.snapshot_id)
+            .collect();
+        assert_eq!(ids, vec![2, 4]);
+
+        assert!(snapshots_since(&metadata, metadata.snapshot(5).unwrap(), 1).is_err());
+        assert!(snapshots_since(&metadata, metadata.snapshot(2).unwrap(), 4).is_err());
+    }
+
+    #[test]
+    fn test_assign_files() {
+        let file = |path: &str, lower_bound: Option<u64>| ScanFile {
+            path: path.to_string(),
+            record_count: 1,
+            lower_bound: lower_bound.map(from_micros),
+        };
+
+        let files = vec![
+            file("a", Some(30)),
+            file("b", Some(10)),
+            file("c", None),
+            file("d", Some(20)),
+        ];
+
+        assert_eq!(
+            assign_files(files.clone(), 0, 2),
+            vec![file("c", None), file("a", Some(30))]
+        );
+        assert_eq!(
+            assign_files(files, 1, 2),
+            vec![file("b", Some(10)), file("d", Some(20))]
+        );
+    }
+
+    #[test]
+    fn test_decode_timestamp() {
+        let bytes = 1_500i64.to_le_bytes();
+        assert_eq!(
+            decode_timestamp(&bytes, arrow::datatypes::TimeUnit::Microsecond),
+            Some(from_micros(1_500))
+        );
+        assert_eq!(
+            decode_timestamp(&bytes[..4], arrow::datatypes::TimeUnit::Microsecond),
+            None
+        );
+    }
+}
@@ -173,6 +173,9 @@ impl<K: Data, T: Data> ImpulseSourceFunc<K, T> {
                 Ok(ControlMessage::LoadCompacted { compacted }) => {
                     ctx.load_compacted(compacted).await;
                 }
+                Ok(ControlMessage::QueryState { tx, .. }) => {
+                    ctx.reject_state_query(tx);
+                }
                 Ok(ControlMessage::NoOp) => {}
                 Err(_) => {
                     // no messages
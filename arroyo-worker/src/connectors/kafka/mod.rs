@@ -4,6 +4,7 @@ use rdkafka::Offset;
 use serde::{Deserialize, Serialize};
 use typify::import_types;
 
+pub mod oauth;
 pub mod sink;
 pub mod source;
 
@@ -35,7 +36,25 @@ pub fn client_configs(connection: &KafkaConfig) -> HashMap<String, String> {
             client_configs.insert("sasl.username".to_string(), username.to_string());
             client_configs.insert("sasl.password".to_string(), password.to_string());
         }
+        // tokens for these are generated by the client context in `oauth`
+        KafkaConfigAuthentication::OAuth { .. } | KafkaConfigAuthentication::AwsMskIam { .. } => {
+            client_configs.insert("sasl.mechanism".to_string(), "OAUTHBEARER".to_string());
+            client_configs.insert("security.protocol".to_string(), "SASL_SSL".to_string());
+        }
     };
 
     client_configs
 }
+
+/// Creates a client for the connection's schema registry, if it has one
+pub fn schema_registry_client(
+    connection: &KafkaConfig,
+) -> Option<crate::schema_registry::SchemaRegistry> {
+    connection.schema_registry.as_ref().map(|registry| {
+        crate::schema_registry::SchemaRegistry::new(
+            &registry.endpoint,
+            registry.api_key.clone(),
+            registry.api_secret.clone(),
+        )
+    })
+}
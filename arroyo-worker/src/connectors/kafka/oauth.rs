@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
+use arroyo_types::to_millis;
+use aws_sigv4::http_request::{
+    sign, SignableRequest, SignatureLocation, SigningParams, SigningSettings,
+};
+use aws_types::credentials::ProvideCredentials;
+use aws_types::region::Region;
+use rdkafka::client::OAuthToken;
+use rdkafka::consumer::ConsumerContext;
+use rdkafka::ClientContext;
+use serde::Deserialize;
+
+use super::{KafkaConfig, KafkaConfigAuthentication};
+
+// MSK accepts signed tokens for at most 15 minutes
+const MSK_TOKEN_EXPIRY: Duration = Duration::from_secs(900);
+const DEFAULT_TOKEN_EXPIRY: Duration = Duration::from_secs(3600);
+
+#[derive(Clone)]
+enum TokenProvider {
+    ClientCredentials {
+        token_endpoint: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    },
+    AwsMskIam {
+        region: String,
+    },
+}
+
+/// Client context for Kafka consumers and producers, which generates OAUTHBEARER tokens for
+/// OAuth and MSK IAM authentication. librdkafka calls into this as part of polling whenever the
+/// current token is close to expiring, so tokens are refreshed for as long as the client is in
+/// use.
+#[derive(Clone, Default)]
+pub struct KafkaContext {
+    token_provider: Option<TokenProvider>,
+}
+
+impl KafkaContext {
+    pub fn new(connection: &KafkaConfig) -> Self {
+        let token_provider = match &connection.authentication {
+            KafkaConfigAuthentication::OAuth {
+                token_endpoint,
+                client_id,
+                client_secret,
+                scope,
+            } => Some(TokenProvider::ClientCredentials {
+                token_endpoint: token_endpoint.clone(),
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                scope: scope.clone(),
+            }),
+            KafkaConfigAuthentication::AwsMskIam { region } => Some(TokenProvider::AwsMskIam {
+                region: region.clone(),
+            }),
+            KafkaConfigAuthentication::None {} | KafkaConfigAuthentication::Sasl { .. } => None,
+        };
+
+        Self { token_provider }
+    }
+}
+
+impl ClientContext for KafkaContext {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        let provider = self
+            .token_provider
+            .clone()
+            .ok_or("OAUTHBEARER authentication is not configured for this connection")?;
+
+        // this is called from within rdkafka's polling, which may be on one of our runtime's
+        // threads, so the token is fetched on a separate thread with its own runtime
+        let token = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(provider.token())
+        })
+        .join()
+        .map_err(|_| "OAUTHBEARER token generation panicked")??;
+
+        Ok(token)
+    }
+}
+
+impl ConsumerContext for KafkaContext {}
+
+impl TokenProvider {
+    async fn token(&self) -> anyhow::Result<OAuthToken> {
+        match self {
+            TokenProvider::ClientCredentials {
+                token_endpoint,
+                client_id,
+                client_secret,
+                scope,
+            } => client_credentials_token(token_endpoint, client_id, client_secret, scope).await,
+            TokenProvider::AwsMskIam { region } => msk_iam_token(region).await,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+async fn client_credentials_token(
+    token_endpoint: &str,
+    client_id: &str,
+    client_secret: &str,
+    scope: &Option<String>,
+) -> anyhow::Result<OAuthToken> {
+    let mut form = vec![("grant_type", "client_credentials")];
+    if let Some(scope) = scope {
+        form.push(("scope", scope.as_str()));
+    }
+
+    let resp = reqwest::Client::new()
+        .post(token_endpoint)
+        .basic_auth(client_id, Some(client_secret))
+        .form(&form)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        bail!("token endpoint responded with {}: {}", status, body);
+    }
+
+    let resp: TokenResponse = serde_json::from_str(&body)
+        .map_err(|e| anyhow!("invalid response from token endpoint: {}", e))?;
+
+    let expiry = resp
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TOKEN_EXPIRY);
+
+    Ok(OAuthToken {
+        token: resp.access_token,
+        principal_name: client_id.to_string(),
+        lifetime_ms: to_millis(SystemTime::now() + expiry) as i64,
+    })
+}
+
+/// MSK IAM tokens are presigned `kafka-cluster:Connect` requests, base64-encoded
+async fn msk_iam_token(region: &str) -> anyhow::Result<OAuthToken> {
+    let config = aws_config::from_env()
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+
+    let credentials = config
+        .credentials_provider()
+        .ok_or_else(|| anyhow!("no AWS credentials are configured"))?
+        .provide_credentials()
+        .await?;
+
+    let now = SystemTime::now();
+
+    let mut settings = SigningSettings::default();
+    settings.signature_location = SignatureLocation::QueryParams;
+    settings.expires_in = Some(MSK_TOKEN_EXPIRY);
+
+    let params = SigningParams::builder()
+        .access_key(credentials.access_key_id())
+        .secret_key(credentials.secret_access_key())
+        .set_security_token(credentials.session_token())
+        .region(region)
+        .service_name("kafka-cluster")
+        .time(now)
+        .settings(settings)
+        .build()?;
+
+    let mut request = http::Request::builder()
+        .method("GET")
+        .uri(format!(
+            "https://kafka.{}.amazonaws.com/?Action=kafka-cluster%3AConnect",
+            region
+        ))
+        .body("")?;
+
+    let (instructions, _) = sign(SignableRequest::from(&request), &params)
+        .map_err(|e| anyhow!("failed to sign MSK IAM token: {}", e))?
+        .into_parts();
+    instructions.apply_to_request(&mut request);
+
+    let url = format!("{}&User-Agent=arroyo", request.uri());
+
+    Ok(OAuthToken {
+        token: base64::encode_config(url, base64::URL_SAFE_NO_PAD),
+        principal_name: "arroyo".to_string(),
+        lifetime_ms: to_millis(now + MSK_TOKEN_EXPIRY) as i64,
+    })
+}
@@ -1,17 +1,20 @@
 use crate::engine::{Context, StreamNode};
 use crate::formats::DataSerializer;
+use crate::schema_registry::SchemaRegistry;
 use crate::SchemaData;
 use anyhow::Result;
 use arroyo_macro::process_fn;
 use arroyo_rpc::formats::Format;
 use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableWriteBehavior};
 use arroyo_rpc::{CheckpointEvent, ControlMessage, OperatorConfig};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
 use arroyo_types::*;
 use std::collections::HashMap;
 use std::marker::PhantomData;
 
 use tracing::{error, warn};
 
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord, Producer};
 use rdkafka::util::Timeout;
 
@@ -21,21 +24,39 @@ use arroyo_types::CheckpointBarrier;
 use rdkafka::error::KafkaError;
 use rdkafka_sys::RDKafkaErrorCode;
 use serde::Serialize;
+use serde_json::Value;
 use std::time::{Duration, SystemTime};
 
-use super::{client_configs, KafkaConfig, KafkaTable, SinkCommitMode, TableType};
+use super::oauth::KafkaContext;
+use super::{
+    client_configs, schema_registry_client, KafkaConfig, KafkaTable, SinkCommitMode, TableType,
+};
 
 #[cfg(test)]
 mod test;
 
+/// Renders a field of the record as a key or header value; strings are written without quotes,
+/// and nulls are omitted
+fn field_to_string(record: &Value, field: &str) -> Option<String> {
+    match record.get(field) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    }
+}
+
 #[derive(StreamNode)]
 pub struct KafkaSinkFunc<K: Key + Serialize, T: SchemaData> {
     topic: String,
     bootstrap_servers: String,
     consistency_mode: ConsistencyMode,
-    producer: Option<FutureProducer>,
+    producer: Option<FutureProducer<KafkaContext>>,
     write_futures: Vec<DeliveryFuture>,
     client_config: HashMap<String, String>,
+    context: KafkaContext,
+    key_field: Option<String>,
+    header_fields: Vec<String>,
+    schema_registry: Option<SchemaRegistry>,
     serializer: DataSerializer<T>,
     _t: PhantomData<K>,
 }
@@ -44,7 +65,7 @@ enum ConsistencyMode {
     AtLeastOnce,
     ExactlyOnce {
         next_transaction_index: usize,
-        producer_to_complete: Option<FutureProducer>,
+        producer_to_complete: Option<FutureProducer<KafkaContext>>,
     },
 }
 
@@ -77,6 +98,10 @@ impl<K: Key + Serialize, T: SchemaData> KafkaSinkFunc<K, T> {
                 .iter()
                 .map(|(key, value)| (key.to_string(), value.to_string()))
                 .collect(),
+            context: KafkaContext::default(),
+            key_field: None,
+            header_fields: vec![],
+            schema_registry: None,
             serializer: DataSerializer::new(format),
             _t: PhantomData,
         }
@@ -89,7 +114,12 @@ impl<K: Key + Serialize, T: SchemaData> KafkaSinkFunc<K, T> {
             .expect("Invalid connection config for KafkaSink");
         let table: KafkaTable =
             serde_json::from_value(config.table).expect("Invalid table config for KafkaSource");
-        let TableType::Sink { commit_mode } = table.type_ else {
+        let TableType::Sink {
+            commit_mode,
+            key_field,
+            header_fields,
+        } = table.type_
+        else {
             panic!("found non-sink kafka config in sink operator");
         };
 
@@ -100,9 +130,14 @@ impl<K: Key + Serialize, T: SchemaData> KafkaSinkFunc<K, T> {
             consistency_mode: commit_mode.unwrap_or(SinkCommitMode::AtLeastOnce).into(),
             write_futures: vec![],
             client_config: client_configs(&connection),
+            context: KafkaContext::new(&connection),
+            key_field,
+            header_fields,
+            schema_registry: schema_registry_client(&connection),
             serializer: DataSerializer::new(
                 config.format.expect("Format must be defined for KafkaSink"),
-            ),
+            )
+            .with_compression(config.compression),
             _t: PhantomData,
         }
     }
@@ -115,6 +150,38 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
     }
 
     async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        if let ConsistencyMode::ExactlyOnce {
+            next_transaction_index,
+            ..
+        } = &mut self.consistency_mode
+        {
+            // reuse the transactional id of the transaction that was open when we last
+            // checkpointed; initializing it fences off any producer from before the restart
+            // and aborts its uncommitted writes, which will be replayed from the checkpoint
+            let s: GlobalKeyedState<usize, usize, _> = ctx.state.get_global_keyed_state('i').await;
+            if let Some(index) = s.get(&ctx.task_info.task_index) {
+                *next_transaction_index = *index;
+            }
+        }
+
+        if let Some(registry) = &self.schema_registry {
+            // values are registered under the subject for the topic, per the default
+            // TopicNameStrategy
+            let subject = format!("{}-value", self.topic);
+            if let Err(e) = self
+                .serializer
+                .init_schema_registry(registry, &subject)
+                .await
+            {
+                ctx.report_error(
+                    "Failed to initialize schema registry".to_string(),
+                    format!("{:?}", e),
+                )
+                .await;
+                panic!("Failed to initialize schema registry: {:?}", e);
+            }
+        }
+
         self.init_producer(&ctx.task_info)
             .expect("Producer creation failed");
     }
@@ -147,7 +214,7 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
 
         match &mut self.consistency_mode {
             ConsistencyMode::AtLeastOnce => {
-                self.producer = Some(client_config.create()?);
+                self.producer = Some(client_config.create_with_context(self.context.clone())?);
             }
             ConsistencyMode::ExactlyOnce {
                 next_transaction_index,
@@ -163,7 +230,8 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
                     next_transaction_index
                 );
                 client_config.set("transactional.id", transactional_id);
-                let producer: FutureProducer = client_config.create()?;
+                let producer: FutureProducer<KafkaContext> =
+                    client_config.create_with_context(self.context.clone())?;
                 producer.init_transactions(Timeout::After(Duration::from_secs(30)))?;
                 producer.begin_transaction()?;
                 *next_transaction_index += 1;
@@ -211,14 +279,24 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
         }
     }
 
-    async fn publish(&mut self, k: Option<String>, v: Vec<u8>) {
-        let mut rec = {
-            if let Some(k) = k.as_ref() {
-                FutureRecord::to(&self.topic).key(k).payload(&v)
-            } else {
-                FutureRecord::to(&self.topic).payload(&v)
-            }
-        };
+    // messages without a payload are tombstones, which delete their key from compacted topics
+    async fn publish(
+        &mut self,
+        k: Option<String>,
+        v: Option<Vec<u8>>,
+        headers: Option<OwnedHeaders>,
+    ) {
+        let mut rec = FutureRecord::<String, Vec<u8>>::to(&self.topic);
+        if let Some(k) = k.as_ref() {
+            rec = rec.key(k);
+        }
+        if let Some(v) = v.as_ref() {
+            rec = rec.payload(v);
+        }
+
+        if let Some(headers) = headers {
+            rec = rec.headers(headers);
+        }
 
         loop {
             match self.producer.as_mut().unwrap().send_result(rec) {
@@ -240,14 +318,40 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
     }
 
     async fn process_element(&mut self, record: &Record<K, T>, _ctx: &mut Context<(), ()>) {
-        let k = record
-            .key
-            .as_ref()
-            .map(|k| serde_json::to_string(k).unwrap());
-        let v = self.serializer.to_vec(&record.value);
+        // the record only needs to be converted to JSON if we're pulling fields out of it
+        let value = if self.key_field.is_some() || !self.header_fields.is_empty() {
+            serde_json::to_value(&record.value).unwrap_or(Value::Null)
+        } else {
+            Value::Null
+        };
+
+        let k = match &self.key_field {
+            Some(field) => field_to_string(&value, field),
+            None => record
+                .key
+                .as_ref()
+                .map(|k| serde_json::to_string(k).unwrap()),
+        };
 
-        if let Some(v) = v {
-            self.publish(k, v).await;
+        let headers = (!self.header_fields.is_empty()).then(|| {
+            self.header_fields
+                .iter()
+                .filter_map(|field| Some((field, field_to_string(&value, field)?)))
+                .fold(
+                    OwnedHeaders::new_with_capacity(self.header_fields.len()),
+                    |headers, (field, value)| {
+                        headers.insert(Header {
+                            key: field,
+                            value: Some(&value),
+                        })
+                    },
+                )
+        });
+
+        if record.value.is_deletion() {
+            self.publish(k, None, headers).await;
+        } else if let Some(v) = self.serializer.to_vec(&record.value) {
+            self.publish(k, Some(v), headers).await;
         }
     }
 
@@ -261,23 +365,36 @@ impl<K: Key + Serialize, T: SchemaData + Serialize> KafkaSinkFunc<K, T> {
             return;
         };
 
-        let Some(committing_producer) = producer_to_complete.take() else {
-            unimplemented!("received a commit message without a producer ready to commit. Restoring from commit phase not yet implemented");
-        };
-        let mut commits_attempted = 0;
-        loop {
-            if committing_producer
-                .commit_transaction(Timeout::After(Duration::from_secs(10)))
-                .is_ok()
-            {
-                break;
-            } else if commits_attempted == 5 {
-                panic!("failed to commit 5 times, giving up");
-            } else {
-                error!("failed to commit {} times, retrying", commits_attempted);
-                commits_attempted += 1;
+        if let Some(committing_producer) = producer_to_complete.take() {
+            let mut commits_attempted = 0;
+            loop {
+                if committing_producer
+                    .commit_transaction(Timeout::After(Duration::from_secs(10)))
+                    .is_ok()
+                {
+                    break;
+                } else if commits_attempted == 5 {
+                    panic!("failed to commit 5 times, giving up");
+                } else {
+                    error!("failed to commit {} times, retrying", commits_attempted);
+                    commits_attempted += 1;
+                }
             }
+        } else {
+            // Kafka transactions are bound to the producer that opened them, so if we restarted
+            // between completing a checkpoint and committing it, that transaction can't be
+            // resumed and will be aborted by the broker once it times out
+            ctx.report_error(
+                "Kafka transaction could not be committed".to_string(),
+                format!(
+                    "received commit for epoch {} without an open transaction; this happens \
+                     when the pipeline restarts during the commit phase",
+                    epoch
+                ),
+            )
+            .await;
         }
+
         let checkpoint_event = arroyo_rpc::ControlResp::CheckpointEvent(CheckpointEvent {
             checkpoint_epoch: epoch,
             operator_id: ctx.task_info.operator_id.clone(),
@@ -1,27 +1,36 @@
+use crate::connectors::partition_watermarks::PartitionWatermarker;
 use crate::engine::{Context, StreamNode};
 use crate::formats::DataDeserializer;
+use crate::watermark_alignment::WATERMARK_ALIGNMENT;
 use crate::SchemaData;
 use crate::SourceFinishType;
 use arroyo_macro::source_fn;
-use arroyo_rpc::formats::{Format, Framing};
+use arroyo_rpc::formats::{Format, Framing, JsonFormat, TimestampFormat};
 use arroyo_rpc::grpc::TableDescriptor;
-use arroyo_rpc::OperatorConfig;
 use arroyo_rpc::{grpc::StopMode, ControlMessage, ControlResp};
+use arroyo_rpc::{OperatorConfig, PartitionWatermarks, RateLimit};
 use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
 use arroyo_types::*;
 use bincode::{Decode, Encode};
-use governor::{Quota, RateLimiter};
+use chrono::{DateTime, Utc};
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::Headers;
 use rdkafka::{ClientConfig, Message as KMessage, Offset, TopicPartitionList};
+use regex::Regex;
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
-use std::num::NonZeroU32;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::select;
+use tokio::time::{interval_at, Instant};
 use tracing::{debug, error, info, warn};
 
-use super::{client_configs, KafkaConfig, KafkaTable, ReadMode, TableType};
+use super::oauth::KafkaContext;
+use super::{
+    client_configs, schema_registry_client, KafkaConfig, KafkaTable, ReadMode, SourceHeaderField,
+    TableType,
+};
 
 #[cfg(test)]
 mod test;
@@ -33,23 +42,117 @@ where
     T: SchemaData + Data,
 {
     topic: String,
+    // if set, we read from every topic matching this pattern rather than just `topic`
+    topic_pattern: Option<Regex>,
+    partition_discovery_interval: Option<Duration>,
     bootstrap_servers: String,
     group_id: Option<String>,
+    commit_offsets: bool,
     offset_mode: super::SourceOffset,
     deserializer: DataDeserializer<T>,
+    metadata_fields: MetadataFields,
     client_configs: HashMap<String, String>,
-    messages_per_second: NonZeroU32,
+    context: KafkaContext,
+    // if set, we generate watermarks for each partition rather than leaving it to the watermark
+    // operator
+    partition_watermarks: Option<PartitionWatermarks>,
     _t: PhantomData<K>,
 }
 
+/// Fields of the schema that are populated from the Kafka message rather than its payload
+#[derive(Default)]
+struct MetadataFields {
+    key: Option<String>,
+    topic: Option<String>,
+    partition: Option<String>,
+    offset: Option<String>,
+    timestamp: Option<String>,
+    headers: Vec<SourceHeaderField>,
+    // whether timestamps are represented as millis or RFC3339 strings in the format
+    timestamps_as_millis: bool,
+}
+
+impl MetadataFields {
+    fn is_empty(&self) -> bool {
+        self.key.is_none()
+            && self.topic.is_none()
+            && self.partition.is_none()
+            && self.offset.is_none()
+            && self.timestamp.is_none()
+            && self.headers.is_empty()
+    }
+
+    fn values(&self, msg: &impl KMessage, timestamp: SystemTime) -> Map<String, Value> {
+        let mut values = Map::new();
+
+        let utf8 = |bytes: Option<&[u8]>| {
+            bytes
+                .map(|b| Value::String(String::from_utf8_lossy(b).to_string()))
+                .unwrap_or(Value::Null)
+        };
+
+        if let Some(field) = &self.key {
+            values.insert(field.clone(), utf8(msg.key()));
+        }
+        if let Some(field) = &self.topic {
+            values.insert(field.clone(), Value::String(msg.topic().to_string()));
+        }
+        if let Some(field) = &self.partition {
+            values.insert(field.clone(), Value::from(msg.partition()));
+        }
+        if let Some(field) = &self.offset {
+            values.insert(field.clone(), Value::from(msg.offset()));
+        }
+        if let Some(field) = &self.timestamp {
+            let value = if self.timestamps_as_millis {
+                Value::from(to_millis(timestamp))
+            } else {
+                Value::String(DateTime::<Utc>::from(timestamp).to_rfc3339())
+            };
+            values.insert(field.clone(), value);
+        }
+
+        for header in &self.headers {
+            let value = msg
+                .headers()
+                .and_then(|headers| headers.iter().filter(|h| h.key == header.header).last())
+                .and_then(|h| h.value);
+            values.insert(header.field.clone(), utf8(value));
+        }
+
+        values
+    }
+}
+
 #[derive(Copy, Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
 pub struct KafkaState {
     partition: i32,
     offset: i64,
 }
 
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
+pub struct KafkaTopicState {
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
 pub fn tables() -> Vec<TableDescriptor> {
-    vec![arroyo_state::global_table("k", "kafka source state")]
+    vec![
+        arroyo_state::global_table("k", "kafka source state"),
+        arroyo_state::global_table("t", "kafka source topic pattern state"),
+    ]
+}
+
+const DEFAULT_PARTITION_DISCOVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A stable hash of the topic name, used to spread the partitions of many small topics evenly
+/// across subtasks
+fn topic_hash(topic: &str) -> usize {
+    // FNV-1a
+    topic.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    }) as usize
 }
 
 #[source_fn(out_k = (), out_t = T)]
@@ -70,15 +173,23 @@ where
     ) -> Self {
         Self {
             topic: topic.to_string(),
+            topic_pattern: None,
+            partition_discovery_interval: Some(DEFAULT_PARTITION_DISCOVERY_INTERVAL),
             bootstrap_servers: servers.to_string(),
             group_id: group,
+            commit_offsets: true,
             offset_mode,
-            deserializer: DataDeserializer::new(format, framing),
+            deserializer: DataDeserializer::new(format, framing).with_rate_limit(Some(RateLimit {
+                messages_per_second: Some(messages_per_second),
+                bytes_per_second: None,
+            })),
+            metadata_fields: MetadataFields::default(),
             client_configs: client_configs
                 .iter()
                 .map(|(key, value)| (key.to_string(), value.to_string()))
                 .collect(),
-            messages_per_second: NonZeroU32::new(messages_per_second).unwrap(),
+            context: KafkaContext::default(),
+            partition_watermarks: None,
             _t: PhantomData,
         }
     }
@@ -94,6 +205,15 @@ where
             offset,
             read_mode,
             group_id,
+            commit_offsets,
+            topic_pattern,
+            partition_discovery_interval_ms,
+            key_field,
+            topic_field,
+            partition_field,
+            offset_field,
+            timestamp_field,
+            header_fields,
         } = &table.type_
         else {
             panic!("found non-source kafka config in source operator");
@@ -103,23 +223,53 @@ where
             client_configs.insert("isolation.level".to_string(), "read_committed".to_string());
         }
 
+        let format = config.format.expect("Format must be set for Kafka source");
+        let metadata_fields = MetadataFields {
+            key: key_field.clone(),
+            topic: topic_field.clone(),
+            partition: partition_field.clone(),
+            offset: offset_field.clone(),
+            timestamp: timestamp_field.clone(),
+            headers: header_fields.clone(),
+            timestamps_as_millis: matches!(
+                &format,
+                Format::Json(JsonFormat {
+                    timestamp_format: TimestampFormat::UnixMillis,
+                    ..
+                }) | Format::Avro(_)
+            ),
+        };
+
+        let mut deserializer = DataDeserializer::new(format, config.framing)
+            .with_bad_data(config.bad_data)
+            .with_compression(config.compression)
+            .with_rate_limit(config.rate_limit);
+        if let Some(registry) = schema_registry_client(&connection) {
+            deserializer = deserializer.with_schema_registry(registry);
+        }
+
+        let topic_pattern = topic_pattern.unwrap_or(false).then(|| {
+            // the pattern must match the entire topic name
+            Regex::new(&format!("^(?:{})$", table.topic)).expect("invalid topic pattern")
+        });
+
         Self {
             topic: table.topic,
+            topic_pattern,
+            partition_discovery_interval: match partition_discovery_interval_ms {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(*ms as u64)),
+                None => Some(DEFAULT_PARTITION_DISCOVERY_INTERVAL),
+            },
             bootstrap_servers: connection.bootstrap_servers.to_string(),
             group_id: group_id.clone(),
+            commit_offsets: commit_offsets.unwrap_or(true),
             offset_mode: *offset,
-            deserializer: DataDeserializer::new(
-                config.format.expect("Format must be set for Kafka source"),
-                config.framing,
-            ),
+            deserializer,
+            metadata_fields,
             client_configs,
-            messages_per_second: NonZeroU32::new(
-                config
-                    .rate_limit
-                    .map(|l| l.messages_per_second)
-                    .unwrap_or(u32::MAX),
-            )
-            .unwrap(),
+            context: KafkaContext::new(&connection),
+            partition_watermarks: config.partition_watermarks,
             _t: PhantomData,
         }
     }
@@ -132,14 +282,62 @@ where
         tables()
     }
 
-    async fn get_consumer(&mut self, ctx: &mut Context<(), T>) -> anyhow::Result<StreamConsumer> {
+    /// The subtask responsible for reading a partition; this must be stable as topics and
+    /// partitions are added, so that no partition is ever read by two subtasks
+    fn assigned_subtask(&self, topic: &str, partition: i32, parallelism: usize) -> usize {
+        let base = if self.topic_pattern.is_some() {
+            topic_hash(topic)
+        } else {
+            0
+        };
+
+        base.wrapping_add(partition as usize) % parallelism
+    }
+
+    /// Fetches the partitions of all topics we read from that are assigned to this subtask
+    fn discover_partitions(
+        &self,
+        consumer: &StreamConsumer<KafkaContext>,
+        task_info: &TaskInfo,
+    ) -> anyhow::Result<Vec<(String, i32)>> {
+        let metadata = match &self.topic_pattern {
+            Some(_) => consumer.fetch_metadata(None, Duration::from_secs(30))?,
+            None => consumer.fetch_metadata(Some(&self.topic), Duration::from_secs(30))?,
+        };
+
+        Ok(metadata
+            .topics()
+            .iter()
+            .filter(|t| match &self.topic_pattern {
+                // skip internal topics like __consumer_offsets
+                Some(pattern) => {
+                    t.error().is_none() && !t.name().starts_with("__") && pattern.is_match(t.name())
+                }
+                None => t.name() == self.topic,
+            })
+            .flat_map(|t| {
+                t.partitions()
+                    .iter()
+                    .map(move |p| (t.name().to_string(), p.id()))
+            })
+            .filter(|(topic, partition)| {
+                self.assigned_subtask(topic, *partition, task_info.parallelism)
+                    == task_info.task_index
+            })
+            .collect())
+    }
+
+    async fn get_consumer(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> anyhow::Result<(StreamConsumer<KafkaContext>, HashSet<(String, i32)>)> {
         info!("Creating kafka consumer for {}", self.bootstrap_servers);
         let mut client_config = ClientConfig::new();
 
         for (key, value) in &self.client_configs {
             client_config.set(key, value);
         }
-        let consumer: StreamConsumer = client_config
+        let consumer: StreamConsumer<KafkaContext> = client_config
             .set("bootstrap.servers", &self.bootstrap_servers)
             .set("enable.partition.eof", "false")
             .set("enable.auto.commit", "false")
@@ -152,44 +350,51 @@ where
                     )
                 }),
             )
-            .create()?;
-
-        let mut s: GlobalKeyedState<i32, KafkaState, _> =
-            ctx.state.get_global_keyed_state('k').await;
-        let state: Vec<&KafkaState> = s.get_all();
+            .create_with_context(self.context.clone())?;
+
+        // the next offset to read for each partition we've restored
+        let state: HashMap<(String, i32), i64> = if self.topic_pattern.is_some() {
+            let mut s: GlobalKeyedState<(String, i32), KafkaTopicState, _> =
+                ctx.state.get_global_keyed_state('t').await;
+            s.get_all()
+                .into_iter()
+                .map(|s| ((s.topic.clone(), s.partition), s.offset))
+                .collect()
+        } else {
+            let mut s: GlobalKeyedState<i32, KafkaState, _> =
+                ctx.state.get_global_keyed_state('k').await;
+            s.get_all()
+                .into_iter()
+                .map(|s| ((self.topic.clone(), s.partition), s.offset))
+                .collect()
+        };
 
         // did we restore any partitions?
         let has_state = !state.is_empty();
 
-        let state: HashMap<i32, KafkaState> = state.iter().map(|s| (s.partition, **s)).collect();
-        let metadata = consumer.fetch_metadata(Some(&self.topic), Duration::from_secs(30))?;
+        let partitions = self.discover_partitions(&consumer, &ctx.task_info)?;
 
         info!("Fetched metadata for topic {}", self.topic);
 
-        let our_partitions: HashMap<_, _> = {
-            let partitions = metadata.topics()[0].partitions();
-            partitions
-                .iter()
-                .enumerate()
-                .filter(|(i, _)| i % ctx.task_info.parallelism == ctx.task_info.task_index)
-                .map(|(_, p)| {
-                    let offset = state
-                        .get(&p.id())
-                        .map(|s| Offset::Offset(s.offset))
-                        .unwrap_or_else(|| {
-                            if has_state {
-                                // if we've restored partitions and we don't know about this one, that means it's
-                                // new, and we want to start from the beginning so we don't drop data
-                                Offset::Beginning
-                            } else {
-                                self.offset_mode.get_offset()
-                            }
-                        });
+        let our_partitions: HashMap<_, _> = partitions
+            .into_iter()
+            .map(|tp| {
+                let offset = state
+                    .get(&tp)
+                    .map(|offset| Offset::Offset(*offset))
+                    .unwrap_or_else(|| {
+                        if has_state {
+                            // if we've restored partitions and we don't know about this one, that means it's
+                            // new, and we want to start from the beginning so we don't drop data
+                            Offset::Beginning
+                        } else {
+                            self.offset_mode.get_offset()
+                        }
+                    });
 
-                    ((self.topic.clone(), p.id()), offset)
-                })
-                .collect()
-        };
+                (tp, offset)
+            })
+            .collect();
 
         info!(
             "partition map for {}-{}: {:?}",
@@ -200,11 +405,59 @@ where
 
         consumer.assign(&topic_partitions)?;
 
-        Ok(consumer)
+        Ok((consumer, our_partitions.into_keys().collect()))
+    }
+
+    /// Starts reading any partitions that have been created since we last checked, returning
+    /// the number of new partitions
+    fn assign_new_partitions(
+        &self,
+        consumer: &StreamConsumer<KafkaContext>,
+        assigned: &mut HashSet<(String, i32)>,
+        task_info: &TaskInfo,
+    ) -> anyhow::Result<usize> {
+        let new: HashMap<_, _> = self
+            .discover_partitions(consumer, task_info)?
+            .into_iter()
+            .filter(|tp| !assigned.contains(tp))
+            // partitions created while we're running are read in full so we don't drop data
+            .map(|tp| (tp, Offset::Beginning))
+            .collect();
+
+        if new.is_empty() {
+            return Ok(0);
+        }
+
+        info!(
+            "discovered new partitions for {}-{}: {:?}",
+            self.topic,
+            task_info.task_index,
+            new.keys()
+        );
+
+        consumer.incremental_assign(&TopicPartitionList::from_topic_map(&new)?)?;
+        let count = new.len();
+        assigned.extend(new.into_keys());
+        Ok(count)
     }
 
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
-        match self.run_int(ctx).await {
+        let result = self.run_int(ctx).await;
+
+        // a source that's stopped reading shouldn't hold back the rest of its group
+        if let Some(alignment) = self
+            .partition_watermarks
+            .as_ref()
+            .and_then(|c| c.alignment.as_ref())
+        {
+            WATERMARK_ALIGNMENT.remove(
+                &alignment.group,
+                &ctx.task_info.operator_id,
+                ctx.task_info.task_index,
+            );
+        }
+
+        match result {
             Ok(r) => r,
             Err(e) => {
                 ctx.control_tx
@@ -223,23 +476,52 @@ where
     }
 
     async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
-        let consumer = self
+        let (consumer, mut assigned) = self
             .get_consumer(ctx)
             .await
             .map_err(|e| UserError::new("Could not create Kafka consumer", format!("{:?}", e)))?;
 
-        let rate_limiter = RateLimiter::direct(Quota::per_second(self.messages_per_second));
-        let mut offsets = HashMap::new();
+        // the last offset read for each partition, by topic
+        let mut offsets: HashMap<String, HashMap<i32, i64>> = HashMap::new();
 
-        if consumer.assignment().unwrap().count() == 0 {
+        if assigned.is_empty() {
             warn!("Kafka Consumer {}-{} is subscribed to no partitions, as there are more subtasks than partitions... setting idle",
                 ctx.task_info.operator_id, ctx.task_info.task_index);
             ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
         }
 
+        let discovery_period = self
+            .partition_discovery_interval
+            .unwrap_or(DEFAULT_PARTITION_DISCOVERY_INTERVAL);
+        let mut discovery = interval_at(Instant::now() + discovery_period, discovery_period);
+
+        let mut watermarker = self.partition_watermarks.as_ref().map(|config| {
+            let mut watermarker = PartitionWatermarker::new(config);
+            for tp in &assigned {
+                watermarker.add_partition(tp.clone(), std::time::Instant::now());
+            }
+            watermarker
+        });
+        // only ticks if we're generating watermarks
+        let mut watermark_interval = tokio::time::interval(
+            self.partition_watermarks
+                .as_ref()
+                .map(|config| Duration::from_micros(config.period_micros))
+                .unwrap_or(Duration::from_secs(1)),
+        );
+
+        // if our watermarks are aligned with other sources, we stop reading while we're too
+        // far ahead of the slowest of them
+        let alignment = self
+            .partition_watermarks
+            .as_ref()
+            .and_then(|config| config.alignment.clone());
+        let mut current_watermark: Option<SystemTime> = None;
+        let mut paused = false;
+
         loop {
             select! {
-                message = consumer.recv() => {
+                message = consumer.recv(), if !paused => {
                     match message {
                         Ok(msg) => {
                             if let Some(v) = msg.payload() {
@@ -247,18 +529,45 @@ where
                                     .ok_or_else(|| UserError::new("Failed to read timestamp from Kafka record",
                                         "The message read from Kafka did not contain a message timestamp"))?;
 
-                                let iter = self.deserializer.deserialize_slice(v);
+                                let timestamp = from_millis(timestamp as u64);
+
+                                let metadata = (!self.metadata_fields.is_empty())
+                                    .then(|| self.metadata_fields.values(&msg, timestamp));
+
+                                self.deserializer.throttle(v).await;
+                                self.deserializer.resolve_schema(v).await?;
+                                let iter = self.deserializer.deserialize_slice_with_fields(v, metadata.as_ref());
 
                                 for value in iter {
+                                    let value = match value {
+                                        Ok(value) => value,
+                                        Err(e) => match self.deserializer.handle_bad_data(&ctx.task_info, v, e)? {
+                                            Some(e) => return Err(e),
+                                            None => continue,
+                                        },
+                                    };
+
                                     ctx.collector.collect(Record {
-                                        timestamp: from_millis(timestamp as u64),
+                                        timestamp,
                                         key: None,
-                                        value: value?,
+                                        value,
                                     }).await;
                                 }
 
-                                offsets.insert(msg.partition(), msg.offset());
-                                rate_limiter.until_ready().await;
+                                if let Some(watermarker) = &mut watermarker {
+                                    watermarker.observe((msg.topic().to_string(), msg.partition()),
+                                        timestamp, std::time::Instant::now());
+                                }
+
+                                match offsets.get_mut(msg.topic()) {
+                                    Some(partitions) => {
+                                        partitions.insert(msg.partition(), msg.offset());
+                                    }
+                                    None => {
+                                        offsets.insert(msg.topic().to_string(),
+                                            HashMap::from([(msg.partition(), msg.offset())]));
+                                    }
+                                }
                             }
                         },
                         Err(err) => {
@@ -266,26 +575,96 @@ where
                         }
                     }
                 }
+                _ = discovery.tick(), if self.partition_discovery_interval.is_some() => {
+                    match self.assign_new_partitions(&consumer, &mut assigned, &ctx.task_info) {
+                        Ok(0) => {}
+                        Ok(n) => {
+                            info!("Started reading from {} new partitions", n);
+                            if let Some(watermarker) = &mut watermarker {
+                                for tp in &assigned {
+                                    watermarker.add_partition(tp.clone(), std::time::Instant::now());
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            // we'll try again on the next interval
+                            warn!("Failed to discover new Kafka partitions: {:?}", e);
+                        }
+                    }
+                }
+                _ = watermark_interval.tick(), if watermarker.is_some() => {
+                    let watermarker = watermarker.as_mut().unwrap();
+                    // while paused our watermark can't advance, and we don't want our partitions to go idle
+                    let watermark = if paused { None } else { watermarker.poll(std::time::Instant::now()) };
+                    if let Some(watermark) = watermark {
+                        if let Some(alignment) = &alignment {
+                            WATERMARK_ALIGNMENT.report(&alignment.group, &ctx.task_info.operator_id,
+                                ctx.task_info.task_index, watermark);
+                        }
+                        current_watermark = match watermark {
+                            Watermark::EventTime(t) => Some(t),
+                            Watermark::Idle => None,
+                        };
+                        ctx.broadcast(Message::Watermark(watermark)).await;
+                    }
+
+                    if let (Some(alignment), Some(watermark)) = (&alignment, current_watermark) {
+                        let should_pause = WATERMARK_ALIGNMENT.should_pause(&alignment.group, watermark,
+                            Duration::from_micros(alignment.max_drift_micros));
+                        if should_pause != paused {
+                            if !should_pause {
+                                watermarker.reset_idle(std::time::Instant::now());
+                            }
+                            info!("{} reading from Kafka, as the watermark is {} the others in alignment group '{}'",
+                                if should_pause { "Pausing" } else { "Resuming" },
+                                if should_pause { "too far ahead of" } else { "back within range of" },
+                                alignment.group);
+                            paused = should_pause;
+                        }
+                    } else {
+                        paused = false;
+                    }
+                }
                 control_message = ctx.control_rx.recv() => {
                     match control_message {
                         Some(ControlMessage::Checkpoint(c)) => {
                             debug!("starting checkpointing {}", ctx.task_info.task_index);
                             let mut topic_partitions = TopicPartitionList::new();
-                            let mut s = ctx.state.get_global_keyed_state('k').await;
-                            for (partition, offset) in &offsets {
-                                let partition2 = partition;
-                                s.insert(*partition, KafkaState {
-                                    partition: *partition2,
-                                    offset: *offset + 1,
-                                }).await;
-                                topic_partitions.add_partition_offset(
-                                    &self.topic, *partition, Offset::Offset(*offset)).unwrap();
+                            if self.topic_pattern.is_some() {
+                                let mut s = ctx.state.get_global_keyed_state('t').await;
+                                for (topic, partitions) in &offsets {
+                                    for (partition, offset) in partitions {
+                                        s.insert((topic.clone(), *partition), KafkaTopicState {
+                                            topic: topic.clone(),
+                                            partition: *partition,
+                                            offset: *offset + 1,
+                                        }).await;
+                                    }
+                                }
+                            } else {
+                                let mut s = ctx.state.get_global_keyed_state('k').await;
+                                for (partition, offset) in offsets.values().flatten() {
+                                    s.insert(*partition, KafkaState {
+                                        partition: *partition,
+                                        offset: *offset + 1,
+                                    }).await;
+                                }
                             }
 
-                            if let Err(e) = consumer.commit(&topic_partitions, CommitMode::Async) {
-                                // This is just used for progress tracking for metrics, so it's not a fatal error if it
-                                // fails. The actual offset is stored in state.
-                                warn!("Failed to commit offset to Kafka {:?}", e);
+                            for (topic, partitions) in &offsets {
+                                for (partition, offset) in partitions {
+                                    // committed offsets are the next offset to read, by Kafka convention
+                                    topic_partitions.add_partition_offset(
+                                        topic, *partition, Offset::Offset(*offset + 1)).unwrap();
+                                }
+                            }
+
+                            if self.commit_offsets && topic_partitions.count() > 0 {
+                                if let Err(e) = consumer.commit(&topic_partitions, CommitMode::Async) {
+                                    // This is just used for progress tracking for metrics, so it's not a fatal error if it
+                                    // fails. The actual offset is stored in state.
+                                    warn!("Failed to commit offset to Kafka {:?}", e);
+                                }
                             }
                             if self.checkpoint(c, ctx).await {
                                 return Ok(SourceFinishType::Immediate);
@@ -309,6 +688,9 @@ where
                         Some(ControlMessage::LoadCompacted {compacted}) => {
                             ctx.load_compacted(compacted).await;
                         }
+                        Some(ControlMessage::QueryState { tx, .. }) => {
+                            ctx.reject_state_query(tx);
+                        }
                         Some(ControlMessage::NoOp) => {}
                         None => {
 
@@ -1,4 +1,5 @@
 use arrow::datatypes::{DataType, Field, Schema};
+use arroyo_state::parquet::checkpoint_url_from_env;
 use arroyo_state::{BackingStore, StateBackend};
 use rand::Rng;
 use std::time::{Duration, SystemTime};
@@ -233,6 +234,7 @@ async fn test_kafka() {
         min_epoch: 0,
         timestamp: (SystemTime::now()),
         then_stop: false,
+        unaligned: false,
     });
     reader.to_control_tx.send(barrier).await.unwrap();
     let checkpoint_completed = reader.assert_control_checkpoint(1).await;
@@ -240,29 +242,36 @@ async fn test_kafka() {
 
     reader.assert_next_message_checkpoint(1).await;
 
-    StateBackend::write_operator_checkpoint_metadata(OperatorCheckpointMetadata {
-        job_id: task_info.job_id.clone(),
-        operator_id: task_info.operator_id.clone(),
-        epoch: 1,
-        start_time: 0,
-        finish_time: 0,
-        min_watermark: Some(0),
-        max_watermark: Some(0),
-        has_state: true,
-        tables: source::tables(),
-        backend_data: checkpoint_completed.subtask_metadata.backend_data,
-        bytes: checkpoint_completed.subtask_metadata.bytes,
-    })
+    StateBackend::write_operator_checkpoint_metadata(
+        &checkpoint_url_from_env(),
+        OperatorCheckpointMetadata {
+            job_id: task_info.job_id.clone(),
+            operator_id: task_info.operator_id.clone(),
+            epoch: 1,
+            start_time: 0,
+            finish_time: 0,
+            min_watermark: Some(0),
+            max_watermark: Some(0),
+            has_state: true,
+            tables: source::tables(),
+            backend_data: checkpoint_completed.subtask_metadata.backend_data,
+            bytes: checkpoint_completed.subtask_metadata.bytes,
+            state_schema: None,
+        },
+    )
     .await;
 
-    StateBackend::write_checkpoint_metadata(CheckpointMetadata {
-        job_id: task_info.job_id.clone(),
-        epoch: 1,
-        min_epoch: 1,
-        start_time: 0,
-        finish_time: 0,
-        operator_ids: vec![task_info.operator_id.clone()],
-    })
+    StateBackend::write_checkpoint_metadata(
+        &checkpoint_url_from_env(),
+        CheckpointMetadata {
+            job_id: task_info.job_id.clone(),
+            epoch: 1,
+            min_epoch: 1,
+            start_time: 0,
+            finish_time: 0,
+            operator_ids: vec![task_info.operator_id.clone()],
+        },
+    )
     .await;
 
     reader.assert_next_message_record_value(20).await;
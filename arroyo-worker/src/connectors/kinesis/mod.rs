@@ -1,3 +1,9 @@
+use aws_config::{
+    default_provider::credentials::DefaultCredentialsChain, from_env,
+    meta::credentials::SharedCredentialsProvider, profile::ProfileFileCredentialsProvider,
+    sts::AssumeRoleProvider, SdkConfig,
+};
+use aws_sdk_kinesis::Region;
 use serde::{Deserialize, Serialize};
 use typify::import_types;
 
@@ -5,3 +11,40 @@ pub mod sink;
 pub mod source;
 
 import_types!(schema = "../connector-schemas/kinesis/table.json");
+
+/// Loads the AWS config for a Kinesis table, applying its region, profile, and
+/// (optionally) a role to assume on top of the base credentials.
+pub(crate) async fn load_aws_config(table: &KinesisTable) -> SdkConfig {
+    let mut loader = from_env();
+    if let Some(region) = &table.aws_region {
+        loader = loader.region(Region::new(region.clone()));
+    }
+
+    let base_provider = match &table.aws_profile {
+        Some(profile) => SharedCredentialsProvider::new(
+            ProfileFileCredentialsProvider::builder()
+                .profile_name(profile)
+                .build(),
+        ),
+        None => {
+            let mut chain = DefaultCredentialsChain::builder();
+            if let Some(region) = &table.aws_region {
+                chain = chain.region(Region::new(region.clone()));
+            }
+            SharedCredentialsProvider::new(chain.build().await)
+        }
+    };
+
+    let provider = match &table.assume_role_arn {
+        Some(role_arn) => {
+            let mut builder = AssumeRoleProvider::builder(role_arn).session_name("arroyo-kinesis");
+            if let Some(region) = &table.aws_region {
+                builder = builder.region(Region::new(region.clone()));
+            }
+            SharedCredentialsProvider::new(builder.build(base_provider))
+        }
+        None => base_provider,
+    };
+
+    loader.credentials_provider(provider).load().await
+}
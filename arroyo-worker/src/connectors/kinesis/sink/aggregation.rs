@@ -0,0 +1,144 @@
+//! Support for the Kinesis Producer Library (KPL) record aggregation format, which packs many
+//! user records into a single Kinesis record. See
+//! https://github.com/awslabs/amazon-kinesis-producer/blob/master/aggregation-format.md
+
+use std::collections::HashMap;
+
+use md5::{Digest, Md5};
+use prost::Message;
+
+pub const KPL_MAGIC: [u8; 4] = [0xF3, 0x89, 0x9A, 0xC2];
+
+// Kinesis records are limited to 1MiB including the partition key; leave some headroom
+// for the magic number, checksum, and protobuf framing
+const MAX_AGGREGATE_SIZE: usize = 1_000_000;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AggregatedRecord {
+    #[prost(string, repeated, tag = "1")]
+    pub partition_key_table: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub explicit_hash_key_table: Vec<String>,
+    #[prost(message, repeated, tag = "3")]
+    pub records: Vec<AggregatedUserRecord>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AggregatedUserRecord {
+    #[prost(uint64, required, tag = "1")]
+    pub partition_key_index: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub explicit_hash_key_index: Option<u64>,
+    #[prost(bytes = "vec", required, tag = "3")]
+    pub data: Vec<u8>,
+}
+
+/// Accumulates user records bound for a single shard into a KPL aggregated record.
+#[derive(Default)]
+pub struct RecordAggregator {
+    record: AggregatedRecord,
+    key_indices: HashMap<String, u64>,
+    size: usize,
+}
+
+impl RecordAggregator {
+    pub fn is_empty(&self) -> bool {
+        self.record.records.is_empty()
+    }
+
+    /// Whether a record with the given key and data can be added without exceeding the
+    /// maximum Kinesis record size.
+    pub fn fits(&self, key: &str, data: &[u8]) -> bool {
+        self.is_empty() || self.size + Self::record_size(key, data) <= MAX_AGGREGATE_SIZE
+    }
+
+    fn record_size(key: &str, data: &[u8]) -> usize {
+        // the partition key is only stored once, but we conservatively count it for each record;
+        // the 16 bytes cover the protobuf tags and varints
+        key.len() + data.len() + 16
+    }
+
+    pub fn add(&mut self, key: String, data: Vec<u8>) {
+        self.size += Self::record_size(&key, &data);
+
+        let next_index = self.key_indices.len() as u64;
+        let partition_key_index = *self.key_indices.entry(key.clone()).or_insert_with(|| {
+            self.record.partition_key_table.push(key);
+            next_index
+        });
+
+        self.record.records.push(AggregatedUserRecord {
+            partition_key_index,
+            explicit_hash_key_index: None,
+            data,
+        });
+    }
+
+    /// Serializes the aggregated record, returning the partition key to use for it (the key of
+    /// the first user record) along with the encoded data.
+    pub fn finish(self) -> (String, Vec<u8>) {
+        let partition_key = self
+            .record
+            .partition_key_table
+            .first()
+            .cloned()
+            .unwrap_or_default();
+
+        let body = self.record.encode_to_vec();
+        let mut hasher = Md5::new();
+        hasher.update(&body);
+
+        let mut data = Vec::with_capacity(KPL_MAGIC.len() + body.len() + 16);
+        data.extend_from_slice(&KPL_MAGIC);
+        data.extend_from_slice(&body);
+        data.extend_from_slice(&hasher.finalize());
+
+        (partition_key, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregation_round_trip() {
+        let mut aggregator = RecordAggregator::default();
+        aggregator.add("a".to_string(), b"one".to_vec());
+        aggregator.add("b".to_string(), b"two".to_vec());
+        aggregator.add("a".to_string(), b"three".to_vec());
+
+        let (key, data) = aggregator.finish();
+        assert_eq!(key, "a");
+        assert_eq!(&data[..4], &KPL_MAGIC);
+
+        let body = &data[4..data.len() - 16];
+        let mut hasher = Md5::new();
+        hasher.update(body);
+        assert_eq!(&data[data.len() - 16..], hasher.finalize().as_slice());
+
+        let decoded = AggregatedRecord::decode(body).unwrap();
+        assert_eq!(decoded.partition_key_table, vec!["a", "b"]);
+        assert_eq!(
+            decoded
+                .records
+                .iter()
+                .map(|r| (r.partition_key_index, r.data.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, b"one".to_vec()),
+                (1, b"two".to_vec()),
+                (0, b"three".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregation_size_limit() {
+        let mut aggregator = RecordAggregator::default();
+        let data = vec![0u8; 600_000];
+        assert!(aggregator.fits("k", &data));
+        aggregator.add("k".to_string(), data.clone());
+        assert!(!aggregator.fits("k", &data));
+    }
+}
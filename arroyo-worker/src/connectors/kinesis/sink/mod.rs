@@ -1,33 +1,43 @@
 use std::{
+    collections::HashMap,
     marker::PhantomData,
-    time::{Duration, SystemTime},
+    num::NonZeroU32,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use arroyo_macro::{process_fn, StreamNode};
 use arroyo_rpc::OperatorConfig;
 use arroyo_types::{CheckpointBarrier, Key, Record};
-use aws_config::from_env;
-use aws_sdk_kinesis::{
-    client::fluent_builders::PutRecords, model::PutRecordsRequestEntry, types::Blob,
-    Client as KinesisClient, Region,
-};
+use aws_sdk_kinesis::{model::PutRecordsRequestEntry, types::Blob, Client as KinesisClient};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use md5::{Digest, Md5};
 use serde::Serialize;
-use tracing::warn;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 use crate::{engine::Context, formats::DataSerializer, SchemaData};
 
-use super::{KinesisTable, TableType};
+use self::aggregation::RecordAggregator;
+
+use super::{load_aws_config, KinesisTable, TableType};
+
+mod aggregation;
+
+// how often to refresh the shard map used for aggregation and per-shard rate limiting
+const SHARD_MAP_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(StreamNode)]
 pub struct KinesisSinkFunc<K: Key + Serialize, T: SchemaData> {
     client: Option<KinesisClient>,
-    aws_region: Option<String>,
+    table: KinesisTable,
     in_progress_batch: Option<BatchRecordPreparer>,
     flush_config: FlushConfig,
     serializer: DataSerializer<T>,
     name: String,
+    partition_key_field: Option<String>,
+    shard_map: Option<ShardMap>,
+    rate_limiter: Option<DefaultKeyedRateLimiter<String>>,
     _phantom: PhantomData<(K, T)>,
 }
 
@@ -39,17 +49,40 @@ impl<K: Key + Serialize, T: SchemaData> KinesisSinkFunc<K, T> {
         let table: KinesisTable =
             serde_json::from_value(config.table).expect("Invalid table config for KafkaSource");
         let flush_config = FlushConfig::new_from_table(&table);
+        let TableType::Sink {
+            partition_key_field,
+            records_per_shard_per_second,
+            ..
+        } = &table.type_
+        else {
+            panic!("found non-sink kinesis config in sink operator");
+        };
+
+        // validated to be between 1 and 1000 when the table is created
+        let rate_limiter = records_per_shard_per_second.map(|rate| {
+            RateLimiter::keyed(Quota::per_second(
+                u32::try_from(rate)
+                    .ok()
+                    .and_then(NonZeroU32::new)
+                    .expect("sink.records_per_shard_per_second must be between 1 and 1000"),
+            ))
+        });
+
         Self {
             client: None,
             in_progress_batch: None,
-            aws_region: table.aws_region,
-            name: table.stream_name,
+            name: table.stream_name.clone(),
             serializer: DataSerializer::new(
                 config
                     .format
                     .expect("Format must be defined for KinesisSink"),
-            ),
+            )
+            .with_compression(config.compression),
+            partition_key_field: partition_key_field.clone(),
+            shard_map: None,
+            rate_limiter,
             flush_config,
+            table,
             _phantom: PhantomData,
         }
     }
@@ -59,44 +92,33 @@ impl<K: Key + Serialize, T: SchemaData> KinesisSinkFunc<K, T> {
     }
 
     async fn on_start(&mut self, _ctx: &mut Context<(), ()>) {
-        let mut loader = from_env();
-        if let Some(region) = &self.aws_region {
-            loader = loader.region(Region::new(region.clone()));
+        self.client = Some(KinesisClient::new(&load_aws_config(&self.table).await));
+        if self.flush_config.aggregate || self.rate_limiter.is_some() {
+            self.refresh_shard_map()
+                .await
+                .expect("failed to list shards for kinesis sink");
         }
-        self.client = Some(KinesisClient::new(&loader.load().await));
     }
 
     async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
         if let Some(batch_preparer) = self.in_progress_batch.take() {
-            batch_preparer
-                .flush()
+            self.flush_with_retries(batch_preparer)
                 .await
                 .expect("failed to flush batch during checkpoint");
         }
     }
 
     async fn process_element(&mut self, record: &Record<K, T>, _ctx: &mut Context<(), ()>) {
-        let k = record
-            .key
-            .as_ref()
-            .map(|k| serde_json::to_string(k).unwrap())
-            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let k = self.partition_key(record);
 
         let Some(v) = self.serializer.to_vec(&record.value) else {
             return;
         };
 
-        let mut batch_preparer = match self.in_progress_batch.take() {
-            None => BatchRecordPreparer::new(
-                self.client
-                    .as_ref()
-                    .unwrap()
-                    .put_records()
-                    .stream_name(self.name.clone()),
-            ),
-            Some(batch_preparer) => batch_preparer,
-        };
-        batch_preparer.add_record(k, v);
+        let shard = self.shard_map.as_ref().map(|m| m.shard_for_key(&k));
+
+        let mut batch_preparer = self.take_or_create_batch_preparer();
+        batch_preparer.add_record(k, v, shard);
         if self.flush_config.should_flush(&batch_preparer) {
             self.flush_with_retries(batch_preparer)
                 .await
@@ -107,6 +129,17 @@ impl<K: Key + Serialize, T: SchemaData> KinesisSinkFunc<K, T> {
     }
 
     async fn handle_tick(&mut self, _: u64, _ctx: &mut Context<(), ()>) {
+        if self
+            .shard_map
+            .as_ref()
+            .map(|m| m.loaded_at.elapsed() > SHARD_MAP_REFRESH_INTERVAL)
+            .unwrap_or(false)
+        {
+            if let Err(e) = self.refresh_shard_map().await {
+                warn!("failed to refresh kinesis shard map: {:?}", e);
+            }
+        }
+
         let Some(batch_preparer) = &self.in_progress_batch else {
             return;
         };
@@ -121,13 +154,56 @@ impl<K: Key + Serialize, T: SchemaData> KinesisSinkFunc<K, T> {
             .expect("failed to flush batch during tick");
     }
 
+    fn partition_key(&self, record: &Record<K, T>) -> String {
+        if let Some(field) = &self.partition_key_field {
+            let value = serde_json::to_value(&record.value)
+                .ok()
+                .and_then(|mut v| v.get_mut(field).map(|f| f.take()));
+            match value {
+                Some(serde_json::Value::String(s)) if !s.is_empty() => return s,
+                Some(serde_json::Value::Null) | None => {}
+                Some(other) => return other.to_string(),
+            }
+        }
+
+        record
+            .key
+            .as_ref()
+            .map(|k| serde_json::to_string(k).unwrap())
+            .unwrap_or_else(|| Uuid::new_v4().to_string())
+    }
+
+    async fn refresh_shard_map(&mut self) -> Result<()> {
+        let shard_map = ShardMap::load(self.client.as_ref().unwrap(), &self.name).await?;
+        info!(
+            "loaded {} open shards for kinesis stream {}",
+            shard_map.shards.len(),
+            self.name
+        );
+        self.shard_map = Some(shard_map);
+        Ok(())
+    }
+
     async fn flush_with_retries(
         &mut self,
         mut record_batch_preparer: BatchRecordPreparer,
     ) -> Result<()> {
         let mut retries = 0;
         loop {
-            let vectors_to_retry = record_batch_preparer.flush().await?;
+            let entries = record_batch_preparer.finish();
+            if let Some(rate_limiter) = &self.rate_limiter {
+                let shard_map = self.shard_map.as_ref().unwrap();
+                for entry in &entries {
+                    let shard = entry
+                        .shard
+                        .clone()
+                        .unwrap_or_else(|| shard_map.shard_for_key(&entry.partition_key).id);
+                    rate_limiter.until_key_ready(&shard).await;
+                }
+            }
+
+            let vectors_to_retry =
+                flush_entries(self.client.as_ref().unwrap(), &self.name, entries).await?;
             if vectors_to_retry.is_empty() {
                 return Ok(());
             } else {
@@ -135,33 +211,114 @@ impl<K: Key + Serialize, T: SchemaData> KinesisSinkFunc<K, T> {
                 warn!("failed to flush batch, retry attempt: {}", retries);
                 tokio::time::sleep(std::time::Duration::from_millis(2000.min(100 << retries)))
                     .await;
-                record_batch_preparer = self.take_or_create_batch_preparer().await;
-                for (k, v) in vectors_to_retry {
-                    record_batch_preparer.add_record(k, v);
+
+                record_batch_preparer = self.take_or_create_batch_preparer();
+                for entry in vectors_to_retry {
+                    record_batch_preparer.add_entry(entry);
                 }
             }
         }
     }
 
-    async fn take_or_create_batch_preparer(&mut self) -> BatchRecordPreparer {
+    fn take_or_create_batch_preparer(&mut self) -> BatchRecordPreparer {
         match self.in_progress_batch.take() {
-            None => BatchRecordPreparer::new(
-                self.client
-                    .as_ref()
-                    .unwrap()
-                    .put_records()
-                    .stream_name(self.name.clone()),
-            ),
+            None => BatchRecordPreparer::new(self.flush_config.aggregate),
             Some(batch_preparer) => batch_preparer,
         }
     }
 }
 
+/// The hash key ranges of the open shards in a stream, used to determine which shard
+/// a partition key will be written to.
+struct ShardMap {
+    shards: Vec<ShardRange>,
+    loaded_at: Instant,
+}
+
+#[derive(Clone)]
+struct ShardRange {
+    id: String,
+    starting_hash_key: u128,
+    ending_hash_key: u128,
+}
+
+impl ShardMap {
+    async fn load(client: &KinesisClient, stream_name: &str) -> Result<Self> {
+        let mut shards = vec![];
+        let mut next_token: Option<String> = None;
+        loop {
+            let call = client.list_shards();
+            let call = match next_token.take() {
+                Some(token) => call.next_token(token),
+                None => call.stream_name(stream_name),
+            };
+            let output = call.send().await?;
+
+            for shard in output.shards().unwrap_or_default() {
+                // closed shards have an ending sequence number
+                if shard
+                    .sequence_number_range()
+                    .and_then(|r| r.ending_sequence_number())
+                    .is_some()
+                {
+                    continue;
+                }
+
+                let range = shard
+                    .hash_key_range()
+                    .ok_or_else(|| anyhow!("shard is missing its hash key range"))?;
+                shards.push(ShardRange {
+                    id: shard.shard_id().unwrap_or_default().to_string(),
+                    starting_hash_key: range.starting_hash_key().unwrap_or("0").parse()?,
+                    ending_hash_key: range.ending_hash_key().unwrap_or("0").parse()?,
+                });
+            }
+
+            match output.next_token() {
+                Some(token) => next_token = Some(token.to_string()),
+                None => break,
+            }
+        }
+
+        if shards.is_empty() {
+            return Err(anyhow!("no open shards for stream {}", stream_name));
+        }
+
+        shards.sort_by_key(|s| s.starting_hash_key);
+
+        Ok(Self {
+            shards,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    fn shard_for_key(&self, partition_key: &str) -> ShardRange {
+        // Kinesis maps partition keys to shards by the MD5 hash of the key as a 128-bit integer
+        let mut hasher = Md5::new();
+        hasher.update(partition_key.as_bytes());
+        let hash = u128::from_be_bytes(hasher.finalize().into());
+
+        self.shards
+            .iter()
+            .find(|s| s.starting_hash_key <= hash && hash <= s.ending_hash_key)
+            .unwrap_or(&self.shards[0])
+            .clone()
+    }
+}
+
+#[derive(Clone)]
+struct BufferedEntry {
+    partition_key: String,
+    // when aggregating, routes the aggregated record to the shard its user records belong to
+    explicit_hash_key: Option<String>,
+    shard: Option<String>,
+    data: Vec<u8>,
+}
+
 struct BatchRecordPreparer {
-    // TODO: figure out how to not need an option
-    put_records_call: Option<PutRecords>,
-    buffered_records: Vec<(String, Vec<u8>)>,
-    record_count: usize,
+    entries: Vec<BufferedEntry>,
+    aggregators: HashMap<String, (ShardRange, RecordAggregator)>,
+    aggregate: bool,
     data_size: usize,
     creation_time: SystemTime,
 }
@@ -170,6 +327,7 @@ struct FlushConfig {
     max_record_count: usize,
     max_data_size: usize,
     max_age: Duration,
+    aggregate: bool,
 }
 
 impl FlushConfig {
@@ -178,6 +336,8 @@ impl FlushConfig {
             batch_flush_interval_millis,
             batch_max_buffer_size,
             records_per_batch,
+            aggregate_records,
+            ..
         } = &table.type_
         else {
             panic!("found non-sink kinesis config in sink operator");
@@ -186,68 +346,135 @@ impl FlushConfig {
             max_record_count: records_per_batch.unwrap_or(500) as usize,
             max_data_size: batch_max_buffer_size.unwrap_or(4_500_000) as usize,
             max_age: Duration::from_millis(batch_flush_interval_millis.unwrap_or(1000) as u64),
+            aggregate: aggregate_records.unwrap_or(false),
         }
     }
 
     fn should_flush(&self, batch_preparer: &BatchRecordPreparer) -> bool {
-        batch_preparer.record_count >= self.max_record_count
+        batch_preparer.record_count() >= self.max_record_count
             || batch_preparer.data_size >= self.max_data_size
             || batch_preparer.creation_time.elapsed().unwrap_or_default() >= self.max_age
     }
 }
 
 impl BatchRecordPreparer {
-    fn new(put_records_call: PutRecords) -> Self {
+    fn new(aggregate: bool) -> Self {
         Self {
-            put_records_call: Some(put_records_call),
-            buffered_records: Vec::new(),
-            record_count: 0,
+            entries: Vec::new(),
+            aggregators: HashMap::new(),
+            aggregate,
             data_size: 0,
             creation_time: SystemTime::now(),
         }
     }
-    fn add_record(&mut self, key: String, value: Vec<u8>) {
-        self.buffered_records.push((key.clone(), value.clone()));
-        let blob = Blob::new(value);
-        self.data_size += blob.as_ref().len();
-        let put_record_request = PutRecordsRequestEntry::builder()
-            .data(blob)
-            .partition_key(key)
-            .build();
-        self.put_records_call = self
-            .put_records_call
-            .take()
-            .map(|call| call.records(put_record_request));
-        self.record_count += 1;
-    }
-
-    async fn flush(mut self) -> Result<Vec<(String, Vec<u8>)>> {
-        if self.record_count == 0 {
-            return Ok(Vec::new());
-        }
-        let response = self.put_records_call.take().unwrap().send().await?;
-        let failed_record_count = response.failed_record_count().unwrap_or(0);
-        if failed_record_count > 0 {
-            warn!(
-                "batch write had {} failed responses out of {}",
-                failed_record_count, self.record_count
-            );
-            let records_to_retry = response
-                .records()
-                .unwrap()
-                .iter()
-                .enumerate()
-                .filter_map(|(i, record)| {
-                    if record.error_code().is_some() {
-                        Some(self.buffered_records[i].clone())
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            Ok(records_to_retry)
-        } else {
-            Ok(Vec::new())
+
+    /// The number of Kinesis records this batch will produce
+    fn record_count(&self) -> usize {
+        self.entries.len() + self.aggregators.len()
+    }
+
+    fn add_record(&mut self, key: String, value: Vec<u8>, shard: Option<ShardRange>) {
+        match (self.aggregate, shard) {
+            (true, Some(shard)) => {
+                self.data_size += value.len();
+                let full = self
+                    .aggregators
+                    .get(&shard.id)
+                    .map(|(_, aggregator)| !aggregator.fits(&key, &value))
+                    .unwrap_or(false);
+
+                if full {
+                    // the user records are already accounted for in data_size
+                    let (full_shard, aggregator) = self.aggregators.remove(&shard.id).unwrap();
+                    self.entries
+                        .push(Self::finish_aggregate(full_shard, aggregator));
+                }
+
+                self.aggregators
+                    .entry(shard.id.clone())
+                    .or_insert_with(|| (shard, RecordAggregator::default()))
+                    .1
+                    .add(key, value);
+            }
+            (_, shard) => self.add_entry(BufferedEntry {
+                partition_key: key,
+                explicit_hash_key: None,
+                shard: shard.map(|s| s.id),
+                data: value,
+            }),
         }
     }
+
+    fn add_entry(&mut self, entry: BufferedEntry) {
+        self.data_size += entry.data.len();
+        self.entries.push(entry);
+    }
+
+    fn finish_aggregate(shard: ShardRange, aggregator: RecordAggregator) -> BufferedEntry {
+        let (partition_key, data) = aggregator.finish();
+        BufferedEntry {
+            partition_key,
+            explicit_hash_key: Some(shard.starting_hash_key.to_string()),
+            shard: Some(shard.id),
+            data,
+        }
+    }
+
+    /// Closes any open aggregates and returns the entries to write
+    fn finish(mut self) -> Vec<BufferedEntry> {
+        for (_, (shard, aggregator)) in self.aggregators.drain() {
+            if !aggregator.is_empty() {
+                self.entries.push(Self::finish_aggregate(shard, aggregator));
+            }
+        }
+        self.entries
+    }
+}
+
+/// Writes the entries to Kinesis, returning any that failed and should be retried
+async fn flush_entries(
+    client: &KinesisClient,
+    stream_name: &str,
+    entries: Vec<BufferedEntry>,
+) -> Result<Vec<BufferedEntry>> {
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut put_records_call = client.put_records().stream_name(stream_name);
+    for entry in &entries {
+        put_records_call = put_records_call.records(
+            PutRecordsRequestEntry::builder()
+                .data(Blob::new(entry.data.clone()))
+                .partition_key(&entry.partition_key)
+                .set_explicit_hash_key(entry.explicit_hash_key.clone())
+                .build(),
+        );
+    }
+
+    let response = put_records_call.send().await?;
+    let failed_record_count = response.failed_record_count().unwrap_or(0);
+    if failed_record_count > 0 {
+        warn!(
+            "batch write had {} failed responses out of {}",
+            failed_record_count,
+            entries.len()
+        );
+        let records_to_retry = response
+            .records()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, record)| {
+                if record.error_code().is_some() {
+                    Some(entries[i].clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok(records_to_retry)
+    } else {
+        Ok(Vec::new())
+    }
 }
@@ -1,5 +1,5 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     fmt::Debug,
     hash::{Hash, Hasher},
     marker::PhantomData,
@@ -14,18 +14,21 @@ use arroyo_rpc::{
     ControlMessage, OperatorConfig,
 };
 use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
-use arroyo_types::{from_nanos, Data, Record, UserError};
-use aws_config::from_env;
+use arroyo_types::{from_millis, from_nanos, Data, Record, UserError};
 use aws_sdk_kinesis::{
     client::fluent_builders::GetShardIterator,
-    model::{Shard, ShardIteratorType},
-    output::GetRecordsOutput,
+    model::{
+        ConsumerStatus, Record as KinesisRecord, Shard, ShardIteratorType, StartingPosition,
+        SubscribeToShardEvent, SubscribeToShardEventStream,
+    },
+    output::{GetRecordsOutput, SubscribeToShardOutput},
     types::SdkError,
-    Client as KinesisClient, Region,
+    Client as KinesisClient,
 };
 use bincode::{Decode, Encode};
 use futures::stream::StreamExt;
 use futures::{stream::FuturesUnordered, Future};
+use md5::{Digest, Md5};
 use tokio::{
     select,
     time::{Duration, MissedTickBehavior},
@@ -35,7 +38,7 @@ use tracing::{debug, info, warn};
 use crate::formats::DataDeserializer;
 use crate::{engine::Context, SchemaData, SourceFinishType};
 
-use super::{KinesisTable, SourceOffset, TableType};
+use super::{load_aws_config, KinesisTable, SourceOffset, TableType};
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd)]
 pub enum KinesisOffset {
@@ -49,22 +52,109 @@ pub struct KinesisSourceFunc<K: Data, T: SchemaData> {
     stream_name: String,
     deserializer: DataDeserializer<T>,
     kinesis_client: Option<KinesisClient>,
-    aws_region: Option<String>,
+    // set when reading with enhanced fan-out
+    consumer_arn: Option<String>,
+    table: KinesisTable,
+    // keyed by `ShardState::key()`, as a merged shard may be read by more than one subtask
     shards: HashMap<String, ShardState>,
     config: KinesisSourceConfig,
+    // the shards that existed when the source was first started, which are read from the
+    // configured offset; any shards created after that are the result of resharding and are
+    // read from the start. None until the first sync, and empty when restored from a checkpoint.
+    initial_shards: Option<HashSet<String>>,
     _phantom: PhantomData<K>,
 }
 
 struct KinesisSourceConfig {
     read_mode: SourceOffset,
+    start_timestamp: Option<SystemTime>,
+    fan_out_consumer: Option<String>,
 }
 
 impl KinesisSourceConfig {
     fn new_from_table(table: &KinesisTable) -> Self {
-        let TableType::Source { offset: read_mode } = table.type_ else {
+        let TableType::Source {
+            offset: read_mode,
+            start_timestamp_millis,
+            fan_out_consumer,
+        } = &table.type_
+        else {
             panic!("found non-source kinesis table in KinesisSource");
         };
-        Self { read_mode }
+        Self {
+            read_mode: *read_mode,
+            start_timestamp: start_timestamp_millis.map(|t| from_millis(t.max(0) as u64)),
+            fan_out_consumer: fan_out_consumer.clone(),
+        }
+    }
+
+    fn initial_offset(&self) -> KinesisOffset {
+        match self.read_mode {
+            SourceOffset::Earliest => KinesisOffset::Earliest,
+            SourceOffset::Latest => KinesisOffset::Latest,
+            SourceOffset::Timestamp => KinesisOffset::Timestamp(
+                self.start_timestamp
+                    .expect("start_timestamp_millis must be set for timestamp offsets"),
+            ),
+        }
+    }
+}
+
+/// An inclusive range of the 128-bit hash keys that Kinesis maps partition keys onto
+#[derive(Clone, Copy, Debug, Encode, Decode, PartialEq, Eq, PartialOrd)]
+struct HashRange {
+    start: u128,
+    end: u128,
+}
+
+impl HashRange {
+    fn for_shard(shard: &Shard) -> Result<Self> {
+        let range = shard
+            .hash_key_range()
+            .ok_or_else(|| anyhow!("shard is missing its hash key range"))?;
+        Ok(Self {
+            start: range.starting_hash_key().unwrap_or("0").parse()?,
+            end: range.ending_hash_key().unwrap_or("0").parse()?,
+        })
+    }
+
+    fn contains(&self, hash_key: u128) -> bool {
+        self.start <= hash_key && hash_key <= self.end
+    }
+
+    fn intersect(&self, other: &HashRange) -> Option<HashRange> {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        (start <= end).then_some(HashRange { start, end })
+    }
+}
+
+/// Kinesis maps partition keys to shards by the MD5 hash of the key as a 128-bit integer
+fn hash_key(partition_key: &str) -> u128 {
+    let mut hasher = Md5::new();
+    hasher.update(partition_key.as_bytes());
+    u128::from_be_bytes(hasher.finalize().into())
+}
+
+fn assigned(key: &str, parallelism: usize, task_index: usize) -> bool {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % parallelism == task_index
+}
+
+/// Whether a reader of part of a shard should emit a record with the given partition key.
+/// Records written with an explicit hash key may fall outside of the range their partition key
+/// hashes to; those are emitted by the reader of the start of the shard, so that each record is
+/// emitted by exactly one reader.
+fn emits(reader: &HashRange, shard: &HashRange, partition_key: &str) -> bool {
+    if reader == shard {
+        return true;
+    }
+    let hash_key = hash_key(partition_key);
+    if shard.contains(hash_key) {
+        reader.contains(hash_key)
+    } else {
+        reader.contains(shard.start)
     }
 }
 
@@ -74,42 +164,61 @@ struct ShardState {
     shard_id: String,
     offset: KinesisOffset,
     closed: bool,
+    // the full hash key range of the shard
+    shard_range: HashRange,
+    // the part of the shard's range that this reader emits. After a merge, the child shard is
+    // read by the readers of both parents, each emitting the keys from its parent's range.
+    hash_range: HashRange,
 }
 
-impl TryFrom<(String, Shard, SourceOffset)> for ShardState {
-    type Error = anyhow::Error;
-    fn try_from(
-        (stream_name, shard, source_offset): (String, Shard, SourceOffset),
-    ) -> Result<Self> {
-        let shard_id = shard
-            .shard_id()
-            .map(|s| s.to_string())
-            .ok_or_else(|| anyhow!("missing_shard_id"))?;
-        let offset = match source_offset {
-            SourceOffset::Earliest => KinesisOffset::Earliest,
-            SourceOffset::Latest => KinesisOffset::Latest,
-        };
-        Ok(Self {
-            stream_name,
-            shard_id,
-            offset,
-            closed: false,
-        })
-    }
-}
 type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
 impl ShardState {
-    fn new(stream_name: String, shard: Shard, source_offset: SourceOffset) -> Self {
+    fn new(
+        stream_name: String,
+        shard_id: String,
+        offset: KinesisOffset,
+        shard_range: HashRange,
+        hash_range: HashRange,
+    ) -> Self {
         Self {
             stream_name,
-            shard_id: shard.shard_id().unwrap().to_string(),
-            offset: match source_offset {
-                SourceOffset::Earliest => KinesisOffset::Earliest,
-                SourceOffset::Latest => KinesisOffset::Latest,
-            },
+            shard_id,
+            offset,
             closed: false,
+            shard_range,
+            hash_range,
+        }
+    }
+
+    fn key(&self) -> String {
+        format!("{}:{}", self.shard_id, self.hash_range.start)
+    }
+
+    /// Returns the children of this shard that its reader should start reading once it has
+    /// been read to its end, restricted to the part of the key space this reader emits. By
+    /// handing children to the readers of their parents (rather than assigning them to subtasks
+    /// independently) every key is read in order across splits and merges.
+    fn children(&self, shards: &[Shard]) -> Result<Vec<(String, HashRange, HashRange)>> {
+        let mut children = vec![];
+        for shard in shards {
+            let is_child = [shard.parent_shard_id(), shard.adjacent_parent_shard_id()]
+                .into_iter()
+                .flatten()
+                .any(|parent| parent == self.shard_id);
+            if !is_child {
+                continue;
+            }
+            let shard_range = HashRange::for_shard(shard)?;
+            if let Some(hash_range) = self.hash_range.intersect(&shard_range) {
+                children.push((
+                    shard.shard_id().unwrap().to_string(),
+                    shard_range,
+                    hash_range,
+                ));
+            }
         }
+        Ok(children)
     }
     fn get_update_shard_iterator_future(
         &self,
@@ -126,15 +235,15 @@ impl ShardState {
             KinesisOffset::Latest => {
                 shard_iterator_call.shard_iterator_type(ShardIteratorType::Latest)
             }
+            // we store the last sequence number we've processed, so resume after it
             KinesisOffset::SequenceNumber(sequence_number) => shard_iterator_call
-                .shard_iterator_type(ShardIteratorType::AtSequenceNumber)
+                .shard_iterator_type(ShardIteratorType::AfterSequenceNumber)
                 .starting_sequence_number(sequence_number.clone()),
             KinesisOffset::Timestamp(timestamp) => shard_iterator_call
                 .shard_iterator_type(ShardIteratorType::AtTimestamp)
                 .timestamp((*timestamp).into()),
         };
-        let shard_id = self.shard_id.clone();
-        Box::pin(AsyncNamedResult::wrap_future(shard_id, async move {
+        Box::pin(AsyncNamedResult::wrap_future(self.key(), async move {
             Ok(AsyncResult::ShardIteratorIdUpdate(
                 shard_iterator_call
                     .send()
@@ -145,6 +254,169 @@ impl ShardState {
             ))
         }))
     }
+
+    fn starting_position(&self) -> StartingPosition {
+        let builder = StartingPosition::builder();
+        match &self.offset {
+            KinesisOffset::Earliest => builder.r#type(ShardIteratorType::TrimHorizon),
+            KinesisOffset::Latest => builder.r#type(ShardIteratorType::Latest),
+            KinesisOffset::SequenceNumber(sequence_number) => builder
+                .r#type(ShardIteratorType::AfterSequenceNumber)
+                .sequence_number(sequence_number.clone()),
+            KinesisOffset::Timestamp(timestamp) => builder
+                .r#type(ShardIteratorType::AtTimestamp)
+                .timestamp((*timestamp).into()),
+        }
+        .build()
+    }
+
+    /// Subscribes to the shard with enhanced fan-out. Subscriptions expire after five minutes,
+    /// at which point this is called again to resubscribe from the current offset.
+    fn get_subscribe_future(
+        &self,
+        kinesis_client: &KinesisClient,
+        consumer_arn: &str,
+    ) -> BoxedFuture<AsyncNamedResult<AsyncResult>> {
+        let subscribe_call = kinesis_client
+            .subscribe_to_shard()
+            .consumer_arn(consumer_arn)
+            .shard_id(&self.shard_id)
+            .starting_position(self.starting_position());
+        Box::pin(AsyncNamedResult::wrap_future(self.key(), async move {
+            let subscription = subscribe_call
+                .send()
+                .await
+                .context("failed to subscribe to shard")?;
+            next_fan_out_event(subscription).await
+        }))
+    }
+}
+
+/// Hands the children of each closed shard in `shards` to its reader, returning the ones to start
+/// reading. A closed shard is dropped once its children are listed, so that it's no longer
+/// written to the source's state on checkpoints.
+fn hand_off_children(
+    shards: &mut HashMap<String, ShardState>,
+    listed: &[Shard],
+    offset: impl Fn(&str) -> KinesisOffset,
+) -> Result<Vec<ShardState>> {
+    let mut handed_off = vec![];
+    let mut children = vec![];
+    for (key, parent) in shards.iter() {
+        if !parent.closed {
+            continue;
+        }
+
+        let parent_children = parent.children(listed)?;
+        // the children of a shard that was just closed may not be listed yet
+        if parent_children.is_empty() {
+            continue;
+        }
+
+        for (shard_id, shard_range, hash_range) in parent_children {
+            children.push(ShardState::new(
+                parent.stream_name.clone(),
+                shard_id.clone(),
+                offset(&shard_id),
+                shard_range,
+                hash_range,
+            ));
+        }
+        handed_off.push(key.clone());
+    }
+
+    for key in handed_off {
+        shards.remove(&key);
+    }
+
+    let mut started = vec![];
+    for shard_state in children {
+        let key = shard_state.key();
+        if shards.contains_key(&key) {
+            continue;
+        }
+        shards.insert(key, shard_state.clone());
+        started.push(shard_state);
+    }
+    Ok(started)
+}
+
+async fn next_fan_out_event(mut subscription: SubscribeToShardOutput) -> Result<AsyncResult> {
+    match subscription
+        .event_stream
+        .recv()
+        .await
+        .context("failed to read from shard subscription")?
+    {
+        Some(SubscribeToShardEventStream::SubscribeToShardEvent(event)) => {
+            Ok(AsyncResult::FanOutEvent(Box::new(subscription), event))
+        }
+        #[allow(unreachable_patterns)]
+        Some(other) => bail!("unexpected event from shard subscription: {:?}", other),
+        None => Ok(AsyncResult::NeedNewIterator),
+    }
+}
+
+/// Registers an enhanced fan-out consumer for the stream (or finds the existing one) and waits
+/// for it to become active, returning its ARN.
+async fn register_consumer(
+    client: &KinesisClient,
+    stream_name: &str,
+    consumer_name: &str,
+) -> Result<String> {
+    let stream_arn = client
+        .describe_stream_summary()
+        .stream_name(stream_name)
+        .send()
+        .await
+        .context("failed to describe stream")?
+        .stream_description_summary()
+        .and_then(|s| s.stream_arn())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("no ARN returned for stream {}", stream_name))?;
+
+    if let Err(error) = client
+        .register_stream_consumer()
+        .stream_arn(&stream_arn)
+        .consumer_name(consumer_name)
+        .send()
+        .await
+    {
+        match &error {
+            // already registered, possibly by another subtask
+            SdkError::ServiceError { err, .. } if err.is_resource_in_use_exception() => {}
+            _ => {
+                return Err(anyhow!(error).context("failed to register stream consumer"));
+            }
+        }
+    }
+
+    // newly-registered consumers take a few seconds to become active
+    for _ in 0..60 {
+        let description = client
+            .describe_stream_consumer()
+            .stream_arn(&stream_arn)
+            .consumer_name(consumer_name)
+            .send()
+            .await
+            .context("failed to describe stream consumer")?;
+
+        if let Some(consumer) = description.consumer_description() {
+            if consumer.consumer_status() == Some(&ConsumerStatus::Active) {
+                return consumer
+                    .consumer_arn()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| anyhow!("no ARN returned for consumer {}", consumer_name));
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    bail!(
+        "timed out waiting for stream consumer {} to become active",
+        consumer_name
+    )
 }
 
 struct AsyncNamedResult<T: Debug> {
@@ -169,6 +441,9 @@ enum AsyncResult {
     // returns the new shard iterator id. Should always initialize a read after receiving this, if it is not None.
     ShardIteratorIdUpdate(Option<String>),
     GetRecords(GetRecordsOutput),
+    // an event from an enhanced fan-out subscription, along with the subscription to read the next one from
+    FanOutEvent(Box<SubscribeToShardOutput>, SubscribeToShardEvent),
+    // the iterator or subscription has expired and needs to be recreated from the current offset
     NeedNewIterator,
 }
 
@@ -182,17 +457,22 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
         let kinesis_config = KinesisSourceConfig::new_from_table(&table);
 
         Self {
-            stream_name: table.stream_name,
+            stream_name: table.stream_name.clone(),
             kinesis_client: None,
-            aws_region: table.aws_region,
+            consumer_arn: None,
             config: kinesis_config,
             shards: HashMap::new(),
+            initial_shards: None,
             deserializer: DataDeserializer::new(
                 config
                     .format
                     .expect("format must be set for kinesis source"),
                 config.framing,
-            ),
+            )
+            .with_bad_data(config.bad_data)
+            .with_compression(config.compression)
+            .with_rate_limit(config.rate_limit),
+            table,
             _phantom: PhantomData,
         }
     }
@@ -211,17 +491,6 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
         }
     }
 
-    fn update_shard(&mut self, shard_state: ShardState) {
-        let current_state = self.shards.get_mut(&shard_state.shard_id);
-        match current_state {
-            Some(_current_state) => {}
-            None => {
-                self.shards
-                    .insert(shard_state.shard_id.clone(), shard_state);
-            }
-        }
-    }
-
     /// Initializes the shards for the operator. First shards are read out of state,
     /// then `sync_shards()` is called to find any new shards.
     /// It returns a future for each shard to fetch the next shard iterator id.
@@ -232,21 +501,22 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
         let mut futures = Vec::new();
         let mut s: GlobalKeyedState<String, ShardState, _> =
             ctx.state.get_global_keyed_state('k').await;
-        for (shard_id, shard_state) in s
-            .get_all()
+        let restored = s.get_all();
+        // if we've restored from a checkpoint, any shards we don't know about are new
+        if !restored.is_empty() {
+            self.initial_shards = Some(HashSet::new());
+        }
+        for (key, shard_state) in restored
             .into_iter()
-            .map(|shard_state| (shard_state.shard_id.clone(), shard_state.clone()))
-            .filter(|(shard_id, _shard_state)| {
-                let mut hasher = DefaultHasher::new();
-                shard_id.hash(&mut hasher);
-                let shard_hash = hasher.finish() as usize;
-                shard_hash % ctx.task_info.parallelism == ctx.task_info.task_index
+            .map(|shard_state| (shard_state.key(), shard_state.clone()))
+            .filter(|(key, _shard_state)| {
+                assigned(key, ctx.task_info.parallelism, ctx.task_info.task_index)
             })
         {
-            futures.push(
-                shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
-            );
-            self.shards.insert(shard_id, shard_state);
+            if !shard_state.closed {
+                futures.push(self.start_shard_future(&shard_state));
+            }
+            self.shards.insert(key, shard_state);
         }
         let new_futures = self.sync_shards(ctx).await?;
         futures.extend(new_futures.into_iter());
@@ -260,30 +530,34 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
 
     async fn handle_async_result_split(
         &mut self,
-        shard_id: String,
+        key: String,
         async_result: AsyncResult,
         ctx: &mut Context<(), T>,
     ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
         match async_result {
             AsyncResult::ShardIteratorIdUpdate(new_shard_iterator) => {
-                self.handle_shard_iterator_id_update(shard_id, new_shard_iterator)
+                self.handle_shard_iterator_id_update(key, new_shard_iterator)
                     .await
             }
             AsyncResult::GetRecords(get_records) => {
-                self.handle_get_records(shard_id, get_records, ctx).await
+                self.handle_get_records(key, get_records, ctx).await
             }
-            AsyncResult::NeedNewIterator => self.handle_need_new_iterator(shard_id).await,
+            AsyncResult::FanOutEvent(subscription, event) => {
+                self.handle_fan_out_event(key, subscription, event, ctx)
+                    .await
+            }
+            AsyncResult::NeedNewIterator => self.handle_need_new_iterator(key).await,
         }
     }
 
     async fn handle_shard_iterator_id_update(
         &mut self,
-        shard_id: String,
+        key: String,
         shard_iterator_id: Option<String>,
     ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
-        let shard_state = self.shards.get_mut(&shard_id).unwrap();
+        let shard_state = self.shards.get_mut(&key).unwrap();
         match shard_iterator_id {
-            Some(shard_iterator) => Ok(Some(self.next_read_future(shard_id, shard_iterator))),
+            Some(shard_iterator) => Ok(Some(self.next_read_future(key, shard_iterator))),
             None => {
                 shard_state.closed = true;
                 Ok(None)
@@ -293,11 +567,11 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
 
     fn next_read_future(
         &mut self,
-        shard_id: String,
+        key: String,
         shard_iterator_id: String,
     ) -> BoxedFuture<AsyncNamedResult<AsyncResult>> {
         Box::pin(AsyncNamedResult::wrap_future(
-            shard_id,
+            key,
             Self::read_data_from_shard_iterator(
                 self.kinesis_client.as_ref().unwrap().clone(),
                 shard_iterator_id,
@@ -341,7 +615,7 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
 
     async fn handle_get_records(
         &mut self,
-        shard_id: String,
+        key: String,
         get_records: GetRecordsOutput,
         ctx: &mut Context<(), T>,
     ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
@@ -351,15 +625,17 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
                 .map(|record| record.sequence_number().unwrap().to_owned())
         });
 
-        let next_shard_iterator = self.process_records(get_records, ctx).await?;
-        let shard_state = self.shards.get_mut(&shard_id).unwrap();
+        let next_shard_iterator = get_records.next_shard_iterator;
+        self.process_records(&key, get_records.records.unwrap_or_default(), ctx)
+            .await?;
+        let shard_state = self.shards.get_mut(&key).unwrap();
 
         if let Some(last_sequence_number) = last_sequence_number {
             shard_state.offset = KinesisOffset::SequenceNumber(last_sequence_number.to_string());
         }
 
         match next_shard_iterator {
-            Some(shard_iterator_id) => Ok(Some(self.next_read_future(shard_id, shard_iterator_id))),
+            Some(shard_iterator_id) => Ok(Some(self.next_read_future(key, shard_iterator_id))),
             None => {
                 shard_state.closed = true;
                 Ok(None)
@@ -368,20 +644,65 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
     }
     async fn handle_need_new_iterator(
         &mut self,
-        shard_id: String,
+        key: String,
+    ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
+        let shard_state = self.shards.get(&key).unwrap();
+        Ok(Some(self.start_shard_future(shard_state)))
+    }
+
+    /// Returns a future that starts reading from the shard at its current offset, either by
+    /// fetching a shard iterator or, in enhanced fan-out mode, by subscribing to it.
+    fn start_shard_future(
+        &self,
+        shard_state: &ShardState,
+    ) -> BoxedFuture<AsyncNamedResult<AsyncResult>> {
+        let client = self.kinesis_client.as_ref().unwrap();
+        match &self.consumer_arn {
+            Some(consumer_arn) => shard_state.get_subscribe_future(client, consumer_arn),
+            None => shard_state.get_update_shard_iterator_future(client),
+        }
+    }
+
+    async fn handle_fan_out_event(
+        &mut self,
+        key: String,
+        subscription: Box<SubscribeToShardOutput>,
+        event: SubscribeToShardEvent,
+        ctx: &mut Context<(), T>,
     ) -> Result<Option<BoxedFuture<AsyncNamedResult<AsyncResult>>>, UserError> {
-        let shard_state = self.shards.get_mut(&shard_id).unwrap();
-        Ok(Some(shard_state.get_update_shard_iterator_future(
-            self.kinesis_client.as_ref().unwrap(),
-        )))
+        self.process_records(&key, event.records.unwrap_or_default(), ctx)
+            .await?;
+        let shard_state = self.shards.get_mut(&key).unwrap();
+
+        // the continuation sequence number is where we should resume from, and is only
+        // missing once the shard has been closed and fully read
+        match event.continuation_sequence_number {
+            Some(continuation) => {
+                shard_state.offset = KinesisOffset::SequenceNumber(continuation);
+                Ok(Some(Box::pin(AsyncNamedResult::wrap_future(
+                    key,
+                    next_fan_out_event(*subscription),
+                ))))
+            }
+            None => {
+                shard_state.closed = true;
+                Ok(None)
+            }
+        }
     }
 
-    async fn init_client(&mut self) {
-        let mut loader = from_env();
-        if let Some(region) = &self.aws_region {
-            loader = loader.region(Region::new(region.clone()));
+    async fn init_client(&mut self) -> Result<()> {
+        let client = KinesisClient::new(&load_aws_config(&self.table).await);
+        if let Some(consumer_name) = &self.config.fan_out_consumer {
+            let consumer_arn = register_consumer(&client, &self.stream_name, consumer_name).await?;
+            info!(
+                "reading from {} with enhanced fan-out consumer {}",
+                self.stream_name, consumer_arn
+            );
+            self.consumer_arn = Some(consumer_arn);
         }
-        self.kinesis_client = Some(KinesisClient::new(&loader.load().await));
+        self.kinesis_client = Some(client);
+        Ok(())
     }
 
     /// Runs the Kinesis source, handling incoming records and control messages.
@@ -392,7 +713,9 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
     /// * An interval that periodically polls for new shards, initializing their futures.
     /// * Polling off of the control queue, to perform checkpointing and stop the operator.
     async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
-        self.init_client().await;
+        self.init_client()
+            .await
+            .map_err(|e| UserError::new("failed to initialize Kinesis client", e.to_string()))?;
         let starting_futures = self
             .init_shards(ctx)
             .await
@@ -406,8 +729,8 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
         loop {
             select! {
                 result = futures.select_next_some() => {
-                    let shard_id = result.name;
-                    match self.handle_async_result_split(shard_id,
+                    let key = result.name;
+                    match self.handle_async_result_split(key,
                         result.result.map_err(|e| UserError::new("Fatal Kinesis error", e.to_string()))?, ctx).await? {
                         Some(future) => {
                             futures.push(future);
@@ -431,8 +754,8 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
                         Some(ControlMessage::Checkpoint(c)) => {
                             debug!("starting checkpointing {}", ctx.task_info.task_index);
                             let mut s = ctx.state.get_global_keyed_state('k').await;
-                            for (shard_id, shard_state) in &self.shards {
-                                s.insert(shard_id.clone(), shard_state.clone()).await;
+                            for (key, shard_state) in &self.shards {
+                                s.insert(key.clone(), shard_state.clone()).await;
                             }
                             if self.checkpoint(c, ctx).await {
                                 return Ok(SourceFinishType::Immediate);
@@ -456,6 +779,9 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
                         Some(ControlMessage::LoadCompacted { compacted }) => {
                             ctx.load_compacted(compacted).await;
                         },
+                        Some(ControlMessage::QueryState { tx, .. }) => {
+                            ctx.reject_state_query(tx);
+                        }
                         Some(ControlMessage::NoOp ) => {}
                         None => {
 
@@ -468,52 +794,117 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
 
     async fn process_records(
         &mut self,
-        get_records_output: GetRecordsOutput,
+        key: &str,
+        records: Vec<KinesisRecord>,
         ctx: &mut Context<(), T>,
-    ) -> Result<Option<String>, UserError> {
-        let records = get_records_output.records.unwrap_or_default();
+    ) -> Result<(), UserError> {
+        let shard_state = self.shards.get(key).unwrap();
+        let (hash_range, shard_range) = (shard_state.hash_range, shard_state.shard_range);
+
         for record in records {
+            if !emits(
+                &hash_range,
+                &shard_range,
+                record.partition_key().unwrap_or_default(),
+            ) {
+                continue;
+            }
+
             let data = record.data.unwrap().into_inner();
 
             let timestamp = record.approximate_arrival_timestamp.unwrap();
+            self.deserializer.throttle(&data).await;
             let iter = self.deserializer.deserialize_slice(&data);
             for value in iter {
+                let value = match value {
+                    Ok(value) => value,
+                    Err(e) => match self
+                        .deserializer
+                        .handle_bad_data(&ctx.task_info, &data, e)?
+                    {
+                        Some(e) => return Err(e),
+                        None => continue,
+                    },
+                };
+
                 let output_record = Record {
                     timestamp: from_nanos(timestamp.as_nanos() as u128),
                     key: None,
-                    value: value?,
+                    value,
                 };
                 ctx.collect(output_record).await;
             }
         }
-        Ok(get_records_output.next_shard_iterator)
+        Ok(())
     }
 
+    /// Starts reading any shards this subtask has become responsible for. On the first sync of a
+    /// new pipeline the shards without parents are distributed between subtasks; after that, new
+    /// shards are only picked up by the readers of their parents once those have been read to
+    /// their ends, which preserves the order of each key across splits and merges.
     async fn sync_shards(
         &mut self,
         ctx: &mut Context<(), T>,
     ) -> Result<Vec<BoxedFuture<AsyncNamedResult<AsyncResult>>>> {
+        let listed = self.get_splits().await?;
         let mut futures = Vec::new();
-        for shard in self.get_splits().await? {
-            // check hash
-            let shard_id = shard.shard_id().unwrap().to_string();
-            let mut hasher = DefaultHasher::new();
-            shard_id.hash(&mut hasher);
-            let shard_hash = hasher.finish() as usize;
-
-            if self.shards.contains_key(&shard_id)
-                || shard_hash % ctx.task_info.parallelism != ctx.task_info.task_index
-            {
-                continue;
+
+        if self.initial_shards.is_none() {
+            let shard_ids: HashSet<String> = listed
+                .iter()
+                .map(|shard| shard.shard_id().unwrap().to_string())
+                .collect();
+
+            // parents that have expired past the stream's retention aren't listed
+            for shard in &listed {
+                let has_parent = [shard.parent_shard_id(), shard.adjacent_parent_shard_id()]
+                    .into_iter()
+                    .flatten()
+                    .any(|parent| shard_ids.contains(parent));
+                let shard_id = shard.shard_id().unwrap().to_string();
+                if has_parent
+                    || !assigned(
+                        &shard_id,
+                        ctx.task_info.parallelism,
+                        ctx.task_info.task_index,
+                    )
+                {
+                    continue;
+                }
+
+                let range = HashRange::for_shard(shard)?;
+                let shard_state = ShardState::new(
+                    self.stream_name.clone(),
+                    shard_id,
+                    self.config.initial_offset(),
+                    range,
+                    range,
+                );
+                futures.push(self.start_shard_future(&shard_state));
+                self.shards.insert(shard_state.key(), shard_state);
+            }
+
+            self.initial_shards = Some(shard_ids);
+        }
+
+        let initial_shards = self.initial_shards.as_ref().unwrap();
+        let initial_offset = self.config.initial_offset();
+        let children = hand_off_children(&mut self.shards, &listed, |shard_id| {
+            if initial_shards.contains(shard_id) {
+                initial_offset.clone()
+            } else {
+                KinesisOffset::Earliest
             }
-            let shard_state =
-                ShardState::new(self.stream_name.clone(), shard, self.config.read_mode);
+        })?;
 
-            futures.push(
-                shard_state.get_update_shard_iterator_future(self.kinesis_client.as_ref().unwrap()),
+        for shard_state in children {
+            debug!(
+                "starting shard {} after its parent was closed",
+                shard_state.shard_id
             );
-            self.shards.insert(shard_id, shard_state);
+            futures.push(self.start_shard_future(&shard_state));
         }
+
         Ok(futures)
     }
 
@@ -560,3 +951,187 @@ impl<K: Data, T: SchemaData> KinesisSourceFunc<K, T> {
         Ok(shard_collect)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_kinesis::model::HashKeyRange;
+
+    use super::*;
+
+    fn shard(id: &str, parents: &[&str], start: u128, end: u128) -> Shard {
+        Shard::builder()
+            .shard_id(id)
+            .set_parent_shard_id(parents.first().map(|p| p.to_string()))
+            .set_adjacent_parent_shard_id(parents.get(1).map(|p| p.to_string()))
+            .hash_key_range(
+                HashKeyRange::builder()
+                    .starting_hash_key(start.to_string())
+                    .ending_hash_key(end.to_string())
+                    .build(),
+            )
+            .build()
+    }
+
+    fn state(id: &str, shard_range: HashRange, hash_range: HashRange) -> ShardState {
+        let mut state = ShardState::new(
+            "stream".to_string(),
+            id.to_string(),
+            KinesisOffset::Earliest,
+            shard_range,
+            hash_range,
+        );
+        state.closed = true;
+        state
+    }
+
+    fn range(start: u128, end: u128) -> HashRange {
+        HashRange { start, end }
+    }
+
+    #[test]
+    fn test_hash_range() {
+        let r = range(10, 20);
+        assert!(r.contains(10));
+        assert!(r.contains(20));
+        assert!(!r.contains(21));
+
+        assert_eq!(Some(range(15, 20)), r.intersect(&range(15, 30)));
+        assert_eq!(Some(range(20, 20)), r.intersect(&range(20, 30)));
+        assert_eq!(None, r.intersect(&range(21, 30)));
+
+        let parsed = HashRange::for_shard(&shard("a", &[], 0, u128::MAX)).unwrap();
+        assert_eq!(range(0, u128::MAX), parsed);
+    }
+
+    #[test]
+    fn test_emits() {
+        let full = range(0, u128::MAX);
+        let key = "some-key";
+        let h = hash_key(key);
+
+        assert!(emits(&full, &full, key));
+
+        // a merged shard is read by two readers, each emitting the keys in its own range
+        let lower = range(0, h);
+        let upper = range(h + 1, u128::MAX);
+        assert!(emits(&lower, &full, key));
+        assert!(!emits(&upper, &full, key));
+
+        // records whose key hashes outside of the shard are emitted by the reader of its start
+        let shard = range(h + 1, u128::MAX);
+        let first = range(h + 1, h + 10);
+        let rest = range(h + 11, u128::MAX);
+        assert!(emits(&first, &shard, key));
+        assert!(!emits(&rest, &shard, key));
+    }
+
+    #[test]
+    fn test_split_children() {
+        let parent = state("parent", range(0, 99), range(0, 99));
+        let shards = vec![
+            shard("parent", &[], 0, 99),
+            shard("left", &["parent"], 0, 49),
+            shard("right", &["parent"], 50, 99),
+            shard("other", &[], 100, 199),
+        ];
+
+        assert_eq!(
+            vec![
+                ("left".to_string(), range(0, 49), range(0, 49)),
+                ("right".to_string(), range(50, 99), range(50, 99)),
+            ],
+            parent.children(&shards).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge_children() {
+        let left = state("left", range(0, 49), range(0, 49));
+        let right = state("right", range(50, 99), range(50, 99));
+        let shards = vec![
+            shard("left", &[], 0, 49),
+            shard("right", &[], 50, 99),
+            shard("merged", &["left", "right"], 0, 99),
+        ];
+
+        // each parent's reader reads its part of the merged shard
+        assert_eq!(
+            vec![("merged".to_string(), range(0, 99), range(0, 49))],
+            left.children(&shards).unwrap()
+        );
+        assert_eq!(
+            vec![("merged".to_string(), range(0, 99), range(50, 99))],
+            right.children(&shards).unwrap()
+        );
+
+        // and a later split of the merged shard only gives each reader its own part of it
+        let merged = left.children(&shards).unwrap().remove(0);
+        let merged = state(&merged.0, merged.1, merged.2);
+        let shards = vec![
+            shard("merged", &["left", "right"], 0, 99),
+            shard("a", &["merged"], 0, 24),
+            shard("b", &["merged"], 25, 74),
+            shard("c", &["merged"], 75, 99),
+        ];
+        assert_eq!(
+            vec![
+                ("a".to_string(), range(0, 24), range(0, 24)),
+                ("b".to_string(), range(25, 74), range(25, 49)),
+            ],
+            merged.children(&shards).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hand_off_children() {
+        let mut shards: HashMap<String, ShardState> = [
+            state("parent", range(0, 99), range(0, 99)),
+            ShardState::new(
+                "stream".to_string(),
+                "other".to_string(),
+                KinesisOffset::Latest,
+                range(100, 199),
+                range(100, 199),
+            ),
+        ]
+        .into_iter()
+        .map(|s| (s.key(), s))
+        .collect();
+
+        // nothing is handed off until the children are listed
+        let listed = vec![shard("parent", &[], 0, 99), shard("other", &[], 100, 199)];
+        assert!(
+            hand_off_children(&mut shards, &listed, |_| KinesisOffset::Earliest)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(2, shards.len());
+
+        let listed = vec![
+            shard("parent", &[], 0, 99),
+            shard("left", &["parent"], 0, 49),
+            shard("right", &["parent"], 50, 99),
+            shard("other", &[], 100, 199),
+        ];
+        let mut started: Vec<String> =
+            hand_off_children(&mut shards, &listed, |_| KinesisOffset::Earliest)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.shard_id)
+                .collect();
+        started.sort();
+        assert_eq!(vec!["left".to_string(), "right".to_string()], started);
+
+        // the closed parent is dropped, while open shards are kept
+        let mut ids: Vec<&str> = shards.values().map(|s| s.shard_id.as_str()).collect();
+        ids.sort();
+        assert_eq!(vec!["left", "other", "right"], ids);
+
+        // and the children aren't started again on the next sync
+        assert!(
+            hand_off_children(&mut shards, &listed, |_| KinesisOffset::Earliest)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -1,11 +1,34 @@
+pub mod alert;
+pub mod bigquery;
 pub mod blackhole;
+pub mod cassandra;
+pub mod clickhouse;
+pub mod datagen;
+pub mod deferred_ack;
+pub mod elasticsearch;
 pub mod filesystem;
 pub mod fluvio;
+pub mod grpc;
+pub mod http_lookup;
+pub mod iceberg;
 pub mod impulse;
 pub mod kafka;
 pub mod kinesis;
+pub mod mongodb;
+pub mod mqtt;
+pub mod mysql_cdc;
+pub mod nats;
 pub mod nexmark;
+pub mod partition_watermarks;
 pub mod polling_http;
+pub mod postgres;
+pub mod postgres_cdc;
+pub mod pubsub;
+pub mod pulsar;
+pub mod rabbitmq;
+pub mod redis;
+pub mod remote;
+pub mod snowflake;
 pub mod sse;
 pub mod two_phase_committer;
 pub mod webhook;
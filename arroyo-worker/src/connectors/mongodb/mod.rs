@@ -0,0 +1,202 @@
+use arroyo_rpc::formats::TimestampFormat;
+use chrono::{TimeZone, Utc};
+use mongodb::bson::{Bson, Document};
+use mongodb::options::ClientOptions;
+use mongodb::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use typify::import_types;
+
+pub mod source;
+
+import_types!(schema = "../connector-schemas/mongodb/connection.json");
+import_types!(schema = "../connector-schemas/mongodb/table.json");
+
+pub(crate) async fn connect(config: &MongoDbConfig) -> anyhow::Result<Client> {
+    let mut options = ClientOptions::parse(&config.uri).await?;
+    options.app_name = Some("arroyo".to_string());
+    Ok(Client::with_options(options)?)
+}
+
+fn datetime(millis: i64, format: &TimestampFormat) -> Value {
+    match format {
+        TimestampFormat::UnixMillis => Value::Number(millis.into()),
+        TimestampFormat::RFC3339 => Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .map(|t| Value::String(t.to_rfc3339()))
+            .unwrap_or(Value::Null),
+    }
+}
+
+/// Converts a BSON value into the JSON representation our deserializer expects; object ids and
+/// decimals become strings, and dates are written in the table's timestamp format
+pub(crate) fn bson_to_json(value: Bson, format: &TimestampFormat) -> Value {
+    match value {
+        Bson::Null | Bson::Undefined => Value::Null,
+        Bson::Boolean(b) => Value::Bool(b),
+        Bson::Int32(i) => Value::Number(i.into()),
+        Bson::Int64(i) => Value::Number(i.into()),
+        Bson::Double(f) => Number::from_f64(f)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        Bson::String(s) | Bson::Symbol(s) | Bson::JavaScriptCode(s) => Value::String(s),
+        Bson::ObjectId(id) => Value::String(id.to_hex()),
+        Bson::Decimal128(d) => Value::String(d.to_string()),
+        Bson::DateTime(t) => datetime(t.timestamp_millis(), format),
+        Bson::Timestamp(t) => datetime(t.time as i64 * 1000, format),
+        Bson::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|v| bson_to_json(v, format))
+                .collect(),
+        ),
+        Bson::Document(doc) => Value::Object(
+            doc.into_iter()
+                .map(|(k, v)| (k, bson_to_json(v, format)))
+                .collect(),
+        ),
+        other => other.into_relaxed_extjson(),
+    }
+}
+
+/// Converts a document into a JSON object with exactly the fields of the schema, which are given
+/// as pairs of name and nullability
+pub(crate) fn conform(
+    mut doc: Document,
+    fields: &[(String, bool)],
+    missing: &MissingFields,
+    extra: &ExtraFields,
+    format: &TimestampFormat,
+) -> Result<Value, String> {
+    let mut object = serde_json::Map::new();
+
+    for (name, nullable) in fields {
+        match doc.remove(name) {
+            Some(value) => {
+                object.insert(name.clone(), bson_to_json(value, format));
+            }
+            None if !nullable => {
+                return Err(format!("document is missing non-nullable field '{}'", name));
+            }
+            None if *missing == MissingFields::Fail => {
+                return Err(format!("document is missing field '{}'", name));
+            }
+            None => {
+                object.insert(name.clone(), Value::Null);
+            }
+        }
+    }
+
+    if *extra == ExtraFields::Fail {
+        // every document has an _id, so we don't require it to be declared
+        if let Some(field) = doc.keys().find(|k| *k != "_id") {
+            return Err(format!(
+                "document has field '{}', which is not in the schema",
+                field
+            ));
+        }
+    }
+
+    Ok(Value::Object(object))
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::oid::ObjectId;
+    use mongodb::bson::{doc, DateTime};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_bson_to_json() {
+        let id = ObjectId::parse_str("65254b5a1f8e4a0b9c3d2e1f").unwrap();
+        let doc = doc! {
+            "_id": id,
+            "count": 3i64,
+            "score": 1.5,
+            "created": DateTime::from_millis(1696942938000),
+            "tags": ["a", "b"],
+            "nested": { "ok": true },
+        };
+
+        assert_eq!(
+            bson_to_json(Bson::Document(doc.clone()), &TimestampFormat::UnixMillis),
+            json!({
+                "_id": "65254b5a1f8e4a0b9c3d2e1f",
+                "count": 3,
+                "score": 1.5,
+                "created": 1696942938000i64,
+                "tags": ["a", "b"],
+                "nested": { "ok": true },
+            })
+        );
+
+        assert_eq!(
+            bson_to_json(
+                doc.get("created").unwrap().clone(),
+                &TimestampFormat::RFC3339
+            ),
+            json!("2023-10-10T13:02:18+00:00")
+        );
+    }
+
+    #[test]
+    fn test_conform() {
+        let fields = vec![("name".to_string(), false), ("email".to_string(), true)];
+        let format = TimestampFormat::UnixMillis;
+
+        let doc = doc! { "_id": 1, "name": "alice" };
+        assert_eq!(
+            conform(
+                doc.clone(),
+                &fields,
+                &MissingFields::Null,
+                &ExtraFields::Fail,
+                &format
+            )
+            .unwrap(),
+            json!({"name": "alice", "email": null})
+        );
+        assert!(conform(
+            doc,
+            &fields,
+            &MissingFields::Fail,
+            &ExtraFields::Ignore,
+            &format
+        )
+        .is_err());
+
+        let doc = doc! { "name": "bob", "email": "bob@example.com", "age": 30 };
+        assert_eq!(
+            conform(
+                doc.clone(),
+                &fields,
+                &MissingFields::Fail,
+                &ExtraFields::Ignore,
+                &format
+            )
+            .unwrap(),
+            json!({"name": "bob", "email": "bob@example.com"})
+        );
+        assert!(conform(
+            doc,
+            &fields,
+            &MissingFields::Null,
+            &ExtraFields::Fail,
+            &format
+        )
+        .is_err());
+
+        let doc = doc! { "email": "carol@example.com" };
+        assert!(conform(
+            doc,
+            &fields,
+            &MissingFields::Null,
+            &ExtraFields::Ignore,
+            &format
+        )
+        .is_err());
+    }
+}
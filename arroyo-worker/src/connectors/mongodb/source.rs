@@ -0,0 +1,391 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arrow::datatypes::DataType;
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Format, JsonFormat, TimestampFormat};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use futures::StreamExt;
+use mongodb::bson::Document;
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
+use mongodb::options::{ChangeStreamOptions, FullDocumentBeforeChangeType, FullDocumentType};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info};
+
+use super::{conform, connect, ExtraFields, MissingFields, MongoDbConfig, MongoDbTable};
+
+#[derive(StreamNode)]
+pub struct MongoDbSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: MongoDbConfig,
+    table: MongoDbTable,
+    deserializer: DataDeserializer<T>,
+    debezium: bool,
+    timestamp_format: TimestampFormat,
+    // pairs of name and nullability for the fields we read from each document
+    fields: Vec<(String, bool)>,
+    resume_token: Option<ResumeToken>,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct MongoDbState {
+    // the change stream resume token, serialized as JSON
+    resume_token: String,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> MongoDbSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MongoDbSource");
+        let connection: MongoDbConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MongoDbSource");
+        let table: MongoDbTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MongoDbSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for MongoDbSource"),
+            config.bad_data,
+            config.rate_limit,
+        )
+    }
+
+    pub fn new(
+        config: MongoDbConfig,
+        table: MongoDbTable,
+        format: Format,
+        bad_data: Option<BadData>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        let (debezium, timestamp_format) = match &format {
+            Format::Json(JsonFormat {
+                debezium,
+                timestamp_format,
+                ..
+            }) => (*debezium, timestamp_format.clone()),
+            _ => panic!("MongoDbSource requires a JSON format"),
+        };
+
+        // with debezium, the document fields are those of the before and after structs
+        let schema = T::schema();
+        let fields = if debezium {
+            match schema.field_with_name("after").map(|f| f.data_type()) {
+                Ok(DataType::Struct(fields)) => fields.clone(),
+                _ => panic!("invalid debezium schema for MongoDbSource"),
+            }
+        } else {
+            schema.fields.clone()
+        };
+
+        Self {
+            config,
+            table,
+            deserializer: DataDeserializer::new(format, None)
+                .with_bad_data(bad_data)
+                .with_rate_limit(rate_limit),
+            debezium,
+            timestamp_format,
+            fields: fields
+                .iter()
+                .map(|f| (f.name().clone(), f.is_nullable()))
+                .collect(),
+            resume_token: None,
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("mongodb-{}", self.table.collection)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("m", "mongodb source state")]
+    }
+
+    fn state_key(&self) -> String {
+        format!("{}.{}", self.table.database, self.table.collection)
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn report_row_error(&mut self, name: String, details: String, ctx: &mut Context<(), T>) {
+        self.errors += 1;
+        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+            ctx.report_error(format!("{} x {}", name, self.errors), details)
+                .await;
+            self.errors = 0;
+            self.last_reported_error = Instant::now();
+        }
+    }
+
+    fn conform(&self, doc: Option<Document>) -> Result<serde_json::Value, String> {
+        match doc {
+            Some(doc) => conform(
+                doc,
+                &self.fields,
+                self.table
+                    .missing_fields
+                    .as_ref()
+                    .unwrap_or(&MissingFields::Null),
+                self.table
+                    .extra_fields
+                    .as_ref()
+                    .unwrap_or(&ExtraFields::Ignore),
+                &self.timestamp_format,
+            ),
+            None => Ok(serde_json::Value::Null),
+        }
+    }
+
+    async fn handle_event(
+        &mut self,
+        event: ChangeStreamEvent<Document>,
+        ctx: &mut Context<(), T>,
+    ) -> Result<(), UserError> {
+        let timestamp = event
+            .cluster_time
+            .map(|t| from_millis(t.time as u64 * 1000))
+            .unwrap_or_else(SystemTime::now);
+
+        let op = match event.operation_type {
+            OperationType::Insert => "c",
+            OperationType::Update | OperationType::Replace => "u",
+            OperationType::Delete => "d",
+            OperationType::Drop
+            | OperationType::DropDatabase
+            | OperationType::Rename
+            | OperationType::Invalidate => {
+                return Err(UserError::new(
+                    "MongoDB change stream invalidated",
+                    format!(
+                        "{}.{} was dropped or renamed ({:?})",
+                        self.table.database, self.table.collection, event.operation_type
+                    ),
+                ));
+            }
+            _ => return Ok(()),
+        };
+
+        let envelope = if self.debezium {
+            let before = self.conform(event.full_document_before_change);
+            let after = self.conform(event.full_document);
+            match (before, after) {
+                (Ok(before), Ok(after)) => json!({
+                    "before": before,
+                    "after": after,
+                    "op": op,
+                }),
+                (Err(e), _) | (_, Err(e)) => {
+                    self.report_row_error("Invalid MongoDB document".to_string(), e, ctx)
+                        .await;
+                    return Ok(());
+                }
+            }
+        } else {
+            // without pre-images we can't retract previous versions, so we only emit the
+            // current version of inserted and updated documents
+            let Some(doc) = event.full_document else {
+                return Ok(());
+            };
+
+            match self.conform(Some(doc)) {
+                Ok(value) => value,
+                Err(e) => {
+                    self.report_row_error("Invalid MongoDB document".to_string(), e, ctx)
+                        .await;
+                    return Ok(());
+                }
+            }
+        };
+
+        let json = serde_json::to_vec(&envelope).unwrap();
+        self.deserializer.throttle(&json).await;
+        for value in self.deserializer.deserialize_slice(&json) {
+            match value {
+                Ok(value) => {
+                    ctx.collector
+                        .collect(Record {
+                            timestamp,
+                            key: None,
+                            value,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    if let Some(e) = self
+                        .deserializer
+                        .handle_bad_data(&ctx.task_info, &json, e)?
+                    {
+                        self.report_row_error(e.name, e.details, ctx).await;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn write_state(&mut self, ctx: &mut Context<(), T>) {
+        let Some(token) = &self.resume_token else {
+            return;
+        };
+
+        let state = MongoDbState {
+            resume_token: serde_json::to_string(token).unwrap(),
+        };
+
+        let mut s = ctx.state.get_global_keyed_state('m').await;
+        s.insert(self.state_key(), state).await;
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // a change stream is a single ordered stream, so only one subtask reads it
+        if ctx.task_info.task_index != 0 {
+            ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
+
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        let s: GlobalKeyedState<String, MongoDbState, _> =
+            ctx.state.get_global_keyed_state('m').await;
+        if let Some(state) = s.get(&self.state_key()) {
+            self.resume_token = Some(serde_json::from_str(&state.resume_token).map_err(|e| {
+                UserError::new("Invalid resume token in checkpoint", e.to_string())
+            })?);
+        }
+
+        let client = connect(&self.config)
+            .await
+            .map_err(|e| UserError::new("Failed to connect to MongoDB", format!("{:?}", e)))?;
+
+        let collection = client
+            .database(&self.table.database)
+            .collection::<Document>(&self.table.collection);
+
+        let mut options = ChangeStreamOptions::default();
+        options.full_document = Some(FullDocumentType::UpdateLookup);
+        if self.debezium {
+            // deletes and updates can only be retracted if the server gives us the previous
+            // version of the document, which requires pre-images to be enabled on the collection
+            options.full_document_before_change = Some(FullDocumentBeforeChangeType::Required);
+        }
+        options.resume_after = self.resume_token.clone();
+
+        if let Some(token) = &options.resume_after {
+            info!("resuming MongoDB change stream from {:?}", token);
+        }
+
+        // the driver transparently resumes the stream after transient errors
+        let mut stream = collection
+            .watch(None, options)
+            .await
+            .map_err(|e| UserError::new("Failed to open MongoDB change stream", e.to_string()))?;
+
+        loop {
+            select! {
+                event = stream.next() => {
+                    match event {
+                        Some(Ok(event)) => {
+                            self.handle_event(event, ctx).await?;
+                        }
+                        Some(Err(e)) => {
+                            return Err(UserError::new("Error reading MongoDB change stream", e.to_string()));
+                        }
+                        None => {
+                            return Err(UserError::new("MongoDB change stream closed", "The server closed the change stream"));
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(ControlMessage::Checkpoint(_)) = &control_message {
+                        // the token reflects the last event we've emitted, or a later position
+                        // if the server has sent a post-batch token with no further events
+                        self.resume_token = stream.resume_token().or(self.resume_token.take());
+                        self.write_state(ctx).await;
+                    }
+
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping MongoDB source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,114 @@
+pub mod sink;
+pub mod source;
+
+pub use arroyo_connectors::mqtt::{mqtt_options, MqttConfig, MqttTable, TableType};
+
+pub(crate) fn client_id(
+    config: &MqttConfig,
+    job_id: &str,
+    operator_id: &str,
+    task: usize,
+) -> String {
+    format!(
+        "{}_{}_{}_{}",
+        config.client_prefix.as_deref().unwrap_or("arroyo"),
+        job_id,
+        operator_id,
+        task
+    )
+}
+
+/// The topic filter each subtask of a source subscribes to. With more than one subtask, they
+/// share an MQTT shared subscription (`$share/{group}/{filter}`), so that the broker spreads the
+/// topic's messages over them rather than delivering every message to each one
+pub(crate) fn subscription_topic(
+    config: &MqttConfig,
+    table: &MqttTable,
+    job_id: &str,
+    operator_id: &str,
+    parallelism: usize,
+) -> String {
+    if parallelism <= 1 {
+        return table.topic.clone();
+    }
+
+    format!(
+        "$share/{}_{}_{}/{}",
+        config.client_prefix.as_deref().unwrap_or("arroyo"),
+        job_id,
+        operator_id,
+        table.topic
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_connectors::mqtt::Tls;
+
+    fn config(url: &str, tls: Option<Tls>) -> MqttConfig {
+        MqttConfig {
+            url: url.to_string(),
+            client_prefix: None,
+            username: None,
+            password: None,
+            tls,
+        }
+    }
+
+    #[test]
+    fn test_subscription_topic() {
+        let table = MqttTable {
+            topic: "sensors/+/temperature".to_string(),
+            qos: None,
+            type_: TableType::Source {},
+        };
+        let config = config("mqtt://localhost", None);
+
+        assert_eq!(
+            "sensors/+/temperature",
+            subscription_topic(&config, &table, "job_1", "op_2", 1)
+        );
+        assert_eq!(
+            "$share/arroyo_job_1_op_2/sensors/+/temperature",
+            subscription_topic(&config, &table, "job_1", "op_2", 4)
+        );
+    }
+
+    #[test]
+    fn test_mqtt_options() {
+        let options = mqtt_options(&config("mqtt://broker:1884", None), "id".to_string()).unwrap();
+        assert_eq!(("broker".to_string(), 1884), options.broker_address());
+
+        let options = mqtt_options(&config("mqtts://broker", None), "id".to_string()).unwrap();
+        assert_eq!(("broker".to_string(), 8883), options.broker_address());
+
+        assert!(mqtt_options(&config("http://broker", None), "id".to_string()).is_err());
+
+        // a client certificate can't be used without a CA to verify the broker against
+        let client_cert = Tls {
+            ca: None,
+            cert: Some("cert".to_string()),
+            key: Some("key".to_string()),
+        };
+        assert!(mqtt_options(
+            &config("mqtts://broker", Some(client_cert.clone())),
+            "id".to_string()
+        )
+        .is_err());
+
+        // nor can TLS options be used without TLS
+        assert!(mqtt_options(
+            &config("mqtt://broker", Some(client_cert)),
+            "id".to_string()
+        )
+        .is_err());
+
+        let partial = Tls {
+            ca: None,
+            cert: Some("cert".to_string()),
+            key: None,
+        };
+        assert!(mqtt_options(&config("mqtts://broker", Some(partial)), "id".to_string()).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use rumqttc::{AsyncClient, Event, Packet};
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::{client_id, mqtt_options, MqttConfig, MqttTable, TableType};
+
+#[derive(StreamNode)]
+pub struct MqttSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: MqttConfig,
+    table: MqttTable,
+    retain: bool,
+    client: Option<AsyncClient>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> MqttSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MqttSink");
+        let connection: MqttConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MqttSink");
+        let table: MqttTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MqttSink");
+        let TableType::Sink { retain } = &table.type_ else {
+            panic!("found non-sink MQTT config in sink operator");
+        };
+
+        Self {
+            config: connection,
+            retain: retain.unwrap_or(false),
+            table,
+            client: None,
+            serializer: DataSerializer::new(
+                config.format.expect("Format must be defined for MqttSink"),
+            )
+            .with_compression(config.compression),
+            _t: PhantomData,
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> MqttSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("mqtt-sink-{}", self.table.topic)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        let options = match mqtt_options(
+            &self.config,
+            client_id(
+                &self.config,
+                &ctx.task_info.job_id,
+                &ctx.task_info.operator_id,
+                ctx.task_info.task_index,
+            ),
+        ) {
+            Ok(options) => options,
+            Err(e) => {
+                ctx.report_error("Invalid MQTT configuration".to_string(), e.to_string())
+                    .await;
+                panic!("Invalid MQTT configuration: {:?}", e);
+            }
+        };
+
+        let (client, mut eventloop) = AsyncClient::new(options, 100);
+
+        // rumqttc requires the event loop to be driven for publishes to be sent
+        tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("MQTT sink connected to broker");
+                    }
+                    Ok(_) => {}
+                    Err(rumqttc::ConnectionError::RequestsDone) => {
+                        return;
+                    }
+                    Err(e) => {
+                        warn!("Error from MQTT event loop: {:?}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        self.client = Some(client);
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        if let Err(e) = self
+            .client
+            .as_ref()
+            .unwrap()
+            .publish(&self.table.topic, self.table.qos(), self.retain, v)
+            .await
+        {
+            ctx.report_error("Failed to publish to MQTT".to_string(), e.to_string())
+                .await;
+            panic!("Failed to publish to MQTT: {:?}", e);
+        }
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        if let Some(client) = self.client.take() {
+            if let Err(e) = client.disconnect().await {
+                warn!("Failed to disconnect MQTT client: {:?}", e);
+            }
+        }
+    }
+}
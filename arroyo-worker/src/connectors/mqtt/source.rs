@@ -0,0 +1,230 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_types::*;
+use rumqttc::{AsyncClient, Event, Packet, SubscribeReasonCode};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::{client_id, mqtt_options, subscription_topic, MqttConfig, MqttTable, TableType};
+
+#[derive(StreamNode)]
+pub struct MqttSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: MqttConfig,
+    table: MqttTable,
+    deserializer: DataDeserializer<T>,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> MqttSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn new(
+        config: MqttConfig,
+        table: MqttTable,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        Self {
+            config,
+            table,
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_compression(compression)
+                .with_rate_limit(rate_limit),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MqttSource");
+        let connection: MqttConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MqttSource");
+        let table: MqttTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MqttSource");
+        let TableType::Source {} = &table.type_ else {
+            panic!("found non-source MQTT config in source operator");
+        };
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for MqttSource"),
+            config.framing,
+            config.bad_data,
+            config.compression,
+            config.rate_limit,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("mqtt-{}", self.table.topic)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping MQTT source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let topic = subscription_topic(
+            &self.config,
+            &self.table,
+            &ctx.task_info.job_id,
+            &ctx.task_info.operator_id,
+            ctx.task_info.parallelism,
+        );
+
+        let options = mqtt_options(
+            &self.config,
+            client_id(
+                &self.config,
+                &ctx.task_info.job_id,
+                &ctx.task_info.operator_id,
+                ctx.task_info.task_index,
+            ),
+        )
+        .map_err(|e| UserError::new("Invalid MQTT configuration", format!("{:?}", e)))?;
+
+        let (client, mut eventloop) = AsyncClient::new(options, 100);
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+
+        loop {
+            select! {
+                event = eventloop.poll() => {
+                    match event {
+                        Ok(Event::Incoming(Packet::Publish(p))) => {
+                            self.deserializer.throttle(&p.payload).await;
+                            for value in self.deserializer.deserialize_slice(&p.payload) {
+                                match value {
+                                    Ok(value) => {
+                                        ctx.collector.collect(Record {
+                                            timestamp: SystemTime::now(),
+                                            key: None,
+                                            value,
+                                        }).await;
+                                    }
+                                    Err(e) => {
+                                        if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, &p.payload, e)? {
+                                            errors += 1;
+                                            if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                                ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                                errors = 0;
+                                                last_reported_error = Instant::now();
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            // we use clean sessions, so we need to (re)subscribe every time we connect
+                            info!("Connected to MQTT broker; subscribing to {}", topic);
+                            client
+                                .subscribe(&topic, self.table.qos())
+                                .await
+                                .map_err(|e| {
+                                    UserError::new(
+                                        "Failed to subscribe to MQTT topic",
+                                        format!("{}: {:?}", topic, e),
+                                    )
+                                })?;
+                        }
+                        Ok(Event::Incoming(Packet::SubAck(ack))) => {
+                            if ack.return_codes.iter().any(|c| matches!(c, SubscribeReasonCode::Failure)) {
+                                return Err(UserError::new(
+                                    "MQTT broker rejected subscription",
+                                    format!("the broker rejected the subscription to '{}'; sources with \
+                                        a parallelism above 1 use shared subscriptions, which the broker \
+                                        must support", topic),
+                                ));
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            // the event loop will try to reconnect on the next poll
+                            warn!("Error from MQTT event loop: {:?}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{anyhow, bail};
+use chrono::{NaiveDate, NaiveDateTime};
+use mysql_async::binlog::value::BinlogValue;
+use mysql_async::prelude::Queryable;
+use mysql_async::{Conn, OptsBuilder, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::Number;
+use typify::import_types;
+
+pub mod source;
+
+import_types!(schema = "../connector-schemas/mysql/connection.json");
+import_types!(schema = "../connector-schemas/mysql_cdc/table.json");
+
+impl MySqlCdcTable {
+    /// The server id to register as a replica with; these must be unique across all replicas
+    /// of the server, so by default we derive one from the operator
+    pub fn server_id(&self, job_id: &str, operator_id: &str) -> u32 {
+        self.server_id.map(|id| id as u32).unwrap_or_else(|| {
+            let mut hasher = DefaultHasher::new();
+            job_id.hash(&mut hasher);
+            operator_id.hash(&mut hasher);
+            // stay clear of the small ids that are typically assigned by hand
+            (hasher.finish() as u32) | (1 << 30)
+        })
+    }
+}
+
+pub(crate) async fn connect(config: &MySqlConfig) -> anyhow::Result<Conn> {
+    let port = config.port.unwrap_or(3306);
+    if !(1..=65535).contains(&port) {
+        bail!("invalid port {}", port);
+    }
+
+    let opts = OptsBuilder::default()
+        .ip_or_hostname(&config.host)
+        .tcp_port(port as u16)
+        .user(Some(&config.username))
+        .pass(config.password.as_ref());
+
+    Ok(Conn::new(opts).await?)
+}
+
+pub(crate) struct Column {
+    pub name: String,
+    pub data_type: String,
+    pub is_bool: bool,
+}
+
+pub(crate) async fn load_columns(
+    conn: &mut Conn,
+    table: &MySqlCdcTable,
+) -> anyhow::Result<Vec<Column>> {
+    let columns: Vec<(String, String, String)> = conn
+        .exec(
+            "SELECT COLUMN_NAME, DATA_TYPE, COLUMN_TYPE FROM information_schema.columns \
+             WHERE TABLE_SCHEMA = ? AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION",
+            (&table.database, &table.table_name),
+        )
+        .await?;
+
+    if columns.is_empty() {
+        bail!(
+            "table {}.{} does not exist or has no columns",
+            table.database,
+            table.table_name
+        );
+    }
+
+    Ok(columns
+        .into_iter()
+        .map(|(name, data_type, column_type)| Column {
+            name,
+            is_bool: column_type == "tinyint(1)",
+            data_type,
+        })
+        .collect())
+}
+
+fn float(f: f64) -> serde_json::Value {
+    Number::from_f64(f)
+        .map(serde_json::Value::Number)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+fn datetime_millis(text: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+impl Column {
+    /// Converts a value from either the binlog or the text protocol into JSON that matches the
+    /// schema inferred for the column; datetimes are interpreted as UTC
+    pub fn to_json(&self, value: BinlogValue) -> serde_json::Value {
+        use serde_json::Value as J;
+
+        let value = match value {
+            BinlogValue::Value(v) => v,
+            BinlogValue::Jsonb(j) => {
+                return serde_json::Value::try_from(j)
+                    .map(|j| J::String(j.to_string()))
+                    .unwrap_or(J::Null);
+            }
+            // partial JSON updates aren't supported
+            BinlogValue::JsonDiff(_) => return J::Null,
+        };
+
+        match value {
+            Value::NULL => J::Null,
+            Value::Int(i) if self.is_bool => J::Bool(i != 0),
+            Value::Int(i) => J::Number(i.into()),
+            Value::UInt(u) => J::Number(u.into()),
+            Value::Float(f) => float(f as f64),
+            Value::Double(f) => float(f),
+            Value::Date(year, month, day, hour, minute, second, micros) => {
+                let date = NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32);
+                match self.data_type.as_str() {
+                    "date" => date
+                        .map(|d| J::String(d.format("%Y-%m-%d").to_string()))
+                        .unwrap_or(J::Null),
+                    _ => date
+                        .and_then(|d| {
+                            d.and_hms_micro_opt(hour as u32, minute as u32, second as u32, micros)
+                        })
+                        .map(|t| J::Number(t.timestamp_millis().into()))
+                        .unwrap_or(J::Null),
+                }
+            }
+            Value::Time(negative, days, hours, minutes, seconds, micros) => J::String(format!(
+                "{}{:02}:{:02}:{:02}.{:06}",
+                if negative { "-" } else { "" },
+                days * 24 + hours as u32,
+                minutes,
+                seconds,
+                micros
+            )),
+            Value::Bytes(bytes) => {
+                let text = String::from_utf8_lossy(&bytes).to_string();
+                match self.data_type.as_str() {
+                    _ if self.is_bool => J::Bool(text != "0"),
+                    "tinyint" | "smallint" | "mediumint" | "int" | "bigint" => text
+                        .parse::<i64>()
+                        .map(|i| J::Number(i.into()))
+                        .or_else(|_| text.parse::<u64>().map(|u| J::Number(u.into())))
+                        .unwrap_or(J::String(text)),
+                    "float" | "double" | "decimal" => {
+                        text.parse::<f64>().map(float).unwrap_or(J::Null)
+                    }
+                    "datetime" => datetime_millis(&text)
+                        .map(|t| J::Number(t.into()))
+                        .unwrap_or(J::String(text)),
+                    // the binlog encodes timestamps as fractional unix seconds
+                    "timestamp" => text
+                        .parse::<f64>()
+                        .ok()
+                        .map(|t| (t * 1000.0) as i64)
+                        .or_else(|| datetime_millis(&text))
+                        .map(|t| J::Number(t.into()))
+                        .unwrap_or(J::String(text)),
+                    _ => J::String(text),
+                }
+            }
+        }
+    }
+}
+
+/// Parses a GTID set like `3E11FA47-71CA-11E1-9E33-C80AA9429562:1-5:7,...` into the highest
+/// transaction number seen from each source server
+pub(crate) fn parse_gtid_set(set: &str) -> anyhow::Result<HashMap<[u8; 16], u64>> {
+    let mut gtids = HashMap::new();
+    for entry in set.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let mut parts = entry.split(':');
+        let sid = uuid::Uuid::parse_str(parts.next().unwrap())
+            .map_err(|e| anyhow!("invalid GTID set '{}': {}", set, e))?;
+
+        let max = parts
+            .map(|interval| {
+                interval
+                    .rsplit('-')
+                    .next()
+                    .unwrap()
+                    .parse::<u64>()
+                    .map_err(|e| anyhow!("invalid GTID set '{}': {}", set, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+
+        gtids.insert(sid.into_bytes(), max);
+    }
+
+    Ok(gtids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gtid_set() {
+        let gtids = parse_gtid_set(
+            "3e11fa47-71ca-11e1-9e33-c80aa9429562:1-5:7-9,\n\
+             3e11fa47-71ca-11e1-9e33-c80aa9429563:23",
+        )
+        .unwrap();
+
+        assert_eq!(gtids.len(), 2);
+        assert_eq!(
+            gtids[&uuid::Uuid::parse_str("3e11fa47-71ca-11e1-9e33-c80aa9429562")
+                .unwrap()
+                .into_bytes()],
+            9
+        );
+        assert_eq!(
+            gtids[&uuid::Uuid::parse_str("3e11fa47-71ca-11e1-9e33-c80aa9429563")
+                .unwrap()
+                .into_bytes()],
+            23
+        );
+    }
+}
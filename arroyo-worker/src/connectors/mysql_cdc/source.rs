@@ -0,0 +1,471 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Format};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use futures::StreamExt;
+use mysql_async::binlog::events::{EventData, RowsEventData};
+use mysql_async::binlog::row::BinlogRow;
+use mysql_async::binlog::value::BinlogValue;
+use mysql_async::prelude::Queryable;
+use mysql_async::{BinlogStreamRequest, GnoInterval, Row, Sid};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info};
+
+use super::{connect, load_columns, parse_gtid_set, Column, MySqlCdcTable, MySqlConfig};
+
+#[derive(StreamNode)]
+pub struct MySqlCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: MySqlConfig,
+    table: MySqlCdcTable,
+    deserializer: DataDeserializer<T>,
+    columns: Vec<Column>,
+    // the last committed transaction we've emitted from each source server
+    gtids: HashMap<[u8; 16], u64>,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct MySqlCdcState {
+    // pairs of server uuid and transaction number
+    gtids: Vec<(String, u64)>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> MySqlCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for MySqlCdcSource");
+        let connection: MySqlConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for MySqlCdcSource");
+        let table: MySqlCdcTable =
+            serde_json::from_value(config.table).expect("Invalid table config for MySqlCdcSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for MySqlCdcSource"),
+            config.bad_data,
+            config.rate_limit,
+        )
+    }
+
+    pub fn new(
+        config: MySqlConfig,
+        table: MySqlCdcTable,
+        format: Format,
+        bad_data: Option<BadData>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        Self {
+            config,
+            table,
+            deserializer: DataDeserializer::new(format, None)
+                .with_bad_data(bad_data)
+                .with_rate_limit(rate_limit),
+            columns: vec![],
+            gtids: HashMap::new(),
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("mysql-cdc-{}", self.table.table_name)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("m", "mysql cdc source state")]
+    }
+
+    fn state_key(&self) -> String {
+        format!("{}.{}", self.table.database, self.table.table_name)
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    fn row_to_json(&self, values: Vec<BinlogValue<'static>>) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.columns
+                .iter()
+                .zip(values)
+                .map(|(column, value)| (column.name.clone(), column.to_json(value)))
+                .collect(),
+        )
+    }
+
+    fn binlog_row_to_json(&self, row: Option<BinlogRow>) -> serde_json::Value {
+        row.map(|r| self.row_to_json(r.unwrap()))
+            .unwrap_or(serde_json::Value::Null)
+    }
+
+    async fn emit(
+        &mut self,
+        envelope: serde_json::Value,
+        timestamp: SystemTime,
+        ctx: &mut Context<(), T>,
+    ) -> Result<(), UserError> {
+        let json = serde_json::to_vec(&envelope).unwrap();
+        self.deserializer.throttle(&json).await;
+        for value in self.deserializer.deserialize_slice(&json) {
+            match value {
+                Ok(value) => {
+                    ctx.collector
+                        .collect(Record {
+                            timestamp,
+                            key: None,
+                            value,
+                        })
+                        .await;
+                }
+                Err(e) => {
+                    if let Some(e) = self
+                        .deserializer
+                        .handle_bad_data(&ctx.task_info, &json, e)?
+                    {
+                        self.errors += 1;
+                        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+                            ctx.report_error(format!("{} x {}", e.name, self.errors), e.details)
+                                .await;
+                            self.errors = 0;
+                            self.last_reported_error = Instant::now();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn write_state(&mut self, ctx: &mut Context<(), T>) {
+        let state = MySqlCdcState {
+            gtids: self
+                .gtids
+                .iter()
+                .map(|(sid, gno)| (uuid::Uuid::from_bytes(*sid).to_string(), *gno))
+                .collect(),
+        };
+
+        let mut s = ctx.state.get_global_keyed_state('m').await;
+        s.insert(self.state_key(), state).await;
+    }
+
+    /// Reads the current contents of the table from a consistent snapshot, and sets our GTID
+    /// position to the point the snapshot was taken at. Checkpoints are deferred until the
+    /// snapshot completes, so a failure partway through will start a new snapshot.
+    async fn snapshot(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        let map_err =
+            |e: anyhow::Error| UserError::new("Failed to snapshot MySQL table", format!("{:?}", e));
+        let table = format!("`{}`.`{}`", self.table.database, self.table.table_name);
+
+        info!("taking initial snapshot of {}", table);
+
+        // briefly block writes to the table so that the snapshot lines up with the GTID set
+        let mut lock_conn = connect(&self.config).await.map_err(map_err)?;
+        let mut snapshot_conn = connect(&self.config).await.map_err(map_err)?;
+
+        let gtid_executed = async {
+            lock_conn
+                .query_drop(format!("LOCK TABLES {} READ", table))
+                .await?;
+            let gtid_executed: Option<String> = lock_conn
+                .query_first("SELECT @@GLOBAL.gtid_executed")
+                .await?;
+            snapshot_conn.query_drop("SET time_zone = '+00:00'").await?;
+            snapshot_conn
+                .query_drop("START TRANSACTION WITH CONSISTENT SNAPSHOT, READ ONLY")
+                .await?;
+            lock_conn.query_drop("UNLOCK TABLES").await?;
+            lock_conn.disconnect().await?;
+            anyhow::Ok(gtid_executed.unwrap_or_default())
+        }
+        .await
+        .map_err(map_err)?;
+
+        let mut deferred_checkpoints = vec![];
+        let mut rows = 0;
+
+        {
+            let mut result = snapshot_conn
+                .query_iter(format!("SELECT * FROM {}", table))
+                .await
+                .map_err(|e| map_err(e.into()))?;
+
+            loop {
+                select! {
+                    row = result.next() => {
+                        let Some(row): Option<Row> = row.map_err(|e| map_err(e.into()))? else {
+                            break;
+                        };
+
+                        let values = row.unwrap().into_iter().map(BinlogValue::Value).collect();
+                        let envelope = json!({
+                            "before": null,
+                            "after": self.row_to_json(values),
+                            "op": "c",
+                        });
+                        self.emit(envelope, SystemTime::now(), ctx).await?;
+                        rows += 1;
+                    }
+                    control_message = ctx.control_rx.recv() => {
+                        match control_message {
+                            Some(ControlMessage::Checkpoint(c)) => {
+                                deferred_checkpoints.push(c);
+                            }
+                            msg => {
+                                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                                    return Ok(Some(r));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        snapshot_conn
+            .query_drop("COMMIT")
+            .await
+            .map_err(|e| map_err(e.into()))?;
+
+        info!("finished snapshot of {} ({} rows)", table, rows);
+
+        self.gtids = parse_gtid_set(&gtid_executed).map_err(map_err)?;
+
+        for c in deferred_checkpoints {
+            self.write_state(ctx).await;
+            if self.checkpoint(c, ctx).await {
+                return Ok(Some(SourceFinishType::Immediate));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // the binlog is a single ordered stream, so only one subtask reads it
+        if ctx.task_info.task_index != 0 {
+            ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
+
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        let mut conn = connect(&self.config)
+            .await
+            .map_err(|e| UserError::new("Failed to connect to MySQL", format!("{:?}", e)))?;
+
+        self.columns = load_columns(&mut conn, &self.table)
+            .await
+            .map_err(|e| UserError::new("Failed to load table schema", format!("{:?}", e)))?;
+
+        let s: GlobalKeyedState<String, MySqlCdcState, _> =
+            ctx.state.get_global_keyed_state('m').await;
+        let restored = s.get(&self.state_key()).cloned();
+
+        match restored {
+            Some(state) => {
+                for (sid, gno) in state.gtids {
+                    let sid = uuid::Uuid::parse_str(&sid).map_err(|e| {
+                        UserError::new("Invalid GTID in checkpoint", format!("{:?}", e))
+                    })?;
+                    self.gtids.insert(sid.into_bytes(), gno);
+                }
+            }
+            None if self.table.snapshot.unwrap_or(true) => {
+                if let Some(r) = self.snapshot(ctx).await? {
+                    return Ok(r);
+                }
+            }
+            None => {
+                let gtid_executed: Option<String> = conn
+                    .query_first("SELECT @@GLOBAL.gtid_executed")
+                    .await
+                    .map_err(|e| UserError::new("Failed to read GTID position", e.to_string()))?;
+                self.gtids = parse_gtid_set(&gtid_executed.unwrap_or_default()).map_err(|e| {
+                    UserError::new("Failed to read GTID position", format!("{:?}", e))
+                })?;
+            }
+        }
+
+        // we ask the server for everything after the transactions we've already emitted
+        let sids: Vec<Sid> = self
+            .gtids
+            .iter()
+            .map(|(sid, gno)| Sid::new(*sid).with_interval(GnoInterval::new(1, gno + 1)))
+            .collect();
+
+        let request = BinlogStreamRequest::new(
+            self.table
+                .server_id(&ctx.task_info.job_id, &ctx.task_info.operator_id),
+        )
+        .with_gtid()
+        .with_gtid_set(sids);
+
+        let mut stream = conn
+            .get_binlog_stream(request)
+            .await
+            .map_err(|e| UserError::new("Failed to read MySQL binlog", e.to_string()))?;
+
+        // changes are buffered until their transaction commits, so that checkpoints always fall
+        // on transaction boundaries
+        let mut current_gtid = None;
+        let mut pending = vec![];
+
+        loop {
+            select! {
+                event = stream.next() => {
+                    let event = match event {
+                        Some(Ok(event)) => event,
+                        Some(Err(e)) => {
+                            return Err(UserError::new("Error reading MySQL binlog", e.to_string()));
+                        }
+                        None => {
+                            return Err(UserError::new("MySQL binlog stream closed", "The server closed the binlog stream"));
+                        }
+                    };
+
+                    let timestamp = from_millis(event.header().timestamp() as u64 * 1000);
+
+                    let data = event.read_data().map_err(|e| {
+                        UserError::new("Failed to decode MySQL binlog event", e.to_string())
+                    })?;
+
+                    match data {
+                        Some(EventData::GtidEvent(gtid)) => {
+                            current_gtid = Some((gtid.sid(), gtid.gno()));
+                            pending.clear();
+                        }
+                        Some(EventData::RowsEvent(rows)) => {
+                            let Some(tme) = stream.get_tme(rows.table_id()) else {
+                                continue;
+                            };
+
+                            if tme.database_name() != self.table.database.as_str()
+                                || tme.table_name() != self.table.table_name.as_str() {
+                                continue;
+                            }
+
+                            let op = match &rows {
+                                RowsEventData::WriteRowsEventV1(_) | RowsEventData::WriteRowsEvent(_) => "c",
+                                RowsEventData::UpdateRowsEventV1(_) | RowsEventData::UpdateRowsEvent(_)
+                                    | RowsEventData::PartialUpdateRowsEvent(_) => "u",
+                                RowsEventData::DeleteRowsEventV1(_) | RowsEventData::DeleteRowsEvent(_) => "d",
+                            };
+
+                            for row in rows.rows(tme) {
+                                let (before, after) = row.map_err(|e| {
+                                    UserError::new("Failed to decode MySQL binlog row", e.to_string())
+                                })?;
+
+                                pending.push(json!({
+                                    "before": self.binlog_row_to_json(before),
+                                    "after": self.binlog_row_to_json(after),
+                                    "op": op,
+                                }));
+                            }
+                        }
+                        Some(EventData::XidEvent(_)) => {
+                            if let Some((sid, gno)) = current_gtid.take() {
+                                self.gtids.insert(sid, gno);
+                            }
+
+                            for envelope in std::mem::take(&mut pending) {
+                                self.emit(envelope, timestamp, ctx).await?;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(ControlMessage::Checkpoint(_)) = &control_message {
+                        self.write_state(ctx).await;
+                    }
+
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping MySQL CDC source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, bail};
+use async_nats::ConnectOptions;
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/nats/connection.json");
+import_types!(schema = "../connector-schemas/nats/table.json");
+
+impl DeliverPolicy {
+    pub fn policy(&self) -> async_nats::jetstream::consumer::DeliverPolicy {
+        match self {
+            DeliverPolicy::All => async_nats::jetstream::consumer::DeliverPolicy::All,
+            DeliverPolicy::New => async_nats::jetstream::consumer::DeliverPolicy::New,
+            DeliverPolicy::Last => async_nats::jetstream::consumer::DeliverPolicy::Last,
+        }
+    }
+}
+
+impl NatsConfig {
+    pub async fn connect(&self) -> anyhow::Result<async_nats::Client> {
+        Ok(nats_options(self)?.connect(self.servers.as_str()).await?)
+    }
+}
+
+/// The name shared by all subtasks of the operator, used as the durable consumer name for
+/// JetStream and the queue group for core NATS so that messages are spread across subtasks
+pub(crate) fn consumer_name(job_id: &str, operator_id: &str) -> String {
+    format!("arroyo-{}-{}", job_id, operator_id)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn nats_options(config: &NatsConfig) -> anyhow::Result<ConnectOptions> {
+    let mut options = ConnectOptions::new().name("arroyo");
+
+    match (
+        &config.username,
+        &config.password,
+        &config.token,
+        &config.credentials,
+    ) {
+        (None, None, None, None) => {}
+        (Some(username), Some(password), None, None) => {
+            options = options.user_and_password(username.clone(), password.clone());
+        }
+        (None, None, Some(token), None) => {
+            options = options.token(token.clone());
+        }
+        (None, None, None, Some(credentials)) => {
+            options = options
+                .credentials(credentials)
+                .map_err(|e| anyhow!("invalid credentials: {}", e))?;
+        }
+        (Some(_), None, None, None) | (None, Some(_), None, None) => {
+            bail!("username and password must be set together")
+        }
+        _ => bail!("only one of username/password, token, or credentials may be set"),
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::consumer_name;
+
+    #[test]
+    fn test_consumer_name() {
+        assert_eq!(
+            consumer_name("job_1a2b", "operator.3"),
+            "arroyo-job_1a2b-operator_3"
+        );
+        assert_eq!(consumer_name("j*b", "op>"), "arroyo-j_b-op_");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use async_nats::jetstream::context::PublishAckFuture;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+use super::{NatsConfig, NatsTable, TableType};
+
+// bounds the number of JetStream publishes we'll wait on at once
+const MAX_PENDING_ACKS: usize = 1024;
+
+#[derive(StreamNode)]
+pub struct NatsSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: NatsConfig,
+    table: NatsTable,
+    jetstream: bool,
+    client: Option<async_nats::Client>,
+    context: Option<async_nats::jetstream::Context>,
+    pending_acks: Vec<PublishAckFuture>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> NatsSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for NatsSink");
+        let connection: NatsConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for NatsSink");
+        let table: NatsTable =
+            serde_json::from_value(config.table).expect("Invalid table config for NatsSink");
+        let TableType::Sink { jetstream } = &table.type_ else {
+            panic!("found non-sink NATS config in sink operator");
+        };
+
+        Self {
+            config: connection,
+            jetstream: jetstream.unwrap_or(false),
+            table,
+            client: None,
+            context: None,
+            pending_acks: vec![],
+            serializer: DataSerializer::new(
+                config.format.expect("Format must be defined for NatsSink"),
+            )
+            .with_compression(config.compression),
+            _t: PhantomData,
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> NatsSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("nats-sink-{}", self.table.subject)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        let client = match self.config.connect().await {
+            Ok(client) => client,
+            Err(e) => {
+                ctx.report_error("Failed to connect to NATS".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to connect to NATS: {:?}", e);
+            }
+        };
+
+        if self.jetstream {
+            self.context = Some(async_nats::jetstream::new(client.clone()));
+        }
+        self.client = Some(client);
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        let result = match &self.context {
+            Some(context) => context
+                .publish(self.table.subject.clone(), v.into())
+                .await
+                .map(|ack| self.pending_acks.push(ack))
+                .map_err(|e| e.to_string()),
+            None => self
+                .client
+                .as_ref()
+                .unwrap()
+                .publish(self.table.subject.clone(), v.into())
+                .await
+                .map_err(|e| e.to_string()),
+        };
+
+        if let Err(e) = result {
+            ctx.report_error("Failed to publish to NATS".to_string(), e.clone())
+                .await;
+            panic!("Failed to publish to NATS: {}", e);
+        }
+
+        if self.pending_acks.len() >= MAX_PENDING_ACKS {
+            self.flush(ctx).await;
+        }
+    }
+
+    /// Waits until everything published so far has reached the server or, for JetStream, has
+    /// been persisted by the stream
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        let result = match &self.context {
+            Some(_) => {
+                let mut result = Ok(());
+                for ack in self.pending_acks.drain(..) {
+                    if let Err(e) = ack.await {
+                        result = Err(e.to_string());
+                    }
+                }
+                result
+            }
+            None => self
+                .client
+                .as_ref()
+                .unwrap()
+                .flush()
+                .await
+                .map_err(|e| e.to_string()),
+        };
+
+        if let Err(e) = result {
+            ctx.report_error("Failed to write to NATS".to_string(), e.clone())
+                .await;
+            panic!("Failed to write to NATS: {}", e);
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+}
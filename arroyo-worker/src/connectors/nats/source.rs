@@ -0,0 +1,285 @@
+use crate::connectors::deferred_ack::DeferredAck;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use anyhow::anyhow;
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_types::*;
+use async_nats::jetstream::consumer::{pull, AckPolicy};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::{consumer_name, NatsConfig, NatsTable, TableType};
+
+/// How long JetStream waits for an ack before redelivering; this needs to comfortably exceed
+/// two checkpoint intervals, as we only ack once the checkpoint covering a message has completed
+const ACK_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(StreamNode)]
+pub struct NatsSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: NatsConfig,
+    table: NatsTable,
+    deserializer: DataDeserializer<T>,
+    // JetStream messages that have been emitted, held until their checkpoint completes
+    acks: DeferredAck<Vec<async_nats::jetstream::Message>>,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> NatsSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn new(
+        config: NatsConfig,
+        table: NatsTable,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        Self {
+            config,
+            table,
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_compression(compression)
+                .with_rate_limit(rate_limit),
+            acks: DeferredAck::default(),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for NatsSource");
+        let connection: NatsConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for NatsSource");
+        let table: NatsTable =
+            serde_json::from_value(config.table).expect("Invalid table config for NatsSource");
+        let TableType::Source { .. } = &table.type_ else {
+            panic!("found non-source NATS config in source operator");
+        };
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for NatsSource"),
+            config.framing,
+            config.bad_data,
+            config.compression,
+            config.rate_limit,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("nats-{}", self.table.subject)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                for message in self.acks.checkpoint() {
+                    if let Err(e) = message.ack().await {
+                        warn!(
+                            "Failed to ack NATS message; it will be redelivered: {:?}",
+                            e
+                        );
+                    }
+                }
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping NATS source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    /// Subscribes to the subject, returning a stream of payloads along with the JetStream
+    /// message that needs to be acked, if any
+    async fn subscribe(
+        &self,
+        ctx: &Context<(), T>,
+    ) -> anyhow::Result<
+        BoxStream<'static, anyhow::Result<(Bytes, Option<async_nats::jetstream::Message>)>>,
+    > {
+        let TableType::Source {
+            stream,
+            consumer,
+            deliver_policy,
+        } = &self.table.type_
+        else {
+            unreachable!();
+        };
+
+        let client = self.config.connect().await?;
+        // every subtask joins the same queue group or durable consumer, and the server spreads
+        // messages between them
+        let name = consumer_name(&ctx.task_info.job_id, &ctx.task_info.operator_id);
+
+        let Some(stream) = stream else {
+            let subscriber = client
+                .queue_subscribe(self.table.subject.clone(), name)
+                .await?;
+            return Ok(subscriber.map(|m| Ok((m.payload, None))).boxed());
+        };
+
+        let consumer = async_nats::jetstream::new(client)
+            .get_stream(stream)
+            .await
+            .map_err(|e| anyhow!("failed to get stream '{}': {}", stream, e))?
+            .get_or_create_consumer(
+                consumer.as_deref().unwrap_or(&name),
+                pull::Config {
+                    durable_name: Some(consumer.clone().unwrap_or(name.clone())),
+                    filter_subject: self.table.subject.clone(),
+                    ack_policy: AckPolicy::Explicit,
+                    ack_wait: ACK_WAIT,
+                    // we hold acks until the next checkpoint, so the default limit would
+                    // stall high-throughput consumers
+                    max_ack_pending: -1,
+                    deliver_policy: deliver_policy
+                        .as_ref()
+                        .map(|p| p.policy())
+                        .unwrap_or(async_nats::jetstream::consumer::DeliverPolicy::All),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("failed to create consumer on stream '{}': {}", stream, e))?;
+
+        Ok(consumer
+            .messages()
+            .await?
+            .map(|m| match m {
+                Ok(m) => Ok((m.message.payload.clone(), Some(m))),
+                Err(e) => Err(anyhow!("{}", e)),
+            })
+            .boxed())
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let mut messages = self
+            .subscribe(ctx)
+            .await
+            .map_err(|e| UserError::new("Failed to subscribe to NATS", format!("{:?}", e)))?;
+
+        info!("Subscribed to NATS subject {}", self.table.subject);
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+
+        loop {
+            select! {
+                message = messages.next() => {
+                    match message {
+                        Some(Ok((payload, jetstream_message))) => {
+                            self.deserializer.throttle(&payload).await;
+                            for value in self.deserializer.deserialize_slice(&payload) {
+                                match value {
+                                    Ok(value) => {
+                                        ctx.collector.collect(Record {
+                                            timestamp: SystemTime::now(),
+                                            key: None,
+                                            value,
+                                        }).await;
+                                    }
+                                    Err(e) => {
+                                        if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, &payload, e)? {
+                                            errors += 1;
+                                            if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                                ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                                errors = 0;
+                                                last_reported_error = Instant::now();
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            if let Some(message) = jetstream_message {
+                                self.acks.read(message);
+                            }
+                        }
+                        Some(Err(e)) => {
+                            // the client reconnects on its own, so these are generally transient
+                            warn!("Error reading from NATS: {:?}", e);
+                        }
+                        None => {
+                            return Err(UserError::new(
+                                "NATS subscription closed",
+                                format!("the subscription to {} was closed by the server", self.table.subject),
+                            ));
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
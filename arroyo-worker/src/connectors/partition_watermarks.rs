@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_rpc::PartitionWatermarks;
+use arroyo_types::Watermark;
+
+struct PartitionState {
+    max_timestamp: Option<SystemTime>,
+    last_message: Instant,
+}
+
+/// Generates the watermarks of a source from those of each partition that it reads, which trail
+/// the latest timestamp read from the partition by the max out-of-orderness. The source's
+/// watermark is the least of those of its partitions, ignoring partitions that have been idle
+/// for longer than the idle timeout, and the source is idle once all of its partitions are.
+pub struct PartitionWatermarker<P> {
+    max_out_of_orderness: Duration,
+    idle_timeout: Option<Duration>,
+    partitions: HashMap<P, PartitionState>,
+    last_watermark: Option<Watermark>,
+    max_watermark: Option<SystemTime>,
+}
+
+impl<P: Hash + Eq> PartitionWatermarker<P> {
+    pub fn new(config: &PartitionWatermarks) -> Self {
+        Self {
+            max_out_of_orderness: Duration::from_micros(config.max_out_of_orderness_micros),
+            idle_timeout: config.idle_timeout_micros.map(Duration::from_micros),
+            partitions: HashMap::new(),
+            last_watermark: None,
+            max_watermark: None,
+        }
+    }
+
+    /// Starts tracking a partition, which holds back the watermark until it either receives a
+    /// message or goes idle
+    pub fn add_partition(&mut self, partition: P, now: Instant) {
+        self.partitions.entry(partition).or_insert(PartitionState {
+            max_timestamp: None,
+            last_message: now,
+        });
+    }
+
+    pub fn observe(&mut self, partition: P, timestamp: SystemTime, now: Instant) {
+        let state = self.partitions.entry(partition).or_insert(PartitionState {
+            max_timestamp: None,
+            last_message: now,
+        });
+        state.max_timestamp = Some(state.max_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+        state.last_message = now;
+    }
+
+    /// Restarts the idle timeouts of all partitions, for when the source resumes reading after
+    /// deliberately pausing, which shouldn't make its partitions look idle
+    pub fn reset_idle(&mut self, now: Instant) {
+        for state in self.partitions.values_mut() {
+            state.last_message = now;
+        }
+    }
+
+    /// Returns the watermark of the source if it has changed since it was last returned
+    pub fn poll(&mut self, now: Instant) -> Option<Watermark> {
+        if self.partitions.is_empty() {
+            return None;
+        }
+
+        let mut active = self
+            .partitions
+            .values()
+            .filter(|state| match self.idle_timeout {
+                Some(timeout) => now.saturating_duration_since(state.last_message) < timeout,
+                None => true,
+            })
+            .peekable();
+
+        let watermark = if active.peek().is_none() {
+            Watermark::Idle
+        } else {
+            // partitions that haven't been read from yet hold back the watermark
+            let min_timestamp = active
+                .map(|state| state.max_timestamp)
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min()
+                .unwrap();
+
+            // a partition that comes back from being idle can't move the watermark backwards
+            let watermark = self.max_watermark.into_iter().fold(
+                min_timestamp
+                    .checked_sub(self.max_out_of_orderness)
+                    .unwrap_or(SystemTime::UNIX_EPOCH),
+                SystemTime::max,
+            );
+            self.max_watermark = Some(watermark);
+            Watermark::EventTime(watermark)
+        };
+
+        if self.last_watermark == Some(watermark) {
+            return None;
+        }
+
+        self.last_watermark = Some(watermark);
+        Some(watermark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watermarker(idle_timeout_secs: Option<u64>) -> PartitionWatermarker<i32> {
+        PartitionWatermarker::new(&PartitionWatermarks {
+            period_micros: 1_000_000,
+            max_out_of_orderness_micros: 1_000_000,
+            idle_timeout_micros: idle_timeout_secs.map(|s| s * 1_000_000),
+            alignment: None,
+        })
+    }
+
+    fn time(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_least_partition_watermark() {
+        let start = Instant::now();
+        let mut watermarker = watermarker(None);
+        watermarker.add_partition(0, start);
+        watermarker.add_partition(1, start);
+
+        watermarker.observe(0, time(100), start);
+        assert_eq!(watermarker.poll(start), None);
+
+        watermarker.observe(1, time(50), start);
+        assert_eq!(
+            watermarker.poll(start),
+            Some(Watermark::EventTime(time(49)))
+        );
+        assert_eq!(watermarker.poll(start), None);
+
+        watermarker.observe(1, time(200), start);
+        assert_eq!(
+            watermarker.poll(start),
+            Some(Watermark::EventTime(time(99)))
+        );
+    }
+
+    #[test]
+    fn test_idle_partitions() {
+        let start = Instant::now();
+        let mut watermarker = watermarker(Some(10));
+        watermarker.add_partition(0, start);
+        watermarker.add_partition(1, start);
+
+        watermarker.observe(0, time(100), start);
+        assert_eq!(watermarker.poll(start), None);
+
+        // the partition that never received a message stops holding back the watermark
+        let later = start + Duration::from_secs(5);
+        watermarker.observe(0, time(110), later);
+        let later = start + Duration::from_secs(11);
+        assert_eq!(
+            watermarker.poll(later),
+            Some(Watermark::EventTime(time(109)))
+        );
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(watermarker.poll(later), Some(Watermark::Idle));
+
+        // and a late partition can't move it backwards when it wakes up
+        watermarker.observe(1, time(20), later);
+        assert_eq!(
+            watermarker.poll(later),
+            Some(Watermark::EventTime(time(109)))
+        );
+    }
+}
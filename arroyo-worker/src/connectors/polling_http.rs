@@ -1,8 +1,13 @@
 use bincode::{Decode, Encode};
-use bytes::Bytes;
 use futures::StreamExt;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use regex::Regex;
+use reqwest::header::{LINK, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde_json::Value;
 use std::borrow::Cow;
-use std::str::FromStr;
+use std::num::NonZeroU32;
+use std::sync::OnceLock;
 use std::time::SystemTime;
 use std::{marker::PhantomData, time::Duration};
 
@@ -30,6 +35,9 @@ import_types!(schema = "../connector-schemas/polling_http/table.json");
 
 const DEFAULT_POLLING_INTERVAL: Duration = Duration::from_secs(1);
 const MAX_BODY_SIZE: usize = 5 * 1024 * 1024; // 5M ought to be enough for anybody
+const DEFAULT_MAX_PAGES: usize = 100;
+const MAX_RETRIES: u32 = 5;
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
 
 #[derive(StreamNode)]
 pub struct PollingHttpSourceFunc<K, T>
@@ -39,11 +47,21 @@ where
 {
     state: PollingHttpSourceState,
     client: reqwest::Client,
-    endpoint: url::Url,
+    // the endpoint and body may contain {{ watermark }}, so they're rendered before each poll
+    endpoint: String,
     method: reqwest::Method,
-    body: Option<Bytes>,
+    body: Option<String>,
     polling_interval: Duration,
     emit_behavior: EmitBehavior,
+    records_path: Option<String>,
+    pagination: Pagination,
+    pagination_path: Option<String>,
+    cursor_param: String,
+    max_pages: usize,
+    watermark_field: Option<String>,
+    initial_watermark: Option<String>,
+    watermark: Option<Value>,
+    rate_limiter: Option<DefaultDirectRateLimiter>,
     deserializer: DataDeserializer<T>,
 
     _t: PhantomData<(K, T)>,
@@ -84,7 +102,9 @@ where
                 .format
                 .expect("polling http source must have a format configured"),
             config.framing,
-        );
+        )
+        .with_bad_data(config.bad_data)
+        .with_rate_limit(config.rate_limit);
 
         Self {
             state: PollingHttpSourceState { last_message: None },
@@ -93,19 +113,34 @@ where
                 .timeout(Duration::from_secs(5))
                 .build()
                 .expect("could not construct http client"),
-            endpoint: url::Url::from_str(&table.endpoint).expect("invalid endpoint"),
+            endpoint: table.endpoint,
             method: match table.method {
                 None | Some(Method::Get) => reqwest::Method::GET,
                 Some(Method::Post) => reqwest::Method::POST,
                 Some(Method::Put) => reqwest::Method::PUT,
                 Some(Method::Patch) => reqwest::Method::PATCH,
             },
-            body: table.body.map(|b| b.into()),
+            body: table.body,
             polling_interval: table
                 .poll_interval_ms
                 .map(|d| Duration::from_millis(d as u64))
                 .unwrap_or(DEFAULT_POLLING_INTERVAL),
             emit_behavior: table.emit_behavior.unwrap_or(EmitBehavior::All),
+            records_path: table.records_path,
+            pagination: table.pagination.unwrap_or(Pagination::None),
+            pagination_path: table.pagination_path,
+            cursor_param: table.cursor_param.unwrap_or_else(|| "cursor".to_string()),
+            max_pages: table
+                .max_pages
+                .map(|p| p.max(1) as usize)
+                .unwrap_or(DEFAULT_MAX_PAGES),
+            watermark_field: table.watermark_field,
+            initial_watermark: table.initial_watermark,
+            watermark: None,
+            rate_limiter: table
+                .max_requests_per_second
+                .and_then(|r| NonZeroU32::new(r as u32))
+                .map(|r| RateLimiter::direct(Quota::per_second(r))),
             deserializer,
             _t: PhantomData,
         }
@@ -116,7 +151,10 @@ where
     }
 
     fn tables(&self) -> Vec<TableDescriptor> {
-        vec![arroyo_state::global_table("s", "polling http source state")]
+        vec![
+            arroyo_state::global_table("s", "polling http source state"),
+            arroyo_state::global_table("w", "polling http source watermark"),
+        ]
     }
 
     async fn on_start(&mut self, ctx: &mut Context<(), T>) {
@@ -126,6 +164,9 @@ where
         if let Some(state) = s.get(&()) {
             self.state = state.clone();
         }
+
+        let w: GlobalKeyedState<(), String, _> = ctx.state.get_global_keyed_state('w').await;
+        self.watermark = w.get(&()).and_then(|w| serde_json::from_str(w).ok());
     }
 
     async fn our_handle_control_message(
@@ -141,6 +182,12 @@ where
                     ctx.state.get_global_keyed_state('s').await;
                 s.insert((), state).await;
 
+                if let Some(watermark) = &self.watermark {
+                    let mut w: GlobalKeyedState<(), String, _> =
+                        ctx.state.get_global_keyed_state('w').await;
+                    w.insert((), watermark.to_string()).await;
+                }
+
                 if self.checkpoint(c, ctx).await {
                     return Some(SourceFinishType::Immediate);
                 }
@@ -163,32 +210,80 @@ where
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
             ControlMessage::NoOp => {}
         }
         None
     }
 
-    async fn request(&mut self) -> Result<Vec<u8>, UserError> {
-        let mut request = self
-            .client
-            .request(self.method.clone(), self.endpoint.clone());
-
-        if let Some(body) = self.body.clone() {
-            request = request.body(body);
+    /// The current watermark as it should be substituted into templates
+    fn watermark_string(&self) -> Option<String> {
+        match &self.watermark {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => Some(other.to_string()),
+            None => self.initial_watermark.clone(),
         }
+    }
 
-        let resp = self
-            .client
-            .execute(request.build().expect("building request failed"))
-            .await
-            .map_err(|e| {
-                UserError::new(
-                    "request failed",
-                    format!("failed to execute HTTP request: {}", e),
-                )
-            })?;
+    async fn request(
+        &mut self,
+        url: url::Url,
+        body: Option<String>,
+    ) -> Result<(Vec<u8>, Option<String>), UserError> {
+        let mut attempts = 0;
+        let resp = loop {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.until_ready().await;
+            }
+
+            let mut request = self.client.request(self.method.clone(), url.clone());
+
+            if let Some(body) = body.clone() {
+                request = request.body(body);
+            }
+
+            let resp = self
+                .client
+                .execute(request.build().expect("building request failed"))
+                .await
+                .map_err(|e| {
+                    UserError::new(
+                        "request failed",
+                        format!("failed to execute HTTP request: {}", e),
+                    )
+                })?;
+
+            if resp.status() != StatusCode::TOO_MANY_REQUESTS || attempts >= MAX_RETRIES {
+                break resp;
+            }
+
+            // back off as long as the server asks us to, if it tells us
+            let wait = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(1 << attempts))
+                .min(MAX_RETRY_WAIT);
+
+            warn!(
+                "HTTP request to {} was rate limited; retrying in {:?}",
+                url, wait
+            );
+            tokio::time::sleep(wait).await;
+            attempts += 1;
+        };
 
         if resp.status().is_success() {
+            let link = resp
+                .headers()
+                .get(LINK)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+
             let content_len = resp.content_length().unwrap_or(0);
             if content_len > MAX_BODY_SIZE as u64 {
                 return Err(UserError::new(
@@ -219,7 +314,7 @@ where
                 }
             }
 
-            Ok(buf)
+            Ok((buf, link))
         } else {
             let status = resp.status();
             let bytes = resp.bytes().await;
@@ -230,7 +325,7 @@ where
 
             warn!(
                 "HTTP request to {} failed with {}: {}",
-                self.endpoint,
+                url,
                 status.as_u16(),
                 error_body
             );
@@ -242,6 +337,148 @@ where
         }
     }
 
+    /// Fetches and emits the pages of results for one poll of the endpoint
+    async fn poll(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> Result<Option<SourceFinishType>, UserError> {
+        let watermark = self.watermark_string();
+        let invalid_endpoint = |e: url::ParseError| {
+            UserError::new(
+                "invalid endpoint",
+                format!("endpoint is not a valid URL: {}", e),
+            )
+        };
+
+        let first_url = url::Url::parse(&render_template(
+            &self.endpoint,
+            watermark
+                .as_ref()
+                .map(|w| url::form_urlencoded::byte_serialize(w.as_bytes()).collect::<String>())
+                .as_deref(),
+        ))
+        .map_err(invalid_endpoint)?;
+        let body = self
+            .body
+            .as_ref()
+            .map(|b| render_template(b, watermark.as_deref()));
+
+        let mut url = first_url.clone();
+        for page in 0..self.max_pages {
+            let (buf, link) = self.request(url.clone(), body.clone()).await?;
+
+            if page == 0 {
+                if self.emit_behavior == EmitBehavior::Changed
+                    && Some(&buf) == self.state.last_message.as_ref()
+                {
+                    return Ok(None);
+                }
+                self.state.last_message = Some(buf.clone());
+            }
+
+            let parsed = if self.records_path.is_some()
+                || matches!(self.pagination, Pagination::Cursor | Pagination::NextLink)
+            {
+                Some(serde_json::from_slice::<Value>(&buf).map_err(|e| {
+                    UserError::new(
+                        "invalid response",
+                        format!("response from {} is not valid JSON: {}", url, e),
+                    )
+                })?)
+            } else {
+                None
+            };
+
+            match (self.records_path.clone(), &parsed) {
+                (Some(path), Some(parsed)) => {
+                    let records = match parsed.pointer(&path) {
+                        Some(Value::Array(records)) => records.iter().collect(),
+                        None | Some(Value::Null) => vec![],
+                        Some(record) => vec![record],
+                    };
+
+                    for record in records {
+                        if let Some(field) = &self.watermark_field {
+                            if let Some(value) = record.get(field) {
+                                advance_watermark(&mut self.watermark, value);
+                            }
+                        }
+
+                        let bytes = serde_json::to_vec(record).unwrap();
+                        self.emit(ctx, &bytes).await?;
+                    }
+                }
+                _ => {
+                    self.emit(ctx, &buf).await?;
+                }
+            }
+
+            let next = match self.pagination {
+                Pagination::None => None,
+                Pagination::Cursor => parsed
+                    .as_ref()
+                    .and_then(|p| p.pointer(self.pagination_path.as_ref()?))
+                    .and_then(|c| match c {
+                        Value::String(s) if !s.is_empty() => Some(s.clone()),
+                        Value::Number(n) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .map(|cursor| with_query_param(&first_url, &self.cursor_param, &cursor)),
+                Pagination::NextLink => parsed
+                    .as_ref()
+                    .and_then(|p| p.pointer(self.pagination_path.as_ref()?)?.as_str())
+                    .filter(|s| !s.is_empty())
+                    .map(|link| url.join(link))
+                    .transpose()
+                    .map_err(invalid_endpoint)?,
+                Pagination::LinkHeader => link
+                    .as_deref()
+                    .and_then(next_link)
+                    .map(|link| url.join(&link))
+                    .transpose()
+                    .map_err(invalid_endpoint)?,
+            };
+
+            match next {
+                Some(next) if next != url => url = next,
+                _ => break,
+            }
+
+            while let Ok(msg) = ctx.control_rx.try_recv() {
+                if let Some(r) = self.our_handle_control_message(ctx, Some(msg)).await {
+                    return Ok(Some(r));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn emit(&mut self, ctx: &mut Context<(), T>, buf: &[u8]) -> Result<(), UserError> {
+        self.deserializer.throttle(buf).await;
+        let iter = self.deserializer.deserialize_slice(buf);
+
+        for record in iter {
+            match record {
+                Ok(value) => {
+                    ctx.collect(Record {
+                        timestamp: SystemTime::now(),
+                        key: None,
+                        value,
+                    })
+                    .await;
+                }
+                Err(e) => {
+                    if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, buf, e)? {
+                        ctx.report_user_error(e).await;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
         // since there's no way to partition across an http source, only read on the first task
         let mut timer = tokio::time::interval(self.polling_interval);
@@ -251,33 +488,11 @@ where
             loop {
                 select! {
                     _ = timer.tick()  => {
-                        match self.request().await {
-                            Ok(buf) => {
-                                if self.emit_behavior == EmitBehavior::Changed {
-                                    if Some(&buf) == self.state.last_message.as_ref() {
-                                        continue;
-                                    }
-                                }
-
-                                let iter = self.deserializer.deserialize_slice(&buf);
-
-                                for record in iter {
-                                    match record {
-                                        Ok(value) => {
-                                            ctx.collect(Record {
-                                                timestamp: SystemTime::now(),
-                                                key: None,
-                                                value,
-                                            }).await;
-                                        }
-                                        Err(e) => {
-                                            ctx.report_user_error(e).await;
-                                        }
-                                    }
-                                }
-
-                                self.state.last_message = Some(buf);
+                        match self.poll(ctx).await {
+                            Ok(Some(r)) => {
+                                return r;
                             }
+                            Ok(None) => {}
                             Err(e) => {
                                 ctx.report_user_error(e).await;
                             }
@@ -302,3 +517,121 @@ where
         }
     }
 }
+
+/// Substitutes `{{ watermark }}` in the template
+fn render_template(template: &str, watermark: Option<&str>) -> String {
+    static TEMPLATE: OnceLock<Regex> = OnceLock::new();
+    TEMPLATE
+        .get_or_init(|| Regex::new(r"\{\{\s*watermark\s*\}\}").unwrap())
+        .replace_all(template, watermark.unwrap_or(""))
+        .to_string()
+}
+
+/// Moves the watermark forward if `value` is larger; numbers are compared numerically and
+/// strings (like RFC3339 timestamps) lexicographically
+fn advance_watermark(watermark: &mut Option<Value>, value: &Value) {
+    let larger = match (&watermark, value) {
+        (_, Value::Null) => false,
+        (None, _) => true,
+        (Some(Value::Number(a)), Value::Number(b)) => b.as_f64() > a.as_f64(),
+        (Some(Value::String(a)), Value::String(b)) => b > a,
+        _ => false,
+    };
+
+    if larger {
+        *watermark = Some(value.clone());
+    }
+}
+
+/// Returns `url` with the query parameter `name` set to `value`
+fn with_query_param(url: &url::Url, name: &str, value: &str) -> url::Url {
+    let pairs: Vec<_> = url
+        .query_pairs()
+        .filter(|(k, _)| k != name)
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(name, value);
+    url
+}
+
+/// Finds the `rel="next"` link in a Link header, like
+/// `<https://api.example.com/items?page=2>; rel="next", <...>; rel="last"`
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+        let target = parts.next()?.trim();
+        let is_next = parts.any(|p| {
+            let p = p.trim();
+            p == "rel=\"next\"" || p == "rel=next"
+        });
+
+        is_next.then(|| {
+            target
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{advance_watermark, next_link, render_template, with_query_param};
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            render_template(
+                "https://example.com/items?since={{ watermark }}",
+                Some("10")
+            ),
+            "https://example.com/items?since=10"
+        );
+        assert_eq!(
+            render_template("{\"since\": \"{{watermark}}\"}", None),
+            "{\"since\": \"\"}"
+        );
+    }
+
+    #[test]
+    fn test_advance_watermark() {
+        let mut watermark = None;
+        advance_watermark(&mut watermark, &json!(5));
+        advance_watermark(&mut watermark, &json!(3));
+        advance_watermark(&mut watermark, &json!(null));
+        assert_eq!(watermark, Some(json!(5)));
+
+        let mut watermark = Some(json!("2023-01-01T00:00:00Z"));
+        advance_watermark(&mut watermark, &json!("2023-02-01T00:00:00Z"));
+        advance_watermark(&mut watermark, &json!("2022-12-01T00:00:00Z"));
+        assert_eq!(watermark, Some(json!("2023-02-01T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_pagination() {
+        let url = url::Url::parse("https://example.com/items?limit=10&cursor=abc").unwrap();
+        assert_eq!(
+            with_query_param(&url, "cursor", "d e").as_str(),
+            "https://example.com/items?limit=10&cursor=d+e"
+        );
+
+        assert_eq!(
+            next_link(
+                "<https://example.com/items?page=1>; rel=\"prev\", \
+                <https://example.com/items?page=3>; rel=\"next\""
+            ),
+            Some("https://example.com/items?page=3".to_string())
+        );
+        assert_eq!(
+            next_link("<https://example.com/items?page=1>; rel=\"last\""),
+            None
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use arroyo_rpc::OperatorConfig;
+use async_trait::async_trait;
+use tokio_postgres::Client;
+
+use crate::operators::lookup_join::LookupSource;
+
+use super::{connect, quote_ident, PostgresConfig, PostgresTable};
+
+/// Looks up the rows of a Postgres table whose key column matches, read as JSON with
+/// `row_to_json`
+pub struct PostgresLookup {
+    config: PostgresConfig,
+    table: PostgresTable,
+    key_column: String,
+    client: Option<Client>,
+}
+
+impl PostgresLookup {
+    pub fn from_config(config: &str, key_column: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PostgresLookup");
+        let connection: PostgresConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PostgresLookup");
+        let table: PostgresTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PostgresLookup");
+
+        Self {
+            config: connection,
+            table,
+            key_column: key_column.to_string(),
+            client: None,
+        }
+    }
+}
+
+#[async_trait]
+impl LookupSource for PostgresLookup {
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        if self.client.as_ref().map(|c| c.is_closed()).unwrap_or(true) {
+            self.client = Some(connect(&self.config).await?);
+        }
+
+        // keys are compared as text so that they can be passed in a single array, whatever the
+        // type of the key column; if several rows share a key, the last one returned wins
+        let statement = format!(
+            "SELECT t.{key}::text, row_to_json(t)::text FROM {table} t WHERE t.{key}::text = ANY($1)",
+            key = quote_ident(&self.key_column),
+            table = self.table.qualified_name(),
+        );
+
+        let rows: HashMap<String, String> = self
+            .client
+            .as_ref()
+            .unwrap()
+            .query(&statement, &[&keys])
+            .await?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(keys
+            .iter()
+            .map(|key| rows.get(key).map(|row| row.as_bytes().to_vec()))
+            .collect())
+    }
+}
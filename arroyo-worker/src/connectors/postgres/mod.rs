@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub use super::postgres_cdc::{connect, PostgresConfig};
+
+pub mod lookup;
+pub mod sink;
+
+import_types!(schema = "../connector-schemas/postgres/table.json");
+
+impl PostgresTable {
+    pub fn schema_name(&self) -> &str {
+        self.schema_name.as_deref().unwrap_or("public")
+    }
+
+    /// The quoted, schema-qualified name of the table
+    pub fn qualified_name(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_ident(self.schema_name()),
+            quote_ident(&self.table_name)
+        )
+    }
+}
+
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_rpc::formats::{Format, JsonFormat};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::{Data, Key, Record, TaskInfo};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::Client;
+use tracing::{info, warn};
+
+use crate::connectors::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
+
+use super::{connect, quote_ident, CommitMode, PostgresConfig, PostgresTable};
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Upsert(Value),
+    Delete(Value),
+}
+
+pub struct PostgresSinkFunc<K: Key, T: Data + Sync + Serialize> {
+    config: PostgresConfig,
+    table: PostgresTable,
+    debezium: bool,
+    client: Option<Client>,
+    primary_keys: Vec<String>,
+    columns: Option<Vec<String>>,
+    // changes are buffered in arrival order; when the table has a primary key only the last change
+    // for each row is kept, as Postgres won't let a single statement affect a row twice
+    buffer: Vec<Change>,
+    buffer_index: HashMap<String, usize>,
+    last_flush: Instant,
+    in_transaction: bool,
+    epoch: u64,
+    _t: PhantomData<(K, T)>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct PostgresSinkRecovery {
+    task_index: usize,
+    // the epoch of the last transaction prepared by this subtask
+    epoch: u64,
+}
+
+impl<K: Key, T: Data + Sync + Serialize> PostgresSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> TwoPhaseCommitterOperator<K, T, Self> {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PostgresSink");
+        let connection: PostgresConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PostgresSink");
+        let table: PostgresTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PostgresSink");
+
+        let debezium = match config.format {
+            Some(Format::Json(JsonFormat { debezium, .. })) => debezium,
+            _ => panic!("PostgresSink requires a JSON format"),
+        };
+
+        TwoPhaseCommitterOperator::new(Self {
+            config: connection,
+            table,
+            debezium,
+            client: None,
+            primary_keys: vec![],
+            columns: None,
+            buffer: vec![],
+            buffer_index: HashMap::new(),
+            last_flush: Instant::now(),
+            in_transaction: false,
+            epoch: 0,
+            _t: PhantomData,
+        })
+    }
+
+    fn exactly_once(&self) -> bool {
+        matches!(self.table.commit_mode, Some(CommitMode::ExactlyOnce))
+    }
+
+    fn client(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("postgres sink was not initialized")
+    }
+
+    async fn load_primary_keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .client()
+            .query(
+                "SELECT a.attname::text FROM pg_index i \
+                 JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+                 WHERE i.indrelid = format('%I.%I', $1::text, $2::text)::regclass \
+                 AND i.indisprimary",
+                &[&self.table.schema_name(), &self.table.table_name],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    /// Rolls back transactions that were prepared by this subtask (or by subtasks that no
+    /// longer exist) but were never part of a completed checkpoint
+    async fn abort_orphaned_transactions(
+        &self,
+        task_info: &TaskInfo,
+        recovered: &HashMap<usize, u64>,
+    ) -> Result<()> {
+        let prefix = transaction_prefix(task_info);
+        let rows = self
+            .client()
+            .query(
+                "SELECT gid FROM pg_prepared_xacts WHERE database = current_database()",
+                &[],
+            )
+            .await?;
+
+        for row in rows {
+            let gid: String = row.get(0);
+            let Some((task_index, epoch)) = gid
+                .strip_prefix(&prefix)
+                .and_then(|s| s.split_once('_'))
+                .and_then(|(t, e)| Some((t.parse::<usize>().ok()?, e.parse::<u64>().ok()?)))
+            else {
+                continue;
+            };
+
+            if task_index % task_info.parallelism != task_info.task_index
+                || recovered
+                    .get(&task_index)
+                    .map(|e| epoch <= *e)
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+
+            info!("rolling back orphaned prepared transaction {}", gid);
+            self.client()
+                .batch_execute(&format!("ROLLBACK PREPARED '{}'", gid))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn key(&self, row: &Value) -> Result<Option<String>> {
+        if self.primary_keys.is_empty() {
+            return Ok(None);
+        }
+
+        let key: Vec<&Value> = self
+            .primary_keys
+            .iter()
+            .map(|k| {
+                row.get(k)
+                    .ok_or_else(|| anyhow!("primary key '{}' missing from record", k))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Some(serde_json::to_string(&key)?))
+    }
+
+    fn buffer_change(&mut self, change: Change) -> Result<()> {
+        let row = match &change {
+            Change::Upsert(row) => row,
+            Change::Delete(row) => {
+                if self.primary_keys.is_empty() {
+                    bail!(
+                        "cannot delete from table {} without a primary key; set primary_keys in \
+                        the table config",
+                        self.table.qualified_name()
+                    );
+                }
+                row
+            }
+        };
+
+        if self.columns.is_none() {
+            let Value::Object(fields) = row else {
+                bail!("expected a JSON object but found {}", row);
+            };
+            self.columns = Some(fields.keys().cloned().collect());
+        }
+
+        match self.key(row)? {
+            Some(key) => {
+                if let Some(idx) = self.buffer_index.get(&key) {
+                    self.buffer[*idx] = change;
+                } else {
+                    self.buffer_index.insert(key, self.buffer.len());
+                    self.buffer.push(change);
+                }
+            }
+            None => {
+                self.buffer.push(change);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn upsert_statement(&self, columns: &[String]) -> String {
+        let table = self.table.qualified_name();
+        let column_list = columns
+            .iter()
+            .map(|c| quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut statement = format!(
+            "INSERT INTO {table} ({column_list}) SELECT {column_list} \
+             FROM json_populate_recordset(NULL::{table}, $1::text::json)"
+        );
+
+        if !self.primary_keys.is_empty() {
+            let keys = self
+                .primary_keys
+                .iter()
+                .map(|c| quote_ident(c))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let updates: Vec<_> = columns
+                .iter()
+                .filter(|c| !self.primary_keys.contains(c))
+                .map(|c| format!("{0} = EXCLUDED.{0}", quote_ident(c)))
+                .collect();
+
+            if updates.is_empty() {
+                statement.push_str(&format!(" ON CONFLICT ({}) DO NOTHING", keys));
+            } else {
+                statement.push_str(&format!(
+                    " ON CONFLICT ({}) DO UPDATE SET {}",
+                    keys,
+                    updates.join(", ")
+                ));
+            }
+        }
+
+        statement
+    }
+
+    fn delete_statement(&self) -> String {
+        let table = self.table.qualified_name();
+        let condition = self
+            .primary_keys
+            .iter()
+            .map(|c| format!("t.{0} = d.{0}", quote_ident(c)))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        format!(
+            "DELETE FROM {table} AS t \
+             USING json_populate_recordset(NULL::{table}, $1::text::json) AS d WHERE {condition}"
+        )
+    }
+
+    /// Writes the buffered changes to the table. In exactly-once mode they are written into the
+    /// open transaction, which is prepared at the next checkpoint; otherwise they are committed
+    /// immediately.
+    async fn flush(&mut self) -> Result<()> {
+        self.last_flush = Instant::now();
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut upserts = vec![];
+        let mut deletes = vec![];
+        for change in self.buffer.drain(..) {
+            match change {
+                Change::Upsert(row) => upserts.push(row),
+                Change::Delete(row) => deletes.push(row),
+            }
+        }
+        self.buffer_index.clear();
+
+        if !self.in_transaction {
+            self.client().batch_execute("BEGIN").await?;
+            self.in_transaction = true;
+        }
+
+        // each key appears at most once in a batch, so the deletes and upserts are independent
+        if !deletes.is_empty() {
+            let rows = serde_json::to_string(&deletes)?;
+            self.client()
+                .execute(&self.delete_statement(), &[&rows])
+                .await?;
+        }
+
+        if !upserts.is_empty() {
+            let rows = serde_json::to_string(&upserts)?;
+            let statement = self.upsert_statement(self.columns.as_ref().unwrap());
+            self.client().execute(&statement, &[&rows]).await?;
+        }
+
+        if !self.exactly_once() {
+            self.client().batch_execute("COMMIT").await?;
+            self.in_transaction = false;
+        }
+
+        Ok(())
+    }
+}
+
+fn transaction_prefix(task_info: &TaskInfo) -> String {
+    format!("arroyo_{}_{}_", task_info.job_id, task_info.operator_id)
+}
+
+fn transaction_id(task_info: &TaskInfo, epoch: u64) -> String {
+    format!(
+        "{}{}_{}",
+        transaction_prefix(task_info),
+        task_info.task_index,
+        epoch
+    )
+}
+
+#[async_trait]
+impl<K: Key, T: Data + Sync + Serialize> TwoPhaseCommitter<K, T> for PostgresSinkFunc<K, T> {
+    type DataRecovery = PostgresSinkRecovery;
+    type PreCommit = String;
+
+    fn name(&self) -> String {
+        "postgres_sink".to_string()
+    }
+
+    async fn init(
+        &mut self,
+        task_info: &TaskInfo,
+        data_recovery: Vec<Self::DataRecovery>,
+    ) -> Result<()> {
+        self.client = Some(connect(&self.config).await?);
+
+        self.primary_keys = if self.table.primary_keys.is_empty() {
+            self.load_primary_keys().await?
+        } else {
+            self.table.primary_keys.clone()
+        };
+
+        if self.primary_keys.is_empty() && self.debezium {
+            bail!(
+                "table {} has no primary key, so it can't be used with updating queries; set \
+                primary_keys in the table config",
+                self.table.qualified_name()
+            );
+        }
+
+        let recovered: HashMap<usize, u64> = data_recovery
+            .into_iter()
+            .map(|r| (r.task_index, r.epoch))
+            .collect();
+
+        self.epoch = recovered.values().max().map(|e| e + 1).unwrap_or(0);
+
+        if self.exactly_once() {
+            self.abort_orphaned_transactions(task_info, &recovered)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn insert_record(&mut self, record: &Record<K, T>) -> Result<()> {
+        let value = serde_json::to_value(&record.value)?;
+
+        if self.debezium {
+            let op = value.get("op").and_then(|op| op.as_str());
+            let before = value.get("before").filter(|v| !v.is_null());
+            let after = value.get("after").filter(|v| !v.is_null());
+
+            match (op, before, after) {
+                (Some("c"), _, Some(after)) => {
+                    self.buffer_change(Change::Upsert(after.clone()))?;
+                }
+                (Some("u"), Some(before), Some(after)) => {
+                    // if the key changed, the old row needs to be removed
+                    if self.key(before)? != self.key(after)? {
+                        self.buffer_change(Change::Delete(before.clone()))?;
+                    }
+                    self.buffer_change(Change::Upsert(after.clone()))?;
+                }
+                (Some("d"), Some(before), _) => {
+                    self.buffer_change(Change::Delete(before.clone()))?;
+                }
+                _ => bail!("invalid debezium record: {}", value),
+            }
+        } else {
+            self.buffer_change(Change::Upsert(value))?;
+        }
+
+        let batch_size = self
+            .table
+            .batch_size
+            .map(|s| s as usize)
+            .unwrap_or(DEFAULT_BATCH_SIZE);
+
+        let flush_interval = self
+            .table
+            .flush_interval_ms
+            .map(|ms| Duration::from_millis(ms as u64))
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL);
+
+        // there's no timer, so the interval is only checked as records arrive; anything left over
+        // is written at the next checkpoint
+        if self.buffer.len() >= batch_size || self.last_flush.elapsed() >= flush_interval {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn commit(
+        &mut self,
+        _task_info: &TaskInfo,
+        pre_commit: Vec<Self::PreCommit>,
+    ) -> Result<()> {
+        for gid in pre_commit {
+            match self
+                .client()
+                .batch_execute(&format!("COMMIT PREPARED '{}'", gid))
+                .await
+            {
+                Ok(_) => {}
+                // the transaction was already committed before we restarted
+                Err(e) if e.code() == Some(&SqlState::UNDEFINED_OBJECT) => {
+                    warn!("prepared transaction {} no longer exists: {:?}", gid, e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn checkpoint(
+        &mut self,
+        task_info: &TaskInfo,
+        _watermark: Option<SystemTime>,
+        _stopping: bool,
+    ) -> Result<(Self::DataRecovery, HashMap<String, Self::PreCommit>)> {
+        self.flush().await?;
+
+        let mut pre_commits = HashMap::new();
+        if self.in_transaction {
+            let gid = transaction_id(task_info, self.epoch);
+            self.client()
+                .batch_execute(&format!("PREPARE TRANSACTION '{}'", gid))
+                .await?;
+            self.in_transaction = false;
+            pre_commits.insert(gid.clone(), gid);
+        }
+
+        let recovery = PostgresSinkRecovery {
+            task_index: task_info.task_index,
+            epoch: self.epoch,
+        };
+        self.epoch += 1;
+
+        Ok((recovery, pre_commits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sink(table: Value, primary_keys: &[&str]) -> PostgresSinkFunc<(), String> {
+        PostgresSinkFunc {
+            config: serde_json::from_value(json!({
+                "host": "localhost",
+                "database": "db",
+                "username": "arroyo",
+            }))
+            .unwrap(),
+            table: serde_json::from_value(table).unwrap(),
+            debezium: false,
+            client: None,
+            primary_keys: primary_keys.iter().map(|k| k.to_string()).collect(),
+            columns: None,
+            buffer: vec![],
+            buffer_index: HashMap::new(),
+            last_flush: Instant::now(),
+            in_transaction: false,
+            epoch: 0,
+            _t: PhantomData,
+        }
+    }
+
+    #[test]
+    fn test_commit_mode() {
+        // prepared transactions are opt-in, as they're disabled on most servers
+        assert!(!sink(json!({"table_name": "t"}), &[]).exactly_once());
+        assert!(!sink(
+            json!({"table_name": "t", "commit_mode": "at_least_once"}),
+            &[]
+        )
+        .exactly_once());
+        assert!(sink(
+            json!({"table_name": "t", "commit_mode": "exactly_once"}),
+            &[]
+        )
+        .exactly_once());
+    }
+
+    #[test]
+    fn test_statements() {
+        let s = sink(json!({"table_name": "t", "schema_name": "s"}), &["id"]);
+        let columns = vec!["id".to_string(), "value".to_string()];
+
+        assert_eq!(
+            "INSERT INTO \"s\".\"t\" (\"id\", \"value\") SELECT \"id\", \"value\" \
+             FROM json_populate_recordset(NULL::\"s\".\"t\", $1::text::json) \
+             ON CONFLICT (\"id\") DO UPDATE SET \"value\" = EXCLUDED.\"value\"",
+            s.upsert_statement(&columns)
+        );
+
+        assert_eq!(
+            "DELETE FROM \"s\".\"t\" AS t \
+             USING json_populate_recordset(NULL::\"s\".\"t\", $1::text::json) AS d \
+             WHERE t.\"id\" = d.\"id\"",
+            s.delete_statement()
+        );
+
+        let s = sink(json!({"table_name": "t"}), &[]);
+        assert_eq!(
+            "INSERT INTO \"public\".\"t\" (\"id\", \"value\") SELECT \"id\", \"value\" \
+             FROM json_populate_recordset(NULL::\"public\".\"t\", $1::text::json)",
+            s.upsert_statement(&columns)
+        );
+    }
+
+    #[test]
+    fn test_buffer_keeps_last_change_per_key() {
+        let mut s = sink(json!({"table_name": "t"}), &["id"]);
+
+        s.buffer_change(Change::Upsert(json!({"id": 1, "value": "a"})))
+            .unwrap();
+        s.buffer_change(Change::Upsert(json!({"id": 2, "value": "b"})))
+            .unwrap();
+        s.buffer_change(Change::Delete(json!({"id": 1, "value": "a"})))
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                Change::Delete(json!({"id": 1, "value": "a"})),
+                Change::Upsert(json!({"id": 2, "value": "b"})),
+            ],
+            s.buffer
+        );
+        assert_eq!(Some(vec!["id".to_string(), "value".to_string()]), s.columns);
+
+        assert!(s
+            .buffer_change(Change::Upsert(json!({"value": "c"})))
+            .is_err());
+    }
+
+    #[test]
+    fn test_buffer_without_primary_key() {
+        let mut s = sink(json!({"table_name": "t"}), &[]);
+
+        s.buffer_change(Change::Upsert(json!({"id": 1}))).unwrap();
+        s.buffer_change(Change::Upsert(json!({"id": 1}))).unwrap();
+        assert_eq!(2, s.buffer.len());
+
+        assert!(s.buffer_change(Change::Delete(json!({"id": 1}))).is_err());
+    }
+
+    #[test]
+    fn test_transaction_ids() {
+        let mut task_info = TaskInfo::for_test("job", "op");
+        task_info.task_index = 3;
+        assert_eq!("arroyo_job_op_3_7", transaction_id(&task_info, 7));
+        assert!(transaction_id(&task_info, 7).starts_with(&transaction_prefix(&task_info)));
+    }
+}
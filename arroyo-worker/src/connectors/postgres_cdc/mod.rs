@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use typify::import_types;
+
+pub mod pgoutput;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/postgres/connection.json");
+import_types!(schema = "../connector-schemas/postgres_cdc/table.json");
+
+impl PostgresCdcTable {
+    pub fn schema_name(&self) -> &str {
+        self.schema_name.as_deref().unwrap_or("public")
+    }
+
+    pub fn slot_name(&self) -> String {
+        self.slot_name
+            .clone()
+            .unwrap_or_else(|| format!("arroyo_{}", self.table_name.to_lowercase()))
+    }
+
+    /// The temporary slot that changes are consumed from, which is copied from the main slot
+    /// when the source starts
+    pub fn reader_slot_name(&self) -> String {
+        format!("{}_reader", self.slot_name())
+    }
+
+    pub fn publication_name(&self) -> String {
+        self.publication_name
+            .clone()
+            .unwrap_or_else(|| format!("arroyo_{}", self.table_name.to_lowercase()))
+    }
+}
+
+pub(crate) async fn connect(config: &PostgresConfig) -> anyhow::Result<tokio_postgres::Client> {
+    let port = config.port.unwrap_or(5432);
+    if !(1..=65535).contains(&port) {
+        bail!("invalid port {}", port);
+    }
+
+    let mut pg_config = tokio_postgres::Config::new();
+    pg_config
+        .host(&config.host)
+        .port(port as u16)
+        .dbname(&config.database)
+        .user(&config.username)
+        .application_name("arroyo")
+        .connect_timeout(Duration::from_secs(10))
+        .ssl_mode(match config.ssl_mode {
+            Some(SslMode::Disable) => tokio_postgres::config::SslMode::Disable,
+            Some(SslMode::Prefer) | None => tokio_postgres::config::SslMode::Prefer,
+            Some(SslMode::Require) => tokio_postgres::config::SslMode::Require,
+        });
+
+    if let Some(password) = &config.password {
+        pg_config.password(password);
+    }
+
+    let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+    let (client, connection) = pg_config.connect(tls).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            warn!("Postgres connection error: {:?}", e);
+        }
+    });
+
+    Ok(client)
+}
@@ -0,0 +1,357 @@
+//! Decoder for the messages produced by Postgres's built-in `pgoutput` logical decoding plugin
+//! (protocol version 1). See
+//! https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveDateTime};
+use serde_json::{json, Number, Value};
+
+// Postgres timestamps count from 2000-01-01
+const PG_EPOCH_OFFSET_SECS: u64 = 946_684_800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub type_oid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub id: u32,
+    pub namespace: String,
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TupleValue {
+    Null,
+    // an unchanged TOASTed value, which isn't sent by the server
+    Unchanged,
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgOutputMessage {
+    Begin {
+        final_lsn: u64,
+        commit_time: SystemTime,
+    },
+    Commit {
+        commit_lsn: u64,
+    },
+    Relation(Relation),
+    Insert {
+        relation_id: u32,
+        new: Vec<TupleValue>,
+    },
+    Update {
+        relation_id: u32,
+        old: Option<Vec<TupleValue>>,
+        new: Vec<TupleValue>,
+    },
+    Delete {
+        relation_id: u32,
+        old: Vec<TupleValue>,
+    },
+    // truncate, type, origin, and logical messages, which we don't need
+    Other(u8),
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < n {
+            bail!("unexpected end of pgoutput message");
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> anyhow::Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> anyhow::Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> anyhow::Result<String> {
+        let end = self
+            .data
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow!("unterminated string in pgoutput message"))?;
+        let s = String::from_utf8(self.take(end)?.to_vec())?;
+        self.take(1)?;
+        Ok(s)
+    }
+
+    fn tuple(&mut self) -> anyhow::Result<Vec<TupleValue>> {
+        let n = self.i16()?;
+        (0..n)
+            .map(|_| match self.u8()? {
+                b'n' => Ok(TupleValue::Null),
+                b'u' => Ok(TupleValue::Unchanged),
+                b't' => {
+                    let len = self.i32()? as usize;
+                    Ok(TupleValue::Text(String::from_utf8(
+                        self.take(len)?.to_vec(),
+                    )?))
+                }
+                b => bail!("unsupported tuple value type '{}'", b as char),
+            })
+            .collect()
+    }
+}
+
+fn pg_time(micros: i64) -> SystemTime {
+    let base = UNIX_EPOCH + Duration::from_secs(PG_EPOCH_OFFSET_SECS);
+    if micros >= 0 {
+        base + Duration::from_micros(micros as u64)
+    } else {
+        base - Duration::from_micros(micros.unsigned_abs())
+    }
+}
+
+impl PgOutputMessage {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let mut r = Reader { data };
+        let tag = r.u8()?;
+        Ok(match tag {
+            b'B' => {
+                let final_lsn = r.i64()? as u64;
+                let commit_time = pg_time(r.i64()?);
+                PgOutputMessage::Begin {
+                    final_lsn,
+                    commit_time,
+                }
+            }
+            b'C' => {
+                let _flags = r.u8()?;
+                PgOutputMessage::Commit {
+                    commit_lsn: r.i64()? as u64,
+                }
+            }
+            b'R' => {
+                let id = r.i32()? as u32;
+                let namespace = r.cstr()?;
+                let name = r.cstr()?;
+                let _replica_identity = r.u8()?;
+                let n = r.i16()?;
+                let columns = (0..n)
+                    .map(|_| {
+                        let _flags = r.u8()?;
+                        let name = r.cstr()?;
+                        let type_oid = r.i32()? as u32;
+                        let _type_modifier = r.i32()?;
+                        Ok(Column { name, type_oid })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                PgOutputMessage::Relation(Relation {
+                    id,
+                    namespace,
+                    name,
+                    columns,
+                })
+            }
+            b'I' => {
+                let relation_id = r.i32()? as u32;
+                if r.u8()? != b'N' {
+                    bail!("expected new tuple in insert message");
+                }
+                PgOutputMessage::Insert {
+                    relation_id,
+                    new: r.tuple()?,
+                }
+            }
+            b'U' => {
+                let relation_id = r.i32()? as u32;
+                let (old, new) = match r.u8()? {
+                    b'K' | b'O' => {
+                        let old = r.tuple()?;
+                        if r.u8()? != b'N' {
+                            bail!("expected new tuple in update message");
+                        }
+                        (Some(old), r.tuple()?)
+                    }
+                    b'N' => (None, r.tuple()?),
+                    b => bail!("unexpected tuple type '{}' in update message", b as char),
+                };
+                PgOutputMessage::Update {
+                    relation_id,
+                    old,
+                    new,
+                }
+            }
+            b'D' => {
+                let relation_id = r.i32()? as u32;
+                match r.u8()? {
+                    b'K' | b'O' => {}
+                    b => bail!("unexpected tuple type '{}' in delete message", b as char),
+                }
+                PgOutputMessage::Delete {
+                    relation_id,
+                    old: r.tuple()?,
+                }
+            }
+            other => PgOutputMessage::Other(other),
+        })
+    }
+}
+
+/// Converts a value from Postgres's text output format into JSON, using the column type to
+/// produce numbers, booleans, and timestamps (as unix millis) where possible
+fn text_to_json(type_oid: u32, text: String) -> Value {
+    match type_oid {
+        // bool
+        16 => Value::Bool(text == "t"),
+        // int2, int4, int8, oid
+        20 | 21 | 23 | 26 => text
+            .parse::<i64>()
+            .map(|i| Value::Number(i.into()))
+            .unwrap_or(Value::String(text)),
+        // float4, float8, numeric
+        700 | 701 | 1700 => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        // timestamp
+        1114 => NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f")
+            .map(|t| Value::Number(t.timestamp_millis().into()))
+            .unwrap_or(Value::String(text)),
+        // timestamptz
+        1184 => DateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f%#z")
+            .map(|t| Value::Number(t.timestamp_millis().into()))
+            .unwrap_or(Value::String(text)),
+        _ => Value::String(text),
+    }
+}
+
+impl Relation {
+    pub fn to_json(&self, tuple: Vec<TupleValue>) -> Value {
+        Value::Object(
+            self.columns
+                .iter()
+                .zip(tuple)
+                .map(|(column, value)| {
+                    let value = match value {
+                        TupleValue::Null | TupleValue::Unchanged => Value::Null,
+                        TupleValue::Text(text) => text_to_json(column.type_oid, text),
+                    };
+                    (column.name.clone(), value)
+                })
+                .collect(),
+        )
+    }
+
+    /// Produces a Debezium-style change envelope for a row change in this relation
+    pub fn envelope(
+        &self,
+        before: Option<Vec<TupleValue>>,
+        after: Option<Vec<TupleValue>>,
+        op: &str,
+    ) -> Value {
+        json!({
+            "before": before.map(|t| self.to_json(t)),
+            "after": after.map(|t| self.to_json(t)),
+            "op": op,
+        })
+    }
+}
+
+pub fn parse_lsn(lsn: &str) -> anyhow::Result<u64> {
+    let (hi, lo) = lsn
+        .split_once('/')
+        .ok_or_else(|| anyhow!("invalid LSN '{}'", lsn))?;
+    Ok((u64::from_str_radix(hi, 16)? << 32) | u64::from_str_radix(lo, 16)?)
+}
+
+pub fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(buf: &mut Vec<u8>, s: &str) {
+        buf.push(b't');
+        buf.extend_from_slice(&(s.len() as i32).to_be_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_decode_relation_and_changes() {
+        let mut relation = vec![b'R'];
+        relation.extend_from_slice(&16384i32.to_be_bytes());
+        relation.extend_from_slice(b"public\0orders\0");
+        relation.push(b'f');
+        relation.extend_from_slice(&3i16.to_be_bytes());
+        for (name, oid) in [("id", 20i32), ("paid", 16), ("created_at", 1184)] {
+            relation.push(1);
+            relation.extend_from_slice(name.as_bytes());
+            relation.push(0);
+            relation.extend_from_slice(&oid.to_be_bytes());
+            relation.extend_from_slice(&(-1i32).to_be_bytes());
+        }
+
+        let PgOutputMessage::Relation(relation) = PgOutputMessage::decode(&relation).unwrap()
+        else {
+            panic!("expected relation");
+        };
+        assert_eq!(relation.name, "orders");
+        assert_eq!(relation.columns.len(), 3);
+
+        let mut update = vec![b'U'];
+        update.extend_from_slice(&16384i32.to_be_bytes());
+        update.push(b'O');
+        update.extend_from_slice(&3i16.to_be_bytes());
+        text(&mut update, "5");
+        text(&mut update, "f");
+        text(&mut update, "2023-06-01 12:00:00.5+00");
+        update.push(b'N');
+        update.extend_from_slice(&3i16.to_be_bytes());
+        text(&mut update, "5");
+        text(&mut update, "t");
+        update.push(b'n');
+
+        let PgOutputMessage::Update { old, new, .. } = PgOutputMessage::decode(&update).unwrap()
+        else {
+            panic!("expected update");
+        };
+
+        assert_eq!(
+            relation.envelope(old, Some(new), "u"),
+            json!({
+                "before": {"id": 5, "paid": false, "created_at": 1685620800500i64},
+                "after": {"id": 5, "paid": true, "created_at": null},
+                "op": "u",
+            })
+        );
+    }
+
+    #[test]
+    fn test_lsn_round_trip() {
+        let lsn = parse_lsn("16/B374D848").unwrap();
+        assert_eq!(lsn, 0x16_B374_D848);
+        assert_eq!(format_lsn(lsn), "16/B374D848");
+    }
+}
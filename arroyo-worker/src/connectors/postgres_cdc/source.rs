@@ -0,0 +1,440 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Format};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::pgoutput::{format_lsn, parse_lsn, PgOutputMessage, Relation};
+use super::{connect, PostgresCdcTable, PostgresConfig};
+
+// the number of changes to request from the slot in each poll
+const BATCH_SIZE: i32 = 1000;
+
+#[derive(StreamNode)]
+pub struct PostgresCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: PostgresConfig,
+    table: PostgresCdcTable,
+    deserializer: DataDeserializer<T>,
+    relations: HashMap<u32, Relation>,
+    // the commit LSN of the last transaction we've emitted
+    lsn: u64,
+    // the LSN stored in the previous checkpoint, which the slot can safely be advanced to
+    checkpointed_lsn: u64,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct PostgresCdcState {
+    lsn: u64,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> PostgresCdcSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PostgresCdcSource");
+        let connection: PostgresConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PostgresCdcSource");
+        let table: PostgresCdcTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for PostgresCdcSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for PostgresCdcSource"),
+            config.bad_data,
+            config.rate_limit,
+        )
+    }
+
+    pub fn new(
+        config: PostgresConfig,
+        table: PostgresCdcTable,
+        format: Format,
+        bad_data: Option<BadData>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        Self {
+            config,
+            table,
+            deserializer: DataDeserializer::new(format, None)
+                .with_bad_data(bad_data)
+                .with_rate_limit(rate_limit),
+            relations: HashMap::new(),
+            lsn: 0,
+            checkpointed_lsn: 0,
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("postgres-cdc-{}", self.table.table_name)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("p", "postgres cdc source state")]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    /// Creates the publication and replication slot if they don't already exist, and the
+    /// temporary reader slot for this session
+    async fn setup(&self, client: &tokio_postgres::Client) -> anyhow::Result<()> {
+        // make sure timestamptz values are rendered in UTC
+        client.batch_execute("SET TimeZone = 'UTC'").await?;
+
+        let publication = self.table.publication_name();
+        if client
+            .query_opt(
+                "SELECT 1 FROM pg_publication WHERE pubname = $1",
+                &[&publication],
+            )
+            .await?
+            .is_none()
+        {
+            info!("creating publication {}", publication);
+            client
+                .batch_execute(&format!(
+                    "CREATE PUBLICATION \"{}\" FOR TABLE \"{}\".\"{}\"",
+                    publication,
+                    self.table.schema_name(),
+                    self.table.table_name
+                ))
+                .await?;
+        }
+
+        let slot = self.table.slot_name();
+        if client
+            .query_opt(
+                "SELECT 1 FROM pg_replication_slots WHERE slot_name = $1",
+                &[&slot],
+            )
+            .await?
+            .is_none()
+        {
+            info!("creating logical replication slot {}", slot);
+            client
+                .execute(
+                    "SELECT pg_create_logical_replication_slot($1, 'pgoutput')",
+                    &[&slot],
+                )
+                .await?;
+        }
+
+        // the main slot is only advanced once changes have been checkpointed, so we consume
+        // changes from a temporary copy of it, which is dropped when the session ends. That way
+        // each poll starts where the previous one left off instead of re-reading everything
+        // since the last checkpoint.
+        let reader = self.table.reader_slot_name();
+        client
+            .execute(
+                "SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots \
+                 WHERE slot_name = $1 AND NOT active",
+                &[&reader],
+            )
+            .await?;
+        client
+            .execute(
+                "SELECT pg_copy_logical_replication_slot($1, $2, true)",
+                &[&slot, &reader],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reads the next batch of changes from the slot, returning whether any new transactions were
+    /// emitted
+    async fn poll(
+        &mut self,
+        client: &tokio_postgres::Client,
+        ctx: &mut Context<(), T>,
+    ) -> Result<bool, UserError> {
+        // the reader slot starts from the main slot's position, which may be behind the
+        // checkpoint we restored from, so we skip any transactions we've already emitted
+        let rows = client
+            .query(
+                "SELECT data FROM pg_logical_slot_get_binary_changes($1, NULL, $2, \
+                 'proto_version', '1', 'publication_names', $3)",
+                &[
+                    &self.table.reader_slot_name(),
+                    &BATCH_SIZE,
+                    &self.table.publication_name(),
+                ],
+            )
+            .await
+            .map_err(|e| {
+                UserError::new("Failed to read from replication slot", format!("{:?}", e))
+            })?;
+
+        let mut emitted = false;
+        let mut skipping = true;
+        let mut commit_time = SystemTime::now();
+
+        for row in rows {
+            let data: &[u8] = row.get(0);
+            let message = PgOutputMessage::decode(data).map_err(|e| {
+                UserError::new(
+                    "Failed to decode logical replication message",
+                    e.to_string(),
+                )
+            })?;
+
+            let (relation_id, envelope) = match message {
+                PgOutputMessage::Begin {
+                    final_lsn,
+                    commit_time: time,
+                } => {
+                    skipping = final_lsn <= self.lsn;
+                    commit_time = time;
+                    continue;
+                }
+                PgOutputMessage::Commit { commit_lsn } => {
+                    if !skipping {
+                        self.lsn = commit_lsn;
+                        emitted = true;
+                    }
+                    continue;
+                }
+                PgOutputMessage::Relation(relation) => {
+                    self.relations.insert(relation.id, relation);
+                    continue;
+                }
+                PgOutputMessage::Other(_) => continue,
+                _ if skipping => continue,
+                PgOutputMessage::Insert { relation_id, new } => {
+                    (relation_id, (None, Some(new), "c"))
+                }
+                PgOutputMessage::Update {
+                    relation_id,
+                    old,
+                    new,
+                } => (relation_id, (old, Some(new), "u")),
+                PgOutputMessage::Delete { relation_id, old } => {
+                    (relation_id, (Some(old), None, "d"))
+                }
+            };
+
+            let Some(relation) = self.relations.get(&relation_id) else {
+                return Err(UserError::new(
+                    "Failed to decode logical replication message",
+                    format!("received change for unknown relation {}", relation_id),
+                ));
+            };
+
+            // the publication may include other tables
+            if relation.namespace != self.table.schema_name()
+                || relation.name != self.table.table_name
+            {
+                continue;
+            }
+
+            let (before, after, op) = envelope;
+            let json = serde_json::to_vec(&relation.envelope(before, after, op)).unwrap();
+
+            self.deserializer.throttle(&json).await;
+            for value in self.deserializer.deserialize_slice(&json) {
+                match value {
+                    Ok(value) => {
+                        ctx.collector
+                            .collect(Record {
+                                timestamp: commit_time,
+                                key: None,
+                                value,
+                            })
+                            .await;
+                    }
+                    Err(e) => {
+                        if let Some(e) =
+                            self.deserializer
+                                .handle_bad_data(&ctx.task_info, &json, e)?
+                        {
+                            self.errors += 1;
+                            if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+                                ctx.report_error(
+                                    format!("{} x {}", e.name, self.errors),
+                                    e.details,
+                                )
+                                .await;
+                                self.errors = 0;
+                                self.last_reported_error = Instant::now();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(emitted)
+    }
+
+    async fn advance_slot(&self, client: &tokio_postgres::Client) {
+        let slot = self.table.slot_name();
+        let confirmed: Option<String> = match client
+            .query_one(
+                "SELECT confirmed_flush_lsn::text FROM pg_replication_slots WHERE slot_name = $1",
+                &[&slot],
+            )
+            .await
+        {
+            Ok(row) => row.get(0),
+            Err(e) => {
+                warn!("Failed to read position of slot {}: {:?}", slot, e);
+                return;
+            }
+        };
+
+        // the slot can't be moved backwards
+        if confirmed.and_then(|c| parse_lsn(&c).ok()).unwrap_or(0) >= self.checkpointed_lsn {
+            return;
+        }
+
+        if let Err(e) = client
+            .execute(
+                "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+                &[&slot, &format_lsn(self.checkpointed_lsn)],
+            )
+            .await
+        {
+            warn!("Failed to advance slot {}: {:?}", slot, e);
+        }
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // a replication slot can only be read by a single consumer
+        if ctx.task_info.task_index != 0 {
+            ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
+
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        let client = connect(&self.config)
+            .await
+            .map_err(|e| UserError::new("Failed to connect to Postgres", format!("{:?}", e)))?;
+
+        self.setup(&client).await.map_err(|e| {
+            UserError::new("Failed to set up logical replication", format!("{:?}", e))
+        })?;
+
+        let s: GlobalKeyedState<String, PostgresCdcState, _> =
+            ctx.state.get_global_keyed_state('p').await;
+        if let Some(state) = s.get(&self.table.slot_name()) {
+            info!(
+                "restoring postgres cdc source from LSN {}",
+                format_lsn(state.lsn)
+            );
+            self.lsn = state.lsn;
+            self.checkpointed_lsn = state.lsn;
+        }
+
+        let poll_interval =
+            Duration::from_millis(self.table.poll_interval_ms.unwrap_or(1000) as u64);
+        let mut delay = Duration::ZERO;
+
+        loop {
+            select! {
+                _ = tokio::time::sleep(delay) => {
+                    delay = if self.poll(&client, ctx).await? {
+                        Duration::ZERO
+                    } else {
+                        poll_interval
+                    };
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(ControlMessage::Checkpoint(_)) = &control_message {
+                        // changes up to the previous checkpoint won't be needed again, so we can
+                        // release them from the slot; we lag a checkpoint behind so that we can
+                        // still recover if this one fails
+                        self.advance_slot(&client).await;
+
+                        let mut s = ctx.state.get_global_keyed_state('p').await;
+                        s.insert(self.table.slot_name(), PostgresCdcState { lsn: self.lsn }).await;
+                        self.checkpointed_lsn = self.lsn;
+                    }
+
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping Postgres CDC source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use serde::{Deserialize, Serialize};
+use tonic::Code;
+use typify::import_types;
+
+pub mod proto;
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/pubsub/connection.json");
+import_types!(schema = "../connector-schemas/pubsub/table.json");
+
+const PUBSUB_SCOPE: &str = "https://www.googleapis.com/auth/pubsub";
+
+pub(crate) async fn authenticate(config: &PubSubConfig) -> Result<AuthenticationManager> {
+    Ok(match &config.credentials {
+        Some(credentials) => {
+            AuthenticationManager::from(CustomServiceAccount::from_json(credentials)?)
+        }
+        None => AuthenticationManager::new().await?,
+    })
+}
+
+pub(crate) async fn token(auth: &AuthenticationManager) -> Result<String> {
+    // tokens are cached by the manager and refreshed before they expire
+    Ok(auth.get_token(&[PUBSUB_SCOPE]).await?.as_str().to_string())
+}
+
+/// Expands a short topic or subscription name into its full resource name
+pub(crate) fn resource_name(project_id: &str, kind: &str, name: &str) -> String {
+    if name.starts_with("projects/") {
+        name.to_string()
+    } else {
+        format!("projects/{}/{}/{}", project_id, kind, name)
+    }
+}
+
+/// Whether a request that failed with `code` may succeed if retried, following Google's
+/// retry guidance for Pub/Sub
+pub(crate) fn is_retryable(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+            | Code::Aborted
+            | Code::Internal
+            | Code::Unknown
+            | Code::Cancelled
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::{is_retryable, resource_name};
+
+    #[test]
+    fn test_resource_name() {
+        assert_eq!(
+            resource_name("my-project", "subscriptions", "events"),
+            "projects/my-project/subscriptions/events"
+        );
+        assert_eq!(
+            resource_name("my-project", "topics", "projects/other/topics/events"),
+            "projects/other/topics/events"
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(Code::Unavailable));
+        assert!(is_retryable(Code::ResourceExhausted));
+        assert!(!is_retryable(Code::PermissionDenied));
+        assert!(!is_retryable(Code::NotFound));
+        assert!(!is_retryable(Code::InvalidArgument));
+    }
+}
@@ -0,0 +1,194 @@
+//! The subset of the Pub/Sub API (google.pubsub.v1.Publisher and google.pubsub.v1.Subscriber)
+//! used by the connector, along with a minimal client for it.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use tokio::sync::mpsc::Sender;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, ClientTlsConfig};
+use tonic::Request;
+
+const ENDPOINT: &str = "https://pubsub.googleapis.com";
+const DOMAIN: &str = "pubsub.googleapis.com";
+const PUBLISHER: &str = "/google.pubsub.v1.Publisher";
+const SUBSCRIBER: &str = "/google.pubsub.v1.Subscriber";
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PubsubMessage {
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+    #[prost(map = "string, string", tag = "2")]
+    pub attributes: HashMap<String, String>,
+    #[prost(string, tag = "3")]
+    pub message_id: String,
+    #[prost(message, optional, tag = "4")]
+    pub publish_time: Option<prost_types::Timestamp>,
+    #[prost(string, tag = "5")]
+    pub ordering_key: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PublishRequest {
+    #[prost(string, tag = "1")]
+    pub topic: String,
+    #[prost(message, repeated, tag = "2")]
+    pub messages: Vec<PubsubMessage>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PublishResponse {
+    #[prost(string, repeated, tag = "1")]
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamingPullRequest {
+    #[prost(string, tag = "1")]
+    pub subscription: String,
+    #[prost(string, repeated, tag = "2")]
+    pub ack_ids: Vec<String>,
+    #[prost(int32, repeated, tag = "3")]
+    pub modify_deadline_seconds: Vec<i32>,
+    #[prost(string, repeated, tag = "4")]
+    pub modify_deadline_ack_ids: Vec<String>,
+    #[prost(int32, tag = "5")]
+    pub stream_ack_deadline_seconds: i32,
+    #[prost(string, tag = "6")]
+    pub client_id: String,
+    #[prost(int64, tag = "7")]
+    pub max_outstanding_messages: i64,
+    #[prost(int64, tag = "8")]
+    pub max_outstanding_bytes: i64,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReceivedMessage {
+    #[prost(string, tag = "1")]
+    pub ack_id: String,
+    #[prost(message, optional, tag = "2")]
+    pub message: Option<PubsubMessage>,
+    #[prost(int32, tag = "3")]
+    pub delivery_attempt: i32,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AcknowledgeConfirmation {
+    #[prost(string, repeated, tag = "1")]
+    pub ack_ids: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub invalid_ack_ids: Vec<String>,
+    #[prost(string, repeated, tag = "3")]
+    pub unordered_ack_ids: Vec<String>,
+    #[prost(string, repeated, tag = "4")]
+    pub temporary_failed_ack_ids: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscriptionProperties {
+    #[prost(bool, tag = "1")]
+    pub exactly_once_delivery_enabled: bool,
+    #[prost(bool, tag = "2")]
+    pub message_ordering_enabled: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamingPullResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub received_messages: Vec<ReceivedMessage>,
+    #[prost(message, optional, tag = "4")]
+    pub subscription_properties: Option<SubscriptionProperties>,
+    #[prost(message, optional, tag = "5")]
+    pub acknowledge_confirmation: Option<AcknowledgeConfirmation>,
+}
+
+/// An open StreamingPull call; acks and deadline modifications are sent over `tx`
+pub struct StreamingPull {
+    pub tx: Sender<StreamingPullRequest>,
+    pub responses: Streaming<StreamingPullResponse>,
+}
+
+#[derive(Clone)]
+pub struct PubSubClient {
+    grpc: tonic::client::Grpc<Channel>,
+}
+
+impl PubSubClient {
+    pub async fn connect() -> Result<Self> {
+        let channel = Channel::from_static(ENDPOINT)
+            .tls_config(ClientTlsConfig::new().domain_name(DOMAIN))?
+            .connect()
+            .await?;
+
+        Ok(Self {
+            grpc: tonic::client::Grpc::new(channel),
+        })
+    }
+
+    fn request<T>(message: T, token: &str, routing: String) -> Result<Request<T>> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            MetadataValue::try_from(format!("Bearer {}", token))?,
+        );
+        request
+            .metadata_mut()
+            .insert("x-goog-request-params", MetadataValue::try_from(routing)?);
+        Ok(request)
+    }
+
+    pub async fn publish(
+        &mut self,
+        token: &str,
+        topic: &str,
+        messages: Vec<PubsubMessage>,
+    ) -> std::result::Result<PublishResponse, tonic::Status> {
+        let request = Self::request(
+            PublishRequest {
+                topic: topic.to_string(),
+                messages,
+            },
+            token,
+            format!("topic={}", topic),
+        )
+        .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+        self.grpc
+            .ready()
+            .await
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+        let path = PathAndQuery::from_maybe_shared(format!("{}/Publish", PUBLISHER))
+            .map_err(|e| tonic::Status::internal(e.to_string()))?;
+
+        Ok(self
+            .grpc
+            .unary(request, path, ProstCodec::default())
+            .await?
+            .into_inner())
+    }
+
+    /// Opens a StreamingPull call, sending `first` to start it
+    pub async fn streaming_pull(
+        &mut self,
+        token: &str,
+        first: StreamingPullRequest,
+    ) -> Result<StreamingPull> {
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let routing = format!("subscription={}", first.subscription);
+        tx.send(first).await.unwrap();
+
+        self.grpc.ready().await?;
+        let path = PathAndQuery::from_maybe_shared(format!("{}/StreamingPull", SUBSCRIBER))?;
+        let request = Self::request(ReceiverStream::new(rx), token, routing)?;
+        let responses = self
+            .grpc
+            .streaming(request, path, ProstCodec::default())
+            .await?
+            .into_inner();
+
+        Ok(StreamingPull { tx, responses })
+    }
+}
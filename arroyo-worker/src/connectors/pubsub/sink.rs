@@ -0,0 +1,181 @@
+use crate::connectors::webhook::render_template;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use gcp_auth::AuthenticationManager;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use super::proto::{PubSubClient, PubsubMessage};
+use super::{
+    authenticate, is_retryable, resource_name, token, PubSubConfig, PubSubTable, TableType,
+};
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 10;
+
+#[derive(StreamNode)]
+pub struct PubSubSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: PubSubConfig,
+    topic: String,
+    ordering_key: Option<String>,
+    batch_size: usize,
+    auth: Option<AuthenticationManager>,
+    client: Option<PubSubClient>,
+    buffer: Vec<PubsubMessage>,
+    last_flush: Instant,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> PubSubSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PubSubSink");
+        let connection: PubSubConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PubSubSink");
+        let table: PubSubTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PubSubSink");
+        let TableType::Sink {
+            topic,
+            ordering_key,
+            batch_size,
+        } = &table.type_
+        else {
+            panic!("found non-sink Pub/Sub config in sink operator");
+        };
+
+        Self {
+            topic: resource_name(&connection.project_id, "topics", topic),
+            config: connection,
+            ordering_key: ordering_key.clone(),
+            batch_size: batch_size.map(|s| s as usize).unwrap_or(DEFAULT_BATCH_SIZE),
+            auth: None,
+            client: None,
+            buffer: vec![],
+            last_flush: Instant::now(),
+            serializer: DataSerializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for PubSubSink"),
+            )
+            .with_compression(config.compression),
+            _t: PhantomData,
+        }
+    }
+
+    /// Publishes a batch, retrying transient failures. Batches are published one at a time, so
+    /// messages with the same ordering key are published in order.
+    async fn publish(&mut self, messages: Vec<PubsubMessage>) -> Result<(), String> {
+        let mut attempts = 0;
+        loop {
+            let token = token(self.auth.as_ref().unwrap())
+                .await
+                .map_err(|e| format!("failed to authenticate: {}", e))?;
+
+            match self
+                .client
+                .as_mut()
+                .unwrap()
+                .publish(&token, &self.topic, messages.clone())
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(status) if is_retryable(status.code()) && attempts < MAX_RETRIES => {
+                    warn!(
+                        "Failed to publish to {}, retrying: {:?}",
+                        self.topic, status
+                    );
+                    attempts += 1;
+                    tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts.min(6))))
+                        .await;
+                }
+                Err(status) => return Err(status.message().to_string()),
+            }
+        }
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        self.last_flush = Instant::now();
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let messages = std::mem::take(&mut self.buffer);
+        if let Err(e) = self.publish(messages).await {
+            ctx.report_error(format!("Failed to publish to {}", self.topic), e.clone())
+                .await;
+            panic!("Failed to publish to {}: {}", self.topic, e);
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> PubSubSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("pubsub-sink-{}", self.topic)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        let result = async {
+            let auth = authenticate(&self.config).await?;
+            let client = PubSubClient::connect().await?;
+            anyhow::Ok((auth, client))
+        }
+        .await;
+
+        match result {
+            Ok((auth, client)) => {
+                self.auth = Some(auth);
+                self.client = Some(client);
+            }
+            Err(e) => {
+                ctx.report_error("Failed to connect to Pub/Sub".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to connect to Pub/Sub: {:?}", e);
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(data) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        let ordering_key = self
+            .ordering_key
+            .as_ref()
+            .map(|key| render_template(key, &serde_json::to_value(&record.value).unwrap()))
+            .unwrap_or_default();
+
+        self.buffer.push(PubsubMessage {
+            data,
+            ordering_key,
+            ..Default::default()
+        });
+
+        if self.buffer.len() >= self.batch_size {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<(), ()>) {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+}
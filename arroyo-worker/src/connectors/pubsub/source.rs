@@ -0,0 +1,462 @@
+use crate::connectors::deferred_ack::DeferredAck;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_types::*;
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, info, warn};
+
+use super::proto::{PubSubClient, StreamingPullRequest, StreamingPullResponse};
+use super::{
+    authenticate, is_retryable, resource_name, token, PubSubConfig, PubSubTable, TableType,
+};
+
+const DEFAULT_ACK_DEADLINE_SECONDS: i32 = 60;
+const DEFAULT_MAX_OUTSTANDING_MESSAGES: i64 = 100_000;
+// keeps ack requests well under the 10MB request size limit
+const MAX_ACK_IDS_PER_REQUEST: usize = 2500;
+
+#[derive(StreamNode)]
+pub struct PubSubSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: PubSubConfig,
+    subscription: String,
+    ack_deadline_seconds: i32,
+    max_outstanding_messages: i64,
+    deserializer: DataDeserializer<T>,
+    ack_ids: AckIds,
+    stream_tx: Option<Sender<StreamingPullRequest>>,
+    _t: PhantomData<K>,
+}
+
+/// The ack ids of the messages we're holding. Messages are only acked once the checkpoint
+/// covering them has completed, and until then their deadlines are extended.
+#[derive(Debug, Default)]
+struct AckIds {
+    // messages that we've emitted, held until their checkpoint completes
+    deferred: DeferredAck<Vec<String>>,
+    // messages whose checkpoint has completed but whose acks haven't been sent (or need to be
+    // resent)
+    to_ack: Vec<String>,
+}
+
+impl AckIds {
+    /// Called when a checkpoint starts
+    fn checkpoint(&mut self) {
+        self.to_ack.extend(self.deferred.checkpoint());
+    }
+
+    /// The messages whose deadlines need to be extended
+    fn held(&self) -> Vec<String> {
+        self.deferred.held().chain(&self.to_ack).cloned().collect()
+    }
+}
+
+/// Splits ack ids into requests for the pull stream
+fn chunked_requests(
+    ack_ids: &[String],
+    f: impl Fn(Vec<String>) -> StreamingPullRequest,
+) -> Vec<StreamingPullRequest> {
+    ack_ids
+        .chunks(MAX_ACK_IDS_PER_REQUEST)
+        .map(|ids| f(ids.to_vec()))
+        .collect()
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> PubSubSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn new(
+        config: PubSubConfig,
+        table: PubSubTable,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        let TableType::Source {
+            subscription,
+            ack_deadline_seconds,
+            max_outstanding_messages,
+        } = &table.type_
+        else {
+            panic!("found non-source Pub/Sub config in source operator");
+        };
+
+        Self {
+            subscription: resource_name(&config.project_id, "subscriptions", subscription),
+            config,
+            ack_deadline_seconds: ack_deadline_seconds
+                .map(|s| s as i32)
+                .unwrap_or(DEFAULT_ACK_DEADLINE_SECONDS),
+            max_outstanding_messages: max_outstanding_messages
+                .unwrap_or(DEFAULT_MAX_OUTSTANDING_MESSAGES),
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_compression(compression)
+                .with_rate_limit(rate_limit),
+            ack_ids: AckIds::default(),
+            stream_tx: None,
+            _t: PhantomData,
+        }
+    }
+
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PubSubSource");
+        let connection: PubSubConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PubSubSource");
+        let table: PubSubTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PubSubSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for PubSubSource"),
+            config.framing,
+            config.bad_data,
+            config.compression,
+            config.rate_limit,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("pubsub-{}", self.subscription)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                self.ack_ids.checkpoint();
+                self.send_acks().await;
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping Pub/Sub source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    /// Sends pending acks over the pull stream; if we're not connected they're kept until we are
+    async fn send_acks(&mut self) {
+        let Some(tx) = &self.stream_tx else {
+            return;
+        };
+
+        for request in chunked_requests(&self.ack_ids.to_ack, |ack_ids| StreamingPullRequest {
+            ack_ids,
+            ..Default::default()
+        }) {
+            if tx.send(request).await.is_err() {
+                // the stream has closed; we'll send them again once we reconnect
+                return;
+            }
+        }
+
+        self.ack_ids.to_ack.clear();
+    }
+
+    /// Pushes back the deadlines of messages we're holding, as they won't be acked until the
+    /// checkpoint covering them has completed
+    async fn extend_deadlines(&mut self) {
+        let Some(tx) = &self.stream_tx else {
+            return;
+        };
+
+        let ids = self.ack_ids.held();
+        for request in chunked_requests(&ids, |ack_ids| StreamingPullRequest {
+            modify_deadline_seconds: vec![self.ack_deadline_seconds; ack_ids.len()],
+            modify_deadline_ack_ids: ack_ids,
+            ..Default::default()
+        }) {
+            if tx.send(request).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn handle_response(
+        &mut self,
+        response: StreamingPullResponse,
+        ctx: &mut Context<(), T>,
+        errors: &mut usize,
+        last_reported_error: &mut Instant,
+    ) -> Result<(), UserError> {
+        if let Some(properties) = response.subscription_properties {
+            info!(
+                "Pulling from {} (exactly-once delivery: {}, message ordering: {})",
+                self.subscription,
+                properties.exactly_once_delivery_enabled,
+                properties.message_ordering_enabled
+            );
+        }
+
+        // only sent for subscriptions with exactly-once delivery, where an ack can fail
+        if let Some(confirmation) = response.acknowledge_confirmation {
+            if !confirmation.invalid_ack_ids.is_empty() {
+                warn!(
+                    "{} acks were rejected as invalid or expired; those messages may be redelivered",
+                    confirmation.invalid_ack_ids.len()
+                );
+            }
+            self.ack_ids
+                .to_ack
+                .extend(confirmation.temporary_failed_ack_ids);
+        }
+
+        for received in response.received_messages {
+            let Some(message) = received.message else {
+                continue;
+            };
+
+            let timestamp = message
+                .publish_time
+                .map(|t| from_millis(t.seconds as u64 * 1000 + t.nanos as u64 / 1_000_000))
+                .unwrap_or_else(SystemTime::now);
+
+            self.deserializer.throttle(&message.data).await;
+            for value in self.deserializer.deserialize_slice(&message.data) {
+                match value {
+                    Ok(value) => {
+                        ctx.collector
+                            .collect(Record {
+                                timestamp,
+                                key: None,
+                                value,
+                            })
+                            .await;
+                    }
+                    Err(e) => {
+                        if let Some(e) =
+                            self.deserializer
+                                .handle_bad_data(&ctx.task_info, &message.data, e)?
+                        {
+                            *errors += 1;
+                            if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                ctx.report_error(format!("{} x {}", e.name, errors), e.details)
+                                    .await;
+                                *errors = 0;
+                                *last_reported_error = Instant::now();
+                            }
+                        }
+                    }
+                }
+            }
+
+            self.ack_ids.deferred.read(received.ack_id);
+        }
+
+        Ok(())
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let auth = authenticate(&self.config)
+            .await
+            .map_err(|e| UserError::new("Failed to authenticate with GCP", e.to_string()))?;
+
+        let mut client = PubSubClient::connect()
+            .await
+            .map_err(|e| UserError::new("Failed to connect to Pub/Sub", e.to_string()))?;
+
+        // each subtask opens its own stream, and Pub/Sub spreads messages between them
+        let client_id = format!(
+            "arroyo-{}-{}-{}",
+            ctx.task_info.job_id, ctx.task_info.operator_id, ctx.task_info.task_index
+        );
+
+        let mut extend_interval =
+            tokio::time::interval(Duration::from_secs(self.ack_deadline_seconds as u64 / 2));
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+        let mut backoff = Duration::from_millis(100);
+
+        loop {
+            let token = token(&auth)
+                .await
+                .map_err(|e| UserError::new("Failed to authenticate with GCP", e.to_string()))?;
+
+            let request = StreamingPullRequest {
+                subscription: self.subscription.clone(),
+                stream_ack_deadline_seconds: self.ack_deadline_seconds,
+                client_id: client_id.clone(),
+                max_outstanding_messages: self.max_outstanding_messages,
+                ..Default::default()
+            };
+
+            let mut stream = match client.streaming_pull(&token, request).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if let Some(status) = e.downcast_ref::<tonic::Status>() {
+                        if !is_retryable(status.code()) {
+                            return Err(UserError::new(
+                                "Failed to pull from Pub/Sub",
+                                format!("{}: {}", self.subscription, status.message()),
+                            ));
+                        }
+                    }
+
+                    warn!("Failed to open stream for {}: {:?}", self.subscription, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(10));
+                    continue;
+                }
+            };
+
+            backoff = Duration::from_millis(100);
+            self.stream_tx = Some(stream.tx);
+            self.send_acks().await;
+
+            loop {
+                select! {
+                    response = stream.responses.message() => {
+                        match response {
+                            Ok(Some(response)) => {
+                                self.handle_response(response, ctx, &mut errors, &mut last_reported_error).await?;
+                            }
+                            Ok(None) => {
+                                debug!("Pub/Sub closed stream for {}", self.subscription);
+                                break;
+                            }
+                            Err(status) if is_retryable(status.code()) => {
+                                // streams are periodically closed by the server
+                                debug!("Pub/Sub stream for {} closed: {:?}", self.subscription, status);
+                                break;
+                            }
+                            Err(status) => {
+                                return Err(UserError::new(
+                                    "Failed to pull from Pub/Sub",
+                                    format!("{}: {}", self.subscription, status.message()),
+                                ));
+                            }
+                        }
+                    }
+                    _ = extend_interval.tick() => {
+                        self.extend_deadlines().await;
+                        // resend any acks that temporarily failed
+                        self.send_acks().await;
+                    }
+                    control_message = ctx.control_rx.recv() => {
+                        if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                            return Ok(r);
+                        }
+                    }
+                }
+            }
+
+            self.stream_tx = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunked_requests, AckIds, StreamingPullRequest, MAX_ACK_IDS_PER_REQUEST};
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_acks_held_until_checkpoint_completes() {
+        let mut acks = AckIds::default();
+        acks.deferred.read("a".to_string());
+        acks.deferred.read("b".to_string());
+
+        // nothing is acked when the first checkpoint starts, as it may not complete, but the
+        // deadlines of the messages it covers are still extended
+        acks.checkpoint();
+        assert!(acks.to_ack.is_empty());
+        assert_eq!(ids(&["a", "b"]), acks.held());
+
+        acks.deferred.read("c".to_string());
+        assert_eq!(ids(&["c", "a", "b"]), acks.held());
+
+        // once the next one starts, the messages covered by the first can be acked
+        acks.checkpoint();
+        assert_eq!(ids(&["a", "b"]), acks.to_ack);
+        assert_eq!(ids(&["c", "a", "b"]), acks.held());
+
+        acks.to_ack.clear();
+        acks.checkpoint();
+        assert_eq!(ids(&["c"]), acks.to_ack);
+        assert_eq!(0, acks.deferred.held().count());
+    }
+
+    #[test]
+    fn test_chunked_requests() {
+        let ids: Vec<_> = (0..MAX_ACK_IDS_PER_REQUEST * 2 + 1)
+            .map(|i| i.to_string())
+            .collect();
+
+        let requests = chunked_requests(&ids, |ack_ids| StreamingPullRequest {
+            ack_ids,
+            ..Default::default()
+        });
+
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].ack_ids.len(), MAX_ACK_IDS_PER_REQUEST);
+        assert_eq!(requests[2].ack_ids, vec![ids.last().unwrap().clone()]);
+        assert!(chunked_requests(&[], |_| unreachable!()).is_empty());
+    }
+}
@@ -0,0 +1,24 @@
+use pulsar::{Authentication, Pulsar, TokioExecutor};
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/pulsar/connection.json");
+import_types!(schema = "../connector-schemas/pulsar/table.json");
+
+pub(crate) async fn pulsar_client(
+    config: &PulsarConfig,
+) -> Result<Pulsar<TokioExecutor>, pulsar::Error> {
+    let mut builder = Pulsar::builder(&config.service_url, TokioExecutor);
+
+    if let PulsarConfigAuthentication::Token { token } = &config.authentication {
+        builder = builder.with_auth(Authentication {
+            name: "token".to_string(),
+            data: token.as_bytes().to_vec(),
+        });
+    }
+
+    builder.build().await
+}
@@ -0,0 +1,171 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use pulsar::producer::{self, SendFuture};
+use pulsar::{Producer, TokioExecutor};
+use serde::Serialize;
+use std::marker::PhantomData;
+use tracing::warn;
+
+use super::{pulsar_client, PulsarConfig, PulsarTable, TableType};
+
+#[derive(StreamNode)]
+pub struct PulsarSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: PulsarConfig,
+    table: PulsarTable,
+    key_field: Option<String>,
+    producer: Option<Producer<TokioExecutor>>,
+    // sends that have been handed to the producer but not yet acknowledged by the broker
+    in_flight: Vec<SendFuture>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> PulsarSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PulsarSink");
+        let connection: PulsarConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PulsarSink");
+        let table: PulsarTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PulsarSink");
+        let TableType::Sink { key_field } = table.type_.clone() else {
+            panic!("found non-sink Pulsar config in sink operator");
+        };
+
+        Self {
+            config: connection,
+            table,
+            key_field,
+            producer: None,
+            in_flight: vec![],
+            serializer: DataSerializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for PulsarSink"),
+            )
+            .with_compression(config.compression),
+            _t: PhantomData,
+        }
+    }
+
+    /// The key determines which partition a message is written to and, for consumers using
+    /// Key_Shared subscriptions, which consumer receives it
+    fn message_key(&self, record: &Record<K, T>) -> Option<String> {
+        if let Some(field) = &self.key_field {
+            let value = serde_json::to_value(&record.value)
+                .ok()
+                .and_then(|mut v| v.get_mut(field).map(|f| f.take()));
+            return match value {
+                Some(serde_json::Value::String(s)) => Some(s),
+                Some(serde_json::Value::Null) | None => None,
+                Some(other) => Some(other.to_string()),
+            };
+        }
+
+        record
+            .key
+            .as_ref()
+            .map(|k| serde_json::to_string(k).unwrap())
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        if let Some(producer) = self.producer.as_mut() {
+            if let Err(e) = producer.send_batch().await {
+                warn!("Failed to flush Pulsar producer batch: {:?}", e);
+            }
+        }
+
+        for send in self.in_flight.drain(..) {
+            if let Err(e) = send.await {
+                ctx.report_error("Failed to write to Pulsar".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to write to Pulsar: {:?}", e);
+            }
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> PulsarSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("pulsar-sink-{}", self.table.topic)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        let producer = match pulsar_client(&self.config).await {
+            Ok(client) => {
+                client
+                    .producer()
+                    .with_topic(&self.table.topic)
+                    .with_name(format!(
+                        "arroyo-{}-{}-{}",
+                        ctx.task_info.job_id, ctx.task_info.operator_id, ctx.task_info.task_index
+                    ))
+                    .build()
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match producer {
+            Ok(producer) => {
+                self.producer = Some(producer);
+            }
+            Err(e) => {
+                ctx.report_error(
+                    "Failed to create Pulsar producer".to_string(),
+                    e.to_string(),
+                )
+                .await;
+                panic!("Failed to create Pulsar producer: {:?}", e);
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(payload) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        let message = producer::Message {
+            payload,
+            partition_key: self.message_key(record),
+            event_time: Some(to_millis(record.timestamp)),
+            ..Default::default()
+        };
+
+        match self.producer.as_mut().unwrap().send(message).await {
+            Ok(send) => {
+                self.in_flight.push(send);
+            }
+            Err(e) => {
+                ctx.report_error("Failed to write to Pulsar".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to write to Pulsar: {:?}", e);
+            }
+        }
+
+        // don't let acknowledgements pile up between checkpoints
+        if self.in_flight.len() >= 1000 {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        // all messages before the barrier must be durably written before we can checkpoint
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+        if let Some(mut producer) = self.producer.take() {
+            if let Err(e) = producer.close().await {
+                warn!("Failed to close Pulsar producer: {:?}", e);
+            }
+        }
+    }
+}
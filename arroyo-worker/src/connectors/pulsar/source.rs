@@ -0,0 +1,483 @@
+use crate::connectors::deferred_ack::DeferredAck;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use futures::TryStreamExt;
+use pulsar::consumer::{ConsumerOptions, InitialPosition as PulsarInitialPosition};
+use pulsar::proto::MessageIdData;
+use pulsar::{Consumer, SubType, TokioExecutor};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::{
+    pulsar_client, InitialPosition, PulsarConfig, PulsarTable, SubscriptionType, TableType,
+};
+
+#[derive(StreamNode)]
+pub struct PulsarSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: PulsarConfig,
+    table: PulsarTable,
+    subscription_name: Option<String>,
+    subscription_type: SubscriptionType,
+    initial_position: Option<InitialPosition>,
+    deserializer: DataDeserializer<T>,
+    _t: PhantomData<K>,
+}
+
+/// The position of the last message we've emitted from a topic (or a partition of a
+/// partitioned topic, which Pulsar exposes as its own topic)
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct PulsarState {
+    topic: String,
+    ledger_id: u64,
+    entry_id: u64,
+    partition: i32,
+    batch_index: i32,
+}
+
+impl PulsarState {
+    fn new(topic: String, id: &MessageIdData) -> Self {
+        Self {
+            topic,
+            ledger_id: id.ledger_id,
+            entry_id: id.entry_id,
+            partition: id.partition.unwrap_or(-1),
+            batch_index: id.batch_index.unwrap_or(-1),
+        }
+    }
+
+    fn message_id(&self) -> MessageIdData {
+        MessageIdData {
+            ledger_id: self.ledger_id,
+            entry_id: self.entry_id,
+            partition: Some(self.partition),
+            batch_index: Some(self.batch_index),
+            ..Default::default()
+        }
+    }
+
+    fn position(&self) -> (u64, u64, i32) {
+        (self.ledger_id, self.entry_id, self.batch_index)
+    }
+}
+
+fn position(id: &MessageIdData) -> (u64, u64, i32) {
+    (id.ledger_id, id.entry_id, id.batch_index.unwrap_or(-1))
+}
+
+/// Messages that have been read but not yet acked
+#[derive(Debug, Default, PartialEq)]
+struct Acks {
+    // the last message read from each topic, to be cumulatively acked
+    cumulative: HashMap<String, MessageIdData>,
+    // messages to be individually acked
+    individual: Vec<(String, MessageIdData)>,
+}
+
+impl Acks {
+    fn add(&mut self, subscription_type: SubscriptionType, topic: &str, id: MessageIdData) {
+        match subscription_type {
+            SubscriptionType::Exclusive | SubscriptionType::Failover => {
+                self.cumulative.insert(topic.to_string(), id);
+            }
+            SubscriptionType::Shared | SubscriptionType::KeyShared => {
+                self.individual.push((topic.to_string(), id));
+            }
+        }
+    }
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> PulsarSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for PulsarSource");
+        let connection: PulsarConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for PulsarSource");
+        let table: PulsarTable =
+            serde_json::from_value(config.table).expect("Invalid table config for PulsarSource");
+        let TableType::Source {
+            subscription_name,
+            subscription_type,
+            initial_position,
+        } = table.type_.clone()
+        else {
+            panic!("found non-source Pulsar config in source operator");
+        };
+
+        Self::new(
+            connection,
+            table,
+            subscription_name,
+            subscription_type,
+            initial_position,
+            config
+                .format
+                .expect("Format must be specified for PulsarSource"),
+            config.framing,
+            config.bad_data,
+            config.compression,
+            config.rate_limit,
+        )
+    }
+
+    pub fn new(
+        config: PulsarConfig,
+        table: PulsarTable,
+        subscription_name: Option<String>,
+        subscription_type: SubscriptionType,
+        initial_position: Option<InitialPosition>,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        Self {
+            config,
+            table,
+            subscription_name,
+            subscription_type,
+            initial_position,
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_compression(compression)
+                .with_rate_limit(rate_limit),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("pulsar-{}", self.table.topic)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("p", "pulsar source state")]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn get_consumer(
+        &mut self,
+        ctx: &mut Context<(), T>,
+    ) -> anyhow::Result<Consumer<Vec<u8>, TokioExecutor>> {
+        let client = pulsar_client(&self.config).await?;
+
+        let sub_type = match self.subscription_type {
+            SubscriptionType::Exclusive => SubType::Exclusive,
+            SubscriptionType::Failover => SubType::Failover,
+            SubscriptionType::Shared => SubType::Shared,
+            SubscriptionType::KeyShared => SubType::KeyShared,
+        };
+
+        let initial_position = match self.initial_position {
+            Some(InitialPosition::Earliest) => PulsarInitialPosition::Earliest,
+            Some(InitialPosition::Latest) | None => PulsarInitialPosition::Latest,
+        };
+
+        let mut consumer: Consumer<Vec<u8>, TokioExecutor> = client
+            .consumer()
+            .with_topic(&self.table.topic)
+            .with_subscription_type(sub_type)
+            .with_subscription(self.subscription_name.clone().unwrap_or_else(|| {
+                format!(
+                    "arroyo-{}-{}",
+                    ctx.task_info.job_id, ctx.task_info.operator_id
+                )
+            }))
+            .with_consumer_name(format!(
+                "arroyo-{}-{}-{}",
+                ctx.task_info.job_id, ctx.task_info.operator_id, ctx.task_info.task_index
+            ))
+            .with_options(ConsumerOptions::default().with_initial_position(initial_position))
+            .build()
+            .await?;
+
+        // move the subscription's cursor back to the positions recorded in our last checkpoint,
+        // which may be behind what was acked. For exclusive subscriptions we're the only reader;
+        // for failover subscriptions the cursor is shared by all of the subtasks, so the first
+        // one seeks for them and the others skip what they've already emitted until it does.
+        let seek = match self.subscription_type {
+            SubscriptionType::Exclusive => true,
+            SubscriptionType::Failover => ctx.task_info.task_index == 0,
+            SubscriptionType::Shared | SubscriptionType::KeyShared => false,
+        };
+
+        if seek {
+            let s: GlobalKeyedState<String, PulsarState, _> =
+                ctx.state.get_global_keyed_state('p').await;
+            for state in s.get_all() {
+                info!(
+                    "seeking {} to {:?} from checkpoint",
+                    state.topic,
+                    state.position()
+                );
+                consumer
+                    .seek(
+                        Some(vec![state.topic.clone()]),
+                        Some(state.message_id()),
+                        None,
+                        client.clone(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(consumer)
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // an exclusive subscription only allows a single consumer, so only the first subtask
+        // reads; the other subscription types spread messages across all of the subtasks
+        if self.subscription_type == SubscriptionType::Exclusive && ctx.task_info.task_index != 0 {
+            ctx.broadcast(Message::Watermark(Watermark::Idle)).await;
+
+            loop {
+                let msg = ctx.control_rx.recv().await;
+                if let Some(r) = self.handle_control_message(ctx, msg).await {
+                    return Ok(r);
+                }
+            }
+        }
+
+        let mut consumer = self
+            .get_consumer(ctx)
+            .await
+            .map_err(|e| UserError::new("Could not create Pulsar consumer", format!("{:?}", e)))?;
+
+        // seeking is inclusive of the checkpointed message, which we've already emitted
+        let mut restored: HashMap<String, (u64, u64, i32)> = if matches!(
+            self.subscription_type,
+            SubscriptionType::Exclusive | SubscriptionType::Failover
+        ) {
+            let s: GlobalKeyedState<String, PulsarState, _> =
+                ctx.state.get_global_keyed_state('p').await;
+            s.get_all()
+                .into_iter()
+                .map(|s| (s.topic.clone(), s.position()))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        // the last message read from each topic, to checkpoint
+        let mut positions: HashMap<String, MessageIdData> = HashMap::new();
+        let mut acks: DeferredAck<Acks> = DeferredAck::default();
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+
+        loop {
+            select! {
+                message = consumer.try_next() => {
+                    let msg = match message {
+                        Ok(Some(msg)) => msg,
+                        Ok(None) => {
+                            info!("Pulsar consumer for {} finished", self.table.topic);
+                            return Ok(SourceFinishType::Final);
+                        }
+                        Err(e) => {
+                            return Err(UserError::new("Error while reading from Pulsar", e.to_string()));
+                        }
+                    };
+
+                    let id = msg.message_id().clone();
+                    if let Some(restored_position) = restored.get(&msg.topic) {
+                        if position(&id) <= *restored_position {
+                            continue;
+                        }
+                        restored.remove(&msg.topic);
+                    }
+
+                    let timestamp = msg.metadata().event_time
+                        .filter(|t| *t > 0)
+                        .unwrap_or(msg.metadata().publish_time);
+
+                    self.deserializer.throttle(&msg.payload.data).await;
+                    for value in self.deserializer.deserialize_slice(&msg.payload.data) {
+                        match value {
+                            Ok(value) => {
+                                ctx.collector.collect(Record {
+                                    timestamp: from_millis(timestamp),
+                                    key: None,
+                                    value,
+                                }).await;
+                            }
+                            Err(e) => {
+                                if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, &msg.payload.data, e)? {
+                                    errors += 1;
+                                    if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                        ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                        errors = 0;
+                                        last_reported_error = Instant::now();
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if matches!(self.subscription_type, SubscriptionType::Exclusive | SubscriptionType::Failover) {
+                        positions.insert(msg.topic.clone(), id.clone());
+                    }
+                    acks.reading().add(self.subscription_type, &msg.topic, id);
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(ControlMessage::Checkpoint(_)) = &control_message {
+                        let mut s = ctx.state.get_global_keyed_state('p').await;
+                        for (topic, id) in &positions {
+                            s.insert(topic.clone(), PulsarState::new(topic.clone(), id)).await;
+                        }
+
+                        // messages that aren't acked before a failure will be redelivered
+                        let checkpointed = acks.checkpoint();
+                        for (topic, id) in checkpointed.cumulative {
+                            if let Err(e) = consumer.cumulative_ack_with_id(&topic, id).await {
+                                warn!("Failed to ack Pulsar messages on {}: {:?}", topic, e);
+                            }
+                        }
+
+                        for (topic, id) in checkpointed.individual {
+                            if let Err(e) = consumer.ack_with_id(&topic, id).await {
+                                warn!("Failed to ack Pulsar message on {}: {:?}", topic, e);
+                            }
+                        }
+                    }
+
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        if let Err(e) = consumer.close().await {
+                            warn!("Failed to close Pulsar consumer: {:?}", e);
+                        }
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping Pulsar source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(entry_id: u64) -> MessageIdData {
+        MessageIdData {
+            ledger_id: 1,
+            entry_id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_acks_held_until_checkpoint_completes() {
+        let mut acks: DeferredAck<Acks> = DeferredAck::default();
+        acks.reading().add(SubscriptionType::Exclusive, "a", id(1));
+        acks.reading().add(SubscriptionType::Exclusive, "a", id(2));
+        acks.reading().add(SubscriptionType::Exclusive, "b", id(1));
+
+        // nothing is acked when the first checkpoint starts, as it may not complete
+        assert_eq!(Acks::default(), acks.checkpoint());
+
+        acks.reading().add(SubscriptionType::Exclusive, "a", id(3));
+
+        // once the next one starts the messages covered by the first are acked
+        let checkpointed = acks.checkpoint();
+        assert_eq!(
+            HashMap::from([("a".to_string(), id(2)), ("b".to_string(), id(1))]),
+            checkpointed.cumulative
+        );
+        assert!(checkpointed.individual.is_empty());
+
+        let checkpointed = acks.checkpoint();
+        assert_eq!(
+            HashMap::from([("a".to_string(), id(3))]),
+            checkpointed.cumulative
+        );
+
+        assert_eq!(Acks::default(), acks.checkpoint());
+    }
+
+    #[test]
+    fn test_shared_acks() {
+        let mut acks: DeferredAck<Acks> = DeferredAck::default();
+        acks.reading().add(SubscriptionType::Shared, "a", id(1));
+        acks.reading().add(SubscriptionType::Shared, "a", id(3));
+        assert_eq!(Acks::default(), acks.checkpoint());
+
+        acks.reading().add(SubscriptionType::KeyShared, "a", id(2));
+        let checkpointed = acks.checkpoint();
+        assert!(checkpointed.cumulative.is_empty());
+        assert_eq!(
+            vec![("a".to_string(), id(1)), ("a".to_string(), id(3))],
+            checkpointed.individual
+        );
+
+        assert_eq!(vec![("a".to_string(), id(2))], acks.checkpoint().individual);
+    }
+}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/rabbitmq/connection.json");
+import_types!(schema = "../connector-schemas/rabbitmq/table.json");
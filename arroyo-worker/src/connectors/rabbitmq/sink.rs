@@ -0,0 +1,186 @@
+use crate::connectors::webhook::{render_template, template_regex};
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::publisher_confirm::PublisherConfirm;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+use serde::Serialize;
+use std::marker::PhantomData;
+
+use super::{RabbitmqConfig, RabbitmqTable, TableType};
+
+// bounds the number of publisher confirms we'll wait on at once
+const MAX_PENDING_CONFIRMS: usize = 1024;
+
+#[derive(StreamNode)]
+pub struct RabbitmqSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: RabbitmqConfig,
+    exchange: String,
+    routing_key: String,
+    // whether the exchange or routing key depend on the record
+    templated: bool,
+    properties: BasicProperties,
+    connection: Option<Connection>,
+    channel: Option<Channel>,
+    pending_confirms: Vec<PublisherConfirm>,
+    serializer: DataSerializer<T>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> RabbitmqSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for RabbitmqSink");
+        let connection: RabbitmqConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for RabbitmqSink");
+        let table: RabbitmqTable =
+            serde_json::from_value(config.table).expect("Invalid table config for RabbitmqSink");
+        let TableType::Sink {
+            exchange,
+            routing_key,
+            persistent,
+        } = &table.type_
+        else {
+            panic!("found non-sink RabbitMQ config in sink operator");
+        };
+
+        let exchange = exchange.clone().unwrap_or_default();
+        let routing_key = routing_key.clone().unwrap_or_default();
+
+        let mut properties = BasicProperties::default();
+        if persistent.unwrap_or(false) {
+            properties = properties.with_delivery_mode(2);
+        }
+
+        Self {
+            config: connection,
+            templated: template_regex().is_match(&exchange)
+                || template_regex().is_match(&routing_key),
+            exchange,
+            routing_key,
+            properties,
+            connection: None,
+            channel: None,
+            pending_confirms: vec![],
+            serializer: DataSerializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for RabbitmqSink"),
+            )
+            .with_compression(config.compression),
+            _t: PhantomData,
+        }
+    }
+
+    async fn connect(&self) -> anyhow::Result<(Connection, Channel)> {
+        let connection =
+            Connection::connect(&self.config.url, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        // enable publisher confirms so that we know when the broker has taken our messages
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+
+        Ok((connection, channel))
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> RabbitmqSinkFunc<K, T> {
+    fn name(&self) -> String {
+        format!("rabbitmq-sink-{}", self.exchange)
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        match self.connect().await {
+            Ok((connection, channel)) => {
+                self.connection = Some(connection);
+                self.channel = Some(channel);
+            }
+            Err(e) => {
+                ctx.report_error("Failed to connect to RabbitMQ".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to connect to RabbitMQ: {:?}", e);
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        let (exchange, routing_key) = if self.templated {
+            let value = serde_json::to_value(&record.value).unwrap();
+            (
+                render_template(&self.exchange, &value),
+                render_template(&self.routing_key, &value),
+            )
+        } else {
+            (self.exchange.clone(), self.routing_key.clone())
+        };
+
+        match self
+            .channel
+            .as_ref()
+            .unwrap()
+            .basic_publish(
+                &exchange,
+                &routing_key,
+                BasicPublishOptions::default(),
+                &v,
+                self.properties.clone(),
+            )
+            .await
+        {
+            Ok(confirm) => self.pending_confirms.push(confirm),
+            Err(e) => {
+                ctx.report_error("Failed to publish to RabbitMQ".to_string(), e.to_string())
+                    .await;
+                panic!("Failed to publish to RabbitMQ: {:?}", e);
+            }
+        }
+
+        if self.pending_confirms.len() >= MAX_PENDING_CONFIRMS {
+            self.flush(ctx).await;
+        }
+    }
+
+    /// Waits for the broker to confirm everything published so far
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        let mut error = None;
+        for confirm in self.pending_confirms.drain(..) {
+            match confirm.await {
+                Ok(confirmation) if confirmation.is_nack() => {
+                    error = Some("broker rejected a message".to_string());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error = Some(e.to_string());
+                }
+            }
+        }
+
+        if let Some(e) = error {
+            ctx.report_error("Failed to write to RabbitMQ".to_string(), e.clone())
+                .await;
+            panic!("Failed to write to RabbitMQ: {}", e);
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+
+        if let Some(connection) = self.connection.take() {
+            let _ = connection.close(0, "").await;
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use crate::connectors::deferred_ack::DeferredAck;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::formats::{BadData, Compression, Format, Framing};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_rpc::{OperatorConfig, RateLimit};
+use arroyo_types::*;
+use futures::StreamExt;
+use lapin::acker::Acker;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::{Connection, ConnectionProperties, Consumer};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tracing::{debug, info, warn};
+
+use super::{RabbitmqConfig, RabbitmqTable, TableType};
+
+/// By default the broker doesn't limit how many deliveries a subtask may hold unacked (a prefetch
+/// count of 0). Deliveries are only acked once the checkpoint after the one covering them starts,
+/// so a configured limit needs to cover two checkpoint intervals' worth of messages; otherwise the
+/// source stalls until the next checkpoint releases its acks.
+const DEFAULT_PREFETCH_COUNT: u16 = 0;
+
+#[derive(StreamNode)]
+pub struct RabbitmqSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: RabbitmqConfig,
+    queue: String,
+    prefetch_count: u16,
+    deserializer: DataDeserializer<T>,
+    // the last delivery read, as acking it with `multiple` acks every one before it on the channel
+    acks: DeferredAck<Option<Acker>>,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> RabbitmqSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn new(
+        config: RabbitmqConfig,
+        table: RabbitmqTable,
+        format: Format,
+        framing: Option<Framing>,
+        bad_data: Option<BadData>,
+        compression: Option<Compression>,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        let TableType::Source {
+            queue,
+            prefetch_count,
+        } = &table.type_
+        else {
+            panic!("found non-source RabbitMQ config in source operator");
+        };
+
+        Self {
+            config,
+            queue: queue.clone(),
+            prefetch_count: prefetch_count
+                .map(|p| p as u16)
+                .unwrap_or(DEFAULT_PREFETCH_COUNT),
+            deserializer: DataDeserializer::new(format, framing)
+                .with_bad_data(bad_data)
+                .with_compression(compression)
+                .with_rate_limit(rate_limit),
+            acks: DeferredAck::default(),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for RabbitmqSource");
+        let connection: RabbitmqConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for RabbitmqSource");
+        let table: RabbitmqTable =
+            serde_json::from_value(config.table).expect("Invalid table config for RabbitmqSource");
+
+        Self::new(
+            connection,
+            table,
+            config
+                .format
+                .expect("Format must be specified for RabbitmqSource"),
+            config.framing,
+            config.bad_data,
+            config.compression,
+            config.rate_limit,
+        )
+    }
+
+    fn name(&self) -> String {
+        format!("rabbitmq-{}", self.queue)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                // if the ack is lost the messages are redelivered
+                if let Some(acker) = self.acks.checkpoint() {
+                    if let Err(e) = acker.ack(BasicAckOptions { multiple: true }).await {
+                        warn!("Failed to ack RabbitMQ messages: {:?}", e);
+                    }
+                }
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping RabbitMQ source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    async fn consume(&self, ctx: &Context<(), T>) -> anyhow::Result<(Connection, Consumer)> {
+        let connection =
+            Connection::connect(&self.config.url, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+
+        // unacked messages are held until the checkpoint covering them has completed, so a
+        // prefetch count bounds how much we can read between checkpoints
+        channel
+            .basic_qos(self.prefetch_count, BasicQosOptions::default())
+            .await?;
+
+        let consumer = channel
+            .basic_consume(
+                &self.queue,
+                &format!(
+                    "arroyo-{}-{}-{}",
+                    ctx.task_info.job_id, ctx.task_info.operator_id, ctx.task_info.task_index
+                ),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        Ok((connection, consumer))
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        // all subtasks consume from the queue, and the broker spreads messages between them
+        let (_connection, mut consumer) = self.consume(ctx).await.map_err(|e| {
+            UserError::new(
+                "Failed to consume from RabbitMQ",
+                format!("{}: {:?}", self.queue, e),
+            )
+        })?;
+
+        info!("Consuming from RabbitMQ queue {}", self.queue);
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+
+        loop {
+            select! {
+                delivery = consumer.next() => {
+                    match delivery {
+                        Some(Ok(delivery)) => {
+                            self.deserializer.throttle(&delivery.data).await;
+                            for value in self.deserializer.deserialize_slice(&delivery.data) {
+                                match value {
+                                    Ok(value) => {
+                                        ctx.collector.collect(Record {
+                                            timestamp: SystemTime::now(),
+                                            key: None,
+                                            value,
+                                        }).await;
+                                    }
+                                    Err(e) => {
+                                        if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, &delivery.data, e)? {
+                                            errors += 1;
+                                            if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                                ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                                errors = 0;
+                                                last_reported_error = Instant::now();
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+
+                            *self.acks.reading() = Some(delivery.acker);
+                        }
+                        Some(Err(e)) => {
+                            // unacked messages are requeued by the broker when the channel closes,
+                            // and we'll resume from the last checkpoint on restart
+                            return Err(UserError::new("Error reading from RabbitMQ", format!("{:?}", e)));
+                        }
+                        None => {
+                            return Err(UserError::new(
+                                "RabbitMQ consumer closed",
+                                format!("the consumer for queue {} was cancelled", self.queue),
+                            ));
+                        }
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use arroyo_rpc::OperatorConfig;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+
+use crate::operators::lookup_join::LookupSource;
+
+use super::{redis_client, RedisConfig, RedisTable, TableType};
+
+/// Looks up the string values (as written by the Redis string table sink) at `<key_prefix><key>`
+pub struct RedisLookup {
+    config: RedisConfig,
+    key_prefix: String,
+    connection: Option<ConnectionManager>,
+}
+
+impl RedisLookup {
+    pub fn from_config(config: &str, _key_column: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for RedisLookup");
+        let connection: RedisConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for RedisLookup");
+        let table: RedisTable =
+            serde_json::from_value(config.table).expect("Invalid table config for RedisLookup");
+        let TableType::Lookup { key_prefix } = table.type_ else {
+            panic!("found non-lookup Redis config in lookup operator");
+        };
+
+        Self {
+            config: connection,
+            key_prefix,
+            connection: None,
+        }
+    }
+}
+
+#[async_trait]
+impl LookupSource for RedisLookup {
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        if self.connection.is_none() {
+            // the connection manager transparently reconnects if the connection is lost
+            let client = redis_client(&self.config)?;
+            self.connection = Some(ConnectionManager::new(client).await?);
+        }
+
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| format!("{}{}", self.key_prefix, key))
+            .collect();
+
+        Ok(redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(self.connection.as_mut().unwrap())
+            .await?)
+    }
+}
@@ -0,0 +1,22 @@
+use redis::{IntoConnectionInfo, RedisResult};
+use serde::{Deserialize, Serialize};
+use typify::import_types;
+
+pub mod lookup;
+pub mod sink;
+pub mod source;
+
+import_types!(schema = "../connector-schemas/redis/connection.json");
+import_types!(schema = "../connector-schemas/redis/table.json");
+
+pub(crate) fn redis_client(config: &RedisConfig) -> RedisResult<redis::Client> {
+    let mut info = config.address.as_str().into_connection_info()?;
+    if config.username.is_some() {
+        info.redis.username = config.username.clone();
+    }
+    if config.password.is_some() {
+        info.redis.password = config.password.clone();
+    }
+
+    redis::Client::open(info)
+}
@@ -0,0 +1,232 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataSerializer;
+use crate::SchemaData;
+use arroyo_macro::process_fn;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use redis::aio::ConnectionManager;
+use redis::streams::StreamMaxlen;
+use redis::Pipeline;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use super::{redis_client, RedisConfig, RedisTable, TableType, Target};
+
+// the number of commands to buffer in the pipeline before sending them to the server
+const FLUSH_SIZE: usize = 500;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_RETRIES: u32 = 20;
+
+#[derive(StreamNode)]
+pub struct RedisSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    config: RedisConfig,
+    target: Target,
+    connection: Option<ConnectionManager>,
+    pipeline: Pipeline,
+    pipeline_size: usize,
+    last_flush: Instant,
+    serializer: DataSerializer<T>,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> RedisSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for RedisSink");
+        let connection: RedisConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for RedisSink");
+        let table: RedisTable =
+            serde_json::from_value(config.table).expect("Invalid table config for RedisSink");
+        let TableType::Sink { target } = table.type_ else {
+            panic!("found non-sink Redis config in sink operator");
+        };
+
+        Self {
+            config: connection,
+            target,
+            connection: None,
+            pipeline: redis::pipe(),
+            pipeline_size: 0,
+            last_flush: Instant::now(),
+            serializer: DataSerializer::new(
+                config.format.expect("Format must be defined for RedisSink"),
+            ),
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        match &self.target {
+            Target::StringTable { key_prefix, .. } | Target::HashTable { key_prefix, .. } => {
+                format!("redis-sink-{}", key_prefix)
+            }
+            Target::StreamTable { stream_key, .. } => format!("redis-sink-{}", stream_key),
+        }
+    }
+
+    async fn on_start(&mut self, _: &mut Context<(), ()>) {
+        let client = redis_client(&self.config).expect("Invalid Redis configuration");
+
+        // the connection manager transparently reconnects if the connection is lost
+        self.connection = Some(
+            ConnectionManager::new(client)
+                .await
+                .expect("Failed to connect to Redis"),
+        );
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(value) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        if let Err(column) = add_command(&mut self.pipeline, &self.target, &record.value, value) {
+            self.report_missing_column(ctx, column).await;
+            return;
+        }
+
+        self.pipeline_size += 1;
+        if self.pipeline_size >= FLUSH_SIZE {
+            self.flush().await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, _: &mut Context<(), ()>) {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush().await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _: &mut Context<(), ()>) {
+        self.flush().await;
+    }
+
+    async fn on_close(&mut self, _: &mut Context<(), ()>) {
+        self.flush().await;
+    }
+
+    async fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pipeline_size == 0 {
+            return;
+        }
+
+        let connection = self
+            .connection
+            .as_mut()
+            .expect("Redis connection not initialized");
+
+        let mut attempts = 0;
+        while let Err(e) = self.pipeline.query_async::<_, ()>(connection).await {
+            attempts += 1;
+            if attempts >= MAX_RETRIES {
+                panic!(
+                    "Failed to write to Redis after {} attempts: {:?}",
+                    attempts, e
+                );
+            }
+
+            warn!("Failed to write to Redis, retrying: {:?}", e);
+            tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempts.min(6)))).await;
+        }
+
+        self.pipeline.clear();
+        self.pipeline_size = 0;
+    }
+
+    async fn report_missing_column(&mut self, ctx: &mut Context<(), ()>, column: String) {
+        self.errors += 1;
+        if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+            ctx.report_error(
+                format!("Missing Redis key x {}", self.errors),
+                format!(
+                    "records with a null value for column '{}' can't be written to Redis",
+                    column
+                ),
+            )
+            .await;
+            self.errors = 0;
+            self.last_reported_error = Instant::now();
+        }
+    }
+}
+
+/// Adds the command that writes this record to the pipeline, returning the name of the column
+/// that was missing if a key couldn't be determined
+fn add_command<T: Serialize>(
+    pipeline: &mut Pipeline,
+    target: &Target,
+    record: &T,
+    value: Vec<u8>,
+) -> Result<(), String> {
+    match target {
+        Target::StringTable {
+            key_prefix,
+            key_column,
+            ttl_secs,
+        } => {
+            let key = column_value(record, key_column).ok_or_else(|| key_column.clone())?;
+
+            let mut cmd = redis::cmd("SET");
+            cmd.arg(format!("{}{}", key_prefix, key)).arg(value);
+            if let Some(ttl) = ttl_secs {
+                cmd.arg("EX").arg(*ttl);
+            }
+            pipeline.add_command(cmd).ignore();
+        }
+        Target::HashTable {
+            key_prefix,
+            key_column,
+            hash_field_column,
+        } => {
+            let key = match key_column {
+                Some(key_column) => format!(
+                    "{}{}",
+                    key_prefix,
+                    column_value(record, key_column).ok_or_else(|| key_column.clone())?
+                ),
+                None => key_prefix.clone(),
+            };
+
+            let field =
+                column_value(record, hash_field_column).ok_or_else(|| hash_field_column.clone())?;
+
+            pipeline.hset(key, field, value).ignore();
+        }
+        Target::StreamTable {
+            stream_key,
+            max_len,
+        } => {
+            match max_len {
+                Some(max_len) => pipeline.xadd_maxlen(
+                    stream_key,
+                    StreamMaxlen::Approx(*max_len as usize),
+                    "*",
+                    &[("value", value)],
+                ),
+                None => pipeline.xadd(stream_key, "*", &[("value", value)]),
+            }
+            .ignore();
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders the value of a column as a string to be used in a key or hash field, returning None
+/// if the column is missing or null
+fn column_value<T: Serialize>(record: &T, column: &str) -> Option<String> {
+    match serde_json::to_value(record).ok()?.get_mut(column)?.take() {
+        Value::Null => None,
+        Value::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
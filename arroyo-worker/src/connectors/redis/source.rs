@@ -0,0 +1,494 @@
+use crate::connectors::deferred_ack::DeferredAck;
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::source_fn;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::OperatorConfig;
+use arroyo_rpc::{grpc::StopMode, ControlMessage};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use redis::aio::ConnectionManager;
+use redis::streams::{
+    StreamId, StreamInfoConsumersReply, StreamPendingCountReply, StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, RedisResult};
+use serde::de::DeserializeOwned;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::error::TryRecvError;
+use tracing::{debug, info, warn};
+
+use super::{redis_client, RedisConfig, RedisTable, SourceOffset, TableType};
+
+// the maximum number of entries to read at once
+const BATCH_SIZE: usize = 1000;
+// how long to wait for new entries before checking for control messages
+const BLOCK_MS: usize = 100;
+
+#[derive(StreamNode)]
+pub struct RedisStreamSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    config: RedisConfig,
+    stream_key: String,
+    group_name: Option<String>,
+    offset: Option<SourceOffset>,
+    deserializer: DataDeserializer<T>,
+    // the ID of the last entry we've emitted
+    last_id: Option<String>,
+    // the IDs of the entries we've read, held until their checkpoint completes
+    acks: DeferredAck<Vec<String>>,
+    last_reported_error: Instant,
+    errors: usize,
+    _t: PhantomData<K>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub struct RedisStreamState {
+    consumer: String,
+    last_id: String,
+}
+
+/// Compares two stream entry IDs, which are of the form `<millis>-<sequence>`
+fn compare_ids(a: &str, b: &str) -> Ordering {
+    fn parse(id: &str) -> (u64, u64) {
+        let (ms, seq) = id.split_once('-').unwrap_or((id, "0"));
+        (ms.parse().unwrap_or(0), seq.parse().unwrap_or(0))
+    }
+
+    parse(a).cmp(&parse(b))
+}
+
+fn is_covered(id: &str, last_id: Option<&String>) -> bool {
+    last_id
+        .map(|last| compare_ids(id, last) != Ordering::Greater)
+        .unwrap_or(false)
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> RedisStreamSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for RedisStreamSource");
+        let connection: RedisConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for RedisStreamSource");
+        let table: RedisTable = serde_json::from_value(config.table)
+            .expect("Invalid table config for RedisStreamSource");
+        let TableType::Source {
+            stream_key,
+            group_name,
+            offset,
+        } = table.type_
+        else {
+            panic!("found non-source Redis config in source operator");
+        };
+
+        Self {
+            config: connection,
+            stream_key,
+            group_name,
+            offset,
+            deserializer: DataDeserializer::new(
+                config
+                    .format
+                    .expect("Format must be specified for RedisStreamSource"),
+                config.framing,
+            )
+            .with_bad_data(config.bad_data)
+            .with_rate_limit(config.rate_limit),
+            last_id: None,
+            acks: DeferredAck::default(),
+            last_reported_error: Instant::now(),
+            errors: 0,
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("redis-stream-{}", self.stream_key)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("r", "redis stream source state")]
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    fn group(&self, ctx: &Context<(), T>) -> String {
+        self.group_name.clone().unwrap_or_else(|| {
+            format!(
+                "arroyo-{}-{}-consumer",
+                ctx.task_info.job_id, ctx.task_info.operator_id
+            )
+        })
+    }
+
+    async fn read(
+        &self,
+        conn: &mut ConnectionManager,
+        group: &str,
+        consumer: &str,
+        id: &str,
+        block: bool,
+    ) -> RedisResult<Vec<StreamId>> {
+        let mut opts = StreamReadOptions::default()
+            .group(group, consumer)
+            .count(BATCH_SIZE);
+        if block {
+            opts = opts.block(BLOCK_MS);
+        }
+
+        let reply: Option<StreamReadReply> = conn
+            .xread_options(&[&self.stream_key], &[id], &opts)
+            .await?;
+
+        Ok(reply
+            .into_iter()
+            .flat_map(|r| r.keys)
+            .flat_map(|k| k.ids)
+            .collect())
+    }
+
+    async fn emit(&mut self, ctx: &mut Context<(), T>, entry: StreamId) -> Result<(), UserError> {
+        // entries written by the redis sink have a single value field; otherwise we treat the
+        // fields of the entry as a JSON object
+        let payload = match entry.map.get("value") {
+            Some(value) => redis::from_redis_value::<Vec<u8>>(value).ok(),
+            None => entry
+                .map
+                .iter()
+                .map(|(k, v)| {
+                    Ok((
+                        k.clone(),
+                        serde_json::Value::String(redis::from_redis_value(v)?),
+                    ))
+                })
+                .collect::<RedisResult<serde_json::Map<String, serde_json::Value>>>()
+                .ok()
+                .map(|fields| serde_json::to_vec(&fields).unwrap()),
+        };
+
+        match payload {
+            Some(payload) => {
+                self.deserializer.throttle(&payload).await;
+                let iter = self.deserializer.deserialize_slice(&payload);
+                for value in iter {
+                    match value {
+                        Ok(value) => {
+                            ctx.collector
+                                .collect(Record {
+                                    timestamp: SystemTime::now(),
+                                    key: None,
+                                    value,
+                                })
+                                .await;
+                        }
+                        Err(e) => {
+                            if let Some(e) =
+                                self.deserializer
+                                    .handle_bad_data(&ctx.task_info, &payload, e)?
+                            {
+                                self.errors += 1;
+                                if self.last_reported_error.elapsed() > Duration::from_secs(30) {
+                                    ctx.report_error(
+                                        format!("{} x {}", e.name, self.errors),
+                                        e.details,
+                                    )
+                                    .await;
+                                    self.errors = 0;
+                                    self.last_reported_error = Instant::now();
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            None => {
+                warn!("Skipping stream entry {} with non-string fields", entry.id);
+            }
+        }
+
+        self.acks.read(entry.id.clone());
+        self.last_id = Some(entry.id);
+        Ok(())
+    }
+
+    /// Moves the pending entries of consumers that no longer exist (because the source has been
+    /// rescaled) to this consumer, acking those that were covered by their last checkpoint
+    async fn claim_orphaned_entries(
+        &self,
+        ctx: &mut Context<(), T>,
+        conn: &mut ConnectionManager,
+        group: &str,
+        consumer: &str,
+        restored: &HashMap<String, String>,
+    ) -> RedisResult<()> {
+        let consumers: StreamInfoConsumersReply =
+            conn.xinfo_consumers(&self.stream_key, group).await?;
+
+        for orphan in consumers.consumers {
+            let Some(index) = orphan
+                .name
+                .strip_prefix("arroyo-")
+                .and_then(|i| i.parse::<usize>().ok())
+            else {
+                continue;
+            };
+
+            if index < ctx.task_info.parallelism
+                || index % ctx.task_info.parallelism != ctx.task_info.task_index
+                || orphan.pending == 0
+            {
+                continue;
+            }
+
+            loop {
+                let pending: StreamPendingCountReply = conn
+                    .xpending_consumer_count(
+                        &self.stream_key,
+                        group,
+                        "-",
+                        "+",
+                        BATCH_SIZE,
+                        &orphan.name,
+                    )
+                    .await?;
+
+                if pending.ids.is_empty() {
+                    break;
+                }
+
+                let (covered, uncovered): (Vec<_>, Vec<_>) = pending
+                    .ids
+                    .into_iter()
+                    .map(|p| p.id)
+                    .partition(|id| is_covered(id, restored.get(&orphan.name)));
+
+                info!(
+                    "claiming {} pending entries from consumer {}",
+                    uncovered.len(),
+                    orphan.name
+                );
+
+                if !covered.is_empty() {
+                    conn.xack::<_, _, _, ()>(&self.stream_key, group, &covered)
+                        .await?;
+                }
+
+                if !uncovered.is_empty() {
+                    let _: redis::Value = redis::cmd("XCLAIM")
+                        .arg(&self.stream_key)
+                        .arg(group)
+                        .arg(consumer)
+                        .arg(0)
+                        .arg(&uncovered)
+                        .arg("JUSTID")
+                        .query_async(conn)
+                        .await?;
+                }
+            }
+
+            conn.xgroup_delconsumer::<_, _, _, ()>(&self.stream_key, group, &orphan.name)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let client = redis_client(&self.config)
+            .map_err(|e| UserError::new("Invalid Redis configuration", format!("{:?}", e)))?;
+
+        let mut conn = ConnectionManager::new(client)
+            .await
+            .map_err(|e| UserError::new("Failed to connect to Redis", format!("{:?}", e)))?;
+
+        let group = self.group(ctx);
+        let consumer = format!("arroyo-{}", ctx.task_info.task_index);
+
+        let start = match self.offset {
+            Some(SourceOffset::Earliest) => "0",
+            Some(SourceOffset::Latest) | None => "$",
+        };
+
+        match conn
+            .xgroup_create_mkstream::<_, _, _, ()>(&self.stream_key, &group, start)
+            .await
+        {
+            Ok(_) => {
+                info!("created consumer group {} for {}", group, self.stream_key);
+            }
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            Err(e) => {
+                return Err(UserError::new(
+                    "Failed to create consumer group",
+                    format!("{:?}", e),
+                ));
+            }
+        }
+
+        let s: GlobalKeyedState<String, RedisStreamState, _> =
+            ctx.state.get_global_keyed_state('r').await;
+        let restored: HashMap<String, String> = s
+            .get_all()
+            .into_iter()
+            .map(|state| (state.consumer.clone(), state.last_id.clone()))
+            .collect();
+        self.last_id = restored.get(&consumer).cloned();
+
+        self.claim_orphaned_entries(ctx, &mut conn, &group, &consumer, &restored)
+            .await
+            .map_err(|e| UserError::new("Failed to claim pending entries", format!("{:?}", e)))?;
+
+        // entries that were delivered to us but not acked are still pending; those covered by the
+        // checkpoint we restored from can be acked, and the rest need to be emitted again
+        let restored_id = self.last_id.clone();
+        let mut cursor = "0".to_string();
+        loop {
+            let entries = self
+                .read(&mut conn, &group, &consumer, &cursor, false)
+                .await
+                .map_err(|e| UserError::new("Failed to read from stream", format!("{:?}", e)))?;
+
+            let Some(last) = entries.last() else {
+                break;
+            };
+            cursor = last.id.clone();
+
+            for entry in entries {
+                if is_covered(&entry.id, restored_id.as_ref()) {
+                    self.acks.restored(entry.id);
+                } else {
+                    self.emit(ctx, entry).await?;
+                }
+            }
+        }
+
+        loop {
+            let entries = self
+                .read(&mut conn, &group, &consumer, ">", true)
+                .await
+                .map_err(|e| UserError::new("Failed to read from stream", format!("{:?}", e)))?;
+
+            for entry in entries {
+                self.emit(ctx, entry).await?;
+            }
+
+            loop {
+                let control_message = match ctx.control_rx.try_recv() {
+                    Ok(msg) => Some(msg),
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+                };
+
+                if let Some(ControlMessage::Checkpoint(_)) = &control_message {
+                    let ids = self.acks.checkpoint();
+                    if !ids.is_empty() {
+                        if let Err(e) = conn
+                            .xack::<_, _, _, ()>(&self.stream_key, &group, &ids)
+                            .await
+                        {
+                            // these will be acked when we next restore
+                            warn!("Failed to ack stream entries: {:?}", e);
+                        }
+                    }
+
+                    if let Some(last_id) = &self.last_id {
+                        let mut s = ctx.state.get_global_keyed_state('r').await;
+                        s.insert(
+                            consumer.clone(),
+                            RedisStreamState {
+                                consumer: consumer.clone(),
+                                last_id: last_id.clone(),
+                            },
+                        )
+                        .await;
+                    }
+                }
+
+                if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                    return Ok(r);
+                }
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping Redis stream source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_ids() {
+        assert_eq!(
+            compare_ids("1700000000000-0", "1700000000000-1"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_ids("1700000000001-0", "1700000000000-9"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_ids("999-10", "999-9"), Ordering::Greater);
+
+        let last = "1700000000000-5".to_string();
+        assert!(is_covered("1700000000000-5", Some(&last)));
+        assert!(!is_covered("1700000000000-6", Some(&last)));
+        assert!(!is_covered("0-1", None));
+    }
+}
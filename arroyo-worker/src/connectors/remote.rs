@@ -0,0 +1,408 @@
+use crate::engine::{Context, StreamNode};
+use crate::formats::{DataDeserializer, DataSerializer};
+use crate::{SchemaData, SourceFinishType};
+use arroyo_macro::{process_fn, source_fn};
+use arroyo_rpc::grpc::connector::remote_connector_client::RemoteConnectorClient;
+use arroyo_rpc::grpc::connector::{
+    write_req, ReadReq, WriteBatch, WriteFlush, WriteReq, WriteResp, WriteStart,
+};
+use arroyo_rpc::grpc::{StopMode, TableDescriptor};
+use arroyo_rpc::{ControlMessage, OperatorConfig};
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::select;
+use tokio::sync::mpsc::Sender;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+use tonic::Streaming;
+use tracing::{debug, info};
+
+// the number of messages the sink buffers before sending them to the connector
+const WRITE_BATCH_SIZE: usize = 512;
+
+/// The connection config written by the API for remote connectors
+#[derive(Deserialize)]
+struct RemoteConnection {
+    endpoint: String,
+    config: serde_json::Value,
+}
+
+fn parse_config(config: &str, operator: &str) -> (OperatorConfig, RemoteConnection) {
+    let config: OperatorConfig =
+        serde_json::from_str(config).unwrap_or_else(|_| panic!("Invalid config for {}", operator));
+    let connection = serde_json::from_value(config.connection.clone())
+        .unwrap_or_else(|_| panic!("Invalid connection config for {}", operator));
+    (config, connection)
+}
+
+async fn connect(endpoint: &str) -> Result<RemoteConnectorClient<Channel>, UserError> {
+    RemoteConnectorClient::connect(endpoint.to_string())
+        .await
+        .map_err(|e| {
+            UserError::new(
+                "Failed to connect to remote connector",
+                format!("could not connect to {}: {}", endpoint, e),
+            )
+        })
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
+pub struct RemoteSourceState {
+    offset: Option<String>,
+}
+
+/// Source for connectors that run as separate services; each subtask reads a stream of
+/// messages from the connector, which decides how to divide the data between subtasks
+#[derive(StreamNode)]
+pub struct RemoteSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    endpoint: String,
+    config: String,
+    table: String,
+    deserializer: DataDeserializer<T>,
+    state: RemoteSourceState,
+    _t: PhantomData<K>,
+}
+
+#[source_fn(out_k = (), out_t = T)]
+impl<K, T> RemoteSourceFunc<K, T>
+where
+    K: DeserializeOwned + Data,
+    T: SchemaData,
+{
+    pub fn from_config(config: &str) -> Self {
+        let (config, connection) = parse_config(config, "RemoteSource");
+
+        Self {
+            endpoint: connection.endpoint,
+            config: connection.config.to_string(),
+            table: config.table.to_string(),
+            deserializer: DataDeserializer::new(
+                config.format.expect("RemoteSource requires a format"),
+                config.framing,
+            )
+            .with_bad_data(config.bad_data)
+            .with_compression(config.compression)
+            .with_rate_limit(config.rate_limit),
+            state: RemoteSourceState::default(),
+            _t: PhantomData,
+        }
+    }
+
+    fn name(&self) -> String {
+        "RemoteSource".to_string()
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table("r", "remote source state")]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), T>) {
+        let s: GlobalKeyedState<usize, RemoteSourceState, _> =
+            ctx.state.get_global_keyed_state('r').await;
+
+        if let Some(state) = s.get(&ctx.task_info.task_index) {
+            self.state = state.clone();
+        }
+    }
+
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+        match self.run_int(ctx).await {
+            Ok(r) => r,
+            Err(e) => {
+                ctx.report_error(e.name.clone(), e.details.clone()).await;
+
+                panic!("{}: {}", e.name, e.details);
+            }
+        }
+    }
+
+    async fn handle_control_message(
+        &mut self,
+        ctx: &mut Context<(), T>,
+        msg: Option<ControlMessage>,
+    ) -> Option<SourceFinishType> {
+        match msg? {
+            ControlMessage::Checkpoint(c) => {
+                debug!("starting checkpointing {}", ctx.task_info.task_index);
+                let mut s: GlobalKeyedState<usize, RemoteSourceState, _> =
+                    ctx.state.get_global_keyed_state('r').await;
+                s.insert(ctx.task_info.task_index, self.state.clone()).await;
+
+                if self.checkpoint(c, ctx).await {
+                    return Some(SourceFinishType::Immediate);
+                }
+            }
+            ControlMessage::Stop { mode } => {
+                info!("Stopping remote source: {:?}", mode);
+
+                match mode {
+                    StopMode::Graceful => {
+                        return Some(SourceFinishType::Graceful);
+                    }
+                    StopMode::Immediate => {
+                        return Some(SourceFinishType::Immediate);
+                    }
+                }
+            }
+            ControlMessage::Commit { .. } => {
+                unreachable!("sources shouldn't receive commit messages");
+            }
+            ControlMessage::LoadCompacted { compacted } => {
+                ctx.load_compacted(compacted).await;
+            }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
+            ControlMessage::NoOp => {}
+        }
+        None
+    }
+
+    async fn run_int(&mut self, ctx: &mut Context<(), T>) -> Result<SourceFinishType, UserError> {
+        let mut stream = connect(&self.endpoint)
+            .await?
+            .read(ReadReq {
+                config: self.config.clone(),
+                table: self.table.clone(),
+                task_index: ctx.task_info.task_index as u32,
+                parallelism: ctx.task_info.parallelism as u32,
+                offset: self.state.offset.clone(),
+            })
+            .await
+            .map_err(|e| UserError::new("Failed to read from remote connector", e.message()))?
+            .into_inner();
+
+        let mut last_reported_error = Instant::now();
+        let mut errors = 0;
+
+        loop {
+            select! {
+                resp = stream.message() => {
+                    let resp = match resp {
+                        Ok(Some(resp)) => resp,
+                        Ok(None) => {
+                            info!("Remote connector finished reading");
+                            return Ok(SourceFinishType::Final);
+                        }
+                        Err(e) => {
+                            return Err(UserError::new("Failed to read from remote connector", e.message()));
+                        }
+                    };
+
+                    for msg in &resp.messages {
+                        self.deserializer.throttle(msg).await;
+                        for value in self.deserializer.deserialize_slice(msg) {
+                            match value {
+                                Ok(value) => {
+                                    ctx.collector.collect(Record {
+                                        timestamp: SystemTime::now(),
+                                        key: None,
+                                        value,
+                                    }).await;
+                                }
+                                Err(e) => {
+                                    if let Some(e) = self.deserializer.handle_bad_data(&ctx.task_info, msg, e)? {
+                                        errors += 1;
+                                        if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                            ctx.report_error(format!("{} x {}", e.name, errors), e.details).await;
+                                            errors = 0;
+                                            last_reported_error = Instant::now();
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    if resp.offset.is_some() {
+                        self.state.offset = resp.offset;
+                    }
+                }
+                control_message = ctx.control_rx.recv() => {
+                    if let Some(r) = self.handle_control_message(ctx, control_message).await {
+                        return Ok(r);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sink for connectors that run as separate services. Messages are sent in batches, and at each
+/// checkpoint the sink waits for the connector to acknowledge that everything sent so far has
+/// been written.
+#[derive(StreamNode)]
+pub struct RemoteSinkFunc<K: Key + Serialize, T: SchemaData + Serialize> {
+    endpoint: String,
+    config: String,
+    table: String,
+    serializer: DataSerializer<T>,
+    buffer: Vec<Vec<u8>>,
+    requests: Option<Sender<WriteReq>>,
+    responses: Option<Streaming<WriteResp>>,
+    _t: PhantomData<K>,
+}
+
+impl<K: Key + Serialize, T: SchemaData + Serialize> RemoteSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> Self {
+        let (config, connection) = parse_config(config, "RemoteSink");
+
+        Self {
+            endpoint: connection.endpoint,
+            config: connection.config.to_string(),
+            table: config.table.to_string(),
+            serializer: DataSerializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for RemoteSink"),
+            )
+            .with_compression(config.compression),
+            buffer: vec![],
+            requests: None,
+            responses: None,
+            _t: PhantomData,
+        }
+    }
+}
+
+#[process_fn(in_k = K, in_t = T)]
+impl<K: Key + Serialize, T: SchemaData + Serialize> RemoteSinkFunc<K, T> {
+    fn name(&self) -> String {
+        "RemoteSink".to_string()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<(), ()>) {
+        let result = async {
+            let mut client = connect(&self.endpoint).await?;
+            let (tx, rx) = tokio::sync::mpsc::channel(16);
+
+            tx.send(WriteReq {
+                request: Some(write_req::Request::Start(WriteStart {
+                    config: self.config.clone(),
+                    table: self.table.clone(),
+                    task_index: ctx.task_info.task_index as u32,
+                    parallelism: ctx.task_info.parallelism as u32,
+                })),
+            })
+            .await
+            .unwrap();
+
+            let responses = client
+                .write(ReceiverStream::new(rx))
+                .await
+                .map_err(|e| UserError::new("Failed to write to remote connector", e.message()))?
+                .into_inner();
+
+            self.requests = Some(tx);
+            self.responses = Some(responses);
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            self.fail(ctx, e).await;
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(v) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        self.buffer.push(v);
+        if self.buffer.len() >= WRITE_BATCH_SIZE {
+            self.send_batch(ctx).await;
+        }
+    }
+
+    async fn send_batch(&mut self, ctx: &mut Context<(), ()>) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let req = WriteReq {
+            request: Some(write_req::Request::Batch(WriteBatch {
+                messages: std::mem::take(&mut self.buffer),
+            })),
+        };
+        self.send(ctx, req).await;
+    }
+
+    async fn send(&mut self, ctx: &mut Context<(), ()>, req: WriteReq) {
+        if self.requests.as_ref().unwrap().send(req).await.is_err() {
+            // the request stream only closes if the connector has failed the call
+            let e = self.response_error().await;
+            self.fail(ctx, e).await;
+        }
+    }
+
+    async fn response_error(&mut self) -> UserError {
+        let details = match self.responses.as_mut().unwrap().message().await {
+            Err(e) => e.message().to_string(),
+            Ok(_) => "the connector closed the write stream".to_string(),
+        };
+        UserError::new("Failed to write to remote connector", details)
+    }
+
+    async fn fail(&mut self, ctx: &mut Context<(), ()>, e: UserError) {
+        ctx.report_error(e.name.clone(), e.details.clone()).await;
+        panic!("{}: {}", e.name, e.details);
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.send_batch(ctx).await;
+        self.send(
+            ctx,
+            WriteReq {
+                request: Some(write_req::Request::Flush(WriteFlush {
+                    epoch: barrier.epoch,
+                })),
+            },
+        )
+        .await;
+
+        let e = match self.responses.as_mut().unwrap().message().await {
+            Ok(Some(resp)) if resp.epoch == barrier.epoch => return,
+            Ok(Some(resp)) => UserError::new(
+                "Unexpected response from remote connector",
+                format!(
+                    "expected a flush of epoch {}, but received {}",
+                    barrier.epoch, resp.epoch
+                ),
+            ),
+            Ok(None) => UserError::new(
+                "Failed to write to remote connector",
+                "the connector closed the write stream",
+            ),
+            Err(e) => UserError::new("Failed to write to remote connector", e.message()),
+        };
+        self.fail(ctx, e).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.send_batch(ctx).await;
+
+        // closing the request stream tells the connector we're done; it finishes the call once
+        // everything has been written
+        self.requests.take();
+        loop {
+            match self.responses.as_mut().unwrap().message().await {
+                Ok(Some(_)) => {}
+                Ok(None) => return,
+                Err(e) => {
+                    let e = UserError::new("Failed to write to remote connector", e.message());
+                    self.fail(ctx, e).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::StatusCode;
+use rsa::pkcs8::{DecodePrivateKey, EncodePublicKey};
+use rsa::RsaPrivateKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use typify::import_types;
+
+pub mod sink;
+
+import_types!(schema = "../connector-schemas/snowflake/connection.json");
+import_types!(schema = "../connector-schemas/snowflake/table.json");
+
+// scoped tokens are valid for an hour
+const TOKEN_TTL: Duration = Duration::from_secs(50 * 60);
+
+fn jwt(config: &SnowflakeConfig) -> Result<String> {
+    let key = RsaPrivateKey::from_pkcs8_pem(&config.private_key)
+        .map_err(|e| anyhow!("invalid private key: {}", e))?;
+    let public_key = key.to_public_key().to_public_key_der()?;
+    let fingerprint = base64::encode(Sha256::digest(public_key.as_bytes()));
+
+    let account = config.account.to_uppercase().replace('.', "-");
+    let user = config.user.to_uppercase();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(jsonwebtoken::encode(
+        &Header::new(Algorithm::RS256),
+        &json!({
+            "iss": format!("{}.{}.SHA256:{}", account, user, fingerprint),
+            "sub": format!("{}.{}", account, user),
+            "iat": now,
+            "exp": now + 3600,
+        }),
+        &EncodingKey::from_rsa_pem(config.private_key.as_bytes())?,
+    )?)
+}
+
+/// An open Snowpipe Streaming channel
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub name: String,
+    continuation_token: String,
+    /// The offset token of the last rows that Snowflake has committed to the table
+    pub last_committed_offset_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AppendError {
+    /// The channel was reopened by another client (or expired) and must be reopened before
+    /// appending more rows; any rows that weren't committed have been discarded
+    Invalidated(String),
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for AppendError {
+    fn from(e: anyhow::Error) -> Self {
+        AppendError::Other(e)
+    }
+}
+
+impl From<reqwest::Error> for AppendError {
+    fn from(e: reqwest::Error) -> Self {
+        AppendError::Other(e.into())
+    }
+}
+
+/// A client for the Snowpipe Streaming REST API
+pub struct SnowflakeClient {
+    config: SnowflakeConfig,
+    pipe_path: String,
+    client: reqwest::Client,
+    ingest_host: Option<String>,
+    token: Option<(String, Instant)>,
+}
+
+impl SnowflakeClient {
+    pub fn new(config: SnowflakeConfig, table: &SnowflakeTable) -> Self {
+        let pipe = table
+            .pipe
+            .clone()
+            .unwrap_or_else(|| format!("{}-STREAMING", table.table_name));
+
+        Self {
+            pipe_path: format!(
+                "databases/{}/schemas/{}/pipes/{}",
+                table.database,
+                table.schema_name.as_deref().unwrap_or("PUBLIC"),
+                pipe
+            ),
+            config,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()
+                .unwrap(),
+            ingest_host: None,
+            token: None,
+        }
+    }
+
+    fn account_url(&self) -> String {
+        format!("https://{}.snowflakecomputing.com", self.config.account)
+    }
+
+    /// Returns a token scoped to the ingest host, exchanging a new JWT for it if needed
+    async fn token(&mut self) -> Result<(String, String)> {
+        if self.ingest_host.is_none() {
+            let response = self
+                .client
+                .get(format!("{}/v2/streaming/hostname", self.account_url()))
+                .bearer_auth(jwt(&self.config)?)
+                .header("X-Snowflake-Authorization-Token-Type", "KEYPAIR_JWT")
+                .send()
+                .await?
+                .error_for_status()?;
+            self.ingest_host = Some(response.text().await?.trim().to_string());
+        }
+        let host = self.ingest_host.clone().unwrap();
+
+        if !matches!(&self.token, Some((_, created)) if created.elapsed() < TOKEN_TTL) {
+            let mut form = vec![
+                (
+                    "grant_type",
+                    "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
+                ),
+                ("scope", host.clone()),
+            ];
+            if let Some(role) = &self.config.role {
+                form[1].1 = format!("session:role:{} {}", role, host);
+            }
+
+            let response = self
+                .client
+                .post(format!("{}/oauth/token", self.account_url()))
+                .bearer_auth(jwt(&self.config)?)
+                .form(&form)
+                .send()
+                .await?;
+
+            let status = response.status();
+            let body = response.text().await?;
+            if !status.is_success() {
+                bail!("failed to get a Snowflake token: {} {}", status, body);
+            }
+
+            self.token = Some((body.trim().to_string(), Instant::now()));
+        }
+
+        Ok((host, self.token.as_ref().unwrap().0.clone()))
+    }
+
+    /// Opens (or reopens) a channel, which invalidates any other handles to it and discards
+    /// rows that were appended through them but not yet committed
+    pub async fn open_channel(&mut self, name: &str) -> Result<Channel> {
+        let (host, token) = self.token().await?;
+
+        let response = self
+            .client
+            .put(format!(
+                "https://{}/v2/streaming/{}/channels/{}",
+                host, self.pipe_path, name
+            ))
+            .bearer_auth(token)
+            .json(&json!({}))
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body: Value = serde_json::from_slice(&response.bytes().await?)?;
+        if !status.is_success() {
+            bail!("failed to open channel {}: {} {}", name, status, body);
+        }
+
+        Ok(Channel {
+            name: name.to_string(),
+            continuation_token: body
+                .get("next_continuation_token")
+                .and_then(|t| t.as_str())
+                .ok_or_else(|| anyhow!("no continuation token when opening channel {}", name))?
+                .to_string(),
+            last_committed_offset_token: committed_offset_token(body.get("channel_status")),
+        })
+    }
+
+    /// Appends newline-delimited JSON rows to the channel, tagged with `offset_token`
+    pub async fn append_rows(
+        &mut self,
+        channel: &mut Channel,
+        rows: String,
+        offset_token: &str,
+    ) -> Result<(), AppendError> {
+        let (host, token) = self.token().await?;
+
+        let response = self
+            .client
+            .post(format!(
+                "https://{}/v2/streaming/data/{}/channels/{}/rows",
+                host, self.pipe_path, channel.name
+            ))
+            .query(&[
+                ("continuationToken", channel.continuation_token.as_str()),
+                ("offsetToken", offset_token),
+            ])
+            .bearer_auth(token)
+            .header("Content-Type", "application/x-ndjson")
+            .body(rows)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.bytes().await?;
+
+        if status == StatusCode::BAD_REQUEST
+            || status == StatusCode::NOT_FOUND
+            || status == StatusCode::CONFLICT
+        {
+            return Err(AppendError::Invalidated(
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+
+        if !status.is_success() {
+            return Err(AppendError::Other(anyhow!(
+                "failed to append rows to channel {}: {} {}",
+                channel.name,
+                status,
+                String::from_utf8_lossy(&body)
+            )));
+        }
+
+        let body: Value = serde_json::from_slice(&body).map_err(anyhow::Error::from)?;
+        channel.continuation_token = body
+            .get("next_continuation_token")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| anyhow!("no continuation token in append response"))?
+            .to_string();
+
+        Ok(())
+    }
+
+    /// Returns the last committed offset token for each of the channels
+    pub async fn committed_offset_tokens(
+        &mut self,
+        channels: &[String],
+    ) -> Result<HashMap<String, Option<String>>> {
+        let (host, token) = self.token().await?;
+
+        let response = self
+            .client
+            .post(format!(
+                "https://{}/v2/streaming/{}:bulk-channel-status",
+                host, self.pipe_path
+            ))
+            .bearer_auth(token)
+            .json(&json!({ "channel_names": channels }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body: Value = serde_json::from_slice(&response.bytes().await?)?;
+        Ok(channels
+            .iter()
+            .map(|name| {
+                (
+                    name.clone(),
+                    committed_offset_token(body.pointer(&format!("/channel_statuses/{}", name))),
+                )
+            })
+            .collect())
+    }
+}
+
+fn committed_offset_token(status: Option<&Value>) -> Option<String> {
+    status?
+        .get("last_committed_offset_token")?
+        .as_str()
+        .map(|s| s.to_string())
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::{Data, Key, Record, TaskInfo};
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::connectors::two_phase_committer::{TwoPhaseCommitter, TwoPhaseCommitterOperator};
+
+use super::{AppendError, Channel, SnowflakeClient, SnowflakeConfig, SnowflakeTable};
+
+// appends are limited to 16MB
+const MAX_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_REOPENS: u32 = 5;
+
+pub struct SnowflakeSinkFunc<K: Key, T: Data + Sync + Serialize> {
+    table: SnowflakeTable,
+    client: SnowflakeClient,
+    channels: HashMap<String, Channel>,
+    channel_name: Option<String>,
+    rows: Vec<String>,
+    epoch: u64,
+    _t: PhantomData<(K, T)>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct SnowflakeSinkRecovery {
+    task_index: usize,
+    epoch: u64,
+}
+
+/// The rows written by a subtask in one epoch, which are appended to its channel once the
+/// checkpoint completes. Each chunk of rows is tagged with the offset token `<epoch>-<chunk>`, so
+/// after a restart we can tell which chunks Snowflake already has from the channel's last
+/// committed offset token.
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct SnowflakeBatch {
+    channel: String,
+    epoch: u64,
+    rows: Vec<String>,
+}
+
+fn parse_offset_token(token: &str) -> Option<(u64, u64)> {
+    let (epoch, chunk) = token.split_once('-')?;
+    Some((epoch.parse().ok()?, chunk.parse().ok()?))
+}
+
+/// Splits rows into newline-delimited chunks of at most `max_bytes` (unless a single row is
+/// larger than that)
+fn chunks(rows: &[String], max_bytes: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    for row in rows {
+        if !current.is_empty() && current.len() + row.len() + 1 > max_bytes {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(row);
+        current.push('\n');
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+impl<K: Key, T: Data + Sync + Serialize> SnowflakeSinkFunc<K, T> {
+    pub fn from_config(config: &str) -> TwoPhaseCommitterOperator<K, T, Self> {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for SnowflakeSink");
+        let connection: SnowflakeConfig = serde_json::from_value(config.connection)
+            .expect("Invalid connection config for SnowflakeSink");
+        let table: SnowflakeTable =
+            serde_json::from_value(config.table).expect("Invalid table config for SnowflakeSink");
+
+        TwoPhaseCommitterOperator::new(Self {
+            client: SnowflakeClient::new(connection, &table),
+            table,
+            channels: HashMap::new(),
+            channel_name: None,
+            rows: vec![],
+            epoch: 0,
+            _t: PhantomData,
+        })
+    }
+
+    async fn channel(&mut self, name: &str) -> Result<&mut Channel> {
+        if !self.channels.contains_key(name) {
+            let channel = self.client.open_channel(name).await?;
+            info!(
+                "opened Snowflake channel {} at offset {:?}",
+                name, channel.last_committed_offset_token
+            );
+            self.channels.insert(name.to_string(), channel);
+        }
+
+        Ok(self.channels.get_mut(name).unwrap())
+    }
+
+    /// Appends the chunks of the batch that Snowflake doesn't already have. Returns false if the
+    /// channel was invalidated, in which case it will be reopened on the next call.
+    async fn append_batch(&mut self, batch: &SnowflakeBatch) -> Result<bool> {
+        let committed = self
+            .channel(&batch.channel)
+            .await?
+            .last_committed_offset_token
+            .as_deref()
+            .and_then(parse_offset_token);
+
+        let mut channel = self.channels.remove(&batch.channel).unwrap();
+        for (i, chunk) in chunks(&batch.rows, MAX_CHUNK_BYTES).into_iter().enumerate() {
+            if matches!(committed, Some(c) if c >= (batch.epoch, i as u64)) {
+                // written before we restarted
+                continue;
+            }
+
+            let token = format!("{}-{}", batch.epoch, i);
+            match self.client.append_rows(&mut channel, chunk, &token).await {
+                Ok(()) => {}
+                Err(AppendError::Invalidated(e)) => {
+                    warn!(
+                        "Snowflake channel {} was invalidated, reopening: {}",
+                        batch.channel, e
+                    );
+                    return Ok(false);
+                }
+                Err(AppendError::Other(e)) => return Err(e),
+            }
+        }
+
+        self.channels.insert(batch.channel.clone(), channel);
+        Ok(true)
+    }
+
+    async fn append_all(&mut self, batches: &[SnowflakeBatch]) -> Result<bool> {
+        for batch in batches {
+            if !self.append_batch(batch).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Waits until Snowflake has committed each channel up to the given offset token
+    async fn wait_for_commit(&mut self, mut pending: HashMap<String, (u64, u64)>) -> Result<()> {
+        let start = Instant::now();
+        while !pending.is_empty() {
+            let names: Vec<String> = pending.keys().cloned().collect();
+            let committed = self.client.committed_offset_tokens(&names).await?;
+
+            pending.retain(|name, target| {
+                let committed = committed
+                    .get(name)
+                    .cloned()
+                    .flatten()
+                    .as_deref()
+                    .and_then(parse_offset_token);
+                !matches!(committed, Some(c) if c >= *target)
+            });
+
+            if pending.is_empty() {
+                break;
+            }
+
+            if start.elapsed() > COMMIT_TIMEOUT {
+                bail!(
+                    "timed out waiting for Snowflake to commit channels {:?}",
+                    pending.keys().collect::<Vec<_>>()
+                );
+            }
+
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<K: Key, T: Data + Sync + Serialize> TwoPhaseCommitter<K, T> for SnowflakeSinkFunc<K, T> {
+    type DataRecovery = SnowflakeSinkRecovery;
+    type PreCommit = SnowflakeBatch;
+
+    fn name(&self) -> String {
+        "snowflake_sink".to_string()
+    }
+
+    async fn init(
+        &mut self,
+        task_info: &TaskInfo,
+        data_recovery: Vec<Self::DataRecovery>,
+    ) -> Result<()> {
+        let prefix = self
+            .table
+            .channel_prefix
+            .clone()
+            .unwrap_or_else(|| format!("{}_{}", task_info.job_id, task_info.operator_id));
+        let name = format!("{}_{}", prefix, task_info.task_index);
+
+        // opening the channel discards anything appended by a previous run that wasn't committed
+        self.channel(&name)
+            .await
+            .map_err(|e| anyhow!("failed to open Snowflake channel {}: {}", name, e))?;
+        self.channel_name = Some(name);
+
+        self.epoch = data_recovery
+            .iter()
+            .map(|r| r.epoch)
+            .max()
+            .map(|e| e + 1)
+            .unwrap_or(0);
+
+        Ok(())
+    }
+
+    async fn insert_record(&mut self, record: &Record<K, T>) -> Result<()> {
+        self.rows.push(serde_json::to_string(&record.value)?);
+        Ok(())
+    }
+
+    async fn commit(&mut self, _: &TaskInfo, pre_commit: Vec<Self::PreCommit>) -> Result<()> {
+        let mut batches = pre_commit;
+        batches.sort_by_key(|b| b.epoch);
+
+        // reopening an invalidated channel discards everything that wasn't committed, including
+        // earlier batches from this commit, so we start over (skipping the committed chunks)
+        let mut reopens = 0;
+        while !self.append_all(&batches).await? {
+            reopens += 1;
+            if reopens > MAX_REOPENS {
+                bail!("Snowflake channels were invalidated {} times", reopens);
+            }
+        }
+
+        let pending = batches
+            .iter()
+            .map(|b| {
+                let last_chunk = chunks(&b.rows, MAX_CHUNK_BYTES).len().saturating_sub(1);
+                (b.channel.clone(), (b.epoch, last_chunk as u64))
+            })
+            .collect();
+
+        self.wait_for_commit(pending).await
+    }
+
+    async fn checkpoint(
+        &mut self,
+        task_info: &TaskInfo,
+        _: Option<SystemTime>,
+        _: bool,
+    ) -> Result<(Self::DataRecovery, HashMap<String, Self::PreCommit>)> {
+        let mut pre_commits = HashMap::new();
+        if !self.rows.is_empty() {
+            let channel = self.channel_name.clone().unwrap();
+            pre_commits.insert(
+                format!("{}_{}", channel, self.epoch),
+                SnowflakeBatch {
+                    channel,
+                    epoch: self.epoch,
+                    rows: std::mem::take(&mut self.rows),
+                },
+            );
+        }
+
+        let recovery = SnowflakeSinkRecovery {
+            task_index: task_info.task_index,
+            epoch: self.epoch,
+        };
+        self.epoch += 1;
+
+        Ok((recovery, pre_commits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, parse_offset_token};
+
+    #[test]
+    fn test_offset_tokens() {
+        assert_eq!(parse_offset_token("12-3"), Some((12, 3)));
+        assert_eq!(parse_offset_token("abc"), None);
+        assert!(parse_offset_token("12-3").unwrap() < parse_offset_token("12-10").unwrap());
+        assert!(parse_offset_token("9-10").unwrap() < parse_offset_token("10-0").unwrap());
+    }
+
+    #[test]
+    fn test_chunks() {
+        let rows: Vec<String> = vec!["aaaa".into(), "bbbb".into(), "cccccccccccc".into()];
+        assert_eq!(chunks(&rows, 10), vec!["aaaa\nbbbb\n", "cccccccccccc\n"]);
+        assert_eq!(chunks(&rows, 100), vec!["aaaa\nbbbb\ncccccccccccc\n"]);
+        assert!(chunks(&[], 10).is_empty());
+    }
+}
@@ -1,26 +1,105 @@
 use crate::engine::Context;
 use crate::formats::DataDeserializer;
 use crate::{SchemaData, SourceFinishType};
+use anyhow::{anyhow, bail};
 use arroyo_macro::{source_fn, StreamNode};
 use arroyo_rpc::formats::{Format, Framing};
 use arroyo_rpc::grpc::{StopMode, TableDescriptor};
 use arroyo_rpc::{ControlMessage, ControlResp, OperatorConfig};
 use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
-use arroyo_types::{string_to_map, Data, Message, Record, Watermark};
+use arroyo_types::{string_to_map, Data, Message, Record, UserError, Watermark};
 use bincode::{Decode, Encode};
-use eventsource_client::{Client, SSE};
+use eventsource_client::{Client, ReconnectOptions, SSE};
+use futures::stream::BoxStream;
 use futures::StreamExt;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashSet;
 use std::marker::PhantomData;
 use std::time::{Duration, Instant, SystemTime};
 use tokio::select;
-use tracing::{debug, info};
+use tokio::time::Instant as TokioInstant;
+use tracing::{debug, info, warn};
 use typify::import_types;
 
 import_types!(schema = "../connector-schemas/sse/table.json");
 
+fn reconnect_options(
+    delay_ms: Option<i64>,
+    max_delay_ms: Option<i64>,
+    backoff_factor: Option<i64>,
+) -> ReconnectOptions {
+    ReconnectOptions::reconnect(true)
+        .retry_initial(true)
+        .delay(Duration::from_millis(delay_ms.unwrap_or(1000) as u64))
+        .delay_max(Duration::from_millis(max_delay_ms.unwrap_or(60_000) as u64))
+        .backoff_factor(backoff_factor.unwrap_or(2) as u32)
+        .build()
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+const DEFAULT_TOKEN_EXPIRY: Duration = Duration::from_secs(3600);
+const TOKEN_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Fetches an access token from the token endpoint, returning it along with the time at which it
+/// should be refreshed. If the endpoint rotates refresh tokens, the new one replaces the
+/// configured token for subsequent refreshes.
+async fn fetch_token(auth: &mut TokenAuthentication) -> anyhow::Result<(String, TokioInstant)> {
+    let mut form = vec![];
+    match &auth.refresh_token {
+        Some(refresh_token) => {
+            form.push(("grant_type", "refresh_token".to_string()));
+            form.push(("refresh_token", refresh_token.clone()));
+        }
+        None => form.push(("grant_type", "client_credentials".to_string())),
+    }
+    if let Some(scopes) = &auth.scopes {
+        form.push(("scope", scopes.clone()));
+    }
+
+    let mut request = reqwest::Client::new().post(&auth.token_endpoint);
+    request = match &auth.client_secret {
+        Some(secret) => request.basic_auth(&auth.client_id, Some(secret)),
+        None => {
+            form.push(("client_id", auth.client_id.clone()));
+            request
+        }
+    };
+
+    let resp = request.form(&form).send().await?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        bail!("token endpoint responded with {}: {}", status, body);
+    }
+
+    let token: TokenResponse = serde_json::from_str(&body)
+        .map_err(|e| anyhow!("invalid response from token endpoint: {:?}", e))?;
+
+    if let Some(refresh_token) = token.refresh_token {
+        auth.refresh_token = Some(refresh_token);
+    }
+
+    // refresh ahead of expiry so that reconnecting doesn't race the old token expiring
+    let expires_in = token
+        .expires_in
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TOKEN_EXPIRY);
+    let margin = (expires_in / 10).min(Duration::from_secs(60));
+
+    Ok((
+        token.access_token,
+        TokioInstant::now() + expires_in - margin,
+    ))
+}
+
 #[derive(Clone, Debug, Encode, Decode, PartialEq, PartialOrd, Default)]
 pub struct SSESourceState {
     last_id: Option<String>,
@@ -35,6 +114,11 @@ where
     url: String,
     headers: Vec<(String, String)>,
     events: Vec<String>,
+    event_type_field: Option<String>,
+    unmatched_events: UnmatchedEvents,
+    reconnect: ReconnectOptions,
+    max_reconnect_attempts: Option<u64>,
+    oauth: Option<TokenAuthentication>,
     deserializer: DataDeserializer<T>,
     state: SSESourceState,
     _t: PhantomData<K>,
@@ -60,6 +144,11 @@ where
                 .map(|(k, v)| (k.to_string(), v.to_string()))
                 .collect(),
             events: events.into_iter().map(|s| s.to_string()).collect(),
+            event_type_field: None,
+            unmatched_events: UnmatchedEvents::Drop,
+            reconnect: reconnect_options(None, None, None),
+            max_reconnect_attempts: None,
+            oauth: None,
             deserializer: DataDeserializer::new(format, framing),
             state: SSESourceState::default(),
             _t: PhantomData,
@@ -82,10 +171,21 @@ where
                 .events
                 .map(|e| e.split(',').map(|e| e.to_string()).collect())
                 .unwrap_or_else(std::vec::Vec::new),
+            event_type_field: table.event_type_field,
+            unmatched_events: table.unmatched_events.unwrap_or(UnmatchedEvents::Drop),
+            reconnect: reconnect_options(
+                table.reconnect_delay_ms,
+                table.max_reconnect_delay_ms,
+                table.reconnect_backoff_factor,
+            ),
+            max_reconnect_attempts: table.max_reconnect_attempts.map(|n| n as u64),
+            oauth: table.oauth,
             deserializer: DataDeserializer::new(
                 config.format.expect("SSESource requires a format"),
                 config.framing,
-            ),
+            )
+            .with_bad_data(config.bad_data)
+            .with_rate_limit(config.rate_limit),
             state: SSESourceState::default(),
             _t: PhantomData,
         }
@@ -142,12 +242,15 @@ where
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
             ControlMessage::NoOp => {}
         }
         None
     }
 
-    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
+    fn stream(&self, token: Option<&str>) -> BoxStream<'static, eventsource_client::Result<SSE>> {
         let mut client = eventsource_client::ClientBuilder::for_url(&self.url).unwrap();
 
         if let Some(id) = &self.state.last_id {
@@ -158,29 +261,73 @@ where
             client = client.header(k, v).unwrap();
         }
 
-        let mut stream = client.build().stream();
-        let events: HashSet<_> = self.events.iter().cloned().collect();
+        if let Some(token) = token {
+            client = client
+                .header("Authorization", &format!("Bearer {}", token))
+                .unwrap();
+        }
 
-        let mut last_reported_error = Instant::now();
-        let mut errors = 0;
+        // the client reconnects on its own, sending the id of the last event it saw so that the
+        // server can resume from there
+        client.reconnect(self.reconnect.clone()).build().stream()
+    }
 
+    async fn run(&mut self, ctx: &mut Context<(), T>) -> SourceFinishType {
         // since there's no way to partition across an event source, only read on the first task
         if ctx.task_info.task_index == 0 {
+            let (token, mut refresh_at) = match &mut self.oauth {
+                Some(auth) => match fetch_token(auth).await {
+                    Ok((token, refresh_at)) => (Some(token), refresh_at),
+                    Err(e) => {
+                        ctx.report_error(
+                            "Failed to fetch access token".to_string(),
+                            format!("{:?}", e),
+                        )
+                        .await;
+                        panic!("Failed to fetch access token: {:?}", e);
+                    }
+                },
+                None => (None, TokioInstant::now()),
+            };
+
+            let mut stream = self.stream(token.as_deref());
+            let mut failed_attempts = 0;
+            let events: HashSet<_> = self.events.iter().cloned().collect();
+
+            let mut last_reported_error = Instant::now();
+            let mut errors = 0;
+
             loop {
                 select! {
                     message = stream.next()  => {
                         match message {
                             Some(Ok(msg)) => {
+                                failed_attempts = 0;
                                 match msg {
                                     SSE::Event(event) => {
                                         if let Some(id) = event.id {
                                             self.state.last_id = Some(id);
                                         }
 
-                                        if events.is_empty() || events.contains(&event.event_type) {
-                                            let iter = self.deserializer.deserialize_slice(&event.data.as_bytes());
+                                        let matched = events.is_empty() || events.contains(&event.event_type);
+                                        if matched || matches!(self.unmatched_events, UnmatchedEvents::BadData) {
+                                            let fields = self.event_type_field.as_ref().map(|f| {
+                                                let mut fields = Map::new();
+                                                fields.insert(f.clone(), Value::String(event.event_type.clone()));
+                                                fields
+                                            });
+
+                                            self.deserializer.throttle(event.data.as_bytes()).await;
+                                            let results: Vec<_> = if matched {
+                                                self.deserializer.deserialize_slice_with_fields(event.data.as_bytes(), fields.as_ref()).collect()
+                                            } else {
+                                                vec![Err(UserError::new(
+                                                    "Unmatched event type",
+                                                    format!("Received event of type '{}', which is not one of the configured events", event.event_type),
+                                                ))]
+                                            };
 
-                                            for v in iter {
+                                            for v in results {
                                                 match v {
                                                     Ok(value) => {
                                                         ctx.collector.collect(Record {
@@ -190,17 +337,26 @@ where
                                                         }).await;
                                                     }
                                                     Err(e) => {
-                                                        errors += 1;
-                                                        if last_reported_error.elapsed() > Duration::from_secs(30) {
-                                                            ctx.control_tx.send(
-                                                                ControlResp::Error {
-                                                                    operator_id: ctx.task_info.operator_id.clone(),
-                                                                    task_index: ctx.task_info.task_index,
-                                                                    message: format!("{} x {}", e.name, errors),
-                                                                    details: e.details,
-                                                            }).await.unwrap();
-                                                            errors = 0;
-                                                            last_reported_error = Instant::now();
+                                                        match self.deserializer.handle_bad_data(&ctx.task_info, event.data.as_bytes(), e) {
+                                                            Ok(Some(e)) => {
+                                                                errors += 1;
+                                                                if last_reported_error.elapsed() > Duration::from_secs(30) {
+                                                                    ctx.control_tx.send(
+                                                                        ControlResp::Error {
+                                                                            operator_id: ctx.task_info.operator_id.clone(),
+                                                                            task_index: ctx.task_info.task_index,
+                                                                            message: format!("{} x {}", e.name, errors),
+                                                                            details: e.details,
+                                                                    }).await.unwrap();
+                                                                    errors = 0;
+                                                                    last_reported_error = Instant::now();
+                                                                }
+                                                            }
+                                                            Ok(None) => {}
+                                                            Err(e) => {
+                                                                ctx.report_error(e.name.clone(), e.details.clone()).await;
+                                                                panic!("{}: {}", e.name, e.details);
+                                                            }
                                                         }
                                                     }
                                                 }
@@ -213,6 +369,12 @@ where
                                 }
                             }
                             Some(Err(e)) => {
+                                failed_attempts += 1;
+                                if self.max_reconnect_attempts.map(|max| failed_attempts <= max).unwrap_or(true) {
+                                    warn!("Error while reading from EventSource (attempt {}); reconnecting: {:?}", failed_attempts, e);
+                                    continue;
+                                }
+
                                 ctx.control_tx.send(
                                     ControlResp::Error {
                                         operator_id: ctx.task_info.operator_id.clone(),
@@ -228,6 +390,21 @@ where
                             }
                         }
                     }
+                    _ = tokio::time::sleep_until(refresh_at), if self.oauth.is_some() => {
+                        // the token can only be changed by reconnecting, which resumes from the
+                        // last event we've seen
+                        match fetch_token(self.oauth.as_mut().unwrap()).await {
+                            Ok((token, next_refresh)) => {
+                                debug!("Refreshed access token; reconnecting");
+                                refresh_at = next_refresh;
+                                stream = self.stream(Some(&token));
+                            }
+                            Err(e) => {
+                                warn!("Failed to refresh access token; retrying: {:?}", e);
+                                refresh_at = TokioInstant::now() + TOKEN_RETRY_DELAY;
+                            }
+                        }
+                    }
                     control_message = ctx.control_rx.recv() => {
                         if let Some(r) = self.our_handle_control_message(ctx, control_message).await {
                             return r;
@@ -1,15 +1,22 @@
 use std::{
+    collections::HashMap,
     marker::PhantomData,
-    sync::Arc,
-    time::{Duration, SystemTime},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime},
 };
 
 use arroyo_macro::process_fn;
+use arroyo_rpc::formats::Format;
 use arroyo_rpc::ControlResp;
 use arroyo_rpc::{grpc::TableDescriptor, OperatorConfig};
 use arroyo_types::{string_to_map, CheckpointBarrier, Key, Record};
 
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, Semaphore};
 
 use tracing::warn;
@@ -24,6 +31,166 @@ use crate::{
 import_types!(schema = "../connector-schemas/webhook/table.json");
 
 const MAX_INFLIGHT: u32 = 50;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A request to be sent to the webhook
+struct WebhookRequest {
+    body: bytes::Bytes,
+    headers: HeaderMap,
+}
+
+/// Everything a request task needs to send a request, retry it, and report failures
+#[derive(Clone)]
+struct RequestSender {
+    client: reqwest::Client,
+    url: Arc<String>,
+    dead_letter_url: Option<Arc<String>>,
+    max_retries: Option<u32>,
+    control_tx: Sender<ControlResp>,
+    last_reported_error_at: Arc<Mutex<SystemTime>>,
+    operator_id: String,
+    task_index: usize,
+}
+
+enum SendError {
+    /// The request may succeed if we try again, like for timeouts, 429s and 5xxs
+    Retryable(String),
+    /// The server rejected the request, so there's no point in retrying it
+    Rejected(String),
+}
+
+impl RequestSender {
+    async fn send_once(&self, url: &str, request: &WebhookRequest) -> Result<(), SendError> {
+        let req = self
+            .client
+            .post(url)
+            .headers(request.headers.clone())
+            .body(request.body.clone())
+            .build()
+            .expect("failed to build request");
+
+        match self.client.execute(req).await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => {
+                let status = resp.status();
+                let details = format!("server responded with error code: {}", status.as_u16());
+                if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                    Err(SendError::Retryable(details))
+                } else {
+                    Err(SendError::Rejected(details))
+                }
+            }
+            Err(e) => Err(SendError::Retryable(e.to_string())),
+        }
+    }
+
+    /// Sends the request, retrying with exponential backoff up to `max_retries` times
+    async fn send_with_retries(&self, url: &str, request: &WebhookRequest) -> Result<(), String> {
+        let mut retries = 0;
+        loop {
+            match self.send_once(url, request).await {
+                Ok(()) => return Ok(()),
+                Err(SendError::Rejected(details)) => return Err(details),
+                Err(SendError::Retryable(details)) => {
+                    if self.max_retries.map(|max| retries >= max).unwrap_or(false) {
+                        return Err(details);
+                    }
+
+                    self.report(format!("webhook failed (retry {})", retries), details)
+                        .await;
+
+                    retries += 1;
+                    tokio::time::sleep(backoff(retries)).await;
+                }
+            }
+        }
+    }
+
+    async fn send(&self, request: WebhookRequest) {
+        let Err(details) = self.send_with_retries(&self.url, &request).await else {
+            return;
+        };
+
+        match &self.dead_letter_url {
+            Some(dead_letter_url) => {
+                if let Err(e) = self.send_with_retries(dead_letter_url, &request).await {
+                    self.report(
+                        "failed to send to dead-letter endpoint; dropping request".to_string(),
+                        e,
+                    )
+                    .await;
+                }
+            }
+            None => {
+                self.report("webhook failed; dropping request".to_string(), details)
+                    .await;
+            }
+        }
+    }
+
+    async fn report(&self, message: String, details: String) {
+        if let Ok(mut last_reported) = self.last_reported_error_at.try_lock() {
+            if last_reported.elapsed().unwrap_or_default() > Duration::from_secs(1) {
+                warn!("websink request failed: {}: {}", message, details);
+
+                self.control_tx
+                    .send(ControlResp::Error {
+                        operator_id: self.operator_id.clone(),
+                        task_index: self.task_index,
+                        message,
+                        details,
+                    })
+                    .await
+                    .unwrap();
+
+                *last_reported = SystemTime::now();
+            }
+        }
+    }
+}
+
+fn backoff(retries: u32) -> Duration {
+    Duration::from_millis(50u64.saturating_mul(1 << retries.min(16))).min(MAX_BACKOFF)
+}
+
+pub(crate) fn template_regex() -> &'static Regex {
+    static TEMPLATE: OnceLock<Regex> = OnceLock::new();
+    TEMPLATE.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap())
+}
+
+/// Substitutes `{{ field }}` references in a template with the record's fields
+pub(crate) fn render_template(template: &str, record: &Value) -> String {
+    template_regex()
+        .replace_all(template, |captures: &regex::Captures| {
+            match record.get(&captures[1]) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Combines serialized records into the body of a batched request; JSON records are sent as an
+/// array and anything else is newline-delimited
+fn batch_body(records: &[Vec<u8>], json: bool) -> Vec<u8> {
+    let (start, separator, end): (&[u8], &[u8], &[u8]) = if json {
+        (b"[", b",", b"]")
+    } else {
+        (b"", b"\n", b"")
+    };
+
+    let mut body = start.to_vec();
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            body.extend_from_slice(separator);
+        }
+        body.extend_from_slice(record);
+    }
+    body.extend_from_slice(end);
+    body
+}
 
 #[derive(StreamNode)]
 pub struct WebhookSinkFunc<K, T>
@@ -32,14 +199,23 @@ where
     T: Serialize + SchemaData,
 {
     url: Arc<String>,
+    dead_letter_url: Option<Arc<String>>,
+    max_retries: Option<u32>,
     semaphore: Arc<Semaphore>,
     client: reqwest::Client,
+    // headers whose values reference fields of the record
+    header_templates: HashMap<HeaderName, String>,
     serializer: DataSerializer<T>,
+    json: bool,
+    batch_size: usize,
+    flush_interval: Duration,
+    buffer: Vec<Vec<u8>>,
+    last_flush: Instant,
     last_reported_error_at: Arc<Mutex<SystemTime>>,
     _t: PhantomData<K>,
 }
 
-#[process_fn(in_k = K, in_t = T)]
+#[process_fn(in_k = K, in_t = T, tick_ms = 100)]
 impl<K, T> WebhookSinkFunc<K, T>
 where
     K: Key,
@@ -51,8 +227,13 @@ where
         let table: WebhookTable =
             serde_json::from_value(config.table).expect("Invalid table config for WebhookSink");
 
-        let headers = string_to_map(table.headers.as_ref().map(|t| t.0.as_str()).unwrap_or(""))
-            .expect("Invalid header map")
+        let (templates, headers): (Vec<_>, Vec<_>) =
+            string_to_map(table.headers.as_ref().map(|t| t.0.as_str()).unwrap_or(""))
+                .expect("Invalid header map")
+                .into_iter()
+                .partition(|(_, v)| template_regex().is_match(v));
+
+        let headers = headers
             .into_iter()
             .map(|(k, v)| {
                 (
@@ -64,19 +245,41 @@ where
             })
             .collect();
 
+        let header_templates = templates
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    (&k).try_into()
+                        .expect(&format!("invalid header name {}", k)),
+                    v,
+                )
+            })
+            .collect();
+
+        let format = config
+            .format
+            .expect("No format configured for webhook sink");
+
         Self {
             url: Arc::new(table.endpoint),
+            dead_letter_url: table.dead_letter_endpoint.map(Arc::new),
+            max_retries: table.max_retries.map(|r| r as u32),
             client: reqwest::ClientBuilder::new()
                 .default_headers(headers)
                 .timeout(Duration::from_secs(5))
                 .build()
                 .expect("could not construct reqwest client"),
             semaphore: Arc::new(Semaphore::new(MAX_INFLIGHT as usize)),
-            serializer: DataSerializer::new(
-                config
-                    .format
-                    .expect("No format configured for webhook sink"),
-            ),
+            header_templates,
+            json: matches!(format, Format::Json(_)),
+            serializer: DataSerializer::new(format),
+            batch_size: table.batch_size.map(|s| s.max(1) as usize).unwrap_or(1),
+            flush_interval: table
+                .flush_interval_ms
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or(DEFAULT_FLUSH_INTERVAL),
+            buffer: vec![],
+            last_flush: Instant::now(),
             last_reported_error_at: Arc::new(Mutex::new(SystemTime::UNIX_EPOCH)),
             _t: PhantomData,
         }
@@ -91,6 +294,63 @@ where
     }
 
     async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<(), ()>) {
+        let Some(body) = self.serializer.to_vec(&record.value) else {
+            return;
+        };
+
+        if self.batch_size > 1 {
+            self.buffer.push(body);
+            if self.buffer.len() >= self.batch_size {
+                self.flush(ctx).await;
+            }
+            return;
+        }
+
+        let mut headers = HeaderMap::new();
+        if !self.header_templates.is_empty() {
+            let value = serde_json::to_value(&record.value).unwrap_or(Value::Null);
+            for (name, template) in &self.header_templates {
+                match HeaderValue::try_from(render_template(template, &value)) {
+                    Ok(v) => {
+                        headers.insert(name.clone(), v);
+                    }
+                    Err(e) => {
+                        warn!("invalid value for header {}: {}", name, e);
+                    }
+                }
+            }
+        }
+
+        self.dispatch(
+            WebhookRequest {
+                body: body.into(),
+                headers,
+            },
+            ctx,
+        )
+        .await;
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<(), ()>) {
+        self.last_flush = Instant::now();
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let body = batch_body(&self.buffer, self.json);
+        self.buffer.clear();
+
+        self.dispatch(
+            WebhookRequest {
+                body: body.into(),
+                headers: HeaderMap::new(),
+            },
+            ctx,
+        )
+        .await;
+    }
+
+    async fn dispatch(&mut self, request: WebhookRequest, ctx: &mut Context<(), ()>) {
         let permit = self
             .semaphore
             .clone()
@@ -98,74 +358,80 @@ where
             .await
             .expect("websink semaphore closed");
 
-        let Some(body) = self.serializer.to_vec(&record.value) else {
-            return;
+        let sender = RequestSender {
+            client: self.client.clone(),
+            url: self.url.clone(),
+            dead_letter_url: self.dead_letter_url.clone(),
+            max_retries: self.max_retries,
+            control_tx: ctx.control_tx.clone(),
+            last_reported_error_at: self.last_reported_error_at.clone(),
+            operator_id: ctx.task_info.operator_id.clone(),
+            task_index: ctx.task_info.task_index,
         };
 
-        let body: bytes::Bytes = body.into();
-
-        let client = self.client.clone();
-        let control_tx = ctx.control_tx.clone();
-        let error_lock = self.last_reported_error_at.clone();
-        let url = self.url.clone();
-
-        // these are just used for (potential) error reporting and we don't need to clone them
-        let operator_id = ctx.task_info.operator_id.clone();
-        let task_index = ctx.task_info.task_index;
-
         tokio::task::spawn(async move {
             // move the permit into the task
             let _permit = permit;
-            let mut retries = 0;
-            loop {
-                let req = client
-                    .post(&*url)
-                    .body(body.clone())
-                    .build()
-                    .expect("failed to build request");
-
-                match client.execute(req).await {
-                    Ok(_) => break,
-                    Err(e) => {
-                        if let Ok(mut last_reported) = error_lock.try_lock() {
-                            if last_reported.elapsed().unwrap_or_default() > Duration::from_secs(1)
-                            {
-                                warn!("websink request failed: {:?}", e);
-
-                                let details = if let Some(status) = e.status() {
-                                    format!("server responded with error code: {}", status.as_u16())
-                                } else {
-                                    e.to_string()
-                                };
-
-                                control_tx
-                                    .send(ControlResp::Error {
-                                        operator_id: operator_id.clone(),
-                                        task_index,
-                                        message: format!("webhook failed (retry {})", retries),
-                                        details,
-                                    })
-                                    .await
-                                    .unwrap();
-
-                                *last_reported = SystemTime::now();
-                            }
-                        }
-
-                        retries += 1;
-
-                        tokio::time::sleep(Duration::from_millis((50 * (1 << retries)).min(5_000)))
-                            .await
-                    }
-                }
-            }
+            sender.send(request).await;
         });
     }
 
-    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, _ctx: &mut Context<(), ()>) {
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<(), ()>) {
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+
         // wait to acquire all of the permits (effectively blocking until all inflight requests are done)
         let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
 
         // TODO: instead of blocking checkpoints on in-progress (or failing) requests, we should store them to state
     }
+
+    async fn on_close(&mut self, ctx: &mut Context<(), ()>) {
+        self.flush(ctx).await;
+        let _permits = self.semaphore.acquire_many(MAX_INFLIGHT).await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{backoff, batch_body, render_template};
+
+    #[test]
+    fn test_render_header() {
+        let record = json!({"id": 5, "tenant": "acme", "missing": null});
+        assert_eq!(
+            render_template("{{tenant}}-{{ id }}", &record),
+            "acme-5".to_string()
+        );
+        assert_eq!(render_template("x{{missing}}{{other}}", &record), "x");
+    }
+
+    #[test]
+    fn test_batch_body() {
+        let records = vec![b"{\"a\":1}".to_vec(), b"{\"a\":2}".to_vec()];
+        assert_eq!(
+            batch_body(&records, true),
+            b"[{\"a\":1},{\"a\":2}]".to_vec()
+        );
+        assert_eq!(
+            batch_body(&records, false),
+            b"{\"a\":1}\n{\"a\":2}".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_millis(100));
+        assert_eq!(backoff(3), Duration::from_millis(400));
+        assert_eq!(backoff(40), Duration::from_secs(5));
+    }
 }
@@ -82,7 +82,10 @@ where
             deserializer: DataDeserializer::new(
                 config.format.expect("WebsocketSource requires a format"),
                 config.framing,
-            ),
+            )
+            .with_bad_data(config.bad_data)
+            .with_compression(config.compression)
+            .with_rate_limit(config.rate_limit),
             state: WebsocketSourceState::default(),
             _t: PhantomData,
         }
@@ -139,6 +142,9 @@ where
             ControlMessage::LoadCompacted { compacted } => {
                 ctx.load_compacted(compacted).await;
             }
+            ControlMessage::QueryState { tx, .. } => {
+                ctx.reject_state_query(tx);
+            }
             ControlMessage::NoOp => {}
         }
         None
@@ -149,13 +155,22 @@ where
         msg: &[u8],
         ctx: &mut Context<(), T>,
     ) -> Result<(), UserError> {
+        self.deserializer.throttle(msg).await;
         let iter = self.deserializer.deserialize_slice(msg);
         for value in iter {
+            let value = match value {
+                Ok(value) => value,
+                Err(e) => match self.deserializer.handle_bad_data(&ctx.task_info, msg, e)? {
+                    Some(e) => return Err(e),
+                    None => continue,
+                },
+            };
+
             ctx.collector
                 .collect(Record {
                     timestamp: SystemTime::now(),
                     key: None,
-                    value: value?,
+                    value,
                 })
                 .await;
         }
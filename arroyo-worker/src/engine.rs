@@ -17,7 +17,7 @@ use arroyo_rpc::grpc::{
     CheckpointMetadata, TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior,
     TaskAssignment,
 };
-use arroyo_rpc::{CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{CompactionResult, ControlMessage, ControlResp, StateQueryResp};
 use arroyo_types::{
     from_micros, range_for_server, server_for_hash, CheckpointBarrier, Data, Key, Message, Record,
     TaskInfo, UserError, Watermark, WorkerId,
@@ -25,15 +25,16 @@ use arroyo_types::{
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
 use petgraph::Direction;
-use prometheus::labels;
+use prometheus::{labels, IntGauge};
 use rand::Rng;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::task::JoinHandle;
 
-use crate::metrics::{register_queue_gauges, QueueGauges, TaskCounters};
+use crate::metrics::{register_queue_gauges, register_source_lag_gauge, QueueGauges, TaskCounters};
 use crate::network_manager::{NetworkManager, Quad, Senders};
-use crate::TIMER_TABLE;
 use crate::{LogicalEdge, LogicalNode, METRICS_PUSH_INTERVAL, PROMETHEUS_PUSH_GATEWAY};
+use crate::{PROCESSING_TIMER_TABLE, TIMER_TABLE};
+use arroyo_state::parquet::checkpoint_url_from_env;
 use arroyo_state::{hash_key, BackingStore, StateBackend, StateStore};
 
 const QUEUE_SIZE: usize = 4 * 1024;
@@ -132,6 +133,8 @@ pub struct Context<K: Key, T: Data, S: BackingStore = StateBackend> {
     pub watermarks: WatermarkHolder,
     pub state: StateStore<S>,
     pub collector: Collector<K, T>,
+    // the earliest processing-time timer, if any are scheduled
+    pub(crate) next_processing_timer: Option<SystemTime>,
     _ts: PhantomData<(K, T)>,
 }
 
@@ -141,11 +144,17 @@ unsafe impl<K: Key, T: Data, S: BackingStore> Sync for Context<K, T, S> {}
 pub struct OutQueue {
     tx: Sender<QueueItem>,
     serialize: bool,
+    // whether every record should be sent to all subtasks of the downstream operator
+    broadcast: bool,
 }
 
 impl OutQueue {
     pub fn new(tx: Sender<QueueItem>, serialize: bool) -> Self {
-        Self { tx, serialize }
+        Self {
+            tx,
+            serialize,
+            broadcast: false,
+        }
     }
 
     pub async fn send(&self, task_info: &TaskInfo, message: Message<impl Key, impl Data>) {
@@ -174,6 +183,8 @@ pub struct Collector<K: Key, T: Data> {
     _ts: PhantomData<(K, T)>,
     tx_queue_rem_gauges: QueueGauges,
     tx_queue_size_gauges: QueueGauges,
+    // only set for source tasks
+    source_lag_gauge: Option<IntGauge>,
 }
 
 impl<K: Key, T: Data> Collector<K, T> {
@@ -191,7 +202,13 @@ impl<K: Key, T: Data> Collector<K, T> {
 
         TaskCounters::MessagesSent.for_task(&self.task_info).inc();
 
-        if self.out_qs.len() == 1 {
+        if let Some(gauge) = &self.source_lag_gauge {
+            if let Ok(lag) = SystemTime::now().duration_since(record.timestamp) {
+                gauge.set(lag.as_millis() as i64);
+            }
+        }
+
+        if self.out_qs.len() == 1 && !self.out_qs[0][0].broadcast {
             let idx = out_idx(&record.key, self.out_qs[0].len());
 
             self.tx_queue_rem_gauges[0][idx]
@@ -210,18 +227,26 @@ impl<K: Key, T: Data> Collector<K, T> {
             let message = Message::Record(record);
 
             for (i, out_node_qs) in self.out_qs.iter().enumerate() {
-                let idx = out_idx(&key, out_node_qs.len());
-                self.tx_queue_rem_gauges[i][idx]
-                    .iter()
-                    .for_each(|c| c.set(self.out_qs[i][idx].tx.capacity() as i64));
-
-                self.tx_queue_size_gauges[i][idx]
-                    .iter()
-                    .for_each(|c| c.set(QUEUE_SIZE as i64));
-
-                out_node_qs[idx]
-                    .send(&self.task_info, message.clone())
-                    .await;
+                let idxs = if out_node_qs[0].broadcast {
+                    0..out_node_qs.len()
+                } else {
+                    let idx = out_idx(&key, out_node_qs.len());
+                    idx..idx + 1
+                };
+
+                for idx in idxs {
+                    self.tx_queue_rem_gauges[i][idx]
+                        .iter()
+                        .for_each(|c| c.set(self.out_qs[i][idx].tx.capacity() as i64));
+
+                    self.tx_queue_size_gauges[i][idx]
+                        .iter()
+                        .for_each(|c| c.set(QUEUE_SIZE as i64));
+
+                    out_node_qs[idx]
+                        .send(&self.task_info, message.clone())
+                        .await;
+                }
             }
         }
     }
@@ -253,10 +278,21 @@ impl<K: Key, T: Data> Context<K, T> {
             write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
             retention_micros: 0,
         });
+        tables.push(TableDescriptor {
+            name: PROCESSING_TIMER_TABLE.to_string(),
+            description: "processing time timer state".to_string(),
+            table_type: TableType::TimeKeyMap as i32,
+            delete_behavior: TableDeleteBehavior::None as i32,
+            write_behavior: TableWriteBehavior::DefaultWrites as i32,
+            retention_micros: 0,
+        });
 
+        // workers are started with the checkpoint URL of their job
+        let checkpoint_url = checkpoint_url_from_env();
         let (state, watermark) = if let Some(metadata) = restore_from {
             let watermark = {
                 let metadata = StateBackend::load_operator_metadata(
+                    &checkpoint_url,
                     &task_info.job_id,
                     &task_info.operator_id,
                     metadata.epoch,
@@ -268,6 +304,7 @@ impl<K: Key, T: Data> Context<K, T> {
                     .map(from_micros)
             };
             let state = StateStore::<StateBackend>::from_checkpoint(
+                &checkpoint_url,
                 &task_info,
                 metadata,
                 tables,
@@ -278,13 +315,24 @@ impl<K: Key, T: Data> Context<K, T> {
             (state, watermark)
         } else {
             (
-                StateStore::<StateBackend>::new(&task_info, tables, control_tx.clone()).await,
+                StateStore::<StateBackend>::new(
+                    &checkpoint_url,
+                    &task_info,
+                    tables,
+                    control_tx.clone(),
+                )
+                .await,
                 None,
             )
         };
 
         let (tx_queue_size_gauges, tx_queue_rem_gauges) =
             register_queue_gauges(&task_info, &out_qs);
+        let source_lag_gauge = if input_partitions == 0 {
+            register_source_lag_gauge(&task_info)
+        } else {
+            None
+        };
 
         Context {
             task_info: task_info.clone(),
@@ -299,9 +347,11 @@ impl<K: Key, T: Data> Context<K, T> {
                 out_qs,
                 tx_queue_rem_gauges,
                 tx_queue_size_gauges,
+                source_lag_gauge,
                 _ts: PhantomData,
             },
             state,
+            next_processing_timer: None,
             _ts: PhantomData,
         }
     }
@@ -388,6 +438,74 @@ impl<K: Key, T: Data> Context<K, T> {
         timer_state.flush().await;
     }
 
+    /// Schedules a timer that fires once the wall clock reaches `time`, however far the watermark
+    /// has advanced. Fired timers are passed to the operator's `handle_processing_timer`.
+    pub async fn schedule_processing_timer<D: Data + PartialEq + Eq>(
+        &mut self,
+        key: &mut K,
+        time: SystemTime,
+        data: D,
+    ) {
+        let mut timer_state: TimeKeyMap<K, TimerValue<K, D>, _> = self
+            .state
+            .get_time_key_map(PROCESSING_TIMER_TABLE, None)
+            .await;
+        let value = TimerValue {
+            time,
+            key: key.clone(),
+            data,
+        };
+
+        debug!(
+            "[{}] scheduling processing time timer for [{}, {:?}]",
+            self.task_info.task_index,
+            hash_key(key),
+            time
+        );
+
+        timer_state.insert(time, key.clone(), value);
+        self.next_processing_timer = Some(
+            self.next_processing_timer
+                .map_or(time, |next| next.min(time)),
+        );
+    }
+
+    pub async fn cancel_processing_timer<D: Data + PartialEq + Eq>(
+        &mut self,
+        key: &mut K,
+        time: SystemTime,
+    ) -> Option<D> {
+        let mut timer_state: TimeKeyMap<K, TimerValue<K, D>, _> = self
+            .state
+            .get_time_key_map(PROCESSING_TIMER_TABLE, None)
+            .await;
+
+        let data = timer_state.remove(time, key).await.map(|v| v.data);
+        self.next_processing_timer = timer_state.get_min_time();
+        data
+    }
+
+    pub async fn flush_processing_timers<D: Data + PartialEq + Eq>(&mut self) {
+        let mut timer_state: TimeKeyMap<K, TimerValue<K, D>, _> = self
+            .state
+            .get_time_key_map(PROCESSING_TIMER_TABLE, None)
+            .await;
+        timer_state.flush().await;
+    }
+
+    /// Loads the processing-time timers restored from a checkpoint, so that they fire
+    pub async fn restore_processing_timers<D: Data + PartialEq + Eq>(&mut self) {
+        let timer_state: TimeKeyMap<K, TimerValue<K, D>, _> = self
+            .state
+            .get_time_key_map(PROCESSING_TIMER_TABLE, None)
+            .await;
+        self.next_processing_timer = timer_state.get_min_time();
+    }
+
+    pub fn next_processing_timer(&self) -> Option<SystemTime> {
+        self.next_processing_timer
+    }
+
     pub async fn collect(&mut self, record: Record<K, T>) {
         self.collector.collect(record).await;
     }
@@ -423,6 +541,17 @@ impl<K: Key, T: Data> Context<K, T> {
     pub async fn load_compacted(&mut self, compaction: CompactionResult) {
         self.state.load_compacted(compaction).await;
     }
+
+    /// Answers a state query for an operator whose state can't be queried, such as a source
+    pub fn reject_state_query(
+        &self,
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<StateQueryResp>>,
+    ) {
+        let _ = tx.send(Err(anyhow::anyhow!(
+            "the state of {} can't be queried",
+            self.task_info.operator_name
+        )));
+    }
 }
 
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
@@ -432,10 +561,16 @@ pub struct TimerValue<K: Key, T: Decode + Encode + Clone + PartialEq + Eq> {
     pub data: T,
 }
 
+/// Tracks the barriers of a checkpoint across an operator's inputs. Aligned checkpoints block
+/// each input once its barrier has arrived until all of them have; unaligned checkpoints are
+/// taken at the first barrier, and the records received on inputs whose barrier hasn't yet
+/// arrived are logged so that they can be persisted with the checkpoint.
 #[derive(Debug)]
 pub struct CheckpointCounter {
     inputs: Vec<Option<u32>>,
     counter: Option<usize>,
+    unaligned: bool,
+    in_flight: Vec<(usize, Vec<u8>)>,
 }
 
 impl CheckpointCounter {
@@ -443,11 +578,26 @@ impl CheckpointCounter {
         CheckpointCounter {
             inputs: vec![None; size],
             counter: None,
+            unaligned: false,
+            in_flight: vec![],
         }
     }
 
     pub fn is_blocked(&self, idx: usize) -> bool {
-        self.inputs[idx].is_some()
+        !self.unaligned && self.inputs[idx].is_some()
+    }
+
+    /// Whether records received on the input are overtaken by an unaligned checkpoint
+    pub fn is_in_flight(&self, idx: usize) -> bool {
+        self.unaligned && self.inputs[idx].is_none()
+    }
+
+    pub fn log_in_flight(&mut self, logical_input: usize, record: Vec<u8>) {
+        self.in_flight.push((logical_input, record));
+    }
+
+    pub fn take_in_flight(&mut self) -> Vec<(usize, Vec<u8>)> {
+        std::mem::take(&mut self.in_flight)
     }
 
     pub fn all_clear(&self) -> bool {
@@ -463,11 +613,15 @@ impl CheckpointCounter {
 
         self.inputs[idx] = Some(checkpoint.epoch);
         self.counter = match self.counter {
-            None => Some(self.inputs.len() - 1),
+            None => {
+                self.unaligned = checkpoint.unaligned;
+                Some(self.inputs.len() - 1)
+            }
             Some(1) => {
                 for v in self.inputs.iter_mut() {
                     *v = None;
                 }
+                self.unaligned = false;
                 None
             }
             Some(n) => Some(n - 1),
@@ -679,7 +833,7 @@ impl Program {
                         physical.add_edge(*f, *t, edge);
                     }
                 }
-                LogicalEdge::Shuffle | LogicalEdge::ShuffleJoin(_) => {
+                LogicalEdge::Shuffle | LogicalEdge::ShuffleJoin(_) | LogicalEdge::Broadcast => {
                     for f in &from_nodes {
                         for (idx, t) in to_nodes.iter().enumerate() {
                             let (tx, rx) = channel(QUEUE_SIZE);
@@ -782,7 +936,7 @@ impl RunningEngine {
             .collect()
     }
 
-    pub fn operator_controls(&self) -> HashMap<String, Vec<Sender<ControlMessage>>> {
+    pub fn operator_controls(&self) -> HashMap<String, BTreeMap<usize, Sender<ControlMessage>>> {
         let mut controls = HashMap::new();
 
         self.program
@@ -812,8 +966,8 @@ impl RunningEngine {
                     .clone();
                 controls
                     .entry(assignment.operator_id.clone())
-                    .or_insert(vec![])
-                    .push(tx);
+                    .or_default()
+                    .insert(w.subtask_idx(), tx);
             });
 
         controls
@@ -878,11 +1032,15 @@ impl Engine {
         let checkpoint_metadata = if let Some(epoch) = config.restore_epoch {
             info!("Restoring checkpoint {} for job {}", epoch, self.job_id);
             Some(
-                StateBackend::load_checkpoint_metadata(&self.job_id, epoch)
-                    .await
-                    .unwrap_or_else(|| {
-                        panic!("failed to load checkpoint metadata for epoch {}", epoch)
-                    }),
+                StateBackend::load_checkpoint_metadata(
+                    &checkpoint_url_from_env(),
+                    &self.job_id,
+                    epoch,
+                )
+                .await
+                .unwrap_or_else(|| {
+                    panic!("failed to load checkpoint metadata for epoch {}", epoch)
+                }),
             )
         } else {
             None
@@ -1058,7 +1216,8 @@ impl Engine {
             };
 
             let tx = edge.weight().tx.as_ref().unwrap().clone();
-            let sender = OutQueue::new(tx, !local);
+            let mut sender = OutQueue::new(tx, !local);
+            sender.broadcast = edge.weight().edge == LogicalEdge::Broadcast;
             out_qs_map
                 .entry(edge.weight().out_logical_idx)
                 .or_default()
@@ -1179,4 +1338,40 @@ mod tests {
         w.set(2, Watermark::Idle);
         assert_eq!(w.watermark(), Some(Watermark::Idle));
     }
+
+    #[test]
+    fn test_checkpoint_counter() {
+        let barrier = |epoch, unaligned| CheckpointBarrier {
+            epoch,
+            min_epoch: 0,
+            timestamp: SystemTime::now(),
+            then_stop: false,
+            unaligned,
+        };
+
+        let mut counter = CheckpointCounter::new(3);
+
+        assert!(!counter.mark(0, &barrier(1, false)));
+        assert!(counter.is_blocked(0));
+        assert!(!counter.is_in_flight(1));
+        assert!(!counter.mark(1, &barrier(1, false)));
+        assert!(counter.mark(2, &barrier(1, false)));
+        assert!(counter.all_clear());
+
+        assert!(!counter.mark(1, &barrier(2, true)));
+        assert!(!counter.is_blocked(1));
+        assert!(!counter.is_in_flight(1));
+        assert!(counter.is_in_flight(0));
+        counter.log_in_flight(0, vec![1]);
+        assert!(!counter.mark(0, &barrier(2, true)));
+        assert!(!counter.is_in_flight(0));
+        assert!(counter.is_in_flight(2));
+        counter.log_in_flight(1, vec![2]);
+        assert!(counter.mark(2, &barrier(2, true)));
+        assert_eq!(counter.take_in_flight(), vec![(0, vec![1]), (1, vec![2])]);
+
+        assert!(!counter.is_in_flight(0));
+        assert!(!counter.is_blocked(0));
+        assert!(counter.all_clear());
+    }
 }
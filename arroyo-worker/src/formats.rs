@@ -1,17 +1,100 @@
 use std::sync::Arc;
 use std::{collections::HashMap, marker::PhantomData};
 
-use arrow::datatypes::{Field, Fields};
-use arroyo_rpc::formats::{Format, Framing, FramingMethod, JsonFormat};
-use arroyo_types::UserError;
+use anyhow::bail;
+use apache_avro::Schema as AvroSchema;
+use arrow::datatypes::{Field, Fields, Schema};
+use arroyo_rpc::formats::{
+    BadData, Compression, CsvFormat, Format, Framing, FramingMethod, JsonFormat,
+};
+use arroyo_rpc::RateLimit;
+use arroyo_types::{TaskInfo, UserError};
+use prost::Message;
+use prost_reflect::DynamicMessage;
 use serde::de::DeserializeOwned;
-use serde_json::{json, Value};
-
+use serde_json::{json, Map, Value};
+use serde_json_path::JsonPath;
+use tracing::info;
+
+use crate::connectors::grpc::{from_json, to_json};
+use crate::schema_registry::{
+    frame, message_descriptor, parse_framing, parse_message_indexes, RegistrySchema, SchemaRegistry,
+};
 use crate::SchemaData;
+use bad_data::BadDataHandler;
+use rate_limit::SourceRateLimiter;
+
+pub mod avro;
+pub mod bad_data;
+pub mod compression;
+pub mod csv;
+pub mod rate_limit;
+
+/// Fields of a JSON record whose values are read from elsewhere in the record
+struct FieldPaths(Vec<(String, FieldPath)>);
+
+enum FieldPath {
+    Pointer(String),
+    JsonPath(JsonPath),
+}
+
+impl FieldPaths {
+    fn new(format: &Format) -> Option<Self> {
+        let Format::Json(json) = format else {
+            return None;
+        };
+
+        if json.field_paths.is_empty() {
+            return None;
+        }
+
+        Some(Self(
+            json.field_paths
+                .iter()
+                .map(|(field, path)| {
+                    let path = if path.starts_with('$') {
+                        FieldPath::JsonPath(
+                            JsonPath::parse(path)
+                                .unwrap_or_else(|e| panic!("invalid JSONPath '{}': {}", path, e)),
+                        )
+                    } else {
+                        FieldPath::Pointer(path.clone())
+                    };
+                    (field.clone(), path)
+                })
+                .collect(),
+        ))
+    }
+
+    /// Sets each field to the value at its path, or null if there is none; where a JSONPath
+    /// matches several values, the first is used
+    fn apply(&self, mut v: Value) -> Result<Value, String> {
+        let values: Vec<_> = self
+            .0
+            .iter()
+            .map(|(field, path)| {
+                let value = match path {
+                    FieldPath::Pointer(pointer) => v.pointer(pointer),
+                    FieldPath::JsonPath(path) => path.query(&v).first(),
+                };
+                (field.clone(), value.cloned().unwrap_or(Value::Null))
+            })
+            .collect();
+
+        let Value::Object(fields) = &mut v else {
+            return Err("Expected a JSON object".to_string());
+        };
+        fields.extend(values);
+
+        Ok(v)
+    }
+}
 
 fn deserialize_slice_json<T: DeserializeOwned>(
     format: &JsonFormat,
+    paths: Option<&FieldPaths>,
     msg: &[u8],
+    additional_fields: Option<&Map<String, Value>>,
 ) -> Result<T, String> {
     let msg = if format.confluent_schema_registry {
         &msg[5..]
@@ -41,17 +124,129 @@ fn deserialize_slice_json<T: DeserializeOwned>(
         //  produce that value. However, without specialization I don't know how to get the compiler to emit
         //  the optimized code for that case.
         Ok(serde_json::from_value(j).unwrap())
+    } else if format.include_schema || paths.is_some() || additional_fields.is_some() {
+        let mut v: Value = serde_json::from_slice(msg)
+            .map_err(|e| format!("Failed to deserialize json: {:?}", e))?;
+
+        if format.include_schema {
+            // messages written by Kafka Connect's JSON converter with schemas enabled (as
+            // Debezium is commonly configured) wrap the record in an envelope alongside its schema
+            v = v.get_mut("payload").map(Value::take).ok_or_else(|| {
+                "`include_schema` set to true, but record does not have a payload field".to_string()
+            })?;
+        }
+
+        if let Some(paths) = paths {
+            v = paths.apply(v)?;
+        }
+
+        record_from_value(v, additional_fields)
     } else {
         serde_json::from_slice(msg)
             .map_err(|e| format!("Failed to deserialize JSON into schema: {:?}", e))
     }
 }
 
-fn deserialize_raw_string<T: DeserializeOwned>(msg: &[u8]) -> Result<T, String> {
-    let json = json! {
-        { "value": String::from_utf8_lossy(msg) }
+/// Deserializes a record that has been decoded into JSON, adding `additional_fields` to it
+fn record_from_value<T: DeserializeOwned>(
+    mut v: Value,
+    additional_fields: Option<&Map<String, Value>>,
+) -> Result<T, String> {
+    if let Some(additional_fields) = additional_fields {
+        let Value::Object(fields) = &mut v else {
+            return Err("Expected a JSON object".to_string());
+        };
+        fields.extend(
+            additional_fields
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+    }
+
+    serde_json::from_value(v)
+        .map_err(|e| format!("Failed to deserialize JSON into schema: {:?}", e))
+}
+
+/// Decodes a message framed with the id of the schema it was written with, which must already
+/// have been fetched from the schema registry
+fn deserialize_registry<T: DeserializeOwned>(
+    registry: Option<&SchemaRegistry>,
+    msg: &[u8],
+    additional_fields: Option<&Map<String, Value>>,
+) -> Result<T, String> {
+    let registry = registry
+        .ok_or_else(|| "a schema registry must be configured to read this format".to_string())?;
+    let (id, payload) = parse_framing(msg)?;
+    let schema = registry.cached_schema(id).ok_or_else(|| {
+        format!(
+            "schema {} has not been fetched from the schema registry",
+            id
+        )
+    })?;
+
+    let v = match &*schema {
+        RegistrySchema::Avro(schema) => {
+            let mut reader = payload;
+            let v = apache_avro::from_avro_datum(schema, &mut reader, None)
+                .map_err(|e| format!("Failed to decode Avro message: {}", e))?;
+            avro::avro_to_json(v, schema)
+                .map_err(|e| format!("Failed to convert Avro message: {}", e))?
+        }
+        RegistrySchema::Protobuf(file) => {
+            let (indexes, payload) = parse_message_indexes(payload)?;
+            let descriptor = message_descriptor(file, &indexes).ok_or_else(|| {
+                format!("schema {} has no message type at index {:?}", id, indexes)
+            })?;
+            let message = DynamicMessage::decode(descriptor, payload)
+                .map_err(|e| format!("Failed to decode Protobuf message: {}", e))?;
+            to_json(&message).map_err(|e| format!("Failed to convert Protobuf message: {}", e))?
+        }
+        RegistrySchema::Json => serde_json::from_slice(payload)
+            .map_err(|e| format!("Failed to deserialize json: {:?}", e))?,
     };
-    Ok(serde_json::from_value(json).unwrap())
+
+    record_from_value(v, additional_fields)
+}
+
+/// Decodes an Avro datum that isn't framed with a schema id, using the table's schema
+fn deserialize_avro<T: DeserializeOwned>(
+    schema: &AvroSchema,
+    msg: &[u8],
+    additional_fields: Option<&Map<String, Value>>,
+) -> Result<T, String> {
+    let mut reader = msg;
+    let v = apache_avro::from_avro_datum(schema, &mut reader, None)
+        .map_err(|e| format!("Failed to decode Avro message: {}", e))?;
+    let v = avro::avro_to_json(v, schema)
+        .map_err(|e| format!("Failed to convert Avro message: {}", e))?;
+
+    record_from_value(v, additional_fields)
+}
+
+/// Raw messages are surfaced as the value of the table's single column, which is TEXT for
+/// raw_string and BYTEA for raw_bytes
+fn deserialize_raw<T: DeserializeOwned>(
+    format: &Format,
+    schema: &Schema,
+    msg: &[u8],
+    additional_fields: Option<&Map<String, Value>>,
+) -> Result<T, String> {
+    let value = match format {
+        Format::RawBytes(_) => Value::from(msg.to_vec()),
+        _ => Value::String(String::from_utf8_lossy(msg).into_owned()),
+    };
+
+    let column = schema
+        .fields()
+        .iter()
+        .map(|f| f.name())
+        .find(|name| !additional_fields.map_or(false, |a| a.contains_key(*name)))
+        .map_or("value", |name| name.as_str());
+
+    let mut record = Map::new();
+    record.insert(column.to_string(), value);
+
+    record_from_value(Value::Object(record), additional_fields)
 }
 
 pub struct FramingIterator<'a> {
@@ -81,7 +276,11 @@ impl<'a> Iterator for FramingIterator<'a> {
         match &self.framing {
             Some(framing) => {
                 match &framing.method {
-                    FramingMethod::Newline(newline) => {
+                    FramingMethod::Newline(newline) => loop {
+                        if self.offset >= self.buf.len() {
+                            return None;
+                        }
+
                         let end = memchr::memchr('\n' as u8, &self.buf[self.offset..])
                             .map(|i| self.offset + i)
                             .unwrap_or(self.buf.len());
@@ -89,11 +288,48 @@ impl<'a> Iterator for FramingIterator<'a> {
                         let prev = self.offset;
                         self.offset = end + 1;
 
+                        // allow CRLF line endings, and skip blank lines (like the trailing
+                        // newline of an NDJSON batch)
+                        let mut end = end;
+                        if end > prev && self.buf[end - 1] == b'\r' {
+                            end -= 1;
+                        }
+                        if end == prev {
+                            continue;
+                        }
+
                         // enforce max len if set
                         let length =
                             (end - prev).min(newline.max_line_length.unwrap_or(u64::MAX) as usize);
 
-                        Some(&self.buf[prev..(prev + length)])
+                        break Some(&self.buf[prev..(prev + length)]);
+                    },
+                    FramingMethod::LengthPrefixed(prefixed) => {
+                        let start = self.offset + prefixed.length_bytes as usize;
+                        let Some(prefix) = self.buf.get(self.offset..start) else {
+                            // a partial prefix can't be framed, so we pass it on as is, where it
+                            // will fail to deserialize
+                            let rest = &self.buf[self.offset..];
+                            self.offset = self.buf.len();
+                            return Some(rest);
+                        };
+
+                        let mut bytes = [0u8; 8];
+                        let length = if prefixed.little_endian {
+                            bytes[..prefix.len()].copy_from_slice(prefix);
+                            u64::from_le_bytes(bytes)
+                        } else {
+                            bytes[8 - prefix.len()..].copy_from_slice(prefix);
+                            u64::from_be_bytes(bytes)
+                        };
+
+                        // likewise a truncated frame is returned with whatever data we have
+                        let end = start
+                            .saturating_add(length.min(usize::MAX as u64) as usize)
+                            .min(self.buf.len());
+                        self.offset = end;
+
+                        Some(&self.buf[start..end])
                     }
                 }
             }
@@ -108,32 +344,210 @@ impl<'a> Iterator for FramingIterator<'a> {
 pub struct DataDeserializer<T: SchemaData> {
     format: Arc<Format>,
     framing: Option<Arc<Framing>>,
+    schema_registry: Option<Arc<SchemaRegistry>>,
+    avro_schema: Option<Arc<AvroSchema>>,
+    field_paths: Option<Arc<FieldPaths>>,
+    schema: Arc<Schema>,
+    compression: Option<Compression>,
+    bad_data: BadDataHandler,
+    rate_limiter: Option<Arc<SourceRateLimiter>>,
     _t: PhantomData<T>,
 }
 
 impl<T: SchemaData> DataDeserializer<T> {
     pub fn new(format: Format, framing: Option<Framing>) -> Self {
         Self {
+            avro_schema: local_avro_schema::<T>(&format).map(Arc::new),
+            field_paths: FieldPaths::new(&format).map(Arc::new),
+            schema: Arc::new(T::schema()),
             format: Arc::new(format),
             framing: framing.map(|f| Arc::new(f)),
+            schema_registry: None,
+            compression: None,
+            bad_data: BadDataHandler::new(None),
+            rate_limiter: None,
             _t: PhantomData,
         }
     }
+
+    pub fn with_schema_registry(mut self, schema_registry: SchemaRegistry) -> Self {
+        self.schema_registry = Some(Arc::new(schema_registry));
+        self
+    }
+
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_bad_data(mut self, bad_data: Option<BadData>) -> Self {
+        self.bad_data = BadDataHandler::new(bad_data);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limiter = rate_limit.map(|l| Arc::new(SourceRateLimiter::new(l)));
+        self
+    }
+
+    /// Waits until the source's rate limit allows it to read `msg`, if it has one. Sources call
+    /// this for each message they read, before deserializing it.
+    pub async fn throttle(&self, msg: &[u8]) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(msg).await;
+        }
+    }
+
+    /// Applies the bad data policy to a message that failed to deserialize. Fails with the error
+    /// if the policy is to fail, and returns it if there's no policy and the source should
+    /// handle it itself; otherwise the message should be skipped.
+    pub fn handle_bad_data(
+        &self,
+        task_info: &TaskInfo,
+        msg: &[u8],
+        error: UserError,
+    ) -> Result<Option<UserError>, UserError> {
+        self.bad_data.handle(task_info, msg, error)
+    }
+
+    /// For formats whose messages are read with the schema they were written with, fetches that
+    /// schema from the schema registry if we haven't seen it before. This must be called before
+    /// the message is deserialized.
+    pub async fn resolve_schema(&self, msg: &[u8]) -> Result<(), UserError> {
+        if !matches!(*self.format, Format::Avro(_) | Format::Protobuf(_)) {
+            return Ok(());
+        }
+
+        // messages that can't be resolved fail on deserialization, with the message
+        let (Some(registry), Ok((id, _))) = (&self.schema_registry, parse_framing(msg)) else {
+            return Ok(());
+        };
+
+        registry
+            .get_schema_by_id(id)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                UserError::new(
+                    "Failed to fetch schema from schema registry",
+                    format!("{:?}", e),
+                )
+            })
+    }
+
     pub fn deserialize_slice<'a>(
         &self,
         msg: &'a [u8],
+    ) -> impl Iterator<Item = Result<T, UserError>> + 'a {
+        self.deserialize_slice_with_fields(msg, None)
+    }
+
+    /// Deserializes a message, adding `additional_fields` to each record; this is used for
+    /// fields that come from the source system rather than the payload, like Kafka headers
+    pub fn deserialize_slice_with_fields<'a>(
+        &self,
+        msg: &'a [u8],
+        additional_fields: Option<&'a Map<String, Value>>,
+    ) -> impl Iterator<Item = Result<T, UserError>> + 'a {
+        let Some(compression) = self.compression else {
+            return Box::new(self.deserialize_frames(msg, additional_fields))
+                as Box<dyn Iterator<Item = Result<T, UserError>> + 'a>;
+        };
+
+        // the decompressed message only lives as long as this call, so its records are
+        // deserialized eagerly
+        let results: Vec<_> = match compression::decompress(compression, msg) {
+            Ok(buf) => self.deserialize_frames(&buf, additional_fields).collect(),
+            Err(e) => vec![Err(e)],
+        };
+        Box::new(results.into_iter())
+    }
+
+    fn deserialize_frames<'a>(
+        &self,
+        msg: &'a [u8],
+        additional_fields: Option<&'a Map<String, Value>>,
     ) -> impl Iterator<Item = Result<T, UserError>> + 'a {
         let format = self.format.clone();
-        FramingIterator::new(self.framing.clone(), msg)
-            .map(move |t| Self::deserialize_single(format.clone(), t))
+        let registry = self.schema_registry.clone();
+        let avro_schema = self.avro_schema.clone();
+        let field_paths = self.field_paths.clone();
+        let schema = self.schema.clone();
+        FramingIterator::new(self.framing.clone(), msg).flat_map(move |t| match &*format {
+            // a CSV message may contain any number of rows
+            Format::Csv(csv) => Self::deserialize_csv(csv, &schema, t, additional_fields),
+            _ => vec![Self::deserialize_single(
+                &format,
+                &schema,
+                registry.as_deref(),
+                avro_schema.as_deref(),
+                field_paths.as_deref(),
+                t,
+                additional_fields,
+            )],
+        })
     }
 
-    fn deserialize_single(format: Arc<Format>, msg: &[u8]) -> Result<T, UserError> {
-        match &*format {
-            Format::Json(json) => deserialize_slice_json(json, msg),
-            Format::Avro(_) => todo!(),
-            Format::Parquet(_) => todo!(),
-            Format::RawString(_) => deserialize_raw_string(msg),
+    fn deserialize_csv(
+        format: &CsvFormat,
+        schema: &Schema,
+        msg: &[u8],
+        additional_fields: Option<&Map<String, Value>>,
+    ) -> Vec<Result<T, UserError>> {
+        let error = |e: String| {
+            UserError::new(
+                "Deserialization failed",
+                format!(
+                    "Failed to deserialize: '{}': {}",
+                    String::from_utf8_lossy(msg),
+                    e
+                ),
+            )
+        };
+
+        let mut reader = csv::reader(format, msg);
+        let headers = if format.header {
+            match reader.headers() {
+                Ok(headers) => Some(headers.clone()),
+                Err(e) => return vec![Err(error(e.to_string()))],
+            }
+        } else {
+            None
+        };
+
+        reader
+            .records()
+            .map(|record| {
+                let record = record.map_err(|e| error(e.to_string()))?;
+                let v = csv::record_to_json(format, schema, headers.as_ref(), &record)
+                    .map_err(error)?;
+                record_from_value(v, additional_fields).map_err(error)
+            })
+            .collect()
+    }
+
+    fn deserialize_single(
+        format: &Format,
+        schema: &Schema,
+        registry: Option<&SchemaRegistry>,
+        avro_schema: Option<&AvroSchema>,
+        field_paths: Option<&FieldPaths>,
+        msg: &[u8],
+        additional_fields: Option<&Map<String, Value>>,
+    ) -> Result<T, UserError> {
+        match (format, avro_schema) {
+            (Format::Json(json), _) => {
+                deserialize_slice_json(json, field_paths, msg, additional_fields)
+            }
+            (Format::Avro(_), Some(schema)) => deserialize_avro(schema, msg, additional_fields),
+            (Format::Avro(_) | Format::Protobuf(_), _) => {
+                deserialize_registry(registry, msg, additional_fields)
+            }
+            (Format::Parquet(_), _) => todo!(),
+            (Format::RawString(_) | Format::RawBytes(_), _) => {
+                deserialize_raw(format, schema, msg, additional_fields)
+            }
+            (Format::Csv(_), _) => unreachable!("CSV messages are deserialized row by row"),
         }
         .map_err(|e| {
             UserError::new(
@@ -148,25 +562,117 @@ impl<T: SchemaData> DataDeserializer<T> {
     }
 }
 
+/// Avro messages that aren't framed with a schema registry id are read and written with a schema
+/// that's known up front
+fn local_avro_schema<T: SchemaData>(format: &Format) -> Option<AvroSchema> {
+    match format {
+        Format::Avro(avro) if !avro.confluent_schema_registry => {
+            Some(avro::local_schema(avro, T::name(), T::schema().fields()))
+        }
+        _ => None,
+    }
+}
+
 pub struct DataSerializer<T: SchemaData> {
+    schema: Schema,
     kafka_schema: Value,
-    #[allow(unused)]
     json_schema: Value,
     format: Format,
+    // the id of the schema we write with, and the schema itself, for formats that use a schema
+    // registry
+    registry_schema: Option<(u32, Arc<RegistrySchema>)>,
+    avro_schema: Option<AvroSchema>,
+    compression: Option<Compression>,
     _t: PhantomData<T>,
 }
 
 impl<T: SchemaData> DataSerializer<T> {
     pub fn new(format: Format) -> Self {
         Self {
+            schema: T::schema(),
+            avro_schema: local_avro_schema::<T>(&format),
             kafka_schema: arrow_to_kafka_json(T::name(), T::schema().fields()),
             json_schema: arrow_to_json_schema(T::schema().fields()),
             format,
+            registry_schema: None,
+            compression: None,
             _t: PhantomData,
         }
     }
 
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// For formats that use a schema registry, looks up the latest schema for the subject to
+    /// write with. If the subject doesn't exist, a schema generated from our schema is
+    /// registered for it.
+    pub async fn init_schema_registry(
+        &mut self,
+        registry: &SchemaRegistry,
+        subject: &str,
+    ) -> anyhow::Result<()> {
+        if !self.format.uses_schema_registry() {
+            return Ok(());
+        }
+
+        let (id, schema) = match registry.get_latest_schema(subject).await? {
+            Some(schema) => schema,
+            None => {
+                let (schema_type, schema) = match &self.format {
+                    Format::Json(_) => ("JSON", self.json_schema.to_string()),
+                    Format::Avro(_) => (
+                        "AVRO",
+                        avro::arrow_to_avro_schema(T::name(), T::schema().fields()).to_string(),
+                    ),
+                    _ => bail!(
+                        "subject '{}' does not exist in the schema registry; Protobuf schemas must \
+                        be registered before they can be written",
+                        subject
+                    ),
+                };
+
+                let id = registry
+                    .register_schema(subject, schema_type, &schema)
+                    .await?;
+                info!("registered schema {} for subject '{}'", id, subject);
+                (id, registry.get_schema_by_id(id).await?)
+            }
+        };
+
+        match (&self.format, &*schema) {
+            (Format::Json(_), RegistrySchema::Json)
+            | (Format::Avro(_), RegistrySchema::Avro(_))
+            | (Format::Protobuf(_), RegistrySchema::Protobuf(_)) => {}
+            _ => bail!(
+                "the latest schema for subject '{}' does not match the format of the table",
+                subject
+            ),
+        }
+
+        self.registry_schema = Some((id, schema));
+        Ok(())
+    }
+
+    fn registry_schema(&self) -> (u32, &RegistrySchema) {
+        let (id, schema) = self
+            .registry_schema
+            .as_ref()
+            .expect("schema registry has not been initialized for this format");
+        (*id, schema)
+    }
+
     pub fn to_vec(&self, record: &T) -> Option<Vec<u8>> {
+        let v = self.serialize(record)?;
+
+        Some(match self.compression {
+            Some(compression) => compression::compress(compression, &v),
+            None => v,
+        })
+    }
+
+    fn serialize(&self, record: &T) -> Option<Vec<u8>> {
         match &self.format {
             Format::Json(json) => {
                 let v = if json.include_schema {
@@ -181,16 +687,60 @@ impl<T: SchemaData> DataSerializer<T> {
                 };
 
                 if json.confluent_schema_registry {
-                    todo!("Serializing to confluent schema registry is not yet supported");
+                    return Some(frame(self.registry_schema().0, &v));
                 }
 
                 Some(v)
             }
-            Format::Avro(_) => todo!(),
+            Format::Avro(_) => {
+                if let Some(schema) = &self.avro_schema {
+                    return Some(Self::to_avro(schema, record));
+                }
+
+                let (id, RegistrySchema::Avro(schema)) = self.registry_schema() else {
+                    unreachable!("checked when the schema registry was initialized");
+                };
+
+                Some(frame(id, &Self::to_avro(schema, record)))
+            }
+            Format::Protobuf(_) => {
+                let (id, RegistrySchema::Protobuf(file)) = self.registry_schema() else {
+                    unreachable!("checked when the schema registry was initialized");
+                };
+
+                // records are written as the first message type in the schema
+                let descriptor = file
+                    .messages()
+                    .next()
+                    .unwrap_or_else(|| panic!("schema {} does not define any messages", id));
+                let message = from_json(descriptor, &serde_json::to_value(record).unwrap())
+                    .unwrap_or_else(|e| {
+                        panic!("record does not match Protobuf schema {}: {}", id, e)
+                    });
+
+                // a message index of 0 refers to the first message type
+                let mut payload = vec![0];
+                message.encode(&mut payload).unwrap();
+
+                Some(frame(id, &payload))
+            }
             Format::Parquet(_) => todo!(),
             Format::RawString(_) => record.to_raw_string(),
+            Format::RawBytes(_) => record.to_raw_bytes(),
+            Format::Csv(csv) => Some(csv::json_to_record(
+                csv,
+                &self.schema,
+                &serde_json::to_value(record).unwrap(),
+            )),
         }
     }
+
+    fn to_avro(schema: &AvroSchema, record: &T) -> Vec<u8> {
+        let value = avro::json_to_avro(&serde_json::to_value(record).unwrap(), schema)
+            .unwrap_or_else(|e| panic!("record does not match Avro schema: {}", e));
+        apache_avro::to_avro_datum(schema, value)
+            .unwrap_or_else(|e| panic!("failed to encode Avro record: {}", e))
+    }
 }
 
 #[derive(Debug)]
@@ -410,13 +960,29 @@ fn field_to_json_schema(field: &Field) -> Value {
         arrow::datatypes::DataType::Struct(s) => arrow_to_json_schema(s),
         arrow::datatypes::DataType::Union(_, _) => todo!(),
         arrow::datatypes::DataType::Dictionary(_, _) => todo!(),
-        arrow::datatypes::DataType::Decimal128(_, _) => todo!(),
+        arrow::datatypes::DataType::Decimal128(_, _) => {
+            // decimals are written as strings so that they keep their exact value, and can be
+            // read from either strings or numbers
+            json! {{ "type": ["string", "number"] }}
+        }
         arrow::datatypes::DataType::Decimal256(_, _) => todo!(),
-        arrow::datatypes::DataType::Map(_, _) => todo!(),
+        arrow::datatypes::DataType::Map(entries, _) => {
+            // maps are serialized as objects, whatever the type of their keys
+            let (_, value) = map_key_value(entries);
+            json! {{"type": "object", "additionalProperties": field_to_json_schema(value) }}
+        }
         arrow::datatypes::DataType::RunEndEncoded(_, _) => todo!(),
     }
 }
 
+/// The key and value fields of the entries of a map
+pub(crate) fn map_key_value(entries: &Field) -> (&Field, &Field) {
+    let arrow::datatypes::DataType::Struct(fields) = entries.data_type() else {
+        unreachable!("map entries are always structs");
+    };
+    (fields[0].as_ref(), fields[1].as_ref())
+}
+
 fn arrow_to_json_schema(fields: &Fields) -> Value {
     let props: HashMap<String, Value> = fields
         .iter()
@@ -488,9 +1054,18 @@ fn field_to_kafka_json(field: &Field) -> Value {
         }
         Union(_, _) => todo!(),
         Dictionary(_, _) => todo!(),
-        Decimal128(_, _) => todo!(),
+        Decimal128(_, _) => "string",
         Decimal256(_, _) => todo!(),
-        Map(_, _) => todo!(),
+        Map(entries, _) => {
+            let (key, value) = map_key_value(entries);
+            return json! {{
+                "type": "map",
+                "keys": field_to_kafka_json(key),
+                "values": field_to_kafka_json(value),
+                "field": field.name().clone(),
+                "optional": field.is_nullable(),
+            }};
+        }
         RunEndEncoded(_, _) => todo!(),
     };
 
@@ -515,10 +1090,153 @@ fn arrow_to_kafka_json(name: &str, fields: &Fields) -> Value {
 
 #[cfg(test)]
 mod tests {
-    use crate::formats::FramingIterator;
-    use arroyo_rpc::formats::{Framing, FramingMethod, NewlineDelimitedFraming};
+    use crate::formats::{deserialize_raw, deserialize_slice_json, FieldPaths, FramingIterator};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arroyo_rpc::formats::{
+        Format, Framing, FramingMethod, JsonFormat, LengthPrefixedFraming, NewlineDelimitedFraming,
+        RawBytesFormat, RawStringFormat,
+    };
+    use serde::Deserialize;
+    use serde_json::{json, Map};
     use std::sync::Arc;
 
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct WithMetadata {
+        value: i64,
+        key: Option<String>,
+        partition: i32,
+    }
+
+    #[test]
+    fn test_additional_fields() {
+        let mut fields = Map::new();
+        fields.insert("key".to_string(), json!("k1"));
+        fields.insert("partition".to_string(), json!(3));
+
+        let result: WithMetadata = deserialize_slice_json(
+            &JsonFormat::default(),
+            None,
+            r#"{"value": 5}"#.as_bytes(),
+            Some(&fields),
+        )
+        .unwrap();
+
+        assert_eq!(
+            WithMetadata {
+                value: 5,
+                key: Some("k1".to_string()),
+                partition: 3,
+            },
+            result
+        );
+
+        // metadata takes precedence over fields of the same name in the payload
+        fields.insert("key".to_string(), json!(null));
+        let result: WithMetadata = deserialize_slice_json(
+            &JsonFormat::default(),
+            None,
+            r#"{"value": 5, "key": "k2", "partition": 1}"#.as_bytes(),
+            Some(&fields),
+        )
+        .unwrap();
+
+        assert_eq!(None, result.key);
+        assert_eq!(3, result.partition);
+
+        assert!(deserialize_slice_json::<WithMetadata>(
+            &JsonFormat::default(),
+            None,
+            "[1, 2]".as_bytes(),
+            Some(&fields)
+        )
+        .is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Envelope {
+        before: Option<i64>,
+        after: Option<i64>,
+        op: String,
+    }
+
+    #[test]
+    fn test_include_schema() {
+        let format = JsonFormat {
+            include_schema: true,
+            debezium: true,
+            ..Default::default()
+        };
+
+        let msg = json!({
+            "schema": {"type": "struct", "fields": []},
+            "payload": {"before": null, "after": 5, "op": "c"}
+        });
+
+        let result: Envelope =
+            deserialize_slice_json(&format, None, msg.to_string().as_bytes(), None).unwrap();
+        assert_eq!(
+            Envelope {
+                before: None,
+                after: Some(5),
+                op: "c".to_string()
+            },
+            result
+        );
+
+        assert!(deserialize_slice_json::<Envelope>(
+            &format,
+            None,
+            r#"{"before": null, "after": 5, "op": "c"}"#.as_bytes(),
+            None
+        )
+        .is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Flattened {
+        id: i64,
+        user_name: Option<String>,
+        first_tag: Option<String>,
+    }
+
+    #[test]
+    fn test_field_paths() {
+        let format = JsonFormat {
+            field_paths: [
+                ("user_name".to_string(), "/user/user name".to_string()),
+                ("first_tag".to_string(), "$.tags[0].name".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let paths = FieldPaths::new(&Format::Json(format.clone()));
+
+        let result: Flattened = deserialize_slice_json(
+            &format,
+            paths.as_ref(),
+            r#"{"id": 1, "user": {"user name": "bob"}, "tags": [{"name": "a"}, {"name": "b"}]}"#
+                .as_bytes(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            Flattened {
+                id: 1,
+                user_name: Some("bob".to_string()),
+                first_tag: Some("a".to_string()),
+            },
+            result
+        );
+
+        // missing paths produce nulls
+        let result: Flattened =
+            deserialize_slice_json(&format, paths.as_ref(), r#"{"id": 2}"#.as_bytes(), None)
+                .unwrap();
+        assert_eq!(None, result.user_name);
+        assert_eq!(None, result.first_tag);
+    }
+
     #[test]
     fn test_line_framing() {
         let framing = Some(Arc::new(Framing {
@@ -588,4 +1306,109 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_ndjson_framing() {
+        let framing = Some(Arc::new(Framing {
+            method: FramingMethod::Newline(NewlineDelimitedFraming {
+                max_line_length: None,
+            }),
+        }));
+
+        let result: Vec<_> =
+            FramingIterator::new(framing, "{\"a\": 1}\r\n\n{\"a\": 2}\r\n".as_bytes())
+                .map(|t| String::from_utf8(t.to_vec()).unwrap())
+                .collect();
+
+        assert_eq!(
+            vec!["{\"a\": 1}".to_string(), "{\"a\": 2}".to_string()],
+            result
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_framing() {
+        let framing = Some(Arc::new(Framing {
+            method: FramingMethod::LengthPrefixed(LengthPrefixedFraming::default()),
+        }));
+
+        let mut buf = vec![];
+        for frame in ["one", "", "three"] {
+            buf.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+            buf.extend_from_slice(frame.as_bytes());
+        }
+
+        let result: Vec<_> = FramingIterator::new(framing.clone(), &buf)
+            .map(|t| String::from_utf8(t.to_vec()).unwrap())
+            .collect();
+        assert_eq!(vec!["one", "", "three"], result);
+
+        // a truncated frame is returned as is
+        let result: Vec<_> = FramingIterator::new(framing, &buf[..buf.len() - 2])
+            .map(|t| String::from_utf8(t.to_vec()).unwrap())
+            .collect();
+        assert_eq!(vec!["one", "", "thr"], result);
+
+        let framing = Some(Arc::new(Framing {
+            method: FramingMethod::LengthPrefixed(LengthPrefixedFraming {
+                length_bytes: 2,
+                little_endian: true,
+            }),
+        }));
+
+        let result: Vec<_> = FramingIterator::new(framing, &[2, 0, b'h', b'i', 1, 0, b'!'])
+            .map(|t| String::from_utf8(t.to_vec()).unwrap())
+            .collect();
+        assert_eq!(vec!["hi", "!"], result);
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct RawBytes {
+        payload: Vec<u8>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct RawString {
+        line: String,
+        partition: i32,
+    }
+
+    #[test]
+    fn test_raw_formats() {
+        let schema = Schema::new(vec![Field::new("payload", DataType::Binary, false)]);
+        let result: RawBytes = deserialize_raw(
+            &Format::RawBytes(RawBytesFormat {}),
+            &schema,
+            &[0, 159, 146, 150],
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            RawBytes {
+                payload: vec![0, 159, 146, 150]
+            },
+            result
+        );
+
+        let schema = Schema::new(vec![
+            Field::new("line", DataType::Utf8, false),
+            Field::new("partition", DataType::Int32, false),
+        ]);
+        let mut fields = Map::new();
+        fields.insert("partition".to_string(), json!(3));
+        let result: RawString = deserialize_raw(
+            &Format::RawString(RawStringFormat {}),
+            &schema,
+            "hello".as_bytes(),
+            Some(&fields),
+        )
+        .unwrap();
+        assert_eq!(
+            RawString {
+                line: "hello".to_string(),
+                partition: 3,
+            },
+            result
+        );
+    }
 }
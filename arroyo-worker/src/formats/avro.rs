@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use apache_avro::types::Value as AvroValue;
+use apache_avro::{Decimal, Schema as AvroSchema};
+use arrow::datatypes::{Field, Fields};
+use arroyo_rpc::formats::AvroFormat;
+use serde_json::{json, Value};
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+fn field_to_avro(field: &Field) -> Value {
+    use arrow::datatypes::DataType::*;
+
+    let typ = match field.data_type() {
+        Boolean => json!("boolean"),
+        Int8 | Int16 | Int32 | UInt8 | UInt16 => json!("int"),
+        Int64 | UInt32 | UInt64 => json!("long"),
+        Float16 | Float32 => json!("float"),
+        Float64 => json!("double"),
+        Utf8 | LargeUtf8 => json!("string"),
+        Binary | FixedSizeBinary(_) | LargeBinary => json!("bytes"),
+        Timestamp(_, _) => json! {{ "type": "long", "logicalType": "timestamp-millis" }},
+        Decimal128(precision, scale) => json! {{
+            "type": "bytes",
+            "logicalType": "decimal",
+            "precision": precision,
+            "scale": scale,
+        }},
+        List(t) | FixedSizeList(t, _) | LargeList(t) => {
+            json! {{ "type": "array", "items": field_to_avro(t) }}
+        }
+        Struct(s) => arrow_to_avro_schema(field.name(), s),
+        // Avro map keys are always strings
+        Map(entries, _) => {
+            let (_, value) = super::map_key_value(entries);
+            json! {{ "type": "map", "values": field_to_avro(value) }}
+        }
+        t => unimplemented!("cannot generate an Avro schema for type {:?}", t),
+    };
+
+    if field.is_nullable() {
+        json!(["null", typ])
+    } else {
+        typ
+    }
+}
+
+/// Generates an Avro schema for our fields, used when the user hasn't provided one and when
+/// registering a schema with a schema registry
+pub fn arrow_to_avro_schema(name: &str, fields: &Fields) -> Value {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|f| {
+            let mut field = json! {{
+                "name": f.name(),
+                "type": field_to_avro(f),
+            }};
+            if f.is_nullable() {
+                field["default"] = Value::Null;
+            }
+            field
+        })
+        .collect();
+
+    json! {{
+        "type": "record",
+        "name": name,
+        "fields": fields,
+    }}
+}
+
+/// The schema that messages without schema registry framing are read and written with: either
+/// the one configured on the table, or one generated from its fields
+pub fn local_schema(format: &AvroFormat, name: &str, fields: &Fields) -> AvroSchema {
+    match &format.schema {
+        Some(schema) => AvroSchema::parse_str(schema),
+        None => AvroSchema::parse(&arrow_to_avro_schema(name, fields)),
+    }
+    .unwrap_or_else(|e| panic!("invalid Avro schema: {}", e))
+}
+
+fn decimal_to_f64(decimal: &Decimal, scale: usize) -> Result<f64, String> {
+    let bytes = Vec::<u8>::try_from(decimal).map_err(|e| format!("invalid decimal: {}", e))?;
+    if bytes.len() > 16 {
+        return Err("decimals wider than 128 bits are not supported".to_string());
+    }
+
+    // the unscaled value is a big-endian two's-complement integer, so we sign-extend it
+    let fill = if matches!(bytes.first(), Some(b) if b & 0x80 != 0) {
+        0xff
+    } else {
+        0
+    };
+    let mut buf = [fill; 16];
+    buf[16 - bytes.len()..].copy_from_slice(&bytes);
+
+    Ok(i128::from_be_bytes(buf) as f64 / 10f64.powi(scale as i32))
+}
+
+/// Encodes a JSON string or number as the unscaled bytes of an Avro decimal. Strings are
+/// converted exactly, so that DECIMAL columns (which are serialized as strings) don't lose
+/// precision.
+fn json_to_decimal(value: &Value, scale: usize, size: Option<usize>) -> Result<Vec<u8>, String> {
+    let decimal: arroyo_types::Decimal = match value {
+        Value::String(s) => s.parse()?,
+        Value::Number(n) => n.to_string().parse()?,
+        _ => return Err(format!("expected a decimal, found {}", value)),
+    };
+    let unscaled = decimal
+        .rescale(scale as i8)
+        .ok_or_else(|| format!("{} does not fit in a decimal with scale {}", decimal, scale))?
+        .value();
+    let bytes = unscaled.to_be_bytes();
+
+    // use the minimal two's-complement representation, unless the fixed size requires padding
+    let min_len = bytes
+        .iter()
+        .zip(bytes.iter().skip(1))
+        .position(|(b, next)| !((*b == 0 && next & 0x80 == 0) || (*b == 0xff && next & 0x80 != 0)))
+        .map(|i| 16 - i)
+        .unwrap_or(1);
+
+    let len = match size {
+        Some(size) if size < min_len => {
+            return Err(format!(
+                "{} does not fit in a decimal of {} bytes",
+                decimal, size
+            ))
+        }
+        Some(size) => size,
+        None => min_len,
+    };
+
+    let fill = if unscaled < 0 { 0xff } else { 0 };
+    let mut out = vec![fill; len.saturating_sub(16)];
+    out.extend_from_slice(&bytes[16 - len.min(16)..]);
+    Ok(out)
+}
+
+/// Converts a decoded Avro value into JSON that can be deserialized into our records. Logical
+/// types are mapped onto the representations our types expect: timestamps and dates become
+/// milliseconds since the epoch and decimals become floats.
+pub fn avro_to_json(value: AvroValue, schema: &AvroSchema) -> Result<Value, String> {
+    Ok(match (value, schema) {
+        (AvroValue::Union(i, v), AvroSchema::Union(union)) => {
+            let variant = union
+                .variants()
+                .get(i as usize)
+                .ok_or_else(|| format!("invalid union index {}", i))?;
+            avro_to_json(*v, variant)?
+        }
+        (AvroValue::Union(_, v), schema) => avro_to_json(*v, schema)?,
+        (AvroValue::Record(values), AvroSchema::Record { fields, .. }) => {
+            let mut map = serde_json::Map::new();
+            for ((name, v), field) in values.into_iter().zip(fields.iter()) {
+                map.insert(name, avro_to_json(v, &field.schema)?);
+            }
+            Value::Object(map)
+        }
+        (AvroValue::Array(values), AvroSchema::Array(items)) => Value::Array(
+            values
+                .into_iter()
+                .map(|v| avro_to_json(v, items))
+                .collect::<Result<_, _>>()?,
+        ),
+        (AvroValue::Map(values), AvroSchema::Map(items)) => Value::Object(
+            values
+                .into_iter()
+                .map(|(k, v)| Ok((k, avro_to_json(v, items)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        (AvroValue::Decimal(d), AvroSchema::Decimal { scale, .. }) => {
+            json!(decimal_to_f64(&d, *scale)?)
+        }
+        (AvroValue::TimestampMillis(t) | AvroValue::LocalTimestampMillis(t), _) => json!(t),
+        (AvroValue::TimestampMicros(t) | AvroValue::LocalTimestampMicros(t), _) => {
+            json!(t / 1000)
+        }
+        (AvroValue::Date(days), _) => json!(days as i64 * MILLIS_PER_DAY),
+        (AvroValue::TimeMillis(t), _) => json!(t),
+        (AvroValue::TimeMicros(t), _) => json!(t / 1000),
+        (AvroValue::Enum(_, symbol), _) => Value::String(symbol),
+        (AvroValue::Uuid(u), _) => Value::String(u.to_string()),
+        (v, _) => Value::try_from(v).map_err(|e| e.to_string())?,
+    })
+}
+
+/// Converts one of our records, serialized as JSON, into an Avro value matching `schema`
+pub fn json_to_avro(value: &Value, schema: &AvroSchema) -> Result<AvroValue, String> {
+    let mismatch = || format!("{} does not match Avro type {:?}", value, schema);
+    let as_i64 = || value.as_i64().ok_or_else(mismatch);
+
+    Ok(match schema {
+        AvroSchema::Union(union) => {
+            for (i, variant) in union.variants().iter().enumerate() {
+                let matches = match (variant, value) {
+                    (AvroSchema::Null, Value::Null) => true,
+                    (AvroSchema::Null, _) | (_, Value::Null) => false,
+                    _ => true,
+                };
+
+                if matches {
+                    if let Ok(v) = json_to_avro(value, variant) {
+                        return Ok(AvroValue::Union(i as u32, Box::new(v)));
+                    }
+                }
+            }
+            return Err(mismatch());
+        }
+        AvroSchema::Null => match value {
+            Value::Null => AvroValue::Null,
+            _ => return Err(mismatch()),
+        },
+        AvroSchema::Boolean => AvroValue::Boolean(value.as_bool().ok_or_else(mismatch)?),
+        AvroSchema::Int => AvroValue::Int(as_i64()? as i32),
+        AvroSchema::Long => AvroValue::Long(as_i64()?),
+        AvroSchema::Float => AvroValue::Float(value.as_f64().ok_or_else(mismatch)? as f32),
+        AvroSchema::Double => AvroValue::Double(value.as_f64().ok_or_else(mismatch)?),
+        AvroSchema::String => AvroValue::String(match value {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }),
+        AvroSchema::Bytes | AvroSchema::Fixed { .. } => {
+            let bytes = match value {
+                Value::String(s) => s.as_bytes().to_vec(),
+                Value::Array(a) => a
+                    .iter()
+                    .map(|b| b.as_u64().map(|b| b as u8))
+                    .collect::<Option<_>>()
+                    .ok_or_else(mismatch)?,
+                _ => return Err(mismatch()),
+            };
+            match schema {
+                AvroSchema::Fixed { size, .. } => AvroValue::Fixed(*size, bytes),
+                _ => AvroValue::Bytes(bytes),
+            }
+        }
+        AvroSchema::Enum { symbols, .. } => {
+            let symbol = value.as_str().ok_or_else(mismatch)?;
+            let i = symbols
+                .iter()
+                .position(|s| s == symbol)
+                .ok_or_else(mismatch)?;
+            AvroValue::Enum(i as u32, symbol.to_string())
+        }
+        AvroSchema::TimestampMillis => AvroValue::TimestampMillis(as_i64()?),
+        AvroSchema::TimestampMicros => AvroValue::TimestampMicros(as_i64()? * 1000),
+        AvroSchema::LocalTimestampMillis => AvroValue::LocalTimestampMillis(as_i64()?),
+        AvroSchema::LocalTimestampMicros => AvroValue::LocalTimestampMicros(as_i64()? * 1000),
+        AvroSchema::Date => AvroValue::Date((as_i64()?.div_euclid(MILLIS_PER_DAY)) as i32),
+        AvroSchema::TimeMillis => AvroValue::TimeMillis(as_i64()? as i32),
+        AvroSchema::TimeMicros => AvroValue::TimeMicros(as_i64()? * 1000),
+        AvroSchema::Decimal { scale, inner, .. } => {
+            let size = match &**inner {
+                AvroSchema::Fixed { size, .. } => Some(*size),
+                _ => None,
+            };
+            AvroValue::Decimal(Decimal::from(json_to_decimal(value, *scale, size)?))
+        }
+        AvroSchema::Array(items) => AvroValue::Array(
+            value
+                .as_array()
+                .ok_or_else(mismatch)?
+                .iter()
+                .map(|v| json_to_avro(v, items))
+                .collect::<Result<_, _>>()?,
+        ),
+        AvroSchema::Map(items) => AvroValue::Map(
+            value
+                .as_object()
+                .ok_or_else(mismatch)?
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), json_to_avro(v, items)?)))
+                .collect::<Result<HashMap<_, _>, String>>()?,
+        ),
+        AvroSchema::Record { fields, .. } => {
+            let record = value.as_object().ok_or_else(mismatch)?;
+            AvroValue::Record(
+                fields
+                    .iter()
+                    .map(|f| {
+                        let v = record.get(&f.name).unwrap_or(&Value::Null);
+                        json_to_avro(v, &f.schema)
+                            .map(|v| (f.name.clone(), v))
+                            .map_err(|e| format!("field '{}': {}", f.name, e))
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        _ => AvroValue::from(value.clone())
+            .resolve(schema)
+            .map_err(|e| e.to_string())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::Schema as AvroSchema;
+    use serde_json::json;
+
+    use super::{avro_to_json, json_to_avro};
+
+    #[test]
+    fn test_logical_types_round_trip() {
+        let schema = AvroSchema::parse_str(
+            r#"{
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "price", "type": {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 2}},
+                    {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-micros"}},
+                    {"name": "day", "type": {"type": "int", "logicalType": "date"}},
+                    {"name": "note", "type": ["null", "string"], "default": null}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let record = json!({
+            "price": -123.45,
+            "ts": 1_700_000_000_123i64,
+            "day": 19_675i64 * 86_400_000,
+            "note": null,
+        });
+
+        let avro = json_to_avro(&record, &schema).unwrap();
+        let bytes = apache_avro::to_avro_datum(&schema, avro).unwrap();
+        let decoded = apache_avro::from_avro_datum(&schema, &mut &bytes[..], None).unwrap();
+
+        assert_eq!(record, avro_to_json(decoded, &schema).unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+use arroyo_rpc::formats::BadData;
+use arroyo_types::{TaskInfo, UserError};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{BaseRecord, DefaultProducerContext, ThreadedProducer};
+use rdkafka::ClientConfig;
+use tracing::warn;
+
+use crate::metrics::TaskCounters;
+
+/// Applies a source's bad data policy to messages that fail to deserialize
+pub struct BadDataHandler {
+    policy: Option<BadData>,
+    dead_letters: Option<(ThreadedProducer<DefaultProducerContext>, String)>,
+}
+
+impl BadDataHandler {
+    pub fn new(policy: Option<BadData>) -> Self {
+        let dead_letters = match &policy {
+            Some(BadData::Route(dlq)) => Some((
+                ClientConfig::new()
+                    .set("bootstrap.servers", &dlq.bootstrap_servers)
+                    .create()
+                    .unwrap_or_else(|e| {
+                        panic!(
+                            "Failed to create dead letter queue producer for {}: {:?}",
+                            dlq.bootstrap_servers, e
+                        )
+                    }),
+                dlq.topic.clone(),
+            )),
+            _ => None,
+        };
+
+        Self {
+            policy,
+            dead_letters,
+        }
+    }
+
+    /// Returns the error if the policy is to fail, or hands it back if no policy is configured
+    /// so that the source can handle it as it always has. Otherwise the message is counted as
+    /// skipped and, if there's a dead letter queue, written there along with the error.
+    pub fn handle(
+        &self,
+        task_info: &TaskInfo,
+        msg: &[u8],
+        error: UserError,
+    ) -> Result<Option<UserError>, UserError> {
+        match &self.policy {
+            None => return Ok(Some(error)),
+            Some(BadData::Fail {}) => return Err(error),
+            Some(BadData::Skip {} | BadData::Route(_)) => {}
+        }
+
+        TaskCounters::DeserializationErrors
+            .for_task(task_info)
+            .inc();
+
+        let Some((producer, topic)) = &self.dead_letters else {
+            return Ok(None);
+        };
+
+        let headers = OwnedHeaders::new_with_capacity(4)
+            .insert(Header {
+                key: "arroyo.error",
+                value: Some(&error.name),
+            })
+            .insert(Header {
+                key: "arroyo.error_details",
+                value: Some(&error.details),
+            })
+            .insert(Header {
+                key: "arroyo.operator_id",
+                value: Some(&task_info.operator_id),
+            })
+            .insert(Header {
+                key: "arroyo.job_id",
+                value: Some(&task_info.job_id),
+            });
+
+        producer
+            .send(
+                BaseRecord::<(), [u8]>::to(topic)
+                    .payload(msg)
+                    .headers(headers),
+            )
+            .map(|_| None)
+            .map_err(|(e, _)| {
+                warn!("Failed to write to dead letter queue {}: {:?}", topic, e);
+                UserError::new(
+                    "Failed to write to dead letter queue",
+                    format!("Could not write message to topic '{}': {:?}", topic, e),
+                )
+            })
+    }
+}
@@ -0,0 +1,59 @@
+use std::io::{Read, Write};
+
+use arroyo_rpc::formats::Compression;
+use arroyo_types::UserError;
+
+pub fn decompress(compression: Compression, msg: &[u8]) -> Result<Vec<u8>, UserError> {
+    let mut buf = vec![];
+    let result = match compression {
+        Compression::Gzip => flate2::read::MultiGzDecoder::new(msg).read_to_end(&mut buf),
+        Compression::Zstd => zstd::stream::read::Decoder::new(msg)
+            .and_then(|mut decoder| decoder.read_to_end(&mut buf)),
+        Compression::Snappy => snap::read::FrameDecoder::new(msg).read_to_end(&mut buf),
+    };
+
+    result.map(|_| buf).map_err(|e| {
+        UserError::new(
+            "Failed to decompress message",
+            format!("message is not valid {:?} data: {}", compression, e),
+        )
+    })
+}
+
+pub fn compress(compression: Compression, msg: &[u8]) -> Vec<u8> {
+    // writing to a vec can't fail
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(msg).unwrap();
+            encoder.finish().unwrap()
+        }
+        Compression::Zstd => zstd::stream::encode_all(msg, 0).unwrap(),
+        Compression::Snappy => {
+            let mut encoder = snap::write::FrameEncoder::new(vec![]);
+            encoder.write_all(msg).unwrap();
+            encoder.into_inner().unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arroyo_rpc::formats::Compression;
+
+    use super::{compress, decompress};
+
+    #[test]
+    fn test_round_trip() {
+        let msg = br#"{"id": 1}
+{"id": 2}"#;
+
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::Snappy] {
+            let compressed = compress(compression, msg);
+            assert_ne!(&compressed[..], &msg[..]);
+            assert_eq!(decompress(compression, &compressed).unwrap(), msg.to_vec());
+        }
+
+        assert!(decompress(Compression::Gzip, msg).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use arrow::datatypes::{DataType, Schema};
+use arroyo_rpc::formats::CsvFormat;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+pub fn reader<'a>(format: &CsvFormat, bytes: &'a [u8]) -> csv::Reader<&'a [u8]> {
+    csv::ReaderBuilder::new()
+        .delimiter(format.delimiter as u8)
+        .quote(format.quote as u8)
+        .has_headers(format.header)
+        .flexible(true)
+        .from_reader(bytes)
+}
+
+fn coerce(
+    format: &CsvFormat,
+    data_type: &DataType,
+    name: &str,
+    value: &str,
+) -> Result<Value, String> {
+    let invalid = || format!("invalid value '{}' for column '{}'", value, name);
+
+    // an empty value is an empty string for text columns, unless empty is the null value
+    if value == format.null_value && (!value.is_empty() || data_type != &DataType::Utf8) {
+        return Ok(Value::Null);
+    }
+
+    Ok(match data_type {
+        DataType::Boolean => Value::Bool(match value.to_lowercase().as_str() {
+            "true" | "t" | "1" => true,
+            "false" | "f" | "0" => false,
+            _ => return Err(invalid()),
+        }),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            Value::from(value.trim().parse::<i64>().map_err(|_| invalid())?)
+        }
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+            Value::from(value.trim().parse::<u64>().map_err(|_| invalid())?)
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+            Value::from(value.trim().parse::<f64>().map_err(|_| invalid())?)
+        }
+        // timestamps may be written as RFC3339 or as milliseconds since the epoch
+        DataType::Timestamp(_, _) => match value.trim().parse::<i64>() {
+            Ok(millis) => Value::String(
+                Utc.timestamp_millis_opt(millis)
+                    .single()
+                    .ok_or_else(invalid)?
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            ),
+            Err(_) => Value::String(value.to_string()),
+        },
+        _ => Value::String(value.to_string()),
+    })
+}
+
+/// Converts a CSV record to a JSON object, using the types of the schema's fields to interpret
+/// the values. Columns are matched to fields by name if there's a header, and otherwise by
+/// position; columns that aren't in the schema are ignored.
+pub fn record_to_json(
+    format: &CsvFormat,
+    schema: &Schema,
+    headers: Option<&StringRecord>,
+    record: &StringRecord,
+) -> Result<Value, String> {
+    let mut object = Map::new();
+
+    match headers {
+        Some(headers) => {
+            for (name, value) in headers.iter().zip(record.iter()) {
+                let Ok(field) = schema.field_with_name(name) else {
+                    continue;
+                };
+                object.insert(
+                    name.to_string(),
+                    coerce(format, field.data_type(), name, value)?,
+                );
+            }
+        }
+        None => {
+            for (field, value) in schema.fields().iter().zip(record.iter()) {
+                object.insert(
+                    field.name().clone(),
+                    coerce(format, field.data_type(), field.name(), value)?,
+                );
+            }
+        }
+    }
+
+    Ok(Value::Object(object))
+}
+
+/// Writes a record, serialized as JSON, as a row with a column for each of the schema's fields,
+/// preceded by a header row if the format has one
+pub fn json_to_record(format: &CsvFormat, schema: &Schema, value: &Value) -> Vec<u8> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.delimiter as u8)
+        .quote(format.quote as u8)
+        .from_writer(vec![]);
+
+    if format.header {
+        writer
+            .write_record(schema.fields().iter().map(|f| f.name().as_str()))
+            .unwrap();
+    }
+
+    let row = schema.fields().iter().map(|f| match value.get(f.name()) {
+        None | Some(Value::Null) => format.null_value.clone(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    });
+    writer.write_record(row).unwrap();
+
+    let mut buf = writer.into_inner().unwrap();
+    // like our other formats, messages don't end with a newline
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use arroyo_rpc::formats::CsvFormat;
+    use serde_json::json;
+
+    use super::{json_to_record, reader, record_to_json};
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("score", DataType::Float64, true),
+            Field::new("active", DataType::Boolean, true),
+        ])
+    }
+
+    #[test]
+    fn test_csv_to_json() {
+        let format = CsvFormat::default();
+        let schema = schema();
+
+        let headers = csv::StringRecord::from(vec!["id", "name", "score", "active", "extra"]);
+        let record = csv::StringRecord::from(vec!["5", "bob", "", "true", "ignored"]);
+
+        assert_eq!(
+            record_to_json(&format, &schema, Some(&headers), &record).unwrap(),
+            json!({"id": 5, "name": "bob", "score": null, "active": true})
+        );
+
+        let record = csv::StringRecord::from(vec!["five", "bob", "", "true", ""]);
+        assert!(record_to_json(&format, &schema, Some(&headers), &record).is_err());
+    }
+
+    #[test]
+    fn test_positional_with_null_value() {
+        let format = CsvFormat {
+            delimiter: '\t',
+            null_value: "\\N".to_string(),
+            ..Default::default()
+        };
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "ts",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+        ]);
+
+        let mut rows = reader(&format, b"5\t\\N\t1700000000123");
+        let record = rows.records().next().unwrap().unwrap();
+        assert_eq!(
+            record_to_json(&format, &schema, None, &record).unwrap(),
+            json!({"id": 5, "name": null, "ts": "2023-11-14T22:13:20.123Z"})
+        );
+    }
+
+    #[test]
+    fn test_json_to_record() {
+        let format = CsvFormat {
+            header: true,
+            null_value: "NULL".to_string(),
+            ..Default::default()
+        };
+
+        let value = json!({"id": 5, "name": "bob, jr", "score": null, "active": true});
+        assert_eq!(
+            String::from_utf8(json_to_record(&format, &schema(), &value)).unwrap(),
+            "id,name,score,active\n5,\"bob, jr\",NULL,true"
+        );
+    }
+}
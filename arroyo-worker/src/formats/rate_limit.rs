@@ -0,0 +1,45 @@
+use std::num::NonZeroU32;
+
+use arroyo_rpc::RateLimit;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+
+/// Token buckets limiting the messages and bytes a source subtask reads per second
+pub struct SourceRateLimiter {
+    messages: Option<DefaultDirectRateLimiter>,
+    bytes: Option<(DefaultDirectRateLimiter, NonZeroU32)>,
+}
+
+impl SourceRateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            messages: limit
+                .messages_per_second
+                .and_then(NonZeroU32::new)
+                .map(|n| RateLimiter::direct(Quota::per_second(n))),
+            bytes: limit
+                .bytes_per_second
+                .and_then(NonZeroU32::new)
+                .map(|n| (RateLimiter::direct(Quota::per_second(n)), n)),
+        }
+    }
+
+    /// Waits until `msg` may be read
+    pub async fn acquire(&self, msg: &[u8]) {
+        if let Some(messages) = &self.messages {
+            messages.until_ready().await;
+        }
+
+        if let Some((bytes, burst)) = &self.bytes {
+            // a bucket can't hold more than a second's worth of bytes, so larger messages are
+            // paid for over several seconds
+            let mut remaining = msg.len().min(u32::MAX as usize) as u32;
+            while let Some(n) = NonZeroU32::new(remaining.min(burst.get())) {
+                bytes
+                    .until_n_ready(n)
+                    .await
+                    .expect("request is within the burst size");
+                remaining -= n.get();
+            }
+        }
+    }
+}
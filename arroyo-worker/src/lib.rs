@@ -4,29 +4,30 @@
 
 use crate::engine::{Engine, Program, StreamConfig, SubtaskNode};
 use crate::network_manager::NetworkManager;
+use crate::watermark_alignment::WATERMARK_ALIGNMENT;
 use anyhow::Result;
 use arrow::datatypes::{DataType, Field, Schema};
 use arroyo_rpc::grpc::controller_grpc_client::ControllerGrpcClient;
 use arroyo_rpc::grpc::worker_grpc_server::{WorkerGrpc, WorkerGrpcServer};
 use arroyo_rpc::grpc::{
     CheckpointReq, CheckpointResp, HeartbeatReq, JobFinishedReq, JobFinishedResp,
-    LoadCompactedDataReq, LoadCompactedDataRes, RegisterWorkerReq, StartExecutionReq,
-    StartExecutionResp, StopExecutionReq, StopExecutionResp, TaskCheckpointCompletedReq,
-    TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq, TaskStartedReq, WorkerErrorReq,
-    WorkerResources,
+    LoadCompactedDataReq, LoadCompactedDataRes, QueryStateReq, QueryStateResp, RegisterWorkerReq,
+    StartExecutionReq, StartExecutionResp, StopExecutionReq, StopExecutionResp,
+    TaskCheckpointCompletedReq, TaskCheckpointEventReq, TaskFailedReq, TaskFinishedReq,
+    TaskStartedReq, WorkerErrorReq, WorkerResources,
 };
 use arroyo_server_common::start_admin_server;
 use arroyo_types::{
     from_millis, grpc_port, ports, to_micros, CheckpointBarrier, Data, Debezium, NodeId, RawJson,
-    WorkerId, JOB_ID_ENV, RUN_ID_ENV,
+    Upsert, WorkerId, JOB_ID_ENV, RUN_ID_ENV,
 };
 use lazy_static::lazy_static;
 use local_ip_address::local_ip;
 use petgraph::graph::DiGraph;
 use rand::Rng;
 use serde::de::DeserializeOwned;
-use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::process::exit;
 use std::str::FromStr;
@@ -40,7 +41,7 @@ use tokio_stream::wrappers::TcpListenerStream;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
-use arroyo_rpc::{CompactionResult, ControlMessage, ControlResp};
+use arroyo_rpc::{CompactionResult, ControlMessage, ControlResp, StateQueryResp};
 pub use ordered_float::OrderedFloat;
 
 pub mod connectors;
@@ -51,6 +52,10 @@ mod metrics;
 mod network_manager;
 pub mod operators;
 mod process_fn;
+pub mod schema_registry;
+pub mod secrets;
+pub mod wasm_udfs;
+pub mod watermark_alignment;
 
 pub const PROMETHEUS_PUSH_GATEWAY: &str = "localhost:9091";
 pub const METRICS_PUSH_INTERVAL: Duration = Duration::from_secs(1);
@@ -70,6 +75,19 @@ pub trait SchemaData: Data + Serialize + DeserializeOwned {
     /// a None value, and should panic if they do not support raw strings (which
     /// indicates a miscompilation).
     fn to_raw_string(&self) -> Option<Vec<u8>>;
+
+    /// Returns the raw bytes of this data, if available for the type
+    ///
+    /// As with `to_raw_string`, implementations should return None if the relevant field is
+    /// Optional and has a None value, and panic if they do not support raw bytes.
+    fn to_raw_bytes(&self) -> Option<Vec<u8>> {
+        unimplemented!("{} cannot be written as raw bytes", Self::name())
+    }
+
+    /// Whether this deletes the row with its key from an upsert sink, rather than being written
+    fn is_deletion(&self) -> bool {
+        false
+    }
 }
 
 impl<T: SchemaData> SchemaData for Debezium<T> {
@@ -102,6 +120,34 @@ impl<T: SchemaData> SchemaData for Debezium<T> {
     }
 }
 
+impl<T: SchemaData> SchemaData for Upsert<T> {
+    fn name() -> &'static str {
+        "upsert"
+    }
+
+    fn schema() -> arrow::datatypes::Schema {
+        T::schema()
+    }
+
+    fn to_raw_string(&self) -> Option<Vec<u8>> {
+        match self {
+            Upsert::Value(value) => value.to_raw_string(),
+            Upsert::Delete(_) => None,
+        }
+    }
+
+    fn to_raw_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Upsert::Value(value) => value.to_raw_bytes(),
+            Upsert::Delete(_) => None,
+        }
+    }
+
+    fn is_deletion(&self) -> bool {
+        self.is_delete()
+    }
+}
+
 impl SchemaData for RawJson {
     fn name() -> &'static str {
         "raw_json"
@@ -149,7 +195,30 @@ where
     Ok(Some(raw.to_string()))
 }
 
+// Serializes a JSON column as the JSON it contains, rather than as a string; values that aren't
+// valid JSON are written as strings
+pub fn serialize_raw_json<S>(value: &String, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match serde_json::value::RawValue::from_string(value.clone()) {
+        Ok(raw) => raw.serialize(s),
+        Err(_) => value.serialize(s),
+    }
+}
+
+pub fn serialize_raw_json_opt<S>(value: &Option<String>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(value) => serialize_raw_json(value, s),
+        None => s.serialize_none(),
+    }
+}
+
 pub static TIMER_TABLE: char = '[';
+pub static PROCESSING_TIMER_TABLE: char = ']';
 
 pub enum SourceFinishType {
     // stop messages should be propagated through the dataflow
@@ -171,6 +240,9 @@ pub enum LogicalEdge {
     Forward,
     Shuffle,
     ShuffleJoin(usize),
+    // sends every record to all subtasks of the downstream operator; as the second input of a
+    // join, it comes after the ShuffleJoin(0) edge in the ordering of inputs
+    Broadcast,
 }
 
 impl Display for LogicalEdge {
@@ -179,6 +251,7 @@ impl Display for LogicalEdge {
             LogicalEdge::Forward => write!(f, "→"),
             LogicalEdge::Shuffle => write!(f, "⤨"),
             LogicalEdge::ShuffleJoin(order) => write!(f, "{}⤨", order),
+            LogicalEdge::Broadcast => write!(f, "⇶"),
         }
     }
 }
@@ -206,7 +279,7 @@ impl Debug for LogicalNode {
 struct EngineState {
     sources: Vec<Sender<ControlMessage>>,
     sinks: Vec<Sender<ControlMessage>>,
-    operator_controls: HashMap<String, Vec<Sender<ControlMessage>>>, // operator_id -> vec of control tx
+    operator_controls: HashMap<String, BTreeMap<usize, Sender<ControlMessage>>>, // operator_id -> subtask index -> control tx
     shutdown_tx: broadcast::Sender<bool>,
 }
 
@@ -469,10 +542,16 @@ impl WorkerServer {
                             job_id: job_id.clone(),
                             time: to_micros(SystemTime::now()),
                             worker_id: worker_id.0,
+                            source_watermarks: WATERMARK_ALIGNMENT.local_watermarks(),
                         })).await;
-                        if let Err(err) = result {
-                            error!("heartbeat failed {:?}", err);
-                            exit(1);
+                        match result {
+                            Ok(resp) => {
+                                WATERMARK_ALIGNMENT.update_remote(resp.into_inner().group_watermarks);
+                            }
+                            Err(err) => {
+                                error!("heartbeat failed {:?}", err);
+                                exit(1);
+                            }
                         }
                     }
                     _ = shutdown_rx.recv() => {
@@ -486,6 +565,43 @@ impl WorkerServer {
     }
 }
 
+/// Asks the operator's subtasks on this worker for their state for `key`. Only an operator can
+/// parse its keys, so the query goes to any of its subtasks first, which either answers or names
+/// the subtask that owns the key. If that one runs on another worker, there's nothing here.
+async fn route_state_query(
+    subtasks: &BTreeMap<usize, Sender<ControlMessage>>,
+    key: String,
+) -> Result<Option<String>, Status> {
+    async fn ask(subtask: &Sender<ControlMessage>, key: String) -> Result<StateQueryResp, Status> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        subtask
+            .send(ControlMessage::QueryState { key, tx })
+            .await
+            .map_err(|_| Status::unavailable("subtask is no longer running"))?;
+        rx.await
+            .map_err(|_| Status::unavailable("subtask is no longer running"))?
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+
+    let Some(first) = subtasks.values().next() else {
+        return Ok(None);
+    };
+
+    match ask(first, key.clone()).await? {
+        StateQueryResp::Value(value) => Ok(value),
+        StateQueryResp::OwnedBy(owner) => match subtasks.get(&owner) {
+            Some(subtask) => match ask(subtask, key).await? {
+                StateQueryResp::Value(value) => Ok(value),
+                StateQueryResp::OwnedBy(other) => Err(Status::internal(format!(
+                    "subtasks {} and {} both claim that the other owns the key",
+                    owner, other
+                ))),
+            },
+            None => Ok(None),
+        },
+    }
+}
+
 #[tonic::async_trait]
 impl WorkerGrpc for WorkerServer {
     async fn start_execution(
@@ -590,6 +706,7 @@ impl WorkerGrpc for WorkerServer {
             min_epoch: req.min_epoch,
             timestamp: from_millis(req.timestamp),
             then_stop: req.then_stop,
+            unaligned: req.unaligned && !req.then_stop,
         };
 
         for n in &senders {
@@ -613,7 +730,7 @@ impl WorkerGrpc for WorkerServer {
 
         let compacted: CompactionResult = req.into();
 
-        for s in nodes {
+        for s in nodes.values() {
             if let Err(e) = s
                 .send(ControlMessage::LoadCompacted {
                     compacted: compacted.clone(),
@@ -630,6 +747,32 @@ impl WorkerGrpc for WorkerServer {
         return Ok(Response::new(LoadCompactedDataRes {}));
     }
 
+    async fn query_state(
+        &self,
+        request: Request<QueryStateReq>,
+    ) -> Result<Response<QueryStateResp>, Status> {
+        let req = request.into_inner();
+
+        let nodes = {
+            let state = self.state.lock().unwrap();
+            let Some(s) = state.as_ref() else {
+                return Err(Status::failed_precondition(
+                    "Job is not running on this worker",
+                ));
+            };
+            s.operator_controls
+                .get(&req.operator_id)
+                .cloned()
+                .ok_or_else(|| {
+                    Status::not_found(format!("No operator with id {}", req.operator_id))
+                })?
+        };
+
+        let value = route_state_query(&nodes, req.key).await?;
+
+        Ok(Response::new(QueryStateResp { value }))
+    }
+
     async fn stop_execution(
         &self,
         request: Request<StopExecutionReq>,
@@ -668,3 +811,73 @@ impl WorkerGrpc for WorkerServer {
         Ok(Response::new(JobFinishedResp {}))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::channel;
+
+    // a subtask of an operator with 3 subtasks, where keys are owned by the subtask at the index
+    // of their length
+    fn fake_subtask(index: usize) -> Sender<ControlMessage> {
+        let (tx, mut rx) = channel(8);
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                let ControlMessage::QueryState { key, tx } = msg else {
+                    panic!("unexpected control message {:?}", msg);
+                };
+                let owner = key.len() % 3;
+                let _ = tx.send(if key == "bad" {
+                    Err(anyhow::anyhow!("invalid key"))
+                } else if owner == index {
+                    Ok(StateQueryResp::Value(Some(format!("{}:{}", index, key))))
+                } else {
+                    Ok(StateQueryResp::OwnedBy(owner))
+                });
+            }
+        });
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_route_state_query() {
+        let subtasks: BTreeMap<_, _> = [0, 2].into_iter().map(|i| (i, fake_subtask(i))).collect();
+
+        // owned by the first local subtask
+        assert_eq!(
+            Some("0:abc".to_string()),
+            route_state_query(&subtasks, "abc".to_string())
+                .await
+                .unwrap()
+        );
+
+        // routed to the local subtask that owns it
+        assert_eq!(
+            Some("2:ab".to_string()),
+            route_state_query(&subtasks, "ab".to_string())
+                .await
+                .unwrap()
+        );
+
+        // owned by a subtask on another worker
+        assert_eq!(
+            None,
+            route_state_query(&subtasks, "a".to_string()).await.unwrap()
+        );
+
+        assert_eq!(
+            tonic::Code::InvalidArgument,
+            route_state_query(&subtasks, "bad".to_string())
+                .await
+                .unwrap_err()
+                .code()
+        );
+
+        assert_eq!(
+            None,
+            route_state_query(&BTreeMap::new(), "abc".to_string())
+                .await
+                .unwrap()
+        );
+    }
+}
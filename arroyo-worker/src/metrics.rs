@@ -1,8 +1,12 @@
 use crate::engine::OutQueue;
 use arroyo_metrics::gauge_for_task;
-use arroyo_types::{TaskInfo, BYTES_RECV, BYTES_SENT, MESSAGES_RECV, MESSAGES_SENT};
+use arroyo_types::{
+    TaskInfo, BUSY_TIME, BYTES_RECV, BYTES_SENT, DESERIALIZATION_ERRORS, MESSAGES_RECV,
+    MESSAGES_SENT, SOURCE_LAG,
+};
 use lazy_static::lazy_static;
 use prometheus::{labels, register_int_counter_vec, IntCounter, IntCounterVec, IntGauge};
+use std::collections::HashMap;
 
 lazy_static! {
     pub static ref TASK_METRIC_LABELS: Vec<&'static str> =
@@ -31,6 +35,18 @@ lazy_static! {
         &TASK_METRIC_LABELS
     )
     .unwrap();
+    pub static ref DESERIALIZATION_ERRORS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        DESERIALIZATION_ERRORS,
+        "Count of messages that could not be deserialized by this subtask",
+        &TASK_METRIC_LABELS
+    )
+    .unwrap();
+    pub static ref BUSY_TIME_COUNTER: IntCounterVec = register_int_counter_vec!(
+        BUSY_TIME,
+        "Microseconds this subtask has spent processing records",
+        &TASK_METRIC_LABELS
+    )
+    .unwrap();
 }
 
 pub enum TaskCounters {
@@ -38,6 +54,8 @@ pub enum TaskCounters {
     MessagesSent,
     BytesReceived,
     BytesSent,
+    DeserializationErrors,
+    BusyTime,
 }
 
 impl TaskCounters {
@@ -63,6 +81,17 @@ impl TaskCounters {
                 &task_info.task_index.to_string(),
                 &task_info.operator_name,
             ]),
+            TaskCounters::DeserializationErrors => DESERIALIZATION_ERRORS_COUNTER
+                .with_label_values(&[
+                    &task_info.operator_id,
+                    &task_info.task_index.to_string(),
+                    &task_info.operator_name,
+                ]),
+            TaskCounters::BusyTime => BUSY_TIME_COUNTER.with_label_values(&[
+                &task_info.operator_id,
+                &task_info.task_index.to_string(),
+                &task_info.operator_name,
+            ]),
         }
     }
 }
@@ -117,3 +146,13 @@ pub fn register_queue_gauges(
 
     (tx_queue_size_gauges, tx_queue_rem_gauges)
 }
+
+/// How far behind the current time the records a source is reading are, for source tasks
+pub fn register_source_lag_gauge(task_info: &TaskInfo) -> Option<IntGauge> {
+    gauge_for_task(
+        task_info,
+        SOURCE_LAG,
+        "Milliseconds the last record read by this source was behind the current time",
+        HashMap::new(),
+    )
+}
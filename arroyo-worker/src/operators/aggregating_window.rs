@@ -5,6 +5,7 @@ use std::{
 };
 
 use crate::engine::{Context, StreamNode};
+use crate::operators::window_alignment::WindowAlignment;
 use arroyo_macro::process_fn;
 use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
 use arroyo_state::tables::time_key_map::TimeKeyMap;
@@ -15,6 +16,7 @@ use tracing::warn;
 pub struct AggregatingWindowFunc<K: Key, T: Data, BinA: Data, MemA: Data, OutT: Data> {
     width: Duration,
     slide: Duration,
+    alignment: WindowAlignment,
     aggregator: fn(&K, Window, &MemA) -> OutT,
     bin_merger: fn(&T, Option<&BinA>) -> BinA,
     in_memory_add: fn(Option<MemA>, BinA) -> MemA,
@@ -44,6 +46,7 @@ impl<K: Key, T: Data, BinA: Data, MemA: Data, OutT: Data>
     pub fn new(
         width: Duration,
         slide: Duration,
+        alignment: WindowAlignment,
         aggregator: fn(&K, Window, &MemA) -> OutT,
         bin_merger: fn(&T, Option<&BinA>) -> BinA,
         in_memory_add: fn(Option<MemA>, BinA) -> MemA,
@@ -52,6 +55,7 @@ impl<K: Key, T: Data, BinA: Data, MemA: Data, OutT: Data>
         AggregatingWindowFunc {
             width,
             slide,
+            alignment,
             aggregator,
             bin_merger,
             in_memory_add,
@@ -62,9 +66,7 @@ impl<K: Key, T: Data, BinA: Data, MemA: Data, OutT: Data>
     }
 
     fn bin_start(&self, timestamp: SystemTime) -> SystemTime {
-        let mut nanos = to_nanos(timestamp);
-        nanos -= nanos % self.slide.as_nanos();
-        from_nanos(nanos)
+        self.alignment.bin_start(timestamp, self.slide)
     }
 
     fn tables(&self) -> Vec<TableDescriptor> {
@@ -521,3 +523,96 @@ pub fn non_nullable_average_remove<T: Sub<Output = T>>(
     }
     Some((current_count - bin_count, current_sum - bin_sum))
 }
+
+pub fn distinct_merge<T: Ord>(
+    mut current: BTreeMap<T, usize>,
+    bin_value: BTreeMap<T, usize>,
+) -> BTreeMap<T, usize> {
+    for (value, count) in bin_value {
+        *current.entry(value).or_default() += count;
+    }
+    current
+}
+
+pub fn distinct_add<T: Ord>(
+    current: Option<BTreeMap<T, usize>>,
+    bin_value: BTreeMap<T, usize>,
+) -> BTreeMap<T, usize> {
+    match current {
+        Some(current) => distinct_merge(current, bin_value),
+        None => bin_value,
+    }
+}
+
+// unlike the other removals this never returns None, as an empty map is still a valid state
+// for a bin that only had nulls
+pub fn distinct_remove<T: Ord>(
+    current: BTreeMap<T, usize>,
+    bin_value: BTreeMap<T, usize>,
+) -> Option<BTreeMap<T, usize>> {
+    let mut map = current;
+    for (value, count) in bin_value {
+        let value_count = map.get(&value).copied().unwrap_or_default();
+        if value_count <= count {
+            map.remove(&value);
+        } else {
+            map.insert(value, value_count - count);
+        }
+    }
+    Some(map)
+}
+
+pub fn distinct_aggregate<T>(memory: &BTreeMap<T, usize>) -> i64 {
+    memory.len() as i64
+}
+
+// HyperLogLog sketches and the values collected by ARRAY_AGG can't be subtracted from, so sliding
+// windows keep the state of each bin in the window, oldest first, and merge them when the window
+// is emitted
+pub fn bins_add<T>(current: Option<Vec<T>>, bin_value: T) -> Vec<T> {
+    let mut bins = current.unwrap_or_default();
+    bins.push(bin_value);
+    bins
+}
+
+pub fn bins_remove<T>(current: Vec<T>, _bin_value: T) -> Option<Vec<T>> {
+    // bins always leave the window in the order they entered it
+    let mut bins = current;
+    bins.remove(0);
+    Some(bins)
+}
+
+pub fn sketch_aggregate(memory: &[Vec<u8>]) -> Vec<u8> {
+    let mut sketch = super::functions::hll::new_sketch();
+    for bin in memory {
+        super::functions::hll::merge(&mut sketch, bin);
+    }
+    sketch
+}
+
+pub fn approx_count_distinct_aggregate(memory: &[Vec<u8>]) -> i64 {
+    super::functions::hll::estimate(&sketch_aggregate(memory)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distinct_memory() {
+        let bin = |values: &[u32]| {
+            values.iter().fold(BTreeMap::new(), |map, v| {
+                distinct_merge(map, BTreeMap::from([(*v, 1)]))
+            })
+        };
+
+        let memory = distinct_add(None, bin(&[1, 2, 2]));
+        let memory = distinct_add(Some(memory), bin(&[2, 3]));
+        assert_eq!(distinct_aggregate(&memory), 3);
+
+        let memory = distinct_remove(memory, bin(&[1, 2, 2])).unwrap();
+        assert_eq!(memory, BTreeMap::from([(2, 1), (3, 1)]));
+        let memory = distinct_remove(memory, bin(&[2, 3])).unwrap();
+        assert_eq!(distinct_aggregate(&memory), 0);
+    }
+}
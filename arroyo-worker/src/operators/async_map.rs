@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::time::{Duration, Instant, SystemTime};
+
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+use futures::stream::{FuturesOrdered, FuturesUnordered};
+use futures::{FutureExt, StreamExt};
+use tracing::warn;
+
+use crate::engine::Context;
+
+pub type UdfFuture<R> = Pin<Box<dyn Future<Output = R> + Send>>;
+
+// the table that the rows whose calls were in flight at a checkpoint are stored in
+const IN_FLIGHT_TABLE: char = 'f';
+
+/// A cache of the results of calls by key. It holds at most `max_entries`, evicting those that
+/// were used least recently once it's full, and entries expire `ttl` after they're inserted.
+pub struct AsyncCache<Q: Hash + Eq + Clone, V> {
+    // the value of each key, along with when it was inserted and when it was last used
+    entries: HashMap<Q, (Instant, u64, V)>,
+    // keys by when they were last used
+    recency: BTreeMap<u64, Q>,
+    next_use: u64,
+    max_entries: usize,
+    ttl: Option<Duration>,
+}
+
+impl<Q: Hash + Eq + Clone, V> AsyncCache<Q, V> {
+    pub fn new(max_entries: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_use: 0,
+            max_entries,
+            ttl,
+        }
+    }
+
+    pub fn get(&mut self, key: &Q) -> Option<&V> {
+        let (inserted, last_used, _) = self.entries.get(key)?;
+        if matches!(self.ttl, Some(ttl) if inserted.elapsed() >= ttl) {
+            let last_used = *last_used;
+            self.recency.remove(&last_used);
+            self.entries.remove(key);
+            return None;
+        }
+
+        let used = self.next_use;
+        self.next_use += 1;
+        let (_, last_used, value) = self.entries.get_mut(key).unwrap();
+        self.recency.remove(last_used);
+        self.recency.insert(used, key.clone());
+        *last_used = used;
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: Q, value: V) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let used = self.next_use;
+        self.next_use += 1;
+        if let Some((_, last_used, _)) = self
+            .entries
+            .insert(key.clone(), (Instant::now(), used, value))
+        {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(used, key);
+
+        while self.entries.len() > self.max_entries {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+// a call that resolves to its sequence number and result, or None if it timed out
+type Call<R> = Pin<Box<dyn Future<Output = (u64, Option<R>)> + Send>>;
+
+// the calls that haven't been emitted yet, which are emitted in the order they were made if the
+// operator is ordered, or as they complete otherwise
+enum InFlight<R> {
+    Ordered(FuturesOrdered<Call<R>>),
+    Unordered(FuturesUnordered<Call<R>>),
+}
+
+impl<R> InFlight<R> {
+    fn len(&self) -> usize {
+        match self {
+            InFlight::Ordered(calls) => calls.len(),
+            InFlight::Unordered(calls) => calls.len(),
+        }
+    }
+
+    fn push(&mut self, call: Call<R>) {
+        match self {
+            InFlight::Ordered(calls) => calls.push_back(call),
+            InFlight::Unordered(calls) => calls.push(call),
+        }
+    }
+
+    async fn next(&mut self) -> Option<(u64, Option<R>)> {
+        match self {
+            InFlight::Ordered(calls) => calls.next().await,
+            InFlight::Unordered(calls) => calls.next().await,
+        }
+    }
+}
+
+/// Maps each row to an async call, like a network request, and merges its result back into the
+/// row. This is the shared machinery of the operators that call out to external systems; they
+/// forward their process, tick, watermark and checkpoint handlers to it.
+///
+/// Calls run concurrently on the runtime, up to `max_in_flight` at a time, so that waiting on
+/// them doesn't block the dataflow. Calls that take longer than `timeout` are retried up to
+/// `retries` times, after which their result is None. Results can be cached by a key computed
+/// from the row, in which case rows with a cached result aren't called for.
+///
+/// In-flight calls are completed before watermarks are forwarded, so no rows are late. They
+/// aren't waited on for checkpoints, which instead store the rows whose calls are in flight;
+/// those rows are called for again when the operator is restored.
+pub struct AsyncMapOperator<K: Key, T: Data, R: Data, OutT: Data> {
+    name: String,
+    call: fn(&T) -> UdfFuture<R>,
+    merge: fn(&T, Option<R>) -> OutT,
+    max_in_flight: usize,
+    timeout: Duration,
+    retries: u32,
+    in_flight: InFlight<R>,
+    // the rows whose results haven't been emitted, with their cache keys, by sequence number
+    pending: HashMap<u64, (Record<K, T>, Option<String>)>,
+    next_seq: u64,
+    cache: Option<(fn(&T) -> Option<String>, AsyncCache<String, R>)>,
+}
+
+impl<K: Key, T: Data, R: Data, OutT: Data> AsyncMapOperator<K, T, R, OutT> {
+    pub fn new(
+        name: &str,
+        call: fn(&T) -> UdfFuture<R>,
+        merge: fn(&T, Option<R>) -> OutT,
+        ordered: bool,
+        max_in_flight: usize,
+        timeout: Duration,
+        retries: u32,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            call,
+            merge,
+            max_in_flight: max_in_flight.max(1),
+            timeout,
+            retries,
+            in_flight: if ordered {
+                InFlight::Ordered(FuturesOrdered::new())
+            } else {
+                InFlight::Unordered(FuturesUnordered::new())
+            },
+            pending: HashMap::new(),
+            next_seq: 0,
+            cache: None,
+        }
+    }
+
+    /// Caches up to `max_entries` results by the key that `cache_key` computes for their rows,
+    /// for `ttl` if it's set. Rows without a key are always called for, and calls that time out
+    /// aren't cached.
+    pub fn with_cache(
+        mut self,
+        cache_key: fn(&T) -> Option<String>,
+        max_entries: usize,
+        ttl: Option<Duration>,
+    ) -> Self {
+        self.cache = Some((cache_key, AsyncCache::new(max_entries, ttl)));
+        self
+    }
+
+    pub fn tables() -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table(
+            IN_FLIGHT_TABLE.to_string(),
+            "rows with calls in flight",
+        )]
+    }
+
+    /// Calls for the rows that were in flight when the checkpoint being restored was taken. If
+    /// the job has been rescaled, each subtask takes over the rows of the subtasks whose index
+    /// maps to it.
+    pub async fn on_start(&mut self, ctx: &mut Context<K, OutT>) {
+        let mut state: GlobalKeyedState<usize, (usize, Vec<(SystemTime, Option<K>, T)>), _> =
+            ctx.state.get_global_keyed_state(IN_FLIGHT_TABLE).await;
+        let task_index = ctx.task_info.task_index;
+        let parallelism = ctx.task_info.parallelism;
+        let restored: Vec<_> = state
+            .get_all()
+            .into_iter()
+            .filter(|(subtask, _)| subtask % parallelism == task_index)
+            .flat_map(|(_, records)| records.iter().cloned())
+            .collect();
+
+        for (timestamp, key, value) in restored {
+            self.process_element(
+                &Record {
+                    timestamp,
+                    key,
+                    value,
+                },
+                ctx,
+            )
+            .await;
+        }
+    }
+
+    pub async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        while self.in_flight.len() >= self.max_in_flight {
+            let (seq, result) = self.in_flight.next().await.unwrap();
+            self.emit(seq, result, ctx).await;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let cache_key = self
+            .cache
+            .as_ref()
+            .and_then(|(cache_key, _)| cache_key(&record.value));
+        let cached = match (&mut self.cache, &cache_key) {
+            (Some((_, cache)), Some(key)) => cache.get(key).cloned(),
+            _ => None,
+        };
+
+        self.pending.insert(seq, (record.clone(), cache_key));
+
+        if let Some(result) = cached {
+            self.in_flight
+                .push(futures::future::ready((seq, Some(result))).boxed());
+            return;
+        }
+
+        let call = self.call;
+        let value = record.value.clone();
+        let timeout = self.timeout;
+        let retries = self.retries;
+        let name = self.name.clone();
+        let handle = tokio::spawn(async move {
+            for attempt in 0..=retries {
+                match tokio::time::timeout(timeout, call(&value)).await {
+                    Ok(result) => return (seq, Some(result)),
+                    Err(_) => warn!(
+                        "call {} of {} timed out after {:?}",
+                        attempt + 1,
+                        name,
+                        timeout
+                    ),
+                }
+            }
+            (seq, None)
+        });
+        self.in_flight.push(
+            handle
+                .map(|result| result.expect("async call panicked"))
+                .boxed(),
+        );
+    }
+
+    /// Emits the calls that have completed, without waiting on the others
+    pub async fn handle_tick(&mut self, ctx: &mut Context<K, OutT>) {
+        while let Some(Some((seq, result))) = self.in_flight.next().now_or_never() {
+            self.emit(seq, result, ctx).await;
+        }
+    }
+
+    pub async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, OutT>) {
+        self.flush(ctx).await;
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    pub async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<K, OutT>) {
+        self.handle_tick(ctx).await;
+
+        let mut pending: Vec<_> = self.pending.iter().collect();
+        pending.sort_by_key(|(seq, _)| **seq);
+        let records = pending
+            .into_iter()
+            .map(|(_, (record, _))| (record.timestamp, record.key.clone(), record.value.clone()))
+            .collect();
+
+        let task_index = ctx.task_info.task_index;
+        let mut state: GlobalKeyedState<usize, (usize, Vec<(SystemTime, Option<K>, T)>), _> =
+            ctx.state.get_global_keyed_state(IN_FLIGHT_TABLE).await;
+        state.insert(task_index, (task_index, records)).await;
+    }
+
+    pub async fn on_close(&mut self, ctx: &mut Context<K, OutT>) {
+        self.flush(ctx).await;
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<K, OutT>) {
+        while let Some((seq, result)) = self.in_flight.next().await {
+            self.emit(seq, result, ctx).await;
+        }
+    }
+
+    async fn emit(&mut self, seq: u64, result: Option<R>, ctx: &mut Context<K, OutT>) {
+        let (record, cache_key) = self
+            .pending
+            .remove(&seq)
+            .expect("completed call that isn't pending");
+
+        if let (Some((_, cache)), Some(key), Some(result)) = (&mut self.cache, cache_key, &result) {
+            cache.insert(key, result.clone());
+        }
+
+        ctx.collect(Record {
+            timestamp: record.timestamp,
+            key: record.key,
+            value: (self.merge)(&record.value, result),
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_eviction() {
+        let mut cache = AsyncCache::new(2, None);
+        cache.insert("a".to_string(), Some(1));
+        cache.insert("b".to_string(), None);
+        assert_eq!(cache.get(&"a".to_string()), Some(&Some(1)));
+        assert_eq!(cache.get(&"b".to_string()), Some(&None));
+        assert_eq!(cache.get(&"c".to_string()), None);
+
+        cache.insert("c".to_string(), Some(3));
+        assert_eq!(cache.get(&"a".to_string()), None);
+        assert_eq!(cache.get(&"c".to_string()), Some(&Some(3)));
+
+        // re-inserting a key doesn't let its earlier entry evict it
+        cache.insert("b".to_string(), Some(2));
+        cache.insert("d".to_string(), Some(4));
+        assert_eq!(cache.get(&"b".to_string()), Some(&Some(2)));
+        assert_eq!(cache.get(&"c".to_string()), None);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = AsyncCache::new(2, None);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+
+        // reading 1 makes 2 the least recently used
+        assert_eq!(cache.get(&1), Some(&"a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some(&"c"));
+    }
+
+    #[test]
+    fn test_cache_expiration() {
+        let mut cache = AsyncCache::new(10, Some(Duration::ZERO));
+        cache.insert("a".to_string(), Some(1));
+        assert_eq!(cache.get(&"a".to_string()), None);
+
+        let mut disabled = AsyncCache::new(0, None);
+        disabled.insert("a".to_string(), Some(1));
+        assert_eq!(disabled.get(&"a".to_string()), None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_types::*;
+
+use crate::engine::{Context, StreamNode};
+use crate::operators::async_map::{AsyncMapOperator, UdfFuture};
+
+/// Calls an async UDF, like one that makes a network request, for each row. Calls run
+/// concurrently on the runtime, up to `max_concurrency` at a time, so that waiting on them doesn't
+/// block the dataflow. Calls that take longer than `timeout` are retried up to `retries` times,
+/// after which their result is null.
+///
+/// In-flight calls are completed before watermarks are forwarded, so no rows are late, and are
+/// stored in checkpoints to be made again on restore.
+#[derive(StreamNode)]
+pub struct AsyncUdfFunc<K: Key, T: Data, R: Data, OutT: Data> {
+    name: String,
+    calls: AsyncMapOperator<K, T, R, OutT>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = OutT, tick_ms = 10)]
+impl<K: Key, T: Data, R: Data, OutT: Data> AsyncUdfFunc<K, T, R, OutT> {
+    fn name(&self) -> String {
+        format!("AsyncUdf<{}>", self.name)
+    }
+
+    pub fn new(
+        name: &str,
+        call: fn(&T) -> UdfFuture<R>,
+        merge: fn(&T, Option<R>) -> OutT,
+        ordered: bool,
+        max_concurrency: usize,
+        timeout: Duration,
+        retries: u32,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            calls: AsyncMapOperator::new(
+                &format!("async UDF {}", name),
+                call,
+                merge,
+                ordered,
+                max_concurrency,
+                timeout,
+                retries,
+            ),
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        AsyncMapOperator::<K, T, R, OutT>::tables()
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<K, OutT>) {
+        self.calls.on_start(ctx).await;
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        self.calls.process_element(record, ctx).await;
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<K, OutT>) {
+        self.calls.handle_tick(ctx).await;
+    }
+
+    async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, OutT>) {
+        self.calls.handle_watermark(watermark, ctx).await;
+    }
+
+    async fn handle_checkpoint(&mut self, barrier: &CheckpointBarrier, ctx: &mut Context<K, OutT>) {
+        self.calls.handle_checkpoint(barrier, ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<K, OutT>) {
+        self.calls.on_close(ctx).await;
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use arroyo_macro::{co_process_fn, StreamNode};
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_state::tables::global_keyed_map::GlobalKeyedState;
+use arroyo_types::*;
+
+use crate::engine::Context;
+
+// the table that the broadcast state is stored in
+const BROADCAST_STATE_TABLE: char = 'b';
+
+/// Connects a keyed data stream to a low-volume control stream, like one of rules, thresholds or
+/// feature flags, whose records are broadcast to every subtask. Each subtask keeps the latest
+/// control record for each control key in broadcast state, and each data record is processed
+/// against the current state, so that what the pipeline does can be changed without redeploying
+/// it.
+///
+/// Control records take effect as soon as they arrive, regardless of their timestamps. The
+/// control stream's watermarks hold back those of the output like any other input, so a control
+/// source that rarely has new records should be allowed to go idle.
+#[derive(StreamNode)]
+pub struct BroadcastConnect<K: Key, T: Data, CK: Key, C: Data, OutT: Data> {
+    control_key: fn(&C) -> String,
+    process: fn(&T, &BTreeMap<String, C>) -> Option<OutT>,
+    state: BTreeMap<String, C>,
+    _t: PhantomData<(K, CK)>,
+}
+
+#[co_process_fn(in_k1=K, in_t1=T, in_k2=CK, in_t2=C, out_k=K, out_t=OutT)]
+impl<K: Key, T: Data, CK: Key, C: Data, OutT: Data> BroadcastConnect<K, T, CK, C, OutT> {
+    fn name(&self) -> String {
+        "BroadcastConnect".to_string()
+    }
+
+    pub fn new(
+        control_key: fn(&C) -> String,
+        process: fn(&T, &BTreeMap<String, C>) -> Option<OutT>,
+    ) -> Self {
+        Self {
+            control_key,
+            process,
+            state: BTreeMap::new(),
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![arroyo_state::global_table(
+            BROADCAST_STATE_TABLE.to_string(),
+            "broadcast state",
+        )]
+    }
+
+    async fn on_start(&mut self, ctx: &mut Context<K, OutT>) {
+        let mut state: GlobalKeyedState<usize, Vec<(String, C)>, _> = ctx
+            .state
+            .get_global_keyed_state(BROADCAST_STATE_TABLE)
+            .await;
+
+        // every subtask has the same state, so it doesn't matter how the job has been rescaled
+        if let Some(entries) = state.get_all().first() {
+            self.state = entries.iter().cloned().collect();
+        }
+    }
+
+    async fn process_left(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        if let Some(value) = (self.process)(&record.value, &self.state) {
+            ctx.collect(Record {
+                timestamp: record.timestamp,
+                key: record.key.clone(),
+                value,
+            })
+            .await;
+        }
+    }
+
+    async fn process_right(&mut self, record: &Record<CK, C>, _: &mut Context<K, OutT>) {
+        self.state
+            .insert((self.control_key)(&record.value), record.value.clone());
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<K, OutT>) {
+        // the state is the same on every subtask, so only the first needs to store it
+        if ctx.task_info.task_index != 0 {
+            return;
+        }
+
+        let mut state: GlobalKeyedState<usize, Vec<(String, C)>, _> = ctx
+            .state
+            .get_global_keyed_state(BROADCAST_STATE_TABLE)
+            .await;
+        state
+            .insert(
+                0,
+                self.state
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            )
+            .await;
+    }
+}
@@ -0,0 +1,79 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::key_time_multi_map::KeyTimeMultiMap;
+use arroyo_types::*;
+
+/// Drops records whose key has already been seen within the last `ttl`, by event time. The times
+/// at which keys were seen are kept in state until the watermark passes their expiration, so
+/// duplicates are also dropped across restarts.
+#[derive(StreamNode)]
+pub struct DeduplicateOperator<K: Key, T: Data> {
+    ttl: Duration,
+    _t: PhantomData<(K, T)>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = T)]
+impl<K: Key, T: Data> DeduplicateOperator<K, T> {
+    fn name(&self) -> String {
+        "Deduplicate".to_string()
+    }
+
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![TableDescriptor {
+            name: "d".to_string(),
+            description: "seen keys".to_string(),
+            table_type: TableType::KeyTimeMultiMap as i32,
+            delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+            write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+            retention_micros: self.ttl.as_micros() as u64,
+        }]
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, T>) {
+        let mut key = record.key.clone().unwrap();
+        let mut seen: KeyTimeMultiMap<K, (), _> = ctx.state.get_key_time_multi_map('d').await;
+
+        let duplicate = seen
+            .get_all_values_with_timestamps(&mut key)
+            .await
+            .map(|mut times| {
+                times.any(|(t, _)| {
+                    // records may arrive out of order, so keys count as seen in either direction
+                    let distance = record
+                        .timestamp
+                        .duration_since(t)
+                        .or_else(|_| t.duration_since(record.timestamp))
+                        .unwrap();
+                    distance < self.ttl
+                })
+            })
+            .unwrap_or(false);
+
+        if duplicate {
+            return;
+        }
+
+        seen.insert(record.timestamp, key, ()).await;
+        ctx.collect(record.clone()).await;
+    }
+
+    async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, T>) {
+        if let Watermark::EventTime(watermark) = watermark {
+            let mut seen: KeyTimeMultiMap<K, (), _> = ctx.state.get_key_time_multi_map('d').await;
+            seen.expire_entries_before(watermark - self.ttl).await;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+}
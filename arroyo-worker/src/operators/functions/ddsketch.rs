@@ -0,0 +1,117 @@
+//! DDSketches for approximate percentiles.
+//!
+//! A sketch counts values in logarithmically-sized buckets, so that any percentile can be
+//! estimated to within 1% of its actual value. Sketches are maps from bucket keys to counts,
+//! ordered by the values in the buckets, which makes them cheap to keep in state. They can also
+//! be merged and subtracted, so sliding windows and updating aggregates maintain them exactly
+//! with the same helpers as `COUNT(DISTINCT ..)`.
+
+use std::collections::BTreeMap;
+
+const RELATIVE_ACCURACY: f64 = 0.01;
+// values with a smaller magnitude are counted as zero
+const MIN_VALUE: f64 = 1e-9;
+
+fn gamma() -> f64 {
+    (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+}
+
+fn index(magnitude: f64) -> i32 {
+    (magnitude.min(f64::MAX).ln() / gamma().ln()).ceil() as i32
+}
+
+/// The key of the bucket for a value. Positive values have positive keys, negative values have
+/// negative keys, and both grow with the magnitude of the value.
+fn key(value: f64) -> i32 {
+    let magnitude = value.abs();
+    if magnitude < MIN_VALUE {
+        return 0;
+    }
+    let key = index(magnitude) - index(MIN_VALUE) + 1;
+    if value > 0.0 {
+        key
+    } else {
+        -key
+    }
+}
+
+/// The value that represents a bucket, which is within the relative accuracy of every value in it
+fn value(key: i32) -> f64 {
+    if key == 0 {
+        return 0.0;
+    }
+    let gamma = gamma();
+    let magnitude = 2.0 * gamma.powi(key.abs() + index(MIN_VALUE) - 1) / (gamma + 1.0);
+    if key > 0 {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+/// Adds a value to the sketch. NaNs are ignored.
+pub fn add(sketch: &mut BTreeMap<i32, usize>, value: f64) {
+    if value.is_nan() {
+        return;
+    }
+    *sketch.entry(key(value)).or_default() += 1;
+}
+
+/// The estimated value at the `percentile` (between 0 and 1) of the values in the sketch, or
+/// None if it's empty
+pub fn percentile(sketch: &BTreeMap<i32, usize>, percentile: f64) -> Option<f64> {
+    let count: usize = sketch.values().sum();
+    if count == 0 {
+        return None;
+    }
+
+    let rank = (percentile * (count - 1) as f64) as usize;
+    let mut seen = 0;
+    for (key, bucket_count) in sketch {
+        seen += bucket_count;
+        if seen > rank {
+            return Some(value(*key));
+        }
+    }
+    unreachable!("rank is less than the count")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(estimate: f64, actual: f64) {
+        assert!(
+            (estimate - actual).abs() <= actual.abs() * RELATIVE_ACCURACY,
+            "estimate {} is too far from {}",
+            estimate,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut sketch = BTreeMap::new();
+        assert_eq!(percentile(&sketch, 0.5), None);
+
+        for i in 1..=1000 {
+            add(&mut sketch, i as f64);
+        }
+        assert_close(percentile(&sketch, 0.0).unwrap(), 1.0);
+        assert_close(percentile(&sketch, 0.5).unwrap(), 500.0);
+        assert_close(percentile(&sketch, 0.99).unwrap(), 990.0);
+        assert_close(percentile(&sketch, 1.0).unwrap(), 1000.0);
+    }
+
+    #[test]
+    fn test_negative_and_zero() {
+        let mut sketch = BTreeMap::new();
+        for v in [-100.0, -10.0, 0.0, 10.0, 100.0, f64::NAN] {
+            add(&mut sketch, v);
+        }
+        assert_close(percentile(&sketch, 0.0).unwrap(), -100.0);
+        assert_close(percentile(&sketch, 0.25).unwrap(), -10.0);
+        assert_eq!(percentile(&sketch, 0.5), Some(0.0));
+        assert_close(percentile(&sketch, 1.0).unwrap(), 100.0);
+    }
+}
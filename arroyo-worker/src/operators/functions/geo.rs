@@ -0,0 +1,327 @@
+//! Geospatial functions over geometries in well-known text (WKT), like `POINT(-122.4 37.8)` or
+//! `POLYGON((0 0, 10 0, 10 10, 0 10, 0 0))`. Coordinates are longitude then latitude, in degrees.
+//!
+//! Polygons are usually geofences that are tested against every row, so each subtask caches them
+//! parsed and indexed by the bands of latitude that their edges cross. Testing whether a point is
+//! in one only checks the edges in the point's band.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// the mean radius of the earth, in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+const MAX_GEOHASH_PRECISION: i64 = 12;
+const MAX_CACHED_POLYGONS: usize = 1024;
+
+thread_local! {
+    static POLYGONS: RefCell<HashMap<String, Option<Rc<Polygon>>>> = RefCell::new(HashMap::new());
+}
+
+/// A point as WKT
+pub fn st_point(lon: f64, lat: f64) -> Option<String> {
+    (lon.is_finite() && lat.is_finite()).then(|| format!("POINT({} {})", lon, lat))
+}
+
+/// The great-circle distance in meters between two points, or None if either isn't a point
+pub fn st_distance(a: String, b: String) -> Option<f64> {
+    let (lon1, lat1) = parse_point(&a)?;
+    let (lon2, lat2) = parse_point(&b)?;
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    Some(2.0 * EARTH_RADIUS * h.sqrt().min(1.0).asin())
+}
+
+/// Whether a polygon or multipolygon contains a point, or None if they aren't valid
+pub fn st_contains(polygon: String, point: String) -> Option<bool> {
+    let (x, y) = parse_point(&point)?;
+    with_polygon(polygon, |polygon| polygon.contains(x, y))
+}
+
+/// Whether a point is within a polygon or multipolygon, or None if they aren't valid
+pub fn st_within(point: String, polygon: String) -> Option<bool> {
+    st_contains(polygon, point)
+}
+
+/// The geohash of a point with the given number of characters, from 1 to 12
+pub fn geohash_encode(lon: f64, lat: f64, precision: i64) -> Option<String> {
+    if !(1..=MAX_GEOHASH_PRECISION).contains(&precision)
+        || !(-180.0..=180.0).contains(&lon)
+        || !(-90.0..=90.0).contains(&lat)
+    {
+        return None;
+    }
+
+    let (mut lon_range, mut lat_range) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut hash = String::with_capacity(precision as usize);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            // bits alternate between longitude and latitude, starting with longitude
+            let (range, value) = if even {
+                (&mut lon_range, lon)
+            } else {
+                (&mut lat_range, lat)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(GEOHASH_ALPHABET[index] as char);
+    }
+    Some(hash)
+}
+
+/// The point at the center of the cell of a geohash, or None if it isn't a valid geohash
+pub fn geohash_decode(hash: String) -> Option<String> {
+    if hash.is_empty() || hash.len() as i64 > MAX_GEOHASH_PRECISION {
+        return None;
+    }
+
+    let (mut lon_range, mut lat_range) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut even = true;
+    for c in hash.to_ascii_lowercase().bytes() {
+        let index = GEOHASH_ALPHABET.iter().position(|a| *a == c)?;
+        for bit in (0..5).rev() {
+            let range: &mut (f64, f64) = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if index & (1 << bit) != 0 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+
+    st_point(
+        (lon_range.0 + lon_range.1) / 2.0,
+        (lat_range.0 + lat_range.1) / 2.0,
+    )
+}
+
+fn with_polygon<T>(wkt: String, f: impl FnOnce(&Polygon) -> T) -> Option<T> {
+    let polygon = POLYGONS.with(|polygons| {
+        let mut polygons = polygons.borrow_mut();
+        if let Some(polygon) = polygons.get(&wkt) {
+            return polygon.clone();
+        }
+        if polygons.len() >= MAX_CACHED_POLYGONS {
+            polygons.clear();
+        }
+        let polygon = Polygon::parse(&wkt).map(Rc::new);
+        polygons.insert(wkt, polygon.clone());
+        polygon
+    })?;
+    Some(f(&polygon))
+}
+
+fn parse_point(wkt: &str) -> Option<(f64, f64)> {
+    let body = strip_tag(wkt, "POINT")?;
+    let coordinates = body.strip_prefix('(')?.strip_suffix(')')?;
+    parse_coordinates(coordinates)
+}
+
+// the rest of the WKT after its geometry type, if it's of that type
+fn strip_tag<'a>(wkt: &'a str, tag: &str) -> Option<&'a str> {
+    let wkt = wkt.trim();
+    let (name, rest) = wkt.split_at(wkt.find('(')?);
+    name.trim().eq_ignore_ascii_case(tag).then(|| rest.trim())
+}
+
+fn parse_coordinates(s: &str) -> Option<(f64, f64)> {
+    let mut parts = s.split_whitespace();
+    let x = parts.next()?.parse::<f64>().ok()?;
+    let y = parts.next()?.parse::<f64>().ok()?;
+    (parts.next().is_none() && x.is_finite() && y.is_finite()).then_some((x, y))
+}
+
+/// The rings of a polygon or multipolygon, indexed by the horizontal bands that their edges
+/// cross. Points are tested with the even-odd rule, so holes don't contain their points.
+#[derive(Debug)]
+struct Polygon {
+    edges: Vec<((f64, f64), (f64, f64))>,
+    min: (f64, f64),
+    max: (f64, f64),
+    band_height: f64,
+    // the indices of the edges that cross each band, from the bottom of the polygon up
+    bands: Vec<Vec<usize>>,
+}
+
+impl Polygon {
+    fn parse(wkt: &str) -> Option<Self> {
+        let body = strip_tag(wkt, "POLYGON").or_else(|| strip_tag(wkt, "MULTIPOLYGON"))?;
+
+        // every innermost parenthesized list is a ring
+        let mut rings = vec![];
+        let mut start = None;
+        for (i, c) in body.char_indices() {
+            match c {
+                '(' => start = Some(i + 1),
+                ')' => {
+                    if let Some(start) = start.take() {
+                        let ring = body[start..i]
+                            .split(',')
+                            .map(parse_coordinates)
+                            .collect::<Option<Vec<_>>>()?;
+                        if ring.len() < 3 {
+                            return None;
+                        }
+                        rings.push(ring);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let edges: Vec<_> = rings
+            .iter()
+            .flat_map(|ring| {
+                // rings should be closed, but unclosed ones are closed here
+                ring.iter()
+                    .zip(ring.iter().cycle().skip(1))
+                    .map(|(a, b)| (*a, *b))
+                    .filter(|(a, b)| a != b)
+            })
+            .collect();
+        if edges.is_empty() {
+            return None;
+        }
+
+        let points = || edges.iter().map(|(a, _)| *a);
+        let min = points().fold((f64::MAX, f64::MAX), |m, p| (m.0.min(p.0), m.1.min(p.1)));
+        let max = points().fold((f64::MIN, f64::MIN), |m, p| (m.0.max(p.0), m.1.max(p.1)));
+
+        let band_count = (edges.len() / 4).clamp(1, 1024);
+        let band_height = ((max.1 - min.1) / band_count as f64).max(f64::MIN_POSITIVE);
+        let mut bands = vec![vec![]; band_count];
+        for (i, (a, b)) in edges.iter().enumerate() {
+            let low = Self::band(min.1, band_height, band_count, a.1.min(b.1));
+            let high = Self::band(min.1, band_height, band_count, a.1.max(b.1));
+            for band in &mut bands[low..=high] {
+                band.push(i);
+            }
+        }
+
+        Some(Self {
+            edges,
+            min,
+            max,
+            band_height,
+            bands,
+        })
+    }
+
+    fn band(min_y: f64, band_height: f64, band_count: usize, y: f64) -> usize {
+        (((y - min_y) / band_height) as usize).min(band_count - 1)
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        if x < self.min.0 || x > self.max.0 || y < self.min.1 || y > self.max.1 {
+            return false;
+        }
+
+        let band = Self::band(self.min.1, self.band_height, self.bands.len(), y);
+        let mut inside = false;
+        for i in &self.bands[band] {
+            let ((x1, y1), (x2, y2)) = self.edges[*i];
+            // counts the edges that a ray from the point to the east crosses
+            if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE_WITH_HOLE: &str =
+        "POLYGON((0 0, 10 0, 10 10, 0 10, 0 0), (4 4, 6 4, 6 6, 4 6, 4 4))";
+
+    fn point(x: f64, y: f64) -> String {
+        st_point(x, y).unwrap()
+    }
+
+    #[test]
+    fn test_contains() {
+        let polygon = SQUARE_WITH_HOLE.to_string();
+        assert_eq!(st_contains(polygon.clone(), point(1.0, 1.0)), Some(true));
+        assert_eq!(st_contains(polygon.clone(), point(5.0, 5.0)), Some(false));
+        assert_eq!(st_contains(polygon.clone(), point(11.0, 1.0)), Some(false));
+        assert_eq!(st_within(point(9.5, 2.0), polygon.clone()), Some(true));
+        assert_eq!(st_contains(polygon, "POINT(1)".to_string()), None);
+
+        let multi = "MULTIPOLYGON(((0 0, 1 0, 1 1, 0 1, 0 0)), ((5 5, 6 5, 6 6, 5 6, 5 5)))";
+        assert_eq!(st_contains(multi.to_string(), point(5.5, 5.5)), Some(true));
+        assert_eq!(st_contains(multi.to_string(), point(3.0, 3.0)), Some(false));
+        assert_eq!(
+            st_contains("POLYGON((0 0, 1 1))".to_string(), point(0.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_contains_many_edges() {
+        // a circle with enough edges to be split into many bands
+        let ring: Vec<_> = (0..=360)
+            .map(|i| {
+                let angle = (i as f64).to_radians();
+                format!("{} {}", angle.cos() * 10.0, angle.sin() * 10.0)
+            })
+            .collect();
+        let circle = format!("POLYGON(({}))", ring.join(", "));
+
+        for (x, y, inside) in [
+            (0.0, 0.0, true),
+            (9.9, 0.0, true),
+            (0.0, -9.9, true),
+            (7.2, 7.2, false),
+            (-10.1, 0.0, false),
+        ] {
+            assert_eq!(
+                st_contains(circle.clone(), point(x, y)),
+                Some(inside),
+                "{} {}",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        // about 5,570 km from New York to London
+        let distance = st_distance(point(-74.006, 40.7128), point(-0.1278, 51.5074)).unwrap();
+        assert!((distance - 5_570_000.0).abs() < 10_000.0, "{}", distance);
+        assert_eq!(st_distance(point(1.0, 1.0), point(1.0, 1.0)), Some(0.0));
+        assert_eq!(
+            st_distance(point(1.0, 1.0), SQUARE_WITH_HOLE.to_string()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_geohash() {
+        assert_eq!(geohash_encode(-5.6, 42.6, 5), Some("ezs42".to_string()));
+        assert_eq!(geohash_encode(-5.6, 42.6, 13), None);
+        assert_eq!(geohash_encode(-5.6, 95.0, 5), None);
+
+        let center = geohash_decode("ezs42".to_string()).unwrap();
+        let (lon, lat) = parse_point(&center).unwrap();
+        assert!((lon - -5.6).abs() < 0.03 && (lat - 42.6).abs() < 0.03);
+        assert_eq!(geohash_decode("ezs4a".to_string()), None);
+    }
+}
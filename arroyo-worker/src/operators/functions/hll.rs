@@ -0,0 +1,162 @@
+//! HyperLogLog sketches for approximate distinct counts.
+//!
+//! Sketches are stored in their serialized form, a version byte followed by one byte per
+//! register, so that they can be kept in state, merged across bins and subtasks, and emitted
+//! as `BYTEA` values that downstream queries can merge further with `hll_union_agg`. Values are
+//! hashed from their bincode encoding with a fixed hash function, so sketches built by different
+//! pipelines over the same values are compatible.
+
+use bincode::enc::write::Writer;
+use bincode::error::EncodeError;
+use bincode::Encode;
+
+const VERSION: u8 = 1;
+// 2^12 registers, for a standard error of about 1.6%
+const PRECISION: u32 = 12;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// An empty sketch
+pub fn new_sketch() -> Vec<u8> {
+    let mut sketch = vec![0; REGISTERS + 1];
+    sketch[0] = VERSION;
+    sketch
+}
+
+/// Whether the bytes are a sketch produced by this version of the format
+pub fn is_valid(sketch: &[u8]) -> bool {
+    sketch.len() == REGISTERS + 1 && sketch[0] == VERSION
+}
+
+/// Adds a value to the sketch
+pub fn add<T: Encode>(sketch: &mut [u8], value: &T) {
+    let hash = hash(value);
+    let index = (hash >> (64 - PRECISION)) as usize;
+    // the position of the first set bit in the rest of the hash, counting from 1
+    let rank = ((hash << PRECISION).leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+    let register = &mut sketch[index + 1];
+    *register = (*register).max(rank);
+}
+
+/// Merges `other` into `sketch`, so that it estimates the distinct values added to either.
+/// Returns false, leaving the sketch unchanged, if `other` isn't a valid sketch.
+pub fn merge(sketch: &mut [u8], other: &[u8]) -> bool {
+    if !is_valid(other) {
+        return false;
+    }
+    for (register, other) in sketch[1..].iter_mut().zip(&other[1..]) {
+        *register = (*register).max(*other);
+    }
+    true
+}
+
+/// The estimated number of distinct values added to the sketch, or None if it isn't valid
+pub fn estimate(sketch: &[u8]) -> Option<i64> {
+    if !is_valid(sketch) {
+        return None;
+    }
+    let registers = &sketch[1..];
+    let m = REGISTERS as f64;
+
+    let mut sum = 0.0;
+    let mut zeros = 0;
+    for register in registers {
+        sum += 1.0 / (1u64 << register) as f64;
+        if *register == 0 {
+            zeros += 1;
+        }
+    }
+
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let raw = alpha * m * m / sum;
+    // small cardinalities are estimated more accurately by counting the empty registers
+    let estimate = if raw <= 2.5 * m && zeros > 0 {
+        m * (m / zeros as f64).ln()
+    } else {
+        raw
+    };
+    Some(estimate.round() as i64)
+}
+
+pub fn hll_sketch_estimate(sketch: Vec<u8>) -> Option<i64> {
+    estimate(&sketch)
+}
+
+fn hash<T: Encode>(value: &T) -> u64 {
+    let mut state = FNV_OFFSET;
+    bincode::encode_into_writer(value, HashWriter(&mut state), bincode::config::standard())
+        .expect("encoding into a hash can't fail");
+    fmix64(state)
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Feeds encoded bytes into an FNV-1a hash
+struct HashWriter<'a>(&'a mut u64);
+
+impl Writer for HashWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        for byte in bytes {
+            *self.0 ^= *byte as u64;
+            *self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+        Ok(())
+    }
+}
+
+// the murmur3 finalizer, which spreads FNV's output across all of the bits that pick registers
+fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(estimate: i64, actual: i64) {
+        let error = (estimate - actual).abs() as f64 / actual as f64;
+        assert!(
+            error < 0.05,
+            "estimate {} is too far from {}",
+            estimate,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_estimate() {
+        let mut sketch = new_sketch();
+        assert_eq!(estimate(&sketch), Some(0));
+
+        for i in 0..100_000i64 {
+            add(&mut sketch, &(i % 20_000));
+        }
+        assert_close(estimate(&sketch).unwrap(), 20_000);
+
+        let mut small = new_sketch();
+        for s in ["a", "b", "c", "a"] {
+            add(&mut small, &s.to_string());
+        }
+        assert_eq!(estimate(&small), Some(3));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut left = new_sketch();
+        let mut right = new_sketch();
+        for i in 0..30_000i64 {
+            add(&mut left, &i);
+            add(&mut right, &(i + 10_000));
+        }
+        assert!(merge(&mut left, &right));
+        assert_close(estimate(&left).unwrap(), 40_000);
+
+        assert!(!merge(&mut left, &[1, 2, 3]));
+        assert_eq!(estimate(&[1, 2, 3]), None);
+    }
+}
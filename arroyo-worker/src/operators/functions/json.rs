@@ -27,3 +27,73 @@ pub fn extract_json_string(json_str: String, path: String) -> Option<String> {
         _ => None,
     }
 }
+
+/// The scalar at the path as text, or None if it's an object, an array or null
+pub fn json_value(json_str: String, path: String) -> Option<String> {
+    let value: Value = serde_json::from_str(&json_str).ok()?;
+    let path = JsonPath::parse(&path).ok()?;
+    match path.query(&value).first()? {
+        Value::String(value) => Some(value.to_string()),
+        value @ (Value::Number(_) | Value::Bool(_)) => Some(value.to_string()),
+        Value::Null | Value::Object(_) | Value::Array(_) => None,
+    }
+}
+
+/// The object or array at the path as JSON, or None if it's a scalar
+pub fn json_query(json_str: String, path: String) -> Option<String> {
+    let value: Value = serde_json::from_str(&json_str).ok()?;
+    let path = JsonPath::parse(&path).ok()?;
+    match path.query(&value).first()? {
+        value @ (Value::Object(_) | Value::Array(_)) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// The elements of a JSON array as JSON, or None if it isn't an array
+pub fn json_array_elements(json_str: String) -> Option<Vec<String>> {
+    match serde_json::from_str(&json_str).ok()? {
+        Value::Array(elements) => Some(elements.iter().map(|v| v.to_string()).collect()),
+        _ => None,
+    }
+}
+
+pub fn is_json(json_str: String) -> bool {
+    serde_json::from_str::<serde::de::IgnoredAny>(&json_str).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_value_and_query() {
+        let json = r#"{"a": {"b": [1, 2]}, "c": "text", "d": 1.5, "e": null}"#.to_string();
+        let value = |path: &str| json_value(json.clone(), path.to_string());
+        let query = |path: &str| json_query(json.clone(), path.to_string());
+
+        assert_eq!(value("$.c"), Some("text".to_string()));
+        assert_eq!(value("$.d"), Some("1.5".to_string()));
+        assert_eq!(value("$.a.b[1]"), Some("2".to_string()));
+        assert_eq!(value("$.a"), None);
+        assert_eq!(value("$.e"), None);
+
+        assert_eq!(query("$.a"), Some(r#"{"b":[1,2]}"#.to_string()));
+        assert_eq!(query("$.a.b"), Some("[1,2]".to_string()));
+        assert_eq!(query("$.c"), None);
+    }
+
+    #[test]
+    fn test_json_array_elements() {
+        assert_eq!(
+            json_array_elements(r#"[1, "a", {"b": true}]"#.to_string()),
+            Some(vec![
+                "1".to_string(),
+                "\"a\"".to_string(),
+                r#"{"b":true}"#.to_string()
+            ])
+        );
+        assert_eq!(json_array_elements("{}".to_string()), None);
+        assert!(is_json("[1, 2]".to_string()));
+        assert!(!is_json("{1, 2}".to_string()));
+    }
+}
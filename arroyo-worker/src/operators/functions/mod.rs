@@ -1,5 +1,8 @@
 pub mod datetime;
+pub mod ddsketch;
+pub mod geo;
 pub mod hash;
+pub mod hll;
 pub mod json;
 pub mod regexp;
 pub mod strings;
@@ -1,8 +1,32 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
 use regex::Regex;
 
+const MAX_CACHED_REGEXES: usize = 256;
+
+thread_local! {
+    static REGEXES: RefCell<HashMap<String, Option<Regex>>> = RefCell::new(HashMap::new());
+}
+
+// regexes are almost always literals, so they're compiled once per thread rather than per row.
+// Returns None if the regex is invalid.
+fn with_regex<T>(regex: String, f: impl FnOnce(&Regex) -> T) -> Option<T> {
+    REGEXES.with(|regexes| {
+        let mut regexes = regexes.borrow_mut();
+        if !regexes.contains_key(&regex) {
+            if regexes.len() >= MAX_CACHED_REGEXES {
+                regexes.clear();
+            }
+            let compiled = Regex::new(&regex).ok();
+            regexes.insert(regex.clone(), compiled);
+        }
+        regexes.get(&regex).unwrap().as_ref().map(f)
+    })
+}
+
 pub fn regexp_match(argument: String, regex: String) -> Vec<String> {
-    let re = Regex::new(&regex).unwrap();
-    match re.captures(argument.as_str()) {
+    with_regex(regex, |re| match re.captures(argument.as_str()) {
         Some(caps) => caps
             .iter()
             .enumerate()
@@ -15,13 +39,31 @@ pub fn regexp_match(argument: String, regex: String) -> Vec<String> {
             })
             .collect(),
         None => vec![],
-    }
+    })
+    .expect("invalid regex")
 }
 
 pub fn regexp_replace(argument: String, regex: String, replacement: String) -> String {
-    let re = Regex::new(&regex).unwrap();
-    let result = re.replace_all(&argument, replacement.as_str());
-    result.into_owned()
+    with_regex(regex, |re| {
+        re.replace_all(&argument, replacement.as_str()).into_owned()
+    })
+    .expect("invalid regex")
+}
+
+/// The text matched by a group of the first match of the regex, where group 0 is the whole
+/// match, or None if it doesn't match or the group didn't participate in the match
+pub fn regexp_extract(argument: String, regex: String, group: i64) -> Option<String> {
+    let group = usize::try_from(group).ok()?;
+    with_regex(regex, |re| {
+        re.captures(&argument)?
+            .get(group)
+            .map(|m| m.as_str().to_string())
+    })
+    .flatten()
+}
+
+pub fn regexp_like(argument: String, regex: String) -> bool {
+    with_regex(regex, |re| re.is_match(&argument)).expect("invalid regex")
 }
 
 #[cfg(test)]
@@ -45,4 +87,32 @@ mod tests {
         );
         assert_eq!(result.as_str(), "ThoXXs");
     }
+
+    #[test]
+    pub fn test_regexp_extract() {
+        let regex = || String::from(r"(\w+)@(\w+)\.com");
+        let input = || String::from("mail alice@example.com now");
+        assert_eq!(
+            regexp_extract(input(), regex(), 0).as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(
+            regexp_extract(input(), regex(), 2).as_deref(),
+            Some("example")
+        );
+        assert_eq!(regexp_extract(input(), regex(), 3), None);
+        assert_eq!(regexp_extract(String::from("no mail"), regex(), 1), None);
+    }
+
+    #[test]
+    pub fn test_regexp_like() {
+        assert!(regexp_like(
+            String::from("order-123"),
+            String::from(r"^order-\d+$")
+        ));
+        assert!(!regexp_like(
+            String::from("order-abc"),
+            String::from(r"^order-\d+$")
+        ));
+    }
 }
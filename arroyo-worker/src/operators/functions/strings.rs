@@ -187,3 +187,108 @@ pub fn rtrim(string: String, trimming_characters: String) -> String {
     let char_slice: &[char] = &chars;
     string.trim_end_matches(char_slice).to_string()
 }
+
+pub fn split_to_array(s: String, delimiter: String) -> Vec<String> {
+    if delimiter.is_empty() {
+        return vec![s];
+    }
+    s.split(&delimiter).map(|part| part.to_string()).collect()
+}
+
+/// A part of a URL, one of HOST, PATH, QUERY, REF, PROTOCOL, AUTHORITY, FILE or USERINFO, or
+/// the value of a query parameter if a key is given for QUERY. None if the URL is invalid or
+/// doesn't have that part.
+pub fn parse_url(url: String, part: String, key: Option<String>) -> Option<String> {
+    use url::{Position, Url};
+
+    let url = Url::parse(&url).ok()?;
+    let part = match part.to_ascii_uppercase().as_str() {
+        "QUERY" => match key {
+            Some(key) => url
+                .query_pairs()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.into_owned()),
+            None => url.query().map(|q| q.to_string()),
+        },
+        _ if key.is_some() => None,
+        "HOST" => url.host_str().map(|h| h.to_string()),
+        "PATH" => Some(url.path().to_string()),
+        "REF" => url.fragment().map(|f| f.to_string()),
+        "PROTOCOL" => Some(url.scheme().to_string()),
+        "AUTHORITY" => Some(url[Position::BeforeUsername..Position::AfterPort].to_string()),
+        "FILE" => Some(url[Position::BeforePath..Position::AfterQuery].to_string()),
+        "USERINFO" => Some(url[Position::BeforeUsername..Position::AfterPassword].to_string()),
+        _ => None,
+    }?;
+    (!part.is_empty()).then_some(part)
+}
+
+/// Formats a string like Postgres's format, where each %s is replaced by the next argument, or
+/// the empty string if it's null, and %% by %. None if there are too few arguments or the
+/// format has any other specifiers.
+pub fn format(format: String, args: Vec<Option<String>>) -> Option<String> {
+    let mut result = String::with_capacity(format.len());
+    let mut args = args.into_iter();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '%' => result.push('%'),
+            's' => result.push_str(&args.next()?.unwrap_or_default()),
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = || "https://user:pw@example.com:8080/a/b.html?x=1&y=two#frag".to_string();
+        let part = |part: &str| parse_url(url(), part.to_string(), None);
+        assert_eq!(part("HOST").as_deref(), Some("example.com"));
+        assert_eq!(part("path").as_deref(), Some("/a/b.html"));
+        assert_eq!(part("QUERY").as_deref(), Some("x=1&y=two"));
+        assert_eq!(part("REF").as_deref(), Some("frag"));
+        assert_eq!(part("PROTOCOL").as_deref(), Some("https"));
+        assert_eq!(
+            part("AUTHORITY").as_deref(),
+            Some("user:pw@example.com:8080")
+        );
+        assert_eq!(part("FILE").as_deref(), Some("/a/b.html?x=1&y=two"));
+        assert_eq!(part("USERINFO").as_deref(), Some("user:pw"));
+        assert_eq!(part("PORT"), None);
+        assert_eq!(
+            parse_url(url(), "QUERY".to_string(), Some("y".to_string())).as_deref(),
+            Some("two")
+        );
+        assert_eq!(
+            parse_url(url(), "QUERY".to_string(), Some("z".to_string())),
+            None
+        );
+        assert_eq!(
+            parse_url("not a url".to_string(), "HOST".to_string(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format(
+                "%s is 100%% %s".to_string(),
+                vec![Some("this".to_string()), None]
+            )
+            .as_deref(),
+            Some("this is 100% ")
+        );
+        assert_eq!(format("%s %s".to_string(), vec![None]), None);
+        assert_eq!(format("%d".to_string(), vec![None]), None);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
+
+use arroyo_macro::{co_process_fn, StreamNode};
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::key_time_multi_map::KeyTimeMultiMap;
+use arroyo_types::*;
+
+use crate::engine::Context;
+
+/// Inner join of rows with the same key whose times are within a band of each other, such that
+/// `lower_bound <= left time - right time <= upper_bound`, in nanoseconds. Rows are only kept for
+/// as long as rows that may still arrive on the other side could fall in their band, so state
+/// is bounded by the width of the band rather than growing with the input.
+#[derive(StreamNode)]
+pub struct IntervalJoin<K: Key, T1: Data, T2: Data> {
+    lower_bound: i64,
+    upper_bound: i64,
+    _t: PhantomData<(K, T1, T2)>,
+}
+
+#[co_process_fn(in_k1=K, in_t1=T1, in_k2=K, in_t2=T2, out_k=K, out_t=(T1, T2))]
+impl<K: Key, T1: Data, T2: Data> IntervalJoin<K, T1, T2> {
+    fn name(&self) -> String {
+        "IntervalJoin".to_string()
+    }
+
+    pub fn new(lower_bound: i64, upper_bound: i64) -> Self {
+        assert!(
+            lower_bound <= upper_bound,
+            "the lower bound of an interval join must not be above its upper bound"
+        );
+
+        Self {
+            lower_bound,
+            upper_bound,
+            _t: PhantomData,
+        }
+    }
+
+    /// How long before the watermark left rows may still join with right rows that arrive
+    fn left_retention(&self) -> Duration {
+        Duration::from_nanos((-self.lower_bound).max(0) as u64)
+    }
+
+    /// How long before the watermark right rows may still join with left rows that arrive
+    fn right_retention(&self) -> Duration {
+        Duration::from_nanos(self.upper_bound.max(0) as u64)
+    }
+
+    fn in_band(&self, left: SystemTime, right: SystemTime) -> bool {
+        let difference = to_nanos(left) as i128 - to_nanos(right) as i128;
+        (self.lower_bound as i128..=self.upper_bound as i128).contains(&difference)
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![
+            TableDescriptor {
+                name: "l".to_string(),
+                description: "join left state".to_string(),
+                table_type: TableType::KeyTimeMultiMap as i32,
+                delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+                write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+                retention_micros: self.left_retention().as_micros() as u64,
+            },
+            TableDescriptor {
+                name: "r".to_string(),
+                description: "join right state".to_string(),
+                table_type: TableType::KeyTimeMultiMap as i32,
+                delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+                write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+                retention_micros: self.right_retention().as_micros() as u64,
+            },
+        ]
+    }
+
+    async fn process_left(&mut self, record: &Record<K, T1>, ctx: &mut Context<K, (T1, T2)>) {
+        if let Some(watermark) = ctx.last_present_watermark() {
+            if record.timestamp < watermark {
+                return;
+            }
+        };
+        let mut key = record.key.clone().unwrap();
+
+        let mut right_state: KeyTimeMultiMap<K, T2, _> =
+            ctx.state.get_key_time_multi_map('r').await;
+        let records: Vec<_> = right_state
+            .get_all_values_with_timestamps(&mut key)
+            .await
+            .map(|rows| {
+                rows.filter(|(timestamp, _)| self.in_band(record.timestamp, *timestamp))
+                    .map(|(timestamp, right)| Record {
+                        timestamp: record.timestamp.max(timestamp),
+                        key: Some(key.clone()),
+                        value: (record.value.clone(), right.clone()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for record in records {
+            ctx.collect(record).await;
+        }
+
+        let mut left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
+        left_state
+            .insert(record.timestamp, key, record.value.clone())
+            .await;
+    }
+
+    async fn process_right(&mut self, record: &Record<K, T2>, ctx: &mut Context<K, (T1, T2)>) {
+        if let Some(watermark) = ctx.last_present_watermark() {
+            if record.timestamp < watermark {
+                return;
+            }
+        };
+        let mut key = record.key.clone().unwrap();
+
+        let mut left_state: KeyTimeMultiMap<K, T1, _> = ctx.state.get_key_time_multi_map('l').await;
+        let records: Vec<_> = left_state
+            .get_all_values_with_timestamps(&mut key)
+            .await
+            .map(|rows| {
+                rows.filter(|(timestamp, _)| self.in_band(*timestamp, record.timestamp))
+                    .map(|(timestamp, left)| Record {
+                        timestamp: record.timestamp.max(timestamp),
+                        key: Some(key.clone()),
+                        value: (left.clone(), record.value.clone()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for record in records {
+            ctx.collect(record).await;
+        }
+
+        let mut right_state: KeyTimeMultiMap<K, T2, _> =
+            ctx.state.get_key_time_multi_map('r').await;
+        right_state
+            .insert(record.timestamp, key, record.value.clone())
+            .await;
+    }
+
+    async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, (T1, T2)>) {
+        if let Watermark::EventTime(watermark) = watermark {
+            let mut left_state: KeyTimeMultiMap<K, T1, _> =
+                ctx.state.get_key_time_multi_map('l').await;
+            left_state
+                .expire_entries_before(watermark - self.left_retention())
+                .await;
+
+            let mut right_state: KeyTimeMultiMap<K, T2, _> =
+                ctx.state.get_key_time_multi_map('r').await;
+            right_state
+                .expire_entries_before(watermark - self.right_retention())
+                .await;
+        }
+
+        ctx.broadcast(arroyo_types::Message::Watermark(watermark))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_band() {
+        let minute = 60 * 1_000_000_000;
+        // left rows may come up to ten minutes before right rows, but not after
+        let join = IntervalJoin::<(), (), ()>::new(-10 * minute, 0);
+
+        let right = from_millis(60 * 60 * 1000);
+        assert!(join.in_band(right, right));
+        assert!(join.in_band(right - Duration::from_secs(10 * 60), right));
+        assert!(!join.in_band(right - Duration::from_secs(11 * 60), right));
+        assert!(!join.in_band(right + Duration::from_nanos(1), right));
+
+        assert_eq!(join.left_retention(), Duration::from_secs(10 * 60));
+        assert_eq!(join.right_retention(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,49 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::engine::{Context, StreamNode};
+use crate::operators::window_alignment::WindowAlignment;
+use arroyo_macro::process_fn;
+use arroyo_types::*;
+
+/// Passes on only the records that a tumbling window with the same width, alignment and allowed
+/// lateness drops for being too late, so that they can be written somewhere for auditing. It
+/// reads the same input as the window, and so sees the same watermarks.
+#[derive(StreamNode)]
+pub struct LateDataFilter<K: Key, T: Data> {
+    width: Duration,
+    alignment: WindowAlignment,
+    allowed_lateness: Duration,
+    _t: PhantomData<(K, T)>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = T)]
+impl<K: Key, T: Data> LateDataFilter<K, T> {
+    fn name(&self) -> String {
+        "LateDataFilter".to_string()
+    }
+
+    pub fn new(width: Duration, alignment: WindowAlignment, allowed_lateness: Duration) -> Self {
+        Self {
+            width,
+            alignment,
+            allowed_lateness,
+            _t: PhantomData,
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, T>) {
+        let Some(watermark) = ctx.last_present_watermark() else {
+            return;
+        };
+
+        if self.alignment.is_late(
+            record.timestamp,
+            self.width,
+            self.allowed_lateness,
+            watermark,
+        ) {
+            ctx.collect(record.clone()).await;
+        }
+    }
+}
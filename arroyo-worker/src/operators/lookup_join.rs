@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::TableDescriptor;
+use arroyo_rpc::OperatorConfig;
+use arroyo_types::*;
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::engine::{Context, StreamNode};
+use crate::formats::DataDeserializer;
+use crate::operators::async_map::AsyncCache;
+use crate::SchemaData;
+
+// the maximum number of rows to buffer before looking up their keys
+const BATCH_SIZE: usize = 100;
+
+/// An external table that rows can be looked up in by key, like a Redis keyspace or a Postgres
+/// table. Lookup sources are constructed with the operator config of their table and the name of
+/// the column the join is keyed on.
+#[async_trait]
+pub trait LookupSource: Send + 'static {
+    /// Fetches the serialized rows for each of the keys, in the same order, or None for keys
+    /// that have no row
+    async fn lookup(&mut self, keys: &[String]) -> anyhow::Result<Vec<Option<Vec<u8>>>>;
+}
+
+/// Joins each row with the row of an external table that has the same key, as in
+/// `JOIN customers FOR SYSTEM_TIME AS OF o.proc_time ON o.customer_id = customers.id`. Rows are
+/// buffered briefly so that their keys can be looked up in batches, and the results (including
+/// misses) are cached, evicting the least recently used. The external table is read as of when the lookup is made, so changes to it
+/// are only seen once cached results expire.
+#[derive(StreamNode)]
+pub struct LookupJoinFunc<K: Key, T: Data, LookupT: SchemaData, OutT: Data> {
+    source: Box<dyn LookupSource>,
+    deserializer: DataDeserializer<LookupT>,
+    key: fn(&T) -> Option<String>,
+    merge: fn(&T, Option<&LookupT>) -> Option<OutT>,
+    cache: AsyncCache<String, Option<LookupT>>,
+    buffer: Vec<Record<K, T>>,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = OutT, tick_ms = 100)]
+impl<K: Key, T: Data, LookupT: SchemaData, OutT: Data> LookupJoinFunc<K, T, LookupT, OutT> {
+    fn name(&self) -> String {
+        "LookupJoin".to_string()
+    }
+
+    pub fn new(
+        config: &str,
+        source: Box<dyn LookupSource>,
+        key: fn(&T) -> Option<String>,
+        merge: fn(&T, Option<&LookupT>) -> Option<OutT>,
+        cache_size: usize,
+        cache_ttl: Option<Duration>,
+    ) -> Self {
+        let config: OperatorConfig =
+            serde_json::from_str(config).expect("Invalid config for LookupJoin");
+
+        Self {
+            source,
+            deserializer: DataDeserializer::new(
+                config
+                    .format
+                    .expect("Format must be defined for lookup tables"),
+                config.framing,
+            ),
+            key,
+            merge,
+            cache: AsyncCache::new(cache_size, cache_ttl),
+            buffer: vec![],
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![]
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        self.buffer.push(record.clone());
+        if self.buffer.len() >= BATCH_SIZE {
+            self.flush(ctx).await;
+        }
+    }
+
+    async fn handle_tick(&mut self, _: u64, ctx: &mut Context<K, OutT>) {
+        self.flush(ctx).await;
+    }
+
+    async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, OutT>) {
+        // buffered rows are emitted first so that they aren't late
+        self.flush(ctx).await;
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    async fn handle_checkpoint(&mut self, _: &CheckpointBarrier, ctx: &mut Context<K, OutT>) {
+        self.flush(ctx).await;
+    }
+
+    async fn on_close(&mut self, ctx: &mut Context<K, OutT>) {
+        self.flush(ctx).await;
+    }
+
+    fn deserialize(&self, key: &str, row: &[u8]) -> Option<LookupT> {
+        match self.deserializer.deserialize_slice(row).next()? {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!(
+                    "Failed to deserialize lookup row for key '{}': {:?}",
+                    key, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn flush(&mut self, ctx: &mut Context<K, OutT>) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let buffer = std::mem::take(&mut self.buffer);
+        let keys: Vec<Option<String>> = buffer.iter().map(|r| (self.key)(&r.value)).collect();
+
+        let mut missing = vec![];
+        for key in keys.iter().flatten() {
+            if self.cache.get(key).is_none() {
+                missing.push(key.clone());
+            }
+        }
+        missing.sort();
+        missing.dedup();
+
+        let mut fetched = HashMap::new();
+        if !missing.is_empty() {
+            let rows = match self.source.lookup(&missing).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    ctx.report_error("Lookup failed".to_string(), format!("{:?}", e))
+                        .await;
+                    panic!("Lookup failed: {:?}", e);
+                }
+            };
+
+            for (key, row) in missing.into_iter().zip(rows) {
+                let value = row.and_then(|row| self.deserialize(&key, &row));
+                fetched.insert(key, value);
+            }
+        }
+
+        for (record, key) in buffer.iter().zip(&keys) {
+            // null keys never match
+            let row = key.as_ref().and_then(|key| match fetched.get(key) {
+                Some(row) => row.as_ref(),
+                None => self.cache.get(key).and_then(|row| row.as_ref()),
+            });
+
+            if let Some(value) = (self.merge)(&record.value, row) {
+                ctx.collect(Record {
+                    timestamp: record.timestamp,
+                    key: record.key.clone(),
+                    value,
+                })
+                .await;
+            }
+        }
+
+        for (key, row) in fetched {
+            self.cache.insert(key, row);
+        }
+    }
+}
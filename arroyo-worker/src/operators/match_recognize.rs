@@ -0,0 +1,436 @@
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::keyed_map::KeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+
+/// A partial match of the pattern
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+struct Run<T> {
+    // the sequence number of the first row of the run
+    start: u64,
+    start_time: SystemTime,
+    // the pattern element that the last row matched, and how many consecutive rows matched it
+    element: usize,
+    count: u64,
+    // the rows of the run, along with the element each of them matched
+    rows: Vec<(usize, T)>,
+}
+
+impl<T> Run<T> {
+    fn end(&self) -> u64 {
+        self.start + self.rows.len() as u64 - 1
+    }
+}
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+struct Partition<T> {
+    // rows that the watermark hasn't passed yet, in event-time order
+    pending: Vec<(SystemTime, T)>,
+    // partial matches, ordered by their start
+    runs: Vec<Run<T>>,
+    // the preferred complete match so far, which is emitted once no run that may be preferred
+    // over it remains
+    candidate: Option<Run<T>>,
+    // the sequence number of the next row
+    seq: u64,
+}
+
+impl<T> Default for Partition<T> {
+    fn default() -> Self {
+        Self {
+            pending: vec![],
+            runs: vec![],
+            candidate: None,
+            seq: 0,
+        }
+    }
+}
+
+impl<T> Partition<T> {
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.runs.is_empty() && self.candidate.is_none()
+    }
+}
+
+/// Finds sequences of rows that match a pattern within each key, as in `MATCH_RECOGNIZE`. The
+/// pattern is a list of elements with a minimum and optional maximum number of consecutive rows;
+/// `predicate(row, element)` decides whether a row may match an element. Rows are processed in
+/// event-time order once the watermark passes them, and every match is emitted as
+/// `measures(rows)`, where each row is paired with the element it matched.
+///
+/// Matches follow the usual semantics: quantifiers are greedy, matches that start earlier are
+/// preferred, and matching continues after the last row of a match. With `within`, a match must
+/// end within that duration of its first row.
+#[derive(StreamNode)]
+pub struct MatchRecognizeFunc<K: Key, T: Data, OutT: Data> {
+    pattern: Vec<(u64, Option<u64>)>,
+    within: Option<Duration>,
+    predicate: fn(&T, usize) -> bool,
+    measures: fn(&[(usize, T)]) -> OutT,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = OutT, timer_t = SystemTime)]
+impl<K: Key, T: Data, OutT: Data> MatchRecognizeFunc<K, T, OutT> {
+    fn name(&self) -> String {
+        "MatchRecognize".to_string()
+    }
+
+    pub fn new(
+        pattern: Vec<(u64, Option<u64>)>,
+        within: Option<Duration>,
+        predicate: fn(&T, usize) -> bool,
+        measures: fn(&[(usize, T)]) -> OutT,
+    ) -> Self {
+        assert!(!pattern.is_empty(), "pattern must not be empty");
+        assert!(
+            pattern
+                .iter()
+                .all(|(min, max)| max.map(|max| max >= 1 && max >= *min).unwrap_or(true)),
+            "pattern quantifiers must allow at least one row"
+        );
+
+        Self {
+            pattern,
+            within,
+            predicate,
+            measures,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![TableDescriptor {
+            name: "p".to_string(),
+            description: "partitions".to_string(),
+            table_type: TableType::TimeKeyMap as i32,
+            // partitions are removed once they have no pending rows or runs
+            delete_behavior: TableDeleteBehavior::None as i32,
+            write_behavior: TableWriteBehavior::DefaultWrites as i32,
+            retention_micros: 0,
+        }]
+    }
+
+    fn is_complete(&self, element: usize, count: u64) -> bool {
+        count >= self.pattern[element].0
+            && self.pattern[element + 1..].iter().all(|(min, _)| *min == 0)
+    }
+
+    fn can_extend(&self, element: usize, count: u64) -> bool {
+        element + 1 < self.pattern.len()
+            || self.pattern[element]
+                .1
+                .map(|max| count < max)
+                .unwrap_or(true)
+    }
+
+    /// The states that `value` moves a run in state `from` to, with staying in the current
+    /// element first; `None` is the state before any row has matched
+    fn successors(&self, from: Option<(usize, u64)>, value: &T) -> Vec<(usize, u64)> {
+        let mut next = vec![];
+
+        let first = match from {
+            Some((element, count)) => {
+                let (min, max) = self.pattern[element];
+                if max.map(|max| count < max).unwrap_or(true) && (self.predicate)(value, element) {
+                    next.push((element, count + 1));
+                }
+                if count < min {
+                    return next;
+                }
+                element + 1
+            }
+            None => 0,
+        };
+
+        // optional elements may be skipped
+        for element in first..self.pattern.len() {
+            if (self.predicate)(value, element) {
+                next.push((element, 1));
+            }
+            if self.pattern[element].0 > 0 {
+                break;
+            }
+        }
+
+        next
+    }
+
+    /// Feeds the next row to the partition's runs, returning the matches that are now final
+    fn advance(&self, p: &mut Partition<T>, time: SystemTime, value: &T) -> Vec<Vec<(usize, T)>> {
+        let seq = p.seq;
+        p.seq += 1;
+
+        if let Some(within) = self.within {
+            p.runs
+                .retain(|r| time.duration_since(r.start_time).unwrap_or_default() <= within);
+        }
+
+        let fresh = Run {
+            start: seq,
+            start_time: time,
+            element: 0,
+            count: 0,
+            rows: vec![],
+        };
+
+        let mut runs: Vec<Run<T>> = vec![];
+        let froms = p
+            .runs
+            .iter()
+            .map(|r| (r, Some((r.element, r.count))))
+            .chain(std::iter::once((&fresh, None)));
+
+        for (run, from) in froms {
+            for (element, count) in self.successors(from, value) {
+                // the first of several ways to reach the same state from the same start is the
+                // greedy one
+                if runs
+                    .iter()
+                    .any(|r| r.start == run.start && r.element == element && r.count == count)
+                {
+                    continue;
+                }
+
+                let mut rows = run.rows.clone();
+                rows.push((element, value.clone()));
+                runs.push(Run {
+                    start: run.start,
+                    start_time: run.start_time,
+                    element,
+                    count,
+                    rows,
+                });
+            }
+        }
+
+        for run in &runs {
+            if !self.is_complete(run.element, run.count) {
+                continue;
+            }
+
+            let preferred = match &p.candidate {
+                None => true,
+                Some(c) => run.start < c.start || (run.start == c.start && run.end() > c.end()),
+            };
+            if preferred {
+                p.candidate = Some(run.clone());
+            }
+        }
+
+        runs.retain(|r| self.can_extend(r.element, r.count));
+        p.runs = runs;
+
+        self.settle(p)
+    }
+
+    /// Drops the runs that can't be extended by any row after `time`, returning the matches
+    /// that are now final
+    fn expire(&self, p: &mut Partition<T>, time: SystemTime) -> Vec<Vec<(usize, T)>> {
+        if let Some(within) = self.within {
+            p.runs.retain(|r| r.start_time + within > time);
+        }
+
+        self.settle(p)
+    }
+
+    fn settle(&self, p: &mut Partition<T>) -> Vec<Vec<(usize, T)>> {
+        let mut matches = vec![];
+
+        while let Some(candidate) = p.candidate.take() {
+            if p.runs.iter().any(|r| r.start <= candidate.start) {
+                // a run that started earlier, or this one, may still produce a preferred match
+                p.candidate = Some(candidate);
+                break;
+            }
+
+            // matching continues after the last row of the match
+            let end = candidate.end();
+            p.runs.retain(|r| r.start > end);
+
+            p.candidate = None;
+            for run in &p.runs {
+                if self.is_complete(run.element, run.count)
+                    && p.candidate
+                        .as_ref()
+                        .map(|c| run.start < c.start)
+                        .unwrap_or(true)
+                {
+                    p.candidate = Some(run.clone());
+                }
+            }
+
+            matches.push(candidate.rows);
+        }
+
+        matches
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        let watermark = ctx
+            .last_present_watermark()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        if watermark >= record.timestamp {
+            // drop late data
+            return;
+        }
+
+        let mut key = record.key.clone().unwrap();
+
+        let mut state: KeyedState<K, Partition<T>, _> = ctx.state.get_key_state('p').await;
+        let mut partition = state.get(&key).cloned().unwrap_or_default();
+
+        // rows with the same timestamp are kept in arrival order
+        let i = partition
+            .pending
+            .partition_point(|(t, _)| *t <= record.timestamp);
+        partition
+            .pending
+            .insert(i, (record.timestamp, record.value.clone()));
+        state.insert(record.timestamp, key.clone(), partition).await;
+
+        ctx.schedule_timer(&mut key, record.timestamp, record.timestamp)
+            .await;
+        if let Some(within) = self.within {
+            // runs starting at this row expire at this point
+            let expiration = record.timestamp + within;
+            ctx.schedule_timer(&mut key, expiration, expiration).await;
+        }
+    }
+
+    async fn handle_timer(&mut self, mut key: K, time: SystemTime, ctx: &mut Context<K, OutT>) {
+        let mut partition = {
+            let mut state: KeyedState<K, Partition<T>, _> = ctx.state.get_key_state('p').await;
+            let Some(partition) = state.get(&key) else {
+                return;
+            };
+            partition.clone()
+        };
+
+        // any row up to the timer's time has arrived, as later ones would be dropped as late
+        let released = partition.pending.partition_point(|(t, _)| *t <= time);
+        let rows: Vec<_> = partition.pending.drain(..released).collect();
+
+        let mut matches = vec![];
+        for (t, value) in rows {
+            matches.extend(self.advance(&mut partition, t, &value));
+        }
+        matches.extend(self.expire(&mut partition, time));
+
+        for rows in matches {
+            // matches are emitted at the current time, so that they aren't late with respect to
+            // the watermarks we've already sent
+            ctx.collect(Record {
+                timestamp: time,
+                key: Some(key.clone()),
+                value: (self.measures)(&rows),
+            })
+            .await;
+        }
+
+        let mut state: KeyedState<K, Partition<T>, _> = ctx.state.get_key_state('p').await;
+        if partition.is_empty() {
+            state.remove(&mut key).await;
+        } else {
+            state.insert(time, key, partition).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rows are (kind, value) pairs, and element i matches rows of kind i
+    fn predicate(row: &(usize, i64), element: usize) -> bool {
+        row.0 == element
+    }
+
+    fn values(rows: &[(usize, (usize, i64))]) -> Vec<i64> {
+        rows.iter().map(|(_, (_, v))| *v).collect()
+    }
+
+    fn run(
+        op: &MatchRecognizeFunc<(), (usize, i64), Vec<i64>>,
+        rows: &[(u64, usize, i64)],
+    ) -> Vec<Vec<i64>> {
+        let mut partition = Partition::default();
+        let mut matches = vec![];
+        for (t, kind, value) in rows {
+            matches.extend(op.advance(&mut partition, from_millis(*t), &(*kind, *value)));
+        }
+        matches.extend(op.expire(&mut partition, from_millis(u64::MAX / 2)));
+        matches.iter().map(|m| values(m)).collect()
+    }
+
+    #[test]
+    fn test_followed_by() {
+        // A B
+        let op = MatchRecognizeFunc::new(vec![(1, Some(1)), (1, Some(1))], None, predicate, values);
+
+        assert_eq!(
+            run(
+                &op,
+                &[
+                    (1, 0, 1),
+                    (2, 1, 2),
+                    (3, 1, 3),
+                    (4, 0, 4),
+                    (5, 0, 5),
+                    (6, 1, 6)
+                ]
+            ),
+            vec![vec![1, 2], vec![5, 6]]
+        );
+    }
+
+    #[test]
+    fn test_greedy_quantifiers() {
+        // A B+ C?
+        let op = MatchRecognizeFunc::new(
+            vec![(1, Some(1)), (1, None), (0, Some(1))],
+            None,
+            predicate,
+            values,
+        );
+
+        assert_eq!(
+            run(
+                &op,
+                &[
+                    (1, 0, 1),
+                    (2, 1, 2),
+                    (3, 1, 3),
+                    (4, 2, 4),
+                    (5, 0, 5),
+                    (6, 1, 6),
+                    (7, 0, 7)
+                ]
+            ),
+            // the second match can only be emitted once a row shows that it doesn't continue
+            vec![vec![1, 2, 3, 4], vec![5, 6]]
+        );
+    }
+
+    #[test]
+    fn test_within() {
+        // A B within 10ms
+        let op = MatchRecognizeFunc::new(
+            vec![(1, Some(1)), (1, Some(1))],
+            Some(Duration::from_millis(10)),
+            predicate,
+            values,
+        );
+
+        assert_eq!(
+            run(&op, &[(1, 0, 1), (20, 1, 2), (30, 0, 3), (35, 1, 4)]),
+            vec![vec![3, 4]]
+        );
+    }
+}
@@ -20,14 +20,25 @@ use wasmtime::{
     TypedFunc,
 };
 pub mod aggregating_window;
+pub mod async_map;
+pub mod async_udf;
+pub mod broadcast_connect;
+pub mod deduplicate;
 pub mod functions;
+pub mod interval_join;
 pub mod join_with_expiration;
 pub mod joins;
+pub mod late_data;
+pub mod lookup_join;
+pub mod match_recognize;
+pub mod over_window;
 pub mod sinks;
 pub mod sliding_top_n_aggregating_window;
+pub mod top_n;
 pub mod tumbling_aggregating_window;
 pub mod tumbling_top_n_window;
 pub mod updating_aggregate;
+pub mod window_alignment;
 pub mod windows;
 
 #[cfg(test)]
@@ -108,6 +119,8 @@ pub struct PeriodicWatermarkGenerator<K: Key, D: Data> {
     idle_time: Option<Duration>,
     last_event: SystemTime,
     idle: bool,
+    // set once the source sends its own watermarks, which then take precedence over ours
+    upstream_watermarks: bool,
     _t: PhantomData<(K, D)>,
 }
 
@@ -128,6 +141,7 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
             idle_time,
             last_event: SystemTime::now(),
             idle: false,
+            upstream_watermarks: false,
             _t: PhantomData,
         }
     }
@@ -147,6 +161,24 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
             idle_time,
             last_event: SystemTime::now(),
             idle: false,
+            upstream_watermarks: false,
+            _t: PhantomData,
+        }
+    }
+
+    /// Forwards the watermarks that the source generates itself, along with its idleness
+    pub fn source_watermarks() -> Self {
+        PeriodicWatermarkGenerator {
+            interval: Duration::ZERO,
+            watermark_function: Box::new(|record| record.timestamp),
+            state_cache: PeriodicWatermarkGeneratorState {
+                last_watermark_emitted_at: SystemTime::UNIX_EPOCH,
+                max_watermark: SystemTime::UNIX_EPOCH,
+            },
+            idle_time: None,
+            last_event: SystemTime::now(),
+            idle: false,
+            upstream_watermarks: true,
             _t: PhantomData,
         }
     }
@@ -189,6 +221,10 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
         ctx.collector.collect(record.clone()).await;
         self.last_event = SystemTime::now();
 
+        if self.upstream_watermarks {
+            return;
+        }
+
         let watermark = (self.watermark_function)(record);
 
         self.state_cache.max_watermark = self.state_cache.max_watermark.max(watermark);
@@ -218,7 +254,23 @@ impl<K: Key, D: Data> PeriodicWatermarkGenerator<K, D> {
         gs.insert(ctx.task_info.task_index, self.state_cache).await;
     }
 
+    async fn handle_watermark(&mut self, watermark: Watermark, ctx: &mut Context<K, D>) {
+        if matches!(watermark, Watermark::EventTime(_)) && !self.upstream_watermarks {
+            info!(
+                "Source for partition {} emits its own watermarks; forwarding them",
+                ctx.task_info.task_index
+            );
+            self.upstream_watermarks = true;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
     async fn handle_tick(&mut self, _: u64, ctx: &mut Context<K, D>) {
+        if self.upstream_watermarks {
+            return;
+        }
+
         if let Some(idle_time) = self.idle_time {
             if self.last_event.elapsed().unwrap_or(Duration::ZERO) > idle_time && !self.idle {
                 info!(
@@ -0,0 +1,248 @@
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::keyed_map::KeyedState;
+use arroyo_types::*;
+use bincode::{Decode, Encode};
+
+#[derive(Encode, Decode, Clone, Debug, PartialEq)]
+struct Partition<T, OutT> {
+    // rows in event-time order, starting from the earliest that a frame may still include
+    rows: Vec<(SystemTime, T)>,
+    // the number of rows at the start of `rows` that have been emitted
+    emitted: usize,
+    // the row number of the last emitted row
+    row_number: u64,
+    // the last emitted row, which functions like moving averages are computed from
+    last: Option<OutT>,
+}
+
+impl<T, OutT> Default for Partition<T, OutT> {
+    fn default() -> Self {
+        Self {
+            rows: vec![],
+            emitted: 0,
+            row_number: 0,
+            last: None,
+        }
+    }
+}
+
+/// Computes a window function over the rows of each key in event-time order, as in
+/// `f(..) OVER (PARTITION BY .. ORDER BY ..)`. Rows are held until the watermark passes them and
+/// the end of their frame, then emitted as `aggregator(frame, index of the row in the frame,
+/// row number, previous row emitted for the key)`.
+#[derive(StreamNode)]
+pub struct OverWindowFunc<K: Key, T: Data, OutT: Data> {
+    preceding: FrameBound,
+    following: FrameBound,
+    aggregator: fn(&[T], usize, u64, Option<&OutT>) -> OutT,
+    _t: PhantomData<K>,
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = OutT, timer_t = SystemTime)]
+impl<K: Key, T: Data, OutT: Data> OverWindowFunc<K, T, OutT> {
+    fn name(&self) -> String {
+        "OverWindow".to_string()
+    }
+
+    pub fn new(
+        preceding: FrameBound,
+        following: FrameBound,
+        aggregator: fn(&[T], usize, u64, Option<&OutT>) -> OutT,
+    ) -> Self {
+        assert!(
+            following != FrameBound::Unbounded,
+            "frames can't extend to unbounded following rows"
+        );
+
+        Self {
+            preceding,
+            following,
+            aggregator,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![TableDescriptor {
+            name: "p".to_string(),
+            description: "partitions".to_string(),
+            table_type: TableType::TimeKeyMap as i32,
+            // rows are removed once no frame needs them, which for count-bounded frames is
+            // unrelated to the watermark
+            delete_behavior: TableDeleteBehavior::None as i32,
+            write_behavior: TableWriteBehavior::DefaultWrites as i32,
+            retention_micros: 0,
+        }]
+    }
+
+    /// The index of the first row in the frame of the row at `i`, which has time `at`
+    fn frame_start(&self, rows: &[(SystemTime, T)], i: usize, at: SystemTime) -> usize {
+        match self.preceding {
+            FrameBound::Unbounded => 0,
+            FrameBound::Rows(n) => i.saturating_sub(n as usize),
+            FrameBound::Time(d) => {
+                let start = at.checked_sub(d).unwrap_or(SystemTime::UNIX_EPOCH);
+                rows.partition_point(|(t, _)| *t < start)
+            }
+        }
+    }
+
+    /// The index after the last row in the frame of the row at `i`, if all of the rows in the
+    /// frame have arrived by `time`
+    fn frame_end(
+        &self,
+        rows: &[(SystemTime, T)],
+        i: usize,
+        released: usize,
+        time: SystemTime,
+    ) -> Option<usize> {
+        match self.following {
+            FrameBound::Unbounded => None,
+            FrameBound::Rows(n) => (i + (n as usize) < released).then_some(i + n as usize + 1),
+            FrameBound::Time(d) => {
+                let end = rows[i].0 + d;
+                (end <= time).then(|| rows.partition_point(|(t, _)| *t <= end))
+            }
+        }
+    }
+
+    async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
+        let watermark = ctx
+            .last_present_watermark()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        if watermark >= record.timestamp {
+            // drop late data
+            return;
+        }
+
+        let mut key = record.key.clone().unwrap();
+
+        let mut state: KeyedState<K, Partition<T, OutT>, _> = ctx.state.get_key_state('p').await;
+        let mut partition = state.get(&key).cloned().unwrap_or_default();
+
+        // rows with the same timestamp are kept in arrival order
+        let i = partition
+            .rows
+            .partition_point(|(t, _)| *t <= record.timestamp);
+        partition
+            .rows
+            .insert(i, (record.timestamp, record.value.clone()));
+        state.insert(record.timestamp, key.clone(), partition).await;
+
+        ctx.schedule_timer(&mut key, record.timestamp, record.timestamp)
+            .await;
+        if let FrameBound::Time(following) = self.following {
+            let end = record.timestamp + following;
+            ctx.schedule_timer(&mut key, end, end).await;
+        }
+    }
+
+    async fn handle_timer(&mut self, key: K, time: SystemTime, ctx: &mut Context<K, OutT>) {
+        let mut partition = {
+            let mut state: KeyedState<K, Partition<T, OutT>, _> =
+                ctx.state.get_key_state('p').await;
+            let Some(partition) = state.get(&key) else {
+                return;
+            };
+            partition.clone()
+        };
+
+        // any row up to the timer's time has arrived, as later ones would be dropped as late
+        let released = partition.rows.partition_point(|(t, _)| *t <= time);
+        let values: Vec<T> = partition.rows.iter().map(|(_, v)| v.clone()).collect();
+
+        while partition.emitted < released {
+            let i = partition.emitted;
+            let Some(end) = self.frame_end(&partition.rows, i, released, time) else {
+                break;
+            };
+            let start = self.frame_start(&partition.rows, i, partition.rows[i].0);
+
+            partition.row_number += 1;
+            let value = (self.aggregator)(
+                &values[start..end],
+                i - start,
+                partition.row_number,
+                partition.last.as_ref(),
+            );
+            partition.last = Some(value.clone());
+
+            // rows that waited for following rows are emitted at the current time, so that they
+            // aren't late with respect to the watermarks we've already sent
+            ctx.collect(Record {
+                timestamp: time,
+                key: Some(key.clone()),
+                value,
+            })
+            .await;
+            partition.emitted += 1;
+        }
+
+        // drop the rows that no remaining frame can include; rows that haven't arrived yet will
+        // be later than `time`
+        let next = partition
+            .rows
+            .get(partition.emitted)
+            .map(|(t, _)| *t)
+            .unwrap_or(time);
+        let keep_from = self
+            .frame_start(&partition.rows, partition.emitted, next)
+            .min(partition.emitted);
+        partition.rows.drain(..keep_from);
+        partition.emitted -= keep_from;
+
+        let mut state: KeyedState<K, Partition<T, OutT>, _> = ctx.state.get_key_state('p').await;
+        state.insert(time, key, partition).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn first(rows: &[i64], _: usize, _: u64, _: Option<&i64>) -> i64 {
+        rows[0]
+    }
+
+    fn rows(times: &[u64]) -> Vec<(SystemTime, i64)> {
+        times.iter().map(|t| (from_millis(*t), *t as i64)).collect()
+    }
+
+    #[test]
+    fn test_row_frames() {
+        let op =
+            OverWindowFunc::<(), i64, i64>::new(FrameBound::Rows(2), FrameBound::Rows(1), first);
+        let rows = rows(&[1, 2, 3, 4, 5]);
+
+        assert_eq!(op.frame_start(&rows, 1, rows[1].0), 0);
+        assert_eq!(op.frame_start(&rows, 3, rows[3].0), 1);
+
+        assert_eq!(op.frame_end(&rows, 2, 4, from_millis(4)), Some(4));
+        // the frame of the fourth row includes the fifth, which hasn't been released yet
+        assert_eq!(op.frame_end(&rows, 3, 4, from_millis(4)), None);
+    }
+
+    #[test]
+    fn test_time_frames() {
+        let op = OverWindowFunc::<(), i64, i64>::new(
+            FrameBound::Time(Duration::from_millis(2)),
+            FrameBound::Time(Duration::from_millis(1)),
+            first,
+        );
+        let rows = rows(&[1, 2, 2, 4, 7]);
+
+        assert_eq!(op.frame_start(&rows, 3, rows[3].0), 1);
+        assert_eq!(op.frame_start(&rows, 4, rows[4].0), 4);
+
+        assert_eq!(op.frame_end(&rows, 1, 3, from_millis(3)), Some(3));
+        // the frame of the row at 4 extends to 5, which the watermark hasn't reached
+        assert_eq!(op.frame_end(&rows, 3, 4, from_millis(4)), None);
+    }
+}
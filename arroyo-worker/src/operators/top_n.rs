@@ -0,0 +1,181 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::engine::{Context, StreamNode};
+use arroyo_macro::process_fn;
+use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_state::tables::keyed_map::KeyedState;
+use arroyo_types::*;
+
+/// Keeps the first `max_elements` rows of each key, as ordered by their sort keys, and emits
+/// the changes to their ranks as updates. This is how `ROW_NUMBER() OVER (PARTITION BY .. ORDER
+/// BY ..)` filtered by `row_number <= N` is computed outside of a window: rows that can't make it
+/// into the top N are dropped immediately, so only N rows are stored per key.
+#[derive(StreamNode)]
+pub struct TopNFunc<K: Key, T: Data, SK: Ord + Send + 'static, OutT: Data> {
+    max_elements: usize,
+    expiration: Duration,
+    extractor: fn(&T) -> SK,
+    converter: fn(T, usize) -> OutT,
+    _t: PhantomData<K>,
+}
+
+/// A change to the ranked rows of a key, with the 1-based rank of the row
+#[derive(Debug, PartialEq)]
+enum RankChange<T> {
+    Retract(T, usize),
+    Update(T, usize, usize),
+    Append(T, usize),
+}
+
+/// Inserts `value` into `rows`, which are sorted by `extractor`, after any rows it's equal to, and
+/// keeps at most `max_elements` of them. Returns the changes to the ranks of the rows, starting
+/// with the row that was pushed out, if any.
+fn insert_ranked<T: Clone, SK: Ord>(
+    rows: &mut Vec<T>,
+    value: T,
+    max_elements: usize,
+    extractor: impl Fn(&T) -> SK,
+) -> Vec<RankChange<T>> {
+    let sort_key = extractor(&value);
+    let position = rows.partition_point(|row| extractor(row) <= sort_key);
+    if position >= max_elements {
+        return vec![];
+    }
+
+    let mut changes = vec![];
+    rows.insert(position, value.clone());
+    if rows.len() > max_elements {
+        let evicted = rows.pop().unwrap();
+        changes.push(RankChange::Retract(evicted, max_elements));
+    }
+
+    for index in (position + 1..rows.len()).rev() {
+        changes.push(RankChange::Update(rows[index].clone(), index, index + 1));
+    }
+    changes.push(RankChange::Append(value, position + 1));
+    changes
+}
+
+#[process_fn(in_k = K, in_t = T, out_k = K, out_t = UpdatingData<OutT>)]
+impl<K: Key, T: Data, SK: Ord + Send + 'static, OutT: Data> TopNFunc<K, T, SK, OutT> {
+    fn name(&self) -> String {
+        "TopN".to_string()
+    }
+
+    pub fn new(
+        max_elements: usize,
+        expiration: Duration,
+        extractor: fn(&T) -> SK,
+        converter: fn(T, usize) -> OutT,
+    ) -> Self {
+        Self {
+            max_elements,
+            expiration,
+            extractor,
+            converter,
+            _t: PhantomData,
+        }
+    }
+
+    fn tables(&self) -> Vec<TableDescriptor> {
+        vec![TableDescriptor {
+            name: "t".to_string(),
+            description: "top n state".to_string(),
+            table_type: TableType::TimeKeyMap as i32,
+            delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
+            write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
+            retention_micros: self.expiration.as_micros() as u64,
+        }]
+    }
+
+    async fn process_element(
+        &mut self,
+        record: &Record<K, T>,
+        ctx: &mut Context<K, UpdatingData<OutT>>,
+    ) {
+        if let Some(watermark) = ctx.last_present_watermark() {
+            if record.timestamp < watermark {
+                return;
+            }
+        }
+
+        let mut state: KeyedState<K, Vec<T>, _> = ctx.state.get_key_state('t').await;
+        let key = record.key.clone().unwrap();
+        let mut rows = state.get(&key).cloned().unwrap_or_default();
+
+        let changes = insert_ranked(
+            &mut rows,
+            record.value.clone(),
+            self.max_elements,
+            self.extractor,
+        );
+        if changes.is_empty() {
+            return;
+        }
+        state.insert(record.timestamp, key.clone(), rows).await;
+
+        for change in changes {
+            let value = match change {
+                RankChange::Retract(row, rank) => {
+                    UpdatingData::Retract((self.converter)(row, rank))
+                }
+                RankChange::Update(row, old, new) => UpdatingData::Update {
+                    old: (self.converter)(row.clone(), old),
+                    new: (self.converter)(row, new),
+                },
+                RankChange::Append(row, rank) => UpdatingData::Append((self.converter)(row, rank)),
+            };
+            ctx.collect(Record {
+                timestamp: record.timestamp,
+                key: Some(key.clone()),
+                value,
+            })
+            .await;
+        }
+    }
+
+    async fn handle_watermark(
+        &mut self,
+        watermark: Watermark,
+        ctx: &mut Context<K, UpdatingData<OutT>>,
+    ) {
+        if let Watermark::EventTime(watermark) = watermark {
+            let mut state: KeyedState<K, Vec<T>, _> = ctx.state.get_key_state('t').await;
+            state
+                .expire_entries_before(watermark - self.expiration)
+                .await;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_ranked() {
+        let mut rows = vec![];
+        let descending = |v: &u32| std::cmp::Reverse(*v);
+
+        assert_eq!(
+            insert_ranked(&mut rows, 5, 2, descending),
+            vec![RankChange::Append(5, 1)]
+        );
+        assert_eq!(
+            insert_ranked(&mut rows, 7, 2, descending),
+            vec![RankChange::Update(5, 1, 2), RankChange::Append(7, 1)]
+        );
+        // pushes 5 out of the top 2
+        assert_eq!(
+            insert_ranked(&mut rows, 6, 2, descending),
+            vec![RankChange::Retract(5, 2), RankChange::Append(6, 2)]
+        );
+        // ties rank after the rows that came first
+        assert_eq!(insert_ranked(&mut rows, 6, 2, descending), vec![]);
+        assert_eq!(insert_ranked(&mut rows, 1, 2, descending), vec![]);
+        assert_eq!(rows, vec![7, 6]);
+    }
+}
@@ -1,15 +1,30 @@
 use std::time::SystemTime;
 
 use crate::engine::{Context, StreamNode};
+use crate::operators::window_alignment::WindowAlignment;
 use arroyo_macro::process_fn;
 use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
 use arroyo_state::tables::time_key_map::TimeKeyMap;
 use arroyo_types::*;
 use std::time::Duration;
 use tracing::debug;
+/// Aggregates each key over tumbling windows, emitting their results once the watermark passes
+/// their end.
+///
+/// With an allowed lateness, each window's bins are kept until the watermark has passed its end by
+/// that much longer, and a late row within it re-emits the window's result for its key with the row
+/// included. Those results keep the window's end as their timestamp, so they are late for any
+/// downstream operator that windows them again.
+///
+/// With `emit_partial`, each key's results for the windows that are still open are also emitted
+/// every `emit_partial` of wall-clock time, so that they're visible before the watermark passes
+/// the windows' ends, or when it stops advancing. These are plain rows, not updates.
 #[derive(StreamNode)]
 pub struct TumblingAggregatingWindowFunc<K: Key, T: Data, BinA: Data, OutT: Data> {
     width: Duration,
+    alignment: WindowAlignment,
+    allowed_lateness: Duration,
+    emit_partial: Option<Duration>,
     aggregator: fn(&K, Window, &BinA) -> OutT,
     bin_merger: fn(&T, Option<&BinA>) -> BinA,
     state: TumblingWindowState,
@@ -17,7 +32,7 @@ pub struct TumblingAggregatingWindowFunc<K: Key, T: Data, BinA: Data, OutT: Data
 
 #[derive(Debug)]
 enum TumblingWindowState {
-    // We haven't received any data.
+    // We haven't received any data that hasn't been emitted.
     NoData,
     // We've received data, but don't have any data in the memory_view.
     BufferedData { earliest_bin_time: SystemTime },
@@ -31,12 +46,18 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
 
     pub fn new(
         width: Duration,
+        alignment: WindowAlignment,
+        allowed_lateness: Duration,
+        emit_partial: Option<Duration>,
         // TODO: this can consume the bin, as we drop it right after.
         aggregator: fn(&K, Window, &BinA) -> OutT,
         bin_merger: fn(&T, Option<&BinA>) -> BinA,
     ) -> Self {
         TumblingAggregatingWindowFunc {
             width,
+            alignment,
+            allowed_lateness,
+            emit_partial,
             aggregator,
             bin_merger,
             state: TumblingWindowState::NoData,
@@ -44,16 +65,15 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
     }
 
     fn bin_start(&self, timestamp: SystemTime) -> SystemTime {
-        if self.width == Duration::ZERO {
-            return timestamp;
-        }
-        let mut nanos = to_nanos(timestamp);
-        nanos -= nanos % self.width.as_nanos();
-        let result = from_nanos(nanos);
+        let result = self.alignment.bin_start(timestamp, self.width);
         debug!("bin start for {:?} is {:?}", timestamp, result);
         result
     }
 
+    fn bin_end(&self, bin_start: SystemTime) -> SystemTime {
+        self.alignment.bin_end(bin_start, self.width)
+    }
+
     fn tables(&self) -> Vec<TableDescriptor> {
         vec![TableDescriptor {
             name: "a".to_string(),
@@ -61,48 +81,127 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
             table_type: TableType::TimeKeyMap as i32,
             delete_behavior: TableDeleteBehavior::NoReadsBeforeWatermark as i32,
             write_behavior: TableWriteBehavior::NoWritesBeforeWatermark as i32,
-            retention_micros: self.width.as_micros() as u64,
+            retention_micros: (self.alignment.max_width(self.width) + self.allowed_lateness)
+                .as_micros() as u64,
         }]
     }
 
     async fn process_element(&mut self, record: &Record<K, T>, ctx: &mut Context<K, OutT>) {
         let bin_start = self.bin_start(record.timestamp);
+        let watermark = ctx.last_present_watermark();
 
-        if let Some(watermark) = ctx.last_present_watermark() {
-            if bin_start < self.bin_start(watermark) {
+        if let Some(watermark) = watermark {
+            if self.alignment.is_late(
+                record.timestamp,
+                self.width,
+                self.allowed_lateness,
+                watermark,
+            ) {
                 return;
             }
         }
 
-        self.state = match self.state {
-            TumblingWindowState::NoData => TumblingWindowState::BufferedData {
-                earliest_bin_time: bin_start,
-            },
-            TumblingWindowState::BufferedData { earliest_bin_time } => {
-                TumblingWindowState::BufferedData {
-                    earliest_bin_time: earliest_bin_time.min(bin_start),
+        // the row is late, but its window is still within the allowed lateness
+        let emitted = watermark.map_or(false, |watermark| bin_start < self.bin_start(watermark));
+
+        if !emitted {
+            self.state = match self.state {
+                TumblingWindowState::NoData => TumblingWindowState::BufferedData {
+                    earliest_bin_time: bin_start,
+                },
+                TumblingWindowState::BufferedData { earliest_bin_time } => {
+                    TumblingWindowState::BufferedData {
+                        earliest_bin_time: earliest_bin_time.min(bin_start),
+                    }
                 }
-            }
-        };
+            };
+        }
 
-        let mut aggregating_map: TimeKeyMap<K, BinA, _> = ctx
-            .state
-            .get_time_key_map('a', ctx.last_present_watermark())
-            .await;
+        let mut aggregating_map: TimeKeyMap<K, BinA, _> =
+            ctx.state.get_time_key_map('a', watermark).await;
 
         let mut key = record.key.clone().unwrap();
         let bin_aggregate = aggregating_map.get(bin_start, &mut key);
         let new_value = (self.bin_merger)(&record.value, bin_aggregate);
-        aggregating_map.insert(bin_start, key, new_value);
+
+        let update = emitted.then(|| Record {
+            timestamp: self.window_end(bin_start),
+            key: Some(key.clone()),
+            value: (self.aggregator)(
+                &key,
+                Window::new(bin_start, self.bin_end(bin_start)),
+                &new_value,
+            ),
+        });
+        aggregating_map.insert(bin_start, key.clone(), new_value);
+
+        if let (false, Some(interval)) = (emitted, self.emit_partial) {
+            // timers for the same key and time replace each other, so there's one per key
+            let next = Self::next_partial(SystemTime::now(), interval);
+            ctx.schedule_processing_timer(&mut key, next, ()).await;
+        }
+
+        if let Some(update) = update {
+            debug!("re-emitting late update {:?}", update);
+            ctx.collect(update).await;
+        }
+    }
+
+    /// The next multiple of `interval` after `now`, so that partial results are emitted for all
+    /// keys at the same times
+    fn next_partial(now: SystemTime, interval: Duration) -> SystemTime {
+        let interval = (interval.as_micros() as u64).max(1);
+        from_micros((to_micros(now) / interval + 1) * interval)
+    }
+
+    async fn handle_processing_timer(&mut self, mut key: K, _: (), ctx: &mut Context<K, OutT>) {
+        let watermark = ctx.last_present_watermark();
+        let mut aggregating_map: TimeKeyMap<K, BinA, _> =
+            ctx.state.get_time_key_map('a', watermark).await;
+
+        let mut records = vec![];
+        let mut next_bin = match watermark {
+            Some(watermark) => aggregating_map.get_min_time_from(self.bin_start(watermark)),
+            None => aggregating_map.get_min_time(),
+        };
+        while let Some(bin_start) = next_bin {
+            if let Some(value) = aggregating_map.get(bin_start, &mut key) {
+                records.push(Record {
+                    timestamp: self.window_end(bin_start),
+                    key: Some(key.clone()),
+                    value: (self.aggregator)(
+                        &key,
+                        Window::new(bin_start, self.bin_end(bin_start)),
+                        value,
+                    ),
+                });
+            }
+            next_bin = aggregating_map.get_min_time_from(self.bin_end(bin_start));
+        }
+
+        // keep emitting while the key has open windows
+        if let (false, Some(interval)) = (records.is_empty(), self.emit_partial) {
+            let next = Self::next_partial(SystemTime::now(), interval);
+            ctx.schedule_processing_timer(&mut key, next, ()).await;
+        }
+
+        for record in records {
+            debug!("emitting partial result {:?}", record);
+            ctx.collect(record).await;
+        }
     }
 
     async fn on_start(&mut self, ctx: &mut Context<K, OutT>) {
-        let map = ctx
-            .state
-            .get_time_key_map::<K, BinA>('a', ctx.last_present_watermark())
-            .await;
+        let watermark = ctx.last_present_watermark();
+        let map = ctx.state.get_time_key_map::<K, BinA>('a', watermark).await;
+
+        // bins before the watermark's have already been emitted, and are only kept for late rows
+        let first_unemitted = match watermark {
+            Some(watermark) => map.get_min_time_from(self.bin_start(watermark)),
+            None => map.get_min_time(),
+        };
 
-        self.state = match map.get_min_time() {
+        self.state = match first_unemitted {
             Some(min_time) => TumblingWindowState::BufferedData {
                 earliest_bin_time: self.bin_start(min_time),
             },
@@ -113,7 +212,7 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
         let watermark_bin = self.bin_start(watermark);
         match self.state {
             TumblingWindowState::BufferedData { earliest_bin_time } => {
-                earliest_bin_time + self.width <= watermark_bin
+                self.bin_end(earliest_bin_time) <= watermark_bin
             }
             TumblingWindowState::NoData => false,
         }
@@ -123,7 +222,7 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
         if self.width == Duration::ZERO {
             bin_start
         } else {
-            bin_start + self.width - Duration::from_nanos(1)
+            self.bin_end(bin_start) - Duration::from_nanos(1)
         }
     }
 
@@ -141,19 +240,30 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
             .await;
 
         let window_end = self.window_end(bin_start);
+        let bin = if self.allowed_lateness.is_zero() {
+            aggregating_map.evict_for_timestamp(bin_start)
+        } else {
+            // the bin is kept until the allowed lateness has passed, so late rows can update it
+            aggregating_map
+                .get_all_for_time(bin_start)
+                .into_iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        };
+
         let mut records = vec![];
-        for (key, value) in aggregating_map.evict_for_timestamp(bin_start) {
+        for (key, value) in bin {
             records.push(Record {
                 timestamp: window_end,
                 key: Some(key.clone()),
                 value: (self.aggregator)(
                     &key,
-                    Window::new(bin_start, bin_start + self.width),
+                    Window::new(bin_start, self.bin_end(bin_start)),
                     &value,
                 ),
             });
         }
-        self.state = match aggregating_map.get_min_time() {
+        self.state = match aggregating_map.get_min_time_from(self.bin_end(bin_start)) {
             Some(min_time) => TumblingWindowState::BufferedData {
                 earliest_bin_time: self.bin_start(min_time),
             },
@@ -178,6 +288,24 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
                 while self.should_advance(t) {
                     self.advance(ctx).await;
                 }
+
+                if !self.allowed_lateness.is_zero() {
+                    // drop the bins that late rows can no longer be added to
+                    let expired_before = self.bin_start(
+                        t.checked_sub(self.allowed_lateness)
+                            .unwrap_or(SystemTime::UNIX_EPOCH),
+                    );
+                    let mut aggregating_map: TimeKeyMap<K, BinA, _> = ctx
+                        .state
+                        .get_time_key_map('a', ctx.last_present_watermark())
+                        .await;
+                    while let Some(min_time) = aggregating_map
+                        .get_min_time()
+                        .filter(|min_time| *min_time < expired_before)
+                    {
+                        aggregating_map.evict_for_timestamp(min_time);
+                    }
+                }
             }
             Watermark::Idle => (),
         }
@@ -196,5 +324,9 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> TumblingAggregatingWindowFunc<K, T
             .get_time_key_map('a', ctx.last_present_watermark())
             .await;
         aggregating_map.flush().await;
+
+        if self.emit_partial.is_some() {
+            ctx.flush_processing_timers::<()>().await;
+        }
     }
 }
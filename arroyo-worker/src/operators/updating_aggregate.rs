@@ -3,12 +3,20 @@ use std::marker::PhantomData;
 use crate::engine::{Context, StreamNode};
 use arroyo_macro::process_fn;
 use arroyo_rpc::grpc::{TableDeleteBehavior, TableDescriptor, TableType, TableWriteBehavior};
+use arroyo_rpc::StateQueryResp;
+use arroyo_state::hash_key;
 use arroyo_state::tables::keyed_map::KeyedState;
 use arroyo_types::*;
+use serde::{de::DeserializeOwned, Serialize};
 use std::time::Duration;
 
 #[derive(StreamNode)]
-pub struct UpdatingAggregateOperator<K: Key, T: Data, BinA: Data, OutT: Data> {
+pub struct UpdatingAggregateOperator<
+    K: Key + DeserializeOwned,
+    T: Data,
+    BinA: Data,
+    OutT: Data + Serialize,
+> {
     expiration: Duration,
     aggregator: fn(&K, &BinA) -> OutT,
     bin_merger: fn(&T, Option<&BinA>) -> Option<BinA>,
@@ -27,7 +35,9 @@ enum StateOp<T: Data> {
 }
 
 #[process_fn(in_k = K, in_t = T, out_k = K, out_t = UpdatingData<OutT>)]
-impl<K: Key, T: Data, BinA: Data, OutT: Data> UpdatingAggregateOperator<K, T, BinA, OutT> {
+impl<K: Key + DeserializeOwned, T: Data, BinA: Data, OutT: Data + Serialize>
+    UpdatingAggregateOperator<K, T, BinA, OutT>
+{
     fn name(&self) -> String {
         "UpdatingAggregate".to_string()
     }
@@ -147,4 +157,129 @@ impl<K: Key, T: Data, BinA: Data, OutT: Data> UpdatingAggregateOperator<K, T, Bi
             .await;
         }
     }
+
+    async fn handle_watermark(
+        &mut self,
+        watermark: Watermark,
+        ctx: &mut Context<K, UpdatingData<OutT>>,
+    ) {
+        if let Watermark::EventTime(watermark) = watermark {
+            let mut aggregating_map: KeyedState<K, BinA, _> = ctx.state.get_key_state('a').await;
+            aggregating_map
+                .expire_entries_before(watermark - self.expiration)
+                .await;
+        }
+
+        ctx.broadcast(Message::Watermark(watermark)).await;
+    }
+
+    async fn query_state(
+        &mut self,
+        key: String,
+        ctx: &mut Context<K, UpdatingData<OutT>>,
+    ) -> anyhow::Result<StateQueryResp> {
+        let key: K = serde_json::from_str(&key)
+            .map_err(|e| anyhow::anyhow!("invalid key for {}: {}", self.name(), e))?;
+
+        // only the subtask that owns the key's hash range has its state
+        let owner = server_for_hash(hash_key(&key), ctx.task_info.parallelism);
+        if owner != ctx.task_info.task_index {
+            return Ok(StateQueryResp::OwnedBy(owner));
+        }
+
+        let mut aggregating_map: KeyedState<K, BinA, _> = ctx.state.get_key_state('a').await;
+        let value = aggregating_map
+            .get(&key)
+            .map(|bin| serde_json::to_string(&(self.aggregator)(&key, bin)))
+            .transpose()?;
+        Ok(StateQueryResp::Value(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::OutQueue;
+    use std::time::SystemTime;
+    use tokio::sync::mpsc::channel;
+
+    fn sum(_: &String, bin: &i64) -> i64 {
+        *bin
+    }
+
+    fn merge(value: &i64, bin: Option<&i64>) -> Option<i64> {
+        Some(value + bin.copied().unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_query_state() {
+        let mut operator =
+            UpdatingAggregateOperator::<String, i64, i64, i64>::new(Duration::ZERO, sum, merge);
+
+        // the first subtask of two
+        let task_info = TaskInfo {
+            parallelism: 2,
+            key_range: range_for_server(0, 2),
+            ..get_test_task_info()
+        };
+        let (_, control_rx) = channel(128);
+        let (command_tx, _) = channel(128);
+        let (data_tx, _data_rx) = channel(128);
+        let mut ctx = Context::new(
+            task_info,
+            None,
+            control_rx,
+            command_tx,
+            1,
+            vec![vec![OutQueue::new(data_tx, false)]],
+            operator.tables(),
+        )
+        .await;
+
+        let keys: Vec<String> = (0..).map(|i| format!("key{}", i)).take(20).collect();
+        let mut owned_keys = keys
+            .iter()
+            .filter(|k| server_for_hash(hash_key(*k), 2) == 0);
+        let owned = owned_keys.next().unwrap();
+        let unset = owned_keys.next().unwrap();
+        let not_owned = keys
+            .iter()
+            .find(|k| server_for_hash(hash_key(*k), 2) == 1)
+            .unwrap();
+
+        for value in [1, 2] {
+            operator
+                .process_element(
+                    &Record {
+                        timestamp: SystemTime::now(),
+                        key: Some(owned.clone()),
+                        value,
+                    },
+                    &mut ctx,
+                )
+                .await;
+        }
+
+        let query = |key: &str| serde_json::to_string(key).unwrap();
+
+        assert_eq!(
+            StateQueryResp::Value(Some("3".to_string())),
+            operator.query_state(query(owned), &mut ctx).await.unwrap()
+        );
+        assert_eq!(
+            StateQueryResp::Value(None),
+            operator.query_state(query(unset), &mut ctx).await.unwrap()
+        );
+        assert_eq!(
+            StateQueryResp::OwnedBy(1),
+            operator
+                .query_state(query(not_owned), &mut ctx)
+                .await
+                .unwrap()
+        );
+        assert!(operator
+            .query_state("not json".to_string(), &mut ctx)
+            .await
+            .is_err());
+    }
 }
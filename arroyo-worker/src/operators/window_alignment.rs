@@ -0,0 +1,204 @@
+use std::time::{Duration, SystemTime};
+
+use arroyo_types::{from_nanos, to_nanos};
+use chrono::{LocalResult, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// Where the bins of tumbling and sliding windows start.
+///
+/// Without a time zone, bins start `offset` after each multiple of their width since the epoch.
+/// With one, they're aligned the same way in the zone's local time, so that one-day bins start at
+/// its midnight. Those bins are shorter or longer than their width when the zone's offset from
+/// UTC changes, as it does for daylight saving time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowAlignment {
+    offset: Duration,
+    timezone: Option<Tz>,
+}
+
+impl WindowAlignment {
+    pub fn new(offset: Duration, timezone: Option<&str>) -> Self {
+        let timezone = timezone.map(|timezone| {
+            timezone
+                .parse()
+                .unwrap_or_else(|e| panic!("invalid time zone '{}': {}", timezone, e))
+        });
+
+        Self { offset, timezone }
+    }
+
+    /// The start of the bin of the given width that contains `timestamp`
+    pub fn bin_start(&self, timestamp: SystemTime, width: Duration) -> SystemTime {
+        if width.is_zero() {
+            return timestamp;
+        }
+
+        match self.timezone {
+            None => {
+                let nanos = to_nanos(timestamp);
+                let width = width.as_nanos();
+                let offset = self.offset.as_nanos() % width;
+                from_nanos(nanos.saturating_sub((nanos + width - offset) % width))
+            }
+            Some(timezone) => {
+                let local_start = self.local_bin_start(timezone, timestamp, width);
+                let start = to_utc(timezone, local_start);
+                if start > timestamp {
+                    // the bin's start was skipped by a change in offset and moved after the
+                    // timestamp, which is still in the previous bin
+                    to_utc(timezone, local_start - width.as_nanos() as i64)
+                } else {
+                    start
+                }
+            }
+        }
+    }
+
+    /// The end of the bin of the given width that starts at `bin_start`
+    pub fn bin_end(&self, bin_start: SystemTime, width: Duration) -> SystemTime {
+        if width.is_zero() {
+            return bin_start;
+        }
+
+        match self.timezone {
+            None => bin_start + width,
+            Some(timezone) => to_utc(
+                timezone,
+                self.local_bin_start(timezone, bin_start, width) + width.as_nanos() as i64,
+            ),
+        }
+    }
+
+    /// Whether rows at `timestamp` are too late to be added to their bin, which happens once the
+    /// watermark has passed the end of the bin by more than `allowed_lateness`
+    pub fn is_late(
+        &self,
+        timestamp: SystemTime,
+        width: Duration,
+        allowed_lateness: Duration,
+        watermark: SystemTime,
+    ) -> bool {
+        let watermark = watermark
+            .checked_sub(allowed_lateness)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        self.bin_start(timestamp, width) < self.bin_start(watermark, width)
+    }
+
+    /// The longest that a bin of the given width can be
+    pub fn max_width(&self, width: Duration) -> Duration {
+        match self.timezone {
+            None => width,
+            // no time zone has changed its offset by more than a day
+            Some(_) => width + Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    // the start of the bin containing `timestamp`, as nanos since the epoch in local time
+    fn local_bin_start(&self, timezone: Tz, timestamp: SystemTime, width: Duration) -> i64 {
+        let utc = Utc.timestamp_nanos(to_nanos(timestamp) as i64);
+        let local = utc.with_timezone(&timezone).naive_local().timestamp_nanos();
+        let width = width.as_nanos() as i64;
+        let offset = self.offset.as_nanos() as i64 % width;
+        local - (local - offset).rem_euclid(width)
+    }
+}
+
+// converts nanos since the epoch in local time to the instant they happen at
+fn to_utc(timezone: Tz, local: i64) -> SystemTime {
+    let naive = Utc.timestamp_nanos(local).naive_utc();
+    let nanos = match timezone.from_local_datetime(&naive) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.timestamp_nanos(),
+        // the local time was skipped by a change in offset, so it's read in the offset from before
+        // the change, which moves it later by the length of the skip
+        LocalResult::None => {
+            let before = timezone
+                .offset_from_utc_datetime(&(naive - chrono::Duration::days(1)))
+                .fix();
+            local - before.local_minus_utc() as i64 * NANOS_PER_SECOND
+        }
+    };
+    from_nanos(nanos as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arroyo_types::from_millis;
+
+    fn utc(s: &str) -> SystemTime {
+        from_millis(
+            chrono::DateTime::parse_from_rfc3339(s)
+                .unwrap()
+                .timestamp_millis() as u64,
+        )
+    }
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn test_offset() {
+        let alignment = WindowAlignment::new(Duration::from_secs(15 * 60), None);
+        let start = alignment.bin_start(utc("2023-06-01T10:05:00Z"), HOUR);
+        assert_eq!(start, utc("2023-06-01T09:15:00Z"));
+        assert_eq!(alignment.bin_end(start, HOUR), utc("2023-06-01T10:15:00Z"));
+        assert_eq!(
+            alignment.bin_start(utc("2023-06-01T10:15:00Z"), HOUR),
+            utc("2023-06-01T10:15:00Z")
+        );
+
+        assert_eq!(
+            WindowAlignment::default().bin_start(utc("2023-06-01T10:05:00Z"), HOUR),
+            utc("2023-06-01T10:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        let alignment = WindowAlignment::new(Duration::ZERO, Some("America/New_York"));
+
+        let start = alignment.bin_start(utc("2023-06-01T02:00:00Z"), DAY);
+        assert_eq!(start, utc("2023-05-31T04:00:00Z"));
+        assert_eq!(alignment.bin_end(start, DAY), utc("2023-06-01T04:00:00Z"));
+
+        // the day that daylight saving time starts is 23 hours long
+        let start = alignment.bin_start(utc("2023-03-12T12:00:00Z"), DAY);
+        assert_eq!(start, utc("2023-03-12T05:00:00Z"));
+        assert_eq!(alignment.bin_end(start, DAY), utc("2023-03-13T04:00:00Z"));
+
+        // and the day that it ends is 25
+        let start = alignment.bin_start(utc("2023-11-05T12:00:00Z"), DAY);
+        assert_eq!(start, utc("2023-11-05T04:00:00Z"));
+        assert_eq!(alignment.bin_end(start, DAY), utc("2023-11-06T05:00:00Z"));
+    }
+
+    #[test]
+    fn test_is_late() {
+        let alignment = WindowAlignment::default();
+        let watermark = utc("2023-06-01T11:10:00Z");
+
+        assert!(!alignment.is_late(utc("2023-06-01T11:05:00Z"), HOUR, Duration::ZERO, watermark));
+        assert!(alignment.is_late(utc("2023-06-01T10:55:00Z"), HOUR, Duration::ZERO, watermark));
+
+        // the previous bin ended ten minutes before the watermark
+        let lateness = Duration::from_secs(15 * 60);
+        assert!(!alignment.is_late(utc("2023-06-01T10:55:00Z"), HOUR, lateness, watermark));
+        assert!(alignment.is_late(utc("2023-06-01T09:55:00Z"), HOUR, lateness, watermark));
+    }
+
+    #[test]
+    fn test_skipped_local_time() {
+        // a bin that would start at 2:30 on the day that the clocks skip from 2:00 to 3:00 starts
+        // at 3:30 instead
+        let alignment =
+            WindowAlignment::new(Duration::from_secs(150 * 60), Some("America/New_York"));
+        let start = alignment.bin_start(utc("2023-03-12T12:00:00Z"), DAY);
+        assert_eq!(start, utc("2023-03-12T07:30:00Z"));
+
+        let start = alignment.bin_start(utc("2023-03-12T07:10:00Z"), DAY);
+        assert_eq!(start, utc("2023-03-11T07:30:00Z"));
+        assert_eq!(alignment.bin_end(start, DAY), utc("2023-03-12T07:30:00Z"));
+    }
+}
@@ -353,7 +353,7 @@ impl<K: Key, T: Data, OutT: Data> SessionWindowFunc<K, T, OutT> {
 
         let mut windows = WindowGroup {
             windows: {
-                let t: KeyedState<'_, K, Vec<Window>, _> = ctx.state.get_key_state('s').await;
+                let mut t: KeyedState<'_, K, Vec<Window>, _> = ctx.state.get_key_state('s').await;
                 t.get(&key).map(|t| t.iter().map(|w| *w).collect())
             }
             .unwrap_or_default(),
@@ -533,6 +533,35 @@ mod tests {
         assert_eq!(from_millis(250), wg.max_end());
     }
 
+    #[test]
+    fn test_extend_window_backwards() {
+        let mut wg = WindowGroup {
+            windows: vec![Window::session(
+                from_millis(100),
+                Duration::from_millis(100),
+            )],
+            gap_size: Duration::from_millis(100),
+        };
+
+        // an out-of-order event whose session runs into an existing window extends that window
+        // back to the event, without changing when it fires
+        assert_eq!(
+            wg.handle_event(from_millis(50)),
+            HandleResult {
+                remove: None,
+                add: None
+            }
+        );
+
+        assert_eq!(
+            wg.windows,
+            vec![Window {
+                start: from_millis(50),
+                end: from_millis(200)
+            }]
+        );
+    }
+
     #[test]
     fn test_merge_windows() {
         let mut wg: WindowGroup = WindowGroup {
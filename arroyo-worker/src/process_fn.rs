@@ -2,7 +2,7 @@ use std::time::SystemTime;
 
 use crate::{
     engine::{Context, TimerValue},
-    TIMER_TABLE,
+    PROCESSING_TIMER_TABLE, TIMER_TABLE,
 };
 
 use arroyo_rpc::grpc::TaskCheckpointEventType;
@@ -23,6 +23,29 @@ impl ProcessFnUtils {
         state.evict_all_before_watermark(watermark)
     }
 
+    pub async fn finished_processing_timers<OutK: Key, OutT: Data, Timer: Data + Eq + PartialEq>(
+        now: SystemTime,
+        ctx: &mut Context<OutK, OutT>,
+    ) -> Vec<(OutK, TimerValue<OutK, Timer>)> {
+        let mut state = ctx
+            .state
+            .get_time_key_map(PROCESSING_TIMER_TABLE, None)
+            .await;
+        let finished = state.evict_all_before_watermark(now);
+        ctx.next_processing_timer = state.get_min_time();
+        finished
+    }
+
+    /// Waits until the wall clock reaches `time`, or forever if there's no time
+    pub async fn sleep_until(time: Option<SystemTime>) {
+        match time {
+            Some(time) => {
+                tokio::time::sleep(time.duration_since(SystemTime::now()).unwrap_or_default()).await
+            }
+            None => std::future::pending().await,
+        }
+    }
+
     pub async fn send_checkpoint_event<OutK: Key, OutT: Data>(
         barrier: arroyo_types::CheckpointBarrier,
         ctx: &mut Context<OutK, OutT>,
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use apache_avro::Schema as AvroSchema;
+use prost_reflect::{FileDescriptor, MessageDescriptor};
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+// Messages in the Confluent wire format start with a zero byte followed by the 4-byte big-endian
+// id of the schema they were written with
+const MAGIC_BYTE: u8 = 0;
+const REGISTRY_CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+const PROTO_FILE_NAME: &str = "schema.proto";
+
+/// A schema fetched from the registry, parsed so that it can be used to read and write messages
+pub enum RegistrySchema {
+    Json,
+    Avro(AvroSchema),
+    Protobuf(FileDescriptor),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaResponse {
+    id: Option<u32>,
+    schema: String,
+    schema_type: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterRequest<'a> {
+    schema: &'a str,
+    schema_type: &'a str,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    id: u32,
+}
+
+/// A client for a Confluent Schema Registry. Schemas are immutable once registered, so they are
+/// cached by id for the lifetime of the client.
+pub struct SchemaRegistry {
+    endpoint: String,
+    api_key: Option<String>,
+    api_secret: Option<String>,
+    client: reqwest::Client,
+    schemas: RwLock<HashMap<u32, Arc<RegistrySchema>>>,
+}
+
+impl SchemaRegistry {
+    pub fn new(endpoint: &str, api_key: Option<String>, api_secret: Option<String>) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key,
+            api_secret,
+            client: reqwest::ClientBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("could not construct reqwest client"),
+            schemas: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let builder = builder.header(CONTENT_TYPE, REGISTRY_CONTENT_TYPE);
+        match &self.api_key {
+            Some(key) => builder.basic_auth(key, self.api_secret.as_ref()),
+            None => builder,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<SchemaResponse>> {
+        let resp = self
+            .request(self.client.get(format!("{}{}", self.endpoint, path)))
+            .send()
+            .await
+            .with_context(|| format!("failed to connect to schema registry {}", self.endpoint))?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !resp.status().is_success() {
+            bail!(
+                "schema registry responded with {}: {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            );
+        }
+
+        Ok(Some(resp.json().await.map_err(|e| {
+            anyhow!("invalid response from schema registry: {}", e)
+        })?))
+    }
+
+    /// Returns the schema with the given id if it has already been fetched
+    pub fn cached_schema(&self, id: u32) -> Option<Arc<RegistrySchema>> {
+        self.schemas.read().unwrap().get(&id).cloned()
+    }
+
+    pub async fn get_schema_by_id(&self, id: u32) -> Result<Arc<RegistrySchema>> {
+        if let Some(schema) = self.cached_schema(id) {
+            return Ok(schema);
+        }
+
+        let resp = self
+            .get(&format!("/schemas/ids/{}", id))
+            .await?
+            .ok_or_else(|| anyhow!("schema {} was not found in the schema registry", id))?;
+
+        info!("fetched schema {} from schema registry", id);
+        let schema = Arc::new(parse_schema(&resp)?);
+        self.schemas.write().unwrap().insert(id, schema.clone());
+        Ok(schema)
+    }
+
+    /// Fetches the latest version of the schema for a subject, if the subject exists
+    pub async fn get_latest_schema(
+        &self,
+        subject: &str,
+    ) -> Result<Option<(u32, Arc<RegistrySchema>)>> {
+        let Some(resp) = self
+            .get(&format!("/subjects/{}/versions/latest", subject))
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let id = resp
+            .id
+            .ok_or_else(|| anyhow!("schema registry response did not include an id"))?;
+        let schema = Arc::new(parse_schema(&resp)?);
+        self.schemas.write().unwrap().insert(id, schema.clone());
+        Ok(Some((id, schema)))
+    }
+
+    /// Registers a schema under a subject, returning its id; if the schema is already registered
+    /// its existing id is returned
+    pub async fn register_schema(
+        &self,
+        subject: &str,
+        schema_type: &str,
+        schema: &str,
+    ) -> Result<u32> {
+        let resp = self
+            .request(
+                self.client
+                    .post(format!("{}/subjects/{}/versions", self.endpoint, subject)),
+            )
+            .json(&RegisterRequest {
+                schema,
+                schema_type,
+            })
+            .send()
+            .await
+            .with_context(|| format!("failed to connect to schema registry {}", self.endpoint))?;
+
+        if !resp.status().is_success() {
+            bail!(
+                "failed to register schema for subject '{}'; schema registry responded with {}: {}",
+                subject,
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            );
+        }
+
+        let resp: RegisterResponse = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("invalid response from schema registry: {}", e))?;
+
+        Ok(resp.id)
+    }
+}
+
+fn parse_schema(resp: &SchemaResponse) -> Result<RegistrySchema> {
+    // the schema type is omitted for Avro schemas
+    match resp.schema_type.as_deref() {
+        None | Some("AVRO") => Ok(RegistrySchema::Avro(
+            AvroSchema::parse_str(&resp.schema)
+                .map_err(|e| anyhow!("invalid Avro schema in schema registry: {}", e))?,
+        )),
+        Some("PROTOBUF") => Ok(RegistrySchema::Protobuf(compile_proto(&resp.schema)?)),
+        Some("JSON") => Ok(RegistrySchema::Json),
+        Some(other) => bail!("unsupported schema type '{}' in schema registry", other),
+    }
+}
+
+/// Resolves the schema's own file from memory, and well-known types like
+/// google/protobuf/timestamp.proto from the ones bundled with protox
+struct SchemaFileResolver {
+    source: String,
+}
+
+impl FileResolver for SchemaFileResolver {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        if name == PROTO_FILE_NAME {
+            File::from_source(name, &self.source)
+        } else {
+            Err(protox::Error::file_not_found(name))
+        }
+    }
+}
+
+fn compile_proto(source: &str) -> Result<FileDescriptor> {
+    let mut resolver = ChainFileResolver::new();
+    resolver.add(SchemaFileResolver {
+        source: source.to_string(),
+    });
+    resolver.add(GoogleFileResolver::new());
+
+    let mut compiler = protox::Compiler::with_file_resolver(resolver);
+    compiler
+        .open_file(PROTO_FILE_NAME)
+        .map_err(|e| anyhow!("invalid Protobuf schema in schema registry: {}", e))?;
+
+    compiler
+        .descriptor_pool()
+        .get_file_by_name(PROTO_FILE_NAME)
+        .ok_or_else(|| anyhow!("compiled Protobuf schema is missing"))
+}
+
+/// Splits a message in the Confluent wire format into the id of its schema and the payload
+pub fn parse_framing(msg: &[u8]) -> Result<(u32, &[u8]), String> {
+    if msg.len() < 5 || msg[0] != MAGIC_BYTE {
+        return Err("message is not framed with a schema registry schema id".to_string());
+    }
+
+    let id = u32::from_be_bytes(msg[1..5].try_into().unwrap());
+    Ok((id, &msg[5..]))
+}
+
+/// Prefixes a payload with the magic byte and schema id
+pub fn frame(id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(payload.len() + 5);
+    buf.push(MAGIC_BYTE);
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+fn decode_zigzag(buf: &mut &[u8]) -> Result<i64, String> {
+    let v = prost::encoding::decode_varint(buf)
+        .map_err(|e| format!("invalid Protobuf message indexes: {}", e))?;
+    Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+/// Protobuf messages carry the path to their message type within the schema's file after the
+/// schema id, as a zigzag-encoded count followed by the index at each level of nesting; a count
+/// of zero is shorthand for the first message in the file
+pub fn parse_message_indexes(payload: &[u8]) -> Result<(Vec<usize>, &[u8]), String> {
+    let mut buf = payload;
+    let count = decode_zigzag(&mut buf)?;
+    if count == 0 {
+        return Ok((vec![0], buf));
+    }
+
+    let indexes = (0..count)
+        .map(|_| decode_zigzag(&mut buf).map(|i| i as usize))
+        .collect::<Result<_, _>>()?;
+
+    Ok((indexes, buf))
+}
+
+/// Finds the message type at the given path of indexes within a file
+pub fn message_descriptor(file: &FileDescriptor, indexes: &[usize]) -> Option<MessageDescriptor> {
+    let (first, rest) = indexes.split_first()?;
+    let mut message = file.messages().nth(*first)?;
+    for i in rest {
+        message = message.child_messages().nth(*i)?;
+    }
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame, parse_framing, parse_message_indexes};
+
+    #[test]
+    fn test_framing() {
+        let framed = frame(258, b"hello");
+        assert_eq!(vec![0, 0, 0, 1, 2, b'h', b'e', b'l', b'l', b'o'], framed);
+        assert_eq!((258, &b"hello"[..]), parse_framing(&framed).unwrap());
+
+        assert!(parse_framing(b"{}").is_err());
+        assert!(parse_framing(&[1, 0, 0, 0, 1, 5]).is_err());
+    }
+
+    #[test]
+    fn test_message_indexes() {
+        // the common case of the first message in the file
+        assert_eq!(
+            (vec![0], &[8u8, 1][..]),
+            parse_message_indexes(&[0, 8, 1]).unwrap()
+        );
+
+        // two levels of nesting, [1, 2], as zigzag varints
+        assert_eq!(
+            (vec![1, 2], &[8u8][..]),
+            parse_message_indexes(&[4, 2, 4, 8]).unwrap()
+        );
+    }
+}
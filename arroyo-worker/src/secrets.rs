@@ -0,0 +1,235 @@
+//! Resolves `${secret:NAME}` references in operator configs, so that credentials can be kept in
+//! an external secret store rather than in the config database. The store is chosen with
+//! `SECRETS_BACKEND`:
+//!
+//! * `env` (the default): the environment variable `NAME`
+//! * `kubernetes`: the file `NAME` in `SECRETS_DIR` (`/etc/arroyo/secrets` by default), where
+//!   Kubernetes secrets are mounted into the worker
+//! * `aws`: the AWS Secrets Manager secret with id `NAME`
+//! * `vault`: the HashiCorp Vault KV v2 secret at `NAME`, given as `<mount>/<path>`, read from
+//!   `VAULT_ADDR` with `VAULT_TOKEN`
+//!
+//! For secrets that hold several values, `${secret:NAME#KEY}` selects a key; AWS secrets are
+//! then parsed as JSON, and Vault secrets default to the key `value`.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use arroyo_types::{SECRETS_BACKEND_ENV, SECRETS_DIR_ENV};
+use regex::Regex;
+use serde_json::Value;
+
+const DEFAULT_SECRETS_DIR: &str = "/etc/arroyo/secrets";
+const DEFAULT_VAULT_KEY: &str = "value";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Env,
+    Kubernetes,
+    Aws,
+    Vault,
+}
+
+impl Backend {
+    fn from_env() -> anyhow::Result<Self> {
+        match std::env::var(SECRETS_BACKEND_ENV).ok().as_deref() {
+            None | Some("env") => Ok(Backend::Env),
+            Some("kubernetes") => Ok(Backend::Kubernetes),
+            Some("aws") => Ok(Backend::Aws),
+            Some("vault") => Ok(Backend::Vault),
+            Some(other) => bail!(
+                "unknown {} '{}'; expected one of env, kubernetes, aws, vault",
+                SECRETS_BACKEND_ENV,
+                other
+            ),
+        }
+    }
+}
+
+fn secret_pattern() -> Regex {
+    Regex::new(r"\$\{secret:([^}#]+)(?:#([^}]+))?\}").unwrap()
+}
+
+/// Replaces each secret reference in `config`, which is JSON, with the secret's value. Values are
+/// escaped so that they can appear within JSON strings.
+fn substitute(
+    config: &str,
+    mut lookup: impl FnMut(&str, Option<&str>) -> anyhow::Result<String>,
+) -> anyhow::Result<String> {
+    let mut resolved = String::with_capacity(config.len());
+    let mut last = 0;
+
+    for captures in secret_pattern().captures_iter(config) {
+        let m = captures.get(0).unwrap();
+        let name = captures.get(1).unwrap().as_str();
+        let key = captures.get(2).map(|k| k.as_str());
+
+        let value = lookup(name, key).map_err(|e| anyhow!("secret '{}': {}", name, e))?;
+        let escaped = serde_json::to_string(&value).unwrap();
+
+        resolved.push_str(&config[last..m.start()]);
+        resolved.push_str(&escaped[1..escaped.len() - 1]);
+        last = m.end();
+    }
+
+    resolved.push_str(&config[last..]);
+    Ok(resolved)
+}
+
+/// Resolves the secrets referenced by an operator config, panicking if any of them can't be
+/// found
+pub fn resolve(config: &str) -> String {
+    let references: Vec<_> = secret_pattern()
+        .captures_iter(config)
+        .map(|c| {
+            (
+                c.get(1).unwrap().as_str().to_string(),
+                c.get(2).map(|k| k.as_str().to_string()),
+            )
+        })
+        .collect();
+
+    if references.is_empty() {
+        return config.to_string();
+    }
+
+    let backend = Backend::from_env().unwrap_or_else(|e| panic!("{}", e));
+
+    // this is called while constructing operators, outside of any async context, so the remote
+    // stores are queried on a separate thread with its own runtime
+    let values = std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(fetch_all(backend, references))
+    })
+    .join()
+    .expect("secret resolution panicked")
+    .unwrap_or_else(|e| panic!("Failed to resolve secrets: {:?}", e));
+
+    substitute(config, |name, key| {
+        values
+            .get(&(name.to_string(), key.map(|k| k.to_string())))
+            .cloned()
+            .ok_or_else(|| anyhow!("not resolved"))
+    })
+    .unwrap_or_else(|e| panic!("Failed to resolve secrets: {:?}", e))
+}
+
+async fn fetch_all(
+    backend: Backend,
+    references: Vec<(String, Option<String>)>,
+) -> anyhow::Result<HashMap<(String, Option<String>), String>> {
+    let mut values = HashMap::new();
+    for (name, key) in references {
+        if values.contains_key(&(name.clone(), key.clone())) {
+            continue;
+        }
+
+        let value = fetch(backend, &name, key.as_deref())
+            .await
+            .map_err(|e| anyhow!("secret '{}': {}", name, e))?;
+        values.insert((name, key), value);
+    }
+    Ok(values)
+}
+
+async fn fetch(backend: Backend, name: &str, key: Option<&str>) -> anyhow::Result<String> {
+    match backend {
+        Backend::Env => std::env::var(name).map_err(|_| anyhow!("environment variable is not set")),
+        Backend::Kubernetes => {
+            let dir =
+                std::env::var(SECRETS_DIR_ENV).unwrap_or_else(|_| DEFAULT_SECRETS_DIR.to_string());
+            let path = std::path::Path::new(&dir).join(name);
+            let value = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?;
+            Ok(value.trim_end_matches('\n').to_string())
+        }
+        Backend::Aws => {
+            let client = aws_sdk_secretsmanager::Client::new(&aws_config::load_from_env().await);
+            let value = client
+                .get_secret_value()
+                .secret_id(name)
+                .send()
+                .await
+                .map_err(|e| anyhow!("could not fetch from AWS Secrets Manager: {}", e))?
+                .secret_string()
+                .ok_or_else(|| anyhow!("secret has no string value"))?
+                .to_string();
+
+            match key {
+                Some(key) => json_key(&serde_json::from_str(&value)?, key),
+                None => Ok(value),
+            }
+        }
+        Backend::Vault => {
+            let addr = std::env::var("VAULT_ADDR").map_err(|_| anyhow!("VAULT_ADDR is not set"))?;
+            let token =
+                std::env::var("VAULT_TOKEN").map_err(|_| anyhow!("VAULT_TOKEN is not set"))?;
+
+            // names are paths within the KV v2 engine, starting with its mount
+            let (mount, path) = name
+                .split_once('/')
+                .ok_or_else(|| anyhow!("expected a name of the form <mount>/<path>"))?;
+
+            let resp = reqwest::Client::new()
+                .get(format!(
+                    "{}/v1/{}/data/{}",
+                    addr.trim_end_matches('/'),
+                    mount,
+                    path
+                ))
+                .header("X-Vault-Token", token)
+                .send()
+                .await?;
+
+            let status = resp.status();
+            let body = resp.text().await?;
+            if !status.is_success() {
+                bail!("Vault responded with {}: {}", status, body);
+            }
+
+            let body: Value = serde_json::from_str(&body)?;
+            json_key(&body["data"]["data"], key.unwrap_or(DEFAULT_VAULT_KEY))
+        }
+    }
+}
+
+fn json_key(value: &Value, key: &str) -> anyhow::Result<String> {
+    match value.get(key) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(v) => Ok(v.to_string()),
+        None => bail!("secret has no key '{}'", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::substitute;
+
+    #[test]
+    fn test_substitute() {
+        let config =
+            r#"{"user":"admin","password":"${secret:db_password}","key":"${secret:creds#key}"}"#;
+
+        let resolved = substitute(config, |name, key| {
+            Ok(match (name, key) {
+                ("db_password", None) => "p\"ss".to_string(),
+                ("creds", Some("key")) => "abc".to_string(),
+                _ => unreachable!(),
+            })
+        })
+        .unwrap();
+
+        assert_eq!(
+            resolved,
+            r#"{"user":"admin","password":"p\"ss","key":"abc"}"#
+        );
+
+        assert_eq!(
+            substitute("{}", |_, _| unreachable!()).unwrap(),
+            "{}".to_string()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use tracing::warn;
+pub use wasmtime::Val;
+use wasmtime::{Config, Engine, Func, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+// roughly the most instructions that a call may execute
+const FUEL_PER_CALL: u64 = 10_000_000;
+const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+lazy_static! {
+    static ref ENGINE: Engine = {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config).expect("could not create the WebAssembly UDF engine")
+    };
+}
+
+thread_local! {
+    static UDFS: RefCell<HashMap<String, WasmUdf>> = RefCell::new(HashMap::new());
+}
+
+// an instance of the module of a UDF, which only has access to its own memory
+struct WasmUdf {
+    store: Store<StoreLimits>,
+    func: Func,
+}
+
+impl WasmUdf {
+    fn new(module: &str, name: &str) -> anyhow::Result<Self> {
+        let module = Module::new(&ENGINE, base64::decode(module)?)?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&ENGINE, limits);
+        store.limiter(|limits| limits);
+
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let func = instance
+            .get_func(&mut store, name)
+            .ok_or_else(|| anyhow!("the module doesn't export {}", name))?;
+
+        Ok(Self { store, func })
+    }
+
+    fn call(&mut self, args: &[Val]) -> anyhow::Result<Val> {
+        // each call gets the same fuel, however much the previous ones used
+        let remaining = self.store.consume_fuel(0)?;
+        self.store
+            .add_fuel(FUEL_PER_CALL.saturating_sub(remaining))?;
+
+        let mut results = [Val::I32(0)];
+        self.func.call(&mut self.store, args, &mut results)?;
+        let [result] = results;
+        Ok(result)
+    }
+}
+
+/// Calls the function `name` of a UDF compiled to WebAssembly, where `module` is the
+/// base64-encoded module that exports it. Each thread runs its own instance of the module, with
+/// limited fuel for each call and limited memory.
+///
+/// Returns None if the call fails, as it does if the function traps or runs out of fuel or memory,
+/// after which the instance is replaced, as it may have been left in an inconsistent state.
+pub fn call(module: &str, name: &str, args: &[Val]) -> Option<Val> {
+    UDFS.with(|udfs| {
+        let mut udfs = udfs.borrow_mut();
+        if !udfs.contains_key(name) {
+            match WasmUdf::new(module, name) {
+                Ok(udf) => {
+                    udfs.insert(name.to_string(), udf);
+                }
+                Err(e) => {
+                    warn!("could not instantiate WebAssembly UDF {}: {:?}", name, e);
+                    return None;
+                }
+            }
+        }
+
+        match udfs.get_mut(name).unwrap().call(args) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("WebAssembly UDF {} failed: {:?}", name, e);
+                udfs.remove(name);
+                None
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // exports add_one(i64) -> i64, and spin(i64) -> i64, which loops forever
+    const MODULE: &str =
+        "AGFzbQEAAAABBgFgAX4BfgMDAgAABxICB2FkZF9vbmUAAARzcGluAAEKEgIHACAAQgF8CwgAA0AMAAsACw==";
+
+    #[test]
+    fn test_call() {
+        for i in 0..3 {
+            assert_eq!(
+                call(MODULE, "add_one", &[Val::I64(i)]).and_then(|v| v.i64()),
+                Some(i + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        assert!(call(MODULE, "spin", &[Val::I64(1)]).is_none());
+
+        // the failed instance is replaced
+        assert!(call(MODULE, "spin", &[Val::I64(1)]).is_none());
+        assert_eq!(
+            call(MODULE, "add_one", &[Val::I64(1)]).and_then(|v| v.i64()),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use arroyo_rpc::grpc::SourceWatermark;
+use arroyo_types::{from_micros, to_micros, Watermark};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    pub static ref WATERMARK_ALIGNMENT: WatermarkAlignment = WatermarkAlignment::default();
+}
+
+/// Tracks the watermarks of the aligned sources of a job, so that each can tell whether it has
+/// gotten too far ahead of the slowest source in its group. The watermarks of the sources running
+/// on this worker are sent to the controller with each heartbeat, which replies with the least
+/// watermark of each group across all of the job's workers.
+#[derive(Default)]
+pub struct WatermarkAlignment {
+    inner: Mutex<AlignmentState>,
+}
+
+#[derive(Default)]
+struct AlignmentState {
+    // the watermarks of the sources on this worker, by group and (operator, subtask)
+    local: HashMap<String, HashMap<(String, usize), SystemTime>>,
+    // the least watermark of each group across all workers, as of the last heartbeat
+    remote: HashMap<String, SystemTime>,
+}
+
+impl WatermarkAlignment {
+    /// Records the latest watermark of a source subtask; idle sources don't hold back the
+    /// others in their group
+    pub fn report(&self, group: &str, operator_id: &str, subtask: usize, watermark: Watermark) {
+        let mut state = self.inner.lock().unwrap();
+        let key = (operator_id.to_string(), subtask);
+        match watermark {
+            Watermark::EventTime(t) => {
+                state
+                    .local
+                    .entry(group.to_string())
+                    .or_default()
+                    .insert(key, t);
+            }
+            Watermark::Idle => {
+                if let Some(sources) = state.local.get_mut(group) {
+                    sources.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Stops tracking a source subtask, for example because it has finished
+    pub fn remove(&self, group: &str, operator_id: &str, subtask: usize) {
+        self.report(group, operator_id, subtask, Watermark::Idle);
+    }
+
+    /// The least watermark of each group among the sources on this worker
+    pub fn local_watermarks(&self) -> Vec<SourceWatermark> {
+        let state = self.inner.lock().unwrap();
+        state
+            .local
+            .iter()
+            .filter_map(|(group, sources)| {
+                Some(SourceWatermark {
+                    group: group.clone(),
+                    watermark: to_micros(*sources.values().min()?),
+                })
+            })
+            .collect()
+    }
+
+    pub fn update_remote(&self, watermarks: Vec<SourceWatermark>) {
+        let mut state = self.inner.lock().unwrap();
+        state.remote = watermarks
+            .into_iter()
+            .map(|w| (w.group, from_micros(w.watermark)))
+            .collect();
+    }
+
+    /// Whether a source whose watermark is `watermark` is more than `max_drift` ahead of the
+    /// slowest source in its group, and so should stop reading until the others catch up
+    pub fn should_pause(&self, group: &str, watermark: SystemTime, max_drift: Duration) -> bool {
+        let state = self.inner.lock().unwrap();
+        let local = state.local.get(group).and_then(|s| s.values().min());
+        let remote = state.remote.get(group);
+
+        match local.into_iter().chain(remote).min() {
+            Some(min) => watermark
+                .duration_since(*min)
+                .map(|drift| drift > max_drift)
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_pauses_sources_ahead_of_group() {
+        let alignment = WatermarkAlignment::default();
+        let max_drift = Duration::from_secs(60);
+
+        alignment.report("a", "source_1", 0, Watermark::EventTime(time(1000)));
+        alignment.report("a", "source_2", 0, Watermark::EventTime(time(100)));
+        alignment.report("b", "source_3", 0, Watermark::EventTime(time(10)));
+
+        assert!(alignment.should_pause("a", time(1000), max_drift));
+        assert!(!alignment.should_pause("a", time(100), max_drift));
+        assert!(!alignment.should_pause("a", time(160), max_drift));
+
+        // idle sources don't hold back the group
+        alignment.report("a", "source_2", 0, Watermark::Idle);
+        assert!(!alignment.should_pause("a", time(1000), max_drift));
+
+        // nor do the sources of other groups, but those on other workers do
+        alignment.update_remote(vec![SourceWatermark {
+            group: "a".to_string(),
+            watermark: to_micros(time(500)),
+        }]);
+        assert!(alignment.should_pause("a", time(1000), max_drift));
+    }
+
+    #[test]
+    fn test_local_watermarks() {
+        let alignment = WatermarkAlignment::default();
+        alignment.report("a", "source_1", 0, Watermark::EventTime(time(20)));
+        alignment.report("a", "source_1", 1, Watermark::EventTime(time(10)));
+        alignment.report("b", "source_2", 0, Watermark::EventTime(time(30)));
+        alignment.remove("b", "source_2", 0);
+
+        assert_eq!(
+            alignment.local_watermarks(),
+            vec![SourceWatermark {
+                group: "a".to_string(),
+                watermark: to_micros(time(10)),
+            }]
+        );
+    }
+}
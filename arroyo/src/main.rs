@@ -36,6 +36,31 @@ enum Commands {
 
     /// Stops a running Arroyo cluster
     Stop {},
+
+    /// Debugging tools for checkpoints
+    Checkpoint {
+        #[command(subcommand)]
+        command: CheckpointCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CheckpointCommands {
+    /// Prints the state of each operator in a checkpoint as JSON, including the sizes and key
+    /// counts of its tables and a sample of their entries
+    Inspect {
+        pipeline_id: String,
+        job_id: String,
+        epoch: u32,
+
+        /// The number of entries of each table to print
+        #[arg(short, long, default_value_t = 10)]
+        sample: u32,
+
+        /// The URL of the Arroyo API
+        #[arg(long, default_value = "http://localhost:8000")]
+        api_url: String,
+    },
 }
 
 #[tokio::main]
@@ -45,6 +70,16 @@ pub async fn main() {
     let result = match &cli.command {
         Commands::Start { tag, daemon } => start(tag.clone(), *daemon).await,
         Commands::Stop {} => stop().await,
+        Commands::Checkpoint {
+            command:
+                CheckpointCommands::Inspect {
+                    pipeline_id,
+                    job_id,
+                    epoch,
+                    sample,
+                    api_url,
+                },
+        } => inspect_checkpoint(api_url, pipeline_id, job_id, *epoch, *sample).await,
     };
 
     if let Err(e) = result {
@@ -314,3 +349,33 @@ async fn stop() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+async fn inspect_checkpoint(
+    api_url: &str,
+    pipeline_id: &str,
+    job_id: &str,
+    epoch: u32,
+    sample: u32,
+) -> anyhow::Result<()> {
+    let url = format!(
+        "{}/api/v1/pipelines/{}/jobs/{}/checkpoints/{}/state?sample={}",
+        api_url.trim_end_matches('/'),
+        pipeline_id,
+        job_id,
+        epoch,
+        sample
+    );
+
+    let resp = reqwest::get(&url)
+        .await
+        .context("Failed to connect to the Arroyo API -- is it running?")?;
+
+    let status = resp.status();
+    let body = resp.text().await.context("Failed to read response")?;
+    if !status.is_success() {
+        bail!("Failed to inspect checkpoint ({}): {}", status, body);
+    }
+
+    println!("{}", body);
+    Ok(())
+}
@@ -194,6 +194,10 @@ pub async fn main() {
                 source_name
             ),
             udfs: None,
+            savepoint: None,
+            checkpoint_url: None,
+            state_backend: None,
+            worker_pod: None,
         },
     )
     .await
@@ -234,6 +238,9 @@ pub async fn main() {
             checkpoint_interval_micros: None,
             parallelism: None,
             stop: Some(Some(StopType::Checkpoint)),
+            autoscaling: None,
+            restart_policy: None,
+            worker_pod: None,
         },
     )
     .await